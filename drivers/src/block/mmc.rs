@@ -0,0 +1,790 @@
+//! SD card driver for the MMC host controllers derived from the Synopsys
+//! DesignWare Mobile Storage Host Controller: the SMHC of Allwinner D1, and
+//! the DW-MSHC of StarFive JH7100 (VisionFive).
+//!
+//! Both have the same commands, interrupt status and internal DMA
+//! controller (IDMAC), at different register offsets. The SMHC transfers
+//! data by IDMAC, with the cache of the C906 maintained by hand. The JH7100
+//! is not DMA coherent either, and its DW-MSHC transfers through the FIFO.
+//!
+//! Specification: <https://www.sdcard.org/downloads/pls/>, Physical Layer
+//! Simplified Specification.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lock::Mutex;
+
+use crate::io::{Io, Mmio};
+use crate::net::{timer_now_as_micros, PAGE_SIZE};
+use crate::scheme::{BlockScheme, Scheme};
+use crate::utils::DmaRegion;
+use crate::{DeviceError, DeviceResult};
+
+/// The register offsets of a controller variant.
+#[derive(Clone, Copy)]
+struct Regs {
+    clkdiv: usize,
+    tmout: usize,
+    ctype: usize,
+    blksiz: usize,
+    bytcnt: usize,
+    intmask: usize,
+    cmdarg: usize,
+    cmd: usize,
+    resp0: usize,
+    rintsts: usize,
+    status: usize,
+    fifoth: usize,
+    bmod: usize,
+    dbaddr: usize,
+    idsts: usize,
+    idinten: usize,
+    fifo: usize,
+}
+
+const REG_CTRL: usize = 0x00;
+
+const SUNXI_REGS: Regs = Regs {
+    clkdiv: 0x04,
+    tmout: 0x08,
+    ctype: 0x0c,
+    blksiz: 0x10,
+    bytcnt: 0x14,
+    intmask: 0x30,
+    cmdarg: 0x1c,
+    cmd: 0x18,
+    resp0: 0x20,
+    rintsts: 0x38,
+    status: 0x3c,
+    fifoth: 0x40,
+    bmod: 0x80,
+    dbaddr: 0x84,
+    idsts: 0x88,
+    idinten: 0x8c,
+    fifo: 0x200,
+};
+
+const DW_REGS: Regs = Regs {
+    clkdiv: 0x08,
+    tmout: 0x14,
+    ctype: 0x18,
+    blksiz: 0x1c,
+    bytcnt: 0x20,
+    intmask: 0x24,
+    cmdarg: 0x28,
+    cmd: 0x2c,
+    resp0: 0x30,
+    rintsts: 0x44,
+    status: 0x48,
+    fifoth: 0x4c,
+    bmod: 0x80,
+    dbaddr: 0x88,
+    idsts: 0x8c,
+    idinten: 0x90,
+    fifo: 0x200,
+};
+
+// registers of the DW-MSHC only
+const DW_REG_PWREN: usize = 0x04;
+const DW_REG_CLKENA: usize = 0x10;
+const DW_REG_VERID: usize = 0x6c;
+/// The FIFO moved from 0x100 in version 2.40a.
+const DW_VERID_FIFO_MOVED: u32 = 0x240a;
+
+// registers of the SMHC only
+/// New timing mode, in which the module clock is halved first.
+const SUNXI_REG_NTSR: usize = 0x5c;
+const SUNXI_NTSR_2X_TIMING_MODE: u32 = 1 << 31;
+const SUNXI_CLKDIV_CARD_CLK_ON: u32 = 1 << 16;
+
+const CTRL_RESET: u32 = 1 << 0;
+const CTRL_FIFO_RESET: u32 = 1 << 1;
+const CTRL_DMA_RESET: u32 = 1 << 2;
+const CTRL_DMA_ENABLE: u32 = 1 << 5;
+/// The FIFO is accessed by the AHB bus instead of the DMA, on the SMHC.
+const SUNXI_CTRL_FIFO_AHB: u32 = 1 << 31;
+
+const CMD_START: u32 = 1 << 31;
+const CMD_USE_HOLD_REG: u32 = 1 << 29;
+const CMD_UPDATE_CLOCK: u32 = 1 << 21;
+const CMD_SEND_INIT: u32 = 1 << 15;
+const CMD_WAIT_PRVDATA: u32 = 1 << 13;
+const CMD_AUTO_STOP: u32 = 1 << 12;
+const CMD_WRITE: u32 = 1 << 10;
+const CMD_DATA: u32 = 1 << 9;
+const CMD_CHECK_CRC: u32 = 1 << 8;
+const CMD_LONG_RESP: u32 = 1 << 7;
+const CMD_RESP: u32 = 1 << 6;
+
+const INT_RESP_ERR: u32 = 1 << 1;
+const INT_CMD_DONE: u32 = 1 << 2;
+const INT_DATA_OVER: u32 = 1 << 3;
+const INT_RESP_CRC: u32 = 1 << 6;
+const INT_DATA_CRC: u32 = 1 << 7;
+const INT_RESP_TIMEOUT: u32 = 1 << 8;
+const INT_DATA_TIMEOUT: u32 = 1 << 9;
+const INT_HOST_TIMEOUT: u32 = 1 << 10;
+const INT_FIFO_RUN: u32 = 1 << 11;
+const INT_HW_LOCKED: u32 = 1 << 12;
+const INT_START_BIT: u32 = 1 << 13;
+const INT_AUTO_CMD_DONE: u32 = 1 << 14;
+const INT_END_BIT: u32 = 1 << 15;
+const INT_CMD_ERRORS: u32 = INT_RESP_ERR | INT_RESP_CRC | INT_RESP_TIMEOUT | INT_HW_LOCKED;
+const INT_DATA_ERRORS: u32 =
+    INT_DATA_CRC | INT_DATA_TIMEOUT | INT_HOST_TIMEOUT | INT_FIFO_RUN | INT_START_BIT | INT_END_BIT;
+
+const STATUS_FIFO_EMPTY: u32 = 1 << 2;
+const STATUS_FIFO_FULL: u32 = 1 << 3;
+const STATUS_DATA_BUSY: u32 = 1 << 9;
+
+const BMOD_SOFT_RESET: u32 = 1 << 0;
+const BMOD_FIXED_BURST: u32 = 1 << 1;
+const BMOD_IDMAC_ENABLE: u32 = 1 << 7;
+
+// IDMAC descriptor flags
+const DES0_DIC: u32 = 1 << 1;
+const DES0_LD: u32 = 1 << 2;
+const DES0_FS: u32 = 1 << 3;
+const DES0_CH: u32 = 1 << 4;
+const DES0_ER: u32 = 1 << 5;
+const DES0_OWN: u32 = 1 << 31;
+
+// SD commands
+const CMD_GO_IDLE_STATE: u32 = 0;
+const CMD_ALL_SEND_CID: u32 = 2;
+const CMD_SEND_RELATIVE_ADDR: u32 = 3;
+const CMD_SELECT_CARD: u32 = 7;
+const CMD_SEND_IF_COND: u32 = 8;
+const CMD_SEND_CSD: u32 = 9;
+const CMD_SET_BLOCKLEN: u32 = 16;
+const CMD_READ_SINGLE_BLOCK: u32 = 17;
+const CMD_READ_MULTIPLE_BLOCK: u32 = 18;
+const CMD_WRITE_BLOCK: u32 = 24;
+const CMD_WRITE_MULTIPLE_BLOCK: u32 = 25;
+const CMD_APP_CMD: u32 = 55;
+const ACMD_SET_BUS_WIDTH: u32 = 6;
+const ACMD_SD_SEND_OP_COND: u32 = 41;
+
+/// The voltage window of 2.7-3.6V, in the OCR.
+const OCR_VOLTAGE_WINDOW: u32 = 0x00ff_8000;
+const OCR_HCS: u32 = 1 << 30;
+const OCR_BUSY: u32 = 1 << 31;
+
+/// The size of the blocks of [`BlockScheme`].
+const BLOCK_SIZE: usize = 512;
+/// The blocks transferred by one request at most.
+const MAX_BLOCKS: usize = 128;
+const IDMAC_DESC_SIZE: usize = 16;
+
+const IDENTIFY_CLOCK_HZ: u32 = 400_000;
+const DEFAULT_SPEED_CLOCK_HZ: u32 = 25_000_000;
+
+const CMD_TIMEOUT_US: u64 = 100_000;
+const DATA_TIMEOUT_US: u64 = 1_000_000;
+const OP_COND_TIMEOUT_US: u64 = 1_000_000;
+
+/// The cards found, to name them.
+static MMC_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The variant of the host controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcHostKind {
+    /// The SMHC of Allwinner D1.
+    Sunxi,
+    /// The Synopsys DW-MSHC.
+    DesignWare,
+}
+
+/// How a command responds.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Resp {
+    None,
+    /// 48 bits, with CRC.
+    Short,
+    /// 48 bits, without a valid CRC, R3 for the OCR.
+    ShortNoCrc,
+    /// 136 bits, R2 for the CID and CSD.
+    Long,
+}
+
+/// The data phase of a command.
+struct Data {
+    blocks: usize,
+    write: bool,
+}
+
+struct MmcHost {
+    base: usize,
+    kind: MmcHostKind,
+    regs: Regs,
+    /// The frequency of the clock fed into the card clock divider.
+    clock_in: u32,
+    /// IDMAC descriptors and the buffer, if the data goes by DMA.
+    dma: Option<(DmaRegion, DmaRegion)>,
+    /// Not DMA, for the data by FIFO.
+    buf: Vec<u8>,
+}
+
+impl MmcHost {
+    fn read(&self, reg: usize) -> u32 {
+        unsafe { Mmio::<u32>::from_base(self.base + reg) }.read()
+    }
+
+    fn write(&mut self, reg: usize, value: u32) {
+        unsafe { Mmio::<u32>::from_base(self.base + reg) }.write(value)
+    }
+
+    /// Poll until `cond` holds, or fails after `timeout_us`.
+    fn wait(&self, timeout_us: u64, mut cond: impl FnMut(&Self) -> bool) -> DeviceResult {
+        let start = timer_now_as_micros();
+        while !cond(self) {
+            if timer_now_as_micros() - start > timeout_us {
+                return Err(DeviceError::IoError);
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    fn init(&mut self) -> DeviceResult {
+        if self.kind == MmcHostKind::DesignWare {
+            self.write(DW_REG_PWREN, 1);
+            if self.read(DW_REG_VERID) & 0xffff < DW_VERID_FIFO_MOVED {
+                self.regs.fifo = 0x100;
+            }
+        } else {
+            let ntsr = self.read(SUNXI_REG_NTSR);
+            self.write(SUNXI_REG_NTSR, ntsr | SUNXI_NTSR_2X_TIMING_MODE);
+        }
+        // polled, without interrupts
+        self.write(self.regs.intmask, 0);
+        // the longest data timeout, and 0x40 cycles for the response
+        self.write(self.regs.tmout, 0xffff_ff40);
+        self.write(self.regs.ctype, 0);
+        // burst of 8, RX watermark at 7, TX watermark at 8
+        self.write(self.regs.fifoth, 0x2007_0008);
+        self.reset()
+    }
+
+    /// Reset the state of the controller and the IDMAC, but not the
+    /// settings of the clock and the bus.
+    fn reset(&mut self) -> DeviceResult {
+        let reset = CTRL_RESET | CTRL_FIFO_RESET | CTRL_DMA_RESET;
+        let ctrl = self.read(REG_CTRL);
+        self.write(REG_CTRL, ctrl | reset);
+        self.wait(CMD_TIMEOUT_US, |h| h.read(REG_CTRL) & reset == 0)?;
+        self.write(self.regs.rintsts, u32::MAX);
+        if let Some(desc) = self.dma.as_ref().map(|(desc, _)| desc.paddr()) {
+            let ctrl = self.read(REG_CTRL) & !SUNXI_CTRL_FIFO_AHB;
+            self.write(REG_CTRL, ctrl | CTRL_DMA_ENABLE);
+            self.write(self.regs.bmod, BMOD_SOFT_RESET);
+            self.write(self.regs.bmod, BMOD_FIXED_BURST | BMOD_IDMAC_ENABLE);
+            self.write(self.regs.idinten, 0);
+            self.write(self.regs.dbaddr, self.desc_addr(desc)?);
+        }
+        Ok(())
+    }
+
+    /// The address of a descriptor or a buffer, for the IDMAC.
+    fn desc_addr(&self, paddr: usize) -> DeviceResult<u32> {
+        // the SMHC of D1 takes the addresses in words
+        let paddr = match self.kind {
+            MmcHostKind::Sunxi => paddr >> 2,
+            MmcHostKind::DesignWare => paddr,
+        };
+        if paddr > u32::MAX as usize {
+            return Err(DeviceError::DmaError);
+        }
+        Ok(paddr as u32)
+    }
+
+    fn set_clock(&mut self, hz: u32) -> DeviceResult {
+        // card clock = clock_in / (2 * div), or clock_in if div == 0
+        let div = if hz >= self.clock_in {
+            0
+        } else {
+            (self.clock_in + 2 * hz - 1) / (2 * hz)
+        };
+        match self.kind {
+            MmcHostKind::Sunxi => {
+                self.write(self.regs.clkdiv, 0);
+                self.update_clock()?;
+                self.write(self.regs.clkdiv, SUNXI_CLKDIV_CARD_CLK_ON | div);
+            }
+            MmcHostKind::DesignWare => {
+                self.write(DW_REG_CLKENA, 0);
+                self.update_clock()?;
+                self.write(self.regs.clkdiv, div);
+                self.update_clock()?;
+                self.write(DW_REG_CLKENA, 1);
+            }
+        }
+        self.update_clock()?;
+        debug!(
+            "mmc: card clock {} Hz",
+            if div == 0 {
+                self.clock_in
+            } else {
+                self.clock_in / (2 * div)
+            }
+        );
+        Ok(())
+    }
+
+    /// Make the clock settings take effect.
+    fn update_clock(&mut self) -> DeviceResult {
+        self.write(
+            self.regs.cmd,
+            CMD_START | CMD_UPDATE_CLOCK | CMD_WAIT_PRVDATA | CMD_USE_HOLD_REG,
+        );
+        self.wait(CMD_TIMEOUT_US, |h| h.read(h.regs.cmd) & CMD_START == 0)
+    }
+
+    /// Send a command, with the data in or out of `self.buf` or the DMA
+    /// buffer. Returns the response, from the lowest bits in `[0]`.
+    fn command(
+        &mut self,
+        index: u32,
+        arg: u32,
+        resp: Resp,
+        data: Option<Data>,
+    ) -> DeviceResult<[u32; 4]> {
+        self.wait(DATA_TIMEOUT_US, |h| {
+            h.read(h.regs.status) & STATUS_DATA_BUSY == 0
+        })?;
+        self.write(self.regs.rintsts, u32::MAX);
+        let mut cmd = CMD_START | CMD_USE_HOLD_REG | CMD_WAIT_PRVDATA | index;
+        match resp {
+            Resp::None => {}
+            Resp::Short => cmd |= CMD_RESP | CMD_CHECK_CRC,
+            Resp::ShortNoCrc => cmd |= CMD_RESP,
+            Resp::Long => cmd |= CMD_RESP | CMD_LONG_RESP | CMD_CHECK_CRC,
+        }
+        if index == CMD_GO_IDLE_STATE {
+            cmd |= CMD_SEND_INIT;
+        }
+        if let Some(data) = &data {
+            cmd |= CMD_DATA;
+            if data.write {
+                cmd |= CMD_WRITE;
+            }
+            if data.blocks > 1 {
+                cmd |= CMD_AUTO_STOP;
+            }
+            let len = data.blocks * BLOCK_SIZE;
+            let ctrl = self.read(REG_CTRL);
+            self.write(REG_CTRL, ctrl | CTRL_FIFO_RESET);
+            self.wait(CMD_TIMEOUT_US, |h| h.read(REG_CTRL) & CTRL_FIFO_RESET == 0)?;
+            self.write(self.regs.blksiz, BLOCK_SIZE as u32);
+            self.write(self.regs.bytcnt, len as u32);
+            if self.dma.is_some() {
+                self.prepare_dma(len, data.write)?;
+            }
+        }
+        self.write(self.regs.cmdarg, arg);
+        self.write(self.regs.cmd, cmd);
+
+        self.wait(CMD_TIMEOUT_US, |h| {
+            h.read(h.regs.rintsts) & (INT_CMD_DONE | INT_CMD_ERRORS) != 0
+        })?;
+        let status = self.read(self.regs.rintsts);
+        if status & INT_CMD_ERRORS != 0 {
+            // a timeout is expected for some commands, e.g. CMD8 of SD 1.x
+            trace!("mmc: CMD{} failed: {:#x}", index, status);
+            if data.is_some() {
+                self.reset()?;
+            }
+            return Err(DeviceError::IoError);
+        }
+        let mut res = [0; 4];
+        match resp {
+            Resp::None => {}
+            Resp::Long => {
+                for (i, r) in res.iter_mut().enumerate() {
+                    *r = self.read(self.regs.resp0 + i * 4);
+                }
+            }
+            _ => res[0] = self.read(self.regs.resp0),
+        }
+
+        if let Some(data) = data {
+            let ret = self.transfer_data(&data);
+            if ret.is_err() {
+                warn!("mmc: data of CMD{} failed", index);
+                self.reset()?;
+                return ret.map(|_| res);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Fill the IDMAC descriptors for `len` bytes of the DMA buffer.
+    fn prepare_dma(&mut self, len: usize, write: bool) -> DeviceResult {
+        let (desc, buf) = self.dma.as_ref().unwrap();
+        let count = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        for i in 0..count {
+            let size = (len - i * PAGE_SIZE).min(PAGE_SIZE);
+            let mut flags = DES0_OWN | DES0_CH | DES0_DIC;
+            if i == 0 {
+                flags |= DES0_FS;
+            }
+            if i == count - 1 {
+                flags = (flags & !DES0_DIC) | DES0_LD | DES0_ER;
+            }
+            let next = desc.paddr() + (i + 1) * IDMAC_DESC_SIZE;
+            let entry = [
+                flags,
+                size as u32,
+                self.desc_addr(buf.paddr() + i * PAGE_SIZE)?,
+                self.desc_addr(next)?,
+            ];
+            for (j, word) in entry.iter().enumerate() {
+                unsafe { desc.as_ptr::<u32>(i * 4 + j).write_volatile(*word) };
+            }
+        }
+        cache::clean(desc.paddr(), count * IDMAC_DESC_SIZE);
+        if write {
+            cache::clean(buf.paddr(), len);
+        } else {
+            // not to write back stale lines over the data later
+            cache::invalidate(buf.paddr(), len);
+        }
+        self.write(self.regs.idsts, u32::MAX);
+        Ok(())
+    }
+
+    fn transfer_data(&mut self, data: &Data) -> DeviceResult {
+        let len = data.blocks * BLOCK_SIZE;
+        if self.dma.is_none() {
+            self.transfer_fifo(len, data.write)?;
+        }
+        let done = if data.blocks > 1 {
+            INT_DATA_OVER | INT_AUTO_CMD_DONE
+        } else {
+            INT_DATA_OVER
+        };
+        self.wait(DATA_TIMEOUT_US, |h| {
+            let status = h.read(h.regs.rintsts);
+            status & done == done || status & INT_DATA_ERRORS != 0
+        })?;
+        let status = self.read(self.regs.rintsts);
+        if status & INT_DATA_ERRORS != 0 {
+            warn!("mmc: data error: {:#x}", status);
+            return Err(DeviceError::IoError);
+        }
+        if let Some((_, buf)) = &self.dma {
+            if !data.write {
+                cache::invalidate(buf.paddr(), len);
+            }
+        }
+        // programming of the written blocks
+        self.wait(DATA_TIMEOUT_US, |h| {
+            h.read(h.regs.status) & STATUS_DATA_BUSY == 0
+        })
+    }
+
+    /// Move the data through the FIFO, one word at a time.
+    fn transfer_fifo(&mut self, len: usize, write: bool) -> DeviceResult {
+        for i in (0..len).step_by(4) {
+            let flag = if write {
+                STATUS_FIFO_FULL
+            } else {
+                STATUS_FIFO_EMPTY
+            };
+            self.wait(DATA_TIMEOUT_US, |h| {
+                h.read(h.regs.status) & flag == 0 || h.read(h.regs.rintsts) & INT_DATA_ERRORS != 0
+            })?;
+            if self.read(self.regs.rintsts) & INT_DATA_ERRORS != 0 {
+                return Err(DeviceError::IoError);
+            }
+            if write {
+                let word = u32::from_le_bytes([
+                    self.buf[i],
+                    self.buf[i + 1],
+                    self.buf[i + 2],
+                    self.buf[i + 3],
+                ]);
+                self.write(self.regs.fifo, word);
+            } else {
+                let word = self.read(self.regs.fifo);
+                self.buf[i..i + 4].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn app_command(&mut self, rca: u32, index: u32, arg: u32, resp: Resp) -> DeviceResult<u32> {
+        self.command(CMD_APP_CMD, rca << 16, Resp::Short, None)?;
+        Ok(self.command(index, arg, resp, None)?[0])
+    }
+
+    /// The buffer of the data.
+    fn data_buf(&mut self, len: usize) -> &mut [u8] {
+        match &self.dma {
+            Some((_, buf)) => unsafe { core::slice::from_raw_parts_mut(buf.as_ptr(0), len) },
+            None => &mut self.buf[..len],
+        }
+    }
+}
+
+/// The bits `[start, start + len)` of a 128-bit register, such as the CSD.
+fn bits(reg: &[u32; 4], start: usize, len: usize) -> u32 {
+    let value = reg
+        .iter()
+        .rev()
+        .fold(0u128, |acc, word| acc << 32 | *word as u128);
+    ((value >> start) & ((1 << len) - 1)) as u32
+}
+
+struct MmcInner {
+    host: MmcHost,
+    /// Whether the card is addressed by blocks instead of bytes.
+    block_addressing: bool,
+}
+
+/// An SD card in the slot of an MMC host controller.
+pub struct MmcCard {
+    inner: Mutex<MmcInner>,
+    name: String,
+    blocks: u64,
+}
+
+impl MmcCard {
+    /// Initialize the controller at `base`, with `clock_in` Hz fed into the
+    /// card clock divider, and identify the card in it.
+    pub fn new(
+        base: usize,
+        kind: MmcHostKind,
+        clock_in: u32,
+        bus_width: u32,
+    ) -> DeviceResult<Self> {
+        let dma = if kind == MmcHostKind::Sunxi {
+            let desc = DmaRegion::new(MAX_BLOCKS * BLOCK_SIZE / PAGE_SIZE * IDMAC_DESC_SIZE)?;
+            let buf = DmaRegion::new(MAX_BLOCKS * BLOCK_SIZE)?;
+            Some((desc, buf))
+        } else {
+            None
+        };
+        let buf = if dma.is_none() {
+            vec![0; MAX_BLOCKS * BLOCK_SIZE]
+        } else {
+            Vec::new()
+        };
+        let mut host = MmcHost {
+            base,
+            kind,
+            regs: if kind == MmcHostKind::Sunxi {
+                SUNXI_REGS
+            } else {
+                DW_REGS
+            },
+            clock_in,
+            dma,
+            buf,
+        };
+        host.init()?;
+        host.set_clock(IDENTIFY_CLOCK_HZ)?;
+
+        host.command(CMD_GO_IDLE_STATE, 0, Resp::None, None)?;
+        // check pattern 0xaa, 2.7-3.6V
+        let sd_v2 = match host.command(CMD_SEND_IF_COND, 0x1aa, Resp::Short, None) {
+            Ok(resp) if resp[0] & 0xfff == 0x1aa => true,
+            Ok(_) => return Err(DeviceError::NotSupported),
+            Err(_) => false,
+        };
+        let arg = OCR_VOLTAGE_WINDOW | if sd_v2 { OCR_HCS } else { 0 };
+        let start = timer_now_as_micros();
+        let ocr = loop {
+            let ocr = host.app_command(0, ACMD_SD_SEND_OP_COND, arg, Resp::ShortNoCrc)?;
+            if ocr & OCR_BUSY != 0 {
+                break ocr;
+            }
+            if timer_now_as_micros() - start > OP_COND_TIMEOUT_US {
+                warn!("mmc: the card is not ready");
+                return Err(DeviceError::NotReady);
+            }
+        };
+        let block_addressing = ocr & OCR_HCS != 0;
+        let cid = host.command(CMD_ALL_SEND_CID, 0, Resp::Long, None)?;
+        let rca = host.command(CMD_SEND_RELATIVE_ADDR, 0, Resp::Short, None)?[0] >> 16;
+        let csd = host.command(CMD_SEND_CSD, rca << 16, Resp::Long, None)?;
+        let blocks = match bits(&csd, 126, 2) {
+            // CSD 2.0, in units of 512 KiB
+            1 => (bits(&csd, 48, 22) as u64 + 1) * 1024,
+            _ => {
+                let c_size = bits(&csd, 62, 12) as u64;
+                let mult = bits(&csd, 47, 3);
+                let read_bl_len = bits(&csd, 80, 4);
+                ((c_size + 1) << (mult + 2 + read_bl_len)) / BLOCK_SIZE as u64
+            }
+        };
+        host.command(CMD_SELECT_CARD, rca << 16, Resp::Short, None)?;
+        if bus_width == 4 {
+            host.app_command(rca, ACMD_SET_BUS_WIDTH, 2, Resp::Short)?;
+            host.write(host.regs.ctype, 1);
+        }
+        if !block_addressing {
+            host.command(CMD_SET_BLOCKLEN, BLOCK_SIZE as u32, Resp::Short, None)?;
+        }
+        host.set_clock(DEFAULT_SPEED_CLOCK_HZ)?;
+
+        // the product name, in CID[103:64]
+        let product: String = (0..5)
+            .map(|i| bits(&cid, 96 - i * 8, 8) as u8 as char)
+            .collect();
+        info!(
+            "mmc: SD card {:?} of {} MiB, by {:?}",
+            product.trim_end(),
+            blocks / 2048,
+            kind
+        );
+        Ok(Self {
+            inner: Mutex::new(MmcInner {
+                host,
+                block_addressing,
+            }),
+            name: format!("mmc{}", MMC_COUNT.fetch_add(1, Ordering::Relaxed)),
+            blocks,
+        })
+    }
+
+    /// The number of blocks of 512 bytes.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    fn rw(&self, block_id: usize, read: Option<&mut [u8]>, write: Option<&[u8]>) -> DeviceResult {
+        let len = read
+            .as_ref()
+            .map(|b| b.len())
+            .or_else(|| write.map(|b| b.len()))
+            .unwrap_or(0);
+        if len == 0 || len % BLOCK_SIZE != 0 || len > MAX_BLOCKS * BLOCK_SIZE {
+            return Err(DeviceError::InvalidParam);
+        }
+        let blocks = len / BLOCK_SIZE;
+        if block_id as u64 + blocks as u64 > self.blocks {
+            return Err(DeviceError::InvalidParam);
+        }
+        let mut inner = self.inner.lock();
+        let arg = if inner.block_addressing {
+            block_id as u32
+        } else {
+            (block_id * BLOCK_SIZE) as u32
+        };
+        let host = &mut inner.host;
+        let (index, data) = match write {
+            Some(src) => {
+                host.data_buf(len).copy_from_slice(src);
+                let index = if blocks > 1 {
+                    CMD_WRITE_MULTIPLE_BLOCK
+                } else {
+                    CMD_WRITE_BLOCK
+                };
+                (
+                    index,
+                    Data {
+                        blocks,
+                        write: true,
+                    },
+                )
+            }
+            None => {
+                let index = if blocks > 1 {
+                    CMD_READ_MULTIPLE_BLOCK
+                } else {
+                    CMD_READ_SINGLE_BLOCK
+                };
+                (
+                    index,
+                    Data {
+                        blocks,
+                        write: false,
+                    },
+                )
+            }
+        };
+        host.command(index, arg, Resp::Short, Some(data))?;
+        if let Some(dst) = read {
+            dst.copy_from_slice(host.data_buf(len));
+        }
+        Ok(())
+    }
+}
+
+impl Scheme for MmcCard {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl BlockScheme for MmcCard {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult {
+        self.rw(block_id, Some(buf), None)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> DeviceResult {
+        self.rw(block_id, None, Some(buf))
+    }
+
+    fn flush(&self) -> DeviceResult {
+        // the writes complete when the card is not busy
+        Ok(())
+    }
+}
+
+/// Maintenance of the data cache, for the devices not DMA coherent.
+mod cache {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "board-d1")] {
+            use crate::net::realtek::utils::{flush_cache, invalidate_dcache};
+
+            pub fn clean(paddr: usize, len: usize) {
+                flush_cache(paddr as u64, len as u64)
+            }
+
+            pub fn invalidate(paddr: usize, len: usize) {
+                invalidate_dcache(paddr as u64, len as u64)
+            }
+        } else {
+            pub fn clean(_paddr: usize, _len: usize) {}
+
+            pub fn invalidate(_paddr: usize, _len: usize) {}
+        }
+    }
+}
+
+/// Set up the module clock and the pins of the SMHC at `smhc_paddr` of D1,
+/// which the firmware leaves alone unless it loads the kernel from the card.
+///
+/// Returns the frequency fed into the card clock divider.
+#[cfg(feature = "board-d1")]
+pub fn d1_smhc_setup(
+    smhc_paddr: usize,
+    map: impl Fn(usize, usize) -> Option<usize>,
+) -> DeviceResult<u32> {
+    const SMHC0_PADDR: usize = 0x0402_0000;
+    const CCU_PADDR: usize = 0x0200_1000;
+    const GPIO_PADDR: usize = 0x0200_0000;
+    const CCU_SMHC0_CLK: usize = 0x830;
+    const CCU_SMHC_BGR: usize = 0x84c;
+    const GPIO_PF_CFG0: usize = 0xf0;
+
+    let index = (smhc_paddr - SMHC0_PADDR) / 0x1000;
+    let ccu = map(CCU_PADDR, PAGE_SIZE).ok_or(DeviceError::NoResources)?;
+    let reg = |base: usize, offset: usize| unsafe { Mmio::<u32>::from_base(base + offset) };
+    // open the gate and deassert the reset
+    let bgr = reg(ccu, CCU_SMHC_BGR);
+    bgr.write(bgr.read() | 1 << index | 1 << (16 + index));
+    // 24 MHz from HOSC, undivided
+    reg(ccu, CCU_SMHC0_CLK + index * 4).write(1 << 31);
+    if index == 0 {
+        // PF0-PF5 as SDC0
+        let gpio = map(GPIO_PADDR, PAGE_SIZE).ok_or(DeviceError::NoResources)?;
+        let cfg = reg(gpio, GPIO_PF_CFG0);
+        cfg.write(cfg.read() & !0x00ff_ffff | 0x0022_2222);
+    }
+    // halved in the new timing mode
+    Ok(12_000_000)
+}
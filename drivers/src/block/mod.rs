@@ -0,0 +1,9 @@
+//! Block devices, and the partitions on them.
+
+pub mod mmc;
+pub mod nvme;
+mod partition;
+
+pub use mmc::{MmcCard, MmcHostKind};
+pub use nvme::{Nvme, NvmeNamespace};
+pub use partition::{scan_partitions, Partition, PartitionType};
@@ -0,0 +1,540 @@
+//! NVM Express driver, with the admin queue and one I/O queue.
+//!
+//! Specification: <https://nvmexpress.org/wp-content/uploads/NVM-Express-1_4-2019.06.10-Ratified.pdf>
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use core::convert::TryInto;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use lock::Mutex;
+
+use crate::io::{Io, Mmio};
+use crate::net::{timer_now_as_micros, PAGE_SIZE};
+use crate::scheme::{BlockScheme, Scheme};
+use crate::utils::DmaRegion;
+use crate::{DeviceError, DeviceResult};
+
+// controller registers
+const REG_CAP_LO: usize = 0x00;
+const REG_CAP_HI: usize = 0x04;
+const REG_CC: usize = 0x14;
+const REG_CSTS: usize = 0x1C;
+const REG_AQA: usize = 0x24;
+const REG_ASQ: usize = 0x28;
+const REG_ACQ: usize = 0x30;
+const REG_DOORBELL_BASE: usize = 0x1000;
+
+const CC_EN: u32 = 1 << 0;
+/// 64-byte submission queue entries.
+const CC_IOSQES: u32 = 6 << 16;
+/// 16-byte completion queue entries.
+const CC_IOCQES: u32 = 4 << 20;
+const CSTS_RDY: u32 = 1 << 0;
+const CSTS_CFS: u32 = 1 << 1;
+
+// admin commands
+const ADMIN_CREATE_IO_SQ: u8 = 0x01;
+const ADMIN_CREATE_IO_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+
+// NVM commands
+const IO_FLUSH: u8 = 0x00;
+const IO_WRITE: u8 = 0x01;
+const IO_READ: u8 = 0x02;
+
+const IDENTIFY_NAMESPACE: u32 = 0;
+const IDENTIFY_CONTROLLER: u32 = 1;
+
+/// The size of the blocks of [`BlockScheme`].
+const BLOCK_SIZE: usize = 512;
+
+const ADMIN_QUEUE_SIZE: u16 = 32;
+const IO_QUEUE_SIZE: u16 = 64;
+/// The I/O commands in flight at most, each with one page of data.
+const IO_SLOTS: usize = 32;
+
+const IO_QUEUE_ID: u16 = 1;
+
+/// The controllers found, to name them.
+static NVME_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Submission queue entry
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct NvmeCommand {
+    opcode: u8,
+    flags: u8,
+    cid: u16,
+    nsid: u32,
+    _rsvd: u64,
+    mptr: u64,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+}
+
+/// Completion queue entry
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct NvmeCompletion {
+    result: u32,
+    _rsvd: u32,
+    sq_head: u16,
+    sq_id: u16,
+    cid: u16,
+    /// Phase tag in bit 0, status field in bits 1..16.
+    status: u16,
+}
+
+impl NvmeCompletion {
+    fn is_success(&self) -> bool {
+        self.status >> 1 == 0
+    }
+}
+
+/// A pair of submission and completion queues.
+struct NvmeQueue {
+    qid: u16,
+    size: u16,
+    sq: DmaRegion,
+    cq: DmaRegion,
+    sq_tail: u16,
+    cq_head: u16,
+    /// The phase tag of the new completions, flipped on each wrap around.
+    phase: bool,
+}
+
+impl NvmeQueue {
+    fn new(qid: u16, size: u16) -> DeviceResult<Self> {
+        Ok(Self {
+            qid,
+            size,
+            sq: DmaRegion::new(size as usize * core::mem::size_of::<NvmeCommand>())?,
+            cq: DmaRegion::new(size as usize * core::mem::size_of::<NvmeCompletion>())?,
+            sq_tail: 0,
+            cq_head: 0,
+            phase: true,
+        })
+    }
+
+    fn submit(&mut self, regs: &NvmeRegs, cmd: NvmeCommand) {
+        unsafe {
+            self.sq
+                .as_ptr::<NvmeCommand>(self.sq_tail as usize)
+                .write_volatile(cmd)
+        };
+        fence(Ordering::Release);
+        self.sq_tail = (self.sq_tail + 1) % self.size;
+        regs.write(regs.sq_doorbell(self.qid), self.sq_tail as u32);
+    }
+
+    /// Take a new completion, if any.
+    fn pop(&mut self, regs: &NvmeRegs) -> Option<NvmeCompletion> {
+        let entry = unsafe {
+            self.cq
+                .as_ptr::<NvmeCompletion>(self.cq_head as usize)
+                .read_volatile()
+        };
+        if (entry.status & 1 == 1) != self.phase {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        self.cq_head += 1;
+        if self.cq_head == self.size {
+            self.cq_head = 0;
+            self.phase = !self.phase;
+        }
+        regs.write(regs.cq_doorbell(self.qid), self.cq_head as u32);
+        Some(entry)
+    }
+}
+
+/// The registers of a controller.
+struct NvmeRegs {
+    base: usize,
+    /// The stride between the doorbells in bytes.
+    doorbell_stride: usize,
+}
+
+impl NvmeRegs {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { Mmio::<u32>::from_base(self.base + offset) }.read()
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { Mmio::<u32>::from_base(self.base + offset) }.write(value)
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        self.write(offset, value as u32);
+        self.write(offset + 4, (value >> 32) as u32);
+    }
+
+    fn sq_doorbell(&self, qid: u16) -> usize {
+        REG_DOORBELL_BASE + (2 * qid as usize) * self.doorbell_stride
+    }
+
+    fn cq_doorbell(&self, qid: u16) -> usize {
+        REG_DOORBELL_BASE + (2 * qid as usize + 1) * self.doorbell_stride
+    }
+
+    /// Wait until `CSTS.RDY` is `ready`, for at most `timeout_ms`.
+    fn wait_ready(&self, ready: bool, timeout_ms: u64) -> DeviceResult {
+        let deadline = timer_now_as_micros() + timeout_ms * 1000;
+        loop {
+            let csts = self.read(REG_CSTS);
+            if csts & CSTS_CFS != 0 {
+                warn!("nvme: controller fatal status");
+                return Err(DeviceError::IoError);
+            }
+            if (csts & CSTS_RDY != 0) == ready {
+                return Ok(());
+            }
+            if timer_now_as_micros() > deadline {
+                warn!("nvme: timeout waiting for CSTS.RDY = {}", ready);
+                return Err(DeviceError::NotReady);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// An NVMe controller.
+///
+/// The I/O commands are completed by its interrupt, or by the submitters
+/// polling the completion queue when no interrupt is delivered.
+pub struct Nvme {
+    name: String,
+    irq: usize,
+    regs: NvmeRegs,
+    admin: Mutex<NvmeQueue>,
+    /// One page for the data of the admin commands.
+    admin_buf: Mutex<DmaRegion>,
+    io: Mutex<NvmeQueue>,
+    /// One page for the data of each I/O command in flight.
+    io_buffers: DmaRegion,
+    /// The free slots of `io_buffers`, used as command identifiers.
+    free_slots: Mutex<Vec<u16>>,
+    /// The completions of the I/O commands, by command identifier.
+    io_done: Mutex<Vec<Option<NvmeCompletion>>>,
+}
+
+impl Nvme {
+    /// Reset and enable the controller with registers at `base`, and create
+    /// its I/O queue, with the interrupts delivered to `irq`.
+    pub fn new(base: usize, irq: usize) -> DeviceResult<Self> {
+        let regs = NvmeRegs {
+            base,
+            doorbell_stride: 4,
+        };
+        let cap = (regs.read(REG_CAP_HI) as u64) << 32 | regs.read(REG_CAP_LO) as u64;
+        let max_entries = (cap & 0xffff) as u16 + 1;
+        let timeout_ms = ((cap >> 24) & 0xff).max(1) * 500;
+        let doorbell_stride = 4 << ((cap >> 32) & 0xf);
+        let mps_min = (cap >> 48) & 0xf;
+        if mps_min != 0 {
+            // the memory page size is set to 4K below
+            warn!(
+                "nvme: minimum page size {:#x} not supported",
+                1 << (12 + mps_min)
+            );
+            return Err(DeviceError::NotSupported);
+        }
+        let regs = NvmeRegs {
+            base,
+            doorbell_stride,
+        };
+
+        // reset
+        regs.write(REG_CC, regs.read(REG_CC) & !CC_EN);
+        regs.wait_ready(false, timeout_ms)?;
+
+        let admin = NvmeQueue::new(0, ADMIN_QUEUE_SIZE.min(max_entries))?;
+        regs.write(
+            REG_AQA,
+            ((admin.size as u32 - 1) << 16) | (admin.size as u32 - 1),
+        );
+        regs.write64(REG_ASQ, admin.sq.paddr() as u64);
+        regs.write64(REG_ACQ, admin.cq.paddr() as u64);
+        // NVM command set, 4K pages, round robin
+        regs.write(REG_CC, CC_EN | CC_IOSQES | CC_IOCQES);
+        regs.wait_ready(true, timeout_ms)?;
+
+        let io = NvmeQueue::new(IO_QUEUE_ID, IO_QUEUE_SIZE.min(max_entries))?;
+        let slots = IO_SLOTS.min(io.size as usize - 1);
+        let nvme = Self {
+            name: format!("nvme{}", NVME_COUNT.fetch_add(1, Ordering::Relaxed)),
+            irq,
+            regs,
+            admin: Mutex::new(admin),
+            admin_buf: Mutex::new(DmaRegion::new(PAGE_SIZE)?),
+            io: Mutex::new(io),
+            io_buffers: DmaRegion::new(slots * PAGE_SIZE)?,
+            free_slots: Mutex::new((0..slots as u16).rev().collect()),
+            io_done: Mutex::new(vec![None; slots]),
+        };
+        nvme.create_io_queue()?;
+        info!("{}: ready, {} I/O slots", nvme.name, slots);
+        Ok(nvme)
+    }
+
+    /// Run an admin command, polling the admin completion queue.
+    fn admin_command(&self, cmd: NvmeCommand) -> DeviceResult<u32> {
+        let mut admin = self.admin.lock();
+        admin.submit(&self.regs, cmd);
+        let deadline = timer_now_as_micros() + 1_000_000;
+        loop {
+            if let Some(c) = admin.pop(&self.regs) {
+                if c.is_success() {
+                    return Ok(c.result);
+                }
+                warn!(
+                    "{}: admin command {:#x} failed, status {:#x}",
+                    self.name,
+                    cmd.opcode,
+                    c.status >> 1
+                );
+                return Err(DeviceError::IoError);
+            }
+            if timer_now_as_micros() > deadline {
+                warn!("{}: admin command {:#x} timeout", self.name, cmd.opcode);
+                return Err(DeviceError::NotReady);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn identify(&self, cns: u32, nsid: u32, data: &mut [u8]) -> DeviceResult {
+        let buf = self.admin_buf.lock();
+        let cmd = NvmeCommand {
+            opcode: ADMIN_IDENTIFY,
+            nsid,
+            prp1: buf.paddr() as u64,
+            cdw10: cns,
+            ..Default::default()
+        };
+        self.admin_command(cmd)?;
+        let len = data.len().min(PAGE_SIZE);
+        let src = unsafe { core::slice::from_raw_parts(buf.vaddr() as *const u8, len) };
+        data[..len].copy_from_slice(src);
+        Ok(())
+    }
+
+    fn create_io_queue(&self) -> DeviceResult {
+        let io = self.io.lock();
+        let qsize = (io.size as u32 - 1) << 16;
+        // physically contiguous, interrupts enabled on vector 0
+        let cmd = NvmeCommand {
+            opcode: ADMIN_CREATE_IO_CQ,
+            prp1: io.cq.paddr() as u64,
+            cdw10: qsize | io.qid as u32,
+            cdw11: (1 << 1) | 1,
+            ..Default::default()
+        };
+        self.admin_command(cmd)?;
+        let cmd = NvmeCommand {
+            opcode: ADMIN_CREATE_IO_SQ,
+            prp1: io.sq.paddr() as u64,
+            cdw10: qsize | io.qid as u32,
+            cdw11: ((io.qid as u32) << 16) | 1,
+            ..Default::default()
+        };
+        self.admin_command(cmd)?;
+        Ok(())
+    }
+
+    /// Complete the I/O commands the controller has finished.
+    fn reap(&self) {
+        let mut completions = Vec::new();
+        {
+            let mut io = self.io.lock();
+            while let Some(c) = io.pop(&self.regs) {
+                completions.push(c);
+            }
+        }
+        if completions.is_empty() {
+            return;
+        }
+        let mut io_done = self.io_done.lock();
+        for c in completions {
+            if let Some(slot) = io_done.get_mut(c.cid as usize) {
+                *slot = Some(c);
+            }
+        }
+    }
+
+    /// Run an I/O command, with at most one page of data read into or
+    /// written from `data`.
+    fn io_command(&self, mut cmd: NvmeCommand, data: IoData) -> DeviceResult {
+        let slot = loop {
+            if let Some(slot) = self.free_slots.lock().pop() {
+                break slot;
+            }
+            self.reap();
+            core::hint::spin_loop();
+        };
+        let buf = self.io_buffers.vaddr() + slot as usize * PAGE_SIZE;
+        if let IoData::Write(data) = &data {
+            unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, data.len()) }
+                .copy_from_slice(data);
+        }
+        self.io_done.lock()[slot as usize] = None;
+        cmd.cid = slot;
+        cmd.prp1 = (self.io_buffers.paddr() + slot as usize * PAGE_SIZE) as u64;
+        self.io.lock().submit(&self.regs, cmd);
+        let completion = loop {
+            if let Some(c) = self.io_done.lock()[slot as usize].take() {
+                break c;
+            }
+            self.reap();
+            core::hint::spin_loop();
+        };
+        if let IoData::Read(data) = data {
+            let src = unsafe { core::slice::from_raw_parts(buf as *const u8, data.len()) };
+            data.copy_from_slice(src);
+        }
+        self.free_slots.lock().push(slot);
+        if completion.is_success() {
+            Ok(())
+        } else {
+            warn!(
+                "{}: I/O command {:#x} failed, status {:#x}",
+                self.name,
+                cmd.opcode,
+                completion.status >> 1
+            );
+            Err(DeviceError::IoError)
+        }
+    }
+
+    /// The namespaces of the controller, as block devices.
+    pub fn namespaces(self: &Arc<Self>) -> DeviceResult<Vec<Arc<NvmeNamespace>>> {
+        let mut data = vec![0u8; PAGE_SIZE];
+        self.identify(IDENTIFY_CONTROLLER, 0, &mut data)?;
+        let nn = u32::from_le_bytes(data[516..520].try_into().unwrap());
+        let model = String::from_utf8_lossy(&data[24..64]);
+        info!("{}: {}, {} namespaces", self.name, model.trim(), nn);
+
+        let mut namespaces = Vec::new();
+        for nsid in 1..=nn {
+            self.identify(IDENTIFY_NAMESPACE, nsid, &mut data)?;
+            let blocks = u64::from_le_bytes(data[0..8].try_into().unwrap());
+            if blocks == 0 {
+                // inactive
+                continue;
+            }
+            let flbas = (data[26] & 0xf) as usize;
+            let lbaf =
+                u32::from_le_bytes(data[128 + flbas * 4..132 + flbas * 4].try_into().unwrap());
+            let lba_size = 1usize << ((lbaf >> 16) & 0xff);
+            if lba_size != BLOCK_SIZE {
+                warn!(
+                    "{}n{}: LBA size {} not supported, skipped",
+                    self.name, nsid, lba_size
+                );
+                continue;
+            }
+            info!(
+                "{}n{}: {} blocks of {} bytes",
+                self.name, nsid, blocks, lba_size
+            );
+            namespaces.push(Arc::new(NvmeNamespace {
+                ctrl: self.clone(),
+                name: format!("{}n{}", self.name, nsid),
+                nsid,
+                blocks: blocks as usize,
+            }));
+        }
+        Ok(namespaces)
+    }
+}
+
+enum IoData<'a> {
+    None,
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+impl Scheme for Nvme {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn handle_irq(&self, irq: usize) {
+        if irq == self.irq {
+            self.reap();
+        }
+    }
+}
+
+/// A namespace of an NVMe controller, with 512-byte logical blocks.
+pub struct NvmeNamespace {
+    ctrl: Arc<Nvme>,
+    name: String,
+    nsid: u32,
+    blocks: usize,
+}
+
+impl NvmeNamespace {
+    /// The number of blocks.
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    fn rw(&self, opcode: u8, block_id: usize, data: IoData) -> DeviceResult {
+        let len = match &data {
+            IoData::Read(buf) => buf.len(),
+            IoData::Write(buf) => buf.len(),
+            IoData::None => 0,
+        };
+        if len == 0 || len % BLOCK_SIZE != 0 || len > PAGE_SIZE {
+            return Err(DeviceError::InvalidParam);
+        }
+        let count = len / BLOCK_SIZE;
+        if block_id + count > self.blocks {
+            return Err(DeviceError::InvalidParam);
+        }
+        let cmd = NvmeCommand {
+            opcode,
+            nsid: self.nsid,
+            cdw10: block_id as u32,
+            cdw11: (block_id as u64 >> 32) as u32,
+            // 0's based
+            cdw12: count as u32 - 1,
+            ..Default::default()
+        };
+        self.ctrl.io_command(cmd, data)
+    }
+}
+
+impl Scheme for NvmeNamespace {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl BlockScheme for NvmeNamespace {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult {
+        self.rw(IO_READ, block_id, IoData::Read(buf))
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> DeviceResult {
+        self.rw(IO_WRITE, block_id, IoData::Write(buf))
+    }
+
+    fn flush(&self) -> DeviceResult {
+        let cmd = NvmeCommand {
+            opcode: IO_FLUSH,
+            nsid: self.nsid,
+            ..Default::default()
+        };
+        self.ctrl.io_command(cmd, IoData::None)
+    }
+}
@@ -0,0 +1,523 @@
+//! Partition tables: MBR, with the logical partitions in its extended
+//! partition, and GPT.
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::convert::TryInto;
+
+use crate::scheme::{BlockFuture, BlockScheme, Scheme};
+use crate::{DeviceError, DeviceResult};
+
+/// Size of a block (sector) in bytes.
+const BLOCK_SIZE: usize = 512;
+
+/// MBR system IDs of extended partitions.
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+/// MBR system ID of the protective partition of GPT.
+const MBR_GPT_PROTECTIVE: u8 = 0xEE;
+/// Number of the first logical partition, as Linux does.
+const FIRST_LOGICAL: usize = 5;
+/// Maximum number of logical partitions, so that a looping chain of
+/// extended boot records ends.
+const MAX_LOGICAL: usize = 128;
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+/// Maximum size of the GPT partition entry array, 128 entries of 128 bytes
+/// are usual.
+const GPT_MAX_ENTRIES_SIZE: usize = 1 << 20;
+
+/// The type of a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The system ID of an MBR partition.
+    Mbr(u8),
+    /// The partition type GUID of a GPT partition, in its on-disk byte order.
+    Gpt([u8; 16]),
+}
+
+impl PartitionType {
+    /// GPT partition type of EFI system partitions.
+    pub const GPT_EFI_SYSTEM: [u8; 16] = guid(
+        0xC12A_7328,
+        0xF81F,
+        0x11D2,
+        [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+    );
+    /// GPT partition type of Microsoft basic data partitions, usually FAT.
+    pub const GPT_BASIC_DATA: [u8; 16] = guid(
+        0xEBD0_A0A2,
+        0xB9E5,
+        0x4433,
+        [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+    );
+    /// GPT partition type of Linux file systems.
+    pub const GPT_LINUX_FS: [u8; 16] = guid(
+        0x0FC6_3DAF,
+        0x8483,
+        0x4772,
+        [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4],
+    );
+
+    /// Whether the partition is meant to hold a FAT file system.
+    pub fn is_fat(&self) -> bool {
+        match self {
+            Self::Mbr(id) => matches!(id, 0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E | 0xEF),
+            Self::Gpt(guid) => *guid == Self::GPT_EFI_SYSTEM || *guid == Self::GPT_BASIC_DATA,
+        }
+    }
+
+    /// Whether the partition is meant to hold a Linux file system.
+    pub fn is_linux(&self) -> bool {
+        match self {
+            Self::Mbr(id) => *id == 0x83,
+            Self::Gpt(guid) => *guid == Self::GPT_LINUX_FS,
+        }
+    }
+}
+
+/// A partition of a block device, which is a block device itself.
+pub struct Partition {
+    disk: Arc<dyn BlockScheme>,
+    number: usize,
+    start: usize,
+    blocks: usize,
+    part_type: PartitionType,
+    name: String,
+}
+
+impl Partition {
+    fn new(
+        disk: &Arc<dyn BlockScheme>,
+        number: usize,
+        start: usize,
+        blocks: usize,
+        part_type: PartitionType,
+    ) -> Self {
+        Self {
+            disk: disk.clone(),
+            number,
+            start,
+            blocks,
+            part_type,
+            name: format!("{}-part{}", disk.name(), number),
+        }
+    }
+
+    /// The number of the partition, e.g. 1 for `/dev/vda1`.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// The first block of the partition on the disk.
+    pub fn start_block(&self) -> usize {
+        self.start
+    }
+
+    /// The number of blocks of the partition.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks
+    }
+
+    /// The type of the partition.
+    pub fn part_type(&self) -> PartitionType {
+        self.part_type
+    }
+
+    /// The disk the partition is on.
+    pub fn disk(&self) -> &Arc<dyn BlockScheme> {
+        &self.disk
+    }
+
+    fn check_range(&self, block_id: usize, len: usize) -> DeviceResult {
+        let blocks = (len + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        if block_id + blocks > self.blocks {
+            return Err(DeviceError::InvalidParam);
+        }
+        Ok(())
+    }
+}
+
+impl Scheme for Partition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl BlockScheme for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult {
+        self.check_range(block_id, buf.len())?;
+        self.disk.read_block(self.start + block_id, buf)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> DeviceResult {
+        self.check_range(block_id, buf.len())?;
+        self.disk.write_block(self.start + block_id, buf)
+    }
+
+    fn flush(&self) -> DeviceResult {
+        self.disk.flush()
+    }
+
+    fn read_block_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> BlockFuture<'a> {
+        if let Err(err) = self.check_range(block_id, buf.len()) {
+            return Box::pin(core::future::ready(Err(err)));
+        }
+        self.disk.read_block_async(self.start + block_id, buf)
+    }
+
+    fn write_block_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> BlockFuture<'a> {
+        if let Err(err) = self.check_range(block_id, buf.len()) {
+            return Box::pin(core::future::ready(Err(err)));
+        }
+        self.disk.write_block_async(self.start + block_id, buf)
+    }
+}
+
+/// Read the partition table of `disk`, ordered by partition number.
+///
+/// Returns an empty list if the disk is not partitioned.
+pub fn scan_partitions(disk: &Arc<dyn BlockScheme>) -> DeviceResult<Vec<Arc<Partition>>> {
+    let mut mbr = [0u8; BLOCK_SIZE];
+    disk.read_block(0, &mut mbr)?;
+    let entries = match mbr_entries(&mbr) {
+        Some(entries) => entries,
+        None => return Ok(Vec::new()),
+    };
+    if entries.iter().any(|e| e.sys_id == MBR_GPT_PROTECTIVE) {
+        return scan_gpt(disk);
+    }
+
+    let mut partitions = Vec::new();
+    let mut extended = None;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.sys_id == 0 || entry.blocks == 0 {
+            continue;
+        }
+        if MBR_EXTENDED.contains(&entry.sys_id) {
+            extended.get_or_insert(entry.start);
+            continue;
+        }
+        partitions.push(Arc::new(Partition::new(
+            disk,
+            i + 1,
+            entry.start,
+            entry.blocks,
+            PartitionType::Mbr(entry.sys_id),
+        )));
+    }
+
+    // logical partitions are in a chain of extended boot records, whose
+    // first entry is relative to the record, and the second one, the link
+    // to the next record, relative to the extended partition
+    if let Some(ext_start) = extended {
+        let mut ebr_start = ext_start;
+        let mut ebr = [0u8; BLOCK_SIZE];
+        for number in FIRST_LOGICAL..FIRST_LOGICAL + MAX_LOGICAL {
+            disk.read_block(ebr_start, &mut ebr)?;
+            let entries = match mbr_entries(&ebr) {
+                Some(entries) => entries,
+                None => {
+                    warn!("invalid extended boot record at block {}", ebr_start);
+                    break;
+                }
+            };
+            let (logical, next) = (entries[0], entries[1]);
+            if logical.sys_id != 0 && logical.blocks != 0 {
+                partitions.push(Arc::new(Partition::new(
+                    disk,
+                    number,
+                    ebr_start + logical.start,
+                    logical.blocks,
+                    PartitionType::Mbr(logical.sys_id),
+                )));
+            }
+            if !MBR_EXTENDED.contains(&next.sys_id) || next.start == 0 {
+                break;
+            }
+            ebr_start = ext_start + next.start;
+        }
+    }
+    Ok(partitions)
+}
+
+/// An entry of the partition table in an MBR.
+#[derive(Debug, Clone, Copy)]
+struct MbrEntry {
+    sys_id: u8,
+    start: usize,
+    blocks: usize,
+}
+
+/// Parse the 4 entries of an MBR, or returns `None` if there is no valid one.
+fn mbr_entries(mbr: &[u8; BLOCK_SIZE]) -> Option<[MbrEntry; 4]> {
+    if mbr[510..512] != [0x55, 0xAA] {
+        return None;
+    }
+    let mut entries = [MbrEntry {
+        sys_id: 0,
+        start: 0,
+        blocks: 0,
+    }; 4];
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let raw = &mbr[446 + i * 16..446 + (i + 1) * 16];
+        // the boot indicator, which is not 0 or 0x80 in boot sectors of
+        // unpartitioned disks, e.g. a FAT file system
+        if raw[0] & 0x7F != 0 {
+            return None;
+        }
+        *entry = MbrEntry {
+            sys_id: raw[4],
+            start: get_u32(raw, 8) as usize,
+            blocks: get_u32(raw, 12) as usize,
+        };
+    }
+    Some(entries)
+}
+
+fn scan_gpt(disk: &Arc<dyn BlockScheme>) -> DeviceResult<Vec<Arc<Partition>>> {
+    let mut header = [0u8; BLOCK_SIZE];
+    disk.read_block(1, &mut header)?;
+    let header_size = get_u32(&header, 12) as usize;
+    if &header[..8] != GPT_SIGNATURE || !(92..=BLOCK_SIZE).contains(&header_size) {
+        warn!("no valid GPT header found");
+        return Ok(Vec::new());
+    }
+    let header_crc = get_u32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        warn!("GPT header checksum mismatch");
+        return Ok(Vec::new());
+    }
+    let entries_lba = get_u64(&header, 72) as usize;
+    let num_entries = get_u32(&header, 80) as usize;
+    let entry_size = get_u32(&header, 84) as usize;
+    let entries_size = num_entries * entry_size;
+    if entry_size < 128 || entry_size % 8 != 0 || entries_size > GPT_MAX_ENTRIES_SIZE {
+        warn!(
+            "invalid GPT entries: {} * {} bytes",
+            num_entries, entry_size
+        );
+        return Ok(Vec::new());
+    }
+
+    let mut entries = vec![0u8; (entries_size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE];
+    for (i, block) in entries.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+        disk.read_block(entries_lba + i, block)?;
+    }
+    if crc32(&entries[..entries_size]) != get_u32(&header, 88) {
+        warn!("GPT entries checksum mismatch");
+        return Ok(Vec::new());
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries[..entries_size].chunks_exact(entry_size).enumerate() {
+        let type_guid: [u8; 16] = entry[..16].try_into().unwrap();
+        let first = get_u64(entry, 32) as usize;
+        let last = get_u64(entry, 40) as usize;
+        if type_guid == [0; 16] || last < first {
+            continue;
+        }
+        partitions.push(Arc::new(Partition::new(
+            disk,
+            i + 1,
+            first,
+            last - first + 1,
+            PartitionType::Gpt(type_guid),
+        )));
+    }
+    Ok(partitions)
+}
+
+/// Encode a GUID in its on-disk byte order, where the first three fields are
+/// little-endian.
+const fn guid(a: u32, b: u16, c: u16, d: [u8; 8]) -> [u8; 16] {
+    let a = a.to_le_bytes();
+    let b = b.to_le_bytes();
+    let c = c.to_le_bytes();
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+        d[7],
+    ]
+}
+
+/// CRC-32 used by GPT, the one of zlib.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn get_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A disk image in memory.
+    struct MemDisk(Vec<u8>);
+
+    impl Scheme for MemDisk {
+        fn name(&self) -> &str {
+            "mem"
+        }
+    }
+
+    impl BlockScheme for MemDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult {
+            let start = block_id * BLOCK_SIZE;
+            let data = self.0.get(start..start + buf.len());
+            buf.copy_from_slice(data.ok_or(DeviceError::InvalidParam)?);
+            Ok(())
+        }
+
+        fn write_block(&self, _block_id: usize, _buf: &[u8]) -> DeviceResult {
+            Err(DeviceError::NotSupported)
+        }
+
+        fn flush(&self) -> DeviceResult {
+            Ok(())
+        }
+    }
+
+    fn set_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u64(buf: &mut [u8], offset: usize, value: u64) {
+        buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Write the entry `index` of the MBR or EBR at `block`.
+    fn set_mbr_entry(
+        image: &mut [u8],
+        block: usize,
+        index: usize,
+        sys_id: u8,
+        start: u32,
+        blocks: u32,
+    ) {
+        let record = &mut image[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE];
+        let entry = 446 + index * 16;
+        record[entry + 4] = sys_id;
+        set_u32(record, entry + 8, start);
+        set_u32(record, entry + 12, blocks);
+        record[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    fn scan(image: Vec<u8>) -> Vec<Arc<Partition>> {
+        let disk: Arc<dyn BlockScheme> = Arc::new(MemDisk(image));
+        scan_partitions(&disk).unwrap()
+    }
+
+    #[test]
+    fn crc32_vector() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn unpartitioned() {
+        assert!(scan(vec![0; 4 * BLOCK_SIZE]).is_empty());
+        // a boot sector of a FAT file system has a signature too
+        let mut image = vec![0; 4 * BLOCK_SIZE];
+        image[446] = 0xEB;
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        assert!(scan(image).is_empty());
+    }
+
+    #[test]
+    fn mbr_extended() {
+        let mut image = vec![0; 64 * BLOCK_SIZE];
+        set_mbr_entry(&mut image, 0, 0, 0x83, 2, 8);
+        set_mbr_entry(&mut image, 0, 1, 0x05, 16, 40);
+        // the first logical partition at block 17, linked to the next record
+        // at block 24
+        set_mbr_entry(&mut image, 16, 0, 0x0C, 1, 4);
+        set_mbr_entry(&mut image, 16, 1, 0x05, 8, 16);
+        // the last one at block 26
+        set_mbr_entry(&mut image, 24, 0, 0x83, 2, 6);
+        image[17 * BLOCK_SIZE] = 0x42;
+
+        let parts = scan(image);
+        let layout: Vec<_> = parts
+            .iter()
+            .map(|p| (p.number(), p.start_block(), p.num_blocks(), p.part_type()))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (1, 2, 8, PartitionType::Mbr(0x83)),
+                (5, 17, 4, PartitionType::Mbr(0x0C)),
+                (6, 26, 6, PartitionType::Mbr(0x83)),
+            ]
+        );
+        assert!(parts[0].part_type().is_linux() && parts[1].part_type().is_fat());
+        assert_eq!(parts[1].name(), "mem-part5");
+
+        // blocks are relative to the partition, which ends at its size
+        let mut buf = [0u8; BLOCK_SIZE];
+        parts[1].read_block(0, &mut buf).unwrap();
+        assert_eq!(buf[0], 0x42);
+        assert!(matches!(
+            parts[1].read_block(4, &mut buf),
+            Err(DeviceError::InvalidParam)
+        ));
+    }
+
+    /// A disk of 64 blocks with a GPT of 4 entries at block 2, and a Linux
+    /// file system partition in the entry 2.
+    fn gpt_image() -> Vec<u8> {
+        let mut image = vec![0; 64 * BLOCK_SIZE];
+        set_mbr_entry(&mut image, 0, 0, MBR_GPT_PROTECTIVE, 1, 63);
+
+        let entries = &mut image[2 * BLOCK_SIZE..3 * BLOCK_SIZE];
+        let entry = &mut entries[128..256];
+        entry[..16].copy_from_slice(&PartitionType::GPT_LINUX_FS);
+        set_u64(entry, 32, 34);
+        set_u64(entry, 40, 62);
+        let entries_crc = crc32(entries);
+
+        let header = &mut image[BLOCK_SIZE..2 * BLOCK_SIZE];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        set_u32(header, 12, 92);
+        set_u64(header, 72, 2);
+        set_u32(header, 80, 4);
+        set_u32(header, 84, 128);
+        set_u32(header, 88, entries_crc);
+        let header_crc = crc32(&header[..92]);
+        set_u32(header, 16, header_crc);
+        image
+    }
+
+    #[test]
+    fn gpt() {
+        let parts = scan(gpt_image());
+        assert_eq!(parts.len(), 1);
+        assert_eq!((parts[0].number(), parts[0].start_block()), (2, 34));
+        assert_eq!(parts[0].num_blocks(), 29);
+        assert!(parts[0].part_type().is_linux());
+    }
+
+    #[test]
+    fn gpt_bad_checksum() {
+        // a header changed after its checksum is rejected
+        let mut image = gpt_image();
+        image[BLOCK_SIZE + 80] = 8;
+        assert!(scan(image).is_empty());
+
+        // and so are the entries
+        let mut image = gpt_image();
+        image[2 * BLOCK_SIZE + 128 + 40] = 63;
+        assert!(scan(image).is_empty());
+    }
+}
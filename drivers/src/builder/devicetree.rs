@@ -24,7 +24,7 @@ use crate::{
     },
     Device, DeviceError, DeviceResult, VirtAddr,
 };
-use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
 
 const MODULE: &str = "device-tree";
 
@@ -60,7 +60,13 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
     /// Parse the device tree from root, and returns an array of [`Device`] it found.
     pub fn build(&self) -> DeviceResult<Vec<Device>> {
         let mut intc_map = BTreeMap::new(); // phandle -> intc
+        let mut hart_map = BTreeMap::new(); // phandle -> hart ID of local intc
         let mut dev_list = Vec::new(); // devices
+        let mut stdout = None; // index of the console UART
+        let stdout_name = self
+            .dt
+            .stdout_path()
+            .and_then(|path| path.rsplit('/').next());
 
         // 解析设备树
         self.dt.walk(&mut |node, comp, props| {
@@ -70,16 +76,20 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
             );
             // parse interrupt controller
             let res = if node.has_prop("interrupt-controller") {
-                self.parse_intc(node, comp, props).map(|(dev, intc)| {
-                    intc_map.insert(
-                        intc.phandle,
-                        Intc {
-                            index: dev_list.len(),
-                            cells: intc.interrupt_cells as _,
-                        },
-                    );
-                    dev
-                })
+                self.parse_intc(node, comp, props, &hart_map)
+                    .map(|(dev, intc)| {
+                        if let Some(hart_id) = props.hart_id {
+                            hart_map.insert(intc.phandle, hart_id as usize);
+                        }
+                        intc_map.insert(
+                            intc.phandle,
+                            Intc {
+                                index: dev_list.len(),
+                                cells: intc.interrupt_cells as _,
+                            },
+                        );
+                        vec![dev]
+                    })
             } else {
                 // parse other device
                 match comp {
@@ -87,16 +97,30 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
                     c if c.contains("virtio,mmio") => self.parse_virtio(node, props),
                     #[cfg(not(feature = "loopback"))]
                     c if c.contains("allwinner,sunxi-gmac") => {
-                        self.parse_ethernet(node, comp, props)
+                        self.parse_ethernet(node, comp, props).map(|dev| vec![dev])
+                    }
+                    c if c.contains("ns16550a")
+                        || c.contains("snps,dw-apb-uart")
+                        || c.contains("allwinner,sun20i-uart") =>
+                    {
+                        self.parse_uart(node, comp, props).map(|dev| vec![dev])
+                    }
+                    c if c.contains("allwinner,sun20i-d1-mmc") || c.contains("snps,dw-mshc") => {
+                        self.parse_mmc(node, comp, props).map(|dev| vec![dev])
                     }
-                    c if c.contains("ns16550a") || c.contains("allwinner,sun20i-uart") => {
-                        self.parse_uart(node, comp, props)
+                    c if c.contains("google,goldfish-rtc") => {
+                        self.parse_rtc(node, comp, props).map(|dev| vec![dev])
                     }
                     _ => Err(DeviceError::NotSupported),
                 }
             };
             match res {
-                Ok(dev) => dev_list.push(dev),
+                Ok(devs) => {
+                    if stdout_name == Some(node.name.as_str()) {
+                        stdout = Some(dev_list.len());
+                    }
+                    dev_list.extend(devs);
+                }
                 Err(DeviceError::NotSupported) => {}
                 Err(err) => warn!("{MODULE}: failed to parsing node {:?}: {err:?}", node.name),
             }
@@ -121,6 +145,13 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
                         warn!("{MODULE}: node with phandle {phandle:#x} is not an interrupt-controller");
                         return Err(DeviceError::InvalidParam);
                     }
+                } else if let Some(cells) = self
+                    .dt
+                    .find_by_phandle(*phandle)
+                    .and_then(|intc| intc.prop_u32("#interrupt-cells").ok())
+                {
+                    // the interrupt parent is disabled, as the S7 hart of FU540
+                    extended = &extended[1 + cells as usize..];
                 } else {
                     warn!(
                         "{MODULE}: no such node with phandle {phandle:#x} as the interrupt-parent"
@@ -130,6 +161,12 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
             }
         }
 
+        // 控制台串口放在最前
+        if let Some(index) = stdout {
+            let console = dev_list.remove(index);
+            dev_list.insert(0, console);
+        }
+
         // 丢弃中断信息
         Ok(dev_list.into_iter().map(|(dev, _)| dev).collect())
     }
@@ -146,6 +183,7 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
         node: &Node,
         comp: &StringList,
         props: &InheritProps,
+        hart_map: &BTreeMap<u32, usize>,
     ) -> DeviceResult<(DevWithInterrupt, IntcProps)> {
         let phandle = node
             .prop_u32("phandle")
@@ -162,9 +200,25 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
         use crate::irq::*;
         let dev = Device::Irq(match comp {
             #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-            c if c.contains("riscv,cpu-intc") => Arc::new(riscv::Intc::new()),
+            c if c.contains("riscv,cpu-intc") => {
+                let hart_id = props.hart_id.ok_or(DeviceError::InvalidParam)?;
+                Arc::new(riscv::Intc::new(hart_id as usize))
+            }
             #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-            c if c.contains("riscv,plic0") => Arc::new(riscv::Plic::new(base_vaddr?)),
+            c if c.contains("riscv,plic0")
+                || c.contains("sifive,plic-1.0.0")
+                || c.contains("thead,c900-plic") =>
+            {
+                // each context takes the phandle of a local intc and the cause
+                let s_ext = riscv::ScauseIntCode::SupervisorExternal as u32;
+                let contexts = interrupts_extended
+                    .chunks_exact(2)
+                    .enumerate()
+                    .filter(|(_, ctx)| ctx[1] == s_ext)
+                    .filter_map(|(i, ctx)| hart_map.get(&ctx[0]).map(|&hart_id| (hart_id, i)))
+                    .collect();
+                Arc::new(riscv::Plic::new(base_vaddr?, contexts))
+            }
             _ => return Err(DeviceError::NotSupported),
         });
 
@@ -178,8 +232,15 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
     }
 
     /// Parse nodes for virtio devices over MMIO.
+    ///
+    /// A console with multiple ports gives a device for each port, and the
+    /// interrupt goes to the first one.
     #[cfg(feature = "virtio")]
-    fn parse_virtio(&self, node: &Node, props: &InheritProps) -> DeviceResult<DevWithInterrupt> {
+    fn parse_virtio(
+        &self,
+        node: &Node,
+        props: &InheritProps,
+    ) -> DeviceResult<Vec<DevWithInterrupt>> {
         use crate::virtio::*;
         use virtio_drivers::{DeviceType, VirtIOHeader};
 
@@ -203,11 +264,25 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
             DeviceType::Block => Device::Block(Arc::new(VirtIoBlk::new(header)?)),
             DeviceType::GPU => Device::Display(Arc::new(VirtIoGpu::new(header)?)),
             DeviceType::Input => Device::Input(Arc::new(VirtIoInput::new(header)?)),
-            DeviceType::Console => Device::Uart(Arc::new(VirtIoConsole::new(header)?)),
+            DeviceType::Console => {
+                let console = VirtIoConsole::new(header)?;
+                let ports = console.other_ports();
+                let mut devs = vec![(Device::Uart(Arc::new(console)), interrupts_extended)];
+                devs.extend(
+                    ports
+                        .into_iter()
+                        .map(|port| (Device::Uart(Arc::new(port)), Vec::new())),
+                );
+                return Ok(devs);
+            }
+            DeviceType::Network => {
+                Device::Net(Arc::new(VirtIoNet::new(header, node.name.clone())?))
+            }
+            DeviceType::EntropySource => Device::Rng(Arc::new(VirtIoRng::new(header)?)),
             _ => return Err(DeviceError::NotSupported),
         };
 
-        Ok((dev, interrupts_extended))
+        Ok(vec![(dev, interrupts_extended)])
     }
 
     /// Parse nodes for Ethernet devices.
@@ -254,10 +329,20 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
                 .ok_or(DeviceError::NoResources)
         });
 
+        let reg_io_width = node.prop_u32("reg-io-width").unwrap_or(1);
+        let reg_shift = node.prop_u32("reg-shift").unwrap_or(0);
+
         use crate::uart::*;
         let dev = Device::Uart(match comp {
-            c if c.contains("ns16550a") => {
-                Arc::new(unsafe { Uart16550Mmio::<u8>::new(base_vaddr?) })
+            c if c.contains("ns16550a") || c.contains("snps,dw-apb-uart") => {
+                match (reg_io_width, reg_shift) {
+                    (1, 0) => Arc::new(unsafe { Uart16550Mmio::<u8>::new(base_vaddr?) }),
+                    (4, 2) => Arc::new(unsafe { Uart16550Mmio::<u32>::new(base_vaddr?) }),
+                    _ => {
+                        warn!("{MODULE}: unsupported UART registers: reg-io-width={reg_io_width}, reg-shift={reg_shift}");
+                        return Err(DeviceError::NotSupported);
+                    }
+                }
             }
             #[cfg(feature = "board-d1")]
             c if c.contains("allwinner,sun20i-uart") => Arc::new(UartAllwinner::new(base_vaddr?)),
@@ -266,4 +351,61 @@ impl<M: IoMapper> DevicetreeDriverBuilder<M> {
 
         Ok((dev, interrupts_extended))
     }
+
+    /// Parse nodes for MMC host controllers, with an SD card in the slot.
+    fn parse_mmc(
+        &self,
+        node: &Node,
+        comp: &StringList,
+        props: &InheritProps,
+    ) -> DeviceResult<DevWithInterrupt> {
+        let (paddr, size) = parse_reg(node, props)?;
+        let base_vaddr = self
+            .io_mapper
+            .query_or_map(paddr as usize, size as usize)
+            .ok_or(DeviceError::NoResources)?;
+        let bus_width = node.prop_u32("bus-width").unwrap_or(1);
+
+        use crate::block::mmc::*;
+        let card = match comp {
+            #[cfg(feature = "board-d1")]
+            c if c.contains("allwinner,sun20i-d1-mmc") => {
+                let clock_in = d1_smhc_setup(paddr as usize, |paddr, size| {
+                    self.io_mapper.query_or_map(paddr, size)
+                })?;
+                MmcCard::new(base_vaddr, MmcHostKind::Sunxi, clock_in, bus_width)?
+            }
+            c if c.contains("snps,dw-mshc") => {
+                let clock_in = node.prop_u32("clock-frequency").unwrap_or(100_000_000);
+                MmcCard::new(base_vaddr, MmcHostKind::DesignWare, clock_in, bus_width)?
+            }
+            _ => return Err(DeviceError::NotSupported),
+        };
+
+        // polled, without interrupts
+        Ok((Device::Block(Arc::new(card)), Vec::new()))
+    }
+
+    /// Parse nodes for real-time clocks.
+    fn parse_rtc(
+        &self,
+        node: &Node,
+        comp: &StringList,
+        props: &InheritProps,
+    ) -> DeviceResult<DevWithInterrupt> {
+        let base_vaddr = parse_reg(node, props).and_then(|(paddr, size)| {
+            self.io_mapper
+                .query_or_map(paddr as usize, size as usize)
+                .ok_or(DeviceError::NoResources)
+        });
+
+        use crate::rtc::*;
+        let dev = Device::Rtc(match comp {
+            c if c.contains("google,goldfish-rtc") => Arc::new(GoldfishRtc::new(base_vaddr?)),
+            _ => return Err(DeviceError::NotSupported),
+        });
+
+        // the alarm is not used
+        Ok((dev, Vec::new()))
+    }
 }
@@ -1,7 +1,10 @@
 use super::{phys_to_virt, PAGE_SIZE};
 use crate::builder::IoMapper;
+use crate::prelude::{IrqPolarity, IrqTriggerMode};
+use crate::scheme::{IrqScheme, Scheme};
 use crate::{Device, DeviceError, DeviceResult};
-use alloc::{format, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, format, sync::Arc, vec, vec::Vec};
+use core::ops::Range;
 use pci::*;
 
 const PCI_COMMAND: u16 = 0x04;
@@ -16,7 +19,12 @@ const _PCI_MSI_UPPER_ADDR: u16 = 0x08;
 const PCI_MSI_DATA_32: u16 = 0x08;
 const PCI_MSI_DATA_64: u16 = 0x0C;
 
+const PCI_MSIX_TABLE: u16 = 0x04;
+const PCI_MSIX_ENABLE: u32 = 1 << 31;
+const PCI_MSIX_FUNCTION_MASK: u32 = 1 << 30;
+
 const PCI_CAP_ID_MSI: u8 = 0x05;
+const PCI_CAP_ID_MSIX: u8 = 0x11;
 
 struct PortOpsImpl;
 
@@ -89,7 +97,10 @@ impl PortOps for PortOpsImpl {
 
 /// Enable the pci device and its interrupt
 /// Return assigned MSI interrupt number when applicable
-unsafe fn enable(loc: Location, paddr: u64) -> Option<usize> {
+///
+/// With `msi_vector`, the MSI is delivered to it, otherwise to a vector
+/// assigned here.
+unsafe fn enable(loc: Location, paddr: u64, msi_vector: Option<usize>) -> Option<usize> {
     let ops = &PortOpsImpl;
     //let am = CSpaceAccessMethod::IO;
     let am = PCI_ACCESS;
@@ -124,16 +135,21 @@ unsafe fn enable(loc: Location, paddr: u64) -> Option<usize> {
             // 0 is (usually) the apic id of the bsp.
             //am.write32(ops, loc, cap_ptr + PCI_MSI_ADDR, 0xfee00000 | (0 << 12));
             am.write32(ops, loc, cap_ptr + PCI_MSI_ADDR, 0xfee00000);
-            MSI_IRQ += 1;
-            let irq = MSI_IRQ;
+            let (irq, vector) = match msi_vector {
+                Some(vector) => (vector as u32, vector as u32),
+                None => {
+                    MSI_IRQ += 1;
+                    // we offset all our irq numbers by 32
+                    (MSI_IRQ, MSI_IRQ + 32)
+                }
+            };
             assigned_irq = Some(irq as usize);
-            // we offset all our irq numbers by 32
             if (orig_ctrl >> 16) & (1 << 7) != 0 {
                 // 64bit
-                am.write32(ops, loc, cap_ptr + PCI_MSI_DATA_64, irq + 32);
+                am.write32(ops, loc, cap_ptr + PCI_MSI_DATA_64, vector);
             } else {
                 // 32bit
-                am.write32(ops, loc, cap_ptr + PCI_MSI_DATA_32, irq + 32);
+                am.write32(ops, loc, cap_ptr + PCI_MSI_DATA_32, vector);
             }
 
             // enable MSI interrupt, assuming 64bit for now
@@ -161,7 +177,144 @@ unsafe fn enable(loc: Location, paddr: u64) -> Option<usize> {
     assigned_irq
 }
 
-pub fn init_driver(dev: &PCIDevice, mapper: &Option<Arc<dyn IoMapper>>) -> DeviceResult<Device> {
+/// Find the capability `id` of the function at `loc`.
+unsafe fn find_capability(loc: Location, id: u8) -> Option<u16> {
+    let ops = &PortOpsImpl;
+    let am = PCI_ACCESS;
+    let mut cap_ptr = am.read8(ops, loc, PCI_CAP_PTR) as u16;
+    while cap_ptr > 0 {
+        if am.read8(ops, loc, cap_ptr) == id {
+            return Some(cap_ptr);
+        }
+        cap_ptr = am.read8(ops, loc, cap_ptr + 1) as u16;
+    }
+    None
+}
+
+/// Enable MSI-X, with the first entry of the table delivered to `vector`.
+///
+/// For the devices with MSI-X but no MSI, like NVMe controllers.
+unsafe fn enable_msix(dev: &PCIDevice, mapper: &Option<Arc<dyn IoMapper>>, vector: usize) -> bool {
+    let ops = &PortOpsImpl;
+    let am = PCI_ACCESS;
+    let cap_ptr = match find_capability(dev.loc, PCI_CAP_ID_MSIX) {
+        Some(cap_ptr) => cap_ptr,
+        None => return false,
+    };
+    let table = am.read32(ops, dev.loc, cap_ptr + PCI_MSIX_TABLE);
+    let (bir, offset) = ((table & 0x7) as usize, (table & !0x7) as usize);
+    let table_base = match dev.bars.get(bir) {
+        Some(Some(BAR::Memory(addr, _, _, _))) => *addr as usize + offset,
+        _ => return false,
+    };
+    if let Some(m) = mapper {
+        m.query_or_map(table_base & !(PAGE_SIZE - 1), PAGE_SIZE);
+    }
+    // entry 0: message address, upper address, data, vector control
+    let entry = phys_to_virt(table_base) as *mut u32;
+    entry.write_volatile(0xfee00000);
+    entry.add(1).write_volatile(0);
+    entry.add(2).write_volatile(vector as u32);
+    entry.add(3).write_volatile(0);
+
+    let ctrl = am.read32(ops, dev.loc, cap_ptr);
+    am.write32(
+        ops,
+        dev.loc,
+        cap_ptr,
+        (ctrl | PCI_MSIX_ENABLE) & !PCI_MSIX_FUNCTION_MASK,
+    );
+    let orig = am.read16(ops, dev.loc, PCI_COMMAND);
+    // PCI Interrupt Disable
+    am.write32(ops, dev.loc, PCI_COMMAND, (orig | 0x400) as u32);
+    debug!(
+        "MSI-X table at {:#x}, enabling vector {}",
+        table_base, vector
+    );
+    true
+}
+
+/// How the interrupt of a PCI function is delivered.
+enum PciIrq {
+    /// MSI to a vector allocated by the interrupt controller.
+    Msi(Range<usize>),
+    /// Legacy INTx on the global system interrupt set by the firmware.
+    Legacy(usize),
+    /// MSI to a vector not managed by an interrupt controller.
+    Unmanaged(usize),
+}
+
+impl PciIrq {
+    /// The IRQ number passed to [`Scheme::handle_irq`].
+    fn irq_num(&self) -> usize {
+        match self {
+            Self::Msi(block) => block.start,
+            Self::Legacy(gsi) => *gsi,
+            Self::Unmanaged(irq) => *irq,
+        }
+    }
+}
+
+/// Enable the device, and route its interrupt through `irq` when given.
+///
+/// MSI is preferred, then MSI-X, then the legacy interrupt.
+fn enable_irq(
+    dev: &PCIDevice,
+    paddr: u64,
+    mapper: &Option<Arc<dyn IoMapper>>,
+    irq: &Option<Arc<dyn IrqScheme>>,
+) -> Option<PciIrq> {
+    let irq = match irq {
+        Some(irq) => irq,
+        None => return unsafe { enable(dev.loc, paddr, None) }.map(PciIrq::Unmanaged),
+    };
+    let block = irq.msi_alloc_block(1).ok();
+    match (
+        unsafe { enable(dev.loc, paddr, block.as_ref().map(|b| b.start)) },
+        block,
+    ) {
+        (Some(_), Some(block)) => Some(PciIrq::Msi(block)),
+        (Some(vector), None) => Some(PciIrq::Unmanaged(vector)),
+        (None, Some(block)) if unsafe { enable_msix(dev, mapper, block.start) } => {
+            Some(PciIrq::Msi(block))
+        }
+        (None, block) => {
+            if let Some(block) = block {
+                irq.msi_free_block(block).ok();
+            }
+            // PCI interrupts are level-triggered and active low
+            let gsi = dev.pic_interrupt_line as usize;
+            irq.configure(gsi, IrqTriggerMode::Level, IrqPolarity::ActiveLow)
+                .ok();
+            Some(PciIrq::Legacy(gsi))
+        }
+    }
+}
+
+/// Deliver the interrupt of `dev` to its IRQ handler.
+fn register_irq(
+    irq: &Option<Arc<dyn IrqScheme>>,
+    pci_irq: &Option<PciIrq>,
+    dev: Arc<dyn Scheme>,
+) -> DeviceResult {
+    match (irq, pci_irq) {
+        (Some(irq), Some(PciIrq::Msi(block))) => {
+            let irq_num = block.start;
+            irq.msi_register_handler(block.clone(), 0, Box::new(move || dev.handle_irq(irq_num)))
+        }
+        (Some(irq), Some(PciIrq::Legacy(gsi))) => {
+            irq.register_device(*gsi, dev)?;
+            irq.unmask(*gsi)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn init_driver(
+    dev: &PCIDevice,
+    mapper: &Option<Arc<dyn IoMapper>>,
+    irq: &Option<Arc<dyn IrqScheme>>,
+) -> DeviceResult<Vec<Device>> {
     let name = format!("enp{}s{}f{}", dev.loc.bus, dev.loc.device, dev.loc.function);
     match (dev.id.vendor_id, dev.id.device_id) {
         (0x8086, 0x100e) | (0x8086, 0x100f) | (0x8086, 0x10d3) => {
@@ -178,24 +331,24 @@ pub fn init_driver(dev: &PCIDevice, mapper: &Option<Arc<dyn IoMapper>>) -> Devic
                 let addr = if addr == 0 { E1000_BASE as u64 } else { addr };
 
                 if let Some(m) = mapper {
-                    m.query_or_map(addr as usize, PAGE_SIZE * 8);
+                    m.query_or_map(addr as usize, len as usize);
                 }
-                let irq = unsafe { enable(dev.loc, addr) };
+                let pci_irq = enable_irq(dev, addr, mapper, irq);
                 let vaddr = phys_to_virt(addr as usize);
-                let dev = Device::Net(Arc::new(crate::net::e1000::init(
+                let net = Arc::new(crate::net::e1000::init(
                     name,
-                    irq.unwrap_or(0),
+                    pci_irq.as_ref().map_or(0, PciIrq::irq_num),
                     vaddr,
-                    len as usize,
                     0,
-                )?));
-                return Ok(dev);
+                )?);
+                register_irq(irq, &pci_irq, net.clone())?;
+                return Ok(vec![Device::Net(net)]);
             }
         }
         (0x8086, 0x10fb) => {
             // 82599ES 10-Gigabit SFI/SFP+ Network Connection
             if let Some(BAR::Memory(addr, _len, _, _)) = dev.bars[0] {
-                let irq = unsafe { enable(dev.loc, 0) };
+                let irq = unsafe { enable(dev.loc, 0, None) };
                 let vaddr = phys_to_virt(addr as usize);
                 info!("Found ixgbe dev {:#x}, irq: {:?}", vaddr, irq);
                 /*
@@ -242,6 +395,28 @@ pub fn init_driver(dev: &PCIDevice, mapper: &Option<Arc<dyn IoMapper>>) -> Devic
             return Err(DeviceError::NotSupported);
         }
     }
+    if dev.id.class == 0x01 && dev.id.subclass == 0x08 {
+        // Mass storage class
+        // NVM subclass
+        if let Some(BAR::Memory(addr, len, _, _)) = dev.bars[0] {
+            info!("Found NVMe dev {:?} BAR0 {:#x?}", dev, addr);
+            if let Some(m) = mapper {
+                m.query_or_map(addr as usize, len as usize);
+            }
+            let pci_irq = enable_irq(dev, 0, mapper, irq);
+            let vaddr = phys_to_virt(addr as usize);
+            let nvme = Arc::new(crate::block::nvme::Nvme::new(
+                vaddr,
+                pci_irq.as_ref().map_or(0, PciIrq::irq_num),
+            )?);
+            register_irq(irq, &pci_irq, nvme.clone())?;
+            return Ok(nvme
+                .namespaces()?
+                .into_iter()
+                .map(|ns| Device::Block(ns as _))
+                .collect());
+        }
+    }
 
     Err(DeviceError::NoResources)
 }
@@ -264,7 +439,13 @@ pub fn detach_driver(_loc: &Location) -> bool {
     false
 }
 
-pub fn init(mapper: Option<Arc<dyn IoMapper>>) -> DeviceResult<Vec<Device>> {
+/// Scan the PCI bus and initialize the drivers of the devices found.
+///
+/// With `irq`, the interrupts of the devices are registered to it.
+pub fn init(
+    mapper: Option<Arc<dyn IoMapper>>,
+    irq: Option<Arc<dyn IrqScheme>>,
+) -> DeviceResult<Vec<Device>> {
     let mapper_driver = if let Some(m) = mapper {
         m.query_or_map(PCI_BASE, PAGE_SIZE * 256 * 32 * 8);
         Some(m)
@@ -289,9 +470,9 @@ pub fn init(mapper: Option<Arc<dyn IoMapper>>) -> DeviceResult<Vec<Device>> {
             dev.pic_interrupt_line,
             dev.interrupt_pin,
         );
-        let res = init_driver(&dev, &mapper_driver);
+        let res = init_driver(&dev, &mapper_driver, &irq);
         match res {
-            Ok(d) => dev_list.push(d),
+            Ok(d) => dev_list.extend(d),
             Err(e) => warn!(
                 "{:?}, failed to initialize PCI device: {:04x}:{:04x}",
                 e, dev.id.vendor_id, dev.id.device_id
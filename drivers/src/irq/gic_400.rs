@@ -84,15 +84,19 @@ impl IntController {
                 self.gicd.write(ext_offset, val);
             }
 
-            // Enable CPU0's GIC interface
-            self.gicc.write(GICC_CTLR, 1);
-
-            // Set CPU0's Interrupt Priority Mask
-            self.gicc.write(GICC_PMR, 0xff);
-
             // Enable IRQ distribution
             self.gicd.write(GICD_CTLR, 0x1);
         }
+        self.init_cpu_if();
+    }
+
+    /// Enable the GIC interface of the current CPU, banked for each CPU.
+    fn init_cpu_if(&self) {
+        unsafe {
+            self.gicc.write(GICC_CTLR, 1);
+            // Set the Interrupt Priority Mask
+            self.gicc.write(GICC_PMR, 0xff);
+        }
     }
 
     pub fn irq_enable(&self, irq: u32) {
@@ -167,6 +171,10 @@ impl IrqScheme for IntController {
     fn unregister(&self, _irq_num: usize) -> DeviceResult {
         todo!()
     }
+
+    fn init_hart(&self) {
+        self.init_cpu_if();
+    }
 }
 
 impl GicDistIf {
@@ -0,0 +1,265 @@
+//! ARM Generic Interrupt Controller v3, with the CPU interface accessed by the
+//! system registers.
+//!
+//! Specification: <https://developer.arm.com/documentation/ihi0069/latest>.
+
+use core::arch::asm;
+
+use crate::prelude::IrqHandler;
+use crate::scheme::{IrqScheme, Scheme};
+use crate::utils::IrqManager;
+use crate::{DeviceError, DeviceResult};
+use lock::Mutex;
+
+/// The size of the distributor registers.
+pub const GICD_SIZE: usize = 0x1_0000;
+/// The size of the redistributor registers of each CPU, `RD_base` and
+/// `SGI_base`.
+pub const GICR_SIZE_PER_CPU: usize = 0x2_0000;
+
+const GICD_CTLR: usize = 0x0000;
+const GICD_TYPER: usize = 0x0004;
+const GICD_IGROUPR: usize = 0x0080;
+const GICD_ISENABLER: usize = 0x0100;
+const GICD_ICENABLER: usize = 0x0180;
+const GICD_IPRIORITYR: usize = 0x0400;
+const GICD_ICFGR: usize = 0x0c00;
+const GICD_IROUTER: usize = 0x6000;
+const GICD_PIDR2: usize = 0xffe8;
+
+const GICD_CTLR_RWP: u32 = 1 << 31;
+const GICD_CTLR_ARE_NS: u32 = 1 << 4;
+const GICD_CTLR_ENABLE_G1A: u32 = 1 << 1;
+const GICD_CTLR_ENABLE_G1: u32 = 1 << 0;
+
+const GICR_TYPER: usize = 0x0008;
+const GICR_WAKER: usize = 0x0014;
+const GICR_SGI_BASE: usize = 0x1_0000;
+
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+/// The IRQs from it on are special, such as the spurious one, 1023.
+const IRQ_SPECIAL: usize = 1020;
+/// The IRQs handled, fewer than the architecture allows.
+const IRQ_COUNT: usize = 512;
+/// The first shared peripheral interrupt, below which each CPU has its own.
+const SPI_BASE: usize = 32;
+/// The priority of all the IRQs, higher than the priority mask.
+const IRQ_PRIORITY: u8 = 0xa0;
+
+/// Whether the distributor at `gicd_base` is of GICv3 or GICv4.
+pub fn is_gic_v3(gicd_base: usize) -> bool {
+    let arch_rev = (read32(gicd_base + GICD_PIDR2) >> 4) & 0xf;
+    arch_rev == 3 || arch_rev == 4
+}
+
+/// Acknowledge the highest priority IRQ pending on the current CPU, or
+/// returns `usize::MAX` if none.
+pub fn pending_irq() -> usize {
+    let iar: usize;
+    unsafe { asm!("mrs {}, S3_0_C12_C12_0", out(reg) iar) }; // ICC_IAR1_EL1
+    let irq_num = iar & 0xff_ffff;
+    if irq_num >= IRQ_SPECIAL {
+        usize::MAX
+    } else {
+        irq_num
+    }
+}
+
+fn read32(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+fn write32(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
+
+/// The affinity of the current CPU, as in `GICR_TYPER` (`Aff3.Aff2.Aff1.Aff0`).
+fn cpu_affinity() -> u64 {
+    let mpidr: u64;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr) };
+    (mpidr & 0xff_ffff) | (((mpidr >> 32) & 0xff) << 24)
+}
+
+pub struct GicV3 {
+    gicd_base: usize,
+    gicr_base: usize,
+    nirqs: usize,
+    manager: Mutex<IrqManager<IRQ_COUNT>>,
+}
+
+impl GicV3 {
+    /// Initialize the distributor, and the redistributor and the CPU interface
+    /// of the current CPU, to which all SPIs are routed.
+    pub fn new(gicd_base: usize, gicr_base: usize) -> Self {
+        let typer = read32(gicd_base + GICD_TYPER);
+        let nirqs = (((typer & 0x1f) as usize + 1) * 32).min(IRQ_COUNT);
+        let gic = Self {
+            gicd_base,
+            gicr_base,
+            nirqs,
+            manager: Mutex::new(IrqManager::new(0..nirqs)),
+        };
+        gic.init_dist();
+        gic.init_hart();
+        gic
+    }
+
+    fn gicd_wait_rwp(&self) {
+        while read32(self.gicd_base + GICD_CTLR) & GICD_CTLR_RWP != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn init_dist(&self) {
+        write32(self.gicd_base + GICD_CTLR, 0);
+        self.gicd_wait_rwp();
+        let aff = cpu_affinity();
+        // the IRQ routed to the affinity `Aff3.Aff2.Aff1.Aff0` in `IROUTER`
+        let route = (aff & 0xff_ffff) | ((aff >> 24) << 32);
+        for irq in (SPI_BASE..self.nirqs).step_by(32) {
+            write32(self.gicd_base + GICD_IGROUPR + irq / 8, u32::MAX);
+            write32(self.gicd_base + GICD_ICENABLER + irq / 8, u32::MAX);
+        }
+        for irq in (SPI_BASE..self.nirqs).step_by(16) {
+            // level triggered
+            write32(self.gicd_base + GICD_ICFGR + irq / 4, 0);
+        }
+        for irq in SPI_BASE..self.nirqs {
+            unsafe {
+                core::ptr::write_volatile(
+                    (self.gicd_base + GICD_IPRIORITYR + irq) as *mut u8,
+                    IRQ_PRIORITY,
+                );
+                core::ptr::write_volatile(
+                    (self.gicd_base + GICD_IROUTER + irq * 8) as *mut u64,
+                    route,
+                );
+            }
+        }
+        write32(
+            self.gicd_base + GICD_CTLR,
+            GICD_CTLR_ARE_NS | GICD_CTLR_ENABLE_G1A | GICD_CTLR_ENABLE_G1,
+        );
+        self.gicd_wait_rwp();
+    }
+
+    /// The `RD_base` of the redistributor of the current CPU.
+    fn gicr_rd_base(&self) -> usize {
+        let aff = cpu_affinity();
+        let mut rd_base = self.gicr_base;
+        loop {
+            let typer = unsafe { core::ptr::read_volatile((rd_base + GICR_TYPER) as *const u64) };
+            if typer >> 32 == aff {
+                return rd_base;
+            }
+            if typer & GICR_TYPER_LAST != 0 {
+                panic!(
+                    "no GICv3 redistributor for the CPU with affinity {:#x}",
+                    aff
+                );
+            }
+            rd_base += GICR_SIZE_PER_CPU;
+        }
+    }
+
+    fn toggle(&self, irq_num: usize, enable: bool) {
+        let (base, offset) = if irq_num < SPI_BASE {
+            let sgi_base = self.gicr_rd_base() + GICR_SGI_BASE;
+            (sgi_base, 0)
+        } else {
+            (self.gicd_base, irq_num / 32 * 4)
+        };
+        let reg = if enable {
+            GICD_ISENABLER
+        } else {
+            GICD_ICENABLER
+        };
+        // the same offsets in the SGI frame of the redistributor
+        write32(base + reg + offset, 1 << (irq_num % 32));
+    }
+}
+
+impl Scheme for GicV3 {
+    fn name(&self) -> &str {
+        "arm-gic-v3"
+    }
+
+    fn handle_irq(&self, irq_num: usize) {
+        if irq_num == usize::MAX {
+            return;
+        }
+        if self.manager.lock().handle(irq_num).is_err() {
+            warn!("no registered handler for IRQ {}!", irq_num);
+        }
+        unsafe { asm!("msr S3_0_C12_C12_1, {}", in(reg) irq_num) }; // ICC_EOIR1_EL1
+    }
+}
+
+impl IrqScheme for GicV3 {
+    fn is_valid_irq(&self, irq_num: usize) -> bool {
+        irq_num < self.nirqs
+    }
+
+    fn mask(&self, irq_num: usize) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        self.toggle(irq_num, false);
+        Ok(())
+    }
+
+    fn unmask(&self, irq_num: usize) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        self.toggle(irq_num, true);
+        Ok(())
+    }
+
+    fn register_handler(&self, irq_num: usize, handler: IrqHandler) -> DeviceResult {
+        self.manager.lock().register_handler(irq_num, handler)?;
+        Ok(())
+    }
+
+    fn unregister(&self, irq_num: usize) -> DeviceResult {
+        self.mask(irq_num)?;
+        self.manager.lock().unregister_handler(irq_num)
+    }
+
+    /// Wake up the redistributor of the current CPU, and enable its CPU
+    /// interface. The SGIs and PPIs are masked until unmasked on each CPU.
+    fn init_hart(&self) {
+        let rd_base = self.gicr_rd_base();
+        let waker = read32(rd_base + GICR_WAKER);
+        write32(rd_base + GICR_WAKER, waker & !GICR_WAKER_PROCESSOR_SLEEP);
+        while read32(rd_base + GICR_WAKER) & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+            core::hint::spin_loop();
+        }
+        let sgi_base = rd_base + GICR_SGI_BASE;
+        write32(sgi_base + GICD_IGROUPR, u32::MAX);
+        write32(sgi_base + GICD_ICENABLER, u32::MAX);
+        for irq in 0..SPI_BASE {
+            unsafe {
+                core::ptr::write_volatile(
+                    (sgi_base + GICD_IPRIORITYR + irq) as *mut u8,
+                    IRQ_PRIORITY,
+                )
+            };
+        }
+        unsafe {
+            // ICC_SRE_EL1.SRE: use the system registers
+            let mut sre: usize;
+            asm!("mrs {}, S3_0_C12_C12_5", out(reg) sre);
+            asm!("msr S3_0_C12_C12_5, {}", "isb", in(reg) sre | 1);
+            // ICC_PMR_EL1: let all priorities through
+            asm!("msr S3_0_C4_C6_0, {}", in(reg) 0xffusize);
+            // ICC_BPR1_EL1: no preemption groups
+            asm!("msr S3_0_C12_C12_3, {}", in(reg) 0usize);
+            // ICC_IGRPEN1_EL1: enable group 1
+            asm!("msr S3_0_C12_C12_7, {}", "isb", in(reg) 1usize);
+        }
+    }
+}
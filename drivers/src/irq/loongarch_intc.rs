@@ -0,0 +1,101 @@
+//! The interrupt lines of a LoongArch CPU core, enabled in `ECFG.LIE`.
+//!
+//! Lines 0-1 are software interrupts, 2-9 hardware interrupts, 10 the
+//! performance counter, 11 the timer and 12 the inter-processor interrupt.
+
+use core::arch::asm;
+
+use lock::Mutex;
+
+use crate::prelude::IrqHandler;
+use crate::scheme::{IrqScheme, Scheme};
+use crate::{DeviceError, DeviceResult};
+
+const IRQ_COUNT: usize = 13;
+const CSR_ECFG: usize = 0x4;
+
+pub struct Intc {
+    handlers: Mutex<[Option<IrqHandler>; IRQ_COUNT]>,
+}
+
+impl Intc {
+    /// Create the driver of the interrupt lines, shared by all CPUs, whose
+    /// `ECFG` are set respectively.
+    pub fn new() -> Self {
+        const NONE: Option<IrqHandler> = None;
+        Self {
+            handlers: Mutex::new([NONE; IRQ_COUNT]),
+        }
+    }
+}
+
+impl Default for Intc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Set the bits of `mask` in `ECFG` to those in `value`.
+fn ecfg_xchg(value: usize, mask: usize) {
+    unsafe { asm!("csrxchg {}, {}, {}", inout(reg) value => _, in(reg) mask, const CSR_ECFG) };
+}
+
+impl Scheme for Intc {
+    fn name(&self) -> &str {
+        "loongarch-intc"
+    }
+
+    fn handle_irq(&self, irq_num: usize) {
+        if !self.is_valid_irq(irq_num) {
+            warn!("spurious interrupt {:#x}!", irq_num);
+            return;
+        }
+        if let Some(h) = &self.handlers.lock()[irq_num] {
+            h();
+        } else {
+            warn!("no registered handler for interrupt {}!", irq_num);
+        }
+    }
+}
+
+impl IrqScheme for Intc {
+    fn is_valid_irq(&self, irq_num: usize) -> bool {
+        irq_num < IRQ_COUNT
+    }
+
+    fn mask(&self, irq_num: usize) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        ecfg_xchg(0, 1 << irq_num);
+        Ok(())
+    }
+
+    fn unmask(&self, irq_num: usize) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        ecfg_xchg(1 << irq_num, 1 << irq_num);
+        Ok(())
+    }
+
+    fn register_handler(&self, irq_num: usize, handler: IrqHandler) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        let mut handlers = self.handlers.lock();
+        if handlers[irq_num].is_some() {
+            Err(DeviceError::AlreadyExists)
+        } else {
+            handlers[irq_num] = Some(handler);
+            Ok(())
+        }
+    }
+
+    fn unregister(&self, irq_num: usize) -> DeviceResult {
+        if !self.is_valid_irq(irq_num) {
+            return Err(DeviceError::InvalidParam);
+        }
+        self.handlers.lock()[irq_num].take().map(|_| ()).ok_or(DeviceError::InvalidParam)
+    }
+}
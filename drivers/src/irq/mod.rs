@@ -20,5 +20,13 @@ cfg_if::cfg_if! {
         }
     } else if #[cfg(target_arch = "aarch64")] {
         pub mod gic_400;
+        pub mod gic_v3;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        mod loongarch_intc;
+        /// Implementation of the LoongArch CPU interrupt controller.
+        #[doc(cfg(target_arch = "loongarch64"))]
+        pub mod loongarch {
+            pub use super::loongarch_intc::Intc;
+        }
     }
 }
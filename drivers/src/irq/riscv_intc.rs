@@ -6,14 +6,11 @@ use crate::scheme::{IrqScheme, Scheme};
 use crate::{DeviceError, DeviceResult};
 use alloc::format;
 use alloc::string::String;
-use core::sync::atomic::{AtomicU8, Ordering};
 
 const S_SOFT: usize = 1;
 const S_TIMER: usize = 5;
 const S_EXT: usize = 9;
 
-static INTC_NUM: AtomicU8 = AtomicU8::new(0);
-
 #[repr(usize)]
 pub enum ScauseIntCode {
     SupervisorSoft = S_SOFT,
@@ -29,9 +26,11 @@ pub struct Intc {
 }
 
 impl Intc {
-    pub fn new() -> Self {
+    /// Create the driver of the local interrupt controller of the hart
+    /// `hart_id`.
+    pub fn new(hart_id: usize) -> Self {
         Self {
-            name: format!("riscv-intc-cpu{}", INTC_NUM.fetch_add(1, Ordering::Relaxed)),
+            name: format!("riscv-intc-cpu{}", hart_id),
             soft_handler: Mutex::new(None),
             timer_handler: Mutex::new(None),
             ext_handler: Mutex::new(None),
@@ -54,12 +53,6 @@ impl Intc {
     }
 }
 
-impl Default for Intc {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl Scheme for Intc {
     fn name(&self) -> &str {
         self.name.as_str()
@@ -1,7 +1,8 @@
+use alloc::collections::BTreeMap;
 use core::arch::asm;
 use core::ops::Range;
 
-use lock::Mutex;
+use lock::{Mutex, RwLock};
 
 use crate::io::{Io, Mmio};
 use crate::prelude::IrqHandler;
@@ -11,35 +12,55 @@ use crate::{utils::IrqManager, DeviceError, DeviceResult};
 const IRQ_RANGE: Range<usize> = 1..1024;
 
 const PLIC_PRIORITY_BASE: usize = 0x0;
-const PLIC_ENABLE_BASE: usize = 0x2080;
-
-const PLIC_CONTEXT_BASE: usize = 0x20_1000;
+const PLIC_ENABLE_BASE: usize = 0x2000;
+const PLIC_CONTEXT_BASE: usize = 0x20_0000;
 const PLIC_CONTEXT_THRESHOLD: usize = 0x0;
 const PLIC_CONTEXT_CLAIM: usize = 0x4 / core::mem::size_of::<u32>();
 
-const PLIC_ENABLE_HART_OFFSET: usize = 0x100 / core::mem::size_of::<u32>();
-const PLIC_PRIORITY_HART_OFFSET: usize = 0x2000 / core::mem::size_of::<u32>();
-const PLIC_CONTEXT_CLAIM_HART_OFFSET: usize = 0x2000 / core::mem::size_of::<u32>();
+const PLIC_ENABLE_CONTEXT_OFFSET: usize = 0x80 / core::mem::size_of::<u32>();
+const PLIC_CONTEXT_OFFSET: usize = 0x1000 / core::mem::size_of::<u32>();
+
+/// The priority of IRQs unmasked. A priority of 0 never interrupts.
+const IRQ_PRIORITY: u8 = 7;
 
 struct PlicUnlocked {
     priority_base: &'static mut Mmio<u32>,
     enable_base: &'static mut Mmio<u32>,
     context_base: &'static mut Mmio<u32>,
-    manager: IrqManager<1024>,
+    /// The S-mode context of each hart.
+    contexts: BTreeMap<usize, usize>,
+    /// The hart each IRQ registered is routed to.
+    routes: BTreeMap<usize, usize>,
 }
 
+/// RISC-V Platform-Level Interrupt Controller.
+///
+/// Each IRQ is routed to the hart it is registered on, by the enable bits of
+/// the S-mode context of the hart. It is masked and unmasked by its priority
+/// instead, so that it may be done on any hart, and an IRQ masked by its own
+/// handler can still be completed.
 pub struct Plic {
     inner: Mutex<PlicUnlocked>,
+    manager: RwLock<IrqManager<1024>>,
 }
 
 impl PlicUnlocked {
-    /// Toggle irq enable on the current hart.
-    fn toggle(&mut self, irq_num: usize, enable: bool) {
+    /// The S-mode context of the hart `hart_id`, the second one of the hart
+    /// if not given.
+    fn context(&self, hart_id: usize) -> usize {
+        self.contexts
+            .get(&hart_id)
+            .copied()
+            .unwrap_or(hart_id * 2 + 1)
+    }
+
+    /// Toggle irq enable on the hart `hart_id`.
+    fn toggle(&mut self, irq_num: usize, hart_id: usize, enable: bool) {
         debug_assert!(IRQ_RANGE.contains(&irq_num));
-        let hart_id = cpu_id() as usize;
+        let context = self.context(hart_id);
         let mmio = self
             .enable_base
-            .add(PLIC_ENABLE_HART_OFFSET * hart_id + irq_num / 32);
+            .add(PLIC_ENABLE_CONTEXT_OFFSET * context + irq_num / 32);
 
         let mask = 1 << (irq_num % 32);
         if enable {
@@ -49,12 +70,28 @@ impl PlicUnlocked {
         }
     }
 
-    /// Ask the PLIC what type of interrupt is occurred on the current hart.
-    fn pending_irq(&mut self) -> Option<usize> {
+    /// Route the IRQ to the current hart.
+    fn route(&mut self, irq_num: usize) {
         let hart_id = cpu_id() as usize;
+        self.set_priority(irq_num, 0);
+        self.toggle(irq_num, hart_id, true);
+        self.routes.insert(irq_num, hart_id);
+    }
+
+    /// Stop routing the IRQ to any hart.
+    fn unroute(&mut self, irq_num: usize) {
+        self.set_priority(irq_num, 0);
+        if let Some(hart_id) = self.routes.remove(&irq_num) {
+            self.toggle(irq_num, hart_id, false);
+        }
+    }
+
+    /// Claim the highest priority IRQ pending on the current hart.
+    fn pending_irq(&mut self) -> Option<usize> {
+        let context = self.context(cpu_id() as usize);
         let irq_num = self
             .context_base
-            .add(PLIC_CONTEXT_CLAIM_HART_OFFSET * hart_id + PLIC_CONTEXT_CLAIM)
+            .add(PLIC_CONTEXT_OFFSET * context + PLIC_CONTEXT_CLAIM)
             .read() as usize;
         if irq_num == 0 {
             None
@@ -66,9 +103,9 @@ impl PlicUnlocked {
     /// Tell the PLIC we've served this IRQ.
     fn eoi(&mut self, irq_num: usize) {
         debug_assert!(IRQ_RANGE.contains(&irq_num));
-        let hart_id = cpu_id() as usize;
+        let context = self.context(cpu_id() as usize);
         self.context_base
-            .add(PLIC_CONTEXT_CLAIM + PLIC_CONTEXT_CLAIM_HART_OFFSET * hart_id)
+            .add(PLIC_CONTEXT_OFFSET * context + PLIC_CONTEXT_CLAIM)
             .write(irq_num as _);
     }
 
@@ -78,11 +115,11 @@ impl PlicUnlocked {
         self.priority_base.add(irq_num).write(priority as _);
     }
 
-    /// Set current hart's priority threshold to 0.
+    /// Set current hart's priority threshold, under which IRQs are ignored.
     fn set_threshold(&mut self, threshold: u8) {
-        let hart_id = cpu_id() as usize;
+        let context = self.context(cpu_id() as usize);
         self.context_base
-            .add(PLIC_PRIORITY_HART_OFFSET * hart_id + PLIC_CONTEXT_THRESHOLD)
+            .add(PLIC_CONTEXT_OFFSET * context + PLIC_CONTEXT_THRESHOLD)
             .write(threshold as _);
     }
 
@@ -92,16 +129,21 @@ impl PlicUnlocked {
 }
 
 impl Plic {
-    pub fn new(base: usize) -> Self {
+    /// Create the driver with the S-mode context of each hart, as given by
+    /// `interrupts-extended` in the device tree. The harts not in `contexts`
+    /// are assumed to have an M-mode and an S-mode context each, as on QEMU.
+    pub fn new(base: usize, contexts: BTreeMap<usize, usize>) -> Self {
         let mut inner = PlicUnlocked {
             priority_base: unsafe { Mmio::<u32>::from_base(base + PLIC_PRIORITY_BASE) },
             enable_base: unsafe { Mmio::<u32>::from_base(base + PLIC_ENABLE_BASE) },
             context_base: unsafe { Mmio::<u32>::from_base(base + PLIC_CONTEXT_BASE) },
-            manager: IrqManager::new(IRQ_RANGE),
+            contexts,
+            routes: BTreeMap::new(),
         };
         inner.init_hart();
         Self {
             inner: Mutex::new(inner),
+            manager: RwLock::new(IrqManager::new(IRQ_RANGE)),
         }
     }
 }
@@ -112,13 +154,17 @@ impl Scheme for Plic {
     }
 
     fn handle_irq(&self, _unused: usize) {
-        let mut inner = self.inner.lock();
-        while let Some(irq_num) = inner.pending_irq() {
-            if inner.manager.handle(irq_num).is_err() {
+        loop {
+            let irq_num = match self.inner.lock().pending_irq() {
+                Some(irq_num) => irq_num,
+                None => break,
+            };
+            // run the handler without the lock, as it may mask the IRQ
+            if self.manager.read().handle(irq_num).is_err() {
                 warn!("no registered handler for IRQ {}!", irq_num);
             }
             trace!("riscv plic handle irq: {}", irq_num);
-            inner.eoi(irq_num);
+            self.inner.lock().eoi(irq_num);
         }
     }
 }
@@ -130,7 +176,7 @@ impl IrqScheme for Plic {
 
     fn mask(&self, irq_num: usize) -> DeviceResult {
         if self.is_valid_irq(irq_num) {
-            self.inner.lock().toggle(irq_num, false);
+            self.inner.lock().set_priority(irq_num, 0);
             Ok(())
         } else {
             Err(DeviceError::InvalidParam)
@@ -138,8 +184,9 @@ impl IrqScheme for Plic {
     }
 
     fn unmask(&self, irq_num: usize) -> DeviceResult {
-        if self.is_valid_irq(irq_num) {
-            self.inner.lock().toggle(irq_num, true);
+        let mut inner = self.inner.lock();
+        if inner.routes.contains_key(&irq_num) {
+            inner.set_priority(irq_num, IRQ_PRIORITY);
             Ok(())
         } else {
             Err(DeviceError::InvalidParam)
@@ -147,14 +194,16 @@ impl IrqScheme for Plic {
     }
 
     fn register_handler(&self, irq_num: usize, handler: IrqHandler) -> DeviceResult {
-        let mut inner = self.inner.lock();
-        inner.manager.register_handler(irq_num, handler).map(|_| {
-            inner.set_priority(irq_num, 7);
-        })
+        let irq_num = self.manager.write().register_handler(irq_num, handler)?;
+        // masked until it is unmasked
+        self.inner.lock().route(irq_num);
+        Ok(())
     }
 
     fn unregister(&self, irq_num: usize) -> DeviceResult {
-        self.inner.lock().manager.unregister_handler(irq_num)
+        self.manager.write().unregister_handler(irq_num)?;
+        self.inner.lock().unroute(irq_num);
+        Ok(())
     }
 
     fn init_hart(&self) {
@@ -1,14 +1,24 @@
 use x2apic::lapic::{
     xapic_base, LocalApic as LocalApicInner, LocalApicBuilder, TimerDivide, TimerMode,
 };
+use x86_64::registers::model_specific::Msr;
 
 use super::{consts, Phys2VirtFn};
 
 static mut LOCAL_APIC: Option<LocalApic> = None;
 static mut BSP_ID: Option<u8> = None;
 
+/// The MSR of the APIC base address, with the x2APIC mode bit.
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+
+/// The current count register of the timer, in the xAPIC and x2APIC modes.
+const XAPIC_TIMER_CURRENT: usize = 0x390;
+const X2APIC_TIMER_CURRENT: u32 = 0x839;
+
 pub struct LocalApic {
     inner: LocalApicInner,
+    base_vaddr: usize,
 }
 
 impl LocalApic {
@@ -31,7 +41,7 @@ impl LocalApic {
 
         assert!(inner.is_bsp());
         BSP_ID = Some((inner.id() >> 24) as u8);
-        LOCAL_APIC = Some(LocalApic { inner });
+        LOCAL_APIC = Some(LocalApic { inner, base_vaddr });
     }
 
     pub unsafe fn init_ap() {
@@ -69,4 +79,19 @@ impl LocalApic {
     pub fn set_timer_initial(&mut self, initial: u32) {
         unsafe { self.inner.set_timer_initial(initial) }
     }
+
+    /// Read the current count of the timer, counting down from the initial.
+    pub fn timer_current(&self) -> u32 {
+        unsafe {
+            if Msr::new(IA32_APIC_BASE).read() & APIC_BASE_X2APIC != 0 {
+                Msr::new(X2APIC_TIMER_CURRENT).read() as u32
+            } else {
+                core::ptr::read_volatile((self.base_vaddr + XAPIC_TIMER_CURRENT) as *const u32)
+            }
+        }
+    }
+
+    pub fn send_ipi(&mut self, vector: usize, dest: u8) {
+        unsafe { self.inner.send_ipi(vector as u8, dest as u32) }
+    }
 }
@@ -79,7 +79,6 @@ impl Scheme for Apic {
     }
 
     fn handle_irq(&self, vector: usize) {
-        Self::local_apic().eoi();
         let res = if vector >= X86_INT_LOCAL_APIC_BASE {
             let handler = self.manager_lapic.lock();
             handler.handle(vector - X86_INT_LOCAL_APIC_BASE)
@@ -89,6 +88,9 @@ impl Scheme for Apic {
         if res.is_err() {
             warn!("no registered handler for interrupt vector {}!", vector);
         }
+        // after the handler, so that a level-triggered IRQ masked by it is
+        // not delivered again
+        Self::local_apic().eoi();
     }
 }
 
@@ -114,7 +116,9 @@ impl IrqScheme for Apic {
     fn configure(&self, gsi: usize, tm: IrqTriggerMode, pol: IrqPolarity) -> DeviceResult {
         let gsi = gsi as u32;
         self.with_ioapic(gsi, |apic| {
-            apic.configure(gsi, tm, pol, LocalApic::bsp_id(), 0);
+            // keep the vector mapped by `register_handler()`
+            let vector = apic.get_vector(gsi);
+            apic.configure(gsi, tm, pol, LocalApic::bsp_id(), vector);
             Ok(())
         })
     }
@@ -149,7 +153,7 @@ impl IrqScheme for Apic {
     }
 
     fn msi_free_block(&self, block: Range<usize>) -> DeviceResult {
-        self.manager_lapic
+        self.manager_ioapic
             .lock()
             .free_block(block.start, block.len())
     }
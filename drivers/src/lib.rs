@@ -3,6 +3,7 @@
 #![cfg_attr(not(feature = "mock"), no_std)]
 #![deny(warnings)]
 #![feature(doc_cfg)]
+#![cfg_attr(target_arch = "loongarch64", feature(asm_experimental_arch, asm_const))]
 
 extern crate alloc;
 
@@ -20,6 +21,7 @@ pub mod mock;
 #[doc(cfg(feature = "virtio"))]
 pub mod virtio;
 
+pub mod block;
 pub mod builder;
 pub mod bus;
 pub mod display;
@@ -28,6 +30,7 @@ pub mod io;
 pub mod irq;
 pub mod net;
 pub mod prelude;
+pub mod rtc;
 pub mod scheme;
 pub mod uart;
 pub mod utils;
@@ -69,6 +72,10 @@ pub enum Device {
     Irq(Arc<dyn scheme::IrqScheme>),
     /// Network device
     Net(Arc<dyn scheme::NetScheme>),
+    /// Hardware random number generator
+    Rng(Arc<dyn scheme::RngScheme>),
+    /// Real-time clock
+    Rtc(Arc<dyn scheme::RtcScheme>),
     /// Uart port
     Uart(Arc<dyn scheme::UartScheme>),
 }
@@ -82,6 +89,8 @@ impl Device {
             Self::Input(d) => d.clone().upcast(),
             Self::Irq(d) => d.clone().upcast(),
             Self::Net(d) => d.clone().upcast(),
+            Self::Rng(d) => d.clone().upcast(),
+            Self::Rtc(d) => d.clone().upcast(),
             Self::Uart(d) => d.clone().upcast(),
         }
     }
@@ -95,6 +104,8 @@ impl fmt::Debug for Device {
             Self::Input(d) => write!(f, "InputDevice({:?})", d.name()),
             Self::Irq(d) => write!(f, "IrqDevice({:?})", d.name()),
             Self::Net(d) => write!(f, "NetDevice({:?})", d.name()),
+            Self::Rng(d) => write!(f, "RngDevice({:?})", d.name()),
+            Self::Rtc(d) => write!(f, "RtcDevice({:?})", d.name()),
             Self::Uart(d) => write!(f, "UartDevice({:?})", d.name()),
         }
     }
@@ -1,10 +1,17 @@
 //! Intel PRO/1000 Network Adapter i.e. e1000 network driver
-//! Datasheet: <https://www.intel.ca/content/dam/doc/datasheet/82574l-gbe-controller-datasheet.pdf>
+//!
+//! Supports the 8254x (e1000) and the 82574 (e1000e) controllers with the
+//! legacy descriptors, as emulated by QEMU.
+//!
+//! Datasheets:
+//! - <https://pdos.csail.mit.edu/6.828/2019/readings/hardware/8254x_GBe_SDM.pdf>
+//! - <https://www.intel.ca/content/dam/doc/datasheet/82574l-gbe-controller-datasheet.pdf>
 
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
 
 use smoltcp::iface::*;
 use smoltcp::phy::{self, DeviceCapabilities};
@@ -12,16 +19,339 @@ use smoltcp::time::Instant;
 use smoltcp::wire::*;
 use smoltcp::Result;
 
-use super::{timer_now_as_micros, ProviderImpl};
-use crate::net::get_sockets;
+use super::timer_now_as_micros;
+use crate::io::{Io, Mmio};
+use crate::net::{get_sockets, SOCKET_ACTIVITY};
 use crate::scheme::{NetScheme, Scheme};
+use crate::utils::DmaRegion;
 use crate::{DeviceError, DeviceResult};
-use isomorphic_drivers::net::ethernet::intel::e1000::E1000;
-use isomorphic_drivers::net::ethernet::structs::EthernetAddress as DriverEthernetAddress;
 use lock::Mutex;
 
+// registers
+const REG_CTRL: usize = 0x0000;
+const REG_STATUS: usize = 0x0008;
+const REG_ICR: usize = 0x00C0;
+const REG_IMS: usize = 0x00D0;
+const REG_IMC: usize = 0x00D8;
+const REG_RCTL: usize = 0x0100;
+const REG_TCTL: usize = 0x0400;
+const REG_TIPG: usize = 0x0410;
+const REG_RDBAL: usize = 0x2800;
+const REG_RDBAH: usize = 0x2804;
+const REG_RDLEN: usize = 0x2808;
+const REG_RDH: usize = 0x2810;
+const REG_RDT: usize = 0x2818;
+const REG_RDTR: usize = 0x2820;
+const REG_TDBAL: usize = 0x3800;
+const REG_TDBAH: usize = 0x3804;
+const REG_TDLEN: usize = 0x3808;
+const REG_TDH: usize = 0x3810;
+const REG_TDT: usize = 0x3818;
+const REG_MTA: usize = 0x5200;
+const REG_RAL0: usize = 0x5400;
+const REG_RAH0: usize = 0x5404;
+
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+const CTRL_LRST: u32 = 1 << 3;
+const CTRL_ILOS: u32 = 1 << 7;
+const CTRL_VME: u32 = 1 << 30;
+const CTRL_PHY_RST: u32 = 1 << 31;
+
+const STATUS_LU: u32 = 1 << 1;
+
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+/// With BSIZE 0, the receive buffers are 2048 bytes.
+const RCTL_BSIZE_2048: u32 = 0;
+const RCTL_SECRC: u32 = 1 << 26;
+
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT_SHIFT: u32 = 4;
+const TCTL_COLD_SHIFT: u32 = 12;
+
+const RAH_AV: u32 = 1 << 31;
+
+const INT_LSC: u32 = 1 << 2;
+const INT_RXDMT0: u32 = 1 << 4;
+const INT_RXO: u32 = 1 << 6;
+const INT_RXT0: u32 = 1 << 7;
+
+const TX_CMD_EOP: u8 = 1 << 0;
+const TX_CMD_IFCS: u8 = 1 << 1;
+const TX_CMD_RS: u8 = 1 << 3;
+const TX_STATUS_DD: u8 = 1 << 0;
+
+const RX_STATUS_DD: u8 = 1 << 0;
+const RX_STATUS_EOP: u8 = 1 << 1;
+
+const RX_RING_SIZE: usize = 64;
+const TX_RING_SIZE: usize = 64;
+const BUFFER_SIZE: usize = 2048;
+/// The largest Ethernet frame, without the FCS.
+const MAX_FRAME_SIZE: usize = 1514;
+
+/// Legacy transmit descriptor
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct TxDesc {
+    addr: u64,
+    length: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+/// Legacy receive descriptor
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct RxDesc {
+    addr: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+/// The e1000 controller, with its receive and transmit rings.
+pub struct E1000 {
+    base: usize,
+    mac: [u8; 6],
+    rx_ring: DmaRegion,
+    rx_buffers: DmaRegion,
+    /// The next receive descriptor to be written back by the device.
+    rx_next: usize,
+    tx_ring: DmaRegion,
+    tx_buffers: DmaRegion,
+    /// The next free transmit descriptor.
+    tx_next: usize,
+}
+
+impl E1000 {
+    /// Reset and initialize the controller with registers at `base`.
+    ///
+    /// `default_mac` is used if no address is set by the EEPROM.
+    pub fn new(base: usize, default_mac: [u8; 6]) -> DeviceResult<Self> {
+        let mut e1000 = Self {
+            base,
+            mac: default_mac,
+            rx_ring: DmaRegion::new(RX_RING_SIZE * core::mem::size_of::<RxDesc>())?,
+            rx_buffers: DmaRegion::new(RX_RING_SIZE * BUFFER_SIZE)?,
+            rx_next: 0,
+            tx_ring: DmaRegion::new(TX_RING_SIZE * core::mem::size_of::<TxDesc>())?,
+            tx_buffers: DmaRegion::new(TX_RING_SIZE * BUFFER_SIZE)?,
+            tx_next: 0,
+        };
+        e1000.reset()?;
+        e1000.init_mac();
+        e1000.init_rx();
+        e1000.init_tx();
+        // receive timer, overrun, ring low and link status change
+        e1000.write_reg(REG_IMS, INT_RXT0 | INT_RXO | INT_RXDMT0 | INT_LSC);
+        e1000.read_reg(REG_ICR);
+        Ok(e1000)
+    }
+
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { Mmio::<u32>::from_base(self.base + offset) }.read()
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { Mmio::<u32>::from_base(self.base + offset) }.write(value)
+    }
+
+    fn reset(&mut self) -> DeviceResult {
+        self.write_reg(REG_IMC, u32::MAX);
+        self.write_reg(REG_CTRL, self.read_reg(REG_CTRL) | CTRL_RST);
+        // the reset completes within 1 us
+        let mut retry = 1_000_000;
+        while self.read_reg(REG_CTRL) & CTRL_RST != 0 {
+            retry -= 1;
+            if retry == 0 {
+                warn!("e1000: reset timeout");
+                return Err(DeviceError::IoError);
+            }
+            core::hint::spin_loop();
+        }
+        self.write_reg(REG_IMC, u32::MAX);
+        self.read_reg(REG_ICR);
+
+        let ctrl = self.read_reg(REG_CTRL);
+        let ctrl =
+            (ctrl | CTRL_SLU | CTRL_ASDE) & !(CTRL_LRST | CTRL_ILOS | CTRL_VME | CTRL_PHY_RST);
+        self.write_reg(REG_CTRL, ctrl);
+        Ok(())
+    }
+
+    fn init_mac(&mut self) {
+        let ral = self.read_reg(REG_RAL0);
+        let rah = self.read_reg(REG_RAH0);
+        if rah & RAH_AV != 0 && (ral != 0 || rah & 0xffff != 0) {
+            // loaded from the EEPROM on reset
+            self.mac[..4].copy_from_slice(&ral.to_le_bytes());
+            self.mac[4..].copy_from_slice(&rah.to_le_bytes()[..2]);
+        } else {
+            let mac = self.mac;
+            self.write_reg(
+                REG_RAL0,
+                u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]),
+            );
+            self.write_reg(
+                REG_RAH0,
+                u16::from_le_bytes([mac[4], mac[5]]) as u32 | RAH_AV,
+            );
+        }
+        // no multicast
+        for i in 0..128 {
+            self.write_reg(REG_MTA + i * 4, 0);
+        }
+    }
+
+    fn init_rx(&mut self) {
+        for i in 0..RX_RING_SIZE {
+            let desc = RxDesc {
+                addr: (self.rx_buffers.paddr() + i * BUFFER_SIZE) as u64,
+                ..Default::default()
+            };
+            unsafe { self.rx_ring.as_ptr::<RxDesc>(i).write_volatile(desc) };
+        }
+        let paddr = self.rx_ring.paddr() as u64;
+        self.write_reg(REG_RDBAL, paddr as u32);
+        self.write_reg(REG_RDBAH, (paddr >> 32) as u32);
+        self.write_reg(
+            REG_RDLEN,
+            (RX_RING_SIZE * core::mem::size_of::<RxDesc>()) as u32,
+        );
+        // all the descriptors but one are owned by the device
+        self.write_reg(REG_RDH, 0);
+        self.write_reg(REG_RDT, (RX_RING_SIZE - 1) as u32);
+        self.write_reg(REG_RDTR, 0);
+        self.write_reg(REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_BSIZE_2048 | RCTL_SECRC);
+    }
+
+    fn init_tx(&mut self) {
+        for i in 0..TX_RING_SIZE {
+            let desc = TxDesc {
+                addr: (self.tx_buffers.paddr() + i * BUFFER_SIZE) as u64,
+                // free to be used
+                status: TX_STATUS_DD,
+                ..Default::default()
+            };
+            unsafe { self.tx_ring.as_ptr::<TxDesc>(i).write_volatile(desc) };
+        }
+        let paddr = self.tx_ring.paddr() as u64;
+        self.write_reg(REG_TDBAL, paddr as u32);
+        self.write_reg(REG_TDBAH, (paddr >> 32) as u32);
+        self.write_reg(
+            REG_TDLEN,
+            (TX_RING_SIZE * core::mem::size_of::<TxDesc>()) as u32,
+        );
+        self.write_reg(REG_TDH, 0);
+        self.write_reg(REG_TDT, 0);
+        // collision threshold and distance of the full-duplex mode
+        self.write_reg(
+            REG_TCTL,
+            TCTL_EN | TCTL_PSP | (0x10 << TCTL_CT_SHIFT) | (0x40 << TCTL_COLD_SHIFT),
+        );
+        // IPGT 10, IPGR1 8, IPGR2 6, as recommended for the IEEE 802.3 standard
+        self.write_reg(REG_TIPG, 10 | (8 << 10) | (6 << 20));
+    }
+
+    /// The MAC address of the controller.
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /// Whether the link is up.
+    pub fn link_up(&self) -> bool {
+        self.read_reg(REG_STATUS) & STATUS_LU != 0
+    }
+
+    /// Acknowledge the interrupt, returning whether there was one.
+    pub fn ack_interrupt(&self) -> bool {
+        // reading ICR clears it
+        self.read_reg(REG_ICR) != 0
+    }
+
+    fn rx_desc(&self, index: usize) -> RxDesc {
+        unsafe { self.rx_ring.as_ptr::<RxDesc>(index).read_volatile() }
+    }
+
+    fn tx_desc(&self, index: usize) -> TxDesc {
+        unsafe { self.tx_ring.as_ptr::<TxDesc>(index).read_volatile() }
+    }
+
+    pub fn can_recv(&self) -> bool {
+        self.rx_desc(self.rx_next).status & RX_STATUS_DD != 0
+    }
+
+    pub fn can_send(&self) -> bool {
+        self.tx_desc(self.tx_next).status & TX_STATUS_DD != 0
+    }
+
+    /// Take a received frame, `None` if there is none.
+    pub fn receive(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let index = self.rx_next;
+            let mut desc = self.rx_desc(index);
+            if desc.status & RX_STATUS_DD == 0 {
+                return None;
+            }
+            fence(Ordering::Acquire);
+            // frames never span buffers, as the long packets are not enabled
+            let frame = if desc.status & RX_STATUS_EOP != 0 && desc.errors == 0 {
+                let len = (desc.length as usize).min(BUFFER_SIZE);
+                let buf = self.rx_buffers.vaddr() + index * BUFFER_SIZE;
+                Some(unsafe { core::slice::from_raw_parts(buf as *const u8, len) }.to_vec())
+            } else {
+                warn!("e1000: drop bad frame, errors {:#x}", desc.errors);
+                None
+            };
+            // give the descriptor back to the device
+            desc.status = 0;
+            unsafe { self.rx_ring.as_ptr::<RxDesc>(index).write_volatile(desc) };
+            fence(Ordering::Release);
+            self.write_reg(REG_RDT, index as u32);
+            self.rx_next = (index + 1) % RX_RING_SIZE;
+            if frame.is_some() {
+                return frame;
+            }
+        }
+    }
+
+    /// Queue a frame to be sent.
+    pub fn send(&mut self, data: &[u8]) -> DeviceResult {
+        if data.len() > MAX_FRAME_SIZE {
+            return Err(DeviceError::InvalidParam);
+        }
+        if !self.can_send() {
+            return Err(DeviceError::NotReady);
+        }
+        let index = self.tx_next;
+        let buf = self.tx_buffers.vaddr() + index * BUFFER_SIZE;
+        unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, data.len()) }
+            .copy_from_slice(data);
+        let desc = TxDesc {
+            addr: (self.tx_buffers.paddr() + index * BUFFER_SIZE) as u64,
+            length: data.len() as u16,
+            cmd: TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS,
+            ..Default::default()
+        };
+        unsafe { self.tx_ring.as_ptr::<TxDesc>(index).write_volatile(desc) };
+        fence(Ordering::Release);
+        self.tx_next = (index + 1) % TX_RING_SIZE;
+        self.write_reg(REG_TDT, self.tx_next as u32);
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
-pub struct E1000Driver(Arc<Mutex<E1000<ProviderImpl>>>);
+pub struct E1000Driver(Arc<Mutex<E1000>>);
 
 #[derive(Clone)]
 pub struct E1000Interface {
@@ -42,21 +372,9 @@ impl Scheme for E1000Interface {
             return;
         }
 
-        let data = self.driver.0.lock().handle_interrupt();
-
-        if data {
-            let timestamp = Instant::from_micros(timer_now_as_micros() as i64);
-            let sockets = get_sockets();
-            let mut sockets = sockets.lock();
-            match self.iface.lock().poll(&mut sockets, timestamp) {
-                Ok(p) => {
-                    //SOCKET_ACTIVITY.notify_all();
-                    info!("e1000 try_handle_interrupt poll: {:?}", p);
-                }
-                Err(err) => {
-                    warn!("poll got err {}", err);
-                }
-            }
+        if self.driver.0.lock().ack_interrupt() {
+            // deliver the received packets to the sockets
+            self.poll().ok();
         }
     }
 }
@@ -81,8 +399,10 @@ impl NetScheme for E1000Interface {
         let mut sockets = sockets.lock();
         match self.iface.lock().poll(&mut sockets, timestamp) {
             Ok(p) => {
-                //SOCKET_ACTIVITY.notify_all();
                 trace!("e1000 NetScheme poll: {:?}", p);
+                if p {
+                    SOCKET_ACTIVITY.trigger(());
+                }
                 Ok(())
             }
             Err(err) => {
@@ -93,22 +413,20 @@ impl NetScheme for E1000Interface {
     }
 
     fn recv(&self, buf: &mut [u8]) -> DeviceResult<usize> {
-        if let Some(vec_recv) = self.driver.0.lock().receive() {
-            buf.copy_from_slice(&vec_recv);
-            Ok(vec_recv.len())
+        if let Some(frame) = self.driver.0.lock().receive() {
+            if frame.len() > buf.len() {
+                return Err(DeviceError::BufferTooSmall);
+            }
+            buf[..frame.len()].copy_from_slice(&frame);
+            Ok(frame.len())
         } else {
             Err(DeviceError::NotReady)
         }
     }
 
     fn send(&self, data: &[u8]) -> DeviceResult<usize> {
-        if self.driver.0.lock().can_send() {
-            let mut driver = self.driver.0.lock();
-            driver.send(data);
-            Ok(data.len())
-        } else {
-            Err(DeviceError::NotReady)
-        }
+        self.driver.0.lock().send(data)?;
+        Ok(data.len())
     }
 }
 
@@ -123,7 +441,7 @@ impl phy::Device<'_> for E1000Driver {
         self.0
             .lock()
             .receive()
-            .map(|vec_recv| (E1000RxToken(vec_recv), E1000TxToken(self.clone())))
+            .map(|frame| (E1000RxToken(frame), E1000TxToken(self.clone())))
     }
 
     fn transmit(&mut self) -> Option<Self::TxToken> {
@@ -136,8 +454,8 @@ impl phy::Device<'_> for E1000Driver {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1536;
-        caps.max_burst_size = Some(64);
+        caps.max_transmission_unit = MAX_FRAME_SIZE;
+        caps.max_burst_size = Some(TX_RING_SIZE);
         caps
     }
 }
@@ -156,38 +474,37 @@ impl phy::TxToken for E1000TxToken {
     where
         F: FnOnce(&mut [u8]) -> Result<R>,
     {
-        let mut buffer = [0u8; 1536];
+        let mut buffer = [0u8; MAX_FRAME_SIZE];
         let result = f(&mut buffer[..len]);
-
-        let mut driver = (self.0).0.lock();
-        driver.send(&buffer[..len]);
-
+        if result.is_ok() {
+            (self.0)
+                .0
+                .lock()
+                .send(&buffer[..len])
+                .map_err(|_| smoltcp::Error::Exhausted)?;
+        }
         result
     }
 }
 
-// JudgeDuck-OS/kern/e1000.c
-pub fn init(
-    name: String,
-    irq: usize,
-    header: usize,
-    size: usize,
-    index: usize,
-) -> DeviceResult<E1000Interface> {
+/// Initialize the e1000 with registers mapped at `header`, configured for
+/// the QEMU user network.
+pub fn init(name: String, irq: usize, header: usize, index: usize) -> DeviceResult<E1000Interface> {
     info!("Probing e1000 {}", name);
 
     // randomly generated
     let mac: [u8; 6] = [0x54, 0x51, 0x9F, 0x71, 0xC0, index as u8];
-
-    let e1000 = E1000::new(header, size, DriverEthernetAddress::from_bytes(&mac));
+    let e1000 = E1000::new(header, mac)?;
+    let ethernet_addr = EthernetAddress::from_bytes(&e1000.mac());
+    if !e1000.link_up() {
+        warn!("e1000 {}: link is down", name);
+    }
 
     let net_driver = E1000Driver(Arc::new(Mutex::new(e1000)));
 
-    let ethernet_addr = EthernetAddress::from_bytes(&mac);
     let ip_addrs = [IpCidr::new(IpAddress::v4(10, 0, 2, (15 + index) as u8), 24)];
     let default_v4_gw = Ipv4Address::new(10, 0, 2, 2); //Qemu user network gateway: 10.0.2.2
-    static mut ROUTES_STORAGE: [Option<(IpCidr, Route)>; 1] = [None; 1];
-    let mut routes = unsafe { Routes::new(&mut ROUTES_STORAGE[..]) };
+    let mut routes = Routes::new(BTreeMap::new());
     routes.add_default_ipv4_route(default_v4_gw).unwrap();
     let neighbor_cache = NeighborCache::new(BTreeMap::new());
 
@@ -199,9 +516,10 @@ pub fn init(
         .finalize();
 
     info!(
-        "e1000 interface {} up with addr 10.0.2.{}/24",
+        "e1000 interface {} up with addr 10.0.2.{}/24, mac {}",
         name,
-        15 + index
+        15 + index,
+        ethernet_addr
     );
     let e1000_iface = E1000Interface {
         iface: Arc::new(Mutex::new(iface)),
@@ -1,7 +1,13 @@
 // smoltcp
-use smoltcp::{iface::Interface, phy::Loopback, time::Instant};
+use smoltcp::{
+    iface::{Interface, InterfaceBuilder, NeighborCache},
+    phy::{Loopback, Medium},
+    time::Instant,
+    wire::IpAddress,
+};
 
-use crate::net::get_sockets;
+use crate::net::{get_sockets, timer_now_as_micros, SOCKET_ACTIVITY};
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 
 use alloc::string::String;
@@ -20,6 +26,31 @@ pub struct LoopbackInterface {
     pub name: String,
 }
 
+impl LoopbackInterface {
+    /// Create the `lo` interface, looping back the packets to 127.0.0.1/8.
+    pub fn new() -> Self {
+        // the loopback device carries ethernet frames, with a made-up address
+        let loopback = Loopback::new(Medium::Ethernet);
+        let mac: [u8; 6] = [0x52, 0x54, 0x98, 0x76, 0x54, 0x32];
+        let ip_addrs = [IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8)];
+        let iface = InterfaceBuilder::new(loopback)
+            .ethernet_addr(EthernetAddress::from_bytes(&mac))
+            .ip_addrs(ip_addrs)
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .finalize();
+        LoopbackInterface {
+            iface: Arc::new(Mutex::new(iface)),
+            name: String::from("lo"),
+        }
+    }
+}
+
+impl Default for LoopbackInterface {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scheme for LoopbackInterface {
     fn name(&self) -> &str {
         "loopback"
@@ -36,11 +67,16 @@ impl NetScheme for LoopbackInterface {
         unimplemented!()
     }
     fn poll(&self) -> DeviceResult {
-        let timestamp = Instant::from_millis(0);
+        let timestamp = Instant::from_micros(timer_now_as_micros() as i64);
         let sockets = get_sockets();
         let mut sockets = sockets.lock();
         match self.iface.lock().poll(&mut sockets, timestamp) {
-            Ok(_) => Ok(()),
+            Ok(changed) => {
+                if changed {
+                    SOCKET_ACTIVITY.trigger(());
+                }
+                Ok(())
+            }
             Err(err) => {
                 debug!("poll got err {}", err);
                 Err(DeviceError::IoError)
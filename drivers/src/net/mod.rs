@@ -5,6 +5,8 @@ use alloc::{sync::Arc, vec};
 use lock::Mutex;
 use smoltcp::socket::SocketSet;
 
+use crate::utils::EventListener;
+
 pub mod e1000;
 pub mod loopback;
 pub use isomorphic_drivers::provider::Provider;
@@ -12,7 +14,7 @@ pub use loopback::LoopbackInterface;
 
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "riscv64")] {
-mod realtek;
+pub(crate) mod realtek;
 mod rtlx;
 
 pub use rtlx::*;
@@ -80,6 +82,10 @@ type PhysAddr = usize;
 lazy_static::lazy_static! {
     pub static ref SOCKETS: Arc<Mutex<SocketSet<'static>>> =
     Arc::new(Mutex::new(SocketSet::new(vec![])));
+
+    /// Triggered when an interface poll may have changed the state of the
+    /// sockets, to wake up the tasks waiting on them.
+    pub static ref SOCKET_ACTIVITY: EventListener = EventListener::new();
 }
 
 // 注意！这个容易出现死锁
@@ -21,4 +21,4 @@ mod log {
 
 pub mod mii;
 pub mod rtl8211f;
-mod utils;
+pub(crate) mod utils;
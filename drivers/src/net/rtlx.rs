@@ -14,7 +14,7 @@ use smoltcp::Result;
 use super::realtek::rtl8211f::{self, RTL8211F};
 use super::{timer_now_as_micros, ProviderImpl, PAGE_SIZE};
 
-use crate::net::get_sockets;
+use crate::net::{get_sockets, SOCKET_ACTIVITY};
 use crate::scheme::{NetScheme, Scheme};
 use crate::{DeviceError, DeviceResult};
 
@@ -52,6 +52,9 @@ impl Scheme for RTLxInterface {
             match self.iface.lock().poll(&mut sockets, timestamp) {
                 Ok(b) => {
                     debug!("nic poll, is changed ?: {}", b);
+                    if b {
+                        SOCKET_ACTIVITY.trigger(());
+                    }
                 }
                 Err(err) => {
                     error!("poll got err {}", err);
@@ -83,6 +86,9 @@ impl NetScheme for RTLxInterface {
         match self.iface.lock().poll(&mut sockets, timestamp) {
             Ok(b) => {
                 debug!("nic poll, is changed ?: {}", b);
+                if b {
+                    SOCKET_ACTIVITY.trigger(());
+                }
                 Ok(())
             }
             Err(err) => {
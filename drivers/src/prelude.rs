@@ -1,8 +1,11 @@
 //! Re-export most commonly used driver types.
 
 pub use crate::scheme::display::{ColorFormat, DisplayInfo, FrameBuffer, Rectangle, RgbColor};
-pub use crate::scheme::input::{CapabilityType, InputCapability, InputEvent, InputEventType};
+pub use crate::scheme::input::{
+    CapabilityType, InputAbsInfo, InputCapability, InputEvent, InputEventType, InputId,
+};
 pub use crate::scheme::irq::{IrqHandler, IrqPolarity, IrqTriggerMode};
+pub use crate::scheme::rtc::RtcTime;
 pub use crate::{Device, DeviceError, DeviceResult};
 
 /// Re-export types from [`input`](crate::input).
@@ -0,0 +1,140 @@
+use core::time::Duration;
+
+use lock::Mutex;
+
+use crate::io::{Io, Pmio};
+use crate::prelude::RtcTime;
+use crate::scheme::{RtcScheme, Scheme};
+use crate::DeviceResult;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+/// An update is in progress, in status register A.
+const STATUS_A_UPDATING: u8 = 0x80;
+/// The updates are stopped to set the time, in status register B.
+const STATUS_B_SET: u8 = 0x80;
+/// The values are binary instead of BCD, in status register B.
+const STATUS_B_BINARY: u8 = 0x04;
+/// The hours are in the 24-hour format, in status register B.
+const STATUS_B_24H: u8 = 0x02;
+/// PM in the hours register, in the 12-hour format.
+const HOURS_PM: u8 = 0x80;
+
+struct CmosInner {
+    index: Pmio<u8>,
+    data: Pmio<u8>,
+}
+
+impl CmosInner {
+    fn read(&mut self, reg: u8) -> u8 {
+        self.index.write(reg);
+        self.data.read()
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        self.index.write(reg);
+        self.data.write(value);
+    }
+}
+
+/// The real-time clock in the CMOS of PCs.
+pub struct CmosRtc {
+    inner: Mutex<CmosInner>,
+}
+
+impl CmosRtc {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(CmosInner {
+                index: Pmio::new(0x70),
+                data: Pmio::new(0x71),
+            }),
+        }
+    }
+}
+
+impl Default for CmosRtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheme for CmosRtc {
+    fn name(&self) -> &str {
+        "cmos-rtc"
+    }
+}
+
+impl RtcScheme for CmosRtc {
+    fn read_time(&self) -> DeviceResult<Duration> {
+        let mut inner = self.inner.lock();
+        // wait until the RTC is not updating
+        while inner.read(REG_STATUS_A) & STATUS_A_UPDATING != 0 {
+            core::hint::spin_loop();
+        }
+        let status_b = inner.read(REG_STATUS_B);
+        let decode = |value: u8| {
+            if status_b & STATUS_B_BINARY == 0 {
+                (value >> 4) * 10 + (value & 0x0f)
+            } else {
+                value
+            }
+        };
+        let hour_raw = inner.read(REG_HOURS);
+        let mut hour = decode(hour_raw & !HOURS_PM);
+        if status_b & STATUS_B_24H == 0 {
+            hour %= 12;
+            if hour_raw & HOURS_PM != 0 {
+                hour += 12;
+            }
+        }
+        let time = RtcTime {
+            year: decode(inner.read(REG_YEAR)) as u32 + 2000,
+            month: decode(inner.read(REG_MONTH)),
+            day: decode(inner.read(REG_DAY)),
+            hour,
+            minute: decode(inner.read(REG_MINUTES)),
+            second: decode(inner.read(REG_SECONDS)),
+        };
+        Ok(Duration::from_secs(time.to_unix()))
+    }
+
+    fn set_time(&self, time: Duration) -> DeviceResult {
+        let time = RtcTime::from_unix(time.as_secs());
+        let mut inner = self.inner.lock();
+        let status_b = inner.read(REG_STATUS_B);
+        let encode = |value: u8| {
+            if status_b & STATUS_B_BINARY == 0 {
+                (value / 10) << 4 | (value % 10)
+            } else {
+                value
+            }
+        };
+        let hour = if status_b & STATUS_B_24H == 0 {
+            let pm = if time.hour >= 12 { HOURS_PM } else { 0 };
+            let hour = match time.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            encode(hour) | pm
+        } else {
+            encode(time.hour)
+        };
+        inner.write(REG_STATUS_B, status_b | STATUS_B_SET);
+        inner.write(REG_SECONDS, encode(time.second));
+        inner.write(REG_MINUTES, encode(time.minute));
+        inner.write(REG_HOURS, hour);
+        inner.write(REG_DAY, encode(time.day));
+        inner.write(REG_MONTH, encode(time.month));
+        inner.write(REG_YEAR, encode((time.year % 100) as u8));
+        inner.write(REG_STATUS_B, status_b & !STATUS_B_SET);
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use core::time::Duration;
+
+use lock::Mutex;
+
+use crate::io::{Io, Mmio};
+use crate::scheme::{RtcScheme, Scheme};
+use crate::DeviceResult;
+
+/// Reading it latches the high half.
+const TIME_LOW: usize = 0x00 / 4;
+const TIME_HIGH: usize = 0x04 / 4;
+
+/// The Goldfish RTC of the QEMU `virt` machine, counting nanoseconds since
+/// the UNIX epoch.
+pub struct GoldfishRtc {
+    base: Mutex<&'static mut Mmio<u32>>,
+}
+
+impl GoldfishRtc {
+    pub fn new(base: usize) -> Self {
+        Self {
+            base: Mutex::new(unsafe { Mmio::<u32>::from_base(base) }),
+        }
+    }
+}
+
+impl Scheme for GoldfishRtc {
+    fn name(&self) -> &str {
+        "goldfish-rtc"
+    }
+}
+
+impl RtcScheme for GoldfishRtc {
+    fn read_time(&self) -> DeviceResult<Duration> {
+        let base = self.base.lock();
+        let low = base.add(TIME_LOW).read() as u64;
+        let high = base.add(TIME_HIGH).read() as u64;
+        Ok(Duration::from_nanos(high << 32 | low))
+    }
+
+    fn set_time(&self, time: Duration) -> DeviceResult {
+        let nanos = time.as_nanos() as u64;
+        let base = self.base.lock();
+        // the time is set when the low half is written
+        base.add(TIME_HIGH).write((nanos >> 32) as u32);
+        base.add(TIME_LOW).write(nanos as u32);
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+//! Real-time clock drivers.
+
+#[cfg(target_arch = "x86_64")]
+mod cmos;
+mod goldfish;
+
+#[cfg(target_arch = "x86_64")]
+pub use cmos::CmosRtc;
+pub use goldfish::GoldfishRtc;
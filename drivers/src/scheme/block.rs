@@ -1,8 +1,29 @@
+use alloc::boxed::Box;
+use core::future::{ready, Future};
+use core::pin::Pin;
+
 use super::Scheme;
 use crate::DeviceResult;
 
+/// The future of an asynchronous block request.
+pub type BlockFuture<'a> = Pin<Box<dyn Future<Output = DeviceResult> + Send + 'a>>;
+
 pub trait BlockScheme: Scheme {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult;
     fn write_block(&self, block_id: usize, buf: &[u8]) -> DeviceResult;
     fn flush(&self) -> DeviceResult;
+
+    /// Read a block, completing when the device does.
+    ///
+    /// Devices without a request queue complete it synchronously.
+    fn read_block_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> BlockFuture<'a> {
+        Box::pin(ready(self.read_block(block_id, buf)))
+    }
+
+    /// Write a block, completing when the device does.
+    ///
+    /// Devices without a request queue complete it synchronously.
+    fn write_block_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> BlockFuture<'a> {
+        Box::pin(ready(self.write_block(block_id, buf)))
+    }
 }
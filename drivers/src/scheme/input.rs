@@ -62,6 +62,25 @@ pub enum CapabilityType {
     InputProp,
 }
 
+/// The identity of an input device, see `struct input_id` of Linux.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+/// The range of an absolute axis, see `struct input_absinfo` of Linux.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputAbsInfo {
+    pub min: i32,
+    pub max: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
 pub struct InputCapability {
     /// bitmap to support up to 1024 bits.
     bitmap: [u64; 16],
@@ -76,7 +95,7 @@ impl InputCapability {
         let mut cap = Self::empty();
         let bitcount = bitmap.len() as u16 * 8;
         for i in 0..bitcount as usize {
-            if bitmap[i / 8] & (1 << (i % 64)) != 0 {
+            if bitmap[i / 8] & (1 << (i % 8)) != 0 {
                 cap.set(i as u16);
             }
         }
@@ -87,6 +106,10 @@ impl InputCapability {
         self.bitmap[code as usize / 64] |= 1 << (code % 64);
     }
 
+    pub fn unset(&mut self, code: u16) {
+        self.bitmap[code as usize / 64] &= !(1 << (code % 64));
+    }
+
     pub fn set_all(&mut self, codes: &[u16]) {
         for &c in codes {
             self.set(c);
@@ -105,6 +128,15 @@ impl InputCapability {
         }
         true
     }
+
+    /// The bitmap in bytes, bit `i` in `bytes[i / 8]`, as in the `EVIOCGBIT` ioctl.
+    pub fn to_bytes(&self) -> [u8; 128] {
+        let mut bytes = [0; 128];
+        for (i, word) in self.bitmap.iter().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
 }
 
 impl fmt::Debug for InputCapability {
@@ -128,4 +160,14 @@ impl fmt::Debug for InputCapability {
 pub trait InputScheme: Scheme + EventScheme<Event = InputEvent> {
     /// Returns the capability bitmap of the specific kind of event.
     fn capability(&self, cap_type: CapabilityType) -> InputCapability;
+
+    /// Returns the identity of the device.
+    fn id(&self) -> InputId {
+        InputId::default()
+    }
+
+    /// Returns the range of the absolute axis `axis`, if the device has it.
+    fn abs_info(&self, _axis: u16) -> Option<InputAbsInfo> {
+        None
+    }
 }
@@ -3,7 +3,7 @@ use alloc::sync::Arc;
 use core::ops::Range;
 
 use super::Scheme;
-use crate::DeviceResult;
+use crate::{DeviceError, DeviceResult};
 
 /// A type alias for
 pub type IrqHandler = Box<dyn Fn() + Send + Sync>;
@@ -33,7 +33,7 @@ pub trait IrqScheme: Scheme {
     /// Configure the specified interrupt vector. If it is invoked, it must be
     /// invoked prior to interrupt registration.
     fn configure(&self, _irq_num: usize, _tm: IrqTriggerMode, _pol: IrqPolarity) -> DeviceResult {
-        unimplemented!()
+        Err(DeviceError::NotSupported)
     }
 
     /// Add an interrupt handler to an IRQ.
@@ -10,6 +10,8 @@ pub(super) mod display;
 pub(super) mod input;
 pub(super) mod irq;
 pub(super) mod net;
+pub(super) mod rng;
+pub(super) mod rtc;
 pub(super) mod uart;
 
 #[macro_use]
@@ -18,12 +20,14 @@ pub(super) use impl_event_scheme;
 
 use alloc::sync::Arc;
 
-pub use block::BlockScheme;
+pub use block::{BlockFuture, BlockScheme};
 pub use display::DisplayScheme;
 pub use event::EventScheme;
 pub use input::InputScheme;
 pub use irq::IrqScheme;
 pub use net::NetScheme;
+pub use rng::RngScheme;
+pub use rtc::RtcScheme;
 pub use uart::UartScheme;
 
 /// Common of all device drivers.
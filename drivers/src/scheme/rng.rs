@@ -0,0 +1,8 @@
+use super::Scheme;
+use crate::DeviceResult;
+
+pub trait RngScheme: Scheme {
+    /// Read random bytes from the device, returns the number of bytes read,
+    /// which may be less than the length of `buf`.
+    fn read(&self, buf: &mut [u8]) -> DeviceResult<usize>;
+}
@@ -0,0 +1,105 @@
+use core::time::Duration;
+
+use super::Scheme;
+use crate::{DeviceError, DeviceResult};
+
+/// A date and time of the Gregorian calendar, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub year: u32,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl RtcTime {
+    /// The date and time `secs` seconds after the UNIX epoch.
+    pub fn from_unix(secs: u64) -> Self {
+        let days = secs / 86400;
+        let rem = secs % 86400;
+        // count years from March, so that the leap day is the last one
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as u64;
+        Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// Seconds since the UNIX epoch.
+    pub fn to_unix(&self) -> u64 {
+        self.days() * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    /// Day of the week, from 0 for Sunday.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((self.days() + 4) % 7) as u8
+    }
+
+    /// Day of the year, from 0 for January 1.
+    pub fn yearday(&self) -> u16 {
+        let jan1 = Self {
+            month: 1,
+            day: 1,
+            ..*self
+        };
+        (self.days() - jan1.days()) as u16
+    }
+
+    /// Number of days since 1970-01-01.
+    fn days(&self) -> u64 {
+        let (year, month, day) = (self.year as u64, self.month as u64, self.day as u64);
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+}
+
+pub trait RtcScheme: Scheme {
+    /// Read the wall-clock time, since the UNIX epoch.
+    fn read_time(&self) -> DeviceResult<Duration>;
+
+    /// Set the wall-clock time, since the UNIX epoch.
+    fn set_time(&self, _time: Duration) -> DeviceResult {
+        Err(DeviceError::NotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtcTime;
+
+    #[test]
+    fn calendar() {
+        // 2000-02-29 00:00:00, a Tuesday
+        let t = RtcTime::from_unix(951_782_400);
+        assert_eq!((t.year, t.month, t.day), (2000, 2, 29));
+        assert_eq!((t.weekday(), t.yearday()), (2, 59));
+        assert_eq!(t.to_unix(), 951_782_400);
+        // 2099-12-31 23:59:59
+        let t = RtcTime::from_unix(4_102_444_799);
+        assert_eq!((t.year, t.month, t.day), (2099, 12, 31));
+        assert_eq!((t.hour, t.minute, t.second), (23, 59, 59));
+        assert_eq!(t.to_unix(), 4_102_444_799);
+    }
+}
@@ -25,6 +25,9 @@ pub struct InheritProps {
     /// The `interrupt-parent` property of the node. If don't have, inherit from
     /// its parent node.
     pub interrupt_parent: u32,
+    /// The hart ID of the nearest `cpu` node above, for the local interrupt
+    /// controller of each hart.
+    pub hart_id: Option<u32>,
 }
 
 impl Devicetree {
@@ -42,14 +45,55 @@ impl Devicetree {
         }
     }
 
+    /// The path in `stdout-path` of `/chosen`, with the aliases resolved and
+    /// the options after `:` removed.
+    pub fn stdout_path(&self) -> Option<&str> {
+        let chosen = self.0.find("/chosen")?;
+        let path = chosen
+            .prop_str("stdout-path")
+            .or_else(|_| chosen.prop_str("linux,stdout-path"))
+            .ok()?;
+        let path = path.split(':').next()?;
+        if path.starts_with('/') {
+            Some(path)
+        } else {
+            self.0.find("/aliases")?.prop_str(path).ok()
+        }
+    }
+
+    /// Find the node with the `phandle`, wherever it is and whatever its
+    /// status is.
+    pub fn find_by_phandle(&self, phandle: u32) -> Option<&Node> {
+        fn find(node: &Node, phandle: u32) -> Option<&Node> {
+            if matches!(node.prop_u32("phandle"), Ok(p) if p == phandle) {
+                return Some(node);
+            }
+            node.children.iter().find_map(|child| find(child, phandle))
+        }
+        find(&self.0.root, phandle)
+    }
+
     fn walk_inner<F>(&self, node: &Node, props: InheritProps, device_node_op: &mut F)
     where
         F: FnMut(&Node, &StringList, &InheritProps),
     {
+        // the nodes disabled and their children are not there
+        if let Ok(status) = node.prop_str("status") {
+            if status != "okay" && status != "ok" {
+                debug!(
+                    "device-tree: skip node {:?} with status {:?}",
+                    node.name, status
+                );
+                return;
+            }
+        }
         let mut props = props;
         if let Ok(num) = node.prop_u32("interrupt-parent") {
             props.interrupt_parent = num;
         }
+        if let Ok("cpu") = node.prop_str("device_type") {
+            props.hart_id = parse_reg(node, &props).ok().map(|(reg, _)| reg as u32);
+        }
         if let Ok(comp) = node.prop_str_list("compatible") {
             device_node_op(node, &comp, &props);
         }
@@ -0,0 +1,43 @@
+use crate::net::{Provider, ProviderImpl, PAGE_SIZE};
+use crate::{DeviceError, DeviceResult};
+
+/// Physically contiguous memory the device accesses by DMA, zeroed on
+/// allocation and freed on drop.
+pub(crate) struct DmaRegion {
+    vaddr: usize,
+    paddr: usize,
+    size: usize,
+}
+
+impl DmaRegion {
+    /// Allocate at least `size` bytes, rounded up to whole pages.
+    pub fn new(size: usize) -> DeviceResult<Self> {
+        let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let (vaddr, paddr) = ProviderImpl::alloc_dma(size);
+        if paddr == 0 {
+            return Err(DeviceError::DmaError);
+        }
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, size) };
+        Ok(Self { vaddr, paddr, size })
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.vaddr
+    }
+
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    /// A pointer to the `index`-th `T` in the region.
+    pub fn as_ptr<T>(&self, index: usize) -> *mut T {
+        debug_assert!((index + 1) * core::mem::size_of::<T>() <= self.size);
+        (self.vaddr as *mut T).wrapping_add(index)
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        ProviderImpl::dealloc_dma(self.vaddr, self.size);
+    }
+}
@@ -1,5 +1,6 @@
 //! Event handler and device tree.
 
+mod dma;
 mod event_listener;
 mod id_allocator;
 mod irq_manager;
@@ -9,6 +10,7 @@ mod graphic_console;
 
 pub mod devicetree;
 
+pub(crate) use dma::DmaRegion;
 pub(super) use id_allocator::IdAllocator;
 pub(super) use irq_manager::IrqManager;
 
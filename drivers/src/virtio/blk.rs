@@ -1,19 +1,198 @@
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
 use lock::Mutex;
-use virtio_drivers::{VirtIOBlk as InnerDriver, VirtIOHeader};
+use virtio_drivers::{BlkResp, Error, RespStatus, VirtIOBlk as InnerDriver, VirtIOHeader};
+
+use crate::scheme::{BlockFuture, BlockScheme, Scheme};
+use crate::{DeviceError, DeviceResult};
 
-use crate::scheme::{BlockScheme, Scheme};
-use crate::DeviceResult;
+const BLK_SIZE: usize = 512;
+
+/// A request on the virtqueue.
+///
+/// It owns the memory the device reads and writes, so that the request can
+/// be abandoned by its future without the device accessing freed memory.
+struct BlkRequest {
+    buf: Box<[u8; BLK_SIZE]>,
+    resp: Box<BlkResp>,
+    done: bool,
+    waker: Option<Waker>,
+}
 
+/// A virtio block device, with requests completed by its interrupt.
 pub struct VirtIoBlk<'a> {
     inner: Mutex<InnerDriver<'a>>,
+    /// The requests in flight, by the head descriptor of their chain.
+    pending: Mutex<BTreeMap<u16, Arc<Mutex<BlkRequest>>>>,
+    /// The tasks waiting for room in the virtqueue.
+    queue_waiters: Mutex<Vec<Waker>>,
 }
 
 impl<'a> VirtIoBlk<'a> {
     pub fn new(header: &'static mut VirtIOHeader) -> DeviceResult<Self> {
         Ok(Self {
             inner: Mutex::new(InnerDriver::new(header)?),
+            pending: Mutex::new(BTreeMap::new()),
+            queue_waiters: Mutex::new(Vec::new()),
         })
     }
+
+    /// Put a request on the virtqueue, `None` if it is full.
+    fn submit(
+        &self,
+        block_id: usize,
+        write: Option<&[u8]>,
+    ) -> DeviceResult<Option<Arc<Mutex<BlkRequest>>>> {
+        let mut req = BlkRequest {
+            buf: Box::new([0; BLK_SIZE]),
+            resp: Box::new(BlkResp::default()),
+            done: false,
+            waker: None,
+        };
+        let mut inner = self.inner.lock();
+        let token = match write {
+            Some(data) => {
+                req.buf.copy_from_slice(data);
+                inner.write_block_nb(block_id, &req.buf[..], &mut req.resp)
+            }
+            None => inner.read_block_nb(block_id, &mut req.buf[..], &mut req.resp),
+        };
+        match token {
+            Ok(token) => {
+                let req = Arc::new(Mutex::new(req));
+                // before the device lock is released, not to miss the completion
+                self.pending.lock().insert(token, req.clone());
+                Ok(Some(req))
+            }
+            // not enough free descriptors
+            Err(Error::BufferTooSmall) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Complete the requests the device has finished.
+    fn reap(&self) {
+        let mut tokens = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            while let Ok(token) = inner.pop_used() {
+                tokens.push(token);
+            }
+        }
+        if tokens.is_empty() {
+            return;
+        }
+        let mut pending = self.pending.lock();
+        for token in tokens {
+            if let Some(req) = pending.remove(&token) {
+                let mut req = req.lock();
+                req.done = true;
+                if let Some(waker) = req.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        drop(pending);
+        for waker in core::mem::take(&mut *self.queue_waiters.lock()) {
+            waker.wake();
+        }
+    }
+
+    fn request<'b>(&'b self, block_id: usize, op: BlkOp<'b>) -> BlkFuture<'b, 'a> {
+        BlkFuture {
+            blk: self,
+            block_id,
+            op,
+            request: None,
+        }
+    }
+}
+
+enum BlkOp<'b> {
+    Read(&'b mut [u8]),
+    Write(&'b [u8]),
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct BlkFuture<'b, 'a> {
+    blk: &'b VirtIoBlk<'a>,
+    block_id: usize,
+    op: BlkOp<'b>,
+    request: Option<Arc<Mutex<BlkRequest>>>,
+}
+
+impl Future for BlkFuture<'_, '_> {
+    type Output = DeviceResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let len = match &this.op {
+            BlkOp::Read(buf) => buf.len(),
+            BlkOp::Write(buf) => buf.len(),
+        };
+        if len != BLK_SIZE {
+            return Poll::Ready(Err(DeviceError::InvalidParam));
+        }
+        // complete the requests even if the interrupt is not delivered
+        this.blk.reap();
+        let req = match &this.request {
+            Some(req) => req.clone(),
+            None => {
+                let mut waiters = this.blk.queue_waiters.lock();
+                let data = match &this.op {
+                    BlkOp::Read(_) => None,
+                    BlkOp::Write(data) => Some(&data[..]),
+                };
+                match this.blk.submit(this.block_id, data) {
+                    Ok(Some(req)) => {
+                        this.request = Some(req.clone());
+                        req
+                    }
+                    Ok(None) => {
+                        waiters.push(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+        };
+        let mut req = req.lock();
+        if !req.done {
+            req.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        if req.resp.status() != RespStatus::Ok {
+            return Poll::Ready(Err(DeviceError::IoError));
+        }
+        if let BlkOp::Read(buf) = &mut this.op {
+            buf.copy_from_slice(&req.buf[..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Busy-wait the completion of `future`, for the synchronous interface.
+fn wait<F: Future>(future: F) -> F::Output {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(ret) = future.as_mut().poll(&mut cx) {
+            return ret;
+        }
+        core::hint::spin_loop();
+    }
 }
 
 impl<'a> Scheme for VirtIoBlk<'a> {
@@ -22,22 +201,30 @@ impl<'a> Scheme for VirtIoBlk<'a> {
     }
 
     fn handle_irq(&self, _irq_num: usize) {
-        self.inner.lock().ack_interrupt();
+        if self.inner.lock().ack_interrupt() {
+            self.reap();
+        }
     }
 }
 
 impl<'a> BlockScheme for VirtIoBlk<'a> {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) -> DeviceResult {
-        self.inner.lock().read_block(block_id, buf)?;
-        Ok(())
+        wait(self.request(block_id, BlkOp::Read(buf)))
     }
 
     fn write_block(&self, block_id: usize, buf: &[u8]) -> DeviceResult {
-        self.inner.lock().write_block(block_id, buf)?;
-        Ok(())
+        wait(self.request(block_id, BlkOp::Write(buf)))
     }
 
     fn flush(&self) -> DeviceResult {
         Ok(())
     }
+
+    fn read_block_async<'b>(&'b self, block_id: usize, buf: &'b mut [u8]) -> BlockFuture<'b> {
+        Box::pin(self.request(block_id, BlkOp::Read(buf)))
+    }
+
+    fn write_block_async<'b>(&'b self, block_id: usize, buf: &'b [u8]) -> BlockFuture<'b> {
+        Box::pin(self.request(block_id, BlkOp::Write(buf)))
+    }
 }
@@ -1,55 +1,384 @@
+use alloc::{collections::VecDeque, format, string::String, sync::Arc, vec::Vec};
 use core::fmt::{Result, Write};
 
 use lock::Mutex;
-use virtio_drivers::{VirtIOConsole as InnerDriver, VirtIOHeader};
+use virtio_drivers::VirtIOHeader;
 
+use super::queue::VirtQueue;
+use crate::net::timer_now_as_micros;
 use crate::prelude::DeviceResult;
-use crate::scheme::{impl_event_scheme, Scheme, UartScheme};
-use crate::utils::EventListener;
+use crate::scheme::{EventScheme, Scheme, UartScheme};
+use crate::utils::{EventHandler, EventListener};
+use crate::DeviceError;
 
-pub struct VirtIoConsole<'a> {
-    inner: Mutex<InnerDriver<'a>>,
-    listener: EventListener,
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+
+/// The ports supported at most, 31 with the default QEMU settings.
+const MAX_PORTS: usize = 8;
+const QUEUE_SIZE: u16 = 16;
+const BUF_SIZE: usize = 256;
+
+/// How long the device is silent after the ports are announced.
+const PORT_SCAN_QUIET_US: u64 = 10_000;
+const PORT_SCAN_TIMEOUT_US: u64 = 200_000;
+
+// control events
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+const VIRTIO_CONSOLE_PORT_ADD: u16 = 1;
+const VIRTIO_CONSOLE_PORT_REMOVE: u16 = 2;
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+const VIRTIO_CONSOLE_CONSOLE_PORT: u16 = 4;
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+const VIRTIO_CONSOLE_PORT_NAME: u16 = 7;
+
+/// `struct virtio_console_control`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ControlMsg {
+    id: u32,
+    event: u16,
+    value: u16,
 }
 
-impl_event_scheme!(VirtIoConsole<'_>);
+const CONTROL_MSG_SIZE: usize = core::mem::size_of::<ControlMsg>();
 
-impl<'a> VirtIoConsole<'a> {
-    pub fn new(header: &'static mut VirtIOHeader) -> DeviceResult<Self> {
-        Ok(Self {
-            inner: Mutex::new(InnerDriver::new(header)?),
-            listener: EventListener::new(),
+impl ControlMsg {
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < CONTROL_MSG_SIZE {
+            return None;
+        }
+        Some(Self {
+            id: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            event: u16::from_le_bytes([buf[4], buf[5]]),
+            value: u16::from_le_bytes([buf[6], buf[7]]),
         })
     }
+
+    fn to_bytes(self) -> [u8; CONTROL_MSG_SIZE] {
+        let mut buf = [0; CONTROL_MSG_SIZE];
+        buf[0..4].copy_from_slice(&self.id.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.event.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.value.to_le_bytes());
+        buf
+    }
+}
+
+struct Port {
+    rx: VirtQueue,
+    tx: VirtQueue,
+    /// The bytes received but not read.
+    rx_data: VecDeque<u8>,
+    /// Announced by the device, always with a single port.
+    present: bool,
+}
+
+struct ConsoleInner {
+    header: &'static mut VirtIOHeader,
+    /// The receive and transmit queues of the control messages, with
+    /// `VIRTIO_CONSOLE_F_MULTIPORT`.
+    control: Option<(VirtQueue, VirtQueue)>,
+    ports: Vec<Port>,
+    /// The port to use as the console, announced by the device.
+    console_port: Option<usize>,
+    /// The control messages received.
+    control_msgs: usize,
 }
 
-impl<'a> Scheme for VirtIoConsole<'a> {
+impl ConsoleInner {
+    /// Send `data` from `port`, waiting for room in the queue.
+    fn send(&mut self, port: usize, data: &[u8]) -> DeviceResult {
+        let header = &mut *self.header;
+        let tx = &mut self.ports[port].tx;
+        for chunk in data.chunks(tx.buf_size()) {
+            loop {
+                // reclaim the buffers sent
+                while tx.pop_used().is_some() {}
+                let added = tx.add(header, chunk.len(), false, |buf| buf.copy_from_slice(chunk));
+                if added.is_some() {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        Ok(())
+    }
+
+    fn send_control(&mut self, id: u32, event: u16, value: u16) -> DeviceResult {
+        let header = &mut *self.header;
+        let (_, ctrl_tx) = self.control.as_mut().ok_or(DeviceError::NotSupported)?;
+        let msg = ControlMsg { id, event, value }.to_bytes();
+        loop {
+            while ctrl_tx.pop_used().is_some() {}
+            if ctrl_tx
+                .add(header, msg.len(), false, |buf| buf.copy_from_slice(&msg))
+                .is_some()
+            {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn handle_control(&mut self, msg: ControlMsg, extra: &[u8]) -> DeviceResult {
+        let id = msg.id as usize;
+        if id >= self.ports.len() {
+            warn!("virtio-console: port {} ignored, at most {}", id, MAX_PORTS);
+            return Ok(());
+        }
+        match msg.event {
+            VIRTIO_CONSOLE_PORT_ADD => {
+                self.ports[id].present = true;
+                self.send_control(msg.id, VIRTIO_CONSOLE_PORT_READY, 1)?;
+                // it is always open on our side
+                self.send_control(msg.id, VIRTIO_CONSOLE_PORT_OPEN, 1)?;
+            }
+            VIRTIO_CONSOLE_PORT_REMOVE => self.ports[id].present = false,
+            VIRTIO_CONSOLE_CONSOLE_PORT => {
+                if self.console_port.is_none() {
+                    self.console_port = Some(id);
+                }
+            }
+            VIRTIO_CONSOLE_PORT_OPEN => {
+                debug!("virtio-console: port {} open by host: {}", id, msg.value);
+            }
+            VIRTIO_CONSOLE_PORT_NAME => {
+                info!(
+                    "virtio-console: port {} is named {:?}",
+                    id,
+                    String::from_utf8_lossy(extra)
+                );
+            }
+            event => debug!("virtio-console: control event {} ignored", event),
+        }
+        Ok(())
+    }
+
+    /// Take the data and the control messages from the device, and return
+    /// the ports that received data.
+    fn poll(&mut self) -> Vec<usize> {
+        let mut messages = Vec::new();
+        if let Some((ctrl_rx, _)) = &mut self.control {
+            while let Some((id, len)) = ctrl_rx.pop_used() {
+                let buf = &ctrl_rx.buffer(id)[..len];
+                if let Some(msg) = ControlMsg::from_bytes(buf) {
+                    messages.push((msg, buf[CONTROL_MSG_SIZE..].to_vec()));
+                }
+                ctrl_rx.add(self.header, BUF_SIZE, true, |_| {});
+            }
+        }
+        self.control_msgs += messages.len();
+        for (msg, extra) in messages {
+            if let Err(err) = self.handle_control(msg, &extra) {
+                warn!("virtio-console: failed to handle {:?}: {:?}", msg, err);
+            }
+        }
+
+        let mut ready = Vec::new();
+        let header = &mut *self.header;
+        for (i, port) in self.ports.iter_mut().enumerate() {
+            let mut received = false;
+            while let Some((id, len)) = port.rx.pop_used() {
+                port.rx_data.extend(&port.rx.buffer(id)[..len]);
+                port.rx.add(header, BUF_SIZE, true, |_| {});
+                received = true;
+            }
+            if received {
+                ready.push(i);
+            }
+        }
+        ready
+    }
+}
+
+/// The state of a console device shared by its ports.
+struct ConsoleDev {
+    inner: Mutex<ConsoleInner>,
+    listeners: Vec<EventListener>,
+}
+
+impl ConsoleDev {
+    fn poll(&self) {
+        let ready = self.inner.lock().poll();
+        // not to deadlock with the handlers receiving data
+        for port in ready {
+            self.listeners[port].trigger(());
+        }
+    }
+}
+
+/// A port of a virtio console.
+///
+/// With `VIRTIO_CONSOLE_F_MULTIPORT`, a device has up to [`MAX_PORTS`]
+/// ports, the others are ignored. The interrupt of the device can be
+/// handled by any of them.
+pub struct VirtIoConsole {
+    dev: Arc<ConsoleDev>,
+    port: usize,
+    name: String,
+}
+
+impl VirtIoConsole {
+    /// Initialize the device, and return its console port, or its first
+    /// port.
+    pub fn new(header: &'static mut VirtIOHeader) -> DeviceResult<Self> {
+        let mut multiport = false;
+        header.begin_init(|features| {
+            multiport = features & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+            features & VIRTIO_CONSOLE_F_MULTIPORT
+        });
+        let nr_ports = if multiport {
+            // struct virtio_console_config { cols, rows: u16, max_nr_ports: u32, .. }
+            let config = header.config_space() as *const u8;
+            let max_nr_ports = unsafe { (config.add(4) as *const u32).read_volatile() };
+            (max_nr_ports as usize).clamp(1, MAX_PORTS)
+        } else {
+            1
+        };
+
+        // port 0 on queue 0 and 1, control on 2 and 3, port N on 2N+2 and 2N+3
+        let mut ports = Vec::with_capacity(nr_ports);
+        let mut control = None;
+        for i in 0..nr_ports {
+            let rx_index = if i == 0 { 0 } else { 2 * i as u32 + 2 };
+            ports.push(Port {
+                rx: VirtQueue::new(header, rx_index, QUEUE_SIZE, BUF_SIZE)?,
+                tx: VirtQueue::new(header, rx_index + 1, QUEUE_SIZE, BUF_SIZE)?,
+                rx_data: VecDeque::new(),
+                present: !multiport,
+            });
+            if i == 0 && multiport {
+                control = Some((
+                    VirtQueue::new(header, 2, QUEUE_SIZE, BUF_SIZE)?,
+                    VirtQueue::new(header, 3, QUEUE_SIZE, BUF_SIZE)?,
+                ));
+            }
+        }
+        header.finish_init();
+
+        let mut inner = ConsoleInner {
+            header,
+            control,
+            ports,
+            console_port: None,
+            control_msgs: 0,
+        };
+        for port in inner.ports.iter_mut() {
+            while port.rx.add(inner.header, BUF_SIZE, true, |_| {}).is_some() {}
+        }
+        if let Some((ctrl_rx, _)) = &mut inner.control {
+            while ctrl_rx.add(inner.header, BUF_SIZE, true, |_| {}).is_some() {}
+        }
+
+        if multiport {
+            inner.send_control(0, VIRTIO_CONSOLE_DEVICE_READY, 1)?;
+            // the ports are announced in reply, before the interrupt is set up
+            let start = timer_now_as_micros();
+            let mut last = start;
+            loop {
+                let received = inner.control_msgs;
+                inner.poll();
+                let now = timer_now_as_micros();
+                if inner.control_msgs != received {
+                    last = now;
+                } else if now - last > PORT_SCAN_QUIET_US || now - start > PORT_SCAN_TIMEOUT_US {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+
+        let present: Vec<usize> = (0..inner.ports.len())
+            .filter(|&i| inner.ports[i].present)
+            .collect();
+        info!(
+            "virtio-console: ports {:?}, console port {:?}",
+            present, inner.console_port
+        );
+        let port = inner
+            .console_port
+            .or_else(|| present.first().copied())
+            .unwrap_or(0);
+        let dev = Arc::new(ConsoleDev {
+            listeners: (0..inner.ports.len())
+                .map(|_| EventListener::new())
+                .collect(),
+            inner: Mutex::new(inner),
+        });
+        Ok(Self::with_port(dev, port))
+    }
+
+    fn with_port(dev: Arc<ConsoleDev>, port: usize) -> Self {
+        Self {
+            dev,
+            port,
+            name: match port {
+                0 => String::from("virtio-console"),
+                _ => format!("virtio-console-port{}", port),
+            },
+        }
+    }
+
+    /// The port number on the device.
+    pub fn port(&self) -> usize {
+        self.port
+    }
+
+    /// The other ports announced by the device.
+    pub fn other_ports(&self) -> Vec<Self> {
+        let inner = self.dev.inner.lock();
+        (0..inner.ports.len())
+            .filter(|&i| i != self.port && inner.ports[i].present)
+            .map(|i| Self::with_port(self.dev.clone(), i))
+            .collect()
+    }
+}
+
+impl EventScheme for VirtIoConsole {
+    type Event = ();
+
+    #[inline]
+    fn trigger(&self, event: ()) {
+        self.dev.listeners[self.port].trigger(event);
+    }
+
+    #[inline]
+    fn subscribe(&self, handler: EventHandler, once: bool) {
+        self.dev.listeners[self.port].subscribe(handler, once);
+    }
+}
+
+impl Scheme for VirtIoConsole {
     fn name(&self) -> &str {
-        "virtio-console"
+        &self.name
     }
 
     fn handle_irq(&self, _irq_num: usize) {
-        self.inner.lock().ack_interrupt().unwrap();
-        self.listener.trigger(());
+        self.dev.inner.lock().header.ack_interrupt();
+        self.dev.poll();
     }
 }
 
-impl<'a> UartScheme for VirtIoConsole<'a> {
+impl UartScheme for VirtIoConsole {
     fn try_recv(&self) -> DeviceResult<Option<u8>> {
-        Ok(self.inner.lock().recv(true)?)
+        let mut inner = self.dev.inner.lock();
+        if inner.ports[self.port].rx_data.is_empty() {
+            inner.poll();
+        }
+        Ok(inner.ports[self.port].rx_data.pop_front())
     }
 
     fn send(&self, ch: u8) -> DeviceResult {
-        self.inner.lock().send(ch)?;
-        Ok(())
+        self.dev.inner.lock().send(self.port, &[ch])
+    }
+
+    fn write_str(&self, s: &str) -> DeviceResult {
+        self.dev.inner.lock().send(self.port, s.as_bytes())
     }
 }
 
-impl<'a> Write for VirtIoConsole<'a> {
+impl Write for VirtIoConsole {
     fn write_str(&mut self, s: &str) -> Result {
-        for b in s.bytes() {
-            self.send(b).unwrap()
-        }
+        UartScheme::write_str(self, s).unwrap();
         Ok(())
     }
 }
@@ -28,13 +28,16 @@ impl<'a> VirtIoGpu<'a> {
             fb_base_vaddr,
             fb_size,
         };
-        gpu.setup_cursor(
+        // the scanout works without the hardware cursor
+        if let Err(err) = gpu.setup_cursor(
             CURSOR_IMG,
             width / 2,
             height / 2,
             CURSOR_HOT_X,
             CURSOR_HOT_Y,
-        )?;
+        ) {
+            warn!("virtio-gpu: failed to set up the cursor: {:?}", err);
+        }
         Ok(Self {
             info,
             inner: Mutex::new(gpu),
@@ -1,23 +1,50 @@
+use alloc::string::String;
 use core::convert::TryFrom;
 
 use lock::Mutex;
 use virtio_drivers::{InputConfigSelect, VirtIOHeader, VirtIOInput as InnerDriver};
 
-use crate::prelude::{CapabilityType, InputCapability, InputEvent, InputEventType};
+use crate::prelude::{
+    CapabilityType, InputAbsInfo, InputCapability, InputEvent, InputEventType, InputId,
+};
 use crate::scheme::{impl_event_scheme, InputScheme, Scheme};
 use crate::utils::EventListener;
 use crate::DeviceResult;
 
 pub struct VirtIoInput<'a> {
+    name: String,
+    id: InputId,
     inner: Mutex<InnerDriver<'a>>,
     listener: EventListener<InputEvent>,
 }
 
 impl<'a> VirtIoInput<'a> {
     pub fn new(header: &'static mut VirtIOHeader) -> DeviceResult<Self> {
-        let inner = Mutex::new(InnerDriver::new(header)?);
+        let mut inner = InnerDriver::new(header)?;
+        let mut buf = [0u8; 128];
+        let size = inner.query_config_select(InputConfigSelect::IdName, 0, &mut buf) as usize;
+        let name = match size {
+            0 => String::from("virtio-input"),
+            _ => String::from_utf8_lossy(&buf[..size]).into(),
+        };
+        // struct virtio_input_devids
+        let size = inner.query_config_select(InputConfigSelect::IdDevids, 0, &mut buf) as usize;
+        let field = |i: usize| u16::from_le_bytes([buf[i * 2], buf[i * 2 + 1]]);
+        let id = if size >= 8 {
+            InputId {
+                bustype: field(0),
+                vendor: field(1),
+                product: field(2),
+                version: field(3),
+            }
+        } else {
+            InputId::default()
+        };
+        info!("virtio-input: {:?}, {:x?}", name, id);
         Ok(Self {
-            inner,
+            name,
+            id,
+            inner: Mutex::new(inner),
             listener: EventListener::new(),
         })
     }
@@ -27,7 +54,7 @@ impl_event_scheme!(VirtIoInput<'_>, InputEvent);
 
 impl<'a> Scheme for VirtIoInput<'a> {
     fn name(&self) -> &str {
-        "virtio-input"
+        &self.name
     }
 
     fn handle_irq(&self, _irq_num: usize) {
@@ -75,4 +102,30 @@ impl<'a> InputScheme for VirtIoInput<'a> {
             }
         }
     }
+
+    fn id(&self) -> InputId {
+        self.id
+    }
+
+    fn abs_info(&self, axis: u16) -> Option<InputAbsInfo> {
+        // struct virtio_input_absinfo
+        let mut buf = [0u8; 20];
+        let size =
+            self.inner
+                .lock()
+                .query_config_select(InputConfigSelect::AbsInfo, axis as u8, &mut buf);
+        if (size as usize) < buf.len() {
+            return None;
+        }
+        let field = |i: usize| {
+            i32::from_le_bytes([buf[i * 4], buf[i * 4 + 1], buf[i * 4 + 2], buf[i * 4 + 3]])
+        };
+        Some(InputAbsInfo {
+            min: field(0),
+            max: field(1),
+            fuzz: field(2),
+            flat: field(3),
+            resolution: field(4),
+        })
+    }
 }
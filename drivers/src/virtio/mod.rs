@@ -4,11 +4,16 @@ mod blk;
 mod console;
 mod gpu;
 mod input;
+mod net;
+mod queue;
+mod rng;
 
 pub use blk::VirtIoBlk;
 pub use console::VirtIoConsole;
 pub use gpu::VirtIoGpu;
 pub use input::VirtIoInput;
+pub use net::VirtIoNet;
+pub use rng::VirtIoRng;
 pub use virtio_drivers::VirtIOHeader;
 
 use crate::DeviceError;
@@ -0,0 +1,182 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use lock::Mutex;
+use smoltcp::iface::{Interface, InterfaceBuilder, NeighborCache, Routes};
+use smoltcp::phy::{self, DeviceCapabilities};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
+use virtio_drivers::{VirtIOHeader, VirtIONet as InnerDriver};
+
+use crate::net::{get_sockets, timer_now_as_micros, SOCKET_ACTIVITY};
+use crate::scheme::{NetScheme, Scheme};
+use crate::{DeviceError, DeviceResult};
+
+/// The largest Ethernet frame, without the FCS.
+const MAX_FRAME_SIZE: usize = 1514;
+
+#[derive(Clone)]
+pub struct VirtIoNetDriver(Arc<Mutex<InnerDriver<'static>>>);
+
+/// A virtio network device with its smoltcp interface, configured for the
+/// QEMU user network.
+pub struct VirtIoNet {
+    driver: VirtIoNetDriver,
+    iface: Mutex<Interface<'static, VirtIoNetDriver>>,
+    name: String,
+}
+
+impl VirtIoNet {
+    pub fn new(header: &'static mut VirtIOHeader, name: String) -> DeviceResult<Self> {
+        let inner = InnerDriver::new(header)?;
+        let ethernet_addr = EthernetAddress::from_bytes(&inner.mac());
+        let driver = VirtIoNetDriver(Arc::new(Mutex::new(inner)));
+
+        let ip_addrs = [IpCidr::new(IpAddress::v4(10, 0, 2, 15), 24)];
+        // the gateway of the QEMU user network
+        let default_v4_gw = Ipv4Address::new(10, 0, 2, 2);
+        let mut routes = Routes::new(BTreeMap::new());
+        routes.add_default_ipv4_route(default_v4_gw).unwrap();
+        let iface = InterfaceBuilder::new(driver.clone())
+            .ethernet_addr(ethernet_addr)
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .ip_addrs(ip_addrs)
+            .routes(routes)
+            .finalize();
+        info!(
+            "virtio-net interface {} up with addr 10.0.2.15/24, mac {}",
+            name, ethernet_addr
+        );
+        Ok(Self {
+            driver,
+            iface: Mutex::new(iface),
+            name,
+        })
+    }
+}
+
+impl Scheme for VirtIoNet {
+    fn name(&self) -> &str {
+        "virtio-net"
+    }
+
+    fn handle_irq(&self, _irq_num: usize) {
+        if self.driver.0.lock().ack_interrupt() {
+            // deliver the received packets to the sockets
+            self.poll().ok();
+        }
+    }
+}
+
+impl NetScheme for VirtIoNet {
+    fn recv(&self, buf: &mut [u8]) -> DeviceResult<usize> {
+        let mut driver = self.driver.0.lock();
+        if driver.can_recv() {
+            Ok(driver.recv(buf)?)
+        } else {
+            Err(DeviceError::NotReady)
+        }
+    }
+
+    fn send(&self, buf: &[u8]) -> DeviceResult<usize> {
+        let mut driver = self.driver.0.lock();
+        if driver.can_send() {
+            driver.send(buf)?;
+            Ok(buf.len())
+        } else {
+            Err(DeviceError::NotReady)
+        }
+    }
+
+    fn get_mac(&self) -> EthernetAddress {
+        self.iface.lock().ethernet_addr()
+    }
+
+    fn get_ifname(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_ip_address(&self) -> Vec<IpCidr> {
+        Vec::from(self.iface.lock().ip_addrs())
+    }
+
+    fn poll(&self) -> DeviceResult {
+        let timestamp = Instant::from_micros(timer_now_as_micros() as i64);
+        let sockets = get_sockets();
+        let mut sockets = sockets.lock();
+        match self.iface.lock().poll(&mut sockets, timestamp) {
+            Ok(changed) => {
+                if changed {
+                    SOCKET_ACTIVITY.trigger(());
+                }
+                Ok(())
+            }
+            Err(err) => {
+                warn!("virtio-net poll got err {}", err);
+                Err(DeviceError::IoError)
+            }
+        }
+    }
+}
+
+pub struct VirtIoNetRxToken(Vec<u8>);
+pub struct VirtIoNetTxToken(VirtIoNetDriver);
+
+impl phy::Device<'_> for VirtIoNetDriver {
+    type RxToken = VirtIoNetRxToken;
+    type TxToken = VirtIoNetTxToken;
+
+    fn receive(&mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let mut driver = self.0.lock();
+        if !driver.can_recv() {
+            return None;
+        }
+        let mut buf = vec![0; MAX_FRAME_SIZE];
+        let len = driver.recv(&mut buf).ok()?;
+        buf.truncate(len);
+        Some((VirtIoNetRxToken(buf), VirtIoNetTxToken(self.clone())))
+    }
+
+    fn transmit(&mut self) -> Option<Self::TxToken> {
+        if self.0.lock().can_send() {
+            Some(VirtIoNetTxToken(self.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = MAX_FRAME_SIZE;
+        caps.max_burst_size = Some(1);
+        caps
+    }
+}
+
+impl phy::RxToken for VirtIoNetRxToken {
+    fn consume<R, F>(mut self, _timestamp: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl phy::TxToken for VirtIoNetTxToken {
+    fn consume<R, F>(self, _timestamp: Instant, len: usize, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        let mut buf = vec![0; len];
+        let result = f(&mut buf)?;
+        (self.0)
+            .0
+            .lock()
+            .send(&buf)
+            .map_err(|_| smoltcp::Error::Exhausted)?;
+        Ok(result)
+    }
+}
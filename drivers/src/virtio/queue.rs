@@ -0,0 +1,156 @@
+//! A split virtqueue in the legacy layout, with a buffer of its own for
+//! each descriptor.
+//!
+//! For the devices whose driver in `virtio-drivers` lacks a feature, such
+//! as the multiport console.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+
+use virtio_drivers::VirtIOHeader;
+
+use crate::net::PAGE_SIZE;
+use crate::utils::DmaRegion;
+use crate::{DeviceError, DeviceResult};
+
+/// The buffer is write-only for the device.
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+pub(super) struct VirtQueue {
+    index: u32,
+    size: u16,
+    buf_size: usize,
+    /// The descriptors and the available ring in the first page, and the
+    /// used ring in the second.
+    ring: DmaRegion,
+    buffers: DmaRegion,
+    free: Vec<u16>,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    /// Set up the queue `index` of the device, with at most `size` buffers
+    /// of `buf_size` bytes.
+    pub fn new(
+        header: &mut VirtIOHeader,
+        index: u32,
+        size: u16,
+        buf_size: usize,
+    ) -> DeviceResult<Self> {
+        if header.queue_used(index) {
+            return Err(DeviceError::AlreadyExists);
+        }
+        let size = size.min(header.max_queue_size() as u16);
+        if size == 0 || size as usize * (size_of::<Descriptor>() + 2) + 6 > PAGE_SIZE {
+            return Err(DeviceError::InvalidParam);
+        }
+        let ring = DmaRegion::new(2 * PAGE_SIZE)?;
+        let buffers = DmaRegion::new(size as usize * buf_size)?;
+        header.queue_set(
+            index,
+            size as u32,
+            PAGE_SIZE as u32,
+            (ring.paddr() / PAGE_SIZE) as u32,
+        );
+        Ok(Self {
+            index,
+            size,
+            buf_size,
+            ring,
+            buffers,
+            free: (0..size).rev().collect(),
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    pub fn buf_size(&self) -> usize {
+        self.buf_size
+    }
+
+    /// The buffer of the descriptor `id`.
+    pub fn buffer(&mut self, id: u16) -> &mut [u8] {
+        let start = self.buffers.as_ptr::<u8>(id as usize * self.buf_size);
+        unsafe { core::slice::from_raw_parts_mut(start, self.buf_size) }
+    }
+
+    /// Make a buffer available to the device, with the first `len` bytes
+    /// filled by `fill`, or written by the device if `device_writes`.
+    ///
+    /// Returns `None` if all the buffers are in use.
+    pub fn add(
+        &mut self,
+        header: &mut VirtIOHeader,
+        len: usize,
+        device_writes: bool,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> Option<u16> {
+        let id = self.free.pop()?;
+        let len = len.min(self.buf_size);
+        fill(&mut self.buffer(id)[..len]);
+        let desc = Descriptor {
+            addr: (self.buffers.paddr() + id as usize * self.buf_size) as u64,
+            len: len as u32,
+            flags: if device_writes { VIRTQ_DESC_F_WRITE } else { 0 },
+            next: 0,
+        };
+        unsafe {
+            self.ring
+                .as_ptr::<Descriptor>(id as usize)
+                .write_volatile(desc);
+            self.avail_ptr(2 + self.avail_idx % self.size)
+                .write_volatile(id);
+        }
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { self.avail_ptr(1).write_volatile(self.avail_idx) };
+        fence(Ordering::SeqCst);
+        header.notify(self.index);
+        Some(id)
+    }
+
+    /// Take a buffer the device has used, with the bytes it has written.
+    ///
+    /// The buffer is kept intact until it is added again.
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used_idx = unsafe { self.used_ptr::<u16>(2).read_volatile() };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let offset = 4 + (self.last_used_idx % self.size) as usize * size_of::<UsedElem>();
+        let elem = unsafe { self.used_ptr::<UsedElem>(offset).read_volatile() };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        let id = elem.id as u16;
+        self.free.push(id);
+        Some((id, (elem.len as usize).min(self.buf_size)))
+    }
+
+    /// The `i`-th `u16` of the available ring.
+    fn avail_ptr(&self, i: u16) -> *mut u16 {
+        let base = self.ring.vaddr() + self.size as usize * size_of::<Descriptor>();
+        (base as *mut u16).wrapping_add(i as usize)
+    }
+
+    fn used_ptr<T>(&self, offset: usize) -> *mut T {
+        (self.ring.vaddr() + PAGE_SIZE + offset) as *mut T
+    }
+}
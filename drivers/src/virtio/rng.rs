@@ -0,0 +1,74 @@
+use lock::Mutex;
+use virtio_drivers::VirtIOHeader;
+
+use super::queue::VirtQueue;
+use crate::net::timer_now_as_micros;
+use crate::scheme::{RngScheme, Scheme};
+use crate::DeviceResult;
+
+const QUEUE_SIZE: u16 = 2;
+const BUF_SIZE: usize = 64;
+
+/// How long to wait for the device, which may be rate limited by the host.
+const READ_TIMEOUT_US: u64 = 100_000;
+
+struct RngInner {
+    header: &'static mut VirtIOHeader,
+    queue: VirtQueue,
+    /// The buffer given to the device, kept across the reads timed out.
+    in_flight: Option<u16>,
+}
+
+/// A virtio entropy device.
+pub struct VirtIoRng {
+    inner: Mutex<RngInner>,
+}
+
+impl VirtIoRng {
+    pub fn new(header: &'static mut VirtIOHeader) -> DeviceResult<Self> {
+        header.begin_init(|_| 0);
+        let queue = VirtQueue::new(header, 0, QUEUE_SIZE, BUF_SIZE)?;
+        header.finish_init();
+        Ok(Self {
+            inner: Mutex::new(RngInner {
+                header,
+                queue,
+                in_flight: None,
+            }),
+        })
+    }
+}
+
+impl Scheme for VirtIoRng {
+    fn name(&self) -> &str {
+        "virtio-rng"
+    }
+
+    fn handle_irq(&self, _irq_num: usize) {
+        // the reads poll the queue
+        self.inner.lock().header.ack_interrupt();
+    }
+}
+
+impl RngScheme for VirtIoRng {
+    fn read(&self, buf: &mut [u8]) -> DeviceResult<usize> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        if inner.in_flight.is_none() {
+            inner.in_flight = inner.queue.add(inner.header, BUF_SIZE, true, |_| {});
+        }
+        let start = timer_now_as_micros();
+        loop {
+            if let Some((id, len)) = inner.queue.pop_used() {
+                inner.in_flight = None;
+                let len = len.min(buf.len());
+                buf[..len].copy_from_slice(&inner.queue.buffer(id)[..len]);
+                return Ok(len);
+            }
+            if timer_now_as_micros() - start > READ_TIMEOUT_US {
+                return Ok(0);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -19,6 +19,10 @@ pub struct KernelConfig {
 
 pub const PHYS_MEMORY_BASE: usize = 0x4000_0000;
 pub const UART_SIZE: usize = 0x1000;
+/// The offsets from the GIC distributor to the CPU interface of GICv2, and to
+/// the redistributors of GICv3, on QEMU virt.
+pub const GICC_OFFSET: usize = 0x1_0000;
+pub const GICR_OFFSET: usize = 0xa_0000;
 pub const VIRTIO_BASE: usize = 0x0a00_0000;
 pub const VIRTIO_SIZE: usize = 0x100;
 pub const PA_1TB_BITS: usize = 40;
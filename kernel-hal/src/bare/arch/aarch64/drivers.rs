@@ -1,28 +1,47 @@
 use crate::arch::timer::set_next_trigger;
 use crate::drivers;
 use crate::hal_fn::mem::phys_to_virt;
-use crate::imp::config::VIRTIO_BASE;
+use crate::imp::config::{GICC_OFFSET, GICR_OFFSET, VIRTIO_BASE};
 use crate::KCONFIG;
 use alloc::boxed::Box;
 use alloc::sync::Arc;
-use zcore_drivers::irq::gic_400;
+use core::sync::atomic::{AtomicBool, Ordering};
+use zcore_drivers::irq::{gic_400, gic_v3};
 use zcore_drivers::scheme::IrqScheme;
 use zcore_drivers::uart::{BufferedUart, Pl011Uart};
 use zcore_drivers::virtio::{VirtIOHeader, VirtIoBlk};
 use zcore_drivers::Device;
 
+const UART_IRQ: usize = 33;
+
+/// Whether the GIC is of version 3, with the CPU interface accessed by the
+/// system registers.
+static GIC_V3: AtomicBool = AtomicBool::new(false);
+
+pub fn is_gic_v3() -> bool {
+    GIC_V3.load(Ordering::Relaxed)
+}
+
 pub fn init_early() {
     let uart = Pl011Uart::new(phys_to_virt(KCONFIG.uart_base));
     let uart = Arc::new(uart);
-    let gic = gic_400::init(
-        phys_to_virt(KCONFIG.gic_base + 0x1_0000),
-        phys_to_virt(KCONFIG.gic_base),
-    );
-    gic.irq_enable(30);
-    gic.irq_enable(33);
-    gic.register_handler(33, Box::new(handle_uart_irq)).ok();
-    gic.register_handler(30, Box::new(set_next_trigger)).ok();
-    drivers::add_device(Device::Irq(Arc::new(gic)));
+    let gicd_base = phys_to_virt(KCONFIG.gic_base);
+    let gic: Arc<dyn IrqScheme> = if gic_v3::is_gic_v3(gicd_base) {
+        GIC_V3.store(true, Ordering::Relaxed);
+        let gicr_base = phys_to_virt(KCONFIG.gic_base + GICR_OFFSET);
+        Arc::new(gic_v3::GicV3::new(gicd_base, gicr_base))
+    } else {
+        Arc::new(gic_400::init(
+            phys_to_virt(KCONFIG.gic_base + GICC_OFFSET),
+            gicd_base,
+        ))
+    };
+    let timer_irq = super::timer_interrupt_vector();
+    gic.register_handler(UART_IRQ, Box::new(handle_uart_irq)).ok();
+    gic.register_handler(timer_irq, Box::new(set_next_trigger)).ok();
+    gic.unmask(timer_irq).ok();
+    gic.unmask(UART_IRQ).ok();
+    drivers::add_device(Device::Irq(gic));
     drivers::add_device(Device::Uart(BufferedUart::new(uart)));
 }
 
@@ -36,6 +55,26 @@ pub fn init() {
     }
 }
 
+/// Initialize the GIC for a secondary CPU, and unmask its timer interrupt,
+/// which is private to each CPU.
+pub fn init_secondary() {
+    let gic = drivers::all_irq().first_unwrap();
+    gic.init_hart();
+    gic.unmask(super::timer_interrupt_vector()).ok();
+}
+
+/// Acknowledge the IRQ pending on the current CPU.
+pub fn pending_irq() -> usize {
+    if is_gic_v3() {
+        gic_v3::pending_irq()
+    } else {
+        gic_400::get_irq_num(
+            phys_to_virt(KCONFIG.gic_base + GICC_OFFSET),
+            phys_to_virt(KCONFIG.gic_base),
+        )
+    }
+}
+
 fn handle_uart_irq() {
     crate::drivers::all_uart().first_unwrap().handle_irq(0);
 }
@@ -0,0 +1,140 @@
+//! Architecture specific parts of the GDB stub.
+//!
+//! Single-step uses the hardware software-step of the debug architecture,
+//! which raises a debug exception after one instruction is executed.
+
+use alloc::vec::Vec;
+use cortex_a::registers::{ESR_EL1, MDSCR_EL1};
+use tock_registers::interfaces::{ReadWriteable, Readable};
+use trapframe::TrapFrame;
+
+use crate::imp::gdbstub::Step;
+use crate::Syndrome;
+
+/// `brk #0`
+const BRK: u32 = 0xd420_0000;
+/// Mask of the immediate of `brk`.
+const BRK_IMM_MASK: u32 = 0xffff << 5;
+
+/// SPSR.SS, step a single instruction after the exception return.
+const SPSR_SS: usize = 1 << 21;
+/// SPSR.D, the debug exceptions mask.
+const SPSR_D: usize = 1 << 9;
+
+/// Number of the general purpose registers, `x0` ~ `x30`.
+const GENERAL_REG_NUM: usize = 31;
+
+fn general(tf: &TrapFrame) -> &[usize; GENERAL_REG_NUM] {
+    unsafe { &*(&tf.general as *const _ as *const [usize; GENERAL_REG_NUM]) }
+}
+
+fn general_mut(tf: &mut TrapFrame) -> &mut [usize; GENERAL_REG_NUM] {
+    unsafe { &mut *(&mut tf.general as *mut _ as *mut [usize; GENERAL_REG_NUM]) }
+}
+
+/// The stack pointer before the exception, right above the trap frame.
+fn kernel_sp(tf: &TrapFrame) -> usize {
+    tf as *const _ as usize + core::mem::size_of::<TrapFrame>()
+}
+
+/// Returns `true` if the current exception is raised by `brk` or single-step.
+pub fn is_debug_trap() -> bool {
+    matches!(
+        Syndrome::from(ESR_EL1.get() as u32),
+        Syndrome::Brk(_) | Syndrome::Step
+    )
+}
+
+/// Triggers a breakpoint exception.
+#[inline(always)]
+pub fn breakpoint() {
+    unsafe { core::arch::asm!("brk #0") };
+}
+
+/// Returns the breakpoint instruction of the given size, as sent in `Z0`.
+pub fn break_insn(kind: usize) -> Option<&'static [u8]> {
+    const BRK_BYTES: [u8; 4] = BRK.to_le_bytes();
+    match kind {
+        4 => Some(&BRK_BYTES),
+        _ => None,
+    }
+}
+
+pub fn pc(tf: &TrapFrame) -> usize {
+    tf.elr
+}
+
+pub fn set_pc(tf: &mut TrapFrame, pc: usize) {
+    tf.elr = pc;
+}
+
+/// Appends `x0` ~ `x30`, `sp`, `pc` and `cpsr` in the order of `g` packets.
+pub fn read_registers(tf: &TrapFrame, buf: &mut Vec<u8>) {
+    for reg in general(tf) {
+        buf.extend_from_slice(&reg.to_le_bytes());
+    }
+    buf.extend_from_slice(&kernel_sp(tf).to_le_bytes());
+    buf.extend_from_slice(&tf.elr.to_le_bytes());
+    buf.extend_from_slice(&(tf.spsr as u32).to_le_bytes());
+}
+
+/// Writes registers from a `G` packet, returns `false` if it is malformed.
+///
+/// The stack pointer can not be changed as the trap frame lives on it.
+pub fn write_registers(tf: &mut TrapFrame, data: &[u8]) -> bool {
+    const SIZE: usize = core::mem::size_of::<usize>();
+    if data.len() < (GENERAL_REG_NUM + 2) * SIZE + 4 {
+        return false;
+    }
+    let mut regs = data
+        .chunks_exact(SIZE)
+        .map(|b| usize::from_le_bytes(b.try_into().unwrap()));
+    for reg in general_mut(tf).iter_mut() {
+        *reg = regs.next().unwrap();
+    }
+    regs.next(); // sp
+    tf.elr = regs.next().unwrap();
+    let cpsr = &data[(GENERAL_REG_NUM + 2) * SIZE..][..4];
+    tf.spsr = u32::from_le_bytes(cpsr.try_into().unwrap()) as usize;
+    true
+}
+
+/// Skips the compiled-in `brk` at `pc`, returns `false` if there is not.
+pub fn skip_break(tf: &mut TrapFrame) -> bool {
+    let insn = unsafe { (tf.elr as *const u32).read() };
+    if insn & !BRK_IMM_MASK == BRK {
+        tf.elr += 4;
+        true
+    } else {
+        false
+    }
+}
+
+/// Prepares to execute a single instruction at `pc`.
+pub(crate) fn step(tf: &mut TrapFrame) -> Step {
+    // unlock the OS lock, or the debug exceptions are not generated
+    unsafe { core::arch::asm!("msr oslar_el1, xzr", "isb") };
+    MDSCR_EL1.modify(MDSCR_EL1::SS::SET + MDSCR_EL1::KDE::SET);
+    tf.spsr = (tf.spsr | SPSR_SS) & !SPSR_D;
+    Step::Hardware
+}
+
+/// Stops single-step after the debug exception.
+pub fn step_done(tf: &mut TrapFrame) {
+    MDSCR_EL1.modify(MDSCR_EL1::SS::CLEAR + MDSCR_EL1::KDE::CLEAR);
+    tf.spsr &= !SPSR_SS;
+}
+
+/// Makes the modified instructions visible to the instruction fetch.
+pub fn flush_icache(vaddr: usize) {
+    unsafe {
+        core::arch::asm!(
+            "dc cvau, {0}
+             dsb ish
+             ic iallu
+             dsb ish
+             isb",
+            in(reg) vaddr
+        )
+    };
+}
@@ -1,6 +1,10 @@
 //! Interrupts management.
 use cortex_a::asm::wfi;
 
+use crate::drivers::all_irq;
+use crate::drivers::prelude::{IrqHandler, IrqPolarity, IrqTriggerMode};
+use crate::HalResult;
+
 hal_fn_impl! {
     impl mod crate::hal_fn::interrupt {
         fn wait_for_interrupt() {
@@ -9,9 +13,33 @@ hal_fn_impl! {
             intr_off();
         }
 
+        fn is_valid_irq(vector: usize) -> bool {
+            all_irq().first_unwrap().is_valid_irq(vector)
+        }
+
+        fn mask_irq(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().mask(vector)?)
+        }
+
+        fn unmask_irq(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().unmask(vector)?)
+        }
+
+        fn configure_irq(vector: usize, tm: IrqTriggerMode, pol: IrqPolarity) -> HalResult {
+            Ok(all_irq().first_unwrap().configure(vector, tm, pol)?)
+        }
+
+        fn register_irq_handler(vector: usize, handler: IrqHandler) -> HalResult {
+            Ok(all_irq().first_unwrap().register_handler(vector, handler)?)
+        }
+
+        fn unregister_irq_handler(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().unregister(vector)?)
+        }
+
         fn handle_irq(vector: usize) {
             // TODO: timer and other devices with GIC interrupt controller
-            crate::drivers::all_irq().first_unwrap().handle_irq(vector);
+            all_irq().first_unwrap().handle_irq(vector);
             if vector == 30 {
                 debug!("Timer");
             }
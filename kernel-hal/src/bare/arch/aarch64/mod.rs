@@ -1,6 +1,8 @@
 pub mod config;
 pub mod cpu;
 pub mod drivers;
+#[cfg(feature = "gdbstub")]
+pub mod gdb;
 pub mod interrupt;
 pub mod mem;
 pub mod timer;
@@ -38,7 +40,8 @@ pub fn primary_init() {
 }
 
 pub fn secondary_init() {
-    unimplemented!()
+    vm::init();
+    drivers::init_secondary();
 }
 
 pub const fn timer_interrupt_vector() -> usize {
@@ -1,15 +1,16 @@
 //! ARM Generic Timer.
 
 use crate::timer::TICKS_PER_SEC;
-use core::time::Duration;
 use cortex_a::{asm::barrier, registers::*};
 use tock_registers::interfaces::{Readable, Writeable};
 
-pub fn timer_now() -> Duration {
+pub fn clock_cycles() -> u64 {
     unsafe { barrier::isb(barrier::SY) }
-    let cur_cnt = CNTPCT_EL0.get() * 1_000_000_000;
-    let freq = CNTFRQ_EL0.get() as u64;
-    Duration::from_nanos(cur_cnt / freq)
+    CNTPCT_EL0.get()
+}
+
+pub fn clock_frequency() -> u64 {
+    CNTFRQ_EL0.get()
 }
 
 pub fn set_next_trigger() {
@@ -1,9 +1,8 @@
 use crate::context::TrapReason;
-use crate::{Info, Kind, Source, KCONFIG};
+use crate::{Info, Kind, Source};
 use cortex_a::registers::FAR_EL1;
 use tock_registers::interfaces::Readable;
 use trapframe::TrapFrame;
-use zcore_drivers::irq::gic_400::get_irq_num;
 
 #[no_mangle]
 pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
@@ -16,13 +15,7 @@ pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
         Kind::Synchronous => {
             sync_handler(tf);
         }
-        Kind::Irq => {
-            use crate::hal_fn::mem::phys_to_virt;
-            crate::interrupt::handle_irq(get_irq_num(
-                phys_to_virt(KCONFIG.gic_base + 0x1_0000),
-                phys_to_virt(KCONFIG.gic_base),
-            ));
-        }
+        Kind::Irq => crate::interrupt::handle_irq(super::drivers::pending_irq()),
         _ => {
             panic!(
                 "Unsupported exception type: {:?}, TrapFrame: {:?}",
@@ -39,6 +32,10 @@ fn breakpoint(elr: &mut usize) {
 }
 
 fn sync_handler(tf: &mut TrapFrame) {
+    #[cfg(feature = "gdbstub")]
+    if super::gdb::is_debug_trap() && crate::imp::gdbstub::handle_trap(tf) {
+        return;
+    }
     match TrapReason::from(tf.trap_num) {
         TrapReason::PageFault(vaddr, flags) => crate::KHANDLER.handle_page_fault(vaddr, flags),
         TrapReason::SoftwareBreakpoint => breakpoint(&mut tf.elr),
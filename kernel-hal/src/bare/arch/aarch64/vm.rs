@@ -7,7 +7,7 @@ use core::fmt::{Debug, Formatter, Result};
 use cortex_a::registers::*;
 use lock::Mutex;
 use tock_registers::interfaces::{Readable, Writeable};
-use zcore_drivers::irq::gic_400::{GICC_SIZE, GICD_SIZE};
+use zcore_drivers::irq::{gic_400, gic_v3};
 
 lazy_static! {
     static ref KERNEL_PT: Mutex<PageTable> = Mutex::new(init_kernel_page_table().unwrap());
@@ -67,14 +67,20 @@ fn init_kernel_page_table() -> PagingResult<PageTable> {
         MMUFlags::READ | MMUFlags::WRITE | MMUFlags::DEVICE,
     )?;
     // gic
+    let (gic_regions, gicd_size) = if super::drivers::is_gic_v3() {
+        let gicr_size = gic_v3::GICR_SIZE_PER_CPU * crate::config::MAX_CORE_NUM;
+        ((GICR_OFFSET, gicr_size), gic_v3::GICD_SIZE)
+    } else {
+        ((GICC_OFFSET, gic_400::GICC_SIZE), gic_400::GICD_SIZE)
+    };
     map_range(
-        phys_to_virt(KCONFIG.gic_base + 0x1_0000),
-        phys_to_virt(KCONFIG.gic_base + 0x1_0000) + GICC_SIZE,
+        phys_to_virt(KCONFIG.gic_base + gic_regions.0),
+        phys_to_virt(KCONFIG.gic_base + gic_regions.0) + gic_regions.1,
         MMUFlags::READ | MMUFlags::WRITE | MMUFlags::DEVICE,
     )?;
     map_range(
         phys_to_virt(KCONFIG.gic_base),
-        phys_to_virt(KCONFIG.gic_base) + GICD_SIZE,
+        phys_to_virt(KCONFIG.gic_base) + gicd_size,
         MMUFlags::READ | MMUFlags::WRITE | MMUFlags::DEVICE,
     )?;
     if cfg!(not(feature = "link-user-img")) {
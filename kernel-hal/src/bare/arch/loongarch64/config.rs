@@ -0,0 +1,18 @@
+//! Kernel configuration.
+
+/// Kernel configuration passed by kernel when calls [`crate::primary_init_early()`].
+#[derive(Debug)]
+pub struct KernelConfig {
+    /// boot cmd line
+    pub cmdline: &'static str,
+    /// phystovirt offset, the base of the cached direct mapped window
+    pub phys_to_virt_offset: usize,
+}
+
+/// The low memory of QEMU loongarch virt, where the kernel is loaded.
+pub const PHYS_MEMORY_BASE: usize = 0;
+pub const PHYS_MEMORY_END: usize = 0x1000_0000;
+/// The NS16550 compatible UART of the Loongson 7A bridge.
+pub const UART_BASE: usize = 0x1fe0_01e0;
+/// The uncached direct mapped window, for MMIO.
+pub const UNCACHED_WINDOW_BASE: usize = 0x8000_0000_0000_0000;
@@ -0,0 +1,85 @@
+//! The user and the trap contexts, saved and restored by [`trap.S`](super::trap),
+//! as the `trapframe` crate does on the other architectures.
+
+use super::csr::{PRMD_PIE, PRMD_PPLV_USER};
+
+/// General registers, `$r0` to `$r31`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GeneralRegs {
+    pub zero: usize,
+    pub ra: usize,
+    pub tp: usize,
+    pub sp: usize,
+    pub a0: usize,
+    pub a1: usize,
+    pub a2: usize,
+    pub a3: usize,
+    pub a4: usize,
+    pub a5: usize,
+    pub a6: usize,
+    pub a7: usize,
+    pub t0: usize,
+    pub t1: usize,
+    pub t2: usize,
+    pub t3: usize,
+    pub t4: usize,
+    pub t5: usize,
+    pub t6: usize,
+    pub t7: usize,
+    pub t8: usize,
+    pub r21: usize,
+    pub fp: usize,
+    pub s0: usize,
+    pub s1: usize,
+    pub s2: usize,
+    pub s3: usize,
+    pub s4: usize,
+    pub s5: usize,
+    pub s6: usize,
+    pub s7: usize,
+    pub s8: usize,
+}
+
+/// The context saved on traps from the kernel.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrapFrame {
+    pub general: GeneralRegs,
+    pub prmd: usize,
+    pub era: usize,
+}
+
+/// The user context, with the exception state when back from user mode.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UserContext {
+    pub general: GeneralRegs,
+    pub prmd: usize,
+    pub era: usize,
+    pub estat: usize,
+    pub badv: usize,
+}
+
+impl Default for UserContext {
+    fn default() -> Self {
+        Self {
+            general: GeneralRegs::default(),
+            prmd: PRMD_PPLV_USER | PRMD_PIE,
+            era: 0,
+            estat: 0,
+            badv: 0,
+        }
+    }
+}
+
+extern "C" {
+    fn run_user(ctx: &mut UserContext);
+}
+
+impl UserContext {
+    /// Go to user mode, and return when a trap occurs there.
+    pub fn run(&mut self) {
+        unsafe { run_user(self) }
+    }
+}
@@ -0,0 +1,34 @@
+//! CPU information.
+
+use super::config::UNCACHED_WINDOW_BASE;
+use super::csr::CPUID;
+
+/// The power management registers of the Loongson 7A bridge, emulated by QEMU
+/// loongarch virt (`ged` in the DTB).
+const GED_BASE: usize = 0x100e_001c;
+const GED_SLEEP_CTL: usize = 0;
+const GED_SLEEP_CTL_SLP_EN: u8 = 1 << 5;
+const GED_SLEEP_CTL_SLP_TYP_S5: u8 = 5 << 2;
+
+hal_fn_impl! {
+    impl mod crate::hal_fn::cpu {
+        fn cpu_id() -> u8 {
+            (csr_read!(CPUID) & 0x1ff) as u8
+        }
+
+        fn cpu_frequency() -> u16 {
+            super::timer::clock_frequency().checked_div(1_000_000).unwrap_or(0) as u16
+        }
+
+        fn reset() -> ! {
+            info!("shutdown...");
+            let sleep_ctl = (UNCACHED_WINDOW_BASE + GED_BASE + GED_SLEEP_CTL) as *mut u8;
+            unsafe {
+                sleep_ctl.write_volatile(GED_SLEEP_CTL_SLP_EN | GED_SLEEP_CTL_SLP_TYP_S5);
+            }
+            loop {
+                unsafe { core::arch::asm!("idle 0") };
+            }
+        }
+    }
+}
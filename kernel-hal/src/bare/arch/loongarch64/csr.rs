@@ -0,0 +1,70 @@
+//! Control and status registers.
+
+pub const CRMD: usize = 0x0;
+pub const PRMD: usize = 0x1;
+pub const EUEN: usize = 0x2;
+pub const ECFG: usize = 0x4;
+pub const ESTAT: usize = 0x5;
+pub const ERA: usize = 0x6;
+pub const BADV: usize = 0x7;
+pub const EENTRY: usize = 0xc;
+pub const ASID: usize = 0x18;
+pub const PGDL: usize = 0x19;
+pub const PGDH: usize = 0x1a;
+pub const PWCL: usize = 0x1c;
+pub const PWCH: usize = 0x1d;
+pub const STLBPS: usize = 0x1e;
+pub const CPUID: usize = 0x20;
+pub const SAVE0: usize = 0x30;
+pub const SAVE1: usize = 0x31;
+pub const TCFG: usize = 0x41;
+pub const TICLR: usize = 0x44;
+pub const TLBRENTRY: usize = 0x88;
+pub const TLBRSAVE: usize = 0x8b;
+pub const TLBRELO0: usize = 0x8c;
+pub const TLBRELO1: usize = 0x8d;
+pub const TLBREHI: usize = 0x8e;
+pub const DMW0: usize = 0x180;
+pub const DMW1: usize = 0x181;
+
+/// `CRMD.IE`: global interrupt enable.
+pub const CRMD_IE: usize = 1 << 2;
+/// `PRMD.PPLV`: the privilege level before the exception, 3 for user mode.
+pub const PRMD_PPLV_USER: usize = 0b11;
+/// `PRMD.PIE`: the interrupt enable before the exception.
+pub const PRMD_PIE: usize = 1 << 2;
+/// `EUEN.FPE`: enable the floating-point instructions.
+pub const EUEN_FPE: usize = 1 << 0;
+
+macro_rules! csr_read {
+    ($csr:expr) => {{
+        let value: usize;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!("csrrd {}, {}", out(reg) value, const $csr)
+        };
+        value
+    }};
+}
+
+macro_rules! csr_write {
+    ($csr:expr, $value:expr) => {{
+        let value: usize = $value;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!("csrwr {}, {}", inout(reg) value => _, const $csr)
+        };
+    }};
+}
+
+/// Set the bits of `mask` in the CSR, to those in `value`.
+macro_rules! csr_xchg {
+    ($csr:expr, $value:expr, $mask:expr) => {{
+        let value: usize = $value;
+        let mask: usize = $mask;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!("csrxchg {}, {}, {}", inout(reg) value => _, in(reg) mask, const $csr)
+        };
+    }};
+}
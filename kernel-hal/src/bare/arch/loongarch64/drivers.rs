@@ -0,0 +1,35 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use zcore_drivers::irq::loongarch::Intc;
+use zcore_drivers::scheme::IrqScheme;
+use zcore_drivers::uart::{BufferedUart, Uart16550Mmio};
+use zcore_drivers::Device;
+
+use super::config::{UART_BASE, UNCACHED_WINDOW_BASE};
+use crate::drivers;
+
+/// Initialize the UART early for the console, polled as its interrupts are
+/// routed through the PCH-PIC and the EIOINTC not driven yet.
+pub(super) fn init_early() {
+    let uart = unsafe { Uart16550Mmio::<u8>::new(UNCACHED_WINDOW_BASE + UART_BASE) };
+    drivers::add_device(Device::Uart(BufferedUart::new(Arc::new(uart))));
+}
+
+pub(super) fn init() {
+    drivers::add_device(Device::Irq(Arc::new(Intc::new())));
+    intc_init();
+}
+
+/// Poll the UART for the received characters, on timer interrupts.
+pub(super) fn poll_uart() {
+    drivers::all_uart().first_unwrap().handle_irq(0);
+}
+
+/// Register and enable the timer interrupt of the current CPU.
+pub(super) fn intc_init() {
+    let intc = drivers::all_irq().first_unwrap();
+    intc.register_handler(super::trap::TIMER_INT_VEC, Box::new(super::timer::timer_tick))
+        .ok();
+    intc.unmask(super::trap::TIMER_INT_VEC).ok();
+}
@@ -0,0 +1,62 @@
+//! Interrupts management.
+
+use super::csr::{CRMD, CRMD_IE};
+use crate::drivers::all_irq;
+use crate::drivers::prelude::{IrqHandler, IrqPolarity, IrqTriggerMode};
+use crate::HalResult;
+
+hal_fn_impl! {
+    impl mod crate::hal_fn::interrupt {
+        fn wait_for_interrupt() {
+            let enable = intr_get();
+            if !enable {
+                intr_on();
+            }
+            unsafe { core::arch::asm!("idle 0") };
+            if !enable {
+                intr_off();
+            }
+        }
+
+        fn is_valid_irq(vector: usize) -> bool {
+            all_irq().first_unwrap().is_valid_irq(vector)
+        }
+
+        fn mask_irq(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().mask(vector)?)
+        }
+
+        fn unmask_irq(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().unmask(vector)?)
+        }
+
+        fn configure_irq(vector: usize, tm: IrqTriggerMode, pol: IrqPolarity) -> HalResult {
+            Ok(all_irq().first_unwrap().configure(vector, tm, pol)?)
+        }
+
+        fn register_irq_handler(vector: usize, handler: IrqHandler) -> HalResult {
+            Ok(all_irq().first_unwrap().register_handler(vector, handler)?)
+        }
+
+        fn unregister_irq_handler(vector: usize) -> HalResult {
+            Ok(all_irq().first_unwrap().unregister(vector)?)
+        }
+
+        fn handle_irq(vector: usize) {
+            trace!("Handle irq vector: {}", vector);
+            all_irq().first_unwrap().handle_irq(vector);
+        }
+
+        fn intr_on() {
+            csr_xchg!(CRMD, CRMD_IE, CRMD_IE);
+        }
+
+        fn intr_off() {
+            csr_xchg!(CRMD, 0, CRMD_IE);
+        }
+
+        fn intr_get() -> bool {
+            csr_read!(CRMD) & CRMD_IE != 0
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::addr::align_up;
+use crate::imp::config::PHYS_MEMORY_END;
+use crate::{PhysAddr, KCONFIG, PAGE_SIZE};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub fn free_pmem_regions() -> Vec<Range<PhysAddr>> {
+    extern "C" {
+        fn ekernel();
+    }
+    let start = align_up(ekernel as usize + PAGE_SIZE) - KCONFIG.phys_to_virt_offset;
+    alloc::vec![start..PHYS_MEMORY_END]
+}
+
+/// Flush the physical frame.
+///
+/// The caches are coherent with the instruction fetches and the DMA on
+/// LoongArch, so only the memory accesses before are ordered.
+pub fn frame_flush(_target: PhysAddr) {
+    unsafe { core::arch::asm!("dbar 0") };
+}
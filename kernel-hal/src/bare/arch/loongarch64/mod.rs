@@ -0,0 +1,59 @@
+#[macro_use]
+mod csr;
+
+mod drivers;
+mod trap;
+
+pub mod config;
+pub mod context;
+pub mod cpu;
+pub mod interrupt;
+pub mod mem;
+pub mod timer;
+pub mod vm;
+
+use crate::utils::init_once::InitOnce;
+use crate::KCONFIG;
+use alloc::string::{String, ToString};
+
+hal_fn_impl_default!(crate::hal_fn::console);
+
+static CMDLINE: InitOnce<String> = InitOnce::new_with_default(String::new());
+
+pub const fn timer_interrupt_vector() -> usize {
+    trap::TIMER_INT_VEC
+}
+
+pub fn cmdline() -> String {
+    CMDLINE.clone()
+}
+
+pub fn init_ram_disk() -> Option<&'static mut [u8]> {
+    None
+}
+
+/// Initialize the traps, as `trapframe::init()` does on other architectures.
+pub fn trap_init() {
+    trap::init();
+    // let user programs use the floating-point instructions
+    csr_write!(csr::EUEN, csr::EUEN_FPE);
+}
+
+pub fn primary_init_early() {
+    CMDLINE.init_once_by(KCONFIG.cmdline.to_string());
+    drivers::init_early();
+}
+
+pub fn primary_init() {
+    vm::init();
+    drivers::init();
+}
+
+pub fn secondary_init() {
+    vm::init();
+    drivers::intc_init();
+}
+
+pub fn timer_init() {
+    timer::init();
+}
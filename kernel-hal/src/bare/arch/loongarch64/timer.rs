@@ -0,0 +1,46 @@
+//! LoongArch stable counter and constant frequency timer.
+
+use super::csr::{TCFG, TICLR};
+use crate::utils::init_once::InitOnce;
+
+/// The frequency of the stable counter in Hz, from `CPUCFG` words 4 and 5.
+static COUNTER_FREQ_HZ: InitOnce<u64> = InitOnce::new_with_default(100_000_000);
+
+const TCFG_EN: usize = 1 << 0;
+const TCFG_PERIODIC: usize = 1 << 1;
+const TICLR_CLR: usize = 1 << 0;
+
+fn cpucfg(word: usize) -> usize {
+    let value: usize;
+    unsafe { core::arch::asm!("cpucfg {}, {}", out(reg) value, in(reg) word) };
+    value
+}
+
+pub fn clock_cycles() -> u64 {
+    let cycles: u64;
+    unsafe { core::arch::asm!("rdtime.d {}, $zero", out(reg) cycles) };
+    cycles
+}
+
+pub fn clock_frequency() -> u64 {
+    *COUNTER_FREQ_HZ
+}
+
+/// Acknowledge the timer interrupt, and the periodic timer goes on.
+pub(super) fn timer_tick() {
+    csr_write!(TICLR, TICLR_CLR);
+    crate::timer::timer_tick();
+    super::drivers::poll_uart();
+}
+
+pub(super) fn init() {
+    let base_freq = cpucfg(4) as u64;
+    let cfg5 = cpucfg(5) as u64;
+    let (mul, div) = (cfg5 & 0xffff, (cfg5 >> 16) & 0xffff);
+    if base_freq != 0 && mul != 0 && div != 0 {
+        COUNTER_FREQ_HZ.init_once_by(base_freq * mul / div);
+    }
+    // the initial value is in multiples of 4
+    let ticks = (clock_frequency() / super::super::timer::TICKS_PER_SEC) as usize & !3;
+    csr_write!(TCFG, ticks | TCFG_PERIODIC | TCFG_EN);
+}
@@ -0,0 +1,42 @@
+use super::context::TrapFrame;
+use super::csr::{BADV, EENTRY, ESTAT, SAVE0, TLBRENTRY};
+use crate::context::TrapReason;
+use crate::KCONFIG;
+
+core::arch::global_asm!(include_str!("trap.S"));
+
+/// The interrupt line of the constant frequency timer.
+pub(super) const TIMER_INT_VEC: usize = 11;
+
+fn breakpoint(era: &mut usize) {
+    info!("Exception::Breakpoint: A breakpoint set @0x{:x} ", era);
+    // skip the `break` instruction
+    *era += 4
+}
+
+#[no_mangle]
+pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
+    let reason = TrapReason::from(csr_read!(ESTAT), csr_read!(BADV));
+    trace!("kernel trap happened: {:?}", reason);
+    match reason {
+        TrapReason::SoftwareBreakpoint => breakpoint(&mut tf.era),
+        TrapReason::PageFault(vaddr, flags) => crate::KHANDLER.handle_page_fault(vaddr, flags),
+        TrapReason::Interrupt(vector) => crate::interrupt::handle_irq(vector),
+        other => panic!("Undefined trap: {:x?} {:#x?}", other, tf),
+    }
+}
+
+/// Set the entries of exceptions and TLB refills, instead of `trapframe::init()`.
+pub(super) fn init() {
+    extern "C" {
+        fn trap_entry();
+        fn tlb_refill_entry();
+    }
+    csr_write!(SAVE0, 0);
+    csr_write!(EENTRY, trap_entry as usize);
+    // TLB refills are in the direct address mode
+    csr_write!(
+        TLBRENTRY,
+        tlb_refill_entry as usize - KCONFIG.phys_to_virt_offset
+    );
+}
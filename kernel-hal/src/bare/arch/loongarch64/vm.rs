@@ -0,0 +1,203 @@
+//! Virtual memory operations.
+//!
+//! The kernel is accessed through the direct mapped windows, so the page
+//! tables only translate user addresses, walked by the TLB refill handler.
+
+use core::convert::TryFrom;
+use core::fmt::{Debug, Formatter, Result};
+
+use lock::Mutex;
+
+use super::csr::{ASID, PGDH, PGDL, PWCH, PWCL, STLBPS, TLBREHI};
+use crate::utils::page_table::{GenericPTE, PageTableImpl, PageTableLevel4};
+use crate::{CachePolicy, MMUFlags, PhysAddr, VirtAddr, PAGE_SIZE};
+
+lazy_static! {
+    /// The page table with no user mappings, as the kernel is not paged.
+    static ref KERNEL_PT: Mutex<PageTable> = Mutex::new(PageTable::new());
+}
+
+/// Page walk controls: 4 levels of 9 bits each above the 4KiB pages, with
+/// 64-bit entries.
+const PWCL_VALUE: usize = 12 | 9 << 5 | 21 << 10 | 9 << 15 | 30 << 20 | 9 << 25;
+const PWCH_VALUE: usize = 39 | 9 << 6;
+const PAGE_SIZE_SHIFT: usize = PAGE_SIZE.trailing_zeros() as usize;
+
+pub(super) fn init() {
+    csr_write!(PWCL, PWCL_VALUE);
+    csr_write!(PWCH, PWCH_VALUE);
+    csr_write!(STLBPS, PAGE_SIZE_SHIFT);
+    csr_write!(TLBREHI, PAGE_SIZE_SHIFT);
+    csr_write!(ASID, 0);
+    let pt = KERNEL_PT.lock();
+    info!("initialized kernel page table @ {:#x}", pt.table_phys());
+    csr_write!(PGDH, pt.table_phys());
+    crate::vm::activate_paging(pt.table_phys());
+}
+
+fn flush_tlb_all() {
+    unsafe { core::arch::asm!("dbar 0", "invtlb 0, $zero, $zero") };
+}
+
+hal_fn_impl! {
+    impl mod crate::hal_fn::vm {
+        fn activate_paging(vmtoken: PhysAddr) {
+            let old_token = current_vmtoken();
+            if old_token != vmtoken {
+                debug!("switch table {:x?} -> {:x?}", old_token, vmtoken);
+                csr_write!(PGDL, vmtoken);
+                flush_tlb_all();
+            }
+        }
+
+        fn current_vmtoken() -> PhysAddr {
+            csr_read!(PGDL)
+        }
+
+        fn flush_tlb(vaddr: Option<VirtAddr>) {
+            if let Some(vaddr) = vaddr {
+                // the entries of ASID 0 or global, at `vaddr`
+                unsafe { core::arch::asm!("dbar 0", "invtlb 6, $zero, {}", in(reg) vaddr) };
+            } else {
+                flush_tlb_all();
+            }
+        }
+
+        fn pt_clone_kernel_space(_dst_pt_root: PhysAddr, _src_pt_root: PhysAddr) {
+            // no kernel mappings in the page tables
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Possible flags for a page table entry.
+    struct PTF: usize {
+        const VALID =       1 << 0;
+        const DIRTY =       1 << 1;
+        /// The lowest privilege level allowed, 3 for user mode.
+        const PLV_USER =    0b11 << 2;
+        /// Memory access type: strongly-ordered uncached (0), coherent
+        /// cached (1), or weakly-ordered uncached (2).
+        const MAT_CC =      1 << 4;
+        const MAT_WUC =     2 << 4;
+        const GLOBAL =      1 << 6;
+        /// Software bits, not checked by the hardware.
+        const PRESENT =     1 << 7;
+        const WRITABLE =    1 << 8;
+        const NO_READ =     1 << 61;
+        const NO_EXECUTE =  1 << 62;
+    }
+}
+
+impl From<MMUFlags> for PTF {
+    fn from(f: MMUFlags) -> Self {
+        // a page which can't be accessed at all is not present
+        if !f.intersects(MMUFlags::RXW) {
+            return PTF::empty();
+        }
+        let mut flags = PTF::VALID | PTF::PRESENT;
+        if !f.contains(MMUFlags::READ) {
+            flags |= PTF::NO_READ;
+        }
+        if f.contains(MMUFlags::WRITE) {
+            // stores to the pages not dirty raise page modification exceptions
+            flags |= PTF::WRITABLE | PTF::DIRTY;
+        }
+        if !f.contains(MMUFlags::EXECUTE) {
+            flags |= PTF::NO_EXECUTE;
+        }
+        if f.contains(MMUFlags::USER) {
+            flags |= PTF::PLV_USER;
+        }
+        match CachePolicy::try_from((f.bits() & 3) as u32) {
+            Ok(CachePolicy::Uncached) | Ok(CachePolicy::UncachedDevice) => {}
+            Ok(CachePolicy::WriteCombining) => flags |= PTF::MAT_WUC,
+            _ if f.contains(MMUFlags::DEVICE) => {}
+            _ => flags |= PTF::MAT_CC,
+        }
+        flags
+    }
+}
+
+impl From<PTF> for MMUFlags {
+    fn from(f: PTF) -> Self {
+        let mut ret = Self::empty();
+        if !f.contains(PTF::VALID) {
+            return ret;
+        }
+        if !f.contains(PTF::NO_READ) {
+            ret |= Self::READ;
+        }
+        if f.contains(PTF::WRITABLE) {
+            ret |= Self::WRITE;
+        }
+        if !f.contains(PTF::NO_EXECUTE) {
+            ret |= Self::EXECUTE;
+        }
+        if f.contains(PTF::PLV_USER) {
+            ret |= Self::USER;
+        }
+        if f.contains(PTF::MAT_WUC) {
+            ret |= Self::from_bits_truncate(CachePolicy::WriteCombining as usize);
+        } else if !f.contains(PTF::MAT_CC) {
+            ret |= Self::from_bits_truncate(CachePolicy::UncachedDevice as usize);
+        }
+        ret
+    }
+}
+
+const PHYS_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000; // 12..48
+
+/// Page table entry. The directory entries are the physical addresses of the
+/// next level tables alone, as loaded by `lddir`.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct LA64PTE(u64);
+
+impl GenericPTE for LA64PTE {
+    fn addr(&self) -> PhysAddr {
+        (self.0 & PHYS_ADDR_MASK) as _
+    }
+    fn flags(&self) -> MMUFlags {
+        PTF::from_bits_truncate(self.0 as usize).into()
+    }
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+    fn is_present(&self) -> bool {
+        if self.is_leaf() {
+            PTF::from_bits_truncate(self.0 as usize).contains(PTF::VALID)
+        } else {
+            !self.is_unused()
+        }
+    }
+    fn is_leaf(&self) -> bool {
+        PTF::from_bits_truncate(self.0 as usize).contains(PTF::PRESENT)
+    }
+
+    fn set_addr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !PHYS_ADDR_MASK) | (paddr as u64 & PHYS_ADDR_MASK);
+    }
+    fn set_flags(&mut self, flags: MMUFlags, _is_huge: bool) {
+        self.0 = (self.0 & PHYS_ADDR_MASK) | PTF::from(flags).bits() as u64;
+    }
+    fn set_table(&mut self, paddr: PhysAddr) {
+        self.0 = paddr as u64 & PHYS_ADDR_MASK;
+    }
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl Debug for LA64PTE {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let mut f = f.debug_struct("LA64PTE");
+        f.field("raw", &self.0);
+        f.field("addr", &self.addr());
+        f.field("flags", &self.flags());
+        f.finish()
+    }
+}
+
+/// 4-level paging of 48-bit virtual addresses.
+pub type PageTable = PageTableImpl<PageTableLevel4, LA64PTE>;
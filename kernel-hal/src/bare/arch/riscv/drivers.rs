@@ -66,7 +66,7 @@ pub(super) fn init() -> DeviceResult {
     {
         use alloc::sync::Arc;
         use zcore_drivers::bus::pci;
-        let pci_devs = pci::init(Some(Arc::new(IoMapperImpl)))?;
+        let pci_devs = pci::init(Some(Arc::new(IoMapperImpl)), None)?;
         for d in pci_devs.into_iter() {
             drivers::add_device(d);
         }
@@ -74,10 +74,11 @@ pub(super) fn init() -> DeviceResult {
 
     intc_init()?;
 
-    #[cfg(feature = "graphic")]
     if let Some(display) = drivers::all_display().first() {
+        #[cfg(feature = "graphic")]
         crate::console::init_graphic_console(display.clone());
         if display.need_flush() {
+            // also for the writes through the mappings of `/dev/fb0`
             // TODO: support nested interrupt to render in time
             crate::thread::spawn(crate::common::future::DisplayFlushFuture::new(display, 30));
         }
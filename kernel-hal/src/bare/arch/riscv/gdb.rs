@@ -0,0 +1,174 @@
+//! Architecture specific parts of the GDB stub.
+//!
+//! RISC-V has no single-step support in S-mode, so stepping is emulated by
+//! putting temporary breakpoints on every possible next instruction.
+
+use alloc::vec::Vec;
+use trapframe::TrapFrame;
+
+use crate::imp::gdbstub::Step;
+
+/// `c.ebreak`
+const C_EBREAK: u16 = 0x9002;
+/// `ebreak`
+const EBREAK: u32 = 0x0010_0073;
+
+/// Number of the general purpose registers, `x0` ~ `x31`.
+const GENERAL_REG_NUM: usize = 32;
+
+fn general(tf: &TrapFrame) -> &[usize; GENERAL_REG_NUM] {
+    unsafe { &*(&tf.general as *const _ as *const [usize; GENERAL_REG_NUM]) }
+}
+
+fn general_mut(tf: &mut TrapFrame) -> &mut [usize; GENERAL_REG_NUM] {
+    unsafe { &mut *(&mut tf.general as *mut _ as *mut [usize; GENERAL_REG_NUM]) }
+}
+
+/// Triggers a breakpoint exception.
+#[inline(always)]
+pub fn breakpoint() {
+    unsafe { core::arch::asm!("ebreak") };
+}
+
+/// Returns the breakpoint instruction of the given size, as sent in `Z0`.
+pub fn break_insn(kind: usize) -> Option<&'static [u8]> {
+    const C_EBREAK_BYTES: [u8; 2] = C_EBREAK.to_le_bytes();
+    const EBREAK_BYTES: [u8; 4] = EBREAK.to_le_bytes();
+    match kind {
+        2 => Some(&C_EBREAK_BYTES),
+        4 => Some(&EBREAK_BYTES),
+        _ => None,
+    }
+}
+
+pub fn pc(tf: &TrapFrame) -> usize {
+    tf.sepc
+}
+
+pub fn set_pc(tf: &mut TrapFrame, pc: usize) {
+    tf.sepc = pc;
+}
+
+/// Appends `x0` ~ `x31` and `pc` in the order of `g` packets.
+pub fn read_registers(tf: &TrapFrame, buf: &mut Vec<u8>) {
+    for reg in general(tf) {
+        buf.extend_from_slice(&reg.to_le_bytes());
+    }
+    buf.extend_from_slice(&tf.sepc.to_le_bytes());
+}
+
+/// Writes registers from a `G` packet, returns `false` if it is malformed.
+pub fn write_registers(tf: &mut TrapFrame, data: &[u8]) -> bool {
+    const SIZE: usize = core::mem::size_of::<usize>();
+    if data.len() < (GENERAL_REG_NUM + 1) * SIZE {
+        return false;
+    }
+    let mut regs = data
+        .chunks_exact(SIZE)
+        .map(|b| usize::from_le_bytes(b.try_into().unwrap()));
+    // x0 is hardwired to zero
+    regs.next();
+    for reg in general_mut(tf).iter_mut().skip(1) {
+        *reg = regs.next().unwrap();
+    }
+    tf.sepc = regs.next().unwrap();
+    true
+}
+
+/// Skips the compiled-in `ebreak` at `pc`, returns `false` if there is not.
+pub fn skip_break(tf: &mut TrapFrame) -> bool {
+    let insn = unsafe { (tf.sepc as *const u16).read() };
+    if insn == C_EBREAK {
+        tf.sepc += 2;
+        true
+    } else if insn & 0b11 == 0b11 && unsafe { (tf.sepc as *const u32).read_unaligned() } == EBREAK {
+        tf.sepc += 4;
+        true
+    } else {
+        false
+    }
+}
+
+/// Prepares to execute a single instruction at `pc`.
+pub(crate) fn step(tf: &mut TrapFrame) -> Step {
+    let pc = tf.sepc;
+    let regs = general(tf);
+    let low = unsafe { (pc as *const u16).read() } as u32;
+    if low & 0b11 != 0b11 {
+        return Step::Software(compressed_next(pc, low, regs));
+    }
+    let insn = low | (unsafe { ((pc + 2) as *const u16).read() } as u32) << 16;
+    let rs1 = regs[((insn >> 15) & 0x1f) as usize];
+    let next = pc + 4;
+    Step::Software(match insn & 0x7f {
+        // jal
+        0x6f => {
+            let imm = (insn >> 31) << 20
+                | ((insn >> 12) & 0xff) << 12
+                | ((insn >> 20) & 1) << 11
+                | ((insn >> 21) & 0x3ff) << 1;
+            [Some(pc.wrapping_add(sign_extend(imm, 21))), None]
+        }
+        // jalr
+        0x67 => {
+            let imm = sign_extend(insn >> 20, 12);
+            [Some(rs1.wrapping_add(imm) & !1), None]
+        }
+        // branch
+        0x63 => {
+            let imm = (insn >> 31) << 12
+                | ((insn >> 7) & 1) << 11
+                | ((insn >> 25) & 0x3f) << 5
+                | ((insn >> 8) & 0xf) << 1;
+            [Some(next), Some(pc.wrapping_add(sign_extend(imm, 13)))]
+        }
+        _ => [Some(next), None],
+    })
+}
+
+/// Nothing to clean up, the temporary breakpoints are removed by the stub.
+pub fn step_done(_tf: &mut TrapFrame) {}
+
+/// Makes the modified instructions visible to the instruction fetch.
+pub fn flush_icache(_vaddr: usize) {
+    unsafe { core::arch::asm!("fence.i") };
+}
+
+fn compressed_next(pc: usize, insn: u32, regs: &[usize; GENERAL_REG_NUM]) -> [Option<usize>; 2] {
+    let next = pc + 2;
+    let funct3 = insn >> 13;
+    match (insn & 0b11, funct3) {
+        // c.j
+        (0b01, 0b101) => {
+            let imm = ((insn >> 12) & 1) << 11
+                | ((insn >> 11) & 1) << 4
+                | ((insn >> 9) & 0b11) << 8
+                | ((insn >> 8) & 1) << 10
+                | ((insn >> 7) & 1) << 6
+                | ((insn >> 6) & 1) << 7
+                | ((insn >> 3) & 0b111) << 1
+                | ((insn >> 2) & 1) << 5;
+            [Some(pc.wrapping_add(sign_extend(imm, 12))), None]
+        }
+        // c.beqz, c.bnez
+        (0b01, 0b110 | 0b111) => {
+            let imm = ((insn >> 12) & 1) << 8
+                | ((insn >> 10) & 0b11) << 3
+                | ((insn >> 5) & 0b11) << 6
+                | ((insn >> 3) & 0b11) << 1
+                | ((insn >> 2) & 1) << 5;
+            [Some(next), Some(pc.wrapping_add(sign_extend(imm, 9)))]
+        }
+        // c.jr, c.jalr
+        (0b10, 0b100) if (insn >> 2) & 0x1f == 0 && (insn >> 7) & 0x1f != 0 => {
+            [Some(regs[((insn >> 7) & 0x1f) as usize] & !1), None]
+        }
+        _ => [Some(next), None],
+    }
+}
+
+/// Sign-extends the lowest `bits` bits of `imm`.
+fn sign_extend(imm: u32, bits: u32) -> usize {
+    let shift = usize::BITS - bits;
+    (((imm as usize) << shift) as isize >> shift) as usize
+}
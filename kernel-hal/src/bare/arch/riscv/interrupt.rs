@@ -1,7 +1,18 @@
 //! Interrupts management.
 
+use alloc::sync::Arc;
+
 use riscv::{asm, register::sstatus};
 
+use crate::drivers::prelude::{IrqHandler, IrqPolarity, IrqTriggerMode};
+use crate::drivers::{all_irq, scheme::IrqScheme};
+use crate::{HalError, HalResult};
+
+/// The interrupt controller of external IRQs.
+fn plic() -> HalResult<Arc<dyn IrqScheme>> {
+    all_irq().find("riscv-plic").ok_or(HalError)
+}
+
 hal_fn_impl! {
     impl mod crate::hal_fn::interrupt {
         fn wait_for_interrupt() {
@@ -15,6 +26,30 @@ hal_fn_impl! {
             }
         }
 
+        fn is_valid_irq(irq: usize) -> bool {
+            plic().map_or(false, |plic| plic.is_valid_irq(irq))
+        }
+
+        fn mask_irq(irq: usize) -> HalResult {
+            Ok(plic()?.mask(irq)?)
+        }
+
+        fn unmask_irq(irq: usize) -> HalResult {
+            Ok(plic()?.unmask(irq)?)
+        }
+
+        fn configure_irq(irq: usize, tm: IrqTriggerMode, pol: IrqPolarity) -> HalResult {
+            Ok(plic()?.configure(irq, tm, pol)?)
+        }
+
+        fn register_irq_handler(irq: usize, handler: IrqHandler) -> HalResult {
+            Ok(plic()?.register_handler(irq, handler)?)
+        }
+
+        fn unregister_irq_handler(irq: usize) -> HalResult {
+            Ok(plic()?.unregister(irq)?)
+        }
+
         fn handle_irq(cause: usize) {
             trace!("Handle irq cause: {}", cause);
             crate::drivers::all_irq().first_unwrap().handle_irq(cause)
@@ -31,5 +66,9 @@ hal_fn_impl! {
         fn intr_get() -> bool {
             sstatus::read().sie()
         }
+
+        fn send_ipi(cpu_id: u8) {
+            sbi_rt::send_ipi(1 << cpu_id, 0);
+        }
     }
 }
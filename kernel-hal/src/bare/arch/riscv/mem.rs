@@ -44,6 +44,11 @@ pub fn free_pmem_regions() -> Vec<Range<PhysAddr>> {
     FREE_PMEM_REGIONS.clone()
 }
 
+/// Flush the physical frame.
+///
+/// Without the Zicbom extension, there is no cache maintenance instruction,
+/// and the caches are kept coherent by the platform. So only the memory
+/// accesses before are ordered.
 pub fn frame_flush(_target: crate::PhysAddr) {
-    unimplemented!()
+    unsafe { core::arch::asm!("fence rw, rw") };
 }
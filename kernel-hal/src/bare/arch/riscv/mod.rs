@@ -3,6 +3,8 @@ mod trap;
 
 pub mod config;
 pub mod cpu;
+#[cfg(feature = "gdbstub")]
+pub mod gdb;
 pub mod interrupt;
 pub mod mem;
 pub mod sbi;
@@ -16,6 +18,9 @@ use core::ops::Range;
 static CMDLINE: InitOnce<String> = InitOnce::new_with_default(String::new());
 static INITRD_REGION: InitOnce<Option<Range<PhysAddr>>> = InitOnce::new_with_default(None);
 static MEMORY_REGIONS: InitOnce<Vec<Range<PhysAddr>>> = InitOnce::new_with_default(Vec::new());
+/// Whether all harts support the Svpbmt extension, which sets the memory
+/// types of pages.
+static SVPBMT: InitOnce<bool> = InitOnce::new_with_default(false);
 
 pub const fn timer_interrupt_vector() -> usize {
     trap::SUPERVISOR_TIMER_INT_VEC
@@ -35,6 +40,7 @@ pub fn primary_init_early() {
     use dtb_walker::{Dtb, DtbObj, Property, Str, WalkOperation::*};
     let mut initrd_start: Option<usize> = None;
     let mut initrd_end: Option<usize> = None;
+    let mut svpbmt: Option<bool> = None;
     // smp 启动时已经检查过了，不要重复检查
     unsafe { Dtb::from_raw_parts_unchecked(phys_to_virt(crate::KCONFIG.dtb_paddr) as _) }.walk(
         |path, obj| match obj {
@@ -44,6 +50,8 @@ pub fn primary_init_early() {
                         || name.starts_with("memory"))
                 {
                     StepInto
+                } else if path.name() == Str::from("cpus") && name.starts_with("cpu@") {
+                    StepInto
                 } else {
                     StepOver
                 }
@@ -58,16 +66,8 @@ pub fn primary_init_early() {
                         info!("Load kernel cmdline from DTB: {cmdline:?}");
                         CMDLINE.init_once_by(cmdline);
                     }
-                    b"linux,initrd-start" => {
-                        if let [a, b, c, d] = *value {
-                            initrd_start = Some(u32::from_be_bytes([a, b, c, d]) as _);
-                        }
-                    }
-                    b"linux,initrd-end" => {
-                        if let [a, b, c, d] = *value {
-                            initrd_end = Some(u32::from_be_bytes([a, b, c, d]) as _);
-                        }
-                    }
+                    b"linux,initrd-start" => initrd_start = be_cells(value),
+                    b"linux,initrd-end" => initrd_end = be_cells(value),
                     _ => {}
                 }
                 StepOver
@@ -79,9 +79,25 @@ pub fn primary_init_early() {
                     let time_freq = u32::from_be_bytes([a, b, c, d]);
                     info!("Load CPU clock frequency from DTB: {time_freq} Hz");
                     super::cpu::CPU_FREQ_MHZ.init_once_by((time_freq / 1_000_000) as u16);
+                    timer::TIMEBASE_FREQ_HZ.init_once_by(time_freq as u64);
                 }
                 StepOver
             }
+            DtbObj::Property(Property::General { name, value })
+                if path.name().starts_with("cpu@") =>
+            {
+                let has_svpbmt = match name.as_bytes() {
+                    b"riscv,isa" => value
+                        .split(|&b| b == b'_' || b == b'\0')
+                        .any(|ext| ext == b"svpbmt"),
+                    b"riscv,isa-extensions" => {
+                        value.split(|&b| b == b'\0').any(|ext| ext == b"svpbmt")
+                    }
+                    _ => return StepOver,
+                };
+                svpbmt = Some(svpbmt.unwrap_or(true) && has_svpbmt);
+                StepOver
+            }
             DtbObj::Property(Property::Reg(reg)) if path.name().starts_with("memory") => {
                 let regions = reg.collect();
                 info!("Load memory regions from DTB: {regions:#x?}");
@@ -91,6 +107,10 @@ pub fn primary_init_early() {
             DtbObj::Property(_) => StepOver,
         },
     );
+    if svpbmt == Some(true) {
+        info!("Svpbmt is supported, the memory types of pages are set");
+        SVPBMT.init_once_by(true);
+    }
     if let Some(s) = initrd_start && let Some(e) = initrd_end {
         let initrd_region = s..e;
         info!("Load initrd regions from DTB: {initrd_region:#x?}");
@@ -98,6 +118,15 @@ pub fn primary_init_early() {
     }
 }
 
+/// A number in one or two big-endian cells, as the addresses of initrd.
+fn be_cells(value: &[u8]) -> Option<usize> {
+    match *value {
+        [a, b, c, d] => Some(u32::from_be_bytes([a, b, c, d]) as _),
+        [a, b, c, d, e, f, g, h] => Some(u64::from_be_bytes([a, b, c, d, e, f, g, h]) as _),
+        _ => None,
+    }
+}
+
 pub fn primary_init() {
     vm::init();
     drivers::init().unwrap();
@@ -1,19 +1,21 @@
-use core::time::Duration;
+use crate::utils::init_once::InitOnce;
 
-fn get_cycle() -> u64 {
+/// The frequency of the `time` CSR in Hz, from the DTB.
+pub(super) static TIMEBASE_FREQ_HZ: InitOnce<u64> = InitOnce::new_with_default(1_000_000_000);
+
+pub fn clock_cycles() -> u64 {
     riscv::register::time::read() as u64
 }
 
+pub fn clock_frequency() -> u64 {
+    *TIMEBASE_FREQ_HZ
+}
+
 pub(super) fn timer_set_next() {
-    let cycles =
-        super::cpu::cpu_frequency() as u64 * 1_000_000 / super::super::timer::TICKS_PER_SEC;
-    sbi_rt::set_timer(get_cycle() + cycles);
+    let cycles = clock_frequency() / super::super::timer::TICKS_PER_SEC;
+    sbi_rt::set_timer(clock_cycles() + cycles);
 }
 
 pub(super) fn init() {
     timer_set_next();
 }
-
-pub(crate) fn timer_now() -> Duration {
-    Duration::from_nanos(get_cycle() * 1000 / super::cpu::cpu_frequency() as u64)
-}
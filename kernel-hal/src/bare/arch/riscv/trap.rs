@@ -1,4 +1,3 @@
-use crate::thread::{get_current_thread, set_current_thread};
 use riscv::register::scause;
 use trapframe::TrapFrame;
 
@@ -22,7 +21,8 @@ pub(super) fn super_timer() {
 pub(super) fn super_soft() {
     #[allow(deprecated)]
     sbi_rt::legacy::clear_ipi();
-    info!("Interrupt::SupervisorSoft!");
+    trace!("Interrupt::SupervisorSoft!");
+    crate::imp::tlb::handle_ipi();
 }
 
 #[no_mangle]
@@ -35,16 +35,16 @@ pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
         crate::vm::current_vmtoken()
     );
     match TrapReason::from(scause) {
-        TrapReason::SoftwareBreakpoint => breakpoint(&mut tf.sepc),
+        TrapReason::SoftwareBreakpoint => {
+            #[cfg(feature = "gdbstub")]
+            if crate::imp::gdbstub::handle_trap(tf) {
+                return;
+            }
+            breakpoint(&mut tf.sepc)
+        }
         TrapReason::PageFault(vaddr, flags) => crate::KHANDLER.handle_page_fault(vaddr, flags),
         TrapReason::Interrupt(vector) => {
             crate::interrupt::handle_irq(vector);
-            if vector == SUPERVISOR_TIMER_INT_VEC {
-                let current_thread = get_current_thread();
-                set_current_thread(None);
-                executor::handle_timeout();
-                set_current_thread(current_thread);
-            }
         }
         other => panic!("Undefined trap: {:x?} {:#x?}", other, tf),
     }
@@ -1,13 +1,13 @@
 //! Virtual memory operations.
 
 use core::fmt::{Debug, Formatter, Result};
-use core::slice;
+use core::{convert::TryFrom, slice};
 
 use lock::Mutex;
 use riscv::{asm, register::satp};
 
 use crate::utils::page_table::{GenericPTE, PageTableImpl, PageTableLevel3};
-use crate::{mem::phys_to_virt, MMUFlags, PhysAddr, VirtAddr, KCONFIG};
+use crate::{mem::phys_to_virt, CachePolicy, MMUFlags, PhysAddr, VirtAddr, KCONFIG};
 
 lazy_static! {
     static ref KERNEL_PT: Mutex<PageTable> = Mutex::new(init_kernel_page_table().unwrap());
@@ -119,6 +119,10 @@ hal_fn_impl! {
             }
         }
 
+        fn tlb_shootdown(vmtoken: PhysAddr, vaddrs: Option<&[VirtAddr]>) {
+            crate::imp::tlb::shootdown(vmtoken, vaddrs);
+        }
+
         fn pt_clone_kernel_space(dst_pt_root: PhysAddr, src_pt_root: PhysAddr) {
             let entry_range = 0x100..0x200; // 0xFFFF_FFC0_0000_0000 .. 0xFFFF_FFFF_FFFF_FFFF
             let dst_table = unsafe { slice::from_raw_parts_mut(phys_to_virt(dst_pt_root) as *mut Rv64PTE, 512) };
@@ -146,12 +150,17 @@ bitflags::bitflags! {
         const DIRTY =       1 << 7;
         const RESERVED1 =   1 << 8;
         const RESERVED2 =   1 << 9;
+        /// Non-cacheable, idempotent memory (Svpbmt).
+        const PBMT_NC =     1 << 61;
+        /// Non-cacheable, non-idempotent I/O memory (Svpbmt).
+        const PBMT_IO =     1 << 62;
     }
 }
 
 impl From<MMUFlags> for PTF {
     fn from(f: MMUFlags) -> Self {
-        if f.is_empty() {
+        // a page which can't be accessed at all is not present
+        if !f.intersects(MMUFlags::RXW) {
             return PTF::empty();
         }
         let mut flags = PTF::VALID;
@@ -167,6 +176,16 @@ impl From<MMUFlags> for PTF {
         if f.contains(MMUFlags::USER) {
             flags |= PTF::USER;
         }
+        // without Svpbmt, the memory types are fixed by the platform
+        if *super::SVPBMT {
+            match CachePolicy::try_from((f.bits() & 3) as u32) {
+                Ok(CachePolicy::Uncached) | Ok(CachePolicy::WriteCombining) => {
+                    flags |= PTF::PBMT_NC;
+                }
+                Ok(CachePolicy::UncachedDevice) => flags |= PTF::PBMT_IO,
+                _ => {}
+            }
+        }
         flags
     }
 }
@@ -186,6 +205,11 @@ impl From<PTF> for MMUFlags {
         if f.contains(PTF::USER) {
             ret |= Self::USER;
         }
+        if f.contains(PTF::PBMT_IO) {
+            ret |= Self::from_bits_truncate(CachePolicy::UncachedDevice as usize);
+        } else if f.contains(PTF::PBMT_NC) {
+            ret |= Self::from_bits_truncate(CachePolicy::Uncached as usize);
+        }
         ret
     }
 }
@@ -219,7 +243,9 @@ impl GenericPTE for Rv64PTE {
     }
     fn set_flags(&mut self, flags: MMUFlags, _is_huge: bool) {
         let flags = PTF::from(flags) | PTF::ACCESSED | PTF::DIRTY;
-        debug_assert!(flags.intersects(PTF::READABLE | PTF::EXECUTABLE));
+        debug_assert!(
+            !flags.contains(PTF::VALID) || flags.intersects(PTF::READABLE | PTF::EXECUTABLE)
+        );
         self.0 = (self.0 & PHYS_ADDR_MASK) | flags.bits() as u64;
     }
     fn set_table(&mut self, paddr: PhysAddr) {
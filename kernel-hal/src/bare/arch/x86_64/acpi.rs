@@ -0,0 +1,120 @@
+//! ACPI tables: the processors and the ISA IRQ overrides in the MADT, and the
+//! HPET.
+
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+use acpi::platform::interrupt::{InterruptModel, Polarity, TriggerMode};
+use acpi::platform::ProcessorState;
+use acpi::{AcpiHandler, AcpiTables, HpetInfo, PhysicalMapping};
+
+use crate::{mem::phys_to_virt, PhysAddr, KCONFIG};
+
+#[derive(Clone)]
+struct AcpiMapHandler;
+
+impl AcpiHandler for AcpiMapHandler {
+    unsafe fn map_physical_region<T>(
+        &self,
+        physical_address: usize,
+        size: usize,
+    ) -> PhysicalMapping<Self, T> {
+        // all the physical memory is mapped already
+        let start = crate::addr::align_down(physical_address);
+        let end = crate::addr::align_up(physical_address + size);
+        PhysicalMapping::new(
+            physical_address,
+            NonNull::new_unchecked(phys_to_virt(physical_address) as *mut T),
+            size,
+            end - start,
+            self.clone(),
+        )
+    }
+
+    fn unmap_physical_region<T>(_region: &PhysicalMapping<Self, T>) {}
+}
+
+/// An ISA IRQ connected to another GSI than its own number, or not edge
+/// triggered and active high.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct IsaOverride {
+    pub gsi: u32,
+    pub level_triggered: bool,
+    pub active_low: bool,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct AcpiInfo {
+    /// The local APIC IDs of the processors usable, the BSP first. Empty if
+    /// there is no MADT.
+    pub cpus: Vec<u32>,
+    /// The overrides of the ISA IRQs, by the IRQ numbers.
+    pub isa_overrides: Vec<(u8, IsaOverride)>,
+    /// The physical address of the registers of the first HPET.
+    pub hpet_paddr: Option<PhysAddr>,
+}
+
+impl AcpiInfo {
+    pub fn isa_override(&self, isa_irq: usize) -> Option<IsaOverride> {
+        self.isa_overrides
+            .iter()
+            .find(|(irq, _)| *irq as usize == isa_irq)
+            .map(|(_, o)| *o)
+    }
+}
+
+fn parse() -> AcpiInfo {
+    let mut info = AcpiInfo::default();
+    if KCONFIG.acpi_rsdp == 0 {
+        warn!("no ACPI RSDP given by the bootloader");
+        return info;
+    }
+    let tables = match unsafe { AcpiTables::from_rsdp(AcpiMapHandler, KCONFIG.acpi_rsdp as _) } {
+        Ok(tables) => tables,
+        Err(err) => {
+            warn!("failed to parse ACPI tables: {:?}", err);
+            return info;
+        }
+    };
+    match tables.platform_info() {
+        Ok(platform) => {
+            if let Some(processors) = platform.processor_info {
+                info.cpus.push(processors.boot_processor.local_apic_id);
+                info.cpus.extend(
+                    processors
+                        .application_processors
+                        .iter()
+                        .filter(|p| !matches!(p.state, ProcessorState::Disabled))
+                        .map(|p| p.local_apic_id),
+                );
+            }
+            if let InterruptModel::Apic(apic) = platform.interrupt_model {
+                info.isa_overrides = apic
+                    .interrupt_source_overrides
+                    .iter()
+                    .map(|o| {
+                        let isa_override = IsaOverride {
+                            gsi: o.global_system_interrupt,
+                            level_triggered: matches!(o.trigger_mode, TriggerMode::Level),
+                            active_low: matches!(o.polarity, Polarity::ActiveLow),
+                        };
+                        (o.isa_source, isa_override)
+                    })
+                    .collect();
+            }
+        }
+        Err(err) => warn!("failed to parse ACPI MADT: {:?}", err),
+    }
+    // the HPET is optional
+    if let Ok(hpet) = HpetInfo::new(&tables) {
+        info.hpet_paddr = Some(hpet.base_address);
+    }
+    info!("ACPI: {:#x?}", info);
+    info
+}
+
+/// The information from the ACPI tables, parsed on the first call.
+pub(super) fn info() -> &'static AcpiInfo {
+    static INFO: spin::Once<AcpiInfo> = spin::Once::new();
+    INFO.call_once(parse)
+}
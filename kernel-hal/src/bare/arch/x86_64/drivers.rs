@@ -1,7 +1,9 @@
 use alloc::{boxed::Box, sync::Arc};
 
 use zcore_drivers::irq::x86::Apic;
-use zcore_drivers::scheme::IrqScheme;
+use zcore_drivers::prelude::{IrqPolarity, IrqTriggerMode};
+use zcore_drivers::rtc::CmosRtc;
+use zcore_drivers::scheme::{IrqScheme, Scheme};
 use zcore_drivers::uart::{BufferedUart, Uart16550Pmio};
 use zcore_drivers::{Device, DeviceResult};
 
@@ -16,6 +18,29 @@ pub(super) fn init_early() -> DeviceResult {
     Ok(())
 }
 
+/// Register the device on the GSI of the ISA IRQ, as the MADT overrides.
+fn register_isa_irq(irq: &Apic, isa_irq: usize, dev: Arc<dyn Scheme>) -> DeviceResult {
+    let gsi = match super::acpi::info().isa_override(isa_irq) {
+        Some(o) => {
+            let tm = if o.level_triggered {
+                IrqTriggerMode::Level
+            } else {
+                IrqTriggerMode::Edge
+            };
+            let pol = if o.active_low {
+                IrqPolarity::ActiveLow
+            } else {
+                IrqPolarity::ActiveHigh
+            };
+            irq.configure(o.gsi as usize, tm, pol)?;
+            o.gsi as usize
+        }
+        None => isa_irq,
+    };
+    irq.register_device(gsi, dev)?;
+    irq.unmask(gsi)
+}
+
 pub(super) fn init() -> DeviceResult {
     Apic::init_local_apic_bsp(crate::mem::phys_to_virt);
     let irq = Arc::new(Apic::new(
@@ -24,34 +49,32 @@ pub(super) fn init() -> DeviceResult {
     ));
     let uarts = drivers::all_uart();
     if let Some(u) = uarts.try_get(0) {
-        irq.register_device(trap::X86_ISA_IRQ_COM1, u.clone().upcast())?;
-        irq.unmask(trap::X86_ISA_IRQ_COM1)?;
+        register_isa_irq(&irq, trap::X86_ISA_IRQ_COM1, u.clone().upcast())?;
 
         if let Some(u) = uarts.try_get(1) {
-            irq.register_device(trap::X86_ISA_IRQ_COM2, u.clone().upcast())?;
-            irq.unmask(trap::X86_ISA_IRQ_COM2)?;
+            register_isa_irq(&irq, trap::X86_ISA_IRQ_COM2, u.clone().upcast())?;
         }
     }
 
-    use x2apic::lapic::{TimerDivide, TimerMode};
+    use x2apic::lapic::TimerMode;
 
     irq.register_local_apic_handler(trap::X86_INT_APIC_TIMER, Box::new(super::trap::super_timer))?;
+    irq.register_local_apic_handler(trap::X86_INT_IPI, Box::new(super::trap::super_ipi))?;
 
     // SAFETY: this will be called once and only once for every core
+    let cycles = super::timer::apic_timer_frequency() / super::super::timer::TICKS_PER_SEC;
     Apic::local_apic().set_timer_mode(TimerMode::Periodic);
-    Apic::local_apic().set_timer_divide(TimerDivide::Div256); // indeed it is Div1, the name is confusing.
-    let cycles =
-        super::cpu::cpu_frequency() as u64 * 1_000_000 / super::super::timer::TICKS_PER_SEC;
     Apic::local_apic().set_timer_initial(cycles as u32);
     Apic::local_apic().disable_timer();
 
-    drivers::add_device(Device::Irq(irq));
+    drivers::add_device(Device::Irq(irq.clone()));
+    drivers::add_device(Device::Rtc(Arc::new(CmosRtc::new())));
 
     #[cfg(not(feature = "loopback"))]
     {
         // PCI scan
         use zcore_drivers::bus::pci;
-        let pci_devs = pci::init(None)?;
+        let pci_devs = pci::init(None, Some(irq.clone()))?;
         for d in pci_devs.into_iter() {
             drivers::add_device(d);
         }
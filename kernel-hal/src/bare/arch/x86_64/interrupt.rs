@@ -6,6 +6,7 @@ use crate::drivers::all_irq;
 use crate::drivers::prelude::{IrqHandler, IrqPolarity, IrqTriggerMode};
 use crate::HalResult;
 use x86_64::instructions::interrupts;
+use zcore_drivers::irq::x86::Apic;
 
 hal_fn_impl! {
     impl mod crate::hal_fn::interrupt {
@@ -33,6 +34,10 @@ hal_fn_impl! {
             interrupts::are_enabled()
         }
 
+        fn send_ipi(cpu_id: u8) {
+            Apic::local_apic().send_ipi(super::trap::X86_INT_IPI, cpu_id);
+        }
+
         fn mask_irq(gsi: usize) -> HalResult {
             Ok(all_irq().first_unwrap().mask(gsi)?)
         }
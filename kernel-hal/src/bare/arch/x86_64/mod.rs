@@ -1,3 +1,4 @@
+mod acpi;
 mod drivers;
 mod trap;
 
@@ -32,6 +33,9 @@ pub fn init_ram_disk() -> Option<&'static mut [u8]> {
 pub fn primary_init_early() {
     // init serial output first
     drivers::init_early().unwrap();
+    vm::init_pat();
+    // calibrate the TSC before other CPUs start
+    timer::clock_frequency();
 }
 
 pub fn primary_init() {
@@ -47,6 +51,25 @@ pub fn primary_init() {
     unsafe {
         // enable global page
         Cr4::update(|f| f.insert(Cr4Flags::PAGE_GLOBAL));
+    }
+    // the APs are started all at once, by their IDs as the CPU IDs
+    let cpus = &acpi::info().cpus;
+    if cpus.len() == 1 {
+        info!("only one processor in the MADT");
+        return;
+    }
+    if let Some(id) = cpus
+        .iter()
+        .find(|&&id| id as usize >= crate::config::MAX_CORE_NUM)
+    {
+        warn!(
+            "APIC ID {} not less than {}, application processors not started",
+            id,
+            crate::config::MAX_CORE_NUM
+        );
+        return;
+    }
+    unsafe {
         // start multi-processors
         x86_smpboot::start_application_processors(
             || (crate::KCONFIG.ap_fn)(),
@@ -61,5 +84,6 @@ pub fn timer_init() {
 }
 
 pub fn secondary_init() {
+    vm::init_pat();
     zcore_drivers::irq::x86::Apic::init_local_apic_ap();
 }
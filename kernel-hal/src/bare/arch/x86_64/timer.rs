@@ -1,8 +1,137 @@
-use core::time::Duration;
+//! Time stamp counter, calibrated by the HPET or the PIT, and the local APIC
+//! timer, calibrated by the TSC.
 
-pub fn timer_now() -> Duration {
-    let cycle = unsafe { core::arch::x86_64::_rdtsc() };
-    Duration::from_nanos(cycle * 1000 / super::cpu::cpu_frequency() as u64)
+use x2apic::lapic::{TimerDivide, TimerMode};
+use zcore_drivers::io::{Io, Mmio, Pmio};
+use zcore_drivers::irq::x86::Apic;
+
+use crate::mem::phys_to_virt;
+
+/// The frequency of the PIT in Hz.
+const PIT_FREQ_HZ: u64 = 1_193_182;
+
+/// The registers of the HPET.
+const HPET_CAPABILITIES: usize = 0x0;
+const HPET_CONFIG: usize = 0x10;
+const HPET_COUNTER: usize = 0xf0;
+
+/// The longest period of the HPET counter allowed, in femtoseconds.
+const HPET_MAX_PERIOD_FS: u64 = 100_000_000;
+
+/// How long the TSC is measured against the PIT.
+const CALIBRATE_MS: u64 = 10;
+
+pub fn clock_cycles() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+pub fn clock_frequency() -> u64 {
+    static TSC_FREQ_HZ: spin::Once<u64> = spin::Once::new();
+    *TSC_FREQ_HZ.call_once(calibrate_tsc)
+}
+
+/// Calibrate the TSC by the HPET if ACPI describes one, or by the PIT.
+fn calibrate_tsc() -> u64 {
+    super::acpi::info()
+        .hpet_paddr
+        .and_then(calibrate_tsc_hpet)
+        .unwrap_or_else(calibrate_tsc_pit)
+}
+
+/// Count the TSC cycles while the main counter of the HPET runs
+/// [`CALIBRATE_MS`].
+fn calibrate_tsc_hpet(hpet_paddr: usize) -> Option<u64> {
+    let base = phys_to_virt(hpet_paddr);
+    let capabilities = unsafe { Mmio::<u64>::from_base(base + HPET_CAPABILITIES) }.read();
+    let period_fs = capabilities >> 32;
+    if period_fs == 0 || period_fs > HPET_MAX_PERIOD_FS {
+        warn!("invalid HPET period: {} fs", period_fs);
+        return None;
+    }
+    let config = unsafe { Mmio::<u64>::from_base(base + HPET_CONFIG) };
+    config.write(config.read() | 1); // ENABLE_CNF
+    let counter = unsafe { Mmio::<u64>::from_base(base + HPET_COUNTER) };
+    let ticks = CALIBRATE_MS * 1_000_000_000_000 / period_fs;
+    let timeout = super::cpu::cpu_frequency() as u64 * 1_000_000;
+    let hpet_start = counter.read();
+    let start = clock_cycles();
+    while counter.read().wrapping_sub(hpet_start) < ticks {
+        if clock_cycles() - start > timeout {
+            warn!("HPET not counting");
+            return None;
+        }
+        core::hint::spin_loop();
+    }
+    let cycles = (clock_cycles() - start) as u128;
+    let elapsed_fs = counter.read().wrapping_sub(hpet_start) as u128 * period_fs as u128;
+    let freq = (cycles * 1_000_000_000_000_000 / elapsed_fs) as u64;
+    info!("TSC frequency calibrated by HPET: {} Hz", freq);
+    if freq == 0 {
+        None
+    } else {
+        Some(freq)
+    }
+}
+
+/// Count the TSC cycles while channel 2 of the PIT counts down
+/// [`CALIBRATE_MS`], or fall back to the base frequency of the processor if
+/// the PIT does not work.
+fn calibrate_tsc_pit() -> u64 {
+    let fallback = super::cpu::cpu_frequency() as u64 * 1_000_000;
+    let mut gate = Pmio::<u8>::new(0x61);
+    let mut command = Pmio::<u8>::new(0x43);
+    let mut channel2 = Pmio::<u8>::new(0x42);
+    // enable the gate of channel 2, with the speaker off
+    gate.write((gate.read() & !0x02) | 0x01);
+    // channel 2, lobyte/hibyte, mode 0 (interrupt on terminal count)
+    command.write(0xb0);
+    let latch = PIT_FREQ_HZ * CALIBRATE_MS / 1000;
+    channel2.write(latch as u8);
+    channel2.write((latch >> 8) as u8);
+    let start = clock_cycles();
+    // the output of channel 2 goes high once it counts down to 0
+    while gate.read() & 0x20 == 0 {
+        if clock_cycles() - start > fallback {
+            warn!("PIT not counting, TSC frequency not calibrated");
+            return fallback;
+        }
+        core::hint::spin_loop();
+    }
+    let freq = (clock_cycles() - start) * 1000 / CALIBRATE_MS;
+    info!("TSC frequency calibrated: {} Hz", freq);
+    if freq == 0 {
+        fallback
+    } else {
+        freq
+    }
+}
+
+/// Count the ticks of the local APIC timer in one-shot mode while the TSC
+/// runs [`CALIBRATE_MS`], or fall back to the base frequency of the processor
+/// if it does not count.
+///
+/// The timer is left with a divisor of 1.
+pub(super) fn apic_timer_frequency() -> u64 {
+    let fallback = super::cpu::cpu_frequency() as u64 * 1_000_000;
+    let lapic = Apic::local_apic();
+    lapic.set_timer_mode(TimerMode::OneShot);
+    lapic.set_timer_divide(TimerDivide::Div256); // indeed it is Div1, the name is confusing.
+    lapic.set_timer_initial(u32::MAX);
+    let start = clock_cycles();
+    let cycles = clock_frequency() * CALIBRATE_MS / 1000;
+    while clock_cycles() - start < cycles {
+        core::hint::spin_loop();
+    }
+    let ticks = (u32::MAX - lapic.timer_current()) as u64;
+    lapic.set_timer_initial(0);
+    let freq = ticks * 1000 / CALIBRATE_MS;
+    info!("APIC timer frequency calibrated: {} Hz", freq);
+    if freq == 0 {
+        warn!("APIC timer not counting, frequency not calibrated");
+        fallback
+    } else {
+        freq
+    }
 }
 
 pub fn init() {
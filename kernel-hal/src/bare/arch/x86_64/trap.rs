@@ -5,6 +5,7 @@ pub(super) const X86_INT_LOCAL_APIC_BASE: usize = 0xf0;
 pub(super) const _X86_INT_APIC_SPURIOUS: usize = X86_INT_LOCAL_APIC_BASE;
 pub(super) const X86_INT_APIC_TIMER: usize = X86_INT_LOCAL_APIC_BASE + 0x1;
 pub(super) const _X86_INT_APIC_ERROR: usize = X86_INT_LOCAL_APIC_BASE + 0x2;
+pub(super) const X86_INT_IPI: usize = X86_INT_LOCAL_APIC_BASE + 0x3;
 
 // ISA IRQ numbers
 pub(super) const _X86_ISA_IRQ_PIT: usize = 0;
@@ -24,6 +25,10 @@ pub(super) fn super_timer() {
     crate::timer::timer_tick();
 }
 
+pub(super) fn super_ipi() {
+    crate::imp::tlb::handle_ipi();
+}
+
 #[no_mangle]
 pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
     trace!(
@@ -37,9 +42,6 @@ pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
         TrapReason::PageFault(vaddr, flags) => crate::KHANDLER.handle_page_fault(vaddr, flags),
         TrapReason::Interrupt(vector) => {
             crate::interrupt::handle_irq(vector);
-            if vector == X86_INT_APIC_TIMER {
-                executor::handle_timeout();
-            }
         }
         other => panic!("Unhandled trap {:x?} {:#x?}", other, tf),
     }
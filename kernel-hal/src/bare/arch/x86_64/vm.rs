@@ -5,13 +5,31 @@ use core::{convert::TryFrom, slice};
 
 use x86_64::{
     instructions::tlb,
-    registers::control::{Cr3, Cr3Flags},
+    registers::{
+        control::{Cr3, Cr3Flags},
+        model_specific::Msr,
+    },
     structures::paging::page_table::PageTableFlags as PTF,
 };
 
 use crate::utils::page_table::{GenericPTE, PageTableImpl, PageTableLevel4};
 use crate::{mem::phys_to_virt, CachePolicy, MMUFlags, PhysAddr, VirtAddr};
 
+/// The IA32_PAT MSR.
+const IA32_PAT: u32 = 0x277;
+
+/// Memory types selected by the PWT and PCD bits of page table entries:
+/// WB, WC, UC- and UC. The entries selected with the PAT bit are the
+/// default ones, which are not used.
+const PAT_VALUE: u64 = 0x0007_0406_0007_0106;
+
+/// Program the PAT of the current CPU, so that write-combining mappings can
+/// be made.
+pub(super) fn init_pat() {
+    unsafe { Msr::new(IA32_PAT).write(PAT_VALUE) };
+    tlb::flush_all();
+}
+
 hal_fn_impl! {
     impl mod crate::hal_fn::vm {
         fn activate_paging(vmtoken: PhysAddr) {
@@ -35,6 +53,10 @@ hal_fn_impl! {
             }
         }
 
+        fn tlb_shootdown(vmtoken: PhysAddr, vaddrs: Option<&[VirtAddr]>) {
+            crate::imp::tlb::shootdown(vmtoken, vaddrs);
+        }
+
         fn pt_clone_kernel_space(dst_pt_root: PhysAddr, src_pt_root: PhysAddr) {
             let entry_range = 0x100..0x200; // 0xFFFF_8000_0000_0000 .. 0xFFFF_FFFF_FFFF_FFFF
             let dst_table = unsafe { slice::from_raw_parts_mut(phys_to_virt(dst_pt_root) as *mut X86PTE, 512) };
@@ -51,7 +73,8 @@ hal_fn_impl! {
 
 impl From<MMUFlags> for PTF {
     fn from(f: MMUFlags) -> Self {
-        if f.is_empty() {
+        // a page which can't be accessed at all is not present
+        if !f.intersects(MMUFlags::RXW) {
             return PTF::empty();
         }
         let mut flags = PTF::PRESENT;
@@ -66,16 +89,15 @@ impl From<MMUFlags> for PTF {
         }
         let cache_policy = (f.bits() & 3) as u32; // 最低三位用于储存缓存策略
         match CachePolicy::try_from(cache_policy) {
-            Ok(CachePolicy::Cached) => {
-                flags.remove(PTF::WRITE_THROUGH);
+            Ok(CachePolicy::Cached) => {}
+            Ok(CachePolicy::WriteCombining) => {
+                flags |= PTF::WRITE_THROUGH;
             }
-            Ok(CachePolicy::Uncached) | Ok(CachePolicy::UncachedDevice) => {
-                flags |= PTF::NO_CACHE | PTF::WRITE_THROUGH;
+            Ok(CachePolicy::Uncached) => {
+                flags |= PTF::NO_CACHE;
             }
-            Ok(CachePolicy::WriteCombining) => {
+            Ok(CachePolicy::UncachedDevice) => {
                 flags |= PTF::NO_CACHE | PTF::WRITE_THROUGH;
-                // 当位于level=1时，页面更大，在1<<12位上（0x100）为1
-                // 但是bitflags里面没有这一位。由页表自行管理标记位去吧
             }
             Err(_) => unreachable!("invalid cache policy"),
         }
@@ -98,10 +120,13 @@ impl From<PTF> for MMUFlags {
         if f.contains(PTF::USER_ACCESSIBLE) {
             ret |= Self::USER;
         }
-        if f.contains(PTF::NO_CACHE | PTF::WRITE_THROUGH) {
-            ret |= Self::CACHE_1;
-        }
-        ret
+        let cache_policy = match (f.contains(PTF::NO_CACHE), f.contains(PTF::WRITE_THROUGH)) {
+            (false, false) => CachePolicy::Cached,
+            (false, true) => CachePolicy::WriteCombining,
+            (true, false) => CachePolicy::Uncached,
+            (true, true) => CachePolicy::UncachedDevice,
+        };
+        ret | Self::from_bits_truncate(cache_policy as usize)
     }
 }
 
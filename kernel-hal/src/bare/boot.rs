@@ -21,16 +21,28 @@ hal_fn_impl! {
 
         fn primary_init() {
             info!("Primary CPU {} init...", crate::cpu::cpu_id());
-            unsafe { trapframe::init() };
+            trap_init();
             super::arch::primary_init();
+            // seed the realtime clock from the RTC probed
+            crate::timer::boot_realtime();
         }
 
         fn secondary_init() {
             // info!("Secondary CPU {} init...", crate::cpu::cpu_id());
             // we can't print anything here, see reason: zcore/main.rs::secondary_main()
-            unsafe { trapframe::init() };
+            trap_init();
             super::arch::secondary_init();
             // now can print
         }
     }
 }
+
+fn trap_init() {
+    cfg_if! {
+        if #[cfg(target_arch = "loongarch64")] {
+            super::arch::trap_init();
+        } else {
+            unsafe { trapframe::init() };
+        }
+    }
+}
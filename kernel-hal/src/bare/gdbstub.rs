@@ -0,0 +1,373 @@
+//! A GDB remote serial protocol stub, for debugging the kernel on real boards.
+//!
+//! The stub talks to GDB over the second UART, the first one is left for the
+//! console. The kernel stops in the stub on breakpoints, single-steps, and
+//! explicit calls of [`breakpoint`]. While stopped, the other CPUs keep running.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt::Write;
+
+use lock::Mutex;
+use trapframe::TrapFrame;
+
+use super::arch::gdb;
+use crate::drivers::{
+    self,
+    scheme::{Scheme, UartScheme},
+};
+use crate::utils::init_once::InitOnce;
+use crate::vm::{GenericPageTable, PageTable};
+
+/// How to execute a single instruction, decided by the architecture.
+pub(crate) enum Step {
+    /// A debug exception is raised after one instruction.
+    Hardware,
+    /// Temporary breakpoints are placed on the possible next instructions.
+    Software([Option<usize>; 2]),
+}
+
+/// An instruction replaced by a breakpoint.
+struct Breakpoint {
+    addr: usize,
+    kind: usize,
+    origin: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Breakpoints set by GDB.
+    breakpoints: Vec<Breakpoint>,
+    /// Temporary breakpoints for a software single-step.
+    step_breakpoints: Vec<Breakpoint>,
+    /// Whether the kernel is executing a single instruction.
+    stepping: bool,
+    /// Whether to continue after the single instruction, to step over a breakpoint.
+    continue_after_step: bool,
+}
+
+static UART: InitOnce<Arc<dyn UartScheme>> = InitOnce::new();
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+/// Binds the stub to the second UART, returns `false` if there is not.
+pub fn init() -> bool {
+    match drivers::all_uart().try_get(1) {
+        Some(uart) => {
+            info!("GDB stub listening on {}", uart.name());
+            UART.init_once_by(uart);
+            true
+        }
+        None => {
+            warn!("GDB stub disabled: no UART for the debugger");
+            false
+        }
+    }
+}
+
+/// Stops the kernel and waits for commands from GDB.
+#[inline(always)]
+pub fn breakpoint() {
+    gdb::breakpoint();
+}
+
+/// Handles a debug exception, returns `false` if it should not be handled by the stub.
+pub(crate) fn handle_trap(tf: &mut TrapFrame) -> bool {
+    let uart = match UART.try_get() {
+        Some(uart) => uart.clone(),
+        None => return false,
+    };
+    let mut state = STATE.lock();
+    // breakpoints are only in memory while the kernel is running
+    state.remove_all();
+    if state.stepping {
+        state.stepping = false;
+        gdb::step_done(tf);
+        if state.continue_after_step {
+            state.continue_after_step = false;
+            state.insert_all();
+            return true;
+        }
+    } else if !state.breakpoints.iter().any(|bp| bp.addr == gdb::pc(tf)) {
+        // a compiled-in breakpoint, which is not restored when resuming
+        gdb::skip_break(tf);
+    }
+    Session {
+        uart: &*uart,
+        state: &mut state,
+        tf,
+    }
+    .run();
+    true
+}
+
+impl State {
+    fn insert_all(&mut self) {
+        for bp in self.breakpoints.iter_mut() {
+            bp.origin = insert(bp.addr, bp.kind).unwrap_or_default();
+        }
+    }
+
+    fn remove_all(&mut self) {
+        let all = self.breakpoints.iter_mut();
+        for bp in all.chain(self.step_breakpoints.iter_mut()) {
+            write_memory(bp.addr, &core::mem::take(&mut bp.origin));
+        }
+        self.step_breakpoints.clear();
+    }
+}
+
+/// Replaces the instruction at `addr` with a breakpoint, returns the original bytes.
+fn insert(addr: usize, kind: usize) -> Option<Vec<u8>> {
+    let insn = gdb::break_insn(kind)?;
+    let mut origin = alloc::vec![0; insn.len()];
+    if read_memory(addr, &mut origin) && write_memory(addr, insn) {
+        Some(origin)
+    } else {
+        None
+    }
+}
+
+/// Accesses the kernel memory through the linear mapping of the physical
+/// memory, so that read-only pages such as the kernel text can be written.
+fn access_memory(mut vaddr: usize, len: usize, mut f: impl FnMut(usize, usize, usize)) -> bool {
+    let pt = PageTable::from_current();
+    let mut done = 0;
+    while done < len {
+        let (paddr, _, size) = match pt.query(vaddr) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let chunk = (size as usize - (vaddr & (size as usize - 1))).min(len - done);
+        f(paddr, done, chunk);
+        vaddr += chunk;
+        done += chunk;
+    }
+    true
+}
+
+fn read_memory(vaddr: usize, buf: &mut [u8]) -> bool {
+    access_memory(vaddr, buf.len(), |paddr, off, len| {
+        crate::mem::pmem_read(paddr, &mut buf[off..off + len])
+    })
+}
+
+fn write_memory(vaddr: usize, buf: &[u8]) -> bool {
+    let ok = access_memory(vaddr, buf.len(), |paddr, off, len| {
+        crate::mem::pmem_write(paddr, &buf[off..off + len])
+    });
+    gdb::flush_icache(vaddr);
+    ok
+}
+
+/// A connection to GDB while the kernel is stopped.
+struct Session<'a> {
+    uart: &'a dyn UartScheme,
+    state: &'a mut State,
+    tf: &'a mut TrapFrame,
+}
+
+impl Session<'_> {
+    fn run(&mut self) {
+        self.send("S05");
+        loop {
+            let packet = self.recv();
+            if self.handle(&packet) {
+                break;
+            }
+        }
+    }
+
+    /// Handles a packet, returns `true` if the kernel should resume.
+    fn handle(&mut self, packet: &[u8]) -> bool {
+        let (cmd, args) = match packet.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => {
+                self.send("");
+                return false;
+            }
+        };
+        match cmd {
+            b'?' => self.send("S05"),
+            b'g' => {
+                let mut regs = Vec::new();
+                gdb::read_registers(self.tf, &mut regs);
+                self.send_hex(&regs);
+            }
+            b'G' => match decode_hex(args) {
+                Some(regs) if gdb::write_registers(self.tf, &regs) => self.send("OK"),
+                _ => self.send("E01"),
+            },
+            b'm' => match parse_pair(args, b',') {
+                Some((addr, len)) => {
+                    let mut buf = alloc::vec![0; len];
+                    if read_memory(addr, &mut buf) {
+                        self.send_hex(&buf);
+                    } else {
+                        self.send("E14");
+                    }
+                }
+                None => self.send("E01"),
+            },
+            b'M' => {
+                let mut parts = args.splitn(2, |&b| b == b':');
+                let range = parts.next().and_then(|r| parse_pair(r, b','));
+                let data = parts.next().and_then(decode_hex);
+                match (range, data) {
+                    (Some((addr, len)), Some(data)) if data.len() == len => {
+                        if write_memory(addr, &data) {
+                            self.send("OK");
+                        } else {
+                            self.send("E14");
+                        }
+                    }
+                    _ => self.send("E01"),
+                }
+            }
+            b'Z' | b'z' => match parse_breakpoint(args) {
+                Some((addr, kind)) if gdb::break_insn(kind).is_some() => {
+                    self.state.breakpoints.retain(|bp| bp.addr != addr);
+                    if cmd == b'Z' {
+                        self.state.breakpoints.push(Breakpoint {
+                            addr,
+                            kind,
+                            origin: Vec::new(),
+                        });
+                    }
+                    self.send("OK");
+                }
+                // only software breakpoints are supported
+                _ => self.send(""),
+            },
+            b'c' | b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    gdb::set_pc(self.tf, addr);
+                }
+                self.resume(cmd == b's');
+                return true;
+            }
+            b'D' | b'k' => {
+                self.state.breakpoints.clear();
+                if cmd == b'D' {
+                    self.send("OK");
+                }
+                return true;
+            }
+            b'H' => self.send("OK"),
+            b'q' if args.starts_with(b"Supported") => self.send("PacketSize=1000"),
+            b'q' if args == b"Attached" => self.send("1"),
+            b'q' if args == b"C" => self.send("QC1"),
+            _ => self.send(""),
+        }
+        false
+    }
+
+    fn resume(&mut self, step: bool) {
+        let pc = gdb::pc(self.tf);
+        let on_breakpoint = self.state.breakpoints.iter().any(|bp| bp.addr == pc);
+        if !step && !on_breakpoint {
+            self.state.insert_all();
+            return;
+        }
+        // step over the breakpoint at `pc` before inserting it back
+        self.state.stepping = true;
+        self.state.continue_after_step = !step;
+        if let Step::Software(targets) = gdb::step(self.tf) {
+            for addr in targets.into_iter().flatten() {
+                // the shortest breakpoint fits any instruction
+                let kind = gdb::break_insn(2).map_or(4, |_| 2);
+                if let Some(origin) = insert(addr, kind) {
+                    self.state
+                        .step_breakpoints
+                        .push(Breakpoint { addr, kind, origin });
+                }
+            }
+        }
+    }
+
+    fn recv_byte(&self) -> u8 {
+        loop {
+            if let Ok(Some(b)) = self.uart.try_recv() {
+                return b;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Receives a packet `$<data>#<checksum>` and acknowledges it.
+    fn recv(&self) -> Vec<u8> {
+        loop {
+            while self.recv_byte() != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.recv_byte() {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let checksum = [self.recv_byte(), self.recv_byte()];
+            let expected = decode_hex(&checksum).and_then(|c| c.first().copied());
+            if expected == Some(checksum_of(&data)) {
+                self.uart.send(b'+').ok();
+                return data;
+            }
+            self.uart.send(b'-').ok();
+        }
+    }
+
+    /// Sends a packet until GDB acknowledges it.
+    fn send(&self, data: &str) {
+        let mut packet = alloc::string::String::with_capacity(data.len() + 4);
+        write!(packet, "${}#{:02x}", data, checksum_of(data.as_bytes())).unwrap();
+        loop {
+            self.uart.write_str(&packet).ok();
+            match self.recv_byte() {
+                b'+' => return,
+                b'-' => continue,
+                // GDB may be started with `set remote noack-packets`
+                _ => return,
+            }
+        }
+    }
+
+    fn send_hex(&self, data: &[u8]) {
+        let mut hex = alloc::string::String::with_capacity(data.len() * 2);
+        for b in data {
+            write!(hex, "{b:02x}").unwrap();
+        }
+        self.send(&hex);
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks_exact(2)
+        .map(|pair| {
+            let s = core::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(s, 16).ok()
+        })
+        .collect()
+}
+
+fn parse_hex(hex: &[u8]) -> Option<usize> {
+    let s = core::str::from_utf8(hex).ok()?;
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Parses `<a><sep><b>` in hex.
+fn parse_pair(args: &[u8], sep: u8) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, |&b| b == sep);
+    Some((parse_hex(parts.next()?)?, parse_hex(parts.next()?)?))
+}
+
+/// Parses `0,<addr>,<kind>` of `Z0` and `z0`, other types are not supported.
+fn parse_breakpoint(args: &[u8]) -> Option<(usize, usize)> {
+    parse_pair(args.strip_prefix(b"0,")?, b',')
+}
@@ -11,14 +11,22 @@ cfg_if! {
         #[path = "arch/aarch64/mod.rs"]
         pub mod arch;
         pub use self::arch::timer_interrupt_vector;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        #[path = "arch/loongarch64/mod.rs"]
+        pub mod arch;
+        pub use self::arch::timer_interrupt_vector;
     }
 }
 
 pub mod boot;
+#[cfg(feature = "gdbstub")]
+pub mod gdbstub;
 pub mod mem;
 pub mod net;
+mod sched;
 pub mod thread;
 pub mod timer;
+mod tlb;
 
 pub use self::arch::{config, cpu, interrupt, vm};
 pub use super::hal_fn::{rand, vdso};
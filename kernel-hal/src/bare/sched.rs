@@ -0,0 +1,268 @@
+//! A priority-aware, time-sliced scheduler of kernel tasks, with per-CPU run
+//! queues.
+//!
+//! Tasks are futures, run until they return `Pending`. The one of the highest
+//! priority runs first, and those of the same priority run in turn. A new task
+//! is put into the shortest run queue, and a task woken is put into the run
+//! queue of the CPU it last ran on, which is interrupted if it is another CPU
+//! to run it soon, or of another CPU if it may not run on that one. Idle CPUs
+//! steal tasks from the others.
+//!
+//! Tasks are not preempted in the kernel. Timer ticks are accounted to the
+//! task running, which should yield once its time slice is used up, checked
+//! by [`need_resched`] when it comes back from the user mode.
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, task::Wake, vec::Vec};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Waker};
+
+use lock::Mutex;
+
+use crate::{config::MAX_CORE_NUM, utils::PerCpuCell};
+
+/// Number of priorities of tasks, from 0 the lowest.
+pub const NUM_PRIORITIES: usize = 32;
+
+/// The default priority of tasks.
+pub const DEFAULT_PRIORITY: usize = 16;
+
+/// Number of timer ticks a task runs before it should yield.
+const TIME_SLICE_TICKS: usize = 1;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+struct Task {
+    /// The future, `None` after it is finished.
+    future: Mutex<Option<BoxFuture>>,
+    /// The priority of the task.
+    priority: AtomicUsize,
+    /// The CPU whose run queue the task is put into when woken.
+    cpu: AtomicUsize,
+    /// The CPUs the task may run on, one bit for each, or 0 for any.
+    affinity: AtomicUsize,
+    /// Whether the task is in a run queue.
+    queued: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            let mut cpu = self.cpu.load(Ordering::Relaxed);
+            if !self.may_run_on(cpu) {
+                cpu = least_loaded_cpu(self.affinity.load(Ordering::Relaxed));
+                self.cpu.store(cpu, Ordering::Relaxed);
+            }
+            RUN_QUEUES[cpu].lock().push(self.clone());
+            if cpu != cpu_id() {
+                crate::interrupt::send_ipi(cpu as u8);
+            }
+        }
+    }
+}
+
+impl Task {
+    fn may_run_on(&self, cpu: usize) -> bool {
+        let affinity = self.affinity.load(Ordering::Relaxed);
+        affinity == 0 || affinity & (1 << cpu) != 0
+    }
+}
+
+/// The run queue of a CPU, one FIFO queue for each priority.
+#[derive(Default)]
+struct RunQueue {
+    queues: [VecDeque<Arc<Task>>; NUM_PRIORITIES],
+    /// A bitmap of the priorities with tasks.
+    ready: u32,
+    /// Number of tasks in the queue.
+    len: usize,
+}
+
+impl RunQueue {
+    fn push(&mut self, task: Arc<Task>) {
+        let priority = task.priority.load(Ordering::Relaxed);
+        self.queues[priority].push_back(task);
+        self.ready |= 1 << priority;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Arc<Task>> {
+        if self.ready == 0 {
+            return None;
+        }
+        let priority = 31 - self.ready.leading_zeros() as usize;
+        let task = self.queues[priority].pop_front();
+        if self.queues[priority].is_empty() {
+            self.ready &= !(1 << priority);
+        }
+        self.len -= 1;
+        task
+    }
+
+    /// Take the task of the highest priority which may run on `cpu`.
+    fn steal(&mut self, cpu: usize) -> Option<Arc<Task>> {
+        let mut ready = self.ready;
+        while ready != 0 {
+            let priority = 31 - ready.leading_zeros() as usize;
+            ready &= !(1 << priority);
+            let queue = &mut self.queues[priority];
+            if let Some(i) = queue.iter().position(|task| task.may_run_on(cpu)) {
+                let task = queue.remove(i);
+                if queue.is_empty() {
+                    self.ready &= !(1 << priority);
+                }
+                self.len -= 1;
+                return task;
+            }
+        }
+        None
+    }
+}
+
+lazy_static! {
+    static ref RUN_QUEUES: Vec<Mutex<RunQueue>> = (0..MAX_CORE_NUM)
+        .map(|_| Mutex::new(RunQueue::default()))
+        .collect();
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TASK: PerCpuCell<Option<Arc<Task>>> = PerCpuCell::new(None);
+
+/// The task running on each CPU.
+static CURRENT_TASK: [PerCpuCell<Option<Arc<Task>>>; MAX_CORE_NUM] = [NO_TASK; MAX_CORE_NUM];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TICKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of timer ticks the running task has run on each CPU.
+static TICKS: [AtomicUsize; MAX_CORE_NUM] = [NO_TICKS; MAX_CORE_NUM];
+
+/// Number of tasks not finished.
+static ALIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// The CPUs running tasks, one bit for each.
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+fn cpu_id() -> usize {
+    super::cpu::cpu_id() as usize
+}
+
+/// Get the CPUs running tasks, one bit for each.
+pub(super) fn online_cpus() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// Get the CPU running tasks in `affinity`, or 0 for any, with the shortest
+/// run queue. Any CPU is chosen if no one in `affinity` is running tasks.
+fn least_loaded_cpu(affinity: usize) -> usize {
+    let online = online_cpus();
+    let cpus = match online & affinity {
+        0 => online,
+        cpus => cpus,
+    };
+    (0..MAX_CORE_NUM)
+        .filter(|cpu| cpus & (1 << cpu) != 0)
+        .min_by_key(|&cpu| RUN_QUEUES[cpu].lock().len)
+        .unwrap_or_else(cpu_id)
+}
+
+/// Spawn a new task of the default priority on the CPU least loaded.
+pub(super) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(Box::pin(future))),
+        priority: AtomicUsize::new(DEFAULT_PRIORITY),
+        cpu: AtomicUsize::new(least_loaded_cpu(0)),
+        affinity: AtomicUsize::new(0),
+        queued: AtomicBool::new(false),
+    });
+    ALIVE_TASKS.fetch_add(1, Ordering::Relaxed);
+    task.wake_by_ref();
+}
+
+/// Set the priority of the running task, which takes effect the next time
+/// it is put into a run queue.
+pub(super) fn set_priority(priority: usize) {
+    if let Some(task) = CURRENT_TASK[cpu_id()].get() {
+        task.priority
+            .store(priority.min(NUM_PRIORITIES - 1), Ordering::Relaxed);
+    }
+}
+
+/// Set the CPUs the running task may run on, which takes effect the next time
+/// it is put into a run queue.
+pub(super) fn set_cpu_affinity(mask: usize) {
+    if let Some(task) = CURRENT_TASK[cpu_id()].get() {
+        task.affinity.store(mask, Ordering::Relaxed);
+    }
+}
+
+/// Account a timer tick to the task running on the current CPU.
+pub(super) fn handle_timeout() {
+    TICKS[cpu_id()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Whether the running task has used up its time slice, and should yield.
+pub(super) fn need_resched() -> bool {
+    TICKS[cpu_id()].load(Ordering::Relaxed) >= TIME_SLICE_TICKS
+}
+
+/// Take a task from the run queue of `cpu`, or steal one from other CPUs.
+fn pick_next(cpu: usize) -> Option<Arc<Task>> {
+    if let Some(task) = RUN_QUEUES[cpu].lock().pop() {
+        return Some(task);
+    }
+    (1..MAX_CORE_NUM)
+        .map(|i| (cpu + i) % MAX_CORE_NUM)
+        .find_map(|other| RUN_QUEUES[other].lock().steal(cpu))
+        .map(|task| {
+            task.cpu.store(cpu, Ordering::Relaxed);
+            task
+        })
+}
+
+/// Poll `task` once on `cpu`.
+fn run_task(cpu: usize, task: Arc<Task>) {
+    // wakes from now on put the task into a run queue again
+    task.queued.store(false, Ordering::Release);
+    let mut future = match task.future.try_lock() {
+        Some(future) => future,
+        // still being polled on another CPU, run it later
+        None => {
+            task.wake_by_ref();
+            return;
+        }
+    };
+    let pinned = match future.as_mut() {
+        Some(pinned) => pinned,
+        None => return,
+    };
+    *CURRENT_TASK[cpu].get_mut() = Some(task.clone());
+    TICKS[cpu].store(0, Ordering::Relaxed);
+    let waker = Waker::from(task.clone());
+    if pinned
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+        .is_ready()
+    {
+        *future = None;
+        ALIVE_TASKS.fetch_sub(1, Ordering::Relaxed);
+    }
+    *CURRENT_TASK[cpu].get_mut() = None;
+}
+
+/// Run tasks on the current CPU until no one is ready.
+///
+/// Returns whether there are tasks not finished.
+pub fn run_until_idle() -> bool {
+    let cpu = cpu_id();
+    ONLINE_CPUS.fetch_or(1 << cpu, Ordering::AcqRel);
+    while let Some(task) = pick_next(cpu) {
+        run_task(cpu, task);
+    }
+    ALIVE_TASKS.load(Ordering::Relaxed) != 0
+}
@@ -5,6 +5,8 @@ use core::{any::Any, future::Future};
 
 use crate::{config::MAX_CORE_NUM, utils::PerCpuCell};
 
+pub use super::sched::run_until_idle;
+
 #[allow(clippy::declare_interior_mutable_const)]
 const DEFAULT_THREAD: PerCpuCell<Option<Arc<dyn Any + Send + Sync>>> = PerCpuCell::new(None);
 
@@ -14,7 +16,19 @@ static CURRENT_THREAD: [PerCpuCell<Option<Arc<dyn Any + Send + Sync>>>; MAX_CORE
 hal_fn_impl! {
     impl mod crate::hal_fn::thread {
         fn spawn(future: impl Future<Output = ()> + Send + 'static) {
-            executor::spawn(future);
+            super::sched::spawn(future);
+        }
+
+        fn set_priority(priority: usize) {
+            super::sched::set_priority(priority);
+        }
+
+        fn set_cpu_affinity(mask: usize) {
+            super::sched::set_cpu_affinity(mask);
+        }
+
+        fn need_resched() -> bool {
+            super::sched::need_resched()
         }
 
         fn set_current_thread(thread: Option<Arc<dyn Any + Send + Sync>>) {
@@ -1,16 +1,21 @@
 //! Time and clock functions.
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use core::time::Duration;
 
 use lock::Mutex;
-use naive_timer::Timer;
+
+use crate::drivers::scheme::{RtcScheme, Scheme};
+use crate::{config::MAX_CORE_NUM, utils::timer_wheel::TimerWheel};
 
 #[allow(dead_code)]
 pub(super) const TICKS_PER_SEC: u64 = 100;
 
 lazy_static! {
-    static ref NAIVE_TIMER: Mutex<Timer> = Mutex::new(Timer::default());
+    /// The timers of each CPU, expired by its own timer interrupts.
+    static ref TIMER_WHEELS: Vec<Mutex<TimerWheel>> = (0..MAX_CORE_NUM)
+        .map(|_| Mutex::new(TimerWheel::new(Duration::from_nanos(1_000_000_000 / TICKS_PER_SEC))))
+        .collect();
 }
 
 hal_fn_impl! {
@@ -20,16 +25,63 @@ hal_fn_impl! {
         }
 
         fn timer_now() -> Duration {
-            super::arch::timer::timer_now()
+            let nanos = clock_cycles() as u128 * 1_000_000_000 / clock_frequency() as u128;
+            Duration::from_nanos(nanos as u64)
+        }
+
+        fn clock_cycles() -> u64 {
+            super::arch::timer::clock_cycles()
+        }
+
+        fn clock_frequency() -> u64 {
+            super::arch::timer::clock_frequency()
+        }
+
+        fn boot_realtime() -> Duration {
+            static BOOT_REALTIME: spin::Once<Duration> = spin::Once::new();
+            if let Some(realtime) = BOOT_REALTIME.get() {
+                return *realtime;
+            }
+            // not to be fixed before any RTC is probed
+            let rtc = match crate::drivers::all_rtc().first() {
+                Some(rtc) => rtc,
+                None => return Duration::ZERO,
+            };
+            *BOOT_REALTIME.call_once(|| match rtc.read_time() {
+                Ok(realtime) => {
+                    info!("Load real time from {}: {:?}", rtc.name(), realtime);
+                    realtime.saturating_sub(timer_now())
+                }
+                Err(err) => {
+                    warn!("failed to read {}: {:?}", rtc.name(), err);
+                    Duration::ZERO
+                }
+            })
         }
 
         fn timer_set(deadline: Duration, callback: Box<dyn FnOnce(Duration) + Send + Sync>) {
-            debug!("Set timer at: {:?}", deadline);
-            NAIVE_TIMER.lock().add(deadline, callback);
+            timer_set_with_slack(deadline, Duration::ZERO, callback);
+        }
+
+        fn timer_set_with_slack(
+            deadline: Duration,
+            slack: Duration,
+            callback: Box<dyn FnOnce(Duration) + Send + Sync>
+        ) {
+            debug!("Set timer at: {:?}, slack: {:?}", deadline, slack);
+            let cpu = crate::cpu::cpu_id() as usize;
+            TIMER_WHEELS[cpu].lock().add(deadline, slack, callback);
         }
 
         fn timer_tick() {
-            NAIVE_TIMER.lock().expire(timer_now());
+            let now = timer_now();
+            let cpu = crate::cpu::cpu_id() as usize;
+            // the callbacks may set timers again
+            let expired = TIMER_WHEELS[cpu].lock().expire(now);
+            for callback in expired {
+                callback(now);
+            }
+            super::sched::handle_timeout();
         }
     }
 }
@@ -0,0 +1,72 @@
+//! TLB shootdown on multiple CPUs by inter-processor interrupts.
+//!
+//! The CPU changing a page table posts a request into the mailbox of each
+//! other CPU running tasks, interrupts it, and waits until all of them have
+//! invalidated their entries. A CPU only invalidates the entries if it is
+//! running the address space of the request, as switching the page table
+//! invalidates the others.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lock::Mutex;
+
+use crate::{config::MAX_CORE_NUM, PhysAddr, VirtAddr};
+
+struct Request {
+    /// The page table root of the address space.
+    vmtoken: PhysAddr,
+    /// The pages to invalidate, or `None` for the entire address space.
+    vaddrs: Option<Vec<VirtAddr>>,
+    /// Number of CPUs not done yet.
+    pending: Arc<AtomicUsize>,
+}
+
+lazy_static! {
+    static ref MAILBOXES: Vec<Mutex<Vec<Request>>> =
+        (0..MAX_CORE_NUM).map(|_| Mutex::new(Vec::new())).collect();
+}
+
+/// Invalidate the TLB entries of `vaddrs` in the address space of `vmtoken`
+/// on the other CPUs, or all of its entries if `vaddrs` is `None`, and wait
+/// until it is done.
+pub(super) fn shootdown(vmtoken: PhysAddr, vaddrs: Option<&[VirtAddr]>) {
+    let cpu = crate::cpu::cpu_id() as usize;
+    let targets = super::sched::online_cpus() & !(1 << cpu);
+    if targets == 0 {
+        return;
+    }
+    let pending = Arc::new(AtomicUsize::new(targets.count_ones() as usize));
+    for target in (0..MAX_CORE_NUM).filter(|i| targets & (1 << i) != 0) {
+        MAILBOXES[target].lock().push(Request {
+            vmtoken,
+            vaddrs: vaddrs.map(|vaddrs| vaddrs.to_vec()),
+            pending: pending.clone(),
+        });
+        crate::interrupt::send_ipi(target as u8);
+    }
+    while pending.load(Ordering::Acquire) != 0 {
+        // serve the requests to this CPU, in case the others are waiting too
+        handle_ipi();
+        core::hint::spin_loop();
+    }
+}
+
+/// Serve the requests of TLB shootdown to the current CPU, called on
+/// inter-processor interrupts.
+pub(super) fn handle_ipi() {
+    let cpu = crate::cpu::cpu_id() as usize;
+    let requests = core::mem::take(&mut *MAILBOXES[cpu].lock());
+    let current = crate::vm::current_vmtoken();
+    for req in requests {
+        if req.vmtoken == current {
+            match req.vaddrs {
+                Some(vaddrs) => vaddrs
+                    .into_iter()
+                    .for_each(|vaddr| crate::vm::flush_tlb(Some(vaddr))),
+                None => crate::vm::flush_tlb(None),
+            }
+        }
+        req.pending.fetch_sub(1, Ordering::Release);
+    }
+}
@@ -3,6 +3,7 @@
 use crate::drivers;
 use core::fmt::{Arguments, Result, Write};
 use lock::Mutex;
+use zcore_drivers::utils::EventHandler;
 
 struct SerialWriter;
 
@@ -83,11 +84,42 @@ pub fn console_write_fmt(fmt: Arguments) {
     graphic_console_write_fmt(fmt);
 }
 
-/// Read buffer data from console (serial).
+/// Read buffer data from console (serial), wait until some data is available.
 pub async fn console_read(buf: &mut [u8]) -> usize {
     super::future::SerialReadFuture::new(buf).await
 }
 
+/// Read the data received by the console (serial) without blocking.
+///
+/// The serial driver buffers the received data in its interrupt handler,
+/// returns 0 if the buffer is empty.
+pub fn console_try_read(buf: &mut [u8]) -> usize {
+    let uart = match drivers::all_uart().first() {
+        Some(uart) => uart,
+        None => return 0,
+    };
+    let mut len = 0;
+    while len < buf.len() {
+        match uart.try_recv() {
+            Ok(Some(c)) => {
+                buf[len] = c;
+                len += 1;
+            }
+            _ => break,
+        }
+    }
+    len
+}
+
+/// Register a handler to be called when the console (serial) receives data.
+///
+/// If `once` is `true`, the handler is removed after being called.
+pub fn console_subscribe_input(handler: EventHandler, once: bool) {
+    if let Some(uart) = drivers::all_uart().first() {
+        uart.subscribe(handler, once);
+    }
+}
+
 /// The POSIX `winsize` structure.
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
@@ -2,9 +2,16 @@
 
 use crate::{MMUFlags, VirtAddr};
 use core::fmt;
-use trapframe::UserContext as UserContextInner;
 
-pub use trapframe::GeneralRegs;
+cfg_if! {
+    if #[cfg(target_arch = "loongarch64")] {
+        use crate::imp::arch::context::UserContext as UserContextInner;
+        pub use crate::imp::arch::context::GeneralRegs;
+    } else {
+        use trapframe::UserContext as UserContextInner;
+        pub use trapframe::GeneralRegs;
+    }
+}
 
 cfg_if! {
     if #[cfg(feature = "libos")] {
@@ -24,6 +31,7 @@ pub enum UserContextField {
     StackPointer,
     ThreadPointer,
     ReturnValue,
+    FramePointer,
 }
 
 /// Reason of the trap.
@@ -159,18 +167,53 @@ impl TrapReason {
             _ => Self::GernelFault(esr as usize),
         }
     }
+
+    /// Get [`TrapReason`] from `ESTAT` and `BADV` for LoongArch.
+    #[cfg(target_arch = "loongarch64")]
+    pub fn from(estat: usize, badv: usize) -> Self {
+        let ecode = (estat >> 16) & 0x3f;
+        match ecode {
+            0 => {
+                // the highest priority interrupt pending and enabled in `ECFG`
+                let ecfg: usize;
+                unsafe { core::arch::asm!("csrrd {}, 0x4", out(reg) ecfg) };
+                let pending = estat & ecfg & 0x1fff;
+                Self::Interrupt(match pending {
+                    0 => usize::MAX,
+                    _ => (usize::BITS - 1 - pending.leading_zeros()) as usize,
+                })
+            }
+            // PIL, PNR
+            0x1 | 0x5 => Self::PageFault(badv, MMUFlags::READ),
+            // PIS, PME
+            0x2 | 0x4 => Self::PageFault(badv, MMUFlags::WRITE),
+            // PIF, PNX
+            0x3 | 0x6 => Self::PageFault(badv, MMUFlags::EXECUTE),
+            // ALE
+            0x9 => Self::UnalignedAccess,
+            // SYS
+            0xb => Self::Syscall,
+            // BRK
+            0xc => Self::SoftwareBreakpoint,
+            // INE, IPE
+            0xd | 0xe => Self::UndefinedInstruction,
+            _ => Self::GernelFault(estat),
+        }
+    }
 }
 
 /// User context saved on trap.
-#[repr(transparent)]
+///
+/// Besides the context saved by `trapframe`, it holds the registers saved and
+/// restored by HAL, e.g. the vector registers on x86.
 #[derive(Clone, Copy)]
-pub struct UserContext(UserContextInner);
+pub struct UserContext(UserContextInner, ExtraRegs);
 
 impl UserContext {
     /// Create an empty user context.
     pub fn new() -> Self {
         let context = UserContextInner::default();
-        Self(context)
+        Self(context, ExtraRegs::default())
     }
 
     /// Initialize the context for entry into userspace.
@@ -204,6 +247,12 @@ impl UserContext {
                 self.0.general.a2 = args[2];
                 // SUM = 1, FS = 0b11, SPIE = 1
                 self.0.sstatus = 1 << 18 | 0b11 << 13 | 1 << 5;
+            } else if #[cfg(target_arch = "loongarch64")] {
+                self.0.era = pc;
+                self.0.general.sp = sp;
+                self.0.general.a0 = args[0];
+                self.0.general.a1 = args[1];
+                self.0.general.a2 = args[2];
             }
         }
     }
@@ -217,6 +266,8 @@ impl UserContext {
                 error!("Please set return addr via stack!");
             } else if #[cfg(target_arch = "aarch64")] {
                 self.0.general.x30 = _ra;
+            } else if #[cfg(target_arch = "loongarch64")] {
+                self.0.general.ra = _ra;
             } else {
                 unimplemented!("Unsupported arch!");
             }
@@ -229,7 +280,11 @@ impl UserContext {
             if #[cfg(feature = "libos")] {
                 self.0.run_fncall()
             } else {
-                self.0.run()
+                #[cfg(target_arch = "x86_64")]
+                self.1.restore();
+                self.0.run();
+                #[cfg(target_arch = "x86_64")]
+                self.1.save();
             }
         }
     }
@@ -250,12 +305,15 @@ impl UserContext {
                 TrapReason::from(self.0.trap_num)
             } else if #[cfg(target_arch = "riscv64")] {
                 TrapReason::from(riscv::register::scause::read())
+            } else if #[cfg(target_arch = "loongarch64")] {
+                TrapReason::from(self.0.estat, self.0.badv)
             } else {
                 unimplemented!()
             }
         }
     }
-    /// Returns a `usize` representing the trap reason. (i.e., IDT vector for x86, `scause` for RISC-V)
+    /// Returns a `usize` representing the trap reason. (i.e., IDT vector for x86, `scause` for RISC-V,
+    /// `ESTAT` for LoongArch)
     pub fn raw_trap_reason(&self) -> usize {
         cfg_if! {
             if #[cfg(target_arch = "x86_64")] {
@@ -264,6 +322,8 @@ impl UserContext {
                 unimplemented!() // ESR_EL1
             } else if #[cfg(target_arch = "riscv64")] {
                 riscv::register::scause::read().bits()
+            } else if #[cfg(target_arch = "loongarch64")] {
+                self.0.estat
             } else {
                 unimplemented!()
             }
@@ -288,6 +348,7 @@ impl UserContext {
                     UserContextField::StackPointer => &mut self.0.general.rsp,
                     UserContextField::ThreadPointer => &mut self.0.general.fsbase,
                     UserContextField::ReturnValue => &mut self.0.general.rax,
+                    UserContextField::FramePointer => &mut self.0.general.rbp,
                 }
             } else if #[cfg(target_arch = "aarch64")] {
                 match which {
@@ -295,6 +356,7 @@ impl UserContext {
                     UserContextField::StackPointer => &mut self.0.sp,
                     UserContextField::ThreadPointer => &mut self.0.tpidr,
                     UserContextField::ReturnValue => &mut self.0.general.x0,
+                    UserContextField::FramePointer => &mut self.0.general.x29,
                 }
             } else if #[cfg(target_arch = "riscv64")] {
                 match which {
@@ -302,6 +364,15 @@ impl UserContext {
                     UserContextField::StackPointer => &mut self.0.general.sp,
                     UserContextField::ThreadPointer => &mut self.0.general.tp,
                     UserContextField::ReturnValue => &mut self.0.general.a0,
+                    UserContextField::FramePointer => &mut self.0.general.s0,
+                }
+            } else if #[cfg(target_arch = "loongarch64")] {
+                match which {
+                    UserContextField::InstrPointer => &mut self.0.era,
+                    UserContextField::StackPointer => &mut self.0.general.sp,
+                    UserContextField::ThreadPointer => &mut self.0.general.tp,
+                    UserContextField::ReturnValue => &mut self.0.general.a0,
+                    UserContextField::FramePointer => &mut self.0.general.fp,
                 }
             } else {
                 unimplemented!()
@@ -319,11 +390,61 @@ impl UserContext {
         *self.field_ref(which) = value;
     }
 
+    /// Returns the reference of vector registers, including the x87 FPU state.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn vector(&self) -> &VectorRegs {
+        &self.1.vector
+    }
+
+    /// Returns the mutable reference of vector registers.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn vector_mut(&mut self) -> &mut VectorRegs {
+        &mut self.1.vector
+    }
+
+    /// Returns the reference of debug registers.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn debug(&self) -> &DebugRegs {
+        &self.1.debug
+    }
+
+    /// Returns the mutable reference of debug registers.
+    ///
+    /// They take effect when the context enters user mode again.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn debug_mut(&mut self) -> &mut DebugRegs {
+        &mut self.1.debug
+    }
+
+    /// Whether a debug exception is raised after every instruction in user mode.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn single_step(&self) -> bool {
+        self.0.general.rflags & RFLAGS_TF != 0
+    }
+
+    /// Set whether a debug exception is raised after every instruction in user mode.
+    #[cfg(any(target_arch = "x86_64", doc))]
+    #[doc(cfg(target_arch = "x86_64"))]
+    pub fn set_single_step(&mut self, enable: bool) {
+        if enable {
+            self.0.general.rflags |= RFLAGS_TF;
+        } else {
+            self.0.general.rflags &= !RFLAGS_TF;
+        }
+    }
+
     /// Advance the instruction pointer in trap handler on some architecture.
     pub fn advance_pc(&mut self, reason: TrapReason) {
         cfg_if! {
             if #[cfg(target_arch = "riscv64")] {
                 if let TrapReason::Syscall = reason { self.0.sepc += 4 }
+            } else if #[cfg(target_arch = "loongarch64")] {
+                if let TrapReason::Syscall = reason { self.0.era += 4 }
             } else {
                 let _ = reason;
             }
@@ -345,6 +466,77 @@ impl fmt::Debug for UserContext {
 
 cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
+        /// The trap flag in RFLAGS, for single-stepping.
+        const RFLAGS_TF: usize = 1 << 8;
+
+        /// Registers not saved by `trapframe`.
+        #[derive(Debug, Default, Clone, Copy)]
+        struct ExtraRegs {
+            vector: VectorRegs,
+            debug: DebugRegs,
+        }
+
+        impl ExtraRegs {
+            /// Restore the registers before entering user mode.
+            #[cfg(not(feature = "libos"))]
+            fn restore(&self) {
+                unsafe {
+                    core::arch::x86_64::_fxrstor64(&self.vector as *const _ as *const u8);
+                    if self.debug.enabled() {
+                        core::arch::asm!(
+                            "mov dr0, {}",
+                            "mov dr1, {}",
+                            "mov dr2, {}",
+                            "mov dr3, {}",
+                            "mov dr7, {}",
+                            in(reg) self.debug.dr[0],
+                            in(reg) self.debug.dr[1],
+                            in(reg) self.debug.dr[2],
+                            in(reg) self.debug.dr[3],
+                            in(reg) self.debug.dr7,
+                        );
+                    }
+                }
+            }
+
+            /// Save the registers after back from user mode.
+            #[cfg(not(feature = "libos"))]
+            fn save(&mut self) {
+                unsafe {
+                    core::arch::x86_64::_fxsave64(&mut self.vector as *mut _ as *mut u8);
+                    if self.debug.enabled() {
+                        // disable the breakpoints in kernel mode
+                        core::arch::asm!(
+                            "mov {}, dr6",
+                            "mov dr7, {}",
+                            out(reg) self.debug.dr6,
+                            in(reg) 0u64,
+                        );
+                    }
+                }
+            }
+        }
+
+        /// X86 debug registers.
+        #[repr(C)]
+        #[derive(Debug, Default, Clone, Copy)]
+        pub struct DebugRegs {
+            /// The addresses of the hardware breakpoints.
+            pub dr: [u64; 4],
+            /// The debug status.
+            pub dr6: u64,
+            /// The debug control.
+            pub dr7: u64,
+        }
+
+        impl DebugRegs {
+            /// Whether any hardware breakpoint is enabled.
+            #[cfg(not(feature = "libos"))]
+            fn enabled(&self) -> bool {
+                self.dr7 & 0xff != 0
+            }
+        }
+
         /// X86 vector registers.
         #[repr(C, align(16))]
         #[derive(Debug, Copy, Clone)]
@@ -391,5 +583,9 @@ cfg_if! {
                 write!(f, "{:#016x}_{:016x}", self.0[1], self.0[0])
             }
         }
+    } else {
+        /// Registers not saved by `trapframe`.
+        #[derive(Debug, Default, Clone, Copy)]
+        struct ExtraRegs;
     }
 }
@@ -0,0 +1,223 @@
+//! Crash reporting support: stack unwinding and symbolizer markup.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The most frames a backtrace walks.
+pub const MAX_FRAMES: usize = 64;
+
+/// Read the frame pointer of the caller.
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+        } else if #[cfg(target_arch = "riscv64")] {
+            unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+        } else if #[cfg(target_arch = "loongarch64")] {
+            unsafe { core::arch::asm!("move {}, $fp", out(reg) fp) };
+        } else {
+            fp = 0;
+        }
+    }
+    fp
+}
+
+/// Walk the chain of frame pointers from `fp`, returns the return addresses.
+///
+/// `read` reads a word of the stack, or returns `None` if the address is not
+/// readable. The walk stops at the first frame which looks invalid.
+pub fn walk_frames(mut fp: usize, mut read: impl FnMut(usize) -> Option<usize>) -> Vec<usize> {
+    const WORD: usize = core::mem::size_of::<usize>();
+    let mut frames = Vec::new();
+    while fp != 0 && fp % WORD == 0 && frames.len() < MAX_FRAMES {
+        // the frame pointer is the stack pointer before the call on RISC-V
+        // and LoongArch, otherwise it points to the saved one, which is
+        // followed by the return address
+        #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+        let (prev_fp, ra) = (read(fp.wrapping_sub(2 * WORD)), read(fp.wrapping_sub(WORD)));
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "loongarch64")))]
+        let (prev_fp, ra) = (read(fp), read(fp.wrapping_add(WORD)));
+        let (prev_fp, ra) = match (prev_fp, ra) {
+            (Some(prev_fp), Some(ra)) if ra != 0 => (prev_fp, ra),
+            _ => break,
+        };
+        frames.push(ra);
+        // the stack grows downwards, the frames of callers are above
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+    frames
+}
+
+/// Returns the return addresses of the current kernel stack.
+///
+/// It relies on the kernel built with frame pointers, and is always empty
+/// in LibOS, where the frames of the host may have no frame pointers.
+#[inline(never)]
+pub fn backtrace() -> Vec<usize> {
+    cfg_if! {
+        if #[cfg(feature = "libos")] {
+            Vec::new()
+        } else {
+            // the kernel stacks are in the higher half of the address space
+            walk_frames(frame_pointer(), |addr| {
+                if (addr as isize) < 0 {
+                    Some(unsafe { (addr as *const usize).read() })
+                } else {
+                    None
+                }
+            })
+        }
+    }
+}
+
+/// Returns the build ID in the ELF notes, from the note of type `NT_GNU_BUILD_ID`.
+pub fn parse_build_id(mut notes: &[u8]) -> Option<&[u8]> {
+    const NT_GNU_BUILD_ID: u32 = 3;
+    let align = |size: usize| (size + 3) / 4 * 4;
+    while notes.len() >= 12 {
+        let word = |i: usize| {
+            let bytes = &notes[i * 4..i * 4 + 4];
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        let (name_size, desc_size, type_) = (word(0) as usize, word(1) as usize, word(2));
+        let desc_offset = 12 + align(name_size);
+        let desc = notes.get(desc_offset..desc_offset + desc_size)?;
+        if type_ == NT_GNU_BUILD_ID && &notes[12..12 + name_size] == b"GNU\0" {
+            return Some(desc);
+        }
+        notes = notes.get(desc_offset + align(desc_size)..)?;
+    }
+    None
+}
+
+/// An element of the symbolizer markup, which the host tools, such as
+/// `fx symbolize`, replace with the symbolized text.
+///
+/// See <https://fuchsia.dev/fuchsia-src/reference/kernel/symbolizer_markup>.
+#[derive(Debug)]
+pub enum Markup<'a> {
+    /// Forget the modules and the mappings described before.
+    Reset,
+    /// An ELF module.
+    Module {
+        /// The id referred by [`Markup::Mmap`].
+        id: usize,
+        /// The name of the module.
+        name: &'a str,
+        /// The build ID of the module.
+        build_id: &'a [u8],
+    },
+    /// A segment of a module loaded at `start`.
+    Mmap {
+        /// The start address of the segment.
+        start: usize,
+        /// The size of the segment.
+        size: usize,
+        /// The id of the module.
+        module: usize,
+        /// The permissions, some of `r`, `w` and `x`.
+        flags: &'a str,
+        /// The address of the segment in the module.
+        module_addr: usize,
+    },
+    /// A frame of a backtrace.
+    Backtrace {
+        /// The index of the frame, from 0 the innermost.
+        frame: usize,
+        /// The address in the frame.
+        pc: usize,
+        /// Whether `pc` is a return address, or the exact address of a fault.
+        is_return_address: bool,
+    },
+}
+
+impl fmt::Display for Markup<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Markup::Reset => write!(f, "{{{{{{reset}}}}}}"),
+            Markup::Module { id, name, build_id } => {
+                write!(f, "{{{{{{module:{}:{}:elf:", id, name)?;
+                for b in build_id {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "}}}}}}")
+            }
+            Markup::Mmap {
+                start,
+                size,
+                module,
+                flags,
+                module_addr,
+            } => write!(
+                f,
+                "{{{{{{mmap:{:#x}:{:#x}:load:{}:{}:{:#x}}}}}}}",
+                start, size, module, flags, module_addr
+            ),
+            Markup::Backtrace {
+                frame,
+                pc,
+                is_return_address,
+            } => {
+                let kind = if is_return_address { "ra" } else { "pc" };
+                write!(f, "{{{{{{bt:{}:{:#x}:{}}}}}}}", frame, pc, kind)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, vec};
+
+    #[test]
+    fn markup() {
+        assert_eq!(format!("{}", Markup::Reset), "{{{reset}}}");
+        let module = Markup::Module {
+            id: 0,
+            name: "libc.so",
+            build_id: &[0xab, 0x01],
+        };
+        assert_eq!(format!("{}", module), "{{{module:0:libc.so:elf:ab01}}}");
+        let mmap = Markup::Mmap {
+            start: 0x1000,
+            size: 0x2000,
+            module: 0,
+            flags: "rx",
+            module_addr: 0,
+        };
+        assert_eq!(
+            format!("{}", mmap),
+            "{{{mmap:0x1000:0x2000:load:0:rx:0x0}}}"
+        );
+        let bt = Markup::Backtrace {
+            frame: 1,
+            pc: 0x1234,
+            is_return_address: true,
+        };
+        assert_eq!(format!("{}", bt), "{{{bt:1:0x1234:ra}}}");
+    }
+
+    #[test]
+    fn build_id() {
+        let mut note = vec![4, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0];
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(&[0xab, 0xcd]);
+        assert_eq!(parse_build_id(&note), Some(&[0xab, 0xcd][..]));
+        note[8] = 1;
+        assert_eq!(parse_build_id(&note), None);
+        // skip the other notes
+        let mut notes = vec![4, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0];
+        notes.extend_from_slice(b"GNU\0\0\0\0\0");
+        note[8] = 3;
+        notes.extend_from_slice(&note);
+        assert_eq!(parse_build_id(&notes), Some(&[0xab, 0xcd][..]));
+    }
+}
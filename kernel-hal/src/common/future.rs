@@ -4,7 +4,7 @@ use core::time::Duration;
 use core::{future::Future, pin::Pin};
 use zcore_drivers::scheme::DisplayScheme;
 
-use crate::timer;
+use crate::{console, timer};
 
 #[must_use = "`yield_now()` does nothing unless polled/`await`-ed"]
 #[derive(Default)]
@@ -67,27 +67,18 @@ impl Future for SerialReadFuture<'_> {
     type Output = usize;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let uart = if let Some(uart) = crate::drivers::all_uart().first() {
-            uart
-        } else {
-            return Poll::Pending;
-        };
         let buf = &mut self.get_mut().buf;
-        let mut n = 0;
-        for i in 0..buf.len() {
-            if let Some(c) = uart.try_recv().unwrap_or(None) {
-                buf[i] = c;
-                n += 1;
-            } else {
-                break;
-            }
-        }
+        let n = console::console_try_read(buf);
         if n > 0 {
             return Poll::Ready(n);
         }
         let waker = cx.waker().clone();
-        uart.subscribe(Box::new(move |_| waker.wake_by_ref()), true);
-        Poll::Pending
+        console::console_subscribe_input(Box::new(move |_| waker.wake_by_ref()), true);
+        // the data may arrive before subscribing
+        match console::console_try_read(buf) {
+            0 => Poll::Pending,
+            n => Poll::Ready(n),
+        }
     }
 }
 
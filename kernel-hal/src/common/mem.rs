@@ -1,7 +1,46 @@
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::{PhysAddr, KHANDLER, PAGE_SIZE};
 
+/// Number of physical frames allocated from the kernel, by [`PhysFrame`]s
+/// and DMA buffers.
+static ALLOCATED_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Statistics of the physical frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    /// Number of frames managed by the frame allocator.
+    pub total: usize,
+    /// Number of frames allocated.
+    pub allocated: usize,
+}
+
+impl FrameStats {
+    /// Number of frames free to allocate.
+    pub fn free(&self) -> usize {
+        self.total.saturating_sub(self.allocated)
+    }
+}
+
+/// Returns the statistics of the physical frames.
+pub fn frame_stats() -> FrameStats {
+    FrameStats {
+        total: *TOTAL_FRAMES,
+        allocated: ALLOCATED_FRAMES.load(Ordering::Relaxed),
+    }
+}
+
+/// Record `count` frames allocated from the kernel.
+pub(crate) fn count_frames_alloc(count: usize) {
+    ALLOCATED_FRAMES.fetch_add(count, Ordering::Relaxed);
+}
+
+/// Record `count` frames deallocated to the kernel.
+pub(crate) fn count_frames_dealloc(count: usize) {
+    ALLOCATED_FRAMES.fetch_sub(count, Ordering::Relaxed);
+}
+
 /// A 4K size physical frame.
 #[derive(Debug)]
 pub struct PhysFrame {
@@ -12,9 +51,12 @@ pub struct PhysFrame {
 impl PhysFrame {
     /// Allocate one physical frame.
     pub fn new() -> Option<Self> {
-        KHANDLER.frame_alloc().map(|paddr| Self {
-            paddr,
-            allocated: true,
+        KHANDLER.frame_alloc().map(|paddr| {
+            count_frames_alloc(1);
+            Self {
+                paddr,
+                allocated: true,
+            }
         })
     }
 
@@ -33,6 +75,7 @@ impl PhysFrame {
     /// Allocate contiguous physical frames.
     pub fn new_contiguous(frame_count: usize, align_log2: usize) -> Vec<Self> {
         Self::alloc_contiguous_base(frame_count, align_log2).map_or(Vec::new(), |base| {
+            count_frames_alloc(frame_count);
             (0..frame_count)
                 .map(|i| Self {
                     paddr: base + i * PAGE_SIZE,
@@ -78,12 +121,19 @@ impl PhysFrame {
 impl Drop for PhysFrame {
     fn drop(&mut self) {
         if self.allocated {
-            KHANDLER.frame_dealloc(self.paddr)
+            KHANDLER.frame_dealloc(self.paddr);
+            count_frames_dealloc(1);
         }
     }
 }
 
 lazy_static! {
+    /// Number of frames in the free physical memory regions given to the frame
+    /// allocator at boot.
+    static ref TOTAL_FRAMES: usize = crate::mem::free_pmem_regions()
+        .iter()
+        .map(|r| (r.end - r.start) / PAGE_SIZE)
+        .sum();
     /// The global physical frame contains all zeros.
     pub static ref ZERO_FRAME: PhysFrame = PhysFrame::new_zero().expect("failed to alloc zero frame");
 }
@@ -1,6 +1,7 @@
 pub(super) mod defs;
 pub(super) mod future;
 pub(super) mod mem;
+pub(super) mod rand;
 pub(super) mod thread;
 pub(super) mod vdso;
 pub(super) mod vm;
@@ -8,4 +9,5 @@ pub(super) mod vm;
 pub mod addr;
 pub mod console;
 pub mod context;
+pub mod debug;
 pub mod user;
@@ -0,0 +1,224 @@
+//! The kernel entropy pool.
+//!
+//! The pool is a ChaCha20 key. Entropy is absorbed by mixing it into the key
+//! and rekeying, and random bytes are the keystream under the key, which is
+//! replaced right after each request (fast key erasure). It is seeded from
+//! the jitter of the cycle counter, the `RDRAND` instruction if present, and
+//! reseeded from the hardware RNGs periodically.
+
+use core::time::Duration;
+
+use lock::Mutex;
+
+use crate::drivers::{
+    self,
+    scheme::{RngScheme, Scheme},
+};
+use crate::hal_fn::timer::{clock_cycles, timer_now};
+
+/// How often the hardware RNGs are read again.
+const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The bytes taken from the hardware RNGs on each reseed.
+const RESEED_BYTES: usize = 32;
+
+/// The samples of the cycle counter to seed the pool.
+const JITTER_SAMPLES: usize = 64;
+
+/// The nonces to tell the blocks for rekeying from the output blocks.
+const NONCE_OUTPUT: u64 = 0;
+const NONCE_REKEY: u64 = 1;
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function, with a 64-bit counter and nonce.
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut state = [0u32; 16];
+    // "expand 32-byte k"
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (x, s) in x.iter_mut().zip(state.iter()) {
+        *x = x.wrapping_add(*s);
+    }
+    x
+}
+
+struct EntropyPool {
+    key: [u32; 8],
+    counter: u64,
+    seeded: bool,
+    /// Whether some hardware RNG has been read.
+    hw_seeded: bool,
+    last_reseed: Duration,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            counter: 0,
+            seeded: false,
+            hw_seeded: false,
+            last_reseed: Duration::ZERO,
+        }
+    }
+
+    fn rekey(&mut self) {
+        let block = chacha20_block(&self.key, self.counter, NONCE_REKEY);
+        self.counter = self.counter.wrapping_add(1);
+        self.key.copy_from_slice(&block[..8]);
+    }
+
+    fn mix(&mut self, data: &[u8]) {
+        for chunk in data.chunks(32) {
+            for (i, b) in chunk.iter().enumerate() {
+                self.key[i / 4] ^= (*b as u32) << (8 * (i % 4));
+            }
+            self.rekey();
+        }
+    }
+
+    fn mix_u64(&mut self, value: u64) {
+        self.mix(&value.to_le_bytes());
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(64) {
+            let block = chacha20_block(&self.key, self.counter, NONCE_OUTPUT);
+            self.counter = self.counter.wrapping_add(1);
+            for (i, b) in chunk.iter_mut().enumerate() {
+                *b = (block[i / 4] >> (8 * (i % 4))) as u8;
+            }
+        }
+        // not to reveal the bytes given out if the pool leaks later
+        self.rekey();
+    }
+
+    /// Seed from what the CPU itself provides.
+    fn seed_from_cpu(&mut self) {
+        let mut samples = [0u64; JITTER_SAMPLES];
+        let mut last = clock_cycles();
+        for s in samples.iter_mut() {
+            let now = clock_cycles() ^ timer_now().as_nanos() as u64;
+            *s = now.wrapping_sub(last);
+            last = now;
+        }
+        for s in samples.iter() {
+            self.mix_u64(*s);
+        }
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use core::arch::x86_64::{__cpuid, _rdrand64_step};
+            // CPUID.01H:ECX.RDRAND[bit 30]
+            if __cpuid(1).ecx & (1 << 30) != 0 {
+                for _ in 0..4 {
+                    let mut r = 0;
+                    if _rdrand64_step(&mut r) == 1 {
+                        self.mix_u64(r);
+                    }
+                }
+            }
+        }
+        self.seeded = true;
+    }
+
+    fn need_reseed(&self, now: Duration) -> bool {
+        !self.hw_seeded || now >= self.last_reseed + RESEED_INTERVAL
+    }
+}
+
+static POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool::new());
+
+/// Read the hardware RNGs, outside the lock of the pool.
+fn read_hw_rngs(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    for rng in drivers::all_rng().as_vec().iter() {
+        match rng.read(&mut buf[len..]) {
+            Ok(n) => len += n,
+            Err(err) => warn!("failed to read from {}: {:?}", rng.name(), err),
+        }
+        if len == buf.len() {
+            break;
+        }
+    }
+    len
+}
+
+fn reseed_if_needed() {
+    let now = timer_now();
+    if !POOL.lock().need_reseed(now) {
+        return;
+    }
+    let mut buf = [0; RESEED_BYTES];
+    let len = read_hw_rngs(&mut buf);
+    let mut pool = POOL.lock();
+    pool.last_reseed = now;
+    if len > 0 {
+        pool.mix(&buf[..len]);
+        pool.hw_seeded = true;
+    }
+}
+
+pub(crate) fn fill_random(buf: &mut [u8]) {
+    reseed_if_needed();
+    let mut pool = POOL.lock();
+    if !pool.seeded {
+        pool.seed_from_cpu();
+    }
+    // the time of each request adds a little
+    pool.mix_u64(clock_cycles());
+    pool.fill(buf);
+}
+
+pub(crate) fn add_entropy(buf: &[u8]) {
+    let mut pool = POOL.lock();
+    pool.mix(buf);
+    pool.mix_u64(clock_cycles());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chacha20_rfc7539_block() {
+        // RFC 7539 2.3.2, with the counter and the first nonce word packed
+        let key = [
+            0x0302_0100,
+            0x0706_0504,
+            0x0b0a_0908,
+            0x0f0e_0d0c,
+            0x1312_1110,
+            0x1716_1514,
+            0x1b1a_1918,
+            0x1f1e_1d1c,
+        ];
+        let block = chacha20_block(&key, 0x0900_0000_0000_0001, 0x0000_0000_4a00_0000);
+        assert_eq!(block[0], 0xe4e7_f110);
+        assert_eq!(block[15], 0x4e3c_50a2);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{addr::is_aligned, MMUFlags, PhysAddr, VirtAddr};
+use crate::{addr::is_aligned, MMUFlags, PhysAddr, VirtAddr, PAGE_SIZE};
+use alloc::vec::Vec;
 
 /// Errors may occur during address translation.
 #[derive(Debug)]
@@ -90,6 +91,19 @@ pub trait GenericPageTable: Sync + Send {
     /// Query the physical address which the page of `vaddr` maps to.
     fn query(&self, vaddr: VirtAddr) -> PagingResult<(PhysAddr, MMUFlags, PageSize)>;
 
+    /// Split the huge page of `vaddr` into pages of the next smaller size,
+    /// which map the same frames with the same flags. Returns the new size.
+    fn split(&mut self, vaddr: VirtAddr) -> PagingResult<PageSize>;
+
+    /// Split the huge pages of `vaddr`, if any, until it is mapped by a 4K page,
+    /// so that the page can be changed alone.
+    fn demote(&mut self, vaddr: VirtAddr) -> PagingResult {
+        while self.query(vaddr)?.2.is_huge() {
+            self.split(vaddr)?;
+        }
+        Ok(())
+    }
+
     fn map_cont(
         &mut self,
         start_vaddr: VirtAddr,
@@ -153,6 +167,14 @@ pub trait GenericPageTable: Sync + Send {
         let mut vaddr = start_vaddr;
         let end_vaddr = vaddr + size;
         while vaddr < end_vaddr {
+            // a huge page partially in the range is split first
+            if let Ok((_, _, size)) = self.query(vaddr) {
+                if size.is_huge() && (!size.is_aligned(vaddr) || end_vaddr - vaddr < size as usize)
+                {
+                    self.split(vaddr)?;
+                    continue;
+                }
+            }
             let page_size = match self.unmap(vaddr) {
                 Ok((_, s)) => {
                     assert!(s.is_aligned(vaddr));
@@ -167,3 +189,58 @@ pub trait GenericPageTable: Sync + Send {
         Ok(())
     }
 }
+
+/// The max number of pages in a [`TlbBatch`], beyond which the entire address
+/// space is invalidated.
+const TLB_BATCH_MAX_PAGES: usize = 32;
+
+/// A batch of pages whose TLB entries are to be invalidated on other CPUs,
+/// after the page table is changed.
+pub struct TlbBatch {
+    vmtoken: PhysAddr,
+    /// The pages to invalidate, or `None` for the entire address space.
+    vaddrs: Option<Vec<VirtAddr>>,
+}
+
+impl TlbBatch {
+    /// Create an empty batch in the address space of `vmtoken`.
+    pub fn new(vmtoken: PhysAddr) -> Self {
+        Self {
+            vmtoken,
+            vaddrs: Some(Vec::new()),
+        }
+    }
+
+    /// Add the page at `vaddr` to the batch.
+    pub fn add(&mut self, vaddr: VirtAddr) {
+        if let Some(vaddrs) = &mut self.vaddrs {
+            if vaddrs.len() < TLB_BATCH_MAX_PAGES {
+                vaddrs.push(vaddr);
+            } else {
+                self.vaddrs = None;
+            }
+        }
+    }
+
+    /// Add the pages in `[start_vaddr, start_vaddr + size)` to the batch.
+    pub fn add_range(&mut self, start_vaddr: VirtAddr, size: usize) {
+        if size / PAGE_SIZE > TLB_BATCH_MAX_PAGES {
+            self.vaddrs = None;
+            return;
+        }
+        for vaddr in (start_vaddr..start_vaddr + size).step_by(PAGE_SIZE) {
+            self.add(vaddr);
+        }
+    }
+
+    /// Invalidate the TLB entries of the pages on other CPUs.
+    ///
+    /// It waits for other CPUs, so no lock they may acquire with interrupts
+    /// disabled should be held.
+    pub fn flush(self) {
+        match &self.vaddrs {
+            Some(vaddrs) if vaddrs.is_empty() => {}
+            vaddrs => crate::vm::tlb_shootdown(self.vmtoken, vaddrs.as_deref()),
+        }
+    }
+}
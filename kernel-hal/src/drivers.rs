@@ -6,12 +6,13 @@ use core::convert::From;
 use lock::{RwLock, RwLockReadGuard};
 
 use zcore_drivers::scheme::{
-    BlockScheme, DisplayScheme, InputScheme, IrqScheme, NetScheme, Scheme, UartScheme,
+    BlockScheme, DisplayScheme, InputScheme, IrqScheme, NetScheme, RngScheme, RtcScheme, Scheme,
+    UartScheme,
 };
 use zcore_drivers::{Device, DeviceError};
 
 /// Re-exported modules from crate [`zcore_drivers`].
-pub use zcore_drivers::{prelude, scheme};
+pub use zcore_drivers::{block, prelude, scheme};
 
 /// A wrapper of a device array with the same [`Scheme`].
 pub struct DeviceList<T: Scheme + ?Sized>(RwLock<Vec<Arc<T>>>);
@@ -65,6 +66,8 @@ struct AllDeviceList {
     input: DeviceList<dyn InputScheme>,
     irq: DeviceList<dyn IrqScheme>,
     net: DeviceList<dyn NetScheme>,
+    rng: DeviceList<dyn RngScheme>,
+    rtc: DeviceList<dyn RtcScheme>,
     uart: DeviceList<dyn UartScheme>,
 }
 
@@ -76,6 +79,8 @@ impl AllDeviceList {
             Device::Input(d) => self.input.add(d),
             Device::Irq(d) => self.irq.add(d),
             Device::Net(d) => self.net.add(d),
+            Device::Rng(d) => self.rng.add(d),
+            Device::Rtc(d) => self.rtc.add(d),
             Device::Uart(d) => self.uart.add(d),
         }
     }
@@ -114,6 +119,16 @@ pub fn all_net() -> &'static DeviceList<dyn NetScheme> {
     &DEVICES.net
 }
 
+/// Returns all devices which implement the [`RngScheme`].
+pub fn all_rng() -> &'static DeviceList<dyn RngScheme> {
+    &DEVICES.rng
+}
+
+/// Returns all devices which implement the [`RtcScheme`].
+pub fn all_rtc() -> &'static DeviceList<dyn RtcScheme> {
+    &DEVICES.rtc
+}
+
 /// Returns all devices which implement the [`UartScheme`].
 pub fn all_uart() -> &'static DeviceList<dyn UartScheme> {
     &DEVICES.uart
@@ -133,6 +148,7 @@ mod virtio_drivers_ffi {
     #[no_mangle]
     extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
         let paddr = KHANDLER.frame_alloc_contiguous(pages, 0).unwrap();
+        crate::common::mem::count_frames_alloc(pages);
         trace!("alloc DMA: paddr={:#x}, pages={}", paddr, pages);
         paddr
     }
@@ -142,6 +158,7 @@ mod virtio_drivers_ffi {
         for i in 0..pages {
             KHANDLER.frame_dealloc(paddr + i * PAGE_SIZE);
         }
+        crate::common::mem::count_frames_dealloc(pages);
         trace!("dealloc DMA: paddr={:#x}, pages={}", paddr, pages);
         0
     }
@@ -157,6 +174,15 @@ mod virtio_drivers_ffi {
     }
 }
 
+mod drivers_timer_ffi {
+    use crate::hal_fn::timer::timer_now;
+
+    #[no_mangle]
+    extern "C" fn drivers_timer_now_as_micros() -> u64 {
+        timer_now().as_micros() as _
+    }
+}
+
 #[cfg(not(feature = "libos"))]
 mod drivers_ffi {
     use crate::{PhysAddr, VirtAddr, KCONFIG, KHANDLER, PAGE_SIZE};
@@ -164,6 +190,7 @@ mod drivers_ffi {
     #[no_mangle]
     extern "C" fn drivers_dma_alloc(pages: usize) -> PhysAddr {
         let paddr = KHANDLER.frame_alloc_contiguous(pages, 0).unwrap();
+        crate::common::mem::count_frames_alloc(pages);
         trace!("alloc DMA: paddr={:#x}, pages={}", paddr, pages);
         paddr
     }
@@ -173,6 +200,7 @@ mod drivers_ffi {
         for i in 0..pages {
             KHANDLER.frame_dealloc(paddr + i * PAGE_SIZE);
         }
+        crate::common::mem::count_frames_dealloc(pages);
         trace!("dealloc DMA: paddr={:#x}, pages={}", paddr, pages);
         0
     }
@@ -186,10 +214,4 @@ mod drivers_ffi {
     extern "C" fn drivers_virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
         vaddr - KCONFIG.phys_to_virt_offset
     }
-
-    use crate::hal_fn::timer::timer_now;
-    #[no_mangle]
-    extern "C" fn drivers_timer_now_as_micros() -> u64 {
-        timer_now().as_micros() as _
-    }
 }
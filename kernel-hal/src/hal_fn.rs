@@ -79,6 +79,11 @@ hal_fn_def! {
         /// Flush TLB by the associated `vaddr`, or flush the entire TLB. (`vaddr` is `None`).
         pub(crate) fn flush_tlb(vaddr: Option<VirtAddr>);
 
+        /// Invalidate the TLB entries of `vaddrs` in the address space of
+        /// `vmtoken` on the other CPUs, or all of its entries if `vaddrs` is
+        /// `None`.
+        pub fn tlb_shootdown(vmtoken: PhysAddr, vaddrs: Option<&[VirtAddr]>) {}
+
         /// Clone kernel space entries (top level only) from `src` page table to `dst` page table.
         pub(crate) fn pt_clone_kernel_space(dst_pt_root: PhysAddr, src_pt_root: PhysAddr);
     }
@@ -103,6 +108,10 @@ hal_fn_def! {
         /// Test weather interrupt is enabled
         pub fn intr_get() -> bool;
 
+        /// Send an inter-processor interrupt to the CPU `cpu_id`, to wake it
+        /// up if it is waiting for interrupts.
+        pub fn send_ipi(cpu_id: u8) {}
+
         /// Disable IRQ.
         pub fn mask_irq(vector: usize) -> HalResult;
 
@@ -145,6 +154,15 @@ hal_fn_def! {
         /// Spawn a new thread.
         pub fn spawn(future: impl Future<Output = ()> + Send + 'static);
 
+        /// Set the scheduling priority of current task, from 0 the lowest.
+        pub fn set_priority(priority: usize) {}
+
+        /// Set the CPUs current task may run on, one bit for each, or 0 for any.
+        pub fn set_cpu_affinity(mask: usize) {}
+
+        /// Whether current task has used up its time slice, and should yield.
+        pub fn need_resched() -> bool { true }
+
         /// Set tid and pid of current task.
         pub fn set_current_thread(thread: Option<Arc<dyn Any + Send + Sync>>) {}
 
@@ -161,6 +179,23 @@ hal_fn_def! {
         /// TODO: use `Instant` as return type.
         pub fn timer_now() -> Duration;
 
+        /// Get the count of cycles of the clock source, from which
+        /// `timer_now()` is derived.
+        pub fn clock_cycles() -> u64 {
+            timer_now().as_nanos() as u64
+        }
+
+        /// Get the frequency of the clock source in Hz.
+        pub fn clock_frequency() -> u64 {
+            1_000_000_000
+        }
+
+        /// Get the wall-clock time, since the UNIX epoch, when `timer_now()`
+        /// was zero, read from the real-time clock of the platform.
+        pub fn boot_realtime() -> Duration {
+            Duration::ZERO
+        }
+
         /// Converting from now-relative durations to absolute deadlines.
         pub fn deadline_after(dur: Duration) -> Duration {
             timer_now() + dur
@@ -170,34 +205,30 @@ hal_fn_def! {
         /// TODO: use `Instant` as the type of `deadline`.
         pub fn timer_set(deadline: Duration, callback: Box<dyn FnOnce(Duration) + Send + Sync>);
 
+        /// Set a new timer, which may be called late by at most `slack` after
+        /// `deadline`, to be coalesced with others.
+        pub fn timer_set_with_slack(
+            deadline: Duration,
+            slack: Duration,
+            callback: Box<dyn FnOnce(Duration) + Send + Sync>
+        ) {
+            timer_set(deadline, callback)
+        }
+
         /// Check timers, call when timer interrupt happened.
         pub(crate) fn timer_tick();
     }
 
     /// Random number generator.
     pub mod rand {
-        /// Fill random bytes to the buffer
-        #[allow(unused_variables)]
+        /// Fill random bytes to the buffer, from the kernel entropy pool.
         pub fn fill_random(buf: &mut [u8]) {
-            cfg_if! {
-                if #[cfg(target_arch = "x86_64")] {
-                    // TODO: optimize
-                    for x in buf.iter_mut() {
-                        let mut r = 0;
-                        unsafe { core::arch::x86_64::_rdrand16_step(&mut r) };
-                        *x = r as _;
-                    }
-                } else {
-                    static mut SEED: u64 = 0xdead_beef_cafe_babe;
-                    for x in buf.iter_mut() {
-                        unsafe {
-                            // from musl
-                            SEED = SEED.wrapping_mul(0x5851_f42d_4c95_7f2d);
-                            *x = (SEED >> 33) as u8;
-                        }
-                    }
-                }
-            }
+            common::rand::fill_random(buf)
+        }
+
+        /// Mix the bytes into the kernel entropy pool.
+        pub fn add_entropy(buf: &[u8]) {
+            common::rand::add_entropy(buf)
         }
     }
 
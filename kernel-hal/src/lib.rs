@@ -4,6 +4,7 @@
 #![deny(warnings)]
 #![feature(doc_cfg)]
 #![cfg_attr(feature = "libos", feature(thread_id_value))]
+#![cfg_attr(target_arch = "loongarch64", feature(asm_experimental_arch, asm_const))]
 
 extern crate alloc;
 #[macro_use]
@@ -37,7 +38,7 @@ cfg_if! {
 pub(crate) use config::KCONFIG;
 pub(crate) use kernel_handler::KHANDLER;
 
-pub use common::{addr, console, context, defs::*, user};
+pub use common::{addr, console, context, debug, defs::*, user};
 pub use config::KernelConfig;
 pub use imp::{
     boot::{primary_init, primary_init_early, secondary_init},
@@ -1,10 +1,91 @@
+//! Interrupts management.
+//!
+//! There are no hardware IRQs in the libos mode. The IRQs are simulated by a
+//! table of handlers, and raised by calling [`handle_irq`].
+
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use lock::Mutex;
+
+use crate::drivers::prelude::{IrqHandler, IrqPolarity, IrqTriggerMode};
+use crate::{HalError, HalResult};
+
+/// Number of the simulated IRQs.
+const NUM_IRQS: usize = 256;
+
+#[derive(Default)]
+struct IrqEntry {
+    handler: Option<Arc<dyn Fn() + Send + Sync>>,
+    masked: bool,
+}
+
+lazy_static! {
+    static ref IRQS: Mutex<BTreeMap<usize, IrqEntry>> = Mutex::new(BTreeMap::new());
+}
+
+fn with_irq<T>(vector: usize, f: impl FnOnce(&mut IrqEntry) -> HalResult<T>) -> HalResult<T> {
+    if vector >= NUM_IRQS {
+        return Err(HalError);
+    }
+    f(IRQS.lock().entry(vector).or_default())
+}
+
 hal_fn_impl! {
     impl mod crate::hal_fn::interrupt {
         fn wait_for_interrupt() {}
+
+        fn is_valid_irq(vector: usize) -> bool {
+            vector < NUM_IRQS
+        }
+
         fn intr_on() {}
         fn intr_off() {}
         fn intr_get() -> bool {
             false
         }
+
+        fn mask_irq(vector: usize) -> HalResult {
+            with_irq(vector, |irq| {
+                irq.masked = true;
+                Ok(())
+            })
+        }
+
+        fn unmask_irq(vector: usize) -> HalResult {
+            with_irq(vector, |irq| {
+                irq.masked = false;
+                Ok(())
+            })
+        }
+
+        fn configure_irq(vector: usize, _tm: IrqTriggerMode, _pol: IrqPolarity) -> HalResult {
+            with_irq(vector, |_| Ok(()))
+        }
+
+        fn register_irq_handler(vector: usize, handler: IrqHandler) -> HalResult {
+            with_irq(vector, |irq| {
+                if irq.handler.is_some() {
+                    return Err(HalError);
+                }
+                irq.handler = Some(Arc::from(handler));
+                Ok(())
+            })
+        }
+
+        fn unregister_irq_handler(vector: usize) -> HalResult {
+            with_irq(vector, |irq| irq.handler.take().map(drop).ok_or(HalError))
+        }
+
+        fn handle_irq(vector: usize) {
+            let handler = with_irq(vector, |irq| match irq.masked {
+                // a masked IRQ is lost, as there is no line to stay asserted
+                true => Ok(None),
+                false => Ok(irq.handler.clone()),
+            });
+            // called without the lock, as the handler may mask the IRQ
+            if let Ok(Some(handler)) = handler {
+                handler();
+            }
+        }
     }
 }
@@ -80,6 +80,11 @@ impl GenericPageTable for PageTable {
         }
     }
 
+    fn split(&mut self, _vaddr: VirtAddr) -> PagingResult<PageSize> {
+        // all pages are 4K
+        Err(PagingError::NotMapped)
+    }
+
     fn unmap_cont(&mut self, vaddr: VirtAddr, size: usize) -> PagingResult {
         if size == 0 {
             return Ok(());
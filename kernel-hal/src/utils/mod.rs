@@ -4,6 +4,7 @@ use core::cell::UnsafeCell;
 cfg_if! {
     if #[cfg(not(feature = "libos"))] {
         pub(crate) mod page_table;
+        pub(crate) mod timer_wheel;
     }
 }
 
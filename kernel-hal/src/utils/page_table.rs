@@ -272,6 +272,35 @@ impl<L: PageTableLevel, PTE: GenericPTE> GenericPageTable for PageTableImpl<L, P
         trace!("PageTable query: {:x?} => {:x?}", vaddr, ret);
         Ok(ret)
     }
+
+    fn split(&mut self, vaddr: VirtAddr) -> PagingResult<PageSize> {
+        let (entry, size) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let new_size = match size {
+            PageSize::Size1G => PageSize::Size2M,
+            PageSize::Size2M => PageSize::Size4K,
+            PageSize::Size4K => return Ok(size),
+        };
+        let (paddr, flags) = (entry.addr(), entry.flags());
+        let table_paddr = self.alloc_intrm_table().ok_or(PagingError::NoMemory)?;
+        for (i, e) in table_of_mut::<PTE>(table_paddr).iter_mut().enumerate() {
+            e.set_addr(paddr + i * new_size as usize);
+            e.set_flags(flags, new_size.is_huge());
+        }
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        entry.set_table(table_paddr);
+        crate::vm::flush_tlb(Some(size.align_down(vaddr)));
+        trace!(
+            "PageTable split: {:x?} {:?} => {:?} in {:#x?}",
+            vaddr,
+            size,
+            new_size,
+            self.table_phys()
+        );
+        Ok(new_size)
+    }
 }
 
 const ENTRY_COUNT: usize = 512;
@@ -0,0 +1,138 @@
+//! A hierarchical timing wheel.
+//!
+//! Timers are kept in the slots of several levels by their expiry ticks. Each
+//! slot of level 0 holds the timers of one tick, and each slot of a higher
+//! level covers all slots of the lower level. When the wheel turns to the
+//! start of a slot, its timers are moved down a level, until they expire at
+//! level 0. Adding a timer and expiring one both take constant time.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::time::Duration;
+
+/// Number of slots of each level, as a power of 2.
+const SLOT_BITS: u32 = 6;
+const SLOTS: u64 = 1 << SLOT_BITS;
+
+/// Number of levels. Timers beyond the highest level are kept in a list.
+const LEVELS: u32 = 4;
+
+/// The callback of a timer, called with the current time after it expires.
+pub type TimerCallback = Box<dyn FnOnce(Duration) + Send + Sync>;
+
+struct Timer {
+    expiry: u64,
+    callback: TimerCallback,
+}
+
+pub struct TimerWheel {
+    /// The length of a tick.
+    tick: Duration,
+    /// The current tick, before which all timers have expired.
+    now: u64,
+    /// The slots of all levels, from level 0.
+    slots: Vec<Vec<Timer>>,
+    /// The timers beyond the highest level.
+    overflow: Vec<Timer>,
+    /// Number of timers.
+    len: usize,
+}
+
+impl TimerWheel {
+    /// Create an empty wheel turning every `tick`.
+    pub fn new(tick: Duration) -> Self {
+        Self {
+            tick,
+            now: 0,
+            slots: (0..LEVELS as u64 * SLOTS).map(|_| Vec::new()).collect(),
+            overflow: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Add a timer expiring in `[deadline, deadline + slack]`.
+    ///
+    /// The expiry tick is rounded within the slack, so that timers close to
+    /// each other expire at the same tick.
+    pub fn add(&mut self, deadline: Duration, slack: Duration, callback: TimerCallback) {
+        let earliest = self.ticks_ceil(deadline);
+        let latest = self
+            .ticks_floor(deadline.saturating_add(slack))
+            .max(earliest);
+        // expired timers run at the next tick
+        let expiry = coalesce(earliest, latest).max(self.now + 1);
+        self.len += 1;
+        self.insert(Timer { expiry, callback });
+    }
+
+    /// Turn the wheel to time `now`, and return the callbacks of the timers
+    /// expired.
+    pub fn expire(&mut self, now: Duration) -> Vec<TimerCallback> {
+        let target = self.ticks_floor(now);
+        let mut expired = Vec::new();
+        while self.now < target {
+            if self.len == 0 {
+                // nothing to turn for, so the wheel catches up at once
+                self.now = target;
+                break;
+            }
+            self.now += 1;
+            self.cascade();
+            let slot = &mut self.slots[(self.now % SLOTS) as usize];
+            self.len -= slot.len();
+            expired.extend(slot.drain(..).map(|timer| timer.callback));
+        }
+        expired
+    }
+
+    fn ticks_floor(&self, time: Duration) -> u64 {
+        (time.as_nanos() / self.tick.as_nanos()) as u64
+    }
+
+    fn ticks_ceil(&self, time: Duration) -> u64 {
+        let tick = self.tick.as_nanos();
+        ((time.as_nanos() + tick - 1) / tick) as u64
+    }
+
+    /// Put `timer` into the slot covering its expiry tick at the lowest level.
+    fn insert(&mut self, timer: Timer) {
+        let delta = timer.expiry - self.now;
+        match (0..LEVELS).find(|&level| delta < SLOTS << (SLOT_BITS * level)) {
+            Some(level) => {
+                let slot = (timer.expiry >> (SLOT_BITS * level)) % SLOTS;
+                self.slots[(level as u64 * SLOTS + slot) as usize].push(timer);
+            }
+            None => self.overflow.push(timer),
+        }
+    }
+
+    /// Move down the timers of the slots of higher levels starting at the
+    /// current tick, from the highest.
+    fn cascade(&mut self) {
+        for level in (1..LEVELS).rev() {
+            if self.now % (1 << (SLOT_BITS * level)) != 0 {
+                continue;
+            }
+            if level == LEVELS - 1 {
+                for timer in core::mem::take(&mut self.overflow) {
+                    self.insert(timer);
+                }
+            }
+            let slot = (self.now >> (SLOT_BITS * level)) % SLOTS;
+            let index = (level as u64 * SLOTS + slot) as usize;
+            for timer in core::mem::take(&mut self.slots[index]) {
+                self.insert(timer);
+            }
+        }
+    }
+}
+
+/// Choose a tick in `[earliest, latest]`, with the most trailing zero bits.
+fn coalesce(earliest: u64, latest: u64) -> u64 {
+    let diff = earliest ^ latest;
+    if diff == 0 {
+        return earliest;
+    }
+    // clear the bits of `latest` below the highest one different
+    let mask = (1u64 << (63 - diff.leading_zeros())) - 1;
+    latest & !mask
+}
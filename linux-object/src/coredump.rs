@@ -0,0 +1,354 @@
+//! ELF core dumps of the processes killed by signals.
+//!
+//! The core file can be loaded by `gdb` together with the executable, it has
+//! a `PT_NOTE` segment with the `NT_PRPSINFO` of the process and a
+//! `NT_PRSTATUS` for every thread, followed by a `PT_LOAD` segment for every
+//! mapping of the address space.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use kernel_hal::context::{UserContext, UserContextField};
+use kernel_hal::MMUFlags;
+use rcore_fs::vfs::{FileType, FsError};
+use zircon_object::object::KernelObject;
+use zircon_object::task::{Process, Thread};
+use zircon_object::vm::{pages, PAGE_SIZE};
+
+use crate::error::{LxError, LxResult};
+use crate::fs::{resize_inode, split_path, File, FileDesc, FileLike, OpenFlags};
+use crate::process::ProcessExt;
+use crate::signal::Signal;
+use crate::thread::ThreadExt;
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// The size of the chunks in which the memory is copied to the core file.
+const CHUNK_SIZE: usize = 0x10000;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        const EM_CURRENT: u16 = 62;
+    } else if #[cfg(target_arch = "aarch64")] {
+        const EM_CURRENT: u16 = 183;
+    } else if #[cfg(target_arch = "riscv64")] {
+        const EM_CURRENT: u16 = 243;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        const EM_CURRENT: u16 = 258;
+    } else {
+        const EM_CURRENT: u16 = 0;
+    }
+}
+
+/// Write the core dump of `proc`, whose `thread` is killed by `signal` in
+/// the context `cx`, to the file `core.<pid>` in the working directory.
+///
+/// Returns the size written, which is truncated at the soft limit of
+/// `RLIMIT_CORE`. Nothing is written if the limit is 0, the default.
+pub fn dump_core(
+    proc: &Arc<Process>,
+    thread: &Arc<Thread>,
+    signal: Signal,
+    cx: &UserContext,
+) -> LxResult<usize> {
+    let limit = proc.linux().core_limit(None).cur;
+    if limit == 0 {
+        return Ok(0);
+    }
+    let mut notes = Vec::new();
+    write_note(&mut notes, NT_PRPSINFO, &prpsinfo(proc));
+    write_note(&mut notes, NT_PRSTATUS, &prstatus(proc, thread, signal, cx));
+    for id in proc.thread_ids() {
+        if id == thread.id() {
+            continue;
+        }
+        let other = match proc.get_child(id).map(|t| t.downcast_arc::<Thread>()) {
+            Ok(Ok(other)) => other,
+            _ => continue,
+        };
+        // skip the threads running on other CPUs
+        if let Ok(cx) = other.context_cloned() {
+            write_note(
+                &mut notes,
+                NT_PRSTATUS,
+                &prstatus(proc, &other, signal, &cx),
+            );
+        }
+    }
+
+    // the headers and the notes, then the memory from a page boundary
+    let vmar = proc.vmar();
+    let mappings = vmar.get_mappings_info();
+    let phnum = mappings.len() + 1;
+    let notes_offset = EHDR_SIZE + PHDR_SIZE * phnum;
+    let mut offset = pages(notes_offset + notes.len()) * PAGE_SIZE;
+    let mut headers = Vec::with_capacity(notes_offset);
+    write_ehdr(&mut headers, phnum as u16);
+    write_phdr(&mut headers, PT_NOTE, 0, notes_offset, 0, notes.len(), 0);
+    let mut segments = Vec::new();
+    for map in mappings.iter() {
+        // the memory never touched reads as zero, it takes no space in the file
+        let filesz = if map.flags.contains(MMUFlags::READ) && map.committed_bytes != 0 {
+            map.size
+        } else {
+            0
+        };
+        let mut flags = 0;
+        for (flag, bit) in [
+            (MMUFlags::EXECUTE, 1),
+            (MMUFlags::WRITE, 2),
+            (MMUFlags::READ, 4),
+        ] {
+            if map.flags.contains(flag) {
+                flags |= bit;
+            }
+        }
+        write_phdr(
+            &mut headers,
+            PT_LOAD,
+            flags,
+            offset,
+            map.addr,
+            filesz,
+            map.size,
+        );
+        if filesz != 0 {
+            segments.push((offset, map.addr, map.size));
+        }
+        offset += filesz;
+    }
+
+    let mut core = CoreFile {
+        file: create_core_file(proc)?,
+        len: 0,
+        limit: limit as usize,
+    };
+    core.write_at(0, &headers)?;
+    core.write_at(notes_offset, &notes)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for (offset, addr, size) in segments {
+        for start in (0..size).step_by(CHUNK_SIZE) {
+            let buf = &mut buf[..CHUNK_SIZE.min(size - start)];
+            if vmar.read_memory(addr + start, buf).is_err() {
+                buf.fill(0);
+            }
+            if !core.write_at(offset + start, buf)? {
+                return Ok(core.len);
+            }
+        }
+    }
+    Ok(core.len)
+}
+
+/// The core file being written, truncated at `limit`.
+struct CoreFile {
+    file: Arc<File>,
+    len: usize,
+    limit: usize,
+}
+
+impl CoreFile {
+    /// Returns `false` if the limit is reached.
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> LxResult<bool> {
+        if offset >= self.limit {
+            return Ok(false);
+        }
+        let buf = &buf[..buf.len().min(self.limit - offset)];
+        self.file.write_at(offset as u64, buf)?;
+        self.len = self.len.max(offset + buf.len());
+        Ok(self.len < self.limit)
+    }
+}
+
+/// Create `core.<pid>` in the working directory, or truncate the existing one.
+fn create_core_file(proc: &Arc<Process>) -> LxResult<Arc<File>> {
+    let linux = proc.linux();
+    let path = format!("core.{}", proc.id());
+    let (dir_path, file_name) = split_path(&path);
+    let dir = linux.lookup_inode_at(FileDesc::CWD, dir_path, true)?;
+    let inode = match dir.find(file_name) {
+        Ok(inode) => {
+            if inode.metadata()?.type_ != FileType::File {
+                return Err(LxError::EISDIR);
+            }
+            resize_inode(&inode, 0)?;
+            inode
+        }
+        Err(FsError::EntryNotFound) => dir.create(file_name, FileType::File, 0o600)?,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(File::new(inode, OpenFlags::WRONLY, path))
+}
+
+fn write_ehdr(buf: &mut Vec<u8>, phnum: u16) {
+    // 64-bit, little-endian, version 1, System V ABI
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&ET_CORE.to_le_bytes());
+    buf.extend_from_slice(&EM_CURRENT.to_le_bytes());
+    buf.extend_from_slice(&1u32.to_le_bytes());
+    // e_entry, e_phoff, e_shoff
+    buf.extend_from_slice(&0u64.to_le_bytes());
+    buf.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+    buf.extend_from_slice(&0u64.to_le_bytes());
+    // e_flags
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    for half in [EHDR_SIZE as u16, PHDR_SIZE as u16, phnum, 64, 0, 0] {
+        buf.extend_from_slice(&half.to_le_bytes());
+    }
+}
+
+fn write_phdr(
+    buf: &mut Vec<u8>,
+    type_: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+) {
+    let align = if type_ == PT_LOAD { PAGE_SIZE } else { 4 };
+    buf.extend_from_slice(&type_.to_le_bytes());
+    buf.extend_from_slice(&flags.to_le_bytes());
+    for word in [offset, vaddr, 0, filesz, memsz, align] {
+        buf.extend_from_slice(&(word as u64).to_le_bytes());
+    }
+}
+
+/// Append a note named `CORE`, with the name and the desc padded to 4 bytes.
+fn write_note(buf: &mut Vec<u8>, type_: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0\0\0\0";
+    buf.extend_from_slice(&5u32.to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&type_.to_le_bytes());
+    buf.extend_from_slice(NAME);
+    buf.extend_from_slice(desc);
+    buf.resize((buf.len() + 3) / 4 * 4, 0);
+}
+
+/// Append `s` as a NUL-terminated string in a field of `len` bytes.
+fn write_str(buf: &mut Vec<u8>, s: &str, len: usize) {
+    let bytes = &s.as_bytes()[..s.len().min(len - 1)];
+    buf.extend_from_slice(bytes);
+    buf.resize(buf.len() + len - bytes.len(), 0);
+}
+
+/// `struct elf_prpsinfo`
+fn prpsinfo(proc: &Arc<Process>) -> Vec<u8> {
+    let linux = proc.linux();
+    let mut buf = Vec::with_capacity(136);
+    // pr_state, pr_sname, pr_zomb, pr_nice, padding, pr_flag
+    buf.extend_from_slice(&[0, b'R', 0, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&0u64.to_le_bytes());
+    // pr_uid, pr_gid
+    buf.extend_from_slice(&[0; 8]);
+    let ppid = linux.parent().map_or(0, |p| p.id());
+    for id in [proc.id(), ppid, linux.pgid(), linux.sid()] {
+        buf.extend_from_slice(&(id as i32).to_le_bytes());
+    }
+    let path = linux.execute_path();
+    let (_, fname) = split_path(&path);
+    write_str(&mut buf, fname, 16);
+    let args: Vec<String> = linux.args();
+    write_str(&mut buf, &args.join(" "), 80);
+    buf
+}
+
+/// `struct elf_prstatus`
+fn prstatus(
+    proc: &Arc<Process>,
+    thread: &Arc<Thread>,
+    signal: Signal,
+    cx: &UserContext,
+) -> Vec<u8> {
+    let linux = proc.linux();
+    let mut buf = Vec::with_capacity(112 + (GREGS_NUM + 1) * 8);
+    // pr_info: si_signo, si_code, si_errno
+    for word in [signal as i32, 0, 0] {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+    // pr_cursig and padding
+    buf.extend_from_slice(&(signal as i16).to_le_bytes());
+    buf.extend_from_slice(&[0; 2]);
+    let (pending, blocked) = {
+        let inner = thread.lock_linux();
+        (inner.signals.val(), inner.signal_mask.val())
+    };
+    buf.extend_from_slice(&pending.to_le_bytes());
+    buf.extend_from_slice(&blocked.to_le_bytes());
+    let ppid = linux.parent().map_or(0, |p| p.id());
+    for id in [thread.id(), ppid, linux.pgid(), linux.sid()] {
+        buf.extend_from_slice(&(id as i32).to_le_bytes());
+    }
+    // pr_utime, pr_stime, pr_cutime, pr_cstime
+    buf.extend_from_slice(&[0; 64]);
+    for reg in gregs(cx) {
+        buf.extend_from_slice(&(reg as u64).to_le_bytes());
+    }
+    // pr_fpvalid and padding
+    buf.extend_from_slice(&[0; 8]);
+    buf
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        pub const GREGS_NUM: usize = 27;
+    } else if #[cfg(target_arch = "aarch64")] {
+        pub const GREGS_NUM: usize = 34;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        pub const GREGS_NUM: usize = 45;
+    } else {
+        pub const GREGS_NUM: usize = 32;
+    }
+}
+
+/// The general registers in the order of `elf_gregset_t`, which is also
+/// the layout of `struct user_regs_struct` read by `PTRACE_GETREGS`.
+#[allow(unused_variables)]
+pub fn gregs(cx: &UserContext) -> [usize; GREGS_NUM] {
+    let mut cx = *cx;
+    let pc = cx.get_field(UserContextField::InstrPointer);
+    let sp = cx.get_field(UserContextField::StackPointer);
+    let g = cx.general();
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            const USER_CS: usize = 0x33;
+            const USER_SS: usize = 0x2b;
+            // orig_rax is -1 out of syscalls
+            [
+                g.r15, g.r14, g.r13, g.r12, g.rbp, g.rbx, g.r11, g.r10, g.r9, g.r8, g.rax, g.rcx,
+                g.rdx, g.rsi, g.rdi, usize::MAX, pc, USER_CS, g.rflags, sp, USER_SS, g.fsbase,
+                g.gsbase, 0, 0, 0, 0,
+            ]
+        } else if #[cfg(target_arch = "aarch64")] {
+            // pstate is not saved in the context
+            [
+                g.x0, g.x1, g.x2, g.x3, g.x4, g.x5, g.x6, g.x7, g.x8, g.x9, g.x10, g.x11, g.x12,
+                g.x13, g.x14, g.x15, g.x16, g.x17, g.x18, g.x19, g.x20, g.x21, g.x22, g.x23, g.x24,
+                g.x25, g.x26, g.x27, g.x28, g.x29, g.x30, sp, pc, 0,
+            ]
+        } else if #[cfg(target_arch = "riscv64")] {
+            // the pc takes the place of x0
+            [
+                pc, g.ra, g.sp, g.gp, g.tp, g.t0, g.t1, g.t2, g.s0, g.s1, g.a0, g.a1, g.a2, g.a3,
+                g.a4, g.a5, g.a6, g.a7, g.s2, g.s3, g.s4, g.s5, g.s6, g.s7, g.s8, g.s9, g.s10,
+                g.s11, g.t3, g.t4, g.t5, g.t6,
+            ]
+        } else if #[cfg(target_arch = "loongarch64")] {
+            // r0-r31, orig_a0, era, badv and the reserved
+            [
+                g.zero, g.ra, g.tp, g.sp, g.a0, g.a1, g.a2, g.a3, g.a4, g.a5, g.a6, g.a7, g.t0,
+                g.t1, g.t2, g.t3, g.t4, g.t5, g.t6, g.t7, g.t8, g.r21, g.fp, g.s0, g.s1, g.s2,
+                g.s3, g.s4, g.s5, g.s6, g.s7, g.s8, g.a0, pc, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        } else {
+            [0; GREGS_NUM]
+        }
+    }
+}
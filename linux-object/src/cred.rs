@@ -0,0 +1,511 @@
+//! Credentials of processes: user and group IDs, and capabilities.
+//!
+//! The rules follow Linux, see `credentials(7)` and `capabilities(7)`.
+//! The capability bounding set and the ambient set are not supported, as if
+//! the bounding set is full and the ambient set is empty.
+
+use alloc::vec::Vec;
+use rcore_fs::vfs::{FileType, Metadata};
+
+use crate::error::{LxError, LxResult};
+
+bitflags::bitflags! {
+    /// Capabilities, see `capabilities(7)`.
+    pub struct Capabilities: u64 {
+        /// Change the owners of files.
+        const CHOWN = 1 << 0;
+        /// Bypass the permission checks of files.
+        const DAC_OVERRIDE = 1 << 1;
+        /// Bypass the read permission checks of files and directories.
+        const DAC_READ_SEARCH = 1 << 2;
+        /// Bypass the checks requiring the owner of files.
+        const FOWNER = 1 << 3;
+        /// Keep the set-user-ID and set-group-ID bits on modification.
+        const FSETID = 1 << 4;
+        /// Bypass the permission checks of sending signals.
+        const KILL = 1 << 5;
+        /// Change the group IDs.
+        const SETGID = 1 << 6;
+        /// Change the user IDs.
+        const SETUID = 1 << 7;
+        /// Change the capabilities of the other sets.
+        const SETPCAP = 1 << 8;
+        /// Set the immutable and append-only flags of files.
+        const LINUX_IMMUTABLE = 1 << 9;
+        /// Bind the ports below 1024.
+        const NET_BIND_SERVICE = 1 << 10;
+        /// Broadcast and listen to multicast.
+        const NET_BROADCAST = 1 << 11;
+        /// Administer the network.
+        const NET_ADMIN = 1 << 12;
+        /// Use raw and packet sockets.
+        const NET_RAW = 1 << 13;
+        /// Lock memory.
+        const IPC_LOCK = 1 << 14;
+        /// Bypass the permission checks of System V IPC.
+        const IPC_OWNER = 1 << 15;
+        /// Load and unload kernel modules.
+        const SYS_MODULE = 1 << 16;
+        /// Perform I/O port operations.
+        const SYS_RAWIO = 1 << 17;
+        /// Use `chroot`.
+        const SYS_CHROOT = 1 << 18;
+        /// Trace any process.
+        const SYS_PTRACE = 1 << 19;
+        /// Use `acct`.
+        const SYS_PACCT = 1 << 20;
+        /// Administer the system, e.g. `mount`.
+        const SYS_ADMIN = 1 << 21;
+        /// Use `reboot`.
+        const SYS_BOOT = 1 << 22;
+        /// Raise the priority of processes.
+        const SYS_NICE = 1 << 23;
+        /// Override the resource limits.
+        const SYS_RESOURCE = 1 << 24;
+        /// Set the system clock.
+        const SYS_TIME = 1 << 25;
+        /// Configure the TTY devices.
+        const SYS_TTY_CONFIG = 1 << 26;
+        /// Create special files with `mknod`.
+        const MKNOD = 1 << 27;
+        /// Take file leases.
+        const LEASE = 1 << 28;
+        /// Write the audit log.
+        const AUDIT_WRITE = 1 << 29;
+        /// Configure the audit.
+        const AUDIT_CONTROL = 1 << 30;
+        /// Set the file capabilities.
+        const SETFCAP = 1 << 31;
+        /// Override the mandatory access control.
+        const MAC_OVERRIDE = 1 << 32;
+        /// Configure the mandatory access control.
+        const MAC_ADMIN = 1 << 33;
+        /// Configure the kernel log.
+        const SYSLOG = 1 << 34;
+        /// Trigger wakeups of the system.
+        const WAKE_ALARM = 1 << 35;
+        /// Block the system from suspending.
+        const BLOCK_SUSPEND = 1 << 36;
+        /// Read the audit log.
+        const AUDIT_READ = 1 << 37;
+        /// Use the performance monitoring.
+        const PERFMON = 1 << 38;
+        /// Use BPF.
+        const BPF = 1 << 39;
+        /// Checkpoint and restore processes.
+        const CHECKPOINT_RESTORE = 1 << 40;
+    }
+}
+
+impl Capabilities {
+    /// The capabilities about files, which follow the filesystem user ID.
+    const FS_SET: Capabilities = Capabilities::from_bits_truncate(
+        Capabilities::CHOWN.bits()
+            | Capabilities::DAC_OVERRIDE.bits()
+            | Capabilities::DAC_READ_SEARCH.bits()
+            | Capabilities::FOWNER.bits()
+            | Capabilities::FSETID.bits()
+            | Capabilities::LINUX_IMMUTABLE.bits()
+            | Capabilities::MKNOD.bits()
+            | Capabilities::MAC_OVERRIDE.bits(),
+    );
+}
+
+/// Read permission, requested by [`Credentials::check_access`].
+pub const R_OK: usize = 4;
+/// Write permission, requested by [`Credentials::check_access`].
+pub const W_OK: usize = 2;
+/// Execute or search permission, requested by [`Credentials::check_access`].
+pub const X_OK: usize = 1;
+
+/// The set-user-ID bit of the file mode.
+pub const S_ISUID: u16 = 0o4000;
+/// The set-group-ID bit of the file mode.
+pub const S_ISGID: u16 = 0o2000;
+/// The sticky bit of the file mode, restricting the removal in directories.
+pub const S_ISVTX: u16 = 0o1000;
+
+/// The most supplementary groups of a process.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// The credentials of a process.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// Real user ID
+    pub uid: u32,
+    /// Effective user ID
+    pub euid: u32,
+    /// Saved set-user-ID
+    pub suid: u32,
+    /// Filesystem user ID
+    pub fsuid: u32,
+    /// Real group ID
+    pub gid: u32,
+    /// Effective group ID
+    pub egid: u32,
+    /// Saved set-group-ID
+    pub sgid: u32,
+    /// Filesystem group ID
+    pub fsgid: u32,
+    /// Supplementary group IDs
+    pub groups: Vec<u32>,
+    /// Permitted capabilities
+    pub cap_permitted: Capabilities,
+    /// Effective capabilities
+    pub cap_effective: Capabilities,
+    /// Inheritable capabilities
+    pub cap_inheritable: Capabilities,
+}
+
+impl Default for Credentials {
+    /// The credentials of root, with all the capabilities.
+    fn default() -> Self {
+        Credentials {
+            uid: 0,
+            euid: 0,
+            suid: 0,
+            fsuid: 0,
+            gid: 0,
+            egid: 0,
+            sgid: 0,
+            fsgid: 0,
+            groups: Vec::new(),
+            cap_permitted: Capabilities::all(),
+            cap_effective: Capabilities::all(),
+            cap_inheritable: Capabilities::empty(),
+        }
+    }
+}
+
+impl Credentials {
+    /// Whether the capability is effective.
+    pub fn has_cap(&self, cap: Capabilities) -> bool {
+        self.cap_effective.contains(cap)
+    }
+
+    /// Whether `gid` is the filesystem group ID or a supplementary group ID.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.fsgid == gid || self.groups.contains(&gid)
+    }
+
+    /// Whether the filesystem user ID owns the file, or has `CAP_FOWNER`.
+    pub fn is_owner(&self, metadata: &Metadata) -> bool {
+        self.fsuid as usize == metadata.uid || self.has_cap(Capabilities::FOWNER)
+    }
+
+    /// Check the access `mask`, some of [`R_OK`], [`W_OK`] and [`X_OK`], to
+    /// the file of `metadata`.
+    pub fn check_access(&self, metadata: &Metadata, mask: usize) -> LxResult {
+        let mode = metadata.mode as usize;
+        let perm = if self.fsuid as usize == metadata.uid {
+            mode >> 6
+        } else if self.in_group(metadata.gid as u32) {
+            mode >> 3
+        } else {
+            mode
+        } & 7;
+        if perm & mask == mask {
+            return Ok(());
+        }
+        let is_dir = metadata.type_ == FileType::Dir;
+        // read and write anything, execute the files executable by anyone
+        if self.has_cap(Capabilities::DAC_OVERRIDE)
+            && (mask & X_OK == 0 || is_dir || mode & 0o111 != 0)
+        {
+            return Ok(());
+        }
+        // read files, read and search directories
+        if self.has_cap(Capabilities::DAC_READ_SEARCH)
+            && (mask == R_OK || (is_dir && mask & W_OK == 0))
+        {
+            return Ok(());
+        }
+        Err(LxError::EACCES)
+    }
+
+    /// The credentials checking the access by `access` and `faccessat`,
+    /// with the real user and group IDs instead of the filesystem ones.
+    pub fn for_access(&self) -> Self {
+        let mut cred = self.clone();
+        cred.fsuid = self.uid;
+        cred.fsgid = self.gid;
+        cred.cap_effective = if self.uid == 0 {
+            self.cap_permitted
+        } else {
+            Capabilities::empty()
+        };
+        cred
+    }
+
+    /// Check whether the owner of the file of `metadata` can be changed,
+    /// `None` to keep the user or the group.
+    pub fn check_chown(&self, metadata: &Metadata, uid: Option<u32>, gid: Option<u32>) -> LxResult {
+        if self.has_cap(Capabilities::CHOWN) {
+            return Ok(());
+        }
+        // only the group can be changed by the owner, to one of its groups
+        let uid_kept = uid.map_or(true, |uid| uid as usize == metadata.uid);
+        let gid_allowed = gid.map_or(true, |gid| {
+            gid as usize == metadata.gid || self.in_group(gid)
+        });
+        if uid_kept && gid_allowed && self.fsuid as usize == metadata.uid {
+            Ok(())
+        } else {
+            Err(LxError::EPERM)
+        }
+    }
+
+    /// Set the user IDs by `setuid`.
+    ///
+    /// With `CAP_SETUID`, all of them are set, otherwise only the effective
+    /// one can be set to the real or the saved one.
+    pub fn set_uid(&mut self, uid: u32) -> LxResult {
+        let old = (self.uid, self.euid, self.suid);
+        if self.has_cap(Capabilities::SETUID) {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(LxError::EPERM);
+        }
+        self.euid = uid;
+        self.fsuid = uid;
+        self.fix_caps_after_setuid(old);
+        Ok(())
+    }
+
+    /// Set the real and the effective user IDs by `setreuid`, `None` to keep
+    /// the ID.
+    pub fn set_reuid(&mut self, ruid: Option<u32>, euid: Option<u32>) -> LxResult {
+        let old = (self.uid, self.euid, self.suid);
+        if !self.has_cap(Capabilities::SETUID) {
+            let ruid_allowed = ruid.map_or(true, |id| id == self.uid || id == self.euid);
+            let euid_allowed = euid.map_or(true, |id| {
+                id == self.uid || id == self.euid || id == self.suid
+            });
+            if !ruid_allowed || !euid_allowed {
+                return Err(LxError::EPERM);
+            }
+        }
+        if let Some(id) = ruid {
+            self.uid = id;
+        }
+        if let Some(id) = euid {
+            self.euid = id;
+        }
+        // the saved one follows if the real one is set, or the effective one
+        // differs from the previous real one
+        if ruid.is_some() || euid.map_or(false, |id| id != old.0) {
+            self.suid = self.euid;
+        }
+        self.fsuid = self.euid;
+        self.fix_caps_after_setuid(old);
+        Ok(())
+    }
+
+    /// Set the real, the effective and the saved user IDs by `setresuid`,
+    /// `None` to keep the ID.
+    ///
+    /// Without `CAP_SETUID`, each one can only be set to one of the current IDs.
+    pub fn set_resuid(
+        &mut self,
+        ruid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> LxResult {
+        let old = (self.uid, self.euid, self.suid);
+        if !self.has_cap(Capabilities::SETUID) {
+            let allowed =
+                |id: Option<u32>| id.map_or(true, |id| id == old.0 || id == old.1 || id == old.2);
+            if !allowed(ruid) || !allowed(euid) || !allowed(suid) {
+                return Err(LxError::EPERM);
+            }
+        }
+        self.uid = ruid.unwrap_or(self.uid);
+        self.euid = euid.unwrap_or(self.euid);
+        self.suid = suid.unwrap_or(self.suid);
+        self.fsuid = self.euid;
+        self.fix_caps_after_setuid(old);
+        Ok(())
+    }
+
+    /// Set the filesystem user ID by `setfsuid`, returns the previous one.
+    ///
+    /// It is not changed if not permitted.
+    pub fn set_fsuid(&mut self, uid: u32) -> u32 {
+        let old = self.fsuid;
+        if self.has_cap(Capabilities::SETUID)
+            || [self.uid, self.euid, self.suid, self.fsuid].contains(&uid)
+        {
+            self.fsuid = uid;
+        }
+        if old == 0 && self.fsuid != 0 {
+            self.cap_effective -= Capabilities::FS_SET;
+        } else if old != 0 && self.fsuid == 0 {
+            self.cap_effective |= self.cap_permitted & Capabilities::FS_SET;
+        }
+        old
+    }
+
+    /// Set the group IDs by `setgid`, in the same way as [`Self::set_uid`].
+    pub fn set_gid(&mut self, gid: u32) -> LxResult {
+        if self.has_cap(Capabilities::SETGID) {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(LxError::EPERM);
+        }
+        self.egid = gid;
+        self.fsgid = gid;
+        Ok(())
+    }
+
+    /// Set the real and the effective group IDs by `setregid`, in the same
+    /// way as [`Self::set_reuid`].
+    pub fn set_regid(&mut self, rgid: Option<u32>, egid: Option<u32>) -> LxResult {
+        let old_gid = self.gid;
+        if !self.has_cap(Capabilities::SETGID) {
+            let rgid_allowed = rgid.map_or(true, |id| id == self.gid || id == self.egid);
+            let egid_allowed = egid.map_or(true, |id| {
+                id == self.gid || id == self.egid || id == self.sgid
+            });
+            if !rgid_allowed || !egid_allowed {
+                return Err(LxError::EPERM);
+            }
+        }
+        if let Some(id) = rgid {
+            self.gid = id;
+        }
+        if let Some(id) = egid {
+            self.egid = id;
+        }
+        if rgid.is_some() || egid.map_or(false, |id| id != old_gid) {
+            self.sgid = self.egid;
+        }
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Set the real, the effective and the saved group IDs by `setresgid`,
+    /// in the same way as [`Self::set_resuid`].
+    pub fn set_resgid(
+        &mut self,
+        rgid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> LxResult {
+        if !self.has_cap(Capabilities::SETGID) {
+            let ids = [self.gid, self.egid, self.sgid];
+            let allowed = |id: Option<u32>| id.map_or(true, |id| ids.contains(&id));
+            if !allowed(rgid) || !allowed(egid) || !allowed(sgid) {
+                return Err(LxError::EPERM);
+            }
+        }
+        self.gid = rgid.unwrap_or(self.gid);
+        self.egid = egid.unwrap_or(self.egid);
+        self.sgid = sgid.unwrap_or(self.sgid);
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Set the filesystem group ID by `setfsgid`, returns the previous one.
+    pub fn set_fsgid(&mut self, gid: u32) -> u32 {
+        let old = self.fsgid;
+        if self.has_cap(Capabilities::SETGID)
+            || [self.gid, self.egid, self.sgid, self.fsgid].contains(&gid)
+        {
+            self.fsgid = gid;
+        }
+        old
+    }
+
+    /// Set the supplementary group IDs by `setgroups`, which requires `CAP_SETGID`.
+    pub fn set_groups(&mut self, groups: Vec<u32>) -> LxResult {
+        if !self.has_cap(Capabilities::SETGID) {
+            return Err(LxError::EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(LxError::EINVAL);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Set the capabilities by `capset`.
+    ///
+    /// The permitted ones can only be dropped, the effective ones must be
+    /// permitted, and the inheritable ones can only be added from the
+    /// permitted ones, or any with `CAP_SETPCAP`.
+    pub fn set_caps(
+        &mut self,
+        effective: Capabilities,
+        permitted: Capabilities,
+        inheritable: Capabilities,
+    ) -> LxResult {
+        let inheritable_limit = if self.has_cap(Capabilities::SETPCAP) {
+            Capabilities::all()
+        } else {
+            self.cap_inheritable | self.cap_permitted
+        };
+        if !self.cap_permitted.contains(permitted)
+            || !permitted.contains(effective)
+            || !inheritable_limit.contains(inheritable)
+        {
+            return Err(LxError::EPERM);
+        }
+        self.cap_effective = effective;
+        self.cap_permitted = permitted;
+        self.cap_inheritable = inheritable;
+        Ok(())
+    }
+
+    /// Transform the credentials on `execve` of the file of `metadata`.
+    ///
+    /// The set-user-ID and set-group-ID bits are honored unless `ignore_set_id`.
+    /// As the files have no capabilities, root gains all of them, and the
+    /// others lose all.
+    pub fn exec(&mut self, metadata: &Metadata, ignore_set_id: bool) {
+        if !ignore_set_id {
+            if metadata.mode & S_ISUID != 0 {
+                self.euid = metadata.uid as u32;
+            }
+            // set-group-ID without group execute is for the mandatory locking
+            if metadata.mode & S_ISGID != 0 && metadata.mode & 0o010 != 0 {
+                self.egid = metadata.gid as u32;
+            }
+        }
+        self.suid = self.euid;
+        self.fsuid = self.euid;
+        self.sgid = self.egid;
+        self.fsgid = self.egid;
+        self.cap_permitted = if self.uid == 0 || self.euid == 0 {
+            Capabilities::all()
+        } else {
+            Capabilities::empty()
+        };
+        self.cap_effective = if self.euid == 0 {
+            self.cap_permitted
+        } else {
+            Capabilities::empty()
+        };
+    }
+
+    /// Adjust the capabilities after the user IDs changed from `old`, the
+    /// real, the effective and the saved ones.
+    fn fix_caps_after_setuid(&mut self, old: (u32, u32, u32)) {
+        let (old_uid, old_euid, old_suid) = old;
+        // lose all when none is root any more
+        if (old_uid == 0 || old_euid == 0 || old_suid == 0)
+            && self.uid != 0
+            && self.euid != 0
+            && self.suid != 0
+        {
+            self.cap_permitted = Capabilities::empty();
+            self.cap_effective = Capabilities::empty();
+        }
+        if old_euid == 0 && self.euid != 0 {
+            self.cap_effective = Capabilities::empty();
+        } else if old_euid != 0 && self.euid == 0 {
+            self.cap_effective = self.cap_permitted;
+        }
+    }
+}
@@ -95,16 +95,34 @@ pub enum LxError {
     ENOTEMPTY = 39,
     /// Too many symbolic links encountered
     ELOOP = 40,
+    /// No message of desired type
+    ENOMSG = 42,
     /// Identifier removed
     EIDRM = 43,
+    /// No data available
+    ENODATA = 61,
+    /// Timer expired
+    ETIME = 62,
     /// Socket operation on non-socket
     ENOTSOCK = 88,
+    /// Destination address required
+    EDESTADDRREQ = 89,
+    /// Protocol wrong type for socket
+    EPROTOTYPE = 91,
     /// Protocol not available
     ENOPROTOOPT = 92,
+    /// Protocol not supported
+    EPROTONOSUPPORT = 93,
+    /// Operation not supported
+    EOPNOTSUPP = 95,
     /// Protocol family not supported
     EPFNOSUPPORT = 96,
     /// Address family not supported by protocol
     EAFNOSUPPORT = 97,
+    /// Address already in use
+    EADDRINUSE = 98,
+    /// Network is unreachable
+    ENETUNREACH = 101,
     /// No buffer space available
     ENOBUFS = 105,
     /// Transport endpoint is already connected
@@ -115,6 +133,12 @@ pub enum LxError {
     ETIMEDOUT = 110,
     /// Connection refused
     ECONNREFUSED = 111,
+    /// Operation already in progress
+    EALREADY = 114,
+    /// Operation now in progress
+    EINPROGRESS = 115,
+    /// Operation canceled
+    ECANCELED = 125,
 }
 
 #[allow(non_snake_case)]
@@ -162,15 +186,27 @@ impl fmt::Display for LxError {
             ENOSYS => "Function not implemented",
             ENOTEMPTY => "Directory not empty",
             ELOOP => "Too many symbolic links encountered",
+            ENOMSG => "No message of desired type",
             EIDRM => "Identifier removed",
+            ENODATA => "No data available",
+            ETIME => "Timer expired",
             ENOTSOCK => "Socket operation on non-socket",
+            EDESTADDRREQ => "Destination address required",
+            EPROTOTYPE => "Protocol wrong type for socket",
             ENOPROTOOPT => "Protocol not available",
+            EPROTONOSUPPORT => "Protocol not supported",
+            EOPNOTSUPP => "Operation not supported",
             EPFNOSUPPORT => "Protocol family not supported",
             EAFNOSUPPORT => "Address family not supported by protocol",
+            EADDRINUSE => "Address already in use",
+            ENETUNREACH => "Network is unreachable",
             ENOBUFS => "No buffer space available",
             EISCONN => "Transport endpoint is already connected",
             ENOTCONN => "Transport endpoint is not connected",
             ECONNREFUSED => "Connection refused",
+            EALREADY => "Operation already in progress",
+            EINPROGRESS => "Operation now in progress",
+            ECANCELED => "Operation Canceled",
             _ => "Unknown error",
         };
         write!(f, "{}", explain)
@@ -189,6 +225,8 @@ impl From<ZxError> for LxError {
             ZxError::TIMED_OUT => LxError::ETIMEDOUT,
             ZxError::STOP => LxError::ESRCH,
             ZxError::BAD_STATE => LxError::EAGAIN,
+            ZxError::NO_MEMORY => LxError::ENOMEM,
+            ZxError::ACCESS_DENIED => LxError::EACCES,
             _ => unimplemented!("unknown error type: {:?}", e),
         }
     }
@@ -205,7 +243,7 @@ impl From<FsError> for LxError {
             FsError::EntryExist => LxError::EEXIST,
             FsError::NotSameFs => LxError::EXDEV,
             FsError::InvalidParam => LxError::EINVAL,
-            FsError::NoDeviceSpace => LxError::ENOMEM,
+            FsError::NoDeviceSpace => LxError::ENOSPC,
             FsError::DirRemoved => LxError::ENOENT,
             FsError::DirNotEmpty => LxError::ENOTEMPTY,
             FsError::WrongFs => LxError::EINVAL,
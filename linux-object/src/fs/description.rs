@@ -0,0 +1,34 @@
+//! Open file descriptions shared by duplicated files
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lock::Mutex;
+
+use super::FileLike;
+
+/// The open file description shared by a file and its duplicates, which is
+/// closed with the last of them.
+#[derive(Default)]
+pub struct OpenFileDescription {
+    /// the file and its duplicates
+    files: Mutex<Vec<Weak<dyn FileLike>>>,
+}
+
+impl OpenFileDescription {
+    /// Wrap `file` of the description, and record it in the description.
+    pub fn add<T: FileLike>(&self, file: T) -> Arc<T> {
+        let file = Arc::new(file);
+        let weak: Weak<dyn FileLike> = Arc::downgrade(&file);
+        let mut files = self.files.lock();
+        files.retain(|file| file.strong_count() > 0);
+        files.push(weak);
+        file
+    }
+
+    /// Returns any file of the description not closed.
+    pub fn file(&self) -> Option<Arc<dyn FileLike>> {
+        self.files.lock().iter().find_map(Weak::upgrade)
+    }
+}
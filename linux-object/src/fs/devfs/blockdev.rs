@@ -0,0 +1,119 @@
+use alloc::{sync::Arc, vec};
+use core::any::Any;
+use rcore_fs::vfs::{make_rdev, FileType, INode, Metadata, PollStatus, Result, Timespec};
+use rcore_fs_devfs::DevFS;
+use zcore_drivers::scheme::BlockScheme;
+
+use super::convert_error;
+
+/// Size of a block (sector) in bytes.
+const BLOCK_SIZE: usize = 512;
+
+/// Block device, accessed by bytes.
+pub struct BlockDev {
+    major: usize,
+    minor: usize,
+    block: Arc<dyn BlockScheme>,
+    inode_id: usize,
+}
+
+impl BlockDev {
+    /// Create a block device node with the device number `major:minor`.
+    pub fn new(major: usize, minor: usize, block: Arc<dyn BlockScheme>) -> Self {
+        Self {
+            major,
+            minor,
+            block,
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+
+    /// Returns the driver of the device, to open a file system on it.
+    pub fn block(&self) -> Arc<dyn BlockScheme> {
+        self.block.clone()
+    }
+}
+
+impl INode for BlockDev {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut block_buf = vec![0u8; BLOCK_SIZE];
+        let mut len = 0;
+        while len < buf.len() {
+            let pos = offset + len;
+            if let Err(e) = self.block.read_block(pos / BLOCK_SIZE, &mut block_buf) {
+                // reading past the end of the device
+                if len > 0 {
+                    break;
+                }
+                return Err(convert_error(e));
+            }
+            let begin = pos % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - begin).min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&block_buf[begin..begin + n]);
+            len += n;
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut block_buf = vec![0u8; BLOCK_SIZE];
+        let mut len = 0;
+        while len < buf.len() {
+            let pos = offset + len;
+            let block_id = pos / BLOCK_SIZE;
+            let begin = pos % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - begin).min(buf.len() - len);
+            if n < BLOCK_SIZE {
+                // partial block, read-modify-write
+                self.block
+                    .read_block(block_id, &mut block_buf)
+                    .map_err(convert_error)?;
+            }
+            block_buf[begin..begin + n].copy_from_slice(&buf[len..len + n]);
+            self.block
+                .write_block(block_id, &block_buf)
+                .map_err(convert_error)?;
+            len += n;
+        }
+        Ok(len)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        self.block.flush().map_err(convert_error)
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.sync_all()
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: BLOCK_SIZE,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::BlockDevice,
+            mode: 0o660,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(self.major, self.minor),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -14,7 +14,11 @@ use crate::error::{LxError, LxResult};
 
 // IOCTLs
 const FBIOGET_VSCREENINFO: u32 = 0x4600;
+const FBIOPUT_VSCREENINFO: u32 = 0x4601;
 const FBIOGET_FSCREENINFO: u32 = 0x4602;
+const FBIOPAN_DISPLAY: u32 = 0x4606;
+const FBIOBLANK: u32 = 0x4611;
+const FBIO_WAITFORVSYNC: u32 = 0x4004_4620;
 
 /// no hardware accelerator
 const FB_ACCEL_NONE: u32 = 0;
@@ -258,13 +262,24 @@ impl From<DisplayInfo> for FbVarScreeninfo {
 /// Framebuffer device
 pub struct FbDev {
     display: Arc<dyn DisplayScheme>,
+    /// The scanout buffer of the display, shared by all mappings
+    vmo: Option<Arc<VmObject>>,
     inode_id: usize,
 }
 
 impl FbDev {
     pub fn new(display: Arc<dyn DisplayScheme>) -> Self {
+        let info = display.info();
+        let vmo = match FbFixScreeninfo::from(info).smem_start {
+            u64::MAX => {
+                warn!("fbdev: framebuffer {:#x} not mapped", info.fb_base_vaddr);
+                None
+            }
+            paddr => Some(VmObject::new_physical(paddr as usize, pages(info.fb_size))),
+        };
         Self {
             display,
+            vmo,
             inode_id: DevFS::new_inode_id(),
         }
     }
@@ -274,12 +289,25 @@ impl FbDev {
         if !page_aligned(offset) || offset >= info.fb_size {
             return Err(LxError::EINVAL);
         }
-        let paddr = FbFixScreeninfo::from(info).smem_start;
-        if paddr == u64::MAX {
-            return Err(LxError::ENOMEM);
-        }
+        let vmo = self.vmo.as_ref().ok_or(LxError::ENOMEM)?;
         let len = len.min(info.fb_size - offset);
-        Ok(VmObject::new_physical(paddr as usize + offset, pages(len)))
+        Ok(vmo.create_slice(offset, len)?)
+    }
+
+    /// Only the current mode can be set, with no panning.
+    fn check_var_screeninfo(&self, var: &FbVarScreeninfo) -> Result<()> {
+        let info = self.display.info();
+        if var.xres != info.width
+            || var.yres != info.height
+            || var.xres_virtual > info.width
+            || var.yres_virtual > info.height
+            || var.xoffset != 0
+            || var.yoffset != 0
+            || (var.bits_per_pixel != 0 && var.bits_per_pixel != info.format.depth() as u32)
+        {
+            return Err(FsError::InvalidParam);
+        }
+        Ok(())
     }
 }
 
@@ -317,6 +345,9 @@ impl INode for FbDev {
         let len = buf.len().min(info.fb_size - offset);
         let mut fb = self.display.fb();
         fb[offset..offset + len].copy_from_slice(&buf[..len]);
+        if self.display.need_flush() {
+            self.display.flush().map_err(|_| FsError::DeviceError)?;
+        }
         Ok(len)
     }
 
@@ -361,6 +392,24 @@ impl INode for FbDev {
                 *dst = self.display.info().into();
                 Ok(0)
             }
+            FBIOPUT_VSCREENINFO => {
+                let var = unsafe { &mut *(data as *mut FbVarScreeninfo) };
+                self.check_var_screeninfo(var)?;
+                // report the mode actually set
+                *var = self.display.info().into();
+                Ok(0)
+            }
+            FBIOPAN_DISPLAY => {
+                let var = unsafe { &*(data as *const FbVarScreeninfo) };
+                if var.xoffset != 0 || var.yoffset != 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                if self.display.need_flush() {
+                    self.display.flush().map_err(|_| FsError::DeviceError)?;
+                }
+                Ok(0)
+            }
+            FBIOBLANK | FBIO_WAITFORVSYNC => Ok(0),
             _ => {
                 warn!("use never support ioctl !");
                 Err(FsError::NotSupported)
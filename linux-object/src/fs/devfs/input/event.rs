@@ -1,10 +1,10 @@
 use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
 use core::task::{Context, Poll};
-use core::{any::Any, future::Future, mem::size_of, pin::Pin};
+use core::{any::Any, convert::TryFrom, future::Future, mem::size_of, pin::Pin};
 
 use lock::Mutex;
 
-use kernel_hal::drivers::prelude::{InputEvent, InputEventType};
+use kernel_hal::drivers::prelude::{CapabilityType, InputCapability, InputEvent, InputEventType};
 use kernel_hal::drivers::scheme::InputScheme;
 use rcore_fs::vfs::*;
 use rcore_fs_devfs::DevFS;
@@ -15,6 +15,49 @@ const BUF_CAPACITY: usize = 64;
 
 const EVENT_DEV_MINOR_BASE: usize = 0x40;
 
+// ioctls, see `include/uapi/linux/input.h` of Linux
+const EV_IOC_TYPE: u32 = b'E' as u32;
+const EVIOCGVERSION: u32 = 0x01;
+const EVIOCGID: u32 = 0x02;
+const EVIOCGNAME: u32 = 0x06;
+const EVIOCGPHYS: u32 = 0x07;
+const EVIOCGUNIQ: u32 = 0x08;
+const EVIOCGPROP: u32 = 0x09;
+const EVIOCGKEY: u32 = 0x18;
+const EVIOCGLED: u32 = 0x19;
+const EVIOCGSND: u32 = 0x1a;
+const EVIOCGSW: u32 = 0x1b;
+/// `EVIOCGBIT(ev, len)` is numbered `0x20 + ev`
+const EVIOCGBIT: u32 = 0x20;
+/// `EVIOCGABS(abs)` is numbered `0x40 + abs`
+const EVIOCGABS: u32 = 0x40;
+const EVIOCGRAB: u32 = 0x90;
+const EVIOCSCLOCKID: u32 = 0xa0;
+
+const EV_VERSION: i32 = 0x010001;
+const EV_MAX: u32 = 0x1f;
+const ABS_CNT: usize = 0x40;
+
+/// `struct input_id`
+#[repr(C)]
+struct InputIdRaw {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+/// `struct input_absinfo`
+#[repr(C)]
+struct InputAbsInfoRaw {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
 /// The event structure itself
 #[repr(C)]
 struct TimedInputEvent {
@@ -26,6 +69,16 @@ struct TimedInputEvent {
 
 struct EventDevInner {
     buf: VecDeque<TimedInputEvent>,
+    /// The keys pressed, for `EVIOCGKEY`
+    keys: InputCapability,
+    /// The LEDs on, for `EVIOCGLED`
+    leds: InputCapability,
+    /// The sounds on, for `EVIOCGSND`
+    sounds: InputCapability,
+    /// The switches on, for `EVIOCGSW`
+    switches: InputCapability,
+    /// The last values of the absolute axes, for `EVIOCGABS`
+    abs_values: [i32; ABS_CNT],
 }
 
 /// Event char device, giving access to raw input device events.
@@ -74,6 +127,25 @@ impl EventDevInner {
     }
 
     fn handle_input_event(&mut self, e: &InputEvent) {
+        let state = match e.event_type {
+            InputEventType::Key => Some(&mut self.keys),
+            InputEventType::Led => Some(&mut self.leds),
+            InputEventType::Sound => Some(&mut self.sounds),
+            InputEventType::Switch => Some(&mut self.switches),
+            _ => None,
+        };
+        if let Some(state) = state {
+            if e.value != 0 {
+                state.set(e.code);
+            } else {
+                state.unset(e.code);
+            }
+        }
+        if let (InputEventType::AbsAxis, Some(value)) =
+            (e.event_type, self.abs_values.get_mut(e.code as usize))
+        {
+            *value = e.value;
+        }
         while self.buf.len() >= BUF_CAPACITY {
             self.buf.pop_front();
         }
@@ -86,6 +158,11 @@ impl EventDev {
     pub fn new(input: Arc<dyn InputScheme>, id: usize) -> Self {
         let inner = Arc::new(Mutex::new(EventDevInner {
             buf: VecDeque::with_capacity(BUF_CAPACITY),
+            keys: InputCapability::empty(),
+            leds: InputCapability::empty(),
+            sounds: InputCapability::empty(),
+            switches: InputCapability::empty(),
+            abs_values: [0; ABS_CNT],
         }));
         let cloned = inner.clone();
         input.subscribe(
@@ -103,6 +180,25 @@ impl EventDev {
     fn can_read(&self) -> bool {
         !self.inner.lock().buf.is_empty()
     }
+    /// The bitmap of the `ev` events the device has, for `EVIOCGBIT`.
+    fn event_bits(&self, ev: u16) -> Option<[u8; 128]> {
+        let cap_type = if ev == 0 {
+            CapabilityType::Event
+        } else {
+            match InputEventType::try_from(ev) {
+                Ok(InputEventType::Key) => CapabilityType::Key,
+                Ok(InputEventType::RelAxis) => CapabilityType::RelAxis,
+                Ok(InputEventType::AbsAxis) => CapabilityType::AbsAxis,
+                Ok(InputEventType::Misc) => CapabilityType::Misc,
+                Ok(InputEventType::Switch) => CapabilityType::Switch,
+                Ok(InputEventType::Led) => CapabilityType::Led,
+                Ok(InputEventType::Sound) => CapabilityType::Sound,
+                Ok(InputEventType::FeedBack) => CapabilityType::FeedBack,
+                _ => return None,
+            }
+        };
+        Some(self.input.capability(cap_type).to_bytes())
+    }
 }
 
 impl INode for EventDev {
@@ -167,6 +263,78 @@ impl INode for EventDev {
         })
     }
 
+    #[allow(unsafe_code)]
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        if (cmd >> 8) & 0xff != EV_IOC_TYPE {
+            return Err(FsError::NotSupported);
+        }
+        let nr = cmd & 0xff;
+        let size = ((cmd >> 16) & 0x3fff) as usize;
+        // copy to the user buffer, and return the bytes copied
+        let copy_out = |src: &[u8]| -> Result<usize> {
+            let len = src.len().min(size);
+            let dst = unsafe { core::slice::from_raw_parts_mut(data as *mut u8, len) };
+            dst.copy_from_slice(&src[..len]);
+            Ok(len)
+        };
+        match nr {
+            EVIOCGVERSION => {
+                unsafe { *(data as *mut i32) = EV_VERSION };
+                Ok(0)
+            }
+            EVIOCGID => {
+                let id = self.input.id();
+                let dst = unsafe { &mut *(data as *mut InputIdRaw) };
+                *dst = InputIdRaw {
+                    bustype: id.bustype,
+                    vendor: id.vendor,
+                    product: id.product,
+                    version: id.version,
+                };
+                Ok(0)
+            }
+            EVIOCGNAME => {
+                let mut name = self.input.name().as_bytes().to_vec();
+                name.push(0);
+                copy_out(&name)
+            }
+            EVIOCGPHYS | EVIOCGUNIQ => Err(FsError::EntryNotFound),
+            EVIOCGPROP => copy_out(&self.input.capability(CapabilityType::InputProp).to_bytes()),
+            EVIOCGKEY => copy_out(&self.inner.lock().keys.to_bytes()),
+            EVIOCGLED => copy_out(&self.inner.lock().leds.to_bytes()),
+            EVIOCGSND => copy_out(&self.inner.lock().sounds.to_bytes()),
+            EVIOCGSW => copy_out(&self.inner.lock().switches.to_bytes()),
+            nr if (EVIOCGBIT..=EVIOCGBIT + EV_MAX).contains(&nr) => {
+                match self.event_bits((nr - EVIOCGBIT) as u16) {
+                    Some(bits) => copy_out(&bits),
+                    None => Err(FsError::InvalidParam),
+                }
+            }
+            nr if (EVIOCGABS..EVIOCGABS + ABS_CNT as u32).contains(&nr) => {
+                let axis = (nr - EVIOCGABS) as u16;
+                let info = self.input.abs_info(axis).ok_or(FsError::InvalidParam)?;
+                let dst = unsafe { &mut *(data as *mut InputAbsInfoRaw) };
+                *dst = InputAbsInfoRaw {
+                    value: self.inner.lock().abs_values[axis as usize],
+                    minimum: info.min,
+                    maximum: info.max,
+                    fuzz: info.fuzz,
+                    flat: info.flat,
+                    resolution: info.resolution,
+                };
+                Ok(0)
+            }
+            // the events are not delivered to other handlers anyway
+            EVIOCGRAB => Ok(0),
+            // the events are always timestamped with the realtime clock
+            EVIOCSCLOCKID => Ok(0),
+            _ => {
+                warn!("evdev: unsupported ioctl {:#x}", cmd);
+                Err(FsError::NotSupported)
+            }
+        }
+    }
+
     fn as_any_ref(&self) -> &dyn Any {
         self
     }
@@ -0,0 +1,183 @@
+//! Implement INode for `/dev/kmsg`, the kernel log of the Linux userspace
+
+use alloc::{boxed::Box, format, string::String, sync::Arc};
+use core::any::Any;
+use core::future::Future;
+use core::pin::Pin;
+
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+use zircon_object::debuglog::{DebugLog, Severity, FLAG_READABLE};
+use zircon_object::object::{KernelObject, Signal};
+
+/// The `/dev/kmsg` device, each open of it reads the kernel log from the oldest record.
+pub struct Kmsg {
+    inode_id: usize,
+}
+
+impl Default for Kmsg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kmsg {
+    /// Create the `/dev/kmsg` INode.
+    pub fn new() -> Self {
+        Kmsg {
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+
+    /// Create a reader of the kernel log for an open file.
+    pub fn open(&self) -> Result<Arc<dyn INode>> {
+        Ok(Arc::new(KmsgFile {
+            dlog: DebugLog::create(FLAG_READABLE),
+            inode_id: self.inode_id,
+        }))
+    }
+}
+
+/// The syslog level of a record, from 0 (`KERN_EMERG`) to 7 (`KERN_DEBUG`).
+pub fn syslog_level(severity: Severity) -> u8 {
+    match severity {
+        Severity::Fatal => 2,
+        Severity::Error => 3,
+        Severity::Warning => 4,
+        Severity::Info => 6,
+        Severity::Debug | Severity::Trace => 7,
+    }
+}
+
+fn severity_of(level: u8) -> Severity {
+    match level {
+        0..=2 => Severity::Fatal,
+        3 => Severity::Error,
+        4 => Severity::Warning,
+        5 | 6 => Severity::Info,
+        _ => Severity::Debug,
+    }
+}
+
+/// An open file of `/dev/kmsg`, reads a record each time.
+struct KmsgFile {
+    dlog: Arc<DebugLog>,
+    inode_id: usize,
+}
+
+impl INode for KmsgFile {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let record = self.dlog.read_record().ok_or(FsError::Again)?;
+        // `<level>,<seq>,<timestamp in us>,<flags>;<message>`
+        let line = format!(
+            "{},{},{},-;{}\n",
+            syslog_level(record.severity),
+            record.seq,
+            record.timestamp / 1000,
+            String::from_utf8_lossy(&record.data).trim_end_matches('\n'),
+        );
+        if buf.len() < line.len() {
+            return Err(FsError::InvalidParam);
+        }
+        buf[..line.len()].copy_from_slice(line.as_bytes());
+        Ok(line.len())
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        let text = text.trim_end_matches('\n');
+        // an optional `<N>` prefix sets the level
+        let (severity, msg) = match text
+            .strip_prefix('<')
+            .and_then(|s| s.split_once('>'))
+            .and_then(|(n, msg)| Some((n.parse::<u32>().ok()?, msg)))
+        {
+            Some((n, msg)) => (severity_of((n & 7) as u8), msg),
+            None => (Severity::Info, text),
+        };
+        self.dlog.write(severity, 0, 0, 0, msg);
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: self.dlog.signal().contains(Signal::READABLE),
+            write: true,
+            error: false,
+        })
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        let dlog: Arc<dyn KernelObject> = self.dlog.clone();
+        Box::pin(async move {
+            dlog.wait_signal(Signal::READABLE).await;
+            Ok(PollStatus {
+                read: true,
+                write: true,
+                error: false,
+            })
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o644,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(1, 11),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl INode for Kmsg {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::NotSupported)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o644,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(1, 11),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
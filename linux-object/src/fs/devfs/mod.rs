@@ -1,9 +1,203 @@
+mod blockdev;
 mod fbdev;
 mod input;
+mod kmsg;
 mod random;
+mod rtc;
 mod uartdev;
 
+pub use blockdev::BlockDev;
 pub use fbdev::FbDev;
 pub use input::{EventDev, MiceDev};
+pub use kmsg::{syslog_level, Kmsg};
 pub use random::RandomINode;
+pub use rtc::RtcDev;
 pub use uartdev::UartDev;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use kernel_hal::drivers;
+use rcore_fs::vfs::{FsError, INode};
+use rcore_fs_devfs::special::{NullINode, ZeroINode};
+use zcore_drivers::DeviceError;
+
+use super::stdio::ConsoleTty;
+use super::Ptmx;
+
+/// A device node exported to `/dev` and `/sys`.
+pub struct DeviceNode {
+    /// Path relative to `/dev`, e.g. `input/event0`
+    pub path: String,
+    /// Device class, i.e. the directory in `/sys/class`
+    pub class: &'static str,
+    /// Major device number
+    pub major: usize,
+    /// Minor device number
+    pub minor: usize,
+    /// The device INode
+    pub inode: Arc<dyn INode>,
+}
+
+impl DeviceNode {
+    fn new(
+        path: String,
+        class: &'static str,
+        major: usize,
+        minor: usize,
+        inode: Arc<dyn INode>,
+    ) -> Self {
+        DeviceNode {
+            path,
+            class,
+            major,
+            minor,
+            inode,
+        }
+    }
+
+    /// The file name of the node, e.g. `event0`
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap()
+    }
+}
+
+/// Collect device nodes from the kernel's device registry.
+pub fn device_nodes() -> Vec<DeviceNode> {
+    let mut nodes = Vec::new();
+    let mut add =
+        |path: String, class: &'static str, major: usize, minor: usize, inode: Arc<dyn INode>| {
+            nodes.push(DeviceNode::new(path, class, major, minor, inode))
+        };
+
+    // memory devices
+    add("null".into(), "mem", 1, 3, Arc::new(NullINode::new()));
+    add("zero".into(), "mem", 1, 5, Arc::new(ZeroINode::new()));
+    add(
+        "random".into(),
+        "mem",
+        1,
+        8,
+        Arc::new(RandomINode::new(false)),
+    );
+    add(
+        "urandom".into(),
+        "mem",
+        1,
+        9,
+        Arc::new(RandomINode::new(true)),
+    );
+    add("kmsg".into(), "mem", 1, 11, Arc::new(Kmsg::new()));
+
+    // terminals
+    add("tty".into(), "tty", 5, 0, Arc::new(ConsoleTty::new(0)));
+    add("console".into(), "tty", 5, 1, Arc::new(ConsoleTty::new(1)));
+    add("ptmx".into(), "tty", 5, 2, Arc::new(Ptmx::new()));
+    // serial ports as `/dev/ttySX`, and virtio console ports as `/dev/hvcX`
+    let (mut ttys, mut hvc) = (0, 0);
+    for uart in drivers::all_uart().as_vec().iter() {
+        let (path, major, minor) = if uart.name().starts_with("virtio-console") {
+            hvc += 1;
+            (format!("hvc{}", hvc - 1), 229, hvc - 1)
+        } else {
+            ttys += 1;
+            (format!("ttyS{}", ttys - 1), 4, 64 + ttys - 1)
+        };
+        let inode = Arc::new(UartDev::new(major, minor, uart.clone()));
+        add(path, "tty", major, minor, inode);
+    }
+
+    // block devices, as `/dev/vdX` for virtio, `/dev/mmcblkX` for SD cards
+    // and `/dev/sdX` for others, followed by their partitions, e.g.
+    // `/dev/vda1` and `/dev/mmcblk0p1`
+    let (mut vd, mut mmc, mut sd) = (0, 0, 0);
+    for block in drivers::all_block().as_vec().iter() {
+        let (prefix, major, minors, index) = if block.name().contains("virtio") {
+            vd += 1;
+            ("vd", 254, 16, vd - 1)
+        } else if block.name().starts_with("mmc") {
+            mmc += 1;
+            ("mmcblk", 179, 8, mmc - 1)
+        } else {
+            sd += 1;
+            ("sd", 8, 16, sd - 1)
+        };
+        let name = if prefix == "mmcblk" {
+            format!("{}{}", prefix, index)
+        } else {
+            format!("{}{}", prefix, (b'a' + index as u8) as char)
+        };
+        let inode = Arc::new(BlockDev::new(major, index * minors, block.clone()));
+        add(name.clone(), "block", major, index * minors, inode);
+        let partitions = drivers::block::scan_partitions(block).unwrap_or_else(|e| {
+            warn!("failed to read the partition table of {}: {:?}", name, e);
+            Vec::new()
+        });
+        for part in partitions {
+            // the first minor number of a disk is the disk itself
+            if part.number() >= minors {
+                warn!("too many partitions on {}, ignore {}", name, part.number());
+                continue;
+            }
+            // a digit at the end of the disk name is followed by `p`
+            let sep = if name.ends_with(|c: char| c.is_ascii_digit()) {
+                "p"
+            } else {
+                ""
+            };
+            let path = format!("{}{}{}", name, sep, part.number());
+            let minor = index * minors + part.number();
+            add(
+                path,
+                "block",
+                major,
+                minor,
+                Arc::new(BlockDev::new(major, minor, part)),
+            );
+        }
+    }
+
+    // real-time clocks at `/dev/rtcX`
+    for (id, rtc) in drivers::all_rtc().as_vec().iter().enumerate() {
+        let inode = Arc::new(RtcDev::new(id, rtc.clone()));
+        add(format!("rtc{}", id), "rtc", RtcDev::MAJOR, id, inode);
+    }
+
+    if let Some(display) = drivers::all_display().first() {
+        // framebuffer device at `/dev/fb0`
+        add(
+            "fb0".into(),
+            "graphics",
+            29,
+            0,
+            Arc::new(FbDev::new(display)),
+        );
+    }
+
+    // mouse devices at `/dev/input/mouseX` and `/dev/input/mice`
+    for (id, m) in MiceDev::from_input_devices(&drivers::all_input().as_vec()) {
+        let (path, minor) = match id {
+            Some(id) => (format!("input/mouse{}", id), 32 + id),
+            None => ("input/mice".into(), 63),
+        };
+        add(path, "input", 13, minor, Arc::new(m));
+    }
+
+    // input event devices at `/dev/input/eventX`
+    for (id, i) in drivers::all_input().as_vec().iter().enumerate() {
+        let inode = Arc::new(EventDev::new(i.clone(), id));
+        add(format!("input/event{}", id), "input", 13, 64 + id, inode);
+    }
+    nodes
+}
+
+fn convert_error(e: DeviceError) -> FsError {
+    match e {
+        DeviceError::NotSupported => FsError::NotSupported,
+        DeviceError::NotReady => FsError::Busy,
+        DeviceError::InvalidParam => FsError::InvalidParam,
+        DeviceError::BufferTooSmall
+        | DeviceError::DmaError
+        | DeviceError::IoError
+        | DeviceError::AlreadyExists
+        | DeviceError::NoResources => FsError::DeviceError,
+    }
+}
@@ -1,61 +1,47 @@
 //! Implement INode for RandomINode
 
-use alloc::sync::Arc;
 use core::any::Any;
 
-use lock::Mutex;
 use rcore_fs::vfs::*;
 use rcore_fs_devfs::DevFS;
 
-/// random INode data struct
-pub struct RandomINodeData {
-    seed: u32,
-}
-
 /// random INode struct
+///
+/// Both `/dev/random` and `/dev/urandom` read from the kernel entropy pool,
+/// and mix the bytes written into it.
 #[derive(Clone)]
 pub struct RandomINode {
-    secure: bool,
+    urandom: bool,
     inode_id: usize,
-    data: Arc<Mutex<RandomINodeData>>,
 }
 
 impl RandomINode {
     /// create a random INode
-    /// - urandom -> secure = true
-    /// - random -> secure = false
-    pub fn new(secure: bool) -> RandomINode {
+    /// - urandom -> urandom = true
+    /// - random -> urandom = false
+    pub fn new(urandom: bool) -> RandomINode {
         RandomINode {
-            secure,
+            urandom,
             inode_id: DevFS::new_inode_id(),
-            data: Arc::new(Mutex::new(RandomINodeData { seed: 1 })),
         }
     }
 }
 
 impl INode for RandomINode {
     fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
-        if self.secure {
-            kernel_hal::rand::fill_random(buf)
-        } else {
-            let mut data = self.data.lock();
-            // from K&R
-            for x in buf.iter_mut() {
-                data.seed = data.seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
-                *x = (data.seed / 65536) as u8;
-            }
-        }
+        kernel_hal::rand::fill_random(buf);
         Ok(buf.len())
     }
 
-    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
-        Err(FsError::NotSupported)
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        kernel_hal::rand::add_entropy(buf);
+        Ok(buf.len())
     }
 
     fn poll(&self) -> Result<PollStatus> {
         Ok(PollStatus {
             read: true,
-            write: false,
+            write: true,
             error: false,
         })
     }
@@ -75,7 +61,7 @@ impl INode for RandomINode {
             nlinks: 1,
             uid: 0,
             gid: 0,
-            rdev: make_rdev(1, if self.secure { 9 } else { 8 }),
+            rdev: make_rdev(1, if self.urandom { 9 } else { 8 }),
         })
     }
 
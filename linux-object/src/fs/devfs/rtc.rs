@@ -0,0 +1,165 @@
+use alloc::sync::Arc;
+use core::any::Any;
+use core::time::Duration;
+
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+use zcore_drivers::prelude::RtcTime;
+use zcore_drivers::scheme::RtcScheme;
+
+use super::convert_error;
+
+const RTC_RD_TIME: u32 = 0x8024_7009;
+const RTC_SET_TIME: u32 = 0x4024_700a;
+
+/// `struct rtc_time`, the same as `struct tm`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct LinuxRtcTime {
+    tm_sec: i32,
+    tm_min: i32,
+    tm_hour: i32,
+    tm_mday: i32,
+    /// 0 to 11
+    tm_mon: i32,
+    /// years since 1900
+    tm_year: i32,
+    tm_wday: i32,
+    tm_yday: i32,
+    tm_isdst: i32,
+}
+
+impl From<RtcTime> for LinuxRtcTime {
+    fn from(t: RtcTime) -> Self {
+        Self {
+            tm_sec: t.second as _,
+            tm_min: t.minute as _,
+            tm_hour: t.hour as _,
+            tm_mday: t.day as _,
+            tm_mon: t.month as i32 - 1,
+            tm_year: t.year as i32 - 1900,
+            tm_wday: t.weekday() as _,
+            tm_yday: t.yearday() as _,
+            tm_isdst: 0,
+        }
+    }
+}
+
+impl LinuxRtcTime {
+    /// Check the fields as `rtc_valid_tm()` of Linux.
+    fn to_rtc_time(&self) -> Result<RtcTime> {
+        let year = self.tm_year + 1900;
+        let month = self.tm_mon + 1;
+        let days_in_month = match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        if year < 1970
+            || !(1..=12).contains(&month)
+            || self.tm_mday < 1
+            || self.tm_mday > days_in_month
+            || !(0..24).contains(&self.tm_hour)
+            || !(0..60).contains(&self.tm_min)
+            || !(0..60).contains(&self.tm_sec)
+        {
+            return Err(FsError::InvalidParam);
+        }
+        Ok(RtcTime {
+            year: year as _,
+            month: month as _,
+            day: self.tm_mday as _,
+            hour: self.tm_hour as _,
+            minute: self.tm_min as _,
+            second: self.tm_sec as _,
+        })
+    }
+}
+
+/// Real-time clock device, as `/dev/rtcX`.
+pub struct RtcDev {
+    minor: usize,
+    rtc: Arc<dyn RtcScheme>,
+    inode_id: usize,
+}
+
+impl RtcDev {
+    /// The major device number, allocated dynamically in Linux.
+    pub const MAJOR: usize = 248;
+
+    /// Create a device node for the RTC.
+    pub fn new(minor: usize, rtc: Arc<dyn RtcScheme>) -> Self {
+        Self {
+            minor,
+            rtc,
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+}
+
+impl INode for RtcDev {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        // no RTC interrupts
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o600, // owner read & write
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(Self::MAJOR, self.minor),
+        })
+    }
+
+    #[allow(unsafe_code)]
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd {
+            RTC_RD_TIME => {
+                let time = self.rtc.read_time().map_err(convert_error)?;
+                let dst = unsafe { &mut *(data as *mut LinuxRtcTime) };
+                *dst = RtcTime::from_unix(time.as_secs()).into();
+                Ok(0)
+            }
+            RTC_SET_TIME => {
+                let src = unsafe { &*(data as *const LinuxRtcTime) };
+                let time = src.to_rtc_time()?;
+                self.rtc
+                    .set_time(Duration::from_secs(time.to_unix()))
+                    .map_err(convert_error)?;
+                Ok(0)
+            }
+            _ => {
+                warn!("rtc ioctl {:#x} unimplemented", cmd);
+                Err(FsError::NotSupported)
+            }
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,20 +1,24 @@
 ﻿use alloc::sync::Arc;
 use core::any::Any;
-use rcore_fs::vfs::{make_rdev, FileType, FsError, INode, Metadata, PollStatus, Result, Timespec};
+use rcore_fs::vfs::{make_rdev, FileType, INode, Metadata, PollStatus, Result, Timespec};
 use rcore_fs_devfs::DevFS;
-use zcore_drivers::{scheme::UartScheme, DeviceError};
+use zcore_drivers::scheme::UartScheme;
 
-/// Uart device.
+use super::convert_error;
+
+/// Uart device, as `/dev/ttySX`, or `/dev/hvcX` for a port of a virtio console.
 pub struct UartDev {
-    index: usize,
+    major: usize,
+    minor: usize,
     port: Arc<dyn UartScheme>,
     inode_id: usize,
 }
 
 impl UartDev {
-    pub fn new(index: usize, port: Arc<dyn UartScheme>) -> Self {
+    pub fn new(major: usize, minor: usize, port: Arc<dyn UartScheme>) -> Self {
         Self {
-            index,
+            major,
+            minor,
             port,
             inode_id: DevFS::new_inode_id(),
         }
@@ -80,7 +84,7 @@ impl INode for UartDev {
             nlinks: 1,
             uid: 0,
             gid: 0,
-            rdev: make_rdev(4, self.index),
+            rdev: make_rdev(self.major, self.minor),
         })
     }
 
@@ -94,16 +98,3 @@ impl INode for UartDev {
         self
     }
 }
-
-fn convert_error(e: DeviceError) -> FsError {
-    match e {
-        DeviceError::NotSupported => FsError::NotSupported,
-        DeviceError::NotReady => FsError::Busy,
-        DeviceError::InvalidParam => FsError::InvalidParam,
-        DeviceError::BufferTooSmall
-        | DeviceError::DmaError
-        | DeviceError::IoError
-        | DeviceError::AlreadyExists
-        | DeviceError::NoResources => FsError::DeviceError,
-    }
-}
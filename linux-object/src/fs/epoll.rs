@@ -0,0 +1,567 @@
+//! Implement epoll instance
+#![deny(missing_docs)]
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+use core::time::Duration;
+
+use async_trait::async_trait;
+use kernel_hal::timer;
+use lock::Mutex;
+use rcore_fs::vfs::PollStatus;
+use zircon_object::object::*;
+
+use super::{ChangeCallback, FileDesc, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::sync::EventBus;
+
+bitflags::bitflags! {
+    /// Epoll event flags
+    #[derive(Default)]
+    pub struct EpollEvents: u32 {
+        /// The associated file is available for read operations.
+        const IN = 0x001;
+        /// There is an exceptional condition on the file descriptor.
+        const PRI = 0x002;
+        /// The associated file is available for write operations.
+        const OUT = 0x004;
+        /// Error condition happened on the associated file descriptor.
+        const ERR = 0x008;
+        /// Hang up happened on the associated file descriptor.
+        const HUP = 0x010;
+        /// Normal data can be read.
+        const RDNORM = 0x040;
+        /// Priority data can be read.
+        const RDBAND = 0x080;
+        /// Normal data can be written.
+        const WRNORM = 0x100;
+        /// Priority data can be written.
+        const WRBAND = 0x200;
+        /// Stream socket peer closed connection.
+        const RDHUP = 0x2000;
+        /// Sets an exclusive wakeup mode for the epoll file descriptor.
+        const EXCLUSIVE = 1 << 28;
+        /// Prevent system suspend while the event is pending.
+        const WAKEUP = 1 << 29;
+        /// Disable the file descriptor after an event is reported.
+        const ONESHOT = 1 << 30;
+        /// Requests edge-triggered notification.
+        const ET = 1 << 31;
+    }
+}
+
+/// Linux struct epoll_event
+#[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+#[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EpollEvent {
+    /// Epoll events
+    pub events: EpollEvents,
+    /// User data variable
+    pub data: u64,
+}
+
+/// Operations of `epoll_ctl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpollCtlOp {
+    /// Add an entry to the interest list.
+    Add,
+    /// Change the settings of an entry in the interest list.
+    Modify,
+    /// Remove an entry from the interest list.
+    Delete,
+}
+
+impl EpollCtlOp {
+    /// Convert from the `op` argument of `epoll_ctl`.
+    pub fn from_raw(op: usize) -> LxResult<Self> {
+        match op {
+            1 => Ok(EpollCtlOp::Add),
+            2 => Ok(EpollCtlOp::Delete),
+            3 => Ok(EpollCtlOp::Modify),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// The key of an entry in the interest list, the file descriptor and the
+/// address of the open file description it refers to, so that the descriptor
+/// is watched again after reused by another file.
+type InterestKey = (FileDesc, usize);
+
+/// The file watched by an entry, referred weakly not to keep it open.
+enum WatchedFile {
+    /// a file without duplicates, closed when dropped
+    File(Weak<dyn FileLike>),
+    /// the open file description of a file and its duplicates, closed with
+    /// the last of them
+    Description(Weak<OpenFileDescription>),
+}
+
+impl WatchedFile {
+    fn new(file: &Arc<dyn FileLike>) -> Self {
+        match file.description() {
+            Some(description) => WatchedFile::Description(Arc::downgrade(&description)),
+            None => WatchedFile::File(Arc::downgrade(file)),
+        }
+    }
+
+    /// The address of the file or the open file description.
+    fn address(&self) -> usize {
+        match self {
+            WatchedFile::File(file) => file.as_ptr() as *const u8 as usize,
+            WatchedFile::Description(description) => description.as_ptr() as usize,
+        }
+    }
+
+    /// Returns a file to poll, `None` after the file is closed.
+    fn upgrade(&self) -> Option<Arc<dyn FileLike>> {
+        match self {
+            WatchedFile::File(file) => file.upgrade(),
+            WatchedFile::Description(description) => description.upgrade()?.file(),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match self {
+            WatchedFile::File(file) => file.strong_count() == 0,
+            WatchedFile::Description(description) => description.strong_count() == 0,
+        }
+    }
+}
+
+/// An entry of the interest list
+struct EpollEntry {
+    /// the file being watched, the entry is removed after it is closed
+    file: WatchedFile,
+    /// the requested events and user data
+    event: EpollEvent,
+    /// events reported by the last wait, used by edge-triggered mode
+    reported: EpollEvents,
+    /// disabled by `EPOLLONESHOT` until the next `EPOLL_CTL_MOD`
+    disabled: bool,
+    /// shared with the callback told of the changes of the file
+    state: Arc<EntryState>,
+}
+
+/// The state of an entry updated by the changes of the file
+#[derive(Default)]
+struct EntryState {
+    /// whether a callback is waiting for the next change of the file
+    armed: AtomicBool,
+    /// whether the file changed since the last wait, a new edge for
+    /// edge-triggered mode
+    changed: AtomicBool,
+}
+
+/// An epoll instance
+pub struct EpollInstance {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    /// the interest list
+    interests: Arc<Mutex<BTreeMap<InterestKey, EpollEntry>>>,
+    /// notified on the changes of the files in the interest list
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+impl_kobject!(EpollInstance);
+
+impl EpollInstance {
+    /// Create a new epoll instance.
+    pub fn new(flags: OpenFlags) -> Arc<Self> {
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(EpollInstance {
+            base: KObjectBase::new(),
+            flags: Mutex::new(flags),
+            description: description.clone(),
+            interests: Arc::new(Mutex::new(BTreeMap::new())),
+            eventbus: EventBus::new(),
+        })
+    }
+
+    /// Add, modify or delete the entry of `fd` referring to `file` in the
+    /// interest list.
+    pub fn control(
+        &self,
+        op: EpollCtlOp,
+        fd: FileDesc,
+        file: Arc<dyn FileLike>,
+        event: EpollEvent,
+    ) -> LxResult {
+        if let Some(epoll) = file.downcast_ref::<EpollInstance>() {
+            if Arc::ptr_eq(&epoll.interests, &self.interests) {
+                return Err(LxError::EINVAL);
+            }
+            if op == EpollCtlOp::Add && epoll.watches(&self.interests) {
+                return Err(LxError::ELOOP);
+            }
+        }
+        let watched = WatchedFile::new(&file);
+        let key = (fd, watched.address());
+        let mut interests = self.interests.lock();
+        remove_closed(&mut interests);
+        match op {
+            EpollCtlOp::Add => {
+                if interests.contains_key(&key) {
+                    return Err(LxError::EEXIST);
+                }
+                interests.insert(
+                    key,
+                    EpollEntry {
+                        file: watched,
+                        event,
+                        reported: EpollEvents::empty(),
+                        disabled: false,
+                        state: Arc::default(),
+                    },
+                );
+            }
+            EpollCtlOp::Modify => {
+                let entry = interests.get_mut(&key).ok_or(LxError::ENOENT)?;
+                entry.event = event;
+                entry.reported = EpollEvents::empty();
+                entry.disabled = false;
+            }
+            EpollCtlOp::Delete => {
+                interests.remove(&key).ok_or(LxError::ENOENT)?;
+            }
+        }
+        drop(interests);
+        // the waiters collect the events again
+        self.eventbus.lock().notify();
+        Ok(())
+    }
+
+    /// Whether the interest list of `interests` is watched by this instance,
+    /// directly or through other instances.
+    fn watches(&self, interests: &Arc<Mutex<BTreeMap<InterestKey, EpollEntry>>>) -> bool {
+        let files: Vec<Arc<dyn FileLike>> = self
+            .interests
+            .lock()
+            .values()
+            .filter_map(|entry| entry.file.upgrade())
+            .collect();
+        files.iter().any(|file| {
+            file.downcast_ref::<EpollInstance>().map_or(false, |epoll| {
+                Arc::ptr_eq(&epoll.interests, interests) || epoll.watches(interests)
+            })
+        })
+    }
+
+    /// Wait for events on the interest list.
+    ///
+    /// A negative `timeout_msecs` blocks indefinitely.
+    pub async fn wait(&self, maxevents: usize, timeout_msecs: isize) -> LxResult<Vec<EpollEvent>> {
+        let deadline = if timeout_msecs >= 0 {
+            Some(timer::deadline_after(Duration::from_millis(
+                timeout_msecs as u64,
+            )))
+        } else {
+            None
+        };
+        EpollFuture {
+            epoll: self,
+            maxevents,
+            deadline,
+            subscribed: Arc::default(),
+            timer_set: false,
+        }
+        .await
+    }
+
+    /// Wake up the waker in `cx` on the next change of the interest list,
+    /// unless subscribed already as told by `subscribed`, which is cleared
+    /// when woken up.
+    fn subscribe(&self, subscribed: &Arc<AtomicBool>, cx: &Context) {
+        if !subscribed.swap(true, Ordering::AcqRel) {
+            let (waker, subscribed) = (cx.waker().clone(), subscribed.clone());
+            self.eventbus.lock().subscribe_once(move || {
+                subscribed.store(false, Ordering::Release);
+                waker.wake_by_ref();
+            });
+        }
+    }
+
+    /// Ask the files in the interest list to tell their next changes, which
+    /// notify the waiters of the instance.
+    fn arm(&self) {
+        let interests = self.interests.lock();
+        for entry in interests.values() {
+            if entry.disabled || entry.state.armed.swap(true, Ordering::AcqRel) {
+                continue;
+            }
+            let file = match entry.file.upgrade() {
+                Some(file) => file,
+                None => continue,
+            };
+            let state = Arc::downgrade(&entry.state);
+            let eventbus = Arc::downgrade(&self.eventbus);
+            let told = file.on_change(Box::new(move || {
+                if let Some(state) = state.upgrade() {
+                    state.armed.store(false, Ordering::Release);
+                    state.changed.store(true, Ordering::Release);
+                }
+                if let Some(eventbus) = eventbus.upgrade() {
+                    eventbus.lock().notify();
+                }
+            }));
+            if !told {
+                entry.state.armed.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    /// Collect ready events, polling each file with the waker in `cx`.
+    fn collect(&self, maxevents: usize, cx: &mut Context) -> LxResult<Vec<EpollEvent>> {
+        let mut interests = self.interests.lock();
+        remove_closed(&mut interests);
+        let mut ready = Vec::new();
+        for entry in interests.values_mut() {
+            if ready.len() >= maxevents {
+                break;
+            }
+            if entry.disabled {
+                continue;
+            }
+            let file = match entry.file.upgrade() {
+                Some(file) => file,
+                None => continue,
+            };
+            if entry.state.changed.swap(false, Ordering::AcqRel) {
+                entry.reported = EpollEvents::empty();
+            }
+            let revents = match poll_entry(entry, &file, cx)? {
+                Some(revents) => revents,
+                None => {
+                    entry.reported = EpollEvents::empty();
+                    continue;
+                }
+            };
+            let wanted = entry.event.events;
+            let revents = if wanted.contains(EpollEvents::ET) {
+                let new = revents - entry.reported;
+                entry.reported = revents;
+                new
+            } else {
+                revents
+            };
+            if revents.is_empty() {
+                continue;
+            }
+            if wanted.contains(EpollEvents::ONESHOT) {
+                entry.disabled = true;
+            }
+            ready.push(EpollEvent {
+                events: revents,
+                data: entry.event.data,
+            });
+        }
+        Ok(ready)
+    }
+
+    /// Whether any file in the interest list is ready, polling each file
+    /// with the waker in `cx`, without consuming the events.
+    fn any_ready(&self, cx: &mut Context) -> LxResult<bool> {
+        let interests = self.interests.lock();
+        for entry in interests.values().filter(|entry| !entry.disabled) {
+            if let Some(file) = entry.file.upgrade() {
+                if poll_entry(entry, &file, cx)?.is_some() {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Poll `file` of `entry` for the requested events. The waker in `cx` is
+/// registered on the file unless it tells its changes to the instance.
+///
+/// Returns `None` if none of the events is ready.
+fn poll_entry(
+    entry: &EpollEntry,
+    file: &Arc<dyn FileLike>,
+    cx: &mut Context,
+) -> LxResult<Option<EpollEvents>> {
+    let wanted = entry.event.events;
+    let mut poll_events = PollEvents::empty();
+    poll_events.set(PollEvents::IN, wanted.contains(EpollEvents::IN));
+    poll_events.set(PollEvents::OUT, wanted.contains(EpollEvents::OUT));
+    let status = if entry.state.armed.load(Ordering::Acquire) {
+        file.poll(poll_events)?
+    } else {
+        match file.async_poll(poll_events).as_mut().poll(cx) {
+            Poll::Ready(ret) => ret?,
+            Poll::Pending => return Ok(None),
+        }
+    };
+    let mut revents = status_to_events(&status);
+    revents.set(EpollEvents::HUP, file.hung_up());
+    let revents = revents & (wanted | EpollEvents::ERR | EpollEvents::HUP);
+    Ok(Some(revents).filter(|revents| !revents.is_empty()))
+}
+
+/// Remove the entries of the files closed, which have no descriptors left.
+fn remove_closed(interests: &mut BTreeMap<InterestKey, EpollEntry>) {
+    interests.retain(|_, entry| !entry.file.is_closed());
+}
+
+fn status_to_events(status: &PollStatus) -> EpollEvents {
+    let mut events = EpollEvents::empty();
+    events.set(EpollEvents::IN, status.read);
+    events.set(EpollEvents::OUT, status.write);
+    events.set(EpollEvents::ERR, status.error);
+    events
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct EpollFuture<'a> {
+    epoll: &'a EpollInstance,
+    maxevents: usize,
+    deadline: Option<Duration>,
+    /// whether subscribed to the changes of the interest list
+    subscribed: Arc<AtomicBool>,
+    /// whether the timer of the deadline is set
+    timer_set: bool,
+}
+
+impl Future for EpollFuture<'_> {
+    type Output = LxResult<Vec<EpollEvent>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // subscribe before collecting, not to miss the changes in between
+        self.epoll.subscribe(&self.subscribed, cx);
+        self.epoll.arm();
+        let ready = match self.epoll.collect(self.maxevents, cx) {
+            Ok(ready) => ready,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        if !ready.is_empty() {
+            return Poll::Ready(Ok(ready));
+        }
+        if let Some(deadline) = self.deadline {
+            if timer::timer_now() >= deadline {
+                return Poll::Ready(Ok(ready));
+            }
+            if !self.timer_set {
+                self.timer_set = true;
+                let waker = cx.waker().clone();
+                timer::timer_set(deadline, Box::new(move |_| waker.wake_by_ref()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Waits until any file in the interest list is ready, for an instance in
+/// the interest list of another one.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct EpollReadyFuture<'a> {
+    epoll: &'a EpollInstance,
+    /// whether subscribed to the changes of the interest list
+    subscribed: Arc<AtomicBool>,
+}
+
+impl Future for EpollReadyFuture<'_> {
+    type Output = LxResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.epoll.subscribe(&self.subscribed, cx);
+        self.epoll.arm();
+        match self.epoll.any_ready(cx) {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for EpollInstance {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(EpollInstance {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            interests: self.interests.clone(),
+            eventbus: self.eventbus.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    /// An epoll instance is readable when any file in its interest list is ready.
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        let interests = self.interests.lock();
+        let read = interests.values().any(|entry| {
+            !entry.disabled
+                && entry.file.upgrade().map_or(false, |file| {
+                    file.poll(PollEvents::IN | PollEvents::OUT)
+                        .map(|s| !(status_to_events(&s) & entry.event.events).is_empty())
+                        .unwrap_or(false)
+                })
+        });
+        Ok(PollStatus {
+            read,
+            write: false,
+            error: false,
+        })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) {
+            EpollReadyFuture {
+                epoll: self,
+                subscribed: Arc::default(),
+            }
+            .await?;
+        }
+        self.poll(events)
+    }
+
+    /// The changes of the files in the interest list are told.
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.eventbus.lock().subscribe_once(callback);
+        self.arm();
+        true
+    }
+}
@@ -0,0 +1,202 @@
+//! Implement eventfd file object
+#![deny(missing_docs)]
+
+use alloc::{boxed::Box, sync::Arc};
+
+use async_trait::async_trait;
+use lock::Mutex;
+use rcore_fs::vfs::PollStatus;
+use zircon_object::object::*;
+
+use super::{ChangeCallback, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::sync::{wait_for_event, Event, EventBus};
+
+bitflags::bitflags! {
+    /// Flags for `eventfd2`
+    pub struct EventFdFlags: usize {
+        /// Provide semaphore-like semantics for reads from the new file descriptor.
+        const SEMAPHORE = 1;
+        /// Set the close-on-exec flag on the new file descriptor.
+        const CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the new open file description.
+        const NONBLOCK = 0o4000;
+    }
+}
+
+/// The maximum value of the eventfd counter.
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+/// Shared state of an eventfd
+struct EventFdData {
+    /// the 64-bit counter
+    count: Mutex<u64>,
+    /// semaphore-like read semantics
+    semaphore: bool,
+    /// readable / writable notifications
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+/// A file object used as an event wait/notify mechanism.
+pub struct EventFd {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    data: Arc<EventFdData>,
+}
+
+impl_kobject!(EventFd);
+
+impl EventFd {
+    /// Create a new eventfd with the initial counter value.
+    pub fn new(initval: u64, flags: EventFdFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDWR;
+        open_flags.set(OpenFlags::NON_BLOCK, flags.contains(EventFdFlags::NONBLOCK));
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(EventFdFlags::CLOEXEC));
+        let eventbus = EventBus::new();
+        if initval > 0 {
+            eventbus.lock().set(Event::READABLE);
+        }
+        eventbus.lock().set(Event::WRITABLE);
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(EventFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(open_flags),
+            description: description.clone(),
+            data: Arc::new(EventFdData {
+                count: Mutex::new(initval),
+                semaphore: flags.contains(EventFdFlags::SEMAPHORE),
+                eventbus,
+            }),
+        })
+    }
+
+    /// Try to take a value from the counter.
+    fn try_read(&self) -> Option<u64> {
+        let mut count = self.data.count.lock();
+        if *count == 0 {
+            return None;
+        }
+        let ret = if self.data.semaphore { 1 } else { *count };
+        *count -= ret;
+        let mut bus = self.data.eventbus.lock();
+        if *count == 0 {
+            bus.clear(Event::READABLE);
+        }
+        bus.change_notify(Event::empty(), Event::WRITABLE);
+        Some(ret)
+    }
+
+    /// Try to add `value` to the counter.
+    fn try_write(&self, value: u64) -> LxResult<bool> {
+        if value == u64::MAX {
+            return Err(LxError::EINVAL);
+        }
+        let mut count = self.data.count.lock();
+        if EVENTFD_MAX - *count < value {
+            return Ok(false);
+        }
+        *count += value;
+        let mut bus = self.data.eventbus.lock();
+        if *count == EVENTFD_MAX {
+            bus.clear(Event::WRITABLE);
+        }
+        if *count > 0 {
+            bus.change_notify(Event::empty(), Event::READABLE);
+        }
+        Ok(true)
+    }
+
+    fn poll_status(&self) -> PollStatus {
+        let count = *self.data.count.lock();
+        PollStatus {
+            read: count > 0,
+            write: count < EVENTFD_MAX,
+            error: false,
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for EventFd {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(EventFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            data: self.data.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LxError::EINVAL);
+        }
+        loop {
+            if let Some(value) = self.try_read() {
+                buf[..8].copy_from_slice(&value.to_ne_bytes());
+                return Ok(8);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.data.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LxError::EINVAL);
+        }
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[..8]);
+        if self.try_write(u64::from_ne_bytes(bytes))? {
+            Ok(8)
+        } else {
+            Err(LxError::EAGAIN)
+        }
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        Ok(self.poll_status())
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        let mut mask = Event::empty();
+        mask.set(Event::READABLE, events.contains(PollEvents::IN));
+        mask.set(Event::WRITABLE, events.contains(PollEvents::OUT));
+        if !mask.is_empty() {
+            wait_for_event(self.data.eventbus.clone(), mask).await;
+        }
+        Ok(self.poll_status())
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.data.eventbus.lock().subscribe_once(callback);
+        true
+    }
+}
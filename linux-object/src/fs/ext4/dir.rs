@@ -0,0 +1,720 @@
+//! Directories, stored as linear lists of entries or indexed by hash trees
+//!
+//! Lookups always scan the leaf blocks linearly, which is correct for both
+//! layouts. New entries of an indexed directory are inserted into the leaf
+//! selected by the hash of the name, so that the index stays valid.
+
+use super::layout::*;
+use super::Ext4;
+use alloc::{string::String, vec::Vec};
+use rcore_fs::vfs::{FsError, Result};
+
+/// Size of the fake entry holding the checksum at the end of a leaf block.
+const TAIL_SIZE: usize = 12;
+const TAIL_FILE_TYPE: u8 = 0xDE;
+
+const DX_HASH_LEGACY: u8 = 0;
+const DX_HASH_HALF_MD4: u8 = 1;
+const DX_HASH_TEA: u8 = 2;
+const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+/// An entry in a directory block
+struct DirEntry {
+    offset: usize,
+    ino: u32,
+    rec_len: usize,
+    name_len: usize,
+    file_type: u8,
+}
+
+/// The space taken by an entry with a name of `name_len` bytes.
+fn entry_len(name_len: usize) -> usize {
+    (8 + name_len + 3) & !3
+}
+
+fn file_type_code(mode: u16) -> u8 {
+    match mode & S_IFMT {
+        S_IFREG => 1,
+        S_IFDIR => 2,
+        S_IFCHR => 3,
+        S_IFBLK => 4,
+        S_IFIFO => 5,
+        S_IFSOCK => 6,
+        S_IFLNK => 7,
+        _ => 0,
+    }
+}
+
+fn is_tail(buf: &[u8], e: &DirEntry) -> bool {
+    e.ino == 0 && e.rec_len == TAIL_SIZE && e.name_len == 0 && buf[e.offset + 7] == TAIL_FILE_TYPE
+}
+
+fn has_tail(buf: &[u8]) -> bool {
+    let offset = buf.len() - TAIL_SIZE;
+    get_u32(buf, offset) == 0
+        && get_u16(buf, offset + 4) == TAIL_SIZE as u16
+        && buf[offset + 6] == 0
+        && buf[offset + 7] == TAIL_FILE_TYPE
+}
+
+fn decode_rec_len(buf: &[u8], offset: usize) -> usize {
+    match get_u16(buf, offset + 4) {
+        0 | 65535 => buf.len(),
+        len => len as usize,
+    }
+}
+
+fn encode_rec_len(len: usize) -> u16 {
+    if len >= 65536 {
+        65535
+    } else {
+        len as u16
+    }
+}
+
+/// Parse the entries of a block, stopping at a corrupted one.
+fn block_entries(buf: &[u8]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= buf.len() {
+        let rec_len = decode_rec_len(buf, offset);
+        let name_len = buf[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > buf.len() || 8 + name_len > rec_len {
+            warn!("ext4: corrupted directory entry");
+            break;
+        }
+        entries.push(DirEntry {
+            offset,
+            ino: get_u32(buf, offset),
+            rec_len,
+            name_len,
+            file_type: buf[offset + 7],
+        });
+        offset += rec_len;
+    }
+    entries
+}
+
+fn entry_name<'a>(buf: &'a [u8], e: &DirEntry) -> &'a [u8] {
+    &buf[e.offset + 8..e.offset + 8 + e.name_len]
+}
+
+fn put_entry(buf: &mut [u8], offset: usize, ino: u32, rec_len: usize, name: &[u8], file_type: u8) {
+    set_u32(buf, offset, ino);
+    set_u16(buf, offset + 4, encode_rec_len(rec_len));
+    buf[offset + 6] = name.len() as u8;
+    buf[offset + 7] = file_type;
+    buf[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+}
+
+/// Insert an entry into a leaf block, returns false if there is no room.
+fn leaf_insert(buf: &mut [u8], name: &[u8], ino: u32, file_type: u8) -> bool {
+    let need = entry_len(name.len());
+    for e in block_entries(buf) {
+        if is_tail(buf, &e) {
+            continue;
+        }
+        let used = if e.ino == 0 { 0 } else { entry_len(e.name_len) };
+        if e.rec_len >= used + need {
+            if used > 0 {
+                set_u16(buf, e.offset + 4, used as u16);
+            }
+            put_entry(buf, e.offset + used, ino, e.rec_len - used, name, file_type);
+            return true;
+        }
+    }
+    false
+}
+
+/// A node of the hash tree index
+struct DxNode {
+    pblock: u64,
+    buf: Vec<u8>,
+    /// the offset of the `count` and `limit` fields
+    count_offset: usize,
+    limit: usize,
+    /// pairs of hash and logical block, the hash of the first is unused
+    entries: Vec<(u32, u32)>,
+}
+
+impl Ext4 {
+    /// The usable space of a leaf block, excluding the checksum tail.
+    fn leaf_space(&self) -> usize {
+        if self.metadata_csum() {
+            self.block_size - TAIL_SIZE
+        } else {
+            self.block_size
+        }
+    }
+
+    fn entry_file_type(&self, mode: u16) -> u8 {
+        if self.sb.feature_incompat() & INCOMPAT_FILETYPE != 0 {
+            file_type_code(mode)
+        } else {
+            0
+        }
+    }
+
+    fn init_tail(&self, buf: &mut [u8]) {
+        if self.metadata_csum() {
+            let offset = buf.len() - TAIL_SIZE;
+            buf[offset..].fill(0);
+            set_u16(buf, offset + 4, TAIL_SIZE as u16);
+            buf[offset + 7] = TAIL_FILE_TYPE;
+        }
+    }
+
+    /// Write block `lblock` of a directory, updating its checksum.
+    fn write_dir_block(
+        &self,
+        ino: u32,
+        dir: &RawInode,
+        lblock: u32,
+        pblock: u64,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        if self.metadata_csum() {
+            let seed = self.inode_csum_seed(ino, dir);
+            if has_tail(buf) {
+                let offset = buf.len() - 4;
+                let csum = crc32c(seed, &buf[..buf.len() - TAIL_SIZE]);
+                set_u32(buf, offset, csum);
+            } else if dir.flags() & INODE_INDEX_FL != 0 && lblock == 0 {
+                dx_update_csum(seed, buf, dx_root_count_offset(buf));
+            }
+        }
+        self.write_block(pblock, buf)
+    }
+
+    /// Visit the blocks of a directory until `f` returns a value.
+    fn dir_blocks<T>(
+        &self,
+        dir: &RawInode,
+        mut f: impl FnMut(u32, u64, &[u8]) -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        let blocks = (dir.size() / self.block_size as u64) as u32;
+        for lblock in 0..blocks {
+            if let Some(pblock) = self.map_block(dir, lblock)? {
+                let buf = self.read_block(pblock)?;
+                if let Some(value) = f(lblock, pblock, &buf)? {
+                    return Ok(Some(value));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find entry `name` in a directory, returns its inode number.
+    pub(super) fn dir_lookup(&self, dir: &RawInode, name: &str) -> Result<Option<u32>> {
+        self.dir_blocks(dir, |_, _, buf| {
+            Ok(block_entries(buf)
+                .iter()
+                .find(|e| e.ino != 0 && entry_name(buf, e) == name.as_bytes())
+                .map(|e| e.ino))
+        })
+    }
+
+    /// The name of the `index`-th entry of a directory.
+    pub(super) fn dir_entry_at(&self, dir: &RawInode, mut index: usize) -> Result<Option<String>> {
+        self.dir_blocks(dir, |_, _, buf| {
+            for e in block_entries(buf).iter().filter(|e| e.ino != 0) {
+                if index == 0 {
+                    let name = String::from_utf8_lossy(entry_name(buf, e));
+                    return Ok(Some(name.into_owned()));
+                }
+                index -= 1;
+            }
+            Ok(None)
+        })
+    }
+
+    pub(super) fn dir_is_empty(&self, dir: &RawInode) -> Result<bool> {
+        let other = self.dir_blocks(dir, |_, _, buf| {
+            Ok(block_entries(buf).into_iter().find(|e| {
+                let name = entry_name(buf, e);
+                e.ino != 0 && name != b"." && name != b".."
+            }))
+        })?;
+        Ok(other.is_none())
+    }
+
+    /// Create the first block of a new directory, with `.` and `..`.
+    pub(super) fn dir_init(&mut self, ino: u32, dir: &mut RawInode, parent: u32) -> Result<()> {
+        let pblock = self.alloc_data_block(ino, dir, 0)?;
+        let file_type = self.entry_file_type(S_IFDIR);
+        let mut buf = vec![0; self.block_size];
+        put_entry(&mut buf, 0, ino, 12, b".", file_type);
+        put_entry(
+            &mut buf,
+            12,
+            parent,
+            self.leaf_space() - 12,
+            b"..",
+            file_type,
+        );
+        self.init_tail(&mut buf);
+        dir.set_size(self.block_size as u64);
+        self.write_dir_block(ino, dir, 0, pblock, &mut buf)
+    }
+
+    /// Point `..` of a directory to `parent`.
+    pub(super) fn dir_set_parent(&mut self, ino: u32, dir: &RawInode, parent: u32) -> Result<()> {
+        let pblock = self.map_block(dir, 0)?.ok_or(FsError::DeviceError)?;
+        let mut buf = self.read_block(pblock)?;
+        let entries = block_entries(&buf);
+        let dotdot = entries.get(1).ok_or(FsError::DeviceError)?;
+        set_u32(&mut buf, dotdot.offset, parent);
+        self.write_dir_block(ino, dir, 0, pblock, &mut buf)
+    }
+
+    /// Add entry `name` of inode `ino` whose mode is `mode`.
+    pub(super) fn dir_add(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut RawInode,
+        name: &str,
+        ino: u32,
+        mode: u16,
+    ) -> Result<()> {
+        let file_type = self.entry_file_type(mode);
+        if dir.flags() & INODE_INDEX_FL != 0 {
+            return self.dx_add(dir_ino, dir, name.as_bytes(), ino, file_type);
+        }
+        let found = self.dir_blocks(dir, |lblock, pblock, buf| {
+            let mut buf = buf.to_vec();
+            if leaf_insert(&mut buf, name.as_bytes(), ino, file_type) {
+                Ok(Some((lblock, pblock, buf)))
+            } else {
+                Ok(None)
+            }
+        })?;
+        let (lblock, pblock, mut buf) = match found {
+            Some(found) => found,
+            None => {
+                let (lblock, pblock) = self.dir_append_block(dir_ino, dir)?;
+                let mut buf = vec![0; self.block_size];
+                put_entry(
+                    &mut buf,
+                    0,
+                    ino,
+                    self.leaf_space(),
+                    name.as_bytes(),
+                    file_type,
+                );
+                self.init_tail(&mut buf);
+                (lblock, pblock, buf)
+            }
+        };
+        self.write_dir_block(dir_ino, dir, lblock, pblock, &mut buf)
+    }
+
+    /// Remove entry `name` from a directory.
+    pub(super) fn dir_remove(&mut self, dir_ino: u32, dir: &RawInode, name: &str) -> Result<()> {
+        let found = self.dir_blocks(dir, |lblock, pblock, buf| {
+            let entries = block_entries(buf);
+            let index = entries
+                .iter()
+                .position(|e| e.ino != 0 && entry_name(buf, e) == name.as_bytes());
+            Ok(index.map(|i| {
+                let mut buf = buf.to_vec();
+                if i > 0 {
+                    let prev = &entries[i - 1];
+                    let rec_len = prev.rec_len + entries[i].rec_len;
+                    set_u16(&mut buf, prev.offset + 4, encode_rec_len(rec_len));
+                } else {
+                    set_u32(&mut buf, entries[i].offset, 0);
+                }
+                (lblock, pblock, buf)
+            }))
+        })?;
+        let (lblock, pblock, mut buf) = found.ok_or(FsError::EntryNotFound)?;
+        self.write_dir_block(dir_ino, dir, lblock, pblock, &mut buf)
+    }
+
+    /// Append an empty block to a directory.
+    fn dir_append_block(&mut self, dir_ino: u32, dir: &mut RawInode) -> Result<(u32, u64)> {
+        let lblock = (dir.size() / self.block_size as u64) as u32;
+        let pblock = self.alloc_data_block(dir_ino, dir, lblock)?;
+        dir.set_size(dir.size() + self.block_size as u64);
+        Ok((lblock, pblock))
+    }
+
+    fn dx_hash(&self, root: &[u8], name: &[u8]) -> u32 {
+        let mut version = root[0x1C];
+        if version <= DX_HASH_TEA && self.sb.flags() & FLAGS_UNSIGNED_HASH != 0 {
+            version += 3;
+        }
+        dirhash(name, version, self.sb.hash_seed())
+    }
+
+    fn load_dx(&self, dir: &RawInode, lblock: u32) -> Result<DxNode> {
+        let pblock = self.map_block(dir, lblock)?.ok_or(FsError::DeviceError)?;
+        let buf = self.read_block(pblock)?;
+        let count_offset = if lblock == 0 {
+            dx_root_count_offset(&buf)
+        } else {
+            8
+        };
+        let limit = get_u16(&buf, count_offset) as usize;
+        let count = get_u16(&buf, count_offset + 2) as usize;
+        if count == 0 || count > limit || count_offset + limit * 8 > buf.len() {
+            warn!("ext4: corrupted directory index");
+            return Err(FsError::DeviceError);
+        }
+        let entries = (0..count)
+            .map(|i| {
+                let entry = count_offset + i * 8;
+                let hash = if i == 0 { 0 } else { get_u32(&buf, entry) };
+                (hash, get_u32(&buf, entry + 4))
+            })
+            .collect();
+        Ok(DxNode {
+            pblock,
+            buf,
+            count_offset,
+            limit,
+            entries,
+        })
+    }
+
+    /// Create an empty index node in a new block of the directory.
+    fn new_dx_node(&mut self, dir_ino: u32, dir: &mut RawInode) -> Result<(u32, DxNode)> {
+        let (lblock, pblock) = self.dir_append_block(dir_ino, dir)?;
+        let mut buf = vec![0; self.block_size];
+        set_u16(&mut buf, 4, encode_rec_len(self.block_size));
+        let tail = if self.metadata_csum() { 8 } else { 0 };
+        let node = DxNode {
+            pblock,
+            buf,
+            count_offset: 8,
+            limit: (self.block_size - 8 - tail) / 8,
+            entries: Vec::new(),
+        };
+        Ok((lblock, node))
+    }
+
+    fn store_dx(&self, dir_ino: u32, dir: &RawInode, node: &mut DxNode) -> Result<()> {
+        let offset = node.count_offset;
+        set_u16(&mut node.buf, offset, node.limit as u16);
+        set_u16(&mut node.buf, offset + 2, node.entries.len() as u16);
+        for (i, &(hash, block)) in node.entries.iter().enumerate() {
+            if i > 0 {
+                set_u32(&mut node.buf, offset + i * 8, hash);
+            }
+            set_u32(&mut node.buf, offset + i * 8 + 4, block);
+        }
+        if self.metadata_csum() {
+            let seed = self.inode_csum_seed(dir_ino, dir);
+            dx_update_csum(seed, &mut node.buf, offset);
+        }
+        self.write_block(node.pblock, &node.buf)
+    }
+
+    /// Add an entry to a directory indexed by a hash tree.
+    fn dx_add(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut RawInode,
+        name: &[u8],
+        ino: u32,
+        file_type: u8,
+    ) -> Result<()> {
+        let root = self.load_dx(dir, 0)?;
+        let levels = root.buf[0x1E] as usize;
+        let hash = self.dx_hash(&root.buf, name);
+        let mut path = vec![root];
+        let mut positions = Vec::new();
+        let leaf = loop {
+            let node = path.last().unwrap();
+            let pos = (1..node.entries.len())
+                .rev()
+                .find(|&i| node.entries[i].0 <= hash)
+                .unwrap_or(0);
+            positions.push(pos);
+            let child = node.entries[pos].1;
+            if path.len() > levels {
+                break child;
+            }
+            path.push(self.load_dx(dir, child)?);
+        };
+        let pblock = self.map_block(dir, leaf)?.ok_or(FsError::DeviceError)?;
+        let mut buf = self.read_block(pblock)?;
+        if leaf_insert(&mut buf, name, ino, file_type) {
+            return self.write_dir_block(dir_ino, dir, leaf, pblock, &mut buf);
+        }
+
+        // split the leaf by the hashes of the names
+        let mut entries: Vec<(u32, Vec<u8>, u32, u8)> = block_entries(&buf)
+            .iter()
+            .filter(|e| e.ino != 0)
+            .map(|e| {
+                let name = entry_name(&buf, e);
+                (
+                    self.dx_hash(&path[0].buf, name),
+                    name.to_vec(),
+                    e.ino,
+                    e.file_type,
+                )
+            })
+            .collect();
+        entries.push((hash, name.to_vec(), ino, file_type));
+        entries.sort_by_key(|e| e.0);
+        let total: usize = entries.iter().map(|e| entry_len(e.1.len())).sum();
+        let mut split = 0;
+        let mut size = 0;
+        while split < entries.len() - 1 && size < total / 2 {
+            size += entry_len(entries[split].1.len());
+            split += 1;
+        }
+        let split = split.max(1);
+        let mut split_hash = entries[split].0;
+        if entries[split - 1].0 == split_hash {
+            // the names with this hash continue in the next block
+            split_hash |= 1;
+        }
+        let (new_leaf, new_pblock) = self.dir_append_block(dir_ino, dir)?;
+        let mut lower = self.build_leaf(&entries[..split]);
+        let mut upper = self.build_leaf(&entries[split..]);
+        self.write_dir_block(dir_ino, dir, leaf, pblock, &mut lower)?;
+        self.write_dir_block(dir_ino, dir, new_leaf, new_pblock, &mut upper)?;
+        self.dx_insert(dir_ino, dir, path, positions, (split_hash, new_leaf))
+    }
+
+    fn build_leaf(&self, entries: &[(u32, Vec<u8>, u32, u8)]) -> Vec<u8> {
+        let mut buf = vec![0; self.block_size];
+        let space = self.leaf_space();
+        let mut offset = 0;
+        for (i, (_, name, ino, file_type)) in entries.iter().enumerate() {
+            let len = entry_len(name.len());
+            let rec_len = if i + 1 == entries.len() {
+                space - offset
+            } else {
+                len
+            };
+            put_entry(&mut buf, offset, *ino, rec_len, name, *file_type);
+            offset += len;
+        }
+        self.init_tail(&mut buf);
+        buf
+    }
+
+    /// Insert `entry` after the positions of `path`, splitting full nodes.
+    fn dx_insert(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut RawInode,
+        mut path: Vec<DxNode>,
+        mut positions: Vec<usize>,
+        mut entry: (u32, u32),
+    ) -> Result<()> {
+        let max_levels = if self.sb.feature_incompat() & INCOMPAT_LARGEDIR != 0 {
+            3
+        } else {
+            2
+        };
+        let mut depth = path.len() - 1;
+        loop {
+            let at = positions[depth] + 1;
+            if path[depth].entries.len() < path[depth].limit {
+                path[depth].entries.insert(at, entry);
+                return self.store_dx(dir_ino, dir, &mut path[depth]);
+            }
+            if depth == 0 {
+                // add a level below the root
+                let levels = path[0].buf[0x1E] as usize;
+                if levels + 1 >= max_levels {
+                    return Err(FsError::NoDeviceSpace);
+                }
+                let (lblock, mut node) = self.new_dx_node(dir_ino, dir)?;
+                node.entries = core::mem::take(&mut path[0].entries);
+                path[0].entries.push((0, lblock));
+                path[0].buf[0x1E] = levels as u8 + 1;
+                self.store_dx(dir_ino, dir, &mut path[0])?;
+                path.insert(1, node);
+                positions.insert(0, 0);
+                depth = 1;
+                continue;
+            }
+            let (lblock, mut node) = self.new_dx_node(dir_ino, dir)?;
+            let half = path[depth].entries.len() / 2;
+            node.entries = path[depth].entries.split_off(half);
+            let split_hash = node.entries[0].0;
+            if at <= half {
+                path[depth].entries.insert(at, entry);
+            } else {
+                node.entries.insert(at - half, entry);
+            }
+            self.store_dx(dir_ino, dir, &mut path[depth])?;
+            self.store_dx(dir_ino, dir, &mut node)?;
+            entry = (split_hash, lblock);
+            depth -= 1;
+        }
+    }
+}
+
+/// The offset of `count` and `limit` in the root of a hash tree.
+fn dx_root_count_offset(buf: &[u8]) -> usize {
+    0x18 + buf[0x1D] as usize
+}
+
+fn dx_update_csum(seed: u32, buf: &mut [u8], count_offset: usize) {
+    let limit = get_u16(buf, count_offset) as usize;
+    let count = get_u16(buf, count_offset + 2) as usize;
+    let tail = count_offset + limit * 8;
+    if tail + 8 > buf.len() {
+        return;
+    }
+    let mut csum = crc32c(seed, &buf[..count_offset + count * 8]);
+    csum = crc32c(csum, &buf[tail..tail + 4]);
+    csum = crc32c(csum, &[0; 4]);
+    set_u32(buf, tail + 4, csum);
+}
+
+/// Compute the hash of a file name, as `ext4fs_dirhash` in Linux.
+fn dirhash(name: &[u8], version: u8, seed: [u32; 4]) -> u32 {
+    let mut buf = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    if seed.iter().any(|&s| s != 0) {
+        buf = seed;
+    }
+    let unsigned = matches!(
+        version,
+        DX_HASH_LEGACY_UNSIGNED | DX_HASH_HALF_MD4_UNSIGNED | DX_HASH_TEA_UNSIGNED
+    );
+    let hash = match version {
+        DX_HASH_LEGACY | DX_HASH_LEGACY_UNSIGNED => dx_hack_hash(name, unsigned),
+        DX_HASH_HALF_MD4 | DX_HASH_HALF_MD4_UNSIGNED => {
+            for offset in (0..name.len()).step_by(32) {
+                half_md4_transform(&mut buf, &str2hashbuf(&name[offset..], 8, unsigned));
+            }
+            buf[1]
+        }
+        DX_HASH_TEA | DX_HASH_TEA_UNSIGNED => {
+            for offset in (0..name.len()).step_by(16) {
+                tea_transform(&mut buf, &str2hashbuf(&name[offset..], 4, unsigned));
+            }
+            buf[0]
+        }
+        _ => 0,
+    };
+    let hash = hash & !1;
+    if hash == 0x7fff_ffff << 1 {
+        (0x7fff_ffff - 1) << 1
+    } else {
+        hash
+    }
+}
+
+fn char_value(c: u8, unsigned: bool) -> u32 {
+    if unsigned {
+        c as u32
+    } else {
+        c as i8 as i32 as u32
+    }
+}
+
+fn dx_hack_hash(name: &[u8], unsigned: bool) -> u32 {
+    let (mut hash0, mut hash1): (u32, u32) = (0x12a3_fe2d, 0x37ab_e8f9);
+    for &c in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ char_value(c, unsigned).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7fff_ffff);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Pack the remaining part of a name into `num` words, padded by its length.
+fn str2hashbuf(msg: &[u8], num: usize, unsigned: bool) -> [u32; 8] {
+    let mut out = [0u32; 8];
+    let len = msg.len() as u32;
+    let mut pad = len | len << 8;
+    pad |= pad << 16;
+    let mut val = pad;
+    let mut n = 0;
+    for (i, &c) in msg.iter().take(num * 4).enumerate() {
+        val = char_value(c, unsigned).wrapping_add(val << 8);
+        if i % 4 == 3 {
+            out[n] = val;
+            n += 1;
+            val = pad;
+        }
+    }
+    if n < num {
+        out[n] = val;
+        n += 1;
+    }
+    for word in out.iter_mut().take(num).skip(n) {
+        *word = pad;
+    }
+    out
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const K2: u32 = 0x5A82_7999;
+    const K3: u32 = 0x6ED9_EBA1;
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+    let [mut a, mut b, mut c, mut d] = *buf;
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a
+                .wrapping_add($f($b, $c, $d))
+                .wrapping_add($x)
+                .rotate_left($s)
+        };
+    }
+    round!(f, a, b, c, d, input[0], 3);
+    round!(f, d, a, b, c, input[1], 7);
+    round!(f, c, d, a, b, input[2], 11);
+    round!(f, b, c, d, a, input[3], 19);
+    round!(f, a, b, c, d, input[4], 3);
+    round!(f, d, a, b, c, input[5], 7);
+    round!(f, c, d, a, b, input[6], 11);
+    round!(f, b, c, d, a, input[7], 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const DELTA: u32 = 0x9E37_79B9;
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let [a, b, c, d] = [input[0], input[1], input[2], input[3]];
+    let mut sum: u32 = 0;
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
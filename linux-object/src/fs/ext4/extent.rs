@@ -0,0 +1,569 @@
+//! Map the logical blocks of files by extent trees, or by indirect blocks
+//! for files created by ext2/3
+
+use super::layout::*;
+use super::{read_exact, write_all, Ext4};
+use alloc::vec::Vec;
+use rcore_fs::vfs::{FsError, Result};
+
+const EXTENT_MAGIC: u16 = 0xF30A;
+/// Size of the header of a tree node, and of each entry in it.
+const ENTRY_SIZE: usize = 12;
+/// Number of entries in the root node in `i_block`.
+const ROOT_ENTRIES: usize = 4;
+const MAX_INIT_LEN: u32 = 32768;
+const MAX_UNINIT_LEN: u32 = 32767;
+
+/// A run of contiguous blocks
+#[derive(Clone, Copy, Debug)]
+struct Extent {
+    lblock: u32,
+    len: u32,
+    pblock: u64,
+    /// allocated but not written yet, reads as zeros
+    uninit: bool,
+}
+
+impl Extent {
+    fn end(&self) -> u32 {
+        self.lblock + self.len
+    }
+
+    fn max_len(&self) -> u32 {
+        if self.uninit {
+            MAX_UNINIT_LEN
+        } else {
+            MAX_INIT_LEN
+        }
+    }
+}
+
+/// All extents of a file, loaded to be modified
+///
+/// The tree is rebuilt from the list when it is stored, reusing the blocks
+/// of the old tree.
+struct ExtentMap {
+    extents: Vec<Extent>,
+    tree_blocks: Vec<u64>,
+    dirty: bool,
+}
+
+impl ExtentMap {
+    fn find(&self, lblock: u32) -> Option<usize> {
+        let i = self.extents.partition_point(|e| e.end() <= lblock);
+        if i < self.extents.len() && self.extents[i].lblock <= lblock {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    fn map(&self, lblock: u32) -> Option<u64> {
+        let e = self.extents[self.find(lblock)?];
+        if e.uninit {
+            None
+        } else {
+            Some(e.pblock + (lblock - e.lblock) as u64)
+        }
+    }
+
+    /// The block following the mapping before `lblock`, to keep files contiguous.
+    fn goal(&self, lblock: u32) -> Option<u64> {
+        let i = self.extents.partition_point(|e| e.lblock <= lblock);
+        let prev = self.extents.get(i.checked_sub(1)?)?;
+        Some(prev.pblock + (lblock - prev.lblock) as u64)
+    }
+
+    fn insert(&mut self, lblock: u32, pblock: u64) {
+        let i = self.extents.partition_point(|e| e.lblock < lblock);
+        self.extents.insert(
+            i,
+            Extent {
+                lblock,
+                len: 1,
+                pblock,
+                uninit: false,
+            },
+        );
+        self.merge_around(i);
+        self.dirty = true;
+    }
+
+    /// Mark block `lblock` in the uninitialized extent `i` as initialized.
+    fn initialize(&mut self, i: usize, lblock: u32) {
+        let e = self.extents.remove(i);
+        let offset = lblock - e.lblock;
+        let mut pieces = Vec::new();
+        if offset > 0 {
+            pieces.push(Extent { len: offset, ..e });
+        }
+        let mid = i + pieces.len();
+        pieces.push(Extent {
+            lblock,
+            len: 1,
+            pblock: e.pblock + offset as u64,
+            uninit: false,
+        });
+        if offset + 1 < e.len {
+            pieces.push(Extent {
+                lblock: lblock + 1,
+                len: e.len - offset - 1,
+                pblock: e.pblock + offset as u64 + 1,
+                uninit: true,
+            });
+        }
+        for (j, piece) in pieces.into_iter().enumerate() {
+            self.extents.insert(i + j, piece);
+        }
+        self.merge_around(mid);
+        self.dirty = true;
+    }
+
+    fn merge_around(&mut self, i: usize) {
+        self.try_merge(i);
+        if i > 0 {
+            self.try_merge(i - 1);
+        }
+    }
+
+    /// Merge extent `i` with the next one if they are contiguous.
+    fn try_merge(&mut self, i: usize) {
+        if i + 1 >= self.extents.len() {
+            return;
+        }
+        let (a, b) = (self.extents[i], self.extents[i + 1]);
+        if a.end() == b.lblock
+            && a.pblock + a.len as u64 == b.pblock
+            && a.uninit == b.uninit
+            && a.len + b.len <= a.max_len()
+        {
+            self.extents[i].len += b.len;
+            self.extents.remove(i + 1);
+        }
+    }
+
+    /// Remove the mappings from block `lblock`, returns the freed ranges.
+    fn truncate(&mut self, lblock: u32) -> Vec<(u64, u64)> {
+        let mut freed = Vec::new();
+        self.extents.retain_mut(|e| {
+            if e.lblock >= lblock {
+                freed.push((e.pblock, e.len as u64));
+                false
+            } else {
+                if e.end() > lblock {
+                    let keep = lblock - e.lblock;
+                    freed.push((e.pblock + keep as u64, (e.len - keep) as u64));
+                    e.len = keep;
+                }
+                true
+            }
+        });
+        if !freed.is_empty() {
+            self.dirty = true;
+        }
+        freed
+    }
+}
+
+/// Write an empty extent tree to `root`.
+pub fn init_root(root: &mut [u8]) {
+    root.fill(0);
+    write_header(root, 0, ROOT_ENTRIES, 0);
+}
+
+fn write_header(node: &mut [u8], entries: usize, max: usize, depth: u16) {
+    set_u16(node, 0, EXTENT_MAGIC);
+    set_u16(node, 2, entries as u16);
+    set_u16(node, 4, max as u16);
+    set_u16(node, 6, depth);
+    set_u32(node, 8, 0);
+}
+
+/// Returns the number of entries and the depth of a node.
+fn parse_header(node: &[u8]) -> Result<(usize, u16)> {
+    let entries = get_u16(node, 2) as usize;
+    if get_u16(node, 0) != EXTENT_MAGIC || ENTRY_SIZE * (entries + 1) > node.len() {
+        warn!("ext4: corrupted extent tree");
+        return Err(FsError::DeviceError);
+    }
+    Ok((entries, get_u16(node, 6)))
+}
+
+fn leaf_entry(node: &[u8], i: usize) -> Extent {
+    let entry = &node[ENTRY_SIZE * (i + 1)..];
+    let len = get_u16(entry, 4) as u32;
+    let (len, uninit) = if len > MAX_INIT_LEN {
+        (len - MAX_INIT_LEN, true)
+    } else {
+        (len, false)
+    };
+    Extent {
+        lblock: get_u32(entry, 0),
+        len,
+        pblock: (get_u16(entry, 6) as u64) << 32 | get_u32(entry, 8) as u64,
+        uninit,
+    }
+}
+
+fn encode_leaf(e: &Extent) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0; ENTRY_SIZE];
+    let len = if e.uninit {
+        e.len + MAX_INIT_LEN
+    } else {
+        e.len
+    };
+    set_u32(&mut entry, 0, e.lblock);
+    set_u16(&mut entry, 4, len as u16);
+    set_u16(&mut entry, 6, (e.pblock >> 32) as u16);
+    set_u32(&mut entry, 8, e.pblock as u32);
+    entry
+}
+
+/// Returns the first logical block and the child of an index entry.
+fn index_entry(node: &[u8], i: usize) -> (u32, u64) {
+    let entry = &node[ENTRY_SIZE * (i + 1)..];
+    let child = (get_u16(entry, 8) as u64) << 32 | get_u32(entry, 4) as u64;
+    (get_u32(entry, 0), child)
+}
+
+fn encode_index(lblock: u32, child: u64) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0; ENTRY_SIZE];
+    set_u32(&mut entry, 0, lblock);
+    set_u32(&mut entry, 4, child as u32);
+    set_u16(&mut entry, 8, (child >> 32) as u16);
+    entry
+}
+
+impl Ext4 {
+    /// Map logical block `lblock` of a file, `None` for a hole.
+    pub(super) fn map_block(&self, inode: &RawInode, lblock: u32) -> Result<Option<u64>> {
+        if inode.flags() & INODE_EXTENTS_FL == 0 {
+            return self.map_indirect(inode, lblock);
+        }
+        let mut node = inode.block().to_vec();
+        loop {
+            let (entries, depth) = parse_header(&node)?;
+            if depth == 0 {
+                let found = (0..entries)
+                    .map(|i| leaf_entry(&node, i))
+                    .find(|e| e.lblock <= lblock && lblock < e.end());
+                return Ok(found
+                    .filter(|e| !e.uninit)
+                    .map(|e| e.pblock + (lblock - e.lblock) as u64));
+            }
+            let child = (0..entries)
+                .map(|i| index_entry(&node, i))
+                .take_while(|&(start, _)| start <= lblock)
+                .last();
+            match child {
+                Some((_, child)) => node = self.read_block(child)?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn map_indirect(&self, inode: &RawInode, lblock: u32) -> Result<Option<u64>> {
+        let per_block = (self.block_size / 4) as u64;
+        let block = inode.block();
+        let mut index = lblock as u64;
+        if index < 12 {
+            return Ok(nonzero(get_u32(block, index as usize * 4)));
+        }
+        index -= 12;
+        let mut span = 1;
+        for level in 0..3 {
+            span *= per_block;
+            if index < span {
+                let mut ptr = get_u32(block, (12 + level) * 4);
+                while span > 1 {
+                    if ptr == 0 {
+                        return Ok(None);
+                    }
+                    span /= per_block;
+                    let buf = self.read_block(ptr as u64)?;
+                    ptr = get_u32(&buf, (index / span) as usize * 4);
+                    index %= span;
+                }
+                return Ok(nonzero(ptr));
+            }
+            index -= span;
+        }
+        Ok(None)
+    }
+
+    fn load_extents(&self, inode: &RawInode) -> Result<ExtentMap> {
+        let mut map = ExtentMap {
+            extents: Vec::new(),
+            tree_blocks: Vec::new(),
+            dirty: false,
+        };
+        self.walk_extents(inode.block(), &mut map)?;
+        Ok(map)
+    }
+
+    fn walk_extents(&self, node: &[u8], map: &mut ExtentMap) -> Result<()> {
+        let (entries, depth) = parse_header(node)?;
+        for i in 0..entries {
+            if depth == 0 {
+                map.extents.push(leaf_entry(node, i));
+            } else {
+                let (_, child) = index_entry(node, i);
+                map.tree_blocks.push(child);
+                let child = self.read_block(child)?;
+                self.walk_extents(&child, map)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the extent tree of `map` into the inode and its tree blocks.
+    fn store_extents(&mut self, ino: u32, inode: &mut RawInode, map: &mut ExtentMap) -> Result<()> {
+        let bs = self.block_size;
+        let max = (bs - ENTRY_SIZE) / ENTRY_SIZE;
+        let seed = self.inode_csum_seed(ino, inode);
+        let goal = self.inode_goal(ino);
+        let mut pool = core::mem::take(&mut map.tree_blocks);
+        let mut level: Vec<(u32, [u8; ENTRY_SIZE])> = map
+            .extents
+            .iter()
+            .map(|e| (e.lblock, encode_leaf(e)))
+            .collect();
+        let mut depth = 0;
+        while level.len() > ROOT_ENTRIES {
+            let mut upper = Vec::new();
+            for chunk in level.chunks(max) {
+                let block = match pool.pop() {
+                    Some(block) => block,
+                    None => {
+                        let block = self.alloc_block(goal)?;
+                        self.add_blocks(inode, 1);
+                        block
+                    }
+                };
+                let mut buf = vec![0; bs];
+                write_header(&mut buf, chunk.len(), max, depth);
+                for (i, (_, entry)) in chunk.iter().enumerate() {
+                    buf[ENTRY_SIZE * (i + 1)..ENTRY_SIZE * (i + 2)].copy_from_slice(entry);
+                }
+                if self.metadata_csum() {
+                    let tail = ENTRY_SIZE * (max + 1);
+                    let csum = crc32c(seed, &buf[..tail]);
+                    set_u32(&mut buf, tail, csum);
+                }
+                self.write_block(block, &buf)?;
+                map.tree_blocks.push(block);
+                upper.push((chunk[0].0, encode_index(chunk[0].0, block)));
+            }
+            level = upper;
+            depth += 1;
+        }
+        let root = inode.block_mut();
+        root.fill(0);
+        write_header(root, level.len(), ROOT_ENTRIES, depth);
+        for (i, (_, entry)) in level.iter().enumerate() {
+            root[ENTRY_SIZE * (i + 1)..ENTRY_SIZE * (i + 2)].copy_from_slice(entry);
+        }
+        for block in pool {
+            self.free_blocks(block, 1)?;
+            self.add_blocks(inode, -1);
+        }
+        map.dirty = false;
+        Ok(())
+    }
+
+    /// The first block of the group of inode `ino`, where its data is placed.
+    fn inode_goal(&self, ino: u32) -> u64 {
+        let group = (ino - 1) / self.sb.inodes_per_group();
+        self.group_first_block(group as usize)
+    }
+
+    fn add_blocks(&self, inode: &mut RawInode, count: i64) {
+        let sectors = count * (self.block_size / 512) as i64;
+        inode.set_sectors((inode.sectors() as i64 + sectors) as u64);
+    }
+
+    /// Get the block of `lblock` to write, allocating it if needed.
+    ///
+    /// Returns the block and whether its old content is invalid.
+    fn block_for_write(
+        &mut self,
+        ino: u32,
+        inode: &mut RawInode,
+        map: &mut ExtentMap,
+        lblock: u32,
+    ) -> Result<(u64, bool)> {
+        if let Some(i) = map.find(lblock) {
+            let e = map.extents[i];
+            let pblock = e.pblock + (lblock - e.lblock) as u64;
+            if e.uninit {
+                map.initialize(i, lblock);
+            }
+            return Ok((pblock, e.uninit));
+        }
+        let goal = map.goal(lblock).unwrap_or_else(|| self.inode_goal(ino));
+        let pblock = self.alloc_block(goal)?;
+        self.add_blocks(inode, 1);
+        map.insert(lblock, pblock);
+        Ok((pblock, true))
+    }
+
+    /// Allocate block `lblock` of a file, filled with zeros.
+    pub(super) fn alloc_data_block(
+        &mut self,
+        ino: u32,
+        inode: &mut RawInode,
+        lblock: u32,
+    ) -> Result<u64> {
+        if inode.flags() & INODE_EXTENTS_FL == 0 {
+            return Err(FsError::NotSupported);
+        }
+        let mut map = self.load_extents(inode)?;
+        let (pblock, _) = self.block_for_write(ino, inode, &mut map, lblock)?;
+        self.write_block(pblock, &vec![0; self.block_size])?;
+        if map.dirty {
+            self.store_extents(ino, inode, &mut map)?;
+        }
+        Ok(pblock)
+    }
+
+    /// Read the data of a file at `offset`, holes are read as zeros.
+    pub(super) fn read_data(
+        &self,
+        inode: &RawInode,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        let size = inode.size() as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let end = size.min(offset + buf.len());
+        let map = if inode.flags() & INODE_EXTENTS_FL != 0 {
+            Some(self.load_extents(inode)?)
+        } else {
+            None
+        };
+        let bs = self.block_size;
+        let mut pos = offset;
+        while pos < end {
+            let lblock = (pos / bs) as u32;
+            let len = (bs - pos % bs).min(end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            let pblock = match &map {
+                Some(map) => map.map(lblock),
+                None => self.map_indirect(inode, lblock)?,
+            };
+            match pblock {
+                Some(pblock) => read_exact(&*self.device, pblock as usize * bs + pos % bs, dst)?,
+                None => dst.fill(0),
+            }
+            pos += len;
+        }
+        Ok(end - offset)
+    }
+
+    /// Write the data of a file at `offset`, growing it if needed.
+    pub(super) fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut RawInode,
+        offset: usize,
+        buf: &[u8],
+    ) -> Result<()> {
+        if inode.flags() & INODE_EXTENTS_FL == 0 {
+            return Err(FsError::NotSupported);
+        }
+        let mut map = self.load_extents(inode)?;
+        let bs = self.block_size;
+        let end = offset + buf.len();
+        let mut pos = offset;
+        let result = (|| {
+            while pos < end {
+                let lblock = (pos / bs) as u32;
+                let len = (bs - pos % bs).min(end - pos);
+                let src = &buf[pos - offset..pos - offset + len];
+                let (pblock, fresh) = self.block_for_write(ino, inode, &mut map, lblock)?;
+                if fresh && len < bs {
+                    let mut block = vec![0; bs];
+                    block[pos % bs..pos % bs + len].copy_from_slice(src);
+                    self.write_block(pblock, &block)?;
+                } else {
+                    write_all(&*self.device, pblock as usize * bs + pos % bs, src)?;
+                }
+                pos += len;
+            }
+            Ok(())
+        })();
+        // keep what has been allocated even if the device is full
+        if map.dirty {
+            self.store_extents(ino, inode, &mut map)?;
+        }
+        if pos > inode.size() as usize {
+            inode.set_size(pos as u64);
+        }
+        result
+    }
+
+    /// Change the size of a file, freeing the blocks beyond it.
+    pub(super) fn truncate(&mut self, ino: u32, inode: &mut RawInode, size: u64) -> Result<()> {
+        let bs = self.block_size as u64;
+        if size < inode.size() {
+            let lblock = ((size + bs - 1) / bs) as u32;
+            if inode.flags() & INODE_EXTENTS_FL != 0 {
+                let mut map = self.load_extents(inode)?;
+                for (start, count) in map.truncate(lblock) {
+                    self.free_blocks(start, count)?;
+                    self.add_blocks(inode, -(count as i64));
+                }
+                if map.dirty {
+                    self.store_extents(ino, inode, &mut map)?;
+                }
+            } else if size == 0 {
+                self.free_indirect(inode)?;
+                init_root(inode.block_mut());
+                inode.set_flags(inode.flags() | INODE_EXTENTS_FL);
+            } else {
+                return Err(FsError::NotSupported);
+            }
+            // the tail of the last block is read as zeros when the file grows
+            if size % bs != 0 {
+                if let Some(pblock) = self.map_block(inode, (size / bs) as u32)? {
+                    let tail = vec![0; (bs - size % bs) as usize];
+                    write_all(&*self.device, (pblock * bs + size % bs) as usize, &tail)?;
+                }
+            }
+        }
+        inode.set_size(size);
+        Ok(())
+    }
+
+    /// Free all blocks of a file mapped by indirect blocks.
+    fn free_indirect(&mut self, inode: &mut RawInode) -> Result<()> {
+        let roots: Vec<u32> = (0..15).map(|i| get_u32(inode.block(), i * 4)).collect();
+        for (i, &root) in roots.iter().enumerate() {
+            self.free_indirect_tree(root, i.saturating_sub(11))?;
+        }
+        inode.set_sectors(0);
+        Ok(())
+    }
+
+    fn free_indirect_tree(&mut self, block: u32, level: usize) -> Result<()> {
+        if block == 0 {
+            return Ok(());
+        }
+        if level > 0 {
+            let buf = self.read_block(block as u64)?;
+            for i in 0..self.block_size / 4 {
+                self.free_indirect_tree(get_u32(&buf, i * 4), level - 1)?;
+            }
+        }
+        self.free_blocks(block as u64, 1)
+    }
+}
+
+fn nonzero(block: u32) -> Option<u64> {
+    match block {
+        0 => None,
+        block => Some(block as u64),
+    }
+}
@@ -0,0 +1,457 @@
+//! On-disk structures of ext4, accessed by offsets in little-endian buffers
+
+use alloc::vec::Vec;
+
+/// Magic number of the superblock.
+pub const EXT4_MAGIC: u16 = 0xEF53;
+/// Byte offset of the primary superblock.
+pub const SUPERBLOCK_OFFSET: usize = 1024;
+/// Size of the superblock in bytes.
+pub const SUPERBLOCK_SIZE: usize = 1024;
+/// Inode number of the root directory.
+pub const ROOT_INO: u32 = 2;
+/// Size of `i_block` in the inode, holding the extent tree root or a fast symlink.
+pub const I_BLOCK_SIZE: usize = 60;
+/// Maximum length of a file name.
+pub const NAME_MAX: usize = 255;
+
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+pub const INCOMPAT_RECOVER: u32 = 0x4;
+pub const INCOMPAT_EXTENTS: u32 = 0x40;
+pub const INCOMPAT_64BIT: u32 = 0x80;
+pub const INCOMPAT_FLEX_BG: u32 = 0x200;
+pub const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+pub const INCOMPAT_LARGEDIR: u32 = 0x4000;
+/// Incompatible features understood by the driver.
+pub const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_RECOVER
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+pub const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+pub const RO_COMPAT_GDT_CSUM: u32 = 0x10;
+pub const RO_COMPAT_DIR_NLINK: u32 = 0x20;
+pub const RO_COMPAT_EXTRA_ISIZE: u32 = 0x40;
+pub const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+pub const RO_COMPAT_ORPHAN_PRESENT: u32 = 0x10000;
+/// Read-only compatible features the driver can keep consistent when writing.
+pub const RO_COMPAT_WRITABLE: u32 = RO_COMPAT_SPARSE_SUPER
+    | RO_COMPAT_LARGE_FILE
+    | RO_COMPAT_HUGE_FILE
+    | RO_COMPAT_GDT_CSUM
+    | RO_COMPAT_DIR_NLINK
+    | RO_COMPAT_EXTRA_ISIZE
+    | RO_COMPAT_METADATA_CSUM
+    | RO_COMPAT_ORPHAN_PRESENT;
+
+/// Superblock flag: directory hashes use unsigned chars.
+pub const FLAGS_UNSIGNED_HASH: u32 = 0x2;
+
+/// Group flag: the inode bitmap is not initialized.
+pub const BG_INODE_UNINIT: u16 = 0x1;
+/// Group flag: the block bitmap is not initialized.
+pub const BG_BLOCK_UNINIT: u16 = 0x2;
+
+/// Inode flag: the directory is indexed by a hash tree.
+pub const INODE_INDEX_FL: u32 = 0x1000;
+/// Inode flag: the file data is mapped by extents.
+pub const INODE_EXTENTS_FL: u32 = 0x80000;
+
+pub const S_IFMT: u16 = 0o170000;
+pub const S_IFSOCK: u16 = 0o140000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFBLK: u16 = 0o060000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFCHR: u16 = 0o020000;
+pub const S_IFIFO: u16 = 0o010000;
+
+pub fn get_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+pub fn set_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn set_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+const fn make_crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = make_crc32c_table();
+
+/// CRC32c without the final inversion, as `ext4_chksum` in Linux.
+pub fn crc32c(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// CRC16 (ANSI), used by group descriptors without `metadata_csum`.
+pub fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// The superblock
+pub struct SuperBlock {
+    pub raw: Vec<u8>,
+}
+
+impl SuperBlock {
+    pub fn inodes_count(&self) -> u32 {
+        get_u32(&self.raw, 0x0)
+    }
+    pub fn blocks_count(&self) -> u64 {
+        self.lo_hi(0x4, 0x150)
+    }
+    pub fn r_blocks_count(&self) -> u64 {
+        self.lo_hi(0x8, 0x154)
+    }
+    pub fn free_blocks_count(&self) -> u64 {
+        self.lo_hi(0xC, 0x158)
+    }
+    pub fn set_free_blocks_count(&mut self, count: u64) {
+        set_u32(&mut self.raw, 0xC, count as u32);
+        if self.is_64bit() {
+            set_u32(&mut self.raw, 0x158, (count >> 32) as u32);
+        }
+    }
+    pub fn free_inodes_count(&self) -> u32 {
+        get_u32(&self.raw, 0x10)
+    }
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        set_u32(&mut self.raw, 0x10, count);
+    }
+    pub fn first_data_block(&self) -> u32 {
+        get_u32(&self.raw, 0x14)
+    }
+    pub fn block_size(&self) -> usize {
+        1024 << get_u32(&self.raw, 0x18)
+    }
+    pub fn blocks_per_group(&self) -> u32 {
+        get_u32(&self.raw, 0x20)
+    }
+    pub fn inodes_per_group(&self) -> u32 {
+        get_u32(&self.raw, 0x28)
+    }
+    pub fn magic(&self) -> u16 {
+        get_u16(&self.raw, 0x38)
+    }
+    pub fn rev_level(&self) -> u32 {
+        get_u32(&self.raw, 0x4C)
+    }
+    pub fn first_ino(&self) -> u32 {
+        if self.rev_level() == 0 {
+            11
+        } else {
+            get_u32(&self.raw, 0x54)
+        }
+    }
+    pub fn inode_size(&self) -> usize {
+        if self.rev_level() == 0 {
+            128
+        } else {
+            get_u16(&self.raw, 0x58) as usize
+        }
+    }
+    pub fn feature_incompat(&self) -> u32 {
+        get_u32(&self.raw, 0x60)
+    }
+    pub fn feature_ro_compat(&self) -> u32 {
+        get_u32(&self.raw, 0x64)
+    }
+    pub fn uuid(&self) -> &[u8] {
+        &self.raw[0x68..0x78]
+    }
+    pub fn reserved_gdt_blocks(&self) -> u32 {
+        get_u16(&self.raw, 0xCE) as u32
+    }
+    pub fn hash_seed(&self) -> [u32; 4] {
+        let mut seed = [0; 4];
+        for (i, s) in seed.iter_mut().enumerate() {
+            *s = get_u32(&self.raw, 0xEC + i * 4);
+        }
+        seed
+    }
+    pub fn desc_size(&self) -> usize {
+        if self.is_64bit() {
+            (get_u16(&self.raw, 0xFE) as usize).max(32)
+        } else {
+            32
+        }
+    }
+    pub fn flags(&self) -> u32 {
+        get_u32(&self.raw, 0x160)
+    }
+    pub fn checksum_seed(&self) -> u32 {
+        get_u32(&self.raw, 0x270)
+    }
+    pub fn is_64bit(&self) -> bool {
+        self.feature_incompat() & INCOMPAT_64BIT != 0
+    }
+    pub fn has_ro_compat(&self, feature: u32) -> bool {
+        self.feature_ro_compat() & feature != 0
+    }
+    pub fn group_count(&self) -> usize {
+        let blocks = self.blocks_count() - self.first_data_block() as u64;
+        ((blocks + self.blocks_per_group() as u64 - 1) / self.blocks_per_group() as u64) as usize
+    }
+    /// Update the checksum of the superblock if `metadata_csum` is enabled.
+    pub fn update_checksum(&mut self) {
+        if self.has_ro_compat(RO_COMPAT_METADATA_CSUM) {
+            let csum = crc32c(!0, &self.raw[..0x3FC]);
+            set_u32(&mut self.raw, 0x3FC, csum);
+        }
+    }
+    fn lo_hi(&self, lo: usize, hi: usize) -> u64 {
+        let mut value = get_u32(&self.raw, lo) as u64;
+        if self.is_64bit() {
+            value |= (get_u32(&self.raw, hi) as u64) << 32;
+        }
+        value
+    }
+}
+
+/// A block group descriptor
+pub struct GroupDesc {
+    pub raw: Vec<u8>,
+}
+
+impl GroupDesc {
+    pub fn block_bitmap(&self) -> u64 {
+        self.lo_hi32(0x0, 0x20)
+    }
+    pub fn inode_bitmap(&self) -> u64 {
+        self.lo_hi32(0x4, 0x24)
+    }
+    pub fn inode_table(&self) -> u64 {
+        self.lo_hi32(0x8, 0x28)
+    }
+    pub fn free_blocks_count(&self) -> u32 {
+        self.lo_hi16(0xC, 0x2C)
+    }
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        self.set_lo_hi16(0xC, 0x2C, count);
+    }
+    pub fn free_inodes_count(&self) -> u32 {
+        self.lo_hi16(0xE, 0x2E)
+    }
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        self.set_lo_hi16(0xE, 0x2E, count);
+    }
+    pub fn used_dirs_count(&self) -> u32 {
+        self.lo_hi16(0x10, 0x30)
+    }
+    pub fn set_used_dirs_count(&mut self, count: u32) {
+        self.set_lo_hi16(0x10, 0x30, count);
+    }
+    pub fn flags(&self) -> u16 {
+        get_u16(&self.raw, 0x12)
+    }
+    pub fn set_flags(&mut self, flags: u16) {
+        set_u16(&mut self.raw, 0x12, flags);
+    }
+    pub fn itable_unused(&self) -> u32 {
+        self.lo_hi16(0x1C, 0x32)
+    }
+    pub fn set_itable_unused(&mut self, count: u32) {
+        self.set_lo_hi16(0x1C, 0x32, count);
+    }
+    pub fn set_block_bitmap_csum(&mut self, csum: u32) {
+        set_u16(&mut self.raw, 0x18, csum as u16);
+        if self.raw.len() >= 0x3C {
+            set_u16(&mut self.raw, 0x38, (csum >> 16) as u16);
+        }
+    }
+    pub fn set_inode_bitmap_csum(&mut self, csum: u32) {
+        set_u16(&mut self.raw, 0x1A, csum as u16);
+        if self.raw.len() >= 0x3C {
+            set_u16(&mut self.raw, 0x3A, (csum >> 16) as u16);
+        }
+    }
+    pub fn set_checksum(&mut self, csum: u16) {
+        set_u16(&mut self.raw, 0x1E, csum);
+    }
+    fn is_64bit(&self) -> bool {
+        self.raw.len() >= 64
+    }
+    fn lo_hi32(&self, lo: usize, hi: usize) -> u64 {
+        let mut value = get_u32(&self.raw, lo) as u64;
+        if self.is_64bit() {
+            value |= (get_u32(&self.raw, hi) as u64) << 32;
+        }
+        value
+    }
+    fn lo_hi16(&self, lo: usize, hi: usize) -> u32 {
+        let mut value = get_u16(&self.raw, lo) as u32;
+        if self.is_64bit() {
+            value |= (get_u16(&self.raw, hi) as u32) << 16;
+        }
+        value
+    }
+    fn set_lo_hi16(&mut self, lo: usize, hi: usize, value: u32) {
+        set_u16(&mut self.raw, lo, value as u16);
+        if self.is_64bit() {
+            set_u16(&mut self.raw, hi, (value >> 16) as u16);
+        }
+    }
+}
+
+/// An inode
+#[derive(Clone)]
+pub struct RawInode {
+    pub raw: Vec<u8>,
+}
+
+impl RawInode {
+    pub fn mode(&self) -> u16 {
+        get_u16(&self.raw, 0x0)
+    }
+    pub fn set_mode(&mut self, mode: u16) {
+        set_u16(&mut self.raw, 0x0, mode);
+    }
+    pub fn file_type(&self) -> u16 {
+        self.mode() & S_IFMT
+    }
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == S_IFDIR
+    }
+    pub fn uid(&self) -> u32 {
+        get_u16(&self.raw, 0x2) as u32 | (get_u16(&self.raw, 0x78) as u32) << 16
+    }
+    pub fn set_uid(&mut self, uid: u32) {
+        set_u16(&mut self.raw, 0x2, uid as u16);
+        set_u16(&mut self.raw, 0x78, (uid >> 16) as u16);
+    }
+    pub fn gid(&self) -> u32 {
+        get_u16(&self.raw, 0x18) as u32 | (get_u16(&self.raw, 0x7A) as u32) << 16
+    }
+    pub fn set_gid(&mut self, gid: u32) {
+        set_u16(&mut self.raw, 0x18, gid as u16);
+        set_u16(&mut self.raw, 0x7A, (gid >> 16) as u16);
+    }
+    pub fn size(&self) -> u64 {
+        get_u32(&self.raw, 0x4) as u64 | (get_u32(&self.raw, 0x6C) as u64) << 32
+    }
+    pub fn set_size(&mut self, size: u64) {
+        set_u32(&mut self.raw, 0x4, size as u32);
+        set_u32(&mut self.raw, 0x6C, (size >> 32) as u32);
+    }
+    pub fn atime(&self) -> u32 {
+        get_u32(&self.raw, 0x8)
+    }
+    pub fn set_atime(&mut self, time: u32) {
+        set_u32(&mut self.raw, 0x8, time);
+    }
+    pub fn ctime(&self) -> u32 {
+        get_u32(&self.raw, 0xC)
+    }
+    pub fn set_ctime(&mut self, time: u32) {
+        set_u32(&mut self.raw, 0xC, time);
+    }
+    pub fn mtime(&self) -> u32 {
+        get_u32(&self.raw, 0x10)
+    }
+    pub fn set_mtime(&mut self, time: u32) {
+        set_u32(&mut self.raw, 0x10, time);
+    }
+    pub fn set_dtime(&mut self, time: u32) {
+        set_u32(&mut self.raw, 0x14, time);
+    }
+    pub fn links_count(&self) -> u16 {
+        get_u16(&self.raw, 0x1A)
+    }
+    pub fn set_links_count(&mut self, count: u16) {
+        set_u16(&mut self.raw, 0x1A, count);
+    }
+    /// Number of 512-byte sectors used by the inode.
+    pub fn sectors(&self) -> u64 {
+        get_u32(&self.raw, 0x1C) as u64 | (get_u16(&self.raw, 0x74) as u64) << 32
+    }
+    pub fn set_sectors(&mut self, sectors: u64) {
+        set_u32(&mut self.raw, 0x1C, sectors as u32);
+        set_u16(&mut self.raw, 0x74, (sectors >> 32) as u16);
+    }
+    pub fn flags(&self) -> u32 {
+        get_u32(&self.raw, 0x20)
+    }
+    pub fn set_flags(&mut self, flags: u32) {
+        set_u32(&mut self.raw, 0x20, flags);
+    }
+    pub fn block(&self) -> &[u8] {
+        &self.raw[0x28..0x28 + I_BLOCK_SIZE]
+    }
+    pub fn block_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[0x28..0x28 + I_BLOCK_SIZE]
+    }
+    pub fn generation(&self) -> u32 {
+        get_u32(&self.raw, 0x64)
+    }
+    pub fn set_generation(&mut self, generation: u32) {
+        set_u32(&mut self.raw, 0x64, generation);
+    }
+    pub fn extra_isize(&self) -> usize {
+        if self.raw.len() > 128 {
+            get_u16(&self.raw, 0x80) as usize
+        } else {
+            0
+        }
+    }
+    pub fn set_extra_isize(&mut self, size: u16) {
+        if self.raw.len() > 128 {
+            set_u16(&mut self.raw, 0x80, size);
+        }
+    }
+    /// Whether `i_checksum_hi` is present.
+    pub fn has_checksum_hi(&self) -> bool {
+        self.extra_isize() >= 4
+    }
+    pub fn set_checksum(&mut self, csum: u32) {
+        set_u16(&mut self.raw, 0x7C, csum as u16);
+        if self.has_checksum_hi() {
+            set_u16(&mut self.raw, 0x82, (csum >> 16) as u16);
+        }
+    }
+}
@@ -0,0 +1,1260 @@
+//! Implement the ext4 file system
+//!
+//! The driver works on the on-disk format directly: extent trees, linear and
+//! hash-tree directories, and the checksums of `metadata_csum` are kept
+//! consistent so that the image can still be checked by `e2fsck`. There is no
+//! journal, so a file system whose journal needs recovery is mounted
+//! read-only.
+#![deny(missing_docs)]
+
+mod dir;
+mod extent;
+mod layout;
+
+use self::layout::*;
+use crate::time::TimeSpec;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use lock::Mutex;
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::*;
+
+/// The ext4 file system
+pub struct Ext4FileSystem {
+    ext4: Mutex<Ext4>,
+    /// opened INodes, so that every inode has at most one instance
+    inodes: Mutex<BTreeMap<u32, Weak<Ext4INode>>>,
+    self_ref: Weak<Ext4FileSystem>,
+}
+
+impl Ext4FileSystem {
+    /// Open the ext4 file system on `device`.
+    pub fn open(device: Arc<dyn Device>) -> Result<Arc<Self>> {
+        let ext4 = Ext4::open(device)?;
+        Ok(Arc::new_cyclic(|fs| Ext4FileSystem {
+            ext4: Mutex::new(ext4),
+            inodes: Mutex::new(BTreeMap::new()),
+            self_ref: fs.clone(),
+        }))
+    }
+
+    fn get_inode(&self, ino: u32) -> Arc<Ext4INode> {
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&ino).and_then(|inode| inode.upgrade()) {
+            return inode;
+        }
+        let inode = Arc::new(Ext4INode {
+            ino,
+            fs: self.self_ref.upgrade().unwrap(),
+        });
+        inodes.insert(ino, Arc::downgrade(&inode));
+        inode
+    }
+
+    fn is_in_use(&self, ino: u32) -> bool {
+        self.inodes
+            .lock()
+            .get(&ino)
+            .map_or(false, |inode| inode.strong_count() > 0)
+    }
+
+    /// Remove the entry `name` of inode `ino` from directory `dir_ino`.
+    ///
+    /// The inode is deleted once it has no links, or when the last INode of
+    /// it is dropped if it is still opened.
+    fn unlink_entry(
+        &self,
+        ext4: &mut Ext4,
+        dir_ino: u32,
+        dir: &mut RawInode,
+        name: &str,
+        ino: u32,
+    ) -> Result<()> {
+        let mut inode = ext4.read_inode(ino)?;
+        if inode.is_dir() {
+            if !ext4.dir_is_empty(&inode)? {
+                return Err(FsError::DirNotEmpty);
+            }
+            inode.set_links_count(0);
+            if dir.links_count() > 2 {
+                dir.set_links_count(dir.links_count() - 1);
+            }
+        } else {
+            inode.set_links_count(inode.links_count().saturating_sub(1));
+        }
+        ext4.dir_remove(dir_ino, dir, name)?;
+        let now = now();
+        inode.set_ctime(now);
+        dir.set_mtime(now);
+        dir.set_ctime(now);
+        if inode.links_count() == 0 && !self.is_in_use(ino) {
+            ext4.delete_inode(ino, &mut inode)?;
+        } else {
+            ext4.write_inode(ino, &mut inode)?;
+        }
+        ext4.write_inode(dir_ino, dir)
+    }
+}
+
+impl FileSystem for Ext4FileSystem {
+    fn sync(&self) -> Result<()> {
+        let ext4 = self.ext4.lock();
+        ext4.device.sync().map_err(|_| FsError::DeviceError)
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.get_inode(ROOT_INO)
+    }
+
+    fn info(&self) -> FsInfo {
+        let ext4 = self.ext4.lock();
+        let sb = &ext4.sb;
+        FsInfo {
+            bsize: ext4.block_size,
+            frsize: ext4.block_size,
+            blocks: sb.blocks_count() as usize,
+            bfree: sb.free_blocks_count() as usize,
+            bavail: sb.free_blocks_count().saturating_sub(sb.r_blocks_count()) as usize,
+            files: sb.inodes_count() as usize,
+            ffree: sb.free_inodes_count() as usize,
+            namemax: NAME_MAX,
+        }
+    }
+}
+
+/// The state of a mounted ext4
+struct Ext4 {
+    device: Arc<dyn Device>,
+    block_size: usize,
+    sb: SuperBlock,
+    groups: Vec<GroupDesc>,
+    /// the seed of all metadata checksums
+    csum_seed: u32,
+    read_only: bool,
+    next_generation: u32,
+}
+
+fn read_exact(device: &dyn Device, offset: usize, buf: &mut [u8]) -> Result<()> {
+    match device.read_at(offset, buf) {
+        Ok(len) if len == buf.len() => Ok(()),
+        _ => Err(FsError::DeviceError),
+    }
+}
+
+fn write_all(device: &dyn Device, offset: usize, buf: &[u8]) -> Result<()> {
+    match device.write_at(offset, buf) {
+        Ok(len) if len == buf.len() => Ok(()),
+        _ => Err(FsError::DeviceError),
+    }
+}
+
+fn test_bit(bitmap: &[u8], bit: usize) -> bool {
+    bitmap[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+fn set_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] |= 1 << (bit % 8);
+}
+
+fn clear_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] &= !(1 << (bit % 8));
+}
+
+fn now() -> u32 {
+    TimeSpec::now().sec as u32
+}
+
+fn is_power_of(mut n: usize, base: usize) -> bool {
+    while n % base == 0 {
+        n /= base;
+    }
+    n == 1
+}
+
+impl Ext4 {
+    fn open(device: Arc<dyn Device>) -> Result<Self> {
+        let mut raw = vec![0; SUPERBLOCK_SIZE];
+        read_exact(&*device, SUPERBLOCK_OFFSET, &mut raw)?;
+        let sb = SuperBlock { raw };
+        if sb.magic() != EXT4_MAGIC {
+            return Err(FsError::WrongFs);
+        }
+        let unsupported = sb.feature_incompat() & !INCOMPAT_SUPPORTED;
+        if unsupported != 0 {
+            warn!("ext4: unsupported incompatible features {:#x}", unsupported);
+            return Err(FsError::NotSupported);
+        }
+        let read_only = if sb.feature_incompat() & INCOMPAT_RECOVER != 0 {
+            warn!("ext4: the journal needs recovery, mounted read-only");
+            true
+        } else if sb.feature_incompat() & INCOMPAT_EXTENTS == 0 {
+            warn!("ext4: no extents, mounted read-only");
+            true
+        } else if sb.feature_ro_compat() & !RO_COMPAT_WRITABLE != 0 {
+            warn!(
+                "ext4: unsupported read-only features {:#x}, mounted read-only",
+                sb.feature_ro_compat() & !RO_COMPAT_WRITABLE
+            );
+            true
+        } else {
+            false
+        };
+        let block_size = sb.block_size();
+        let desc_size = sb.desc_size();
+        let gdt_offset = (sb.first_data_block() as usize + 1) * block_size;
+        let mut groups = Vec::new();
+        for i in 0..sb.group_count() {
+            let mut raw = vec![0; desc_size];
+            read_exact(&*device, gdt_offset + i * desc_size, &mut raw)?;
+            groups.push(GroupDesc { raw });
+        }
+        let csum_seed = if sb.feature_incompat() & INCOMPAT_CSUM_SEED != 0 {
+            sb.checksum_seed()
+        } else {
+            crc32c(!0, sb.uuid())
+        };
+        Ok(Ext4 {
+            device,
+            block_size,
+            sb,
+            groups,
+            csum_seed,
+            read_only,
+            next_generation: now(),
+        })
+    }
+
+    fn writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(FsError::NotSupported);
+        }
+        Ok(())
+    }
+
+    fn metadata_csum(&self) -> bool {
+        self.sb.has_ro_compat(RO_COMPAT_METADATA_CSUM)
+    }
+
+    fn read_block(&self, block: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0; self.block_size];
+        read_exact(&*self.device, block as usize * self.block_size, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn write_block(&self, block: u64, buf: &[u8]) -> Result<()> {
+        write_all(&*self.device, block as usize * self.block_size, buf)
+    }
+
+    fn write_super(&mut self) -> Result<()> {
+        self.sb.update_checksum();
+        write_all(&*self.device, SUPERBLOCK_OFFSET, &self.sb.raw)
+    }
+
+    fn write_group(&mut self, group: usize) -> Result<()> {
+        let csum = self.group_checksum(group);
+        let desc = &mut self.groups[group];
+        desc.set_checksum(csum);
+        let desc_size = desc.raw.len();
+        let offset = (self.sb.first_data_block() as usize + 1) * self.block_size;
+        write_all(&*self.device, offset + group * desc_size, &desc.raw)
+    }
+
+    fn group_checksum(&self, group: usize) -> u16 {
+        let raw = &self.groups[group].raw;
+        let group = (group as u32).to_le_bytes();
+        if self.metadata_csum() {
+            let mut csum = crc32c(self.csum_seed, &group);
+            csum = crc32c(csum, &raw[..0x1E]);
+            csum = crc32c(csum, &[0, 0]);
+            csum = crc32c(csum, &raw[0x20..]);
+            csum as u16
+        } else if self.sb.has_ro_compat(RO_COMPAT_GDT_CSUM) {
+            let mut csum = crc16(!0, self.sb.uuid());
+            csum = crc16(csum, &group);
+            csum = crc16(csum, &raw[..0x1E]);
+            crc16(csum, &raw[0x20..])
+        } else {
+            0
+        }
+    }
+
+    fn group_first_block(&self, group: usize) -> u64 {
+        self.sb.first_data_block() as u64 + group as u64 * self.sb.blocks_per_group() as u64
+    }
+
+    /// Number of blocks in `group`, the last group may be smaller.
+    fn group_blocks(&self, group: usize) -> usize {
+        let remain = self.sb.blocks_count() - self.group_first_block(group);
+        remain.min(self.sb.blocks_per_group() as u64) as usize
+    }
+
+    /// Whether `group` holds a backup of the superblock.
+    fn group_has_super(&self, group: usize) -> bool {
+        group <= 1
+            || !self.sb.has_ro_compat(RO_COMPAT_SPARSE_SUPER)
+            || is_power_of(group, 3)
+            || is_power_of(group, 5)
+            || is_power_of(group, 7)
+    }
+
+    fn read_block_bitmap(&self, group: usize) -> Result<Vec<u8>> {
+        let desc = &self.groups[group];
+        if desc.flags() & BG_BLOCK_UNINIT == 0 {
+            return self.read_block(desc.block_bitmap());
+        }
+        // build the bitmap of an uninitialized group from the metadata in it
+        let mut bitmap = vec![0; self.block_size];
+        let start = self.group_first_block(group);
+        let count = self.group_blocks(group);
+        for bit in count..self.block_size * 8 {
+            set_bit(&mut bitmap, bit);
+        }
+        if self.group_has_super(group) {
+            let desc_size = self.sb.desc_size();
+            let gdt_blocks =
+                (self.groups.len() * desc_size + self.block_size - 1) / self.block_size;
+            for bit in 0..1 + gdt_blocks + self.sb.reserved_gdt_blocks() as usize {
+                set_bit(&mut bitmap, bit);
+            }
+        }
+        let itable_blocks = self.itable_blocks() as u64;
+        for desc in self.groups.iter() {
+            let metadata = [desc.block_bitmap(), desc.inode_bitmap()];
+            let table = desc.inode_table()..desc.inode_table() + itable_blocks;
+            for block in metadata.iter().cloned().chain(table) {
+                if block >= start && block < start + count as u64 {
+                    set_bit(&mut bitmap, (block - start) as usize);
+                }
+            }
+        }
+        Ok(bitmap)
+    }
+
+    fn write_block_bitmap(&mut self, group: usize, bitmap: &[u8]) -> Result<()> {
+        let csum = crc32c(
+            self.csum_seed,
+            &bitmap[..self.sb.blocks_per_group() as usize / 8],
+        );
+        let metadata_csum = self.metadata_csum();
+        let desc = &mut self.groups[group];
+        if metadata_csum {
+            desc.set_block_bitmap_csum(csum);
+        }
+        desc.set_flags(desc.flags() & !BG_BLOCK_UNINIT);
+        let block = desc.block_bitmap();
+        self.write_block(block, bitmap)
+    }
+
+    fn read_inode_bitmap(&self, group: usize) -> Result<Vec<u8>> {
+        let desc = &self.groups[group];
+        if desc.flags() & BG_INODE_UNINIT == 0 {
+            return self.read_block(desc.inode_bitmap());
+        }
+        let mut bitmap = vec![0; self.block_size];
+        for bit in self.sb.inodes_per_group() as usize..self.block_size * 8 {
+            set_bit(&mut bitmap, bit);
+        }
+        Ok(bitmap)
+    }
+
+    fn write_inode_bitmap(&mut self, group: usize, bitmap: &[u8]) -> Result<()> {
+        let csum = crc32c(
+            self.csum_seed,
+            &bitmap[..self.sb.inodes_per_group() as usize / 8],
+        );
+        let metadata_csum = self.metadata_csum();
+        let desc = &mut self.groups[group];
+        if metadata_csum {
+            desc.set_inode_bitmap_csum(csum);
+        }
+        desc.set_flags(desc.flags() & !BG_INODE_UNINIT);
+        let block = desc.inode_bitmap();
+        self.write_block(block, bitmap)
+    }
+
+    /// Number of blocks of the inode table in each group.
+    fn itable_blocks(&self) -> usize {
+        let size = self.sb.inodes_per_group() as usize * self.sb.inode_size();
+        (size + self.block_size - 1) / self.block_size
+    }
+
+    /// Allocate a block, as close to `goal` as possible.
+    fn alloc_block(&mut self, goal: u64) -> Result<u64> {
+        let first = self.sb.first_data_block() as u64;
+        let per_group = self.sb.blocks_per_group() as u64;
+        let goal = if goal < first || goal >= self.sb.blocks_count() {
+            first
+        } else {
+            goal
+        };
+        let goal_group = ((goal - first) / per_group) as usize;
+        // visit the goal group twice, for the blocks before the goal
+        for i in 0..=self.groups.len() {
+            let group = (goal_group + i) % self.groups.len();
+            if self.groups[group].free_blocks_count() == 0 {
+                continue;
+            }
+            let mut bitmap = self.read_block_bitmap(group)?;
+            let start = if i == 0 {
+                ((goal - first) % per_group) as usize
+            } else {
+                0
+            };
+            let found = (start..self.group_blocks(group)).find(|&bit| !test_bit(&bitmap, bit));
+            if let Some(bit) = found {
+                set_bit(&mut bitmap, bit);
+                self.write_block_bitmap(group, &bitmap)?;
+                self.add_free_blocks(group, -1)?;
+                return Ok(self.group_first_block(group) + bit as u64);
+            }
+        }
+        Err(FsError::NoDeviceSpace)
+    }
+
+    /// Free `count` blocks from `start`.
+    fn free_blocks(&mut self, mut start: u64, mut count: u64) -> Result<()> {
+        let first = self.sb.first_data_block() as u64;
+        let per_group = self.sb.blocks_per_group() as u64;
+        while count > 0 {
+            let group = ((start - first) / per_group) as usize;
+            let bit = ((start - first) % per_group) as usize;
+            let len = count.min(per_group - bit as u64) as usize;
+            let mut bitmap = self.read_block_bitmap(group)?;
+            let mut freed = 0;
+            for bit in bit..bit + len {
+                if test_bit(&bitmap, bit) {
+                    clear_bit(&mut bitmap, bit);
+                    freed += 1;
+                }
+            }
+            self.write_block_bitmap(group, &bitmap)?;
+            self.add_free_blocks(group, freed)?;
+            start += len as u64;
+            count -= len as u64;
+        }
+        Ok(())
+    }
+
+    fn add_free_blocks(&mut self, group: usize, delta: i64) -> Result<()> {
+        let desc = &mut self.groups[group];
+        desc.set_free_blocks_count((desc.free_blocks_count() as i64 + delta) as u32);
+        let free = self.sb.free_blocks_count() as i64 + delta;
+        self.sb.set_free_blocks_count(free as u64);
+        self.write_group(group)?;
+        self.write_super()
+    }
+
+    /// Allocate an inode, in the group of its parent if possible.
+    fn alloc_inode(&mut self, parent: u32, is_dir: bool) -> Result<u32> {
+        let per_group = self.sb.inodes_per_group();
+        let first_ino = self.sb.first_ino();
+        let goal_group = ((parent - 1) / per_group) as usize;
+        for i in 0..self.groups.len() {
+            let group = (goal_group + i) % self.groups.len();
+            if self.groups[group].free_inodes_count() == 0 {
+                continue;
+            }
+            let mut bitmap = self.read_inode_bitmap(group)?;
+            let base = group as u32 * per_group + 1;
+            let found = (0..per_group as usize)
+                .find(|&bit| !test_bit(&bitmap, bit) && base + bit as u32 >= first_ino);
+            let bit = match found {
+                Some(bit) => bit,
+                None => continue,
+            };
+            set_bit(&mut bitmap, bit);
+            self.write_inode_bitmap(group, &bitmap)?;
+            if self.groups[group].flags() & BG_BLOCK_UNINIT != 0 {
+                let bitmap = self.read_block_bitmap(group)?;
+                self.write_block_bitmap(group, &bitmap)?;
+            }
+            let desc = &mut self.groups[group];
+            desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+            if is_dir {
+                desc.set_used_dirs_count(desc.used_dirs_count() + 1);
+            }
+            let used = per_group - desc.itable_unused();
+            if bit as u32 >= used {
+                desc.set_itable_unused(per_group - bit as u32 - 1);
+            }
+            self.write_group(group)?;
+            let free = self.sb.free_inodes_count() - 1;
+            self.sb.set_free_inodes_count(free);
+            self.write_super()?;
+            return Ok(base + bit as u32);
+        }
+        Err(FsError::NoDeviceSpace)
+    }
+
+    fn free_inode(&mut self, ino: u32, is_dir: bool) -> Result<()> {
+        let per_group = self.sb.inodes_per_group();
+        let group = ((ino - 1) / per_group) as usize;
+        let bit = ((ino - 1) % per_group) as usize;
+        let mut bitmap = self.read_inode_bitmap(group)?;
+        if !test_bit(&bitmap, bit) {
+            warn!("ext4: inode {} is already free", ino);
+            return Ok(());
+        }
+        clear_bit(&mut bitmap, bit);
+        self.write_inode_bitmap(group, &bitmap)?;
+        let desc = &mut self.groups[group];
+        desc.set_free_inodes_count(desc.free_inodes_count() + 1);
+        if is_dir {
+            desc.set_used_dirs_count(desc.used_dirs_count().saturating_sub(1));
+        }
+        self.write_group(group)?;
+        let free = self.sb.free_inodes_count() + 1;
+        self.sb.set_free_inodes_count(free);
+        self.write_super()
+    }
+
+    fn inode_offset(&self, ino: u32) -> Result<usize> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            return Err(FsError::DeviceError);
+        }
+        let per_group = self.sb.inodes_per_group();
+        let table = self.groups[((ino - 1) / per_group) as usize].inode_table();
+        let index = ((ino - 1) % per_group) as usize;
+        Ok(table as usize * self.block_size + index * self.sb.inode_size())
+    }
+
+    fn read_inode(&self, ino: u32) -> Result<RawInode> {
+        let mut raw = vec![0; self.sb.inode_size()];
+        read_exact(&*self.device, self.inode_offset(ino)?, &mut raw)?;
+        Ok(RawInode { raw })
+    }
+
+    fn write_inode(&self, ino: u32, inode: &mut RawInode) -> Result<()> {
+        if self.metadata_csum() {
+            let mut raw = inode.raw.clone();
+            set_u16(&mut raw, 0x7C, 0);
+            if inode.has_checksum_hi() {
+                set_u16(&mut raw, 0x82, 0);
+            }
+            let csum = crc32c(self.inode_csum_seed(ino, inode), &raw);
+            inode.set_checksum(csum);
+        }
+        write_all(&*self.device, self.inode_offset(ino)?, &inode.raw)
+    }
+
+    /// The seed of the checksums of an inode and its metadata blocks.
+    fn inode_csum_seed(&self, ino: u32, inode: &RawInode) -> u32 {
+        let csum = crc32c(self.csum_seed, &ino.to_le_bytes());
+        crc32c(csum, &inode.generation().to_le_bytes())
+    }
+
+    /// Create an inode in memory, it is written by the caller.
+    fn new_inode(&mut self, type_: FileType, mode: u32, rdev: usize) -> RawInode {
+        let mut inode = RawInode {
+            raw: vec![0; self.sb.inode_size()],
+        };
+        inode.set_mode(file_type_to_mode(type_) | (mode as u16 & 0o7777));
+        let now = now();
+        inode.set_atime(now);
+        inode.set_ctime(now);
+        inode.set_mtime(now);
+        inode.set_links_count(if type_ == FileType::Dir { 2 } else { 1 });
+        inode.set_extra_isize(self.sb.inode_size().saturating_sub(128).min(32) as u16);
+        inode.set_generation(self.next_generation);
+        self.next_generation = self.next_generation.wrapping_add(1);
+        match type_ {
+            FileType::File | FileType::Dir => init_extent_root(&mut inode),
+            FileType::CharDevice | FileType::BlockDevice => set_rdev(&mut inode, rdev),
+            _ => {}
+        }
+        inode
+    }
+
+    /// Free the blocks and the inode `ino`, which has no links.
+    fn delete_inode(&mut self, ino: u32, inode: &mut RawInode) -> Result<()> {
+        if has_data_blocks(inode) {
+            self.truncate(ino, inode, 0)?;
+        }
+        inode.set_dtime(now());
+        self.write_inode(ino, inode)?;
+        self.free_inode(ino, inode.is_dir())
+    }
+}
+
+fn file_type_to_mode(type_: FileType) -> u16 {
+    match type_ {
+        FileType::File => S_IFREG,
+        FileType::Dir => S_IFDIR,
+        FileType::SymLink => S_IFLNK,
+        FileType::CharDevice => S_IFCHR,
+        FileType::BlockDevice => S_IFBLK,
+        FileType::NamedPipe => S_IFIFO,
+        FileType::Socket => S_IFSOCK,
+    }
+}
+
+fn mode_to_file_type(mode: u16) -> FileType {
+    match mode & S_IFMT {
+        S_IFDIR => FileType::Dir,
+        S_IFLNK => FileType::SymLink,
+        S_IFCHR => FileType::CharDevice,
+        S_IFBLK => FileType::BlockDevice,
+        S_IFIFO => FileType::NamedPipe,
+        S_IFSOCK => FileType::Socket,
+        _ => FileType::File,
+    }
+}
+
+/// A symbolic link whose target is stored in `i_block`.
+fn is_fast_symlink(inode: &RawInode) -> bool {
+    inode.file_type() == S_IFLNK
+        && inode.flags() & INODE_EXTENTS_FL == 0
+        && inode.size() < I_BLOCK_SIZE as u64
+}
+
+/// Whether `i_block` maps data blocks, rather than holding a device number
+/// or the target of a symbolic link.
+fn has_data_blocks(inode: &RawInode) -> bool {
+    match inode.file_type() {
+        S_IFREG | S_IFDIR => true,
+        S_IFLNK => !is_fast_symlink(inode),
+        _ => false,
+    }
+}
+
+/// Device numbers use the old 16-bit encoding in `i_block[0]` if possible,
+/// or the new 32-bit one in `i_block[1]`, which matches the layout of `rdev`.
+fn rdev(inode: &RawInode) -> usize {
+    match get_u32(inode.block(), 0) {
+        0 => get_u32(inode.block(), 4) as usize,
+        old => old as usize,
+    }
+}
+
+fn set_rdev(inode: &mut RawInode, rdev: usize) {
+    let block = inode.block_mut();
+    if rdev & !0xffff == 0 {
+        set_u32(block, 0, rdev as u32);
+    } else {
+        set_u32(block, 0, 0);
+        set_u32(block, 4, rdev as u32);
+    }
+}
+
+/// An INode of ext4
+pub struct Ext4INode {
+    ino: u32,
+    fs: Arc<Ext4FileSystem>,
+}
+
+impl Drop for Ext4INode {
+    fn drop(&mut self) {
+        let mut ext4 = self.fs.ext4.lock();
+        if let Ok(mut inode) = ext4.read_inode(self.ino) {
+            if inode.links_count() == 0 && !ext4.read_only {
+                if let Err(e) = ext4.delete_inode(self.ino, &mut inode) {
+                    warn!("ext4: failed to delete inode {}: {:?}", self.ino, e);
+                }
+            }
+        }
+        let mut inodes = self.fs.inodes.lock();
+        if inodes
+            .get(&self.ino)
+            .map_or(false, |inode| inode.strong_count() == 0)
+        {
+            inodes.remove(&self.ino);
+        }
+    }
+}
+
+impl Ext4INode {
+    /// Check a directory before adding `name` in it.
+    fn check_new_entry(&self, ext4: &Ext4, dir: &RawInode, name: &str) -> Result<()> {
+        if !dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        if dir.links_count() == 0 {
+            return Err(FsError::DirRemoved);
+        }
+        if name.is_empty() || name.len() > NAME_MAX || name.contains('/') {
+            return Err(FsError::InvalidParam);
+        }
+        if ext4.dir_lookup(dir, name)?.is_some() {
+            return Err(FsError::EntryExist);
+        }
+        Ok(())
+    }
+}
+
+impl INode for Ext4INode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let ext4 = self.fs.ext4.lock();
+        let inode = ext4.read_inode(self.ino)?;
+        if inode.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        if is_fast_symlink(&inode) {
+            let target = &inode.block()[..inode.size() as usize];
+            if offset >= target.len() {
+                return Ok(0);
+            }
+            let len = buf.len().min(target.len() - offset);
+            buf[..len].copy_from_slice(&target[offset..offset + len]);
+            return Ok(len);
+        }
+        ext4.read_data(&inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut inode = ext4.read_inode(self.ino)?;
+        if inode.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        if is_fast_symlink(&inode) {
+            let end = offset + buf.len();
+            if end < I_BLOCK_SIZE && offset <= inode.size() as usize {
+                inode.block_mut()[offset..end].copy_from_slice(buf);
+                inode.set_size(inode.size().max(end as u64));
+            } else if inode.size() == 0 {
+                init_extent_root(&mut inode);
+                ext4.write_data(self.ino, &mut inode, offset, buf)?;
+            } else {
+                return Err(FsError::NotSupported);
+            }
+        } else {
+            ext4.write_data(self.ino, &mut inode, offset, buf)?;
+        }
+        let now = now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        ext4.write_inode(self.ino, &mut inode)?;
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let ext4 = self.fs.ext4.lock();
+        let inode = ext4.read_inode(self.ino)?;
+        let type_ = mode_to_file_type(inode.mode());
+        let rdev = match type_ {
+            FileType::CharDevice | FileType::BlockDevice => rdev(&inode),
+            _ => 0,
+        };
+        Ok(Metadata {
+            dev: 0,
+            inode: self.ino as usize,
+            size: inode.size() as usize,
+            blk_size: ext4.block_size,
+            blocks: inode.sectors() as usize,
+            atime: Timespec {
+                sec: inode.atime() as _,
+                nsec: 0,
+            },
+            mtime: Timespec {
+                sec: inode.mtime() as _,
+                nsec: 0,
+            },
+            ctime: Timespec {
+                sec: inode.ctime() as _,
+                nsec: 0,
+            },
+            type_,
+            mode: inode.mode() & 0o7777,
+            nlinks: inode.links_count() as usize,
+            uid: inode.uid() as usize,
+            gid: inode.gid() as usize,
+            rdev,
+        })
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        let ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut inode = ext4.read_inode(self.ino)?;
+        inode.set_mode(inode.file_type() | (metadata.mode & 0o7777));
+        inode.set_uid(metadata.uid as u32);
+        inode.set_gid(metadata.gid as u32);
+        inode.set_atime(metadata.atime.sec as u32);
+        inode.set_mtime(metadata.mtime.sec as u32);
+        inode.set_ctime(metadata.ctime.sec as u32);
+        ext4.write_inode(self.ino, &mut inode)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        let ext4 = self.fs.ext4.lock();
+        ext4.device.sync().map_err(|_| FsError::DeviceError)
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.sync_all()
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut inode = ext4.read_inode(self.ino)?;
+        if inode.file_type() != S_IFREG {
+            return Err(FsError::NotFile);
+        }
+        ext4.truncate(self.ino, &mut inode, len as u64)?;
+        let now = now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        ext4.write_inode(self.ino, &mut inode)
+    }
+
+    fn create2(
+        &self,
+        name: &str,
+        type_: FileType,
+        mode: u32,
+        data: usize,
+    ) -> Result<Arc<dyn INode>> {
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut dir = ext4.read_inode(self.ino)?;
+        self.check_new_entry(&ext4, &dir, name)?;
+        let is_dir = type_ == FileType::Dir;
+        let ino = ext4.alloc_inode(self.ino, is_dir)?;
+        let mut inode = ext4.new_inode(type_, mode, data);
+        let result = (|| {
+            if is_dir {
+                ext4.dir_init(ino, &mut inode, self.ino)?;
+            }
+            ext4.write_inode(ino, &mut inode)?;
+            ext4.dir_add(self.ino, &mut dir, name, ino, inode.mode())
+        })();
+        if let Err(e) = result {
+            inode.set_links_count(0);
+            ext4.delete_inode(ino, &mut inode)?;
+            return Err(e);
+        }
+        if is_dir {
+            dir.set_links_count(dir.links_count() + 1);
+        }
+        let now = now();
+        dir.set_mtime(now);
+        dir.set_ctime(now);
+        ext4.write_inode(self.ino, &mut dir)?;
+        drop(ext4);
+        Ok(self.fs.get_inode(ino))
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn INode>) -> Result<()> {
+        let other = other
+            .downcast_ref::<Ext4INode>()
+            .ok_or(FsError::NotSameFs)?;
+        if !Arc::ptr_eq(&other.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut dir = ext4.read_inode(self.ino)?;
+        self.check_new_entry(&ext4, &dir, name)?;
+        let mut inode = ext4.read_inode(other.ino)?;
+        if inode.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        ext4.dir_add(self.ino, &mut dir, name, other.ino, inode.mode())?;
+        let now = now();
+        inode.set_links_count(inode.links_count() + 1);
+        inode.set_ctime(now);
+        ext4.write_inode(other.ino, &mut inode)?;
+        dir.set_mtime(now);
+        dir.set_ctime(now);
+        ext4.write_inode(self.ino, &mut dir)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(FsError::DirNotEmpty);
+        }
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut dir = ext4.read_inode(self.ino)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        let ino = ext4.dir_lookup(&dir, name)?.ok_or(FsError::EntryNotFound)?;
+        self.fs
+            .unlink_entry(&mut ext4, self.ino, &mut dir, name, ino)
+    }
+
+    fn move_(&self, old_name: &str, target: &Arc<dyn INode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<Ext4INode>()
+            .ok_or(FsError::NotSameFs)?;
+        if !Arc::ptr_eq(&target.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        if old_name == "." || old_name == ".." {
+            return Err(FsError::IsDir);
+        }
+        let mut ext4 = self.fs.ext4.lock();
+        ext4.writable()?;
+        let mut dir = ext4.read_inode(self.ino)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        let ino = ext4
+            .dir_lookup(&dir, old_name)?
+            .ok_or(FsError::EntryNotFound)?;
+        let mut inode = ext4.read_inode(ino)?;
+        let is_dir = inode.is_dir();
+        if is_dir {
+            // a directory can not be moved into itself
+            let mut ancestor = target.ino;
+            loop {
+                if ancestor == ino {
+                    return Err(FsError::InvalidParam);
+                }
+                if ancestor == ROOT_INO {
+                    break;
+                }
+                let parent = ext4.read_inode(ancestor)?;
+                ancestor = ext4
+                    .dir_lookup(&parent, "..")?
+                    .ok_or(FsError::DeviceError)?;
+            }
+        }
+        let same_dir = target.ino == self.ino;
+        let mut target_dir = if same_dir {
+            None
+        } else {
+            Some(ext4.read_inode(target.ino)?)
+        };
+        let new_dir = target_dir.as_mut().unwrap_or(&mut dir);
+        if !new_dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        if new_name.is_empty() || new_name.len() > NAME_MAX || new_name.contains('/') {
+            return Err(FsError::InvalidParam);
+        }
+        if let Some(old) = ext4.dir_lookup(new_dir, new_name)? {
+            if old == ino {
+                return Ok(());
+            }
+            let old_is_dir = ext4.read_inode(old)?.is_dir();
+            if old_is_dir && !is_dir {
+                return Err(FsError::IsDir);
+            }
+            if !old_is_dir && is_dir {
+                return Err(FsError::NotDir);
+            }
+            self.fs
+                .unlink_entry(&mut ext4, target.ino, new_dir, new_name, old)?;
+        }
+        ext4.dir_add(target.ino, new_dir, new_name, ino, inode.mode())?;
+        let now = now();
+        new_dir.set_mtime(now);
+        new_dir.set_ctime(now);
+        if is_dir && !same_dir {
+            new_dir.set_links_count(new_dir.links_count() + 1);
+            ext4.dir_set_parent(ino, &inode, target.ino)?;
+        }
+        if let Some(mut new_dir) = target_dir {
+            ext4.write_inode(target.ino, &mut new_dir)?;
+            if is_dir {
+                dir.set_links_count(dir.links_count() - 1);
+            }
+        }
+        ext4.dir_remove(self.ino, &dir, old_name)?;
+        dir.set_mtime(now);
+        dir.set_ctime(now);
+        ext4.write_inode(self.ino, &mut dir)?;
+        inode.set_ctime(now);
+        ext4.write_inode(ino, &mut inode)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let ext4 = self.fs.ext4.lock();
+        let dir = ext4.read_inode(self.ino)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        let ino = ext4.dir_lookup(&dir, name)?.ok_or(FsError::EntryNotFound)?;
+        drop(ext4);
+        Ok(self.fs.get_inode(ino))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        let ext4 = self.fs.ext4.lock();
+        let dir = ext4.read_inode(self.ino)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        ext4.dir_entry_at(&dir, id)?.ok_or(FsError::EntryNotFound)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Set up an empty extent tree in `i_block`.
+fn init_extent_root(inode: &mut RawInode) {
+    inode.set_flags(inode.flags() | INODE_EXTENTS_FL);
+    extent::init_root(inode.block_mut());
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::fs::rcore_fs_wrapper::MemBuf;
+
+    const BLOCK_SIZE: usize = 1024;
+    const BLOCKS: usize = 256;
+    /// Blocks 1 to 21 are in use, from the superblock to the data.
+    const USED_BLOCKS: usize = 21;
+    const INODES: u32 = 32;
+    const INODE_SIZE: usize = 256;
+    const INODE_TABLE: usize = 5;
+    const DIR_INO: u32 = 11;
+    const BIG_INO: u32 = 12;
+    const SMALL_INO: u32 = 13;
+    /// Size of `/big`, whose last block is partial.
+    const BIG_SIZE: usize = 5 * BLOCK_SIZE + 100;
+
+    fn block_mut(image: &mut [u8], block: usize) -> &mut [u8] {
+        &mut image[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+    }
+
+    /// Write an extent tree node of (first logical block, length, physical
+    /// block) entries, the length is unused by the index nodes.
+    fn put_extents(node: &mut [u8], depth: u16, entries: &[(u32, u16, u32)]) {
+        set_u16(node, 0, 0xF30A);
+        set_u16(node, 2, entries.len() as u16);
+        set_u16(node, 4, (node.len() / 12 - 1) as u16);
+        set_u16(node, 6, depth);
+        for (i, &(lblock, len, pblock)) in entries.iter().enumerate() {
+            let entry = &mut node[12 * (i + 1)..];
+            set_u32(entry, 0, lblock);
+            if depth == 0 {
+                set_u16(entry, 4, len);
+                set_u32(entry, 8, pblock);
+            } else {
+                set_u32(entry, 4, pblock);
+            }
+        }
+    }
+
+    /// Write a directory block of (inode, name, file type) entries.
+    fn put_dir(buf: &mut [u8], entries: &[(u32, &str, u8)]) {
+        let mut offset = 0;
+        for (i, &(ino, name, file_type)) in entries.iter().enumerate() {
+            let rec_len = if i + 1 == entries.len() {
+                buf.len() - offset
+            } else {
+                (8 + name.len() + 3) & !3
+            };
+            set_u32(buf, offset, ino);
+            set_u16(buf, offset + 4, rec_len as u16);
+            buf[offset + 6] = name.len() as u8;
+            buf[offset + 7] = file_type;
+            buf[offset + 8..offset + 8 + name.len()].copy_from_slice(name.as_bytes());
+            offset += rec_len;
+        }
+    }
+
+    fn put_inode(
+        image: &mut [u8],
+        ino: u32,
+        mode: u16,
+        size: usize,
+        depth: u16,
+        extents: &[(u32, u16, u32)],
+    ) {
+        let mut inode = RawInode {
+            raw: vec![0; INODE_SIZE],
+        };
+        inode.set_mode(mode);
+        // only the root has a subdirectory
+        let links = match mode & S_IFMT {
+            S_IFDIR if ino == ROOT_INO => 3,
+            S_IFDIR => 2,
+            _ => 1,
+        };
+        inode.set_links_count(links);
+        inode.set_size(size as u64);
+        inode.set_flags(INODE_EXTENTS_FL);
+        inode.set_extra_isize(32);
+        put_extents(inode.block_mut(), depth, extents);
+        let offset = INODE_TABLE * BLOCK_SIZE + (ino as usize - 1) * INODE_SIZE;
+        image[offset..offset + INODE_SIZE].copy_from_slice(&inode.raw);
+    }
+
+    /// An image of one group of 1 KiB blocks, without checksums:
+    ///
+    /// - `/dir/small`, of one extent in the inode
+    /// - `/big`, of two extents in a leaf block, with a hole of 2 blocks
+    ///   between them, and filled with `'a' + logical block`
+    pub(in crate::fs) fn image() -> Arc<MemBuf> {
+        let mut image = vec![0u8; BLOCKS * BLOCK_SIZE];
+        let free_blocks = BLOCKS - 1 - USED_BLOCKS;
+        let free_inodes = INODES - SMALL_INO;
+
+        let sb = &mut image[SUPERBLOCK_OFFSET..SUPERBLOCK_OFFSET + SUPERBLOCK_SIZE];
+        set_u32(sb, 0x0, INODES);
+        set_u32(sb, 0x4, BLOCKS as u32);
+        set_u32(sb, 0xC, free_blocks as u32);
+        set_u32(sb, 0x10, free_inodes);
+        set_u32(sb, 0x14, 1);
+        set_u32(sb, 0x20, 8192);
+        set_u32(sb, 0x28, INODES);
+        set_u16(sb, 0x38, EXT4_MAGIC);
+        set_u32(sb, 0x4C, 1);
+        set_u32(sb, 0x54, DIR_INO);
+        set_u16(sb, 0x58, INODE_SIZE as u16);
+        set_u32(sb, 0x60, INCOMPAT_FILETYPE | INCOMPAT_EXTENTS);
+
+        let desc = block_mut(&mut image, 2);
+        set_u32(desc, 0x0, 3);
+        set_u32(desc, 0x4, 4);
+        set_u32(desc, 0x8, INODE_TABLE as u32);
+        set_u16(desc, 0xC, free_blocks as u16);
+        set_u16(desc, 0xE, free_inodes as u16);
+        set_u16(desc, 0x10, 2);
+        // bits beyond the end of the group are set
+        let bitmap = block_mut(&mut image, 3);
+        for bit in (0..USED_BLOCKS).chain(BLOCKS - 1..BLOCK_SIZE * 8) {
+            set_bit(bitmap, bit);
+        }
+        let bitmap = block_mut(&mut image, 4);
+        for bit in (0..SMALL_INO as usize).chain(INODES as usize..BLOCK_SIZE * 8) {
+            set_bit(bitmap, bit);
+        }
+
+        let dir_mode = S_IFDIR | 0o755;
+        let file_mode = S_IFREG | 0o644;
+        put_inode(&mut image, ROOT_INO, dir_mode, BLOCK_SIZE, 0, &[(0, 1, 13)]);
+        put_inode(&mut image, DIR_INO, dir_mode, BLOCK_SIZE, 0, &[(0, 1, 14)]);
+        put_inode(&mut image, BIG_INO, file_mode, BIG_SIZE, 1, &[(0, 0, 15)]);
+        put_extents(block_mut(&mut image, 15), 0, &[(0, 2, 16), (4, 2, 20)]);
+        put_inode(&mut image, SMALL_INO, file_mode, 6, 0, &[(0, 1, 18)]);
+
+        put_dir(
+            block_mut(&mut image, 13),
+            &[
+                (2, ".", 2),
+                (2, "..", 2),
+                (DIR_INO, "dir", 2),
+                (BIG_INO, "big", 1),
+            ],
+        );
+        put_dir(
+            block_mut(&mut image, 14),
+            &[(DIR_INO, ".", 2), (2, "..", 2), (SMALL_INO, "small", 1)],
+        );
+        for (lblock, block) in [16, 17, 0, 0, 20, 21].iter().enumerate() {
+            if *block != 0 {
+                block_mut(&mut image, *block).fill(b'a' + lblock as u8);
+            }
+        }
+        block_mut(&mut image, 18)[..6].copy_from_slice(b"hello\n");
+        Arc::new(MemBuf::new(Box::leak(image.into_boxed_slice())))
+    }
+
+    #[test]
+    fn read_tree() {
+        let fs = Ext4FileSystem::open(image()).unwrap();
+        let root = fs.root_inode();
+        let names: Vec<_> = (0..).map_while(|i| root.get_entry(i).ok()).collect();
+        assert_eq!(names, [".", "..", "dir", "big"]);
+
+        let dir = root.find("dir").unwrap();
+        let meta = dir.metadata().unwrap();
+        assert_eq!(meta.type_, FileType::Dir);
+        assert_eq!((meta.inode, meta.nlinks), (DIR_INO as usize, 2));
+        let parent = dir.find("..").unwrap().metadata().unwrap();
+        assert_eq!(parent.inode, ROOT_INO as usize);
+
+        let small = dir.find("small").unwrap();
+        let meta = small.metadata().unwrap();
+        assert_eq!(
+            (meta.type_, meta.size, meta.mode),
+            (FileType::File, 6, 0o644)
+        );
+        let mut buf = [0u8; 16];
+        assert_eq!(small.read_at(0, &mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"hello\n");
+
+        assert!(matches!(dir.find("big"), Err(FsError::EntryNotFound)));
+        assert!(matches!(small.find("x"), Err(FsError::NotDir)));
+        assert!(matches!(dir.read_at(0, &mut buf), Err(FsError::IsDir)));
+    }
+
+    #[test]
+    fn read_extents() {
+        let fs = Ext4FileSystem::open(image()).unwrap();
+        let big = fs.root_inode().find("big").unwrap();
+        assert_eq!(big.metadata().unwrap().size, BIG_SIZE);
+        let mut buf = vec![0u8; 8 * BLOCK_SIZE];
+        assert_eq!(big.read_at(0, &mut buf).unwrap(), BIG_SIZE);
+        for (lblock, data) in buf[..BIG_SIZE].chunks(BLOCK_SIZE).enumerate() {
+            let expected = match lblock {
+                2 | 3 => 0,
+                _ => b'a' + lblock as u8,
+            };
+            assert!(data.iter().all(|&b| b == expected));
+        }
+
+        // from an extent into the hole, and up to the end of the file
+        assert_eq!(big.read_at(2 * BLOCK_SIZE - 1, &mut buf[..2]).unwrap(), 2);
+        assert_eq!(&buf[..2], b"b\0");
+        assert_eq!(big.read_at(BIG_SIZE - 1, &mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'f');
+        assert_eq!(big.read_at(BIG_SIZE, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn write_and_remount() {
+        let device = image();
+        let fs = Ext4FileSystem::open(device.clone()).unwrap();
+        let free = fs.info().bfree;
+        let root = fs.root_inode();
+        // fill a block of the hole, and write a new file after a hole
+        let big = root.find("big").unwrap();
+        big.write_at(2 * BLOCK_SIZE, &[b'c'; BLOCK_SIZE]).unwrap();
+        let dir = root.find("dir").unwrap();
+        let file = dir.create2("new", FileType::File, 0o600, 0).unwrap();
+        file.write_at(BLOCK_SIZE, b"world").unwrap();
+        // the 3 extents of `/big` fit in the inode then, and its leaf block
+        // is freed
+        assert_eq!(fs.info().bfree, free - 1);
+        drop(file);
+
+        let fs = Ext4FileSystem::open(device).unwrap();
+        let root = fs.root_inode();
+        let mut buf = [0xffu8; 8];
+        let big = root.find("big").unwrap();
+        big.read_at(3 * BLOCK_SIZE - 4, &mut buf).unwrap();
+        assert_eq!(&buf, b"cccc\0\0\0\0");
+        let dir = root.find("dir").unwrap();
+        let file = dir.find("new").unwrap();
+        let meta = file.metadata().unwrap();
+        assert_eq!((meta.size, meta.mode), (BLOCK_SIZE + 5, 0o600));
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 8);
+        assert_eq!(buf, [0; 8]);
+        assert_eq!(file.read_at(BLOCK_SIZE, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"world");
+
+        // the blocks of a removed file are freed once it is closed
+        dir.unlink("new").unwrap();
+        assert_eq!(fs.info().bfree, free - 1);
+        drop(file);
+        assert_eq!(fs.info().bfree, free);
+    }
+}
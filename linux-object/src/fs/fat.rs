@@ -0,0 +1,951 @@
+//! Implement a read-only FAT file system
+//!
+//! FAT12, FAT16 and FAT32 volumes are recognized, as well as the long file
+//! names of VFAT. It is meant for the boot partition of real boards, from
+//! which configurations and test programs are read, so nothing is ever
+//! written back: every modification fails with `FsError::NotSupported`.
+#![deny(missing_docs)]
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use core::convert::TryInto;
+use lock::Mutex;
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::*;
+
+/// Size of a directory entry.
+const DIR_ENTRY_SIZE: usize = 32;
+/// Maximum length of a long file name, in UCS-2 characters.
+const NAME_MAX: usize = 255;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// Flags of `DIR_NTRes`: the base name or the extension is in lower case.
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+/// The first byte of a deleted entry.
+const ENTRY_DELETED: u8 = 0xE5;
+/// The first byte of a short name starting with `0xE5`.
+const ENTRY_KANJI_E5: u8 = 0x05;
+/// The flag of the last (first stored) entry of a long name.
+const LFN_LAST: u8 = 0x40;
+
+/// Inode numbers of regular files are the position of their entry on the
+/// device with this bit set, those of directories are their first cluster.
+const FILE_ID_BIT: usize = 1 << (usize::BITS - 1);
+/// Inode number of the fixed root directory of FAT12 and FAT16.
+const FIXED_ROOT_ID: usize = 1;
+
+/// The three flavours of FAT, decided by the number of clusters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// The FAT file system
+pub struct FatFileSystem {
+    device: Arc<dyn Device>,
+    fat_type: FatType,
+    /// size of a cluster in bytes
+    cluster_size: usize,
+    /// offset of the first FAT in bytes
+    fat_offset: usize,
+    /// offset and size of the fixed root directory of FAT12 and FAT16
+    root_dir_offset: usize,
+    root_dir_size: usize,
+    /// offset of cluster 2 in bytes
+    data_offset: usize,
+    /// first cluster of the root directory of FAT32
+    root_cluster: u32,
+    /// number of data clusters
+    clusters: u32,
+    /// number of free clusters, from FSInfo or counted on demand
+    free_clusters: Mutex<Option<u32>>,
+    self_ref: Weak<FatFileSystem>,
+}
+
+impl FatFileSystem {
+    /// Open the FAT file system on `device`.
+    ///
+    /// Returns `FsError::WrongFs` if there is no FAT boot sector on it.
+    pub fn open(device: Arc<dyn Device>) -> Result<Arc<Self>> {
+        let mut bs = [0u8; 512];
+        read_exact(&*device, 0, &mut bs)?;
+        if bs[510..512] != [0x55, 0xAA] || (bs[0] != 0xEB && bs[0] != 0xE9) {
+            return Err(FsError::WrongFs);
+        }
+        let bytes_per_sector = get_u16(&bs, 11) as usize;
+        let sectors_per_cluster = bs[13] as usize;
+        let reserved_sectors = get_u16(&bs, 14) as usize;
+        let fats = bs[16] as usize;
+        let root_entries = get_u16(&bs, 17) as usize;
+        let total_sectors = match get_u16(&bs, 19) {
+            0 => get_u32(&bs, 32) as usize,
+            n => n as usize,
+        };
+        let fat_sectors = match get_u16(&bs, 22) {
+            0 => get_u32(&bs, 36) as usize,
+            n => n as usize,
+        };
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fats == 0
+            || fat_sectors == 0
+        {
+            return Err(FsError::WrongFs);
+        }
+        let root_dir_sectors =
+            (root_entries * DIR_ENTRY_SIZE + bytes_per_sector - 1) / bytes_per_sector;
+        let data_sector = reserved_sectors + fats * fat_sectors + root_dir_sectors;
+        if total_sectors <= data_sector {
+            return Err(FsError::WrongFs);
+        }
+        let clusters = ((total_sectors - data_sector) / sectors_per_cluster) as u32;
+        let fat_type = if clusters < 4085 {
+            FatType::Fat12
+        } else if clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+        // the FAT must be large enough to hold an entry for every cluster
+        let fat_bits = match fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        if fat_sectors * bytes_per_sector * 8 < (clusters as usize + 2) * fat_bits {
+            return Err(FsError::WrongFs);
+        }
+        let (root_cluster, free_clusters) = if fat_type == FatType::Fat32 {
+            if root_entries != 0 {
+                return Err(FsError::WrongFs);
+            }
+            let root_cluster = get_u32(&bs, 44);
+            if root_cluster < 2 || root_cluster >= clusters + 2 {
+                return Err(FsError::WrongFs);
+            }
+            let fsinfo = get_u16(&bs, 48) as usize;
+            (
+                root_cluster,
+                read_fsinfo(&*device, fsinfo * bytes_per_sector),
+            )
+        } else {
+            if root_entries == 0 {
+                return Err(FsError::WrongFs);
+            }
+            (0, None)
+        };
+        let fat_offset = reserved_sectors * bytes_per_sector;
+        let root_dir_offset = fat_offset + fats * fat_sectors * bytes_per_sector;
+        Ok(Arc::new_cyclic(|fs| FatFileSystem {
+            device,
+            fat_type,
+            cluster_size: sectors_per_cluster * bytes_per_sector,
+            fat_offset,
+            root_dir_offset,
+            root_dir_size: root_entries * DIR_ENTRY_SIZE,
+            data_offset: data_sector * bytes_per_sector,
+            root_cluster,
+            clusters,
+            free_clusters: Mutex::new(free_clusters.filter(|&free| free <= clusters)),
+            self_ref: fs.clone(),
+        }))
+    }
+
+    fn root_entry(&self) -> DirEntry {
+        DirEntry {
+            name: String::new(),
+            short_name: String::new(),
+            attr: ATTR_DIRECTORY,
+            cluster: self.root_cluster,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            id: match self.fat_type {
+                FatType::Fat32 => self.root_cluster as usize,
+                _ => FIXED_ROOT_ID,
+            },
+        }
+    }
+
+    fn new_inode(&self, entry: DirEntry) -> Arc<FatINode> {
+        Arc::new(FatINode {
+            entry,
+            chain: Mutex::new(None),
+            fs: self.self_ref.upgrade().unwrap(),
+        })
+    }
+
+    /// Read the FAT entry of `cluster`.
+    fn fat_entry(&self, cluster: u32) -> Result<u32> {
+        let cluster = cluster as usize;
+        Ok(match self.fat_type {
+            FatType::Fat12 => {
+                let mut buf = [0u8; 2];
+                read_exact(&*self.device, self.fat_offset + cluster * 3 / 2, &mut buf)?;
+                let entry = u16::from_le_bytes(buf);
+                if cluster % 2 == 0 {
+                    (entry & 0xFFF) as u32
+                } else {
+                    (entry >> 4) as u32
+                }
+            }
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                read_exact(&*self.device, self.fat_offset + cluster * 2, &mut buf)?;
+                u16::from_le_bytes(buf) as u32
+            }
+            FatType::Fat32 => {
+                let mut buf = [0u8; 4];
+                read_exact(&*self.device, self.fat_offset + cluster * 4, &mut buf)?;
+                u32::from_le_bytes(buf) & 0x0FFF_FFFF
+            }
+        })
+    }
+
+    fn is_end_of_chain(&self, entry: u32) -> bool {
+        match self.fat_type {
+            FatType::Fat12 => entry >= 0xFF8,
+            FatType::Fat16 => entry >= 0xFFF8,
+            FatType::Fat32 => entry >= 0x0FFF_FFF8,
+        }
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.clusters + 2
+    }
+
+    /// Collect the cluster chain starting at `first`.
+    fn cluster_chain(&self, first: u32) -> Result<Vec<u32>> {
+        let mut chain = Vec::new();
+        if first == 0 {
+            return Ok(chain);
+        }
+        let mut cluster = first;
+        loop {
+            // a chain longer than the volume must contain a loop
+            if !self.is_valid_cluster(cluster) || chain.len() >= self.clusters as usize {
+                warn!("fat: broken cluster chain from {}", first);
+                return Err(FsError::DeviceError);
+            }
+            chain.push(cluster);
+            let next = self.fat_entry(cluster)?;
+            if self.is_end_of_chain(next) {
+                return Ok(chain);
+            }
+            cluster = next;
+        }
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        self.data_offset + (cluster as usize - 2) * self.cluster_size
+    }
+
+    /// Read the data at `offset` of the file made of `chain`.
+    fn read_chain(&self, chain: &[u32], offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let index = pos / self.cluster_size;
+            if index >= chain.len() {
+                break;
+            }
+            // merge physically contiguous clusters into one read
+            let mut last = index;
+            let wanted = pos + buf.len() - done;
+            while last + 1 < chain.len()
+                && chain[last + 1] == chain[last] + 1
+                && (last + 1) * self.cluster_size < wanted
+            {
+                last += 1;
+            }
+            let in_cluster = pos % self.cluster_size;
+            let len = ((last + 1 - index) * self.cluster_size - in_cluster).min(buf.len() - done);
+            let dev_offset = self.cluster_offset(chain[index]) + in_cluster;
+            read_exact(&*self.device, dev_offset, &mut buf[done..done + len])?;
+            done += len;
+        }
+        Ok(done)
+    }
+
+    /// Count the free clusters by scanning the FAT.
+    fn count_free_clusters(&self) -> Result<u32> {
+        let mut free = 0;
+        for cluster in 2..self.clusters + 2 {
+            if self.fat_entry(cluster)? == 0 {
+                free += 1;
+            }
+        }
+        Ok(free)
+    }
+}
+
+impl FileSystem for FatFileSystem {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.new_inode(self.root_entry())
+    }
+
+    fn info(&self) -> FsInfo {
+        let mut free_clusters = self.free_clusters.lock();
+        if free_clusters.is_none() {
+            *free_clusters = self.count_free_clusters().ok();
+        }
+        let free = free_clusters.unwrap_or(0) as usize;
+        FsInfo {
+            bsize: self.cluster_size,
+            frsize: self.cluster_size,
+            blocks: self.clusters as usize,
+            bfree: free,
+            bavail: free,
+            files: 0,
+            ffree: 0,
+            namemax: NAME_MAX,
+        }
+    }
+}
+
+/// A parsed directory entry
+#[derive(Debug, Clone)]
+struct DirEntry {
+    /// the long name, or the short name if there is none
+    name: String,
+    /// the 8.3 name
+    short_name: String,
+    attr: u8,
+    /// the first cluster, 0 for empty files and the fixed root directory
+    cluster: u32,
+    size: u32,
+    atime: i64,
+    mtime: i64,
+    ctime: i64,
+    id: usize,
+}
+
+impl DirEntry {
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        eq_ignore_case(&self.name, name) || eq_ignore_case(&self.short_name, name)
+    }
+}
+
+/// An INode of the FAT file system
+pub struct FatINode {
+    entry: DirEntry,
+    /// the cluster chain, loaded on first use
+    chain: Mutex<Option<Arc<Vec<u32>>>>,
+    fs: Arc<FatFileSystem>,
+}
+
+impl FatINode {
+    fn is_fixed_root(&self) -> bool {
+        self.entry.id == FIXED_ROOT_ID
+    }
+
+    fn is_root(&self) -> bool {
+        self.is_fixed_root() || (self.entry.is_dir() && self.entry.cluster == self.fs.root_cluster)
+    }
+
+    fn chain(&self) -> Result<Arc<Vec<u32>>> {
+        let mut chain = self.chain.lock();
+        if let Some(chain) = &*chain {
+            return Ok(chain.clone());
+        }
+        let loaded = Arc::new(self.fs.cluster_chain(self.entry.cluster)?);
+        *chain = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    fn size(&self) -> Result<usize> {
+        if self.is_fixed_root() {
+            Ok(self.fs.root_dir_size)
+        } else if self.entry.is_dir() {
+            Ok(self.chain()?.len() * self.fs.cluster_size)
+        } else {
+            Ok(self.entry.size as usize)
+        }
+    }
+
+    /// Read the raw content of this directory and parse its entries.
+    fn entries(&self) -> Result<Vec<DirEntry>> {
+        if !self.entry.is_dir() {
+            return Err(FsError::NotDir);
+        }
+        let fs = &self.fs;
+        let (raw, offsets) = if self.is_fixed_root() {
+            let mut raw = vec![0; fs.root_dir_size];
+            read_exact(&*fs.device, fs.root_dir_offset, &mut raw)?;
+            (raw, Vec::new())
+        } else {
+            let chain = self.chain()?;
+            let mut raw = vec![0; chain.len() * fs.cluster_size];
+            fs.read_chain(&chain, 0, &mut raw)?;
+            let offsets = chain.iter().map(|&c| fs.cluster_offset(c)).collect();
+            (raw, offsets)
+        };
+        let device_offset = |pos: usize| match offsets.get(pos / fs.cluster_size) {
+            Some(base) => base + pos % fs.cluster_size,
+            None => fs.root_dir_offset + pos,
+        };
+
+        let mut entries = Vec::new();
+        if self.is_root() {
+            let root = fs.root_entry();
+            entries.push(DirEntry {
+                name: String::from("."),
+                ..root.clone()
+            });
+            entries.push(DirEntry {
+                name: String::from(".."),
+                ..root
+            });
+        }
+        let mut lfn = LongName::default();
+        for (i, raw) in raw.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+            match raw[0] {
+                0 => break,
+                ENTRY_DELETED => {
+                    lfn.reset();
+                    continue;
+                }
+                _ => {}
+            }
+            let attr = raw[11];
+            if attr & 0x3F == ATTR_LONG_NAME {
+                lfn.push(raw);
+                continue;
+            }
+            if attr & ATTR_VOLUME_ID != 0 {
+                lfn.reset();
+                continue;
+            }
+            let short_name = short_name(raw);
+            let name = lfn
+                .take(checksum(&raw[..11]))
+                .unwrap_or_else(|| short_name.clone());
+            let cluster = (get_u16(raw, 20) as u32) << 16 | get_u16(raw, 26) as u32;
+            let mut entry = DirEntry {
+                name,
+                short_name,
+                attr,
+                cluster,
+                size: get_u32(raw, 28),
+                atime: unix_time(get_u16(raw, 18), 0),
+                mtime: unix_time(get_u16(raw, 24), get_u16(raw, 22)),
+                ctime: unix_time(get_u16(raw, 16), get_u16(raw, 14)),
+                id: FILE_ID_BIT | (device_offset(i * DIR_ENTRY_SIZE) / DIR_ENTRY_SIZE),
+            };
+            if entry.is_dir() {
+                if entry.cluster == 0 || entry.cluster == fs.root_cluster {
+                    // ".." of a subdirectory of the root
+                    let root = fs.root_entry();
+                    entry.cluster = root.cluster;
+                    entry.id = root.id;
+                } else {
+                    entry.id = entry.cluster as usize;
+                }
+                entry.size = 0;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+impl INode for FatINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.entry.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        let size = self.entry.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        let chain = self.chain()?;
+        self.fs.read_chain(&chain, offset, &mut buf[..len])
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let entry = &self.entry;
+        let size = self.size()?;
+        let mut mode = 0o755;
+        if entry.attr & ATTR_READ_ONLY != 0 {
+            mode &= !0o222;
+        }
+        Ok(Metadata {
+            dev: 0,
+            inode: entry.id,
+            size,
+            blk_size: self.fs.cluster_size,
+            blocks: (size + self.fs.cluster_size - 1) / self.fs.cluster_size
+                * (self.fs.cluster_size / 512),
+            atime: Timespec {
+                sec: entry.atime as _,
+                nsec: 0,
+            },
+            mtime: Timespec {
+                sec: entry.mtime as _,
+                nsec: 0,
+            },
+            ctime: Timespec {
+                sec: entry.ctime as _,
+                nsec: 0,
+            },
+            type_: if entry.is_dir() {
+                FileType::Dir
+            } else {
+                FileType::File
+            },
+            mode,
+            nlinks: if entry.is_dir() { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let entry = self
+            .entries()?
+            .into_iter()
+            .find(|entry| entry.matches(name))
+            .ok_or(FsError::EntryNotFound)?;
+        if entry.is_dir() && entry.id == self.entry.id {
+            return Ok(self.fs.new_inode(self.entry.clone()));
+        }
+        Ok(self.fs.new_inode(entry))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        self.entries()?
+            .into_iter()
+            .nth(id)
+            .map(|entry| entry.name)
+            .ok_or(FsError::EntryNotFound)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Long name entries preceding a short entry
+#[derive(Default)]
+struct LongName {
+    /// UCS-2 characters, 13 per entry
+    chars: Vec<u16>,
+    checksum: u8,
+    /// sequence number of the next entry expected, 0 if there is none
+    next: u8,
+}
+
+impl LongName {
+    fn reset(&mut self) {
+        self.next = 0;
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        let order = raw[0];
+        let seq = order & 0x1F;
+        if order & LFN_LAST != 0 {
+            if seq == 0 || seq as usize * 13 > NAME_MAX + 13 {
+                self.reset();
+                return;
+            }
+            self.chars = vec![0xFFFF; seq as usize * 13];
+            self.checksum = raw[13];
+        } else if seq != self.next || seq == 0 || raw[13] != self.checksum {
+            self.reset();
+            return;
+        }
+        let chars = (1..11)
+            .step_by(2)
+            .chain((14..26).step_by(2))
+            .chain((28..32).step_by(2))
+            .map(|i| get_u16(raw, i));
+        let base = (seq as usize - 1) * 13;
+        for (slot, c) in self.chars[base..base + 13].iter_mut().zip(chars) {
+            *slot = c;
+        }
+        self.next = seq - 1;
+    }
+
+    /// Take the name if it is complete and belongs to the short entry whose
+    /// name has `checksum`.
+    fn take(&mut self, checksum: u8) -> Option<String> {
+        let complete = self.next == 0 && !self.chars.is_empty() && self.checksum == checksum;
+        let chars = core::mem::take(&mut self.chars);
+        self.reset();
+        if !complete {
+            return None;
+        }
+        let len = chars.iter().position(|&c| c == 0 || c == 0xFFFF);
+        let name: String = char::decode_utf16(chars[..len.unwrap_or(chars.len())].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+}
+
+/// Decode the 8.3 name of a short entry.
+fn short_name(raw: &[u8]) -> String {
+    let ntres = raw[12];
+    let decode = |bytes: &[u8], lower: bool| -> String {
+        let mut part: String = bytes
+            .iter()
+            .map(|&b| {
+                if b == ENTRY_KANJI_E5 {
+                    ENTRY_DELETED
+                } else {
+                    b
+                }
+            })
+            .map(|b| b as char)
+            .collect();
+        part.truncate(part.trim_end_matches(' ').len());
+        if lower {
+            part.make_ascii_lowercase();
+        }
+        part
+    };
+    let mut name = decode(&raw[..8], ntres & NTRES_LOWER_BASE != 0);
+    let ext = decode(&raw[8..11], ntres & NTRES_LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+/// The checksum of a short name, stored in its long name entries.
+fn checksum(name: &[u8]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// FAT names are case-insensitive.
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Convert a FAT date and time, which are in local time, to seconds since the
+/// epoch, as Linux does with `tz=UTC`.
+fn unix_time(date: u16, time: u16) -> i64 {
+    if date == 0 {
+        return 0;
+    }
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xF).clamp(1, 12) as i64;
+    let day = (date & 0x1F).max(1) as i64;
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs =
+        (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3F) as i64 * 60 + (time & 0x1F) as i64 * 2;
+    days * 86400 + secs
+}
+
+/// Read the free cluster count of the FSInfo sector at `offset`.
+fn read_fsinfo(device: &dyn Device, offset: usize) -> Option<u32> {
+    if offset == 0 {
+        return None;
+    }
+    let mut buf = [0u8; 512];
+    read_exact(device, offset, &mut buf).ok()?;
+    if get_u32(&buf, 0) != 0x4161_5252 || get_u32(&buf, 484) != 0x6141_7272 {
+        return None;
+    }
+    match get_u32(&buf, 488) {
+        0xFFFF_FFFF => None,
+        free => Some(free),
+    }
+}
+
+fn read_exact(device: &dyn Device, offset: usize, buf: &mut [u8]) -> Result<()> {
+    match device.read_at(offset, buf) {
+        Ok(len) if len == buf.len() => Ok(()),
+        _ => Err(FsError::DeviceError),
+    }
+}
+
+fn get_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::rcore_fs_wrapper::MemBuf;
+
+    const SECTOR_SIZE: usize = 512;
+    const SECTORS: usize = 64;
+    /// Sectors of the FAT and of the fixed root directory.
+    const FAT_SECTOR: usize = 1;
+    const ROOT_SECTOR: usize = 2;
+    /// Size of `/Long File Name.txt`, in clusters 3, 5 and 6.
+    const LONG_SIZE: usize = 1100;
+
+    fn sector_mut(image: &mut [u8], sector: usize) -> &mut [u8] {
+        &mut image[sector * SECTOR_SIZE..(sector + 1) * SECTOR_SIZE]
+    }
+
+    /// Clusters of one sector follow the root directory.
+    fn cluster_mut(image: &mut [u8], cluster: usize) -> &mut [u8] {
+        sector_mut(image, ROOT_SECTOR + 1 + cluster - 2)
+    }
+
+    fn set_u16(buf: &mut [u8], offset: usize, value: u16) {
+        buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_fat12(fat: &mut [u8], cluster: usize, value: u16) {
+        let offset = cluster * 3 / 2;
+        let mut entry = get_u16(fat, offset);
+        entry = if cluster % 2 == 0 {
+            (entry & 0xF000) | value
+        } else {
+            (entry & 0x000F) | (value << 4)
+        };
+        set_u16(fat, offset, entry);
+    }
+
+    /// Write the long name entries of `long` for the short name `short` from
+    /// entry `index` of `dir`. Returns the index of the short entry.
+    fn put_long_name(dir: &mut [u8], mut index: usize, short: &[u8; 11], long: &str) -> usize {
+        let mut chars: Vec<u16> = long.encode_utf16().chain([0]).collect();
+        let count = (chars.len() + 12) / 13;
+        chars.resize(count * 13, 0xFFFF);
+        for seq in (1..=count).rev() {
+            let raw = &mut dir[index * DIR_ENTRY_SIZE..(index + 1) * DIR_ENTRY_SIZE];
+            raw[0] = seq as u8 | if seq == count { LFN_LAST } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum(short);
+            let offsets = (1..11)
+                .step_by(2)
+                .chain((14..26).step_by(2))
+                .chain((28..32).step_by(2));
+            for (offset, &c) in offsets.zip(&chars[(seq - 1) * 13..seq * 13]) {
+                set_u16(raw, offset, c);
+            }
+            index += 1;
+        }
+        index
+    }
+
+    /// Write a short entry at entry `index` of `dir`, and returns it.
+    fn put_entry<'a>(
+        dir: &'a mut [u8],
+        index: usize,
+        short: &[u8; 11],
+        attr: u8,
+        cluster: u16,
+        size: u32,
+    ) -> &'a mut [u8] {
+        let raw = &mut dir[index * DIR_ENTRY_SIZE..(index + 1) * DIR_ENTRY_SIZE];
+        raw[..11].copy_from_slice(short);
+        raw[11] = attr;
+        set_u16(raw, 26, cluster);
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// A FAT12 image of one sector per cluster, with a volume label and a
+    /// deleted entry in the root directory, and:
+    ///
+    /// - `/Long File Name.txt`, with a fragmented cluster chain, and filled
+    ///   with `position % 251`
+    /// - `/SUBDIR/INNER.TXT`, which is empty
+    /// - `/readme.txt`, read-only, with a lower case short name
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; SECTORS * SECTOR_SIZE];
+        let bs = sector_mut(&mut image, 0);
+        bs[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        set_u16(bs, 11, SECTOR_SIZE as u16);
+        bs[13] = 1;
+        set_u16(bs, 14, 1);
+        bs[16] = 1;
+        set_u16(bs, 17, (SECTOR_SIZE / DIR_ENTRY_SIZE) as u16);
+        set_u16(bs, 19, SECTORS as u16);
+        set_u16(bs, 22, 1);
+        bs[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let fat = sector_mut(&mut image, FAT_SECTOR);
+        let next = [0xFF8, 0xFFF, 0xFFF, 5, 0xFFF, 6, 0xFFF];
+        for (cluster, &next) in next.iter().enumerate() {
+            set_fat12(fat, cluster, next);
+        }
+
+        let root = sector_mut(&mut image, ROOT_SECTOR);
+        put_entry(root, 0, b"ZCORE      ", ATTR_VOLUME_ID, 0, 0);
+        let i = put_long_name(root, 1, b"LONGFI~1TXT", "Long File Name.txt");
+        put_entry(root, i, b"LONGFI~1TXT", 0x20, 3, LONG_SIZE as u32);
+        put_entry(root, i + 1, b"GONE    TXT", 0x20, 0, 0)[0] = ENTRY_DELETED;
+        put_entry(root, i + 2, b"SUBDIR     ", ATTR_DIRECTORY, 2, 0);
+        let readme = put_entry(root, i + 3, b"README  TXT", ATTR_READ_ONLY, 4, 6);
+        readme[12] = NTRES_LOWER_BASE | NTRES_LOWER_EXT;
+        // modified at 2000-01-01 00:00:00
+        set_u16(readme, 24, (20 << 9) | (1 << 5) | 1);
+
+        let dir = cluster_mut(&mut image, 2);
+        put_entry(dir, 0, b".          ", ATTR_DIRECTORY, 2, 0);
+        put_entry(dir, 1, b"..         ", ATTR_DIRECTORY, 0, 0);
+        put_entry(dir, 2, b"INNER   TXT", 0x20, 0, 0);
+
+        cluster_mut(&mut image, 4)[..6].copy_from_slice(b"hello\n");
+        for (i, &cluster) in [3, 5, 6].iter().enumerate() {
+            for (j, b) in cluster_mut(&mut image, cluster).iter_mut().enumerate() {
+                *b = ((i * SECTOR_SIZE + j) % 251) as u8;
+            }
+        }
+        image
+    }
+
+    fn open(image: Vec<u8>) -> Result<Arc<FatFileSystem>> {
+        FatFileSystem::open(Arc::new(MemBuf::new(Box::leak(image.into_boxed_slice()))))
+    }
+
+    fn entries(inode: &Arc<dyn INode>) -> Vec<String> {
+        (0..).map_while(|i| inode.get_entry(i).ok()).collect()
+    }
+
+    #[test]
+    fn read_tree() {
+        let fs = open(image()).unwrap();
+        assert_eq!(fs.fat_type, FatType::Fat12);
+        let root = fs.root_inode();
+        assert_eq!(
+            entries(&root),
+            [".", "..", "Long File Name.txt", "SUBDIR", "readme.txt"]
+        );
+
+        // names are case-insensitive, and files are found by short names too
+        let long = root.find("LONG FILE NAME.TXT").unwrap();
+        let short = root.find("longfi~1.txt").unwrap();
+        assert_eq!(
+            long.metadata().unwrap().inode,
+            short.metadata().unwrap().inode
+        );
+        assert!(matches!(root.find("gone.txt"), Err(FsError::EntryNotFound)));
+
+        let dir = root.find("subdir").unwrap();
+        assert_eq!(entries(&dir), [".", "..", "INNER.TXT"]);
+        let meta = dir.metadata().unwrap();
+        assert_eq!((meta.type_, meta.inode), (FileType::Dir, 2));
+        let parent = dir.find("..").unwrap().metadata().unwrap();
+        assert_eq!(parent.inode, FIXED_ROOT_ID);
+        let mut buf = [0u8; 16];
+        let inner = dir.find("inner.txt").unwrap();
+        assert_eq!(inner.read_at(0, &mut buf).unwrap(), 0);
+        assert!(matches!(dir.read_at(0, &mut buf), Err(FsError::IsDir)));
+
+        let readme = root.find("README.TXT").unwrap();
+        let meta = readme.metadata().unwrap();
+        assert_eq!(
+            (meta.type_, meta.size, meta.mode),
+            (FileType::File, 6, 0o555)
+        );
+        assert_eq!(meta.mtime.sec, 946_684_800);
+        assert_eq!(readme.read_at(0, &mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"hello\n");
+        assert!(matches!(
+            readme.write_at(0, b"x"),
+            Err(FsError::NotSupported)
+        ));
+    }
+
+    #[test]
+    fn read_chain() {
+        let fs = open(image()).unwrap();
+        // 5 of the 61 clusters are used
+        assert_eq!(fs.info().bfree, 56);
+        let long = fs.root_inode().find("Long File Name.txt").unwrap();
+        let mut buf = vec![0u8; 2 * LONG_SIZE];
+        assert_eq!(long.read_at(0, &mut buf).unwrap(), LONG_SIZE);
+        assert!((0..LONG_SIZE).all(|i| buf[i] == (i % 251) as u8));
+
+        // across the gap between clusters 3 and 5, and up to the end
+        let offset = SECTOR_SIZE - 10;
+        assert_eq!(long.read_at(offset, &mut buf[..20]).unwrap(), 20);
+        assert!((0..20).all(|i| buf[i] == ((offset + i) % 251) as u8));
+        assert_eq!(long.read_at(LONG_SIZE - 4, &mut buf).unwrap(), 4);
+        assert_eq!(long.read_at(LONG_SIZE, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn broken() {
+        assert!(matches!(open(vec![0; SECTOR_SIZE]), Err(FsError::WrongFs)));
+
+        // a loop in a cluster chain
+        let mut image = image();
+        set_fat12(sector_mut(&mut image, FAT_SECTOR), 6, 5);
+        let fs = open(image).unwrap();
+        let long = fs.root_inode().find("Long File Name.txt").unwrap();
+        let mut buf = [0u8; 16];
+        assert!(matches!(
+            long.read_at(0, &mut buf),
+            Err(FsError::DeviceError)
+        ));
+    }
+}
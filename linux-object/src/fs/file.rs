@@ -9,7 +9,11 @@ use rcore_fs::vfs::{FileType, FsError, INode, Metadata, PollStatus};
 use zircon_object::object::*;
 use zircon_object::vm::{pages, VmObject};
 
-use super::FileLike;
+use super::lock::{self, FlockOwner, LockKind};
+use super::{
+    inotify_event, ChangeCallback, FileLike, InotifyMask, OpenFileDescription, PageCache, Pipe,
+    TmpINode,
+};
 use crate::error::{LxError, LxResult};
 
 use zircon_object::vm::PAGE_SIZE_LOG2;
@@ -33,8 +37,12 @@ bitflags::bitflags! {
         const APPEND = 1 << 10;
         /// non block open
         const NON_BLOCK = 1 << 11;
+        /// direct IO, or packet mode of a pipe
+        const DIRECT = 1 << 14;
         /// close on exec
         const CLOEXEC = 1 << 19;
+        /// create an unnamed temporary file in the directory
+        const TMPFILE = 1 << 22;
     }
 }
 
@@ -98,6 +106,8 @@ struct FileInner {
     flags: OpenFlags,
     /// file INode
     inode: Arc<dyn INode>,
+    /// page cache of a regular file
+    cache: Option<Arc<PageCache>>,
 }
 
 /// file implement struct
@@ -108,6 +118,10 @@ pub struct File {
     path: String,
     /// file inner mut data
     inner: RwLock<FileInner>,
+    /// owner of `flock` locks, shared with the duplicates
+    flock_owner: Arc<FlockOwner>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
 }
 
 impl_kobject!(File);
@@ -128,7 +142,7 @@ impl FileInner {
         if !self.flags.non_block() {
             // block
             loop {
-                match self.inode.read_at(offset as usize, buf) {
+                match self.inode_read_at(offset as usize, buf) {
                     Ok(read_len) => return Ok(read_len),
                     Err(FsError::Again) => {
                         self.inode.async_poll().await?;
@@ -137,10 +151,18 @@ impl FileInner {
                 }
             }
         }
-        let len = self.inode.read_at(offset as usize, buf)?;
+        let len = self.inode_read_at(offset as usize, buf)?;
         Ok(len)
     }
 
+    /// read from the page cache, or the INode if there is none
+    fn inode_read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        match &self.cache {
+            Some(cache) => cache.read_at(offset, buf),
+            None => self.inode.read_at(offset, buf),
+        }
+    }
+
     /// write to file
     fn write(&mut self, buf: &[u8]) -> LxResult<usize> {
         let offset = if self.flags.is_append() {
@@ -158,7 +180,17 @@ impl FileInner {
         if !self.flags.writable() {
             return Err(LxError::EBADF);
         }
-        let len = self.inode.write_at(offset as usize, buf)?;
+        // a pipe tells a broken pipe, which its INode can not
+        if let Some(pipe) = self.inode.downcast_ref::<Pipe>() {
+            return pipe.write(buf);
+        }
+        let len = match &self.cache {
+            Some(cache) => cache.write_at(offset as usize, buf)?,
+            None => self.inode.write_at(offset as usize, buf)?,
+        };
+        if len != 0 {
+            inotify_event(&*self.inode, InotifyMask::MODIFY, 0, "");
+        }
         Ok(len)
     }
 }
@@ -166,14 +198,18 @@ impl FileInner {
 impl File {
     /// create a file struct
     pub fn new(inode: Arc<dyn INode>, flags: OpenFlags, path: String) -> Arc<Self> {
-        Arc::new(File {
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(File {
             base: KObjectBase::new(),
             path,
             inner: RwLock::new(FileInner {
                 offset: 0,
                 flags,
+                cache: PageCache::get(&inode),
                 inode,
             }),
+            flock_owner: Arc::new(FlockOwner),
+            description: description.clone(),
         })
     }
 
@@ -199,19 +235,31 @@ impl File {
         if !inner.flags.writable() {
             return Err(LxError::EBADF);
         }
-        inner.inode.resize(len as usize)?;
+        match &inner.cache {
+            Some(cache) => cache.resize(len as usize)?,
+            None => inner.inode.resize(len as usize)?,
+        }
+        inotify_event(&*inner.inode, InotifyMask::MODIFY, 0, "");
         Ok(())
     }
 
     /// Sync all data and metadata
     pub fn sync_all(&self) -> LxResult {
-        self.inner.read().inode.sync_all()?;
+        let inner = self.inner.read();
+        if let Some(cache) = &inner.cache {
+            cache.sync()?;
+        }
+        inner.inode.sync_all()?;
         Ok(())
     }
 
     /// Sync data (not include metadata)
     pub fn sync_data(&self) -> LxResult {
-        self.inner.read().inode.sync_data()?;
+        let inner = self.inner.read();
+        if let Some(cache) = &inner.cache {
+            cache.sync()?;
+        }
+        inner.inode.sync_data()?;
         Ok(())
     }
 
@@ -241,6 +289,15 @@ impl File {
     pub fn inode(&self) -> Arc<dyn INode> {
         self.inner.read().inode.clone()
     }
+
+    /// Place a `flock` lock of this open file on its inode, or unlock it if
+    /// `kind` is `None`.
+    ///
+    /// Fails with `EAGAIN` on a conflicting lock, unless `wait` is set.
+    pub async fn flock(&self, kind: Option<LockKind>, wait: bool) -> LxResult {
+        let inode = self.inode();
+        lock::set_flock(&*inode, self.flock_owner.id(), kind, wait).await
+    }
 }
 
 #[async_trait]
@@ -258,13 +315,19 @@ impl FileLike for File {
     }
 
     fn dup(&self) -> Arc<dyn FileLike> {
-        Arc::new(Self {
+        self.description.add(Self {
             base: KObjectBase::new(),
             path: self.path.clone(),
             inner: RwLock::new(self.inner.read().clone()),
+            flock_owner: self.flock_owner.clone(),
+            description: self.description.clone(),
         })
     }
 
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
         self.inner.write().read(buf).await
     }
@@ -289,17 +352,38 @@ impl FileLike for File {
         Ok(self.inner.read().inode.async_poll().await?)
     }
 
+    fn hung_up(&self) -> bool {
+        let inode = self.inode();
+        inode.downcast_ref::<Pipe>().map_or(false, Pipe::hung_up)
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        match self.inode().downcast_ref::<Pipe>() {
+            Some(pipe) => {
+                pipe.on_change(callback);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn ioctl(&self, request: usize, arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
         // ioctl syscall
-        self.inner.read().inode.io_control(request as u32, arg1)?;
-        Ok(0)
+        Ok(self.inner.read().inode.io_control(request as u32, arg1)?)
     }
 
     /// Returns the [`VmObject`] representing the file with given `offset` and `len`.
-    fn get_vmo(&self, offset: usize, len: usize) -> LxResult<Arc<VmObject>> {
+    fn get_vmo(&self, offset: usize, len: usize, shared: bool) -> LxResult<Arc<VmObject>> {
         let inner = self.inner.read();
         match inner.inode.metadata()?.type_ {
             FileType::File => {
+                let inode = super::fs_inode(&inner.inode);
+                if let Some(inode) = inode.downcast_ref::<TmpINode>() {
+                    return Ok(inode.get_vmo(offset, len, shared)?);
+                }
+                if let Some(cache) = &inner.cache {
+                    return Ok(cache.get_vmo(offset, len, shared)?);
+                }
                 let vmo = VmObject::new_contiguous(pages(len), PAGE_SIZE_LOG2)?;
                 let (guard, buf) = vmo.as_mut_buf()?;
                 inner.inode.read_at(offset, buf)?;
@@ -0,0 +1,457 @@
+//! Implement inotify file object
+#![deny(missing_docs)]
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use async_trait::async_trait;
+use lock::Mutex;
+use rcore_fs::vfs::{INode, PollStatus};
+use zircon_object::object::*;
+
+use super::ioctl::FIONREAD;
+use super::page_cache::inode_key;
+use super::{ChangeCallback, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::sync::{wait_for_event, Event, EventBus};
+
+bitflags::bitflags! {
+    /// Flags for `inotify_init1`
+    pub struct InotifyFlags: usize {
+        /// Set the close-on-exec flag on the new file descriptor.
+        const CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the new open file description.
+        const NONBLOCK = 0o4000;
+    }
+}
+
+bitflags::bitflags! {
+    /// Events and flags of inotify watches
+    pub struct InotifyMask: u32 {
+        /// File was accessed.
+        const ACCESS = 0x1;
+        /// File was modified.
+        const MODIFY = 0x2;
+        /// Metadata changed.
+        const ATTRIB = 0x4;
+        /// Writable file was closed.
+        const CLOSE_WRITE = 0x8;
+        /// Unwritable file closed.
+        const CLOSE_NOWRITE = 0x10;
+        /// File was opened.
+        const OPEN = 0x20;
+        /// File was moved from the watched directory.
+        const MOVED_FROM = 0x40;
+        /// File was moved to the watched directory.
+        const MOVED_TO = 0x80;
+        /// File was created in the watched directory.
+        const CREATE = 0x100;
+        /// File was deleted from the watched directory.
+        const DELETE = 0x200;
+        /// The watched file was deleted.
+        const DELETE_SELF = 0x400;
+        /// The watched file was moved.
+        const MOVE_SELF = 0x800;
+        /// Backing file system was unmounted.
+        const UNMOUNT = 0x2000;
+        /// Event queue overflowed.
+        const Q_OVERFLOW = 0x4000;
+        /// The watch was removed.
+        const IGNORED = 0x8000;
+        /// Only watch the path if it is a directory.
+        const ONLYDIR = 0x0100_0000;
+        /// Do not follow a symbolic link.
+        const DONT_FOLLOW = 0x0200_0000;
+        /// Exclude events on unlinked objects.
+        const EXCL_UNLINK = 0x0400_0000;
+        /// Only create watches.
+        const MASK_CREATE = 0x1000_0000;
+        /// Add to the mask of an existing watch.
+        const MASK_ADD = 0x2000_0000;
+        /// The event occurred against a directory.
+        const ISDIR = 0x4000_0000;
+        /// Only send the event once.
+        const ONESHOT = 0x8000_0000;
+        /// All the events to watch.
+        const ALL_EVENTS = 0xfff;
+    }
+}
+
+/// The most events queued in an inotify instance, like the default
+/// `/proc/sys/fs/inotify/max_queued_events`.
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// The size of `struct inotify_event` without the name.
+const EVENT_HEADER_SIZE: usize = 16;
+
+/// An event waiting to be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: String,
+}
+
+impl InotifyEvent {
+    /// The length of the name with its NUL, padded to the alignment of the
+    /// next event.
+    fn name_len(&self) -> usize {
+        if self.name.is_empty() {
+            0
+        } else {
+            (self.name.len() + 1 + 3) & !3
+        }
+    }
+
+    /// Write `struct inotify_event` to `buf`, returns its size.
+    fn write_to(&self, buf: &mut [u8]) -> usize {
+        let len = self.name_len();
+        buf[0..4].copy_from_slice(&self.wd.to_ne_bytes());
+        buf[4..8].copy_from_slice(&self.mask.bits().to_ne_bytes());
+        buf[8..12].copy_from_slice(&self.cookie.to_ne_bytes());
+        buf[12..16].copy_from_slice(&(len as u32).to_ne_bytes());
+        let name = &mut buf[EVENT_HEADER_SIZE..EVENT_HEADER_SIZE + len];
+        name.fill(0);
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        EVENT_HEADER_SIZE + len
+    }
+}
+
+/// A watch on an inode, of an inotify instance.
+struct Watch {
+    wd: i32,
+    mask: InotifyMask,
+    owner: Weak<InotifyData>,
+}
+
+lazy_static::lazy_static! {
+    /// The watches on each inode
+    static ref WATCHES: Mutex<BTreeMap<(usize, usize), Vec<Watch>>> = Mutex::new(BTreeMap::new());
+}
+
+/// Shared state of an inotify instance
+struct InotifyData {
+    /// the inode of each watch descriptor
+    watches: Mutex<BTreeMap<i32, (usize, usize)>>,
+    /// the next watch descriptor
+    next_wd: Mutex<i32>,
+    /// events waiting to be read
+    queue: Mutex<VecDeque<InotifyEvent>>,
+    /// readable notifications
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+impl InotifyData {
+    fn push(&self, event: InotifyEvent) {
+        let mut queue = self.queue.lock();
+        // identical to the last unread event
+        if queue.back() == Some(&event) {
+            return;
+        }
+        if queue.len() >= MAX_QUEUED_EVENTS {
+            let overflow = InotifyEvent {
+                wd: -1,
+                mask: InotifyMask::Q_OVERFLOW,
+                cookie: 0,
+                name: String::new(),
+            };
+            if queue.back() != Some(&overflow) {
+                queue.push_back(overflow);
+            }
+        } else {
+            queue.push_back(event);
+        }
+        self.eventbus
+            .lock()
+            .change_notify(Event::empty(), Event::READABLE);
+    }
+
+    /// Remove the watch `wd` from the inode `key`, and queue `IN_IGNORED`.
+    fn remove_watch(self: &Arc<Self>, wd: i32, key: (usize, usize)) {
+        let mut watches = WATCHES.lock();
+        if let Some(list) = watches.get_mut(&key) {
+            list.retain(|w| !(w.wd == wd && w.owner.as_ptr() == Arc::as_ptr(self)));
+            if list.is_empty() {
+                watches.remove(&key);
+            }
+        }
+        drop(watches);
+        self.push(InotifyEvent {
+            wd,
+            mask: InotifyMask::IGNORED,
+            cookie: 0,
+            name: String::new(),
+        });
+    }
+}
+
+/// A file object reporting the changes of the watched files.
+pub struct Inotify {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    data: Arc<InotifyData>,
+}
+
+impl_kobject!(Inotify);
+
+impl Inotify {
+    /// Create a new inotify instance without any watch.
+    pub fn new(flags: InotifyFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDONLY;
+        open_flags.set(OpenFlags::NON_BLOCK, flags.contains(InotifyFlags::NONBLOCK));
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(InotifyFlags::CLOEXEC));
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(Inotify {
+            base: KObjectBase::new(),
+            flags: Mutex::new(open_flags),
+            description: description.clone(),
+            data: Arc::new(InotifyData {
+                watches: Mutex::new(BTreeMap::new()),
+                next_wd: Mutex::new(1),
+                queue: Mutex::new(VecDeque::new()),
+                eventbus: EventBus::new(),
+            }),
+        })
+    }
+
+    /// Watch the events in `mask` on `inode`, returns the watch descriptor.
+    ///
+    /// The watch of the inode is replaced, or extended with `IN_MASK_ADD`.
+    pub fn add_watch(&self, inode: &dyn INode, mask: InotifyMask) -> LxResult<i32> {
+        let events = mask & (InotifyMask::ALL_EVENTS | InotifyMask::ONESHOT);
+        if (events - InotifyMask::ONESHOT).is_empty()
+            || mask.contains(InotifyMask::MASK_ADD | InotifyMask::MASK_CREATE)
+        {
+            return Err(LxError::EINVAL);
+        }
+        let key = inode_key(inode)?;
+        let mut watches = WATCHES.lock();
+        let list = watches.entry(key).or_default();
+        let owner = Arc::as_ptr(&self.data);
+        if let Some(watch) = list.iter_mut().find(|w| w.owner.as_ptr() == owner) {
+            if mask.contains(InotifyMask::MASK_CREATE) {
+                return Err(LxError::EEXIST);
+            }
+            if mask.contains(InotifyMask::MASK_ADD) {
+                watch.mask |= events;
+            } else {
+                watch.mask = events;
+            }
+            return Ok(watch.wd);
+        }
+        let wd = {
+            let mut next_wd = self.data.next_wd.lock();
+            *next_wd += 1;
+            *next_wd - 1
+        };
+        list.push(Watch {
+            wd,
+            mask: events,
+            owner: Arc::downgrade(&self.data),
+        });
+        self.data.watches.lock().insert(wd, key);
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd`, queueing `IN_IGNORED`.
+    pub fn rm_watch(&self, wd: i32) -> LxResult {
+        let key = self
+            .data
+            .watches
+            .lock()
+            .remove(&wd)
+            .ok_or(LxError::EINVAL)?;
+        self.data.remove_watch(wd, key);
+        Ok(())
+    }
+
+    /// Move the events fitting in `buf` out of the queue, returns the bytes
+    /// written.
+    fn try_read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        let mut queue = self.data.queue.lock();
+        let mut len = 0;
+        while let Some(event) = queue.front() {
+            if EVENT_HEADER_SIZE + event.name_len() > buf.len() - len {
+                break;
+            }
+            len += event.write_to(&mut buf[len..]);
+            queue.pop_front();
+        }
+        if queue.is_empty() {
+            self.data.eventbus.lock().clear(Event::READABLE);
+        } else if len == 0 {
+            // the next event does not fit in the buffer
+            return Err(LxError::EINVAL);
+        }
+        Ok(len)
+    }
+
+    fn poll_status(&self) -> PollStatus {
+        PollStatus {
+            read: !self.data.queue.lock().is_empty(),
+            write: false,
+            error: false,
+        }
+    }
+}
+
+impl Drop for InotifyData {
+    fn drop(&mut self) {
+        let mut watches = WATCHES.lock();
+        for key in self.watches.lock().values() {
+            if let Some(list) = watches.get_mut(key) {
+                list.retain(|w| w.owner.strong_count() != 0);
+                if list.is_empty() {
+                    watches.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for Inotify {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(Inotify {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            data: self.data.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        loop {
+            let len = self.try_read(buf)?;
+            if len != 0 {
+                return Ok(len);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.data.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        Ok(self.poll_status())
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) {
+            wait_for_event(self.data.eventbus.clone(), Event::READABLE).await;
+        }
+        Ok(self.poll_status())
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.data.eventbus.lock().subscribe_once(callback);
+        true
+    }
+
+    fn ioctl(&self, request: usize, arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        match request {
+            FIONREAD => {
+                let len: usize = self
+                    .data
+                    .queue
+                    .lock()
+                    .iter()
+                    .map(|e| EVENT_HEADER_SIZE + e.name_len())
+                    .sum();
+                unsafe { *(arg1 as *mut u32) = len as u32 };
+                Ok(0)
+            }
+            _ => Err(LxError::ENOTTY),
+        }
+    }
+}
+
+/// The cookie relating the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a
+/// rename.
+pub fn inotify_cookie() -> u32 {
+    static COOKIE: AtomicU32 = AtomicU32::new(1);
+    COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Report the event `mask` on `inode` to its watches, with the `name` of the
+/// child in a directory or an empty one for the inode itself.
+///
+/// `IN_DELETE_SELF` and `IN_UNMOUNT` also remove all watches of the inode.
+pub fn inotify_event(inode: &dyn INode, mask: InotifyMask, cookie: u32, name: &str) {
+    // skip looking up the inode when nothing is watched
+    if WATCHES.lock().is_empty() {
+        return;
+    }
+    let key = match inode_key(inode) {
+        Ok(key) => key,
+        Err(_) => return,
+    };
+    let removed = mask.intersects(InotifyMask::DELETE_SELF | InotifyMask::UNMOUNT);
+    let mut fired = Vec::new();
+    {
+        let watches = WATCHES.lock();
+        let list = match watches.get(&key) {
+            Some(list) => list,
+            None => return,
+        };
+        for watch in list.iter() {
+            let matched = watch.mask.intersects(mask & InotifyMask::ALL_EVENTS);
+            if matched || removed {
+                if let Some(owner) = watch.owner.upgrade() {
+                    fired.push((owner, watch.wd, watch.mask, matched));
+                }
+            }
+        }
+    }
+    for (owner, wd, watch_mask, matched) in fired {
+        if matched {
+            owner.push(InotifyEvent {
+                wd,
+                mask,
+                cookie,
+                name: String::from(name),
+            });
+        }
+        if removed || (matched && watch_mask.contains(InotifyMask::ONESHOT)) {
+            owner.watches.lock().remove(&wd);
+            owner.remove_watch(wd, key);
+        }
+    }
+}
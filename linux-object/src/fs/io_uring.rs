@@ -0,0 +1,893 @@
+//! Implement io_uring, asynchronous IO by rings shared with the user space
+#![deny(missing_docs)]
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::convert::TryInto;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use async_trait::async_trait;
+use kernel_hal::{thread, timer};
+use lock::Mutex;
+use rcore_fs::vfs::PollStatus;
+use zircon_object::{
+    object::*,
+    task::{Process, Status},
+    vm::{pages, VmObject},
+};
+
+use super::{ChangeCallback, FileDesc, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::net::{MsgFlags, SockAddr};
+use crate::process::ProcessExt;
+use crate::sync::{wait_for_event, Event, EventBus};
+
+/// The `mmap` offset of the submission queue ring
+pub const IORING_OFF_SQ_RING: usize = 0;
+/// The `mmap` offset of the completion queue ring
+pub const IORING_OFF_CQ_RING: usize = 0x800_0000;
+/// The `mmap` offset of the submission queue entries
+pub const IORING_OFF_SQES: usize = 0x1000_0000;
+
+/// The largest number of submission queue entries
+const IORING_MAX_ENTRIES: u32 = 4096;
+/// The largest number of completion queue entries
+const IORING_MAX_CQ_ENTRIES: u32 = 2 * IORING_MAX_ENTRIES;
+
+/// Features of the rings: completions are never dropped, the entries are
+/// consumed when submitted, and offset `-1` means the file position.
+const IORING_FEAT_NODROP: u32 = 1 << 1;
+const IORING_FEAT_SUBMIT_STABLE: u32 = 1 << 2;
+const IORING_FEAT_RW_CUR_POS: u32 = 1 << 3;
+
+/// The layout of the submission queue ring: the head, tail, mask, number
+/// of entries, flags and dropped count, then the array of the indexes of
+/// the entries submitted.
+const SQ_HEAD: usize = 0;
+const SQ_TAIL: usize = 4;
+const SQ_RING_MASK: usize = 8;
+const SQ_RING_ENTRIES: usize = 12;
+const SQ_FLAGS: usize = 16;
+const SQ_DROPPED: usize = 20;
+const SQ_ARRAY: usize = 64;
+
+/// The layout of the completion queue ring: the head, tail, mask, number of
+/// entries, overflow count and flags, then the array of the completions.
+const CQ_HEAD: usize = 0;
+const CQ_TAIL: usize = 4;
+const CQ_RING_MASK: usize = 8;
+const CQ_RING_ENTRIES: usize = 12;
+const CQ_OVERFLOW: usize = 16;
+const CQ_FLAGS: usize = 20;
+const CQ_CQES: usize = 64;
+
+/// The size of `struct io_uring_sqe`
+const SQE_SIZE: usize = 64;
+/// The size of `struct io_uring_cqe`
+const CQE_SIZE: usize = 16;
+
+/// The operations supported
+const IORING_OP_NOP: u8 = 0;
+const IORING_OP_TIMEOUT: u8 = 11;
+const IORING_OP_ACCEPT: u8 = 13;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+const IORING_OP_SEND: u8 = 26;
+const IORING_OP_RECV: u8 = 27;
+const SUPPORTED_OPS: [u8; 7] = [
+    IORING_OP_NOP,
+    IORING_OP_TIMEOUT,
+    IORING_OP_ACCEPT,
+    IORING_OP_READ,
+    IORING_OP_WRITE,
+    IORING_OP_SEND,
+    IORING_OP_RECV,
+];
+
+/// The deadline of `IORING_OP_TIMEOUT` is absolute.
+const IORING_TIMEOUT_ABS: u32 = 1;
+
+/// `struct io_sqring_offsets`, the layout of the submission queue ring
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    resv2: u64,
+}
+
+/// `struct io_cqring_offsets`, the layout of the completion queue ring
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    resv2: u64,
+}
+
+/// `struct io_uring_params` of `io_uring_setup`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoUringParams {
+    /// the number of submission queue entries
+    pub sq_entries: u32,
+    /// the number of completion queue entries
+    pub cq_entries: u32,
+    /// flags of setup
+    pub flags: u32,
+    /// the CPU of the polling thread
+    pub sq_thread_cpu: u32,
+    /// the idle time of the polling thread in milliseconds
+    pub sq_thread_idle: u32,
+    /// the features of the rings
+    pub features: u32,
+    /// the ring whose workers are shared
+    pub wq_fd: u32,
+    /// reserved, must be zero
+    pub resv: [u32; 3],
+    /// the layout of the submission queue ring
+    pub sq_off: SqRingOffsets,
+    /// the layout of the completion queue ring
+    pub cq_off: CqRingOffsets,
+}
+
+bitflags::bitflags! {
+    /// Flags of `io_uring_setup`
+    pub struct IoUringSetupFlags: u32 {
+        /// busy-wait for the completions
+        const IOPOLL = 1;
+        /// poll the submission queue by a kernel thread
+        const SQPOLL = 2;
+        /// bind the polling thread to `sq_thread_cpu`
+        const SQ_AFF = 4;
+        /// the number of completion queue entries is given
+        const CQSIZE = 8;
+        /// clamp the numbers of entries to the largest ones
+        const CLAMP = 16;
+        /// share the workers of the ring `wq_fd`
+        const ATTACH_WQ = 32;
+        /// start with the ring disabled
+        const R_DISABLED = 64;
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of `io_uring_enter`
+    pub struct IoUringEnterFlags: usize {
+        /// wait for `min_complete` completions
+        const GETEVENTS = 1;
+        /// wake up the polling thread
+        const SQ_WAKEUP = 2;
+        /// wait for room in the submission queue
+        const SQ_WAIT = 4;
+        /// the argument is `struct io_uring_getevents_arg`
+        const EXT_ARG = 8;
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of a submission queue entry
+    struct SqeFlags: u8 {
+        /// `fd` is an index of the registered files
+        const FIXED_FILE = 1;
+        /// start after the entries submitted before are completed
+        const IO_DRAIN = 2;
+        /// start after the previous entry is completed
+        const IO_LINK = 4;
+        /// like `IO_LINK`, even if the previous entry failed
+        const IO_HARDLINK = 8;
+        /// always run asynchronously
+        const ASYNC = 16;
+        /// pick a buffer from a registered group
+        const BUFFER_SELECT = 32;
+    }
+}
+
+/// `struct io_uring_sqe`, the fields used by the operations supported
+#[derive(Debug, Clone, Copy)]
+struct Sqe {
+    opcode: u8,
+    flags: SqeFlags,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    op_flags: u32,
+    user_data: u64,
+}
+
+impl Sqe {
+    fn parse(buf: &[u8; SQE_SIZE]) -> Self {
+        let u32_at = |i: usize| u32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_ne_bytes(buf[i..i + 8].try_into().unwrap());
+        Sqe {
+            opcode: buf[0],
+            flags: SqeFlags::from_bits_truncate(buf[1]),
+            fd: u32_at(4) as i32,
+            off: u64_at(8),
+            addr: u64_at(16),
+            len: u32_at(24),
+            op_flags: u32_at(28),
+            user_data: u64_at(32),
+        }
+    }
+}
+
+/// A pending `IORING_OP_TIMEOUT` waiting for a number of completions
+struct CountTimeout {
+    id: usize,
+    /// the number of completions to complete it
+    target: u64,
+    user_data: u64,
+}
+
+/// Mutable state of an io_uring
+#[derive(Default)]
+struct IoUringInner {
+    /// the next submission to consume
+    sq_head: u32,
+    /// the next completion to fill
+    cq_tail: u32,
+    /// the completions waiting for room in the completion queue
+    backlog: VecDeque<(u64, i32)>,
+    /// the number of completions, except the ones of timeouts
+    completed: u64,
+    timeouts: Vec<CountTimeout>,
+    next_timeout_id: usize,
+    /// the operations running as tasks, by their IDs
+    running: BTreeMap<usize, Arc<OpCancel>>,
+    next_op_id: usize,
+    /// the registered files, by `IOSQE_FIXED_FILE`
+    files: Option<Vec<Option<Arc<dyn FileLike>>>>,
+    /// the registered eventfd, notified of the completions
+    eventfd: Option<Arc<dyn FileLike>>,
+}
+
+/// The cancellation of a running operation
+#[derive(Default)]
+struct OpCancel {
+    canceled: AtomicBool,
+    /// the waker of the task running the operation
+    waker: Mutex<Option<Waker>>,
+}
+
+impl OpCancel {
+    fn cancel(&self) {
+        self.canceled.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Runs an operation until it completes, or fails with `ECANCELED` once
+/// canceled, dropping the operation.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct CancelableFuture<'a, F> {
+    cancel: &'a OpCancel,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future<Output = LxResult<usize>>> Future for CancelableFuture<'_, F> {
+    type Output = LxResult<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        *self.cancel.waker.lock() = Some(cx.waker().clone());
+        if self.cancel.canceled.load(Ordering::Acquire) {
+            return Poll::Ready(Err(LxError::ECANCELED));
+        }
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Shared state of an io_uring
+struct IoUringData {
+    /// the process whose files and memory the operations use
+    proc: Weak<Process>,
+    sq_entries: u32,
+    cq_entries: u32,
+    sq_ring: Arc<VmObject>,
+    cq_ring: Arc<VmObject>,
+    sqes: Arc<VmObject>,
+    inner: Mutex<IoUringInner>,
+    /// readable when a completion is posted
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+/// An io_uring instance, which runs the operations submitted to its
+/// submission queue as tasks of the executor, and posts their results to
+/// its completion queue.
+pub struct IoUring {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    data: Arc<IoUringData>,
+}
+
+impl_kobject!(IoUring);
+
+impl IoUring {
+    /// Create an io_uring of `entries` submission queue entries for the
+    /// operations of `proc`, filling the sizes and the layouts of its rings
+    /// in `params`.
+    pub fn new(
+        proc: &Arc<Process>,
+        entries: u32,
+        params: &mut IoUringParams,
+    ) -> LxResult<Arc<Self>> {
+        let setup = IoUringSetupFlags::from_bits(params.flags).ok_or(LxError::EINVAL)?;
+        let clamp = setup.contains(IoUringSetupFlags::CLAMP);
+        if !(setup - IoUringSetupFlags::CQSIZE - IoUringSetupFlags::CLAMP).is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        if params.resv.iter().any(|&x| x != 0) {
+            return Err(LxError::EINVAL);
+        }
+        if entries == 0 || (entries > IORING_MAX_ENTRIES && !clamp) {
+            return Err(LxError::EINVAL);
+        }
+        let sq_entries = entries.min(IORING_MAX_ENTRIES).next_power_of_two();
+        let cq_entries = if setup.contains(IoUringSetupFlags::CQSIZE) {
+            let cq = params.cq_entries;
+            if cq == 0 || (cq > IORING_MAX_CQ_ENTRIES && !clamp) {
+                return Err(LxError::EINVAL);
+            }
+            let cq = cq.min(IORING_MAX_CQ_ENTRIES).next_power_of_two();
+            if cq < sq_entries {
+                return Err(LxError::EINVAL);
+            }
+            cq
+        } else {
+            2 * sq_entries
+        };
+        let ring = |size: usize| VmObject::new_paged(pages(size));
+        let data = Arc::new(IoUringData {
+            proc: Arc::downgrade(proc),
+            sq_entries,
+            cq_entries,
+            sq_ring: ring(SQ_ARRAY + 4 * sq_entries as usize),
+            cq_ring: ring(CQ_CQES + CQE_SIZE * cq_entries as usize),
+            sqes: ring(SQE_SIZE * sq_entries as usize),
+            inner: Mutex::new(IoUringInner::default()),
+            eventbus: EventBus::new(),
+        });
+        // the running operations hold the process, canceled once it exits
+        let weak = Arc::downgrade(&data);
+        proc.add_signal_callback(Box::new(move |signal| match weak.upgrade() {
+            Some(data) if signal.contains(Signal::PROCESS_TERMINATED) => {
+                data.cancel_all();
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }));
+        data.write_u32(&data.sq_ring, SQ_RING_MASK, sq_entries - 1)?;
+        data.write_u32(&data.sq_ring, SQ_RING_ENTRIES, sq_entries)?;
+        data.write_u32(&data.cq_ring, CQ_RING_MASK, cq_entries - 1)?;
+        data.write_u32(&data.cq_ring, CQ_RING_ENTRIES, cq_entries)?;
+
+        params.sq_entries = sq_entries;
+        params.cq_entries = cq_entries;
+        params.features = IORING_FEAT_NODROP | IORING_FEAT_SUBMIT_STABLE | IORING_FEAT_RW_CUR_POS;
+        params.sq_off = SqRingOffsets {
+            head: SQ_HEAD as u32,
+            tail: SQ_TAIL as u32,
+            ring_mask: SQ_RING_MASK as u32,
+            ring_entries: SQ_RING_ENTRIES as u32,
+            flags: SQ_FLAGS as u32,
+            dropped: SQ_DROPPED as u32,
+            array: SQ_ARRAY as u32,
+            ..Default::default()
+        };
+        params.cq_off = CqRingOffsets {
+            head: CQ_HEAD as u32,
+            tail: CQ_TAIL as u32,
+            ring_mask: CQ_RING_MASK as u32,
+            ring_entries: CQ_RING_ENTRIES as u32,
+            overflow: CQ_OVERFLOW as u32,
+            cqes: CQ_CQES as u32,
+            flags: CQ_FLAGS as u32,
+            ..Default::default()
+        };
+        let description = Arc::<OpenFileDescription>::default();
+        Ok(description.add(IoUring {
+            base: KObjectBase::new(),
+            flags: Mutex::new(OpenFlags::RDWR | OpenFlags::CLOEXEC),
+            description: description.clone(),
+            data,
+        }))
+    }
+
+    /// Start up to `to_submit` operations of the submission queue, returns
+    /// the number of entries consumed.
+    ///
+    /// The operations which fail before they start are completed at once
+    /// with their errors.
+    pub fn submit(&self, to_submit: usize) -> LxResult<usize> {
+        let data = &self.data;
+        let mut sqes = Vec::new();
+        {
+            let mut inner = data.inner.lock();
+            let head = inner.sq_head;
+            let tail = data.read_u32(&data.sq_ring, SQ_TAIL)?;
+            let count = (tail.wrapping_sub(head) as usize)
+                .min(to_submit)
+                .min(data.sq_entries as usize);
+            for i in 0..count as u32 {
+                let slot = (head.wrapping_add(i) & (data.sq_entries - 1)) as usize;
+                let index = data.read_u32(&data.sq_ring, SQ_ARRAY + 4 * slot)?;
+                if index >= data.sq_entries {
+                    let dropped = data.read_u32(&data.sq_ring, SQ_DROPPED)?;
+                    data.write_u32(&data.sq_ring, SQ_DROPPED, dropped.wrapping_add(1))?;
+                    continue;
+                }
+                let mut buf = [0u8; SQE_SIZE];
+                data.sqes.read(SQE_SIZE * index as usize, &mut buf)?;
+                sqes.push(Sqe::parse(&buf));
+            }
+            inner.sq_head = head.wrapping_add(count as u32);
+            data.write_u32(&data.sq_ring, SQ_HEAD, inner.sq_head)?;
+        }
+        // started without the lock, as some complete at once
+        let count = sqes.len();
+        for sqe in sqes {
+            data.start(sqe);
+        }
+        Ok(count)
+    }
+
+    /// Wait until there are `min_complete` completions in the completion
+    /// queue.
+    pub async fn wait(&self, min_complete: usize) -> LxResult {
+        let data = &self.data;
+        loop {
+            {
+                let mut inner = data.inner.lock();
+                data.flush(&mut inner)?;
+                data.eventbus.lock().clear(Event::READABLE);
+                if data.ready(&inner)? >= min_complete.min(data.cq_entries as usize) {
+                    return Ok(());
+                }
+            }
+            wait_for_event(data.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    /// Register the files used by `IOSQE_FIXED_FILE`, `None` for the
+    /// empty slots.
+    pub fn register_files(&self, files: Vec<Option<Arc<dyn FileLike>>>) -> LxResult {
+        let mut inner = self.data.inner.lock();
+        if inner.files.is_some() {
+            return Err(LxError::EBUSY);
+        }
+        inner.files = Some(files);
+        Ok(())
+    }
+
+    /// Unregister the files used by `IOSQE_FIXED_FILE`.
+    pub fn unregister_files(&self) -> LxResult {
+        self.data
+            .inner
+            .lock()
+            .files
+            .take()
+            .map(|_| ())
+            .ok_or(LxError::ENXIO)
+    }
+
+    /// Register an eventfd to notify of the completions.
+    pub fn register_eventfd(&self, eventfd: Arc<dyn FileLike>) -> LxResult {
+        let mut inner = self.data.inner.lock();
+        if inner.eventfd.is_some() {
+            return Err(LxError::EBUSY);
+        }
+        inner.eventfd = Some(eventfd);
+        Ok(())
+    }
+
+    /// Unregister the eventfd notified of the completions.
+    pub fn unregister_eventfd(&self) -> LxResult {
+        self.data
+            .inner
+            .lock()
+            .eventfd
+            .take()
+            .map(|_| ())
+            .ok_or(LxError::ENXIO)
+    }
+
+    /// Returns `struct io_uring_probe` with `nr_ops` entries of the
+    /// operations, telling which ones are supported.
+    pub fn probe(nr_ops: usize) -> Vec<u8> {
+        const IO_URING_OP_SUPPORTED: u16 = 1;
+        let last_op = *SUPPORTED_OPS.iter().max().unwrap();
+        let ops_len = nr_ops.min(last_op as usize + 1);
+        let mut probe = vec![0u8; 16 + 8 * ops_len];
+        probe[0] = last_op;
+        probe[1] = ops_len as u8;
+        for op in 0..ops_len {
+            let entry = &mut probe[16 + 8 * op..16 + 8 * (op + 1)];
+            entry[0] = op as u8;
+            if SUPPORTED_OPS.contains(&(op as u8)) {
+                entry[2..4].copy_from_slice(&IO_URING_OP_SUPPORTED.to_ne_bytes());
+            }
+        }
+        probe
+    }
+
+    fn poll_status(&self) -> LxResult<PollStatus> {
+        let data = &self.data;
+        let inner = data.inner.lock();
+        Ok(PollStatus {
+            read: data.ready(&inner)? > 0,
+            write: false,
+            error: false,
+        })
+    }
+}
+
+impl IoUringData {
+    fn read_u32(&self, vmo: &VmObject, offset: usize) -> LxResult<u32> {
+        let mut buf = [0u8; 4];
+        vmo.read(offset, &mut buf)?;
+        Ok(u32::from_ne_bytes(buf))
+    }
+
+    fn write_u32(&self, vmo: &VmObject, offset: usize, value: u32) -> LxResult {
+        vmo.write(offset, &value.to_ne_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the number of completions in the completion queue.
+    fn ready(&self, inner: &IoUringInner) -> LxResult<usize> {
+        let head = self.read_u32(&self.cq_ring, CQ_HEAD)?;
+        Ok(inner.cq_tail.wrapping_sub(head) as usize)
+    }
+
+    /// Move the completions of the backlog to the completion queue, as many
+    /// as there is room for.
+    fn flush(&self, inner: &mut IoUringInner) -> LxResult {
+        let mut posted = false;
+        while let Some(&(user_data, res)) = inner.backlog.front() {
+            if self.ready(inner)? >= self.cq_entries as usize {
+                break;
+            }
+            let slot = (inner.cq_tail & (self.cq_entries - 1)) as usize;
+            let mut cqe = [0u8; CQE_SIZE];
+            cqe[..8].copy_from_slice(&user_data.to_ne_bytes());
+            cqe[8..12].copy_from_slice(&res.to_ne_bytes());
+            self.cq_ring.write(CQ_CQES + CQE_SIZE * slot, &cqe)?;
+            inner.cq_tail = inner.cq_tail.wrapping_add(1);
+            self.write_u32(&self.cq_ring, CQ_TAIL, inner.cq_tail)?;
+            inner.backlog.pop_front();
+            posted = true;
+        }
+        if posted {
+            self.eventbus
+                .lock()
+                .change_notify(Event::empty(), Event::READABLE);
+            if let Some(eventfd) = &inner.eventfd {
+                eventfd.write(&1u64.to_ne_bytes()).ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Post the completion of an operation.
+    fn complete(&self, user_data: u64, result: LxResult<usize>) {
+        let res = match result {
+            Ok(n) => n as i32,
+            Err(e) => -(e as i32),
+        };
+        let mut inner = self.inner.lock();
+        inner.backlog.push_back((user_data, res));
+        inner.completed += 1;
+        // the timeouts waiting for this number of completions
+        let completed = inner.completed;
+        let mut i = 0;
+        while i < inner.timeouts.len() {
+            if inner.timeouts[i].target <= completed {
+                let timeout = inner.timeouts.remove(i);
+                inner.backlog.push_back((timeout.user_data, 0));
+            } else {
+                i += 1;
+            }
+        }
+        if let Err(e) = self.flush(&mut inner) {
+            warn!("io_uring: failed to post a completion: {:?}", e);
+        }
+    }
+
+    /// Start the operation of `sqe`.
+    fn start(self: &Arc<Self>, sqe: Sqe) {
+        if !(sqe.flags - SqeFlags::FIXED_FILE - SqeFlags::ASYNC).is_empty() {
+            self.complete(sqe.user_data, Err(LxError::EINVAL));
+            return;
+        }
+        match sqe.opcode {
+            IORING_OP_NOP => self.complete(sqe.user_data, Ok(0)),
+            IORING_OP_TIMEOUT => {
+                if let Err(e) = self.start_timeout(&sqe) {
+                    self.complete(sqe.user_data, Err(e));
+                }
+            }
+            IORING_OP_READ | IORING_OP_WRITE | IORING_OP_ACCEPT | IORING_OP_SEND
+            | IORING_OP_RECV => {
+                let (proc, file) = match self.process() {
+                    Ok(proc) => match self.file(&proc, &sqe) {
+                        Ok(file) => (proc, file),
+                        Err(e) => return self.complete(sqe.user_data, Err(e)),
+                    },
+                    Err(e) => return self.complete(sqe.user_data, Err(e)),
+                };
+                let cancel = Arc::new(OpCancel::default());
+                let id = {
+                    let mut inner = self.inner.lock();
+                    let id = inner.next_op_id;
+                    inner.next_op_id += 1;
+                    inner.running.insert(id, cancel.clone());
+                    id
+                };
+                let data = Arc::downgrade(self);
+                thread::spawn(async move {
+                    let result = CancelableFuture {
+                        cancel: &cancel,
+                        future: Box::pin(execute(&proc, &file, &sqe)),
+                    }
+                    .await;
+                    // released before the completion is seen
+                    drop((proc, file));
+                    if let Some(data) = data.upgrade() {
+                        data.inner.lock().running.remove(&id);
+                        data.complete(sqe.user_data, result);
+                    }
+                });
+            }
+            _ => self.complete(sqe.user_data, Err(LxError::EINVAL)),
+        }
+    }
+
+    /// Returns the process of the operations, `ECANCELED` once it exits.
+    fn process(&self) -> LxResult<Arc<Process>> {
+        self.proc
+            .upgrade()
+            .filter(|proc| !matches!(proc.status(), Status::Exited(_)))
+            .ok_or(LxError::ECANCELED)
+    }
+
+    /// Cancel the running operations with `ECANCELED`, which then release
+    /// the process and the files they hold.
+    fn cancel_all(&self) {
+        let running = core::mem::take(&mut self.inner.lock().running);
+        for cancel in running.values() {
+            cancel.cancel();
+        }
+    }
+
+    /// Returns the file of `sqe`, registered or in the file table.
+    fn file(&self, proc: &Arc<Process>, sqe: &Sqe) -> LxResult<Arc<dyn FileLike>> {
+        if !sqe.flags.contains(SqeFlags::FIXED_FILE) {
+            return proc.linux().get_file_like(FileDesc::from(sqe.fd));
+        }
+        let inner = self.inner.lock();
+        let files = inner.files.as_ref().ok_or(LxError::EBADF)?;
+        files
+            .get(sqe.fd as usize)
+            .cloned()
+            .flatten()
+            .ok_or(LxError::EBADF)
+    }
+
+    /// Start `IORING_OP_TIMEOUT`, completed with `ETIME` at the deadline, or
+    /// with 0 once there are as many completions as `off` if not zero.
+    fn start_timeout(self: &Arc<Self>, sqe: &Sqe) -> LxResult {
+        if sqe.len != 1 || sqe.op_flags & !IORING_TIMEOUT_ABS != 0 {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.process()?;
+        let mut buf = [0u8; 16];
+        proc.vmar().read_memory(sqe.addr as usize, &mut buf)?;
+        let sec = i64::from_ne_bytes(buf[..8].try_into().unwrap());
+        let nsec = i64::from_ne_bytes(buf[8..].try_into().unwrap());
+        if sec < 0 || !(0..1_000_000_000).contains(&nsec) {
+            return Err(LxError::EINVAL);
+        }
+        let value = Duration::new(sec as u64, nsec as u32);
+        let deadline = if sqe.op_flags & IORING_TIMEOUT_ABS != 0 {
+            value
+        } else {
+            timer::deadline_after(value)
+        };
+        let mut inner = self.inner.lock();
+        let id = inner.next_timeout_id;
+        inner.next_timeout_id += 1;
+        let target = inner.completed + sqe.off;
+        inner.timeouts.push(CountTimeout {
+            id,
+            target: if sqe.off == 0 { u64::MAX } else { target },
+            user_data: sqe.user_data,
+        });
+        let data = Arc::downgrade(self);
+        thread::spawn(async move {
+            thread::sleep_until(deadline).await;
+            if let Some(data) = data.upgrade() {
+                let mut inner = data.inner.lock();
+                if let Some(i) = inner.timeouts.iter().position(|t| t.id == id) {
+                    let timeout = inner.timeouts.remove(i);
+                    let res = -(LxError::ETIME as i32);
+                    inner.backlog.push_back((timeout.user_data, res));
+                    if let Err(e) = data.flush(&mut inner) {
+                        warn!("io_uring: failed to post a completion: {:?}", e);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// The operations still running are canceled once the io_uring is closed.
+impl Drop for IoUringData {
+    fn drop(&mut self) {
+        self.cancel_all();
+    }
+}
+
+/// Run the operation of `sqe` on `file`, with the memory of `proc`.
+///
+/// The buffers are accessed by the address space of `proc`, as the task may
+/// run in any other process.
+async fn execute(proc: &Arc<Process>, file: &Arc<dyn FileLike>, sqe: &Sqe) -> LxResult<usize> {
+    let vmar = proc.vmar();
+    let addr = sqe.addr as usize;
+    let len = sqe.len as usize;
+    match sqe.opcode {
+        IORING_OP_READ => {
+            let mut buf = vec![0u8; len];
+            let len = if sqe.off == u64::MAX {
+                file.read(&mut buf).await?
+            } else {
+                file.read_at(sqe.off, &mut buf).await?
+            };
+            vmar.write_memory(addr, &buf[..len])?;
+            Ok(len)
+        }
+        IORING_OP_WRITE => {
+            let mut buf = vec![0u8; len];
+            vmar.read_memory(addr, &mut buf)?;
+            if sqe.off == u64::MAX {
+                file.write(&buf)
+            } else {
+                file.write_at(sqe.off, &buf)
+            }
+        }
+        IORING_OP_RECV => {
+            let mut buf = vec![0u8; len];
+            let flags = MsgFlags::from_bits_truncate(sqe.op_flags as usize);
+            let (result, _) = file.as_socket()?.read(&mut buf, flags).await;
+            let len = result?;
+            vmar.write_memory(addr, &buf[..len])?;
+            Ok(len)
+        }
+        IORING_OP_SEND => {
+            let mut buf = vec![0u8; len];
+            vmar.read_memory(addr, &mut buf)?;
+            file.as_socket()?.write(&buf, None)
+        }
+        IORING_OP_ACCEPT => {
+            let flags = OpenFlags::from_bits(sqe.op_flags as usize).ok_or(LxError::EINVAL)?;
+            if !(flags - OpenFlags::NON_BLOCK - OpenFlags::CLOEXEC).is_empty() {
+                return Err(LxError::EINVAL);
+            }
+            let (socket, endpoint) = file.as_socket()?.accept().await?;
+            socket.set_flags(socket.flags() | flags)?;
+            let fd = proc.linux().add_socket(socket)?;
+            // the address and its length, at `off`, like `accept4`
+            if addr != 0 {
+                let sockaddr = SockAddr::from(endpoint);
+                let bytes = sockaddr.as_bytes()?;
+                let mut addrlen = [0u8; 4];
+                vmar.read_memory(sqe.off as usize, &mut addrlen)?;
+                let max_len = u32::from_ne_bytes(addrlen) as usize;
+                vmar.write_memory(addr, &bytes[..bytes.len().min(max_len)])?;
+                vmar.write_memory(sqe.off as usize, &(bytes.len() as u32).to_ne_bytes())?;
+            }
+            Ok(fd.into())
+        }
+        _ => Err(LxError::EINVAL),
+    }
+}
+
+#[async_trait]
+impl FileLike for IoUring {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(IoUring {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            data: self.data.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        self.poll_status()
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) {
+            self.wait(1).await?;
+        }
+        self.poll_status()
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.data.eventbus.lock().subscribe_once(callback);
+        true
+    }
+
+    /// Map one of the rings, chosen by `offset`.
+    fn get_vmo(&self, offset: usize, len: usize, _shared: bool) -> LxResult<Arc<VmObject>> {
+        let vmo = match offset {
+            IORING_OFF_SQ_RING => &self.data.sq_ring,
+            IORING_OFF_CQ_RING => &self.data.cq_ring,
+            IORING_OFF_SQES => &self.data.sqes,
+            _ => return Err(LxError::EINVAL),
+        };
+        if len > vmo.len() {
+            return Err(LxError::EINVAL);
+        }
+        Ok(vmo.clone())
+    }
+}
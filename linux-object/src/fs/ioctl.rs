@@ -9,6 +9,18 @@ pub const TCGETS: usize = 0x5401;
 #[cfg(target_arch = "mips")]
 pub const TCGETS: usize = 0x540D;
 
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TIOCSCTTY: usize = 0x540E;
+pub const FIONREAD: usize = 0x541B;
+pub const TIOCSWINSZ: usize = 0x5414;
+pub const TIOCNOTTY: usize = 0x5422;
+// _IOR('T', 0x30, unsigned int)
+pub const TIOCGPTN: usize = 0x8004_5430;
+// _IOW('T', 0x31, int)
+pub const TIOCSPTLCK: usize = 0x4004_5431;
+
 #[cfg(not(target_arch = "mips"))]
 pub const TIOCGPGRP: usize = 0x540F;
 // _IOR('t', 119, int)
@@ -0,0 +1,360 @@
+//! Advisory file locks
+//!
+//! - POSIX record locks of `fcntl`, owned by processes and released when the
+//!   owner closes any descriptor of the file or exits
+//! - `flock` locks, owned by open file descriptions and released when the
+//!   last descriptor of the description is closed
+//!
+//! A blocked record lock which would wait for itself through the owners of
+//! the conflicting locks fails with `EDEADLK` instead.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use lock::Mutex;
+use rcore_fs::vfs::INode;
+use zircon_object::object::KoID;
+
+use super::page_cache::inode_key;
+use crate::error::{LxError, LxResult};
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// A read lock, shared with other read locks
+    Shared,
+    /// A write lock, conflicting with any other lock
+    Exclusive,
+}
+
+/// A POSIX record lock on the bytes `start..end` of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLock {
+    /// The process holding the lock
+    pub owner: KoID,
+    /// The kind of the lock
+    pub kind: LockKind,
+    /// The first byte locked
+    pub start: u64,
+    /// The byte after the last one locked, `u64::MAX` up to the end of file
+    pub end: u64,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    fn conflicts(&self, other: &RecordLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other.start, other.end)
+            && (self.kind == LockKind::Exclusive || other.kind == LockKind::Exclusive)
+    }
+}
+
+type FileKey = (usize, usize);
+
+#[derive(Default)]
+struct LockTable {
+    /// Record locks of each file
+    records: BTreeMap<FileKey, Vec<RecordLock>>,
+    /// The owner of each blocked thread, and the owner of the conflicting
+    /// lock it waits for
+    blocked_on: BTreeMap<KoID, (KoID, KoID)>,
+    /// `flock` locks of each file, with the open file description holding it
+    flocks: BTreeMap<FileKey, Vec<(usize, LockKind)>>,
+    /// Blocked requests, woken to retry on any release
+    waiters: Vec<Waker>,
+}
+
+lazy_static::lazy_static! {
+    static ref LOCKS: Mutex<LockTable> = Mutex::new(LockTable::default());
+}
+
+impl LockTable {
+    fn record_conflict(&self, key: FileKey, lock: &RecordLock) -> Option<RecordLock> {
+        self.records
+            .get(&key)?
+            .iter()
+            .find(|l| l.conflicts(lock))
+            .cloned()
+    }
+
+    /// Whether the owner waiting for `holder` would wait for itself, through
+    /// the owners which the threads of each holder wait for.
+    fn would_deadlock(&self, owner: KoID, holder: KoID) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![holder];
+        while let Some(current) = pending.pop() {
+            if current == owner {
+                return true;
+            }
+            if visited.insert(current) {
+                let next = self.blocked_on.values().filter(|(o, _)| *o == current);
+                pending.extend(next.map(|&(_, holder)| holder));
+            }
+        }
+        false
+    }
+
+    /// Lock the range of `lock` or unlock it if `kind` is `None`, replacing
+    /// the locks of the same owner in the range.
+    fn set_record(&mut self, key: FileKey, lock: RecordLock, kind: Option<LockKind>) {
+        let old = self.records.remove(&key).unwrap_or_default();
+        let mut locks = Vec::with_capacity(old.len() + 1);
+        for l in old {
+            if l.owner != lock.owner || !l.overlaps(lock.start, lock.end) {
+                locks.push(l);
+                continue;
+            }
+            if l.start < lock.start {
+                locks.push(RecordLock {
+                    end: lock.start,
+                    ..l
+                });
+            }
+            if l.end > lock.end {
+                locks.push(RecordLock {
+                    start: lock.end,
+                    ..l
+                });
+            }
+        }
+        if let Some(kind) = kind {
+            // merge with the adjacent locks of the same kind
+            let (mut start, mut end) = (lock.start, lock.end);
+            locks.retain(|l| {
+                let merge =
+                    l.owner == lock.owner && l.kind == kind && l.start <= end && start <= l.end;
+                if merge {
+                    start = start.min(l.start);
+                    end = end.max(l.end);
+                }
+                !merge
+            });
+            locks.push(RecordLock {
+                kind,
+                start,
+                end,
+                ..lock
+            });
+        }
+        if !locks.is_empty() {
+            self.records.insert(key, locks);
+        }
+        self.wake_all();
+    }
+
+    fn flock_conflict(&self, key: FileKey, id: usize, kind: LockKind) -> bool {
+        self.flocks.get(&key).map_or(false, |locks| {
+            locks.iter().any(|&(other, other_kind)| {
+                other != id && (kind == LockKind::Exclusive || other_kind == LockKind::Exclusive)
+            })
+        })
+    }
+
+    fn set_flock(&mut self, key: FileKey, id: usize, kind: Option<LockKind>) {
+        let locks = self.flocks.entry(key).or_default();
+        let len = locks.len();
+        locks.retain(|&(other, _)| other != id);
+        let released = locks.len() != len;
+        if let Some(kind) = kind {
+            locks.push((id, kind));
+        } else if locks.is_empty() {
+            self.flocks.remove(&key);
+        }
+        if released {
+            self.wake_all();
+        }
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A lock request, waiting for the conflicting locks to be released.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct LockFuture {
+    key: FileKey,
+    request: Request,
+    wait: bool,
+}
+
+enum Request {
+    /// A record lock requested by a thread of its owner
+    Record(RecordLock, KoID),
+    Flock(usize, LockKind),
+}
+
+impl Future for LockFuture {
+    type Output = LxResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut table = LOCKS.lock();
+        match self.request {
+            Request::Record(lock, thread) => match table.record_conflict(self.key, &lock) {
+                None => {
+                    table.blocked_on.remove(&thread);
+                    table.set_record(self.key, lock, Some(lock.kind));
+                    return Poll::Ready(Ok(()));
+                }
+                Some(_) if !self.wait => return Poll::Ready(Err(LxError::EAGAIN)),
+                Some(holder) => {
+                    if table.would_deadlock(lock.owner, holder.owner) {
+                        table.blocked_on.remove(&thread);
+                        return Poll::Ready(Err(LxError::EDEADLK));
+                    }
+                    table.blocked_on.insert(thread, (lock.owner, holder.owner));
+                }
+            },
+            Request::Flock(id, kind) => {
+                if !table.flock_conflict(self.key, id, kind) {
+                    table.set_flock(self.key, id, Some(kind));
+                    return Poll::Ready(Ok(()));
+                } else if !self.wait {
+                    return Poll::Ready(Err(LxError::EAGAIN));
+                }
+                // a lock being converted is released before waiting
+                table.set_flock(self.key, id, None);
+            }
+        }
+        table.waiters.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for LockFuture {
+    fn drop(&mut self) {
+        // the waiting thread may have been killed
+        if let (Request::Record(_, thread), true) = (&self.request, self.wait) {
+            LOCKS.lock().blocked_on.remove(thread);
+        }
+    }
+}
+
+/// Returns the first lock of others conflicting with `lock` on `inode`.
+pub fn get_record_lock(inode: &dyn INode, lock: &RecordLock) -> LxResult<Option<RecordLock>> {
+    let key = inode_key(inode)?;
+    Ok(LOCKS.lock().record_conflict(key, lock))
+}
+
+/// Place `lock` on `inode` for the thread `thread` of its owner, or unlock
+/// its range if `kind` is `None`.
+///
+/// Fails with `EAGAIN` on a conflicting lock, unless `wait` is set to wait
+/// for it to be released.
+pub async fn set_record_lock(
+    inode: &dyn INode,
+    lock: RecordLock,
+    kind: Option<LockKind>,
+    wait: bool,
+    thread: KoID,
+) -> LxResult {
+    let key = inode_key(inode)?;
+    match kind {
+        Some(kind) => {
+            let lock = RecordLock { kind, ..lock };
+            LockFuture {
+                key,
+                request: Request::Record(lock, thread),
+                wait,
+            }
+            .await
+        }
+        None => {
+            LOCKS.lock().set_record(key, lock, None);
+            Ok(())
+        }
+    }
+}
+
+/// Release the record locks of `owner` on `inode`, when it closes a
+/// descriptor of the file.
+pub fn release_record_locks(inode: &dyn INode, owner: KoID) {
+    if let Ok(key) = inode_key(inode) {
+        let mut table = LOCKS.lock();
+        if let Some(locks) = table.records.get_mut(&key) {
+            locks.retain(|l| l.owner != owner);
+            if locks.is_empty() {
+                table.records.remove(&key);
+            }
+            table.wake_all();
+        }
+    }
+}
+
+/// Release all record locks of `owner`, when it exits.
+pub fn release_all_record_locks(owner: KoID) {
+    let mut table = LOCKS.lock();
+    table.records.retain(|_, locks| {
+        locks.retain(|l| l.owner != owner);
+        !locks.is_empty()
+    });
+    table.blocked_on.retain(|_, (o, _)| *o != owner);
+    table.wake_all();
+}
+
+/// Place a `flock` lock of the open file description `id` on `inode`,
+/// converting its existing lock, or unlock it if `kind` is `None`.
+pub(super) async fn set_flock(
+    inode: &dyn INode,
+    id: usize,
+    kind: Option<LockKind>,
+    wait: bool,
+) -> LxResult {
+    let key = inode_key(inode)?;
+    match kind {
+        Some(kind) => {
+            LockFuture {
+                key,
+                request: Request::Flock(id, kind),
+                wait,
+            }
+            .await
+        }
+        None => {
+            LOCKS.lock().set_flock(key, id, None);
+            Ok(())
+        }
+    }
+}
+
+/// The owner of `flock` locks, shared by an open file and its duplicates, and
+/// releasing the lock when the last of them is closed.
+pub(super) struct FlockOwner;
+
+impl FlockOwner {
+    pub(super) fn id(self: &Arc<Self>) -> usize {
+        Arc::as_ptr(self) as usize
+    }
+}
+
+impl Drop for FlockOwner {
+    fn drop(&mut self) {
+        release_flock(self as *const _ as usize);
+    }
+}
+
+fn release_flock(id: usize) {
+    let mut table = LOCKS.lock();
+    let mut released = false;
+    table.flocks.retain(|_, locks| {
+        let len = locks.len();
+        locks.retain(|&(other, _)| other != id);
+        released |= locks.len() != len;
+        !locks.is_empty()
+    });
+    if released {
+        table.wake_all();
+    }
+}
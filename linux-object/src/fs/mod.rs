@@ -1,40 +1,84 @@
 //! Linux file objects
 
+mod description;
 mod devfs;
+mod epoll;
+mod eventfd;
+mod ext4;
+mod fat;
 mod file;
+mod inotify;
+mod io_uring;
 mod ioctl;
+mod lock;
+mod mount;
+mod overlay;
+mod page_cache;
 mod pipe;
+mod procfs;
 mod pseudo;
+mod pty;
+mod signalfd;
 mod stdio;
+mod sysfs;
+mod timerfd;
+mod tmpfs;
+mod tty;
+mod xattr;
 
 pub mod rcore_fs_wrapper;
 
-use alloc::{boxed::Box, string::ToString, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use core::convert::TryFrom;
 
 use async_trait::async_trait;
 use downcast_rs::impl_downcast;
 
-use kernel_hal::drivers;
-use rcore_fs::vfs::{FileSystem, FileType, INode, Result};
-use rcore_fs_devfs::{
-    special::{NullINode, ZeroINode},
-    DevFS,
-};
-use rcore_fs_mountfs::MountFS;
-use rcore_fs_ramfs::RamFS;
+use rcore_fs::vfs::{FileSystem, FileType, FsError, INode, Result};
+use rcore_fs_devfs::DevFS;
+use rcore_fs_mountfs::MNode;
 use zircon_object::{object::KernelObject, vm::VmObject};
 
+use crate::cred::{Capabilities, S_ISGID, S_ISVTX, W_OK, X_OK};
 use crate::error::{LxError, LxResult};
 use crate::net::Socket;
+use crate::pid_ns::PidNamespace;
 use crate::process::LinuxProcess;
-use devfs::RandomINode;
 use pseudo::Pseudo;
 
+pub use description::OpenFileDescription;
+pub use devfs::{syslog_level, Kmsg};
+pub use epoll::{EpollCtlOp, EpollEvent, EpollEvents, EpollInstance};
+pub use eventfd::{EventFd, EventFdFlags};
+pub use ext4::{Ext4FileSystem, Ext4INode};
+pub use fat::{FatFileSystem, FatINode};
 pub use file::{File, OpenFlags, PollEvents, SeekFrom};
-pub use pipe::Pipe;
+pub use inotify::{inotify_cookie, inotify_event, Inotify, InotifyFlags, InotifyMask};
+pub use io_uring::{
+    IoUring, IoUringEnterFlags, IoUringParams, IoUringSetupFlags, IORING_OFF_CQ_RING,
+    IORING_OFF_SQES, IORING_OFF_SQ_RING,
+};
+pub use lock::{
+    get_record_lock, release_all_record_locks, release_record_locks, set_record_lock, LockKind,
+    RecordLock,
+};
+pub use mount::{Mount, MountFlags, MountNamespace};
+pub use overlay::{OverlayFS, OverlayINode};
+pub use page_cache::{resize_inode, sync_inode, PageCache};
+pub use pipe::{Pipe, PIPE_BUF, PIPE_DEFAULT_SIZE, PIPE_MAX_SIZE};
+pub use procfs::ProcFS;
+pub use pty::{Ptmx, PtsDir, PtyMaster, PtySlave};
 pub use rcore_fs::vfs::{self, PollStatus};
-pub use stdio::{STDIN, STDOUT};
+pub use signalfd::{SignalFd, SignalFdFlags, SignalFdSigInfo};
+pub use stdio::{ConsoleTty, STDIN, STDOUT};
+pub use sysfs::SysFS;
+pub use timerfd::{TimerFd, TimerFdFlags, TimerFdSetFlags};
+pub use tmpfs::{TmpFS, TmpINode};
+pub use tty::{InputFlags, LocalFlags, OutputFlags, Termios, TtyForeground, WinSize};
+pub use xattr::{
+    drop_xattrs, get_xattr, list_xattr, remove_xattr, set_xattr, XattrFlags, XattrNamespace,
+    XATTR_NAME_MAX, XATTR_SIZE_MAX,
+};
 
 #[async_trait]
 /// Generic file interface
@@ -42,6 +86,7 @@ pub use stdio::{STDIN, STDOUT};
 /// - Normal file, Directory
 /// - Socket
 /// - Epoll instance
+/// - eventfd, signalfd, timerfd, inotify
 pub trait FileLike: KernelObject {
     /// Returns open flags.
     fn flags(&self) -> OpenFlags;
@@ -51,6 +96,11 @@ pub trait FileLike: KernelObject {
     fn dup(&self) -> Arc<dyn FileLike> {
         unimplemented!()
     }
+    /// The open file description shared with the duplicates made by
+    /// [`dup`](Self::dup), `None` if the file has no duplicates.
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        None
+    }
     /// read to buffer
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize>;
     /// write from buffer
@@ -65,12 +115,29 @@ pub trait FileLike: KernelObject {
     fn poll(&self, events: PollEvents) -> LxResult<PollStatus>;
     /// wait for some event on a file descriptor use async
     async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus>;
+    /// Whether the other end hung up, reported as `POLLHUP` beside the
+    /// status of [`poll`](Self::poll)
+    fn hung_up(&self) -> bool {
+        false
+    }
+    /// Call `callback` once on the next change of the state of the file,
+    /// though it stays ready, e.g. more data arriving, as edge-triggered
+    /// `epoll` needs.
+    ///
+    /// Returns `false` if the changes are not told, where the waiters rely
+    /// on [`async_poll`](Self::async_poll) only.
+    fn on_change(&self, _callback: ChangeCallback) -> bool {
+        false
+    }
     /// manipulates the underlying device parameters of special files
     fn ioctl(&self, _request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
         Err(LxError::ENOSYS)
     }
     /// Returns the [`VmObject`] representing the file with given `offset` and `len`.
-    fn get_vmo(&self, _offset: usize, _len: usize) -> LxResult<Arc<VmObject>> {
+    ///
+    /// If `shared` is true, writes to the VMO are carried through to the file,
+    /// as `MAP_SHARED` does.
+    fn get_vmo(&self, _offset: usize, _len: usize, _shared: bool) -> LxResult<Arc<VmObject>> {
         Err(LxError::ENOSYS)
     }
     /// Casting between trait objects, or use crate: cast_trait_object
@@ -81,6 +148,9 @@ pub trait FileLike: KernelObject {
 
 impl_downcast!(sync FileLike);
 
+/// A callback of [`FileLike::on_change`]
+pub type ChangeCallback = Box<dyn Fn() + Send + Sync>;
+
 /// file descriptor wrapper
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct FileDesc(i32);
@@ -122,82 +192,91 @@ impl From<FileDesc> for i32 {
     }
 }
 
-/// create root filesystem, mount DevFS and RamFS
-pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
-    let rootfs = MountFS::new(rootfs);
-    let root = rootfs.mountpoint_root_inode();
+/// create the initial mount namespace on the root filesystem, mount DevFS,
+/// ProcFS of `pid_ns`, SysFS, and TmpFS at /tmp and /dev/shm
+pub fn create_root_fs(
+    rootfs: Arc<dyn FileSystem>,
+    pid_ns: &Arc<PidNamespace>,
+) -> Arc<MountNamespace> {
+    let ns = MountNamespace::new(rootfs, "rootfs");
 
-    // create DevFS
+    // create DevFS, populated from the kernel's device registry
+    let devices = devfs::device_nodes();
     let devfs = DevFS::new();
     let devfs_root = devfs.root();
-    devfs_root
-        .add("null", Arc::new(NullINode::new()))
-        .expect("failed to mknod /dev/null");
-    devfs_root
-        .add("zero", Arc::new(ZeroINode::new()))
-        .expect("failed to mknod /dev/zero");
-    devfs_root
-        .add("random", Arc::new(RandomINode::new(false)))
-        .expect("failed to mknod /dev/random");
-    devfs_root
-        .add("urandom", Arc::new(RandomINode::new(true)))
-        .expect("failed to mknod /dev/urandom");
-    devfs_root
-        .add("shm", Arc::new(RandomINode::new(true)))
-        .expect("failed to mknod /dev/shm");
-    if let Some(display) = drivers::all_display().first() {
-        use devfs::{EventDev, FbDev, MiceDev};
-
-        // Add framebuffer device at `/dev/fb0`
-        if let Err(e) = devfs_root.add("fb0", Arc::new(FbDev::new(display.clone()))) {
-            warn!("failed to mknod /dev/fb0: {:?}", e);
+    let mut subdirs = BTreeMap::new();
+    for dev in devices.iter() {
+        let result = match dev.path.split_once('/') {
+            Some((dir, name)) => subdirs
+                .entry(dir)
+                .or_insert_with(|| {
+                    devfs_root
+                        .add_dir(dir)
+                        .unwrap_or_else(|_| panic!("failed to mkdir /dev/{}", dir))
+                })
+                .add(name, dev.inode.clone()),
+            None => devfs_root.add(&dev.path, dev.inode.clone()),
+        };
+        if let Err(e) = result {
+            warn!("failed to mknod /dev/{}: {:?}", dev.path, e);
         }
+    }
+    devfs_root.add_dir("shm").expect("failed to mkdir /dev/shm");
+    devfs_root
+        .add("pts", Arc::new(PtsDir::new()))
+        .expect("failed to mkdir /dev/pts");
+
+    mount_at(&ns, "dev", "devtmpfs", devfs);
+    // POSIX shared memory objects are files in /dev/shm, shared by `mmap`
+    ns.mount(
+        "shm",
+        "/dev/shm",
+        "tmpfs",
+        TmpFS::new(),
+        MountFlags::NOSUID | MountFlags::NODEV,
+    )
+    .expect("failed to mount tmpfs at /dev/shm");
+    mount_at(&ns, "proc", "proc", ProcFS::new(pid_ns.clone()));
+    mount_at(&ns, "sys", "sysfs", SysFS::new(&devices));
+    mount_at(&ns, "tmp", "tmpfs", TmpFS::new());
+
+    // mount the boot partition at /boot, if there is one
+    if let Some(bootfs) = open_boot_fs() {
+        mount_at(&ns, "boot", "vfat", bootfs);
+    }
 
-        let input_dev = devfs_root
-            .add_dir("input")
-            .expect("failed to mkdir /dev/input");
-
-        // Add mouse devices at `/dev/input/mouseX` and `/dev/input/mice`
-        for (id, m) in MiceDev::from_input_devices(&drivers::all_input().as_vec()) {
-            let fname = id.map_or("mice".to_string(), |id| format!("mouse{}", id));
-            if let Err(e) = input_dev.add(&fname, Arc::new(m)) {
-                warn!("failed to mknod /dev/input/{}: {:?}", &fname, e);
-            }
-        }
+    ns
+}
 
-        // Add input event devices at `/dev/input/eventX`
-        for (id, i) in drivers::all_input().as_vec().iter().enumerate() {
-            let fname = format!("event{}", id);
-            if let Err(e) = input_dev.add(&fname, Arc::new(EventDev::new(i.clone(), id))) {
-                warn!("failed to mknod /dev/input/{}: {:?}", &fname, e);
-            }
-        }
+/// Mount `fs` at the directory `name` of the root, creating it if missing.
+fn mount_at(ns: &MountNamespace, name: &str, fstype: &str, fs: Arc<dyn FileSystem>) {
+    let root = ns.root_inode();
+    if root.find(name).is_err() {
+        root.create(name, FileType::Dir, 0o666)
+            .unwrap_or_else(|_| panic!("failed to mkdir /{}", name));
     }
+    let target = format!("/{}", name);
+    ns.mount(fstype, &target, fstype, fs, MountFlags::empty())
+        .unwrap_or_else(|_| panic!("failed to mount {} at {}", fstype, target));
+}
 
-    // Add uart devices at `/dev/ttyS{i}`
-    for (i, uart) in drivers::all_uart().as_vec().iter().enumerate() {
-        let fname = format!("ttyS{}", i);
-        if let Err(e) = devfs_root.add(&fname, Arc::new(devfs::UartDev::new(i, uart.clone()))) {
-            warn!("failed to mknod /dev/{}: {:?}", &fname, e);
+/// Open the first FAT partition on the block devices, which is where the
+/// firmware of real boards loads the kernel from.
+fn open_boot_fs() -> Option<Arc<dyn FileSystem>> {
+    use kernel_hal::drivers::{self, block::scan_partitions};
+    use rcore_fs_wrapper::{Block, BlockCache};
+
+    for disk in drivers::all_block().as_vec().iter() {
+        let partitions = scan_partitions(disk).unwrap_or_default();
+        for part in partitions.into_iter().filter(|p| p.part_type().is_fat()) {
+            let device = Arc::new(BlockCache::new(Block::new(part), 0x100));
+            match FatFileSystem::open(device) {
+                Ok(fs) => return Some(fs),
+                Err(e) => warn!("failed to open the FAT partition: {:?}", e),
+            }
         }
     }
-
-    // mount DevFS at /dev
-    let dev = root.find(true, "dev").unwrap_or_else(|_| {
-        root.create("dev", FileType::Dir, 0o666)
-            .expect("failed to mkdir /dev")
-    });
-    dev.mount(devfs).expect("failed to mount DevFS");
-
-    // mount RamFS at /tmp
-    let ramfs = RamFS::new();
-    let tmp = root.find(true, "tmp").unwrap_or_else(|_| {
-        root.create("tmp", FileType::Dir, 0o666)
-            .expect("failed to mkdir /tmp")
-    });
-    tmp.mount(ramfs).expect("failed to mount RamFS");
-
-    root
+    None
 }
 
 /// extension for INode
@@ -209,6 +288,8 @@ pub trait INodeExt {
 impl INodeExt for dyn INode {
     #[allow(unsafe_code, clippy::uninit_vec)]
     fn read_as_vec(&self) -> Result<Vec<u8>> {
+        // the file may be modified through shared mappings
+        sync_inode(self)?;
         let size = self.metadata()?.size;
         let mut buf = Vec::with_capacity(size);
         unsafe {
@@ -258,16 +339,41 @@ impl LinuxProcess {
             return Ok(Arc::new(Pseudo::new(file.path(), FileType::SymLink)));
         }
 
-        let follow_max_depth = if follow { FOLLOW_MAX_DEPTH } else { 0 };
-        if dirfd == FileDesc::CWD {
-            Ok(self
-                .root_inode()
-                .lookup(&self.current_working_directory())?
-                .lookup_follow(path, follow_max_depth)?)
+        let root = self.root_inode()?;
+        let (dir, dir_path) = if path.starts_with('/') {
+            (root.clone(), Vec::new())
+        } else if dirfd == FileDesc::CWD {
+            self.working_directory(&root)?
         } else {
-            let file = self.get_file(dirfd)?;
-            Ok(file.lookup_follow(path, follow_max_depth)?)
+            (self.get_file(dirfd)?.inode(), Vec::new())
+        };
+        Ok(resolve(&root, dir, dir_path, path, follow)?.0)
+    }
+
+    /// Lookup INode from the working directory, and returns its absolute
+    /// path in the mount namespace as well, without symbolic links.
+    pub fn lookup_ns_path(&self, path: &str, follow: bool) -> LxResult<(Arc<dyn INode>, String)> {
+        let root = self.root_inode()?;
+        let (dir, dir_path) = if path.starts_with('/') {
+            (root.clone(), Vec::new())
+        } else {
+            self.working_directory(&root)?
+        };
+        let (inode, names) = resolve(&root, dir, dir_path, path, follow)?;
+        let mut ns_path = self.root_path();
+        for name in names {
+            if !ns_path.ends_with('/') {
+                ns_path.push('/');
+            }
+            ns_path += &name;
         }
+        Ok((inode, ns_path))
+    }
+
+    /// Returns the working directory, and its names from the root directory.
+    fn working_directory(&self, root: &Arc<dyn INode>) -> LxResult<(Arc<dyn INode>, Vec<String>)> {
+        let cwd = self.current_working_directory();
+        resolve(root, root.clone(), Vec::new(), &cwd, true)
     }
 
     /// Lookup INode from the process.
@@ -276,6 +382,253 @@ impl LinuxProcess {
     pub fn lookup_inode(&self, path: &str) -> LxResult<Arc<dyn INode>> {
         self.lookup_inode_at(FileDesc::CWD, path, true)
     }
+
+    /// Open a file system of `fstype` to mount, on the block `device` if it
+    /// needs one.
+    ///
+    /// A ProcFS shows the processes of the PID namespace of the process.
+    pub fn open_fs(
+        &self,
+        fstype: &str,
+        device: Option<&Arc<dyn INode>>,
+    ) -> LxResult<Arc<dyn FileSystem>> {
+        use rcore_fs::dev::Device;
+        use rcore_fs_wrapper::{Block, BlockCache};
+
+        let block = || -> LxResult<Arc<dyn Device>> {
+            let inode = fs_inode(device.ok_or(LxError::ENOTBLK)?);
+            let dev = inode
+                .downcast_ref::<devfs::BlockDev>()
+                .ok_or(LxError::ENOTBLK)?;
+            Ok(Arc::new(BlockCache::new(Block::new(dev.block()), 0x100)))
+        };
+        let fs: Arc<dyn FileSystem> = match fstype {
+            "tmpfs" => TmpFS::new(),
+            "proc" => ProcFS::new(self.pid_ns()),
+            "sysfs" => SysFS::new(&devfs::device_nodes()),
+            "ext4" => Ext4FileSystem::open(block()?)?,
+            "vfat" | "msdos" => FatFileSystem::open(block()?)?,
+            _ => return Err(LxError::ENODEV),
+        };
+        Ok(fs)
+    }
+
+    /// Open an overlay to mount, of the directories given by the mount
+    /// `options` `lowerdir` and `upperdir`.
+    ///
+    /// The upper directory is a new tmpfs if not given, and only one lower
+    /// directory is supported.
+    pub fn open_overlay(&self, options: &str) -> LxResult<Arc<dyn FileSystem>> {
+        let mut lower = None;
+        let mut upper = None;
+        for option in options.split(',') {
+            match option.split_once('=') {
+                Some(("lowerdir", path)) if !path.contains(':') => {
+                    lower = Some(self.lookup_inode(path)?)
+                }
+                Some(("lowerdir", _)) => return Err(LxError::EINVAL),
+                Some(("upperdir", path)) => upper = Some(self.lookup_inode(path)?),
+                // `workdir` is not needed, as the copy-up is not atomic
+                _ => {}
+            }
+        }
+        let lower = lower.ok_or(LxError::EINVAL)?;
+        let upper = match upper {
+            Some(upper) => {
+                self.check_writable(&upper)?;
+                upper
+            }
+            None => TmpFS::new().root_inode(),
+        };
+        if lower.metadata()?.type_ != FileType::Dir || upper.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        Ok(OverlayFS::new(lower, upper))
+    }
+
+    /// Check the access `mask` to `inode`, some of `R_OK`, `W_OK` and `X_OK`.
+    pub fn check_access(&self, inode: &Arc<dyn INode>, mask: usize) -> LxResult {
+        let metadata = inode.metadata()?;
+        // the devices, pipes and sockets on a read-only mount stay writable
+        if mask & W_OK != 0
+            && matches!(
+                metadata.type_,
+                FileType::File | FileType::Dir | FileType::SymLink
+            )
+        {
+            self.check_writable(inode)?;
+        }
+        self.cred().check_access(&metadata, mask)
+    }
+
+    /// Check that `inode` is not on a read-only mount, before changing it.
+    pub fn check_writable(&self, inode: &Arc<dyn INode>) -> LxResult {
+        if self
+            .mount_ns()
+            .mount_flags(&**inode)
+            .contains(MountFlags::RDONLY)
+        {
+            return Err(LxError::EROFS);
+        }
+        Ok(())
+    }
+
+    /// Check whether `inode` can be unlinked or renamed from the directory `dir`.
+    ///
+    /// It requires the write and search permissions of the directory, and to
+    /// own the file or the directory if the directory is sticky.
+    pub fn check_remove(&self, dir: &Arc<dyn INode>, inode: &Arc<dyn INode>) -> LxResult {
+        let cred = self.cred();
+        let dir_info = dir.metadata()?;
+        cred.check_access(&dir_info, W_OK | X_OK)?;
+        if dir_info.mode & S_ISVTX != 0 {
+            let info = inode.metadata()?;
+            let fsuid = cred.fsuid as usize;
+            if fsuid != info.uid && fsuid != dir_info.uid && !cred.has_cap(Capabilities::FOWNER) {
+                return Err(LxError::EPERM);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create `name` in the directory `dir`, with `mode` masked by the umask,
+    /// owned by the filesystem user and group IDs of the process.
+    pub fn create_inode(
+        &self,
+        dir: &Arc<dyn INode>,
+        name: &str,
+        type_: FileType,
+        mode: u32,
+    ) -> LxResult<Arc<dyn INode>> {
+        self.create_node(dir, name, type_, mode, 0)
+    }
+
+    /// Like [`create_inode`](Self::create_inode), with the device number
+    /// `rdev` of a special file.
+    pub fn create_node(
+        &self,
+        dir: &Arc<dyn INode>,
+        name: &str,
+        type_: FileType,
+        mode: u32,
+        rdev: usize,
+    ) -> LxResult<Arc<dyn INode>> {
+        self.check_access(dir, W_OK | X_OK)?;
+        let inode = dir.create2(name, type_, mode & !self.umask(), rdev)?;
+        self.init_owner(dir, &inode)?;
+        let mut mask = InotifyMask::CREATE;
+        mask.set(InotifyMask::ISDIR, type_ == FileType::Dir);
+        inotify_event(&**dir, mask, 0, name);
+        Ok(inode)
+    }
+
+    /// Set the owner of `inode` newly created in the directory `dir`.
+    ///
+    /// The group is inherited from a set-group-ID directory, as well as the
+    /// set-group-ID bit by the subdirectories.
+    pub fn init_owner(&self, dir: &Arc<dyn INode>, inode: &Arc<dyn INode>) -> LxResult {
+        let cred = self.cred();
+        let dir_info = dir.metadata()?;
+        let mut info = inode.metadata()?;
+        let old = (info.uid, info.gid, info.mode);
+        info.uid = cred.fsuid as usize;
+        if dir_info.mode & S_ISGID != 0 {
+            info.gid = dir_info.gid;
+            if info.type_ == FileType::Dir {
+                info.mode |= S_ISGID;
+            }
+        } else {
+            info.gid = cred.fsgid as usize;
+        }
+        if (info.uid, info.gid, info.mode) == old {
+            return Ok(());
+        }
+        match inode.set_metadata(&info) {
+            // the file systems without owners
+            Ok(()) | Err(FsError::NotSupported) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Resolve `path` from the directory `dir`, whose names from the root
+/// directory `root` are `dir_path`, returns the INode and its names.
+///
+/// The symbolic links on the way are followed, and the last one as well if
+/// `follow` is set. An absolute path, or the target of a symbolic link,
+/// starts from `root`, and `..` of `root` is itself, so that a path never
+/// leaves the root directory of a process after `chroot`.
+fn resolve(
+    root: &Arc<dyn INode>,
+    mut dir: Arc<dyn INode>,
+    mut dir_path: Vec<String>,
+    path: &str,
+    follow: bool,
+) -> LxResult<(Arc<dyn INode>, Vec<String>)> {
+    // a trailing '/' always refers to a directory
+    let follow = follow || path.ends_with('/');
+    // the names left to resolve, the next one last
+    let mut names: Vec<String> = Vec::new();
+    let push_names = |names: &mut Vec<String>, path: &str| {
+        names.extend(path.rsplit('/').filter(|s| !s.is_empty()).map(String::from));
+    };
+    if path.starts_with('/') {
+        dir = root.clone();
+        dir_path.clear();
+    }
+    push_names(&mut names, path);
+    let mut links = 0;
+    while let Some(name) = names.pop() {
+        if dir.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                if !same_inode(&dir, root) {
+                    dir = dir.find("..")?;
+                    dir_path.pop();
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let inode = dir.find(&name)?;
+        if inode.metadata()?.type_ == FileType::SymLink && (follow || !names.is_empty()) {
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(LxError::ELOOP);
+            }
+            let target = String::from_utf8(inode.read_as_vec()?).map_err(|_| LxError::ENOENT)?;
+            if target.starts_with('/') {
+                dir = root.clone();
+                dir_path.clear();
+            }
+            push_names(&mut names, &target);
+        } else {
+            dir = inode;
+            dir_path.push(name);
+        }
+    }
+    Ok((dir, dir_path))
+}
+
+/// Whether `a` and `b` are the same file, on the same mount.
+fn same_inode(a: &Arc<dyn INode>, b: &Arc<dyn INode>) -> bool {
+    let id = |inode: &dyn INode| inode.metadata().map(|m| (m.dev, m.inode)).ok();
+    match (a.downcast_ref::<MNode>(), b.downcast_ref::<MNode>()) {
+        (Some(a), Some(b)) => Arc::ptr_eq(&a.vfs, &b.vfs) && id(&*a.inode) == id(&*b.inode),
+        (None, None) => id(&**a) == id(&**b),
+        _ => false,
+    }
+}
+
+/// Returns the INode of the underlying file system, looking through mount points.
+pub fn fs_inode(inode: &Arc<dyn INode>) -> Arc<dyn INode> {
+    match inode.downcast_ref::<MNode>() {
+        Some(mnode) => mnode.inode.clone(),
+        None => inode.clone(),
+    }
 }
 
 /// Split a `path` str to `(base_path, file_name)`
@@ -289,5 +642,5 @@ pub fn split_path(path: &str) -> (&str, &str) {
     (dir_path, file_name)
 }
 
-/// the max depth for following a link
-const FOLLOW_MAX_DEPTH: usize = 1;
+/// the max number of symbolic links followed in a path, as in Linux
+const MAX_SYMLINKS: usize = 40;
@@ -0,0 +1,367 @@
+//! Mount namespaces
+//!
+//! The mounts of a namespace are recorded in a table, from which its tree of
+//! [`MountFS`] is built. A copy of the namespace, or the namespace after an
+//! unmount, gets a new tree built from the table, so that the changes of one
+//! namespace are never seen by the others.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use lock::Mutex;
+use rcore_fs::vfs::{FileSystem, FileType, FsInfo, INode, Result};
+use rcore_fs_mountfs::{MNode, MountFS};
+
+use super::fs_inode;
+use crate::error::{LxError, LxResult};
+
+bitflags::bitflags! {
+    /// Flags of `mount`
+    pub struct MountFlags: usize {
+        /// mount read-only
+        const RDONLY = 1;
+        /// ignore the set-user-ID and set-group-ID bits
+        const NOSUID = 2;
+        /// disallow access to device special files
+        const NODEV = 4;
+        /// disallow program execution
+        const NOEXEC = 8;
+        /// writes are synced at once
+        const SYNCHRONOUS = 16;
+        /// alter the flags of a mounted file system
+        const REMOUNT = 32;
+        /// allow mandatory locks
+        const MANDLOCK = 64;
+        /// directory modifications are synchronous
+        const DIRSYNC = 128;
+        /// do not update the access times
+        const NOATIME = 1024;
+        /// do not update the access times of directories
+        const NODIRATIME = 2048;
+        /// make a bind mount
+        const BIND = 4096;
+        /// move a subtree
+        const MOVE = 8192;
+        /// recursive bind mount
+        const REC = 16384;
+        /// suppress some warnings
+        const SILENT = 32768;
+        /// make the mount private
+        const PRIVATE = 1 << 18;
+        /// make the mount a slave
+        const SLAVE = 1 << 19;
+        /// make the mount shared
+        const SHARED = 1 << 20;
+        /// update the access times relative to the modify times
+        const RELATIME = 1 << 21;
+        /// always update the access times
+        const STRICTATIME = 1 << 24;
+    }
+}
+
+/// The flags kept for a mount, and changed by remounting.
+const PER_MOUNT_FLAGS: MountFlags = MountFlags::from_bits_truncate(
+    MountFlags::RDONLY.bits()
+        | MountFlags::NOSUID.bits()
+        | MountFlags::NODEV.bits()
+        | MountFlags::NOEXEC.bits()
+        | MountFlags::NOATIME.bits()
+        | MountFlags::NODIRATIME.bits()
+        | MountFlags::RELATIME.bits(),
+);
+
+/// A file system mounted in a namespace.
+#[derive(Clone)]
+pub struct Mount {
+    /// The device or the directory mounted, as given to `mount`
+    pub source: String,
+    /// The absolute path of the mount point
+    pub target: String,
+    /// The type of the file system
+    pub fstype: String,
+    /// The flags of the mount
+    pub flags: MountFlags,
+    fs: Arc<dyn FileSystem>,
+    /// The file system in the tree of the namespace
+    vfs: Option<Arc<MountFS>>,
+}
+
+impl Mount {
+    /// Whether the mount is at `path` or under it.
+    fn is_under(&self, path: &str) -> bool {
+        Self::is_under_path(&self.target, path)
+    }
+
+    /// Whether the normalized `target` is `path` or under it.
+    fn is_under_path(target: &str, path: &str) -> bool {
+        path == "/"
+            || target == path
+            || (target.starts_with(path) && target.as_bytes()[path.len()] == b'/')
+    }
+}
+
+struct MountNsInner {
+    root: Arc<MNode>,
+    /// The mounts in the order mounted, the root file system first
+    mounts: Vec<Mount>,
+}
+
+/// A mount namespace, the view of the file systems shared by processes.
+pub struct MountNamespace {
+    inner: Mutex<MountNsInner>,
+}
+
+impl MountNamespace {
+    /// Create a namespace with `rootfs` mounted at `/`.
+    pub fn new(rootfs: Arc<dyn FileSystem>, fstype: &str) -> Arc<Self> {
+        let mut mounts = vec![Mount {
+            source: String::from("rootfs"),
+            target: String::from("/"),
+            fstype: String::from(fstype),
+            flags: MountFlags::empty(),
+            fs: rootfs,
+            vfs: None,
+        }];
+        let root = build_tree(&mut mounts).expect("failed to mount the root file system");
+        Arc::new(MountNamespace {
+            inner: Mutex::new(MountNsInner { root, mounts }),
+        })
+    }
+
+    /// Copy the namespace for `CLONE_NEWNS`, with the same mounts.
+    pub fn copy(&self) -> LxResult<Arc<Self>> {
+        let mut mounts = self.inner.lock().mounts.clone();
+        let root = build_tree(&mut mounts)?;
+        Ok(Arc::new(MountNamespace {
+            inner: Mutex::new(MountNsInner { root, mounts }),
+        }))
+    }
+
+    /// Returns the root directory of the namespace.
+    pub fn root_inode(&self) -> Arc<dyn INode> {
+        self.inner.lock().root.clone()
+    }
+
+    /// Mount `fs` at the absolute path `target`, which is a directory.
+    pub fn mount(
+        &self,
+        source: &str,
+        target: &str,
+        fstype: &str,
+        fs: Arc<dyn FileSystem>,
+        flags: MountFlags,
+    ) -> LxResult {
+        let target = normalize(target);
+        let mut inner = self.inner.lock();
+        let point = inner.root.lookup(&target)?;
+        if point.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        let point = point.downcast_ref::<MNode>().ok_or(LxError::EINVAL)?;
+        let vfs = point.mount(fs.clone())?;
+        inner.mounts.push(Mount {
+            source: String::from(source),
+            target,
+            fstype: String::from(fstype),
+            flags: flags & PER_MOUNT_FLAGS,
+            fs,
+            vfs: Some(vfs),
+        });
+        Ok(())
+    }
+
+    /// Mount the directory `source` at `target` as well.
+    pub fn bind(
+        &self,
+        source_path: &str,
+        source: &Arc<dyn INode>,
+        target: &str,
+        flags: MountFlags,
+    ) -> LxResult {
+        if source.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        let fs = Arc::new(BindFS {
+            root: fs_inode(source),
+        });
+        self.mount(&normalize(source_path), target, "none", fs, flags)
+    }
+
+    /// Change the flags of the latest mount at `target`.
+    pub fn remount(&self, target: &str, flags: MountFlags) -> LxResult {
+        let target = normalize(target);
+        let mut inner = self.inner.lock();
+        let mount = inner
+            .mounts
+            .iter_mut()
+            .rev()
+            .find(|m| m.target == target)
+            .ok_or(LxError::EINVAL)?;
+        mount.flags = flags & PER_MOUNT_FLAGS;
+        Ok(())
+    }
+
+    /// Unmount the latest mount at `target`.
+    ///
+    /// The mounts under it are unmounted as well if `detach` is set,
+    /// otherwise they keep it busy.
+    pub fn umount(&self, target: &str, detach: bool) -> LxResult {
+        let target = normalize(target);
+        let mut inner = self.inner.lock();
+        let index = inner
+            .mounts
+            .iter()
+            .rposition(|m| m.target == target)
+            .ok_or(LxError::EINVAL)?;
+        if index == 0 {
+            return Err(LxError::EBUSY);
+        }
+        let busy = inner.mounts[index + 1..]
+            .iter()
+            .any(|m| m.is_under(&target));
+        if busy && !detach {
+            return Err(LxError::EBUSY);
+        }
+        let mut mounts = inner.mounts[..index].to_vec();
+        mounts.extend(
+            inner.mounts[index + 1..]
+                .iter()
+                .filter(|m| !m.is_under(&target))
+                .cloned(),
+        );
+        // the open files and the working directories under the mounts keep
+        // the old tree alive, like a lazy unmount
+        inner.root = build_tree(&mut mounts)?;
+        inner.mounts = mounts;
+        Ok(())
+    }
+
+    /// Make the latest mount at `new_root` the root mount, and move the old
+    /// root mount to `put_old`, which is at or under `new_root`.
+    ///
+    /// The other mounts are moved along with the root mount they are under,
+    /// and the processes in the namespace see the new root at once.
+    pub fn pivot_root(&self, new_root: &str, put_old: &str) -> LxResult {
+        let new_root = normalize(new_root);
+        let put_old = normalize(put_old);
+        let mut inner = self.inner.lock();
+        let index = inner
+            .mounts
+            .iter()
+            .rposition(|m| m.target == new_root)
+            .ok_or(LxError::EINVAL)?;
+        if index == 0 {
+            return Err(LxError::EBUSY);
+        }
+        let new_root_len = if new_root == "/" { 0 } else { new_root.len() };
+        let relative = |path: &str| match &path[new_root_len..] {
+            "" => String::from("/"),
+            rest => String::from(rest),
+        };
+        let under = |path: &str| Mount::is_under_path(path, &new_root);
+        if !under(&put_old) {
+            return Err(LxError::EINVAL);
+        }
+        let put_old = relative(&put_old);
+        let mut mounts = Vec::with_capacity(inner.mounts.len());
+        let mut root = inner.mounts[index].clone();
+        root.target = String::from("/");
+        mounts.push(root);
+        for (i, m) in inner.mounts.iter().enumerate() {
+            if i == index {
+                continue;
+            }
+            let mut m = m.clone();
+            // the mounts over the new root stay with it, the others, hidden
+            // or not, stay with the old root
+            m.target = if i > index && under(&m.target) {
+                relative(&m.target)
+            } else if m.target == "/" {
+                put_old.clone()
+            } else if put_old == "/" {
+                m.target
+            } else {
+                put_old.clone() + &m.target
+            };
+            mounts.push(m);
+        }
+        inner.root = build_tree(&mut mounts)?;
+        inner.mounts = mounts;
+        Ok(())
+    }
+
+    /// Returns the flags of the mount which `inode` is on.
+    pub fn mount_flags(&self, inode: &dyn INode) -> MountFlags {
+        let mnode = match inode.downcast_ref::<MNode>() {
+            Some(mnode) => mnode,
+            None => return MountFlags::empty(),
+        };
+        self.inner
+            .lock()
+            .mounts
+            .iter()
+            .find(|m| matches!(&m.vfs, Some(vfs) if Arc::ptr_eq(vfs, &mnode.vfs)))
+            .map_or(MountFlags::empty(), |m| m.flags)
+    }
+
+    /// Returns the content of `/proc/[pid]/mounts`.
+    pub fn mounts_info(&self) -> String {
+        let mut s = String::new();
+        for m in self.inner.lock().mounts.iter() {
+            let mode = if m.flags.contains(MountFlags::RDONLY) {
+                "ro"
+            } else {
+                "rw"
+            };
+            s += &format!("{} {} {} {} 0 0\n", m.source, m.target, m.fstype, mode);
+        }
+        s
+    }
+}
+
+/// Build the tree of `mounts`, setting the file system of each mount in it.
+fn build_tree(mounts: &mut [Mount]) -> LxResult<Arc<MNode>> {
+    let (first, rest) = mounts.split_first_mut().ok_or(LxError::EINVAL)?;
+    let rootfs = MountFS::new(first.fs.clone());
+    let root = rootfs.mountpoint_root_inode();
+    first.vfs = Some(rootfs);
+    for mount in rest {
+        let point = root.lookup(&mount.target)?;
+        let point = point.downcast_ref::<MNode>().ok_or(LxError::EINVAL)?;
+        mount.vfs = Some(point.mount(mount.fs.clone())?);
+    }
+    Ok(root)
+}
+
+/// Normalize the absolute `path`, without `.`, `..` and repeated `/`.
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// A directory of another file system, mounted by a bind mount.
+struct BindFS {
+    root: Arc<dyn INode>,
+}
+
+impl FileSystem for BindFS {
+    fn sync(&self) -> Result<()> {
+        self.root.fs().sync()
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        self.root.fs().info()
+    }
+}
@@ -0,0 +1,468 @@
+//! Implement overlayfs, a union of a writable upper directory over a
+//! read-only lower one
+#![deny(missing_docs)]
+
+use super::xattr::{get_xattr, set_xattr, XattrFlags};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use lock::Mutex;
+use rcore_fs::vfs::*;
+
+/// The attribute marking an upper directory which hides the lower one.
+const OPAQUE_XATTR: &str = "trusted.overlay.opaque";
+
+/// Set on the inode numbers of the files only in the upper directory, so
+/// that they never collide with the ones of the lower directory.
+const UPPER_INODE: usize = 1 << 40;
+
+/// The overlayfs.
+///
+/// The lower directory is never changed. A file is copied up to the upper
+/// directory with its parents before it is changed, an entry removed from
+/// the lower directory is hidden by a whiteout, a character device `0/0` in
+/// the upper directory, and a directory created over a whiteout is marked
+/// opaque, so that the lower directory of the same name stays hidden.
+pub struct OverlayFS {
+    lower_fs: Arc<dyn FileSystem>,
+    upper_fs: Arc<dyn FileSystem>,
+    dev: usize,
+    root: Arc<OverlayINode>,
+}
+
+impl OverlayFS {
+    /// Create an overlay of the directory `upper` over the directory `lower`.
+    pub fn new(lower: Arc<dyn INode>, upper: Arc<dyn INode>) -> Arc<Self> {
+        Arc::new_cyclic(|fs: &Weak<OverlayFS>| OverlayFS {
+            lower_fs: lower.fs(),
+            upper_fs: upper.fs(),
+            dev: lower.metadata().map_or(0, |m| m.dev),
+            root: OverlayINode::new(fs.clone(), None, "", Some(upper), Some(lower)),
+        })
+    }
+}
+
+impl FileSystem for OverlayFS {
+    fn sync(&self) -> Result<()> {
+        self.lower_fs.sync()?;
+        self.upper_fs.sync()
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        // the space left for changes
+        self.upper_fs.info()
+    }
+}
+
+/// An INode of overlayfs
+pub struct OverlayINode {
+    fs: Weak<OverlayFS>,
+    this: Weak<OverlayINode>,
+    /// the lower inode number, kept after the file is copied up
+    ino: usize,
+    inner: Mutex<OverlayINodeInner>,
+}
+
+struct OverlayINodeInner {
+    parent: Option<Arc<OverlayINode>>,
+    name: String,
+    upper: Option<Arc<dyn INode>>,
+    /// the lower file, or the lower directory merged with the upper one
+    lower: Option<Arc<dyn INode>>,
+    /// the children found, so that a file is the same INode by every path
+    children: BTreeMap<String, Weak<OverlayINode>>,
+    /// the merged entries of a directory
+    entries: Option<Vec<String>>,
+}
+
+impl OverlayINode {
+    fn new(
+        fs: Weak<OverlayFS>,
+        parent: Option<Arc<OverlayINode>>,
+        name: &str,
+        upper: Option<Arc<dyn INode>>,
+        lower: Option<Arc<dyn INode>>,
+    ) -> Arc<Self> {
+        let ino = match (&upper, &lower) {
+            (_, Some(lower)) => lower.metadata().map_or(0, |m| m.inode),
+            (Some(upper), None) => upper.metadata().map_or(0, |m| m.inode) | UPPER_INODE,
+            (None, None) => unreachable!(),
+        };
+        Arc::new_cyclic(|this| OverlayINode {
+            fs,
+            this: this.clone(),
+            ino,
+            inner: Mutex::new(OverlayINodeInner {
+                parent,
+                name: String::from(name),
+                upper,
+                lower,
+                children: BTreeMap::new(),
+                entries: None,
+            }),
+        })
+    }
+
+    fn overlayfs(&self) -> Arc<OverlayFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    /// Returns the upper file if there is one, otherwise the lower one.
+    fn real(&self) -> Arc<dyn INode> {
+        let inner = self.inner.lock();
+        inner.upper.clone().or_else(|| inner.lower.clone()).unwrap()
+    }
+
+    /// Returns the upper directory, copied up if not yet.
+    fn upper_dir(&self) -> Result<Arc<dyn INode>> {
+        if self.real().metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        self.copy_up()
+    }
+
+    /// Returns the lower directory, unless hidden by an opaque upper one.
+    fn lower_dir(&self) -> Option<Arc<dyn INode>> {
+        self.inner.lock().lower.clone()
+    }
+
+    /// Copy the file up to the upper directory, with its parents, and
+    /// returns the upper file.
+    fn copy_up(&self) -> Result<Arc<dyn INode>> {
+        let mut inner = self.inner.lock();
+        if let Some(upper) = &inner.upper {
+            return Ok(upper.clone());
+        }
+        let lower = inner.lower.clone().unwrap();
+        // the root always has an upper directory
+        let parent = inner.parent.clone().unwrap().copy_up()?;
+        let info = lower.metadata()?;
+        let upper = match info.type_ {
+            FileType::File => {
+                let upper = parent.create(&inner.name, FileType::File, info.mode as u32)?;
+                let mut buf = [0u8; 4096];
+                let mut offset = 0;
+                loop {
+                    let len = lower.read_at(offset, &mut buf)?;
+                    if len == 0 {
+                        break;
+                    }
+                    upper.write_at(offset, &buf[..len])?;
+                    offset += len;
+                }
+                upper
+            }
+            FileType::SymLink => {
+                let upper = parent.create(&inner.name, FileType::SymLink, info.mode as u32)?;
+                let mut buf = [0u8; 4096];
+                let len = lower.read_at(0, &mut buf)?;
+                upper.write_at(0, &buf[..len])?;
+                upper
+            }
+            type_ => parent.create2(&inner.name, type_, info.mode as u32, info.rdev)?,
+        };
+        match upper.set_metadata(&info) {
+            Ok(()) | Err(FsError::NotSupported) => {}
+            Err(e) => return Err(e),
+        }
+        inner.upper = Some(upper.clone());
+        Ok(upper)
+    }
+
+    /// Returns the merged entries of the directory, `.` and `..` first.
+    fn entries(&self) -> Result<Vec<String>> {
+        if let Some(entries) = &self.inner.lock().entries {
+            return Ok(entries.clone());
+        }
+        let (upper, lower) = {
+            let inner = self.inner.lock();
+            (inner.upper.clone(), inner.lower.clone())
+        };
+        let mut entries = vec![String::from("."), String::from("..")];
+        // the upper entries and the whiteouts
+        let mut seen = BTreeSet::new();
+        for (dir, is_upper) in upper
+            .iter()
+            .map(|d| (d, true))
+            .chain(lower.iter().map(|d| (d, false)))
+        {
+            for name in dir_entries(&**dir) {
+                if seen.contains(&name) {
+                    continue;
+                }
+                if !(is_upper && is_whiteout(&*dir.find(&name)?)) {
+                    entries.push(name.clone());
+                }
+                seen.insert(name);
+            }
+        }
+        self.inner.lock().entries = Some(entries.clone());
+        Ok(entries)
+    }
+
+    /// Forget the entry `name` after it is changed.
+    fn invalidate(&self, name: &str) {
+        let mut inner = self.inner.lock();
+        inner.children.remove(name);
+        inner.entries = None;
+    }
+
+    /// Remove the whiteout `name` in the upper directory `upper` if there
+    /// is one, returns whether there was.
+    fn remove_whiteout(upper: &Arc<dyn INode>, name: &str) -> Result<bool> {
+        match upper.find(name) {
+            Ok(inode) if is_whiteout(&*inode) => {
+                upper.unlink(name)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Hide the lower entry `name`, if there is one, by a whiteout in the
+    /// upper directory `upper`.
+    fn hide_lower(&self, upper: &Arc<dyn INode>, name: &str) -> Result<()> {
+        if let Some(lower) = self.lower_dir() {
+            if lower.find(name).is_ok() {
+                upper.create2(name, FileType::CharDevice, 0, 0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl INode for OverlayINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.real().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.copy_up()?.write_at(offset, buf)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        self.real().poll()
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let mut metadata = self.real().metadata()?;
+        metadata.dev = self.overlayfs().dev;
+        metadata.inode = self.ino;
+        Ok(metadata)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        self.copy_up()?.set_metadata(metadata)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        self.real().sync_all()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.real().sync_data()
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        self.copy_up()?.resize(len)
+    }
+
+    fn create2(
+        &self,
+        name: &str,
+        type_: FileType,
+        mode: u32,
+        data: usize,
+    ) -> Result<Arc<dyn INode>> {
+        if self.find(name).is_ok() {
+            return Err(FsError::EntryExist);
+        }
+        let upper = self.upper_dir()?;
+        let replaced = Self::remove_whiteout(&upper, name)?;
+        let inode = upper.create2(name, type_, mode, data)?;
+        if replaced && type_ == FileType::Dir {
+            set_xattr(&*inode, OPAQUE_XATTR, b"y", XattrFlags::empty())
+                .map_err(|_| FsError::NotSupported)?;
+        }
+        self.invalidate(name);
+        self.find(name)
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn INode>) -> Result<()> {
+        let other = other
+            .downcast_ref::<OverlayINode>()
+            .ok_or(FsError::NotSameFs)?;
+        if !Weak::ptr_eq(&other.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        if self.find(name).is_ok() {
+            return Err(FsError::EntryExist);
+        }
+        let upper = self.upper_dir()?;
+        let other_upper = other.copy_up()?;
+        Self::remove_whiteout(&upper, name)?;
+        upper.link(name, &other_upper)?;
+        self.invalidate(name);
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(FsError::DirNotEmpty);
+        }
+        let inode = self.find(name)?;
+        let inode = inode.downcast_ref::<OverlayINode>().unwrap();
+        let is_dir = inode.real().metadata()?.type_ == FileType::Dir;
+        if is_dir && inode.entries()?.len() > 2 {
+            return Err(FsError::DirNotEmpty);
+        }
+        let upper = self.upper_dir()?;
+        let inode_upper = inode.inner.lock().upper.clone();
+        if let Some(inode_upper) = inode_upper {
+            if is_dir {
+                // only whiteouts are left in the directory
+                for entry in dir_entries(&*inode_upper) {
+                    inode_upper.unlink(&entry)?;
+                }
+            }
+            upper.unlink(name)?;
+        }
+        self.hide_lower(&upper, name)?;
+        self.invalidate(name);
+        Ok(())
+    }
+
+    fn move_(&self, old_name: &str, target: &Arc<dyn INode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<OverlayINode>()
+            .ok_or(FsError::NotSameFs)?;
+        if !Weak::ptr_eq(&target.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        let inode = self.find(old_name)?;
+        let inode = inode.downcast_ref::<OverlayINode>().unwrap();
+        let is_dir = inode.real().metadata()?.type_ == FileType::Dir;
+        // a lower directory would have to be copied up with all its files,
+        // so `rename` fails with `EXDEV` and `mv` copies it instead
+        if is_dir && inode.lower_dir().is_some() {
+            return Err(FsError::NotSameFs);
+        }
+        if let Ok(old) = target.find(new_name) {
+            if core::ptr::eq(old.as_any_ref(), inode.as_any_ref()) {
+                return Ok(());
+            }
+            target.unlink(new_name)?;
+        }
+        let upper = self.upper_dir()?;
+        let inode_upper = inode.copy_up()?;
+        let target_upper = target.upper_dir()?;
+        Self::remove_whiteout(&target_upper, new_name)?;
+        upper.move_(old_name, &target_upper, new_name)?;
+        self.hide_lower(&upper, old_name)?;
+        if is_dir {
+            // never merged with a lower directory of the new name
+            set_xattr(&*inode_upper, OPAQUE_XATTR, b"y", XattrFlags::empty())
+                .map_err(|_| FsError::NotSupported)?;
+        }
+        self.invalidate(old_name);
+        target.invalidate(new_name);
+        target
+            .inner
+            .lock()
+            .children
+            .insert(String::from(new_name), inode.this.clone());
+        let mut inner = inode.inner.lock();
+        inner.parent = target.this.upgrade();
+        inner.name = String::from(new_name);
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        if self.real().metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let this = self.this.upgrade().unwrap();
+        match name {
+            "." => return Ok(this),
+            ".." => {
+                let parent = self.inner.lock().parent.clone();
+                return Ok(parent.unwrap_or(this));
+            }
+            _ => {}
+        }
+        let (upper, lower) = {
+            let inner = self.inner.lock();
+            if let Some(inode) = inner.children.get(name).and_then(|c| c.upgrade()) {
+                return Ok(inode);
+            }
+            (inner.upper.clone(), inner.lower.clone())
+        };
+        let upper = upper.and_then(|dir| dir.find(name).ok());
+        let lower = lower.and_then(|dir| dir.find(name).ok());
+        let (upper, lower) = match upper {
+            Some(upper) if is_whiteout(&*upper) => return Err(FsError::EntryNotFound),
+            // an upper directory is merged with a lower one unless opaque
+            Some(upper) if upper.metadata()?.type_ == FileType::Dir => {
+                let lower = lower.filter(|lower| {
+                    !is_opaque(&*upper)
+                        && matches!(lower.metadata(), Ok(m) if m.type_ == FileType::Dir)
+                });
+                (Some(upper), lower)
+            }
+            Some(upper) => (Some(upper), None),
+            None => (None, Some(lower.ok_or(FsError::EntryNotFound)?)),
+        };
+        let inode = OverlayINode::new(self.fs.clone(), Some(this), name, upper, lower);
+        self.inner
+            .lock()
+            .children
+            .insert(String::from(name), Arc::downgrade(&inode));
+        Ok(inode)
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        if self.real().metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        self.entries()?
+            .get(id)
+            .cloned()
+            .ok_or(FsError::EntryNotFound)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.overlayfs()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Returns the entries of the directory `dir`, without `.` and `..`.
+fn dir_entries(dir: &dyn INode) -> Vec<String> {
+    (0..)
+        .map(|id| dir.get_entry(id))
+        .take_while(|entry| entry.is_ok())
+        .filter_map(|entry| entry.ok())
+        .filter(|name| name != "." && name != "..")
+        .collect()
+}
+
+/// Whether `inode` is a whiteout, hiding the lower file of its name.
+fn is_whiteout(inode: &dyn INode) -> bool {
+    matches!(inode.metadata(), Ok(m) if m.type_ == FileType::CharDevice && m.rdev == 0)
+}
+
+/// Whether the upper directory `dir` hides the lower one of its name.
+fn is_opaque(dir: &dyn INode) -> bool {
+    get_xattr(dir, OPAQUE_XATTR).map_or(false, |value| value == b"y")
+}
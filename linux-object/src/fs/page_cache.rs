@@ -0,0 +1,494 @@
+//! Implement the page cache of regular files on disk file systems
+//!
+//! The pages of a file are kept in a VMO, which serves `read` and `write` of
+//! every opened instance of the file, and is mapped directly by
+//! `mmap(MAP_SHARED)`, so that all of them see the same data. Pages are read
+//! from the file when first accessed, including by page faults of mappings.
+//! Writes go through to the file at once, while the pages written through
+//! shared mappings are recorded by the VMO, and written back on `fsync`,
+//! `msync`, `munmap`, `sync`, or before the file is read without the cache,
+//! e.g. by `execve`.
+#![deny(missing_docs)]
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use lock::Mutex;
+use rcore_fs::vfs::{FileType, FsError, INode, Result};
+use rcore_fs_mountfs::MNode;
+use zircon_object::{
+    object::{KernelObject, KoID},
+    vm::{page_aligned, pages, roundup_pages, VmObject, VmoPager, PAGE_SIZE},
+    ZxError, ZxResult,
+};
+
+use super::{inotify_event, Ext4INode, FatINode, InotifyMask, OverlayINode, TmpINode};
+
+/// Length of the VMO of a cache. Pages are committed on demand, so only the
+/// cached ones take memory. Data beyond it is accessed without the cache.
+const CACHE_VMO_SIZE: usize = 1 << 36;
+/// Maximum number of pages written back to the file at once.
+const BATCH_PAGES: usize = 32;
+
+lazy_static! {
+    /// Page caches, indexed by the file system and the inode number.
+    static ref PAGE_CACHES: Mutex<BTreeMap<(usize, usize), Arc<PageCache>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// The page cache of a regular file
+pub struct PageCache {
+    inode: Arc<dyn INode>,
+    vmo: Arc<VmObject>,
+    inner: Mutex<PageCacheInner>,
+}
+
+struct PageCacheInner {
+    /// size of the file when it was last seen
+    size: usize,
+    /// shared mappings, whose pages may be modified at any time
+    shared: Vec<SharedRange>,
+}
+
+/// A slice of the cache VMO, given to `mmap(MAP_SHARED)`
+struct SharedRange {
+    slice: Weak<VmObject>,
+    offset: usize,
+    len: usize,
+}
+
+impl PageCache {
+    /// Returns the page cache of `inode`, creating it if needed.
+    ///
+    /// Returns `None` if `inode` is not a regular file of a disk file system,
+    /// whose data never changes behind the cache.
+    pub fn get(inode: &Arc<dyn INode>) -> Option<Arc<Self>> {
+        let inode = super::fs_inode(inode);
+        if !is_cacheable(&*inode) || inode.metadata().ok()?.type_ != FileType::File {
+            return None;
+        }
+        let key = inode_key(&*inode).ok()?;
+        let mut caches = PAGE_CACHES.lock();
+        evict_unused(&mut caches);
+        let cache = caches.entry(key).or_insert_with(|| {
+            let pager = Arc::new(FilePager {
+                inode: inode.clone(),
+            });
+            Arc::new(PageCache {
+                inode,
+                vmo: VmObject::new_paged_with_pager(pages(CACHE_VMO_SIZE), pager),
+                inner: Mutex::new(PageCacheInner {
+                    size: 0,
+                    shared: Vec::new(),
+                }),
+            })
+        });
+        Some(cache.clone())
+    }
+
+    /// Returns the page cache of `inode` if it has one.
+    pub fn lookup(inode: &dyn INode) -> Option<Arc<Self>> {
+        let key = inode_key(inode).ok()?;
+        PAGE_CACHES.lock().get(&key).cloned()
+    }
+
+    /// Read the file at `offset`.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        let size = self.refresh_size(&mut inner)?;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        let buf = &mut buf[..len];
+        if offset + len > self.vmo.len() {
+            return self.inode.read_at(offset, buf);
+        }
+        // pages not cached yet are read from the file by the VMO
+        self.vmo
+            .read(offset, buf)
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(len)
+    }
+
+    /// Write the file at `offset`, updating the cached pages.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        self.refresh_size(&mut inner)?;
+        let len = self.inode.write_at(offset, buf)?;
+        let end = offset + len;
+        inner.size = inner.size.max(end);
+        if end > self.vmo.len() {
+            return Ok(len);
+        }
+        // pages not cached yet will be read from the file when needed
+        let mut pos = offset;
+        while pos < end {
+            let next = ((pos / PAGE_SIZE + 1) * PAGE_SIZE).min(end);
+            if self.is_cached(pos / PAGE_SIZE) {
+                self.vmo
+                    .write(pos, &buf[pos - offset..next - offset])
+                    .map_err(|_| FsError::DeviceError)?;
+            }
+            pos = next;
+        }
+        Ok(len)
+    }
+
+    /// Change the size of the file.
+    pub fn resize(&self, len: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+        self.refresh_size(&mut inner)?;
+        // shared mappings beyond the new end must not be written back later
+        self.write_back(&mut inner)?;
+        self.inode.resize(len)?;
+        self.truncate_pages(&mut inner, len)?;
+        inner.size = len;
+        Ok(())
+    }
+
+    /// Returns a VMO of the file in `[offset, offset + len)` for `mmap`.
+    ///
+    /// A shared VMO is a slice of the cache, while a private one is a copy of
+    /// the file.
+    pub fn get_vmo(&self, offset: usize, len: usize, shared: bool) -> Result<Arc<VmObject>> {
+        let len = roundup_pages(len);
+        if !page_aligned(offset) || offset + len > self.vmo.len() {
+            return Err(FsError::InvalidParam);
+        }
+        let mut inner = self.inner.lock();
+        self.refresh_size(&mut inner)?;
+        if shared {
+            let slice = self
+                .vmo
+                .create_slice(offset, len)
+                .map_err(|_| FsError::InvalidParam)?;
+            inner.shared.push(SharedRange {
+                slice: Arc::downgrade(&slice),
+                offset,
+                len,
+            });
+            return Ok(slice);
+        }
+        let vmo = VmObject::new_paged(pages(len));
+        let mut buf = vec![0u8; BATCH_PAGES * PAGE_SIZE];
+        for pos in (0..len).step_by(buf.len()) {
+            let buf = &mut buf[..(len - pos).min(BATCH_PAGES * PAGE_SIZE)];
+            self.vmo
+                .read(offset + pos, buf)
+                .map_err(|_| FsError::DeviceError)?;
+            vmo.write(pos, buf).map_err(|_| FsError::DeviceError)?;
+        }
+        Ok(vmo)
+    }
+
+    /// Write back the pages modified through shared mappings.
+    pub fn sync(&self) -> Result<()> {
+        let mut inner = self.inner.lock();
+        self.refresh_size(&mut inner)?;
+        self.write_back(&mut inner)
+    }
+
+    /// Write back the pages modified through shared mappings of all files.
+    pub fn sync_all() -> Result<()> {
+        let caches: Vec<_> = PAGE_CACHES.lock().values().cloned().collect();
+        for cache in caches {
+            cache.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Write back the pages modified through shared mappings of the files
+    /// which any of `vmos` maps, e.g. the VMOs mapped in the range of `msync`.
+    pub fn sync_vmos(vmos: &[KoID]) -> Result<()> {
+        let caches: Vec<_> = PAGE_CACHES.lock().values().cloned().collect();
+        for cache in caches {
+            let mapped = cache.inner.lock().shared.iter().any(|range| {
+                range
+                    .slice
+                    .upgrade()
+                    .map_or(false, |slice| vmos.contains(&slice.id()))
+            });
+            if mapped {
+                cache.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the size of the file, which may have been changed without the
+    /// cache, e.g. by `truncate`.
+    fn refresh_size(&self, inner: &mut PageCacheInner) -> Result<usize> {
+        let size = self.inode.metadata()?.size;
+        if size < inner.size {
+            self.truncate_pages(inner, size)?;
+        }
+        inner.size = size;
+        Ok(size)
+    }
+
+    /// Whether the page at `page` is in the cache.
+    fn is_cached(&self, page: usize) -> bool {
+        self.vmo.committed_pages_in_range(page, page + 1) != 0
+    }
+
+    /// Write back the pages written through shared mappings, and forget the
+    /// mappings which are unmapped.
+    fn write_back(&self, inner: &mut PageCacheInner) -> Result<()> {
+        inner.shared.retain(|range| range.slice.strong_count() > 0);
+        let dirty = self.vmo.take_dirty_pages(0, pages(self.vmo.len()));
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        // writes after the pages are read below make them dirty again
+        for range in inner.shared.iter() {
+            let slice = match range.slice.upgrade() {
+                Some(slice) => slice,
+                None => continue,
+            };
+            let first = range.offset / PAGE_SIZE;
+            let pages_range = first..first + pages(range.len);
+            for &page in dirty.iter().filter(|page| pages_range.contains(page)) {
+                slice.write_protect(page - first, page - first + 1);
+            }
+        }
+        // nothing beyond the end of the file is written back
+        let end_page = pages(inner.size);
+        let mut buf = vec![0u8; BATCH_PAGES * PAGE_SIZE];
+        let mut i = 0;
+        while i < dirty.len() && dirty[i] < end_page {
+            let first = dirty[i];
+            let mut count = 1;
+            while count < BATCH_PAGES
+                && i + count < dirty.len()
+                && dirty[i + count] == first + count
+                && first + count < end_page
+            {
+                count += 1;
+            }
+            let pos = first * PAGE_SIZE;
+            let buf = &mut buf[..(count * PAGE_SIZE).min(inner.size - pos)];
+            self.vmo.read(pos, buf).map_err(|_| FsError::DeviceError)?;
+            self.inode.write_at(pos, buf)?;
+            i += count;
+        }
+        Ok(())
+    }
+
+    /// Drop the cached data beyond `size`.
+    fn truncate_pages(&self, inner: &mut PageCacheInner, size: usize) -> Result<()> {
+        let end = roundup_pages(size);
+        if size < end && self.is_cached(size / PAGE_SIZE) {
+            self.vmo
+                .zero(size, end - size)
+                .map_err(|_| FsError::DeviceError)?;
+        }
+        // pages may be cached up to the old end of the file, or the end of
+        // mappings beyond it
+        let live = inner.shared.iter().filter(|r| r.slice.strong_count() > 0);
+        let limit = live
+            .clone()
+            .map(|r| r.offset + r.len)
+            .fold(roundup_pages(inner.size), usize::max);
+        // pages still mapped are zeroed instead of being freed
+        let mapped = live.count() > 0;
+        let zeros = vec![0u8; PAGE_SIZE];
+        for page in end / PAGE_SIZE..limit / PAGE_SIZE {
+            if !self.is_cached(page) {
+                continue;
+            }
+            let result = if mapped {
+                self.vmo.write(page * PAGE_SIZE, &zeros)
+            } else {
+                self.vmo.decommit(page * PAGE_SIZE, PAGE_SIZE)
+            };
+            result.map_err(|_| FsError::DeviceError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the pages of a cache from its file.
+struct FilePager {
+    inode: Arc<dyn INode>,
+}
+
+impl VmoPager for FilePager {
+    fn supply_page(&self, page_idx: usize, buf: &mut [u8]) -> ZxResult {
+        let offset = page_idx * PAGE_SIZE;
+        let mut len = 0;
+        while len < buf.len() {
+            match self.inode.read_at(offset + len, &mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) => {
+                    warn!("page cache: failed to read page {}: {:?}", page_idx, e);
+                    return Err(ZxError::IO);
+                }
+            }
+        }
+        // beyond the end of the file
+        buf[len..].fill(0);
+        Ok(())
+    }
+}
+
+/// Write back the pages of `inode` modified through shared mappings, so that
+/// it can be read without the cache.
+pub fn sync_inode(inode: &dyn INode) -> Result<()> {
+    match PageCache::lookup(inode) {
+        Some(cache) => cache.sync(),
+        None => Ok(()),
+    }
+}
+
+/// Change the size of `inode`, through its page cache if it has one.
+pub fn resize_inode(inode: &Arc<dyn INode>, len: usize) -> Result<()> {
+    match PageCache::lookup(&**inode) {
+        Some(cache) => cache.resize(len)?,
+        None => inode.resize(len)?,
+    }
+    inotify_event(&**inode, InotifyMask::MODIFY, 0, "");
+    Ok(())
+}
+
+/// Only the data of disk file systems is cached. Others either keep their
+/// files in memory already, or generate the content on every read.
+fn is_cacheable(inode: &dyn INode) -> bool {
+    inode.downcast_ref::<Ext4INode>().is_some()
+        || inode.downcast_ref::<FatINode>().is_some()
+        || inode.downcast_ref::<rcore_fs_sfs::INodeImpl>().is_some()
+}
+
+/// Identify the file of `inode` by its file system and inode number, the
+/// same for every path to it.
+pub(super) fn inode_key(inode: &dyn INode) -> Result<(usize, usize)> {
+    let inode = match inode.downcast_ref::<MNode>() {
+        Some(mnode) => &*mnode.inode,
+        None => inode,
+    };
+    if !is_cacheable(inode)
+        && inode.downcast_ref::<TmpINode>().is_none()
+        && inode.downcast_ref::<OverlayINode>().is_none()
+    {
+        // pipes and devices have no file system, and are identified by the
+        // inode objects themselves
+        return Ok((0, inode as *const dyn INode as *const u8 as usize));
+    }
+    let fs = Arc::as_ptr(&inode.fs()) as *const u8 as usize;
+    Ok((fs, inode.metadata()?.inode))
+}
+
+/// Drop the caches which are neither opened nor mapped.
+fn evict_unused(caches: &mut BTreeMap<(usize, usize), Arc<PageCache>>) {
+    caches.retain(|_, cache| {
+        if Arc::strong_count(cache) > 1 {
+            return true;
+        }
+        let mut inner = cache.inner.lock();
+        if let Err(e) = cache.write_back(&mut inner) {
+            warn!("page cache: failed to write back: {:?}", e);
+        }
+        !inner.shared.is_empty()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{ext4::tests::image, Ext4FileSystem};
+    use zircon_object::vm::MMUFlags;
+
+    /// Size of `/big` of the test image, of blocks of 1 KiB filled with
+    /// `'a' + block`, except the blocks 2 and 3 which are a hole.
+    const BIG_SIZE: usize = 5 * 1024 + 100;
+
+    fn root() -> Arc<dyn INode> {
+        Ext4FileSystem::open(image()).unwrap().root_inode()
+    }
+
+    fn read_file(inode: &dyn INode, offset: usize, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let len = inode.read_at(offset, &mut buf).unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    #[test]
+    fn read_write() {
+        let inode = root().find("big").unwrap();
+        let cache = PageCache::get(&inode).unwrap();
+        assert!(Arc::ptr_eq(&cache, &PageCache::lookup(&*inode).unwrap()));
+        let mut buf = vec![0u8; 2 * PAGE_SIZE];
+        assert_eq!(cache.read_at(0, &mut buf).unwrap(), BIG_SIZE);
+        assert_eq!((buf[1024], buf[2048], buf[4096]), (b'b', 0, b'e'));
+
+        // writes go through to the file, and to the cached pages
+        cache.write_at(1020, b"written").unwrap();
+        assert_eq!(read_file(&*inode, 1020, 7), b"written");
+        assert_eq!(cache.read_at(1020, &mut buf[..7]).unwrap(), 7);
+        assert_eq!(&buf[..7], b"written");
+
+        // the file is truncated behind the cache, and extended again
+        inode.resize(1024).unwrap();
+        assert_eq!(cache.read_at(0, &mut buf).unwrap(), 1024);
+        inode.resize(BIG_SIZE).unwrap();
+        assert_eq!(cache.read_at(0, &mut buf).unwrap(), BIG_SIZE);
+        assert_eq!(&buf[1020..1024], b"writ");
+        assert!(buf[1024..BIG_SIZE].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn write_back() {
+        let inode = root().find("big").unwrap();
+        let cache = PageCache::get(&inode).unwrap();
+        let shared = cache.get_vmo(0, BIG_SIZE, true).unwrap();
+        let private = cache.get_vmo(0, BIG_SIZE, false).unwrap();
+
+        // pages written through shared mappings are written back on sync
+        shared.commit_page(1, MMUFlags::WRITE).unwrap();
+        shared.write(PAGE_SIZE, b"mapped").unwrap();
+        assert_eq!(read_file(&*inode, PAGE_SIZE, 6), b"eeeeee");
+        cache.sync().unwrap();
+        assert_eq!(read_file(&*inode, PAGE_SIZE, 6), b"mapped");
+
+        // but not beyond the end of the file
+        shared.write(BIG_SIZE, b"beyond").unwrap();
+        cache.sync().unwrap();
+        assert_eq!(inode.metadata().unwrap().size, BIG_SIZE);
+
+        // private mappings are copies
+        let mut buf = [0u8; 6];
+        private.read(PAGE_SIZE, &mut buf).unwrap();
+        assert_eq!(&buf, b"eeeeee");
+        private.write(0, b"copied").unwrap();
+        assert_eq!(read_file(&*inode, 0, 6), b"aaaaaa");
+    }
+
+    #[test]
+    fn evict() {
+        let root = root();
+        let inode = root.find("big").unwrap();
+        let cache = PageCache::get(&inode).unwrap();
+        let shared = cache.get_vmo(0, PAGE_SIZE, true).unwrap();
+        shared.commit_page(0, MMUFlags::WRITE).unwrap();
+        shared.write(0, b"evicted").unwrap();
+        drop(cache);
+
+        // unused caches are evicted when a cache is created, after they are
+        // written back, but not while they are mapped
+        let small = root.find("dir").unwrap().find("small").unwrap();
+        let small_cache = PageCache::get(&small).unwrap();
+        assert!(PageCache::lookup(&*inode).is_some());
+        assert_eq!(read_file(&*inode, 0, 7), b"evicted");
+
+        drop(shared);
+        drop(small_cache);
+        PageCache::get(&small).unwrap();
+        assert!(PageCache::lookup(&*inode).is_none());
+        assert_eq!(read_file(&*inode, 0, 8), b"evicteda");
+    }
+}
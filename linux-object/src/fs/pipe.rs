@@ -1,80 +1,288 @@
-//! Implement INode for Pipe
+//! Implement INode for Pipe, and FIFOs (named pipes) on top of it
 #![deny(missing_docs)]
 
+use crate::error::{LxError, LxResult};
 use crate::{sync::Event, sync::EventBus};
-use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc};
+use alloc::{
+    boxed::Box,
+    collections::{vec_deque::VecDeque, BTreeMap},
+    sync::{Arc, Weak},
+};
 use core::{any::Any, cmp::min};
 use core::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
 };
+use lazy_static::lazy_static;
 use lock::Mutex;
 use rcore_fs::vfs::*;
+use zircon_object::vm::PAGE_SIZE;
+
+use super::{page_cache::inode_key, ChangeCallback, OpenFlags};
+
+/// The capacity of a new pipe
+pub const PIPE_DEFAULT_SIZE: usize = 0x10000;
+/// The largest capacity an unprivileged process may set,
+/// as `/proc/sys/fs/pipe-max-size`
+pub const PIPE_MAX_SIZE: usize = 0x100000;
+/// Writes of at most this many bytes are atomic
+pub const PIPE_BUF: usize = 4096;
 
 #[derive(Clone, PartialEq, Eq)]
-#[allow(dead_code)]
 /// Pipe end specify
 pub enum PipeEnd {
     /// read end
     Read,
     /// write end
     Write,
+    /// both ends, a FIFO opened for reading and writing
+    Both,
+}
+
+impl PipeEnd {
+    fn reads(&self) -> bool {
+        *self != PipeEnd::Write
+    }
+
+    fn writes(&self) -> bool {
+        *self != PipeEnd::Read
+    }
 }
 
 /// Pipe inner data
 pub struct PipeData {
     /// pipe buffer
     buf: VecDeque<u8>,
+    /// the length of each packet in the buffer, in packet mode (`O_DIRECT`)
+    packets: Option<VecDeque<usize>>,
+    /// the largest number of bytes in the buffer
+    capacity: usize,
     /// event bus for pipe
     eventbus: EventBus,
-    /// number of pipe ends
-    end_cnt: i32,
+    /// number of read ends
+    readers: usize,
+    /// number of write ends
+    writers: usize,
+    /// number of read ends ever opened, for the open of a FIFO to tell a
+    /// read end opened and closed while it waits
+    read_opens: usize,
+    /// number of write ends ever opened
+    write_opens: usize,
+}
+
+impl PipeData {
+    fn new(packet: bool) -> Self {
+        PipeData {
+            buf: VecDeque::new(),
+            packets: if packet { Some(VecDeque::new()) } else { None },
+            capacity: PIPE_DEFAULT_SIZE,
+            eventbus: EventBus::default(),
+            readers: 0,
+            writers: 0,
+            read_opens: 0,
+            write_opens: 0,
+        }
+    }
+
+    /// Update the events by the state of the buffer and the ends.
+    ///
+    /// The pipe is readable at the end of file, and writable when there is
+    /// room for an atomic write, or when the write would fail.
+    fn update_events(&mut self) {
+        let readable = !self.buf.is_empty() || self.writers == 0;
+        let writable = self.room() >= min(PIPE_BUF, self.capacity) || self.readers == 0;
+        let mut set = Event::empty();
+        set.set(Event::READABLE, readable);
+        set.set(Event::WRITABLE, writable);
+        // every change is told, though the events stay the same
+        self.eventbus
+            .change_notify(Event::READABLE | Event::WRITABLE, set);
+    }
+
+    fn room(&self) -> usize {
+        self.capacity.saturating_sub(self.buf.len())
+    }
+}
+
+lazy_static! {
+    /// The buffers of the FIFOs opened, by the file of the FIFO
+    static ref FIFOS: Mutex<BTreeMap<(usize, usize), Weak<Mutex<PipeData>>>> =
+        Mutex::new(BTreeMap::new());
 }
 
 /// pipe struct
-#[derive(Clone)]
 pub struct Pipe {
     data: Arc<Mutex<PipeData>>,
     direction: PipeEnd,
+    /// the INode of a FIFO, which describes it
+    fifo: Option<Arc<dyn INode>>,
 }
 
 impl Drop for Pipe {
     fn drop(&mut self) {
         // pipe end closed
         let mut data = self.data.lock();
-        data.end_cnt -= 1;
+        if self.direction.reads() {
+            data.readers -= 1;
+        }
+        if self.direction.writes() {
+            data.writers -= 1;
+        }
         data.eventbus.set(Event::CLOSED);
+        data.update_events();
     }
 }
 
-#[allow(dead_code)]
 impl Pipe {
     /// Create a pair of INode: (read, write)
     pub fn create_pair() -> (Pipe, Pipe) {
-        let inner = PipeData {
-            buf: VecDeque::new(),
-            eventbus: EventBus::default(),
-            end_cnt: 2, // one read, one write
-        };
-        let data = Arc::new(Mutex::new(inner));
+        Self::create_pair_with(false)
+    }
+
+    /// Create a pair of INode: (read, write), whose writes are packets in
+    /// `packet` mode, each read by at most one read.
+    pub fn create_pair_with(packet: bool) -> (Pipe, Pipe) {
+        let data = Arc::new(Mutex::new(PipeData::new(packet)));
         (
-            Pipe {
-                data: data.clone(),
-                direction: PipeEnd::Read,
-            },
-            Pipe {
-                data,
-                direction: PipeEnd::Write,
-            },
+            Pipe::attach(&data, PipeEnd::Read, None),
+            Pipe::attach(&data, PipeEnd::Write, None),
         )
     }
+
+    /// Create a new end of the pipe `data`.
+    fn attach(
+        data: &Arc<Mutex<PipeData>>,
+        direction: PipeEnd,
+        fifo: Option<Arc<dyn INode>>,
+    ) -> Pipe {
+        let mut inner = data.lock();
+        if direction.reads() {
+            inner.readers += 1;
+            inner.read_opens += 1;
+        }
+        if direction.writes() {
+            inner.writers += 1;
+            inner.write_opens += 1;
+        }
+        inner.update_events();
+        // wake up the opens waiting for this end
+        inner.eventbus.notify();
+        drop(inner);
+        Pipe {
+            data: data.clone(),
+            direction,
+            fifo,
+        }
+    }
+
+    /// Open an end of the FIFO `inode` by the access mode of `flags`.
+    ///
+    /// The ends opened of a FIFO share a buffer, which is dropped with the
+    /// last of them. Opening a read or write end blocks until the other end
+    /// is also opened, unless `flags` is non-blocking, when a write end fails
+    /// with `ENXIO` without a read end. The end is closed if the wait is
+    /// dropped, e.g. interrupted by a signal with
+    /// [`interruptible`](crate::thread::interruptible).
+    pub async fn open_fifo(inode: Arc<dyn INode>, flags: OpenFlags) -> LxResult<Pipe> {
+        let direction = match (flags.readable(), flags.writable()) {
+            (true, true) => PipeEnd::Both,
+            (true, false) => PipeEnd::Read,
+            _ => PipeEnd::Write,
+        };
+        let key = inode_key(&*inode)?;
+        let data = {
+            let mut fifos = FIFOS.lock();
+            fifos.retain(|_, data| data.strong_count() > 0);
+            match fifos.get(&key).and_then(Weak::upgrade) {
+                Some(data) => data,
+                None => {
+                    let data = Arc::new(Mutex::new(PipeData::new(false)));
+                    fifos.insert(key, Arc::downgrade(&data));
+                    data
+                }
+            }
+        };
+        if flags.non_block() && direction == PipeEnd::Write && data.lock().readers == 0 {
+            return Err(LxError::ENXIO);
+        }
+        let (read_opens, write_opens) = {
+            let data = data.lock();
+            (data.read_opens, data.write_opens)
+        };
+        let pipe = Pipe::attach(&data, direction, Some(inode));
+        if !flags.non_block() {
+            FifoOpenFuture {
+                pipe: &pipe,
+                read_opens,
+                write_opens,
+            }
+            .await;
+        }
+        Ok(pipe)
+    }
+
+    /// Returns the capacity of the pipe (`F_GETPIPE_SZ`).
+    pub fn capacity(&self) -> usize {
+        self.data.lock().capacity
+    }
+
+    /// Set the capacity of the pipe to at least `size` bytes
+    /// (`F_SETPIPE_SZ`), returns the capacity set.
+    ///
+    /// The capacity is rounded up to a power of two number of pages, and can
+    /// not be less than the bytes in the pipe.
+    pub fn set_capacity(&self, size: usize) -> LxResult<usize> {
+        let capacity = size
+            .max(PAGE_SIZE)
+            .checked_next_power_of_two()
+            .ok_or(LxError::EINVAL)?;
+        let mut data = self.data.lock();
+        if capacity < data.buf.len() {
+            return Err(LxError::EBUSY);
+        }
+        data.capacity = capacity;
+        data.update_events();
+        Ok(capacity)
+    }
+
+    /// Write to the write end, returns the bytes written.
+    ///
+    /// A write of at most [`PIPE_BUF`] bytes is written whole or not at all.
+    /// Fails with `EAGAIN` if nothing can be written now, or `EPIPE` if the
+    /// read ends are all closed.
+    pub fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        if !self.direction.writes() {
+            return Err(LxError::EBADF);
+        }
+        let mut data = self.data.lock();
+        if data.readers == 0 {
+            return Err(LxError::EPIPE);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let room = data.room();
+        if room == 0 || (buf.len() <= PIPE_BUF && room < buf.len()) {
+            return Err(LxError::EAGAIN);
+        }
+        let len = min(room, buf.len());
+        data.buf.extend(&buf[..len]);
+        if let Some(packets) = &mut data.packets {
+            // a long write is split into packets of `PIPE_BUF` bytes
+            for chunk in buf[..len].chunks(PIPE_BUF) {
+                packets.push_back(chunk.len());
+            }
+        }
+        data.update_events();
+        Ok(len)
+    }
+
     /// whether the pipe struct is readable
     fn can_read(&self) -> bool {
-        if let PipeEnd::Read = self.direction {
-            // true
+        if self.direction.reads() {
             let data = self.data.lock();
-            !data.buf.is_empty() || data.end_cnt < 2 // other end closed
+            !data.buf.is_empty() || data.writers == 0 // other end closed
         } else {
             false
         }
@@ -82,12 +290,34 @@ impl Pipe {
 
     /// whether the pipe struct is writeable
     fn can_write(&self) -> bool {
-        if let PipeEnd::Write = self.direction {
-            self.data.lock().end_cnt == 2
+        if self.direction.writes() {
+            let data = self.data.lock();
+            data.readers != 0 && data.room() >= min(PIPE_BUF, data.capacity)
         } else {
             false
         }
     }
+
+    /// whether the other end is closed
+    fn peer_closed(&self) -> bool {
+        let data = self.data.lock();
+        match self.direction {
+            PipeEnd::Read => data.writers == 0,
+            PipeEnd::Write => data.readers == 0,
+            PipeEnd::Both => false,
+        }
+    }
+
+    /// Whether the write ends are all closed, reported as `POLLHUP` by the
+    /// read end.
+    pub fn hung_up(&self) -> bool {
+        self.direction == PipeEnd::Read && self.peer_closed()
+    }
+
+    /// Call `callback` once on the next change of the pipe.
+    pub fn on_change(&self, callback: ChangeCallback) {
+        self.data.lock().eventbus.subscribe_once(callback);
+    }
 }
 
 impl INode for Pipe {
@@ -96,37 +326,71 @@ impl INode for Pipe {
         if buf.is_empty() {
             return Ok(0);
         }
-        if let PipeEnd::Read = self.direction {
+        if self.direction.reads() {
             let mut data = self.data.lock();
-            if data.buf.is_empty() && data.end_cnt == 2 {
-                Err(FsError::Again)
-            } else {
-                let len = min(buf.len(), data.buf.len());
-                for item in buf.iter_mut().take(len) {
-                    *item = data.buf.pop_front().unwrap();
+            if data.buf.is_empty() {
+                return if data.writers == 0 {
+                    Ok(0)
+                } else {
+                    Err(FsError::Again)
+                };
+            }
+            // a packet is read whole, the rest of it that does not fit is discarded
+            let (len, consumed) = match &mut data.packets {
+                Some(packets) => {
+                    let packet = packets.pop_front().unwrap();
+                    (min(buf.len(), packet), packet)
+                }
+                None => {
+                    let len = min(buf.len(), data.buf.len());
+                    (len, len)
                 }
-                if data.buf.is_empty() {
-                    data.eventbus.clear(Event::READABLE);
+            };
+            for (i, byte) in data.buf.drain(..consumed).enumerate() {
+                if i < len {
+                    buf[i] = byte;
                 }
-                Ok(len)
             }
+            data.update_events();
+            Ok(len)
         } else {
             Ok(0)
         }
     }
 
     /// write to pipe
+    ///
+    /// A broken pipe can not be told by [`FsError`], so a [`File`](super::File)
+    /// writes by [`Pipe::write`] instead.
     fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
-        if let PipeEnd::Write = self.direction {
-            let mut data = self.data.lock();
-            for c in buf {
-                data.buf.push_back(*c);
-            }
-            data.eventbus.set(Event::READABLE);
-            Ok(buf.len())
-        } else {
-            Ok(0)
+        self.write(buf).map_err(|err| match err {
+            LxError::EAGAIN => FsError::Again,
+            _ => FsError::InvalidParam,
+        })
+    }
+
+    /// The metadata of a FIFO, or of an anonymous pipe
+    fn metadata(&self) -> Result<Metadata> {
+        if let Some(fifo) = &self.fifo {
+            return fifo.metadata();
         }
+        let zero = Timespec { sec: 0, nsec: 0 };
+        Ok(Metadata {
+            dev: 0,
+            inode: Arc::as_ptr(&self.data) as *const u8 as usize,
+            size: 0,
+            blk_size: PAGE_SIZE,
+            blocks: 0,
+            atime: zero,
+            mtime: zero,
+            ctime: zero,
+            type_: FileType::NamedPipe,
+            mode: 0o600,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
     }
 
     /// monitoring events and determine whether the pipe is readable or writeable
@@ -135,7 +399,7 @@ impl INode for Pipe {
         Ok(PollStatus {
             read: self.can_read(),
             write: self.can_write(),
-            error: false,
+            error: self.direction == PipeEnd::Write && self.peer_closed(),
         })
     }
 
@@ -151,7 +415,7 @@ impl INode for Pipe {
             type Output = Result<PollStatus>;
 
             fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-                if self.pipe.can_read() || self.pipe.can_write() {
+                if self.pipe.can_read() || self.pipe.can_write() || self.pipe.peer_closed() {
                     return Poll::Ready(self.pipe.poll());
                 }
                 let waker = cx.waker().clone();
@@ -174,3 +438,34 @@ impl INode for Pipe {
         self
     }
 }
+
+/// Waits in the open of a FIFO until the other end is opened.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct FifoOpenFuture<'a> {
+    pipe: &'a Pipe,
+    /// the numbers of ends opened before this one
+    read_opens: usize,
+    write_opens: usize,
+}
+
+impl<'a> Future for FifoOpenFuture<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut data = self.pipe.data.lock();
+        let ready = match self.pipe.direction {
+            PipeEnd::Read => data.writers > 0 || data.write_opens != self.write_opens,
+            PipeEnd::Write => data.readers > 0 || data.read_opens != self.read_opens,
+            PipeEnd::Both => true,
+        };
+        if ready {
+            return Poll::Ready(());
+        }
+        let waker = cx.waker().clone();
+        data.eventbus.subscribe(Box::new(move |_| {
+            waker.wake_by_ref();
+            true
+        }));
+        Poll::Pending
+    }
+}
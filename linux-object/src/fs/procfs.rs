@@ -0,0 +1,728 @@
+//! Implement a synthetic `/proc` backed by live process state
+#![deny(missing_docs)]
+
+use crate::pid_ns::PidNamespace;
+use crate::process::ProcessExt;
+use crate::thread::current_thread;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::any::Any;
+use core::fmt::Write;
+use rcore_fs::vfs::*;
+use zircon_object::{
+    object::{KernelObject, KoID},
+    task::{Process, Status},
+    util::syscall_stats::{syscall_stats, SyscallTable, LATENCY_BUCKETS},
+    vm::{vmo_page_bytes, MMUFlags, PAGE_SIZE},
+};
+
+/// Inode id of the root directory.
+const ROOT_INODE_ID: usize = 1;
+/// Inode id of `/proc/meminfo`.
+const MEMINFO_INODE_ID: usize = 2;
+/// Inode id of the directory `/proc/zcore`.
+const ZCORE_INODE_ID: usize = 3;
+/// Inode id of `/proc/zcore/syscalls`.
+const SYSCALLS_INODE_ID: usize = 4;
+
+/// The proc file system.
+///
+/// It holds no state but the PID namespace it shows: every entry is generated
+/// from the processes in the job of the current process at the time it is
+/// looked up or read, named by their PIDs in the namespace.
+pub struct ProcFS {
+    pid_ns: Arc<PidNamespace>,
+}
+
+impl ProcFS {
+    /// Create a new proc file system, showing the processes of `pid_ns`.
+    pub fn new(pid_ns: Arc<PidNamespace>) -> Arc<Self> {
+        Arc::new(ProcFS { pid_ns })
+    }
+}
+
+impl FileSystem for ProcFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        Arc::new(ProcRoot {
+            pid_ns: self.pid_ns.clone(),
+        })
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+fn current_process() -> Option<Arc<Process>> {
+    current_thread().map(|thread| thread.proc().clone())
+}
+
+/// Find the process `pid` in the job of the current process.
+fn find_process(pid: KoID) -> Result<Arc<Process>> {
+    let current = current_process().ok_or(FsError::EntryNotFound)?;
+    if current.id() == pid {
+        return Ok(current);
+    }
+    current
+        .job()
+        .get_child(pid)
+        .ok()
+        .and_then(|obj| obj.downcast_arc::<Process>().ok())
+        .ok_or(FsError::EntryNotFound)
+}
+
+fn dir_metadata(inode: usize) -> Metadata {
+    Metadata {
+        dev: 0,
+        inode,
+        size: 0,
+        blk_size: 0,
+        blocks: 0,
+        atime: Timespec { sec: 0, nsec: 0 },
+        mtime: Timespec { sec: 0, nsec: 0 },
+        ctime: Timespec { sec: 0, nsec: 0 },
+        type_: FileType::Dir,
+        mode: 0o555,
+        nlinks: 2,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+    }
+}
+
+/// The root directory `/proc`
+struct ProcRoot {
+    pid_ns: Arc<PidNamespace>,
+}
+
+impl ProcRoot {
+    /// Returns the PIDs of the processes in the namespace.
+    fn pids(&self) -> Vec<KoID> {
+        if !self.pid_ns.is_initial() {
+            return self
+                .pid_ns
+                .koids()
+                .into_iter()
+                .filter_map(|koid| self.pid_ns.pid_of(koid))
+                .collect();
+        }
+        current_process()
+            .map(|proc| proc.job().process_ids())
+            .unwrap_or_default()
+    }
+
+    /// Returns the current process, if it is in the namespace.
+    fn current_process(&self) -> Result<Arc<Process>> {
+        let proc = current_process().ok_or(FsError::EntryNotFound)?;
+        self.pid_ns
+            .pid_of(proc.id())
+            .ok_or(FsError::EntryNotFound)?;
+        Ok(proc)
+    }
+}
+
+impl INode for ProcRoot {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(dir_metadata(ROOT_INODE_ID))
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        match name {
+            "." | ".." => Ok(Arc::new(ProcRoot {
+                pid_ns: self.pid_ns.clone(),
+            })),
+            "meminfo" => Ok(Arc::new(ProcKernelFile::meminfo())),
+            "zcore" => Ok(Arc::new(ProcZcoreDir {
+                pid_ns: self.pid_ns.clone(),
+            })),
+            "mounts" => Ok(Arc::new(ProcFile {
+                pid_ns: self.pid_ns.clone(),
+                pid: self.current_process()?.id(),
+                kind: ProcFileKind::Mounts,
+            })),
+            "self" => Ok(Arc::new(ProcPidDir {
+                pid_ns: self.pid_ns.clone(),
+                pid: self.current_process()?.id(),
+            })),
+            _ => {
+                let pid: KoID = name.parse().map_err(|_| FsError::EntryNotFound)?;
+                let koid = self.pid_ns.koid_of(pid).ok_or(FsError::EntryNotFound)?;
+                find_process(koid)?;
+                Ok(Arc::new(ProcPidDir {
+                    pid_ns: self.pid_ns.clone(),
+                    pid: koid,
+                }))
+            }
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            2 => Ok(String::from("self")),
+            3 => Ok(String::from("meminfo")),
+            4 => Ok(String::from("zcore")),
+            5 => Ok(String::from("mounts")),
+            i => self
+                .pids()
+                .get(i - 6)
+                .map(|pid| pid.to_string())
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The directory `/proc/[pid]`
+struct ProcPidDir {
+    pid_ns: Arc<PidNamespace>,
+    /// The KoID of the process
+    pid: KoID,
+}
+
+impl INode for ProcPidDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(dir_metadata(self.pid as usize * ProcFileKind::ALL.len()))
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        match name {
+            "." => Ok(Arc::new(ProcPidDir {
+                pid_ns: self.pid_ns.clone(),
+                pid: self.pid,
+            })),
+            ".." => Ok(Arc::new(ProcRoot {
+                pid_ns: self.pid_ns.clone(),
+            })),
+            _ => {
+                let kind = ProcFileKind::ALL
+                    .iter()
+                    .find(|kind| kind.name() == name)
+                    .ok_or(FsError::EntryNotFound)?;
+                find_process(self.pid)?;
+                Ok(Arc::new(ProcFile {
+                    pid_ns: self.pid_ns.clone(),
+                    pid: self.pid,
+                    kind: *kind,
+                }))
+            }
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => ProcFileKind::ALL
+                .get(i - 2)
+                .map(|kind| String::from(kind.name()))
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Files in the directory `/proc/[pid]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcFileKind {
+    Cmdline,
+    Exe,
+    Maps,
+    Mounts,
+    Stat,
+    Status,
+    Strace,
+}
+
+impl ProcFileKind {
+    const ALL: [ProcFileKind; 7] = [
+        ProcFileKind::Cmdline,
+        ProcFileKind::Exe,
+        ProcFileKind::Maps,
+        ProcFileKind::Mounts,
+        ProcFileKind::Stat,
+        ProcFileKind::Status,
+        ProcFileKind::Strace,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ProcFileKind::Cmdline => "cmdline",
+            ProcFileKind::Exe => "exe",
+            ProcFileKind::Maps => "maps",
+            ProcFileKind::Mounts => "mounts",
+            ProcFileKind::Stat => "stat",
+            ProcFileKind::Status => "status",
+            ProcFileKind::Strace => "strace",
+        }
+    }
+}
+
+/// A file in `/proc/[pid]`, whose content is generated on every read
+struct ProcFile {
+    pid_ns: Arc<PidNamespace>,
+    /// The KoID of the process
+    pid: KoID,
+    kind: ProcFileKind,
+}
+
+impl ProcFile {
+    fn content(&self) -> Result<Vec<u8>> {
+        let proc = find_process(self.pid)?;
+        let content = match self.kind {
+            ProcFileKind::Cmdline => {
+                let mut s = String::new();
+                for arg in proc.linux().args() {
+                    s += &arg;
+                    s.push('\0');
+                }
+                s
+            }
+            ProcFileKind::Exe => proc.linux().execute_path(),
+            ProcFileKind::Maps => maps(&proc),
+            ProcFileKind::Mounts => proc.linux().mount_ns().mounts_info(),
+            ProcFileKind::Stat => stat(&proc, &self.pid_ns),
+            ProcFileKind::Status => status(&proc, &self.pid_ns),
+            ProcFileKind::Strace => format!("{}\n", proc.linux().strace() as u8),
+        };
+        Ok(content.into_bytes())
+    }
+}
+
+/// Read the generated `content` of a file at `offset`.
+fn read_content(content: &[u8], offset: usize, buf: &mut [u8]) -> Result<usize> {
+    if offset >= content.len() {
+        return Ok(0);
+    }
+    let len = (content.len() - offset).min(buf.len());
+    buf[..len].copy_from_slice(&content[offset..offset + len]);
+    Ok(len)
+}
+
+fn file_metadata(inode: usize) -> Metadata {
+    Metadata {
+        dev: 0,
+        inode,
+        // like Linux, the size is unknown until the content is generated
+        size: 0,
+        blk_size: 0,
+        blocks: 0,
+        atime: Timespec { sec: 0, nsec: 0 },
+        mtime: Timespec { sec: 0, nsec: 0 },
+        ctime: Timespec { sec: 0, nsec: 0 },
+        type_: FileType::File,
+        mode: 0o444,
+        nlinks: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+    }
+}
+
+impl INode for ProcFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        read_content(&self.content()?, offset, buf)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.kind != ProcFileKind::Strace {
+            return Err(FsError::NotSupported);
+        }
+        // `1` to log the syscalls of the process, `0` to stop
+        let enabled = match core::str::from_utf8(buf).map(str::trim) {
+            Ok("0") => false,
+            Ok("1") => true,
+            _ => return Err(FsError::InvalidParam),
+        };
+        find_process(self.pid)?.linux().set_strace(enabled);
+        Ok(buf.len())
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        // truncated by the shell redirection before written
+        if self.kind == ProcFileKind::Strace {
+            Ok(())
+        } else {
+            Err(FsError::NotSupported)
+        }
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: self.kind == ProcFileKind::Strace,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let index = ProcFileKind::ALL.iter().position(|k| *k == self.kind);
+        let mut metadata =
+            file_metadata(self.pid as usize * ProcFileKind::ALL.len() + index.unwrap() + 1);
+        match self.kind {
+            ProcFileKind::Exe => {
+                metadata.type_ = FileType::SymLink;
+                metadata.mode = 0o777;
+            }
+            ProcFileKind::Strace => metadata.mode = 0o644,
+            _ => {}
+        }
+        Ok(metadata)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The directory `/proc/zcore`, for the statistics specific to zCore
+struct ProcZcoreDir {
+    pid_ns: Arc<PidNamespace>,
+}
+
+impl INode for ProcZcoreDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(dir_metadata(ZCORE_INODE_ID))
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        match name {
+            "." => Ok(Arc::new(ProcZcoreDir {
+                pid_ns: self.pid_ns.clone(),
+            })),
+            ".." => Ok(Arc::new(ProcRoot {
+                pid_ns: self.pid_ns.clone(),
+            })),
+            "syscalls" => Ok(Arc::new(ProcKernelFile::syscalls())),
+            _ => Err(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            2 => Ok(String::from("syscalls")),
+            _ => Err(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file of kernel wide state, such as `/proc/meminfo`
+struct ProcKernelFile {
+    inode_id: usize,
+    content: fn() -> String,
+}
+
+impl ProcKernelFile {
+    fn meminfo() -> Self {
+        ProcKernelFile {
+            inode_id: MEMINFO_INODE_ID,
+            content: meminfo,
+        }
+    }
+
+    fn syscalls() -> Self {
+        ProcKernelFile {
+            inode_id: SYSCALLS_INODE_ID,
+            content: syscalls,
+        }
+    }
+}
+
+impl INode for ProcKernelFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        read_content((self.content)().as_bytes(), offset, buf)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(file_metadata(self.inode_id))
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The file name of the executable, truncated to 15 bytes like `TASK_COMM_LEN`.
+fn comm(proc: &Process) -> String {
+    let path = proc.linux().execute_path();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    name.chars().take(15).collect()
+}
+
+/// The state character and its description, as shown in `stat` and `status`.
+fn state(proc: &Process) -> (char, &'static str) {
+    match proc.status() {
+        Status::Exited(_) => ('Z', "zombie"),
+        _ if proc.linux().is_stopped() => ('T', "stopped"),
+        _ => ('R', "running"),
+    }
+}
+
+/// Returns the ID of the process `koid` in `pid_ns`, or 0 if it is not in it.
+fn pid_in(pid_ns: &PidNamespace, koid: KoID) -> KoID {
+    pid_ns.pid_of(koid).unwrap_or(0)
+}
+
+fn ppid(proc: &Process, pid_ns: &PidNamespace) -> KoID {
+    proc.linux()
+        .parent()
+        .map_or(0, |parent| pid_in(pid_ns, parent.id()))
+}
+
+/// Returns the PIDs of `proc` in the namespaces from `pid_ns` to its own.
+fn nspids(proc: &Process, pid_ns: &PidNamespace) -> Vec<KoID> {
+    let mut pids = Vec::new();
+    let mut ns = Some(proc.linux().pid_ns());
+    while let Some(current) = ns {
+        pids.push(pid_in(&current, proc.id()));
+        if core::ptr::eq(&*current, pid_ns) {
+            break;
+        }
+        ns = current.parent().cloned();
+    }
+    pids.reverse();
+    pids
+}
+
+/// Returns the virtual size and the resident size in bytes.
+fn memory_usage(proc: &Process) -> (usize, usize) {
+    proc.vmar()
+        .get_mappings_info()
+        .iter()
+        .fold((0, 0), |(vsize, rss), info| {
+            (vsize + info.size, rss + info.committed_bytes)
+        })
+}
+
+fn maps(proc: &Process) -> String {
+    let mut s = String::new();
+    for info in proc.vmar().get_mappings_info() {
+        let flag = |f: MMUFlags, c: char| if info.flags.contains(f) { c } else { '-' };
+        writeln!(
+            s,
+            "{:08x}-{:08x} {}{}{}p {:08x} 00:00 {:<10} {}",
+            info.addr,
+            info.addr + info.size,
+            flag(MMUFlags::READ, 'r'),
+            flag(MMUFlags::WRITE, 'w'),
+            flag(MMUFlags::EXECUTE, 'x'),
+            info.vmo_offset,
+            info.vmo_koid,
+            info.vmo_name,
+        )
+        .unwrap();
+    }
+    s
+}
+
+fn stat(proc: &Process, pid_ns: &PidNamespace) -> String {
+    let linux = proc.linux();
+    let (vsize, rss) = memory_usage(proc);
+    let mut s = String::new();
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags
+    write!(
+        s,
+        "{} ({}) {} {} {} {} 0 -1 0",
+        pid_in(pid_ns, proc.id()),
+        comm(proc),
+        state(proc).0,
+        ppid(proc, pid_ns),
+        pid_in(pid_ns, linux.pgid()),
+        pid_in(pid_ns, linux.sid()),
+    )
+    .unwrap();
+    // minflt cminflt majflt cmajflt utime stime cutime cstime priority nice
+    s += " 0 0 0 0 0 0 0 0 20 0";
+    // num_threads itrealvalue starttime vsize rss
+    write!(
+        s,
+        " {} 0 0 {} {}",
+        proc.thread_ids().len(),
+        vsize,
+        rss / PAGE_SIZE
+    )
+    .unwrap();
+    // the remaining fields from rsslim to exit_code are not tracked
+    for _ in 25..=52 {
+        s += " 0";
+    }
+    s.push('\n');
+    s
+}
+
+fn status(proc: &Process, pid_ns: &PidNamespace) -> String {
+    let (state, desc) = state(proc);
+    let (vsize, rss) = memory_usage(proc);
+    let cred = proc.linux().cred();
+    let mut s = String::new();
+    writeln!(s, "Name:\t{}", comm(proc)).unwrap();
+    writeln!(s, "State:\t{} ({})", state, desc).unwrap();
+    let pid = pid_in(pid_ns, proc.id());
+    writeln!(s, "Tgid:\t{}", pid).unwrap();
+    writeln!(s, "Pid:\t{}", pid).unwrap();
+    writeln!(s, "PPid:\t{}", ppid(proc, pid_ns)).unwrap();
+    writeln!(
+        s,
+        "Uid:\t{}\t{}\t{}\t{}",
+        cred.uid, cred.euid, cred.suid, cred.fsuid
+    )
+    .unwrap();
+    writeln!(
+        s,
+        "Gid:\t{}\t{}\t{}\t{}",
+        cred.gid, cred.egid, cred.sgid, cred.fsgid
+    )
+    .unwrap();
+    s += "Groups:\t";
+    for gid in cred.groups.iter() {
+        write!(s, "{} ", gid).unwrap();
+    }
+    s.push('\n');
+    writeln!(s, "VmSize:\t{:>8} kB", vsize / 1024).unwrap();
+    writeln!(s, "VmRSS:\t{:>8} kB", rss / 1024).unwrap();
+    writeln!(s, "Threads:\t{}", proc.thread_ids().len()).unwrap();
+    s += "NSpid:";
+    for pid in nspids(proc, pid_ns) {
+        write!(s, "\t{}", pid).unwrap();
+    }
+    s.push('\n');
+    writeln!(s, "CapInh:\t{:016x}", cred.cap_inheritable.bits()).unwrap();
+    writeln!(s, "CapPrm:\t{:016x}", cred.cap_permitted.bits()).unwrap();
+    writeln!(s, "CapEff:\t{:016x}", cred.cap_effective.bits()).unwrap();
+    s
+}
+
+fn meminfo() -> String {
+    let frames = kernel_hal::mem::frame_stats();
+    let kb = |frames: usize| frames * PAGE_SIZE / 1024;
+    let vmo_kb = vmo_page_bytes() / 1024;
+    let mut s = String::new();
+    writeln!(s, "MemTotal:       {:>8} kB", kb(frames.total)).unwrap();
+    writeln!(s, "MemFree:        {:>8} kB", kb(frames.free())).unwrap();
+    writeln!(s, "MemAvailable:   {:>8} kB", kb(frames.free())).unwrap();
+    writeln!(s, "AnonPages:      {:>8} kB", vmo_kb).unwrap();
+    writeln!(
+        s,
+        "Unevictable:    {:>8} kB",
+        kb(frames.allocated).saturating_sub(vmo_kb)
+    )
+    .unwrap();
+    s
+}
+
+/// Syscalls invoked at least once, the most invoked first.
+///
+/// Empty unless zCore is built with the feature `syscall-stats`.
+fn syscalls() -> String {
+    let mut infos = syscall_stats(SyscallTable::Zircon);
+    infos.extend(syscall_stats(SyscallTable::Linux));
+    infos.sort_by(|a, b| b.count.cmp(&a.count));
+    let mut s = String::new();
+    write!(s, "table   num      count   total_us     avg_us     max_us").unwrap();
+    // the upper bounds of the latency histogram buckets
+    for i in 0..LATENCY_BUCKETS - 1 {
+        write!(s, " {:>7}", format!("<{}us", 1u64 << i)).unwrap();
+    }
+    writeln!(s, " {:>7}", "longer").unwrap();
+    for info in infos {
+        let table = if info.table == SyscallTable::Linux as u32 {
+            "linux"
+        } else {
+            "zircon"
+        };
+        write!(
+            s,
+            "{:<6} {:>4} {:>10} {:>10} {:>10} {:>10}",
+            table,
+            info.num,
+            info.count,
+            info.total_ns / 1000,
+            info.total_ns / info.count / 1000,
+            info.max_ns / 1000,
+        )
+        .unwrap();
+        for count in info.histogram {
+            write!(s, " {:>7}", count).unwrap();
+        }
+        s.push('\n');
+    }
+    s
+}
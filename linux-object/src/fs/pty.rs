@@ -0,0 +1,533 @@
+//! Implement pseudo-terminal master/slave pairs, `/dev/ptmx` and `/dev/pts`
+#![deny(missing_docs)]
+
+use super::ioctl::*;
+use super::tty::*;
+use crate::signal::Signal;
+use crate::sync::{Event, EventBus};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use core::cmp::min;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use lazy_static::lazy_static;
+use lock::Mutex;
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+use zircon_object::object::KoID;
+
+/// Maximum number of pseudo-terminal pairs.
+const MAX_PTY: usize = 256;
+
+/// Mutable state of a pseudo-terminal pair
+struct PtyInner {
+    ldisc: LineDiscipline,
+    /// bytes written by the slave, to be read by the master
+    output: VecDeque<u8>,
+    winsize: WinSize,
+    /// foreground process group
+    foreground: TtyForeground,
+    /// the slave can not be opened while locked
+    locked: bool,
+    /// the master side has been closed
+    hangup: bool,
+    /// readable events for the master side
+    master_bus: EventBus,
+    /// readable events for the slave side
+    slave_bus: EventBus,
+}
+
+/// Shared state of a pseudo-terminal pair
+pub struct PtyData {
+    index: usize,
+    slave_inode_id: usize,
+    inner: Mutex<PtyInner>,
+}
+
+impl PtyData {
+    /// Apply output processing and queue `buf` for the master.
+    fn push_output(inner: &mut PtyInner, buf: &[u8]) {
+        let oflag = inner.ldisc.termios.oflag();
+        let onlcr = oflag.contains(OutputFlags::OPOST | OutputFlags::ONLCR);
+        for &c in buf {
+            if onlcr && c == b'\n' {
+                inner.output.push_back(b'\r');
+            }
+            inner.output.push_back(c);
+        }
+        if !inner.output.is_empty() {
+            inner.master_bus.set(Event::READABLE);
+        }
+    }
+
+    /// Handle terminal ioctls shared by both sides.
+    #[allow(unsafe_code)]
+    fn tty_ioctl(&self, cmd: usize, data: usize) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        match cmd {
+            TCGETS => {
+                unsafe { *(data as *mut Termios) = inner.ldisc.termios };
+                Ok(0)
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = unsafe { *(data as *const Termios) };
+                inner.ldisc.set_termios(termios, cmd == TCSETSF);
+                if inner.ldisc.can_read() {
+                    inner.slave_bus.set(Event::READABLE);
+                }
+                Ok(0)
+            }
+            TIOCGWINSZ => {
+                unsafe { *(data as *mut WinSize) = inner.winsize };
+                Ok(0)
+            }
+            TIOCSWINSZ => {
+                inner.winsize = unsafe { *(data as *const WinSize) };
+                inner.foreground.signal(Signal::SIGWINCH);
+                Ok(0)
+            }
+            TIOCGPGRP => {
+                unsafe { *(data as *mut u32) = inner.foreground.get_or_acquire() as u32 };
+                Ok(0)
+            }
+            TIOCSPGRP => {
+                let pgid = unsafe { *(data as *const u32) } as KoID;
+                inner.foreground.set_pgid(pgid)?;
+                Ok(0)
+            }
+            TIOCSCTTY => {
+                inner.foreground.set_controlling();
+                Ok(0)
+            }
+            TIOCNOTTY => Ok(0),
+            FIONREAD => {
+                unsafe { *(data as *mut u32) = inner.ldisc.input.len() as u32 };
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn metadata(&self, inode: usize, rdev: usize, mode: u32) -> Metadata {
+        Metadata {
+            dev: 1,
+            inode,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev,
+        }
+    }
+}
+
+lazy_static! {
+    /// allocated pseudo-terminal pairs, indexed by the pty number
+    static ref PTYS: Mutex<BTreeMap<usize, Weak<PtyData>>> = Mutex::new(BTreeMap::new());
+}
+
+/// Future waiting for the `READABLE` event of one side of a pty.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct PtyFuture<'a> {
+    data: &'a PtyData,
+    master: bool,
+}
+
+impl<'a> Future for PtyFuture<'a> {
+    type Output = Result<PollStatus>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = if self.master {
+            master_status(self.data)
+        } else {
+            slave_status(self.data)
+        };
+        if status.read || status.error {
+            return Poll::Ready(Ok(status));
+        }
+        let waker = cx.waker().clone();
+        let mut inner = self.data.inner.lock();
+        let bus = if self.master {
+            &mut inner.master_bus
+        } else {
+            &mut inner.slave_bus
+        };
+        bus.subscribe(Box::new(move |_| {
+            waker.wake_by_ref();
+            true
+        }));
+        Poll::Pending
+    }
+}
+
+fn master_status(data: &PtyData) -> PollStatus {
+    let inner = data.inner.lock();
+    PollStatus {
+        read: !inner.output.is_empty(),
+        write: true,
+        error: false,
+    }
+}
+
+fn slave_status(data: &PtyData) -> PollStatus {
+    let inner = data.inner.lock();
+    PollStatus {
+        read: inner.ldisc.can_read() || inner.hangup,
+        write: !inner.hangup,
+        error: inner.hangup,
+    }
+}
+
+/// The master side of a pseudo-terminal, created by opening `/dev/ptmx`
+pub struct PtyMaster {
+    data: Arc<PtyData>,
+    inode_id: usize,
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        PTYS.lock().remove(&self.data.index);
+        let mut inner = self.data.inner.lock();
+        inner.hangup = true;
+        inner.slave_bus.set(Event::READABLE | Event::CLOSED);
+    }
+}
+
+impl INode for PtyMaster {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut inner = self.data.inner.lock();
+        if inner.output.is_empty() {
+            return Err(FsError::Again);
+        }
+        let len = min(buf.len(), inner.output.len());
+        for (dst, src) in buf.iter_mut().zip(inner.output.drain(..len)) {
+            *dst = src;
+        }
+        if inner.output.is_empty() {
+            inner.master_bus.clear(Event::READABLE);
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.data.inner.lock();
+        let mut echo = Vec::new();
+        let mut signals = Vec::new();
+        for &c in buf {
+            if let Some(signal) = inner.ldisc.receive(c, &mut echo) {
+                signals.push(signal);
+            }
+        }
+        PtyData::push_output(&mut inner, &echo);
+        if inner.ldisc.can_read() {
+            inner.slave_bus.set(Event::READABLE);
+        }
+        let foreground = inner.foreground.clone();
+        drop(inner);
+        for signal in signals {
+            foreground.signal(signal);
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(master_status(&self.data))
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        Box::pin(PtyFuture {
+            data: &self.data,
+            master: true,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(self.data.metadata(self.inode_id, make_rdev(5, 2), 0o666))
+    }
+
+    #[allow(unsafe_code)]
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            TIOCGPTN => {
+                unsafe { *(data as *mut u32) = self.data.index as u32 };
+                Ok(0)
+            }
+            TIOCSPTLCK => {
+                self.data.inner.lock().locked = unsafe { *(data as *const i32) } != 0;
+                Ok(0)
+            }
+            cmd => self.data.tty_ioctl(cmd, data),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The slave side of a pseudo-terminal, found at `/dev/pts/N`
+pub struct PtySlave {
+    data: Arc<PtyData>,
+}
+
+impl INode for PtySlave {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let foreground = self.data.inner.lock().foreground.clone();
+        foreground.check_access(Signal::SIGTTIN)?;
+        let mut inner = self.data.inner.lock();
+        // non-canonical read with `VMIN == 0` does not block
+        let no_wait = inner.ldisc.termios.cc[VMIN] == 0
+            && !inner.ldisc.termios.lflag().contains(LocalFlags::ICANON);
+        match inner.ldisc.read(buf) {
+            Some(len) => {
+                if !inner.ldisc.can_read() {
+                    inner.slave_bus.clear(Event::READABLE);
+                }
+                Ok(len)
+            }
+            // the master is closed, return end-of-file
+            None if inner.hangup => Ok(0),
+            None if no_wait => Ok(0),
+            None => Err(FsError::Again),
+        }
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        let (foreground, tostop) = {
+            let inner = self.data.inner.lock();
+            let tostop = inner.ldisc.termios.lflag().contains(LocalFlags::TOSTOP);
+            (inner.foreground.clone(), tostop)
+        };
+        if tostop {
+            foreground.check_access(Signal::SIGTTOU)?;
+        }
+        let mut inner = self.data.inner.lock();
+        if inner.hangup {
+            return Err(FsError::DeviceError);
+        }
+        PtyData::push_output(&mut inner, buf);
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(slave_status(&self.data))
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        Box::pin(PtyFuture {
+            data: &self.data,
+            master: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(self.data.metadata(
+            self.data.slave_inode_id,
+            make_rdev(136, self.data.index),
+            0o620,
+        ))
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        self.data.tty_ioctl(cmd as usize, data)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The `/dev/ptmx` device, opening it creates a new pseudo-terminal pair
+pub struct Ptmx {
+    inode_id: usize,
+}
+
+impl Default for Ptmx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ptmx {
+    /// Create the `/dev/ptmx` INode.
+    pub fn new() -> Self {
+        Ptmx {
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+
+    /// Allocate a new pseudo-terminal pair, returning the master.
+    ///
+    /// The slave is locked until `TIOCSPTLCK` is issued on the master.
+    pub fn open(&self) -> Result<Arc<dyn INode>> {
+        let mut ptys = PTYS.lock();
+        let index = (0..MAX_PTY)
+            .find(|i| !ptys.contains_key(i))
+            .ok_or(FsError::NoDeviceSpace)?;
+        let data = Arc::new(PtyData {
+            index,
+            slave_inode_id: DevFS::new_inode_id(),
+            inner: Mutex::new(PtyInner {
+                ldisc: LineDiscipline::default(),
+                output: VecDeque::new(),
+                winsize: WinSize::default(),
+                foreground: TtyForeground::default(),
+                locked: true,
+                hangup: false,
+                master_bus: EventBus::default(),
+                slave_bus: EventBus::default(),
+            }),
+        });
+        ptys.insert(index, Arc::downgrade(&data));
+        Ok(Arc::new(PtyMaster {
+            data,
+            inode_id: DevFS::new_inode_id(),
+        }))
+    }
+}
+
+impl INode for Ptmx {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::NotSupported)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(5, 2),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The `/dev/pts` directory, listing the slaves of allocated pairs
+pub struct PtsDir {
+    inode_id: usize,
+}
+
+impl Default for PtsDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PtsDir {
+    /// Create the `/dev/pts` INode.
+    pub fn new() -> Self {
+        PtsDir {
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+
+    /// Indices of the currently allocated pairs.
+    fn indices(&self) -> Vec<usize> {
+        PTYS.lock()
+            .iter()
+            .filter(|(_, data)| data.strong_count() > 0)
+            .map(|(&index, _)| index)
+            .collect()
+    }
+}
+
+impl INode for PtsDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::Dir,
+            mode: 0o755,
+            nlinks: 2,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let index: usize = name.parse().map_err(|_| FsError::EntryNotFound)?;
+        let data = PTYS
+            .lock()
+            .get(&index)
+            .and_then(|data| data.upgrade())
+            .ok_or(FsError::EntryNotFound)?;
+        if data.inner.lock().locked {
+            return Err(FsError::Busy);
+        }
+        Ok(Arc::new(PtySlave { data }))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => self
+                .indices()
+                .get(i - 2)
+                .map(|index| index.to_string())
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,224 @@
+//! Implement signalfd file object
+#![deny(missing_docs)]
+
+use alloc::{boxed::Box, sync::Arc};
+
+use async_trait::async_trait;
+use lock::Mutex;
+use rcore_fs::vfs::PollStatus;
+use zircon_object::object::*;
+
+use super::{ChangeCallback, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::signal::{SignalCode, Sigset};
+use crate::sync::{wait_for_event, Event, EventBus};
+use crate::thread::{current_thread, ThreadExt};
+
+bitflags::bitflags! {
+    /// Flags for `signalfd4`
+    pub struct SignalFdFlags: usize {
+        /// Set the close-on-exec flag on the new file descriptor.
+        const CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the new open file description.
+        const NONBLOCK = 0o4000;
+    }
+}
+
+/// Linux struct signalfd_siginfo
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SignalFdSigInfo {
+    /// signal number
+    pub signo: u32,
+    /// error number (unused)
+    pub errno: i32,
+    /// signal code
+    pub code: i32,
+    /// PID of sender
+    pub pid: u32,
+    /// real UID of sender
+    pub uid: u32,
+    /// file descriptor (SIGIO)
+    pub fd: i32,
+    /// kernel timer ID (POSIX timers)
+    pub tid: u32,
+    /// band event (SIGIO)
+    pub band: u32,
+    /// POSIX timer overrun count
+    pub overrun: u32,
+    /// trap number that caused signal
+    pub trapno: u32,
+    /// exit status or signal (SIGCHLD)
+    pub status: i32,
+    /// integer sent by sigqueue
+    pub int: i32,
+    /// pointer sent by sigqueue
+    pub ptr: u64,
+    /// user CPU time consumed (SIGCHLD)
+    pub utime: u64,
+    /// system CPU time consumed (SIGCHLD)
+    pub stime: u64,
+    /// address that generated signal
+    pub addr: u64,
+    /// least significant bit of address
+    pub addr_lsb: u16,
+    _pad: [u8; 46],
+}
+
+/// The size of `struct signalfd_siginfo`.
+const SIGINFO_SIZE: usize = core::mem::size_of::<SignalFdSigInfo>();
+
+/// A file object that accepts signals targeted at the caller.
+pub struct SignalFd {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    mask: Arc<Mutex<Sigset>>,
+    /// event bus of the owner process
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+impl_kobject!(SignalFd);
+
+impl SignalFd {
+    /// Create a new signalfd accepting signals in `mask`.
+    pub fn new(mask: Sigset, flags: SignalFdFlags, eventbus: Arc<Mutex<EventBus>>) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDONLY;
+        open_flags.set(
+            OpenFlags::NON_BLOCK,
+            flags.contains(SignalFdFlags::NONBLOCK),
+        );
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(SignalFdFlags::CLOEXEC));
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(SignalFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(open_flags),
+            description: description.clone(),
+            mask: Arc::new(Mutex::new(mask)),
+            eventbus,
+        })
+    }
+
+    /// Replace the signal mask of an existing signalfd.
+    pub fn set_mask(&self, mask: Sigset) {
+        *self.mask.lock() = mask;
+    }
+
+    /// Dequeue a pending signal of the current thread which is in the mask.
+    fn dequeue(&self) -> Option<SignalFdSigInfo> {
+        let thread = current_thread()?;
+        let mut linux = thread.lock_linux();
+        let mask = *self.mask.lock();
+        let pending = Sigset::new(linux.signals.val() & mask.val());
+        let signal = pending.find_first_signal()?;
+        linux.signals.remove(signal);
+        Some(SignalFdSigInfo {
+            signo: signal as u32,
+            code: SignalCode::USER as i32,
+            ..Default::default()
+        })
+    }
+
+    /// Is there any pending signal of the current thread in the mask.
+    fn has_pending(&self) -> bool {
+        match current_thread() {
+            Some(thread) => thread.lock_linux().signals.val() & self.mask.lock().val() != 0,
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for SignalFd {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(SignalFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            mask: self.mask.clone(),
+            eventbus: self.eventbus.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        if buf.len() < SIGINFO_SIZE {
+            return Err(LxError::EINVAL);
+        }
+        loop {
+            let mut len = 0;
+            while len + SIGINFO_SIZE <= buf.len() {
+                match self.dequeue() {
+                    Some(info) => {
+                        #[allow(unsafe_code)]
+                        let bytes = unsafe {
+                            core::slice::from_raw_parts(
+                                &info as *const SignalFdSigInfo as *const u8,
+                                SIGINFO_SIZE,
+                            )
+                        };
+                        buf[len..len + SIGINFO_SIZE].copy_from_slice(bytes);
+                        len += SIGINFO_SIZE;
+                    }
+                    None => break,
+                }
+            }
+            if len > 0 {
+                return Ok(len);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            self.eventbus.lock().clear(Event::RECEIVE_SIGNAL);
+            wait_for_event(self.eventbus.clone(), Event::RECEIVE_SIGNAL).await;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        Ok(PollStatus {
+            read: self.has_pending(),
+            write: false,
+            error: false,
+        })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) && !self.has_pending() {
+            self.eventbus.lock().clear(Event::RECEIVE_SIGNAL);
+            wait_for_event(self.eventbus.clone(), Event::RECEIVE_SIGNAL).await;
+        }
+        self.poll(events)
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.eventbus.lock().subscribe_once(callback);
+        true
+    }
+}
@@ -1,10 +1,12 @@
 //! Implement INode for Stdin & Stdout
 
 use super::ioctl::*;
+use super::tty::{LineDiscipline, LocalFlags, Termios, TtyForeground, VMIN};
+use crate::signal::Signal;
 use crate::{sync::Event, sync::EventBus};
 use alloc::boxed::Box;
-use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::any::Any;
 use core::future::Future;
 use core::pin::Pin;
@@ -13,22 +15,16 @@ use kernel_hal::console::{self, ConsoleWinSize};
 use lazy_static::lazy_static;
 use lock::Mutex;
 use rcore_fs::vfs::*;
+use zircon_object::object::KoID;
 
 lazy_static! {
     /// STDIN global reference
     pub static ref STDIN: Arc<Stdin> = {
         let stdin = Arc::new(Stdin::default());
         let cloned = stdin.clone();
-        if let Some(uart) = kernel_hal::drivers::all_uart().first() {
-            uart.clone().subscribe(
-                Box::new(move |_| {
-                    while let Some(c) = uart.try_recv().unwrap_or(None) {
-                        cloned.push(c as char);
-                    }
-                }),
-                false,
-            );
-        }
+        console::console_subscribe_input(Box::new(move |_| cloned.receive()), false);
+        // the input received before subscribing
+        stdin.receive();
         stdin
     };
     /// STDOUT global reference
@@ -38,28 +34,119 @@ lazy_static! {
 /// Stdin struct, for Stdin buffer
 #[derive(Default)]
 pub struct Stdin {
-    buf: Mutex<VecDeque<char>>,
+    ldisc: Mutex<LineDiscipline>,
     eventbus: Mutex<EventBus>,
+    foreground: Mutex<TtyForeground>,
 }
 
 impl Stdin {
-    /// push a char in Stdin buffer
-    pub fn push(&self, c: char) {
-        self.buf.lock().push_back(c);
-        self.eventbus.lock().set(Event::READABLE);
-    }
-    /// pop a char in Stdin buffer
-    pub fn pop(&self) -> char {
-        let mut buf_lock = self.buf.lock();
-        let c = buf_lock.pop_front().unwrap();
-        if buf_lock.len() == 0 {
-            self.eventbus.lock().clear(Event::READABLE);
+    /// Take the input from the console, called on the serial interrupt.
+    fn receive(&self) {
+        let mut buf = [0; 64];
+        loop {
+            let len = console::console_try_read(&mut buf);
+            if len == 0 {
+                break;
+            }
+            for &c in &buf[..len] {
+                self.push(c);
+            }
         }
-        c
     }
+
+    /// push a byte in Stdin buffer
+    ///
+    /// Signal characters are not buffered, but sent to the foreground process group.
+    pub fn push(&self, c: u8) {
+        let mut echo = Vec::new();
+        let mut ldisc = self.ldisc.lock();
+        let signal = if cfg!(target_os = "none") {
+            ldisc.receive(c, &mut echo)
+        } else {
+            // the host terminal has done the line editing and echoing
+            let signal = ldisc.termios.signal_char(c);
+            if signal.is_none() {
+                ldisc.input.push_back(c);
+            }
+            signal
+        };
+        if ldisc.can_read() {
+            self.eventbus.lock().set(Event::READABLE);
+        }
+        drop(ldisc);
+        if !echo.is_empty() {
+            // a multi-byte character is echoed byte by byte
+            let s = unsafe { core::str::from_utf8_unchecked(&echo) };
+            console::console_write_str(s);
+        }
+        if let Some(signal) = signal {
+            let foreground = self.foreground.lock().clone();
+            foreground.signal(signal);
+        }
+    }
+
+    /// Returns the local modes of the console.
+    fn lflag(&self) -> LocalFlags {
+        self.ldisc.lock().termios.lflag()
+    }
+
     /// specify whether the Stdin buffer is readable
     pub fn can_read(&self) -> bool {
-        self.buf.lock().len() > 0
+        self.ldisc.lock().can_read()
+    }
+
+    /// Check whether the current process may access the console.
+    fn check_access(&self, signal: Signal) -> Result<()> {
+        let foreground = self.foreground.lock().clone();
+        foreground.check_access(signal)
+    }
+
+    /// Handle terminal ioctls of the console, shared by stdin and stdout.
+    #[allow(unsafe_code)]
+    fn tty_ioctl(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            TIOCGWINSZ => {
+                let winsize = data as *mut ConsoleWinSize;
+                unsafe { *winsize = console::console_win_size() };
+                Ok(0)
+            }
+            TCGETS => {
+                // TODO: verify pointer
+                unsafe { *(data as *mut Termios) = self.ldisc.lock().termios };
+                Ok(0)
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = unsafe { *(data as *const Termios) };
+                let mut ldisc = self.ldisc.lock();
+                ldisc.set_termios(termios, cmd as usize == TCSETSF);
+                if ldisc.can_read() {
+                    self.eventbus.lock().set(Event::READABLE);
+                } else {
+                    self.eventbus.lock().clear(Event::READABLE);
+                }
+                Ok(0)
+            }
+            TIOCGPGRP => {
+                let pgid = self.foreground.lock().get_or_acquire();
+                unsafe { *(data as *mut u32) = pgid as u32 };
+                Ok(0)
+            }
+            TIOCSPGRP => {
+                let pgid = unsafe { *(data as *const u32) } as KoID;
+                self.foreground.lock().set_pgid(pgid)?;
+                Ok(0)
+            }
+            TIOCSCTTY => {
+                self.foreground.lock().set_controlling();
+                Ok(0)
+            }
+            TIOCNOTTY => Ok(0),
+            FIONREAD => {
+                unsafe { *(data as *mut u32) = self.ldisc.lock().input.len() as u32 };
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
     }
 }
 
@@ -69,11 +156,20 @@ pub struct Stdout;
 
 impl INode for Stdin {
     fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
-        if self.can_read() {
-            buf[0] = self.pop() as u8;
-            Ok(1)
-        } else {
-            Err(FsError::Again)
+        self.check_access(Signal::SIGTTIN)?;
+        let mut ldisc = self.ldisc.lock();
+        // non-canonical read with `VMIN == 0` does not block
+        let no_wait =
+            ldisc.termios.cc[VMIN] == 0 && !ldisc.termios.lflag().contains(LocalFlags::ICANON);
+        match ldisc.read(buf) {
+            Some(len) => {
+                if !ldisc.can_read() {
+                    self.eventbus.lock().clear(Event::READABLE);
+                }
+                Ok(len)
+            }
+            None if no_wait => Ok(0),
+            None => Err(FsError::Again),
         }
     }
     fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
@@ -115,28 +211,8 @@ impl INode for Stdin {
         Box::pin(SerialFuture { stdin: self })
     }
 
-    //
     fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
-        match cmd as usize {
-            TIOCGWINSZ => {
-                let winsize = data as *mut ConsoleWinSize;
-                unsafe { *winsize = console::console_win_size() };
-                Ok(0)
-            }
-            TCGETS | TIOCSPGRP => {
-                warn!("stdin TCGETS | TIOCSPGRP, pretend to be tty.");
-                // pretend to be tty
-                Ok(0)
-            }
-            TIOCGPGRP => {
-                warn!("stdin TIOCGPGRP, pretend to be have a tty process group.");
-                // pretend to be have a tty process group
-                // TODO: verify pointer
-                unsafe { *(data as *mut u32) = 0 };
-                Ok(0)
-            }
-            _ => Err(FsError::NotSupported),
-        }
+        self.tty_ioctl(cmd, data)
     }
 
     fn as_any_ref(&self) -> &dyn Any {
@@ -149,6 +225,9 @@ impl INode for Stdout {
         unimplemented!()
     }
     fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        if STDIN.lflag().contains(LocalFlags::TOSTOP) {
+            STDIN.check_access(Signal::SIGTTOU)?;
+        }
         // we do not care the utf-8 things, we just want to print it!
         let s = unsafe { core::str::from_utf8_unchecked(buf) };
         kernel_hal::console::console_write_str(s);
@@ -162,25 +241,7 @@ impl INode for Stdout {
         })
     }
     fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
-        match cmd as usize {
-            TIOCGWINSZ => {
-                let winsize = data as *mut ConsoleWinSize;
-                unsafe { *winsize = console::console_win_size() };
-                Ok(0)
-            }
-            TCGETS | TIOCSPGRP => {
-                warn!("stdout TCGETS | TIOCSPGRP, pretend to be tty.");
-                // pretend to be tty
-                Ok(0)
-            }
-            TIOCGPGRP => {
-                // pretend to be have a tty process group
-                // TODO: verify pointer
-                unsafe { *(data as *mut u32) = 0 };
-                Ok(0)
-            }
-            _ => Err(FsError::NotSupported),
-        }
+        STDIN.tty_ioctl(cmd, data)
     }
 
     /// Get metadata of the INode
@@ -207,3 +268,55 @@ impl INode for Stdout {
         self
     }
 }
+
+/// The console terminal, i.e. `/dev/tty` and `/dev/console`
+///
+/// It reads from [`STDIN`] and writes to [`STDOUT`].
+pub struct ConsoleTty {
+    minor: usize,
+    inode_id: usize,
+}
+
+impl ConsoleTty {
+    /// Create a console terminal node with the device number `5:minor`.
+    pub fn new(minor: usize) -> Self {
+        ConsoleTty {
+            minor,
+            inode_id: rcore_fs_devfs::DevFS::new_inode_id(),
+        }
+    }
+}
+
+impl INode for ConsoleTty {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        STDIN.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        STDOUT.write_at(offset, buf)
+    }
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: STDIN.can_read(),
+            write: true,
+            error: false,
+        })
+    }
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        STDIN.async_poll()
+    }
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        STDIN.tty_ioctl(cmd, data)
+    }
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            inode: self.inode_id,
+            rdev: make_rdev(5, self.minor),
+            ..STDOUT.metadata()?
+        })
+    }
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,195 @@
+//! Implement a minimal `/sys` exporting the registered devices
+#![deny(missing_docs)]
+
+use super::devfs::DeviceNode;
+use super::pseudo::Pseudo;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+};
+use core::any::Any;
+use lock::Mutex;
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+
+/// The sys file system.
+///
+/// For each device node there are `class/<class>/<name>/{dev,uevent}`, and
+/// block devices also appear in `block/<name>`, which is what `mdev -s`
+/// scans to create the device nodes.
+pub struct SysFS {
+    root: Arc<SysDir>,
+}
+
+impl SysFS {
+    /// Create a sys file system exporting `devices`.
+    pub fn new(devices: &[DeviceNode]) -> Arc<Self> {
+        let root = SysDir::new(Weak::new());
+        let class = root.add_dir("class");
+        let block = root.add_dir("block");
+        root.add_dir("dev").add_dir("char");
+        root.add_dir("dev").add_dir("block");
+        for dev in devices {
+            let dir = class.add_dir(dev.class).add_dir(dev.name());
+            add_device_attrs(&dir, dev);
+            if dev.class == "block" {
+                add_device_attrs(&block.add_dir(dev.name()), dev);
+            }
+            let kind = if dev.class == "block" {
+                "block"
+            } else {
+                "char"
+            };
+            let by_number = format!("{}:{}", dev.major, dev.minor);
+            add_device_attrs(&root.add_dir("dev").add_dir(kind).add_dir(&by_number), dev);
+        }
+        Arc::new(SysFS { root })
+    }
+}
+
+fn add_device_attrs(dir: &SysDir, dev: &DeviceNode) {
+    dir.add_file("dev", &format!("{}:{}\n", dev.major, dev.minor));
+    let devtype = if dev.class == "block" {
+        "DEVTYPE=disk\n"
+    } else {
+        ""
+    };
+    dir.add_file(
+        "uevent",
+        &format!(
+            "MAJOR={}\nMINOR={}\nDEVNAME={}\n{}",
+            dev.major, dev.minor, dev.path, devtype
+        ),
+    );
+}
+
+impl FileSystem for SysFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+/// A read-only directory, whose entries are added when the file system is built
+struct SysDir {
+    inode_id: usize,
+    this: Weak<SysDir>,
+    parent: Weak<SysDir>,
+    dirs: Mutex<BTreeMap<String, Arc<SysDir>>>,
+    files: Mutex<BTreeMap<String, Arc<Pseudo>>>,
+}
+
+impl SysDir {
+    fn new(parent: Weak<SysDir>) -> Arc<Self> {
+        Arc::new_cyclic(|this| SysDir {
+            inode_id: DevFS::new_inode_id(),
+            this: this.clone(),
+            parent: if parent.strong_count() == 0 {
+                this.clone()
+            } else {
+                parent
+            },
+            dirs: Mutex::new(BTreeMap::new()),
+            files: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Get the sub-directory `name`, or create it if not exists.
+    fn add_dir(&self, name: &str) -> Arc<SysDir> {
+        self.dirs
+            .lock()
+            .entry(String::from(name))
+            .or_insert_with(|| SysDir::new(self.this.clone()))
+            .clone()
+    }
+
+    fn add_file(&self, name: &str, content: &str) {
+        let file = Arc::new(Pseudo::new(content, FileType::File));
+        self.files.lock().insert(String::from(name), file);
+    }
+}
+
+impl INode for SysDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 0,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::Dir,
+            mode: 0o555,
+            nlinks: 2,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let dir = match name {
+            "." => self.this.upgrade(),
+            ".." => self.parent.upgrade(),
+            _ => self.dirs.lock().get(name).cloned(),
+        };
+        if let Some(dir) = dir {
+            return Ok(dir);
+        }
+        match self.files.lock().get(name) {
+            Some(file) => Ok(file.clone()),
+            None => Err(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => {
+                let dirs = self.dirs.lock();
+                let files = self.files.lock();
+                dirs.keys()
+                    .chain(files.keys())
+                    .nth(i - 2)
+                    .cloned()
+                    .ok_or(FsError::EntryNotFound)
+            }
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
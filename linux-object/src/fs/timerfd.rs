@@ -0,0 +1,263 @@
+//! Implement timerfd file object
+#![deny(missing_docs)]
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use core::convert::TryFrom;
+use core::time::Duration;
+
+use async_trait::async_trait;
+use kernel_hal::{thread, timer};
+use lock::Mutex;
+use rcore_fs::vfs::PollStatus;
+use zircon_object::object::*;
+
+use super::{ChangeCallback, FileLike, OpenFileDescription, OpenFlags, PollEvents};
+use crate::error::{LxError, LxResult};
+use crate::sync::{wait_for_event, Event, EventBus};
+use crate::time::{realtime_to_monotonic, ClockId, ITimerSpec, TimeSpec};
+
+bitflags::bitflags! {
+    /// Flags for `timerfd_create`
+    pub struct TimerFdFlags: usize {
+        /// Set the close-on-exec flag on the new file descriptor.
+        const CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the new open file description.
+        const NONBLOCK = 0o4000;
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags for `timerfd_settime`
+    pub struct TimerFdSetFlags: usize {
+        /// Interpret `new_value.value` as an absolute value on the timer's clock.
+        const ABSTIME = 1;
+        /// Mark the timer cancelable on discontinuous changes of the realtime clock.
+        const CANCEL_ON_SET = 2;
+    }
+}
+
+/// Mutable state of a timerfd
+#[derive(Default)]
+struct TimerFdInner {
+    /// the next expiration, `None` if the timer is disarmed
+    deadline: Option<Duration>,
+    /// the period of the timer, zero for one-shot timers
+    interval: Duration,
+    /// number of expirations since the last read
+    expirations: u64,
+    /// bumped on every `settime` so that stale callbacks are ignored
+    generation: usize,
+}
+
+/// Shared state of a timerfd
+struct TimerFdData {
+    clock: usize,
+    inner: Mutex<TimerFdInner>,
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+/// A file object that delivers timer expiration notifications.
+pub struct TimerFd {
+    base: KObjectBase,
+    flags: Mutex<OpenFlags>,
+    /// shared with the duplicates
+    description: Arc<OpenFileDescription>,
+    data: Arc<TimerFdData>,
+}
+
+impl_kobject!(TimerFd);
+
+impl TimerFd {
+    /// Create a new disarmed timerfd on the given clock.
+    pub fn new(clock: usize, flags: TimerFdFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDWR;
+        open_flags.set(OpenFlags::NON_BLOCK, flags.contains(TimerFdFlags::NONBLOCK));
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(TimerFdFlags::CLOEXEC));
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(TimerFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(open_flags),
+            description: description.clone(),
+            data: Arc::new(TimerFdData {
+                clock,
+                inner: Mutex::new(TimerFdInner::default()),
+                eventbus: EventBus::new(),
+            }),
+        })
+    }
+
+    /// Returns the clock id of this timer.
+    pub fn clock(&self) -> usize {
+        self.data.clock
+    }
+
+    /// Returns the current setting of the timer.
+    pub fn get_time(&self) -> ITimerSpec {
+        let inner = self.data.inner.lock();
+        let value = match inner.deadline {
+            Some(deadline) => deadline.saturating_sub(timer::timer_now()).into(),
+            None => TimeSpec::default(),
+        };
+        ITimerSpec {
+            interval: inner.interval.into(),
+            value,
+        }
+    }
+
+    /// Arm or disarm the timer, returning the old setting.
+    pub fn set_time(&self, new: ITimerSpec, flags: TimerFdSetFlags) -> ITimerSpec {
+        let old = self.get_time();
+        let value: Duration = new.value.into();
+        let mut inner = self.data.inner.lock();
+        inner.generation += 1;
+        inner.interval = new.interval.into();
+        inner.expirations = 0;
+        self.data.eventbus.lock().clear(Event::READABLE);
+        if value.is_zero() {
+            inner.deadline = None;
+            return old;
+        }
+        let deadline = if flags.contains(TimerFdSetFlags::ABSTIME) {
+            match ClockId::try_from(self.data.clock) {
+                Ok(clock) if clock.is_realtime() => realtime_to_monotonic(value),
+                _ => value,
+            }
+        } else {
+            timer::deadline_after(value)
+        };
+        inner.deadline = Some(deadline);
+        Self::arm(Arc::downgrade(&self.data), deadline, inner.generation);
+        old
+    }
+
+    /// Spawn a task that sleeps until `deadline` and counts expirations.
+    ///
+    /// Periodic timers are re-armed from the task instead of the timer callback,
+    /// since the HAL timer queue is locked while its callbacks run.
+    fn arm(data: Weak<TimerFdData>, deadline: Duration, generation: usize) {
+        thread::spawn(async move {
+            let mut deadline = deadline;
+            loop {
+                thread::sleep_until(deadline).await;
+                match data.upgrade() {
+                    Some(data) => match Self::fire(&data, timer::timer_now(), generation) {
+                        Some(next) => deadline = next,
+                        None => break,
+                    },
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Called on timer expiration, returns the next deadline of a periodic timer.
+    fn fire(data: &Arc<TimerFdData>, now: Duration, generation: usize) -> Option<Duration> {
+        let mut inner = data.inner.lock();
+        if inner.generation != generation {
+            return None;
+        }
+        let deadline = inner.deadline?;
+        let next = if inner.interval.is_zero() {
+            inner.expirations += 1;
+            None
+        } else {
+            // count every period elapsed since the last expiration
+            let missed =
+                (now.saturating_sub(deadline).as_nanos() / inner.interval.as_nanos()) as u32;
+            inner.expirations += missed as u64 + 1;
+            Some(deadline + inner.interval * (missed + 1))
+        };
+        inner.deadline = next;
+        data.eventbus
+            .lock()
+            .change_notify(Event::empty(), Event::READABLE);
+        next
+    }
+
+    fn poll_status(&self) -> PollStatus {
+        PollStatus {
+            read: self.data.inner.lock().expirations > 0,
+            write: false,
+            error: false,
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for TimerFd {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.lock();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(TimerFd {
+            base: KObjectBase::new(),
+            flags: Mutex::new(self.flags()),
+            description: self.description.clone(),
+            data: self.data.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LxError::EINVAL);
+        }
+        loop {
+            {
+                let mut inner = self.data.inner.lock();
+                if inner.expirations > 0 {
+                    buf[..8].copy_from_slice(&inner.expirations.to_ne_bytes());
+                    inner.expirations = 0;
+                    self.data.eventbus.lock().clear(Event::READABLE);
+                    return Ok(8);
+                }
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.data.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, _events: PollEvents) -> LxResult<PollStatus> {
+        Ok(self.poll_status())
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) {
+            wait_for_event(self.data.eventbus.clone(), Event::READABLE).await;
+        }
+        Ok(self.poll_status())
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.data.eventbus.lock().subscribe_once(callback);
+        true
+    }
+}
@@ -0,0 +1,468 @@
+//! Implement tmpfs, a memory file system whose files are backed by VMOs
+#![deny(missing_docs)]
+
+use crate::time::TimeSpec;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+};
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::Mutex;
+use rcore_fs::vfs::*;
+use zircon_object::vm::{pages, roundup_pages, VmObject, PAGE_SIZE};
+
+/// Maximum length of a file name.
+const MAX_NAME_LEN: usize = 255;
+
+/// The tmpfs.
+///
+/// Every regular file or symbolic link owns a paged VMO whose length is the
+/// size limit of the file system. Pages are committed lazily, so the length
+/// costs nothing, and files never move when they grow, which allows `mmap` to
+/// share pages with the file. The space in use is accounted in pages by the
+/// file sizes, and writes beyond the size limit fail with `ENOSPC`.
+pub struct TmpFS {
+    size_limit: usize,
+    used: Mutex<usize>,
+    root: Arc<TmpINode>,
+}
+
+impl TmpFS {
+    /// Create a tmpfs whose size is limited to half of the physical memory.
+    pub fn new() -> Arc<Self> {
+        let memory: usize = kernel_hal::mem::free_pmem_regions()
+            .iter()
+            .map(|region| region.end - region.start)
+            .sum();
+        Self::with_size_limit(memory / 2)
+    }
+
+    /// Create a tmpfs whose size is limited to `size_limit` bytes.
+    pub fn with_size_limit(size_limit: usize) -> Arc<Self> {
+        Arc::new_cyclic(|fs: &Weak<TmpFS>| TmpFS {
+            size_limit: roundup_pages(size_limit),
+            used: Mutex::new(0),
+            root: TmpINode::new(fs.clone(), Weak::new(), FileType::Dir, 0o1777, 0),
+        })
+    }
+
+    /// Create an unnamed regular file in `dir`, for `O_TMPFILE`.
+    ///
+    /// The file is released when the last reference to it is dropped.
+    pub fn create_tmpfile(dir: &Arc<dyn INode>, mode: u32) -> Result<Arc<dyn INode>> {
+        let dir = super::fs_inode(dir);
+        let dir = dir
+            .downcast_ref::<TmpINode>()
+            .ok_or(FsError::NotSupported)?;
+        if dir.inner.lock().type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let inode = TmpINode::new(dir.fs.clone(), Weak::new(), FileType::File, mode, 0);
+        inode.inner.lock().nlinks = 0;
+        Ok(inode)
+    }
+
+    /// Account the change of a file size from `old` to `new` bytes.
+    fn charge(&self, old: usize, new: usize) -> Result<()> {
+        let (old, new) = (roundup_pages(old), roundup_pages(new));
+        let mut used = self.used.lock();
+        if new > old && *used + (new - old) > self.size_limit {
+            return Err(FsError::NoDeviceSpace);
+        }
+        *used = *used + new - old;
+        Ok(())
+    }
+}
+
+impl FileSystem for TmpFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        let blocks = self.size_limit / PAGE_SIZE;
+        let free = blocks - *self.used.lock() / PAGE_SIZE;
+        FsInfo {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks,
+            bfree: free,
+            bavail: free,
+            files: 0,
+            ffree: 0,
+            namemax: MAX_NAME_LEN,
+        }
+    }
+}
+
+static NEXT_INODE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// An INode of tmpfs
+pub struct TmpINode {
+    id: usize,
+    fs: Weak<TmpFS>,
+    this: Weak<TmpINode>,
+    /// the pages of a regular file or a symbolic link
+    vmo: Option<Arc<VmObject>>,
+    inner: Mutex<TmpINodeInner>,
+}
+
+struct TmpINodeInner {
+    type_: FileType,
+    parent: Weak<TmpINode>,
+    children: BTreeMap<String, Arc<TmpINode>>,
+    size: usize,
+    mode: u16,
+    nlinks: usize,
+    uid: usize,
+    gid: usize,
+    rdev: usize,
+    atime: Timespec,
+    mtime: Timespec,
+    ctime: Timespec,
+}
+
+impl TmpINode {
+    fn new(
+        fs: Weak<TmpFS>,
+        parent: Weak<TmpINode>,
+        type_: FileType,
+        mode: u32,
+        rdev: usize,
+    ) -> Arc<Self> {
+        let vmo = match type_ {
+            FileType::File | FileType::SymLink => {
+                // `fs` is not available yet when creating the root directory
+                let size_limit = fs.upgrade().map_or(0, |fs| fs.size_limit);
+                Some(VmObject::new_paged(pages(size_limit)))
+            }
+            _ => None,
+        };
+        let now: Timespec = TimeSpec::now().into();
+        Arc::new_cyclic(|this| TmpINode {
+            id: NEXT_INODE_ID.fetch_add(1, Ordering::Relaxed),
+            fs,
+            this: this.clone(),
+            vmo,
+            inner: Mutex::new(TmpINodeInner {
+                type_,
+                parent,
+                children: BTreeMap::new(),
+                size: 0,
+                mode: mode as u16 & 0o7777,
+                nlinks: if type_ == FileType::Dir { 2 } else { 1 },
+                uid: 0,
+                gid: 0,
+                rdev,
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+        })
+    }
+
+    fn tmpfs(&self) -> Arc<TmpFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    /// Returns a VMO of the file in `[offset, offset + len)` for `mmap`.
+    ///
+    /// A shared VMO is a slice of the file pages, while a private one is a
+    /// copy-on-write child of them.
+    pub fn get_vmo(&self, offset: usize, len: usize, shared: bool) -> Result<Arc<VmObject>> {
+        let vmo = self.vmo.as_ref().ok_or(FsError::NotFile)?;
+        let vmo = if shared {
+            vmo.create_slice(offset, len)
+        } else {
+            vmo.create_child(false, offset, roundup_pages(len))
+        };
+        vmo.map_err(|_| FsError::InvalidParam)
+    }
+
+    /// Change the size of a regular file, zeroing the truncated part.
+    fn set_size(&self, inner: &mut TmpINodeInner, size: usize) -> Result<()> {
+        let vmo = self.vmo.as_ref().ok_or(FsError::NotFile)?;
+        if size > vmo.len() {
+            return Err(FsError::NoDeviceSpace);
+        }
+        self.tmpfs().charge(inner.size, size)?;
+        if size < inner.size {
+            let end = roundup_pages(size);
+            vmo.zero(size, end - size)
+                .map_err(|_| FsError::DeviceError)?;
+            if inner.size > end {
+                vmo.decommit(end, roundup_pages(inner.size) - end)
+                    .map_err(|_| FsError::DeviceError)?;
+            }
+        }
+        inner.size = size;
+        Ok(())
+    }
+
+    fn dir_inner(&self) -> Result<lock::MutexGuard<'_, TmpINodeInner>> {
+        let inner = self.inner.lock();
+        if inner.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        Ok(inner)
+    }
+}
+
+impl Drop for TmpINode {
+    fn drop(&mut self) {
+        let size = self.inner.lock().size;
+        if let Some(fs) = self.fs.upgrade() {
+            fs.charge(size, 0).unwrap();
+        }
+    }
+}
+
+impl INode for TmpINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        if inner.type_ == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        let vmo = self.vmo.as_ref().ok_or(FsError::NotSupported)?;
+        if offset >= inner.size {
+            return Ok(0);
+        }
+        let len = buf.len().min(inner.size - offset);
+        vmo.read(offset, &mut buf[..len])
+            .map_err(|_| FsError::DeviceError)?;
+        inner.atime = TimeSpec::now().into();
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        if inner.type_ == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        let vmo = self.vmo.as_ref().ok_or(FsError::NotSupported)?;
+        let end = offset + buf.len();
+        if end > inner.size {
+            self.set_size(&mut inner, end)?;
+        }
+        vmo.write(offset, buf).map_err(|_| FsError::DeviceError)?;
+        let now = TimeSpec::now().into();
+        inner.mtime = now;
+        inner.ctime = now;
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let inner = self.inner.lock();
+        Ok(Metadata {
+            dev: 0,
+            inode: self.id,
+            size: inner.size,
+            blk_size: PAGE_SIZE,
+            blocks: roundup_pages(inner.size) / 512,
+            atime: inner.atime,
+            mtime: inner.mtime,
+            ctime: inner.ctime,
+            type_: inner.type_,
+            mode: inner.mode,
+            nlinks: inner.nlinks,
+            uid: inner.uid,
+            gid: inner.gid,
+            rdev: inner.rdev,
+        })
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        let mut inner = self.inner.lock();
+        inner.atime = metadata.atime;
+        inner.mtime = metadata.mtime;
+        inner.ctime = metadata.ctime;
+        inner.mode = metadata.mode;
+        inner.uid = metadata.uid;
+        inner.gid = metadata.gid;
+        Ok(())
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+        if inner.type_ != FileType::File {
+            return Err(FsError::NotFile);
+        }
+        self.set_size(&mut inner, len)?;
+        let now = TimeSpec::now().into();
+        inner.mtime = now;
+        inner.ctime = now;
+        Ok(())
+    }
+
+    fn create2(
+        &self,
+        name: &str,
+        type_: FileType,
+        mode: u32,
+        data: usize,
+    ) -> Result<Arc<dyn INode>> {
+        let mut inner = self.dir_inner()?;
+        if name.len() > MAX_NAME_LEN {
+            return Err(FsError::InvalidParam);
+        }
+        if name == "." || name == ".." || inner.children.contains_key(name) {
+            return Err(FsError::EntryExist);
+        }
+        if inner.nlinks == 0 {
+            return Err(FsError::DirRemoved);
+        }
+        let inode = TmpINode::new(self.fs.clone(), self.this.clone(), type_, mode, data);
+        inner.children.insert(String::from(name), inode.clone());
+        if type_ == FileType::Dir {
+            inner.nlinks += 1;
+        }
+        Ok(inode)
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn INode>) -> Result<()> {
+        let other = other.downcast_ref::<TmpINode>().ok_or(FsError::NotSameFs)?;
+        if !Weak::ptr_eq(&other.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        let other = other.this.upgrade().unwrap();
+        let mut inner = self.dir_inner()?;
+        if inner.children.contains_key(name) {
+            return Err(FsError::EntryExist);
+        }
+        let mut other_inner = other.inner.lock();
+        if other_inner.type_ == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        other_inner.nlinks += 1;
+        other_inner.ctime = TimeSpec::now().into();
+        drop(other_inner);
+        inner.children.insert(String::from(name), other);
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(FsError::DirNotEmpty);
+        }
+        let mut inner = self.dir_inner()?;
+        let other = inner
+            .children
+            .get(name)
+            .ok_or(FsError::EntryNotFound)?
+            .clone();
+        let mut other_inner = other.inner.lock();
+        if other_inner.type_ == FileType::Dir {
+            if !other_inner.children.is_empty() {
+                return Err(FsError::DirNotEmpty);
+            }
+            other_inner.nlinks = 0;
+            inner.nlinks -= 1;
+        } else {
+            other_inner.nlinks -= 1;
+        }
+        other_inner.ctime = TimeSpec::now().into();
+        drop(other_inner);
+        inner.children.remove(name);
+        Ok(())
+    }
+
+    fn move_(&self, old_name: &str, target: &Arc<dyn INode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<TmpINode>()
+            .ok_or(FsError::NotSameFs)?;
+        if !Weak::ptr_eq(&target.fs, &self.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        let inode = self.find(old_name)?;
+        let inode = inode.downcast_ref::<TmpINode>().unwrap();
+        let inode = inode.this.upgrade().unwrap();
+        // a directory can not be moved into itself
+        let mut dir = target.this.upgrade();
+        while let Some(d) = dir {
+            if Arc::ptr_eq(&d, &inode) {
+                return Err(FsError::InvalidParam);
+            }
+            dir = d.inner.lock().parent.upgrade();
+        }
+        if let Ok(old) = target.find(new_name) {
+            let old = old.downcast_ref::<TmpINode>().unwrap();
+            if core::ptr::eq(old, &*inode) {
+                return Ok(());
+            }
+            target.unlink(new_name)?;
+        }
+        let is_dir = inode.inner.lock().type_ == FileType::Dir;
+        let same_dir = core::ptr::eq(target, self);
+        self.dir_inner()?.children.remove(old_name);
+        target
+            .dir_inner()?
+            .children
+            .insert(String::from(new_name), inode.clone());
+        if is_dir && !same_dir {
+            self.inner.lock().nlinks -= 1;
+            target.inner.lock().nlinks += 1;
+            inode.inner.lock().parent = target.this.clone();
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let inner = self.dir_inner()?;
+        match name {
+            "." => Ok(self.this.upgrade().unwrap()),
+            ".." => match inner.parent.upgrade() {
+                Some(parent) => Ok(parent),
+                None => Ok(self.this.upgrade().unwrap()),
+            },
+            _ => inner
+                .children
+                .get(name)
+                .map(|inode| inode.clone() as Arc<dyn INode>)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        let inner = self.dir_inner()?;
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => inner
+                .children
+                .keys()
+                .nth(i - 2)
+                .cloned()
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.tmpfs()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
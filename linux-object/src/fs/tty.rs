@@ -0,0 +1,444 @@
+//! Terminal attributes and job control shared by the console and pseudo-terminals
+#![deny(missing_docs)]
+
+use crate::process::{process_group, ProcessExt};
+use crate::signal::{Signal, SIG_IGN};
+use crate::thread::{current_thread, ThreadExt};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use rcore_fs::vfs::{FsError, Result};
+use zircon_object::object::KoID;
+use zircon_object::task::{Job, Process};
+
+/// Number of control characters in `struct termios`.
+pub(super) const NCCS: usize = 19;
+
+// indices of control characters
+pub(super) const VINTR: usize = 0;
+pub(super) const VQUIT: usize = 1;
+pub(super) const VERASE: usize = 2;
+pub(super) const VKILL: usize = 3;
+pub(super) const VEOF: usize = 4;
+pub(super) const VMIN: usize = 6;
+pub(super) const VSUSP: usize = 10;
+pub(super) const VEOL: usize = 11;
+pub(super) const VWERASE: usize = 14;
+pub(super) const VEOL2: usize = 16;
+
+bitflags::bitflags! {
+    /// Input modes of termios
+    #[derive(Default)]
+    pub struct InputFlags: u32 {
+        /// Translate NL to CR on input.
+        const INLCR = 0o100;
+        /// Ignore carriage return on input.
+        const IGNCR = 0o200;
+        /// Translate carriage return to newline on input.
+        const ICRNL = 0o400;
+        /// Enable XON/XOFF flow control on output.
+        const IXON = 0o2000;
+    }
+}
+
+bitflags::bitflags! {
+    /// Output modes of termios
+    #[derive(Default)]
+    pub struct OutputFlags: u32 {
+        /// Enable implementation-defined output processing.
+        const OPOST = 0o1;
+        /// Map NL to CR-NL on output.
+        const ONLCR = 0o4;
+    }
+}
+
+bitflags::bitflags! {
+    /// Local modes of termios
+    #[derive(Default)]
+    pub struct LocalFlags: u32 {
+        /// Generate the corresponding signal for INTR, QUIT and SUSP.
+        const ISIG = 0o1;
+        /// Enable canonical mode.
+        const ICANON = 0o2;
+        /// Echo input characters.
+        const ECHO = 0o10;
+        /// ERASE character erases the preceding input character.
+        const ECHOE = 0o20;
+        /// KILL character erases the current line.
+        const ECHOK = 0o40;
+        /// Echo the NL character even if ECHO is not set.
+        const ECHONL = 0o100;
+        /// Disable flushing the input queue when generating signals.
+        const NOFLSH = 0o200;
+        /// Send SIGTTOU to background processes which write to the terminal.
+        const TOSTOP = 0o400;
+        /// Echo control characters as `^X`.
+        const ECHOCTL = 0o1000;
+        /// KILL is echoed by erasing each character on the line.
+        const ECHOKE = 0o4000;
+        /// Enable implementation-defined input processing.
+        const IEXTEN = 0o100000;
+    }
+}
+
+/// Linux struct termios (the kernel layout used by `TCGETS`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    /// input modes
+    pub iflag: u32,
+    /// output modes
+    pub oflag: u32,
+    /// control modes
+    pub cflag: u32,
+    /// local modes
+    pub lflag: u32,
+    /// line discipline
+    pub line: u8,
+    /// control characters
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Termios {
+            iflag: (InputFlags::ICRNL | InputFlags::IXON).bits(),
+            oflag: (OutputFlags::OPOST | OutputFlags::ONLCR).bits(),
+            // B38400 | CS8 | CREAD | HUPCL
+            cflag: 0o2277,
+            lflag: (LocalFlags::ISIG
+                | LocalFlags::ICANON
+                | LocalFlags::ECHO
+                | LocalFlags::ECHOE
+                | LocalFlags::ECHOK
+                | LocalFlags::ECHOCTL
+                | LocalFlags::ECHOKE
+                | LocalFlags::IEXTEN)
+                .bits(),
+            line: 0,
+            cc: [
+                0o003, 0o034, 0o177, 0o025, 0o004, 0, 1, 0, 0o021, 0o023, 0o032, 0, 0o022, 0o017,
+                0o027, 0o026, 0, 0, 0,
+            ],
+        }
+    }
+}
+
+impl Termios {
+    /// Returns the input modes.
+    pub fn iflag(&self) -> InputFlags {
+        InputFlags::from_bits_truncate(self.iflag)
+    }
+    /// Returns the output modes.
+    pub fn oflag(&self) -> OutputFlags {
+        OutputFlags::from_bits_truncate(self.oflag)
+    }
+    /// Returns the local modes.
+    pub fn lflag(&self) -> LocalFlags {
+        LocalFlags::from_bits_truncate(self.lflag)
+    }
+    /// whether `c` is the enabled control character at `index`
+    pub(super) fn is_cc(&self, index: usize, c: u8) -> bool {
+        self.cc[index] != 0 && self.cc[index] == c
+    }
+    /// Returns the signal generated by the input character `c`, if any.
+    pub fn signal_char(&self, c: u8) -> Option<Signal> {
+        if !self.lflag().contains(LocalFlags::ISIG) {
+            None
+        } else if self.is_cc(VINTR, c) {
+            Some(Signal::SIGINT)
+        } else if self.is_cc(VQUIT, c) {
+            Some(Signal::SIGQUIT)
+        } else if self.is_cc(VSUSP, c) {
+            Some(Signal::SIGTSTP)
+        } else {
+            None
+        }
+    }
+}
+
+/// Linux struct winsize
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WinSize {
+    /// rows, in characters
+    pub row: u16,
+    /// columns, in characters
+    pub col: u16,
+    /// horizontal size, in pixels
+    pub xpixel: u16,
+    /// vertical size, in pixels
+    pub ypixel: u16,
+}
+
+/// The foreground process group of a terminal
+///
+/// The job of the process group is recorded, so that signals can be delivered
+/// from contexts without a current thread, e.g. the console interrupt.
+#[derive(Default, Clone)]
+pub struct TtyForeground {
+    job: Weak<Job>,
+    /// foreground process group, 0 if unset
+    pgid: KoID,
+    /// session the terminal is controlling
+    sid: KoID,
+}
+
+/// Returns the process running on the current CPU.
+fn current_process() -> Option<Arc<Process>> {
+    current_thread().map(|thread| thread.proc().clone())
+}
+
+impl TtyForeground {
+    /// Returns the foreground process group.
+    pub fn pgid(&self) -> KoID {
+        self.pgid
+    }
+
+    /// Returns the session the terminal is controlling.
+    pub fn sid(&self) -> KoID {
+        self.sid
+    }
+
+    /// Returns the foreground process group, the terminal becomes the
+    /// controlling terminal of the caller's session if it has none yet.
+    pub fn get_or_acquire(&mut self) -> KoID {
+        if self.pgid == 0 {
+            self.set_controlling();
+        }
+        self.pgid
+    }
+
+    /// Make the terminal the controlling terminal of the current session,
+    /// with the current process group in the foreground.
+    pub fn set_controlling(&mut self) {
+        if let Some(proc) = current_process() {
+            self.job = Arc::downgrade(&proc.job());
+            self.pgid = proc.linux().pgid();
+            self.sid = proc.linux().sid();
+        }
+    }
+
+    /// Set the foreground process group, it must be in the session of the caller.
+    pub fn set_pgid(&mut self, pgid: KoID) -> Result<()> {
+        let proc = current_process().ok_or(FsError::NotSupported)?;
+        let job = proc.job();
+        let sid = proc.linux().sid();
+        if self.sid != 0 && self.sid != sid {
+            return Err(FsError::NotSupported);
+        }
+        if !process_group(&job, pgid)
+            .iter()
+            .any(|p| p.linux().sid() == sid)
+        {
+            return Err(FsError::InvalidParam);
+        }
+        self.job = Arc::downgrade(&job);
+        self.pgid = pgid;
+        self.sid = sid;
+        Ok(())
+    }
+
+    /// Send `signal` to the foreground process group.
+    pub fn signal(&self, signal: Signal) {
+        if self.pgid == 0 {
+            return;
+        }
+        if let Some(job) = self.job.upgrade() {
+            for proc in process_group(&job, self.pgid) {
+                proc.send_signal(signal);
+            }
+        }
+    }
+
+    /// Check whether the current process may access the terminal.
+    ///
+    /// A background process of the session gets `signal` (`SIGTTIN` or
+    /// `SIGTTOU`) sent to its process group and the access is interrupted.
+    pub fn check_access(&self, signal: Signal) -> Result<()> {
+        if self.pgid == 0 {
+            return Ok(());
+        }
+        let (proc, thread) = match current_thread() {
+            Some(thread) => (thread.proc().clone(), thread),
+            None => return Ok(()),
+        };
+        let linux = proc.linux();
+        if linux.sid() != self.sid || linux.pgid() == self.pgid {
+            return Ok(());
+        }
+        let ignored = linux.signal_action(signal).handler == SIG_IGN
+            || thread.lock_linux().signal_mask.contains(signal);
+        if ignored {
+            // writes are allowed, reads fail with EIO
+            return match signal {
+                Signal::SIGTTOU => Ok(()),
+                _ => Err(FsError::DeviceError),
+            };
+        }
+        for proc in process_group(&proc.job(), linux.pgid()) {
+            proc.send_signal(signal);
+        }
+        Err(FsError::Interrupted)
+    }
+}
+
+/// Line discipline of a terminal, i.e. the slave side of a pseudo-terminal or the console
+#[derive(Default)]
+pub(super) struct LineDiscipline {
+    pub(super) termios: Termios,
+    /// the line being edited in canonical mode
+    pub(super) line: Vec<u8>,
+    /// bytes ready to be read
+    pub(super) input: VecDeque<u8>,
+    /// number of pending end-of-file conditions in canonical mode
+    pub(super) eof: usize,
+}
+
+impl LineDiscipline {
+    /// Process an input byte from the master or the serial.
+    ///
+    /// Bytes to echo are appended to `echo`. Returns the signal to deliver to
+    /// the foreground process group, if any.
+    pub(super) fn receive(&mut self, mut c: u8, echo: &mut Vec<u8>) -> Option<Signal> {
+        let termios = self.termios;
+        let iflag = termios.iflag();
+        let lflag = termios.lflag();
+        if c == b'\r' {
+            if iflag.contains(InputFlags::IGNCR) {
+                return None;
+            }
+            if iflag.contains(InputFlags::ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag.contains(InputFlags::INLCR) {
+            c = b'\r';
+        }
+
+        if let Some(signal) = termios.signal_char(c) {
+            if !lflag.contains(LocalFlags::NOFLSH) {
+                self.line.clear();
+                self.input.clear();
+                self.eof = 0;
+            }
+            self.echo_char(c, echo);
+            return Some(signal);
+        }
+
+        if !lflag.contains(LocalFlags::ICANON) {
+            self.input.push_back(c);
+            self.echo_char(c, echo);
+            return None;
+        }
+
+        if termios.is_cc(VERASE, c) {
+            if self.line.pop().is_some() && lflag.contains(LocalFlags::ECHO | LocalFlags::ECHOE) {
+                echo.extend_from_slice(b"\x08 \x08");
+            }
+        } else if termios.is_cc(VWERASE, c) && lflag.contains(LocalFlags::IEXTEN) {
+            while self.line.last() == Some(&b' ') {
+                self.erase_one(echo);
+            }
+            while matches!(self.line.last(), Some(&ch) if ch != b' ') {
+                self.erase_one(echo);
+            }
+        } else if termios.is_cc(VKILL, c) {
+            if lflag.contains(LocalFlags::ECHOKE) {
+                while !self.line.is_empty() {
+                    self.erase_one(echo);
+                }
+            } else {
+                self.line.clear();
+                self.echo_char(c, echo);
+                if lflag.contains(LocalFlags::ECHOK) {
+                    echo.push(b'\n');
+                }
+            }
+        } else if termios.is_cc(VEOF, c) {
+            if self.line.is_empty() {
+                self.eof += 1;
+            } else {
+                self.input.extend(self.line.drain(..));
+            }
+        } else if c == b'\n' || termios.is_cc(VEOL, c) || termios.is_cc(VEOL2, c) {
+            self.line.push(c);
+            self.input.extend(self.line.drain(..));
+            if c == b'\n' && lflag.contains(LocalFlags::ECHONL) && !lflag.contains(LocalFlags::ECHO)
+            {
+                echo.push(c);
+            } else {
+                self.echo_char(c, echo);
+            }
+        } else {
+            self.line.push(c);
+            self.echo_char(c, echo);
+        }
+        None
+    }
+
+    /// Erase the last character of the current line.
+    fn erase_one(&mut self, echo: &mut Vec<u8>) {
+        if self.line.pop().is_some() && self.termios.lflag().contains(LocalFlags::ECHO) {
+            echo.extend_from_slice(b"\x08 \x08");
+        }
+    }
+
+    fn echo_char(&self, c: u8, echo: &mut Vec<u8>) {
+        let lflag = self.termios.lflag();
+        if !lflag.contains(LocalFlags::ECHO) {
+            return;
+        }
+        if lflag.contains(LocalFlags::ECHOCTL) && c < 0x20 && c != b'\n' && c != b'\t' {
+            echo.push(b'^');
+            echo.push(c + 0x40);
+        } else {
+            echo.push(c);
+        }
+    }
+
+    /// Change the attributes, discarding the pending input if `flush` is set.
+    pub(super) fn set_termios(&mut self, termios: Termios, flush: bool) {
+        let was_canonical = self.termios.lflag().contains(LocalFlags::ICANON);
+        self.termios = termios;
+        if flush {
+            self.line.clear();
+            self.input.clear();
+            self.eof = 0;
+        } else if was_canonical && !termios.lflag().contains(LocalFlags::ICANON) {
+            // the partial line becomes readable in non-canonical mode
+            let line: Vec<u8> = self.line.drain(..).collect();
+            self.input.extend(line);
+        }
+    }
+
+    /// Whether a read would not block.
+    pub(super) fn can_read(&self) -> bool {
+        !self.input.is_empty() || self.eof > 0
+    }
+
+    /// Read processed input, returning `None` if nothing is available.
+    pub(super) fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.input.is_empty() {
+            if self.eof > 0 {
+                self.eof -= 1;
+                return Some(0);
+            }
+            return None;
+        }
+        let canonical = self.termios.lflag().contains(LocalFlags::ICANON);
+        let mut len = 0;
+        while len < buf.len() {
+            match self.input.pop_front() {
+                Some(c) => {
+                    buf[len] = c;
+                    len += 1;
+                    if canonical && c == b'\n' {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        Some(len)
+    }
+}
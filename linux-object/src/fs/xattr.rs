@@ -0,0 +1,126 @@
+//! Extended attributes of files
+//!
+//! The file systems underneath have no place for them, so the attributes are
+//! kept in memory for each inode, and are lost on reboot.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use lock::Mutex;
+use rcore_fs::vfs::INode;
+
+use super::page_cache::inode_key;
+use crate::error::{LxError, LxResult};
+
+/// The longest name of an attribute
+pub const XATTR_NAME_MAX: usize = 255;
+/// The largest value of an attribute
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+bitflags::bitflags! {
+    /// Flags of `setxattr`
+    pub struct XattrFlags: usize {
+        /// fail if the attribute exists
+        const CREATE = 1;
+        /// fail if the attribute does not exist
+        const REPLACE = 2;
+    }
+}
+
+/// The namespace of an attribute, given by the prefix of its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrNamespace {
+    /// `user.`, accessible with the permissions of the file
+    User,
+    /// `trusted.`, accessible with `CAP_SYS_ADMIN`
+    Trusted,
+    /// `security.`, used by security modules
+    Security,
+    /// `system.`, e.g. POSIX ACLs
+    System,
+}
+
+impl XattrNamespace {
+    /// Parse the namespace of the attribute `name`.
+    pub fn of(name: &str) -> LxResult<Self> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX {
+            return Err(LxError::ERANGE);
+        }
+        let (prefix, rest) = name.split_at(name.find('.').ok_or(LxError::EOPNOTSUPP)? + 1);
+        if rest.is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        match prefix {
+            "user." => Ok(XattrNamespace::User),
+            "trusted." => Ok(XattrNamespace::Trusted),
+            "security." => Ok(XattrNamespace::Security),
+            "system." => Ok(XattrNamespace::System),
+            _ => Err(LxError::EOPNOTSUPP),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref XATTRS: Mutex<BTreeMap<(usize, usize), BTreeMap<String, Vec<u8>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Set the attribute `name` of `inode` to `value`.
+pub fn set_xattr(inode: &dyn INode, name: &str, value: &[u8], flags: XattrFlags) -> LxResult {
+    XattrNamespace::of(name)?;
+    if value.len() > XATTR_SIZE_MAX {
+        return Err(LxError::E2BIG);
+    }
+    let key = inode_key(inode)?;
+    let mut xattrs = XATTRS.lock();
+    let attrs = xattrs.entry(key).or_default();
+    match attrs.contains_key(name) {
+        true if flags.contains(XattrFlags::CREATE) => return Err(LxError::EEXIST),
+        false if flags.contains(XattrFlags::REPLACE) => return Err(LxError::ENODATA),
+        _ => {}
+    }
+    attrs.insert(String::from(name), value.to_vec());
+    Ok(())
+}
+
+/// Get the value of the attribute `name` of `inode`.
+pub fn get_xattr(inode: &dyn INode, name: &str) -> LxResult<Vec<u8>> {
+    XattrNamespace::of(name)?;
+    let key = inode_key(inode)?;
+    XATTRS
+        .lock()
+        .get(&key)
+        .and_then(|attrs| attrs.get(name))
+        .cloned()
+        .ok_or(LxError::ENODATA)
+}
+
+/// Returns the names of all attributes of `inode`.
+pub fn list_xattr(inode: &dyn INode) -> LxResult<Vec<String>> {
+    let key = inode_key(inode)?;
+    Ok(XATTRS
+        .lock()
+        .get(&key)
+        .map(|attrs| attrs.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// Remove the attribute `name` of `inode`.
+pub fn remove_xattr(inode: &dyn INode, name: &str) -> LxResult {
+    XattrNamespace::of(name)?;
+    let key = inode_key(inode)?;
+    let mut xattrs = XATTRS.lock();
+    let attrs = xattrs.get_mut(&key).ok_or(LxError::ENODATA)?;
+    attrs.remove(name).ok_or(LxError::ENODATA)?;
+    if attrs.is_empty() {
+        xattrs.remove(&key);
+    }
+    Ok(())
+}
+
+/// Drop the attributes of `inode` once it has no links, before its inode
+/// number is reused.
+pub fn drop_xattrs(inode: &dyn INode) {
+    if let Ok(key) = inode_key(inode) {
+        XATTRS.lock().remove(&key);
+    }
+}
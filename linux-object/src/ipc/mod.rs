@@ -1,29 +1,49 @@
 //! Linux Inter-Process Communication
 #![deny(missing_docs)]
+mod msg_queue;
 mod semary;
 mod shared_mem;
 
+pub use self::msg_queue::*;
 pub use self::semary::*;
 pub use self::shared_mem::*;
-use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use crate::cred::{Capabilities, Credentials};
+use crate::error::{LxError, LxResult};
+use crate::sync::EventBus;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
 use bitflags::*;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll};
 use lock::Mutex;
+use zircon_object::object::KoID;
+
+/// The key creating a new IPC object every time
+pub const IPC_PRIVATE: u32 = 0;
+
+/// The maximum number of IPC objects of a kind
+const IPCMNI: usize = 32768;
+
+/// The number of sequence numbers, keeping the IDs positive `int`s
+const IPC_SEQ_MAX: usize = i32::MAX as usize / IPCMNI;
 
 /// Semaphore table in a process
 #[derive(Default)]
 pub struct SemProc {
-    /// Semaphore arrays
-    arrays: BTreeMap<SemId, Arc<SemArray>>,
-    /// Undo operations when process terminates
-    undos: BTreeMap<(SemId, SemNum), SemOp>,
+    /// The process of the undo operations
+    owner: KoID,
+    /// The semaphore sets with undo operations of the process, performed
+    /// when it terminates
+    undos: BTreeSet<SemId>,
 }
 
 /// Shared_memory table in a process
-#[derive(Default, Clone)]
+#[derive(Default)]
 pub struct ShmProc {
-    /// Shared_memory identifier sets
-    shm_identifiers: BTreeMap<ShmId, ShmIdentifier>,
+    /// Shared_memory segments attached by address
+    shm_identifiers: BTreeMap<usize, ShmIdentifier>,
 }
 
 bitflags! {
@@ -61,115 +81,169 @@ pub struct IpcPerm {
     pub __pad2: usize,
 }
 
-/// Semaphore set identifier (in a process)
+/// Semaphore set identifier (in the system)
 type SemId = usize;
-/// Shared_memory identifier (in a process)
+/// Shared_memory identifier (in the system)
 type ShmId = usize;
+/// Message queue identifier (in the system)
+type MsgId = usize;
 
 /// Semaphore number (in an array)
 type SemNum = u16;
 
-/// Semaphore operation value
-type SemOp = i16;
-
-impl SemProc {
-    /// Insert the `array` and return its ID
-    pub fn add(&mut self, array: Arc<SemArray>) -> SemId {
-        let id = self.get_free_id();
-        self.arrays.insert(id, array);
-        id
+impl IpcPerm {
+    /// The permissions of a new object of `key`, owned by the caller of `cred`,
+    /// with the least significant 9 bits of `flags` as the mode.
+    fn new(id: usize, key: u32, flags: usize, cred: &Credentials) -> Self {
+        IpcPerm {
+            key,
+            uid: cred.euid,
+            gid: cred.egid,
+            cuid: cred.euid,
+            cgid: cred.egid,
+            mode: (flags as u32) & 0x1ff,
+            __seq: (id / IPCMNI) as u32,
+            __pad1: 0,
+            __pad2: 0,
+        }
     }
 
-    /// Remove an `array` by ID
-    pub fn remove(&mut self, id: SemId) {
-        self.arrays.remove(&id);
+    /// Check the access of `mask` (`R_OK`, `W_OK`) to the object.
+    pub fn check_access(&self, cred: &Credentials, mask: usize) -> LxResult {
+        let mode = self.mode as usize;
+        let granted = if cred.euid == self.uid || cred.euid == self.cuid {
+            mode >> 6
+        } else if cred.in_group(self.gid) || cred.in_group(self.cgid) {
+            mode >> 3
+        } else {
+            mode
+        } & 7;
+        if granted & mask == mask || cred.has_cap(Capabilities::IPC_OWNER) {
+            Ok(())
+        } else {
+            Err(LxError::EACCES)
+        }
     }
 
-    /// Get a free ID
-    fn get_free_id(&self) -> SemId {
-        (0..).find(|i| self.arrays.get(i).is_none()).unwrap()
+    /// Check the access requested by the permission bits of `flags` of
+    /// `semget`, `shmget` or `msgget` to an existing object.
+    fn check_get(&self, cred: &Credentials, flags: usize) -> LxResult {
+        self.check_access(cred, ((flags >> 6) | (flags >> 3) | flags) & 7)
     }
 
-    /// Get an semaphore set by `id`
-    pub fn get(&self, id: SemId) -> Option<Arc<SemArray>> {
-        self.arrays.get(&id).cloned()
+    /// Check the caller of `cred` may change or remove the object, which
+    /// needs to be its owner or creator, or `CAP_SYS_ADMIN`.
+    pub fn check_owner(&self, cred: &Credentials) -> LxResult {
+        if cred.euid == self.uid || cred.euid == self.cuid || cred.has_cap(Capabilities::SYS_ADMIN)
+        {
+            Ok(())
+        } else {
+            Err(LxError::EPERM)
+        }
     }
 
-    /// Add an undo operation
-    pub fn add_undo(&mut self, id: SemId, num: SemNum, op: SemOp) {
-        let old_val = *self.undos.get(&(id, num)).unwrap_or(&0);
-        let new_val = old_val - op;
-        self.undos.insert((id, num), new_val);
+    /// for IPC_SET
+    fn set(&mut self, new: &IpcPerm) {
+        self.uid = new.uid;
+        self.gid = new.gid;
+        self.mode = (self.mode & !0x1ff) | (new.mode & 0x1ff);
     }
 }
 
-/// Fork the semaphore table. Clear undo info.
-impl Clone for SemProc {
-    fn clone(&self) -> Self {
-        SemProc {
-            arrays: self.arrays.clone(),
-            undos: BTreeMap::default(),
-        }
-    }
+/// Allocate the ID of a new object among `objects` by ID, which is
+/// `seq * IPCMNI + index` of the lowest free index.
+///
+/// `seq` is increased on every allocation, so that the ID of a removed
+/// object is not reused soon, and a stale ID is rejected instead of referring
+/// to another object.
+fn alloc_ipc_id<T>(objects: &BTreeMap<usize, T>, seq: &AtomicUsize) -> LxResult<usize> {
+    let used: BTreeSet<usize> = objects.keys().map(|id| id % IPCMNI).collect();
+    let index = (0..IPCMNI)
+        .find(|i| !used.contains(i))
+        .ok_or(LxError::ENOSPC)?;
+    let seq = seq.fetch_add(1, Ordering::Relaxed) % IPC_SEQ_MAX;
+    Ok(seq * IPCMNI + index)
 }
 
-/// Auto perform semaphores undo on drop
-impl Drop for SemProc {
-    fn drop(&mut self) {
-        for (&(id, num), &op) in self.undos.iter() {
-            debug!("semundo: id: {}, num: {}, op: {}", id, num, op);
-            let sem_array = self.arrays[&id].clone();
-            let sem = &sem_array[num as usize];
-            match op {
-                1 => sem.release(),
-                0 => {}
-                _ => unimplemented!("Semaphore: semundo.(Not 1)"),
-            }
-        }
-    }
+/// The index in the table of an IPC object of `id`, for `SHM_STAT` and
+/// `MSG_STAT`.
+fn ipc_index(id: usize) -> usize {
+    id % IPCMNI
 }
 
-impl ShmProc {
-    /// Insert the `SharedGuard` and return its ID
-    pub fn add(&mut self, shared_guard: Arc<Mutex<ShmGuard>>) -> ShmId {
-        let id = self.get_free_id();
-        let shm_identifier = ShmIdentifier {
-            addr: 0,
-            guard: shared_guard,
-        };
-        self.shm_identifiers.insert(id, shm_identifier);
-        id
-    }
+/// The state of an IPC object waited for, with an `EventBus` notified on
+/// every change.
+trait IpcWaitable {
+    fn eventbus(&mut self) -> &mut EventBus;
+}
 
-    /// Get a free ID
-    fn get_free_id(&self) -> ShmId {
-        (0..)
-            .find(|i| self.shm_identifiers.get(i).is_none())
-            .unwrap()
-    }
+/// Wait until `poll` returns `Poll::Ready`, with the state locked.
+///
+/// `poll` is called again when the `EventBus` of the state is notified.
+fn ipc_wait<D: IpcWaitable, T, F>(state: &Mutex<D>, poll: F) -> IpcWaitFuture<'_, D, F>
+where
+    F: FnMut(&mut D) -> Poll<T> + Unpin,
+{
+    IpcWaitFuture { state, poll }
+}
 
-    /// Get an semaphore set by `id`
-    pub fn get(&self, id: ShmId) -> Option<ShmIdentifier> {
-        self.shm_identifiers.get(&id).cloned()
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct IpcWaitFuture<'a, D, F> {
+    state: &'a Mutex<D>,
+    poll: F,
+}
+
+impl<D, T, F> Future for IpcWaitFuture<'_, D, F>
+where
+    D: IpcWaitable,
+    F: FnMut(&mut D) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let state = self.state;
+        let mut inner = state.lock();
+        if let Poll::Ready(ret) = (self.poll)(&mut inner) {
+            return Poll::Ready(ret);
+        }
+        let waker = cx.waker().clone();
+        inner.eventbus().subscribe(Box::new(move |_| {
+            waker.wake_by_ref();
+            true
+        }));
+        Poll::Pending
     }
+}
 
-    /// Used to set Virtual Addr
-    pub fn set(&mut self, id: ShmId, shm_id: ShmIdentifier) {
-        self.shm_identifiers.insert(id, shm_id);
+impl SemProc {
+    /// Record the semaphore set `id` to undo the operations of the process
+    /// `owner` in, which are recorded by [`SemArray::semop`].
+    pub fn add_undo(&mut self, id: SemId, owner: KoID) {
+        self.owner = owner;
+        self.undos.insert(id);
     }
 
-    /// get id from virtaddr
-    pub fn get_id(&self, addr: usize) -> Option<ShmId> {
-        for (key, value) in &self.shm_identifiers {
-            if value.addr == addr {
-                return Some(*key);
+    /// Perform the undo operations, when the process terminates
+    pub fn undo_all(&mut self) {
+        for id in core::mem::take(&mut self.undos) {
+            // the removed sets are not undone, their IDs are not reused soon
+            if let Ok(sem_array) = SemArray::get(id) {
+                sem_array.exit(self.owner);
             }
         }
-        None
     }
+}
+
+/// Fork the semaphore table. Clear undo info.
+impl Clone for SemProc {
+    fn clone(&self) -> Self {
+        SemProc::default()
+    }
+}
 
-    /// Pop Shared Area
-    pub fn pop(&mut self, id: ShmId) {
-        self.shm_identifiers.remove(&id);
+/// Auto perform semaphores undo on drop
+impl Drop for SemProc {
+    fn drop(&mut self) {
+        self.undo_all();
     }
 }
@@ -0,0 +1,327 @@
+//! Linux message queue ipc
+use super::*;
+use crate::time::TimeSpec;
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use lazy_static::lazy_static;
+use lock::RwLock;
+
+lazy_static! {
+    /// Message queues of the system by ID
+    static ref MSG_QUEUES: RwLock<BTreeMap<MsgId, Arc<MsgQueue>>> = RwLock::new(BTreeMap::new());
+}
+
+/// The sequence number of the next message queue ID
+static MSG_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximum size of a message
+pub const MSGMAX: usize = 8192;
+
+/// The default maximum size of the messages in a queue
+pub const MSGMNB: usize = 16384;
+
+bitflags! {
+    /// flags for msgsnd() and msgrcv()
+    pub struct MsgFlags: usize {
+        /// return immediately instead of waiting
+        const IPC_NOWAIT = 0o4000;
+        /// truncate the message received if it is too long
+        const NOERROR = 0o10000;
+        /// receive the first message not of the type
+        const EXCEPT = 0o20000;
+        /// copy the message at the index given as the type, leaving it in the queue
+        const COPY = 0o40000;
+    }
+}
+
+/// msqid data structure
+///
+/// struct msqid_ds
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MsqidDs {
+    /// Ownership and permissions
+    pub perm: IpcPerm,
+    /// Time of last msgsnd(2)
+    pub stime: usize,
+    /// Time of last msgrcv(2)
+    pub rtime: usize,
+    /// Time of last change
+    pub ctime: usize,
+    /// Current number of bytes in queue
+    pub cbytes: usize,
+    /// Current number of messages in queue
+    pub qnum: usize,
+    /// Maximum number of bytes allowed in queue
+    pub qbytes: usize,
+    /// PID of last msgsnd(2)
+    pub lspid: u32,
+    /// PID of last msgrcv(2)
+    pub lrpid: u32,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// A message in a queue
+struct Message {
+    mtype: isize,
+    data: Vec<u8>,
+}
+
+/// The messages of a queue
+struct MsgQueueInner {
+    messages: VecDeque<Message>,
+    /// Total size of the messages
+    bytes: usize,
+    /// Maximum size of the messages
+    qbytes: usize,
+    /// Whether the queue is removed, failing the waiters
+    removed: bool,
+    /// Notified on every message sent or received
+    eventbus: EventBus,
+}
+
+impl IpcWaitable for MsgQueueInner {
+    fn eventbus(&mut self) -> &mut EventBus {
+        &mut self.eventbus
+    }
+}
+
+/// A System V message queue
+pub struct MsgQueue {
+    /// The ID of the queue
+    pub id: MsgId,
+    /// msqid data structure, whose counters are updated by `stat`
+    msqid_ds: Mutex<MsqidDs>,
+    inner: Mutex<MsgQueueInner>,
+}
+
+impl MsgQueue {
+    /// Get the ID of the message queue of `key`, or create a queue with
+    /// `IpcGetFlag::CREAT` in `flags`, owned by the caller of `cred`.
+    ///
+    /// A new queue is always created for `IPC_PRIVATE`.
+    pub fn get_or_create(key: u32, flags: usize, cred: &Credentials) -> LxResult<MsgId> {
+        let mut queues = MSG_QUEUES.write();
+        let flag = IpcGetFlag::from_bits_truncate(flags);
+
+        if key != IPC_PRIVATE {
+            let found = queues
+                .values()
+                .find(|queue| queue.msqid_ds.lock().perm.key == key);
+            if let Some(queue) = found {
+                if flag.contains(IpcGetFlag::CREAT) && flag.contains(IpcGetFlag::EXCLUSIVE) {
+                    return Err(LxError::EEXIST);
+                }
+                queue.msqid_ds.lock().perm.check_get(cred, flags)?;
+                return Ok(queue.id);
+            }
+            if !flag.contains(IpcGetFlag::CREAT) {
+                return Err(LxError::ENOENT);
+            }
+        }
+        let id = alloc_ipc_id(&queues, &MSG_SEQ)?;
+        let queue = Arc::new(MsgQueue {
+            id,
+            msqid_ds: Mutex::new(MsqidDs {
+                perm: IpcPerm::new(id, key, flags, cred),
+                stime: 0,
+                rtime: 0,
+                ctime: TimeSpec::now().sec,
+                cbytes: 0,
+                qnum: 0,
+                qbytes: MSGMNB,
+                lspid: 0,
+                lrpid: 0,
+                __unused4: 0,
+                __unused5: 0,
+            }),
+            inner: Mutex::new(MsgQueueInner {
+                messages: VecDeque::new(),
+                bytes: 0,
+                qbytes: MSGMNB,
+                removed: false,
+                eventbus: EventBus::default(),
+            }),
+        });
+        queues.insert(id, queue);
+        Ok(id)
+    }
+
+    /// Get a message queue by `id`
+    pub fn get(id: MsgId) -> LxResult<Arc<Self>> {
+        MSG_QUEUES.read().get(&id).cloned().ok_or(LxError::EINVAL)
+    }
+
+    /// Get a message queue by its index in the table, for `MSG_STAT`
+    pub fn get_by_index(index: usize) -> LxResult<Arc<Self>> {
+        MSG_QUEUES
+            .read()
+            .values()
+            .find(|queue| ipc_index(queue.id) == index)
+            .cloned()
+            .ok_or(LxError::EINVAL)
+    }
+
+    /// Check the access of `mask` (`R_OK`, `W_OK`) to the queue.
+    pub fn check_access(&self, cred: &Credentials, mask: usize) -> LxResult {
+        self.msqid_ds.lock().perm.check_access(cred, mask)
+    }
+
+    /// Check the caller of `cred` may change or remove the queue.
+    pub fn check_owner(&self, cred: &Credentials) -> LxResult {
+        self.msqid_ds.lock().perm.check_owner(cred)
+    }
+
+    /// Send a message of `mtype` by the process `pid`, waiting for the room
+    /// in the queue unless `MsgFlags::IPC_NOWAIT`.
+    pub async fn send(&self, mtype: isize, data: Vec<u8>, flags: MsgFlags, pid: u32) -> LxResult {
+        if mtype <= 0 || data.len() > MSGMAX {
+            return Err(LxError::EINVAL);
+        }
+        let mut message = Some(Message { mtype, data });
+        ipc_wait(&self.inner, move |inner| {
+            if inner.removed {
+                return Poll::Ready(Err(LxError::EIDRM));
+            }
+            let len = message.as_ref().unwrap().data.len();
+            // the number of messages is limited by `qbytes` as well
+            if inner.bytes + len > inner.qbytes || inner.messages.len() >= inner.qbytes {
+                if flags.contains(MsgFlags::IPC_NOWAIT) {
+                    return Poll::Ready(Err(LxError::EAGAIN));
+                }
+                return Poll::Pending;
+            }
+            inner.bytes += len;
+            inner.messages.push_back(message.take().unwrap());
+            inner.eventbus.notify();
+            Poll::Ready(Ok(()))
+        })
+        .await?;
+        let mut ds = self.msqid_ds.lock();
+        ds.stime = TimeSpec::now().sec;
+        ds.lspid = pid;
+        Ok(())
+    }
+
+    /// Receive a message of at most `max_len` bytes selected by `msgtyp` by
+    /// the process `pid`, waiting for it unless `MsgFlags::IPC_NOWAIT`.
+    ///
+    /// - If `msgtyp` is 0, the first message is received.
+    /// - If `msgtyp` is greater than 0, the first message of the type, or not
+    ///   of it with `MsgFlags::EXCEPT`.
+    /// - If `msgtyp` is less than 0, the first message of the lowest type not
+    ///   greater than its absolute value.
+    ///
+    /// With `MsgFlags::COPY`, the message at the index `msgtyp` is copied
+    /// instead, leaving it in the queue.
+    ///
+    /// Returns the type and the data of the message, which is truncated with
+    /// `MsgFlags::NOERROR` if longer than `max_len`, otherwise left in the
+    /// queue with `E2BIG`.
+    pub async fn receive(
+        &self,
+        msgtyp: isize,
+        max_len: usize,
+        flags: MsgFlags,
+        pid: u32,
+    ) -> LxResult<(isize, Vec<u8>)> {
+        if flags.contains(MsgFlags::COPY)
+            && (!flags.contains(MsgFlags::IPC_NOWAIT) || flags.contains(MsgFlags::EXCEPT))
+        {
+            return Err(LxError::EINVAL);
+        }
+        let ret = ipc_wait(&self.inner, move |inner| {
+            if inner.removed {
+                return Poll::Ready(Err(LxError::EIDRM));
+            }
+            let index = match inner.find(msgtyp, flags) {
+                Some(index) => index,
+                None if flags.contains(MsgFlags::IPC_NOWAIT) => {
+                    return Poll::Ready(Err(LxError::ENOMSG))
+                }
+                None => return Poll::Pending,
+            };
+            let message = &inner.messages[index];
+            if message.data.len() > max_len && !flags.contains(MsgFlags::NOERROR) {
+                return Poll::Ready(Err(LxError::E2BIG));
+            }
+            if flags.contains(MsgFlags::COPY) {
+                let len = message.data.len().min(max_len);
+                return Poll::Ready(Ok((message.mtype, message.data[..len].to_vec())));
+            }
+            let mut message = inner.messages.remove(index).unwrap();
+            inner.bytes -= message.data.len();
+            inner.eventbus.notify();
+            message.data.truncate(max_len);
+            Poll::Ready(Ok((message.mtype, message.data)))
+        })
+        .await?;
+        if !flags.contains(MsgFlags::COPY) {
+            let mut ds = self.msqid_ds.lock();
+            ds.rtime = TimeSpec::now().sec;
+            ds.lrpid = pid;
+        }
+        Ok(ret)
+    }
+
+    /// Get the msqid data structure, for IPC_STAT
+    pub fn stat(&self) -> MsqidDs {
+        let inner = self.inner.lock();
+        let mut ds = *self.msqid_ds.lock();
+        ds.cbytes = inner.bytes;
+        ds.qnum = inner.messages.len();
+        ds.qbytes = inner.qbytes;
+        ds
+    }
+
+    /// for IPC_SET
+    /// see man msgctl(2)
+    ///
+    /// Raising `qbytes` beyond `MSGMNB` needs `CAP_SYS_RESOURCE`.
+    pub fn set(&self, new: &MsqidDs, cred: &Credentials) -> LxResult {
+        if new.qbytes > MSGMNB && !cred.has_cap(Capabilities::SYS_RESOURCE) {
+            return Err(LxError::EPERM);
+        }
+        let mut inner = self.inner.lock();
+        inner.qbytes = new.qbytes;
+        // senders may fit in now
+        inner.eventbus.notify();
+        drop(inner);
+        let mut ds = self.msqid_ds.lock();
+        ds.perm.set(&new.perm);
+        ds.ctime = TimeSpec::now().sec;
+        Ok(())
+    }
+
+    /// remove the queue, the waiters fail with `EIDRM`
+    pub fn remove(&self) {
+        MSG_QUEUES.write().remove(&self.id);
+        let mut inner = self.inner.lock();
+        inner.removed = true;
+        inner.messages.clear();
+        inner.eventbus.notify();
+    }
+}
+
+impl MsgQueueInner {
+    /// Find the index of the message selected by `msgtyp`
+    fn find(&self, msgtyp: isize, flags: MsgFlags) -> Option<usize> {
+        if flags.contains(MsgFlags::COPY) {
+            let index = msgtyp as usize;
+            return (msgtyp >= 0 && index < self.messages.len()).then_some(index);
+        }
+        let mut messages = self.messages.iter().enumerate();
+        match msgtyp {
+            0 => messages.next().map(|(i, _)| i),
+            t if t > 0 && flags.contains(MsgFlags::EXCEPT) => {
+                messages.find(|(_, m)| m.mtype != t).map(|(i, _)| i)
+            }
+            t if t > 0 => messages.find(|(_, m)| m.mtype == t).map(|(i, _)| i),
+            t => messages
+                .filter(|(_, m)| m.mtype <= -t)
+                .min_by_key(|(_, m)| m.mtype)
+                .map(|(i, _)| i),
+        }
+    }
+}
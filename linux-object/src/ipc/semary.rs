@@ -1,12 +1,25 @@
 //! Linux semaphore ipc
 use super::*;
-use crate::error::LxError;
-use crate::sync::Semaphore;
 use crate::time::*;
-use alloc::{collections::BTreeMap, sync::Arc, sync::Weak, vec::Vec};
-use core::ops::Index;
+use alloc::{sync::Arc, vec::Vec};
+use bitflags::bitflags;
 use lazy_static::*;
-use lock::{Mutex, RwLock};
+use lock::RwLock;
+use zircon_object::object::KoID;
+
+lazy_static! {
+    /// Semaphore sets of the system by ID
+    static ref SEM_ARRAYS: RwLock<BTreeMap<SemId, Arc<SemArray>>> = RwLock::new(BTreeMap::new());
+}
+
+/// The sequence number of the next semaphore set ID
+static SEM_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximum semaphores per semaphore set
+pub const SEMMSL: usize = 256;
+
+/// The maximum value of a semaphore
+pub const SEMVMX: i32 = 32767;
 
 /// semid data structure
 ///
@@ -26,38 +39,139 @@ pub struct SemidDs {
     pub nsems: usize,
 }
 
-/// A System V semaphore set
-pub struct SemArray {
-    /// semid data structure
-    pub semid_ds: Mutex<SemidDs>,
-    sems: Vec<Semaphore>,
+/// An operation to be performed on a single semaphore
+///
+/// Ref: <http://man7.org/linux/man-pages/man2/semop.2.html>
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SemBuf {
+    /// The semaphore number in the set
+    pub num: u16,
+    /// Added to the semaphore, or 0 to wait for it to be zero
+    pub op: i16,
+    /// `SemFlags`
+    pub flags: i16,
 }
 
-impl Index<usize> for SemArray {
-    type Output = Semaphore;
-    fn index(&self, idx: usize) -> &Semaphore {
-        &self.sems[idx]
+bitflags! {
+    /// flags of an operation on a semaphore
+    pub struct SemFlags: i16 {
+        /// For SemOP
+        const IPC_NOWAIT = 0x800;
+        /// it will be automatically undone when the process terminates.
+        const SEM_UNDO = 0x1000;
     }
 }
 
-lazy_static! {
-    static ref KEY2SEM: RwLock<BTreeMap<u32, Weak<SemArray>>> = RwLock::new(BTreeMap::new());
+/// A semaphore in a set
+#[derive(Clone, Copy, Default)]
+struct Sem {
+    /// The value of the semaphore
+    value: i32,
+    /// PID of the last operation
+    pid: u32,
+    /// Number of the waiters for the value to increase
+    ncnt: usize,
+    /// Number of the waiters for the value to become zero
+    zcnt: usize,
+}
+
+/// The semaphores of a set
+struct SemArrayInner {
+    sems: Vec<Sem>,
+    /// The adjustments of the `SemFlags::SEM_UNDO` operations by process
+    /// and semaphore, undone when the process exits
+    undos: BTreeMap<(KoID, SemNum), i32>,
+    /// Whether the set is removed, failing the waiters
+    removed: bool,
+    /// Notified on every change of the values
+    eventbus: EventBus,
+}
+
+impl IpcWaitable for SemArrayInner {
+    fn eventbus(&mut self) -> &mut EventBus {
+        &mut self.eventbus
+    }
+}
+
+/// A System V semaphore set
+pub struct SemArray {
+    /// The ID of the set
+    pub id: SemId,
+    /// semid data structure
+    pub semid_ds: Mutex<SemidDs>,
+    inner: Mutex<SemArrayInner>,
 }
 
 impl SemArray {
-    /// remove semaphores
-    pub fn remove(&self) {
-        let mut key2sem = KEY2SEM.write();
-        let key = self.semid_ds.lock().perm.key;
-        key2sem.remove(&key);
-        for sem in self.sems.iter() {
-            sem.remove();
+    /// Get the ID of the semaphore set of `key`, or create a set of `nsems`
+    /// semaphores with `IpcGetFlag::CREAT` in `flags`, owned by the caller of `cred`.
+    ///
+    /// A new set is always created for `IPC_PRIVATE`.
+    pub fn get_or_create(
+        key: u32,
+        nsems: usize,
+        flags: usize,
+        cred: &Credentials,
+    ) -> LxResult<SemId> {
+        let mut arrays = SEM_ARRAYS.write();
+        let flag = IpcGetFlag::from_bits_truncate(flags);
+
+        if key != IPC_PRIVATE {
+            let found = arrays
+                .values()
+                .find(|array| array.semid_ds.lock().perm.key == key);
+            if let Some(array) = found {
+                if flag.contains(IpcGetFlag::CREAT) && flag.contains(IpcGetFlag::EXCLUSIVE) {
+                    return Err(LxError::EEXIST);
+                }
+                let ds = array.semid_ds.lock();
+                ds.perm.check_get(cred, flags)?;
+                if nsems > ds.nsems {
+                    return Err(LxError::EINVAL);
+                }
+                return Ok(array.id);
+            }
+            if !flag.contains(IpcGetFlag::CREAT) {
+                return Err(LxError::ENOENT);
+            }
         }
+        if nsems == 0 || nsems > SEMMSL {
+            return Err(LxError::EINVAL);
+        }
+        let id = alloc_ipc_id(&arrays, &SEM_SEQ)?;
+        let array = Arc::new(SemArray {
+            id,
+            semid_ds: Mutex::new(SemidDs {
+                perm: IpcPerm::new(id, key, flags, cred),
+                otime: 0,
+                ctime: TimeSpec::now().sec,
+                nsems,
+                __pad1: 0,
+                __pad2: 0,
+            }),
+            inner: Mutex::new(SemArrayInner {
+                sems: vec![Sem::default(); nsems],
+                undos: BTreeMap::new(),
+                removed: false,
+                eventbus: EventBus::default(),
+            }),
+        });
+        arrays.insert(id, array);
+        Ok(id)
     }
 
-    /// set last semop time
-    pub fn otime(&self) {
-        self.semid_ds.lock().otime = TimeSpec::now().sec;
+    /// Get a semaphore set by `id`
+    pub fn get(id: SemId) -> LxResult<Arc<Self>> {
+        SEM_ARRAYS.read().get(&id).cloned().ok_or(LxError::EINVAL)
+    }
+
+    /// remove semaphores, the waiters fail with `EIDRM`
+    pub fn remove(&self) {
+        SEM_ARRAYS.write().remove(&self.id);
+        let mut inner = self.inner.lock();
+        inner.removed = true;
+        inner.eventbus.notify();
     }
 
     /// set last change time
@@ -68,65 +182,211 @@ impl SemArray {
     /// for IPC_SET
     /// see man semctl(2)
     pub fn set(&self, new: &SemidDs) {
-        let mut lock = self.semid_ds.lock();
-        lock.perm.uid = new.perm.uid;
-        lock.perm.gid = new.perm.gid;
-        lock.perm.mode = new.perm.mode & 0x1ff;
+        self.semid_ds.lock().perm.set(&new.perm);
     }
 
-    /// Get the semaphore array with `key`.
-    /// If not exist, create a new one with `nsems` elements.
-    pub fn get_or_create(mut key: u32, nsems: usize, flags: usize) -> Result<Arc<Self>, LxError> {
-        let mut key2sem = KEY2SEM.write();
-        let flag = IpcGetFlag::from_bits_truncate(flags);
+    /// The number of semaphores in the set
+    pub fn nsems(&self) -> usize {
+        self.inner.lock().sems.len()
+    }
 
-        if key == 0 {
-            // IPC_PRIVATE
-            // find an empty key slot
-            key = (1u32..).find(|i| key2sem.get(i).is_none()).unwrap();
-        } else {
-            // check existence
-            if let Some(weak_array) = key2sem.get(&key) {
-                if let Some(array) = weak_array.upgrade() {
-                    if flag.contains(IpcGetFlag::CREAT) && flag.contains(IpcGetFlag::EXCLUSIVE) {
-                        // exclusive
-                        return Err(LxError::EEXIST);
-                    }
-                    return Ok(array);
+    /// Get the `num`-th semaphore
+    fn sem(&self, num: usize) -> LxResult<Sem> {
+        self.inner
+            .lock()
+            .sems
+            .get(num)
+            .copied()
+            .ok_or(LxError::EINVAL)
+    }
+
+    /// Get the value of the `num`-th semaphore
+    pub fn get_value(&self, num: usize) -> LxResult<i32> {
+        Ok(self.sem(num)?.value)
+    }
+
+    /// Get the PID of the last operation on the `num`-th semaphore
+    pub fn get_pid(&self, num: usize) -> LxResult<u32> {
+        Ok(self.sem(num)?.pid)
+    }
+
+    /// Get the number of the waiters for the `num`-th semaphore to increase
+    pub fn get_ncnt(&self, num: usize) -> LxResult<usize> {
+        Ok(self.sem(num)?.ncnt)
+    }
+
+    /// Get the number of the waiters for the `num`-th semaphore to become zero
+    pub fn get_zcnt(&self, num: usize) -> LxResult<usize> {
+        Ok(self.sem(num)?.zcnt)
+    }
+
+    /// Get the values of all semaphores
+    pub fn get_all(&self) -> Vec<u16> {
+        let inner = self.inner.lock();
+        inner.sems.iter().map(|sem| sem.value as u16).collect()
+    }
+
+    /// Set the value of the `num`-th semaphore by the process `pid`, clearing
+    /// the adjustments of it.
+    pub fn set_value(&self, num: usize, value: i32, pid: u32) -> LxResult {
+        if !(0..=SEMVMX).contains(&value) {
+            return Err(LxError::ERANGE);
+        }
+        let mut inner = self.inner.lock();
+        let sem = inner.sems.get_mut(num).ok_or(LxError::EINVAL)?;
+        sem.value = value;
+        sem.pid = pid;
+        inner.undos.retain(|&(_, n), _| n as usize != num);
+        inner.eventbus.notify();
+        drop(inner);
+        self.ctime();
+        Ok(())
+    }
+
+    /// Set the values of all semaphores by the process `pid`, clearing all
+    /// the adjustments.
+    pub fn set_all(&self, values: &[u16], pid: u32) -> LxResult {
+        if values.iter().any(|&value| value as i32 > SEMVMX) {
+            return Err(LxError::ERANGE);
+        }
+        let mut inner = self.inner.lock();
+        for (sem, &value) in inner.sems.iter_mut().zip(values) {
+            sem.value = value as i32;
+            sem.pid = pid;
+        }
+        inner.undos.clear();
+        inner.eventbus.notify();
+        drop(inner);
+        self.ctime();
+        Ok(())
+    }
+
+    /// Perform all `ops` atomically by the process `pid`, waiting until none
+    /// of them blocks, or fail with `EAGAIN` if the one blocking has
+    /// `SemFlags::IPC_NOWAIT`.
+    ///
+    /// The adjustments of the `SemFlags::SEM_UNDO` operations are recorded
+    /// along with them, and undone by [`exit`](Self::exit).
+    pub async fn semop(&self, ops: &[SemBuf], pid: KoID) -> LxResult {
+        if ops.iter().any(|op| op.num as usize >= self.nsems()) {
+            return Err(LxError::EFBIG);
+        }
+        let mut waiting = SemWaiting {
+            array: self,
+            blocked: None,
+        };
+        ipc_wait(&self.inner, move |inner| {
+            if let Some((num, zero)) = waiting.blocked.take() {
+                inner.sems[num].unwait(zero);
+            }
+            if inner.removed {
+                return Poll::Ready(Err(LxError::EIDRM));
+            }
+            match inner.try_semop(ops, pid) {
+                Ok(None) => Poll::Ready(Ok(())),
+                Ok(Some((num, zero))) => {
+                    inner.sems[num].wait(zero);
+                    waiting.blocked = Some((num, zero));
+                    Poll::Pending
                 }
+                Err(err) => Poll::Ready(Err(err)),
+            }
+        })
+        .await?;
+        self.semid_ds.lock().otime = TimeSpec::now().sec;
+        Ok(())
+    }
+
+    /// Undo the `SemFlags::SEM_UNDO` operations of the process `pid` on its
+    /// exit, keeping the values in range.
+    pub fn exit(&self, pid: KoID) {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let mut changed = false;
+        inner.undos.retain(|&(owner, num), &mut adj| {
+            if owner != pid {
+                return true;
             }
+            debug!("semundo: id: {}, num: {}, adj: {}", self.id, num, adj);
+            let sem = &mut inner.sems[num as usize];
+            sem.value = (sem.value + adj).max(0).min(SEMVMX);
+            changed = true;
+            false
+        });
+        if changed {
+            inner.eventbus.notify();
         }
+    }
+}
 
-        // not found, create one
-        let mut semaphores = Vec::new();
-        for _ in 0..nsems {
-            semaphores.push(Semaphore::new(0));
+impl SemArrayInner {
+    /// Perform all `ops` if none of them blocks.
+    ///
+    /// Returns the semaphore blocking, and whether it is waited to be zero.
+    fn try_semop(&mut self, ops: &[SemBuf], pid: KoID) -> LxResult<Option<(usize, bool)>> {
+        let mut values: Vec<i32> = self.sems.iter().map(|sem| sem.value).collect();
+        for op in ops {
+            let num = op.num as usize;
+            let value = values[num] + op.op as i32;
+            let blocked = match op.op {
+                0 => values[num] != 0,
+                _ => value < 0,
+            };
+            if blocked {
+                if SemFlags::from_bits_truncate(op.flags).contains(SemFlags::IPC_NOWAIT) {
+                    return Err(LxError::EAGAIN);
+                }
+                return Ok(Some((num, op.op == 0)));
+            }
+            if value > SEMVMX {
+                return Err(LxError::ERANGE);
+            }
+            values[num] = value;
         }
+        for (sem, value) in self.sems.iter_mut().zip(values) {
+            sem.value = value;
+        }
+        for op in ops {
+            self.sems[op.num as usize].pid = pid as u32;
+            let flags = SemFlags::from_bits_truncate(op.flags);
+            if flags.contains(SemFlags::SEM_UNDO) && op.op != 0 {
+                *self.undos.entry((pid, op.num)).or_insert(0) -= op.op as i32;
+            }
+        }
+        self.eventbus.notify();
+        Ok(None)
+    }
+}
 
-        // insert to global map
-        let array = Arc::new(SemArray {
-            semid_ds: Mutex::new(SemidDs {
-                perm: IpcPerm {
-                    key,
-                    uid: 0,
-                    gid: 0,
-                    cuid: 0,
-                    cgid: 0,
-                    // least significant 9 bits
-                    mode: (flags as u32) & 0x1ff,
-                    __seq: 0,
-                    __pad1: 0,
-                    __pad2: 0,
-                },
-                otime: 0,
-                ctime: TimeSpec::now().sec,
-                nsems,
-                __pad1: 0,
-                __pad2: 0,
-            }),
-            sems: semaphores,
-        });
-        key2sem.insert(key, Arc::downgrade(&array));
-        Ok(array)
+impl Sem {
+    fn wait(&mut self, zero: bool) {
+        if zero {
+            self.zcnt += 1;
+        } else {
+            self.ncnt += 1;
+        }
+    }
+
+    fn unwait(&mut self, zero: bool) {
+        if zero {
+            self.zcnt -= 1;
+        } else {
+            self.ncnt -= 1;
+        }
+    }
+}
+
+/// The semaphore a `semop` is blocked on, not counted as waited any more
+/// when the operation is cancelled.
+struct SemWaiting<'a> {
+    array: &'a SemArray,
+    blocked: Option<(usize, bool)>,
+}
+
+impl Drop for SemWaiting<'_> {
+    fn drop(&mut self) {
+        if let Some((num, zero)) = self.blocked.take() {
+            self.array.inner.lock().sems[num].unwait(zero);
+        }
     }
 }
@@ -1,16 +1,30 @@
 //! Linux Shared memory ipc
 use super::*;
-use crate::error::LxError;
+use crate::cred::{Credentials, R_OK, W_OK};
+use crate::error::{LxError, LxResult};
 use crate::time::TimeSpec;
-use alloc::{collections::BTreeMap, sync::Arc, sync::Weak};
+use alloc::{collections::BTreeMap, sync::Arc};
 use lazy_static::lazy_static;
 use lock::{Mutex, RwLock};
 use zircon_object::vm::*;
 
 lazy_static! {
-    static ref KEY2SHM: RwLock<BTreeMap<u32, Weak<Mutex<ShmGuard>>>> = RwLock::new(BTreeMap::new());
+    /// Shared memory segments of the system by ID
+    static ref SHM_SEGMENTS: RwLock<BTreeMap<ShmId, Arc<ShmGuard>>> = RwLock::new(BTreeMap::new());
 }
 
+/// The sequence number of the next shared memory segment ID
+static SHM_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// The flag in `IpcPerm::mode` of a segment to be destroyed after the last detach
+const SHM_DEST: u32 = 0o1000;
+
+/// The minimum size of a segment
+const SHMMIN: usize = 1;
+
+/// The maximum size of a segment, the default of Linux
+const SHMMAX: usize = usize::MAX - (1 << 24);
+
 /// shmid data structure
 ///
 /// struct shmid_ds
@@ -35,65 +49,68 @@ pub struct ShmidDs {
     pub nattch: usize,
 }
 
-/// shared memory Identifier for process
+/// A shared memory segment attached to a process
 #[derive(Clone)]
 pub struct ShmIdentifier {
     /// Shared memory address
     pub addr: usize,
     /// Shared memory buffer and data
-    pub guard: Arc<Mutex<ShmGuard>>,
+    pub guard: Arc<ShmGuard>,
 }
 
 /// shared memory buffer and data
 pub struct ShmGuard {
+    /// The ID of the segment
+    pub id: ShmId,
     /// shared memory buffer
     pub shared_guard: Arc<VmObject>,
     /// shared memory data
     pub shmid_ds: Mutex<ShmidDs>,
 }
 
-impl ShmIdentifier {
-    /// set the shared memory address on attach
-    pub fn set_addr(&mut self, addr: usize) {
-        self.addr = addr;
-    }
-
-    /// Get or create a ShmGuard
-    pub fn new_shared_guard(
+impl ShmGuard {
+    /// Get the ID of the segment of `key`, or create a segment of `size` bytes
+    /// with `IpcGetFlag::CREAT` in `flags`, owned by the caller of `cred`.
+    ///
+    /// A new segment is always created for `IPC_PRIVATE`.
+    pub fn get_or_create(
         key: u32,
-        memsize: usize,
+        size: usize,
         flags: usize,
         cpid: u32,
-    ) -> Result<Arc<Mutex<ShmGuard>>, LxError> {
-        let mut key2shm = KEY2SHM.write();
+        cred: &Credentials,
+    ) -> LxResult<ShmId> {
+        let mut segments = SHM_SEGMENTS.write();
         let flag = IpcGetFlag::from_bits_truncate(flags);
 
-        // found in the map
-        if let Some(weak_guard) = key2shm.get(&key) {
-            if let Some(guard) = weak_guard.upgrade() {
+        if key != IPC_PRIVATE {
+            let found = segments
+                .values()
+                .find(|shm| shm.shmid_ds.lock().perm.key == key);
+            if let Some(shm) = found {
                 if flag.contains(IpcGetFlag::CREAT) && flag.contains(IpcGetFlag::EXCLUSIVE) {
-                    // exclusive
                     return Err(LxError::EEXIST);
                 }
-                return Ok(guard);
+                shm.shmid_ds.lock().perm.check_get(cred, flags)?;
+                if size > shm.shmid_ds.lock().segsz {
+                    return Err(LxError::EINVAL);
+                }
+                return Ok(shm.id);
+            }
+            if !flag.contains(IpcGetFlag::CREAT) {
+                return Err(LxError::ENOENT);
             }
         }
-        let shared_guard = Arc::new(Mutex::new(ShmGuard {
-            shared_guard: VmObject::new_paged(pages(memsize)),
+        if !(SHMMIN..=SHMMAX).contains(&size) {
+            return Err(LxError::EINVAL);
+        }
+        let id = alloc_ipc_id(&segments, &SHM_SEQ)?;
+        let shm = Arc::new(ShmGuard {
+            id,
+            shared_guard: VmObject::new_paged(pages(size)),
             shmid_ds: Mutex::new(ShmidDs {
-                perm: IpcPerm {
-                    key,
-                    uid: 0,
-                    gid: 0,
-                    cuid: 0,
-                    cgid: 0,
-                    // least significant 9 bits
-                    mode: (flags as u32) & 0x1ff,
-                    __seq: 0,
-                    __pad1: 0,
-                    __pad2: 0,
-                },
-                segsz: memsize,
+                perm: IpcPerm::new(id, key, flags, cred),
+                segsz: size,
                 atime: 0,
                 dtime: 0,
                 ctime: TimeSpec::now().sec,
@@ -101,14 +118,61 @@ impl ShmIdentifier {
                 lpid: 0,
                 nattch: 0,
             }),
-        }));
-        // insert to global map
-        key2shm.insert(key, Arc::downgrade(&shared_guard));
-        Ok(shared_guard)
+        });
+        segments.insert(id, shm);
+        Ok(id)
+    }
+
+    /// Get a segment by `id`
+    pub fn get(id: ShmId) -> LxResult<Arc<Self>> {
+        SHM_SEGMENTS.read().get(&id).cloned().ok_or(LxError::EINVAL)
+    }
+
+    /// The number of segments and the pages of them, for `SHM_INFO`
+    pub fn usage() -> (usize, usize) {
+        let segments = SHM_SEGMENTS.read();
+        let pages = segments
+            .values()
+            .map(|shm| shm.shared_guard.len() / PAGE_SIZE)
+            .sum();
+        (segments.len(), pages)
+    }
+
+    /// Get a segment by its index in the table, for `SHM_STAT`
+    pub fn get_by_index(index: usize) -> LxResult<Arc<Self>> {
+        SHM_SEGMENTS
+            .read()
+            .values()
+            .find(|shm| ipc_index(shm.id) == index)
+            .cloned()
+            .ok_or(LxError::EINVAL)
+    }
+
+    /// The largest index in use, for `SHM_INFO`
+    pub fn max_index() -> usize {
+        SHM_SEGMENTS
+            .read()
+            .keys()
+            .map(|&id| ipc_index(id))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Check the access of `mask` (`R_OK`, `W_OK`) to the segment.
+    pub fn check_access(&self, cred: &Credentials, mask: usize) -> LxResult {
+        self.shmid_ds.lock().perm.check_access(cred, mask)
+    }
+
+    /// Check the caller of `cred` may change or remove the segment.
+    pub fn check_owner(&self, cred: &Credentials) -> LxResult {
+        self.shmid_ds.lock().perm.check_owner(cred)
+    }
+
+    /// Check the access for `shmat`, which is read only if not `writable`.
+    pub fn check_attach(&self, cred: &Credentials, writable: bool) -> LxResult {
+        self.check_access(cred, if writable { R_OK | W_OK } else { R_OK })
     }
-}
 
-impl ShmGuard {
     /// set last attach time
     pub fn attach(&self, pid: u32) {
         let mut ds = self.shmid_ds.lock();
@@ -121,8 +185,25 @@ impl ShmGuard {
     pub fn detach(&self, pid: u32) {
         let mut ds = self.shmid_ds.lock();
         ds.dtime = TimeSpec::now().sec;
-        ds.nattch -= 1;
         ds.lpid = pid;
+        drop(ds);
+        self.release();
+    }
+
+    /// Count an attach inherited by a child process
+    fn inherit(&self) {
+        self.shmid_ds.lock().nattch += 1;
+    }
+
+    /// Drop an attach, destroying the segment removed after the last one
+    fn release(&self) {
+        let mut ds = self.shmid_ds.lock();
+        ds.nattch -= 1;
+        let destroy = ds.nattch == 0 && ds.perm.mode & SHM_DEST != 0;
+        drop(ds);
+        if destroy {
+            SHM_SEGMENTS.write().remove(&self.id);
+        }
     }
 
     /// set last change time
@@ -133,16 +214,53 @@ impl ShmGuard {
     /// for IPC_SET
     /// see man shmctl(2)
     pub fn set(&self, new: &ShmidDs) {
-        let mut lock = self.shmid_ds.lock();
-        lock.perm.uid = new.perm.uid;
-        lock.perm.gid = new.perm.gid;
-        lock.perm.mode = new.perm.mode & 0x1ff;
+        self.shmid_ds.lock().perm.set(&new.perm);
     }
 
-    /// remove Shared memory
+    /// Mark the segment to be destroyed after the last detach, and forget its
+    /// key, so that `shmget` creates another segment for it.
     pub fn remove(&self) {
-        let mut key2shm = KEY2SHM.write();
-        let key = self.shmid_ds.lock().perm.key;
-        key2shm.remove(&key);
+        let mut ds = self.shmid_ds.lock();
+        ds.perm.mode |= SHM_DEST;
+        ds.perm.key = IPC_PRIVATE;
+        let destroy = ds.nattch == 0;
+        drop(ds);
+        if destroy {
+            SHM_SEGMENTS.write().remove(&self.id);
+        }
+    }
+}
+
+impl ShmProc {
+    /// Record the segment of `guard` attached at `addr`
+    pub fn attach(&mut self, addr: usize, guard: Arc<ShmGuard>) {
+        self.shm_identifiers
+            .insert(addr, ShmIdentifier { addr, guard });
+    }
+
+    /// Forget the segment attached at `addr`, returning it
+    pub fn detach(&mut self, addr: usize) -> Option<ShmIdentifier> {
+        self.shm_identifiers.remove(&addr)
+    }
+}
+
+/// Fork the shared memory table, the child process attaches the segments as well.
+impl Clone for ShmProc {
+    fn clone(&self) -> Self {
+        for shm in self.shm_identifiers.values() {
+            shm.guard.inherit();
+        }
+        ShmProc {
+            shm_identifiers: self.shm_identifiers.clone(),
+        }
+    }
+}
+
+/// Detach all segments when the process exits or executes another program
+impl Drop for ShmProc {
+    fn drop(&mut self) {
+        for shm in self.shm_identifiers.values() {
+            shm.guard.release();
+        }
     }
 }
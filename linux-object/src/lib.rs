@@ -18,10 +18,14 @@ pub mod error;
 pub mod fs;
 
 // layer 2
+pub mod coredump;
+pub mod cred;
 pub mod ipc;
 pub mod loader;
 pub mod net;
+pub mod pid_ns;
 pub mod process;
+pub mod seccomp;
 pub mod signal;
 pub mod sync;
 pub mod thread;
@@ -113,7 +113,7 @@ pub const AT_PHDR: u8 = 3;
 pub const AT_PHENT: u8 = 4;
 pub const AT_PHNUM: u8 = 5;
 pub const AT_PAGESZ: u8 = 6;
-#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "loongarch64"))]
 pub const AT_BASE: u8 = 7;
-#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "loongarch64"))]
 pub const AT_ENTRY: u8 = 9;
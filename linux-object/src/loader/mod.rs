@@ -12,6 +12,10 @@ use {
 
 mod abi;
 
+/// The number of guard pages below a stack, into which it grows down, as
+/// the default stack guard gap of Linux.
+pub const STACK_GUARD_PAGES: usize = 256;
+
 /// Linux ELF Program Loader.
 pub struct LinuxElfLoader {
     /// syscall entry
@@ -46,7 +50,9 @@ impl LinuxElfLoader {
 
         if let Ok(interp) = elf.get_interpreter() {
             info!("interp: {:?}, path: {:?}", interp, path);
-            let inode = self.root_inode.lookup(interp)?;
+            // relative to the root directory, which is not the root of the
+            // file system after `chroot`
+            let inode = self.root_inode.lookup(interp.trim_start_matches('/'))?;
             let data = inode.read_as_vec()?;
             let mut new_args = vec![interp.into(), path.clone()];
             new_args.extend_from_slice(&args[1..]);
@@ -82,10 +88,11 @@ impl LinuxElfLoader {
             }
         }
 
-        let stack_vmo = VmObject::new_paged(self.stack_pages);
+        let stack_vmo = VmObject::new_paged(STACK_GUARD_PAGES + self.stack_pages);
         let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
-        let stack_bottom = vmar.map(None, stack_vmo.clone(), 0, stack_vmo.len(), flags)?;
-        let mut sp = stack_bottom + stack_vmo.len();
+        let guard_size = STACK_GUARD_PAGES * PAGE_SIZE;
+        let stack_bottom = vmar.map_with_guard(None, stack_vmo.clone(), guard_size, flags, true)?;
+        let mut sp = stack_bottom + self.stack_pages * PAGE_SIZE;
         debug!("load stack bottom: {:#x}", stack_bottom);
 
         let info = abi::ProcInitInfo {
@@ -103,7 +110,7 @@ impl LinuxElfLoader {
                 if let Some(phdr_vaddr) = elf.get_phdr_vaddr() {
                     map.insert(abi::AT_PHDR, phdr_vaddr as usize);
                 }
-                #[cfg(target_arch = "aarch64")]
+                #[cfg(any(target_arch = "aarch64", target_arch = "loongarch64"))]
                 {
                     map.insert(abi::AT_BASE, base);
                     map.insert(abi::AT_ENTRY, entry);
@@ -118,7 +125,7 @@ impl LinuxElfLoader {
             },
         };
         let init_stack = info.push_at(sp);
-        stack_vmo.write(self.stack_pages * PAGE_SIZE - init_stack.len(), &init_stack)?;
+        stack_vmo.write(stack_vmo.len() - init_stack.len(), &init_stack)?;
         sp -= init_stack.len();
 
         debug!(
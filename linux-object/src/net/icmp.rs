@@ -0,0 +1,331 @@
+// icmpsocket
+
+use crate::error::{LxError, LxResult};
+use crate::fs::{ChangeCallback, FileLike, OpenFlags, PollStatus};
+use crate::net::*;
+use alloc::{boxed::Box, sync::Arc, vec};
+use async_trait::async_trait;
+use lock::Mutex;
+use smoltcp::socket::{IcmpEndpoint, IcmpPacketMetadata, IcmpSocket, IcmpSocketBuffer};
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet};
+
+// third part
+#[allow(unused_imports)]
+use zircon_object::impl_kobject;
+#[allow(unused_imports)]
+use zircon_object::object::*;
+
+/// ICMP datagram socket structure, as the "ping" sockets of Linux
+///
+/// Only echo requests can be sent through it. The identifier of the
+/// requests is replaced by the one the socket is bound to, so that the
+/// replies are delivered back to this socket.
+pub struct IcmpSocketState {
+    /// Kernel object base
+    base: KObjectBase,
+    /// IcmpSocket Inner
+    inner: Mutex<IcmpInner>,
+}
+
+/// ICMP socket inner
+pub struct IcmpInner {
+    /// A wrapper for `SocketHandle`
+    handle: GlobalSocketHandle,
+    /// The echo identifier, assigned when bound
+    ident: Option<u16>,
+    /// remember remote address for connect fn
+    remote_addr: Option<IpAddress>,
+    /// Options of the socket level.
+    options: SocketOptions,
+    /// flags on the socket
+    flags: OpenFlags,
+}
+
+impl Default for IcmpSocketState {
+    fn default() -> Self {
+        IcmpSocketState::new()
+    }
+}
+
+impl IcmpSocketState {
+    /// missing documentation
+    pub fn new() -> Self {
+        info!("icmp new");
+        let rx_buffer = IcmpSocketBuffer::new(
+            vec![IcmpPacketMetadata::EMPTY; ICMP_METADATA_BUF],
+            vec![0; ICMP_RECVBUF],
+        );
+        let tx_buffer = IcmpSocketBuffer::new(
+            vec![IcmpPacketMetadata::EMPTY; ICMP_METADATA_BUF],
+            vec![0; ICMP_SENDBUF],
+        );
+        let socket = IcmpSocket::new(rx_buffer, tx_buffer);
+        let handle = GlobalSocketHandle(get_sockets().lock().add(socket));
+
+        IcmpSocketState {
+            base: KObjectBase::new(),
+            inner: Mutex::new(IcmpInner {
+                handle,
+                ident: None,
+                remote_addr: None,
+                options: SocketOptions::default(),
+                flags: OpenFlags::RDWR,
+            }),
+        }
+    }
+}
+
+impl IcmpSocketState {
+    fn is_nonblock(&self) -> bool {
+        self.inner.lock().flags.contains(OpenFlags::NON_BLOCK)
+    }
+
+    /// Bind the socket to the echo identifier `ident`.
+    fn bind_ident(inner: &mut IcmpInner, ident: u16) -> SysResult {
+        let sockets = get_sockets();
+        let mut set = sockets.lock();
+        let mut socket = set.get::<IcmpSocket>(inner.handle.0);
+        match socket.bind(IcmpEndpoint::Ident(ident)) {
+            Ok(()) => {
+                inner.ident = Some(ident);
+                Ok(0)
+            }
+            Err(_) => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Try to receive an ICMP message, `None` if it would block.
+    fn try_recv(&self, data: &mut [u8]) -> Option<LxResult<(usize, IpAddress)>> {
+        let inner = self.inner.lock();
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<IcmpSocket>(inner.handle.0);
+        loop {
+            match socket.recv() {
+                // the requests with our identifier are delivered too,
+                // e.g. the ones we sent to ourselves on the loopback
+                Ok((payload, _)) if payload.first() == Some(&8) => continue,
+                Ok((payload, addr)) => {
+                    let len = payload.len().min(data.len());
+                    data[..len].copy_from_slice(&payload[..len]);
+                    return Some(Ok((len, addr)));
+                }
+                // The receive buffer is empty. Try again later...
+                Err(smoltcp::Error::Exhausted) => return None,
+                Err(err) => {
+                    error!("icmp socket recv error: {:?}", err);
+                    return Some(Err(LxError::ENOTCONN));
+                }
+            }
+        }
+    }
+}
+
+/// missing in implementation
+#[async_trait]
+impl Socket for IcmpSocketState {
+    /// read to buffer
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint) {
+        info!("icmp read");
+        let nonblock = self.is_nonblock() || flags.contains(MsgFlags::DONTWAIT);
+        match wait_socket(nonblock, || self.try_recv(data)).await {
+            Ok((size, addr)) => (Ok(size), Endpoint::Ip(IpEndpoint::new(addr, 0))),
+            Err(err) => (Err(err), Endpoint::Ip(IpEndpoint::UNSPECIFIED)),
+        }
+    }
+    /// write from buffer
+    fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
+        info!("icmp write");
+        let mut inner = self.inner.lock();
+        let remote_addr = match sendto_endpoint {
+            Some(Endpoint::Ip(endpoint)) => endpoint.addr,
+            Some(_) => return Err(LxError::EINVAL),
+            None => inner.remote_addr.ok_or(LxError::EDESTADDRREQ)?,
+        };
+        let mut packet = vec![0u8; data.len()];
+        packet.copy_from_slice(data);
+        let mut icmp = Icmpv4Packet::new_checked(&mut packet[..]).map_err(|_| LxError::EINVAL)?;
+        if icmp.msg_type() != Icmpv4Message::EchoRequest || icmp.msg_code() != 0 {
+            return Err(LxError::EINVAL);
+        }
+        let ident = match inner.ident {
+            Some(ident) => ident,
+            None => {
+                let ident = get_ephemeral_port();
+                Self::bind_ident(&mut inner, ident)?;
+                ident
+            }
+        };
+        icmp.set_echo_ident(ident);
+        icmp.fill_checksum();
+
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<IcmpSocket>(inner.handle.0);
+        match socket.send_slice(&packet, remote_addr) {
+            Ok(()) => {}
+            Err(smoltcp::Error::Exhausted) => return Err(LxError::ENOBUFS),
+            Err(_) => return Err(LxError::EINVAL),
+        }
+
+        drop(socket);
+        drop(sets);
+        drop(inner);
+        poll_ifaces();
+
+        Ok(data.len())
+    }
+    /// connect
+    async fn connect(&self, endpoint: Endpoint) -> SysResult {
+        if let Endpoint::Ip(ip) = endpoint {
+            self.inner.lock().remote_addr = Some(ip.addr);
+            Ok(0)
+        } else {
+            Err(LxError::EINVAL)
+        }
+    }
+    /// wait for some event on a file descriptor
+    fn poll(&self, events: PollEvents) -> (bool, bool, bool) {
+        if events.contains(PollEvents::IN) {
+            poll_ifaces();
+        }
+        let inner = self.inner.lock();
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let socket = sets.get::<IcmpSocket>(inner.handle.0);
+        let input = socket.can_recv();
+        // an unbound socket is bound on the first send
+        let output = !socket.is_open() || socket.can_send();
+        (input, output, false)
+    }
+
+    fn bind(&self, endpoint: Endpoint) -> SysResult {
+        info!("icmp bind");
+        let mut inner = self.inner.lock();
+        if inner.ident.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        match endpoint {
+            Endpoint::Ip(ip) => {
+                let ident = match ip.port {
+                    0 => get_ephemeral_port(),
+                    port => port,
+                };
+                Self::bind_ident(&mut inner, ident)
+            }
+            _ => Err(LxError::EINVAL),
+        }
+    }
+    fn listen(&self, _backlog: usize) -> SysResult {
+        Err(LxError::EOPNOTSUPP)
+    }
+    fn shutdown(&self, _how: Shutdown) -> SysResult {
+        Err(LxError::EOPNOTSUPP)
+    }
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, Endpoint)> {
+        Err(LxError::EOPNOTSUPP)
+    }
+    fn endpoint(&self) -> Option<Endpoint> {
+        self.inner
+            .lock()
+            .ident
+            .map(|ident| Endpoint::Ip(IpEndpoint::new(IpAddress::Unspecified, ident)))
+    }
+    fn remote_endpoint(&self) -> Option<Endpoint> {
+        self.inner
+            .lock()
+            .remote_addr
+            .map(|addr| Endpoint::Ip(IpEndpoint::new(addr, 0)))
+    }
+    fn setsockopt(&self, _level: usize, _opt: usize, _data: &[u8]) -> SysResult {
+        warn!("setsockopt is unimplemented");
+        Ok(0)
+    }
+
+    fn get_buffer_capacity(&self) -> Option<(usize, usize)> {
+        let sockets = get_sockets();
+        let mut set = sockets.lock();
+        let socket = set.get::<IcmpSocket>(self.inner.lock().handle.0);
+        let (recv_ca, send_ca) = (
+            socket.payload_recv_capacity(),
+            socket.payload_send_capacity(),
+        );
+        Some((recv_ca, send_ca))
+    }
+
+    fn socket_type(&self) -> Option<SocketType> {
+        Some(SocketType::SOCK_DGRAM)
+    }
+
+    fn socket_options(&self) -> Option<SocketOptions> {
+        Some(self.inner.lock().options)
+    }
+
+    fn set_socket_options(&self, options: SocketOptions) -> SysResult {
+        self.inner.lock().options = options;
+        Ok(0)
+    }
+}
+
+impl_kobject!(IcmpSocketState);
+
+#[async_trait]
+impl FileLike for IcmpSocketState {
+    fn flags(&self) -> OpenFlags {
+        self.inner.lock().flags
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.inner.lock().flags;
+
+        // See fcntl, only O_APPEND, O_ASYNC, O_DIRECT, O_NOATIME, O_NONBLOCK
+        flags.set(OpenFlags::APPEND, f.contains(OpenFlags::APPEND));
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Socket::read(self, buf, MsgFlags::empty()).await.0
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        Socket::write(self, buf, None)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        let (read, write, error) = Socket::poll(self, events);
+        Ok(PollStatus { read, write, error })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        wait_socket(false, || {
+            let (read, write, error) = Socket::poll(self, events);
+            let ready = (read && events.contains(PollEvents::IN))
+                || (write && events.contains(PollEvents::OUT))
+                || error;
+            ready.then(|| Ok(PollStatus { read, write, error }))
+        })
+        .await
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        on_socket_activity(callback)
+    }
+
+    fn ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize> {
+        Socket::ioctl(self, request, arg1, arg2, arg3)
+    }
+
+    fn as_socket(&self) -> LxResult<&dyn Socket> {
+        Ok(self)
+    }
+}
@@ -4,8 +4,8 @@
 /// missing documentation
 #[macro_use]
 pub mod socket_address;
-use crate::fs::{FileLike, PollEvents};
-use smoltcp::wire::IpEndpoint;
+use crate::fs::{ChangeCallback, FileLike, PollEvents};
+use smoltcp::wire::{IpCidr, IpEndpoint};
 pub use socket_address::*;
 
 /// missing documentation
@@ -24,9 +24,13 @@ pub use raw::*;
 pub mod netlink;
 pub use netlink::*;
 
-/// missing documentation
-// pub mod icmp;
-// pub use icmp::*;
+/// Unix domain sockets
+pub mod unix;
+pub use unix::*;
+
+/// ICMP datagram sockets
+pub mod icmp;
+pub use icmp::*;
 
 // pub mod stack;
 
@@ -79,6 +83,13 @@ pub const ICMP_SENDBUF: usize = 64 * 1024; // 64K
 /// missing documentation
 pub const ICMP_RECVBUF: usize = 64 * 1024; // 64K
 
+// ========UNIX
+
+/// The buffer sizes reported for unix sockets, whose queues are not bounded.
+pub const UNIX_SENDBUF: usize = 208 * 1024;
+/// missing documentation
+pub const UNIX_RECVBUF: usize = 208 * 1024;
+
 // ========Other
 
 /// missing documentation
@@ -183,12 +194,22 @@ numeric_enum! {
     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
     /// Generic musl socket optname.
     pub enum SolOptname {
+        /// reuseaddr
+        REUSEADDR = 2,
+        /// type
+        TYPE = 3,
+        /// error
+        ERROR = 4,
         /// sndbuf
         SNDBUF = 7,  // 获取发送缓冲区长度
         /// rcvbuf
         RCVBUF = 8,  // 获取接收缓冲区长度
         /// linger
         LINGER = 13,
+        /// receive `SCM_CREDENTIALS` messages
+        PASSCRED = 16,
+        /// credentials of the peer
+        PEERCRED = 17,
     }
 }
 
@@ -212,6 +233,94 @@ numeric_enum! {
     }
 }
 
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+    #[allow(non_camel_case_types)]
+    /// How to shut down a socket.
+    pub enum Shutdown {
+        /// Further receptions are disallowed.
+        SHUT_RD = 0,
+        /// Further transmissions are disallowed.
+        SHUT_WR = 1,
+        /// Both are disallowed.
+        SHUT_RDWR = 2,
+    }
+}
+
+impl Shutdown {
+    /// Whether further receptions are disallowed.
+    pub fn read(self) -> bool {
+        self != Shutdown::SHUT_WR
+    }
+
+    /// Whether further transmissions are disallowed.
+    pub fn write(self) -> bool {
+        self != Shutdown::SHUT_RD
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of `send()` and `recv()`.
+    pub struct MsgFlags: usize {
+        /// Return the data without removing it from the queue.
+        const PEEK = 0x2;
+        /// Do not block, as if the socket were non-blocking.
+        const DONTWAIT = 0x40;
+        /// Return the real length of a datagram, even if it was truncated.
+        const TRUNC = 0x20;
+        /// Do not raise `SIGPIPE` when the peer closed the connection.
+        const NOSIGNAL = 0x4000;
+        /// Set close-on-exec on the file descriptors received by `SCM_RIGHTS`.
+        const CMSG_CLOEXEC = 0x4000_0000;
+    }
+}
+
+/// Level of the control messages of sockets.
+pub const SOL_SOCKET: i32 = 1;
+/// Control message passing file descriptors.
+pub const SCM_RIGHTS: i32 = 1;
+/// Control message passing process credentials.
+pub const SCM_CREDENTIALS: i32 = 2;
+/// The maximum number of file descriptors passed by one `SCM_RIGHTS` message.
+pub const SCM_MAX_FD: usize = 253;
+/// The `msg_flags` bit telling some control data was discarded.
+pub const MSG_CTRUNC: usize = 0x8;
+
+/// Process credentials, as `struct ucred`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UCred {
+    /// process ID
+    pub pid: u32,
+    /// user ID
+    pub uid: u32,
+    /// group ID
+    pub gid: u32,
+}
+
+/// Ancillary data passed along a message by `sendmsg()` and `recvmsg()`.
+#[derive(Default, Clone)]
+pub struct Ancillary {
+    /// Files passed by `SCM_RIGHTS`.
+    pub rights: Vec<Arc<dyn FileLike>>,
+    /// Credentials passed by `SCM_CREDENTIALS`.
+    pub cred: Option<UCred>,
+}
+
+/// Options of the socket level, kept by the sockets supporting them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SocketOptions {
+    /// Whether the local address may be reused, by `SO_REUSEADDR`.
+    pub reuse_addr: bool,
+    /// The receive buffer size asked by `SO_RCVBUF`.
+    pub recv_buf: Option<usize>,
+    /// The send buffer size asked by `SO_SNDBUF`.
+    pub send_buf: Option<usize>,
+    /// Whether credentials are received along messages, by `SO_PASSCRED`.
+    pub pass_cred: bool,
+}
+
 // ============= Define =============
 
 // ============= SocketHandle =============
@@ -257,8 +366,120 @@ fn poll_ifaces() {
     }
 }
 
+/// Choose the source address of the packets sent to `dst`.
+///
+/// The packets to the loopback network come from 127.0.0.1, the others from
+/// the interface on the network of `dst`, or from any other interface.
+fn source_address(dst: Ipv4Address) -> Option<Ipv4Address> {
+    if dst.is_loopback() {
+        return Some(Ipv4Address::new(127, 0, 0, 1));
+    }
+    let addrs: Vec<_> = get_net_device()
+        .iter()
+        .flat_map(|iface| iface.get_ip_address())
+        .filter_map(|cidr| match cidr {
+            IpCidr::Ipv4(cidr) => Some(cidr),
+            _ => None,
+        })
+        .collect();
+    addrs
+        .iter()
+        .find(|cidr| cidr.contains_addr(&dst))
+        .or_else(|| addrs.iter().find(|cidr| !cidr.address().is_loopback()))
+        .map(|cidr| cidr.address())
+}
+
 // ============= SocketHandle =============
 
+// ============= Waiting =============
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+use core::time::Duration;
+use kernel_hal::timer;
+use zcore_drivers::net::SOCKET_ACTIVITY;
+
+/// How often a waiting socket is checked at least, to run the TCP timers
+/// of the interfaces without IRQs.
+const SOCKET_RECHECK_MS: u64 = 20;
+
+/// Wait until `f` returns `Some`.
+///
+/// `f` is retried after the interfaces are polled, each time they may have
+/// changed the state of the sockets. With `nonblock`, it is tried only once,
+/// and `EAGAIN` is returned if it would block.
+async fn wait_socket<T, F>(nonblock: bool, mut f: F) -> LxResult<T>
+where
+    F: FnMut() -> Option<LxResult<T>> + Send + Unpin,
+{
+    if nonblock {
+        poll_ifaces();
+        return f().unwrap_or(Err(LxError::EAGAIN));
+    }
+    SocketFuture {
+        f,
+        subscribed: Arc::new(AtomicBool::new(false)),
+        timer_set: Arc::new(AtomicBool::new(false)),
+    }
+    .await
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct SocketFuture<F> {
+    f: F,
+    /// Whether a waker is subscribed to [`SOCKET_ACTIVITY`].
+    subscribed: Arc<AtomicBool>,
+    /// Whether a timer is set to recheck.
+    timer_set: Arc<AtomicBool>,
+}
+
+impl<T, F> Future for SocketFuture<F>
+where
+    F: FnMut() -> Option<LxResult<T>> + Unpin,
+{
+    type Output = LxResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        poll_ifaces();
+        // subscribe before checking, not to miss the activity in between
+        if !self.subscribed.swap(true, Ordering::AcqRel) {
+            let (waker, subscribed) = (cx.waker().clone(), self.subscribed.clone());
+            SOCKET_ACTIVITY.subscribe(
+                Box::new(move |_| {
+                    subscribed.store(false, Ordering::Release);
+                    waker.wake_by_ref();
+                }),
+                true,
+            );
+        }
+        if let Some(ret) = (self.f)() {
+            return Poll::Ready(ret);
+        }
+        if !self.timer_set.swap(true, Ordering::AcqRel) {
+            let (waker, timer_set) = (cx.waker().clone(), self.timer_set.clone());
+            timer::timer_set(
+                timer::timer_now() + Duration::from_millis(SOCKET_RECHECK_MS),
+                Box::new(move |_| {
+                    timer_set.store(false, Ordering::Release);
+                    waker.wake_by_ref();
+                }),
+            );
+        }
+        Poll::Pending
+    }
+}
+
+/// Call `callback` once on the next activity of the interfaces, for
+/// [`FileLike::on_change`] of the sockets.
+fn on_socket_activity(callback: ChangeCallback) -> bool {
+    SOCKET_ACTIVITY.subscribe(Box::new(move |_| callback()), true);
+    true
+}
+
+// ============= Waiting =============
+
 // ============= Rand Port =============
 
 /// !!!! need riscv rng
@@ -306,15 +527,32 @@ use crate::error::*;
 use alloc::boxed::Box;
 use alloc::fmt::Debug;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use async_trait::async_trait;
 // use core::ops::{Deref, DerefMut};
 /// Common methods that a socket must have
 #[async_trait]
 pub trait Socket: Send + Sync + Debug {
-    /// missing documentation
-    async fn read(&self, data: &mut [u8]) -> (SysResult, Endpoint);
+    /// Receive data with `flags`, and the endpoint it comes from.
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint);
     /// missing documentation
     fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult;
+    /// Receive data like [`Socket::read`], along with its ancillary data.
+    async fn read_msg(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint, Ancillary) {
+        let (ret, endpoint) = self.read(data, flags).await;
+        (ret, endpoint, Ancillary::default())
+    }
+    /// Send data like [`Socket::write`], along with ancillary data.
+    ///
+    /// Only unix sockets carry the ancillary data, others drop it.
+    fn write_msg(
+        &self,
+        data: &[u8],
+        sendto_endpoint: Option<Endpoint>,
+        _ancillary: Ancillary,
+    ) -> SysResult {
+        self.write(data, sendto_endpoint)
+    }
     /// wait for some event (in, out, err) on a fd
     fn poll(&self, _events: PollEvents) -> (bool, bool, bool) {
         unimplemented!()
@@ -325,12 +563,12 @@ pub trait Socket: Send + Sync + Debug {
     fn bind(&self, _endpoint: Endpoint) -> SysResult {
         Err(LxError::EINVAL)
     }
-    /// missing documentation
-    fn listen(&self) -> SysResult {
+    /// Listen for connections, with at most `backlog` pending ones.
+    fn listen(&self, _backlog: usize) -> SysResult {
         Err(LxError::EINVAL)
     }
-    /// missing documentation
-    fn shutdown(&self) -> SysResult {
+    /// Shut down a part of a full-duplex connection.
+    fn shutdown(&self, _how: Shutdown) -> SysResult {
         Err(LxError::EINVAL)
     }
     /// missing documentation
@@ -363,6 +601,22 @@ pub trait Socket: Send + Sync + Debug {
     fn socket_type(&self) -> Option<SocketType> {
         None
     }
+    /// Get the options of the socket level, if supported.
+    fn socket_options(&self) -> Option<SocketOptions> {
+        None
+    }
+    /// Set the options of the socket level.
+    fn set_socket_options(&self, _options: SocketOptions) -> SysResult {
+        Err(LxError::ENOPROTOOPT)
+    }
+    /// Take the pending error of the socket, reported by `SO_ERROR`.
+    fn take_error(&self) -> Option<LxError> {
+        None
+    }
+    /// Credentials of the peer, reported by `SO_PEERCRED`.
+    fn peer_cred(&self) -> Option<UCred> {
+        None
+    }
 }
 
 /*
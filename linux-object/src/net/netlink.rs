@@ -1,236 +1,336 @@
-// udpsocket
+// netlink socket
 
 use super::socket_address::*;
 use crate::{
     error::{LxError, LxResult},
-    fs::FileLike,
-    net::{AddressFamily, Endpoint, SockAddr, Socket, SysResult},
+    fs::{ChangeCallback, FileLike, OpenFlags, PollEvents, PollStatus},
+    net::{AddressFamily, Endpoint, MsgFlags, Shutdown, SockAddr, Socket, SysResult},
+    sync::{wait_for_event, Event, EventBus},
 };
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
 use async_trait::async_trait;
 use bitflags::bitflags;
 use core::{mem::size_of, slice};
 use kernel_hal::{net::get_net_device, user::*};
 use lock::Mutex;
-
-#[derive(Debug, Clone)]
+use smoltcp::wire::{IpAddress, IpCidr};
+
+// third part
+#[allow(unused_imports)]
+use zircon_object::impl_kobject;
+#[allow(unused_imports)]
+use zircon_object::object::*;
+
+/// `ARPHRD_ETHER`, the hardware type of ethernet interfaces.
+const ARPHRD_ETHER: u16 = 1;
+/// `ARPHRD_LOOPBACK`, the hardware type of the loopback interface.
+const ARPHRD_LOOPBACK: u16 = 772;
+/// The MTU reported for ethernet interfaces.
+const ETHERNET_MTU: u32 = 1500;
+/// The MTU reported for the loopback interface.
+const LOOPBACK_MTU: u32 = 65536;
+/// `RT_SCOPE_HOST`, the scope of addresses valid on this host only.
+const RT_SCOPE_HOST: u8 = 254;
+
+/// Netlink route socket structure, answering the requests of the
+/// `NETLINK_ROUTE` protocol from the state of the network interfaces.
 pub struct NetlinkSocketState {
-    data: Arc<Mutex<Vec<Vec<u8>>>>,
-    _local_endpoint: Option<NetlinkEndpoint>,
+    /// Kernel object base
+    base: KObjectBase,
+    /// `READABLE` while there are responses to receive
+    eventbus: Arc<Mutex<EventBus>>,
+    /// Netlink socket inner
+    inner: Mutex<NetlinkInner>,
 }
 
-impl Default for NetlinkSocketState {
-    fn default() -> Self {
-        Self {
-            data: Arc::new(Mutex::new(Vec::new())),
-            _local_endpoint: Some(NetlinkEndpoint::new(0, 0)),
-        }
-    }
+/// Netlink socket inner
+struct NetlinkInner {
+    /// Responses waiting to be received, one message each.
+    buffer: VecDeque<Vec<u8>>,
+    /// The port ID of the socket, set by `bind()`.
+    port_id: u32,
+    /// flags on the socket
+    flags: OpenFlags,
 }
-impl NetlinkSocketState {}
 
-#[async_trait]
-impl Socket for NetlinkSocketState {
-    /// missing documentation
-    async fn read(&self, data: &mut [u8]) -> (LxResult<usize>, Endpoint) {
-        let mut buffer = self.data.lock();
-        let msg = buffer.remove(0);
-        let len = msg.len();
-        if !msg.is_empty() {
-            data[..len].copy_from_slice(&msg[..len]);
-            (
-                Ok(len),
-                Endpoint::Netlink(NetlinkEndpoint {
-                    port_id: 0,
-                    multicast_groups_mask: 0,
-                }),
-            )
-        } else {
-            (
-                Ok(0),
-                Endpoint::Netlink(NetlinkEndpoint {
-                    port_id: 0,
-                    multicast_groups_mask: 0,
-                }),
-            )
+impl NetlinkSocketState {
+    /// Create a netlink route socket with the default port ID `port_id`,
+    /// the process ID as Linux assigns it.
+    pub fn new(port_id: u32) -> Self {
+        NetlinkSocketState {
+            base: KObjectBase::new(),
+            eventbus: EventBus::new(),
+            inner: Mutex::new(NetlinkInner {
+                buffer: VecDeque::new(),
+                port_id,
+                flags: OpenFlags::RDWR,
+            }),
         }
     }
 
-    fn write(&self, data: &[u8], _sendto_endpoint: Option<Endpoint>) -> SysResult {
-        if data.len() < size_of::<NetlinkMessageHeader>() {
-            return Err(LxError::EINVAL);
+    /// Try to receive the responses fitting in `data`, `None` if there is none.
+    ///
+    /// The first message is truncated if it doesn't fit.
+    fn try_recv(&self, data: &mut [u8], flags: MsgFlags) -> Option<usize> {
+        let mut inner = self.inner.lock();
+        let first_len = inner.buffer.front()?.len();
+        let mut copied = 0;
+        let mut count = 0;
+        for msg in inner.buffer.iter() {
+            if count > 0 && copied + msg.len() > data.len() {
+                break;
+            }
+            let len = msg.len().min(data.len() - copied);
+            data[copied..copied + len].copy_from_slice(&msg[..len]);
+            copied += len;
+            count += 1;
         }
-        #[allow(unsafe_code)]
-        let header = unsafe { &*(data.as_ptr() as *const NetlinkMessageHeader) };
-        if header.nlmsg_len as usize > data.len() {
-            return Err(LxError::EINVAL);
+        if !flags.contains(MsgFlags::PEEK) {
+            inner.buffer.drain(..count);
+            if inner.buffer.is_empty() {
+                self.eventbus.lock().clear(Event::READABLE);
+            }
+        }
+        if count == 1 && flags.contains(MsgFlags::TRUNC) {
+            copied = copied.max(first_len);
         }
+        Some(copied)
+    }
+
+    /// Answer one request, returning the response messages.
+    fn handle_request(&self, header: &NetlinkMessageHeader, payload: &[u8]) -> Vec<Vec<u8>> {
+        let port_id = self.inner.lock().port_id;
+        let new_message = |msg_type: NetlinkMessageType, flags: NetlinkMessageFlags| {
+            let mut msg = Vec::new();
+            msg.push_ext(NetlinkMessageHeader {
+                nlmsg_len: 0, // to be determined later
+                nlmsg_type: msg_type.into(),
+                nlmsg_flags: flags,
+                nlmsg_seq: header.nlmsg_seq,
+                nlmsg_pid: port_id,
+            });
+            msg
+        };
+        let mut responses = Vec::new();
         let message_type = NetlinkMessageType::from(header.nlmsg_type);
-        let mut buffer = self.data.lock();
-        buffer.clear();
         match message_type {
             NetlinkMessageType::GetLink => {
-                let ifaces = get_net_device();
-                for (i, iface) in ifaces.iter().enumerate() {
-                    let mut msg = Vec::new();
-                    let new_header = NetlinkMessageHeader {
-                        nlmsg_len: 0, // to be determined later
-                        nlmsg_type: NetlinkMessageType::NewLink.into(),
-                        nlmsg_flags: NetlinkMessageFlags::MULTI,
-                        nlmsg_seq: header.nlmsg_seq,
-                        nlmsg_pid: header.nlmsg_pid,
-                    };
-                    msg.push_ext(new_header);
-
+                for (i, iface) in get_net_device().iter().enumerate() {
+                    let mut msg =
+                        new_message(NetlinkMessageType::NewLink, NetlinkMessageFlags::MULTI);
+                    let loopback = is_loopback(&iface.get_ip_address());
                     let if_info = IfaceInfoMsg {
                         ifi_family: AddressFamily::Unspecified.into(),
-                        ifi_type: 0,
-                        ifi_index: i as u32,
-                        ifi_flags: 0,
+                        ifi_type: if loopback {
+                            ARPHRD_LOOPBACK
+                        } else {
+                            ARPHRD_ETHER
+                        },
+                        ifi_index: i as u32 + 1,
+                        ifi_flags: if loopback {
+                            (IfaceFlags::UP | IfaceFlags::LOOPBACK | IfaceFlags::RUNNING).bits()
+                        } else {
+                            (IfaceFlags::UP
+                                | IfaceFlags::BROADCAST
+                                | IfaceFlags::RUNNING
+                                | IfaceFlags::MULTICAST)
+                                .bits()
+                        },
                         ifi_change: 0,
                     };
                     msg.align4();
                     msg.push_ext(if_info);
 
-                    let mut attrs = Vec::new();
-
+                    let mut ifname = iface.get_ifname().into_bytes();
+                    ifname.push(0);
+                    msg.push_attr(RouteAttrTypes::Ifname.into(), &ifname);
                     let mac_addr = iface.get_mac();
-                    let attr = RouteAttr {
-                        rta_len: (mac_addr.as_bytes().len() + size_of::<RouteAttr>()) as u16,
-                        rta_type: RouteAttrTypes::Address.into(),
-                    };
-                    attrs.align4();
-                    attrs.push_ext(attr);
-                    for byte in mac_addr.as_bytes() {
-                        attrs.push(*byte);
-                    }
-
-                    let ifname = iface.get_ifname();
-                    let attr = RouteAttr {
-                        rta_len: (ifname.as_bytes().len() + size_of::<RouteAttr>()) as u16,
-                        rta_type: RouteAttrTypes::Ifname.into(),
-                    };
-                    attrs.align4();
-                    attrs.push_ext(attr);
-                    for byte in ifname.as_bytes() {
-                        attrs.push(*byte);
-                    }
-
-                    msg.align4();
-                    msg.append(&mut attrs);
-
-                    msg.align4();
-                    msg.set_ext(0, msg.len() as u32);
-
-                    buffer.push(msg);
+                    msg.push_attr(RouteAttrTypes::Address.into(), mac_addr.as_bytes());
+                    msg.push_attr(RouteAttrTypes::Broadcast.into(), &[0xff; 6]);
+                    let mtu = if loopback { LOOPBACK_MTU } else { ETHERNET_MTU };
+                    msg.push_attr(RouteAttrTypes::MTU.into(), &mtu.to_ne_bytes());
+                    responses.push(msg);
                 }
+                responses.push(done_message(new_message(
+                    NetlinkMessageType::Done,
+                    NetlinkMessageFlags::MULTI,
+                )));
             }
             NetlinkMessageType::GetAddr => {
-                let ifaces = get_net_device();
-                for (i, iface) in ifaces.iter().enumerate() {
-                    let ip_addrs = iface.get_ip_address();
-
-                    // for j in 0..ip_addrs.len() {
-                    for ip in &ip_addrs {
-                        let mut msg = Vec::new();
-                        let new_header = NetlinkMessageHeader {
-                            nlmsg_len: 0, // to be determined later
-                            nlmsg_type: NetlinkMessageType::NewAddr.into(),
-                            nlmsg_flags: NetlinkMessageFlags::MULTI,
-                            nlmsg_seq: header.nlmsg_seq,
-                            nlmsg_pid: header.nlmsg_pid,
+                // the dump may be limited to a family
+                let family = payload.first().copied().unwrap_or(0);
+                for (i, iface) in get_net_device().iter().enumerate() {
+                    for cidr in iface.get_ip_address() {
+                        let ipv4 = match cidr {
+                            IpCidr::Ipv4(ipv4) => ipv4,
+                            _ => continue,
                         };
-                        msg.push_ext(new_header);
-
-                        let family: u16 = AddressFamily::Internet.into();
+                        let inet: u16 = AddressFamily::Internet.into();
+                        if family != 0 && family as u16 != inet {
+                            continue;
+                        }
+                        let mut msg =
+                            new_message(NetlinkMessageType::NewAddr, NetlinkMessageFlags::MULTI);
+                        let loopback = ipv4.address().is_loopback();
                         let if_addr = IfaceAddrMsg {
-                            ifa_family: family as u8,
-                            ifa_prefixlen: ip.prefix_len(),
+                            ifa_family: inet as u8,
+                            ifa_prefixlen: ipv4.prefix_len(),
                             ifa_flags: 0,
-                            ifa_scope: 0,
-                            ifa_index: i as u32,
+                            ifa_scope: if loopback { RT_SCOPE_HOST } else { 0 },
+                            ifa_index: i as u32 + 1,
                         };
                         msg.align4();
                         msg.push_ext(if_addr);
 
-                        let mut attrs = Vec::new();
-
-                        let ip_addr = ip.address();
-                        let attr = RouteAttr {
-                            rta_len: (ip_addr.as_bytes().len() + size_of::<RouteAttr>()) as u16,
-                            rta_type: RouteAttrTypes::Address.into(),
-                        };
-                        attrs.align4();
-                        attrs.push_ext(attr);
-                        for byte in ip_addr.as_bytes() {
-                            attrs.push(*byte);
+                        let addr = ipv4.address();
+                        msg.push_attr(AddrAttrTypes::Address.into(), addr.as_bytes());
+                        msg.push_attr(AddrAttrTypes::Local.into(), addr.as_bytes());
+                        if !loopback {
+                            let mask = u32::MAX.checked_shr(ipv4.prefix_len() as u32).unwrap_or(0);
+                            let broadcast = u32::from_be_bytes(addr.0) | mask;
+                            msg.push_attr(
+                                AddrAttrTypes::Broadcast.into(),
+                                &broadcast.to_be_bytes(),
+                            );
                         }
+                        let mut label = iface.get_ifname().into_bytes();
+                        label.push(0);
+                        msg.push_attr(AddrAttrTypes::Label.into(), &label);
+                        responses.push(msg);
+                    }
+                }
+                responses.push(done_message(new_message(
+                    NetlinkMessageType::Done,
+                    NetlinkMessageFlags::MULTI,
+                )));
+            }
+            NetlinkMessageType::Noop | NetlinkMessageType::Done => {}
+            _ => {
+                warn!("unsupported netlink request: {:?}", message_type);
+                let msg = new_message(NetlinkMessageType::Error, NetlinkMessageFlags::empty());
+                responses.push(error_message(msg, LxError::EOPNOTSUPP as i32, header));
+            }
+        }
+        if responses.is_empty() && header.nlmsg_flags.contains(NetlinkMessageFlags::ACK) {
+            let msg = new_message(NetlinkMessageType::Error, NetlinkMessageFlags::empty());
+            responses.push(error_message(msg, 0, header));
+        }
+        for msg in responses.iter_mut() {
+            msg.align4();
+            msg.set_ext(0, msg.len() as u32);
+        }
+        responses
+    }
+}
 
-                        msg.align4();
-                        msg.append(&mut attrs);
+/// Whether an interface with `addrs` is the loopback one.
+fn is_loopback(addrs: &[IpCidr]) -> bool {
+    addrs.iter().any(|cidr| match cidr.address() {
+        IpAddress::Ipv4(addr) => addr.is_loopback(),
+        _ => false,
+    })
+}
 
-                        msg.align4();
-                        msg.set_ext(0, msg.len() as u32);
+/// Complete `NLMSG_DONE` with its payload.
+fn done_message(mut msg: Vec<u8>) -> Vec<u8> {
+    msg.push_ext(0i32);
+    msg
+}
 
-                        buffer.push(msg);
-                    }
-                }
+/// Complete `NLMSG_ERROR` with `errno` and the header of the request.
+fn error_message(mut msg: Vec<u8>, errno: i32, request: &NetlinkMessageHeader) -> Vec<u8> {
+    msg.push_ext(-errno);
+    msg.push_ext(*request);
+    msg
+}
+
+#[async_trait]
+impl Socket for NetlinkSocketState {
+    /// missing documentation
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (LxResult<usize>, Endpoint) {
+        let nonblock = self.inner.lock().flags.contains(OpenFlags::NON_BLOCK)
+            || flags.contains(MsgFlags::DONTWAIT);
+        let kernel = Endpoint::Netlink(NetlinkEndpoint::new(0, 0));
+        loop {
+            if let Some(len) = self.try_recv(data, flags) {
+                return (Ok(len), kernel);
+            }
+            if nonblock {
+                return (Err(LxError::EAGAIN), kernel);
             }
-            _ => {}
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn write(&self, data: &[u8], _sendto_endpoint: Option<Endpoint>) -> SysResult {
+        let mut offset = 0;
+        let mut responses = Vec::new();
+        // a write may carry several requests
+        while data.len() - offset >= size_of::<NetlinkMessageHeader>() {
+            #[allow(unsafe_code)]
+            let header = unsafe {
+                (data[offset..].as_ptr() as *const NetlinkMessageHeader).read_unaligned()
+            };
+            let len = header.nlmsg_len as usize;
+            if len < size_of::<NetlinkMessageHeader>() || len > data.len() - offset {
+                return Err(LxError::EINVAL);
+            }
+            let payload = &data[offset + size_of::<NetlinkMessageHeader>()..offset + len];
+            responses.extend(self.handle_request(&header, payload));
+            offset += (len + 3) & !3;
+        }
+        if offset == 0 {
+            return Err(LxError::EINVAL);
+        }
+        if !responses.is_empty() {
+            self.inner.lock().buffer.extend(responses);
+            self.eventbus
+                .lock()
+                .change_notify(Event::empty(), Event::READABLE);
         }
-        let mut msg = Vec::new();
-        let new_header = NetlinkMessageHeader {
-            nlmsg_len: 0, // to be determined later
-            nlmsg_type: NetlinkMessageType::Done.into(),
-            nlmsg_flags: NetlinkMessageFlags::MULTI,
-            nlmsg_seq: header.nlmsg_seq,
-            nlmsg_pid: header.nlmsg_pid,
-        };
-        msg.push_ext(new_header);
-        msg.align4();
-        msg.set_ext(0, msg.len() as u32);
-        buffer.push(msg);
         Ok(data.len())
     }
 
     /// connect
-    async fn connect(&self, _endpoint: Endpoint) -> SysResult {
-        unimplemented!()
-    }
-
-    fn bind(&self, _endpoint: Endpoint) -> SysResult {
-        warn!("bind netlink socket");
-        // if let Endpoint::Netlink(mut net_link) = endpoint {
-        //     if net_link.port_id == 0 {
-        //         net_link.port_id = get_ephemeral_port();
-        //     }
-        //     self.local_endpoint = Some(ip);
-        //     self.is_listening = false;
-        //     Ok(0)
-        // } else {
-        //     Err(LxError::EINVAL)
-        // }
-        Ok(0)
+    async fn connect(&self, endpoint: Endpoint) -> SysResult {
+        match endpoint {
+            // only the kernel can be talked to
+            Endpoint::Netlink(_) => Ok(0),
+            _ => Err(LxError::EINVAL),
+        }
     }
 
-    fn listen(&self) -> SysResult {
-        unimplemented!()
+    fn bind(&self, endpoint: Endpoint) -> SysResult {
+        match endpoint {
+            Endpoint::Netlink(netlink) => {
+                if netlink.port_id != 0 {
+                    self.inner.lock().port_id = netlink.port_id;
+                }
+                Ok(0)
+            }
+            _ => Err(LxError::EINVAL),
+        }
     }
 
-    fn shutdown(&self) -> SysResult {
-        unimplemented!()
+    fn listen(&self, _backlog: usize) -> SysResult {
+        Err(LxError::EOPNOTSUPP)
+    }
+
+    fn shutdown(&self, _how: Shutdown) -> SysResult {
+        Err(LxError::EOPNOTSUPP)
     }
 
     async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, Endpoint)> {
-        unimplemented!()
+        Err(LxError::EOPNOTSUPP)
     }
 
     fn endpoint(&self) -> Option<Endpoint> {
-        Some(Endpoint::Netlink(NetlinkEndpoint::new(0, 0)))
+        let port_id = self.inner.lock().port_id;
+        Some(Endpoint::Netlink(NetlinkEndpoint::new(port_id, 0)))
     }
 
     fn remote_endpoint(&self) -> Option<Endpoint> {
-        unimplemented!()
+        Some(Endpoint::Netlink(NetlinkEndpoint::new(0, 0)))
     }
 
     fn setsockopt(&self, _level: usize, _opt: usize, _data: &[u8]) -> SysResult {
@@ -240,6 +340,59 @@ impl Socket for NetlinkSocketState {
     fn ioctl(&self, _request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> SysResult {
         Ok(0)
     }
+
+    fn poll(&self, _events: PollEvents) -> (bool, bool, bool) {
+        (!self.inner.lock().buffer.is_empty(), true, false)
+    }
+}
+
+impl_kobject!(NetlinkSocketState);
+
+#[async_trait]
+impl FileLike for NetlinkSocketState {
+    fn flags(&self) -> OpenFlags {
+        self.inner.lock().flags
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.inner.lock().flags;
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Socket::read(self, buf, MsgFlags::empty()).await.0
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        Socket::write(self, buf, None)
+    }
+
+    fn poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        let (read, write, error) = Socket::poll(self, events);
+        Ok(PollStatus { read, write, error })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        if events.contains(PollEvents::IN) && !events.contains(PollEvents::OUT) {
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+        FileLike::poll(self, events)
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.eventbus.lock().subscribe_once(callback);
+        true
+    }
+
+    fn as_socket(&self) -> LxResult<&dyn Socket> {
+        Ok(self)
+    }
 }
 
 /// Common structure:
@@ -336,6 +489,33 @@ enum_with_unknown! {
     }
 }
 
+bitflags! {
+    /// Flags of network interfaces, `IFF_*`
+    struct IfaceFlags: u32 {
+        const UP = 0x1;
+        const BROADCAST = 0x2;
+        const LOOPBACK = 0x8;
+        const RUNNING = 0x40;
+        const MULTICAST = 0x1000;
+    }
+}
+
+enum_with_unknown! {
+    /// Address Attr Types, `IFA_*`
+    pub doc enum AddrAttrTypes(u16) {
+        /// Unspecified
+        Unspecified = 0,
+        /// Interface address
+        Address = 1,
+        /// Local address
+        Local = 2,
+        /// Name of the interface
+        Label = 3,
+        /// Broadcast address
+        Broadcast = 4,
+    }
+}
+
 enum_with_unknown! {
     /// Route Attr Types
     pub doc enum RouteAttrTypes(u16) {
@@ -358,6 +538,7 @@ trait VecExt {
     fn align4(&mut self);
     fn push_ext<T: Sized>(&mut self, data: T);
     fn set_ext<T: Sized>(&mut self, offset: usize, data: T);
+    fn push_attr(&mut self, rta_type: u16, data: &[u8]);
 }
 
 impl VecExt for Vec<u8> {
@@ -386,6 +567,15 @@ impl VecExt for Vec<u8> {
             unsafe { slice::from_raw_parts(&data as *const T as *const u8, size_of::<T>()) };
         self[offset..(bytes.len() + offset)].copy_from_slice(bytes);
     }
+
+    fn push_attr(&mut self, rta_type: u16, data: &[u8]) {
+        self.align4();
+        self.push_ext(RouteAttr {
+            rta_len: (data.len() + size_of::<RouteAttr>()) as u16,
+            rta_type,
+        });
+        self.extend_from_slice(data);
+    }
 }
 
 #[repr(C)]
@@ -1,21 +1,51 @@
-use crate::{error::LxError, net::*};
-use alloc::boxed::Box;
+// rawsocket
+
+use crate::error::{LxError, LxResult};
+use crate::fs::{ChangeCallback, FileLike, OpenFlags, PollStatus};
+use crate::net::*;
+use alloc::{boxed::Box, sync::Arc, vec};
 use async_trait::async_trait;
+use lock::Mutex;
 use smoltcp::{
     socket::{RawPacketMetadata, RawSocket, RawSocketBuffer},
     wire::{IpProtocol, IpVersion, Ipv4Address, Ipv4Packet},
 };
 
-/// missing documentation
-#[derive(Debug, Clone)]
+// third part
+#[allow(unused_imports)]
+use zircon_object::impl_kobject;
+#[allow(unused_imports)]
+use zircon_object::object::*;
+
+/// The TTL of the packets whose header is built by the kernel
+const RAW_HOP_LIMIT: u8 = 64;
+
+/// Raw IPv4 socket structure
 pub struct RawSocketState {
+    /// Kernel object base
+    base: KObjectBase,
+    /// RawSocket Inner
+    inner: Mutex<RawInner>,
+}
+
+/// Raw socket inner
+pub struct RawInner {
+    /// A wrapper for `SocketHandle`
     handle: GlobalSocketHandle,
+    /// Whether the IPv4 header is provided by the user, see `IP_HDRINCL`
     header_included: bool,
+    /// remember remote address for connect fn
+    remote_addr: Option<Ipv4Address>,
+    /// Options of the socket level.
+    options: SocketOptions,
+    /// flags on the socket
+    flags: OpenFlags,
 }
 
 impl RawSocketState {
-    /// missing documentation
+    /// Create a raw socket receiving and sending the IPv4 packets of `protocol`.
     pub fn new(protocol: u8) -> Self {
+        info!("raw new: protocol {}", protocol);
         let rx_buffer = RawSocketBuffer::new(
             vec![RawPacketMetadata::EMPTY; RAW_METADATA_BUF],
             vec![0; RAW_RECVBUF],
@@ -33,119 +63,237 @@ impl RawSocketState {
         let handle = GlobalSocketHandle(get_sockets().lock().add(socket));
 
         RawSocketState {
-            handle,
-            header_included: false,
+            base: KObjectBase::new(),
+            inner: Mutex::new(RawInner {
+                handle,
+                header_included: false,
+                remote_addr: None,
+                options: SocketOptions::default(),
+                flags: OpenFlags::RDWR,
+            }),
+        }
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.inner.lock().flags.contains(OpenFlags::NON_BLOCK)
+    }
+
+    /// Try to receive a packet with its IPv4 header, `None` if it would block.
+    fn try_recv(&self, data: &mut [u8]) -> Option<LxResult<(usize, Ipv4Address)>> {
+        let inner = self.inner.lock();
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<RawSocket>(inner.handle.0);
+        match socket.recv() {
+            Ok(packet) => {
+                let src_addr = Ipv4Packet::new_checked(packet)
+                    .map(|packet| packet.src_addr())
+                    .unwrap_or(Ipv4Address::UNSPECIFIED);
+                let len = packet.len().min(data.len());
+                data[..len].copy_from_slice(&packet[..len]);
+                Some(Ok((len, src_addr)))
+            }
+            // The receive buffer is empty. Try again later...
+            Err(smoltcp::Error::Exhausted) => None,
+            Err(err) => {
+                error!("raw socket recv error: {:?}", err);
+                Some(Err(LxError::ENOTCONN))
+            }
         }
     }
 }
 
-/// missing in implementation
 #[async_trait]
 impl Socket for RawSocketState {
-    async fn read(&self, data: &mut [u8]) -> (SysResult, Endpoint) {
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint) {
         info!("raw read");
-        loop {
-            info!("raw read loop");
-            poll_ifaces();
-            let net_sockets = get_sockets();
-            let mut sockets = net_sockets.lock();
-            let mut socket = sockets.get::<RawSocket>(self.handle.0);
-            if socket.can_recv() {
-                if let Ok(size) = socket.recv_slice(data) {
-                    let packet = Ipv4Packet::new_unchecked(data);
-                    // avoid deadlock
-                    drop(socket);
-                    drop(sockets);
-                    poll_ifaces();
-                    return (
-                        Ok(size),
-                        Endpoint::Ip(IpEndpoint {
-                            addr: IpAddress::Ipv4(packet.src_addr()),
-                            port: 0,
-                        }),
-                    );
-                }
-            } else {
-                return (
-                    Err(LxError::ENOTCONN),
-                    Endpoint::Ip(IpEndpoint::UNSPECIFIED),
-                );
-            }
-            drop(socket);
-            drop(sockets);
+        let nonblock = self.is_nonblock() || flags.contains(MsgFlags::DONTWAIT);
+        match wait_socket(nonblock, || self.try_recv(data)).await {
+            Ok((size, addr)) => (
+                Ok(size),
+                Endpoint::Ip(IpEndpoint::new(IpAddress::Ipv4(addr), 0)),
+            ),
+            Err(err) => (Err(err), Endpoint::Ip(IpEndpoint::UNSPECIFIED)),
         }
     }
 
     fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
         info!("raw write");
-        let net_sockets = get_sockets();
-        let mut sockets = net_sockets.lock();
-        let mut socket = sockets.get::<RawSocket>(self.handle.0);
-        if self.header_included {
-            match socket.send_slice(data) {
-                Ok(()) => Ok(data.len()),
-                Err(_) => Err(LxError::ENOBUFS),
-            }
-        } else if let Some(Endpoint::Ip(endpoint)) = sendto_endpoint {
-            // todo: this is a temporary solution
-            let v4_src = Ipv4Address::new(192, 168, 0, 123);
-
-            if let IpAddress::Ipv4(v4_dst) = endpoint.addr {
-                let len = data.len();
-                // using 20-byte IPv4 header
-                let mut buffer = vec![0u8; len + 20];
-                let mut packet = Ipv4Packet::new_unchecked(&mut buffer);
-                packet.set_version(4);
-                packet.set_header_len(20);
-                packet.set_total_len((20 + len) as u16);
-                packet.set_protocol(socket.ip_protocol());
-                packet.set_src_addr(v4_src);
-                packet.set_dst_addr(v4_dst);
-                let payload = packet.payload_mut();
-                payload.copy_from_slice(data);
-                packet.fill_checksum();
-
-                socket.send_slice(&buffer).unwrap();
-
-                // avoid deadlock
-                drop(socket);
-                drop(sockets);
-                Ok(len)
-            } else {
-                unimplemented!("ip type")
+        let inner = self.inner.lock();
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<RawSocket>(inner.handle.0);
+        if inner.header_included {
+            let packet = Ipv4Packet::new_checked(data).map_err(|_| LxError::EINVAL)?;
+            if packet.protocol() != socket.ip_protocol() {
+                return Err(LxError::EINVAL);
             }
+            socket.send_slice(data).map_err(|_| LxError::ENOBUFS)?;
         } else {
-            Err(LxError::ENOTCONN)
+            let v4_dst = match sendto_endpoint {
+                Some(Endpoint::Ip(IpEndpoint {
+                    addr: IpAddress::Ipv4(addr),
+                    ..
+                })) => addr,
+                Some(_) => return Err(LxError::EINVAL),
+                None => inner.remote_addr.ok_or(LxError::EDESTADDRREQ)?,
+            };
+            let v4_src = source_address(v4_dst).ok_or(LxError::ENETUNREACH)?;
+            let len = data.len();
+            // using 20-byte IPv4 header
+            let mut buffer = vec![0u8; len + 20];
+            let mut packet = Ipv4Packet::new_unchecked(&mut buffer);
+            packet.set_version(4);
+            packet.set_header_len(20);
+            packet.set_total_len((20 + len) as u16);
+            packet.set_hop_limit(RAW_HOP_LIMIT);
+            packet.set_protocol(socket.ip_protocol());
+            packet.set_src_addr(v4_src);
+            packet.set_dst_addr(v4_dst);
+            packet.payload_mut().copy_from_slice(data);
+            packet.fill_checksum();
+            socket.send_slice(&buffer).map_err(|_| LxError::ENOBUFS)?;
+        }
+
+        // avoid deadlock
+        drop(socket);
+        drop(sets);
+        drop(inner);
+        poll_ifaces();
+        Ok(data.len())
+    }
+
+    async fn connect(&self, endpoint: Endpoint) -> SysResult {
+        match endpoint {
+            Endpoint::Ip(IpEndpoint {
+                addr: IpAddress::Ipv4(addr),
+                ..
+            }) => {
+                self.inner.lock().remote_addr = Some(addr);
+                Ok(0)
+            }
+            _ => Err(LxError::EINVAL),
         }
     }
 
-    async fn connect(&self, _endpoint: Endpoint) -> SysResult {
-        unimplemented!()
+    fn poll(&self, events: PollEvents) -> (bool, bool, bool) {
+        if events.contains(PollEvents::IN) {
+            poll_ifaces();
+        }
+        let inner = self.inner.lock();
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let socket = sets.get::<RawSocket>(inner.handle.0);
+        (socket.can_recv(), socket.can_send(), false)
     }
 
-    fn setsockopt(&self, _level: usize, _opt: usize, _data: &[u8]) -> SysResult {
-        // match (level, opt) {
-        //     (IPPROTO_IP, IP_HDRINCL) => {
-        //         if let Some(arg) = data.first() {
-        //             self.header_included = *arg > 0;
-        //             debug!("hdrincl set to {}", self.header_included);
-        //         }
-        //     }
-        //     _ => {}
-        // }
+    fn bind(&self, _endpoint: Endpoint) -> SysResult {
+        // the packets are not filtered by the local address
         Ok(0)
     }
+
+    fn remote_endpoint(&self) -> Option<Endpoint> {
+        self.inner
+            .lock()
+            .remote_addr
+            .map(|addr| Endpoint::Ip(IpEndpoint::new(IpAddress::Ipv4(addr), 0)))
+    }
+
+    fn setsockopt(&self, level: usize, opt: usize, data: &[u8]) -> SysResult {
+        match (level, opt) {
+            (IPPROTO_IP, IP_HDRINCL) => {
+                let mut inner = self.inner.lock();
+                inner.header_included = data.iter().any(|&b| b != 0);
+                debug!("hdrincl set to {}", inner.header_included);
+                Ok(0)
+            }
+            _ => {
+                warn!("setsockopt({}, {}) is unimplemented", level, opt);
+                Ok(0)
+            }
+        }
+    }
+
     fn get_buffer_capacity(&self) -> Option<(usize, usize)> {
         let sockets = get_sockets();
         let mut s = sockets.lock();
-        let socket = s.get::<RawSocket>(self.handle.0);
+        let socket = s.get::<RawSocket>(self.inner.lock().handle.0);
         let (recv_ca, send_ca) = (
             socket.payload_recv_capacity(),
             socket.payload_send_capacity(),
         );
         Some((recv_ca, send_ca))
     }
+
     fn socket_type(&self) -> Option<SocketType> {
         Some(SocketType::SOCK_RAW)
     }
+
+    fn socket_options(&self) -> Option<SocketOptions> {
+        Some(self.inner.lock().options)
+    }
+
+    fn set_socket_options(&self, options: SocketOptions) -> SysResult {
+        self.inner.lock().options = options;
+        Ok(0)
+    }
+}
+
+impl_kobject!(RawSocketState);
+
+#[async_trait]
+impl FileLike for RawSocketState {
+    fn flags(&self) -> OpenFlags {
+        self.inner.lock().flags
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.inner.lock().flags;
+
+        // See fcntl, only O_APPEND, O_ASYNC, O_DIRECT, O_NOATIME, O_NONBLOCK
+        flags.set(OpenFlags::APPEND, f.contains(OpenFlags::APPEND));
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Socket::read(self, buf, MsgFlags::empty()).await.0
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        Socket::write(self, buf, None)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        let (read, write, error) = Socket::poll(self, events);
+        Ok(PollStatus { read, write, error })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        wait_socket(false, || {
+            let (read, write, error) = Socket::poll(self, events);
+            let ready = (read && events.contains(PollEvents::IN))
+                || (write && events.contains(PollEvents::OUT))
+                || error;
+            ready.then(|| Ok(PollStatus { read, write, error }))
+        })
+        .await
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        on_socket_activity(callback)
+    }
+
+    fn as_socket(&self) -> LxResult<&dyn Socket> {
+        Ok(self)
+    }
 }
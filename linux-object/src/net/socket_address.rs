@@ -1,5 +1,6 @@
 // core
 
+use alloc::{string::String, vec::Vec};
 use core::{cmp::min, mem::size_of};
 
 // crate
@@ -108,6 +109,25 @@ pub enum Endpoint {
     LinkLevel(LinkLevelEndpoint),
     /// missing documentation
     Netlink(NetlinkEndpoint),
+    /// The address of a unix domain socket
+    Unix(UnixEndpoint),
+}
+
+/// The address of a unix domain socket.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnixEndpoint {
+    /// Not bound to an address.
+    Unnamed,
+    /// A path name in the file system.
+    Path(String),
+    /// A name in the abstract namespace, given after a null byte in `sun_path`.
+    Abstract(Vec<u8>),
+}
+
+impl Default for UnixEndpoint {
+    fn default() -> Self {
+        UnixEndpoint::Unnamed
+    }
 }
 
 /// missing documentation
@@ -189,6 +209,23 @@ impl From<Endpoint> for SockAddr {
                     nl_groups: netlink.multicast_groups_mask,
                 },
             }
+        } else if let Endpoint::Unix(unix) = endpoint {
+            let mut addr_un = SockAddrUn {
+                sun_family: AddressFamily::Unix.into(),
+                sun_path: [0; 108],
+            };
+            match unix {
+                UnixEndpoint::Unnamed => {}
+                UnixEndpoint::Path(path) => {
+                    let len = path.len().min(107);
+                    addr_un.sun_path[..len].copy_from_slice(&path.as_bytes()[..len]);
+                }
+                UnixEndpoint::Abstract(name) => {
+                    let len = name.len().min(107);
+                    addr_un.sun_path[1..=len].copy_from_slice(&name[..len]);
+                }
+            }
+            SockAddr { addr_un }
         } else {
             unimplemented!("not match");
         }
@@ -200,6 +237,23 @@ pub fn sockaddr_to_endpoint(addr: SockAddr, len: usize) -> Result<Endpoint, LxEr
     if len < size_of::<u16>() {
         return Err(LxError::EINVAL);
     }
+    #[allow(unsafe_code)]
+    let family = AddressFamily::from(unsafe { addr.family });
+    if family == AddressFamily::Unix {
+        // the length of the address is given by `len` only
+        let path_len = len.min(size_of::<SockAddrUn>()) - size_of::<u16>();
+        #[allow(unsafe_code)]
+        let path = unsafe { &addr.addr_un.sun_path[..path_len] };
+        return Ok(Endpoint::Unix(match path.first() {
+            None => UnixEndpoint::Unnamed,
+            Some(0) => UnixEndpoint::Abstract(path[1..].to_vec()),
+            Some(_) => {
+                let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+                let path = core::str::from_utf8(&path[..end]).map_err(|_| LxError::EINVAL)?;
+                UnixEndpoint::Path(String::from(path))
+            }
+        }));
+    }
     // let addr = unsafe { vm.check_read_ptr(addr)? };
     if len < addr.len()? {
         return Err(LxError::EINVAL);
@@ -234,11 +288,28 @@ impl SockAddr {
             AddressFamily::Internet => Ok(size_of::<SockAddrIn>()),
             AddressFamily::Packet => Ok(size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(size_of::<SockAddrNl>()),
-            AddressFamily::Unix => Err(LxError::EINVAL),
+            AddressFamily::Unix => {
+                #[allow(unsafe_code)]
+                let path = unsafe { &self.addr_un.sun_path };
+                // abstract names ending with null bytes are cut, as they can't be told apart
+                let path_len = if path[0] == 0 {
+                    path.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1)
+                } else {
+                    path.iter().position(|&c| c == 0).unwrap_or(path.len() - 1) + 1
+                };
+                Ok(size_of::<u16>() + path_len)
+            }
             _ => Err(LxError::EINVAL),
         }
     }
 
+    /// Returns the bytes of the address, as many as its length.
+    pub fn as_bytes(&self) -> Result<&[u8], LxError> {
+        let len = self.len()?;
+        #[allow(unsafe_code)]
+        Ok(unsafe { core::slice::from_raw_parts(self as *const SockAddr as *const u8, len) })
+    }
+
     /// # Safety
     /// Write to user sockaddr
     /// Check mutability for user
@@ -303,7 +374,7 @@ impl SockAddr {
     /// Write to msg
     /// Check mutability for user
     #[allow(dead_code)]
-    pub fn write_to_msg(self, msg: UserInOutPtr<MsgHdr>) -> SysResult {
+    pub fn write_to_msg(self, mut msg: UserInOutPtr<MsgHdr>) -> SysResult {
         if msg.is_null() {
             return Ok(0);
         }
@@ -319,9 +390,10 @@ impl SockAddr {
         #[allow(unsafe_code)]
         unsafe {
             let source = slice::from_raw_parts(&self as *const SockAddr as *const u8, written_len);
-            let mut addr: UserOutPtr<u8> = core::mem::transmute(hdr.msg_name);
+            let mut addr: UserOutPtr<u8> = hdr.msg_name.as_addr().into();
             addr.write_array(source)?;
         }
+        msg.write(hdr)?;
         Ok(0)
     }
 }
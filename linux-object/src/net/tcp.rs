@@ -2,7 +2,7 @@
 
 // crate
 use crate::error::{LxError, LxResult};
-use crate::fs::{FileLike, OpenFlags, PollStatus};
+use crate::fs::{ChangeCallback, FileLike, OpenFlags, PollStatus};
 use crate::net::*;
 use alloc::sync::Arc;
 use lock::Mutex;
@@ -10,6 +10,7 @@ use lock::Mutex;
 // alloc
 use alloc::boxed::Box;
 use alloc::vec;
+use alloc::vec::Vec;
 
 // smoltcp
 use smoltcp::socket::{TcpSocket, TcpSocketBuffer, TcpState};
@@ -21,6 +22,11 @@ use async_trait::async_trait;
 #[allow(unused_imports)]
 use zircon_object::object::*;
 
+/// The most pending connections of a listening socket.
+///
+/// Each of them takes a smoltcp socket listening in advance, with its buffers.
+pub const TCP_MAX_BACKLOG: usize = 8;
+
 /// TCP socket structure
 pub struct TcpSocketState {
     /// Kernel object base
@@ -37,32 +43,171 @@ pub struct TcpInner {
     local_endpoint: Option<IpEndpoint>, // save local endpoint for bind()
     /// missing documentation
     is_listening: bool,
+    /// The other sockets listening for the backlog, besides `handle`.
+    backlog: Vec<GlobalSocketHandle>,
+    /// Whether a non-blocking connect is in progress.
+    connecting: bool,
+    /// Whether the connection has been established.
+    connected: bool,
+    /// Whether further receptions are disallowed by `shutdown()`.
+    read_shutdown: bool,
+    /// The pending error, reported by `SO_ERROR`.
+    error: Option<LxError>,
+    /// Options of the socket level.
+    options: SocketOptions,
     /// flags on the socket
     flags: OpenFlags,
 }
 
+impl TcpInner {
+    fn new(handle: GlobalSocketHandle) -> Self {
+        TcpInner {
+            handle,
+            local_endpoint: None,
+            is_listening: false,
+            backlog: Vec::new(),
+            connecting: false,
+            connected: false,
+            read_shutdown: false,
+            error: None,
+            options: SocketOptions::default(),
+            flags: OpenFlags::RDWR,
+        }
+    }
+
+    /// Track the connection with the current `state` of the socket.
+    fn update(&mut self, state: TcpState) {
+        match state {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => {}
+            _ => self.connected = true,
+        }
+        if self.connecting && state != TcpState::SynSent {
+            self.connecting = false;
+            if !self.connected {
+                self.error = Some(LxError::ECONNREFUSED);
+            }
+        }
+    }
+
+    /// The handles of all sockets listening.
+    fn listeners(&self) -> impl Iterator<Item = &GlobalSocketHandle> {
+        core::iter::once(&self.handle).chain(self.backlog.iter())
+    }
+}
+
 impl Default for TcpSocketState {
     fn default() -> Self {
         TcpSocketState::new()
     }
 }
 
+/// Create a smoltcp TCP socket with the default buffers.
+fn new_tcp_socket() -> TcpSocket<'static> {
+    let rx_buffer = TcpSocketBuffer::new(vec![0; TCP_RECVBUF]);
+    let tx_buffer = TcpSocketBuffer::new(vec![0; TCP_SENDBUF]);
+    TcpSocket::new(rx_buffer, tx_buffer)
+}
+
+/// Whether a listening socket has a connection to accept.
+fn ready_to_accept(socket: &TcpSocket) -> bool {
+    socket.is_active() && socket.state() != TcpState::SynReceived
+}
+
 impl TcpSocketState {
     /// missing documentation
     pub fn new() -> Self {
-        let rx_buffer = TcpSocketBuffer::new(vec![0; TCP_RECVBUF]);
-        let tx_buffer = TcpSocketBuffer::new(vec![0; TCP_SENDBUF]);
-        let socket = TcpSocket::new(rx_buffer, tx_buffer);
-        let handle = GlobalSocketHandle(get_sockets().lock().add(socket));
+        let handle = GlobalSocketHandle(get_sockets().lock().add(new_tcp_socket()));
 
         TcpSocketState {
             base: KObjectBase::new(),
-            inner: Mutex::new(TcpInner {
-                handle,
-                local_endpoint: None,
-                is_listening: false,
-                flags: OpenFlags::RDWR,
-            }),
+            inner: Mutex::new(TcpInner::new(handle)),
+        }
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.inner.lock().flags.contains(OpenFlags::NON_BLOCK)
+    }
+
+    /// Try to receive data, `None` if it would block.
+    fn try_recv(&self, data: &mut [u8], flags: MsgFlags) -> Option<LxResult<(usize, IpEndpoint)>> {
+        let mut inner = self.inner.lock();
+        if inner.is_listening {
+            return Some(Err(LxError::ENOTCONN));
+        }
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<TcpSocket>(inner.handle.0);
+        inner.update(socket.state());
+        let remote_endpoint = socket.remote_endpoint();
+        if inner.read_shutdown || data.is_empty() {
+            return Some(Ok((0, remote_endpoint)));
+        }
+        let copied_len = if flags.contains(MsgFlags::PEEK) {
+            socket.peek_slice(data)
+        } else {
+            socket.recv_slice(data)
+        };
+        match copied_len {
+            Ok(size) if size > 0 => Some(Ok((size, remote_endpoint))),
+            _ => match socket.state() {
+                // not connected yet
+                TcpState::SynSent | TcpState::SynReceived => None,
+                _ if !inner.connected => Some(Err(LxError::ENOTCONN)),
+                _ if socket.may_recv() => None,
+                // the peer has closed the connection
+                _ => Some(Ok((0, remote_endpoint))),
+            },
+        }
+    }
+
+    /// Try to accept a connection, `None` if it would block.
+    fn try_accept(&self) -> Option<LxResult<(Arc<dyn FileLike>, Endpoint)>> {
+        let mut inner = self.inner.lock();
+        let endpoint = match inner.local_endpoint {
+            Some(endpoint) if inner.is_listening => endpoint,
+            _ => return Some(Err(LxError::EINVAL)),
+        };
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let index = inner
+            .listeners()
+            .position(|handle| ready_to_accept(&sets.get::<TcpSocket>(handle.0)))?;
+        let handle = inner.listeners().nth(index).unwrap().0;
+        let remote_endpoint = sets.get::<TcpSocket>(handle).remote_endpoint();
+
+        // listen again in place of the connection
+        let mut socket = new_tcp_socket();
+        socket.listen(endpoint).unwrap();
+        let new_handle = GlobalSocketHandle(sets.add(socket));
+        drop(sets);
+        let old_handle = match index {
+            0 => core::mem::replace(&mut inner.handle, new_handle),
+            i => core::mem::replace(&mut inner.backlog[i - 1], new_handle),
+        };
+
+        let mut new_inner = TcpInner::new(old_handle);
+        new_inner.local_endpoint = inner.local_endpoint;
+        new_inner.connected = true;
+        let new_socket = Arc::new(TcpSocketState {
+            base: KObjectBase::new(),
+            inner: Mutex::new(new_inner),
+        });
+        Some(Ok((
+            new_socket as Arc<dyn FileLike>,
+            Endpoint::Ip(remote_endpoint),
+        )))
+    }
+
+    /// Whether the socket is ready for `events`.
+    fn ready(&self, events: PollEvents) -> Option<PollStatus> {
+        let (read, write, error) = Socket::poll(self, events);
+        if (read && events.contains(PollEvents::IN))
+            || (write && events.contains(PollEvents::OUT))
+            || error
+        {
+            Some(PollStatus { read, write, error })
+        } else {
+            None
         }
     }
 }
@@ -70,154 +215,128 @@ impl TcpSocketState {
 #[async_trait]
 impl Socket for TcpSocketState {
     /// read to buffer
-    async fn read(&self, data: &mut [u8]) -> (SysResult, Endpoint) {
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint) {
         info!("tcp read");
-        let inner = self.inner.lock();
-        loop {
-            //poll_ifaces();
-
-            let sets = get_sockets();
-            let mut sets = sets.lock();
-            let mut socket = sets.get::<TcpSocket>(inner.handle.0);
-
-            let copied_len = socket.recv_slice(data);
-            // avoid deadlock in poll_ifaces()
-            drop(socket);
-            drop(sets);
-
-            match copied_len {
-                Ok(0) | Err(smoltcp::Error::Exhausted) => {
-                    poll_ifaces();
-                    if inner.flags.contains(OpenFlags::NON_BLOCK) {
-                        return (Err(LxError::EAGAIN), Endpoint::Ip(IpEndpoint::UNSPECIFIED));
-                    } else {
-                        // Continue reading
-                        trace!("Continue reading");
-                    }
-                }
-                Ok(size) => {
-                    let endpoint = get_sockets()
-                        .lock()
-                        .get::<TcpSocket>(inner.handle.0)
-                        .remote_endpoint();
-                    return (Ok(size), Endpoint::Ip(endpoint));
-                }
-                Err(err) => {
-                    error!("Tcp socket read error: {:?}", err);
-                    return (
-                        Err(LxError::ENOTCONN),
-                        Endpoint::Ip(IpEndpoint::UNSPECIFIED),
-                    );
-                }
-            }
+        let nonblock = self.is_nonblock() || flags.contains(MsgFlags::DONTWAIT);
+        match wait_socket(nonblock, || self.try_recv(data, flags)).await {
+            Ok((size, endpoint)) => (Ok(size), Endpoint::Ip(endpoint)),
+            Err(err) => (Err(err), Endpoint::Ip(IpEndpoint::UNSPECIFIED)),
         }
     }
     /// write from buffer
     fn write(&self, data: &[u8], _sendto_endpoint: Option<Endpoint>) -> SysResult {
-        //loop {
+        let mut inner = self.inner.lock();
         let sets = get_sockets();
         let mut sets = sets.lock();
-        let mut socket = sets.get::<TcpSocket>(self.inner.lock().handle.0);
+        let mut socket = sets.get::<TcpSocket>(inner.handle.0);
+        inner.update(socket.state());
+        if !socket.may_send() {
+            return Err(if inner.connecting {
+                LxError::EAGAIN
+            } else if inner.connected {
+                LxError::EPIPE
+            } else {
+                LxError::ENOTCONN
+            });
+        }
         let copied_len = socket.send_slice(data);
 
         drop(socket);
         drop(sets);
+        drop(inner);
         poll_ifaces();
 
         match copied_len {
+            Ok(0) if !data.is_empty() => Err(LxError::EAGAIN),
             Ok(size) => Ok(size),
             Err(err) => {
                 error!("Tcp socket write error: {:?}", err);
                 Err(LxError::ENOBUFS)
             }
         }
-        //}
     }
     /// connect
     async fn connect(&self, endpoint: Endpoint) -> SysResult {
-        let inner = self.inner.lock();
-        #[allow(warnings)]
-        if let Endpoint::Ip(ip) = endpoint {
-            get_sockets()
-                .lock()
-                .get::<TcpSocket>(inner.handle.0)
-                .connect(ip, get_ephemeral_port())
-                .map_err(|_| LxError::ENOBUFS)?;
-
-            let mut tc = 0;
-            // wait for connection result
-            loop {
-                //Submit a SYN
-                poll_ifaces();
-
-                match get_sockets()
-                    .lock()
-                    .get::<TcpSocket>(inner.handle.0)
-                    .state()
-                {
-                    TcpState::SynSent => {
-                        // still connecting
-                    }
-                    TcpState::Established => {
-                        return Ok(0);
-                    }
-                    _ => {
-                        warn!("connect failed ...");
-                        if tc < 10 {
-                            tc += 1;
-                        } else {
-                            return Err(LxError::ECONNREFUSED);
-                        }
-                    }
-                }
+        let ip = match endpoint {
+            Endpoint::Ip(ip) => ip,
+            _ => {
+                error!("connect: bad endpoint");
+                return Err(LxError::EINVAL);
+            }
+        };
+        let nonblock = {
+            let mut inner = self.inner.lock();
+            if inner.is_listening {
+                return Err(LxError::EINVAL);
             }
-        } else {
-            error!("connect: bad endpoint");
-            Err(LxError::EINVAL)
-        }
-    }
-    /// wait for some event on a file descriptor
-    fn poll(&self, events: PollEvents) -> (bool, bool, bool) {
-        //poll_ifaces();
-        let inner = self.inner.lock();
-        let (recv_state, send_state) = {
             let sets = get_sockets();
             let mut sets = sets.lock();
-            let socket = sets.get::<TcpSocket>(inner.handle.0);
-            debug!(
-                "tcp is_listening: {:?}, now tcp state: {:?}",
-                inner.is_listening,
-                socket.state()
-            );
-
-            (socket.can_recv(), socket.can_send())
+            let mut socket = sets.get::<TcpSocket>(inner.handle.0);
+            inner.update(socket.state());
+            if inner.connecting {
+                return Err(LxError::EALREADY);
+            }
+            if inner.connected {
+                return Err(LxError::EISCONN);
+            }
+            let local_endpoint = inner
+                .local_endpoint
+                .unwrap_or_else(|| IpEndpoint::new(IpAddress::Unspecified, get_ephemeral_port()));
+            socket
+                .connect(ip, local_endpoint)
+                .map_err(|err| match err {
+                    smoltcp::Error::Illegal => LxError::EISCONN,
+                    _ => LxError::EINVAL,
+                })?;
+            inner.connecting = true;
+            inner.flags.contains(OpenFlags::NON_BLOCK)
         };
-        if (events.contains(PollEvents::IN) && !recv_state)
-            || (events.contains(PollEvents::OUT) && !send_state)
-        {
+        if nonblock {
+            // submit the SYN
             poll_ifaces();
+            return Err(LxError::EINPROGRESS);
         }
-
-        let (mut read, mut write, mut error) = (false, false, false);
-
+        // wait for connection result
+        wait_socket(false, || {
+            let mut inner = self.inner.lock();
+            let state = get_sockets()
+                .lock()
+                .get::<TcpSocket>(inner.handle.0)
+                .state();
+            inner.update(state);
+            if inner.connecting {
+                return None;
+            }
+            Some(match inner.error.take() {
+                Some(err) => Err(err),
+                None => Ok(0),
+            })
+        })
+        .await
+    }
+    /// wait for some event on a file descriptor
+    fn poll(&self, _events: PollEvents) -> (bool, bool, bool) {
+        poll_ifaces();
+        let mut inner = self.inner.lock();
         let sets = get_sockets();
         let mut sets = sets.lock();
-        let socket = sets.get::<TcpSocket>(inner.handle.0);
 
-        //Todo, syscall async poll needs to be executed after first Pending
-        if inner.is_listening && socket.is_active() {
+        if inner.is_listening {
             // a new connection
-            read = true;
-        } else if !socket.is_open() {
-            error = true;
-        } else {
-            if socket.can_recv() {
-                read = true; // POLLIN
-            }
-            if socket.can_send() {
-                write = true; // POLLOUT
-            }
+            let read = inner
+                .listeners()
+                .any(|handle| ready_to_accept(&sets.get::<TcpSocket>(handle.0)));
+            return (read, false, false);
         }
+
+        let socket = sets.get::<TcpSocket>(inner.handle.0);
+        inner.update(socket.state());
+        let error = inner.error.is_some();
+        // readable at the end of the stream, to read 0
+        let read =
+            socket.can_recv() || inner.read_shutdown || (inner.connected && !socket.may_recv());
+        // a failed connection is reported as writable
+        let write = socket.can_send() || error;
         debug!("tcp poll: {:?}", (read, write, error));
         (read, write, error)
     }
@@ -236,84 +355,56 @@ impl Socket for TcpSocketState {
         }
     }
 
-    fn listen(&self) -> SysResult {
+    fn listen(&self, backlog: usize) -> SysResult {
         let mut inner = self.inner.lock();
-        if inner.is_listening {
-            // it is ok to listen twice
-            info!("It's already listening");
-            return Ok(0);
+        let local_endpoint = inner.local_endpoint.ok_or(LxError::EINVAL)?;
+        if !inner.is_listening {
+            info!("socket listening on {:?}", local_endpoint);
+            let sets = get_sockets();
+            let mut sets = sets.lock();
+            let mut socket = sets.get::<TcpSocket>(inner.handle.0);
+            if !socket.is_listening() {
+                socket.listen(local_endpoint).map_err(|_| LxError::EINVAL)?;
+            }
+            inner.is_listening = true;
         }
 
-        let local_endpoint = inner.local_endpoint.ok_or(LxError::EINVAL)?;
-        info!("socket listening on {:?}", local_endpoint);
+        // it is ok to listen twice, to enlarge the backlog
+        let backlog = backlog.clamp(1, TCP_MAX_BACKLOG);
+        while inner.backlog.len() + 1 < backlog {
+            let mut socket = new_tcp_socket();
+            socket.listen(local_endpoint).unwrap();
+            let handle = GlobalSocketHandle(get_sockets().lock().add(socket));
+            inner.backlog.push(handle);
+        }
+        Ok(0)
+    }
 
+    fn shutdown(&self, how: Shutdown) -> SysResult {
+        let mut inner = self.inner.lock();
         let sets = get_sockets();
         let mut sets = sets.lock();
         let mut socket = sets.get::<TcpSocket>(inner.handle.0);
-
-        if socket.is_listening() {
-            return Ok(0);
+        inner.update(socket.state());
+        if !inner.connected {
+            return Err(LxError::ENOTCONN);
         }
-        match socket.listen(local_endpoint) {
-            Ok(()) => {
-                inner.is_listening = true;
-                Ok(0)
-            }
-            Err(_) => Err(LxError::EINVAL),
+        if how.read() {
+            inner.read_shutdown = true;
         }
-    }
-
-    fn shutdown(&self) -> SysResult {
-        let sets = get_sockets();
-        let mut sets = sets.lock();
-        let mut socket = sets.get::<TcpSocket>(self.inner.lock().handle.0);
-        socket.close();
+        if how.write() {
+            // send FIN
+            socket.close();
+        }
+        drop(socket);
+        drop(sets);
+        drop(inner);
+        poll_ifaces();
         Ok(0)
     }
 
     async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, Endpoint)> {
-        let mut inner = self.inner.lock();
-        let endpoint = inner.local_endpoint.ok_or(LxError::EINVAL)?;
-        loop {
-            //poll_ifaces();
-            let sets = get_sockets();
-            let mut sets = sets.lock();
-            let socket = sets.get::<TcpSocket>(inner.handle.0);
-            if socket.is_active() {
-                let remote_endpoint = socket.remote_endpoint();
-                drop(socket);
-                drop(sets);
-
-                let new_socket = {
-                    let rx_buffer = TcpSocketBuffer::new(vec![0; TCP_RECVBUF]);
-                    let tx_buffer = TcpSocketBuffer::new(vec![0; TCP_SENDBUF]);
-                    let mut socket = TcpSocket::new(rx_buffer, tx_buffer);
-                    socket.listen(endpoint).unwrap();
-
-                    let new_handle = GlobalSocketHandle(get_sockets().lock().add(socket));
-                    let old_handle = ::core::mem::replace(&mut inner.handle, new_handle);
-
-                    Arc::new(TcpSocketState {
-                        base: KObjectBase::new(),
-                        inner: Mutex::new(TcpInner {
-                            handle: old_handle,
-                            local_endpoint: inner.local_endpoint,
-                            is_listening: false,
-                            flags: OpenFlags::RDWR,
-                        }),
-                    })
-                };
-
-                return Ok((
-                    new_socket as Arc<dyn FileLike>,
-                    Endpoint::Ip(remote_endpoint),
-                ));
-            } else {
-                drop(socket);
-                drop(sets);
-                poll_ifaces();
-            }
-        }
+        wait_socket(self.is_nonblock(), || self.try_accept()).await
     }
 
     fn endpoint(&self) -> Option<Endpoint> {
@@ -353,6 +444,25 @@ impl Socket for TcpSocketState {
     fn socket_type(&self) -> Option<SocketType> {
         Some(SocketType::SOCK_STREAM)
     }
+
+    fn socket_options(&self) -> Option<SocketOptions> {
+        Some(self.inner.lock().options)
+    }
+
+    fn set_socket_options(&self, options: SocketOptions) -> SysResult {
+        self.inner.lock().options = options;
+        Ok(0)
+    }
+
+    fn take_error(&self) -> Option<LxError> {
+        let mut inner = self.inner.lock();
+        let state = get_sockets()
+            .lock()
+            .get::<TcpSocket>(inner.handle.0)
+            .state();
+        inner.update(state);
+        inner.error.take()
+    }
 }
 
 impl_kobject!(TcpSocketState);
@@ -391,7 +501,7 @@ impl FileLike for TcpSocketState {
     }
 
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
-        Socket::read(self, buf).await.0
+        Socket::read(self, buf, MsgFlags::empty()).await.0
     }
 
     async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
@@ -408,8 +518,11 @@ impl FileLike for TcpSocketState {
     }
 
     async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
-        let (read, write, error) = Socket::poll(self, events);
-        Ok(PollStatus { read, write, error })
+        wait_socket(false, || self.ready(events).map(Ok)).await
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        on_socket_activity(callback)
     }
 
     fn ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize> {
@@ -1,7 +1,7 @@
 // udpsocket
 
 use crate::error::{LxError, LxResult};
-use crate::fs::{FileLike, OpenFlags, PollStatus};
+use crate::fs::{ChangeCallback, FileLike, OpenFlags, PollStatus};
 use crate::net::*;
 use alloc::{boxed::Box, sync::Arc, vec};
 use async_trait::async_trait;
@@ -28,6 +28,12 @@ pub struct UdpInner {
     handle: GlobalSocketHandle,
     /// remember remote endpoint for connect fn
     remote_endpoint: Option<IpEndpoint>,
+    /// Whether further receptions are disallowed by `shutdown()`.
+    read_shutdown: bool,
+    /// Whether further transmissions are disallowed by `shutdown()`.
+    write_shutdown: bool,
+    /// Options of the socket level.
+    options: SocketOptions,
     /// flags on the socket
     flags: OpenFlags,
 }
@@ -58,53 +64,69 @@ impl UdpSocketState {
             inner: Mutex::new(UdpInner {
                 handle,
                 remote_endpoint: None,
+                read_shutdown: false,
+                write_shutdown: false,
+                options: SocketOptions::default(),
                 flags: OpenFlags::RDWR,
             }),
         }
     }
 }
 
+impl UdpSocketState {
+    fn is_nonblock(&self) -> bool {
+        self.inner.lock().flags.contains(OpenFlags::NON_BLOCK)
+    }
+
+    /// Try to receive a datagram, `None` if it would block.
+    fn try_recv(&self, data: &mut [u8], flags: MsgFlags) -> Option<LxResult<(usize, IpEndpoint)>> {
+        let inner = self.inner.lock();
+        if inner.read_shutdown {
+            return Some(Ok((0, IpEndpoint::UNSPECIFIED)));
+        }
+        let sets = get_sockets();
+        let mut sets = sets.lock();
+        let mut socket = sets.get::<UdpSocket>(inner.handle.0);
+        let copied_len = if flags.contains(MsgFlags::PEEK) {
+            socket.peek().map(|(payload, endpoint)| {
+                let len = payload.len().min(data.len());
+                data[..len].copy_from_slice(&payload[..len]);
+                (len, *endpoint)
+            })
+        } else {
+            socket.recv_slice(data)
+        };
+        match copied_len {
+            Ok(ret) => Some(Ok(ret)),
+            // The receive buffer is empty. Try again later...
+            Err(smoltcp::Error::Exhausted) => None,
+            Err(err) => {
+                error!("udp socket recv_slice error: {:?}", err);
+                Some(Err(LxError::ENOTCONN))
+            }
+        }
+    }
+}
+
 /// missing in implementation
 #[async_trait]
 impl Socket for UdpSocketState {
     /// read to buffer
-    async fn read(&self, data: &mut [u8]) -> (SysResult, Endpoint) {
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint) {
         info!("udp read");
-        let inner = self.inner.lock();
-        loop {
-            let sets = get_sockets();
-            let mut sets = sets.lock();
-            let mut socket = sets.get::<UdpSocket>(inner.handle.0);
-            let copied_len = socket.recv_slice(data);
-            drop(socket);
-            drop(sets);
-
-            match copied_len {
-                Ok((size, endpoint)) => return (Ok(size), Endpoint::Ip(endpoint)),
-                Err(smoltcp::Error::Exhausted) => {
-                    poll_ifaces();
-                    // The receive buffer is empty. Try again later...
-                    if inner.flags.contains(OpenFlags::NON_BLOCK) {
-                        debug!("NON_BLOCK: Try again later...");
-                        return (Err(LxError::EAGAIN), Endpoint::Ip(IpEndpoint::UNSPECIFIED));
-                    } else {
-                        trace!("udp Exhausted. try again")
-                    }
-                }
-                Err(err) => {
-                    error!("udp socket recv_slice error: {:?}", err);
-                    return (
-                        Err(LxError::ENOTCONN),
-                        Endpoint::Ip(IpEndpoint::UNSPECIFIED),
-                    );
-                }
-            }
+        let nonblock = self.is_nonblock() || flags.contains(MsgFlags::DONTWAIT);
+        match wait_socket(nonblock, || self.try_recv(data, flags)).await {
+            Ok((size, endpoint)) => (Ok(size), Endpoint::Ip(endpoint)),
+            Err(err) => (Err(err), Endpoint::Ip(IpEndpoint::UNSPECIFIED)),
         }
     }
     /// write from buffer
     fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
         info!("udp write");
         let inner = self.inner.lock();
+        if inner.write_shutdown {
+            return Err(LxError::EPIPE);
+        }
         let remote_endpoint = {
             if let Some(Endpoint::Ip(ref endpoint)) = sendto_endpoint {
                 endpoint
@@ -161,20 +183,13 @@ impl Socket for UdpSocketState {
             poll_ifaces();
         }
 
-        let (mut input, mut output, mut err) = (false, false, false);
         let sets = get_sockets();
         let mut sets = sets.lock();
         let socket = sets.get::<UdpSocket>(inner.handle.0);
-        if !socket.is_open() {
-            err = true;
-        } else {
-            if socket.can_recv() {
-                input = true;
-            }
-            if socket.can_send() {
-                output = true;
-            }
-        }
+        let input = socket.can_recv() || inner.read_shutdown;
+        // an unbound socket is bound on the first send
+        let output = !socket.is_open() || socket.can_send();
+        let err = false;
         debug!("udp poll: {:?}", (input, output, err));
         (input, output, err)
     }
@@ -197,17 +212,20 @@ impl Socket for UdpSocketState {
             Err(LxError::EINVAL)
         }
     }
-    fn listen(&self) -> SysResult {
-        warn!("listen is unimplemented");
-        Err(LxError::EINVAL)
+    fn listen(&self, _backlog: usize) -> SysResult {
+        Err(LxError::EOPNOTSUPP)
     }
-    fn shutdown(&self) -> SysResult {
-        warn!("shutdown is unimplemented");
-        Err(LxError::EINVAL)
+    fn shutdown(&self, how: Shutdown) -> SysResult {
+        let mut inner = self.inner.lock();
+        if inner.remote_endpoint.is_none() {
+            return Err(LxError::ENOTCONN);
+        }
+        inner.read_shutdown |= how.read();
+        inner.write_shutdown |= how.write();
+        Ok(0)
     }
     async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, Endpoint)> {
-        warn!("accept is unimplemented");
-        Err(LxError::EINVAL)
+        Err(LxError::EOPNOTSUPP)
     }
     fn endpoint(&self) -> Option<Endpoint> {
         let net_sockets = get_sockets();
@@ -286,6 +304,15 @@ impl Socket for UdpSocketState {
     fn socket_type(&self) -> Option<SocketType> {
         Some(SocketType::SOCK_DGRAM)
     }
+
+    fn socket_options(&self) -> Option<SocketOptions> {
+        Some(self.inner.lock().options)
+    }
+
+    fn set_socket_options(&self, options: SocketOptions) -> SysResult {
+        self.inner.lock().options = options;
+        Ok(0)
+    }
 }
 
 impl_kobject!(UdpSocketState);
@@ -307,7 +334,7 @@ impl FileLike for UdpSocketState {
     }
 
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
-        Socket::read(self, buf).await.0
+        Socket::read(self, buf, MsgFlags::empty()).await.0
     }
 
     async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
@@ -324,8 +351,18 @@ impl FileLike for UdpSocketState {
     }
 
     async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
-        let (read, write, error) = Socket::poll(self, events);
-        Ok(PollStatus { read, write, error })
+        wait_socket(false, || {
+            let (read, write, error) = Socket::poll(self, events);
+            let ready = (read && events.contains(PollEvents::IN))
+                || (write && events.contains(PollEvents::OUT))
+                || error;
+            ready.then(|| Ok(PollStatus { read, write, error }))
+        })
+        .await
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        on_socket_activity(callback)
     }
 
     fn ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize> {
@@ -0,0 +1,679 @@
+// unix socket
+
+use crate::error::{LxError, LxResult};
+use crate::fs::{ChangeCallback, FileLike, OpenFileDescription, OpenFlags, PollStatus};
+use crate::net::*;
+use crate::sync::{wait_for_event, Event, EventBus};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Weak;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use async_trait::async_trait;
+use core::cmp::min;
+use core::mem::take;
+use lazy_static::lazy_static;
+use lock::Mutex;
+
+// third part
+#[allow(unused_imports)]
+use zircon_object::impl_kobject;
+#[allow(unused_imports)]
+use zircon_object::object::*;
+
+/// The maximum number of pending connections of a listening socket.
+const UNIX_MAX_BACKLOG: usize = 128;
+
+lazy_static! {
+    /// Sockets bound to an address, found by `connect()` and `sendto()`.
+    ///
+    /// Path names are not created in the file system, they only live here.
+    static ref BOUND_SOCKETS: Mutex<BTreeMap<UnixEndpoint, Weak<UnixShared>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// A message queued on a unix socket.
+struct UnixMessage {
+    data: Vec<u8>,
+    /// How much of `data` has been read, by a stream.
+    offset: usize,
+    /// The address of the sender.
+    from: UnixEndpoint,
+    /// Files passed by `SCM_RIGHTS`.
+    rights: Vec<Arc<dyn FileLike>>,
+    /// Credentials of the sender.
+    cred: UCred,
+}
+
+/// Unix domain socket structure
+pub struct UnixSocketState {
+    /// Kernel object base
+    base: KObjectBase,
+    /// State shared by the duplicated sockets
+    shared: Arc<UnixShared>,
+    /// The open file description shared by the duplicated sockets
+    description: Arc<OpenFileDescription>,
+}
+
+/// The state of a unix socket, referred weakly by its peers and its address.
+struct UnixShared {
+    /// `SOCK_STREAM`, `SOCK_DGRAM` or `SOCK_SEQPACKET`
+    socket_type: SocketType,
+    /// Credentials of the process creating the socket.
+    cred: UCred,
+    /// `READABLE` while there is something to receive or accept,
+    /// `WRITABLE` while a listening socket can queue connections.
+    eventbus: Arc<Mutex<EventBus>>,
+    inner: Mutex<UnixInner>,
+}
+
+/// Unix socket inner
+struct UnixInner {
+    /// flags on the socket
+    flags: OpenFlags,
+    /// Options of the socket level.
+    options: SocketOptions,
+    /// The address bound to.
+    local_endpoint: UnixEndpoint,
+    /// The other end of a connection, or the default destination of datagrams.
+    peer: Option<Weak<UnixShared>>,
+    /// The address of `peer`.
+    peer_endpoint: UnixEndpoint,
+    /// Credentials of `peer` when connected.
+    peer_cred: Option<UCred>,
+    /// The maximum number of pending connections, `Some` if listening.
+    backlog: Option<usize>,
+    /// Connections waiting to be accepted.
+    pending: VecDeque<Arc<UnixSocketState>>,
+    /// Messages to receive.
+    recv_queue: VecDeque<UnixMessage>,
+    /// Whether the peer of a connection is closed or shut down for writing.
+    peer_closed: bool,
+    /// Whether further receptions are disallowed by `shutdown()`.
+    read_shutdown: bool,
+    /// Whether further transmissions are disallowed by `shutdown()`.
+    write_shutdown: bool,
+}
+
+impl UnixShared {
+    fn new(socket_type: SocketType, cred: UCred) -> Arc<Self> {
+        let eventbus = EventBus::new();
+        eventbus.lock().set(Event::WRITABLE);
+        Arc::new(UnixShared {
+            socket_type,
+            cred,
+            eventbus,
+            inner: Mutex::new(UnixInner {
+                flags: OpenFlags::RDWR,
+                options: SocketOptions::default(),
+                local_endpoint: UnixEndpoint::Unnamed,
+                peer: None,
+                peer_endpoint: UnixEndpoint::Unnamed,
+                peer_cred: None,
+                backlog: None,
+                pending: VecDeque::new(),
+                recv_queue: VecDeque::new(),
+                peer_closed: false,
+                read_shutdown: false,
+                write_shutdown: false,
+            }),
+        })
+    }
+
+    /// Whether the socket is connection-oriented.
+    fn is_connection(&self) -> bool {
+        self.socket_type != SocketType::SOCK_DGRAM
+    }
+
+    /// Update the events from the state in `inner`.
+    fn update_events(&self, inner: &UnixInner) {
+        let readable = !inner.recv_queue.is_empty()
+            || !inner.pending.is_empty()
+            || inner.peer_closed
+            || inner.read_shutdown;
+        let writable = inner.backlog.map_or(true, |max| inner.pending.len() < max);
+        let mut set = Event::empty();
+        set.set(Event::READABLE, readable);
+        set.set(Event::WRITABLE, writable);
+        self.eventbus
+            .lock()
+            .change_notify(Event::READABLE | Event::WRITABLE, set);
+    }
+
+    /// Queue a message to be received.
+    fn deliver(&self, msg: UnixMessage) -> LxResult<usize> {
+        let mut inner = self.inner.lock();
+        if inner.read_shutdown {
+            return Err(if self.is_connection() {
+                LxError::EPIPE
+            } else {
+                LxError::ECONNREFUSED
+            });
+        }
+        let len = msg.data.len();
+        inner.recv_queue.push_back(msg);
+        self.update_events(&inner);
+        Ok(len)
+    }
+
+    /// No more data will come from the peer of a connection.
+    fn close_peer(&self) {
+        let mut inner = self.inner.lock();
+        inner.peer_closed = true;
+        self.update_events(&inner);
+        self.eventbus.lock().set(Event::CLOSED);
+    }
+
+    /// Queue a connection from `client` on a listening socket.
+    ///
+    /// Returns the accepting end of the connection, or `None` if the backlog is full.
+    fn queue_connection(
+        &self,
+        client: &Arc<UnixShared>,
+        client_endpoint: UnixEndpoint,
+    ) -> LxResult<Option<Arc<UnixShared>>> {
+        let mut inner = self.inner.lock();
+        let backlog = inner.backlog.ok_or(LxError::ECONNREFUSED)?;
+        if inner.pending.len() >= backlog {
+            return Ok(None);
+        }
+        let server = UnixShared::new(self.socket_type, self.cred);
+        {
+            let mut server_inner = server.inner.lock();
+            server_inner.local_endpoint = inner.local_endpoint.clone();
+            server_inner.peer = Some(Arc::downgrade(client));
+            server_inner.peer_endpoint = client_endpoint;
+            server_inner.peer_cred = Some(client.cred);
+        }
+        inner
+            .pending
+            .push_back(UnixSocketState::with_shared(server.clone()));
+        self.update_events(&inner);
+        Ok(Some(server))
+    }
+}
+
+impl Drop for UnixShared {
+    fn drop(&mut self) {
+        let (peer, local_endpoint, pending) = {
+            let mut inner = self.inner.lock();
+            (
+                inner.peer.take(),
+                take(&mut inner.local_endpoint),
+                take(&mut inner.pending),
+            )
+        };
+        if self.is_connection() {
+            if let Some(peer) = peer.and_then(|peer| peer.upgrade()) {
+                peer.close_peer();
+            }
+        }
+        if local_endpoint != UnixEndpoint::Unnamed {
+            let mut bound = BOUND_SOCKETS.lock();
+            if bound
+                .get(&local_endpoint)
+                .map_or(false, |socket| socket.strong_count() == 0)
+            {
+                bound.remove(&local_endpoint);
+            }
+        }
+        // closes the connections never accepted
+        drop(pending);
+    }
+}
+
+/// Find the socket bound to `endpoint`.
+fn lookup(endpoint: &UnixEndpoint) -> LxResult<Arc<UnixShared>> {
+    let socket = BOUND_SOCKETS
+        .lock()
+        .get(endpoint)
+        .and_then(|socket| socket.upgrade());
+    match (socket, endpoint) {
+        (Some(socket), _) => Ok(socket),
+        (None, UnixEndpoint::Path(_)) => Err(LxError::ENOENT),
+        (None, _) => Err(LxError::ECONNREFUSED),
+    }
+}
+
+impl UnixSocketState {
+    /// Create a unix socket of `socket_type`, by a process with `cred`.
+    pub fn new(socket_type: SocketType, cred: UCred) -> Arc<Self> {
+        info!("unix new: {:?}", socket_type);
+        Self::with_shared(UnixShared::new(socket_type, cred))
+    }
+
+    /// Create a socket of a new open file description on `shared`.
+    fn with_shared(shared: Arc<UnixShared>) -> Arc<Self> {
+        let description = Arc::<OpenFileDescription>::default();
+        description.add(UnixSocketState {
+            base: KObjectBase::new(),
+            shared,
+            description: description.clone(),
+        })
+    }
+
+    /// Create a pair of connected unix sockets, for `socketpair()`.
+    pub fn new_pair(socket_type: SocketType, cred: UCred) -> (Arc<Self>, Arc<Self>) {
+        let (a, b) = (Self::new(socket_type, cred), Self::new(socket_type, cred));
+        let link = |this: &Arc<Self>, other: &Arc<Self>| {
+            let mut inner = this.shared.inner.lock();
+            inner.peer = Some(Arc::downgrade(&other.shared));
+            inner.peer_cred = Some(cred);
+        };
+        link(&a, &b);
+        link(&b, &a);
+        (a, b)
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.shared
+            .inner
+            .lock()
+            .flags
+            .contains(OpenFlags::NON_BLOCK)
+    }
+
+    /// Call `f` until it returns `Some`, waiting for the socket to be readable in between.
+    async fn wait_readable<T, F>(&self, nonblock: bool, mut f: F) -> LxResult<T>
+    where
+        F: FnMut() -> Option<LxResult<T>> + Send,
+    {
+        loop {
+            if let Some(ret) = f() {
+                return ret;
+            }
+            if nonblock {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.shared.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    /// Try to receive a message, `None` if it would block.
+    fn try_recv(
+        &self,
+        data: &mut [u8],
+        flags: MsgFlags,
+    ) -> Option<LxResult<(usize, UnixEndpoint, Ancillary)>> {
+        let shared = &self.shared;
+        let mut inner = shared.inner.lock();
+        if inner.recv_queue.is_empty() {
+            if inner.peer_closed || inner.read_shutdown {
+                return Some(Ok((0, inner.peer_endpoint.clone(), Ancillary::default())));
+            }
+            if shared.is_connection() && inner.peer.is_none() {
+                return Some(Err(LxError::ENOTCONN));
+            }
+            return None;
+        }
+        let peek = flags.contains(MsgFlags::PEEK);
+        let pass_cred = inner.options.pass_cred;
+        let mut ancillary = Ancillary::default();
+        let mut copied = 0;
+        let mut from = UnixEndpoint::Unnamed;
+        if shared.socket_type == SocketType::SOCK_STREAM {
+            for (i, msg) in inner.recv_queue.iter_mut().enumerate() {
+                // the files passed along some data are received by a read of its own
+                if i > 0 && !msg.rights.is_empty() {
+                    break;
+                }
+                let len = min(data.len() - copied, msg.data.len() - msg.offset);
+                data[copied..copied + len].copy_from_slice(&msg.data[msg.offset..msg.offset + len]);
+                copied += len;
+                if i == 0 {
+                    ancillary.rights = if peek {
+                        msg.rights.clone()
+                    } else {
+                        take(&mut msg.rights)
+                    };
+                    ancillary.cred = pass_cred.then(|| msg.cred);
+                }
+                if !peek {
+                    msg.offset += len;
+                }
+                if copied == data.len() {
+                    break;
+                }
+            }
+            if !peek {
+                inner.recv_queue.retain(|msg| msg.offset < msg.data.len());
+            }
+        } else {
+            let mut msg = if peek {
+                let msg = inner.recv_queue.front().unwrap();
+                UnixMessage {
+                    data: msg.data.clone(),
+                    offset: 0,
+                    from: msg.from.clone(),
+                    rights: msg.rights.clone(),
+                    cred: msg.cred,
+                }
+            } else {
+                inner.recv_queue.pop_front().unwrap()
+            };
+            copied = min(data.len(), msg.data.len());
+            data[..copied].copy_from_slice(&msg.data[..copied]);
+            if flags.contains(MsgFlags::TRUNC) {
+                copied = msg.data.len();
+            }
+            ancillary.rights = take(&mut msg.rights);
+            ancillary.cred = pass_cred.then(|| msg.cred);
+            from = msg.from;
+        }
+        shared.update_events(&inner);
+        if shared.is_connection() {
+            from = inner.peer_endpoint.clone();
+        }
+        Some(Ok((copied, from, ancillary)))
+    }
+}
+
+#[async_trait]
+impl Socket for UnixSocketState {
+    /// read to buffer
+    async fn read(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint) {
+        let (ret, endpoint, _) = self.read_msg(data, flags).await;
+        (ret, endpoint)
+    }
+
+    /// write from buffer
+    fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
+        self.write_msg(data, sendto_endpoint, Ancillary::default())
+    }
+
+    async fn read_msg(&self, data: &mut [u8], flags: MsgFlags) -> (SysResult, Endpoint, Ancillary) {
+        info!("unix read");
+        let nonblock = self.is_nonblock() || flags.contains(MsgFlags::DONTWAIT);
+        match self
+            .wait_readable(nonblock, || self.try_recv(data, flags))
+            .await
+        {
+            Ok((len, from, ancillary)) => (Ok(len), Endpoint::Unix(from), ancillary),
+            Err(err) => (
+                Err(err),
+                Endpoint::Unix(UnixEndpoint::Unnamed),
+                Ancillary::default(),
+            ),
+        }
+    }
+
+    fn write_msg(
+        &self,
+        data: &[u8],
+        sendto_endpoint: Option<Endpoint>,
+        ancillary: Ancillary,
+    ) -> SysResult {
+        info!("unix write");
+        let shared = &self.shared;
+        let (peer, from) = {
+            let inner = shared.inner.lock();
+            if inner.write_shutdown {
+                return Err(LxError::EPIPE);
+            }
+            (inner.peer.clone(), inner.local_endpoint.clone())
+        };
+        let target = match sendto_endpoint {
+            Some(Endpoint::Unix(endpoint)) if !shared.is_connection() => lookup(&endpoint)?,
+            Some(Endpoint::Unix(_)) => return Err(LxError::EISCONN),
+            Some(_) => return Err(LxError::EINVAL),
+            None => match peer {
+                Some(peer) => peer.upgrade().ok_or(if shared.is_connection() {
+                    LxError::EPIPE
+                } else {
+                    LxError::ECONNREFUSED
+                })?,
+                None => return Err(LxError::ENOTCONN),
+            },
+        };
+        if target.socket_type != shared.socket_type {
+            return Err(LxError::EPROTOTYPE);
+        }
+        if data.is_empty() && shared.socket_type == SocketType::SOCK_STREAM {
+            return Ok(0);
+        }
+        target.deliver(UnixMessage {
+            data: data.to_vec(),
+            offset: 0,
+            from,
+            rights: ancillary.rights,
+            cred: ancillary.cred.unwrap_or(shared.cred),
+        })
+    }
+
+    /// connect
+    async fn connect(&self, endpoint: Endpoint) -> SysResult {
+        let endpoint = match endpoint {
+            Endpoint::Unix(endpoint) => endpoint,
+            _ => return Err(LxError::EINVAL),
+        };
+        let shared = &self.shared;
+        let target = lookup(&endpoint)?;
+        if target.socket_type != shared.socket_type {
+            return Err(LxError::EPROTOTYPE);
+        }
+        if !shared.is_connection() {
+            let mut inner = shared.inner.lock();
+            inner.peer = Some(Arc::downgrade(&target));
+            inner.peer_endpoint = endpoint;
+            return Ok(0);
+        }
+        let local_endpoint = {
+            let inner = shared.inner.lock();
+            if inner.peer.is_some() {
+                return Err(LxError::EISCONN);
+            }
+            if inner.backlog.is_some() {
+                return Err(LxError::EINVAL);
+            }
+            inner.local_endpoint.clone()
+        };
+        let server = loop {
+            if let Some(server) = target.queue_connection(shared, local_endpoint.clone())? {
+                break server;
+            }
+            if self.is_nonblock() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(target.eventbus.clone(), Event::WRITABLE).await;
+        };
+        let mut inner = shared.inner.lock();
+        inner.peer = Some(Arc::downgrade(&server));
+        inner.peer_endpoint = endpoint;
+        inner.peer_cred = Some(target.cred);
+        Ok(0)
+    }
+
+    /// wait for some event on a file descriptor
+    fn poll(&self, _events: PollEvents) -> (bool, bool, bool) {
+        let shared = &self.shared;
+        let inner = shared.inner.lock();
+        let input = !inner.recv_queue.is_empty()
+            || !inner.pending.is_empty()
+            || inner.peer_closed
+            || inner.read_shutdown;
+        let output = !inner.write_shutdown && (!shared.is_connection() || inner.peer.is_some());
+        let err = false;
+        debug!("unix poll: {:?}", (input, output, err));
+        (input, output, err)
+    }
+
+    fn bind(&self, endpoint: Endpoint) -> SysResult {
+        info!("unix bind: {:?}", endpoint);
+        let endpoint = match endpoint {
+            Endpoint::Unix(UnixEndpoint::Unnamed) => return Err(LxError::EINVAL),
+            Endpoint::Unix(endpoint) => endpoint,
+            _ => return Err(LxError::EINVAL),
+        };
+        let mut inner = self.shared.inner.lock();
+        if inner.local_endpoint != UnixEndpoint::Unnamed {
+            return Err(LxError::EINVAL);
+        }
+        let mut bound = BOUND_SOCKETS.lock();
+        if bound
+            .get(&endpoint)
+            .map_or(false, |socket| socket.strong_count() > 0)
+        {
+            return Err(LxError::EADDRINUSE);
+        }
+        bound.insert(endpoint.clone(), Arc::downgrade(&self.shared));
+        inner.local_endpoint = endpoint;
+        Ok(0)
+    }
+
+    fn listen(&self, backlog: usize) -> SysResult {
+        let shared = &self.shared;
+        if !shared.is_connection() {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        let mut inner = shared.inner.lock();
+        if inner.local_endpoint == UnixEndpoint::Unnamed || inner.peer.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        inner.backlog = Some(backlog.clamp(1, UNIX_MAX_BACKLOG));
+        shared.update_events(&inner);
+        Ok(0)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> SysResult {
+        let shared = &self.shared;
+        let peer = {
+            let mut inner = shared.inner.lock();
+            if inner.peer.is_none() {
+                return Err(LxError::ENOTCONN);
+            }
+            inner.read_shutdown |= how.read();
+            inner.write_shutdown |= how.write();
+            shared.update_events(&inner);
+            inner.peer.clone()
+        };
+        if how.write() && shared.is_connection() {
+            if let Some(peer) = peer.and_then(|peer| peer.upgrade()) {
+                peer.close_peer();
+            }
+        }
+        Ok(0)
+    }
+
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, Endpoint)> {
+        let shared = &self.shared;
+        if !shared.is_connection() {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        let socket = self
+            .wait_readable(self.is_nonblock(), || {
+                let mut inner = shared.inner.lock();
+                if inner.backlog.is_none() {
+                    return Some(Err(LxError::EINVAL));
+                }
+                let socket = inner.pending.pop_front()?;
+                shared.update_events(&inner);
+                Some(Ok(socket))
+            })
+            .await?;
+        let peer_endpoint = socket.shared.inner.lock().peer_endpoint.clone();
+        let socket: Arc<dyn FileLike> = socket;
+        Ok((socket, Endpoint::Unix(peer_endpoint)))
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        let inner = self.shared.inner.lock();
+        Some(Endpoint::Unix(inner.local_endpoint.clone()))
+    }
+
+    fn remote_endpoint(&self) -> Option<Endpoint> {
+        let inner = self.shared.inner.lock();
+        inner
+            .peer
+            .as_ref()
+            .map(|_| Endpoint::Unix(inner.peer_endpoint.clone()))
+    }
+
+    fn get_buffer_capacity(&self) -> Option<(usize, usize)> {
+        Some((UNIX_RECVBUF, UNIX_SENDBUF))
+    }
+
+    fn socket_type(&self) -> Option<SocketType> {
+        Some(self.shared.socket_type)
+    }
+
+    fn socket_options(&self) -> Option<SocketOptions> {
+        Some(self.shared.inner.lock().options)
+    }
+
+    fn set_socket_options(&self, options: SocketOptions) -> SysResult {
+        self.shared.inner.lock().options = options;
+        Ok(0)
+    }
+
+    fn peer_cred(&self) -> Option<UCred> {
+        self.shared.inner.lock().peer_cred
+    }
+}
+
+impl_kobject!(UnixSocketState);
+
+#[async_trait]
+impl FileLike for UnixSocketState {
+    fn flags(&self) -> OpenFlags {
+        self.shared.inner.lock().flags
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.shared.inner.lock().flags;
+
+        // See fcntl, only O_APPEND, O_ASYNC, O_DIRECT, O_NOATIME, O_NONBLOCK
+        flags.set(OpenFlags::APPEND, f.contains(OpenFlags::APPEND));
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        self.description.add(UnixSocketState {
+            base: KObjectBase::new(),
+            shared: self.shared.clone(),
+            description: self.description.clone(),
+        })
+    }
+
+    fn description(&self) -> Option<Arc<OpenFileDescription>> {
+        Some(self.description.clone())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Socket::read(self, buf, MsgFlags::empty()).await.0
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        Socket::write(self, buf, None)
+    }
+
+    fn poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        let (read, write, error) = Socket::poll(self, events);
+        Ok(PollStatus { read, write, error })
+    }
+
+    async fn async_poll(&self, events: PollEvents) -> LxResult<PollStatus> {
+        loop {
+            let (read, write, error) = Socket::poll(self, events);
+            if (read && events.contains(PollEvents::IN))
+                || (write && events.contains(PollEvents::OUT))
+                || error
+            {
+                return Ok(PollStatus { read, write, error });
+            }
+            wait_for_event(self.shared.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn on_change(&self, callback: ChangeCallback) -> bool {
+        self.shared.eventbus.lock().subscribe_once(callback);
+        true
+    }
+
+    fn as_socket(&self) -> LxResult<&dyn Socket> {
+        Ok(self)
+    }
+}
@@ -0,0 +1,144 @@
+//! PID namespaces
+//!
+//! A process is identified by its KoID in the initial namespace. A namespace
+//! created by `clone(CLONE_NEWPID)` numbers its processes from 1, the first
+//! being its init process, and they are also visible in each ancestor of the
+//! namespace, with the PIDs there.
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use lock::Mutex;
+use zircon_object::{
+    object::{KernelObject, KoID},
+    task::Process,
+};
+
+use crate::error::{LxError, LxResult};
+
+/// The deepest nesting of namespaces, as in Linux
+const MAX_PID_NS_LEVEL: usize = 32;
+/// The largest PID, after which the PIDs wrap around
+const PID_MAX: KoID = 1 << 22;
+
+/// A PID namespace.
+pub struct PidNamespace {
+    parent: Option<Arc<PidNamespace>>,
+    level: usize,
+    inner: Mutex<PidNsInner>,
+}
+
+#[derive(Default)]
+struct PidNsInner {
+    /// The last PID allocated
+    last: KoID,
+    /// The process numbered by each PID, and its KoID
+    procs: BTreeMap<KoID, (KoID, Weak<Process>)>,
+    /// The PID of each process by its KoID
+    pids: BTreeMap<KoID, KoID>,
+}
+
+impl PidNamespace {
+    /// Create an initial namespace, where the PID of a process is its KoID.
+    pub fn new() -> Arc<Self> {
+        Arc::new(PidNamespace {
+            parent: None,
+            level: 0,
+            inner: Mutex::new(PidNsInner::default()),
+        })
+    }
+
+    /// Create a namespace nested in this one.
+    pub fn new_child(self: &Arc<Self>) -> LxResult<Arc<Self>> {
+        if self.level >= MAX_PID_NS_LEVEL {
+            return Err(LxError::ENOSPC);
+        }
+        Ok(Arc::new(PidNamespace {
+            parent: Some(self.clone()),
+            level: self.level + 1,
+            inner: Mutex::new(PidNsInner::default()),
+        }))
+    }
+
+    /// Whether it is an initial namespace.
+    pub fn is_initial(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// Returns the namespace it is nested in.
+    pub fn parent(&self) -> Option<&Arc<Self>> {
+        self.parent.as_ref()
+    }
+
+    /// Returns the PID of the process `koid` in the namespace, or `None` if
+    /// the process is not visible in it.
+    pub fn pid_of(&self, koid: KoID) -> Option<KoID> {
+        if self.is_initial() {
+            return Some(koid);
+        }
+        self.inner.lock().pids.get(&koid).cloned()
+    }
+
+    /// Returns the KoID of the process `pid` of the namespace.
+    pub fn koid_of(&self, pid: KoID) -> Option<KoID> {
+        if self.is_initial() {
+            return Some(pid);
+        }
+        self.inner.lock().procs.get(&pid).map(|&(koid, _)| koid)
+    }
+
+    /// Returns the KoIDs of the processes living in a nested namespace, by
+    /// the order of their PIDs.
+    pub fn koids(&self) -> Vec<KoID> {
+        self.inner
+            .lock()
+            .procs
+            .values()
+            .filter(|(_, proc)| proc.strong_count() > 0)
+            .map(|&(koid, _)| koid)
+            .collect()
+    }
+
+    /// Number `proc` in the namespace and its ancestors.
+    pub(crate) fn attach(&self, proc: &Arc<Process>) {
+        let mut ns = Some(self);
+        while let Some(current) = ns {
+            current.alloc(proc);
+            ns = current.parent.as_deref();
+        }
+    }
+
+    /// Number `proc` in the namespace only, returns its PID.
+    pub(crate) fn alloc(&self, proc: &Arc<Process>) -> KoID {
+        if self.is_initial() {
+            return proc.id();
+        }
+        let mut inner = self.inner.lock();
+        // the PIDs of the processes released are free again
+        let dead: Vec<KoID> = inner
+            .procs
+            .iter()
+            .filter(|(_, (_, proc))| proc.strong_count() == 0)
+            .map(|(&pid, _)| pid)
+            .collect();
+        for pid in dead {
+            if let Some((koid, _)) = inner.procs.remove(&pid) {
+                inner.pids.remove(&koid);
+            }
+        }
+        let mut pid = inner.last;
+        loop {
+            pid = if pid >= PID_MAX { 1 } else { pid + 1 };
+            if !inner.procs.contains_key(&pid) {
+                break;
+            }
+        }
+        inner.last = pid;
+        inner.procs.insert(pid, (proc.id(), Arc::downgrade(proc)));
+        inner.pids.insert(proc.id(), pid);
+        pid
+    }
+}
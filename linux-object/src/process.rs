@@ -1,11 +1,20 @@
 //! Linux Process
 
 use crate::{
+    cred::{Capabilities, Credentials},
     error::{LxError, LxResult},
-    fs::{File, FileDesc, FileLike, OpenFlags, STDIN, STDOUT},
+    fs::{
+        release_all_record_locks, release_record_locks, File, FileDesc, FileLike, MountNamespace,
+        OpenFlags, STDIN, STDOUT,
+    },
     ipc::*,
     net::SOCKET_FD,
+    pid_ns::PidNamespace,
+    seccomp::{Seccomp, SeccompData, SeccompFilter},
     signal::{Signal as LinuxSignal, SignalAction},
+    sync::{Event, EventBus},
+    thread::ThreadExt,
+    time::IntervalTimer,
 };
 use alloc::{
     boxed::Box,
@@ -22,7 +31,7 @@ use rcore_fs::vfs::{FileSystem, INode};
 use zircon_object::{
     object::{KernelObject, KoID, Signal},
     signal::Futex,
-    task::{Job, Process, Status},
+    task::{Job, Process, Status, Thread},
     ZxResult,
 };
 
@@ -36,12 +45,27 @@ pub trait ProcessExt {
     fn linux(&self) -> &LinuxProcess;
     /// fork from current linux process
     fn fork_from(parent: &Arc<Self>, vfork: bool) -> ZxResult<Arc<Self>>;
+    /// Send a signal to the process.
+    ///
+    /// The signal is delivered to a thread not blocking it, otherwise it is
+    /// left pending on the first thread.
+    fn send_signal(&self, signal: LinuxSignal);
+    /// Terminate the process by `signal`, reported to the parent by `wait4`
+    /// with `core_dumped` if its core has been dumped.
+    fn exit_by_signal(&self, signal: LinuxSignal, core_dumped: bool);
 }
 
 impl ProcessExt for Process {
     fn create_linux(job: &Arc<Job>, rootfs: Arc<dyn FileSystem>) -> ZxResult<Arc<Self>> {
         let linux_proc = LinuxProcess::new(rootfs);
-        Process::create_with_ext(job, "root", linux_proc)
+        let proc = Process::create_with_ext(job, "root", linux_proc)?;
+        // the first process leads a new session and process group
+        let mut inner = proc.linux().inner.lock();
+        inner.id = proc.id();
+        inner.pgid = proc.id();
+        inner.sid = proc.id();
+        drop(inner);
+        Ok(proc)
     }
 
     fn linux(&self) -> &LinuxProcess {
@@ -54,18 +78,34 @@ impl ProcessExt for Process {
     fn fork_from(parent: &Arc<Self>, vfork: bool) -> ZxResult<Arc<Self>> {
         let linux_parent = parent.linux();
         let mut linux_parent_inner = linux_parent.inner.lock();
+        let pid_ns = linux_parent.child_pid_ns();
         let new_linux_proc = LinuxProcess {
-            root_inode: linux_parent.root_inode.clone(),
+            mnt_ns: Mutex::new(linux_parent.mount_ns()),
+            pid_ns: Mutex::new(pid_ns.clone()),
+            child_pid_ns: Mutex::new(None),
             parent: Arc::downgrade(parent),
+            event_bus: EventBus::new(),
             inner: Mutex::new(LinuxProcessInner {
                 execute_path: linux_parent_inner.execute_path.clone(),
-                current_working_directory: linux_parent_inner.current_working_directory.clone(),
+                args: linux_parent_inner.args.clone(),
+                fs: linux_parent_inner.fs.clone(),
                 files: linux_parent_inner.files.clone(),
                 signal_actions: linux_parent_inner.signal_actions.clone(),
+                pgid: linux_parent_inner.pgid,
+                sid: linux_parent_inner.sid,
+                core_limit: linux_parent_inner.core_limit,
+                strace: linux_parent_inner.strace,
+                cred: linux_parent_inner.cred.clone(),
+                umask: linux_parent_inner.umask,
+                seccomp: linux_parent_inner.seccomp.clone(),
+                no_new_privs: linux_parent_inner.no_new_privs,
+                shm_identifiers: linux_parent_inner.shm_identifiers.clone(),
                 ..Default::default()
             }),
         };
         let new_proc = Process::create_with_ext(&parent.job(), "", new_linux_proc)?;
+        new_proc.linux().inner.lock().id = new_proc.id();
+        pid_ns.attach(&new_proc);
         linux_parent_inner
             .children
             .insert(new_proc.id(), new_proc.clone());
@@ -75,15 +115,95 @@ impl ProcessExt for Process {
 
         // notify parent on terminated
         let parent = parent.clone();
+        let id = new_proc.id();
+        let proc = Arc::downgrade(&new_proc);
         new_proc.add_signal_callback(Box::new(move |signal| {
             if signal.contains(Signal::PROCESS_TERMINATED) {
                 info!("Received signal: {:?}", signal);
+                release_all_record_locks(id);
+                if let Some(proc) = proc.upgrade() {
+                    proc.linux().inner.lock().semaphores.undo_all();
+                    kill_pid_ns_if_init(&proc);
+                }
                 parent.signal_set(Signal::SIGCHLD);
             }
             false
         }));
         Ok(new_proc)
     }
+
+    fn send_signal(&self, signal: LinuxSignal) {
+        match signal {
+            LinuxSignal::SIGKILL => {
+                self.exit_by_signal(LinuxSignal::SIGKILL, false);
+                self.linux().notify_signal();
+                return;
+            }
+            LinuxSignal::SIGCONT => self.linux().resume(),
+            _ => {}
+        }
+        let threads: Vec<Arc<Thread>> = self
+            .thread_ids()
+            .into_iter()
+            .filter_map(|tid| self.get_child(tid).ok())
+            .filter_map(|obj| obj.downcast_arc().ok())
+            .collect();
+        let target = threads
+            .iter()
+            .find(|t| !t.lock_linux().signal_mask.contains(signal))
+            .or_else(|| threads.first());
+        if let Some(thread) = target {
+            thread.send_signal(signal);
+        }
+    }
+
+    fn exit_by_signal(&self, signal: LinuxSignal, core_dumped: bool) {
+        self.linux().inner.lock().term_signal = Some((signal, core_dumped));
+        // the exit code seen by the host follows the shells
+        self.exit(128 + signal as i64);
+    }
+}
+
+/// Kill the other processes in the PID namespace of `proc` if it is the init
+/// process of the namespace.
+fn kill_pid_ns_if_init(proc: &Arc<Process>) {
+    let pid_ns = proc.linux().pid_ns();
+    if pid_ns.is_initial() || pid_ns.pid_of(proc.id()) != Some(1) {
+        return;
+    }
+    let job = proc.job();
+    for koid in pid_ns.koids() {
+        if koid == proc.id() {
+            continue;
+        }
+        if let Ok(other) = job.get_child(koid) {
+            if let Ok(other) = other.downcast_arc::<Process>() {
+                other.send_signal(LinuxSignal::SIGKILL);
+            }
+        }
+    }
+}
+
+/// Release the record locks of the process `owner` on the file of a closed
+/// descriptor, as any descriptor of the file is closed.
+fn unlock_on_close(file: &Arc<dyn FileLike>, owner: KoID) {
+    if let Some(file) = file.downcast_ref::<File>() {
+        release_record_locks(&*file.inode(), owner);
+    }
+}
+
+/// Wait status of a child stopped by `signal`.
+fn stopped_status(signal: LinuxSignal) -> ExitCode {
+    0x7f | ((signal as ExitCode) << 8)
+}
+
+/// Wait status of a child exited with `code`, or terminated by a signal.
+fn exited_status(child: &Process, code: i64) -> ExitCode {
+    match child.linux().inner.lock().term_signal {
+        Some((signal, true)) => signal as ExitCode | 0x80,
+        Some((signal, false)) => signal as ExitCode,
+        None => ((code as ExitCode) & 0xff) << 8,
+    }
 }
 
 /// Wait for state changes in a child of the calling process, and obtain information about
@@ -91,37 +211,80 @@ impl ProcessExt for Process {
 ///
 /// A state change is considered to be:
 /// - the child terminated.
-/// - the child was stopped by a signal. TODO
+/// - the child was stopped by a signal, if `untraced` is set or the child is traced.
 /// - the child was resumed by a signal. TODO
-pub async fn wait_child(proc: &Arc<Process>, pid: KoID, nonblock: bool) -> LxResult<ExitCode> {
+pub async fn wait_child(
+    proc: &Arc<Process>,
+    pid: KoID,
+    nonblock: bool,
+    untraced: bool,
+) -> LxResult<ExitCode> {
     loop {
         let mut inner = proc.linux().inner.lock();
         let child = inner.children.get(&pid).ok_or(LxError::ECHILD)?;
         if let Status::Exited(code) = child.status() {
+            let status = exited_status(child, code);
             inner.children.remove(&pid);
-            return Ok(code as ExitCode);
+            return Ok(status);
+        }
+        // the stops of traced children are always reported
+        let untraced = untraced || child.linux().is_traced();
+        if untraced {
+            if let Some(signal) = child.linux().take_stop_report() {
+                return Ok(stopped_status(signal));
+            }
         }
         if nonblock {
             return Err(LxError::EAGAIN);
         }
         let child: Arc<dyn KernelObject> = child.clone();
         drop(inner);
-        child.signal_clear(Signal::PROCESS_TERMINATED);
-        child.wait_signal(Signal::PROCESS_TERMINATED).await;
+        if untraced {
+            // stopped children notify the parent only
+            let proc: Arc<dyn KernelObject> = proc.clone();
+            proc.signal_clear(Signal::SIGCHLD);
+            proc.wait_signal(Signal::SIGCHLD).await;
+        } else {
+            child.signal_clear(Signal::PROCESS_TERMINATED);
+            child.wait_signal(Signal::PROCESS_TERMINATED).await;
+        }
     }
 }
 
-/// Wait for state changes in a child of the calling process.
-pub async fn wait_child_any(proc: &Arc<Process>, nonblock: bool) -> LxResult<(KoID, ExitCode)> {
+/// Wait for state changes in a child of the calling process, returns the PID
+/// of the child seen by the caller.
+///
+/// If `pgid` is given, only children in that process group are waited for.
+pub async fn wait_child_any(
+    proc: &Arc<Process>,
+    pgid: Option<KoID>,
+    nonblock: bool,
+    untraced: bool,
+) -> LxResult<(KoID, ExitCode)> {
     loop {
         let mut inner = proc.linux().inner.lock();
-        if inner.children.is_empty() {
+        let pids: Vec<KoID> = inner
+            .children
+            .iter()
+            .filter(|(_, child)| pgid.map_or(true, |pgid| child.linux().pgid() == pgid))
+            .map(|(&pid, _)| pid)
+            .collect();
+        if pids.is_empty() {
             return Err(LxError::ECHILD);
         }
-        for (&pid, child) in inner.children.iter() {
+        for pid in pids {
+            let child = &inner.children[&pid];
+            // the PID is released with the child
+            let seen_pid = proc.linux().pid_of(pid);
             if let Status::Exited(code) = child.status() {
+                let status = exited_status(child, code);
                 inner.children.remove(&pid);
-                return Ok((pid, code as ExitCode));
+                return Ok((seen_pid, status));
+            }
+            if untraced || child.linux().is_traced() {
+                if let Some(signal) = child.linux().take_stop_report() {
+                    return Ok((seen_pid, stopped_status(signal)));
+                }
             }
         }
         drop(inner);
@@ -136,27 +299,63 @@ pub async fn wait_child_any(proc: &Arc<Process>, nonblock: bool) -> LxResult<(Ko
     }
 }
 
+/// Returns the processes in the process group `pgid` of `job`.
+pub fn process_group(job: &Arc<Job>, pgid: KoID) -> Vec<Arc<Process>> {
+    job.process_ids()
+        .into_iter()
+        .filter_map(|pid| job.get_child(pid).ok())
+        .filter_map(|obj| obj.downcast_arc::<Process>().ok())
+        .filter(|proc| proc.linux().pgid() == pgid)
+        .collect()
+}
+
 /// Linux specific process information.
 pub struct LinuxProcess {
-    /// The root INode of file system
-    root_inode: Arc<dyn INode>,
+    /// The mount namespace, giving the root INode of file system
+    mnt_ns: Mutex<Arc<MountNamespace>>,
+    /// The PID namespace
+    pid_ns: Mutex<Arc<PidNamespace>>,
+    /// The PID namespace of the children, if not the one of the process
+    child_pid_ns: Mutex<Option<Arc<PidNamespace>>>,
     /// Parent process
     parent: Weak<Process>,
+    /// Process events, e.g. signal arrival
+    event_bus: Arc<Mutex<EventBus>>,
     /// Inner
     inner: Mutex<LinuxProcessInner>,
 }
 
+/// The root and the working directory of a process.
+///
+/// They are kept as paths, so that they are found again in the tree of the
+/// mount namespace after it changes.
+#[derive(Default, Clone)]
+struct FsContext {
+    /// Root directory, the path in the mount namespace
+    ///
+    /// Omit leading '/', empty unless changed by `chroot`.
+    root: String,
+    /// Current Working Directory, relative to the root directory
+    ///
+    /// Omit leading '/'.
+    cwd: String,
+}
+
 /// Linux process mut inner data
 #[derive(Default)]
 struct LinuxProcessInner {
+    /// The KoID of the process, the owner of its record locks
+    id: KoID,
     /// Execute path
     execute_path: String,
-    /// Current Working Directory
-    ///
-    /// Omit leading '/'.
-    current_working_directory: String,
+    /// Command line arguments
+    args: Vec<String>,
+    /// Root and working directories
+    fs: FsContext,
     /// file open number limit
     file_limit: RLimit,
+    /// core dump size limit
+    core_limit: RLimit,
     /// Opened files
     files: HashMap<FileDesc, Arc<dyn FileLike>>,
     /// Semaphore
@@ -169,6 +368,36 @@ struct LinuxProcessInner {
     children: HashMap<KoID, Arc<Process>>,
     /// Signal actions
     signal_actions: SignalActions,
+    /// Process group ID
+    pgid: KoID,
+    /// Session ID
+    sid: KoID,
+    /// The signal stopping the process, `None` if it is running
+    stop_signal: Option<LinuxSignal>,
+    /// Whether the stop has been reported to the parent by `wait4`
+    stop_reported: bool,
+    /// The signal terminating the process, and whether its core is dumped
+    term_signal: Option<(LinuxSignal, bool)>,
+    /// Whether the process is traced by the parent
+    traced: bool,
+    /// The thread in a ptrace-stop
+    ptrace_thread: Option<Weak<Thread>>,
+    /// The signal to deliver after the ptrace-stop, chosen by the tracer
+    ptrace_signal: Option<LinuxSignal>,
+    /// Whether the syscalls are logged to the console, by `/proc/[pid]/strace`
+    strace: bool,
+    /// User and group IDs, and capabilities
+    cred: Credentials,
+    /// The file mode creation mask
+    umask: u32,
+    /// The seccomp mode and filters
+    seccomp: Seccomp,
+    /// Whether `execve` can not grant privileges, set by `PR_SET_NO_NEW_PRIVS`
+    no_new_privs: bool,
+    /// POSIX interval timers by ID
+    timers: HashMap<usize, Arc<IntervalTimer>>,
+    /// The timer of `ITIMER_REAL`
+    real_timer: Option<Arc<IntervalTimer>>,
 }
 
 #[derive(Clone)]
@@ -229,16 +458,202 @@ impl LinuxProcess {
         files.insert(1.into(), stdout);
         files.insert(2.into(), stderr);
 
+        let pid_ns = PidNamespace::new();
         LinuxProcess {
-            root_inode: crate::fs::create_root_fs(rootfs), //Arc::clone(&ROOT_INODE),访问磁盘可能更快？
+            mnt_ns: Mutex::new(crate::fs::create_root_fs(rootfs, &pid_ns)),
+            pid_ns: Mutex::new(pid_ns),
+            child_pid_ns: Mutex::new(None),
             parent: Weak::default(),
+            event_bus: EventBus::new(),
             inner: Mutex::new(LinuxProcessInner {
                 files,
+                // no core dumps unless enabled by `setrlimit`, as in Linux
+                core_limit: RLimit {
+                    cur: 0,
+                    max: u64::MAX,
+                },
+                umask: 0o022,
                 ..Default::default()
             }),
         }
     }
 
+    /// Get the process group ID.
+    pub fn pgid(&self) -> KoID {
+        self.inner.lock().pgid
+    }
+
+    /// Set the process group ID.
+    pub fn set_pgid(&self, pgid: KoID) {
+        self.inner.lock().pgid = pgid;
+    }
+
+    /// Get the session ID.
+    pub fn sid(&self) -> KoID {
+        self.inner.lock().sid
+    }
+
+    /// Create a new session led by the process `pid`, which also leads a new process group.
+    pub fn set_sid(&self, pid: KoID) {
+        let mut inner = self.inner.lock();
+        inner.sid = pid;
+        inner.pgid = pid;
+    }
+
+    /// Stop the process by a job control `signal`, and notify the parent.
+    pub fn stop(&self, signal: LinuxSignal) {
+        let mut inner = self.inner.lock();
+        inner.stop_signal = Some(signal);
+        inner.stop_reported = false;
+        drop(inner);
+        if let Some(parent) = self.parent() {
+            parent.signal_set(Signal::SIGCHLD);
+        }
+    }
+
+    /// Resume a stopped process, on receiving `SIGCONT`.
+    ///
+    /// A process in a ptrace-stop is only resumed by the tracer.
+    pub fn resume(&self) {
+        let mut inner = self.inner.lock();
+        if inner.ptrace_thread.is_none() {
+            inner.stop_signal = None;
+        }
+    }
+
+    /// Whether the process is stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.lock().stop_signal.is_some()
+    }
+
+    /// Set whether the process is traced by the parent.
+    pub fn set_traced(&self, traced: bool) {
+        self.inner.lock().traced = traced;
+    }
+
+    /// Whether the process is traced by the parent.
+    pub fn is_traced(&self) -> bool {
+        self.inner.lock().traced
+    }
+
+    /// Enter a ptrace-stop of `thread` on the delivery of `signal`, and
+    /// notify the tracer. The thread should be suspended until resumed by
+    /// [`ptrace_resume`](Self::ptrace_resume).
+    pub fn ptrace_stop(&self, thread: &Arc<Thread>, signal: LinuxSignal) {
+        let mut inner = self.inner.lock();
+        inner.ptrace_thread = Some(Arc::downgrade(thread));
+        inner.ptrace_signal = None;
+        drop(inner);
+        self.stop(signal);
+    }
+
+    /// Returns the thread in the ptrace-stop.
+    pub fn ptrace_thread(&self) -> Option<Arc<Thread>> {
+        let inner = self.inner.lock();
+        inner.stop_signal?;
+        inner.ptrace_thread.as_ref()?.upgrade()
+    }
+
+    /// Leave the ptrace-stop and deliver `signal`, if any.
+    ///
+    /// Returns the thread to resume.
+    pub fn ptrace_resume(&self, signal: Option<LinuxSignal>) -> Option<Arc<Thread>> {
+        let mut inner = self.inner.lock();
+        inner.stop_signal?;
+        let thread = inner.ptrace_thread.take()?.upgrade();
+        inner.stop_signal = None;
+        inner.ptrace_signal = signal;
+        thread
+    }
+
+    /// Returns the signal to deliver after the ptrace-stop.
+    pub fn take_ptrace_signal(&self) -> Option<LinuxSignal> {
+        self.inner.lock().ptrace_signal.take()
+    }
+
+    /// Set whether the syscalls of the process are logged to the console.
+    pub fn set_strace(&self, enabled: bool) {
+        self.inner.lock().strace = enabled;
+    }
+
+    /// Whether the syscalls of the process are logged to the console.
+    pub fn strace(&self) -> bool {
+        self.inner.lock().strace
+    }
+
+    /// Returns the credentials.
+    pub fn cred(&self) -> Credentials {
+        self.inner.lock().cred.clone()
+    }
+
+    /// Replace the credentials.
+    pub fn set_cred(&self, cred: Credentials) {
+        self.inner.lock().cred = cred;
+    }
+
+    /// Returns the file mode creation mask.
+    pub fn umask(&self) -> u32 {
+        self.inner.lock().umask
+    }
+
+    /// Set the file mode creation mask, returns the previous one.
+    pub fn set_umask(&self, umask: u32) -> u32 {
+        core::mem::replace(&mut self.inner.lock().umask, umask & 0o777)
+    }
+
+    /// Returns the seccomp mode: 0 for disabled, 1 for strict, 2 for filter.
+    pub fn seccomp_mode(&self) -> usize {
+        self.inner.lock().seccomp.mode()
+    }
+
+    /// Enter the seccomp strict mode.
+    pub fn set_seccomp_strict(&self) -> LxResult {
+        self.inner.lock().seccomp.set_strict()
+    }
+
+    /// Install a seccomp filter.
+    ///
+    /// Requires `no_new_privs` or `CAP_SYS_ADMIN`, otherwise returns `EACCES`.
+    pub fn add_seccomp_filter(&self, filter: SeccompFilter) -> LxResult {
+        let mut inner = self.inner.lock();
+        if !inner.no_new_privs && !inner.cred.has_cap(Capabilities::SYS_ADMIN) {
+            return Err(LxError::EACCES);
+        }
+        inner.seccomp.add_filter(filter)
+    }
+
+    /// Whether the process is in the seccomp strict mode.
+    pub fn is_seccomp_strict(&self) -> bool {
+        self.inner.lock().seccomp.is_strict()
+    }
+
+    /// Run the seccomp filters on a syscall, returns the action to take.
+    pub fn seccomp_filter(&self, data: &SeccompData) -> u32 {
+        self.inner.lock().seccomp.filter(data)
+    }
+
+    /// Set `no_new_privs`, which can not be unset.
+    pub fn set_no_new_privs(&self) {
+        self.inner.lock().no_new_privs = true;
+    }
+
+    /// Whether `no_new_privs` is set.
+    pub fn no_new_privs(&self) -> bool {
+        self.inner.lock().no_new_privs
+    }
+
+    /// Returns the stop signal if the stop has not been reported by `wait4` yet.
+    fn take_stop_report(&self) -> Option<LinuxSignal> {
+        let mut inner = self.inner.lock();
+        match inner.stop_signal {
+            Some(signal) if !inner.stop_reported => {
+                inner.stop_reported = true;
+                Some(signal)
+            }
+            _ => None,
+        }
+    }
+
     /// Get futex object.
     #[allow(unsafe_code)]
     pub fn get_futex(&self, uaddr: VirtAddr) -> Arc<Futex> {
@@ -277,7 +692,8 @@ impl LinuxProcess {
         self.insert_file(inner, fd, file)
     }
 
-    /// Add a file to the file descriptor table at given `fd`.
+    /// Add a file to the file descriptor table at given `fd`, closing the
+    /// file of `fd` if any.
     pub fn add_file_at(&self, fd: FileDesc, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
         let inner = self.inner.lock();
         self.insert_file(inner, fd, file)
@@ -290,8 +706,11 @@ impl LinuxProcess {
         fd: FileDesc,
         file: Arc<dyn FileLike>,
     ) -> LxResult<FileDesc> {
-        if inner.files.len() < inner.file_limit.cur as usize {
-            inner.files.insert(fd, file);
+        if inner.files.contains_key(&fd) || inner.files.len() < inner.file_limit.cur as usize {
+            // the file replaced is closed
+            if let Some(old) = inner.files.insert(fd, file) {
+                unlock_on_close(&old, inner.id);
+            }
             Ok(fd)
         } else {
             Err(LxError::EMFILE)
@@ -308,6 +727,16 @@ impl LinuxProcess {
         old
     }
 
+    /// get and set core dump size limit
+    pub fn core_limit(&self, new_limit: Option<RLimit>) -> RLimit {
+        let mut inner = self.inner.lock();
+        let old = inner.core_limit;
+        if let Some(limit) = new_limit {
+            inner.core_limit = limit;
+        }
+        old
+    }
+
     /// Get the `File` with given `fd`.
     pub fn get_file(&self, fd: FileDesc) -> LxResult<Arc<File>> {
         let file = self
@@ -344,12 +773,109 @@ impl LinuxProcess {
     /// Close file descriptor `fd`.
     pub fn close_file(&self, fd: FileDesc) -> LxResult {
         let mut inner = self.inner.lock();
-        inner.files.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
+        let file = inner.files.remove(&fd).ok_or(LxError::EBADF)?;
+        unlock_on_close(&file, inner.id);
+        Ok(())
+    }
+
+    /// Get root INode of the process, which is the root directory of the
+    /// mount namespace unless changed by `chroot`.
+    pub fn root_inode(&self) -> LxResult<Arc<dyn INode>> {
+        let ns_root = self.mount_ns().root_inode();
+        let root = self.inner.lock().fs.root.clone();
+        if root.is_empty() {
+            return Ok(ns_root);
+        }
+        Ok(ns_root.lookup(&root)?)
+    }
+
+    /// Get the path of the root directory in the mount namespace.
+    pub fn root_path(&self) -> String {
+        String::from("/") + &self.inner.lock().fs.root
+    }
+
+    /// Change the root directory to `path`, the normalized path of a
+    /// directory in the mount namespace.
+    ///
+    /// The working directory is kept if it is under the new root, otherwise
+    /// it moves to the new root, so that it can not be used to escape.
+    pub fn change_root(&self, path: &str) {
+        let mut inner = self.inner.lock();
+        let root = path.trim_matches('/');
+        let cwd = [inner.fs.root.as_str(), inner.fs.cwd.as_str()]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/");
+        inner.fs.cwd = if root.is_empty() {
+            cwd
+        } else if cwd == root {
+            String::new()
+        } else {
+            match cwd.strip_prefix(root).and_then(|s| s.strip_prefix('/')) {
+                Some(rest) => String::from(rest),
+                None => String::new(),
+            }
+        };
+        inner.fs.root = String::from(root);
     }
 
-    /// Get root INode of the process.
-    pub fn root_inode(&self) -> &Arc<dyn INode> {
-        &self.root_inode
+    /// Get the mount namespace of the process.
+    pub fn mount_ns(&self) -> Arc<MountNamespace> {
+        self.mnt_ns.lock().clone()
+    }
+
+    /// Move the process to a copy of its mount namespace, for `CLONE_NEWNS`.
+    pub fn unshare_mount_ns(&self) -> LxResult {
+        let mut mnt_ns = self.mnt_ns.lock();
+        *mnt_ns = mnt_ns.copy()?;
+        Ok(())
+    }
+
+    /// Get the PID namespace of the process.
+    pub fn pid_ns(&self) -> Arc<PidNamespace> {
+        self.pid_ns.lock().clone()
+    }
+
+    /// Get the PID namespace of the children created later.
+    pub fn child_pid_ns(&self) -> Arc<PidNamespace> {
+        match &*self.child_pid_ns.lock() {
+            Some(pid_ns) => pid_ns.clone(),
+            None => self.pid_ns(),
+        }
+    }
+
+    /// Create the children later in a new PID namespace, for
+    /// `unshare(CLONE_NEWPID)`.
+    pub fn unshare_pid_ns(&self) -> LxResult {
+        let mut child_pid_ns = self.child_pid_ns.lock();
+        if child_pid_ns.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        *child_pid_ns = Some(self.pid_ns().new_child()?);
+        Ok(())
+    }
+
+    /// Move `proc`, the process newly created, into a new PID namespace as
+    /// its init process, for `clone(CLONE_NEWPID)`.
+    pub fn enter_new_pid_ns(&self, proc: &Arc<Process>) -> LxResult {
+        let mut pid_ns = self.pid_ns.lock();
+        let new_ns = pid_ns.new_child()?;
+        new_ns.alloc(proc);
+        *pid_ns = new_ns;
+        Ok(())
+    }
+
+    /// Returns the PID of the process `koid` seen by this process, or 0 if it
+    /// is not in the PID namespace of this process.
+    pub fn pid_of(&self, koid: KoID) -> KoID {
+        self.pid_ns().pid_of(koid).unwrap_or(0)
+    }
+
+    /// Returns the KoID of the process `pid` seen by this process.
+    pub fn koid_of(&self, pid: KoID) -> LxResult<KoID> {
+        self.pid_ns().koid_of(pid).ok_or(LxError::ESRCH)
     }
 
     /// Get parent process.
@@ -357,9 +883,22 @@ impl LinuxProcess {
         self.parent.upgrade()
     }
 
+    /// Get the event bus of the process.
+    pub fn event_bus(&self) -> Arc<Mutex<EventBus>> {
+        self.event_bus.clone()
+    }
+
+    /// Notify waiters that a signal was sent to a thread of this process.
+    pub fn notify_signal(&self) {
+        let mut bus = self.event_bus.lock();
+        // clear first so that every new signal triggers the callbacks
+        bus.clear(Event::RECEIVE_SIGNAL);
+        bus.set(Event::RECEIVE_SIGNAL);
+    }
+
     /// Get current working directory.
     pub fn current_working_directory(&self) -> String {
-        String::from("/") + &self.inner.lock().current_working_directory
+        String::from("/") + &self.inner.lock().fs.cwd
     }
 
     /// Change working directory.
@@ -370,7 +909,7 @@ impl LinuxProcess {
         let mut inner = self.inner.lock();
         let cwd = match path.as_bytes()[0] {
             b'/' => String::new(),
-            _ => inner.current_working_directory.clone(),
+            _ => inner.fs.cwd.clone(),
         };
         let mut cwd_vec: Vec<_> = cwd.split('/').filter(|x| !x.is_empty()).collect();
         for seg in path.split('/') {
@@ -382,7 +921,7 @@ impl LinuxProcess {
                 _ => cwd_vec.push(seg),
             }
         }
-        inner.current_working_directory = cwd_vec.join("/");
+        inner.fs.cwd = cwd_vec.join("/");
     }
 
     /// Get execute path.
@@ -395,6 +934,16 @@ impl LinuxProcess {
         self.inner.lock().execute_path = String::from(path);
     }
 
+    /// Get command line arguments.
+    pub fn args(&self) -> Vec<String> {
+        self.inner.lock().args.clone()
+    }
+
+    /// Set command line arguments.
+    pub fn set_args(&self, args: &[String]) {
+        self.inner.lock().args = args.to_vec();
+    }
+
     /// Get signal action.
     pub fn signal_action(&self, signal: LinuxSignal) -> SignalAction {
         self.inner.lock().signal_actions.table[signal as u8 as usize]
@@ -405,6 +954,42 @@ impl LinuxProcess {
         self.inner.lock().signal_actions.table[signal as u8 as usize] = action;
     }
 
+    /// Add a POSIX interval timer, returning its ID.
+    pub fn add_timer(&self, timer: Arc<IntervalTimer>) -> usize {
+        let mut inner = self.inner.lock();
+        let id = (0..).find(|id| !inner.timers.contains_key(id)).unwrap();
+        inner.timers.insert(id, timer);
+        id
+    }
+
+    /// Get the POSIX interval timer of `id`.
+    pub fn get_timer(&self, id: usize) -> LxResult<Arc<IntervalTimer>> {
+        self.inner
+            .lock()
+            .timers
+            .get(&id)
+            .cloned()
+            .ok_or(LxError::EINVAL)
+    }
+
+    /// Delete the POSIX interval timer of `id`, which is disarmed.
+    pub fn remove_timer(&self, id: usize) -> LxResult {
+        match self.inner.lock().timers.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Delete all POSIX interval timers, on `execve`.
+    pub fn remove_timers(&self) {
+        self.inner.lock().timers.clear();
+    }
+
+    /// Get the timer of `ITIMER_REAL`, created by `new` at the first time.
+    pub fn real_timer(&self, new: impl FnOnce() -> Arc<IntervalTimer>) -> Arc<IntervalTimer> {
+        self.inner.lock().real_timer.get_or_insert_with(new).clone()
+    }
+
     /// Close file that FD_CLOEXEC is set
     pub fn remove_cloexec_files(&self) {
         let mut inner = self.inner.lock();
@@ -424,53 +1009,31 @@ impl LinuxProcess {
             })
             .collect::<Vec<_>>();
         for fd in close_fds {
-            inner.files.remove(&fd).map(|_| ()).unwrap();
+            let file = inner.files.remove(&fd).unwrap();
+            unlock_on_close(&file, inner.id);
         }
     }
 
-    /// Insert a `SemArray` and return its ID
-    pub fn semaphores_add(&self, array: Arc<SemArray>) -> usize {
-        self.inner.lock().semaphores.add(array)
-    }
-
-    /// Get an semaphore set by `id`
-    pub fn semaphores_get(&self, id: usize) -> Option<Arc<SemArray>> {
-        self.inner.lock().semaphores.get(id)
-    }
-
-    /// Add an undo operation
-    pub fn semaphores_add_undo(&self, id: usize, num: u16, op: i16) {
-        self.inner.lock().semaphores.add_undo(id, num, op)
-    }
-
-    /// Remove an `SemArray` by ID
-    pub fn semaphores_remove(&self, id: usize) {
-        self.inner.lock().semaphores.remove(id)
-    }
-
-    /// get ShmId from Virtual Addr
-    pub fn shm_get_id(&self, id: usize) -> Option<usize> {
-        self.inner.lock().shm_identifiers.get_id(id)
-    }
-
-    /// get the ShmIdentifier from shm_identifiers
-    pub fn shm_get(&self, id: usize) -> Option<ShmIdentifier> {
-        self.inner.lock().shm_identifiers.get(id)
+    /// Record the semaphore set `id` to undo the operations of the process
+    /// `owner` in when it terminates.
+    pub fn semaphores_add_undo(&self, id: usize, owner: KoID) {
+        self.inner.lock().semaphores.add_undo(id, owner)
     }
 
-    /// Delete the ShmIdentifier from shm_identifiers
-    pub fn shm_pop(&self, id: usize) {
-        self.inner.lock().shm_identifiers.pop(id)
+    /// Record the shared memory segment of `guard` attached at `addr`
+    pub fn shm_attach(&self, addr: usize, guard: Arc<ShmGuard>) {
+        self.inner.lock().shm_identifiers.attach(addr, guard)
     }
 
-    /// Insert the `SharedGuard` and return its ID
-    pub fn shm_add(&self, shared_guard: Arc<Mutex<ShmGuard>>) -> usize {
-        self.inner.lock().shm_identifiers.add(shared_guard)
+    /// Forget the shared memory segment attached at `addr`, returning it
+    pub fn shm_detach(&self, addr: usize) -> Option<ShmIdentifier> {
+        self.inner.lock().shm_identifiers.detach(addr)
     }
 
-    /// Set Virtual Addr for shared memory
-    pub fn shm_set(&self, id: usize, shm_id: ShmIdentifier) {
-        self.inner.lock().shm_identifiers.set(id, shm_id)
+    /// Detach all shared memory segments, on `execve`.
+    pub fn shm_detach_all(&self) {
+        let shm = core::mem::take(&mut self.inner.lock().shm_identifiers);
+        drop(shm);
     }
 }
 
@@ -0,0 +1,454 @@
+//! Secure computing mode, filtering the syscalls of a process.
+//!
+//! In the strict mode, only `read`, `write`, `exit` and `rt_sigreturn` are
+//! allowed. In the filter mode, every syscall is checked by the classic BPF
+//! programs installed, and the action of the highest precedence is taken.
+//!
+//! Different from Linux, the mode and the filters belong to the process
+//! rather than the thread, as if installed with `SECCOMP_FILTER_FLAG_TSYNC`.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::mem::size_of;
+
+use crate::error::{LxError, LxResult};
+
+/// Kill the process.
+pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+/// Kill the thread.
+pub const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+/// Send `SIGSYS` to the thread instead of the syscall.
+pub const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+/// Return the errno in the data instead of the syscall.
+pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+/// Notify a supervisor in the user space, not supported.
+pub const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+/// Notify the tracer, not supported.
+pub const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+/// Allow the syscall after logging it.
+pub const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+/// Allow the syscall.
+pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+/// The mask of the action in a return value.
+pub const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+/// The mask of the data in a return value.
+pub const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        /// The `AUDIT_ARCH_*` value of the syscalls, checked by the filters.
+        pub const AUDIT_ARCH_CURRENT: u32 = 0xc000_003e;
+    } else if #[cfg(target_arch = "aarch64")] {
+        /// The `AUDIT_ARCH_*` value of the syscalls, checked by the filters.
+        pub const AUDIT_ARCH_CURRENT: u32 = 0xc000_00b7;
+    } else if #[cfg(target_arch = "riscv64")] {
+        /// The `AUDIT_ARCH_*` value of the syscalls, checked by the filters.
+        pub const AUDIT_ARCH_CURRENT: u32 = 0xc000_00f3;
+    } else {
+        /// The `AUDIT_ARCH_*` value of the syscalls, checked by the filters.
+        pub const AUDIT_ARCH_CURRENT: u32 = 0xc000_0102;
+    }
+}
+
+/// The most instructions of a program.
+const BPF_MAXINSNS: usize = 4096;
+/// The most instructions of all the filters of a process.
+const MAX_INSNS_PER_PATH: usize = 32768;
+/// The number of the scratch memory words.
+const BPF_MEMWORDS: u32 = 16;
+
+// instruction classes
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+// sizes and modes of loads
+const BPF_W: u16 = 0x00;
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+// ALU and jump operations
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+// the operands
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// An instruction of classic BPF, `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SockFilter {
+    /// The opcode.
+    pub code: u16,
+    /// The offset to jump to if true.
+    pub jt: u8,
+    /// The offset to jump to if false.
+    pub jf: u8,
+    /// The generic field.
+    pub k: u32,
+}
+
+/// The input of the filters, `struct seccomp_data`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SeccompData {
+    /// The syscall number.
+    pub nr: i32,
+    /// The `AUDIT_ARCH_*` value.
+    pub arch: u32,
+    /// The address of the syscall instruction.
+    pub instruction_pointer: u64,
+    /// The syscall arguments.
+    pub args: [u64; 6],
+}
+
+impl SeccompData {
+    /// The data as 32-bit words in the little-endian memory layout.
+    fn words(&self) -> [u32; 16] {
+        let mut words = [0; 16];
+        words[0] = self.nr as u32;
+        words[1] = self.arch;
+        let dwords = core::iter::once(&self.instruction_pointer).chain(self.args.iter());
+        for (i, dword) in dwords.enumerate() {
+            words[2 + i * 2] = *dword as u32;
+            words[3 + i * 2] = (*dword >> 32) as u32;
+        }
+        words
+    }
+}
+
+/// A validated classic BPF program.
+#[derive(Debug)]
+pub struct SeccompFilter {
+    prog: Vec<SockFilter>,
+}
+
+impl SeccompFilter {
+    /// Validate the program, which may only load the [`SeccompData`], and
+    /// only jump forwards to end with a return.
+    pub fn new(prog: Vec<SockFilter>) -> LxResult<Self> {
+        if prog.is_empty() || prog.len() > BPF_MAXINSNS {
+            return Err(LxError::EINVAL);
+        }
+        let len = prog.len();
+        let in_range = |pc: usize, offset: usize| pc + 1 + offset < len;
+        for (pc, insn) in prog.iter().enumerate() {
+            let k = insn.k;
+            let valid = match insn.code {
+                c if c == BPF_LD | BPF_W | BPF_ABS => {
+                    k % 4 == 0 && (k as usize) < size_of::<SeccompData>()
+                }
+                c if c == BPF_LD | BPF_W | BPF_LEN || c == BPF_LDX | BPF_W | BPF_LEN => true,
+                c if c == BPF_LD | BPF_IMM || c == BPF_LDX | BPF_IMM => true,
+                c if c == BPF_LD | BPF_MEM || c == BPF_LDX | BPF_MEM => k < BPF_MEMWORDS,
+                BPF_ST | BPF_STX => k < BPF_MEMWORDS,
+                c if c == BPF_MISC | BPF_TAX || c == BPF_MISC | BPF_TXA => true,
+                c if c == BPF_RET | BPF_K || c == BPF_RET | BPF_A => true,
+                c if c & 0x07 == BPF_ALU && c <= 0xff => match c & 0xf0 {
+                    BPF_DIV | BPF_MOD => c & BPF_X != 0 || k != 0,
+                    BPF_LSH | BPF_RSH => c & BPF_X != 0 || k < 32,
+                    BPF_NEG => c & BPF_X == 0,
+                    op => op <= BPF_XOR,
+                },
+                c if c == BPF_JMP | BPF_JA => in_range(pc, k as usize),
+                c if c & 0x07 == BPF_JMP && c <= 0xff => {
+                    matches!(c & 0xf0, BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET)
+                        && in_range(pc, insn.jt as usize)
+                        && in_range(pc, insn.jf as usize)
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err(LxError::EINVAL);
+            }
+        }
+        if prog[len - 1].code & 0x07 != BPF_RET {
+            return Err(LxError::EINVAL);
+        }
+        Ok(SeccompFilter { prog })
+    }
+
+    /// Run the program on `data`, returns the action with its data.
+    pub fn run(&self, data: &SeccompData) -> u32 {
+        let words = data.words();
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut mem = [0u32; BPF_MEMWORDS as usize];
+        let mut pc = 0;
+        loop {
+            let insn = self.prog[pc];
+            let k = insn.k;
+            pc += 1;
+            match insn.code {
+                c if c == BPF_LD | BPF_W | BPF_ABS => a = words[k as usize / 4],
+                c if c == BPF_LD | BPF_W | BPF_LEN => a = size_of::<SeccompData>() as u32,
+                c if c == BPF_LDX | BPF_W | BPF_LEN => x = size_of::<SeccompData>() as u32,
+                c if c == BPF_LD | BPF_IMM => a = k,
+                c if c == BPF_LDX | BPF_IMM => x = k,
+                c if c == BPF_LD | BPF_MEM => a = mem[k as usize],
+                c if c == BPF_LDX | BPF_MEM => x = mem[k as usize],
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                c if c == BPF_MISC | BPF_TAX => x = a,
+                c if c == BPF_MISC | BPF_TXA => a = x,
+                c if c == BPF_RET | BPF_K => return k,
+                c if c == BPF_RET | BPF_A => return a,
+                c if c & 0x07 == BPF_ALU => {
+                    let src = if c & BPF_X != 0 { x } else { k };
+                    a = match c & 0xf0 {
+                        BPF_ADD => a.wrapping_add(src),
+                        BPF_SUB => a.wrapping_sub(src),
+                        BPF_MUL => a.wrapping_mul(src),
+                        // the program returns 0 on division by zero, as in Linux
+                        BPF_DIV | BPF_MOD if src == 0 => return SECCOMP_RET_KILL_THREAD,
+                        BPF_DIV => a / src,
+                        BPF_MOD => a % src,
+                        BPF_OR => a | src,
+                        BPF_AND => a & src,
+                        BPF_XOR => a ^ src,
+                        BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                        _ => a.wrapping_neg(),
+                    };
+                }
+                c if c == BPF_JMP | BPF_JA => pc += k as usize,
+                c => {
+                    let src = if c & BPF_X != 0 { x } else { k };
+                    let taken = match c & 0xf0 {
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        _ => a & src != 0,
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+            }
+        }
+    }
+}
+
+/// The seccomp state of a process.
+#[derive(Debug, Default, Clone)]
+pub struct Seccomp {
+    strict: bool,
+    /// The filters, the latest installed first.
+    filters: Vec<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    /// The mode returned by `PR_GET_SECCOMP`: 0 for disabled, 1 for strict,
+    /// 2 for filter.
+    pub fn mode(&self) -> usize {
+        if self.strict {
+            1
+        } else if !self.filters.is_empty() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Enter the strict mode, which can not be left.
+    pub fn set_strict(&mut self) -> LxResult {
+        if !self.filters.is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        self.strict = true;
+        Ok(())
+    }
+
+    /// Install a filter in addition to the ones installed.
+    pub fn add_filter(&mut self, filter: SeccompFilter) -> LxResult {
+        if self.strict {
+            return Err(LxError::EINVAL);
+        }
+        let insns: usize = self.filters.iter().map(|f| f.prog.len()).sum();
+        if insns + filter.prog.len() > MAX_INSNS_PER_PATH {
+            return Err(LxError::ENOMEM);
+        }
+        self.filters.insert(0, Arc::new(filter));
+        Ok(())
+    }
+
+    /// Run all the filters on `data`, returns the action of the highest
+    /// precedence, or `SECCOMP_RET_ALLOW` if not in the filter mode.
+    ///
+    /// The actions are compared as signed integers, the lower the higher
+    /// precedence, and the latest installed wins on the same action.
+    pub fn filter(&self, data: &SeccompData) -> u32 {
+        let mut ret = SECCOMP_RET_ALLOW;
+        for filter in self.filters.iter() {
+            let cur = filter.run(data);
+            if ((cur & SECCOMP_RET_ACTION_FULL) as i32) < ((ret & SECCOMP_RET_ACTION_FULL) as i32) {
+                ret = cur;
+            }
+        }
+        ret
+    }
+
+    /// Whether in the strict mode.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn stmt(code: u16, k: u32) -> SockFilter {
+        SockFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+        SockFilter { code, jt, jf, k }
+    }
+
+    const fn ret(k: u32) -> SockFilter {
+        stmt(BPF_RET | BPF_K, k)
+    }
+
+    fn syscall(nr: i32, arg0: u64) -> SeccompData {
+        SeccompData {
+            nr,
+            arch: AUDIT_ARCH_CURRENT,
+            args: [arg0, 0, 0, 0, 0, 0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let invalid = [
+            // empty or too long
+            vec![],
+            vec![ret(SECCOMP_RET_ALLOW); BPF_MAXINSNS + 1],
+            // unknown opcode
+            vec![stmt(0xff, 0), ret(SECCOMP_RET_ALLOW)],
+            // load out of the data, or not aligned
+            vec![stmt(BPF_LD | BPF_W | BPF_ABS, 64), ret(SECCOMP_RET_ALLOW)],
+            vec![stmt(BPF_LD | BPF_W | BPF_ABS, 2), ret(SECCOMP_RET_ALLOW)],
+            // scratch memory out of range
+            vec![stmt(BPF_ST, BPF_MEMWORDS), ret(SECCOMP_RET_ALLOW)],
+            // division by a constant zero
+            vec![stmt(BPF_ALU | BPF_DIV | BPF_K, 0), ret(SECCOMP_RET_ALLOW)],
+            // jumps out of the program
+            vec![stmt(BPF_JMP | BPF_JA, 1), ret(SECCOMP_RET_ALLOW)],
+            vec![
+                jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1),
+                ret(SECCOMP_RET_ALLOW),
+            ],
+            // no return at the end
+            vec![ret(SECCOMP_RET_ALLOW), stmt(BPF_LD | BPF_IMM, 0)],
+        ];
+        for prog in invalid {
+            assert!(SeccompFilter::new(prog).is_err());
+        }
+        let longest = vec![ret(SECCOMP_RET_ALLOW); BPF_MAXINSNS];
+        assert!(SeccompFilter::new(longest).is_ok());
+    }
+
+    #[test]
+    fn conditional_jump() {
+        // return EPERM for syscall 2 with the flag 0x40 in the first argument
+        let filter = SeccompFilter::new(vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, 4),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH_CURRENT, 1, 0),
+            ret(SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 2, 0, 3),
+            stmt(BPF_LD | BPF_W | BPF_ABS, 16),
+            jump(BPF_JMP | BPF_JSET | BPF_K, 0x40, 0, 1),
+            ret(SECCOMP_RET_ERRNO | 1),
+            ret(SECCOMP_RET_ALLOW),
+        ])
+        .unwrap();
+        assert_eq!(filter.run(&syscall(2, 0x41)), SECCOMP_RET_ERRNO | 1);
+        assert_eq!(filter.run(&syscall(2, 0x1)), SECCOMP_RET_ALLOW);
+        assert_eq!(filter.run(&syscall(3, 0x40)), SECCOMP_RET_ALLOW);
+        let mut data = syscall(2, 0x40);
+        data.arch = 0;
+        assert_eq!(filter.run(&data), SECCOMP_RET_KILL_PROCESS);
+    }
+
+    #[test]
+    fn alu() {
+        // (nr * 3 + 1) / x, returned as the data of ERRNO
+        let filter = SeccompFilter::new(vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, 16),
+            stmt(BPF_MISC | BPF_TAX, 0),
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0),
+            stmt(BPF_ALU | BPF_MUL | BPF_K, 3),
+            stmt(BPF_ALU | BPF_ADD | BPF_K, 1),
+            stmt(BPF_ALU | BPF_DIV | BPF_X, 0),
+            stmt(BPF_ALU | BPF_OR | BPF_K, SECCOMP_RET_ERRNO),
+            stmt(BPF_RET | BPF_A, 0),
+        ])
+        .unwrap();
+        assert_eq!(filter.run(&syscall(5, 2)), SECCOMP_RET_ERRNO | 8);
+        // the program stops with 0 on a division by zero
+        assert_eq!(filter.run(&syscall(5, 0)), SECCOMP_RET_KILL_THREAD);
+    }
+
+    #[test]
+    fn action_precedence() {
+        let actions = [
+            SECCOMP_RET_ALLOW,
+            SECCOMP_RET_TRACE,
+            SECCOMP_RET_ERRNO | 1,
+            SECCOMP_RET_TRAP,
+            SECCOMP_RET_KILL_THREAD,
+            SECCOMP_RET_KILL_PROCESS,
+        ];
+        let data = syscall(0, 0);
+        let mut seccomp = Seccomp::default();
+        assert_eq!(seccomp.filter(&data), SECCOMP_RET_ALLOW);
+        // each filter installed takes precedence over the ones before
+        for &action in actions.iter() {
+            let filter = SeccompFilter::new(vec![ret(action)]).unwrap();
+            seccomp.add_filter(filter).unwrap();
+            assert_eq!(seccomp.filter(&data), action);
+        }
+        // but not the other way around
+        let mut seccomp = Seccomp::default();
+        for &action in actions.iter().rev() {
+            let filter = SeccompFilter::new(vec![ret(action)]).unwrap();
+            seccomp.add_filter(filter).unwrap();
+            assert_eq!(seccomp.filter(&data), SECCOMP_RET_KILL_PROCESS);
+        }
+        // the latest installed wins on the same action
+        let mut seccomp = Seccomp::default();
+        for errno in 1..=2 {
+            let filter = SeccompFilter::new(vec![ret(SECCOMP_RET_ERRNO | errno)]).unwrap();
+            seccomp.add_filter(filter).unwrap();
+        }
+        assert_eq!(seccomp.filter(&data), SECCOMP_RET_ERRNO | 2);
+        assert_eq!(seccomp.mode(), 2);
+        assert!(seccomp.set_strict().is_err());
+    }
+}
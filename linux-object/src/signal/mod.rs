@@ -45,6 +45,19 @@ cfg_if::cfg_if! {
             pub _pad: [u64; 15], // very strange, maybe a bug of musl libc
             pub context: MachineContext,
         }
+    } else if #[cfg(target_arch = "loongarch64")] {
+        /// See musl struct __ucontext
+        #[repr(C)]
+        #[derive(Clone, Default, Debug)]
+        pub struct SignalUserContext {
+            pub flags: usize,
+            pub link: usize,
+            pub stack: SignalStack,
+            pub sig_mask: Sigset,
+            pub _pad: [u64; 15], // very strange, maybe a bug of musl libc
+            pub _uc_pad: u64,
+            pub context: MachineContext,
+        }
     } else { // others structures, this sample is for aarch64
         /// See musl struct __ucontext
         #[repr(C)]
@@ -151,6 +164,32 @@ cfg_if::cfg_if! {
                 self.general_regs[0] = pc;
             }
         }
+    } else if #[cfg(target_arch = "loongarch64")] {
+        /// struct mcontext
+        #[repr(C, align(16))]
+        #[derive(Clone, Debug, Default, Eq, PartialEq)]
+        pub struct MachineContext {
+            pub pc: usize,
+            pub general_regs: [usize; 32],
+            pub flags: u32,
+        }
+
+        impl MachineContext {
+            pub fn new(pc : usize) -> Self {
+                Self {
+                    pc,
+                    ..Self::default()
+                }
+            }
+
+            pub fn get_pc(&self) -> usize {
+                self.pc
+            }
+
+            pub fn set_pc(&mut self, pc: usize) {
+                self.pc = pc;
+            }
+        }
     } else {
         /// TODO: other archs, this sample is for aarch64
         /// struct mcontext
@@ -297,6 +336,21 @@ numeric_enum! {
     }
 }
 
+/// The action taken by a signal without a handler
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum SignalDefaultAction {
+    /// terminate the process
+    Terminate,
+    /// terminate the process and dump core
+    CoreDump,
+    /// ignore the signal
+    Ignore,
+    /// stop the process
+    Stop,
+    /// continue the process if it is stopped
+    Continue,
+}
+
 impl Signal {
     pub const RTMIN: usize = 32;
     pub const RTMAX: usize = 64;
@@ -308,4 +362,17 @@ impl Signal {
     pub fn as_bit(&self) -> u64 {
         1 << (*self as u64 - 1)
     }
+
+    /// Returns the default action of the signal.
+    pub fn default_action(self) -> SignalDefaultAction {
+        use Signal::*;
+        match self {
+            SIGCHLD | SIGURG | SIGWINCH => SignalDefaultAction::Ignore,
+            SIGCONT => SignalDefaultAction::Continue,
+            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => SignalDefaultAction::Stop,
+            SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU
+            | SIGXFSZ | SIGSYS => SignalDefaultAction::CoreDump,
+            _ => SignalDefaultAction::Terminate,
+        }
+    }
 }
@@ -80,11 +80,35 @@ impl EventBus {
         }
     }
 
+    /// call the callbacks with the current event flags, though not changed,
+    /// e.g. to wake up the waiters of a state not told by the flags
+    pub fn notify(&mut self) {
+        let event = self.event;
+        self.callbacks.retain(|f| !f(event));
+    }
+
+    /// change event flags like [`change`](Self::change), but call the
+    /// callbacks though not changed, e.g. to tell more data arriving at a
+    /// file readable already
+    pub fn change_notify(&mut self, reset: Event, set: Event) {
+        self.event.remove(reset);
+        self.event.insert(set);
+        self.notify();
+    }
+
     /// push a EventHandler into the callback vector
     pub fn subscribe(&mut self, callback: EventHandler) {
         self.callbacks.push(callback);
     }
 
+    /// call `callback` once on the next call of the callbacks
+    pub fn subscribe_once(&mut self, callback: impl Fn() + Send + 'static) {
+        self.subscribe(Box::new(move |_| {
+            callback();
+            true
+        }));
+    }
+
     /// get the callback vector length
     pub fn get_callback_len(&self) -> usize {
         self.callbacks.len()
@@ -1,15 +1,30 @@
 //! Linux Thread
 
-use crate::error::SysResult;
+use crate::error::{LxError, LxResult, SysResult};
 use crate::process::ProcessExt;
-use crate::signal::{SigInfo, Signal, SignalStack, SignalUserContext, Sigset};
+use crate::signal::{
+    SigInfo, Signal, SignalDefaultAction, SignalStack, SignalUserContext, Sigset, SIG_DFL, SIG_IGN,
+};
+use crate::sync::Event;
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
 use kernel_hal::context::{UserContext, UserContextField};
 use kernel_hal::user::{Out, UserInPtr, UserOutPtr, UserPtr};
 use lock::{Mutex, MutexGuard};
 use zircon_object::task::{CurrentThread, Process, Thread};
 use zircon_object::ZxResult;
 
+/// Get the thread running on the current CPU.
+pub fn current_thread() -> Option<Arc<Thread>> {
+    kernel_hal::thread::get_current_thread()?
+        .downcast::<Thread>()
+        .ok()
+}
+
 /// Thread extension for linux
 pub trait ThreadExt {
     /// create linux thread
@@ -26,6 +41,11 @@ pub trait ThreadExt {
     ) -> SysResult;
     /// Set robust list.
     fn set_robust_list(&self, head: UserInPtr<RobustList>, len: usize);
+    /// Add `signal` to the pending signals and wake up the waiters of the process.
+    fn send_signal(&self, signal: Signal);
+    /// Whether a pending signal is neither blocked nor ignored, which
+    /// interrupts the waits of the thread.
+    fn has_signal_to_handle(&self) -> bool;
 }
 
 /// CurrentThread extension for linux
@@ -44,6 +64,7 @@ impl ThreadExt for Thread {
             robust_list: 0.into(),
             robust_list_len: 0,
             handling_signal: None,
+            saved_signal_mask: None,
         });
         Thread::create_with_ext(proc, "", linux_thread)
     }
@@ -74,6 +95,82 @@ impl ThreadExt for Thread {
         self.lock_linux().robust_list = head;
         self.lock_linux().robust_list_len = len;
     }
+
+    fn send_signal(&self, signal: Signal) {
+        self.lock_linux().signals.insert(signal);
+        self.proc().linux().notify_signal();
+    }
+
+    fn has_signal_to_handle(&self) -> bool {
+        let mut pending = {
+            let linux = self.lock_linux();
+            linux.signals.mask_with(&linux.signal_mask)
+        };
+        let proc = self.proc().linux();
+        while let Some(signal) = pending.find_first_signal() {
+            let handler = proc.signal_action(signal).handler;
+            let ignored = handler == SIG_IGN
+                || handler == SIG_DFL
+                    && matches!(
+                        signal.default_action(),
+                        SignalDefaultAction::Ignore | SignalDefaultAction::Continue
+                    );
+            if !ignored {
+                return true;
+            }
+            pending.remove(signal);
+        }
+        false
+    }
+}
+
+/// Wait for `future` unless a signal to handle arrives at `thread`, which
+/// fails the wait with `EINTR`.
+pub fn interruptible<'a, T>(
+    thread: &'a Arc<Thread>,
+    future: impl Future<Output = LxResult<T>> + 'a,
+) -> impl Future<Output = LxResult<T>> + 'a {
+    InterruptibleFuture {
+        thread,
+        future: Box::pin(future),
+        subscribed: Arc::default(),
+    }
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct InterruptibleFuture<'a, F> {
+    thread: &'a Arc<Thread>,
+    future: Pin<Box<F>>,
+    /// whether a callback waits for the next signal sent to the process
+    subscribed: Arc<AtomicBool>,
+}
+
+impl<T, F: Future<Output = LxResult<T>>> Future for InterruptibleFuture<'_, F> {
+    type Output = LxResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // subscribe before checking, not to miss a signal in between
+        if !self.subscribed.swap(true, Ordering::AcqRel) {
+            let waker = cx.waker().clone();
+            let subscribed = self.subscribed.clone();
+            let bus = self.thread.proc().linux().event_bus();
+            bus.lock().subscribe(Box::new(move |event| {
+                if !event.contains(Event::RECEIVE_SIGNAL) {
+                    return false;
+                }
+                subscribed.store(false, Ordering::Release);
+                waker.wake_by_ref();
+                true
+            }));
+        }
+        if let Poll::Ready(ret) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(ret);
+        }
+        if self.thread.has_signal_to_handle() {
+            return Poll::Ready(Err(LxError::EINTR));
+        }
+        Poll::Pending
+    }
 }
 
 impl CurrentThreadExt for CurrentThread {
@@ -121,6 +218,9 @@ pub struct LinuxThread {
     robust_list_len: usize,
     /// handling signals
     pub handling_signal: Option<u32>,
+    /// The signal mask to restore once a signal is chosen to handle, while
+    /// the one replaced during a wait (e.g. `ppoll`) lasts until then
+    pub saved_signal_mask: Option<Sigset>,
 }
 
 fn unmodified_check(siginfo: &SigInfo, user_ctx: &SignalUserContext) -> usize {
@@ -171,7 +271,10 @@ impl LinuxThread {
     }
 
     /// Handle signal
+    ///
+    /// Returns the signal and the mask to restore after handling it.
     pub fn handle_signal(&mut self) -> Option<(Signal, Sigset)> {
+        let saved_mask = self.saved_signal_mask.take();
         if self.handling_signal.is_none() {
             let signal = self
                 .signals
@@ -180,9 +283,19 @@ impl LinuxThread {
             if let Some(signal) = signal {
                 self.handling_signal = Some(signal as u32);
                 self.signals.remove(signal);
-                return Some((signal, self.signal_mask));
+                return Some((signal, saved_mask.unwrap_or(self.signal_mask)));
             }
         }
+        if let Some(mask) = saved_mask {
+            self.signal_mask = mask;
+        }
         None
     }
+
+    /// Finish handling a signal without a handler, restoring the signal
+    /// mask `sigmask` returned by [`handle_signal`](Self::handle_signal).
+    pub fn end_handle_signal(&mut self, sigmask: Sigset) {
+        self.handling_signal = None;
+        self.signal_mask = sigmask;
+    }
 }
@@ -1,8 +1,48 @@
 //! Linux time objects
 
-use alloc::sync::Arc;
+use crate::error::LxError;
+use crate::process::ProcessExt;
+use crate::signal::Signal;
+use crate::thread::ThreadExt;
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicI64, Ordering};
 use core::time::Duration;
+use kernel_hal::timer;
+use lock::Mutex;
 use rcore_fs::vfs::*;
+use zircon_object::task::{Process, Thread};
+
+/// Adjustment of the realtime clock by `clock_settime`, in nanoseconds.
+static REALTIME_ADJUST: AtomicI64 = AtomicI64::new(0);
+
+/// Get the offset of the realtime clock from the monotonic one, in
+/// nanoseconds.
+fn realtime_offset() -> i128 {
+    timer::boot_realtime().as_nanos() as i128 + REALTIME_ADJUST.load(Ordering::Relaxed) as i128
+}
+
+/// Get the current time of the realtime clock, since the UNIX epoch.
+pub fn realtime_now() -> Duration {
+    let nanos = timer::timer_now().as_nanos() as i128 + realtime_offset();
+    Duration::from_nanos(nanos.max(0) as u64)
+}
+
+/// Set the realtime clock to `time`, since the UNIX epoch.
+pub fn set_realtime(time: Duration) {
+    let adjust = time.as_nanos() as i128
+        - timer::timer_now().as_nanos() as i128
+        - timer::boot_realtime().as_nanos() as i128;
+    REALTIME_ADJUST.store(adjust as i64, Ordering::Relaxed);
+}
+
+/// Convert a time of the realtime clock to the monotonic one, which timers
+/// are set by. Times before boot are converted to zero.
+pub fn realtime_to_monotonic(time: Duration) -> Duration {
+    let nanos = time.as_nanos() as i128 - realtime_offset();
+    Duration::from_nanos(nanos.max(0) as u64)
+}
 
 /// TimeSpec struct for clock_gettime, similar to Timespec
 #[repr(C)]
@@ -36,13 +76,9 @@ impl TimeVal {
 }
 
 impl TimeSpec {
-    /// create TimeSpec
+    /// create TimeSpec of the realtime clock
     pub fn now() -> TimeSpec {
-        let time = kernel_hal::timer::timer_now();
-        TimeSpec {
-            sec: time.as_secs() as usize,
-            nsec: (time.as_nanos() % 1_000_000_000) as usize,
-        }
+        realtime_now().into()
     }
 
     /// update TimeSpec for a file inode
@@ -88,6 +124,15 @@ impl From<TimeSpec> for Duration {
     }
 }
 
+impl From<Duration> for TimeSpec {
+    fn from(d: Duration) -> Self {
+        Self {
+            sec: d.as_secs() as _,
+            nsec: d.subsec_nanos() as _,
+        }
+    }
+}
+
 impl From<TimeSpec> for TimeVal {
     fn from(t: TimeSpec) -> Self {
         Self {
@@ -97,6 +142,53 @@ impl From<TimeSpec> for TimeVal {
     }
 }
 
+impl From<TimeVal> for TimeSpec {
+    fn from(t: TimeVal) -> Self {
+        Self {
+            sec: t.sec,
+            nsec: t.usec * 1_000,
+        }
+    }
+}
+
+/// ITimerSpec struct for timerfd_settime / timer_settime
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ITimerSpec {
+    /// interval for periodic timer
+    pub interval: TimeSpec,
+    /// initial expiration
+    pub value: TimeSpec,
+}
+
+/// ITimerVal struct for setitimer / getitimer
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ITimerVal {
+    /// interval for periodic timer
+    pub interval: TimeVal,
+    /// time until the next expiration
+    pub value: TimeVal,
+}
+
+impl From<ITimerVal> for ITimerSpec {
+    fn from(t: ITimerVal) -> Self {
+        Self {
+            interval: t.interval.into(),
+            value: t.value.into(),
+        }
+    }
+}
+
+impl From<ITimerSpec> for ITimerVal {
+    fn from(t: ITimerSpec) -> Self {
+        Self {
+            interval: t.interval.into(),
+            value: t.value.into(),
+        }
+    }
+}
+
 /// RUsage for sys_getrusage()
 /// ignore other fields for now
 #[repr(C)]
@@ -122,7 +214,7 @@ pub struct Tms {
 }
 
 /// Clock id
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(usize)]
 pub enum ClockId {
     /// missing documentation
@@ -147,9 +239,21 @@ pub enum ClockId {
     ClockBootTimeAlarm = 9,
 }
 
-impl From<usize> for ClockId {
-    fn from(t: usize) -> ClockId {
-        match t {
+impl ClockId {
+    /// Whether the clock follows the realtime clock, which may be set.
+    pub fn is_realtime(self) -> bool {
+        matches!(
+            self,
+            ClockId::ClockRealTime | ClockId::ClockRealTimeCoarse | ClockId::ClockRealTimeAlarm
+        )
+    }
+}
+
+impl TryFrom<usize> for ClockId {
+    type Error = LxError;
+
+    fn try_from(t: usize) -> Result<ClockId, LxError> {
+        Ok(match t {
             0 => ClockId::ClockRealTime,
             1 => ClockId::ClockMonotonic,
             2 => ClockId::ClockProcessCpuTimeId,
@@ -160,8 +264,8 @@ impl From<usize> for ClockId {
             7 => ClockId::ClockBootTime,
             8 => ClockId::ClockRealTimeAlarm,
             9 => ClockId::ClockBootTimeAlarm,
-            _ => unreachable!(),
-        }
+            _ => return Err(LxError::EINVAL),
+        })
     }
 }
 
@@ -184,3 +288,158 @@ impl From<usize> for ClockFlags {
         }
     }
 }
+
+/// Notify the process by sending the signal.
+pub const SIGEV_SIGNAL: i32 = 0;
+/// Notify nothing.
+pub const SIGEV_NONE: i32 = 1;
+/// Notify the thread of `tid` by sending the signal.
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// SigEvent struct for timer_create, of how to notify on expirations
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SigEvent {
+    /// data passed with the notification
+    pub value: usize,
+    /// the signal to send
+    pub signo: i32,
+    /// how to notify, one of `SIGEV_*`
+    pub notify: i32,
+    /// the thread to signal for `SIGEV_THREAD_ID`
+    pub tid: i32,
+    _pad: [u32; 11],
+}
+
+/// Where the signal of an [`IntervalTimer`] is sent.
+#[derive(Clone)]
+pub enum SignalTarget {
+    /// No signal is sent.
+    None,
+    /// Send the signal to the process.
+    Process(Weak<Process>, Signal),
+    /// Send the signal to the thread.
+    Thread(Weak<Thread>, Signal),
+}
+
+/// A POSIX interval timer, which sends a signal on each expiration.
+pub struct IntervalTimer {
+    clock: ClockId,
+    target: SignalTarget,
+    inner: Mutex<IntervalTimerInner>,
+}
+
+#[derive(Default)]
+struct IntervalTimerInner {
+    /// the next expiration on the monotonic clock, `None` if disarmed
+    deadline: Option<Duration>,
+    /// the period of the timer, zero for one-shot timers
+    interval: Duration,
+    /// number of expirations missed before the last signal
+    overrun: usize,
+    /// bumped on every `set_time` so that stale callbacks are ignored
+    generation: usize,
+}
+
+impl IntervalTimer {
+    /// Create a new disarmed timer on `clock`, sending signals to `target`.
+    pub fn new(clock: ClockId, target: SignalTarget) -> Arc<Self> {
+        Arc::new(IntervalTimer {
+            clock,
+            target,
+            inner: Mutex::new(IntervalTimerInner::default()),
+        })
+    }
+
+    /// Returns the current setting of the timer, with the time until the
+    /// next expiration.
+    pub fn get_time(&self) -> ITimerSpec {
+        let inner = self.inner.lock();
+        let value = match inner.deadline {
+            Some(deadline) => deadline.saturating_sub(timer::timer_now()).into(),
+            None => TimeSpec::default(),
+        };
+        ITimerSpec {
+            interval: inner.interval.into(),
+            value,
+        }
+    }
+
+    /// Arm or disarm the timer, returning the old setting.
+    ///
+    /// `new.value` is an absolute time on the clock of the timer if
+    /// `absolute`, otherwise relative to now.
+    pub fn set_time(self: &Arc<Self>, new: ITimerSpec, absolute: bool) -> ITimerSpec {
+        let old = self.get_time();
+        let value: Duration = new.value.into();
+        let mut inner = self.inner.lock();
+        inner.generation += 1;
+        inner.interval = new.interval.into();
+        inner.overrun = 0;
+        if value.is_zero() {
+            inner.deadline = None;
+            return old;
+        }
+        let deadline = match absolute {
+            true if self.clock.is_realtime() => realtime_to_monotonic(value),
+            true => value,
+            false => timer::deadline_after(value),
+        };
+        inner.deadline = Some(deadline);
+        Self::arm(Arc::downgrade(self), deadline, inner.generation);
+        old
+    }
+
+    /// Number of expirations missed before the last signal was sent.
+    pub fn overrun(&self) -> usize {
+        self.inner.lock().overrun
+    }
+
+    fn arm(timer: Weak<Self>, deadline: Duration, generation: usize) {
+        timer::timer_set(
+            deadline,
+            Box::new(move |now| {
+                if let Some(timer) = timer.upgrade() {
+                    timer.fire(now, generation);
+                }
+            }),
+        );
+    }
+
+    /// Called by HAL timer, to send the signal and re-arm periodic timers.
+    fn fire(self: &Arc<Self>, now: Duration, generation: usize) {
+        let mut inner = self.inner.lock();
+        if inner.generation != generation {
+            return;
+        }
+        let deadline = match inner.deadline {
+            Some(deadline) => deadline,
+            None => return,
+        };
+        if inner.interval.is_zero() {
+            inner.deadline = None;
+            inner.overrun = 0;
+        } else {
+            let missed =
+                (now.saturating_sub(deadline).as_nanos() / inner.interval.as_nanos()) as u32;
+            let next = deadline + inner.interval * (missed + 1);
+            inner.overrun = missed as usize;
+            inner.deadline = Some(next);
+            Self::arm(Arc::downgrade(self), next, generation);
+        }
+        drop(inner);
+        match &self.target {
+            SignalTarget::None => {}
+            SignalTarget::Process(proc, signal) => {
+                if let Some(proc) = proc.upgrade() {
+                    proc.send_signal(*signal);
+                }
+            }
+            SignalTarget::Thread(thread, signal) => {
+                if let Some(thread) = thread.upgrade() {
+                    thread.send_signal(*signal);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Syscalls of credentials
+//!
+//! - getuid, geteuid, getresuid, setuid, setreuid, setresuid, setfsuid
+//! - getgid, getegid, getresgid, setgid, setregid, setresgid, setfsgid
+//! - getgroups, setgroups
+//! - capget, capset
+
+use super::*;
+use alloc::vec::Vec;
+use linux_object::cred::{Capabilities, NGROUPS_MAX};
+
+/// `_LINUX_CAPABILITY_VERSION_1`, with 32-bit capability sets
+const CAP_VERSION_1: u32 = 0x1998_0330;
+/// `_LINUX_CAPABILITY_VERSION_2`, deprecated for the same layout as version 3
+const CAP_VERSION_2: u32 = 0x2007_1026;
+/// `_LINUX_CAPABILITY_VERSION_3`, with 64-bit capability sets
+const CAP_VERSION_3: u32 = 0x2008_0522;
+
+/// `struct __user_cap_header_struct`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+/// `struct __user_cap_data_struct`, the lower or the higher 32 bits of the
+/// capability sets
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Convert an ID argument, where -1 keeps the ID.
+fn optional_id(id: usize) -> Option<u32> {
+    if id as i32 == -1 {
+        None
+    } else {
+        Some(id as u32)
+    }
+}
+
+impl Syscall<'_> {
+    /// Returns the real user ID.
+    pub fn sys_getuid(&self) -> SysResult {
+        Ok(self.linux_process().cred().uid as usize)
+    }
+
+    /// Returns the effective user ID.
+    pub fn sys_geteuid(&self) -> SysResult {
+        Ok(self.linux_process().cred().euid as usize)
+    }
+
+    /// Returns the real group ID.
+    pub fn sys_getgid(&self) -> SysResult {
+        Ok(self.linux_process().cred().gid as usize)
+    }
+
+    /// Returns the effective group ID.
+    pub fn sys_getegid(&self) -> SysResult {
+        Ok(self.linux_process().cred().egid as usize)
+    }
+
+    /// Set the user IDs
+    /// (see [linux man setuid(2)](https://man7.org/linux/man-pages/man2/setuid.2.html)).
+    pub fn sys_setuid(&self, uid: usize) -> SysResult {
+        info!("setuid: uid={}", uid);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_uid(uid as u32)?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Set the real and the effective user IDs, -1 to keep the ID.
+    pub fn sys_setreuid(&self, ruid: usize, euid: usize) -> SysResult {
+        info!("setreuid: ruid={}, euid={}", ruid as i32, euid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_reuid(optional_id(ruid), optional_id(euid))?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Set the real, the effective and the saved user IDs, -1 to keep the ID.
+    pub fn sys_setresuid(&self, ruid: usize, euid: usize, suid: usize) -> SysResult {
+        info!(
+            "setresuid: ruid={}, euid={}, suid={}",
+            ruid as i32, euid as i32, suid as i32
+        );
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_resuid(optional_id(ruid), optional_id(euid), optional_id(suid))?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Get the real, the effective and the saved user IDs.
+    pub fn sys_getresuid(
+        &self,
+        mut ruid: UserOutPtr<u32>,
+        mut euid: UserOutPtr<u32>,
+        mut suid: UserOutPtr<u32>,
+    ) -> SysResult {
+        let cred = self.linux_process().cred();
+        ruid.write(cred.uid)?;
+        euid.write(cred.euid)?;
+        suid.write(cred.suid)?;
+        Ok(0)
+    }
+
+    /// Set the filesystem user ID, returns the previous one.
+    pub fn sys_setfsuid(&self, fsuid: usize) -> SysResult {
+        info!("setfsuid: fsuid={}", fsuid);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        let old = cred.set_fsuid(fsuid as u32);
+        proc.set_cred(cred);
+        Ok(old as usize)
+    }
+
+    /// Set the group IDs
+    /// (see [linux man setgid(2)](https://man7.org/linux/man-pages/man2/setgid.2.html)).
+    pub fn sys_setgid(&self, gid: usize) -> SysResult {
+        info!("setgid: gid={}", gid);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_gid(gid as u32)?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Set the real and the effective group IDs, -1 to keep the ID.
+    pub fn sys_setregid(&self, rgid: usize, egid: usize) -> SysResult {
+        info!("setregid: rgid={}, egid={}", rgid as i32, egid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_regid(optional_id(rgid), optional_id(egid))?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Set the real, the effective and the saved group IDs, -1 to keep the ID.
+    pub fn sys_setresgid(&self, rgid: usize, egid: usize, sgid: usize) -> SysResult {
+        info!(
+            "setresgid: rgid={}, egid={}, sgid={}",
+            rgid as i32, egid as i32, sgid as i32
+        );
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_resgid(optional_id(rgid), optional_id(egid), optional_id(sgid))?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Get the real, the effective and the saved group IDs.
+    pub fn sys_getresgid(
+        &self,
+        mut rgid: UserOutPtr<u32>,
+        mut egid: UserOutPtr<u32>,
+        mut sgid: UserOutPtr<u32>,
+    ) -> SysResult {
+        let cred = self.linux_process().cred();
+        rgid.write(cred.gid)?;
+        egid.write(cred.egid)?;
+        sgid.write(cred.sgid)?;
+        Ok(0)
+    }
+
+    /// Set the filesystem group ID, returns the previous one.
+    pub fn sys_setfsgid(&self, fsgid: usize) -> SysResult {
+        info!("setfsgid: fsgid={}", fsgid);
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        let old = cred.set_fsgid(fsgid as u32);
+        proc.set_cred(cred);
+        Ok(old as usize)
+    }
+
+    /// Get the supplementary group IDs, returns the number of them.
+    ///
+    /// If `size` is 0, only the number is returned.
+    pub fn sys_getgroups(&self, size: usize, mut list: UserOutPtr<u32>) -> SysResult {
+        let groups = self.linux_process().cred().groups;
+        if size == 0 {
+            return Ok(groups.len());
+        }
+        if size < groups.len() {
+            return Err(LxError::EINVAL);
+        }
+        list.write_array(&groups)?;
+        Ok(groups.len())
+    }
+
+    /// Set the supplementary group IDs, which requires `CAP_SETGID`.
+    pub fn sys_setgroups(&self, size: usize, list: UserInPtr<u32>) -> SysResult {
+        info!("setgroups: size={}, list={:?}", size, list);
+        if size > NGROUPS_MAX {
+            return Err(LxError::EINVAL);
+        }
+        let groups = if size == 0 {
+            Vec::new()
+        } else {
+            list.read_array(size)?
+        };
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_groups(groups)?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+
+    /// Get the capabilities of the calling process
+    /// (see [linux man capget(2)](https://man7.org/linux/man-pages/man2/capget.2.html)).
+    ///
+    /// Only the calling process can be queried.
+    pub fn sys_capget(
+        &self,
+        mut header: UserInOutPtr<CapUserHeader>,
+        mut data: UserOutPtr<CapUserData>,
+    ) -> SysResult {
+        let mut hdr = header.read()?;
+        info!("capget: header={:x?}", hdr);
+        let words = cap_words(&mut header, &mut hdr)?;
+        if hdr.pid != 0 && hdr.pid as KoID != self.zircon_process().id() {
+            return Err(LxError::ESRCH);
+        }
+        if data.is_null() {
+            return Ok(0);
+        }
+        let cred = self.linux_process().cred();
+        let sets = [
+            cred.cap_effective.bits(),
+            cred.cap_permitted.bits(),
+            cred.cap_inheritable.bits(),
+        ];
+        let mut buf = [CapUserData::default(); 2];
+        for (i, word) in buf.iter_mut().enumerate() {
+            *word = CapUserData {
+                effective: (sets[0] >> (32 * i)) as u32,
+                permitted: (sets[1] >> (32 * i)) as u32,
+                inheritable: (sets[2] >> (32 * i)) as u32,
+            };
+        }
+        data.write_array(&buf[..words])?;
+        Ok(0)
+    }
+
+    /// Set the capabilities of the calling process
+    /// (see [linux man capset(2)](https://man7.org/linux/man-pages/man2/capset.2.html)).
+    pub fn sys_capset(
+        &self,
+        mut header: UserInOutPtr<CapUserHeader>,
+        data: UserInPtr<CapUserData>,
+    ) -> SysResult {
+        let mut hdr = header.read()?;
+        info!("capset: header={:x?}", hdr);
+        let words = cap_words(&mut header, &mut hdr)?;
+        if hdr.pid != 0 && hdr.pid as KoID != self.zircon_process().id() {
+            return Err(LxError::EPERM);
+        }
+        let buf = data.read_array(words)?;
+        let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+        for (i, word) in buf.iter().enumerate() {
+            effective |= (word.effective as u64) << (32 * i);
+            permitted |= (word.permitted as u64) << (32 * i);
+            inheritable |= (word.inheritable as u64) << (32 * i);
+        }
+        let proc = self.linux_process();
+        let mut cred = proc.cred();
+        cred.set_caps(
+            Capabilities::from_bits_truncate(effective),
+            Capabilities::from_bits_truncate(permitted),
+            Capabilities::from_bits_truncate(inheritable),
+        )?;
+        proc.set_cred(cred);
+        Ok(0)
+    }
+}
+
+/// Returns the number of [`CapUserData`] of the version in the header.
+///
+/// On an unknown version, the preferred one is written back to the header.
+fn cap_words(header: &mut UserInOutPtr<CapUserHeader>, hdr: &mut CapUserHeader) -> LxResult<usize> {
+    match hdr.version {
+        CAP_VERSION_1 => Ok(1),
+        CAP_VERSION_2 | CAP_VERSION_3 => Ok(2),
+        _ => {
+            hdr.version = CAP_VERSION_3;
+            header.write(*hdr)?;
+            Err(LxError::EINVAL)
+        }
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! - getcwd
 //! - chdir
+//! - chroot
 //! - mkdir(at)
+//! - mknod(at)
 //! - rmdir(at)
 //! - getdents64
 //! - link(at)
@@ -13,7 +15,8 @@
 use super::*;
 use bitflags::bitflags;
 use kernel_hal::user::UserOutPtr;
-use linux_object::fs::vfs::FileType;
+use linux_object::cred::{Capabilities, W_OK, X_OK};
+use linux_object::fs::vfs::{FileType, INode};
 
 impl Syscall<'_> {
     /// return a null-terminated string containing an absolute pathname
@@ -43,10 +46,35 @@ impl Syscall<'_> {
         if info.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
+        proc.check_access(&inode, X_OK)?;
         proc.change_directory(path);
         Ok(0)
     }
 
+    /// Change the root directory
+    /// (see [linux man chroot(2)](https://man7.org/linux/man-pages/man2/chroot.2.html)),
+    /// which requires `CAP_SYS_CHROOT`.
+    ///
+    /// The absolute paths, and `..` of the new root, are resolved from the
+    /// new root afterwards, and the working directory moves to the new root
+    /// unless it is under it already.
+    pub fn sys_chroot(&self, path: UserInPtr<u8>) -> SysResult {
+        let path = path.as_c_str()?;
+        info!("chroot: path={:?}", path);
+
+        let proc = self.linux_process();
+        let (inode, ns_path) = proc.lookup_ns_path(path, true)?;
+        if inode.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        proc.check_access(&inode, X_OK)?;
+        if !proc.cred().has_cap(Capabilities::SYS_CHROOT) {
+            return Err(LxError::EPERM);
+        }
+        proc.change_root(&ns_path);
+        Ok(0)
+    }
+
     /// Make a directory.
     /// - path – pointer to string with directory name
     /// - mode – file system permissions mode
@@ -69,9 +97,60 @@ impl Syscall<'_> {
         if inode.find(file_name).is_ok() {
             return Err(LxError::EEXIST);
         }
-        inode.create(file_name, FileType::Dir, mode as u32)?;
+        proc.create_inode(&inode, file_name, FileType::Dir, mode as u32)?;
+        Ok(0)
+    }
+    /// create a special or ordinary file
+    pub fn sys_mknod(&self, path: UserInPtr<u8>, mode: usize, dev: usize) -> SysResult {
+        self.sys_mknodat(FileDesc::CWD, path, mode, dev)
+    }
+
+    /// create a special or ordinary file relative to directory file descriptor
+    ///
+    /// The type of the file is given by `mode`: a regular file, a FIFO, a
+    /// socket, or a character or block device numbered `dev`, which needs
+    /// `CAP_MKNOD`.
+    pub fn sys_mknodat(
+        &self,
+        dirfd: FileDesc,
+        path: UserInPtr<u8>,
+        mode: usize,
+        dev: usize,
+    ) -> SysResult {
+        const S_IFMT: usize = 0o170_000;
+        const S_IFIFO: usize = 0o010_000;
+        const S_IFCHR: usize = 0o020_000;
+        const S_IFDIR: usize = 0o040_000;
+        const S_IFBLK: usize = 0o060_000;
+        const S_IFREG: usize = 0o100_000;
+        const S_IFSOCK: usize = 0o140_000;
+        let path = path.as_c_str()?;
+        info!(
+            "mknodat: dirfd={:?}, path={:?}, mode={:#o}, dev={:#x}",
+            dirfd, path, mode, dev
+        );
+        let proc = self.linux_process();
+        let (type_, rdev) = match mode & S_IFMT {
+            0 | S_IFREG => (FileType::File, 0),
+            S_IFIFO => (FileType::NamedPipe, 0),
+            S_IFSOCK => (FileType::Socket, 0),
+            S_IFCHR | S_IFBLK if !proc.cred().has_cap(Capabilities::MKNOD) => {
+                return Err(LxError::EPERM)
+            }
+            S_IFCHR => (FileType::CharDevice, dev),
+            S_IFBLK => (FileType::BlockDevice, dev),
+            S_IFDIR => return Err(LxError::EPERM),
+            _ => return Err(LxError::EINVAL),
+        };
+        let (dir_path, file_name) = split_path(path);
+        let inode = proc.lookup_inode_at(dirfd, dir_path, true)?;
+        if inode.find(file_name).is_ok() {
+            return Err(LxError::EEXIST);
+        }
+        proc.create_node(&inode, file_name, type_, (mode & 0o7777) as u32, rdev)?;
         Ok(0)
     }
+
     /// Remove a directory.
     /// - path – pointer to string with directory name
     pub fn sys_rmdir(&self, path: UserInPtr<u8>) -> SysResult {
@@ -85,7 +164,9 @@ impl Syscall<'_> {
         if file_inode.metadata()?.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
+        proc.check_remove(&dir_inode, &file_inode)?;
         dir_inode.unlink(file_name)?;
+        after_unlink(&dir_inode, file_name, &file_inode)?;
         Ok(0)
     }
 
@@ -153,7 +234,10 @@ impl Syscall<'_> {
         let (new_dir_path, new_file_name) = split_path(newpath);
         let inode = proc.lookup_inode_at(olddirfd, oldpath, true)?;
         let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, true)?;
+        proc.check_access(&new_dir_inode, W_OK | X_OK)?;
         new_dir_inode.link(new_file_name, &inode)?;
+        inotify_event(&*new_dir_inode, InotifyMask::CREATE, 0, new_file_name);
+        inotify_event(&*inode, InotifyMask::ATTRIB, 0, "");
         Ok(0)
     }
 
@@ -169,12 +253,6 @@ impl Syscall<'_> {
     /// The unlinkat() system call operates in exactly the same way as either unlink or rmdir.
     pub fn sys_unlinkat(&self, dirfd: FileDesc, path: UserInPtr<u8>, flags: usize) -> SysResult {
         let path = path.as_c_str()?;
-        // hard code special path
-        let path = if path == "/dev/shm/testshm" {
-            "/testshm"
-        } else {
-            path
-        };
         let flags = AtFlags::from_bits_truncate(flags);
         info!(
             "unlinkat: dirfd={:?}, path={:?}, flags={:?}",
@@ -188,7 +266,9 @@ impl Syscall<'_> {
         if file_inode.metadata()?.type_ == FileType::Dir {
             return Err(LxError::EISDIR);
         }
+        proc.check_remove(&dir_inode, &file_inode)?;
         dir_inode.unlink(file_name)?;
+        after_unlink(&dir_inode, file_name, &file_inode)?;
         Ok(0)
     }
 
@@ -217,7 +297,29 @@ impl Syscall<'_> {
         let (new_dir_path, new_file_name) = split_path(newpath);
         let old_dir_inode = proc.lookup_inode_at(olddirfd, old_dir_path, false)?;
         let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, false)?;
+        let inode = old_dir_inode.find(old_file_name)?;
+        proc.check_remove(&old_dir_inode, &inode)?;
+        match new_dir_inode.find(new_file_name) {
+            Ok(target) => proc.check_remove(&new_dir_inode, &target)?,
+            Err(_) => proc.check_access(&new_dir_inode, W_OK | X_OK)?,
+        }
         old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name)?;
+        let cookie = inotify_cookie();
+        let mut isdir = InotifyMask::empty();
+        isdir.set(InotifyMask::ISDIR, inode.metadata()?.type_ == FileType::Dir);
+        inotify_event(
+            &*old_dir_inode,
+            InotifyMask::MOVED_FROM | isdir,
+            cookie,
+            old_file_name,
+        );
+        inotify_event(
+            &*new_dir_inode,
+            InotifyMask::MOVED_TO | isdir,
+            cookie,
+            new_file_name,
+        );
+        inotify_event(&*inode, InotifyMask::MOVE_SELF, 0, "");
         Ok(0)
     }
 
@@ -255,6 +357,22 @@ impl Syscall<'_> {
     }
 }
 
+/// Report the removal of the entry `name` of `inode` from `dir`, and drop
+/// the extended attributes of `inode` if it has no other links.
+fn after_unlink(dir: &Arc<dyn INode>, name: &str, inode: &Arc<dyn INode>) -> LxResult {
+    let info = inode.metadata()?;
+    let is_dir = info.type_ == FileType::Dir;
+    let mut mask = InotifyMask::DELETE;
+    mask.set(InotifyMask::ISDIR, is_dir);
+    inotify_event(&**dir, mask, 0, name);
+    // a removed directory is empty, and gone with its `.` link
+    if info.nlinks == 0 || is_dir {
+        inotify_event(&**inode, InotifyMask::DELETE_SELF, 0, "");
+        drop_xattrs(&**inode);
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 #[repr(packed)] // Don't use 'C'. Or its size will align up to 8 bytes.
 pub struct LinuxDirent64 {
@@ -360,5 +478,6 @@ bitflags! {
     pub struct AtFlags: usize {
         const EMPTY_PATH = 0x1000;
         const SYMLINK_NOFOLLOW = 0x100;
+        const EACCESS = 0x200;
     }
 }
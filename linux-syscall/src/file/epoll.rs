@@ -0,0 +1,106 @@
+//! Syscalls of epoll
+//!
+//! - epoll_create, epoll_create1
+//! - epoll_ctl
+//! - epoll_wait, epoll_pwait
+
+use super::poll::wait_with_sigmask;
+use super::*;
+use linux_object::signal::Sigset;
+
+impl Syscall<'_> {
+    /// Open an epoll file descriptor.
+    ///
+    /// `size` is ignored but must be greater than zero.
+    pub fn sys_epoll_create(&self, size: usize) -> SysResult {
+        info!("epoll_create: size={}", size as isize);
+        if (size as isize) <= 0 {
+            return Err(LxError::EINVAL);
+        }
+        self.sys_epoll_create1(0)
+    }
+
+    /// Open an epoll file descriptor, `flags` may contain `EPOLL_CLOEXEC`.
+    pub fn sys_epoll_create1(&self, flags: usize) -> SysResult {
+        info!("epoll_create1: flags={:#x}", flags);
+        let flags = OpenFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if !(flags - OpenFlags::CLOEXEC).is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        let epoll = EpollInstance::new(flags | OpenFlags::RDWR);
+        let fd = self.linux_process().add_file(epoll)?;
+        Ok(fd.into())
+    }
+
+    /// Add, modify, or remove entries in the interest list of the epoll instance.
+    pub fn sys_epoll_ctl(
+        &self,
+        epfd: FileDesc,
+        op: usize,
+        fd: FileDesc,
+        event: UserInPtr<EpollEvent>,
+    ) -> SysResult {
+        let op = EpollCtlOp::from_raw(op)?;
+        info!(
+            "epoll_ctl: epfd={:?}, op={:?}, fd={:?}, event={:?}",
+            epfd, op, fd, event
+        );
+        let proc = self.linux_process();
+        let epoll = proc
+            .get_file_like(epfd)?
+            .downcast_arc::<EpollInstance>()
+            .map_err(|_| LxError::EINVAL)?;
+        let file = proc.get_file_like(fd)?;
+        let event = match op {
+            EpollCtlOp::Delete => EpollEvent::default(),
+            _ => event.read()?,
+        };
+        epoll.control(op, fd, file, event)?;
+        Ok(0)
+    }
+
+    /// Wait for an I/O event on an epoll file descriptor.
+    pub async fn sys_epoll_wait(
+        &self,
+        epfd: FileDesc,
+        events: UserOutPtr<EpollEvent>,
+        maxevents: usize,
+        timeout: isize,
+    ) -> SysResult {
+        self.sys_epoll_pwait(epfd, events, maxevents, timeout, 0.into(), 0)
+            .await
+    }
+
+    /// Wait for an I/O event on an epoll file descriptor,
+    /// with the signal mask temporarily replaced by `sigmask`.
+    pub async fn sys_epoll_pwait(
+        &self,
+        epfd: FileDesc,
+        mut events: UserOutPtr<EpollEvent>,
+        maxevents: usize,
+        timeout: isize,
+        sigmask: UserInPtr<Sigset>,
+        sigsetsize: usize,
+    ) -> SysResult {
+        info!(
+            "epoll_pwait: epfd={:?}, events={:?}, maxevents={}, timeout={}, sigmask={:?}",
+            epfd, events, maxevents, timeout as i32, sigmask
+        );
+        if maxevents as i32 <= 0 {
+            return Err(LxError::EINVAL);
+        }
+        let epoll = self
+            .linux_process()
+            .get_file_like(epfd)?
+            .downcast_arc::<EpollInstance>()
+            .map_err(|_| LxError::EINVAL)?;
+        let sigmask = sigmask.read_if_not_null()?;
+        if sigmask.is_some() && sigsetsize != core::mem::size_of::<Sigset>() {
+            return Err(LxError::EINVAL);
+        }
+        let wait = epoll.wait(maxevents, timeout as i32 as isize);
+        let ready = wait_with_sigmask(self.thread, sigmask, wait).await?;
+        events.write_array(&ready)?;
+        Ok(ready.len())
+    }
+}
@@ -3,19 +3,27 @@
 //! - open(at)
 //! - close
 //! - dup2
+//! - flock
 //! - pipe
+//! - eventfd
+//! - inotify_init1, inotify_add_watch, inotify_rm_watch
 
 use super::*;
 use alloc::string::String;
+use linux_object::cred::{R_OK, W_OK, X_OK};
+use linux_object::thread::interruptible;
 
 impl Syscall<'_> {
     /// Opens or creates a file, depending on the flags passed to the call. Returns an integer with the file descriptor.
-    pub fn sys_open(&self, path: UserInPtr<u8>, flags: usize, mode: usize) -> SysResult {
-        self.sys_openat(FileDesc::CWD, path, flags, mode)
+    pub async fn sys_open(&self, path: UserInPtr<u8>, flags: usize, mode: usize) -> SysResult {
+        self.sys_openat(FileDesc::CWD, path, flags, mode).await
     }
 
     /// open file relative to directory file descriptor
-    pub fn sys_openat(
+    ///
+    /// Opening a FIFO waits for its other end, see [`Pipe::open_fifo`], which
+    /// a signal interrupts with `EINTR`.
+    pub async fn sys_openat(
         &self,
         dir_fd: FileDesc,
         path: UserInPtr<u8>,
@@ -24,19 +32,26 @@ impl Syscall<'_> {
     ) -> SysResult {
         let proc = self.linux_process();
         let path = path.as_c_str()?;
-        // hard code special path
-        let path = if path == "/dev/shm/testshm" {
-            "/testshm"
-        } else {
-            path
-        };
         let flags = OpenFlags::from_bits_truncate(flags);
         info!(
             "openat: dir_fd={:?}, path={:?}, flags={:?}, mode={:#o}",
             dir_fd, path, flags, mode
         );
 
-        let inode = if flags.contains(OpenFlags::CREATE) {
+        let inode = if flags.contains(OpenFlags::TMPFILE) {
+            if !flags.writable() {
+                return Err(LxError::EINVAL);
+            }
+            let dir_inode = proc.lookup_inode_at(dir_fd, path, true)?;
+            proc.check_access(&dir_inode, W_OK | X_OK)?;
+            let mode = mode as u32 & !proc.umask();
+            let inode = TmpFS::create_tmpfile(&dir_inode, mode).map_err(|e| match e {
+                FsError::NotSupported => LxError::EOPNOTSUPP,
+                e => LxError::from(e),
+            })?;
+            proc.init_owner(&dir_inode, &inode)?;
+            inode
+        } else if flags.contains(OpenFlags::CREATE) {
             let (dir_path, file_name) = split_path(path);
             // relative to cwd
             let dir_inode = proc.lookup_inode_at(dir_fd, dir_path, true)?;
@@ -45,15 +60,35 @@ impl Syscall<'_> {
                     if flags.contains(OpenFlags::EXCLUSIVE) {
                         return Err(LxError::EEXIST);
                     }
+                    proc.check_access(&file_inode, open_access(flags))?;
                     file_inode
                 }
                 Err(FsError::EntryNotFound) => {
-                    dir_inode.create(file_name, FileType::File, mode as u32)?
+                    proc.create_inode(&dir_inode, file_name, FileType::File, mode as u32)?
                 }
                 Err(e) => return Err(LxError::from(e)),
             }
         } else {
-            proc.lookup_inode_at(dir_fd, path, true)?
+            let inode = proc.lookup_inode_at(dir_fd, path, true)?;
+            proc.check_access(&inode, open_access(flags))?;
+            inode
+        };
+        if flags.contains(OpenFlags::TRUNCATE)
+            && flags.writable()
+            && inode.metadata()?.type_ == FileType::File
+        {
+            resize_inode(&inode, 0)?;
+        }
+        // opening `/dev/ptmx` allocates a new pseudo-terminal pair
+        // and opening `/dev/kmsg` creates a new reader of the kernel log
+        let inode = if let Some(ptmx) = inode.downcast_ref::<Ptmx>() {
+            ptmx.open()?
+        } else if let Some(kmsg) = inode.downcast_ref::<Kmsg>() {
+            kmsg.open()?
+        } else if inode.metadata()?.type_ == FileType::NamedPipe {
+            Arc::new(interruptible(self.thread, Pipe::open_fifo(inode, flags)).await?)
+        } else {
+            inode
         };
         let file = File::new(inode, flags, path.into());
         let fd = proc.add_file(file)?;
@@ -63,8 +98,7 @@ impl Syscall<'_> {
     /// Closes a file descriptor, so that it no longer refers to any file and may be reused.
     pub fn sys_close(&self, fd: FileDesc) -> SysResult {
         info!("close: fd={:?}", fd);
-        let proc = self.linux_process();
-        proc.close_file(fd)?;
+        self.linux_process().close_file(fd)?;
         Ok(0)
     }
 
@@ -72,10 +106,12 @@ impl Syscall<'_> {
     pub fn sys_dup2(&self, fd1: FileDesc, fd2: FileDesc) -> SysResult {
         info!("dup2: from {:?} to {:?}", fd1, fd2);
         let proc = self.linux_process();
-        // close fd2 first if it is opened
-        let _ = proc.close_file(fd2);
-        let file_like = proc.get_file_like(fd1)?.dup();
-        let fd2 = proc.add_file_at(fd2, file_like)?;
+        let file_like = proc.get_file_like(fd1)?;
+        if fd1 == fd2 {
+            return Ok(fd2.into());
+        }
+        // the file of fd2 is closed if it is opened
+        let fd2 = proc.add_file_at(fd2, file_like.dup())?;
         Ok(fd2.into())
     }
 
@@ -97,11 +133,14 @@ impl Syscall<'_> {
     pub fn sys_pipe2(&self, mut fds: UserOutPtr<[i32; 2]>, flags: usize) -> SysResult {
         info!("pipe2: fds={:?}, flags: {:#x}", fds, flags);
 
+        let base_flags = OpenFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if !(base_flags - OpenFlags::NON_BLOCK - OpenFlags::CLOEXEC - OpenFlags::DIRECT).is_empty()
+        {
+            return Err(LxError::EINVAL);
+        }
         let proc = self.linux_process();
-        let (read, write) = Pipe::create_pair();
+        let (read, write) = Pipe::create_pair_with(base_flags.contains(OpenFlags::DIRECT));
 
-        let base_flags =
-            OpenFlags::from_bits_truncate(flags) & (OpenFlags::NON_BLOCK | OpenFlags::CLOEXEC);
         let read_fd = proc.add_file(File::new(
             Arc::new(read),
             base_flags | OpenFlags::RDONLY,
@@ -123,9 +162,71 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// Create an inotify instance, returns its file descriptor
+    /// (see [linux man inotify_init(2)](https://man7.org/linux/man-pages/man2/inotify_init.2.html)).
+    pub fn sys_inotify_init1(&self, flags: usize) -> SysResult {
+        let flags = InotifyFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("inotify_init1: flags={:?}", flags);
+        let fd = self.linux_process().add_file(Inotify::new(flags))?;
+        Ok(fd.into())
+    }
+
+    /// Watch the events in `mask` on the file of `path`, returns the watch
+    /// descriptor.
+    pub fn sys_inotify_add_watch(
+        &self,
+        fd: FileDesc,
+        path: UserInPtr<u8>,
+        mask: usize,
+    ) -> SysResult {
+        let path = path.as_c_str()?;
+        let mask = InotifyMask::from_bits_truncate(mask as u32);
+        info!(
+            "inotify_add_watch: fd={:?}, path={:?}, mask={:?}",
+            fd, path, mask
+        );
+        let proc = self.linux_process();
+        let inotify = proc
+            .get_file_like(fd)?
+            .downcast_arc::<Inotify>()
+            .map_err(|_| LxError::EINVAL)?;
+        let follow = !mask.contains(InotifyMask::DONT_FOLLOW);
+        let inode = proc.lookup_inode_at(FileDesc::CWD, path, follow)?;
+        if mask.contains(InotifyMask::ONLYDIR) && inode.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        proc.check_access(&inode, R_OK)?;
+        let wd = inotify.add_watch(&*inode, mask)?;
+        Ok(wd as usize)
+    }
+
+    /// Remove the watch `wd` of an inotify instance.
+    pub fn sys_inotify_rm_watch(&self, fd: FileDesc, wd: usize) -> SysResult {
+        info!("inotify_rm_watch: fd={:?}, wd={}", fd, wd as i32);
+        let inotify = self
+            .linux_process()
+            .get_file_like(fd)?
+            .downcast_arc::<Inotify>()
+            .map_err(|_| LxError::EINVAL)?;
+        inotify.rm_watch(wd as i32)?;
+        Ok(0)
+    }
+
+    /// Create a file descriptor for event notification.
+    pub fn sys_eventfd2(&self, initval: usize, flags: usize) -> SysResult {
+        let flags = EventFdFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("eventfd2: initval={}, flags={:?}", initval, flags);
+        let eventfd = EventFd::new(initval as u32 as u64, flags);
+        let fd = self.linux_process().add_file(eventfd)?;
+        Ok(fd.into())
+    }
+
     /// apply or remove an advisory lock on an open file
-    /// TODO: handle operation
-    pub fn sys_flock(&mut self, fd: FileDesc, operation: usize) -> SysResult {
+    /// (see [linux man flock(2)](https://man7.org/linux/man-pages/man2/flock.2.html)).
+    ///
+    /// The lock is shared by the duplicates of the descriptor, and released
+    /// when the last of them is closed.
+    pub async fn sys_flock(&mut self, fd: FileDesc, operation: usize) -> SysResult {
         bitflags! {
             struct Operation: u8 {
                 const LOCK_SH = 1;
@@ -134,11 +235,30 @@ impl Syscall<'_> {
                 const LOCK_UN = 8;
             }
         }
-        let operation = Operation::from_bits(operation as u8).unwrap();
+        let operation = Operation::from_bits(operation as u8).ok_or(LxError::EINVAL)?;
         info!("flock: fd: {:?}, operation: {:?}", fd, operation);
         let proc = self.linux_process();
-
-        proc.get_file(fd)?;
+        let file = proc.get_file(fd)?;
+        let kind = match operation - Operation::LOCK_NB {
+            Operation::LOCK_SH => Some(LockKind::Shared),
+            Operation::LOCK_EX => Some(LockKind::Exclusive),
+            Operation::LOCK_UN => None,
+            _ => return Err(LxError::EINVAL),
+        };
+        file.flock(kind, !operation.contains(Operation::LOCK_NB))
+            .await?;
         Ok(0)
     }
 }
+
+/// Returns the access to check on opening a file with `flags`.
+fn open_access(flags: OpenFlags) -> usize {
+    let mut mask = 0;
+    if flags.readable() {
+        mask |= R_OK;
+    }
+    if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+        mask |= W_OK;
+    }
+    mask
+}
@@ -1,17 +1,120 @@
 //! File operations
 //!
-//! - read, pread, readv
-//! - write, pwrite, writev
+//! - read, pread, readv, preadv, preadv2
+//! - write, pwrite, writev, pwritev, pwritev2
 //! - lseek
 //! - truncate, ftruncate
-//! - sendfile, copy_file_range
+//! - mount, umount2 (with bind mounts), pivot_root, statfs, fstatfs
 //! - sync, fsync, fdatasync
-//! - ioctl, fcntl
+//! - ioctl, fcntl (with record locks)
 //! - access, faccessat
+//! - umask, chmod, fchmod, fchmodat
+//! - chown, lchown, fchown, fchownat
 
 use super::*;
+use linux_object::cred::{Capabilities, S_ISGID, S_ISUID, W_OK};
+use linux_object::fs::vfs::INode;
 use linux_object::{process::FsInfo, time::TimeSpec};
 
+/// The largest number of buffers of a vectored IO
+const IOV_MAX: usize = 1024;
+
+bitflags! {
+    /// Flags of `preadv2` and `pwritev2`
+    struct RwfFlags: usize {
+        /// high priority request, poll if possible
+        const HIPRI = 0x1;
+        /// per-IO O_DSYNC
+        const DSYNC = 0x2;
+        /// per-IO O_SYNC
+        const SYNC = 0x4;
+        /// per-IO, return EAGAIN if the operation would block
+        const NOWAIT = 0x8;
+        /// per-IO O_APPEND
+        const APPEND = 0x10;
+    }
+}
+
+/// Returns the offset of `preadv2` or `pwritev2`, or `None` for -1, the file offset.
+fn rwf_offset(offset: u64) -> LxResult<Option<u64>> {
+    match offset as i64 {
+        -1 => Ok(None),
+        x if x < 0 => Err(LxError::EINVAL),
+        _ => Ok(Some(offset)),
+    }
+}
+
+/// Read into the buffers described by `iov_count` iovecs at `iov_ptr`, at
+/// `offset` or the file offset if `None`.
+async fn read_vectored(
+    file_like: &Arc<dyn FileLike>,
+    iov_ptr: UserInPtr<IoVecOut>,
+    iov_count: usize,
+    offset: Option<u64>,
+    flags: RwfFlags,
+) -> SysResult {
+    if iov_count > IOV_MAX {
+        return Err(LxError::EINVAL);
+    }
+    let mut iovs = iov_ptr.read_iovecs(iov_count)?;
+    if iovs.total_len() > isize::MAX as usize {
+        return Err(LxError::EINVAL);
+    }
+    if flags.contains(RwfFlags::NOWAIT) && !file_like.poll(PollEvents::IN)?.read {
+        return Err(LxError::EAGAIN);
+    }
+    let mut buf = vec![0u8; iovs.total_len()];
+    let len = match offset {
+        Some(offset) => file_like.read_at(offset, &mut buf).await?,
+        None => file_like.read(&mut buf).await?,
+    };
+    iovs.write_from_buf(&buf[..len])?;
+    Ok(len)
+}
+
+/// Write the buffers described by `iov_count` iovecs at `iov_ptr`, at
+/// `offset` or the file offset if `None`.
+async fn write_vectored(
+    file_like: &Arc<dyn FileLike>,
+    iov_ptr: UserInPtr<IoVecIn>,
+    iov_count: usize,
+    offset: Option<u64>,
+    flags: RwfFlags,
+) -> SysResult {
+    if iov_count > IOV_MAX {
+        return Err(LxError::EINVAL);
+    }
+    let iovs = iov_ptr.read_iovecs(iov_count)?;
+    if iovs.total_len() > isize::MAX as usize {
+        return Err(LxError::EINVAL);
+    }
+    if flags.contains(RwfFlags::NOWAIT) && !file_like.poll(PollEvents::OUT)?.write {
+        return Err(LxError::EAGAIN);
+    }
+    let buf = iovs.read_to_vec()?;
+    let file = file_like.clone().downcast_arc::<File>().ok();
+    let len = match (offset, &file) {
+        // the offset is ignored, and the file offset is not changed
+        (Some(_), Some(file)) if flags.contains(RwfFlags::APPEND) => {
+            file.write_at(file.metadata()?.size as u64, &buf)?
+        }
+        (None, Some(file)) if flags.contains(RwfFlags::APPEND) => {
+            file.seek(SeekFrom::End(0))?;
+            file.write(&buf)?
+        }
+        (Some(offset), _) => file_like.write_at(offset, &buf)?,
+        (None, _) => write_wait(file_like, &buf).await?,
+    };
+    if let Some(file) = &file {
+        if flags.contains(RwfFlags::SYNC) {
+            file.sync_all()?;
+        } else if flags.contains(RwfFlags::DSYNC) {
+            file.sync_data()?;
+        }
+    }
+    Ok(len)
+}
+
 impl Syscall<'_> {
     /// Reads from a specified file using a file descriptor. Before using this call,
     /// you must first obtain a file descriptor using the opensyscall. Returns bytes read successfully.
@@ -33,11 +136,10 @@ impl Syscall<'_> {
     /// - fd – file descriptor
     /// - base – pointer to the buffer write
     /// - len – number of bytes to write
-    pub fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
+    pub async fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
         info!("write: fd={:?}, base={:?}, len={:#x}", fd, base, len);
-        self.linux_process()
-            .get_file_like(fd)?
-            .write(base.as_slice(len)?)
+        let file_like = self.linux_process().get_file_like(fd)?;
+        write_wait(&file_like, base.as_slice(len)?).await
     }
 
     /// read from or write to a file descriptor at a given offset
@@ -90,19 +192,14 @@ impl Syscall<'_> {
         iov_count: usize,
     ) -> SysResult {
         info!("readv: fd={:?}, iov={:?}, count={}", fd, iov_ptr, iov_count);
-        let mut iovs = iov_ptr.read_iovecs(iov_count)?;
-        let proc = self.linux_process();
-        let file_like = proc.get_file_like(fd)?;
-        let mut buf = vec![0u8; iovs.total_len()];
-        let len = file_like.read(&mut buf).await?;
-        iovs.write_from_buf(&buf)?;
-        Ok(len)
+        let file_like = self.linux_process().get_file_like(fd)?;
+        read_vectored(&file_like, iov_ptr, iov_count, None, RwfFlags::empty()).await
     }
 
     /// works just like write except that multiple buffers are written out.
     /// writes iov_count buffers of data described
     /// by iov to the file associated with the file descriptor fd ("gather output").
-    pub fn sys_writev(
+    pub async fn sys_writev(
         &self,
         fd: FileDesc,
         iov_ptr: UserInPtr<IoVecIn>,
@@ -112,12 +209,73 @@ impl Syscall<'_> {
             "writev: fd={:?}, iov={:?}, count={}",
             fd, iov_ptr, iov_count
         );
-        let iovs = iov_ptr.read_iovecs(iov_count)?;
-        let buf = iovs.read_to_vec()?;
-        let proc = self.linux_process();
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(&buf)?;
-        Ok(len)
+        let file_like = self.linux_process().get_file_like(fd)?;
+        write_vectored(&file_like, iov_ptr, iov_count, None, RwfFlags::empty()).await
+    }
+
+    /// like readv, but reads at `offset` of the file, and the file offset is not changed.
+    pub async fn sys_preadv(
+        &self,
+        fd: FileDesc,
+        iov_ptr: UserInPtr<IoVecOut>,
+        iov_count: usize,
+        offset: u64,
+    ) -> SysResult {
+        self.sys_preadv2(fd, iov_ptr, iov_count, offset, 0).await
+    }
+
+    /// like writev, but writes at `offset` of the file, and the file offset is not changed.
+    pub async fn sys_pwritev(
+        &self,
+        fd: FileDesc,
+        iov_ptr: UserInPtr<IoVecIn>,
+        iov_count: usize,
+        offset: u64,
+    ) -> SysResult {
+        self.sys_pwritev2(fd, iov_ptr, iov_count, offset, 0).await
+    }
+
+    /// like preadv, with `RWF_*` flags of the call.
+    ///
+    /// An `offset` of -1 reads at the file offset, updating it like readv.
+    pub async fn sys_preadv2(
+        &self,
+        fd: FileDesc,
+        iov_ptr: UserInPtr<IoVecOut>,
+        iov_count: usize,
+        offset: u64,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "preadv2: fd={:?}, iov={:?}, count={}, offset={}, flags={:#x}",
+            fd, iov_ptr, iov_count, offset as i64, flags
+        );
+        let flags = RwfFlags::from_bits(flags).ok_or(LxError::EOPNOTSUPP)?;
+        let offset = rwf_offset(offset)?;
+        let file_like = self.linux_process().get_file_like(fd)?;
+        read_vectored(&file_like, iov_ptr, iov_count, offset, flags).await
+    }
+
+    /// like pwritev, with `RWF_*` flags of the call.
+    ///
+    /// An `offset` of -1 writes at the file offset, updating it like writev.
+    /// With `RWF_APPEND`, the data is always appended to the end of the file.
+    pub async fn sys_pwritev2(
+        &self,
+        fd: FileDesc,
+        iov_ptr: UserInPtr<IoVecIn>,
+        iov_count: usize,
+        offset: u64,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "pwritev2: fd={:?}, iov={:?}, count={}, offset={}, flags={:#x}",
+            fd, iov_ptr, iov_count, offset as i64, flags
+        );
+        let flags = RwfFlags::from_bits(flags).ok_or(LxError::EOPNOTSUPP)?;
+        let offset = rwf_offset(offset)?;
+        let file_like = self.linux_process().get_file_like(fd)?;
+        write_vectored(&file_like, iov_ptr, iov_count, offset, flags).await
     }
 
     /// repositions the offset of the open file associated with the file descriptor fd
@@ -145,7 +303,10 @@ impl Syscall<'_> {
     pub fn sys_truncate(&self, path: UserInPtr<u8>, len: usize) -> SysResult {
         let path = path.as_c_str()?;
         info!("truncate: path={:?}, len={}", path, len);
-        self.linux_process().lookup_inode(path)?.resize(len)?;
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode(path)?;
+        proc.check_access(&inode, W_OK)?;
+        resize_inode(&inode, len)?;
         Ok(0)
     }
 
@@ -157,101 +318,12 @@ impl Syscall<'_> {
         Ok(0)
     }
 
-    /// copies data between one file descriptor and another.
-    pub async fn sys_sendfile(
-        &self,
-        out_fd: FileDesc,
-        in_fd: FileDesc,
-        offset_ptr: UserInOutPtr<u64>,
-        count: usize,
-    ) -> SysResult {
-        self.sys_copy_file_range(in_fd, offset_ptr, out_fd, 0.into(), count, 0)
-            .await
-    }
-
-    /// copies data between one file descriptor and anothe, read from specified offset and write new offset back
-    pub async fn sys_copy_file_range(
-        &self,
-        in_fd: FileDesc,
-        mut in_offset: UserInOutPtr<u64>,
-        out_fd: FileDesc,
-        mut out_offset: UserInOutPtr<u64>,
-        count: usize,
-        flags: usize,
-    ) -> SysResult {
-        info!(
-            "copy_file_range: in={:?}, out={:?}, in_offset={:?}, out_offset={:?}, count={}, flags={}",
-            in_fd, out_fd, in_offset, out_offset, count, flags
-        );
-        let proc = self.linux_process();
-        let in_file = proc.get_file(in_fd)?;
-        let out_file = proc.get_file(out_fd)?;
-        let mut buffer = [0u8; 1024];
-
-        // for in_offset and out_offset
-        // null means update file offset
-        // non-null means update {in,out}_offset instead
-
-        let mut read_offset = if !in_offset.is_null() {
-            in_offset.read()?
-        } else {
-            in_file.seek(SeekFrom::Current(0))?
-        };
-
-        let orig_out_file_offset = out_file.seek(SeekFrom::Current(0))?;
-        let write_offset = if !out_offset.is_null() {
-            let offset = out_offset.read()?;
-            out_file.seek(SeekFrom::Start(offset))?
-        } else {
-            0
-        };
-
-        // read from specified offset and write new offset back
-        let mut bytes_read = 0;
-        let mut total_written = 0;
-        while bytes_read < count {
-            let len = buffer.len().min(count - bytes_read);
-            let read_len = in_file.read_at(read_offset, &mut buffer[..len]).await?;
-            if read_len == 0 {
-                break;
-            }
-            bytes_read += read_len;
-            read_offset += read_len as u64;
-
-            let mut bytes_written = 0;
-            let mut rlen = read_len;
-            while bytes_written < read_len {
-                let write_len = out_file.write(&buffer[bytes_written..(bytes_written + rlen)])?;
-                if write_len == 0 {
-                    info!(
-                        "copy_file_range:END_ERR in={:?}, out={:?}, in_offset={:?}, out_offset={:?}, count={} = bytes_read {}, bytes_written {}, write_len {}",
-                        in_fd, out_fd, in_offset, out_offset, count, bytes_read, bytes_written, write_len
-                    );
-                    return Err(LxError::EBADF);
-                }
-                bytes_written += write_len;
-                rlen -= write_len;
-            }
-            total_written += bytes_written;
-        }
-
-        if !in_offset.is_null() {
-            in_offset.write(read_offset)?;
-        } else {
-            in_file.seek(SeekFrom::Current(bytes_read as i64))?;
-        }
-        out_offset.write_if_not_null(write_offset + total_written as u64)?;
-        if !out_offset.is_null() {
-            out_file.seek(SeekFrom::Start(orig_out_file_offset))?;
-        }
-        Ok(total_written)
-    }
-
     /// causes all buffered modifications to file metadata and data to be written to the underlying file systems.
     pub fn sys_sync(&self) -> SysResult {
         info!("sync:");
+        PageCache::sync_all()?;
         let proc = self.linux_process();
-        proc.root_inode().fs().sync()?;
+        proc.root_inode()?.fs().sync()?;
         Ok(0)
     }
 
@@ -293,7 +365,7 @@ impl Syscall<'_> {
     /// Manipulate a file descriptor.
     /// - cmd – cmd flag
     /// - arg – additional parameters based on cmd
-    pub fn sys_fcntl(&self, fd: FileDesc, cmd: usize, arg: usize) -> SysResult {
+    pub async fn sys_fcntl(&self, fd: FileDesc, cmd: usize, arg: usize) -> SysResult {
         info!("fcntl: fd={:?}, cmd={}, arg={}", fd, cmd, arg);
         let proc = self.linux_process();
         let file_like = proc.get_file_like(fd)?;
@@ -328,6 +400,22 @@ impl Syscall<'_> {
                     dup.set_flags(flags)?;
                     Ok(new_fd.into())
                 }
+                FcntlCmd::GETLK | FcntlCmd::SETLK | FcntlCmd::SETLKW => {
+                    self.fcntl_lock(fd, cmd, arg.into()).await?;
+                    Ok(0)
+                }
+                FcntlCmd::GETPIPE_SZ | FcntlCmd::SETPIPE_SZ => {
+                    let file = proc.get_file(fd)?;
+                    let inode = file.inode();
+                    let pipe = inode.downcast_ref::<Pipe>().ok_or(LxError::EBADF)?;
+                    if cmd == FcntlCmd::GETPIPE_SZ {
+                        return Ok(pipe.capacity());
+                    }
+                    if arg > PIPE_MAX_SIZE && !proc.cred().has_cap(Capabilities::SYS_RESOURCE) {
+                        return Err(LxError::EPERM);
+                    }
+                    pipe.set_capacity(arg)
+                }
                 _ => Err(LxError::EINVAL),
             }
         } else {
@@ -335,13 +423,86 @@ impl Syscall<'_> {
         }
     }
 
+    /// Get, place or remove a POSIX record lock of the process on the file of
+    /// `fd`, as described by `struct flock` at `flock`.
+    ///
+    /// A blocked `F_SETLKW` which would wait for the process itself fails with
+    /// `EDEADLK`.
+    async fn fcntl_lock(
+        &self,
+        fd: FileDesc,
+        cmd: FcntlCmd,
+        mut flock: UserInOutPtr<Flock>,
+    ) -> LxResult {
+        const F_RDLCK: i16 = 0;
+        const F_WRLCK: i16 = 1;
+        const F_UNLCK: i16 = 2;
+        let mut lk = flock.read()?;
+        info!("fcntl_lock: fd={:?}, cmd={:?}, flock={:?}", fd, cmd, lk);
+        let file = self.linux_process().get_file(fd)?;
+        let base = match lk.l_whence {
+            0 => 0,
+            1 => file.seek(SeekFrom::Current(0))? as i64,
+            2 => file.metadata()?.size as i64,
+            _ => return Err(LxError::EINVAL),
+        };
+        let start = base.checked_add(lk.l_start).ok_or(LxError::EINVAL)?;
+        let (start, end) = match lk.l_len {
+            0 => (start, u64::MAX),
+            len if len > 0 => (start, start.saturating_add(len) as u64),
+            len => (start.checked_add(len).ok_or(LxError::EINVAL)?, start as u64),
+        };
+        if start < 0 {
+            return Err(LxError::EINVAL);
+        }
+        let kind = match lk.l_type {
+            F_RDLCK => Some(LockKind::Shared),
+            F_WRLCK => Some(LockKind::Exclusive),
+            F_UNLCK if cmd != FcntlCmd::GETLK => None,
+            _ => return Err(LxError::EINVAL),
+        };
+        let lock = RecordLock {
+            owner: self.zircon_process().id(),
+            kind: kind.unwrap_or(LockKind::Shared),
+            start: start as u64,
+            end,
+        };
+        if cmd == FcntlCmd::GETLK {
+            match get_record_lock(&*file.inode(), &lock)? {
+                Some(other) => {
+                    lk.l_type = match other.kind {
+                        LockKind::Shared => F_RDLCK,
+                        LockKind::Exclusive => F_WRLCK,
+                    };
+                    lk.l_whence = 0;
+                    lk.l_start = other.start as i64;
+                    lk.l_len = match other.end {
+                        u64::MAX => 0,
+                        end => (end - other.start) as i64,
+                    };
+                    lk.l_pid = self.linux_process().pid_of(other.owner) as i32;
+                }
+                None => lk.l_type = F_UNLCK,
+            }
+            flock.write(lk)?;
+            return Ok(());
+        }
+        let flags = file.flags();
+        match kind {
+            Some(LockKind::Shared) if !flags.readable() => return Err(LxError::EBADF),
+            Some(LockKind::Exclusive) if !flags.writable() => return Err(LxError::EBADF),
+            _ => {}
+        }
+        let wait = cmd == FcntlCmd::SETLKW;
+        set_record_lock(&*file.inode(), lock, kind, wait, self.thread.id()).await
+    }
+
     /// Checks whether the calling process can access the file pathname
     pub fn sys_access(&self, path: UserInPtr<u8>, mode: usize) -> SysResult {
         self.sys_faccessat(FileDesc::CWD, path, mode, 0)
     }
 
     /// Check user's permissions of a file relative to a directory file descriptor
-    /// TODO: check permissions based on uid/gid
     pub fn sys_faccessat(
         &self,
         dirfd: FileDesc,
@@ -349,7 +510,6 @@ impl Syscall<'_> {
         mode: usize,
         flags: usize,
     ) -> SysResult {
-        // TODO: check permissions based on uid/gid
         let path = path.as_c_str()?;
         let flags = AtFlags::from_bits_truncate(flags);
         info!(
@@ -358,7 +518,146 @@ impl Syscall<'_> {
         );
         let proc = self.linux_process();
         let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
-        let _inode = proc.lookup_inode_at(dirfd, path, follow)?;
+        let inode = proc.lookup_inode_at(dirfd, path, follow)?;
+        // checked with the real IDs, unless `AT_EACCESS` is set
+        let cred = if flags.contains(AtFlags::EACCESS) {
+            proc.cred()
+        } else {
+            proc.cred().for_access()
+        };
+        if mode & W_OK != 0 {
+            proc.check_writable(&inode)?;
+        }
+        cred.check_access(&inode.metadata()?, mode & 7)?;
+        Ok(0)
+    }
+
+    /// Set the file mode creation mask, returns the previous one
+    pub fn sys_umask(&self, mask: usize) -> SysResult {
+        info!("umask: mask={:#o}", mask);
+        let old = self.linux_process().set_umask(mask as u32);
+        Ok(old as usize)
+    }
+
+    /// Change the permissions of a file
+    pub fn sys_chmod(&self, path: UserInPtr<u8>, mode: usize) -> SysResult {
+        self.sys_fchmodat(FileDesc::CWD, path, mode)
+    }
+
+    /// Change the permissions of an opened file
+    pub fn sys_fchmod(&self, fd: FileDesc, mode: usize) -> SysResult {
+        info!("fchmod: fd={:?}, mode={:#o}", fd, mode);
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.chmod_inode(&inode, mode)
+    }
+
+    /// Change the permissions of a file relative to a directory file descriptor
+    ///
+    /// Different from the libc function, the syscall has no flags.
+    pub fn sys_fchmodat(&self, dirfd: FileDesc, path: UserInPtr<u8>, mode: usize) -> SysResult {
+        let path = path.as_c_str()?;
+        info!(
+            "fchmodat: dirfd={:?}, path={:?}, mode={:#o}",
+            dirfd, path, mode
+        );
+        let inode = self.linux_process().lookup_inode_at(dirfd, path, true)?;
+        self.chmod_inode(&inode, mode)
+    }
+
+    /// Change the permissions of `inode`, which requires to own it.
+    fn chmod_inode(&self, inode: &Arc<dyn INode>, mode: usize) -> SysResult {
+        let proc = self.linux_process();
+        proc.check_writable(inode)?;
+        let mut metadata = inode.metadata()?;
+        let cred = proc.cred();
+        if !cred.is_owner(&metadata) {
+            return Err(LxError::EPERM);
+        }
+        metadata.mode = mode as u16 & 0o7777;
+        // the set-group-ID bit is cleared if the group is not of the process
+        if !cred.in_group(metadata.gid as u32) && !cred.has_cap(Capabilities::FSETID) {
+            metadata.mode &= !S_ISGID;
+        }
+        inode.set_metadata(&metadata)?;
+        inotify_event(&**inode, InotifyMask::ATTRIB, 0, "");
+        Ok(0)
+    }
+
+    /// Change the owner and the group of a file
+    pub fn sys_chown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, 0)
+    }
+
+    /// Change the owner and the group of a file, without following symbolic links
+    pub fn sys_lchown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        let flags = AtFlags::SYMLINK_NOFOLLOW.bits();
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, flags)
+    }
+
+    /// Change the owner and the group of an opened file
+    pub fn sys_fchown(&self, fd: FileDesc, uid: usize, gid: usize) -> SysResult {
+        info!(
+            "fchown: fd={:?}, uid={}, gid={}",
+            fd, uid as i32, gid as i32
+        );
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.chown_inode(&inode, uid, gid)
+    }
+
+    /// Change the owner and the group of a file relative to a directory file descriptor
+    pub fn sys_fchownat(
+        &self,
+        dirfd: FileDesc,
+        path: UserInPtr<u8>,
+        uid: usize,
+        gid: usize,
+        flags: usize,
+    ) -> SysResult {
+        let path = path.as_c_str()?;
+        let flags = AtFlags::from_bits_truncate(flags);
+        info!(
+            "fchownat: dirfd={:?}, path={:?}, uid={}, gid={}, flags={:?}",
+            dirfd, path, uid as i32, gid as i32, flags
+        );
+        let proc = self.linux_process();
+        let inode = if path.is_empty() && flags.contains(AtFlags::EMPTY_PATH) {
+            proc.get_file(dirfd)?.inode()
+        } else {
+            let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
+            proc.lookup_inode_at(dirfd, path, follow)?
+        };
+        self.chown_inode(&inode, uid, gid)
+    }
+
+    /// Change the owner of `inode`, where an ID of -1 is kept.
+    fn chown_inode(&self, inode: &Arc<dyn INode>, uid: usize, gid: usize) -> SysResult {
+        let id = |id: usize| {
+            if id as i32 == -1 {
+                None
+            } else {
+                Some(id as u32)
+            }
+        };
+        let (uid, gid) = (id(uid), id(gid));
+        let proc = self.linux_process();
+        proc.check_writable(inode)?;
+        let mut metadata = inode.metadata()?;
+        proc.cred().check_chown(&metadata, uid, gid)?;
+        if let Some(uid) = uid {
+            metadata.uid = uid as usize;
+        }
+        if let Some(gid) = gid {
+            metadata.gid = gid as usize;
+        }
+        // the set-user-ID and set-group-ID bits of executables are cleared
+        if metadata.type_ != FileType::Dir && (uid.is_some() || gid.is_some()) {
+            metadata.mode &= !S_ISUID;
+            if metadata.mode & 0o010 != 0 {
+                metadata.mode &= !S_ISGID;
+            }
+        }
+        inode.set_metadata(&metadata)?;
+        inotify_event(&**inode, InotifyMask::ATTRIB, 0, "");
         Ok(0)
     }
 
@@ -403,6 +702,7 @@ impl Syscall<'_> {
             };
             proc.lookup_inode_at(dirfd, pathname, follow)?
         };
+        proc.check_writable(&inode)?;
         let mut metadata = inode.metadata()?;
         if times[0].nsec != UTIME_OMIT {
             if times[0].nsec == UTIME_NOW {
@@ -423,6 +723,116 @@ impl Syscall<'_> {
             };
         }
         inode.set_metadata(&metadata)?;
+        inotify_event(&*inode, InotifyMask::ATTRIB, 0, "");
+        Ok(0)
+    }
+
+    /// Mount a filesystem at the directory `target`
+    /// (see [linux man mount(2)](https://man7.org/linux/man-pages/man2/mount.2.html)),
+    /// which requires `CAP_SYS_ADMIN`.
+    ///
+    /// Supports the filesystems of `LinuxProcess::open_fs`, overlays, bind
+    /// mounts and remounting with new flags. The `data` of options is only
+    /// used by overlays.
+    pub fn sys_mount(
+        &self,
+        source: UserInPtr<u8>,
+        target: UserInPtr<u8>,
+        fstype: UserInPtr<u8>,
+        flags: usize,
+        data: UserInPtr<u8>,
+    ) -> SysResult {
+        let proc = self.linux_process();
+        if !proc.cred().has_cap(Capabilities::SYS_ADMIN) {
+            return Err(LxError::EPERM);
+        }
+        let target = target.as_c_str()?;
+        let flags = MountFlags::from_bits_truncate(flags);
+        info!("mount: target={:?}, flags={:?}", target, flags);
+        let ns = proc.mount_ns();
+        let (_, path) = proc.lookup_ns_path(target, true)?;
+        if flags.contains(MountFlags::REMOUNT) {
+            ns.remount(&path, flags)?;
+        } else if flags.intersects(MountFlags::SHARED | MountFlags::PRIVATE | MountFlags::SLAVE) {
+            // mount events are never propagated between namespaces
+        } else if flags.contains(MountFlags::MOVE) {
+            return self.unimplemented("mount --move", Err(LxError::EINVAL));
+        } else if flags.contains(MountFlags::BIND) {
+            let source = source.as_c_str()?;
+            let (inode, source_path) = proc.lookup_ns_path(source, true)?;
+            ns.bind(&source_path, &inode, &path, flags)?;
+        } else {
+            let fstype = fstype.as_c_str()?;
+            let (source, device) = if source.is_null() {
+                ("none", None)
+            } else {
+                let source = source.as_c_str()?;
+                (source, proc.lookup_inode(source).ok())
+            };
+            info!("mount: source={:?}, fstype={:?}", source, fstype);
+            let fs = if fstype == "overlay" {
+                let options = if data.is_null() { "" } else { data.as_c_str()? };
+                info!("mount: options={:?}", options);
+                proc.open_overlay(options)?
+            } else {
+                proc.open_fs(fstype, device.as_ref())?
+            };
+            ns.mount(source, &path, fstype, fs, flags)?;
+        }
+        Ok(0)
+    }
+
+    /// Unmount the filesystem mounted last at the directory `target`
+    /// (see [linux man umount2(2)](https://man7.org/linux/man-pages/man2/umount2.2.html)),
+    /// which requires `CAP_SYS_ADMIN`.
+    ///
+    /// The open files and working directories under it stay usable, as after
+    /// a lazy unmount.
+    pub fn sys_umount2(&self, target: UserInPtr<u8>, flags: usize) -> SysResult {
+        let proc = self.linux_process();
+        if !proc.cred().has_cap(Capabilities::SYS_ADMIN) {
+            return Err(LxError::EPERM);
+        }
+        let target = target.as_c_str()?;
+        let flags = UmountFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("umount2: target={:?}, flags={:?}", target, flags);
+        if flags.contains(UmountFlags::EXPIRE)
+            && flags.intersects(UmountFlags::FORCE | UmountFlags::DETACH)
+        {
+            return Err(LxError::EINVAL);
+        }
+        let follow = !flags.contains(UmountFlags::NOFOLLOW);
+        let (_, path) = proc.lookup_ns_path(target, follow)?;
+        proc.mount_ns()
+            .umount(&path, flags.contains(UmountFlags::DETACH))?;
+        Ok(0)
+    }
+
+    /// Change the root mount
+    /// (see [linux man pivot_root(2)](https://man7.org/linux/man-pages/man2/pivot_root.2.html)),
+    /// which requires `CAP_SYS_ADMIN`.
+    ///
+    /// The root mount moves to the directory `put_old`, and the mount at the
+    /// directory `new_root` becomes the root mount of the mount namespace.
+    pub fn sys_pivot_root(&self, new_root: UserInPtr<u8>, put_old: UserInPtr<u8>) -> SysResult {
+        let new_root = new_root.as_c_str()?;
+        let put_old = put_old.as_c_str()?;
+        info!("pivot_root: new_root={:?}, put_old={:?}", new_root, put_old);
+        let proc = self.linux_process();
+        if !proc.cred().has_cap(Capabilities::SYS_ADMIN) {
+            return Err(LxError::EPERM);
+        }
+        // the root directory of the process must be the root mount
+        if proc.root_path() != "/" {
+            return Err(LxError::EINVAL);
+        }
+        let (new_root, new_root_path) = proc.lookup_ns_path(new_root, true)?;
+        let (put_old, put_old_path) = proc.lookup_ns_path(put_old, true)?;
+        if new_root.metadata()?.type_ != FileType::Dir || put_old.metadata()?.type_ != FileType::Dir
+        {
+            return Err(LxError::ENOTDIR);
+        }
+        proc.mount_ns().pivot_root(&new_root_path, &put_old_path)?;
         Ok(0)
     }
 
@@ -436,12 +846,7 @@ impl Syscall<'_> {
         let path = path.as_c_str()?;
         info!("statfs: path={:?}, buf={:?}", path, buf);
 
-        // TODO
-        // 现在 `path` 没用到，因为没实现真正的挂载，不可能搞一个非主要文件系统的路径。
-        // 实现挂载之后，要用 `path` 分辨路径在哪个文件系统里，根据对应文件系统的特性返回统计信息。
-        // （以及根据挂载选项填写 `StatFs::f_flags`！）
-
-        let info = self.linux_process().root_inode().fs().info();
+        let info = self.linux_process().lookup_inode(path)?.fs().info();
         buf.write(info.into())?;
         Ok(0)
     }
@@ -507,6 +912,36 @@ impl From<FsInfo> for StatFs {
     }
 }
 
+bitflags! {
+    /// Flags of `umount2`
+    pub struct UmountFlags: usize {
+        /// force the unmount even if busy
+        const FORCE = 1;
+        /// detach the mount, and the mounts under it
+        const DETACH = 2;
+        /// mark the mount as expired
+        const EXPIRE = 4;
+        /// do not follow the target if it is a symbolic link
+        const NOFOLLOW = 8;
+    }
+}
+
+/// `struct flock`, a POSIX record lock of `fcntl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Flock {
+    /// `F_RDLCK`, `F_WRLCK` or `F_UNLCK`
+    l_type: i16,
+    /// `SEEK_SET`, `SEEK_CUR` or `SEEK_END`, which `l_start` is relative to
+    l_whence: i16,
+    /// The first byte of the range
+    l_start: i64,
+    /// The length of the range, 0 up to the end of file
+    l_len: i64,
+    /// The process holding a conflicting lock, returned by `F_GETLK`
+    l_pid: i32,
+}
+
 numeric_enum_macro::numeric_enum! {
     #[repr(usize)]
     #[allow(non_camel_case_types)]
@@ -531,5 +966,9 @@ numeric_enum_macro::numeric_enum! {
         SETLKW = 7,
         /// like F_DUPFD, but additionally set the close-on-exec flag
         DUPFD_CLOEXEC = F_LINUX_SPECIFIC_BASE + 6,
+        /// set the capacity of a pipe
+        SETPIPE_SZ = F_LINUX_SPECIFIC_BASE + 7,
+        /// get the capacity of a pipe
+        GETPIPE_SZ = F_LINUX_SPECIFIC_BASE + 8,
     }
 }
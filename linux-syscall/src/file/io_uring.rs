@@ -0,0 +1,153 @@
+//! Syscalls of io_uring
+//!
+//! - io_uring_setup
+//! - io_uring_enter
+//! - io_uring_register
+
+use super::*;
+use linux_object::signal::Sigset;
+use linux_object::thread::ThreadExt;
+
+impl Syscall<'_> {
+    /// Create an io_uring of `entries` submission queue entries, whose rings
+    /// are mapped by `mmap` with the offsets filled in `params`.
+    pub fn sys_io_uring_setup(
+        &self,
+        entries: usize,
+        mut params: UserInOutPtr<IoUringParams>,
+    ) -> SysResult {
+        let mut p = params.read()?;
+        info!("io_uring_setup: entries={}, params={:?}", entries, p);
+        let ring = IoUring::new(self.zircon_process(), entries as u32, &mut p)?;
+        let fd = self.linux_process().add_file(ring)?;
+        params.write(p)?;
+        Ok(fd.into())
+    }
+
+    /// Submit `to_submit` entries of the submission queue of the io_uring
+    /// `fd`, and wait for `min_complete` completions with `IORING_ENTER_GETEVENTS`.
+    ///
+    /// The signal mask is replaced by `sig` while waiting, like `epoll_pwait`.
+    pub async fn sys_io_uring_enter(
+        &self,
+        fd: FileDesc,
+        to_submit: usize,
+        min_complete: usize,
+        flags: usize,
+        sig: UserInPtr<Sigset>,
+        sigsz: usize,
+    ) -> SysResult {
+        info!(
+            "io_uring_enter: fd={:?}, to_submit={}, min_complete={}, flags={:#x}, sig={:?}",
+            fd, to_submit, min_complete, flags, sig
+        );
+        let flags = IoUringEnterFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if flags.contains(IoUringEnterFlags::EXT_ARG) {
+            return Err(LxError::EINVAL);
+        }
+        let ring = self
+            .linux_process()
+            .get_file_like(fd)?
+            .downcast_arc::<IoUring>()
+            .map_err(|_| LxError::EOPNOTSUPP)?;
+        let submitted = ring.submit(to_submit as u32 as usize)?;
+        if !flags.contains(IoUringEnterFlags::GETEVENTS) || min_complete == 0 {
+            return Ok(submitted);
+        }
+        let old_mask = match sig.read_if_not_null()? {
+            Some(mask) => {
+                if sigsz != core::mem::size_of::<Sigset>() {
+                    return Err(LxError::EINVAL);
+                }
+                let mut thread = self.thread.lock_linux();
+                let old = thread.signal_mask;
+                thread.signal_mask = mask;
+                Some(old)
+            }
+            None => None,
+        };
+        let ret = ring.wait(min_complete as u32 as usize).await;
+        if let Some(old) = old_mask {
+            self.thread.lock_linux().signal_mask = old;
+        }
+        ret?;
+        Ok(submitted)
+    }
+
+    /// Register or unregister the files, or the eventfd, of the io_uring
+    /// `fd`, or probe the operations supported.
+    pub fn sys_io_uring_register(
+        &self,
+        fd: FileDesc,
+        opcode: usize,
+        arg: usize,
+        nr_args: usize,
+    ) -> SysResult {
+        const IORING_REGISTER_FILES: usize = 2;
+        const IORING_UNREGISTER_FILES: usize = 3;
+        const IORING_REGISTER_EVENTFD: usize = 4;
+        const IORING_UNREGISTER_EVENTFD: usize = 5;
+        const IORING_REGISTER_EVENTFD_ASYNC: usize = 7;
+        const IORING_REGISTER_PROBE: usize = 8;
+        /// The largest number of registered files
+        const IORING_MAX_FIXED_FILES: usize = 1 << 15;
+        info!(
+            "io_uring_register: fd={:?}, opcode={}, arg={:#x}, nr_args={}",
+            fd, opcode, arg, nr_args
+        );
+        let proc = self.linux_process();
+        let ring = proc
+            .get_file_like(fd)?
+            .downcast_arc::<IoUring>()
+            .map_err(|_| LxError::EOPNOTSUPP)?;
+        match opcode {
+            IORING_REGISTER_FILES => {
+                if nr_args == 0 || nr_args > IORING_MAX_FIXED_FILES {
+                    return Err(LxError::EINVAL);
+                }
+                let fds = UserInPtr::<i32>::from(arg).read_array(nr_args)?;
+                let mut files = Vec::with_capacity(nr_args);
+                for fd in fds {
+                    // -1 leaves the slot empty
+                    files.push(match fd {
+                        -1 => None,
+                        fd => Some(proc.get_file_like(fd.into())?),
+                    });
+                }
+                ring.register_files(files)?;
+            }
+            IORING_UNREGISTER_FILES => {
+                if arg != 0 || nr_args != 0 {
+                    return Err(LxError::EINVAL);
+                }
+                ring.unregister_files()?;
+            }
+            IORING_REGISTER_EVENTFD | IORING_REGISTER_EVENTFD_ASYNC => {
+                if nr_args != 1 {
+                    return Err(LxError::EINVAL);
+                }
+                let efd = UserInPtr::<i32>::from(arg).read()?;
+                let eventfd = proc.get_file_like(efd.into())?;
+                if eventfd.clone().downcast_arc::<EventFd>().is_err() {
+                    return Err(LxError::EBADF);
+                }
+                ring.register_eventfd(eventfd)?;
+            }
+            IORING_UNREGISTER_EVENTFD => {
+                if arg != 0 || nr_args != 0 {
+                    return Err(LxError::EINVAL);
+                }
+                ring.unregister_eventfd()?;
+            }
+            IORING_REGISTER_PROBE => {
+                if nr_args > 256 {
+                    return Err(LxError::EINVAL);
+                }
+                let probe = IoUring::probe(nr_args);
+                UserOutPtr::<u8>::from(arg).write_array(&probe)?;
+            }
+            _ => return Err(LxError::EINVAL),
+        }
+        Ok(0)
+    }
+}
@@ -6,10 +6,47 @@ use linux_object::fs::vfs::{FileType, FsError};
 use linux_object::fs::*;
 
 mod dir;
+mod epoll;
 mod fd;
 #[allow(clippy::module_inception)]
 mod file;
+mod io_uring;
 mod poll;
+mod splice;
 mod stat;
+mod xattr;
 
 use self::dir::AtFlags;
+
+/// Write `buf` to `file_like`, waiting for room in it unless it is
+/// non-blocking, e.g. when a pipe is full.
+///
+/// Returns the bytes written before the room or the data runs out, or an
+/// error if none is written.
+async fn write_wait(file_like: &Arc<dyn FileLike>, buf: &[u8]) -> SysResult {
+    let mut written = 0;
+    loop {
+        match file_like.write(&buf[written..]) {
+            Ok(0) => break,
+            Ok(len) => {
+                written += len;
+                if written == buf.len() {
+                    break;
+                }
+            }
+            Err(LxError::EAGAIN) if !file_like.flags().non_block() => {
+                let status = file_like.async_poll(PollEvents::OUT).await?;
+                if !status.write && !status.error {
+                    return if written == 0 {
+                        Err(LxError::EAGAIN)
+                    } else {
+                        Ok(written)
+                    };
+                }
+            }
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(written)
+}
@@ -13,28 +13,82 @@ use core::task::{Context, Poll};
 use core::time::Duration;
 use kernel_hal::timer;
 use linux_object::fs::{FileDesc, PollEvents};
+use linux_object::signal::Sigset;
+use linux_object::thread::{interruptible, ThreadExt};
 use linux_object::time::*;
 
 impl Syscall<'_> {
     /// Wait for some event on a file descriptor
+    ///
+    /// A negative `timeout_msecs` means an infinite timeout.
     pub async fn sys_poll(
         &mut self,
-        mut ufds: UserInOutPtr<PollFd>,
+        ufds: UserInOutPtr<PollFd>,
         nfds: usize,
         timeout_msecs: isize,
     ) -> SysResult {
-        let mut polls = ufds.read_array(nfds)?;
         info!(
             "poll: ufds: {:?}, nfds: {:?}, timeout_msecs: {}",
-            polls, nfds, timeout_msecs
+            ufds, nfds, timeout_msecs
         );
+        let timeout_msecs = timeout_msecs as i32;
+        let deadline = if timeout_msecs >= 0 {
+            Some(timer::deadline_after(Duration::from_millis(
+                timeout_msecs as u64,
+            )))
+        } else {
+            None
+        };
+        let thread = self.thread;
+        interruptible(thread, self.do_poll(ufds, nfds, deadline)).await
+    }
+
+    /// Wait for some event on a file descriptor
+    ///
+    /// ppoll() allows an application to safely wait until either a file descriptor becomes ready or until a signal is caught
+    pub async fn sys_ppoll(
+        &mut self,
+        ufds: UserInOutPtr<PollFd>,
+        nfds: usize,
+        timeout: UserInPtr<TimeSpec>,
+        sigmask: UserInPtr<Sigset>,
+        sigsetsize: usize,
+    ) -> SysResult {
+        let deadline = match timeout.read_if_not_null()? {
+            Some(timeout) => {
+                info!("ppoll: timeout: {:?}", timeout);
+                if timeout.nsec >= 1_000_000_000 {
+                    return Err(LxError::EINVAL);
+                }
+                Some(timer::deadline_after(timeout.into()))
+            }
+            None => None,
+        };
+        let sigmask = sigmask.read_if_not_null()?;
+        if sigmask.is_some() && sigsetsize != core::mem::size_of::<Sigset>() {
+            return Err(LxError::EINVAL);
+        }
+        let thread = self.thread;
+        wait_with_sigmask(thread, sigmask, self.do_poll(ufds, nfds, deadline)).await
+    }
+
+    /// Poll the file descriptors in `ufds` until some of them are ready or
+    /// `deadline` is reached, `None` means waiting forever.
+    async fn do_poll(
+        &mut self,
+        mut ufds: UserInOutPtr<PollFd>,
+        nfds: usize,
+        deadline: Option<Duration>,
+    ) -> SysResult {
+        if nfds > self.linux_process().file_limit(None).cur as usize {
+            return Err(LxError::EINVAL);
+        }
+        let mut polls = ufds.read_array(nfds)?;
 
-        let begin_time_ms = TimeVal::now().to_msec();
         #[must_use = "future does nothing unless polled/`await`-ed"]
         struct PollFuture<'a> {
             polls: &'a mut Vec<PollFd>,
-            timeout_msecs: isize,
-            begin_time_ms: usize,
+            deadline: Option<Duration>,
             syscall: &'a Syscall<'a>,
         }
         impl<'a> Future for PollFuture<'a> {
@@ -43,23 +97,17 @@ impl Syscall<'_> {
             fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
                 use PollEvents as PE;
                 let proc = self.syscall.linux_process();
+                // number of fds with nonzero `revents`
                 let mut events = 0;
 
                 // iterate each poll to check whether it is ready
                 for poll in self.as_mut().polls.iter_mut() {
                     poll.revents = PE::empty();
-
-                    /* To speed up the socket
-                    use linux_object::net::SOCKET_FD;
-                    if <FileDesc as Into<usize>>::into(poll.fd) >= SOCKET_FD {
-                        debug!("Found socket fd: {:?}", poll.fd);
-                        //poll.revents |= PE::ERR;
-                        poll.revents = poll.events;
-                        events += 1;
+                    if <FileDesc as Into<i32>>::into(poll.fd) < 0 {
+                        // negative fds are ignored
                         continue;
-                    } */
+                    }
                     if let Ok(file_like) = proc.get_file_like(poll.fd) {
-                        debug!("get file like: {:?}", file_like);
                         let mut fut = Box::pin(file_like.async_poll(poll.events));
                         let status = match fut.as_mut().poll(cx) {
                             Poll::Ready(Ok(ret)) => ret,
@@ -71,22 +119,26 @@ impl Syscall<'_> {
                         };
                         if status.error {
                             poll.revents |= PE::ERR;
-                            events += 1;
+                        }
+                        if file_like.hung_up() {
+                            poll.revents |= PE::HUP;
                         }
                         if status.read && poll.events.contains(PE::IN) {
                             poll.revents |= PE::IN;
-                            events += 1;
                         }
                         if status.write && poll.events.contains(PE::OUT) {
                             poll.revents |= PE::OUT;
-                            events += 1;
                         }
-                    } else if <FileDesc as Into<i32>>::into(poll.fd) < 0 {
-                        warn!("A negative fd: {:?}", poll.fd);
-                        poll.revents = PE::empty();
+                        if poll.revents.is_empty() {
+                            // ready for other events, wait for the next change
+                            let waker = cx.waker().clone();
+                            file_like.on_change(Box::new(move || waker.wake_by_ref()));
+                        }
                     } else {
                         warn!("can not find filelike object from fd: {:?}", poll.fd);
                         poll.revents |= PE::INVAL;
+                    }
+                    if !poll.revents.is_empty() {
                         events += 1;
                     }
                 }
@@ -95,46 +147,20 @@ impl Syscall<'_> {
                     return Poll::Ready(Ok(events));
                 }
 
-                match self.timeout_msecs {
-                    // no timeout, return now;
-                    0 => return Poll::Ready(Ok(0)),
-                    1.. => {
-                        let current_time_ms = TimeVal::now().to_msec();
-                        let deadline = self.begin_time_ms + self.timeout_msecs as usize;
-                        if current_time_ms >= deadline {
-                            return Poll::Ready(Ok(0));
-                        } else {
-                            let waker = cx.waker().clone();
-                            timer::timer_set(
-                                Duration::from_millis(deadline as u64),
-                                Box::new(move |_| waker.wake_by_ref()),
-                            );
-                        }
-                    }
-                    -1 => {
-                        // When the timeout = -1, the poll blocks indefinitely.
-                        // Fixme. So Check this Future regularly every 500ms
-                        let current_time_ms = TimeVal::now().to_msec();
-                        let deadline = current_time_ms + 500;
-                        let waker = cx.waker().clone();
-                        timer::timer_set(
-                            Duration::from_millis(deadline as u64),
-                            Box::new(move |_| waker.wake_by_ref()),
-                        );
-                    }
-                    _ => {
-                        info!("No waker. timeout: {:?}", self.timeout_msecs);
+                if let Some(deadline) = self.deadline {
+                    if timer::timer_now() >= deadline {
+                        return Poll::Ready(Ok(0));
                     }
+                    let waker = cx.waker().clone();
+                    timer::timer_set(deadline, Box::new(move |_| waker.wake_by_ref()));
                 }
-
                 Poll::Pending
             }
         }
 
         let future = PollFuture {
             polls: &mut polls,
-            timeout_msecs,
-            begin_time_ms,
+            deadline,
             syscall: self,
         };
         let result = future.await;
@@ -143,26 +169,6 @@ impl Syscall<'_> {
         result
     }
 
-    /// Wait for some event on a file descriptor
-    ///
-    /// ppoll() allows an application to safely wait until either a file descriptor becomes ready or until a signal is caught
-    pub async fn sys_ppoll(
-        &mut self,
-        ufds: UserInOutPtr<PollFd>,
-        nfds: usize,
-        timeout: UserInPtr<TimeSpec>,
-    ) -> SysResult {
-        let timeout_msecs = if timeout.is_null() {
-            -1
-        } else {
-            let timeout = timeout.read().unwrap();
-            info!("sys_ppoll: timeout: {:?}", timeout);
-            timeout.to_msec() as isize
-        };
-
-        self.sys_poll(ufds, nfds, timeout_msecs).await
-    }
-
     /// similar to select, but have sigmask argument
     pub async fn sys_pselect6(
         &mut self,
@@ -290,6 +296,30 @@ impl Syscall<'_> {
     }
 }
 
+/// Wait for `future` with the signal mask of `thread` replaced by `sigmask`
+/// if any, failing with `EINTR` on a signal to handle.
+///
+/// The signal interrupting the wait is handled with the mask replaced, which
+/// is restored after that, as `ppoll` and `epoll_pwait` do.
+pub(super) async fn wait_with_sigmask<T>(
+    thread: &CurrentThread,
+    sigmask: Option<Sigset>,
+    future: impl Future<Output = LxResult<T>>,
+) -> LxResult<T> {
+    let old_mask =
+        sigmask.map(|mask| core::mem::replace(&mut thread.lock_linux().signal_mask, mask));
+    let ret = interruptible(thread, future).await;
+    if let Some(old) = old_mask {
+        let mut linux = thread.lock_linux();
+        if matches!(ret, Err(LxError::EINTR)) {
+            linux.saved_signal_mask = Some(old);
+        } else {
+            linux.signal_mask = old;
+        }
+    }
+    ret
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct PollFd {
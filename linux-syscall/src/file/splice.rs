@@ -0,0 +1,343 @@
+//! Syscalls moving data between files in the kernel
+//!
+//! - sendfile
+//! - splice
+//! - copy_file_range
+//!
+//! The data is moved by a kernel buffer, reading regular files through
+//! their page cache, so it never goes through the user space.
+
+use super::*;
+
+/// The largest number of bytes moved by one call, as `MAX_RW_COUNT` of Linux
+const MAX_RW_COUNT: usize = 0x7fff_f000;
+/// The size of the kernel buffer
+const TRANSFER_CHUNK: usize = 0x10000;
+/// The largest number of bytes spliced into a pipe by one call, the default
+/// capacity of a pipe of Linux
+const PIPE_SPLICE_MAX: usize = 0x10000;
+
+bitflags! {
+    struct SpliceFlags: usize {
+        /// move pages instead of copying
+        const MOVE = 1;
+        /// do not block on the pipes
+        const NONBLOCK = 2;
+        /// more data will be coming in a subsequent splice
+        const MORE = 4;
+        /// the pages are a gift, for vmsplice only
+        const GIFT = 8;
+    }
+}
+
+/// One end of a transfer.
+struct TransferEnd {
+    file: Arc<dyn FileLike>,
+    /// The offset in a regular file, or `None` to read or write it as a
+    /// stream, updating its position if any
+    offset: Option<u64>,
+}
+
+impl TransferEnd {
+    async fn read(&mut self, buf: &mut [u8]) -> LxResult<usize> {
+        match &mut self.offset {
+            Some(offset) => {
+                let len = self.file.read_at(*offset, buf).await?;
+                *offset += len as u64;
+                Ok(len)
+            }
+            None => self.file.read(buf).await,
+        }
+    }
+
+    async fn write(&mut self, buf: &[u8]) -> LxResult<usize> {
+        match &mut self.offset {
+            Some(offset) => {
+                let len = self.file.write_at(*offset, buf)?;
+                *offset += len as u64;
+                Ok(len)
+            }
+            None => write_wait(&self.file, buf).await,
+        }
+    }
+
+    /// Whether a read would not block.
+    fn readable(&self) -> LxResult<bool> {
+        match self.offset {
+            Some(_) => Ok(true),
+            None => Ok(self.file.poll(PollEvents::IN)?.read),
+        }
+    }
+}
+
+/// Returns the file if it is a regular file, which has offsets.
+fn regular_file(file: &Arc<dyn FileLike>) -> Option<Arc<File>> {
+    let file = file.clone().downcast_arc::<File>().ok()?;
+    match file.metadata().ok()?.type_ {
+        FileType::File | FileType::BlockDevice => Some(file),
+        _ => None,
+    }
+}
+
+/// Whether the file is an end of a pipe.
+fn is_pipe(file: &Arc<dyn FileLike>) -> bool {
+    match file.clone().downcast_arc::<File>() {
+        Ok(file) => file.inode().downcast_ref::<Pipe>().is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Move up to `count` bytes from `input` to `output`, returns the number of
+/// bytes moved.
+///
+/// It stops early at the end of `input`, when `output` takes no more, or
+/// when reading a stream again would block. An error is returned only if
+/// nothing is moved.
+async fn transfer(input: &mut TransferEnd, output: &mut TransferEnd, count: usize) -> SysResult {
+    let mut buf = vec![0u8; count.min(TRANSFER_CHUNK)];
+    let mut total = 0;
+    while total < count {
+        if total != 0 && !input.readable()? {
+            break;
+        }
+        let len = buf.len().min(count - total);
+        let read_len = match input.read(&mut buf[..len]).await {
+            Ok(0) => break,
+            Ok(read_len) => read_len,
+            Err(err) if total == 0 => return Err(err),
+            Err(_) => break,
+        };
+        let mut written = 0;
+        while written < read_len {
+            match output.write(&buf[written..read_len]).await {
+                Ok(0) => break,
+                Ok(write_len) => written += write_len,
+                Err(err) if total + written == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        total += written;
+        if written < read_len {
+            // what is not written is left in a regular file
+            if let Some(offset) = &mut input.offset {
+                *offset -= (read_len - written) as u64;
+            }
+            break;
+        }
+    }
+    Ok(total)
+}
+
+impl Syscall<'_> {
+    /// Copy `count` bytes from `in_fd` to `out_fd`, which may be any file.
+    ///
+    /// If `offset_ptr` is not null, `in_fd` is read from the offset it points
+    /// to, and the offset after the last byte read is written back to it,
+    /// leaving the file position of `in_fd` unchanged.
+    pub async fn sys_sendfile(
+        &self,
+        out_fd: FileDesc,
+        in_fd: FileDesc,
+        mut offset_ptr: UserInOutPtr<u64>,
+        count: usize,
+    ) -> SysResult {
+        info!(
+            "sendfile: out={:?}, in={:?}, offset={:?}, count={}",
+            out_fd, in_fd, offset_ptr, count
+        );
+        let proc = self.linux_process();
+        let in_file = proc.get_file_like(in_fd)?;
+        let out_file = proc.get_file_like(out_fd)?;
+        if in_file.clone().downcast_arc::<File>().is_err() {
+            return Err(LxError::EINVAL);
+        }
+        if out_file.flags().is_append() {
+            return Err(LxError::EINVAL);
+        }
+        let regular = regular_file(&in_file);
+        let offset = match offset_ptr.read_if_not_null()? {
+            Some(offset) if (offset as i64) < 0 => return Err(LxError::EINVAL),
+            Some(offset) => {
+                regular.as_ref().ok_or(LxError::ESPIPE)?;
+                Some(offset)
+            }
+            None => match &regular {
+                Some(file) => Some(file.seek(SeekFrom::Current(0))?),
+                None => None,
+            },
+        };
+        let mut input = TransferEnd {
+            file: in_file,
+            offset,
+        };
+        let mut output = TransferEnd {
+            file: out_file,
+            offset: None,
+        };
+        let len = transfer(&mut input, &mut output, count.min(MAX_RW_COUNT)).await?;
+        if let Some(offset) = input.offset {
+            if offset_ptr.is_null() {
+                regular.unwrap().seek(SeekFrom::Start(offset))?;
+            } else {
+                offset_ptr.write(offset)?;
+            }
+        }
+        Ok(len)
+    }
+
+    /// Move `len` bytes between a pipe and another file.
+    ///
+    /// The offset of the other file may be given like
+    /// [`copy_file_range`](Self::sys_copy_file_range), but not the one of a
+    /// pipe. At most the capacity of a pipe is moved into it at a time.
+    pub async fn sys_splice(
+        &self,
+        fd_in: FileDesc,
+        mut off_in: UserInOutPtr<u64>,
+        fd_out: FileDesc,
+        mut off_out: UserInOutPtr<u64>,
+        len: usize,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "splice: in={:?}, off_in={:?}, out={:?}, off_out={:?}, len={}, flags={:#x}",
+            fd_in, off_in, fd_out, off_out, len, flags
+        );
+        let flags = SpliceFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let proc = self.linux_process();
+        let in_file = proc.get_file_like(fd_in)?;
+        let out_file = proc.get_file_like(fd_out)?;
+        let (in_pipe, out_pipe) = (is_pipe(&in_file), is_pipe(&out_file));
+        if !in_pipe && !out_pipe {
+            return Err(LxError::EINVAL);
+        }
+        if (in_pipe && !off_in.is_null()) || (out_pipe && !off_out.is_null()) {
+            return Err(LxError::ESPIPE);
+        }
+        let offset_of = |file: &Arc<dyn FileLike>, ptr: &UserInOutPtr<u64>| -> LxResult<_> {
+            match ptr.read_if_not_null()? {
+                Some(offset) if (offset as i64) < 0 => Err(LxError::EINVAL),
+                Some(offset) => {
+                    regular_file(file).ok_or(LxError::ESPIPE)?;
+                    Ok(Some(offset))
+                }
+                None => Ok(None),
+            }
+        };
+        let mut input = TransferEnd {
+            offset: offset_of(&in_file, &off_in)?,
+            file: in_file,
+        };
+        let mut output = TransferEnd {
+            offset: offset_of(&out_file, &off_out)?,
+            file: out_file,
+        };
+        // the position of a regular file is moved by the bytes read
+        let in_regular = match input.offset {
+            Some(_) => None,
+            None => regular_file(&input.file),
+        };
+        if let Some(file) = &in_regular {
+            input.offset = Some(file.seek(SeekFrom::Current(0))?);
+        }
+        if flags.contains(SpliceFlags::NONBLOCK) && !input.readable()? {
+            return Err(LxError::EAGAIN);
+        }
+        let mut count = len.min(MAX_RW_COUNT);
+        if out_pipe {
+            count = count.min(PIPE_SPLICE_MAX);
+        }
+        let moved = transfer(&mut input, &mut output, count).await?;
+        match (&in_regular, input.offset) {
+            (Some(file), Some(offset)) => {
+                file.seek(SeekFrom::Start(offset))?;
+            }
+            (None, Some(offset)) => off_in.write(offset)?,
+            _ => {}
+        }
+        if let Some(offset) = output.offset {
+            off_out.write(offset)?;
+        }
+        Ok(moved)
+    }
+
+    /// Copy `count` bytes between two regular files.
+    ///
+    /// A null `in_offset` or `out_offset` means the file position, which is
+    /// moved by the bytes copied. Otherwise the offset it points to is used
+    /// and updated instead, leaving the file position unchanged.
+    pub async fn sys_copy_file_range(
+        &self,
+        in_fd: FileDesc,
+        mut in_offset: UserInOutPtr<u64>,
+        out_fd: FileDesc,
+        mut out_offset: UserInOutPtr<u64>,
+        count: usize,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "copy_file_range: in={:?}, out={:?}, in_offset={:?}, out_offset={:?}, count={}, flags={}",
+            in_fd, out_fd, in_offset, out_offset, count, flags
+        );
+        if flags != 0 {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.linux_process();
+        let in_file = proc.get_file(in_fd)?;
+        let out_file = proc.get_file(out_fd)?;
+        let (in_meta, out_meta) = (in_file.metadata()?, out_file.metadata()?);
+        if in_meta.type_ == FileType::Dir || out_meta.type_ == FileType::Dir {
+            return Err(LxError::EISDIR);
+        }
+        if in_meta.type_ != FileType::File || out_meta.type_ != FileType::File {
+            return Err(LxError::EINVAL);
+        }
+        if !in_file.flags().readable()
+            || !out_file.flags().writable()
+            || out_file.flags().is_append()
+        {
+            return Err(LxError::EBADF);
+        }
+        let read_offset = match in_offset.read_if_not_null()? {
+            Some(offset) => offset,
+            None => in_file.seek(SeekFrom::Current(0))?,
+        };
+        let write_offset = match out_offset.read_if_not_null()? {
+            Some(offset) => offset,
+            None => out_file.seek(SeekFrom::Current(0))?,
+        };
+        if (read_offset as i64) < 0 || (write_offset as i64) < 0 {
+            return Err(LxError::EINVAL);
+        }
+        let count = count.min(MAX_RW_COUNT);
+        // the ranges may not overlap in the same file
+        if in_meta.dev == out_meta.dev
+            && in_meta.inode == out_meta.inode
+            && read_offset < write_offset + count as u64
+            && write_offset < read_offset + count as u64
+        {
+            return Err(LxError::EINVAL);
+        }
+        let mut input = TransferEnd {
+            file: in_file.clone(),
+            offset: Some(read_offset),
+        };
+        let mut output = TransferEnd {
+            file: out_file.clone(),
+            offset: Some(write_offset),
+        };
+        let len = transfer(&mut input, &mut output, count).await?;
+        let (read_offset, write_offset) = (input.offset.unwrap(), output.offset.unwrap());
+        if in_offset.is_null() {
+            in_file.seek(SeekFrom::Start(read_offset))?;
+        } else {
+            in_offset.write(read_offset)?;
+        }
+        if out_offset.is_null() {
+            out_file.seek(SeekFrom::Start(write_offset))?;
+        } else {
+            out_offset.write(write_offset)?;
+        }
+        Ok(len)
+    }
+}
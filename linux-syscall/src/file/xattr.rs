@@ -0,0 +1,259 @@
+//! Extended attributes
+//!
+//! - setxattr, lsetxattr, fsetxattr
+//! - getxattr, lgetxattr, fgetxattr
+//! - listxattr, llistxattr, flistxattr
+//! - removexattr, lremovexattr, fremovexattr
+
+use super::*;
+use alloc::vec::Vec;
+use linux_object::cred::{Capabilities, R_OK, W_OK};
+use linux_object::fs::vfs::INode;
+
+impl Syscall<'_> {
+    /// Set an extended attribute of a file
+    /// (see [linux man setxattr(2)](https://man7.org/linux/man-pages/man2/setxattr.2.html)).
+    pub fn sys_setxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, true)?;
+        self.setxattr(&inode, name, value, size, flags)
+    }
+
+    /// Set an extended attribute of a file, without following symbolic links
+    pub fn sys_lsetxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, false)?;
+        self.setxattr(&inode, name, value, size, flags)
+    }
+
+    /// Set an extended attribute of an opened file
+    pub fn sys_fsetxattr(
+        &self,
+        fd: FileDesc,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.setxattr(&inode, name, value, size, flags)
+    }
+
+    /// Get an extended attribute of a file, returns the length of its value
+    /// (see [linux man getxattr(2)](https://man7.org/linux/man-pages/man2/getxattr.2.html)).
+    ///
+    /// If `size` is 0, only the length is returned.
+    pub fn sys_getxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, true)?;
+        self.getxattr(&inode, name, value, size)
+    }
+
+    /// Get an extended attribute of a file, without following symbolic links
+    pub fn sys_lgetxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, false)?;
+        self.getxattr(&inode, name, value, size)
+    }
+
+    /// Get an extended attribute of an opened file
+    pub fn sys_fgetxattr(
+        &self,
+        fd: FileDesc,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.getxattr(&inode, name, value, size)
+    }
+
+    /// List the names of the extended attributes of a file, each ending with
+    /// a NUL, returns the length of the list
+    /// (see [linux man listxattr(2)](https://man7.org/linux/man-pages/man2/listxattr.2.html)).
+    ///
+    /// If `size` is 0, only the length is returned.
+    pub fn sys_listxattr(
+        &self,
+        path: UserInPtr<u8>,
+        list: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, true)?;
+        self.listxattr(&inode, list, size)
+    }
+
+    /// List the extended attributes of a file, without following symbolic links
+    pub fn sys_llistxattr(
+        &self,
+        path: UserInPtr<u8>,
+        list: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let inode = self.xattr_path_inode(path, false)?;
+        self.listxattr(&inode, list, size)
+    }
+
+    /// List the extended attributes of an opened file
+    pub fn sys_flistxattr(&self, fd: FileDesc, list: UserOutPtr<u8>, size: usize) -> SysResult {
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.listxattr(&inode, list, size)
+    }
+
+    /// Remove an extended attribute of a file
+    /// (see [linux man removexattr(2)](https://man7.org/linux/man-pages/man2/removexattr.2.html)).
+    pub fn sys_removexattr(&self, path: UserInPtr<u8>, name: UserInPtr<u8>) -> SysResult {
+        let inode = self.xattr_path_inode(path, true)?;
+        self.removexattr(&inode, name)
+    }
+
+    /// Remove an extended attribute of a file, without following symbolic links
+    pub fn sys_lremovexattr(&self, path: UserInPtr<u8>, name: UserInPtr<u8>) -> SysResult {
+        let inode = self.xattr_path_inode(path, false)?;
+        self.removexattr(&inode, name)
+    }
+
+    /// Remove an extended attribute of an opened file
+    pub fn sys_fremovexattr(&self, fd: FileDesc, name: UserInPtr<u8>) -> SysResult {
+        let inode = self.linux_process().get_file(fd)?.inode();
+        self.removexattr(&inode, name)
+    }
+
+    fn xattr_path_inode(&self, path: UserInPtr<u8>, follow: bool) -> LxResult<Arc<dyn INode>> {
+        let path = path.as_c_str()?;
+        self.linux_process()
+            .lookup_inode_at(FileDesc::CWD, path, follow)
+    }
+
+    fn setxattr(
+        &self,
+        inode: &Arc<dyn INode>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        let name = name.as_c_str()?;
+        let flags = XattrFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!(
+            "setxattr: name={:?}, size={}, flags={:?}",
+            name, size, flags
+        );
+        if size > XATTR_SIZE_MAX {
+            return Err(LxError::E2BIG);
+        }
+        self.check_xattr_access(inode, name, true)?;
+        let value = if size == 0 {
+            Vec::new()
+        } else {
+            value.read_array(size)?
+        };
+        set_xattr(&**inode, name, &value, flags)?;
+        Ok(0)
+    }
+
+    fn getxattr(
+        &self,
+        inode: &Arc<dyn INode>,
+        name: UserInPtr<u8>,
+        mut value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let name = name.as_c_str()?;
+        info!("getxattr: name={:?}, size={}", name, size);
+        self.check_xattr_access(inode, name, false)?;
+        let data = get_xattr(&**inode, name)?;
+        if size != 0 {
+            if size < data.len() {
+                return Err(LxError::ERANGE);
+            }
+            value.write_array(&data)?;
+        }
+        Ok(data.len())
+    }
+
+    fn listxattr(
+        &self,
+        inode: &Arc<dyn INode>,
+        mut list: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        info!("listxattr: size={}", size);
+        // trusted attributes are hidden from the unprivileged
+        let trusted = self.linux_process().cred().has_cap(Capabilities::SYS_ADMIN);
+        let mut buf = Vec::new();
+        for name in list_xattr(&**inode)? {
+            if trusted || XattrNamespace::of(&name)? != XattrNamespace::Trusted {
+                buf.extend_from_slice(name.as_bytes());
+                buf.push(0);
+            }
+        }
+        if size != 0 {
+            if size < buf.len() {
+                return Err(LxError::ERANGE);
+            }
+            list.write_array(&buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn removexattr(&self, inode: &Arc<dyn INode>, name: UserInPtr<u8>) -> SysResult {
+        let name = name.as_c_str()?;
+        info!("removexattr: name={:?}", name);
+        self.check_xattr_access(inode, name, true)?;
+        remove_xattr(&**inode, name)?;
+        Ok(0)
+    }
+
+    /// Check the access to the attribute `name` of `inode`, which depends on
+    /// its namespace.
+    fn check_xattr_access(&self, inode: &Arc<dyn INode>, name: &str, write: bool) -> LxResult {
+        let proc = self.linux_process();
+        if write {
+            proc.check_writable(inode)?;
+        }
+        let cred = proc.cred();
+        let metadata = inode.metadata()?;
+        match XattrNamespace::of(name)? {
+            // only regular files and directories have user attributes
+            XattrNamespace::User => match metadata.type_ {
+                FileType::File | FileType::Dir => {
+                    cred.check_access(&metadata, if write { W_OK } else { R_OK })
+                }
+                _ if write => Err(LxError::EPERM),
+                _ => Err(LxError::ENODATA),
+            },
+            XattrNamespace::Trusted if !cred.has_cap(Capabilities::SYS_ADMIN) => {
+                Err(LxError::EPERM)
+            }
+            XattrNamespace::Security | XattrNamespace::System
+                if write && !cred.is_owner(&metadata) =>
+            {
+                Err(LxError::EPERM)
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use super::*;
 use bitflags::*;
+use linux_object::cred::{R_OK, W_OK};
 use numeric_enum_macro::numeric_enum;
 use zircon_object::vm::*;
 
 pub use linux_object::ipc::*;
 
-/// Syscalls of inter-process communication and System V semaphore Set operation.
+/// Syscalls of inter-process communication: System V semaphore sets,
+/// shared memory segments and message queues.
 ///
 /// # Menu
 ///
@@ -16,6 +18,10 @@ pub use linux_object::ipc::*;
 /// - [`shmat`](Self::sys_shmat)
 /// - [`shmdt`](Self::sys_shmdt)
 /// - [`shmctl`](Self::sys_shmctl)
+/// - [`msgget`](Self::sys_msgget)
+/// - [`msgsnd`](Self::sys_msgsnd)
+/// - [`msgrcv`](Self::sys_msgrcv)
+/// - [`msgctl`](Self::sys_msgctl)
 impl Syscall<'_> {
     /// Get a System V semaphore set identifier
     /// (see [linux man semget(2)](https://www.man7.org/linux/man-pages/man2/semget.2.html)).
@@ -56,16 +62,8 @@ impl Syscall<'_> {
     /// If the semaphore set already exists, the permissions are verified.
     pub fn sys_semget(&self, key: usize, nsems: usize, flags: usize) -> SysResult {
         info!("semget: key: {} nsems: {} flags: {:#x}", key, nsems, flags);
-
-        /// The maximum semaphores per semaphore set
-        const SEMMSL: usize = 256;
-
-        if nsems > SEMMSL {
-            return Err(LxError::EINVAL);
-        }
-
-        let sem_array = SemArray::get_or_create(key as u32, nsems, flags)?;
-        let id = self.linux_process().semaphores_add(sem_array);
+        let cred = self.linux_process().cred();
+        let id = SemArray::get_or_create(key as u32, nsems, flags, &cred)?;
         Ok(id)
     }
 
@@ -89,36 +87,43 @@ impl Syscall<'_> {
     ///
     /// Each operation is performed on the `SemBuf::num`-th semaphore of the semaphore set,
     /// where the first semaphore of the set is numbered 0.
-    /// There are two types of operation, distinguished by the value of `SemBuf::op`.
+    /// There are three types of operation, distinguished by the value of `SemBuf::op`.
+    ///
+    /// - If `op` is a positive integer, it is added to the semaphore value.
+    /// - If `op` is zero, the process waits for the semaphore value to become zero.
+    /// - If `op` is less than zero, the process waits for the semaphore value to be
+    ///   greater than or equal to the absolute value of `op`, then subtracts it.
     ///
-    /// - If `op` is +1, see [`acquire`](linux_object::sync::Semaphore::acquire).
-    /// - If `op` is -1, see [`release`](linux_object::sync::Semaphore::release).
+    /// The operations are performed atomically: none of them is performed until all can be.
+    /// If one of them would block and has `IPC_NOWAIT`, `semop` fails with [`EAGAIN`](LxError::EAGAIN).
     pub async fn sys_semop(&self, id: usize, ops: UserInPtr<SemBuf>, num_ops: usize) -> SysResult {
-        info!("semop: id: {}", id);
-        let ops = ops.as_slice(num_ops)?;
-
-        let sem_array = self
-            .linux_process()
-            .semaphores_get(id)
-            .ok_or(LxError::EINVAL)?;
-        sem_array.otime();
-        for &SemBuf { num, op, flags } in ops {
-            let flags = SemFlags::from_bits_truncate(flags);
-            if flags.contains(SemFlags::IPC_NOWAIT) {
-                unimplemented!("Semaphore: semop.IPC_NOWAIT");
-            }
-            let sem = &sem_array[num as usize];
-
-            match op {
-                1 => sem.release(),
-                -1 => sem.acquire().await?,
-                _ => unimplemented!("Semaphore: semop.(Not 1/-1)"),
-            }
-            sem.set_pid(self.zircon_process().id() as usize);
-            if flags.contains(SemFlags::SEM_UNDO) {
-                self.linux_process().semaphores_add_undo(id, num, op);
-            }
+        info!("semop: id: {}, num_ops: {}", id, num_ops);
+        /// The maximum operations per semop call
+        const SEMOPM: usize = 500;
+        if num_ops == 0 {
+            return Err(LxError::EINVAL);
+        }
+        if num_ops > SEMOPM {
+            return Err(LxError::E2BIG);
         }
+        let ops = ops.read_array(num_ops)?;
+        let sem_array = SemArray::get(id)?;
+        let alter = ops.iter().any(|op| op.op != 0);
+        let mask = if alter { W_OK } else { R_OK };
+        sem_array
+            .semid_ds
+            .lock()
+            .perm
+            .check_access(&self.linux_process().cred(), mask)?;
+        let pid = self.zircon_process().id();
+        let undo = ops
+            .iter()
+            .any(|op| SemFlags::from_bits_truncate(op.flags).contains(SemFlags::SEM_UNDO));
+        if undo {
+            // before the operations, so that they are undone however the process exits
+            self.linux_process().semaphores_add_undo(id, pid);
+        }
+        sem_array.semop(&ops, pid).await?;
         Ok(0)
     }
 
@@ -130,17 +135,15 @@ impl Syscall<'_> {
     /// or on the `num`-th semaphore of that set
     /// (The semaphores in a set are numbered starting at 0).
     ///
-    /// TODO
+    /// `arg` is the value for `SETVAL`, or points to a `SemidDs` for `IPC_STAT` and `IPC_SET`,
+    /// or to an array of `u16` for `GETALL` and `SETALL`.
+    ///
+    /// The set removed by `IPC_RMID` fails all processes waiting on it with [`EIDRM`](LxError::EIDRM).
     pub fn sys_semctl(&self, id: usize, num: usize, cmd: usize, arg: usize) -> SysResult {
         info!(
             "semctl: id: {}, num: {}, cmd: {} arg: {:#x}",
             id, num, cmd, arg
         );
-        let sem_array = self
-            .linux_process()
-            .semaphores_get(id)
-            .ok_or(LxError::EINVAL)?;
-
         let cmd = match SemctlCmds::try_from(cmd) {
             Ok(t) => t,
             Err(_) => {
@@ -148,13 +151,18 @@ impl Syscall<'_> {
                 return Err(LxError::EINVAL);
             }
         };
+        let sem_array = SemArray::get(id)?;
+        let cred = self.linux_process().cred();
+        let pid = self.zircon_process().id() as u32;
+        let check_access = |mask| sem_array.semid_ds.lock().perm.check_access(&cred, mask);
         match cmd {
             SemctlCmds::IPC_RMID => {
+                sem_array.semid_ds.lock().perm.check_owner(&cred)?;
                 sem_array.remove();
-                self.linux_process().semaphores_remove(id);
                 Ok(0)
             }
             SemctlCmds::IPC_SET => {
+                sem_array.semid_ds.lock().perm.check_owner(&cred)?;
                 // arg is struct semid_ds
                 let ptr = UserInPtr::from(arg);
                 let ds: SemidDs = ptr.read()?;
@@ -164,25 +172,36 @@ impl Syscall<'_> {
                 Ok(0)
             }
             SemctlCmds::IPC_STAT => {
+                check_access(R_OK)?;
                 // arg is struct semid_ds
                 let mut ptr = UserOutPtr::from(arg);
                 ptr.write(*sem_array.semid_ds.lock())?;
                 Ok(0)
             }
+            SemctlCmds::GETALL => {
+                check_access(R_OK)?;
+                UserOutPtr::<u16>::from(arg).write_array(&sem_array.get_all())?;
+                Ok(0)
+            }
+            SemctlCmds::SETALL => {
+                check_access(W_OK)?;
+                let values = UserInPtr::<u16>::from(arg).read_array(sem_array.nsems())?;
+                sem_array.set_all(&values, pid)?;
+                Ok(0)
+            }
+            SemctlCmds::SETVAL => {
+                check_access(W_OK)?;
+                sem_array.set_value(num, arg as i32, pid)?;
+                Ok(0)
+            }
             _ => {
-                let sem = &sem_array[num as usize];
+                check_access(R_OK)?;
                 match cmd {
-                    SemctlCmds::GETPID => Ok(sem.get_pid()),
-                    SemctlCmds::GETVAL => Ok(sem.get() as usize),
-                    SemctlCmds::GETNCNT => Ok(sem.get_ncnt()),
-                    SemctlCmds::GETZCNT => Ok(0),
-                    SemctlCmds::SETVAL => {
-                        sem.set(arg as isize);
-                        sem.set_pid(self.zircon_process().id() as usize);
-                        sem_array.ctime();
-                        Ok(0)
-                    }
-                    _ => unimplemented!("Semaphore Semctl cmd: {:?}", cmd),
+                    SemctlCmds::GETPID => Ok(sem_array.get_pid(num)? as usize),
+                    SemctlCmds::GETVAL => Ok(sem_array.get_value(num)? as usize),
+                    SemctlCmds::GETNCNT => Ok(sem_array.get_ncnt(num)?),
+                    SemctlCmds::GETZCNT => Ok(sem_array.get_zcnt(num)?),
+                    _ => unreachable!(),
                 }
             }
         }
@@ -192,21 +211,22 @@ impl Syscall<'_> {
     /// (see [linux man shmget(2)](https://www.man7.org/linux/man-pages/man2/shmget.2.html)).
     ///
     /// `shmget` returns the identifier of the System V shared memory segment
-    /// associated with the value of the argument key.
-    /// Differ from linux, this syscall always create a new set.
+    /// associated with the value of the argument key, which is shared by all processes.
+    /// A new segment of `size` bytes is created if `key` is `IPC_PRIVATE`,
+    /// or if no segment is associated with `key` and `IpcGetFlag::CREAT` is specified in `shmflg`.
     pub fn sys_shmget(&self, key: usize, size: usize, shmflg: usize) -> SysResult {
         info!(
             "shmget: key: {}, size: {}, shmflg: {:#x}",
             key, size, shmflg
         );
-
-        let shared_guard = ShmIdentifier::new_shared_guard(
+        let cred = self.linux_process().cred();
+        let id = ShmGuard::get_or_create(
             key as u32,
             size,
             shmflg,
             self.zircon_process().id() as u32,
+            &cred,
         )?;
-        let id = self.linux_process().shm_add(shared_guard);
         Ok(id)
     }
 
@@ -217,37 +237,46 @@ impl Syscall<'_> {
     /// to the address space of the calling process.
     /// The attaching address is specified by `addr`.
     /// If `addr` is zero, the system chooses a suitable page-aligned address to attach the segment.
-    pub fn sys_shmat(&self, id: usize, mut addr: VirtAddr, shmflg: usize) -> SysResult {
-        let mut shm_identifier = self.linux_process().shm_get(id).ok_or(LxError::EINVAL)?;
+    /// Otherwise it must be page-aligned, or be rounded down with `ShmFlags::RND`.
+    ///
+    /// The segment stays attached in the child processes after `fork`.
+    pub fn sys_shmat(&self, id: usize, addr: VirtAddr, shmflg: usize) -> SysResult {
+        let flags = ShmFlags::from_bits_truncate(shmflg);
+        info!("shmat: id: {}, addr = {:#x}, flags = {:?}", id, addr, flags);
+        let shm = ShmGuard::get(id)?;
+        let writable = !flags.contains(ShmFlags::RDONLY);
+        shm.check_attach(&self.linux_process().cred(), writable)?;
 
+        let mut mmu_flags = MMUFlags::READ;
+        if writable {
+            mmu_flags |= MMUFlags::WRITE;
+        }
+        if flags.contains(ShmFlags::EXEC) {
+            mmu_flags |= MMUFlags::EXECUTE;
+        }
         let proc = self.zircon_process();
         let vmar = proc.vmar();
-        if addr == 0 {
-            // although NULL can be a valid address
-            // but in C, NULL is regarded as allocation failure
-            // so just skip it
-            addr = PAGE_SIZE;
-        }
-        let shm_guard = shm_identifier.guard.lock();
-        let vmo = shm_guard.shared_guard.clone();
-        info!(
-            "shmat: id: {}, addr = {:#x}, size = {}, flags = {:#x}",
-            id,
-            addr,
-            vmo.len(),
-            shmflg
-        );
-        let addr = vmar.map(
-            None,
-            vmo.clone(),
-            0,
-            vmo.len(),
-            MMUFlags::READ | MMUFlags::WRITE | MMUFlags::EXECUTE,
-        )?;
-        shm_identifier.addr = addr;
-        self.linux_process().shm_set(id, shm_identifier.clone());
-
-        shm_guard.attach(proc.id() as u32);
+        // a slice of the segment is shared with the child processes
+        let vmo = shm.shared_guard.create_slice(0, shm.shared_guard.len())?;
+        let vmar_offset = if addr == 0 {
+            None
+        } else {
+            let addr = if flags.contains(ShmFlags::RND) {
+                addr & !(PAGE_SIZE - 1)
+            } else {
+                addr
+            };
+            if !page_aligned(addr) {
+                return Err(LxError::EINVAL);
+            }
+            if flags.contains(ShmFlags::REMAP) {
+                vmar.unmap(addr, vmo.len())?;
+            }
+            Some(addr.checked_sub(vmar.addr()).ok_or(LxError::EINVAL)?)
+        };
+        let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), mmu_flags)?;
+        shm.attach(proc.id() as u32);
+        self.linux_process().shm_attach(addr, shm);
         Ok(addr)
     }
 
@@ -258,64 +287,202 @@ impl Syscall<'_> {
     /// from the address space of the calling process.
     /// The to-be-detached segment must be currently attached with `addr`
     /// equal to the value returned by the attaching [`sys_shmat`](Self::sys_shmat) call.
-    pub fn sys_shmdt(&self, id: usize, addr: VirtAddr, shmflg: usize) -> SysResult {
-        info!(
-            "shmdt: id = {}, addr = {:#x}, flag = {:#x}",
-            id, addr, shmflg
-        );
-        let proc = self.linux_process();
-        let opt_id = proc.shm_get_id(addr);
-        if let Some(id) = opt_id {
-            let shm_identifier = proc.shm_get(id).ok_or(LxError::EINVAL)?;
-            proc.shm_pop(id);
-            shm_identifier
-                .guard
-                .lock()
-                .detach(self.zircon_process().id() as u32);
-        }
+    pub fn sys_shmdt(&self, addr: VirtAddr) -> SysResult {
+        info!("shmdt: addr = {:#x}", addr);
+        let shm = self
+            .linux_process()
+            .shm_detach(addr)
+            .ok_or(LxError::EINVAL)?;
+        let proc = self.zircon_process();
+        proc.vmar().unmap(addr, shm.guard.shared_guard.len())?;
+        shm.guard.detach(proc.id() as u32);
         Ok(0)
     }
 
     /// System V shared memory operations
     /// (see [linux man shmctl(2)](https://www.man7.org/linux/man-pages/man2/shmctl.2.html)).
     ///
-    /// performs the control operation specified by cmd on the shared memory segment whose identifier is given in id
+    /// performs the control operation specified by cmd on the shared memory segment whose identifier is given in id.
+    ///
+    /// A segment removed by `ShmctlCmds::IPC_RMID` is destroyed after the last process detaches it.
     pub fn sys_shmctl(&self, id: usize, cmd: usize, buffer: usize) -> SysResult {
         info!("shmctl: id: {}, cmd: {} buffer: {:#x}", id, cmd, buffer);
-        let shm_identifier = self.linux_process().shm_get(id).ok_or(LxError::EINVAL)?;
-        let shm_guard = shm_identifier.guard.lock();
         let cmd = match ShmctlCmds::try_from(cmd) {
             Ok(t) => t,
             Err(_) => {
-                error!("invalid semctl cmd: {}", cmd);
+                error!("invalid shmctl cmd: {}", cmd);
                 return Err(LxError::EINVAL);
             }
         };
+        if cmd == ShmctlCmds::SHM_INFO {
+            let (used_ids, shm_tot) = ShmGuard::usage();
+            let mut buffer: UserOutPtr<ShmInfo> = buffer.into();
+            buffer.write(ShmInfo {
+                used_ids: used_ids as i32,
+                shm_tot,
+                shm_rss: shm_tot,
+                shm_swp: 0,
+            })?;
+            return Ok(ShmGuard::max_index());
+        }
+        // SHM_STAT takes the index in the table instead of the identifier
+        let shm = match cmd {
+            ShmctlCmds::SHM_STAT => ShmGuard::get_by_index(id)?,
+            _ => ShmGuard::get(id)?,
+        };
+        let cred = self.linux_process().cred();
         match cmd {
             ShmctlCmds::IPC_RMID => {
-                shm_guard.remove();
-                self.linux_process().shm_pop(id);
+                shm.check_owner(&cred)?;
+                shm.remove();
                 Ok(0)
             }
             ShmctlCmds::IPC_SET => {
+                shm.check_owner(&cred)?;
                 let buffer: UserInPtr<ShmidDs> = buffer.into();
                 let set_ds = buffer.read()?;
-                shm_guard.set(&set_ds);
-                shm_guard.ctime();
+                shm.set(&set_ds);
+                shm.ctime();
                 Ok(0)
             }
             ShmctlCmds::IPC_STAT | ShmctlCmds::SHM_STAT => {
-                let shmid_ds = shm_guard.shmid_ds.lock();
+                shm.check_access(&cred, R_OK)?;
+                let shmid_ds = *shm.shmid_ds.lock();
                 let mut buffer: UserOutPtr<ShmidDs> = buffer.into();
-                buffer.write(*shmid_ds)?;
+                buffer.write(shmid_ds)?;
+                // SHM_STAT returns the identifier
+                Ok(if cmd == ShmctlCmds::SHM_STAT {
+                    shm.id
+                } else {
+                    0
+                })
+            }
+            // segments are never swapped out
+            ShmctlCmds::SHM_LOCK | ShmctlCmds::SHM_UNLOCK => {
+                shm.check_owner(&cred)?;
                 Ok(0)
             }
-            ShmctlCmds::SHM_INFO => {
-                let mut buffer: UserOutPtr<ShmInfo> = buffer.into();
-                buffer.write(ShmInfo::default())?;
+            // handled above
+            ShmctlCmds::SHM_INFO => unreachable!(),
+        }
+    }
+
+    /// Get a System V message queue identifier
+    /// (see [linux man msgget(2)](https://www.man7.org/linux/man-pages/man2/msgget.2.html)).
+    ///
+    /// `msgget` returns the identifier of the message queue associated with `key`.
+    /// A new queue is created if `key` is `IPC_PRIVATE`,
+    /// or if no queue is associated with `key` and `IpcGetFlag::CREAT` is specified in `flags`.
+    pub fn sys_msgget(&self, key: usize, flags: usize) -> SysResult {
+        info!("msgget: key: {}, flags: {:#x}", key, flags);
+        let cred = self.linux_process().cred();
+        let id = MsgQueue::get_or_create(key as u32, flags, &cred)?;
+        Ok(id)
+    }
+
+    /// Send a message to a System V message queue
+    /// (see [linux man msgsnd(2)](https://www.man7.org/linux/man-pages/man2/msgsnd.2.html)).
+    ///
+    /// `msgp` points to a `long` message type, followed by `size` bytes of the message.
+    /// The process waits for room in the queue unless `MsgFlags::IPC_NOWAIT` is given.
+    pub async fn sys_msgsnd(&self, id: usize, msgp: usize, size: usize, flags: usize) -> SysResult {
+        let flags = MsgFlags::from_bits_truncate(flags);
+        info!(
+            "msgsnd: id: {}, msgp: {:#x}, size: {}, flags: {:?}",
+            id, msgp, size, flags
+        );
+        if size > MSGMAX {
+            return Err(LxError::EINVAL);
+        }
+        let queue = MsgQueue::get(id)?;
+        queue.check_access(&self.linux_process().cred(), W_OK)?;
+        let mtype = UserInPtr::<isize>::from(msgp).read()?;
+        let data = UserInPtr::<u8>::from(msgp + core::mem::size_of::<isize>()).read_array(size)?;
+        queue
+            .send(mtype, data, flags, self.zircon_process().id() as u32)
+            .await?;
+        Ok(0)
+    }
+
+    /// Receive a message from a System V message queue
+    /// (see [linux man msgrcv(2)](https://www.man7.org/linux/man-pages/man2/msgrcv.2.html)).
+    ///
+    /// The message selected by `msgtyp` (see [`MsgQueue::receive`]) is written to `msgp`
+    /// as a `long` message type followed by at most `size` bytes.
+    /// Returns the number of bytes of the message written.
+    pub async fn sys_msgrcv(
+        &self,
+        id: usize,
+        msgp: usize,
+        size: usize,
+        msgtyp: usize,
+        flags: usize,
+    ) -> SysResult {
+        let flags = MsgFlags::from_bits_truncate(flags);
+        info!(
+            "msgrcv: id: {}, msgp: {:#x}, size: {}, msgtyp: {}, flags: {:?}",
+            id, msgp, size, msgtyp as isize, flags
+        );
+        if (size as isize) < 0 {
+            return Err(LxError::EINVAL);
+        }
+        let queue = MsgQueue::get(id)?;
+        queue.check_access(&self.linux_process().cred(), R_OK)?;
+        let (mtype, data) = queue
+            .receive(
+                msgtyp as isize,
+                size,
+                flags,
+                self.zircon_process().id() as u32,
+            )
+            .await?;
+        UserOutPtr::<isize>::from(msgp).write(mtype)?;
+        UserOutPtr::<u8>::from(msgp + core::mem::size_of::<isize>()).write_array(&data)?;
+        Ok(data.len())
+    }
+
+    /// System V message control operations
+    /// (see [linux man msgctl(2)](https://www.man7.org/linux/man-pages/man2/msgctl.2.html)).
+    ///
+    /// The queue removed by `IPC_RMID` fails all processes waiting on it with [`EIDRM`](LxError::EIDRM).
+    pub fn sys_msgctl(&self, id: usize, cmd: usize, buffer: usize) -> SysResult {
+        info!("msgctl: id: {}, cmd: {}, buffer: {:#x}", id, cmd, buffer);
+        let cmd = match MsgctlCmds::try_from(cmd) {
+            Ok(t) => t,
+            Err(_) => {
+                error!("invalid msgctl cmd: {}", cmd);
+                return Err(LxError::EINVAL);
+            }
+        };
+        // MSG_STAT takes the index in the table instead of the identifier
+        let queue = match cmd {
+            MsgctlCmds::MSG_STAT => MsgQueue::get_by_index(id)?,
+            _ => MsgQueue::get(id)?,
+        };
+        let cred = self.linux_process().cred();
+        match cmd {
+            MsgctlCmds::IPC_RMID => {
+                queue.check_owner(&cred)?;
+                queue.remove();
+                Ok(0)
+            }
+            MsgctlCmds::IPC_SET => {
+                queue.check_owner(&cred)?;
+                let buffer: UserInPtr<MsqidDs> = buffer.into();
+                queue.set(&buffer.read()?, &cred)?;
                 Ok(0)
             }
-            _ => unimplemented!("Semaphore Semctl cmd: {:?}", cmd),
+            MsgctlCmds::IPC_STAT | MsgctlCmds::MSG_STAT => {
+                queue.check_access(&cred, R_OK)?;
+                let mut buffer: UserOutPtr<MsqidDs> = buffer.into();
+                buffer.write(queue.stat())?;
+                // MSG_STAT returns the identifier
+                Ok(if cmd == MsgctlCmds::MSG_STAT {
+                    queue.id
+                } else {
+                    0
+                })
+            }
         }
     }
 }
@@ -354,7 +521,7 @@ numeric_enum! {
     #[repr(usize)]
     #[derive(Debug, Eq, PartialEq)]
     #[allow(non_camel_case_types)]
-    /// for the third argument of semctl(), specified the control operation
+    /// for the second argument of shmctl(), specified the control operation
     pub enum ShmctlCmds {
         /// Mark the segment to be destroyed, actually be destroyed after the last process detaches it
         IPC_RMID = 0,
@@ -375,14 +542,22 @@ numeric_enum! {
     }
 }
 
-/// An operation to be performed on a single semaphore
-///
-/// Ref: <http://man7.org/linux/man-pages/man2/semop.2.html>
-#[repr(C)]
-pub struct SemBuf {
-    num: u16,
-    op: i16,
-    flags: i16,
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Eq, PartialEq)]
+    #[allow(non_camel_case_types)]
+    /// for the second argument of msgctl(), specified the control operation
+    pub enum MsgctlCmds {
+        /// Remove the message queue, awakening all waiting readers and writers
+        IPC_RMID = 0,
+        /// Write the values of some members of the msqid_ds structure pointed to by buf
+        IPC_SET = 1,
+        /// Copy information from the kernel data structure associated with
+        /// msqid into the msqid_ds structure pointed to by buf.
+        IPC_STAT = 2,
+        /// Returns a msqid_ds structure as for IPC_STAT
+        MSG_STAT = 11,
+    }
 }
 
 /// shm_info structure for shmctl
@@ -400,10 +575,15 @@ struct ShmInfo {
 }
 
 bitflags! {
-    pub struct SemFlags: i16 {
-        /// For SemOP
-        const IPC_NOWAIT = 0x800;
-        /// it will be automatically undone when the process terminates.
-        const SEM_UNDO = 0x1000;
+    /// flags for shmat()
+    pub struct ShmFlags: usize {
+        /// attach read-only
+        const RDONLY = 0o10000;
+        /// round the attach address down to SHMLBA
+        const RND = 0o20000;
+        /// take over the mappings in the range
+        const REMAP = 0o40000;
+        /// allow the segment to be executed
+        const EXEC = 0o100000;
     }
 }
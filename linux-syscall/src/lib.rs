@@ -28,11 +28,14 @@ use alloc::sync::Arc;
 use core::convert::TryFrom;
 
 use kernel_hal::user::{IoVecIn, IoVecOut, UserInOutPtr, UserInPtr, UserOutPtr};
-use linux_object::error::{LxError, SysResult};
+use linux_object::error::{LxError, LxResult, SysResult};
 use linux_object::fs::FileDesc;
-use linux_object::process::{wait_child, wait_child_any, LinuxProcess, ProcessExt, RLimit};
+use linux_object::process::{
+    process_group, wait_child, wait_child_any, LinuxProcess, ProcessExt, RLimit,
+};
 use zircon_object::object::{KernelObject, KoID, Signal};
 use zircon_object::task::{CurrentThread, Process, Thread, ThreadFn};
+use zircon_object::util::syscall_stats::{SyscallTable, SyscallTimer};
 use zircon_object::vm::VirtAddr;
 
 use self::consts::SyscallType as Sys;
@@ -41,11 +44,14 @@ mod consts {
     // generated from syscall.h.in
     include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 }
+mod cred;
 mod file;
 mod ipc;
 mod misc;
 mod net;
+mod seccomp;
 mod signal;
+mod strace;
 mod task;
 mod time;
 mod vm;
@@ -76,11 +82,17 @@ impl Syscall<'_> {
                 return LxError::EINVAL as _;
             }
         };
+        let _timer = SyscallTimer::start(SyscallTable::Linux, num);
+        if let Some(ret) = self.seccomp_check(&sys_type, num, &args) {
+            info!("<= seccomp {}", ret);
+            return ret;
+        }
+        let strace = self.strace_enter(&sys_type, &args);
         let [a0, a1, a2, a3, a4, a5] = args;
         let ret = match sys_type {
             Sys::READ => self.sys_read(a0.into(), a1.into(), a2).await,
-            Sys::WRITE => self.sys_write(a0.into(), a1.into(), a2),
-            Sys::OPENAT => self.sys_openat(a0.into(), a1.into(), a2, a3),
+            Sys::WRITE => self.sys_write(a0.into(), a1.into(), a2).await,
+            Sys::OPENAT => self.sys_openat(a0.into(), a1.into(), a2, a3).await,
             Sys::CLOSE => self.sys_close(a0.into()),
             Sys::FSTAT => self.sys_fstat(a0.into(), a1.into()),
             Sys::NEWFSTATAT => self.sys_fstatat(a0.into(), a1.into(), a2.into(), a3),
@@ -89,10 +101,20 @@ impl Syscall<'_> {
             Sys::PREAD64 => self.sys_pread(a0.into(), a1.into(), a2, a3 as _).await,
             Sys::PWRITE64 => self.sys_pwrite(a0.into(), a1.into(), a2, a3 as _),
             Sys::READV => self.sys_readv(a0.into(), a1.into(), a2).await,
-            Sys::WRITEV => self.sys_writev(a0.into(), a1.into(), a2),
+            Sys::WRITEV => self.sys_writev(a0.into(), a1.into(), a2).await,
+            Sys::PREADV => self.sys_preadv(a0.into(), a1.into(), a2, a3 as _).await,
+            Sys::PWRITEV => self.sys_pwritev(a0.into(), a1.into(), a2, a3 as _).await,
+            Sys::PREADV2 => {
+                self.sys_preadv2(a0.into(), a1.into(), a2, a3 as _, a5)
+                    .await
+            }
+            Sys::PWRITEV2 => {
+                self.sys_pwritev2(a0.into(), a1.into(), a2, a3 as _, a5)
+                    .await
+            }
             Sys::SENDFILE => self.sys_sendfile(a0.into(), a1.into(), a2.into(), a3).await,
-            Sys::FCNTL => self.sys_fcntl(a0.into(), a1, a2),
-            Sys::FLOCK => self.sys_flock(a0.into(), a1),
+            Sys::FCNTL => self.sys_fcntl(a0.into(), a1, a2).await,
+            Sys::FLOCK => self.sys_flock(a0.into(), a1).await,
             Sys::FSYNC => self.sys_fsync(a0.into()),
             Sys::FDATASYNC => self.sys_fdatasync(a0.into()),
             Sys::TRUNCATE => self.sys_truncate(a0.into(), a1),
@@ -100,21 +122,39 @@ impl Syscall<'_> {
             Sys::GETDENTS64 => self.sys_getdents64(a0.into(), a1.into(), a2),
             Sys::GETCWD => self.sys_getcwd(a0.into(), a1),
             Sys::CHDIR => self.sys_chdir(a0.into()),
+            Sys::CHROOT => self.sys_chroot(a0.into()),
             Sys::RENAMEAT => self.sys_renameat(a0.into(), a1.into(), a2.into(), a3.into()),
             Sys::MKDIRAT => self.sys_mkdirat(a0.into(), a1.into(), a2),
+            Sys::MKNODAT => self.sys_mknodat(a0.into(), a1.into(), a2, a3),
             Sys::LINKAT => self.sys_linkat(a0.into(), a1.into(), a2.into(), a3.into(), a4),
             Sys::UNLINKAT => self.sys_unlinkat(a0.into(), a1.into(), a2),
             Sys::SYMLINKAT => self.unimplemented("symlinkat", Err(LxError::EACCES)),
             Sys::READLINKAT => self.sys_readlinkat(a0.into(), a1.into(), a2.into(), a3),
-            Sys::FCHMOD => self.unimplemented("fchmod", Ok(0)),
-            Sys::FCHMODAT => self.unimplemented("fchmodat", Ok(0)),
-            Sys::FCHOWN => self.unimplemented("fchown", Ok(0)),
-            Sys::FCHOWNAT => self.unimplemented("fchownat", Ok(0)),
+            Sys::FCHMOD => self.sys_fchmod(a0.into(), a1),
+            Sys::FCHMODAT => self.sys_fchmodat(a0.into(), a1.into(), a2),
+            Sys::FCHOWN => self.sys_fchown(a0.into(), a1, a2),
+            Sys::FCHOWNAT => self.sys_fchownat(a0.into(), a1.into(), a2, a3, a4),
             Sys::FACCESSAT => self.sys_faccessat(a0.into(), a1.into(), a2, a3),
+            Sys::SETXATTR => self.sys_setxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::LSETXATTR => self.sys_lsetxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::FSETXATTR => self.sys_fsetxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::GETXATTR => self.sys_getxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::LGETXATTR => self.sys_lgetxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::FGETXATTR => self.sys_fgetxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::LISTXATTR => self.sys_listxattr(a0.into(), a1.into(), a2),
+            Sys::LLISTXATTR => self.sys_llistxattr(a0.into(), a1.into(), a2),
+            Sys::FLISTXATTR => self.sys_flistxattr(a0.into(), a1.into(), a2),
+            Sys::REMOVEXATTR => self.sys_removexattr(a0.into(), a1.into()),
+            Sys::LREMOVEXATTR => self.sys_lremovexattr(a0.into(), a1.into()),
+            Sys::FREMOVEXATTR => self.sys_fremovexattr(a0.into(), a1.into()),
             Sys::DUP => self.sys_dup(a0.into()),
             Sys::DUP3 => self.sys_dup2(a0.into(), a1.into()), // TODO: handle `flags`
             Sys::PIPE2 => self.sys_pipe2(a0.into(), a1),      // TODO: handle `flags`
             Sys::UTIMENSAT => self.sys_utimensat(a0.into(), a1.into(), a2.into(), a3),
+            Sys::SPLICE => {
+                self.sys_splice(a0.into(), a1.into(), a2.into(), a3.into(), a4, a5)
+                    .await
+            }
             Sys::COPY_FILE_RANGE => {
                 self.sys_copy_file_range(a0.into(), a1.into(), a2.into(), a3.into(), a4, a5)
                     .await
@@ -125,30 +165,47 @@ impl Syscall<'_> {
                 self.sys_pselect6(a0, a1.into(), a2.into(), a3.into(), a4.into(), a5)
                     .await
             }
-            Sys::PPOLL => self.sys_ppoll(a0.into(), a1, a2.into()).await, // ignore sigmask
-            //            Sys::EPOLL_CREATE1 => self.sys_epoll_create1(a0),
-            //            Sys::EPOLL_CTL => self.sys_epoll_ctl(a0, a1, a2, a3.into()),
-            //            Sys::EPOLL_PWAIT => self.sys_epoll_pwait(a0, a1.into(), a2, a3, a4),
-            //            Sys::EVENTFD2 => self.unimplemented("eventfd2", Err(LxError::EACCES)),
+            Sys::PPOLL => {
+                self.sys_ppoll(a0.into(), a1, a2.into(), a3.into(), a4)
+                    .await
+            }
+            Sys::EPOLL_CREATE1 => self.sys_epoll_create1(a0),
+            Sys::EPOLL_CTL => self.sys_epoll_ctl(a0.into(), a1, a2.into(), a3.into()),
+            Sys::EPOLL_PWAIT => {
+                self.sys_epoll_pwait(a0.into(), a1.into(), a2, a3 as _, a4.into(), a5)
+                    .await
+            }
+            Sys::EVENTFD2 => self.sys_eventfd2(a0, a1),
+            Sys::INOTIFY_INIT1 => self.sys_inotify_init1(a0),
+            Sys::INOTIFY_ADD_WATCH => self.sys_inotify_add_watch(a0.into(), a1.into(), a2),
+            Sys::INOTIFY_RM_WATCH => self.sys_inotify_rm_watch(a0.into(), a1),
+            Sys::SIGNALFD4 => self.sys_signalfd4(a0.into(), a1.into(), a2, a3),
+            Sys::TIMERFD_CREATE => self.sys_timerfd_create(a0, a1),
+            Sys::TIMERFD_SETTIME => self.sys_timerfd_settime(a0.into(), a1, a2.into(), a3.into()),
+            Sys::TIMERFD_GETTIME => self.sys_timerfd_gettime(a0.into(), a1.into()),
+            Sys::IO_URING_SETUP => self.sys_io_uring_setup(a0, a1.into()),
+            Sys::IO_URING_ENTER => {
+                self.sys_io_uring_enter(a0.into(), a1, a2, a3, a4.into(), a5)
+                    .await
+            }
+            Sys::IO_URING_REGISTER => self.sys_io_uring_register(a0.into(), a1, a2, a3),
 
-            //            Sys::SOCKETPAIR => self.unimplemented("socketpair", Err(LxError::EACCES)),
             // file system
             Sys::STATFS => self.sys_statfs(a0.into(), a1.into()),
             Sys::FSTATFS => self.sys_fstatfs(a0.into(), a1.into()),
             Sys::SYNC => self.sys_sync(),
-            Sys::MOUNT => self.unimplemented("mount", Err(LxError::EACCES)),
-            Sys::UMOUNT2 => self.unimplemented("umount2", Err(LxError::EACCES)),
+            Sys::MOUNT => self.sys_mount(a0.into(), a1.into(), a2.into(), a3, a4.into()),
+            Sys::UMOUNT2 => self.sys_umount2(a0.into(), a1),
+            Sys::PIVOT_ROOT => self.sys_pivot_root(a0.into(), a1.into()),
 
             // memory
             Sys::BRK => self.unimplemented("brk", Err(LxError::ENOMEM)),
             Sys::MMAP => self.sys_mmap(a0, a1, a2, a3, a4.into(), a5 as _).await,
             Sys::MPROTECT => self.sys_mprotect(a0, a1, a2),
             Sys::MUNMAP => self.sys_munmap(a0, a1),
-            Sys::MADVISE => {
-                info!("madvise unimplemented");
-                Ok(0)
-            }
-            Sys::MREMAP => self.unimplemented("mremap", Err(LxError::ENOMEM)),
+            Sys::MSYNC => self.sys_msync(a0, a1, a2),
+            Sys::MADVISE => self.sys_madvise(a0, a1, a2),
+            Sys::MREMAP => self.sys_mremap(a0, a1, a2, a3, a4),
 
             // signal
             Sys::RT_SIGACTION => self.sys_rt_sigaction(a0, a1.into(), a2.into(), a3),
@@ -159,20 +216,21 @@ impl Syscall<'_> {
 
             // schedule
             Sys::SCHED_YIELD => self.unimplemented("yield", Ok(0)),
-            Sys::SCHED_GETAFFINITY => self.unimplemented("sched_getaffinity", Ok(0)),
-            Sys::SCHED_SETAFFINITY => self.unimplemented("sched_setaffinity", Ok(0)),
+            Sys::SCHED_GETAFFINITY => self.sys_sched_getaffinity(a0, a1, a2.into()),
+            Sys::SCHED_SETAFFINITY => self.sys_sched_setaffinity(a0, a1, a2.into()),
 
             // socket
             Sys::SOCKET => self.sys_socket(a0, a1, a2),
+            Sys::SOCKETPAIR => self.sys_socketpair(a0, a1, a2, a3.into()),
             Sys::CONNECT => self.sys_connect(a0, a1.into(), a2).await,
             Sys::ACCEPT => self.sys_accept(a0, a1.into(), a2.into()).await,
-            //            Sys::ACCEPT4 => self.sys_accept(a0, a1.into(), a2.into()), // use accept for accept4
+            Sys::ACCEPT4 => self.sys_accept4(a0, a1.into(), a2.into(), a3).await,
             Sys::SENDTO => self.sys_sendto(a0, a1.into(), a2, a3, a4.into(), a5),
             Sys::RECVFROM => {
                 self.sys_recvfrom(a0, a1.into(), a2, a3, a4.into(), a5.into())
                     .await
             }
-            Sys::SENDMSG => self.unimplemented("sys_sendmsg(),", Ok(0)),
+            Sys::SENDMSG => self.sys_sendmsg(a0, a1.into(), a2),
             Sys::RECVMSG => self.sys_recvmsg(a0, a1.into(), a2).await,
             Sys::SHUTDOWN => self.sys_shutdown(a0, a1),
             Sys::BIND => self.sys_bind(a0, a1.into(), a2),
@@ -188,20 +246,29 @@ impl Syscall<'_> {
             Sys::EXIT => self.sys_exit(a0 as _),
             Sys::EXIT_GROUP => self.sys_exit_group(a0 as _),
             Sys::WAIT4 => self.sys_wait4(a0 as _, a1.into(), a2 as _).await,
+            Sys::UNSHARE => self.sys_unshare(a0),
             Sys::SET_TID_ADDRESS => self.sys_set_tid_address(a0.into()),
             Sys::FUTEX => self.sys_futex(a0, a1 as _, a2 as _, a3, a4, a5 as _).await,
             Sys::GET_ROBUST_LIST => self.sys_get_robust_list(a0 as _, a1.into(), a2.into()),
             Sys::SET_ROBUST_LIST => self.sys_set_robust_list(a0.into(), a1 as _),
             Sys::TKILL => self.sys_tkill(a0, a1),
             Sys::TGKILL => self.sys_tgkill(a0, a1, a2),
+            Sys::PTRACE => self.sys_ptrace(a0, a1, a2, a3),
 
             // time
             Sys::NANOSLEEP => self.sys_nanosleep(a0.into()).await,
             Sys::CLOCK_NANOSLEEP => self.sys_clock_nanosleep(a0, a1, a2.into(), a3.into()).await,
-            Sys::SETITIMER => self.unimplemented("setitimer", Ok(0)),
+            Sys::GETITIMER => self.sys_getitimer(a0, a1.into()),
+            Sys::SETITIMER => self.sys_setitimer(a0, a1.into(), a2.into()),
+            Sys::TIMER_CREATE => self.sys_timer_create(a0, a1.into(), a2.into()),
+            Sys::TIMER_SETTIME => self.sys_timer_settime(a0, a1, a2.into(), a3.into()),
+            Sys::TIMER_GETTIME => self.sys_timer_gettime(a0, a1.into()),
+            Sys::TIMER_GETOVERRUN => self.sys_timer_getoverrun(a0),
+            Sys::TIMER_DELETE => self.sys_timer_delete(a0),
             Sys::GETTIMEOFDAY => self.sys_gettimeofday(a0.into(), a1.into()),
             Sys::CLOCK_GETTIME => self.sys_clock_gettime(a0, a1.into()),
-            Sys::CLOCK_GETRES => self.unimplemented("clock_getres", Ok(0)),
+            Sys::CLOCK_SETTIME => self.sys_clock_settime(a0, a1.into()),
+            Sys::CLOCK_GETRES => self.sys_clock_getres(a0, a1.into()),
 
             // sem
             #[cfg(not(target_arch = "mips"))]
@@ -217,37 +284,61 @@ impl Syscall<'_> {
             #[cfg(not(target_arch = "mips"))]
             Sys::SHMAT => self.sys_shmat(a0, a1, a2),
             #[cfg(not(target_arch = "mips"))]
-            Sys::SHMDT => self.sys_shmdt(a0, a1, a2),
+            Sys::SHMDT => self.sys_shmdt(a0),
             #[cfg(not(target_arch = "mips"))]
             Sys::SHMCTL => self.sys_shmctl(a0, a1, a2),
 
+            // msg
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGGET => self.sys_msgget(a0, a1),
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGSND => self.sys_msgsnd(a0, a1, a2, a3).await,
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGRCV => self.sys_msgrcv(a0, a1, a2, a3, a4).await,
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGCTL => self.sys_msgctl(a0, a1, a2),
+
             // system
             Sys::GETPID => self.sys_getpid(),
             Sys::GETTID => self.sys_gettid(),
             Sys::UNAME => self.sys_uname(a0.into()),
-            Sys::UMASK => self.unimplemented("umask", Ok(0o777)),
+            Sys::UMASK => self.sys_umask(a0),
             //            Sys::GETRLIMIT => self.sys_getrlimit(),
             //            Sys::SETRLIMIT => self.sys_setrlimit(),
             Sys::GETRUSAGE => self.sys_getrusage(a0, a1.into()),
             Sys::SYSINFO => self.sys_sysinfo(a0.into()),
             Sys::TIMES => self.sys_times(a0.into()),
-            Sys::GETUID => self.unimplemented("getuid", Ok(0)),
-            Sys::GETGID => self.unimplemented("getgid", Ok(0)),
-            Sys::SETUID => self.unimplemented("setuid", Ok(0)),
-            Sys::GETEUID => self.unimplemented("geteuid", Ok(0)),
-            Sys::GETEGID => self.unimplemented("getegid", Ok(0)),
-            Sys::SETPGID => self.unimplemented("setpgid", Ok(0)),
+            Sys::GETUID => self.sys_getuid(),
+            Sys::GETGID => self.sys_getgid(),
+            Sys::SETUID => self.sys_setuid(a0),
+            Sys::SETGID => self.sys_setgid(a0),
+            Sys::GETEUID => self.sys_geteuid(),
+            Sys::GETEGID => self.sys_getegid(),
+            Sys::SETREUID => self.sys_setreuid(a0, a1),
+            Sys::SETREGID => self.sys_setregid(a0, a1),
+            Sys::SETRESUID => self.sys_setresuid(a0, a1, a2),
+            Sys::GETRESUID => self.sys_getresuid(a0.into(), a1.into(), a2.into()),
+            Sys::SETRESGID => self.sys_setresgid(a0, a1, a2),
+            Sys::GETRESGID => self.sys_getresgid(a0.into(), a1.into(), a2.into()),
+            Sys::SETFSUID => self.sys_setfsuid(a0),
+            Sys::SETFSGID => self.sys_setfsgid(a0),
+            Sys::CAPGET => self.sys_capget(a0.into(), a1.into()),
+            Sys::CAPSET => self.sys_capset(a0.into(), a1.into()),
+            Sys::SETPGID => self.sys_setpgid(a0, a1),
             Sys::GETPPID => self.sys_getppid(),
-            Sys::SETSID => self.unimplemented("setsid", Ok(0)),
-            Sys::GETPGID => self.unimplemented("getpgid", Ok(0)),
-            Sys::GETGROUPS => self.unimplemented("getgroups", Ok(0)),
-            Sys::SETGROUPS => self.unimplemented("setgroups", Ok(0)),
+            Sys::SETSID => self.sys_setsid(),
+            Sys::GETPGID => self.sys_getpgid(a0),
+            Sys::GETSID => self.sys_getsid(a0),
+            Sys::GETGROUPS => self.sys_getgroups(a0, a1.into()),
+            Sys::SETGROUPS => self.sys_setgroups(a0, a1.into()),
             //            Sys::SETPRIORITY => self.sys_set_priority(a0),
-            Sys::PRCTL => self.unimplemented("prctl", Ok(0)),
+            Sys::PRCTL => self.sys_prctl(a0, a1, a2),
+            Sys::SECCOMP => self.sys_seccomp(a0, a1, a2),
             Sys::MEMBARRIER => self.unimplemented("membarrier", Ok(0)),
             Sys::PRLIMIT64 => self.sys_prlimit64(a0, a1, a2.into(), a3.into()),
             //            Sys::REBOOT => self.sys_reboot(a0 as u32, a1 as u32, a2 as u32, a3.into()),
             Sys::GETRANDOM => self.sys_getrandom(a0.into(), a1 as usize, a2 as u32),
+            Sys::SYSLOG => self.sys_syslog(a0 as i32, a1.into(), a2),
             Sys::RT_SIGQUEUEINFO => self.unimplemented("rt_sigqueueinfo", Ok(0)),
 
             // kernel module
@@ -259,12 +350,16 @@ impl Syscall<'_> {
 
             #[cfg(target_arch = "x86_64")]
             _ => self.x86_64_syscall(sys_type, args).await,
-            #[cfg(target_arch = "riscv64")]
+            #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
             _ => self.riscv64_syscall(sys_type, args).await,
             #[cfg(target_arch = "aarch64")]
             _ => self.aarch64_syscall(sys_type, args).await,
         };
         info!("<= {:?}", ret);
+        self.sigpipe_check(&sys_type, &args, &ret);
+        if let Some(entry) = strace {
+            entry.exit(&ret);
+        }
         match ret {
             Ok(value) => value as isize,
             Err(err) => -(err as isize),
@@ -287,7 +382,7 @@ impl Syscall<'_> {
     async fn x86_64_syscall(&mut self, sys_type: Sys, args: [usize; 6]) -> SysResult {
         let [a0, a1, a2, a3, a4, _a5] = args;
         match sys_type {
-            Sys::OPEN => self.sys_open(a0.into(), a1, a2),
+            Sys::OPEN => self.sys_open(a0.into(), a1, a2).await,
             Sys::STAT => self.sys_stat(a0.into(), a1.into()),
             Sys::LSTAT => self.sys_lstat(a0.into(), a1.into()),
             Sys::POLL => self.sys_poll(a0.into(), a1, a2 as _).await,
@@ -298,27 +393,34 @@ impl Syscall<'_> {
                     .await
             }
             Sys::DUP2 => self.sys_dup2(a0.into(), a1.into()),
-            //            Sys::ALARM => self.unimplemented("alarm", Ok(0)),
+            Sys::EVENTFD => self.sys_eventfd2(a0, 0),
+            Sys::INOTIFY_INIT => self.sys_inotify_init1(0),
+            Sys::SIGNALFD => self.sys_signalfd4(a0.into(), a1.into(), a2, 0),
+            Sys::ALARM => self.sys_alarm(a0),
+            Sys::GETPGRP => self.sys_getpgid(0),
             Sys::FORK => self.sys_fork(),
             Sys::VFORK => self.sys_vfork().await,
             Sys::RENAME => self.sys_rename(a0.into(), a1.into()),
             Sys::MKDIR => self.sys_mkdir(a0.into(), a1),
+            Sys::MKNOD => self.sys_mknod(a0.into(), a1, a2),
             Sys::RMDIR => self.sys_rmdir(a0.into()),
             Sys::LINK => self.sys_link(a0.into(), a1.into()),
             Sys::UNLINK => self.sys_unlink(a0.into()),
             Sys::READLINK => self.sys_readlink(a0.into(), a1.into(), a2),
-            Sys::CHMOD => self.unimplemented("chmod", Ok(0)),
-            Sys::CHOWN => self.unimplemented("chown", Ok(0)),
+            Sys::CHMOD => self.sys_chmod(a0.into(), a1),
+            Sys::CHOWN => self.sys_chown(a0.into(), a1, a2),
+            Sys::LCHOWN => self.sys_lchown(a0.into(), a1, a2),
             Sys::ARCH_PRCTL => self.sys_arch_prctl(a0 as _, a1),
             Sys::TIME => self.sys_time(a0.into()),
             Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a4, a3.into()),
-            //            Sys::EPOLL_CREATE => self.sys_epoll_create(a0),
-            //            Sys::EPOLL_WAIT => self.sys_epoll_wait(a0, a1.into(), a2, a3),
+            Sys::EPOLL_CREATE => self.sys_epoll_create(a0),
+            Sys::EPOLL_WAIT => self.sys_epoll_wait(a0.into(), a1.into(), a2, a3 as _).await,
             _ => self.unknown_syscall(sys_type),
         }
     }
 
-    #[cfg(target_arch = "riscv64")]
+    /// syscall specified for riscv64, and loongarch64 with the same generic table
+    #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
     async fn riscv64_syscall(&mut self, sys_type: Sys, args: [usize; 6]) -> SysResult {
         let [a0, a1, a2, a3, a4, _a5] = args;
         match sys_type {
@@ -1,10 +1,17 @@
 use super::*;
+use alloc::string::String;
 use bitflags::bitflags;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::time::Duration;
 use kernel_hal::timer::timer_now;
+use linux_object::fs::syslog_level;
 use linux_object::time::*;
+use zircon_object::debuglog::{DebugLog, DLOG_SIZE, FLAG_READABLE};
 use zircon_object::task::ThreadState;
 
+/// Records before it are cleared by `syslog`, but `/dev/kmsg` still reads them.
+static SYSLOG_CLEAR_SEQ: AtomicU64 = AtomicU64::new(0);
+
 impl Syscall<'_> {
     #[cfg(target_arch = "x86_64")]
     /// set architecture-specific thread state
@@ -39,6 +46,8 @@ impl Syscall<'_> {
             "aarch64"
         } else if cfg!(target_arch = "riscv64") {
             "riscv64"
+        } else if cfg!(target_arch = "loongarch64") {
+            "loongarch64"
         } else {
             "unknown"
         };
@@ -151,6 +160,11 @@ impl Syscall<'_> {
                 })?;
                 Ok(0)
             }
+            RLIMIT_CORE => {
+                let new_limit = new_limit.read_if_not_null()?;
+                old_limit.write_if_not_null(proc.core_limit(new_limit))?;
+                Ok(0)
+            }
             RLIMIT_NOFILE => {
                 let new_limit = new_limit.read_if_not_null()?;
                 old_limit.write_if_not_null(proc.file_limit(new_limit))?;
@@ -174,14 +188,93 @@ impl Syscall<'_> {
     /// - `flag` - a bit mask that can contain zero or more of the following values ORed together:
     ///   - GRND_RANDOM
     ///   - GRND_NONBLOCK
+    ///   - GRND_INSECURE
     /// - returns the number of bytes that were copied to the buffer buf.
+    ///
+    /// The bytes all come from the kernel entropy pool, which never blocks
+    /// once seeded at boot, so the flags are only checked.
     pub fn sys_getrandom(&mut self, mut buf: UserOutPtr<u8>, len: usize, flag: u32) -> SysResult {
         info!("getrandom: buf: {:?}, len: {:?}, flag {:?}", buf, len, flag);
+        let flags = GetRandomFlags::from_bits(flag).ok_or(LxError::EINVAL)?;
+        if flags.contains(GetRandomFlags::RANDOM | GetRandomFlags::INSECURE) {
+            return Err(LxError::EINVAL);
+        }
+        // as much as Linux returns at once
+        let len = len.min(33_554_431);
         let mut buffer = vec![0u8; len];
         kernel_hal::rand::fill_random(&mut buffer);
         buf.write_array(&buffer[..len])?;
         Ok(len)
     }
+
+    /// read and/or clear kernel message ring buffer
+    ///
+    /// Only the actions used by `dmesg` are supported, the console log level
+    /// actions do nothing.
+    pub fn sys_syslog(&self, type_: i32, mut buf: UserOutPtr<u8>, len: usize) -> SysResult {
+        info!("syslog: type: {}, buf: {:?}, len: {}", type_, buf, len);
+        const SYSLOG_ACTION_READ_ALL: i32 = 3;
+        const SYSLOG_ACTION_READ_CLEAR: i32 = 4;
+        const SYSLOG_ACTION_CLEAR: i32 = 5;
+        const SYSLOG_ACTION_SIZE_UNREAD: i32 = 9;
+        const SYSLOG_ACTION_SIZE_BUFFER: i32 = 10;
+        match type_ {
+            SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+                if (len as isize) < 0 {
+                    return Err(LxError::EINVAL);
+                }
+                let text = syslog_text();
+                // the newest messages if the buffer is too small
+                let text = &text.as_bytes()[text.len().saturating_sub(len)..];
+                buf.write_array(text)?;
+                if type_ == SYSLOG_ACTION_READ_CLEAR {
+                    SYSLOG_CLEAR_SEQ.store(zircon_object::debuglog::next_seq(), Ordering::Relaxed);
+                }
+                Ok(text.len())
+            }
+            SYSLOG_ACTION_CLEAR => {
+                SYSLOG_CLEAR_SEQ.store(zircon_object::debuglog::next_seq(), Ordering::Relaxed);
+                Ok(0)
+            }
+            SYSLOG_ACTION_SIZE_UNREAD => Ok(syslog_text().len()),
+            SYSLOG_ACTION_SIZE_BUFFER => Ok(DLOG_SIZE),
+            0 | 1 | 6 | 7 | 8 => Ok(0),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// Format the kernel log not cleared yet, one `<level>[seconds] message` per line.
+fn syslog_text() -> String {
+    let dlog = DebugLog::create(FLAG_READABLE);
+    let clear_seq = SYSLOG_CLEAR_SEQ.load(Ordering::Relaxed);
+    let mut text = String::new();
+    while let Some(record) = dlog.read_record() {
+        if record.seq < clear_seq {
+            continue;
+        }
+        let micros = record.timestamp / 1000;
+        text += &format!(
+            "<{}>[{:5}.{:06}] {}\n",
+            syslog_level(record.severity),
+            micros / 1_000_000,
+            micros % 1_000_000,
+            String::from_utf8_lossy(&record.data).trim_end_matches('\n'),
+        );
+    }
+    text
+}
+
+bitflags! {
+    /// for flag argument in getrandom()
+    struct GetRandomFlags: u32 {
+        /// do not block if no entropy is available
+        const NONBLOCK = 1;
+        /// draw from the blocking pool
+        const RANDOM = 2;
+        /// return bytes even before the pool is initialized
+        const INSECURE = 4;
+    }
 }
 
 bitflags! {
@@ -207,6 +300,7 @@ bitflags! {
 const USER_STACK_SIZE: usize = 8 * 1024 * 1024; // 8 MB, the default config of Linux
 
 const RLIMIT_STACK: usize = 3;
+const RLIMIT_CORE: usize = 4;
 const RLIMIT_RSS: usize = 5;
 const RLIMIT_NOFILE: usize = 7;
 const RLIMIT_AS: usize = 9;
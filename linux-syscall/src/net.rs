@@ -1,11 +1,29 @@
 use super::*;
+use alloc::vec::Vec;
 use core::mem::size_of;
-use kernel_hal::user::{IoVecs, UserInOutPtr};
+use kernel_hal::user::UserInOutPtr;
 use linux_object::{
     fs::{FileLike, OpenFlags},
     net::*,
 };
 
+/// The size of `struct cmsghdr`, before the data of a control message.
+const CMSG_HDR_LEN: usize = size_of::<usize>() + 2 * size_of::<i32>();
+
+/// Round up the length of a control message, as `CMSG_ALIGN`.
+fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// Append a control message with `data` to `control`, as `CMSG_SPACE` takes.
+fn push_cmsg(control: &mut Vec<u8>, level: i32, cmsg_type: i32, data: &[u8]) {
+    control.extend_from_slice(&(CMSG_HDR_LEN + data.len()).to_ne_bytes());
+    control.extend_from_slice(&level.to_ne_bytes());
+    control.extend_from_slice(&cmsg_type.to_ne_bytes());
+    control.extend_from_slice(data);
+    control.resize(cmsg_align(control.len()), 0);
+}
+
 impl Syscall<'_> {
     /// creates an endpoint for communication and returns a file descriptor that refers to that endpoint.
     pub fn sys_socket(&mut self, domain: usize, _type: usize, protocol: usize) -> SysResult {
@@ -31,6 +49,10 @@ impl Syscall<'_> {
         let flags = OpenFlags::from_bits_truncate(_type & !SOCKET_TYPE_MASK);
         let protocol = match Protocol::try_from(protocol) {
             Ok(protocol) => protocol,
+            Err(_) if domain == Domain::AF_NETLINK => {
+                warn!("unsupported netlink protocol: {protocol}");
+                return Err(LxError::EPROTONOSUPPORT);
+            }
             Err(_) => {
                 warn!("invalid protocol: {protocol}");
                 return Err(LxError::EINVAL);
@@ -45,17 +67,23 @@ impl Syscall<'_> {
             | (Domain::AF_INET, SocketType::SOCK_DGRAM, Protocol::IPPROTO_UDP) => {
                 Arc::new(UdpSocketState::new())
             }
-            /*
-            (AF_INET, SOCK_RAW, _) => {
-                Arc::new(RawSocketState::new(protocol as u8))
+            (Domain::AF_UNIX, SocketType::SOCK_STREAM, Protocol::IPPROTO_IP)
+            | (Domain::AF_UNIX, SocketType::SOCK_DGRAM, Protocol::IPPROTO_IP)
+            | (Domain::AF_UNIX, SocketType::SOCK_SEQPACKET, Protocol::IPPROTO_IP) => {
+                UnixSocketState::new(socket_type, self.ucred())
+            }
+            // NETLINK_ROUTE
+            (Domain::AF_NETLINK, SocketType::SOCK_RAW, Protocol::IPPROTO_IP)
+            | (Domain::AF_NETLINK, SocketType::SOCK_DGRAM, Protocol::IPPROTO_IP) => {
+                Arc::new(NetlinkSocketState::new(self.zircon_process().id() as u32))
             }
-            // TODO, UnixSocket
-            (AF_UNIX, SOCK_STREAM, Protocol::IPPROTO_IP) => {}
-            (AF_NETLINK, SOCK_RAW, _) => {
-                Arc::new(NetlinkSocketState::new())
+            // ping sockets
+            (Domain::AF_INET, SocketType::SOCK_DGRAM, Protocol::IPPROTO_ICMP) => {
+                Arc::new(IcmpSocketState::new())
+            }
+            (Domain::AF_INET, SocketType::SOCK_RAW, _) => {
+                Arc::new(RawSocketState::new(protocol as usize as u8))
             }
-            (AF_PACKET, SOCK_RAW, _) => {}
-            */
             (_, _, _) => {
                 warn!(
                     "unsupported socket type: domain={:?}, type={:?}, protocol={:?}",
@@ -69,6 +97,179 @@ impl Syscall<'_> {
         Ok(fd.into())
     }
 
+    /// creates a pair of connected unix sockets, returning their file descriptors in `sv`.
+    pub fn sys_socketpair(
+        &mut self,
+        domain: usize,
+        _type: usize,
+        protocol: usize,
+        mut sv: UserOutPtr<[i32; 2]>,
+    ) -> SysResult {
+        info!(
+            "sys_socketpair: domain:{}, type:{}, protocol:{}, sv:{:?}",
+            domain, _type, protocol, sv
+        );
+        match Domain::try_from(domain) {
+            Ok(Domain::AF_UNIX) => {}
+            Ok(_) => return Err(LxError::EOPNOTSUPP),
+            Err(_) => return Err(LxError::EAFNOSUPPORT),
+        }
+        let socket_type = match SocketType::try_from(_type & SOCKET_TYPE_MASK) {
+            Ok(t @ SocketType::SOCK_STREAM)
+            | Ok(t @ SocketType::SOCK_DGRAM)
+            | Ok(t @ SocketType::SOCK_SEQPACKET) => t,
+            _ => return Err(LxError::EINVAL),
+        };
+        if protocol != Protocol::IPPROTO_IP as usize {
+            return Err(LxError::EPROTONOSUPPORT);
+        }
+        let flags = OpenFlags::from_bits_truncate(_type & !SOCKET_TYPE_MASK);
+        let (a, b) = UnixSocketState::new_pair(socket_type, self.ucred());
+        let proc = self.linux_process();
+        let mut fds = [0; 2];
+        for (fd, socket) in fds.iter_mut().zip([a, b].iter()) {
+            socket.set_flags(flags)?;
+            *fd = proc.add_socket(socket.clone())?.into();
+        }
+        sv.write(fds)?;
+        Ok(0)
+    }
+
+    /// Credentials of the current process, passed along unix socket messages.
+    fn ucred(&self) -> UCred {
+        UCred {
+            pid: self.zircon_process().id() as u32,
+            uid: 0,
+            gid: 0,
+        }
+    }
+
+    /// Read a socket address, turning a relative unix socket path into an absolute one.
+    fn read_endpoint(&self, addr: UserInPtr<SockAddr>, addrlen: usize) -> LxResult<Endpoint> {
+        match sockaddr_to_endpoint(addr.read()?, addrlen)? {
+            Endpoint::Unix(UnixEndpoint::Path(path)) if !path.starts_with('/') => {
+                let cwd = self.linux_process().current_working_directory();
+                let path = path.trim_start_matches("./");
+                Ok(Endpoint::Unix(UnixEndpoint::Path(format!(
+                    "{}/{}",
+                    cwd.trim_end_matches('/'),
+                    path
+                ))))
+            }
+            endpoint => Ok(endpoint),
+        }
+    }
+
+    /// Parse the control messages given to `sendmsg()`.
+    fn read_ancillary(&self, control: UserInPtr<u8>, len: usize) -> LxResult<Ancillary> {
+        let mut ancillary = Ancillary::default();
+        if control.is_null() || len == 0 {
+            return Ok(ancillary);
+        }
+        let control = control.as_slice(len)?;
+        let mut offset = 0;
+        while offset + CMSG_HDR_LEN <= control.len() {
+            let field = |start: usize, len: usize| &control[offset + start..offset + start + len];
+            let mut cmsg_len = [0u8; size_of::<usize>()];
+            cmsg_len.copy_from_slice(field(0, size_of::<usize>()));
+            let cmsg_len = usize::from_ne_bytes(cmsg_len);
+            let mut level = [0u8; 4];
+            level.copy_from_slice(field(size_of::<usize>(), 4));
+            let mut cmsg_type = [0u8; 4];
+            cmsg_type.copy_from_slice(field(size_of::<usize>() + 4, 4));
+            if cmsg_len < CMSG_HDR_LEN || offset + cmsg_len > control.len() {
+                return Err(LxError::EINVAL);
+            }
+            let data = field(CMSG_HDR_LEN, cmsg_len - CMSG_HDR_LEN);
+            match (i32::from_ne_bytes(level), i32::from_ne_bytes(cmsg_type)) {
+                (SOL_SOCKET, SCM_RIGHTS) => {
+                    let fds = data.chunks_exact(size_of::<i32>());
+                    if ancillary.rights.len() + fds.len() > SCM_MAX_FD {
+                        return Err(LxError::EINVAL);
+                    }
+                    for fd in fds {
+                        let fd = i32::from_ne_bytes([fd[0], fd[1], fd[2], fd[3]]);
+                        let file = self.linux_process().get_file_like(fd.into())?;
+                        ancillary.rights.push(file);
+                    }
+                }
+                (SOL_SOCKET, SCM_CREDENTIALS) => {
+                    if data.len() < size_of::<UCred>() {
+                        return Err(LxError::EINVAL);
+                    }
+                    let word = |i: usize| {
+                        u32::from_ne_bytes([
+                            data[i * 4],
+                            data[i * 4 + 1],
+                            data[i * 4 + 2],
+                            data[i * 4 + 3],
+                        ])
+                    };
+                    let cred = UCred {
+                        pid: word(0),
+                        uid: word(1),
+                        gid: word(2),
+                    };
+                    // no process can speak for another
+                    if cred != self.ucred() {
+                        return Err(LxError::EPERM);
+                    }
+                    ancillary.cred = Some(cred);
+                }
+                _ => return Err(LxError::EINVAL),
+            }
+            offset += cmsg_align(cmsg_len);
+        }
+        Ok(ancillary)
+    }
+
+    /// Install the files and write the control messages received by `recvmsg()`.
+    ///
+    /// Returns the length of the control messages written, and whether some were truncated.
+    fn write_ancillary(
+        &self,
+        ancillary: Ancillary,
+        mut control: UserOutPtr<u8>,
+        len: usize,
+        flags: MsgFlags,
+    ) -> LxResult<(usize, bool)> {
+        let mut buf = Vec::new();
+        let mut truncated = false;
+        if !ancillary.rights.is_empty() {
+            let room = len.saturating_sub(CMSG_HDR_LEN) / size_of::<i32>();
+            truncated |= room < ancillary.rights.len();
+            let mut fds = Vec::new();
+            // the files left out are closed
+            for file in ancillary.rights.into_iter().take(room) {
+                if flags.contains(MsgFlags::CMSG_CLOEXEC) {
+                    file.set_flags(file.flags() | OpenFlags::CLOEXEC)?;
+                }
+                let fd: i32 = self.linux_process().add_file(file)?.into();
+                fds.extend_from_slice(&fd.to_ne_bytes());
+            }
+            if !fds.is_empty() {
+                push_cmsg(&mut buf, SOL_SOCKET, SCM_RIGHTS, &fds);
+            }
+        }
+        if let Some(cred) = ancillary.cred {
+            let mut data = Vec::new();
+            for word in [cred.pid, cred.uid, cred.gid].iter() {
+                data.extend_from_slice(&word.to_ne_bytes());
+            }
+            if buf.len() + CMSG_HDR_LEN + data.len() <= len {
+                push_cmsg(&mut buf, SOL_SOCKET, SCM_CREDENTIALS, &data);
+            } else {
+                truncated = true;
+            }
+        }
+        // the padding of the last message may not fit
+        buf.truncate(len);
+        if !buf.is_empty() {
+            control.write_array(&buf)?;
+        }
+        Ok((buf.len(), truncated))
+    }
+
     ///  connects the socket referred to by the file descriptor sockfd to the address specified by addr.
     pub async fn sys_connect(
         &mut self,
@@ -80,7 +281,7 @@ impl Syscall<'_> {
             "sys_connect: sockfd:{}, addr:{:?}, addrlen:{}",
             sockfd, addr, addrlen
         );
-        let endpoint = sockaddr_to_endpoint(addr.read()?, addrlen)?;
+        let endpoint = self.read_endpoint(addr, addrlen)?;
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
         file_like.clone().as_socket()?.connect(endpoint).await?;
         Ok(0)
@@ -100,10 +301,28 @@ impl Syscall<'_> {
             sockfd, level, optname, optval, optlen
         );
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
-        file_like
-            .clone()
-            .as_socket()?
-            .setsockopt(level, optname, optval.as_slice(optlen)?)
+        let socket = file_like.as_socket()?;
+        if let (Ok(Level::SOL_SOCKET), Ok(optname)) =
+            (Level::try_from(level), SolOptname::try_from(optname))
+        {
+            if let Some(mut options) = socket.socket_options() {
+                let value = match optval.as_slice(optlen)? {
+                    [a, b, c, d, ..] => u32::from_ne_bytes([*a, *b, *c, *d]),
+                    _ => return Err(LxError::EINVAL),
+                };
+                match optname {
+                    SolOptname::REUSEADDR => options.reuse_addr = value != 0,
+                    SolOptname::PASSCRED => options.pass_cred = value != 0,
+                    SolOptname::SNDBUF => options.send_buf = Some(value as usize),
+                    SolOptname::RCVBUF => options.recv_buf = Some(value as usize),
+                    _ => {
+                        return socket.setsockopt(level, optname as usize, optval.as_slice(optlen)?)
+                    }
+                }
+                return socket.set_socket_options(options);
+            }
+        }
+        socket.setsockopt(level, optname, optval.as_slice(optlen)?)
     }
 
     /// get options for the socket referred to by the file descriptor sockfd.
@@ -140,26 +359,45 @@ impl Syscall<'_> {
                 };
 
                 let file_like = self.linux_process().get_file_like(sockfd.into())?;
-                let (recv_buf_ca, send_buf_ca) = file_like
-                    .clone()
-                    .as_socket()?
-                    .get_buffer_capacity()
-                    .unwrap();
-                debug!("sys_getsockopt recv and send buffer capacity: {}, {}. optval: {:?}, optlen: {:?}", recv_buf_ca, send_buf_ca, optval.check(), optlen.check());
-
-                match optname {
-                    SolOptname::SNDBUF => {
-                        optval.write(send_buf_ca as u32)?;
-                        optlen.write(size_of::<u32>() as u32)?;
-                        Ok(0)
+                let socket = file_like.as_socket()?;
+                if optname == SolOptname::PEERCRED {
+                    let cred = socket.peer_cred().ok_or(LxError::ENOTCONN)?;
+                    let mut optval: UserOutPtr<UCred> = optval.as_addr().into();
+                    optval.write(cred)?;
+                    optlen.write(size_of::<UCred>() as u32)?;
+                    return Ok(0);
+                }
+                let value = match optname {
+                    SolOptname::REUSEADDR => {
+                        let options = socket.socket_options().ok_or(LxError::ENOPROTOOPT)?;
+                        options.reuse_addr as u32
                     }
-                    SolOptname::RCVBUF => {
-                        optval.write(recv_buf_ca as u32)?;
-                        optlen.write(size_of::<u32>() as u32)?;
-                        Ok(0)
+                    SolOptname::PASSCRED => {
+                        let options = socket.socket_options().ok_or(LxError::ENOPROTOOPT)?;
+                        options.pass_cred as u32
                     }
-                    _ => Err(LxError::ENOPROTOOPT),
-                }
+                    SolOptname::TYPE => socket.socket_type().ok_or(LxError::ENOPROTOOPT)? as u32,
+                    SolOptname::ERROR => socket.take_error().map_or(0, |err| err as u32),
+                    SolOptname::SNDBUF | SolOptname::RCVBUF => {
+                        let (recv_buf_ca, send_buf_ca) =
+                            socket.get_buffer_capacity().ok_or(LxError::ENOPROTOOPT)?;
+                        let options = socket.socket_options().unwrap_or_default();
+                        // the size asked is doubled for the bookkeeping, as Linux
+                        let asked = match optname {
+                            SolOptname::SNDBUF => options.send_buf,
+                            _ => options.recv_buf,
+                        };
+                        let capacity = match optname {
+                            SolOptname::SNDBUF => send_buf_ca,
+                            _ => recv_buf_ca,
+                        };
+                        asked.map_or(capacity, |size| size * 2) as u32
+                    }
+                    _ => return Err(LxError::ENOPROTOOPT),
+                };
+                optval.write(value)?;
+                optlen.write(size_of::<u32>() as u32)?;
+                Ok(0)
             }
             Level::IPPROTO_TCP => {
                 let optname = match TcpOptname::try_from(optname) {
@@ -205,7 +443,7 @@ impl Syscall<'_> {
         let endpoint = if dest_addr.is_null() {
             None
         } else {
-            let endpoint = sockaddr_to_endpoint(dest_addr.read()?, addrlen)?;
+            let endpoint = self.read_endpoint(dest_addr, addrlen)?;
             Some(endpoint)
         };
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
@@ -232,45 +470,74 @@ impl Syscall<'_> {
         );
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
         debug!("FileLike {} flags: {:?}", sockfd, file_like.flags());
+        let flags = MsgFlags::from_bits_truncate(flags);
         let mut data = vec![0u8; len];
-        let (result, endpoint) = file_like.clone().as_socket()?.read(&mut data).await;
-        if result.is_ok() && !src_addr.is_null() {
+        let (result, endpoint) = file_like.as_socket()?.read(&mut data, flags).await;
+        let len = result?;
+        if !src_addr.is_null() {
             let sockaddr_in = SockAddr::from(endpoint);
             sockaddr_in.write_to(src_addr, addrlen)?;
         }
         buf.write_array(&data[..len])?;
-        result
+        Ok(len)
     }
 
-    /// receive messages from a socket
+    /// transmit a message to another socket, gathered from the buffers of `msg`
+    pub fn sys_sendmsg(
+        &mut self,
+        sockfd: usize,
+        msg: UserInPtr<MsgHdr>,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "sys_sendmsg: sockfd:{}, msg:{:?}, flags:{}",
+            sockfd, msg, flags
+        );
+        let hdr = msg.read()?;
+        let endpoint = if hdr.msg_name.is_null() {
+            None
+        } else {
+            let name: UserInPtr<SockAddr> = hdr.msg_name.as_addr().into();
+            Some(self.read_endpoint(name, hdr.msg_namelen as usize)?)
+        };
+        let iov_ptr: UserInPtr<IoVecIn> = hdr.msg_iov.as_addr().into();
+        let data = iov_ptr.read_iovecs(hdr.msg_iovlen)?.read_to_vec()?;
+        let ancillary = self.read_ancillary(hdr.msg_control.into(), hdr.msg_controllen)?;
+        let file_like = self.linux_process().get_file_like(sockfd.into())?;
+        file_like.as_socket()?.write_msg(&data, endpoint, ancillary)
+    }
+
+    /// receive messages from a socket, scattered to the buffers of `msg`
     pub async fn sys_recvmsg(
         &mut self,
         sockfd: usize,
-        msg: UserInOutPtr<MsgHdr>,
+        mut msg: UserInOutPtr<MsgHdr>,
         flags: usize,
     ) -> SysResult {
         info!(
             "sys_recvmsg: sockfd:{}, msg:{:?}, flags:{}",
             sockfd, msg, flags
         );
-        let hdr = msg.read().unwrap();
-
-        let iov_ptr = hdr.msg_iov;
-        let iovlen = hdr.msg_iovlen;
-        let mut iovs = IoVecs::new(iov_ptr, iovlen);
-        let mut data = vec![0u8; 8192];
+        let hdr = msg.read()?;
+        let mut iovs = hdr.msg_iov.read_iovecs(hdr.msg_iovlen)?;
+        let mut data = vec![0u8; iovs.total_len()];
 
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
-        let (result, endpoint) = file_like.clone().as_socket()?.read(&mut data).await;
-
-        let addr = hdr.msg_name;
-        if result.is_ok() && !addr.is_null() {
-            iovs.write_from_buf(&data).unwrap();
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let (result, endpoint, ancillary) = file_like.as_socket()?.read_msg(&mut data, flags).await;
+        let len = result?;
+        iovs.write_from_buf(&data[..len.min(data.len())])?;
+        if !hdr.msg_name.is_null() {
             let sockaddr_in = SockAddr::from(endpoint);
-            sockaddr_in.write_to_msg(msg)?;
+            sockaddr_in.write_to_msg(msg.as_addr().into())?;
         }
-
-        result
+        let (controllen, truncated) =
+            self.write_ancillary(ancillary, hdr.msg_control.into(), hdr.msg_controllen, flags)?;
+        let mut hdr = msg.read()?;
+        hdr.msg_controllen = controllen;
+        hdr.msg_flags = if truncated { MSG_CTRUNC } else { 0 };
+        msg.write(hdr)?;
+        Ok(len)
     }
 
     /// assigns the address specified by addr to the socket referred to by the file descriptor sockfd
@@ -284,7 +551,7 @@ impl Syscall<'_> {
             "sys_bind: sockfd:{:?}, addr:{:?}, addrlen:{}",
             sockfd, addr, addrlen
         );
-        let endpoint = sockaddr_to_endpoint(addr.read()?, addrlen)?;
+        let endpoint = self.read_endpoint(addr, addrlen)?;
         debug!("sys_bind: fd:{} bind to {:?}", sockfd, endpoint);
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
         file_like.clone().as_socket()?.bind(endpoint)
@@ -297,15 +564,15 @@ impl Syscall<'_> {
         // smoltcp tcp sockets do not support backlog
         // open multiple sockets for each connection
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
-        file_like.clone().as_socket()?.listen()
+        file_like.as_socket()?.listen(backlog)
     }
 
     /// shutdown a socket
     pub fn sys_shutdown(&mut self, sockfd: usize, howto: usize) -> SysResult {
         info!("sys_shutdown: sockfd:{}, howto:{}", sockfd, howto);
-        // todo: how to use 'howto'
+        let how = Shutdown::try_from(howto).map_err(|_| LxError::EINVAL)?;
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
-        file_like.clone().as_socket()?.shutdown()
+        file_like.as_socket()?.shutdown(how)
     }
 
     /// accept() is used with connection-based socket types (SOCK_STREAM, SOCK_SEQPACKET).
@@ -319,15 +586,31 @@ impl Syscall<'_> {
         sockfd: usize,
         addr: UserOutPtr<SockAddr>,
         addrlen: UserInOutPtr<u32>,
+    ) -> SysResult {
+        self.sys_accept4(sockfd, addr, addrlen, 0).await
+    }
+
+    /// accept() with `SOCK_NONBLOCK` or `SOCK_CLOEXEC` in `flags` set on the new socket.
+    pub async fn sys_accept4(
+        &mut self,
+        sockfd: usize,
+        addr: UserOutPtr<SockAddr>,
+        addrlen: UserInOutPtr<u32>,
+        flags: usize,
     ) -> SysResult {
         info!(
-            "sys_accept: sockfd:{}, addr:{:?}, addrlen={:?}",
-            sockfd, addr, addrlen
+            "sys_accept4: sockfd:{}, addr:{:?}, addrlen={:?}, flags={:#x}",
+            sockfd, addr, addrlen, flags
         );
+        let valid_flags = SocketType::SOCK_NONBLOCK as usize | SocketType::SOCK_CLOEXEC as usize;
+        if flags & !valid_flags != 0 {
+            return Err(LxError::EINVAL);
+        }
         // smoltcp tcp sockets do not support backlog
         // open multiple sockets for each connection
         let file_like = self.linux_process().get_file_like(sockfd.into())?;
-        let (new_socket, remote_endpoint) = file_like.clone().as_socket()?.accept().await?;
+        let (new_socket, remote_endpoint) = file_like.as_socket()?.accept().await?;
+        new_socket.set_flags(new_socket.flags() | OpenFlags::from_bits_truncate(flags))?;
         debug!(
             "FileLike{} flags: {:?}, New flags: {:?}",
             sockfd,
@@ -0,0 +1,168 @@
+//! Syscalls of seccomp and the checks on the syscall entry
+//!
+//! - seccomp
+//! - prctl
+
+use super::*;
+use kernel_hal::context::UserContextField;
+use linux_object::seccomp::*;
+use linux_object::signal::Signal as LinuxSignal;
+use linux_object::thread::{CurrentThreadExt, ThreadExt};
+
+/// `struct sock_fprog`, a classic BPF program in the user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SockFprog {
+    len: u16,
+    filter: usize,
+}
+
+impl Syscall<'_> {
+    /// Check the syscall against the seccomp mode of the process.
+    ///
+    /// Returns `None` if the syscall is allowed, otherwise the value returned
+    /// instead of it.
+    pub(crate) fn seccomp_check(
+        &self,
+        sys_type: &Sys,
+        num: u32,
+        args: &[usize; 6],
+    ) -> Option<isize> {
+        let linux = self.linux_process();
+        if linux.is_seccomp_strict() {
+            if matches!(
+                sys_type,
+                Sys::READ | Sys::WRITE | Sys::EXIT | Sys::RT_SIGRETURN
+            ) {
+                return None;
+            }
+            warn!("seccomp: {:?} is not allowed in the strict mode", sys_type);
+            self.zircon_process()
+                .exit_by_signal(LinuxSignal::SIGKILL, false);
+            return Some(-(LxError::ENOSYS as isize));
+        }
+        if linux.seccomp_mode() == 0 {
+            return None;
+        }
+        let mut data = SeccompData {
+            nr: num as i32,
+            arch: AUDIT_ARCH_CURRENT,
+            instruction_pointer: self
+                .thread
+                .with_context(|cx| cx.get_field(UserContextField::InstrPointer))
+                .unwrap_or(0) as u64,
+            args: [0; 6],
+        };
+        for (dst, src) in data.args.iter_mut().zip(args.iter()) {
+            *dst = *src as u64;
+        }
+        let ret = linux.seccomp_filter(&data);
+        let action = ret & SECCOMP_RET_ACTION_FULL;
+        match action {
+            SECCOMP_RET_ALLOW => None,
+            SECCOMP_RET_LOG => {
+                info!("seccomp: {:?} allowed with logging", sys_type);
+                None
+            }
+            SECCOMP_RET_ERRNO => Some(-((ret & SECCOMP_RET_DATA).min(4095) as isize)),
+            SECCOMP_RET_TRAP => {
+                self.thread.send_signal(LinuxSignal::SIGSYS);
+                Some(-(LxError::ENOSYS as isize))
+            }
+            // no tracer or supervisor can handle the syscall
+            SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => Some(-(LxError::ENOSYS as isize)),
+            SECCOMP_RET_KILL_THREAD if self.zircon_process().thread_ids().len() > 1 => {
+                warn!("seccomp: {:?} killed the thread", sys_type);
+                self.thread.exit_linux(0);
+                Some(-(LxError::ENOSYS as isize))
+            }
+            _ => {
+                warn!("seccomp: {:?} killed the process", sys_type);
+                self.zircon_process()
+                    .exit_by_signal(LinuxSignal::SIGSYS, false);
+                Some(-(LxError::ENOSYS as isize))
+            }
+        }
+    }
+
+    /// Operate on the secure computing state of the process
+    /// (see [linux man seccomp(2)](https://man7.org/linux/man-pages/man2/seccomp.2.html)).
+    pub fn sys_seccomp(&mut self, op: usize, flags: usize, args: usize) -> SysResult {
+        const SECCOMP_SET_MODE_STRICT: usize = 0;
+        const SECCOMP_SET_MODE_FILTER: usize = 1;
+        const SECCOMP_GET_ACTION_AVAIL: usize = 2;
+        const SECCOMP_FILTER_FLAG_TSYNC: usize = 1;
+        const SECCOMP_FILTER_FLAG_LOG: usize = 2;
+        info!("seccomp: op={}, flags={:#x}, args={:#x}", op, flags, args);
+        match op {
+            SECCOMP_SET_MODE_STRICT if flags == 0 && args == 0 => {
+                self.linux_process().set_seccomp_strict()?;
+                Ok(0)
+            }
+            SECCOMP_SET_MODE_FILTER
+                if flags & !(SECCOMP_FILTER_FLAG_TSYNC | SECCOMP_FILTER_FLAG_LOG) == 0 =>
+            {
+                self.set_seccomp_filter(args)?;
+                Ok(0)
+            }
+            SECCOMP_GET_ACTION_AVAIL if flags == 0 => {
+                let action = UserInPtr::<u32>::from(args).read()?;
+                match action {
+                    SECCOMP_RET_KILL_PROCESS
+                    | SECCOMP_RET_KILL_THREAD
+                    | SECCOMP_RET_TRAP
+                    | SECCOMP_RET_ERRNO
+                    | SECCOMP_RET_LOG
+                    | SECCOMP_RET_ALLOW => Ok(0),
+                    _ => Err(LxError::EOPNOTSUPP),
+                }
+            }
+            _ => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Operations on the process
+    /// (see [linux man prctl(2)](https://man7.org/linux/man-pages/man2/prctl.2.html)).
+    ///
+    /// Only the options of seccomp and `no_new_privs` are supported, the
+    /// others do nothing.
+    pub fn sys_prctl(&mut self, option: usize, arg2: usize, arg3: usize) -> SysResult {
+        const PR_GET_SECCOMP: usize = 21;
+        const PR_SET_SECCOMP: usize = 22;
+        const PR_SET_NO_NEW_PRIVS: usize = 38;
+        const PR_GET_NO_NEW_PRIVS: usize = 39;
+        const SECCOMP_MODE_STRICT: usize = 1;
+        const SECCOMP_MODE_FILTER: usize = 2;
+        info!(
+            "prctl: option={}, arg2={:#x}, arg3={:#x}",
+            option, arg2, arg3
+        );
+        let linux = self.linux_process();
+        match option {
+            PR_GET_SECCOMP => Ok(linux.seccomp_mode()),
+            PR_SET_SECCOMP => {
+                match arg2 {
+                    SECCOMP_MODE_STRICT => linux.set_seccomp_strict()?,
+                    SECCOMP_MODE_FILTER => self.set_seccomp_filter(arg3)?,
+                    _ => return Err(LxError::EINVAL),
+                }
+                Ok(0)
+            }
+            PR_SET_NO_NEW_PRIVS if arg2 == 1 => {
+                linux.set_no_new_privs();
+                Ok(0)
+            }
+            PR_SET_NO_NEW_PRIVS => Err(LxError::EINVAL),
+            PR_GET_NO_NEW_PRIVS => Ok(linux.no_new_privs() as usize),
+            _ => self.unimplemented("prctl", Ok(0)),
+        }
+    }
+
+    /// Install the filter of the `struct sock_fprog` at `prog`.
+    fn set_seccomp_filter(&self, prog: usize) -> LxResult {
+        let prog = UserInPtr::<SockFprog>::from(prog).read()?;
+        let insns = UserInPtr::<SockFilter>::from(prog.filter).read_array(prog.len as usize)?;
+        let filter = SeccompFilter::new(insns)?;
+        self.linux_process().add_seccomp_filter(filter)
+    }
+}
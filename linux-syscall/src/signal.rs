@@ -6,8 +6,11 @@
 //! - kill
 //! - tkill
 //! - sigaltstack
+//! - signalfd4
 
 use super::*;
+use alloc::vec::Vec;
+use linux_object::fs::{SignalFd, SignalFdFlags};
 use linux_object::signal::{Signal, SignalAction, SignalStack, SignalStackFlags, Sigset};
 use linux_object::thread::ThreadExt;
 use numeric_enum_macro::numeric_enum;
@@ -121,8 +124,50 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// Create a file descriptor that can be used to accept signals targeted at the caller.
+    ///
+    /// If `fd` is -1, a new file descriptor is created, otherwise the mask of
+    /// the existing signalfd is replaced.
+    pub fn sys_signalfd4(
+        &self,
+        fd: FileDesc,
+        mask: UserInPtr<Sigset>,
+        sizemask: usize,
+        flags: usize,
+    ) -> SysResult {
+        let flags = SignalFdFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!(
+            "signalfd4: fd={:?}, mask={:?}, sizemask={}, flags={:?}",
+            fd, mask, sizemask, flags
+        );
+        if sizemask != core::mem::size_of::<Sigset>() {
+            return Err(LxError::EINVAL);
+        }
+        let mut mask = mask.read()?;
+        // SIGKILL and SIGSTOP can not be received via a signalfd
+        mask.remove(Signal::SIGKILL);
+        mask.remove(Signal::SIGSTOP);
+        let proc = self.linux_process();
+        if i32::from(fd) == -1 {
+            let signalfd = SignalFd::new(mask, flags, proc.event_bus());
+            let fd = proc.add_file(signalfd)?;
+            Ok(fd.into())
+        } else {
+            let signalfd = proc
+                .get_file_like(fd)?
+                .downcast_arc::<SignalFd>()
+                .map_err(|_| LxError::EINVAL)?;
+            signalfd.set_mask(mask);
+            Ok(fd.into())
+        }
+    }
+
     /// Send a signal to a process specified by pid
-    /// TODO: support all the arguments
+    ///
+    /// - `pid > 0`: the process `pid`
+    /// - `pid == 0`: every process in the process group of the caller
+    /// - `pid == -1`: every process except the init process
+    /// - `pid < -1`: every process in the process group `-pid`
     pub fn sys_kill(&self, pid: isize, signum: usize) -> SysResult {
         let signal = Signal::try_from(signum as u8).map_err(|_| LxError::EINVAL)?;
         info!(
@@ -131,64 +176,51 @@ impl Syscall<'_> {
             pid,
             signal
         );
-        warn!(
-            "The sys_kill only supports killing a process (SIGKILL) or sending a signal to
-            an arbitrary thread of a process within the same job as the calling thread. 
-            As for the latter, the signal will be delivered to an arbitrarily selected thread 
-            in the target process that is not blocking the signal."
-        );
         enum SendTarget {
             EveryProcessInGroup,
             EveryProcess,
             EveryProcessInGroupByPID(KoID),
             Pid(KoID),
         }
+        let parent = self.zircon_process().clone();
+        let pid_ns = parent.linux().pid_ns();
+        let koid_of = |pid: isize| pid_ns.koid_of(pid as KoID).ok_or(LxError::ESRCH);
         let target = match pid {
-            p if p > 0 => SendTarget::Pid(p as KoID),
+            p if p > 0 => SendTarget::Pid(koid_of(p)?),
             0 => SendTarget::EveryProcessInGroup,
             -1 => SendTarget::EveryProcess,
-            p if p < -1 => SendTarget::EveryProcessInGroupByPID((-p) as KoID),
-            _ => unimplemented!(),
+            p => SendTarget::EveryProcessInGroupByPID(koid_of(-p)?),
         };
-        let parent = self.zircon_process().clone();
-        match target {
-            SendTarget::Pid(pid) => {
-                match parent.job().get_child(pid as u64) {
-                    Ok(obj) => {
-                        match signal {
-                            Signal::SIGKILL => {
-                                let current_pid = parent.id();
-                                if current_pid == (pid as u64) {
-                                    // killing myself
-                                    parent.exit((128 + Signal::SIGKILL as i32) as i64);
-                                } else {
-                                    let process: Arc<Process> = obj.downcast_arc().unwrap();
-                                    process.exit((128 + Signal::SIGKILL as i32) as i64);
-                                }
-                            }
-                            sig => {
-                                let process: Arc<Process> = obj.downcast_arc().unwrap();
-                                let tids = process.thread_ids();
-                                for tid in tids {
-                                    let thread = process.get_child(tid).unwrap();
-                                    let thread: Arc<Thread> = thread.downcast_arc().unwrap();
-                                    let mut thread_linux = thread.lock_linux();
-                                    if thread_linux.signal_mask.contains(sig) {
-                                        continue;
-                                    } else {
-                                        thread_linux.signals.insert(signal);
-                                        break;
-                                    }
-                                }
-                            }
-                        };
-                        Ok(0)
-                    }
-                    Err(_) => Err(LxError::EINVAL),
-                }
-            }
-            _ => unimplemented!(),
+        let job = parent.job();
+        let targets: Vec<Arc<Process>> = match target {
+            SendTarget::Pid(pid) => match job.get_child(pid as u64) {
+                Ok(obj) => vec![obj.downcast_arc().unwrap()],
+                Err(_) => return Err(LxError::EINVAL),
+            },
+            SendTarget::EveryProcessInGroup => process_group(&job, parent.linux().pgid()),
+            SendTarget::EveryProcessInGroupByPID(pgid) => process_group(&job, pgid),
+            SendTarget::EveryProcess => job
+                .process_ids()
+                .into_iter()
+                .filter_map(|pid| job.get_child(pid).ok())
+                .filter_map(|obj| obj.downcast_arc::<Process>().ok())
+                // except the init process, and the processes not in the
+                // PID namespace of the caller
+                .filter(|proc| {
+                    proc.linux().parent().is_some()
+                        && matches!(pid_ns.pid_of(proc.id()), Some(pid) if pid != 1)
+                })
+                .collect(),
+        };
+        if targets.is_empty() {
+            return Err(LxError::ESRCH);
+        }
+        // the signal is delivered to an arbitrarily selected thread
+        // of each target process that is not blocking the signal
+        for proc in targets {
+            proc.send_signal(signal);
         }
+        Ok(0)
     }
 
     /// Send a signal to a thread specified by tid
@@ -204,9 +236,7 @@ impl Syscall<'_> {
         match parent.get_child(tid as u64) {
             Ok(obj) => {
                 let thread: Arc<Thread> = obj.downcast_arc().unwrap();
-                let mut thread_linux = thread.lock_linux();
-                thread_linux.signals.insert(signal);
-                drop(thread_linux);
+                thread.send_signal(signal);
                 Ok(0)
             }
             Err(_) => Err(LxError::EINVAL),
@@ -229,16 +259,15 @@ impl Syscall<'_> {
             belongs to the same job as the calling thread."
         );
         let parent = self.zircon_process().clone();
+        let tgid = parent.linux().koid_of(tgid as KoID)?;
         match parent
             .job()
-            .get_child(tgid as u64)
+            .get_child(tgid)
             .map(|proc| proc.get_child(tid as u64))
         {
             Ok(Ok(obj)) => {
                 let thread: Arc<Thread> = obj.downcast_arc().unwrap();
-                let mut thread_linux = thread.lock_linux();
-                thread_linux.signals.insert(signal);
-                drop(thread_linux);
+                thread.send_signal(signal);
                 Ok(0)
             }
             _ => Err(LxError::EINVAL),
@@ -264,4 +293,28 @@ impl Syscall<'_> {
             .unwrap();
         Ok(0)
     }
+
+    /// Raise `SIGPIPE` on the thread if a write to a pipe or a socket failed
+    /// with `EPIPE`, unless `MSG_NOSIGNAL` is given to a send.
+    pub(crate) fn sigpipe_check(&self, sys_type: &Sys, args: &[usize; 6], ret: &SysResult) {
+        use linux_object::net::MsgFlags;
+        if !matches!(ret, Err(LxError::EPIPE)) {
+            return;
+        }
+        let raise = match sys_type {
+            Sys::WRITE
+            | Sys::WRITEV
+            | Sys::PWRITE64
+            | Sys::PWRITEV
+            | Sys::PWRITEV2
+            | Sys::SENDFILE
+            | Sys::SPLICE => true,
+            Sys::SENDTO => args[3] & MsgFlags::NOSIGNAL.bits() == 0,
+            Sys::SENDMSG => args[2] & MsgFlags::NOSIGNAL.bits() == 0,
+            _ => false,
+        };
+        if raise {
+            self.thread.send_signal(Signal::SIGPIPE);
+        }
+    }
 }
@@ -0,0 +1,133 @@
+//! Logging of syscalls in the style of `strace`
+//!
+//! It is enabled for a process by writing `1` to `/proc/[pid]/strace`, and is
+//! inherited by the children forked later. The log goes to the console
+//! regardless of the log level, one line for each syscall.
+
+use super::*;
+use alloc::string::String;
+use core::fmt::Write;
+
+/// The longest string argument printed, the rest is replaced by `...`.
+const MAX_STR_LEN: usize = 64;
+
+/// A syscall being logged, with the arguments decoded on entry.
+pub(crate) struct StraceEntry {
+    line: String,
+    /// Whether the result is an address.
+    hex_result: bool,
+}
+
+impl Syscall<'_> {
+    /// Decode the syscall on entry, if the calling process is logged.
+    pub(crate) fn strace_enter(&self, sys_type: &Sys, args: &[usize; 6]) -> Option<StraceEntry> {
+        if !self.linux_process().strace() {
+            return None;
+        }
+        let name = format!("{:?}", sys_type).to_lowercase();
+        let mut line = format!(
+            "[{} {}] {}(",
+            self.zircon_process().id(),
+            self.thread.id(),
+            name
+        );
+        let spec = arg_spec(sys_type);
+        for (i, (kind, arg)) in spec.chars().zip(args.iter()).enumerate() {
+            if i != 0 {
+                line += ", ";
+            }
+            write_arg(&mut line, kind, *arg);
+        }
+        line.push(')');
+        // these never return to the caller
+        if matches!(sys_type, Sys::EXIT | Sys::EXIT_GROUP | Sys::EXECVE) {
+            kernel_hal::console::console_write_fmt(format_args!("{} = ?\n", line));
+        }
+        Some(StraceEntry {
+            line,
+            hex_result: matches!(sys_type, Sys::MMAP | Sys::BRK),
+        })
+    }
+}
+
+impl StraceEntry {
+    /// Log the syscall with its result `ret`.
+    pub(crate) fn exit(self, ret: &SysResult) {
+        let mut line = self.line;
+        match ret {
+            Ok(value) if self.hex_result => write!(line, " = {:#x}", value).unwrap(),
+            Ok(value) => write!(line, " = {}", value).unwrap(),
+            Err(err) => write!(line, " = -1 {:?} ({})", err, err).unwrap(),
+        }
+        line.push('\n');
+        kernel_hal::console::console_write_str(&line);
+    }
+}
+
+/// The kinds of the arguments of a syscall, one character for each:
+/// - `f`: a file descriptor
+/// - `s`: a string
+/// - `d`: a decimal integer
+/// - `o`: an octal integer, e.g. a file mode
+/// - `x`: a hexadecimal integer, e.g. a pointer or flags
+///
+/// The syscalls unknown here are printed with six hexadecimal arguments.
+fn arg_spec(sys_type: &Sys) -> &'static str {
+    match sys_type {
+        Sys::READ | Sys::WRITE | Sys::READV | Sys::WRITEV | Sys::GETDENTS64 => "fxd",
+        Sys::PREAD64 | Sys::PWRITE64 | Sys::PREADV | Sys::PWRITEV => "fxdd",
+        Sys::PREADV2 | Sys::PWRITEV2 => "fxdddx",
+        Sys::OPENAT => "fsxo",
+        Sys::CLOSE | Sys::DUP => "f",
+        Sys::DUP3 => "ffx",
+        Sys::FSTAT => "fx",
+        Sys::NEWFSTATAT => "fsxx",
+        Sys::LSEEK => "fdd",
+        Sys::IOCTL | Sys::FCNTL => "fxx",
+        Sys::CHDIR => "s",
+        Sys::MKDIRAT => "fso",
+        Sys::UNLINKAT | Sys::FACCESSAT => "fsx",
+        Sys::READLINKAT => "fsxd",
+        Sys::RENAMEAT => "fsfs",
+        Sys::PIPE2 => "xx",
+        Sys::EXECVE => "sxx",
+        Sys::MMAP => "xdxxfd",
+        Sys::MUNMAP => "xd",
+        Sys::MPROTECT => "xdx",
+        Sys::BRK => "x",
+        Sys::EXIT | Sys::EXIT_GROUP => "d",
+        Sys::WAIT4 => "dxx",
+        Sys::KILL => "dd",
+        Sys::CLONE => "xxxxx",
+        Sys::SOCKET => "ddd",
+        Sys::CONNECT => "fxd",
+        Sys::NANOSLEEP => "xx",
+        #[cfg(target_arch = "x86_64")]
+        Sys::OPEN => "sxo",
+        #[cfg(target_arch = "x86_64")]
+        Sys::STAT | Sys::LSTAT | Sys::ACCESS => "sx",
+        _ => "xxxxxx",
+    }
+}
+
+fn write_arg(line: &mut String, kind: char, arg: usize) {
+    const AT_FDCWD: isize = -100;
+    match kind {
+        'f' if arg as isize == AT_FDCWD => *line += "AT_FDCWD",
+        'f' => write!(line, "{}", arg as i32).unwrap(),
+        'd' => write!(line, "{}", arg as isize).unwrap(),
+        'o' => write!(line, "{:#o}", arg).unwrap(),
+        's' => match UserInPtr::<u8>::from(arg).as_c_str() {
+            Ok(s) if s.len() > MAX_STR_LEN => {
+                let end = (0..=MAX_STR_LEN)
+                    .rev()
+                    .find(|&i| s.is_char_boundary(i))
+                    .unwrap_or(0);
+                write!(line, "{:?}...", &s[..end]).unwrap()
+            }
+            Ok(s) => write!(line, "{:?}", s).unwrap(),
+            Err(_) => write!(line, "{:#x}", arg).unwrap(),
+        },
+        _ => write!(line, "{:#x}", arg).unwrap(),
+    }
+}
@@ -2,13 +2,18 @@ use super::*;
 use core::fmt::Debug;
 use core::mem::size_of;
 
-use alloc::string::ToString;
+use alloc::{string::ToString, vec::Vec};
 use bitflags::bitflags;
 
 use kernel_hal::context::UserContextField;
+use linux_object::coredump::gregs;
+use linux_object::cred::{Capabilities, X_OK};
+use linux_object::fs::{INodeExt, MountFlags};
+use linux_object::loader::LinuxElfLoader;
+use linux_object::signal::Signal as LinuxSignal;
 use linux_object::thread::{CurrentThreadExt, RobustList, ThreadExt};
 use linux_object::time::TimeSpec;
-use linux_object::{fs::INodeExt, loader::LinuxElfLoader};
+use zircon_object::task::Task;
 use zircon_object::vm::USER_STACK_PAGES;
 
 /// Syscalls for process.
@@ -18,15 +23,23 @@ use zircon_object::vm::USER_STACK_PAGES;
 /// - [`fork`](Self::sys_fork)
 /// - [`vfork`](Self::sys_vfork)
 /// - [`clone`](Self::sys_clone)
+/// - [`unshare`](Self::sys_unshare)
 /// - [`wait4`](Self::sys_wait4)
 /// - [`execve`](Self::sys_execve)
 /// - [`gettid`](Self::sys_gettid)
 /// - [`getpid`](Self::sys_getpid)
 /// - [`getppid`](Self::sys_getppid)
+/// - [`getpgid`](Self::sys_getpgid)
+/// - [`setpgid`](Self::sys_setpgid)
+/// - [`getsid`](Self::sys_getsid)
+/// - [`setsid`](Self::sys_setsid)
 /// - [`exit`](Self::sys_exit)
 /// - [`exit_group`](Self::sys_exit_group)
 /// - [`nanosleep`](Self::sys_nanosleep)
 /// - [`set_tid_address`](Self::sys_set_tid_address)
+/// - [`sched_getaffinity`](Self::sys_sched_getaffinity)
+/// - [`sched_setaffinity`](Self::sys_sched_setaffinity)
+/// - [`ptrace`](Self::sys_ptrace)
 impl Syscall<'_> {
     /// `fork` creates a new process by duplicating the calling process
     /// (see [linux man fork(2)](https://www.man7.org/linux/man-pages/man2/fork.2.html)).
@@ -61,14 +74,26 @@ impl Syscall<'_> {
     ///   This means that the two file descriptors share open file status flags and file offset.
     pub fn sys_fork(&self) -> SysResult {
         info!("fork:");
+        self.fork(CloneFlags::empty())
+    }
+
+    /// Fork the process, into new mount and PID namespaces if `CLONE_NEWNS`
+    /// and `CLONE_NEWPID` are set.
+    fn fork(&self, flags: CloneFlags) -> SysResult {
         let new_proc = Process::fork_from(self.zircon_process(), false)?; // old pt NULL here
+        if flags.contains(CloneFlags::NEWNS) {
+            new_proc.linux().unshare_mount_ns()?;
+        }
+        if flags.contains(CloneFlags::NEWPID) {
+            new_proc.linux().enter_new_pid_ns(&new_proc)?;
+        }
         let new_thread = Thread::create_linux(&new_proc)?;
         let mut new_ctx = self.thread.context_cloned()?;
         new_ctx.set_field(UserContextField::ReturnValue, 0);
         new_thread.with_context(|ctx| *ctx = new_ctx)?;
         new_thread.start(self.thread_fn)?;
         info!("fork: {} -> {}", self.zircon_process().id(), new_proc.id());
-        Ok(new_proc.id() as usize)
+        Ok(self.linux_process().pid_of(new_proc.id()) as usize)
     }
 
     /// `sys_vfork`, just like [`Self::sys_fork`], creates a child process of the calling process
@@ -94,7 +119,7 @@ impl Syscall<'_> {
             new_proc.id()
         );
         new_proc.wait_signal(Signal::SIGNALED).await; // wait for execve
-        Ok(new_proc.id() as usize)
+        Ok(self.linux_process().pid_of(new_proc.id()) as usize)
     }
 
     /// `sys_clone` create a new thread in the current process.
@@ -113,15 +138,29 @@ impl Syscall<'_> {
         newtls: usize,
         mut child_tid: UserOutPtr<i32>,
     ) -> SysResult {
-        let _flags = CloneFlags::from_bits_truncate(flags);
+        let clone_flags = CloneFlags::from_bits_truncate(flags);
         info!(
             "clone: flags={:#x}, newsp={:#x}, parent_tid={:?}, child_tid={:?}, newtls={:#x}",
             flags, newsp, parent_tid, child_tid, newtls
         );
-        if flags == 0x4111 || flags == 0x11 {
+        let new_ns = CloneFlags::NEWNS | CloneFlags::NEWPID;
+        if clone_flags.intersects(new_ns) {
+            // the filesystem information can not be shared across mount
+            // namespaces, nor the signal handlers across PID namespaces
+            if clone_flags.contains(CloneFlags::NEWNS | CloneFlags::FS)
+                || clone_flags.contains(CloneFlags::NEWPID | CloneFlags::THREAD)
+            {
+                return Err(LxError::EINVAL);
+            }
+            if !self.linux_process().cred().has_cap(Capabilities::SYS_ADMIN) {
+                return Err(LxError::EPERM);
+            }
+        }
+        let fork_flags = flags & !new_ns.bits();
+        if fork_flags == 0x4111 || fork_flags == 0x11 {
             // VFORK | VM | SIGCHILD
             warn!("sys_clone is calling sys_fork instead, ignoring other args");
-            return self.sys_fork();
+            return self.fork(clone_flags);
         }
         if flags != 0x7d_0f00 && flags != 0x5d_0f00 {
             // 0x5d0f00: gcc of alpine linux
@@ -145,6 +184,36 @@ impl Syscall<'_> {
         Ok(tid as usize)
     }
 
+    /// Disassociate parts of the process execution context shared with other
+    /// processes (see [linux man unshare(2)](https://man7.org/linux/man-pages/man2/unshare.2.html)).
+    ///
+    /// Only new mount and PID namespaces are supported, by `CLONE_NEWNS` and
+    /// `CLONE_NEWPID`. The caller stays in its PID namespace, and its children
+    /// created later are in the new one.
+    pub fn sys_unshare(&self, flags: usize) -> SysResult {
+        let flags = CloneFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("unshare: flags={:?}", flags);
+        // the filesystem information of a process is never shared
+        let unsupported = flags - CloneFlags::NEWNS - CloneFlags::NEWPID - CloneFlags::FS;
+        if !unsupported.is_empty() {
+            warn!("unshare: unsupported flags {:?}", unsupported);
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.linux_process();
+        if flags.intersects(CloneFlags::NEWNS | CloneFlags::NEWPID)
+            && !proc.cred().has_cap(Capabilities::SYS_ADMIN)
+        {
+            return Err(LxError::EPERM);
+        }
+        if flags.contains(CloneFlags::NEWNS) {
+            proc.unshare_mount_ns()?;
+        }
+        if flags.contains(CloneFlags::NEWPID) {
+            proc.unshare_pid_ns()?;
+        }
+        Ok(0)
+    }
+
     /// `sys_wait4` suspends execution of the calling thread
     /// until a child specified by `pid` argument has changed state
     /// (see [linux man wait4(2)](https://www.man7.org/linux/man-pages/man2/wait4.2.html)).
@@ -166,7 +235,7 @@ impl Syscall<'_> {
     ///
     /// - **STOPPED**   = 0x000_0002;
     ///
-    ///   TODO
+    ///   also return if a child has stopped
     ///
     /// - **EXITED**    = 0x000_0004;
     ///
@@ -193,7 +262,7 @@ impl Syscall<'_> {
         #[derive(Debug)]
         enum WaitTarget {
             AnyChild,
-            AnyChildInGroup,
+            AnyChildInGroup(KoID),
             Pid(KoID),
         }
         bitflags! {
@@ -205,23 +274,31 @@ impl Syscall<'_> {
                 const NOWAIT    = 0x100_0000;
             }
         }
+        let linux_proc = self.linux_process();
+        let koid_of = |pid: i32| linux_proc.koid_of(pid as KoID).map_err(|_| LxError::ECHILD);
         let target = match pid {
             -1 => WaitTarget::AnyChild,
-            0 => WaitTarget::AnyChildInGroup,
-            p if p > 0 => WaitTarget::Pid(p as KoID),
-            _ => unimplemented!(),
+            0 => WaitTarget::AnyChildInGroup(linux_proc.pgid()),
+            p if p > 0 => WaitTarget::Pid(koid_of(p)?),
+            p => WaitTarget::AnyChildInGroup(koid_of(-p)?),
         };
         let flags = WaitFlags::from_bits_truncate(options);
         let nohang = flags.contains(WaitFlags::NOHANG);
+        let untraced = flags.contains(WaitFlags::STOPPED);
         info!(
             "wait4: target={:?}, wstatus={:?}, options={:?}",
             target, wstatus, flags,
         );
+        let proc = self.zircon_process();
         let (pid, code) = match target {
-            WaitTarget::AnyChild | WaitTarget::AnyChildInGroup => {
-                wait_child_any(self.zircon_process(), nohang).await?
+            WaitTarget::AnyChild => wait_child_any(proc, None, nohang, untraced).await?,
+            WaitTarget::AnyChildInGroup(pgid) => {
+                wait_child_any(proc, Some(pgid), nohang, untraced).await?
+            }
+            WaitTarget::Pid(koid) => {
+                let code = wait_child(proc, koid, nohang, untraced).await?;
+                (pid as KoID, code)
             }
-            WaitTarget::Pid(pid) => (pid, wait_child(self.zircon_process(), pid, nohang).await?),
         };
         wstatus.write_if_not_null(code)?;
         Ok(pid as usize)
@@ -271,9 +348,18 @@ impl Syscall<'_> {
         // Read program file
         let proc = self.linux_process();
         let inode = proc.lookup_inode(path)?;
+        let metadata = inode.metadata()?;
+        let mut cred = proc.cred();
+        cred.check_access(&metadata, X_OK)?;
+        let mount_flags = proc.mount_ns().mount_flags(&*inode);
+        if mount_flags.contains(MountFlags::NOEXEC) {
+            return Err(LxError::EACCES);
+        }
         let data = inode.read_as_vec()?;
 
         proc.remove_cloexec_files();
+        proc.remove_timers();
+        proc.shm_detach_all();
 
         // 注意！即将销毁旧应用程序的用户空间，现在将必要的信息拷贝到内核！
         // Notice! About to destroy the user space of the old application, now copy the necessary information into kernel!
@@ -281,13 +367,20 @@ impl Syscall<'_> {
         let vmar = self.zircon_process().vmar();
         vmar.clear()?;
 
+        // the set-user-ID bits are ignored for traced processes and on
+        // `nosuid` mounts, as in Linux
+        let nosuid = mount_flags.contains(MountFlags::NOSUID);
+        cred.exec(&metadata, proc.no_new_privs() || proc.is_traced() || nosuid);
+        proc.set_cred(cred);
+
         // Modify exec path
         proc.set_execute_path(&path);
+        proc.set_args(&args);
 
         let (entry, sp) = LinuxElfLoader {
             syscall_entry: self.syscall_entry,
             stack_pages: USER_STACK_PAGES,
-            root_inode: proc.root_inode().clone(),
+            root_inode: proc.root_inode()?,
         }
         .load(&vmar, &data, args, envs, path)?;
 
@@ -320,7 +413,7 @@ impl Syscall<'_> {
     pub fn sys_getpid(&self) -> SysResult {
         info!("getpid:");
         let proc = self.zircon_process();
-        let pid = proc.id();
+        let pid = proc.linux().pid_of(proc.id());
         Ok(pid as usize)
     }
 
@@ -331,10 +424,104 @@ impl Syscall<'_> {
     pub fn sys_getppid(&self) -> SysResult {
         info!("getppid:");
         let proc = self.linux_process();
-        let ppid = proc.parent().map(|p| p.id()).unwrap_or(0);
+        // the parent of the init process of a PID namespace is not in it
+        let ppid = proc.parent().map(|p| proc.pid_of(p.id())).unwrap_or(0);
         Ok(ppid as usize)
     }
 
+    /// Find the process `pid` in the job of the calling process, 0 means the caller.
+    fn find_process(&self, pid: usize) -> LxResult<Arc<Process>> {
+        let proc = self.zircon_process();
+        if pid == 0 {
+            return Ok(proc.clone());
+        }
+        let koid = proc.linux().koid_of(pid as KoID)?;
+        if koid == proc.id() {
+            return Ok(proc.clone());
+        }
+        proc.job()
+            .get_child(koid)
+            .ok()
+            .and_then(|obj| obj.downcast_arc::<Process>().ok())
+            .ok_or(LxError::ESRCH)
+    }
+
+    /// `sys_getpgid` returns the process group ID of the process specified by `pid`
+    /// (see [linux man getpgid(2)](https://www.man7.org/linux/man-pages/man2/getpgid.2.html)).
+    /// If `pid` is zero, the process ID of the calling process is used.
+    pub fn sys_getpgid(&self, pid: usize) -> SysResult {
+        info!("getpgid: pid={}", pid);
+        let proc = self.find_process(pid)?;
+        let pgid = self.linux_process().pid_of(proc.linux().pgid());
+        Ok(pgid as usize)
+    }
+
+    /// `sys_setpgid` sets the process group ID of the process specified by `pid` to `pgid`
+    /// (see [linux man setpgid(2)](https://www.man7.org/linux/man-pages/man2/setpgid.2.html)).
+    ///
+    /// If `pid` is zero, the calling process is used. If `pgid` is zero,
+    /// the process ID of the target is used. The target must be the caller or
+    /// one of its children in the same session, and `pgid` must refer to a
+    /// process group in that session.
+    pub fn sys_setpgid(&self, pid: usize, pgid: usize) -> SysResult {
+        info!("setpgid: pid={}, pgid={}", pid, pgid);
+        let caller = self.zircon_process();
+        let target = self.find_process(pid)?;
+        let is_child = target
+            .linux()
+            .parent()
+            .map_or(false, |parent| parent.id() == caller.id());
+        if target.id() != caller.id() && !is_child {
+            return Err(LxError::ESRCH);
+        }
+        let sid = caller.linux().sid();
+        let linux = target.linux();
+        if linux.sid() != sid || linux.sid() == target.id() {
+            // in another session, or a session leader
+            return Err(LxError::EPERM);
+        }
+        let pgid = if pgid == 0 {
+            target.id()
+        } else {
+            caller
+                .linux()
+                .koid_of(pgid as KoID)
+                .map_err(|_| LxError::EPERM)?
+        };
+        if pgid != target.id()
+            && !process_group(&caller.job(), pgid)
+                .iter()
+                .any(|p| p.linux().sid() == sid)
+        {
+            return Err(LxError::EPERM);
+        }
+        linux.set_pgid(pgid);
+        Ok(0)
+    }
+
+    /// `sys_getsid` returns the session ID of the process specified by `pid`
+    /// (see [linux man getsid(2)](https://www.man7.org/linux/man-pages/man2/getsid.2.html)).
+    pub fn sys_getsid(&self, pid: usize) -> SysResult {
+        info!("getsid: pid={}", pid);
+        let proc = self.find_process(pid)?;
+        let sid = self.linux_process().pid_of(proc.linux().sid());
+        Ok(sid as usize)
+    }
+
+    /// `sys_setsid` creates a new session if the calling process is not a process group leader
+    /// (see [linux man setsid(2)](https://www.man7.org/linux/man-pages/man2/setsid.2.html)).
+    /// The calling process is the leader of the new session and of a new process group.
+    pub fn sys_setsid(&self) -> SysResult {
+        info!("setsid:");
+        let proc = self.zircon_process();
+        let pid = proc.id();
+        if !process_group(&proc.job(), pid).is_empty() {
+            return Err(LxError::EPERM);
+        }
+        proc.linux().set_sid(pid);
+        Ok(proc.linux().pid_of(pid) as usize)
+    }
+
     /// `sys_exit` system call terminates only the calling thread
     /// (see [linux man _exit(2)](https://www.man7.org/linux/man-pages/man2/exit.2.html),
     /// this syscall is same as a raw `_exit` in glibc),
@@ -393,6 +580,72 @@ impl Syscall<'_> {
         Ok(tid as usize)
     }
 
+    /// `sched_getaffinity` writes the CPU affinity mask of the thread `tid` to `mask`
+    /// (see [linux man sched_getaffinity(2)](https://www.man7.org/linux/man-pages/man2/sched_getaffinity.2.html)).
+    /// If `tid` is zero, the calling thread is used.
+    ///
+    /// Returns the number of bytes written, at most `len`.
+    pub fn sys_sched_getaffinity(
+        &self,
+        tid: usize,
+        len: usize,
+        mut mask: UserOutPtr<u8>,
+    ) -> SysResult {
+        info!(
+            "sched_getaffinity: tid={}, len={}, mask={:?}",
+            tid, len, mask
+        );
+        if len * 8 < kernel_hal::vdso::vdso_constants().max_num_cpus as usize
+            || len % size_of::<usize>() != 0
+        {
+            return Err(LxError::EINVAL);
+        }
+        let mut cpus = self.find_thread(tid)?.cpu_affinity_mask();
+        if cpus == [0; 8] {
+            cpus = all_cpus_mask();
+        }
+        let len = len.min(size_of::<[u64; 8]>());
+        let bytes: Vec<u8> = cpus.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        mask.write_array(&bytes[..len])?;
+        Ok(len)
+    }
+
+    /// `sched_setaffinity` sets the CPU affinity mask of the thread `tid` to `mask`
+    /// (see [linux man sched_setaffinity(2)](https://www.man7.org/linux/man-pages/man2/sched_setaffinity.2.html)).
+    /// If `tid` is zero, the calling thread is used.
+    ///
+    /// The CPUs not present are ignored, and at least one present CPU must be in `mask`.
+    pub fn sys_sched_setaffinity(&self, tid: usize, len: usize, mask: UserInPtr<u8>) -> SysResult {
+        info!(
+            "sched_setaffinity: tid={}, len={}, mask={:?}",
+            tid, len, mask
+        );
+        let thread = self.find_thread(tid)?;
+        let mut bytes = mask.read_array(len.min(size_of::<[u64; 8]>()))?;
+        bytes.resize(size_of::<[u64; 8]>(), 0);
+        let mut cpus = [0u64; 8];
+        for ((word, chunk), all) in cpus.iter_mut().zip(bytes.chunks(8)).zip(all_cpus_mask()) {
+            *word = u64::from_ne_bytes(<[u8; 8]>::try_from(chunk).unwrap()) & all;
+        }
+        if cpus == [0; 8] {
+            return Err(LxError::EINVAL);
+        }
+        thread.set_cpu_affinity_mask(cpus);
+        Ok(0)
+    }
+
+    /// Find the thread `tid` in the calling process, 0 means the caller.
+    fn find_thread(&self, tid: usize) -> LxResult<Arc<Thread>> {
+        if tid == 0 || tid as KoID == self.thread.id() {
+            return Ok(self.thread.inner());
+        }
+        self.zircon_process()
+            .get_child(tid as KoID)
+            .ok()
+            .and_then(|obj| obj.downcast_arc::<Thread>().ok())
+            .ok_or(LxError::ESRCH)
+    }
+
     /// Get robust list.
     pub fn sys_get_robust_list(
         &self,
@@ -414,6 +667,138 @@ impl Syscall<'_> {
         self.thread.set_robust_list(head, len);
         Ok(0)
     }
+
+    /// `sys_ptrace` lets the calling process observe and control a traced child
+    /// (see [linux man ptrace(2)](https://www.man7.org/linux/man-pages/man2/ptrace.2.html)).
+    ///
+    /// A traced process stops on every signal but `SIGKILL`, and the stop is
+    /// reported to the tracer by [`Self::sys_wait4`]. Only the parent can be
+    /// the tracer, and the requests other than `PTRACE_TRACEME`,
+    /// `PTRACE_ATTACH` and `PTRACE_KILL` require the tracee to be stopped.
+    pub fn sys_ptrace(&self, request: usize, pid: usize, addr: usize, data: usize) -> SysResult {
+        const TRACEME: usize = 0;
+        const PEEKTEXT: usize = 1;
+        const PEEKDATA: usize = 2;
+        const POKETEXT: usize = 4;
+        const POKEDATA: usize = 5;
+        const CONT: usize = 7;
+        const KILL: usize = 8;
+        const SINGLESTEP: usize = 9;
+        const GETREGS: usize = 12;
+        const ATTACH: usize = 16;
+        const DETACH: usize = 17;
+        info!(
+            "ptrace: request={}, pid={}, addr={:#x}, data={:#x}",
+            request, pid, addr, data
+        );
+        if request == TRACEME {
+            let proc = self.linux_process();
+            if proc.parent().is_none() || proc.is_traced() {
+                return Err(LxError::EPERM);
+            }
+            proc.set_traced(true);
+            return Ok(0);
+        }
+        let tracee = self.find_process(pid)?;
+        let linux = tracee.linux();
+        if linux.parent().map(|p| p.id()) != Some(self.zircon_process().id()) {
+            return Err(LxError::EPERM);
+        }
+        if request == ATTACH {
+            if linux.is_traced() {
+                return Err(LxError::EPERM);
+            }
+            linux.set_traced(true);
+            tracee.send_signal(LinuxSignal::SIGSTOP);
+            return Ok(0);
+        }
+        if !linux.is_traced() {
+            return Err(LxError::ESRCH);
+        }
+        if request == KILL {
+            tracee.send_signal(LinuxSignal::SIGKILL);
+            return Ok(0);
+        }
+        let thread = linux.ptrace_thread().ok_or(LxError::ESRCH)?;
+        match request {
+            PEEKTEXT | PEEKDATA => {
+                let mut buf = [0u8; size_of::<usize>()];
+                match tracee.vmar().read_memory(addr, &mut buf) {
+                    Ok(len) if len == buf.len() => {}
+                    _ => return Err(LxError::EIO),
+                }
+                UserOutPtr::<usize>::from(data).write(usize::from_ne_bytes(buf))?;
+                Ok(0)
+            }
+            POKETEXT | POKEDATA => {
+                let buf = data.to_ne_bytes();
+                match tracee.vmar().write_memory(addr, &buf) {
+                    Ok(len) if len == buf.len() => Ok(0),
+                    _ => Err(LxError::EIO),
+                }
+            }
+            GETREGS => {
+                let cx = thread.context_cloned().map_err(|_| LxError::ESRCH)?;
+                UserOutPtr::<usize>::from(data).write_array(&gregs(&cx))?;
+                Ok(0)
+            }
+            CONT | SINGLESTEP | DETACH => {
+                let signal = match data {
+                    0 => None,
+                    signum => Some(LinuxSignal::try_from(signum as u8).map_err(|_| LxError::EIO)?),
+                };
+                set_single_step(&thread, request == SINGLESTEP)?;
+                if request == DETACH {
+                    linux.set_traced(false);
+                }
+                if let Some(thread) = linux.ptrace_resume(signal) {
+                    thread.resume();
+                }
+                Ok(0)
+            }
+            _ => {
+                warn!("ptrace: unsupported request {}", request);
+                Err(LxError::EIO)
+            }
+        }
+    }
+}
+
+/// Set or clear the trap flag of the stopped `thread`, which traps after
+/// every instruction.
+#[cfg(target_arch = "x86_64")]
+fn set_single_step(thread: &Thread, enable: bool) -> LxResult {
+    const TRAP_FLAG: usize = 1 << 8;
+    thread
+        .with_context(|cx| {
+            let regs = cx.general_mut();
+            if enable {
+                regs.rflags |= TRAP_FLAG;
+            } else {
+                regs.rflags &= !TRAP_FLAG;
+            }
+        })
+        .map_err(|_| LxError::ESRCH)
+}
+
+/// Single step is not supported, RISC-V has no such hardware support.
+#[cfg(not(target_arch = "x86_64"))]
+fn set_single_step(_thread: &Thread, enable: bool) -> LxResult {
+    if enable {
+        Err(LxError::EIO)
+    } else {
+        Ok(())
+    }
+}
+
+/// The mask of all CPUs present, one bit for each.
+fn all_cpus_mask() -> [u64; 8] {
+    let num_cpus = kernel_hal::vdso::vdso_constants().max_num_cpus as usize;
+    let mut mask = [0u64; 8];
+    for cpu in 0..num_cpus.min(512) {
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    mask
 }
 
 bitflags! {
@@ -1,24 +1,62 @@
 //! Syscalls for time
-//! - clock_gettime
+//! - clock_gettime, clock_settime, clock_getres
+//! - timerfd_create, timerfd_settime, timerfd_gettime
+//! - timer_create, timer_settime, timer_gettime, timer_getoverrun, timer_delete
+//! - getitimer, setitimer, alarm
 //!
 use crate::Syscall;
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+use core::time::Duration;
+use kernel_hal::timer;
 use kernel_hal::{user::UserInPtr, user::UserOutPtr};
 use linux_object::error::LxError;
 use linux_object::error::SysResult;
+use linux_object::fs::{FileDesc, TimerFd, TimerFdFlags, TimerFdSetFlags};
+use linux_object::signal::Signal;
 use linux_object::time::*;
+use zircon_object::{
+    object::{KernelObject, KoID},
+    task::Thread,
+};
 
 const USEC_PER_TICK: usize = 10000;
 
+/// Interpret the value of `timer_settime` as an absolute time.
+const TIMER_ABSTIME: usize = 1;
+
+/// The `setitimer` timer counting the real time.
+const ITIMER_REAL: usize = 0;
+
 impl Syscall<'_> {
-    /// finds the resolution (precision) of the specified clock clockid, and,
-    /// if buffer is non-NULL, stores it in the struct timespec pointed to by buffer
+    /// retrieve the time of the specified clock clockid, and stores it in
+    /// the struct timespec pointed to by buffer
+    ///
+    /// The monotonic clocks are not slewed and the system never suspends, so
+    /// `CLOCK_MONOTONIC_RAW` and `CLOCK_BOOTTIME` are the same as
+    /// `CLOCK_MONOTONIC`.
     pub fn sys_clock_gettime(&self, clock: usize, mut buf: UserOutPtr<TimeSpec>) -> SysResult {
         info!("clock_gettime: id={:?} buf={:?}", clock, buf);
         if buf.is_null() {
             return Err(LxError::EINVAL);
         }
-        // TODO: handle clock_settime
-        let ts = TimeSpec::now();
+        let time = match ClockId::try_from(clock)? {
+            id if id.is_realtime() => realtime_now(),
+            ClockId::ClockThreadCpuTimeId => Duration::from_nanos(self.thread.get_time()),
+            ClockId::ClockProcessCpuTimeId => {
+                let proc = self.zircon_process();
+                let nanos = proc
+                    .thread_ids()
+                    .into_iter()
+                    .filter_map(|id| proc.get_child(id).ok())
+                    .filter_map(|obj| obj.downcast_arc::<Thread>().ok())
+                    .map(|thread| thread.get_time())
+                    .sum();
+                Duration::from_nanos(nanos)
+            }
+            _ => timer::timer_now(),
+        };
+        let ts = TimeSpec::from(time);
         buf.write(ts)?;
 
         info!("TimeSpec: {:?}", ts);
@@ -26,6 +64,38 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// set the time of the specified clock clockid, of which only
+    /// `CLOCK_REALTIME` is settable
+    pub fn sys_clock_settime(&self, clock: usize, buf: UserInPtr<TimeSpec>) -> SysResult {
+        let ts = buf.read()?;
+        info!("clock_settime: id={:?} ts={:?}", clock, ts);
+        if ts.nsec >= 1_000_000_000 {
+            return Err(LxError::EINVAL);
+        }
+        // all processes run as root, which has `CAP_SYS_TIME`
+        match ClockId::try_from(clock)? {
+            ClockId::ClockRealTime => set_realtime(ts.into()),
+            _ => return Err(LxError::EINVAL),
+        }
+        Ok(0)
+    }
+
+    /// finds the resolution (precision) of the specified clock clockid, and,
+    /// if buffer is non-NULL, stores it in the struct timespec pointed to by buffer
+    pub fn sys_clock_getres(&self, clock: usize, mut buf: UserOutPtr<TimeSpec>) -> SysResult {
+        info!("clock_getres: id={:?} buf={:?}", clock, buf);
+        let res = match ClockId::try_from(clock)? {
+            // the coarse clocks are updated by timer ticks
+            ClockId::ClockRealTimeCoarse | ClockId::ClockMonotonicCoarse => TimeSpec {
+                sec: 0,
+                nsec: USEC_PER_TICK * 1_000,
+            },
+            _ => TimeSpec { sec: 0, nsec: 1 },
+        };
+        buf.write_if_not_null(res)?;
+        Ok(0)
+    }
+
     /// get the time with second and microseconds
     pub fn sys_gettimeofday(
         &mut self,
@@ -130,41 +200,240 @@ impl Syscall<'_> {
             req.read()?,
             rem
         );
-        use core::time::Duration;
-        use kernel_hal::{thread, timer};
+        use kernel_hal::thread;
         let duration: Duration = req.read()?.into();
-        let clockid = ClockId::from(clockid);
+        let clockid = ClockId::try_from(clockid)?;
         let flags = ClockFlags::from(flags);
         info!("clockid={:?}, flags={:?}", clockid, flags,);
-        match clockid {
-            ClockId::ClockRealTime => {
-                match flags {
-                    ClockFlags::ZeroFlag => {
-                        thread::sleep_until(timer::deadline_after(duration)).await;
-                    }
-                    ClockFlags::TimerAbsTime => {
-                        // 目前统一由nanosleep代替了、之后再修改
-                        thread::sleep_until(timer::deadline_after(duration)).await;
-                    }
-                }
+        let deadline = match (clockid, flags) {
+            // sleeping on CPU-time clocks is not supported
+            (ClockId::ClockProcessCpuTimeId | ClockId::ClockThreadCpuTimeId, _) => {
+                return Err(LxError::EINVAL)
             }
-            ClockId::ClockMonotonic => match flags {
-                ClockFlags::ZeroFlag => {
-                    thread::sleep_until(timer::deadline_after(duration)).await;
-                }
-                ClockFlags::TimerAbsTime => {
-                    thread::sleep_until(timer::deadline_after(duration)).await;
+            (_, ClockFlags::ZeroFlag) => timer::deadline_after(duration),
+            (id, ClockFlags::TimerAbsTime) if id.is_realtime() => realtime_to_monotonic(duration),
+            (_, ClockFlags::TimerAbsTime) => duration,
+        };
+        thread::sleep_until(deadline).await;
+        Ok(0)
+    }
+
+    /// create a timer that delivers timer expiration notifications via a file descriptor
+    pub fn sys_timerfd_create(&self, clockid: usize, flags: usize) -> SysResult {
+        let flags = TimerFdFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("timerfd_create: clockid={}, flags={:?}", clockid, flags);
+        match ClockId::try_from(clockid)? {
+            ClockId::ClockRealTime
+            | ClockId::ClockMonotonic
+            | ClockId::ClockBootTime
+            | ClockId::ClockRealTimeAlarm
+            | ClockId::ClockBootTimeAlarm => {}
+            _ => return Err(LxError::EINVAL),
+        }
+        let timerfd = TimerFd::new(clockid, flags);
+        let fd = self.linux_process().add_file(timerfd)?;
+        Ok(fd.into())
+    }
+
+    /// arm or disarm the timer referred to by the file descriptor
+    pub fn sys_timerfd_settime(
+        &self,
+        fd: FileDesc,
+        flags: usize,
+        new_value: UserInPtr<ITimerSpec>,
+        mut old_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        let flags = TimerFdSetFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let new_value = new_value.read()?;
+        info!(
+            "timerfd_settime: fd={:?}, flags={:?}, new_value={:?}",
+            fd, flags, new_value
+        );
+        if new_value.value.nsec >= 1_000_000_000 || new_value.interval.nsec >= 1_000_000_000 {
+            return Err(LxError::EINVAL);
+        }
+        let timerfd = self.get_timerfd(fd)?;
+        let old = timerfd.set_time(new_value, flags);
+        old_value.write_if_not_null(old)?;
+        Ok(0)
+    }
+
+    /// return the current setting of the timer referred to by the file descriptor
+    pub fn sys_timerfd_gettime(
+        &self,
+        fd: FileDesc,
+        mut curr_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!("timerfd_gettime: fd={:?}", fd);
+        let timerfd = self.get_timerfd(fd)?;
+        curr_value.write(timerfd.get_time())?;
+        Ok(0)
+    }
+
+    /// create a POSIX per-process timer, which notifies as `sevp` describes,
+    /// or sends `SIGALRM` to the process if `sevp` is NULL
+    pub fn sys_timer_create(
+        &self,
+        clockid: usize,
+        sevp: UserInPtr<SigEvent>,
+        mut timerid: UserOutPtr<i32>,
+    ) -> SysResult {
+        info!("timer_create: clockid={}, sevp={:?}", clockid, sevp);
+        let clock = ClockId::try_from(clockid)?;
+        // timers on CPU-time clocks are not supported
+        if matches!(
+            clock,
+            ClockId::ClockProcessCpuTimeId | ClockId::ClockThreadCpuTimeId
+        ) {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.zircon_process();
+        let target = if sevp.is_null() {
+            SignalTarget::Process(Arc::downgrade(proc), Signal::SIGALRM)
+        } else {
+            let event = sevp.read()?;
+            let signal = || {
+                u8::try_from(event.signo)
+                    .ok()
+                    .and_then(|signo| Signal::try_from(signo).ok())
+                    .ok_or(LxError::EINVAL)
+            };
+            match event.notify {
+                SIGEV_NONE => SignalTarget::None,
+                SIGEV_SIGNAL => SignalTarget::Process(Arc::downgrade(proc), signal()?),
+                SIGEV_THREAD_ID => {
+                    let thread = proc
+                        .get_child(event.tid as KoID)
+                        .ok()
+                        .and_then(|obj| obj.downcast_arc::<Thread>().ok())
+                        .ok_or(LxError::EINVAL)?;
+                    SignalTarget::Thread(Arc::downgrade(&thread), signal()?)
                 }
-            },
-            ClockId::ClockProcessCpuTimeId => {}
-            ClockId::ClockThreadCpuTimeId => {}
-            ClockId::ClockMonotonicRaw => {}
-            ClockId::ClockRealTimeCoarse => {}
-            ClockId::ClockMonotonicCoarse => {}
-            ClockId::ClockBootTime => {}
-            ClockId::ClockRealTimeAlarm => {}
-            ClockId::ClockBootTimeAlarm => {}
+                _ => return Err(LxError::EINVAL),
+            }
+        };
+        let id = self
+            .linux_process()
+            .add_timer(IntervalTimer::new(clock, target));
+        timerid.write(id as i32)?;
+        Ok(0)
+    }
+
+    /// arm or disarm the POSIX per-process timer
+    pub fn sys_timer_settime(
+        &self,
+        timerid: usize,
+        flags: usize,
+        new_value: UserInPtr<ITimerSpec>,
+        mut old_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        let new_value = new_value.read()?;
+        info!(
+            "timer_settime: timerid={}, flags={:#x}, new_value={:?}",
+            timerid, flags, new_value
+        );
+        if flags & !TIMER_ABSTIME != 0
+            || new_value.value.nsec >= 1_000_000_000
+            || new_value.interval.nsec >= 1_000_000_000
+        {
+            return Err(LxError::EINVAL);
+        }
+        let timer = self.linux_process().get_timer(timerid)?;
+        let old = timer.set_time(new_value, flags & TIMER_ABSTIME != 0);
+        old_value.write_if_not_null(old)?;
+        Ok(0)
+    }
+
+    /// return the time until the next expiration of the POSIX per-process
+    /// timer, and its interval
+    pub fn sys_timer_gettime(
+        &self,
+        timerid: usize,
+        mut curr_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!("timer_gettime: timerid={}", timerid);
+        let timer = self.linux_process().get_timer(timerid)?;
+        curr_value.write(timer.get_time())?;
+        Ok(0)
+    }
+
+    /// return the overrun count of the POSIX per-process timer
+    pub fn sys_timer_getoverrun(&self, timerid: usize) -> SysResult {
+        info!("timer_getoverrun: timerid={}", timerid);
+        Ok(self.linux_process().get_timer(timerid)?.overrun())
+    }
+
+    /// delete the POSIX per-process timer
+    pub fn sys_timer_delete(&self, timerid: usize) -> SysResult {
+        info!("timer_delete: timerid={}", timerid);
+        self.linux_process().remove_timer(timerid)?;
+        Ok(0)
+    }
+
+    /// get the value of an interval timer, of which only `ITIMER_REAL` is
+    /// supported
+    pub fn sys_getitimer(&self, which: usize, mut curr_value: UserOutPtr<ITimerVal>) -> SysResult {
+        info!("getitimer: which={}", which);
+        let timer = self.real_timer(which)?;
+        curr_value.write(timer.get_time().into())?;
+        Ok(0)
+    }
+
+    /// set the value of an interval timer, of which only `ITIMER_REAL` is
+    /// supported, sending `SIGALRM` on expirations
+    pub fn sys_setitimer(
+        &self,
+        which: usize,
+        new_value: UserInPtr<ITimerVal>,
+        mut old_value: UserOutPtr<ITimerVal>,
+    ) -> SysResult {
+        let new_value = new_value.read()?;
+        info!("setitimer: which={}, new_value={:?}", which, new_value);
+        if new_value.value.usec >= 1_000_000 || new_value.interval.usec >= 1_000_000 {
+            return Err(LxError::EINVAL);
         }
+        let timer = self.real_timer(which)?;
+        let old = timer.set_time(new_value.into(), false);
+        old_value.write_if_not_null(old.into())?;
         Ok(0)
     }
+
+    /// arrange for `SIGALRM` to be sent to the process in `seconds`, or cancel
+    /// the alarm if it is 0, returning the seconds remaining of the previous one
+    #[cfg(target_arch = "x86_64")]
+    pub fn sys_alarm(&self, seconds: usize) -> SysResult {
+        info!("alarm: seconds={}", seconds);
+        let new_value = ITimerSpec {
+            interval: TimeSpec::default(),
+            value: TimeSpec {
+                sec: seconds,
+                nsec: 0,
+            },
+        };
+        let old = self.real_timer(ITIMER_REAL)?.set_time(new_value, false);
+        // round to the nearest second, but not to 0 if an alarm is pending
+        let remaining = old.value.sec + (old.value.nsec >= 500_000_000) as usize;
+        Ok(remaining.max((old.value.nsec != 0) as usize))
+    }
+
+    fn real_timer(&self, which: usize) -> Result<Arc<IntervalTimer>, LxError> {
+        if which != ITIMER_REAL {
+            warn!("only ITIMER_REAL is supported, which={}", which);
+            return Err(LxError::EINVAL);
+        }
+        let proc = Arc::downgrade(self.zircon_process());
+        Ok(self.linux_process().real_timer(|| {
+            IntervalTimer::new(
+                ClockId::ClockRealTime,
+                SignalTarget::Process(proc, Signal::SIGALRM),
+            )
+        }))
+    }
+
+    fn get_timerfd(&self, fd: FileDesc) -> Result<Arc<TimerFd>, LxError> {
+        self.linux_process()
+            .get_file_like(fd)?
+            .downcast_arc::<TimerFd>()
+            .map_err(|_| LxError::EINVAL)
+    }
 }
@@ -1,6 +1,11 @@
 use super::*;
+use alloc::vec::Vec;
 use bitflags::bitflags;
-use zircon_object::vm::{pages, MMUFlags, VmObject};
+use linux_object::{fs::PageCache, loader::STACK_GUARD_PAGES};
+use zircon_object::{
+    vm::{page_aligned, pages, roundup_pages, MMUFlags, VmAddressRegion, VmObject, PAGE_SIZE},
+    ZxError,
+};
 
 /// Syscalls for virtual memory.
 ///
@@ -8,7 +13,10 @@ use zircon_object::vm::{pages, MMUFlags, VmObject};
 ///
 /// - [`mmap`](Self::sys_mmap)
 /// - [`mprotect`](Self::sys_mprotect)
+/// - [`mremap`](Self::sys_mremap)
 /// - [`munmap`](Self::sys_munmap)
+/// - [`msync`](Self::sys_msync)
+/// - [`madvise`](Self::sys_madvise)
 impl Syscall<'_> {
     /// Map files or devices into memory
     /// (see [linux man mmap(2)](https://www.man7.org/linux/man-pages/man2/mmap.2.html)).
@@ -71,6 +79,12 @@ impl Syscall<'_> {
     ///   Both `fd` and `offset` arguments are ignored.
     ///   The use of `MmapFlags::ANONYMOUS` in conjunction with `MmapFlags::SHARED`
     ///   causes an [`EINVAL`](LxError::EINVAL) to be returned.
+    ///
+    /// - **`MmapFlags::GROWSDOWN`**
+    ///
+    ///   The anonymous mapping is a stack, which has guard pages below it. An access to
+    ///   them extends the mapping down to the page accessed, except the lowest one,
+    ///   so that a stack overflow raises `SIGSEGV`.
     pub async fn sys_mmap(
         &self,
         addr: usize,
@@ -99,12 +113,25 @@ impl Syscall<'_> {
             if flags.contains(MmapFlags::SHARED) {
                 return Err(LxError::EINVAL);
             }
-            let vmo = VmObject::new_paged(pages(len));
+            if flags.contains(MmapFlags::GROWSDOWN) {
+                let guard_size = STACK_GUARD_PAGES * PAGE_SIZE;
+                let vmar_offset = match vmar_offset {
+                    Some(offset) => Some(offset.checked_sub(guard_size).ok_or(LxError::ENOMEM)?),
+                    None => None,
+                };
+                let vmo = VmObject::new_paged(STACK_GUARD_PAGES + pages(len));
+                let addr =
+                    vmar.map_with_guard(vmar_offset, vmo, guard_size, prot.to_flags(), true)?;
+                return Ok(addr);
+            }
+            // resizable for mremap
+            let vmo = VmObject::new_paged_with_resizable(true, pages(len));
             let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), prot.to_flags())?;
             Ok(addr)
         } else {
             let file_like = self.linux_process().get_file_like(fd)?;
-            let vmo = file_like.get_vmo(offset as usize, len)?;
+            let shared = flags.contains(MmapFlags::SHARED);
+            let vmo = file_like.get_vmo(offset as usize, len, shared)?;
             let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), prot.to_flags())?;
             Ok(addr)
         }
@@ -113,11 +140,11 @@ impl Syscall<'_> {
     /// Set protection on a region of memory
     /// (see [linux man mprotect(2)](https://www.man7.org/linux/man-pages/man2/mprotect.2.html)).
     ///
-    /// **NOTE!** This syscall is now unimplemented. Calling it always return `Ok(0)`.
-    ///
     /// `sys_mprotect` changes the access protections for the calling process's memory pages
     /// containing any part of the address range in the interval `[addr, addr+len-1]`.
-    /// `addr` must be aligned to a page boundary.
+    /// `addr` must be aligned to a page boundary, otherwise an [`EINVAL`](LxError::EINVAL)
+    /// is returned. If any page in the range is not mapped, an [`ENOMEM`](LxError::ENOMEM)
+    /// is returned. Mappings are split where the range begins or ends within them.
     ///
     /// If the calling process tries to access memory in a manner that violates the protections,
     /// then the kernel generates a SIGSEGV signal for the process.
@@ -144,10 +171,76 @@ impl Syscall<'_> {
             "mprotect: addr={:#x}, size={:#x}, prot={:?}",
             addr, len, prot
         );
-        warn!("mprotect: unimplemented");
+        if !page_aligned(addr) {
+            return Err(LxError::EINVAL);
+        }
+        let len = roundup_pages(len);
+        if len == 0 {
+            return Ok(0);
+        }
+        let vmar = self.thread.proc().vmar();
+        vmar.protect(addr, len, prot.to_flags())
+            .map_err(|e| match e {
+                ZxError::NOT_FOUND => LxError::ENOMEM,
+                e => e.into(),
+            })?;
         Ok(0)
     }
 
+    /// Remap a virtual memory address
+    /// (see [linux man mremap(2)](https://www.man7.org/linux/man-pages/man2/mremap.2.html)).
+    ///
+    /// `sys_mremap` expands or shrinks the mapping at `[old_addr, old_addr+old_len)`
+    /// to `new_len` bytes, keeping its content. The range must be in one mapping, otherwise
+    /// an [`EFAULT`](LxError::EFAULT) is returned.
+    ///
+    /// - **`MremapFlags::MAYMOVE`**
+    ///
+    ///   If the mapping can't be expanded in place, it is moved to a new address.
+    ///   Otherwise, an [`ENOMEM`](LxError::ENOMEM) is returned.
+    ///
+    /// - **`MremapFlags::FIXED`**
+    ///
+    ///   The mapping is moved to `new_addr`, replacing any mapping there.
+    ///   It must be used together with `MremapFlags::MAYMOVE`.
+    ///
+    /// Returns the new address of the mapping.
+    pub fn sys_mremap(
+        &self,
+        old_addr: usize,
+        old_len: usize,
+        new_len: usize,
+        flags: usize,
+        new_addr: usize,
+    ) -> SysResult {
+        let flags = MremapFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!(
+            "mremap: old_addr={:#x}, old_size={:#x}, new_size={:#x}, flags={:?}, new_addr={:#x}",
+            old_addr, old_len, new_len, flags, new_addr
+        );
+        if !page_aligned(old_addr) || new_len == 0 {
+            return Err(LxError::EINVAL);
+        }
+        if flags.contains(MremapFlags::FIXED) && !flags.contains(MremapFlags::MAYMOVE) {
+            return Err(LxError::EINVAL);
+        }
+        let new_addr = flags.contains(MremapFlags::FIXED).then(|| new_addr);
+        let vmar = self.thread.proc().vmar();
+        let addr = vmar
+            .remap(
+                old_addr,
+                roundup_pages(old_len),
+                roundup_pages(new_len),
+                flags.contains(MremapFlags::MAYMOVE),
+                new_addr,
+            )
+            .map_err(|e| match e {
+                ZxError::NOT_FOUND => LxError::EFAULT,
+                e => e.into(),
+            })?;
+        Ok(addr)
+    }
+
     /// Unmap files or devices into memory
     /// (see [linux man munmap(2)](https://www.man7.org/linux/man-pages/man2/munmap.2.html)).
     ///
@@ -161,13 +254,103 @@ impl Syscall<'_> {
     ///
     /// Both `addr` and `len` must be aligned to the page size, additionally, `len` must greater than 0.
     /// Otherwise, an [`EINVAL`](LxError::EINVAL) is returned.
+    ///
+    /// Pages of files modified through `MAP_SHARED` mappings in the range are written back
+    /// before they are unmapped.
     pub fn sys_munmap(&self, addr: usize, len: usize) -> SysResult {
         info!("munmap: addr={:#x}, size={:#x}", addr, len);
         let proc = self.thread.proc();
         let vmar = proc.vmar();
+        if page_aligned(addr) && len != 0 {
+            PageCache::sync_vmos(&mapped_vmos(&vmar, addr, len))?;
+        }
         vmar.unmap(addr, len)?;
         Ok(0)
     }
+
+    /// Synchronize a file with a memory map
+    /// (see [linux man msync(2)](https://www.man7.org/linux/man-pages/man2/msync.2.html)).
+    ///
+    /// Pages modified through `MAP_SHARED` mappings of the files mapped in `[addr, addr+len)`
+    /// are written back to the files. All modified pages of those files are written back,
+    /// not only those in the range, and the write back is always synchronous.
+    ///
+    /// `addr` must be aligned to the page size, and `flags` must not contain both
+    /// `MS_ASYNC` and `MS_SYNC`. Otherwise, an [`EINVAL`](LxError::EINVAL) is returned.
+    /// If nothing is mapped in the range, an [`ENOMEM`](LxError::ENOMEM) is returned.
+    pub fn sys_msync(&self, addr: usize, len: usize, flags: usize) -> SysResult {
+        let flags = MsyncFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!(
+            "msync: addr={:#x}, size={:#x}, flags={:?}",
+            addr, len, flags
+        );
+        if !page_aligned(addr) || flags.contains(MsyncFlags::ASYNC | MsyncFlags::SYNC) {
+            return Err(LxError::EINVAL);
+        }
+        let vmar = self.thread.proc().vmar();
+        let vmos = mapped_vmos(&vmar, addr, len);
+        if vmos.is_empty() {
+            return Err(LxError::ENOMEM);
+        }
+        PageCache::sync_vmos(&vmos)?;
+        Ok(0)
+    }
+
+    /// Give advice about use of memory
+    /// (see [linux man madvise(2)](https://www.man7.org/linux/man-pages/man2/madvise.2.html)).
+    ///
+    /// `addr` must be aligned to the page size, otherwise an [`EINVAL`](LxError::EINVAL)
+    /// is returned. If any page in `[addr, addr+len)` is not mapped, an
+    /// [`ENOMEM`](LxError::ENOMEM) is returned.
+    ///
+    /// - **`MADV_DONTNEED`**
+    ///
+    ///   The pages are freed, and the next access sees zero pages. Private file
+    ///   mappings are copies of the file, so they are also zeroed.
+    ///   Pages of `MAP_SHARED` mappings are kept in the page cache.
+    ///
+    /// - **`MADV_FREE`**
+    ///
+    ///   The pages of private anonymous mappings may be freed when memory runs
+    ///   short, unless they are written again.
+    ///
+    /// The other known advice is accepted and ignored.
+    pub fn sys_madvise(&self, addr: usize, len: usize, advice: usize) -> SysResult {
+        info!(
+            "madvise: addr={:#x}, size={:#x}, advice={}",
+            addr, len, advice
+        );
+        if !page_aligned(addr) {
+            return Err(LxError::EINVAL);
+        }
+        let len = roundup_pages(len);
+        if len == 0 {
+            return Ok(0);
+        }
+        let vmar = self.thread.proc().vmar();
+        let res = match advice {
+            MADV_DONTNEED => vmar.decommit(addr, len),
+            MADV_FREE => vmar.mark_freeable(addr, len),
+            MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL | MADV_WILLNEED => Ok(()),
+            MADV_DONTFORK..=MADV_PAGEOUT => Ok(()),
+            _ => return Err(LxError::EINVAL),
+        };
+        res.map_err(|e| match e {
+            ZxError::NOT_FOUND => LxError::ENOMEM,
+            e => e.into(),
+        })?;
+        Ok(0)
+    }
+}
+
+/// Returns the VMOs mapped in `[addr, addr+len)`.
+fn mapped_vmos(vmar: &VmAddressRegion, addr: usize, len: usize) -> Vec<KoID> {
+    let end = addr.saturating_add(len);
+    vmar.get_mappings_info()
+        .iter()
+        .filter(|map| map.addr < end && addr < map.addr + map.size)
+        .map(|map| map.vmo_koid)
+        .collect()
 }
 
 bitflags! {
@@ -182,6 +365,8 @@ bitflags! {
         const FIXED = 1 << 4;
         /// The mapping is not backed by any file. (non-POSIX)
         const ANONYMOUS = MMAP_ANONYMOUS;
+        /// The mapping is a stack growing down. (non-POSIX)
+        const GROWSDOWN = 1 << 8;
     }
 }
 
@@ -217,10 +402,47 @@ impl MmapProt {
         if self.contains(MmapProt::EXEC) {
             flags |= MMUFlags::EXECUTE;
         }
-        // FIXME: hack for unimplemented mprotect
-        if self.is_empty() {
-            flags |= MMUFlags::READ | MMUFlags::WRITE;
-        }
         flags
     }
 }
+
+bitflags! {
+    /// for the flags argument in mremap()
+    pub struct MremapFlags: usize {
+        #[allow(clippy::identity_op)]
+        /// The mapping may be moved to a new address.
+        const MAYMOVE = 1 << 0;
+        /// The mapping is moved to the given new address.
+        const FIXED = 1 << 1;
+    }
+}
+
+bitflags! {
+    /// for the flags argument in msync()
+    pub struct MsyncFlags: usize {
+        #[allow(clippy::identity_op)]
+        /// Schedule the write back and return.
+        const ASYNC = 1 << 0;
+        /// Invalidate other mappings of the same file.
+        const INVALIDATE = 1 << 1;
+        /// Write back and wait for it to complete.
+        const SYNC = 1 << 2;
+    }
+}
+
+/// No special treatment.
+const MADV_NORMAL: usize = 0;
+/// Expect page references in random order.
+const MADV_RANDOM: usize = 1;
+/// Expect page references in sequential order.
+const MADV_SEQUENTIAL: usize = 2;
+/// Expect access in the near future.
+const MADV_WILLNEED: usize = 3;
+/// Do not expect access in the near future, the pages are freed.
+const MADV_DONTNEED: usize = 4;
+/// The pages may be freed until written again.
+const MADV_FREE: usize = 8;
+/// The first of the hints about fork, merging, huge pages, core dumps and reclaim.
+const MADV_DONTFORK: usize = 10;
+/// The last of the hints about fork, merging, huge pages, core dumps and reclaim.
+const MADV_PAGEOUT: usize = 21;
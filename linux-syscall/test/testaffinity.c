@@ -0,0 +1,40 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <errno.h>
+#include <assert.h>
+#include <sched.h>
+#include <unistd.h>
+#include <sys/syscall.h>
+
+int main(int argc, char **argv)
+{
+    cpu_set_t set;
+
+    // threads may run on any CPU at first
+    CPU_ZERO(&set);
+    assert(sched_getaffinity(0, sizeof(set), &set) == 0);
+    int ncpus = CPU_COUNT(&set);
+    assert(ncpus >= 1);
+    assert(CPU_ISSET(0, &set));
+
+    // the mask is kept
+    CPU_ZERO(&set);
+    CPU_SET(0, &set);
+    assert(sched_setaffinity(0, sizeof(set), &set) == 0);
+    CPU_ZERO(&set);
+    assert(sched_getaffinity(syscall(SYS_gettid), sizeof(set), &set) == 0);
+    assert(CPU_COUNT(&set) == 1 && CPU_ISSET(0, &set));
+
+    // the raw syscall returns the size of the mask
+    long size = syscall(SYS_sched_getaffinity, 0, sizeof(set), &set);
+    assert(size > 0 && size <= sizeof(set) && size % sizeof(long) == 0);
+
+    // bad arguments
+    CPU_ZERO(&set);
+    assert(sched_setaffinity(0, sizeof(set), &set) == -1 && errno == EINVAL);
+    assert(syscall(SYS_sched_getaffinity, 0, 3, &set) == -1 && errno == EINVAL);
+    assert(sched_getaffinity(99999, sizeof(set), &set) == -1 && errno == ESRCH);
+
+    printf("affinity test passed\n");
+    return 0;
+}
@@ -0,0 +1,68 @@
+#include <time.h>
+#include <stdio.h>
+#include <errno.h>
+#include <assert.h>
+
+static long long to_nsec(struct timespec *ts)
+{
+    return ts->tv_sec * 1000000000LL + ts->tv_nsec;
+}
+
+int main(int argc, char **argv)
+{
+    struct timespec ts, ts2;
+
+    // all clocks are readable, and the monotonic ones never go back
+    clockid_t clocks[] = {CLOCK_REALTIME, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID,
+                          CLOCK_THREAD_CPUTIME_ID, CLOCK_MONOTONIC_RAW, CLOCK_REALTIME_COARSE,
+                          CLOCK_MONOTONIC_COARSE, CLOCK_BOOTTIME};
+    for (int i = 0; i < sizeof(clocks) / sizeof(clocks[0]); i++)
+    {
+        assert(clock_gettime(clocks[i], &ts) == 0);
+        assert(ts.tv_nsec >= 0 && ts.tv_nsec < 1000000000);
+        assert(clock_getres(clocks[i], &ts) == 0);
+        assert(ts.tv_sec == 0 && ts.tv_nsec > 0);
+    }
+    assert(clock_gettime(CLOCK_MONOTONIC_RAW, &ts) == 0);
+    assert(clock_gettime(CLOCK_MONOTONIC_RAW, &ts2) == 0);
+    assert(to_nsec(&ts2) >= to_nsec(&ts));
+
+    // unknown clocks
+    assert(clock_gettime(100, &ts) == -1 && errno == EINVAL);
+    assert(clock_getres(100, &ts) == -1 && errno == EINVAL);
+
+    // set the realtime clock an hour later, which leaves the monotonic clock
+    struct timespec mono, mono2, real;
+    assert(clock_gettime(CLOCK_MONOTONIC, &mono) == 0);
+    assert(clock_gettime(CLOCK_REALTIME, &real) == 0);
+    real.tv_sec += 3600;
+    assert(clock_settime(CLOCK_REALTIME, &real) == 0);
+    assert(clock_gettime(CLOCK_REALTIME, &ts) == 0);
+    assert(to_nsec(&ts) >= to_nsec(&real));
+    assert(to_nsec(&ts) - to_nsec(&real) < 1000000000LL);
+    assert(clock_gettime(CLOCK_MONOTONIC, &mono2) == 0);
+    assert(to_nsec(&mono2) - to_nsec(&mono) < 1000000000LL);
+    real.tv_sec -= 3600;
+    assert(clock_settime(CLOCK_REALTIME, &real) == 0);
+
+    // only the realtime clock is settable
+    assert(clock_settime(CLOCK_MONOTONIC, &mono) == -1 && errno == EINVAL);
+    real.tv_nsec = 1000000000;
+    assert(clock_settime(CLOCK_REALTIME, &real) == -1 && errno == EINVAL);
+
+    // sleep until an absolute time of the realtime clock
+    assert(clock_gettime(CLOCK_REALTIME, &real) == 0);
+    assert(clock_gettime(CLOCK_MONOTONIC, &mono) == 0);
+    real.tv_nsec += 10000000;
+    if (real.tv_nsec >= 1000000000)
+    {
+        real.tv_sec += 1;
+        real.tv_nsec -= 1000000000;
+    }
+    assert(clock_nanosleep(CLOCK_REALTIME, TIMER_ABSTIME, &real, NULL) == 0);
+    assert(clock_gettime(CLOCK_MONOTONIC, &mono2) == 0);
+    assert(to_nsec(&mono2) - to_nsec(&mono) < 1000000000LL);
+
+    printf("clock test passed\n");
+    return 0;
+}
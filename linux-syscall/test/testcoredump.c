@@ -0,0 +1,79 @@
+#include <elf.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+
+// Fork a child which faults with the soft limit of `RLIMIT_CORE` set to
+// `limit`, returns its pid.
+static pid_t fault_child(rlim_t limit, int *status)
+{
+    pid_t pid = fork();
+    if (pid == 0)
+    {
+        struct rlimit rlim = {limit, RLIM_INFINITY};
+        if (setrlimit(RLIMIT_CORE, &rlim) != 0)
+            exit(1);
+        *(volatile int *)0 = 0;
+        exit(2);
+    }
+    assert(pid > 0);
+    assert(waitpid(pid, status, 0) == pid);
+    assert(WIFSIGNALED(*status) && WTERMSIG(*status) == SIGSEGV);
+    return pid;
+}
+
+int main(int argc, char **argv)
+{
+    int status;
+    char path[32];
+    // the core is dumped in the working directory
+    assert(chdir("/tmp") == 0);
+
+    // the limit is inherited, 0 by default
+    struct rlimit rlim;
+    assert(getrlimit(RLIMIT_CORE, &rlim) == 0 && rlim.rlim_cur == 0);
+    pid_t pid = fault_child(0, &status);
+    assert(!WCOREDUMP(status));
+    snprintf(path, sizeof(path), "core.%d", pid);
+    assert(access(path, F_OK) == -1 && errno == ENOENT);
+
+    pid = fault_child(RLIM_INFINITY, &status);
+    assert(WCOREDUMP(status));
+    snprintf(path, sizeof(path), "core.%d", pid);
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    Elf64_Ehdr ehdr;
+    assert(read(fd, &ehdr, sizeof(ehdr)) == sizeof(ehdr));
+    assert(memcmp(ehdr.e_ident, ELFMAG, SELFMAG) == 0);
+    assert(ehdr.e_ident[EI_CLASS] == ELFCLASS64);
+    assert(ehdr.e_type == ET_CORE);
+    assert(ehdr.e_phoff == sizeof(ehdr) && ehdr.e_phnum > 1);
+    // the notes come first, followed by the memory
+    Elf64_Phdr phdr;
+    assert(pread(fd, &phdr, sizeof(phdr), ehdr.e_phoff) == sizeof(phdr));
+    assert(phdr.p_type == PT_NOTE && phdr.p_filesz > 0);
+    assert(pread(fd, &phdr, sizeof(phdr), ehdr.e_phoff + ehdr.e_phentsize) == sizeof(phdr));
+    assert(phdr.p_type == PT_LOAD);
+    close(fd);
+    assert(unlink(path) == 0);
+
+    // the core is truncated at the limit
+    pid = fault_child(4096, &status);
+    assert(WCOREDUMP(status));
+    snprintf(path, sizeof(path), "core.%d", pid);
+    struct stat st;
+    assert(stat(path, &st) == 0);
+    assert(st.st_size > 0 && st.st_size <= 4096);
+    assert(unlink(path) == 0);
+
+    printf("coredump test passed\n");
+    return 0;
+}
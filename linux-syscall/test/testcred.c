@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <grp.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <assert.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <sys/prctl.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+
+#define FILE_PATH "/tmp/testcred"
+
+#define CAP_VERSION_3 0x20080522
+#define CAP_DAC_OVERRIDE 1
+#define CAP_SETUID 7
+
+struct cap_header {
+    unsigned int version;
+    int pid;
+};
+
+struct cap_data {
+    unsigned int effective;
+    unsigned int permitted;
+    unsigned int inheritable;
+};
+
+static struct cap_data capget0(void)
+{
+    struct cap_header header = {CAP_VERSION_3, 0};
+    struct cap_data data[2];
+    assert(syscall(SYS_capget, &header, data) == 0);
+    return data[0];
+}
+
+static int has_cap(unsigned int caps, int cap)
+{
+    return (caps >> cap) & 1;
+}
+
+static void test_unprivileged(void)
+{
+    uid_t r, e, s;
+
+    assert(setgroups(0, NULL) == 0);
+    assert(setresgid(100, 100, 100) == 0);
+    // root is kept as the saved user ID
+    assert(setresuid(1000, 1000, 0) == 0);
+    assert(getresuid(&r, &e, &s) == 0);
+    assert(r == 1000 && e == 1000 && s == 0);
+    assert(getgid() == 100 && getegid() == 100);
+
+    // the effective capabilities are dropped with the effective root
+    struct cap_data caps = capget0();
+    assert(caps.effective == 0);
+    assert(has_cap(caps.permitted, CAP_SETUID));
+    assert(access(FILE_PATH, R_OK) == -1 && errno == EACCES);
+    assert(open(FILE_PATH, O_RDONLY) == -1 && errno == EACCES);
+    assert(chown(FILE_PATH, 1000, 100) == -1 && errno == EPERM);
+
+    // and restored from the permitted ones with it
+    assert(seteuid(0) == 0);
+    caps = capget0();
+    assert(has_cap(caps.effective, CAP_DAC_OVERRIDE));
+    int fd = open(FILE_PATH, O_RDONLY);
+    assert(fd >= 0);
+    close(fd);
+    assert(chown(FILE_PATH, 1000, 100) == 0);
+
+    // all capabilities are lost without root in any user ID
+    assert(setresuid(1000, 1000, 1000) == 0);
+    caps = capget0();
+    assert(caps.effective == 0 && caps.permitted == 0);
+    assert(setuid(0) == -1 && errno == EPERM);
+    assert(seteuid(0) == -1 && errno == EPERM);
+    assert(setgid(0) == -1 && errno == EPERM);
+    assert(setgroups(0, NULL) == -1 && errno == EPERM);
+
+    // the owner can only change the group to one of its groups
+    assert(access(FILE_PATH, R_OK | W_OK) == 0);
+    assert(chown(FILE_PATH, -1, 0) == -1 && errno == EPERM);
+    assert(chown(FILE_PATH, 0, -1) == -1 && errno == EPERM);
+    assert(chown(FILE_PATH, -1, 100) == 0);
+
+    // capabilities can only be dropped
+    struct cap_header header = {CAP_VERSION_3, 0};
+    struct cap_data data[2] = {{1 << CAP_SETUID, 1 << CAP_SETUID, 0}, {0, 0, 0}};
+    assert(syscall(SYS_capset, &header, data) == -1 && errno == EPERM);
+
+    // a new file is owned by the filesystem IDs
+    fd = open(FILE_PATH ".own", O_CREAT | O_WRONLY, 0600);
+    assert(fd >= 0);
+    close(fd);
+    struct stat st;
+    assert(stat(FILE_PATH ".own", &st) == 0);
+    assert(st.st_uid == 1000 && st.st_gid == 100);
+    assert(chmod(FILE_PATH ".own", 0644) == 0);
+    assert(chmod("/tmp", 0777) == -1 && errno == EPERM);
+
+    // a seccomp filter needs no_new_privs without CAP_SYS_ADMIN
+    struct sock_filter allow = BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW);
+    struct sock_fprog prog = {1, &allow};
+    assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &prog) == -1 && errno == EACCES);
+    assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+    assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &prog) == 0);
+}
+
+int main(int argc, char **argv)
+{
+    int status;
+    struct stat st;
+    unlink(FILE_PATH);
+    unlink(FILE_PATH ".own");
+    int fd = open(FILE_PATH, O_CREAT | O_WRONLY, 0600);
+    assert(fd >= 0);
+    close(fd);
+
+    // root has all capabilities
+    struct cap_data caps = capget0();
+    assert(has_cap(caps.effective, CAP_DAC_OVERRIDE));
+    assert(has_cap(caps.effective, CAP_SETUID));
+    // an unknown version gets the preferred one
+    struct cap_header header = {0, 0};
+    assert(syscall(SYS_capget, &header, NULL) == -1 && errno == EINVAL);
+    assert(header.version == CAP_VERSION_3);
+
+    pid_t child = fork();
+    if (child == 0)
+    {
+        test_unprivileged();
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(getuid() == 0 && geteuid() == 0);
+    assert(stat(FILE_PATH, &st) == 0);
+    assert(st.st_uid == 1000 && st.st_gid == 100);
+
+    // the files of the other user are accessible by root
+    fd = open(FILE_PATH ".own", O_RDWR);
+    assert(fd >= 0);
+    close(fd);
+    assert(chown(FILE_PATH, 0, 0) == 0);
+
+    assert(unlink(FILE_PATH) == 0);
+    assert(unlink(FILE_PATH ".own") == 0);
+    printf("credentials test passed\n");
+    return 0;
+}
@@ -0,0 +1,133 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/epoll.h>
+#include <sys/wait.h>
+
+static volatile sig_atomic_t handled;
+
+static void handler(int sig, siginfo_t *info, void *ucontext)
+{
+    handled = sig;
+}
+
+int main(int argc, char **argv)
+{
+    int pipefd[2];
+    struct epoll_event ev, events[4];
+    char buf[8];
+
+    assert(pipe(pipefd) == 0);
+    int epfd = epoll_create1(EPOLL_CLOEXEC);
+    assert(epfd >= 0);
+
+    // level-triggered
+    ev.events = EPOLLIN;
+    ev.data.fd = pipefd[0];
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, pipefd[0], &ev) == 0);
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, pipefd[0], &ev) == -1);
+
+    // test time out
+    assert(epoll_wait(epfd, events, 4, 100) == 0);
+
+    write(pipefd[1], "test", strlen("test"));
+    assert(epoll_wait(epfd, events, 4, 1000) == 1);
+    assert(events[0].data.fd == pipefd[0]);
+    assert(events[0].events & EPOLLIN);
+    // still readable
+    assert(epoll_wait(epfd, events, 4, 0) == 1);
+
+    // edge-triggered
+    ev.events = EPOLLIN | EPOLLET;
+    assert(epoll_ctl(epfd, EPOLL_CTL_MOD, pipefd[0], &ev) == 0);
+    assert(epoll_wait(epfd, events, 4, 0) == 1);
+    assert(epoll_wait(epfd, events, 4, 0) == 0);
+    // more data is a new edge
+    assert(write(pipefd[1], "more", 4) == 4);
+    assert(epoll_wait(epfd, events, 4, 0) == 1);
+    assert(epoll_wait(epfd, events, 4, 0) == 0);
+    assert(read(pipefd[0], buf, sizeof(buf)) == 8);
+
+    assert(epoll_ctl(epfd, EPOLL_CTL_DEL, pipefd[0], NULL) == 0);
+    assert(epoll_ctl(epfd, EPOLL_CTL_DEL, pipefd[0], NULL) == -1);
+
+    // a closed file is removed, and its descriptor is watched again after reused
+    ev.events = EPOLLIN;
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, pipefd[0], &ev) == 0);
+    assert(write(pipefd[1], "test", 4) == 4);
+    close(pipefd[0]);
+    close(pipefd[1]);
+    assert(epoll_wait(epfd, events, 4, 0) == 0);
+    int newfd[2];
+    assert(pipe(newfd) == 0);
+    assert(newfd[0] == pipefd[0]);
+    ev.data.fd = newfd[0];
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, newfd[0], &ev) == 0);
+    assert(epoll_wait(epfd, events, 4, 0) == 0);
+
+    // an instance can not watch itself through another one
+    int epfd2 = epoll_create1(0);
+    assert(epfd2 >= 0);
+    ev.events = EPOLLIN;
+    assert(epoll_ctl(epfd2, EPOLL_CTL_ADD, epfd, &ev) == 0);
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, epfd2, &ev) == -1 && errno == ELOOP);
+
+    // a blocking wait is woken up by a write, through the nested instance
+    pid_t child = fork();
+    if (child == 0)
+    {
+        usleep(100000);
+        assert(write(newfd[1], "test", 4) == 4);
+        exit(0);
+    }
+    assert(epoll_wait(epfd2, events, 4, -1) == 1);
+    assert(epoll_wait(epfd, events, 4, 0) == 1);
+    assert(events[0].data.fd == newfd[0]);
+    assert(waitpid(child, NULL, 0) == child);
+    assert(read(newfd[0], buf, sizeof(buf)) == 4);
+    close(epfd2);
+
+    // a file stays watched while a duplicate of it is open
+    int dupfds[2];
+    assert(pipe(dupfds) == 0);
+    ev.events = EPOLLIN;
+    ev.data.fd = dupfds[0];
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, dupfds[0], &ev) == 0);
+    int dupfd = dup(dupfds[0]);
+    assert(dupfd >= 0);
+    close(dupfds[0]);
+    assert(write(dupfds[1], "test", 4) == 4);
+    assert(epoll_wait(epfd, events, 4, 0) == 1);
+    assert(events[0].data.fd == dupfds[0]);
+    // and is removed after the last of them is closed
+    close(dupfd);
+    assert(epoll_wait(epfd, events, 4, 0) == 0);
+    close(dupfds[1]);
+
+    // a signal unblocked by the mask of epoll_pwait interrupts it
+    struct sigaction sa = {0};
+    sa.sa_sigaction = handler;
+    sa.sa_flags = SA_SIGINFO;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    sigset_t mask, current;
+    sigemptyset(&mask);
+    sigaddset(&mask, SIGUSR1);
+    assert(sigprocmask(SIG_BLOCK, &mask, NULL) == 0);
+    assert(kill(getpid(), SIGUSR1) == 0);
+    sigemptyset(&mask);
+    assert(epoll_pwait(epfd, events, 4, -1, &mask) == -1 && errno == EINTR);
+    assert(handled == SIGUSR1);
+    assert(sigprocmask(SIG_SETMASK, NULL, &current) == 0);
+    assert(sigismember(&current, SIGUSR1));
+
+    close(newfd[0]);
+    close(newfd[1]);
+    close(epfd);
+
+    printf("epoll test passed\n");
+    return 0;
+}
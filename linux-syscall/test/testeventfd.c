@@ -0,0 +1,64 @@
+#include <stdio.h>
+#include <stdint.h>
+#include <unistd.h>
+#include <assert.h>
+#include <poll.h>
+#include <signal.h>
+#include <sys/eventfd.h>
+#include <sys/signalfd.h>
+#include <sys/timerfd.h>
+
+int main(int argc, char **argv)
+{
+    uint64_t val;
+    struct pollfd fds[1];
+
+    // test eventfd
+    int efd = eventfd(0, EFD_NONBLOCK);
+    assert(efd >= 0);
+    assert(read(efd, &val, sizeof(val)) == -1);
+    val = 3;
+    assert(write(efd, &val, sizeof(val)) == sizeof(val));
+    fds[0].fd = efd;
+    fds[0].events = POLLIN;
+    assert(poll(fds, 1, 0) == 1);
+    assert(read(efd, &val, sizeof(val)) == sizeof(val));
+    assert(val == 3);
+    close(efd);
+
+    // test eventfd semaphore mode
+    efd = eventfd(2, EFD_SEMAPHORE);
+    assert(read(efd, &val, sizeof(val)) == sizeof(val) && val == 1);
+    assert(read(efd, &val, sizeof(val)) == sizeof(val) && val == 1);
+    close(efd);
+
+    // test timerfd
+    int tfd = timerfd_create(CLOCK_MONOTONIC, 0);
+    assert(tfd >= 0);
+    struct itimerspec its = {
+        .it_interval = {0, 0},
+        .it_value = {0, 100000000},
+    };
+    assert(timerfd_settime(tfd, 0, &its, NULL) == 0);
+    assert(read(tfd, &val, sizeof(val)) == sizeof(val));
+    assert(val == 1);
+    assert(timerfd_gettime(tfd, &its) == 0);
+    assert(its.it_value.tv_sec == 0 && its.it_value.tv_nsec == 0);
+    close(tfd);
+
+    // test signalfd
+    sigset_t mask;
+    sigemptyset(&mask);
+    sigaddset(&mask, SIGUSR1);
+    sigprocmask(SIG_BLOCK, &mask, NULL);
+    int sfd = signalfd(-1, &mask, 0);
+    assert(sfd >= 0);
+    kill(getpid(), SIGUSR1);
+    struct signalfd_siginfo info;
+    assert(read(sfd, &info, sizeof(info)) == sizeof(info));
+    assert(info.ssi_signo == SIGUSR1);
+    close(sfd);
+
+    printf("eventfd, timerfd and signalfd test passed\n");
+    return 0;
+}
@@ -0,0 +1,78 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+
+#define FIFO_PATH "/tmp/testfifo"
+
+static volatile sig_atomic_t handled;
+
+static void handler(int sig, siginfo_t *info, void *ucontext)
+{
+    handled = sig;
+}
+
+int main(int argc, char **argv)
+{
+    int status;
+    unlink(FIFO_PATH);
+    assert(mkfifo(FIFO_PATH, 0600) == 0);
+
+    // a signal interrupts the open waiting for the other end
+    struct sigaction sa = {0};
+    sa.sa_sigaction = handler;
+    sa.sa_flags = SA_SIGINFO;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    pid_t parent = getpid();
+    pid_t child = fork();
+    if (child == 0)
+    {
+        usleep(100000);
+        kill(parent, SIGUSR1);
+        exit(0);
+    }
+    assert(open(FIFO_PATH, O_RDONLY) == -1 && errno == EINTR);
+    assert(handled == SIGUSR1);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+
+    // the interrupted open leaves no read end
+    assert(open(FIFO_PATH, O_WRONLY | O_NONBLOCK) == -1 && errno == ENXIO);
+
+    // a blocking open waits for the other end
+    child = fork();
+    if (child == 0)
+    {
+        usleep(100000);
+        int fd = open(FIFO_PATH, O_WRONLY);
+        assert(fd >= 0);
+        assert(write(fd, "fifo", 4) == 4);
+        close(fd);
+        exit(0);
+    }
+    int fd = open(FIFO_PATH, O_RDONLY);
+    assert(fd >= 0);
+    char buf[8];
+    assert(read(fd, buf, sizeof(buf)) == 4);
+    assert(memcmp(buf, "fifo", 4) == 0);
+    assert(read(fd, buf, sizeof(buf)) == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    close(fd);
+
+    // a nonblocking read end opens at once, then a write end can open
+    fd = open(FIFO_PATH, O_RDONLY | O_NONBLOCK);
+    assert(fd >= 0);
+    int wfd = open(FIFO_PATH, O_WRONLY | O_NONBLOCK);
+    assert(wfd >= 0);
+    close(wfd);
+    close(fd);
+
+    assert(unlink(FIFO_PATH) == 0);
+    printf("fifo test passed\n");
+    return 0;
+}
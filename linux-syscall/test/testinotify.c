@@ -0,0 +1,145 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/inotify.h>
+#include <sys/ioctl.h>
+#include <sys/stat.h>
+
+#ifndef IN_MASK_CREATE
+#define IN_MASK_CREATE 0x10000000
+#endif
+
+#define DIR_PATH "/tmp/testinotify"
+#define FILE_A DIR_PATH "/a"
+#define FILE_B DIR_PATH "/b"
+#define FILE_X "/tmp/testinotify_x"
+#define FILE_Y "/tmp/testinotify_y"
+
+// the default of /proc/sys/fs/inotify/max_queued_events
+#define MAX_QUEUED_EVENTS 16384
+#define EVENT_SIZE sizeof(struct inotify_event)
+
+static char buf[(MAX_QUEUED_EVENTS + 1) * EVENT_SIZE] __attribute__((aligned(8)));
+
+// Read the queued events, returns the number of them.
+static int read_events(int fd, struct inotify_event **events, int max)
+{
+    int len = read(fd, buf, sizeof(buf));
+    assert(len > 0);
+    int count = 0;
+    for (char *p = buf; p < buf + len; count++)
+    {
+        assert(count < max);
+        events[count] = (struct inotify_event *)p;
+        p += EVENT_SIZE + events[count]->len;
+    }
+    return count;
+}
+
+static void check_event(struct inotify_event *e, int wd, uint32_t mask, const char *name)
+{
+    assert(e->wd == wd && e->mask == mask);
+    if (name)
+        assert(e->len > strlen(name) && strcmp(e->name, name) == 0);
+    else
+        assert(e->len == 0);
+}
+
+static void test_dir_events(void)
+{
+    struct inotify_event *events[8];
+    int fd = inotify_init1(IN_NONBLOCK | IN_CLOEXEC);
+    assert(fd >= 0);
+    assert(read(fd, buf, sizeof(buf)) == -1 && errno == EAGAIN);
+    assert(mkdir(DIR_PATH, 0755) == 0);
+    int dir_wd = inotify_add_watch(fd, DIR_PATH, IN_CREATE | IN_DELETE | IN_MOVE);
+    assert(dir_wd > 0);
+    assert(inotify_add_watch(fd, DIR_PATH, IN_CREATE | IN_MASK_CREATE) == -1 && errno == EEXIST);
+    assert(inotify_add_watch(fd, FILE_A, IN_MODIFY) == -1 && errno == ENOENT);
+
+    int file = open(FILE_A, O_CREAT | O_WRONLY, 0644);
+    assert(file >= 0);
+    int file_wd = inotify_add_watch(fd, FILE_A, IN_MODIFY);
+    assert(file_wd > 0 && file_wd != dir_wd);
+    assert(write(file, "x", 1) == 1);
+    close(file);
+    assert(rename(FILE_A, FILE_B) == 0);
+    assert(unlink(FILE_B) == 0);
+
+    // the file watch is removed with the file
+    int count;
+    assert(ioctl(fd, FIONREAD, &count) == 0 && count > 0);
+    assert(read_events(fd, events, 8) == 6);
+    check_event(events[0], dir_wd, IN_CREATE, "a");
+    check_event(events[1], file_wd, IN_MODIFY, NULL);
+    check_event(events[2], dir_wd, IN_MOVED_FROM, "a");
+    check_event(events[3], dir_wd, IN_MOVED_TO, "b");
+    check_event(events[4], dir_wd, IN_DELETE, "b");
+    check_event(events[5], file_wd, IN_IGNORED, NULL);
+    // the two events of a rename are paired by their cookie
+    assert(events[2]->cookie != 0 && events[2]->cookie == events[3]->cookie);
+    assert(events[0]->cookie == 0 && events[4]->cookie == 0);
+
+    // the events of subdirectories are flagged
+    assert(mkdir(FILE_A, 0755) == 0);
+    assert(rmdir(FILE_A) == 0);
+    assert(read_events(fd, events, 8) == 2);
+    check_event(events[0], dir_wd, IN_CREATE | IN_ISDIR, "a");
+    check_event(events[1], dir_wd, IN_DELETE | IN_ISDIR, "a");
+
+    assert(inotify_rm_watch(fd, dir_wd) == 0);
+    assert(inotify_rm_watch(fd, dir_wd) == -1 && errno == EINVAL);
+    assert(read_events(fd, events, 8) == 1);
+    check_event(events[0], dir_wd, IN_IGNORED, NULL);
+    assert(read(fd, buf, sizeof(buf)) == -1 && errno == EAGAIN);
+    assert(rmdir(DIR_PATH) == 0);
+    close(fd);
+}
+
+static void test_overflow(void)
+{
+    int fd = inotify_init1(IN_NONBLOCK);
+    int x = open(FILE_X, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+    int y = open(FILE_Y, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+    assert(fd >= 0 && x >= 0 && y >= 0);
+    int x_wd = inotify_add_watch(fd, FILE_X, IN_MODIFY);
+    int y_wd = inotify_add_watch(fd, FILE_Y, IN_MODIFY);
+    assert(x_wd > 0 && y_wd > 0);
+
+    // alternate the files, as an event identical to the last one is merged
+    for (int i = 0; i < MAX_QUEUED_EVENTS / 2 + 1; i++)
+    {
+        assert(write(x, "x", 1) == 1);
+        assert(write(y, "y", 1) == 1);
+    }
+    int count;
+    assert(ioctl(fd, FIONREAD, &count) == 0);
+    assert(count == (MAX_QUEUED_EVENTS + 1) * EVENT_SIZE);
+
+    // a buffer too small for an event is rejected
+    assert(read(fd, buf, EVENT_SIZE - 1) == -1 && errno == EINVAL);
+    assert(read(fd, buf, sizeof(buf)) == sizeof(buf));
+    struct inotify_event *events = (struct inotify_event *)buf;
+    check_event(&events[0], x_wd, IN_MODIFY, NULL);
+    check_event(&events[1], y_wd, IN_MODIFY, NULL);
+    check_event(&events[MAX_QUEUED_EVENTS], -1, IN_Q_OVERFLOW, NULL);
+    assert(read(fd, buf, sizeof(buf)) == -1 && errno == EAGAIN);
+
+    close(x);
+    close(y);
+    close(fd);
+    assert(unlink(FILE_X) == 0);
+    assert(unlink(FILE_Y) == 0);
+}
+
+int main(int argc, char **argv)
+{
+    test_dir_events();
+    test_overflow();
+    printf("inotify test passed\n");
+    return 0;
+}
@@ -0,0 +1,173 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+
+#ifndef SYS_io_uring_setup
+#define SYS_io_uring_setup 425
+#define SYS_io_uring_enter 426
+#endif
+
+#define IORING_OFF_SQ_RING 0ULL
+#define IORING_OFF_CQ_RING 0x8000000ULL
+#define IORING_OFF_SQES 0x10000000ULL
+#define IORING_ENTER_GETEVENTS 1
+#define IORING_OP_NOP 0
+#define IORING_OP_READ 22
+
+struct sqring_offsets
+{
+    uint32_t head, tail, ring_mask, ring_entries, flags, dropped, array, resv1;
+    uint64_t resv2;
+};
+
+struct cqring_offsets
+{
+    uint32_t head, tail, ring_mask, ring_entries, overflow, cqes, flags, resv1;
+    uint64_t resv2;
+};
+
+struct uring_params
+{
+    uint32_t sq_entries, cq_entries, flags, sq_thread_cpu, sq_thread_idle;
+    uint32_t features, wq_fd, resv[3];
+    struct sqring_offsets sq_off;
+    struct cqring_offsets cq_off;
+};
+
+struct sqe
+{
+    uint8_t opcode, flags;
+    uint16_t ioprio;
+    int32_t fd;
+    uint64_t off, addr;
+    uint32_t len, op_flags;
+    uint64_t user_data;
+    uint64_t pad[3];
+};
+
+struct cqe
+{
+    uint64_t user_data;
+    int32_t res;
+    uint32_t flags;
+};
+
+struct ring
+{
+    int fd;
+    uint32_t *sq_tail, *sq_mask, *sq_array;
+    uint32_t *cq_head, *cq_tail, *cq_mask;
+    struct sqe *sqes;
+    struct cqe *cqes;
+};
+
+static void ring_init(struct ring *ring)
+{
+    struct uring_params p;
+    memset(&p, 0, sizeof(p));
+    ring->fd = syscall(SYS_io_uring_setup, 4, &p);
+    assert(ring->fd >= 0);
+    size_t sq_len = p.sq_off.array + p.sq_entries * 4;
+    size_t cq_len = p.cq_off.cqes + p.cq_entries * sizeof(struct cqe);
+    char *sq = mmap(NULL, sq_len, PROT_READ | PROT_WRITE, MAP_SHARED, ring->fd, IORING_OFF_SQ_RING);
+    char *cq = mmap(NULL, cq_len, PROT_READ | PROT_WRITE, MAP_SHARED, ring->fd, IORING_OFF_CQ_RING);
+    ring->sqes = mmap(NULL, p.sq_entries * sizeof(struct sqe), PROT_READ | PROT_WRITE, MAP_SHARED,
+                      ring->fd, IORING_OFF_SQES);
+    assert(sq != MAP_FAILED && cq != MAP_FAILED && ring->sqes != MAP_FAILED);
+    ring->sq_tail = (uint32_t *)(sq + p.sq_off.tail);
+    ring->sq_mask = (uint32_t *)(sq + p.sq_off.ring_mask);
+    ring->sq_array = (uint32_t *)(sq + p.sq_off.array);
+    ring->cq_head = (uint32_t *)(cq + p.cq_off.head);
+    ring->cq_tail = (uint32_t *)(cq + p.cq_off.tail);
+    ring->cq_mask = (uint32_t *)(cq + p.cq_off.ring_mask);
+    ring->cqes = (struct cqe *)(cq + p.cq_off.cqes);
+}
+
+// queue an entry and submit it, without waiting for the completion
+static void submit(struct ring *ring, uint8_t opcode, int fd, void *buf, uint32_t len, uint64_t user_data)
+{
+    uint32_t tail = *ring->sq_tail;
+    uint32_t index = tail & *ring->sq_mask;
+    struct sqe *sqe = &ring->sqes[index];
+    memset(sqe, 0, sizeof(*sqe));
+    sqe->opcode = opcode;
+    sqe->fd = fd;
+    sqe->off = (uint64_t)-1;
+    sqe->addr = (uint64_t)(uintptr_t)buf;
+    sqe->len = len;
+    sqe->user_data = user_data;
+    ring->sq_array[index] = index;
+    __atomic_store_n(ring->sq_tail, tail + 1, __ATOMIC_RELEASE);
+    assert(syscall(SYS_io_uring_enter, ring->fd, 1, 0, 0, NULL, 0) == 1);
+}
+
+// wait for a completion and consume it
+static struct cqe reap(struct ring *ring)
+{
+    assert(syscall(SYS_io_uring_enter, ring->fd, 0, 1, IORING_ENTER_GETEVENTS, NULL, 0) == 0);
+    uint32_t head = *ring->cq_head;
+    assert(head != __atomic_load_n(ring->cq_tail, __ATOMIC_ACQUIRE));
+    struct cqe cqe = ring->cqes[head & *ring->cq_mask];
+    __atomic_store_n(ring->cq_head, head + 1, __ATOMIC_RELEASE);
+    return cqe;
+}
+
+int main(int argc, char **argv)
+{
+    struct ring ring;
+    struct cqe cqe;
+    char buf[8];
+    int pipefd[2];
+    int status;
+
+    ring_init(&ring);
+    submit(&ring, IORING_OP_NOP, -1, NULL, 0, 1);
+    cqe = reap(&ring);
+    assert(cqe.user_data == 1 && cqe.res == 0);
+
+    // a read of an empty pipe completes once written
+    assert(pipe(pipefd) == 0);
+    submit(&ring, IORING_OP_READ, pipefd[0], buf, sizeof(buf), 2);
+    assert(write(pipefd[1], "test", 4) == 4);
+    cqe = reap(&ring);
+    assert(cqe.user_data == 2 && cqe.res == 4);
+    assert(memcmp(buf, "test", 4) == 0);
+
+    // a bad file fails the operation, not the submission
+    submit(&ring, IORING_OP_READ, 100, buf, sizeof(buf), 3);
+    cqe = reap(&ring);
+    assert(cqe.user_data == 3 && cqe.res == -EBADF);
+
+    // the operations running when the process exits are canceled, and
+    // release the files
+    pid_t child = fork();
+    if (child == 0)
+    {
+        struct ring other;
+        close(pipefd[1]);
+        ring_init(&other);
+        submit(&other, IORING_OP_READ, pipefd[0], buf, sizeof(buf), 4);
+        close(pipefd[0]);
+        exit(0);
+    }
+    close(pipefd[0]);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    signal(SIGPIPE, SIG_IGN);
+    for (int i = 0; i < 100 && write(pipefd[1], "x", 1) == 1; i++)
+        usleep(10000);
+    assert(write(pipefd[1], "x", 1) == -1 && errno == EPIPE);
+
+    close(pipefd[1]);
+    close(ring.fd);
+    printf("io_uring test passed\n");
+    return 0;
+}
@@ -0,0 +1,174 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/ipc.h>
+#include <sys/msg.h>
+#include <sys/sem.h>
+#include <sys/wait.h>
+
+union semun {
+    int val;
+    struct semid_ds *buf;
+    unsigned short *array;
+};
+
+struct message {
+    long mtype;
+    char mtext[16];
+};
+
+static int semop1(int semid, short num, short op, short flags)
+{
+    struct sembuf sops = {num, op, flags};
+    return semop(semid, &sops, 1);
+}
+
+static void test_semop(void)
+{
+    int status;
+    int semid = semget(IPC_PRIVATE, 2, IPC_CREAT | 0600);
+    assert(semid >= 0);
+
+    // IPC_NOWAIT fails instead of blocking, and nothing is performed
+    struct sembuf sops[2] = {{0, 1, 0}, {1, -1, IPC_NOWAIT}};
+    assert(semop(semid, sops, 2) == -1 && errno == EAGAIN);
+    assert(semctl(semid, 0, GETVAL) == 0);
+
+    // a decrement blocks until a child increases the value
+    pid_t child = fork();
+    if (child == 0)
+    {
+        while (semctl(semid, 0, GETNCNT) != 1)
+            usleep(10000);
+        assert(semop1(semid, 0, 2, 0) == 0);
+        exit(0);
+    }
+    assert(semop1(semid, 0, -1, 0) == 0);
+    assert(semctl(semid, 0, GETVAL) == 1);
+    assert(semctl(semid, 0, GETNCNT) == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+
+    // waiting for zero
+    child = fork();
+    if (child == 0)
+    {
+        while (semctl(semid, 0, GETZCNT) != 1)
+            usleep(10000);
+        assert(semop1(semid, 0, -1, 0) == 0);
+        exit(0);
+    }
+    assert(semop1(semid, 0, 0, 0) == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+
+    // the operations of SEM_UNDO are undone when the process exits
+    child = fork();
+    if (child == 0)
+    {
+        assert(semop1(semid, 0, 3, SEM_UNDO) == 0);
+        assert(semop1(semid, 1, 2, SEM_UNDO) == 0);
+        assert(semop1(semid, 1, -1, SEM_UNDO) == 0);
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(semctl(semid, 0, GETVAL) == 0);
+    assert(semctl(semid, 1, GETVAL) == 0);
+
+    // SETVAL clears the adjustments of the semaphore
+    child = fork();
+    if (child == 0)
+    {
+        assert(semop1(semid, 0, 1, SEM_UNDO) == 0);
+        assert(semop1(semid, 1, 1, SEM_UNDO) == 0);
+        union semun arg = {.val = 5};
+        assert(semctl(semid, 0, SETVAL, arg) == 0);
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(semctl(semid, 0, GETVAL) == 5);
+    assert(semctl(semid, 1, GETVAL) == 0);
+
+    // a removed set fails the waiters, and its ID is not valid any more
+    child = fork();
+    if (child == 0)
+    {
+        int ret = semop1(semid, 1, -1, 0);
+        exit(ret == -1 && errno == EIDRM ? 0 : 1);
+    }
+    while (semctl(semid, 1, GETNCNT) != 1)
+        usleep(10000);
+    assert(semctl(semid, 0, IPC_RMID) == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(semctl(semid, 0, GETVAL) == -1 && errno == EINVAL);
+    int other = semget(IPC_PRIVATE, 1, IPC_CREAT | 0600);
+    assert(other >= 0 && other != semid);
+    assert(semctl(other, 0, IPC_RMID) == 0);
+}
+
+static void test_msg(void)
+{
+    int status;
+    struct message msg;
+    int msqid = msgget(IPC_PRIVATE, IPC_CREAT | 0600);
+    assert(msqid >= 0);
+
+    // an empty queue has no message
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), 0, IPC_NOWAIT) == -1);
+    assert(errno == ENOMSG);
+
+    long types[] = {3, 1, 2, 1};
+    for (int i = 0; i < 4; i++)
+    {
+        msg.mtype = types[i];
+        snprintf(msg.mtext, sizeof(msg.mtext), "msg%d", i);
+        assert(msgsnd(msqid, &msg, strlen(msg.mtext) + 1, 0) == 0);
+    }
+    struct msqid_ds ds;
+    assert(msgctl(msqid, IPC_STAT, &ds) == 0);
+    assert(ds.msg_qnum == 4);
+
+    // the first message of the type
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), 1, 0) == 5);
+    assert(msg.mtype == 1 && strcmp(msg.mtext, "msg1") == 0);
+    // the first message of the lowest type not greater than 2
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), -2, 0) == 5);
+    assert(msg.mtype == 1 && strcmp(msg.mtext, "msg3") == 0);
+    // the first message not of the type
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), 3, MSG_EXCEPT) == 5);
+    assert(msg.mtype == 2 && strcmp(msg.mtext, "msg2") == 0);
+    // too long messages are kept unless truncated
+    assert(msgrcv(msqid, &msg, 2, 0, 0) == -1 && errno == E2BIG);
+    assert(msgrcv(msqid, &msg, 2, 0, MSG_NOERROR) == 2);
+    assert(msg.mtype == 3 && strncmp(msg.mtext, "ms", 2) == 0);
+
+    // a receiver blocks until a message of the type is sent
+    pid_t child = fork();
+    if (child == 0)
+    {
+        struct message m = {.mtype = 4, .mtext = "late"};
+        usleep(100000);
+        m.mtype = 5;
+        assert(msgsnd(msqid, &m, 5, 0) == 0);
+        m.mtype = 4;
+        assert(msgsnd(msqid, &m, 5, 0) == 0);
+        exit(0);
+    }
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), 4, 0) == 5);
+    assert(msg.mtype == 4 && strcmp(msg.mtext, "late") == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(msgrcv(msqid, &msg, sizeof(msg.mtext), 5, IPC_NOWAIT) == 5);
+
+    assert(msgctl(msqid, IPC_RMID, NULL) == 0);
+    assert(msgsnd(msqid, &msg, 5, 0) == -1 && errno == EINVAL);
+}
+
+int main(void)
+{
+    test_semop();
+    test_msg();
+    printf("semaphore and message queue test passed\n");
+    return 0;
+}
@@ -0,0 +1,77 @@
+#include <time.h>
+#include <stdio.h>
+#include <errno.h>
+#include <assert.h>
+#include <signal.h>
+#include <unistd.h>
+#include <sys/time.h>
+
+static volatile int alarms = 0;
+
+static void handler(int signo)
+{
+    assert(signo == SIGALRM);
+    alarms++;
+}
+
+static void wait_alarms(int n)
+{
+    // give up after a second
+    for (int i = 0; i < 1000 && alarms < n; i++)
+        usleep(1000);
+}
+
+int main(int argc, char **argv)
+{
+    signal(SIGALRM, handler);
+
+    // a periodic ITIMER_REAL
+    struct itimerval itv = {{0, 10000}, {0, 10000}}, old;
+    assert(setitimer(ITIMER_REAL, &itv, NULL) == 0);
+    wait_alarms(3);
+    assert(alarms >= 3);
+    assert(getitimer(ITIMER_REAL, &old) == 0);
+    assert(old.it_interval.tv_usec == 10000);
+    struct itimerval zero = {{0, 0}, {0, 0}};
+    assert(setitimer(ITIMER_REAL, &zero, &old) == 0);
+    assert(old.it_interval.tv_usec == 10000);
+    assert(getitimer(ITIMER_REAL, &old) == 0);
+    assert(old.it_value.tv_sec == 0 && old.it_value.tv_usec == 0);
+    assert(setitimer(ITIMER_VIRTUAL, &itv, NULL) == -1 && errno == EINVAL);
+
+    // alarm returns the seconds remaining
+    assert(alarm(10) == 0);
+    assert(alarm(0) == 10);
+
+    // a one-shot POSIX timer sending SIGALRM
+    alarms = 0;
+    struct sigevent sev = {0};
+    sev.sigev_notify = SIGEV_SIGNAL;
+    sev.sigev_signo = SIGALRM;
+    timer_t timer;
+    assert(timer_create(CLOCK_MONOTONIC, &sev, &timer) == 0);
+    struct itimerspec its = {{0, 0}, {0, 10000000}}, cur;
+    assert(timer_settime(timer, 0, &its, NULL) == 0);
+    wait_alarms(1);
+    assert(alarms == 1);
+    assert(timer_gettime(timer, &cur) == 0);
+    assert(cur.it_value.tv_sec == 0 && cur.it_value.tv_nsec == 0);
+
+    // an absolute time on the realtime clock
+    timer_t timer2;
+    assert(timer_create(CLOCK_REALTIME, NULL, &timer2) == 0);
+    assert(clock_gettime(CLOCK_REALTIME, &its.it_value) == 0);
+    its.it_value.tv_sec += 1;
+    assert(timer_settime(timer2, TIMER_ABSTIME, &its, NULL) == 0);
+    assert(timer_gettime(timer2, &cur) == 0);
+    assert(cur.it_value.tv_sec <= 1);
+    assert(cur.it_value.tv_sec != 0 || cur.it_value.tv_nsec > 0);
+
+    // deleted timers never fire
+    assert(timer_delete(timer2) == 0);
+    assert(timer_delete(timer) == 0);
+    assert(timer_gettime(timer, &cur) == -1 && errno == EINVAL);
+
+    printf("interval timer test passed\n");
+    return 0;
+}
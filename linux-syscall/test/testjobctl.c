@@ -0,0 +1,60 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <signal.h>
+#include <assert.h>
+#include <sys/wait.h>
+
+int main(int argc, char **argv)
+{
+    int status;
+    pid_t self = getpid();
+
+    // the calling process already leads a process group
+    assert(getpgrp() == getpgid(0));
+    assert(getsid(0) > 0);
+
+    // a child moves into a new process group and stops itself
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(setpgid(0, 0) == 0);
+        assert(getpgrp() == getpid());
+        raise(SIGSTOP);
+        exit(3);
+    }
+    assert(child > 0);
+    assert(waitpid(child, &status, WUNTRACED) == child);
+    assert(WIFSTOPPED(status));
+    assert(WSTOPSIG(status) == SIGSTOP);
+    assert(getpgid(child) == child);
+    assert(getpgid(child) != getpgrp());
+
+    // resume the whole process group
+    assert(kill(-child, SIGCONT) == 0);
+    assert(waitpid(child, &status, 0) == child);
+
+    // a signal without a handler terminates the child
+    child = fork();
+    if (child == 0)
+    {
+        while (1)
+            pause();
+    }
+    usleep(100000);
+    assert(kill(child, SIGTERM) == 0);
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGTERM);
+
+    // a new session can not be created by a process group leader
+    assert(getpgrp() != self || setsid() == -1);
+    child = fork();
+    if (child == 0)
+    {
+        pid_t sid = setsid();
+        exit(sid == getpid() && getsid(0) == getpid() ? 0 : 1);
+    }
+    assert(waitpid(child, &status, 0) == child);
+    assert(status == 0);
+    return 0;
+}
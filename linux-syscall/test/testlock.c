@@ -0,0 +1,91 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/file.h>
+#include <sys/wait.h>
+
+#define LOCK_PATH "/tmp/testlock"
+
+static int lock(int fd, short type, off_t start, off_t len, int cmd)
+{
+    struct flock fl = {.l_type = type, .l_whence = SEEK_SET, .l_start = start, .l_len = len};
+    return fcntl(fd, cmd, &fl);
+}
+
+// whether another process fails to lock the range
+static int locked_by_other(int fd, off_t start, off_t len)
+{
+    int status;
+    pid_t child = fork();
+    if (child == 0)
+        exit(lock(fd, F_WRLCK, start, len, F_SETLK) == -1 && errno == EAGAIN ? 0 : 1);
+    assert(waitpid(child, &status, 0) == child);
+    return WIFEXITED(status) && WEXITSTATUS(status) == 0;
+}
+
+int main(int argc, char **argv)
+{
+    int status;
+    int fd = open(LOCK_PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(lock(fd, F_WRLCK, 0, 10, F_SETLK) == 0);
+    assert(locked_by_other(fd, 0, 10));
+    assert(!locked_by_other(fd, 10, 10));
+
+    // closing any descriptor of the file releases the locks
+    int other = open(LOCK_PATH, O_RDWR);
+    assert(other >= 0);
+    close(other);
+    assert(!locked_by_other(fd, 0, 10));
+
+    // so does dup2 over a descriptor of the file
+    assert(lock(fd, F_WRLCK, 0, 10, F_SETLK) == 0);
+    other = open(LOCK_PATH, O_RDWR);
+    int null = open("/dev/null", O_RDWR);
+    assert(other >= 0 && null >= 0);
+    assert(dup2(null, other) == other);
+    assert(!locked_by_other(fd, 0, 10));
+    close(other);
+    close(null);
+
+    // but not dup2 to the same descriptor
+    assert(lock(fd, F_WRLCK, 0, 10, F_SETLK) == 0);
+    assert(dup2(fd, fd) == fd);
+    assert(locked_by_other(fd, 0, 10));
+    assert(lock(fd, F_UNLCK, 0, 0, F_SETLK) == 0);
+
+    // a wait for a process waiting for the caller fails
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    assert(lock(fd, F_WRLCK, 0, 1, F_SETLK) == 0);
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(lock(fd, F_WRLCK, 1, 1, F_SETLK) == 0);
+        assert(write(pipefd[1], "x", 1) == 1);
+        assert(lock(fd, F_WRLCK, 0, 1, F_SETLKW) == 0);
+        exit(0);
+    }
+    char c;
+    assert(read(pipefd[0], &c, 1) == 1);
+    usleep(100000);
+    assert(lock(fd, F_WRLCK, 1, 1, F_SETLKW) == -1 && errno == EDEADLK);
+    assert(lock(fd, F_UNLCK, 0, 1, F_SETLK) == 0);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+
+    // flock locks of different open file descriptions conflict
+    other = open(LOCK_PATH, O_RDWR);
+    assert(flock(fd, LOCK_EX) == 0);
+    assert(flock(other, LOCK_SH | LOCK_NB) == -1 && errno == EWOULDBLOCK);
+    assert(flock(fd, LOCK_UN) == 0);
+    assert(flock(other, LOCK_SH | LOCK_NB) == 0);
+    close(other);
+
+    close(fd);
+    assert(unlink(LOCK_PATH) == 0);
+    printf("file lock test passed\n");
+    return 0;
+}
@@ -0,0 +1,42 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <string.h>
+#include <errno.h>
+#include <assert.h>
+#include <sys/mman.h>
+
+#define PAGE 4096
+
+int main(int argc, char **argv)
+{
+    char *p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    memset(p, 'a', 4 * PAGE);
+
+    // the pages are zero after they are freed
+    assert(madvise(p + PAGE, 2 * PAGE, MADV_DONTNEED) == 0);
+    assert(p[PAGE] == 0 && p[3 * PAGE - 1] == 0);
+    assert(p[0] == 'a' && p[3 * PAGE] == 'a');
+    p[PAGE] = 'b';
+    assert(p[PAGE] == 'b');
+
+    // the content is kept or zero, until written again
+    assert(madvise(p, PAGE, MADV_FREE) == 0);
+    assert(p[0] == 'a' || p[0] == 0);
+    p[0] = 'c';
+    assert(p[0] == 'c');
+
+    // hints are accepted
+    assert(madvise(p, 4 * PAGE, MADV_WILLNEED) == 0);
+    assert(madvise(p, 4 * PAGE, MADV_SEQUENTIAL) == 0);
+
+    // bad arguments
+    assert(madvise(p + 1, PAGE, MADV_DONTNEED) == -1 && errno == EINVAL);
+    assert(madvise(p, PAGE, 12345) == -1 && errno == EINVAL);
+    assert(munmap(p + 2 * PAGE, PAGE) == 0);
+    assert(madvise(p, 4 * PAGE, MADV_DONTNEED) == -1 && errno == ENOMEM);
+    assert(munmap(p, 4 * PAGE) == 0);
+
+    printf("madvise test passed\n");
+    return 0;
+}
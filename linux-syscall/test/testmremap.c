@@ -0,0 +1,46 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <string.h>
+#include <errno.h>
+#include <assert.h>
+#include <sys/mman.h>
+
+#define PAGE 4096
+
+int main(int argc, char **argv)
+{
+    // reserve without access, then make a part accessible, like thread stacks
+    char *p = mmap(NULL, 4 * PAGE, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    assert(mprotect(p + PAGE, 2 * PAGE, PROT_READ | PROT_WRITE) == 0);
+    memset(p + PAGE, 'a', 2 * PAGE);
+    assert(p[PAGE] == 'a' && p[3 * PAGE - 1] == 'a');
+
+    // bad arguments
+    assert(mprotect(p + 1, PAGE, PROT_READ) == -1 && errno == EINVAL);
+    assert(munmap(p + 3 * PAGE, PAGE) == 0);
+    assert(mprotect(p + 2 * PAGE, 2 * PAGE, PROT_READ) == -1 && errno == ENOMEM);
+    assert(mprotect(p + 2 * PAGE, PAGE, PROT_READ) == 0);
+    assert(p[2 * PAGE] == 'a');
+    assert(munmap(p, 3 * PAGE) == 0);
+
+    // grow in place, or move keeping the content
+    char *q = mmap(NULL, 2 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(q != MAP_FAILED);
+    memset(q, 'b', 2 * PAGE);
+    assert(mremap(q, PAGE, 2 * PAGE, 0) == MAP_FAILED && errno == ENOMEM);
+    char *r = mremap(q, 2 * PAGE, 8 * PAGE, MREMAP_MAYMOVE);
+    assert(r != MAP_FAILED);
+    assert(r[0] == 'b' && r[2 * PAGE - 1] == 'b');
+    memset(r + 2 * PAGE, 'c', 6 * PAGE);
+    assert(r[8 * PAGE - 1] == 'c');
+
+    // shrink
+    assert(mremap(r, 8 * PAGE, PAGE, 0) == r);
+    assert(r[0] == 'b');
+    assert(mremap(r + PAGE, PAGE, 2 * PAGE, 0) == MAP_FAILED && errno == EFAULT);
+    assert(munmap(r, PAGE) == 0);
+
+    printf("mremap test passed\n");
+    return 0;
+}
@@ -0,0 +1,75 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <errno.h>
+#include <ifaddrs.h>
+#include <sys/socket.h>
+#include <linux/netlink.h>
+#include <linux/rtnetlink.h>
+
+int main(int argc, char **argv)
+{
+    char buf[8192];
+
+    int fd = socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE);
+    assert(fd >= 0);
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    assert(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+    socklen_t len = sizeof(addr);
+    assert(getsockname(fd, (struct sockaddr *)&addr, &len) == 0);
+    assert(addr.nl_family == AF_NETLINK && addr.nl_pid != 0);
+
+    // dump the links, ended by NLMSG_DONE
+    struct {
+        struct nlmsghdr nlh;
+        struct rtgenmsg g;
+    } req = {
+        .nlh = {
+            .nlmsg_len = sizeof(req),
+            .nlmsg_type = RTM_GETLINK,
+            .nlmsg_flags = NLM_F_REQUEST | NLM_F_DUMP,
+            .nlmsg_seq = 1,
+        },
+        .g = {.rtgen_family = AF_UNSPEC},
+    };
+    assert(send(fd, &req, sizeof(req), 0) == sizeof(req));
+    int done = 0;
+    while (!done) {
+        int n = recv(fd, buf, sizeof(buf), 0);
+        assert(n > 0);
+        for (struct nlmsghdr *h = (void *)buf; NLMSG_OK(h, n); h = NLMSG_NEXT(h, n)) {
+            assert(h->nlmsg_seq == 1 && h->nlmsg_pid == addr.nl_pid);
+            if (h->nlmsg_type == NLMSG_DONE) {
+                done = 1;
+                break;
+            }
+            assert(h->nlmsg_type == RTM_NEWLINK);
+            struct ifinfomsg *ifi = NLMSG_DATA(h);
+            assert(ifi->ifi_index > 0);
+        }
+    }
+    assert(recv(fd, buf, sizeof(buf), MSG_DONTWAIT) == -1 && errno == EAGAIN);
+
+    // unsupported requests are answered by an error
+    req.nlh.nlmsg_type = RTM_GETROUTE + 100;
+    assert(send(fd, &req, sizeof(req), 0) == sizeof(req));
+    int n = recv(fd, buf, sizeof(buf), 0);
+    struct nlmsghdr *h = (void *)buf;
+    assert(NLMSG_OK(h, n) && h->nlmsg_type == NLMSG_ERROR);
+    struct nlmsgerr *err = NLMSG_DATA(h);
+    assert(err->error == -EOPNOTSUPP);
+    close(fd);
+
+    // enumerate the interfaces as the libc does
+    struct ifaddrs *ifaddr;
+    assert(getifaddrs(&ifaddr) == 0);
+    for (struct ifaddrs *ifa = ifaddr; ifa != NULL; ifa = ifa->ifa_next) {
+        assert(ifa->ifa_name != NULL);
+        printf("%s: family %d\n", ifa->ifa_name, ifa->ifa_addr ? ifa->ifa_addr->sa_family : -1);
+    }
+    freeifaddrs(ifaddr);
+
+    printf("netlink test passed\n");
+    return 0;
+}
@@ -0,0 +1,206 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+
+#define NS_DIR "/tmp/ns"
+#define BIND_DIR "/tmp/nsbind"
+#define ROOT_DIR "/tmp/nsroot"
+
+static void touch(const char *path)
+{
+    int fd = open(path, O_CREAT | O_WRONLY, 0644);
+    assert(fd >= 0);
+    close(fd);
+}
+
+static void wait_ok(pid_t child)
+{
+    int status;
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_mount_ns(void)
+{
+    mkdir(NS_DIR, 0755);
+    mkdir(BIND_DIR, 0755);
+
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(unshare(CLONE_NEWNS) == 0);
+        assert(mount("none", NS_DIR, "tmpfs", 0, NULL) == 0);
+        touch(NS_DIR "/file");
+
+        // a bind mount shows the same files
+        assert(mount(NS_DIR, BIND_DIR, NULL, MS_BIND, NULL) == 0);
+        assert(access(BIND_DIR "/file", F_OK) == 0);
+        touch(BIND_DIR "/other");
+        assert(access(NS_DIR "/other", F_OK) == 0);
+
+        // a read-only mount rejects the writes through it only
+        assert(mount(NULL, BIND_DIR, NULL, MS_REMOUNT | MS_BIND | MS_RDONLY, NULL) == 0);
+        assert(open(BIND_DIR "/new", O_CREAT | O_WRONLY, 0644) == -1 && errno == EROFS);
+        assert(unlink(BIND_DIR "/file") == -1 && errno == EROFS);
+        touch(NS_DIR "/new");
+
+        // a mount under another keeps it busy
+        assert(umount2("/tmp", 0) == -1 && errno == EBUSY);
+        assert(umount(BIND_DIR) == 0);
+        assert(access(BIND_DIR "/file", F_OK) == -1 && errno == ENOENT);
+        assert(umount(BIND_DIR) == -1 && errno == EINVAL);
+        exit(0);
+    }
+    wait_ok(child);
+
+    // the mounts of the namespace are not seen outside
+    assert(access(NS_DIR "/file", F_OK) == -1 && errno == ENOENT);
+    assert(access(BIND_DIR "/file", F_OK) == -1 && errno == ENOENT);
+
+    // only root can mount
+    child = fork();
+    if (child == 0)
+    {
+        assert(setuid(1000) == 0);
+        assert(mount("none", NS_DIR, "tmpfs", 0, NULL) == -1 && errno == EPERM);
+        assert(unshare(CLONE_NEWNS) == -1 && errno == EPERM);
+        exit(0);
+    }
+    wait_ok(child);
+
+    assert(rmdir(NS_DIR) == 0);
+    assert(rmdir(BIND_DIR) == 0);
+}
+
+static void test_pid_ns(void)
+{
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(unshare(CLONE_NEWNS | CLONE_NEWPID) == 0);
+        assert(unshare(CLONE_NEWPID) == -1 && errno == EINVAL);
+        // the caller stays in its namespace
+        assert(getpid() != 1);
+
+        pid_t init = fork();
+        if (init == 0)
+        {
+            // the first child is the init process of the new namespace
+            assert(getpid() == 1);
+            assert(getppid() == 0);
+            assert(mount("proc", "/proc", "proc", 0, NULL) == 0);
+            assert(access("/proc/1", F_OK) == 0);
+            assert(access("/proc/2", F_OK) == -1 && errno == ENOENT);
+
+            pid_t grandchild = fork();
+            if (grandchild == 0)
+            {
+                assert(getpid() == 2 && getppid() == 1);
+                assert(access("/proc/2", F_OK) == 0);
+                exit(0);
+            }
+            assert(grandchild == 2);
+            wait_ok(grandchild);
+            exit(0);
+        }
+        assert(init > 1);
+        wait_ok(init);
+        exit(0);
+    }
+    wait_ok(child);
+}
+
+static void test_chroot(void)
+{
+    char cwd[64];
+    mkdir(ROOT_DIR, 0755);
+    mkdir(ROOT_DIR "/dir", 0755);
+    touch(ROOT_DIR "/file");
+
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(chroot(ROOT_DIR "/file") == -1 && errno == ENOTDIR);
+        assert(chdir(ROOT_DIR "/dir") == 0);
+        assert(chroot(ROOT_DIR) == 0);
+
+        // the working directory under the new root is kept
+        assert(getcwd(cwd, sizeof(cwd)) != NULL);
+        assert(strcmp(cwd, "/dir") == 0);
+        assert(access("/file", F_OK) == 0);
+        assert(access("/tmp", F_OK) == -1 && errno == ENOENT);
+
+        // `..` of the root is the root itself
+        struct stat root, parent;
+        assert(stat("/", &root) == 0);
+        assert(stat("/../..", &parent) == 0);
+        assert(root.st_ino == parent.st_ino && root.st_dev == parent.st_dev);
+        assert(chdir("../..") == 0);
+        assert(getcwd(cwd, sizeof(cwd)) != NULL);
+        assert(strcmp(cwd, "/") == 0);
+        exit(0);
+    }
+    wait_ok(child);
+
+    // only a process with CAP_SYS_CHROOT can change the root
+    child = fork();
+    if (child == 0)
+    {
+        assert(setuid(1000) == 0);
+        assert(chroot(ROOT_DIR) == -1 && errno == EPERM);
+        exit(0);
+    }
+    wait_ok(child);
+
+    assert(unlink(ROOT_DIR "/file") == 0);
+    assert(rmdir(ROOT_DIR "/dir") == 0);
+}
+
+static void test_pivot_root(void)
+{
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(unshare(CLONE_NEWNS) == 0);
+        assert(mount("none", ROOT_DIR, "tmpfs", 0, NULL) == 0);
+        assert(mkdir(ROOT_DIR "/old", 0755) == 0);
+        touch(ROOT_DIR "/file");
+
+        // the old root must be under the new one
+        assert(syscall(SYS_pivot_root, ROOT_DIR, "/tmp") == -1 && errno == EINVAL);
+        assert(syscall(SYS_pivot_root, ROOT_DIR, ROOT_DIR "/old") == 0);
+        assert(chdir("/") == 0);
+        assert(access("/file", F_OK) == 0);
+        // the mounts move along with the old root
+        assert(access("/old" ROOT_DIR "/file", F_OK) == -1 && errno == ENOENT);
+        assert(access("/old/tmp", F_OK) == 0);
+        assert(umount2("/old", MNT_DETACH) == 0);
+        assert(access("/old/tmp", F_OK) == -1 && errno == ENOENT);
+        exit(0);
+    }
+    wait_ok(child);
+
+    // the root is not changed outside the namespace
+    assert(access(ROOT_DIR "/file", F_OK) == -1 && errno == ENOENT);
+    assert(rmdir(ROOT_DIR) == 0);
+}
+
+int main(int argc, char **argv)
+{
+    test_mount_ns();
+    test_pid_ns();
+    test_chroot();
+    test_pivot_root();
+    printf("namespace test passed\n");
+    return 0;
+}
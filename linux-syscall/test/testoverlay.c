@@ -0,0 +1,110 @@
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+
+#define BASE_DIR "/tmp/ovl"
+#define LOWER BASE_DIR "/lower"
+#define UPPER BASE_DIR "/upper"
+#define MERGED BASE_DIR "/merged"
+
+static void write_file(const char *path, const char *data, int flags)
+{
+    int fd = open(path, O_WRONLY | flags, 0644);
+    assert(fd >= 0);
+    assert(write(fd, data, strlen(data)) == strlen(data));
+    close(fd);
+}
+
+static void check_file(const char *path, const char *data)
+{
+    char buf[32] = {0};
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    assert(read(fd, buf, sizeof(buf)) == strlen(data));
+    assert(strcmp(buf, data) == 0);
+    close(fd);
+}
+
+static int count_entries(const char *path)
+{
+    int count = 0;
+    DIR *dir = opendir(path);
+    assert(dir != NULL);
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL)
+    {
+        if (strcmp(entry->d_name, ".") != 0 && strcmp(entry->d_name, "..") != 0)
+            count++;
+    }
+    closedir(dir);
+    return count;
+}
+
+int main(int argc, char **argv)
+{
+    struct stat st, lower_st;
+    assert(mkdir(BASE_DIR, 0755) == 0);
+    assert(mkdir(LOWER, 0755) == 0);
+    assert(mkdir(LOWER "/sub", 0755) == 0);
+    assert(mkdir(UPPER, 0755) == 0);
+    assert(mkdir(MERGED, 0755) == 0);
+    write_file(LOWER "/a", "lower", O_CREAT);
+    write_file(LOWER "/sub/b", "b", O_CREAT);
+    write_file(LOWER "/sub/c", "c", O_CREAT);
+
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=/tmp/a:/tmp/b,upperdir=" UPPER) == -1);
+    assert(errno == EINVAL);
+    assert(mount("overlay", MERGED, "overlay", 0,
+                 "lowerdir=" LOWER ",upperdir=" UPPER ",workdir=" BASE_DIR) == 0);
+    check_file(MERGED "/a", "lower");
+    assert(count_entries(MERGED) == 2);
+
+    // a lower file is copied up when changed, keeping its inode number
+    assert(stat(MERGED "/a", &lower_st) == 0);
+    write_file(MERGED "/a", "+upper", O_APPEND);
+    check_file(MERGED "/a", "lower+upper");
+    check_file(UPPER "/a", "lower+upper");
+    check_file(LOWER "/a", "lower");
+    assert(stat(MERGED "/a", &st) == 0);
+    assert(st.st_ino == lower_st.st_ino);
+
+    // a new file is created in the upper directory
+    write_file(MERGED "/sub/d", "d", O_CREAT);
+    check_file(UPPER "/sub/d", "d");
+    assert(access(LOWER "/sub/d", F_OK) == -1 && errno == ENOENT);
+    assert(count_entries(MERGED "/sub") == 3);
+
+    // a removed lower file is hidden by a whiteout
+    assert(unlink(MERGED "/sub/b") == 0);
+    assert(access(MERGED "/sub/b", F_OK) == -1 && errno == ENOENT);
+    assert(access(LOWER "/sub/b", F_OK) == 0);
+    assert(stat(UPPER "/sub/b", &st) == 0);
+    assert(S_ISCHR(st.st_mode) && st.st_rdev == 0);
+    assert(count_entries(MERGED "/sub") == 2);
+
+    // a lower directory can not be renamed
+    assert(rename(MERGED "/sub", MERGED "/moved") == -1 && errno == EXDEV);
+    assert(rmdir(MERGED "/sub") == -1 && errno == ENOTEMPTY);
+
+    // a directory created again is not merged with the lower one
+    assert(unlink(MERGED "/sub/c") == 0);
+    assert(unlink(MERGED "/sub/d") == 0);
+    assert(rmdir(MERGED "/sub") == 0);
+    assert(access(MERGED "/sub", F_OK) == -1 && errno == ENOENT);
+    assert(mkdir(MERGED "/sub", 0755) == 0);
+    assert(count_entries(MERGED "/sub") == 0);
+    assert(access(MERGED "/sub/c", F_OK) == -1 && errno == ENOENT);
+    assert(count_entries(LOWER "/sub") == 2);
+
+    assert(umount(MERGED) == 0);
+    assert(access(MERGED "/a", F_OK) == -1 && errno == ENOENT);
+    printf("overlayfs test passed\n");
+    return 0;
+}
@@ -0,0 +1,85 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <netinet/ip.h>
+#include <netinet/ip_icmp.h>
+#include <sys/socket.h>
+
+static unsigned short checksum(void *data, int len)
+{
+    unsigned int sum = 0;
+    unsigned short *p = data;
+    for (; len > 1; len -= 2)
+        sum += *p++;
+    if (len)
+        sum += *(unsigned char *)p;
+    while (sum >> 16)
+        sum = (sum & 0xffff) + (sum >> 16);
+    return ~sum;
+}
+
+static int make_echo(char *buf, unsigned short seq)
+{
+    struct icmphdr *icmp = (struct icmphdr *)buf;
+    memset(buf, 0, sizeof(struct icmphdr));
+    icmp->type = ICMP_ECHO;
+    icmp->un.echo.id = htons(0x1234);
+    icmp->un.echo.sequence = htons(seq);
+    strcpy(buf + sizeof(struct icmphdr), "ping payload");
+    int len = sizeof(struct icmphdr) + strlen("ping payload");
+    icmp->checksum = checksum(buf, len);
+    return len;
+}
+
+int main(int argc, char **argv)
+{
+    char buf[256];
+    struct sockaddr_in addr = {.sin_family = AF_INET};
+    addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    socklen_t addrlen = sizeof(addr);
+
+    // a ping socket only sends echo requests, and gets their replies
+    int fd = socket(AF_INET, SOCK_DGRAM, IPPROTO_ICMP);
+    assert(fd >= 0);
+    int len = make_echo(buf, 1);
+    assert(sendto(fd, buf, len, 0, (struct sockaddr *)&addr, sizeof(addr)) == len);
+    memset(buf, 0, sizeof(buf));
+    int n = recvfrom(fd, buf, sizeof(buf), 0, (struct sockaddr *)&addr, &addrlen);
+    assert(n == len);
+    struct icmphdr *reply = (struct icmphdr *)buf;
+    assert(reply->type == ICMP_ECHOREPLY);
+    assert(ntohs(reply->un.echo.sequence) == 1);
+    assert(strcmp(buf + sizeof(struct icmphdr), "ping payload") == 0);
+    assert(addr.sin_addr.s_addr == htonl(INADDR_LOOPBACK));
+    buf[0] = ICMP_ECHOREPLY;
+    assert(sendto(fd, buf, len, 0, (struct sockaddr *)&addr, sizeof(addr)) == -1);
+    close(fd);
+
+    // a raw socket gets the replies with their IPv4 header
+    fd = socket(AF_INET, SOCK_RAW, IPPROTO_ICMP);
+    assert(fd >= 0);
+    len = make_echo(buf, 2);
+    assert(sendto(fd, buf, len, 0, (struct sockaddr *)&addr, sizeof(addr)) == len);
+    for (;;) {
+        n = recv(fd, buf, sizeof(buf), 0);
+        assert(n > (int)sizeof(struct iphdr));
+        struct iphdr *ip = (struct iphdr *)buf;
+        assert(ip->protocol == IPPROTO_ICMP);
+        assert(ip->saddr == htonl(INADDR_LOOPBACK));
+        reply = (struct icmphdr *)(buf + ip->ihl * 4);
+        // our own request is looped back too
+        if (reply->type == ICMP_ECHO)
+            continue;
+        assert(reply->type == ICMP_ECHOREPLY);
+        assert(ntohs(reply->un.echo.id) == 0x1234);
+        assert(ntohs(reply->un.echo.sequence) == 2);
+        break;
+    }
+    close(fd);
+
+    printf("ping test passed\n");
+    return 0;
+}
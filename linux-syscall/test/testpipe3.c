@@ -0,0 +1,118 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+
+static char buf[0x10000];
+
+static void test_capacity(void)
+{
+    int fds[2];
+    int status;
+    assert(pipe2(fds, O_NONBLOCK) == 0);
+    assert(fcntl(fds[0], F_GETPIPE_SZ) == 0x10000);
+
+    // the capacity is rounded up to a power of two number of pages
+    assert(fcntl(fds[1], F_SETPIPE_SZ, 5000) == 2 * PAGE_SIZE);
+    assert(fcntl(fds[0], F_GETPIPE_SZ) == 2 * PAGE_SIZE);
+    assert(fcntl(fds[1], F_SETPIPE_SZ, 1) == PAGE_SIZE);
+    assert(fcntl(fds[1], F_SETPIPE_SZ, 2 * PAGE_SIZE) == 2 * PAGE_SIZE);
+
+    // the writes stop at the capacity
+    assert(write(fds[1], buf, sizeof(buf)) == 2 * PAGE_SIZE);
+    assert(write(fds[1], buf, 1) == -1 && errno == EAGAIN);
+    // which can not drop below the bytes in the pipe
+    assert(fcntl(fds[1], F_SETPIPE_SZ, PAGE_SIZE) == -1 && errno == EBUSY);
+    assert(read(fds[0], buf, sizeof(buf)) == 2 * PAGE_SIZE);
+
+    // a write of at most PIPE_BUF bytes is written whole or not at all
+    assert(write(fds[1], buf, 2 * PAGE_SIZE - 100) == 2 * PAGE_SIZE - 100);
+    assert(write(fds[1], buf, 200) == -1 && errno == EAGAIN);
+    assert(write(fds[1], buf, PIPE_BUF + 1) == 100);
+    assert(read(fds[0], buf, sizeof(buf)) == 2 * PAGE_SIZE);
+
+    // only a privileged process can go beyond the limit
+    int limit = 0x100000;
+    assert(fcntl(fds[1], F_SETPIPE_SZ, limit * 2) == limit * 2);
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(setuid(1000) == 0);
+        assert(fcntl(fds[1], F_SETPIPE_SZ, limit) == limit);
+        assert(fcntl(fds[1], F_SETPIPE_SZ, limit * 2) == -1 && errno == EPERM);
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    int dir = open("/tmp", O_RDONLY);
+    assert(fcntl(dir, F_GETPIPE_SZ) == -1 && errno == EBADF);
+    close(dir);
+
+    close(fds[0]);
+    close(fds[1]);
+}
+
+static void test_packet(void)
+{
+    int fds[2];
+    assert(pipe2(fds, O_DIRECT | O_NONBLOCK) == 0);
+
+    // each write is a packet, read by one read
+    assert(write(fds[1], "abc", 3) == 3);
+    assert(write(fds[1], "defgh", 5) == 5);
+    assert(read(fds[0], buf, sizeof(buf)) == 3);
+    assert(memcmp(buf, "abc", 3) == 0);
+    // the rest of a packet that does not fit is discarded
+    assert(read(fds[0], buf, 2) == 2);
+    assert(memcmp(buf, "de", 2) == 0);
+    assert(read(fds[0], buf, sizeof(buf)) == -1 && errno == EAGAIN);
+
+    // a long write is split into packets of PIPE_BUF bytes
+    assert(write(fds[1], buf, PIPE_BUF + 10) == PIPE_BUF + 10);
+    assert(read(fds[0], buf, sizeof(buf)) == PIPE_BUF);
+    assert(read(fds[0], buf, sizeof(buf)) == 10);
+
+    close(fds[0]);
+    close(fds[1]);
+}
+
+static void test_broken(void)
+{
+    int fds[2];
+    int status;
+    assert(pipe(fds) == 0);
+    close(fds[0]);
+
+    // SIGPIPE terminates the writer by default
+    pid_t child = fork();
+    if (child == 0)
+    {
+        write(fds[1], "x", 1);
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGPIPE);
+
+    // otherwise the write fails with EPIPE
+    assert(signal(SIGPIPE, SIG_IGN) != SIG_ERR);
+    assert(write(fds[1], "x", 1) == -1 && errno == EPIPE);
+    assert(signal(SIGPIPE, SIG_DFL) != SIG_ERR);
+    close(fds[1]);
+}
+
+int main(int argc, char **argv)
+{
+    test_capacity();
+    test_packet();
+    test_broken();
+    printf("pipe capacity test passed\n");
+    return 0;
+}
@@ -1,3 +1,4 @@
+#include <errno.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <unistd.h>
@@ -6,6 +7,16 @@
 #include <assert.h>
 #include <time.h>
 #include <string.h>
+#include <signal.h>
+#include <stdint.h>
+#include <sys/eventfd.h>
+
+static volatile sig_atomic_t handled;
+
+static void handler(int sig, siginfo_t *info, void *ucontext)
+{
+    handled = sig;
+}
 
 int main(int argc, char **argv)
 {
@@ -48,6 +59,49 @@ int main(int argc, char **argv)
     assert(ret == 2);
     assert(fds[0].revents == POLLIN);
 
+    // test ppoll with a signal mask and an eventfd
+    sigset_t mask;
+    sigemptyset(&mask);
+    sigaddset(&mask, SIGUSR1);
+    int efd = eventfd(0, 0);
+    assert(efd >= 0);
+    fds[0].fd = efd;
+    fds[0].events = POLLIN;
+    ts.tv_sec = 0;
+    ts.tv_nsec = 100000000;
+    ret = ppoll(fds, 1, &ts, &mask);
+    assert(ret == 0);
+
+    uint64_t val = 1;
+    write(efd, &val, sizeof(val));
+    ret = ppoll(fds, 1, NULL, &mask);
+    assert(ret == 1);
+    assert(fds[0].revents == POLLIN);
+
+    // a signal unblocked by the mask of ppoll interrupts it, and is handled
+    // before the original mask is restored
+    struct sigaction sa = {0};
+    sa.sa_sigaction = handler;
+    sa.sa_flags = SA_SIGINFO;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    assert(sigprocmask(SIG_BLOCK, &mask, NULL) == 0);
+    assert(read(efd, &val, sizeof(val)) == sizeof(val));
+    assert(kill(getpid(), SIGUSR1) == 0);
+    assert(handled == 0);
+    sigset_t unblocked, current;
+    sigemptyset(&unblocked);
+    ret = ppoll(fds, 1, NULL, &unblocked);
+    assert(ret == -1 && errno == EINTR);
+    assert(handled == SIGUSR1);
+    assert(sigprocmask(SIG_SETMASK, NULL, &current) == 0);
+    assert(sigismember(&current, SIGUSR1));
+
+    // negative fds are ignored
+    fds[0].fd = -1;
+    ret = poll(fds, 1, 0);
+    assert(ret == 0);
+
+    close(efd);
     close(pipefd[0]);
     close(pipefd[1]);
     return 0;
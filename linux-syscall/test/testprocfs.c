@@ -0,0 +1,82 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+#include <assert.h>
+#include <signal.h>
+#include <sys/wait.h>
+
+static int read_file(const char *path, char *buf, int size)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    int n = 0, ret;
+    while ((ret = read(fd, buf + n, size - 1 - n)) > 0)
+        n += ret;
+    buf[n] = 0;
+    close(fd);
+    return n;
+}
+
+int main(int argc, char **argv)
+{
+    char buf[4096], expect[64], path[64];
+
+    // test status
+    read_file("/proc/self/status", buf, sizeof(buf));
+    sprintf(expect, "Pid:\t%d\n", getpid());
+    assert(strstr(buf, expect) != NULL);
+    assert(strstr(buf, "Name:\ttestprocfs\n") != NULL);
+
+    // test stat
+    read_file("/proc/self/stat", buf, sizeof(buf));
+    sprintf(expect, "%d (testprocfs) R %d ", getpid(), getppid());
+    assert(strncmp(buf, expect, strlen(expect)) == 0);
+
+    // test cmdline
+    int n = read_file("/proc/self/cmdline", buf, sizeof(buf));
+    assert(n > 0 && buf[n - 1] == 0);
+    assert(strstr(buf, "testprocfs") != NULL);
+
+    // test maps: there must be an executable mapping
+    read_file("/proc/self/maps", buf, sizeof(buf));
+    assert(strstr(buf, "r-xp") != NULL);
+
+    // test meminfo: the free memory is less than the total
+    unsigned long total, free;
+    read_file("/proc/meminfo", buf, sizeof(buf));
+    assert(sscanf(buf, "MemTotal: %lu kB\nMemFree: %lu kB", &total, &free) == 2);
+    assert(total > 0 && free < total);
+
+    // test strace: the logging of syscalls is toggled by writing 1 or 0
+    read_file("/proc/self/strace", buf, sizeof(buf));
+    assert(strcmp(buf, "0\n") == 0);
+    int fd = open("/proc/self/strace", O_WRONLY | O_TRUNC);
+    assert(fd >= 0);
+    assert(write(fd, "1\n", 2) == 2);
+    read_file("/proc/self/strace", buf, sizeof(buf));
+    assert(strcmp(buf, "1\n") == 0);
+    assert(write(fd, "2", 1) == -1 && errno == EINVAL);
+    assert(write(fd, "0", 1) == 1);
+    close(fd);
+    read_file("/proc/self/strace", buf, sizeof(buf));
+    assert(strcmp(buf, "0\n") == 0);
+
+    // test a child process by pid
+    pid_t pid = fork();
+    if (pid == 0)
+    {
+        pause();
+        return 0;
+    }
+    sprintf(path, "/proc/%d/status", pid);
+    read_file(path, buf, sizeof(buf));
+    sprintf(expect, "PPid:\t%d\n", getpid());
+    assert(strstr(buf, expect) != NULL);
+    kill(pid, SIGKILL);
+    waitpid(pid, NULL, 0);
+
+    assert(open("/proc/999999/status", O_RDONLY) < 0);
+    return 0;
+}
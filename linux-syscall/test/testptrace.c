@@ -0,0 +1,88 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/ptrace.h>
+#include <sys/user.h>
+#include <sys/wait.h>
+
+static volatile long value = 0x1234;
+static volatile int handled = 0;
+
+static void handler(int sig)
+{
+    handled = sig;
+}
+
+static void child(void)
+{
+    signal(SIGUSR1, handler);
+    if (ptrace(PTRACE_TRACEME, 0, NULL, NULL) != 0)
+        exit(1);
+    // a process is traced only once
+    if (ptrace(PTRACE_TRACEME, 0, NULL, NULL) != -1 || errno != EPERM)
+        exit(2);
+    // the tracer peeks and pokes the memory at the stop
+    raise(SIGSTOP);
+    if (value != 0x5678)
+        exit(3);
+    // the signal suppressed by the tracer does not terminate the child
+    raise(SIGTERM);
+    // the signal injected by the tracer is delivered
+    raise(SIGUSR2);
+    if (handled != SIGUSR1)
+        exit(4);
+    exit(0);
+}
+
+// Wait for the next stop of the tracee, returns the stop signal.
+static int wait_stop(pid_t pid)
+{
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSTOPPED(status));
+    return WSTOPSIG(status);
+}
+
+int main(int argc, char **argv)
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        child();
+    assert(pid > 0);
+
+    // the stops of a tracee are reported without WUNTRACED
+    assert(wait_stop(pid) == SIGSTOP);
+    errno = 0;
+    assert(ptrace(PTRACE_PEEKDATA, pid, &value, NULL) == 0x1234 && errno == 0);
+    assert(ptrace(PTRACE_POKEDATA, pid, &value, (void *)0x5678) == 0);
+    assert(value == 0x1234);
+    assert(ptrace(PTRACE_PEEKDATA, pid, NULL, NULL) == -1 && errno == EIO);
+
+#ifdef __x86_64__
+    struct user_regs_struct regs;
+    assert(ptrace(PTRACE_GETREGS, pid, NULL, &regs) == 0);
+    assert(regs.rip != 0 && regs.rsp != 0);
+    assert(regs.cs == 0x33 && regs.ss == 0x2b);
+    assert(regs.orig_rax == (unsigned long)-1);
+#endif
+
+    // suppress the stop signal
+    assert(ptrace(PTRACE_CONT, pid, NULL, NULL) == 0);
+    assert(wait_stop(pid) == SIGTERM);
+    assert(ptrace(PTRACE_CONT, pid, NULL, NULL) == 0);
+    // replace the signal
+    assert(wait_stop(pid) == SIGUSR2);
+    assert(ptrace(PTRACE_CONT, pid, NULL, (void *)SIGUSR1) == 0);
+
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // the tracee is gone once reaped
+    assert(ptrace(PTRACE_CONT, pid, NULL, NULL) == -1 && errno == ESRCH);
+    printf("ptrace test passed\n");
+    return 0;
+}
@@ -0,0 +1,61 @@
+#define _XOPEN_SOURCE 600
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <assert.h>
+#include <termios.h>
+#include <sys/ioctl.h>
+
+int main(int argc, char **argv)
+{
+    char buf[64];
+    int ret;
+
+    int master = posix_openpt(O_RDWR | O_NOCTTY);
+    assert(master >= 0);
+    assert(grantpt(master) == 0);
+    assert(unlockpt(master) == 0);
+    char *name = ptsname(master);
+    assert(name != NULL);
+    int slave = open(name, O_RDWR | O_NOCTTY);
+    assert(slave >= 0);
+
+    // canonical input with echo
+    write(master, "hellx\x7fo\r", 7);
+    ret = read(slave, buf, sizeof(buf));
+    assert(ret == 6);
+    assert(memcmp(buf, "hello\n", 6) == 0);
+    ret = read(master, buf, sizeof(buf));
+    assert(ret > 0);
+
+    // output processing maps NL to CR-NL
+    write(slave, "hi\n", 3);
+    ret = read(master, buf, sizeof(buf));
+    assert(ret == 4);
+    assert(memcmp(buf, "hi\r\n", 4) == 0);
+
+    // raw mode without echo
+    struct termios tio;
+    assert(tcgetattr(slave, &tio) == 0);
+    cfmakeraw(&tio);
+    assert(tcsetattr(slave, TCSANOW, &tio) == 0);
+    write(master, "x", 1);
+    ret = read(slave, buf, sizeof(buf));
+    assert(ret == 1 && buf[0] == 'x');
+
+    // window size
+    struct winsize ws = {.ws_row = 24, .ws_col = 80};
+    assert(ioctl(master, TIOCSWINSZ, &ws) == 0);
+    memset(&ws, 0, sizeof(ws));
+    assert(ioctl(slave, TIOCGWINSZ, &ws) == 0);
+    assert(ws.ws_row == 24 && ws.ws_col == 80);
+
+    // hang up when the master is closed
+    close(master);
+    ret = read(slave, buf, sizeof(buf));
+    assert(ret == 0);
+    close(slave);
+    return 0;
+}
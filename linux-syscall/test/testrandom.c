@@ -4,15 +4,47 @@
  #include <syscall.h>
  #include <linux/random.h>
 #endif
+ #include <errno.h>
+ #include <fcntl.h>
  #include <stdio.h>
+ #include <string.h>
+ #include <unistd.h>
 
- int main(){
-    int buf;
+#ifndef GRND_INSECURE
+ #define GRND_INSECURE 4
+#endif
+
+ static long get_random(void *buf, size_t len, unsigned int flags){
 #ifdef HAVE_GETRANDOM
-    getrandom((void *)&buf,sizeof(buf),GRND_RANDOM);
+    return getrandom(buf,len,flags);
 #else
-    syscall(SYS_getrandom, (void *)&buf, sizeof(buf), GRND_RANDOM);
+    return syscall(SYS_getrandom, buf, len, flags);
 #endif
+ }
+
+ int main(){
+    int buf;
+    get_random((void *)&buf,sizeof(buf),GRND_RANDOM);
     printf("random: %d\n",buf);
+
+    // unknown flags, or both pools at once
+    if (get_random(&buf,sizeof(buf),0x80) != -1 || errno != EINVAL)
+        return 1;
+    if (get_random(&buf,sizeof(buf),GRND_RANDOM | GRND_INSECURE) != -1 || errno != EINVAL)
+        return 1;
+
+    char a[32], b[32];
+    if (get_random(a,sizeof(a),GRND_NONBLOCK) != sizeof(a))
+        return 1;
+    int fd = open("/dev/urandom", O_RDWR);
+    if (fd < 0)
+        return 1;
+    if (write(fd, "entropy", 7) != 7)
+        return 1;
+    if (read(fd, b, sizeof(b)) != sizeof(b))
+        return 1;
+    close(fd);
+    if (memcmp(a, b, sizeof(a)) == 0)
+        return 1;
     return 0;
  }
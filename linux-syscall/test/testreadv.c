@@ -0,0 +1,91 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/syscall.h>
+#include <sys/uio.h>
+
+#define FILE_PATH "/tmp/testreadv"
+
+#ifndef RWF_NOWAIT
+#define RWF_NOWAIT 0x8
+#endif
+#ifndef RWF_APPEND
+#define RWF_APPEND 0x10
+#endif
+
+static const char data[] = "0123456789abcdef";
+
+static void check_file(int fd, off_t offset, const char *expected)
+{
+    char buf[64] = {0};
+    size_t len = strlen(expected);
+    assert(pread(fd, buf, sizeof(buf), offset) == len);
+    assert(memcmp(buf, expected, len) == 0);
+}
+
+static void test_vectored(int fd)
+{
+    char a[4] = {0}, b[4] = {0};
+    struct iovec iov[2] = {{a, 3}, {b, 3}};
+
+    // the offset is used instead of the file position
+    struct iovec out[2] = {{(void *)"0123", 4}, {(void *)"456789abcdef", 12}};
+    assert(pwritev(fd, out, 2, 0) == 16);
+    assert(lseek(fd, 0, SEEK_CUR) == 0);
+    check_file(fd, 0, data);
+    assert(preadv(fd, iov, 2, 4) == 6);
+    assert(memcmp(a, "456", 3) == 0 && memcmp(b, "789", 3) == 0);
+    assert(lseek(fd, 0, SEEK_CUR) == 0);
+}
+
+static void test_rwf(int fd)
+{
+    char a[4] = {0}, b[4] = {0};
+    struct iovec iov[2] = {{a, 3}, {b, 3}};
+
+    // an offset of -1 is the file position
+    assert(lseek(fd, 2, SEEK_SET) == 2);
+    assert(syscall(SYS_preadv2, fd, iov, 2, -1, 0, 0) == 6);
+    assert(memcmp(a, "234", 3) == 0 && memcmp(b, "567", 3) == 0);
+    assert(lseek(fd, 0, SEEK_CUR) == 8);
+    assert(syscall(SYS_preadv2, fd, iov, 2, 13, 0, 0) == 3);
+    assert(memcmp(a, "def", 3) == 0);
+    assert(lseek(fd, 0, SEEK_CUR) == 8);
+    assert(syscall(SYS_preadv2, fd, iov, 2, -2, 0, 0) == -1 && errno == EINVAL);
+    assert(syscall(SYS_preadv2, fd, iov, 2, 0, 0, 0x80) == -1 && errno == EOPNOTSUPP);
+
+    // RWF_APPEND writes at the end whatever the offset
+    struct iovec out = {(void *)"xyz", 3};
+    assert(syscall(SYS_pwritev2, fd, &out, 1, 0, 0, RWF_APPEND) == 3);
+    check_file(fd, 0, "0123456789abcdefxyz");
+    assert(lseek(fd, 0, SEEK_CUR) == 8);
+
+    // RWF_NOWAIT fails instead of blocking on an empty pipe
+    int fds[2];
+    assert(pipe(fds) == 0);
+    assert(syscall(SYS_preadv2, fds[0], iov, 2, -1, 0, RWF_NOWAIT) == -1 && errno == EAGAIN);
+    assert(write(fds[1], "pipe", 4) == 4);
+    assert(syscall(SYS_preadv2, fds[0], iov, 2, -1, 0, RWF_NOWAIT) == 4);
+    assert(memcmp(a, "pip", 3) == 0 && b[0] == 'e');
+    close(fds[0]);
+    close(fds[1]);
+}
+
+int main(int argc, char **argv)
+{
+    int fd = open(FILE_PATH, O_CREAT | O_TRUNC | O_RDWR, 0644);
+    assert(fd >= 0);
+
+    test_vectored(fd);
+    test_rwf(fd);
+
+    close(fd);
+    assert(unlink(FILE_PATH) == 0);
+    printf("readv test passed\n");
+    return 0;
+}
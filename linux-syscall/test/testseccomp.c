@@ -0,0 +1,123 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <assert.h>
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+
+#if defined(__x86_64__)
+#define ARCH AUDIT_ARCH_X86_64
+#elif defined(__aarch64__)
+#define ARCH AUDIT_ARCH_AARCH64
+#else
+#define ARCH AUDIT_ARCH_RISCV64
+#endif
+
+static void wait_signaled(pid_t child, int sig)
+{
+    int status;
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == sig);
+}
+
+static void wait_ok(pid_t child)
+{
+    int status;
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_strict(void)
+{
+    int fds[2];
+    char c;
+    assert(pipe(fds) == 0);
+
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(syscall(SYS_seccomp, SECCOMP_SET_MODE_STRICT, 1, NULL) == -1 && errno == EINVAL);
+        if (syscall(SYS_seccomp, SECCOMP_SET_MODE_STRICT, 0, NULL) != 0)
+            exit(1);
+        // read and write are allowed
+        write(fds[1], "x", 1);
+        // but nothing else
+        syscall(SYS_getpid);
+        syscall(SYS_exit, 1);
+    }
+    wait_signaled(child, SIGKILL);
+    assert(read(fds[0], &c, 1) == 1 && c == 'x');
+
+    // exit is allowed too
+    child = fork();
+    if (child == 0)
+    {
+        if (prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT) != 0)
+            exit(1);
+        syscall(SYS_exit, 0);
+    }
+    wait_ok(child);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+static void test_errno(void)
+{
+    struct sock_filter insns[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, arch)),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, ARCH, 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr)),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, SYS_getppid, 0, 1),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | EPERM),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    struct sock_fprog prog = {sizeof(insns) / sizeof(insns[0]), insns};
+    // a jump out of the program is rejected
+    struct sock_filter bad[] = {
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    struct sock_fprog bad_prog = {2, bad};
+
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(prctl(PR_GET_SECCOMP) == 0);
+        assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+        assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &bad_prog) == -1 && errno == EINVAL);
+        assert(syscall(SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &prog) == 0);
+        assert(prctl(PR_GET_SECCOMP) == 2);
+
+        // the filtered syscall fails with the errno, the others run
+        assert(syscall(SYS_getppid) == -1 && errno == EPERM);
+        assert(getpid() > 0);
+        // and the filter is kept by the children
+        pid_t grandchild = fork();
+        if (grandchild == 0)
+            exit(syscall(SYS_getppid) == -1 && errno == EPERM ? 0 : 1);
+        wait_ok(grandchild);
+
+        // the strict mode can not be entered then
+        assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT) == -1 && errno == EINVAL);
+        exit(0);
+    }
+    wait_ok(child);
+    assert(getppid() > 0);
+}
+
+int main(int argc, char **argv)
+{
+    test_strict();
+    test_errno();
+    printf("seccomp test passed\n");
+    return 0;
+}
@@ -0,0 +1,64 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/ipc.h>
+#include <sys/shm.h>
+#include <sys/wait.h>
+
+#define KEY 0x5348
+
+int main(void)
+{
+    int status;
+    int fds[2];
+    struct shmid_ds ds;
+    assert(pipe(fds) == 0);
+
+    int shmid = shmget(KEY, 4096, IPC_CREAT | IPC_EXCL | 0600);
+    assert(shmid >= 0);
+    char *p = shmat(shmid, NULL, 0);
+    assert(p != (void *)-1);
+    strcpy(p, "shared");
+
+    // the child inherits the attach
+    pid_t child = fork();
+    if (child == 0)
+    {
+        char c;
+        assert(read(fds[0], &c, 1) == 1);
+        assert(strcmp(p, "removed") == 0);
+        strcpy(p, "child");
+        exit(0);
+    }
+    assert(shmctl(shmid, IPC_STAT, &ds) == 0);
+    assert(ds.shm_nattch == 2);
+
+    // a removed segment stays usable while attached, but its key is forgotten
+    assert(shmctl(shmid, IPC_RMID, NULL) == 0);
+    assert(shmctl(shmid, IPC_STAT, &ds) == 0);
+    assert(ds.shm_perm.mode & SHM_DEST);
+    assert(ds.shm_perm.__key == IPC_PRIVATE);
+    assert(shmget(KEY, 4096, 0600) == -1 && errno == ENOENT);
+    int other = shmget(KEY, 4096, IPC_CREAT | IPC_EXCL | 0600);
+    assert(other >= 0 && other != shmid);
+    assert(shmctl(other, IPC_RMID, NULL) == 0);
+    assert(shmctl(other, IPC_STAT, &ds) == -1 && errno == EINVAL);
+
+    strcpy(p, "removed");
+    assert(shmdt(p) == 0);
+    assert(shmctl(shmid, IPC_STAT, &ds) == 0);
+    assert(ds.shm_nattch == 1);
+
+    // and is destroyed after the last detach, by the exit of the child
+    assert(write(fds[1], "x", 1) == 1);
+    assert(waitpid(child, &status, 0) == child && status == 0);
+    assert(shmctl(shmid, IPC_STAT, &ds) == -1 && errno == EINVAL);
+    assert(shmat(shmid, NULL, 0) == (void *)-1 && errno == EINVAL);
+
+    printf("shared memory removal test passed\n");
+    return 0;
+}
@@ -0,0 +1,79 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <errno.h>
+#include <poll.h>
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <sys/socket.h>
+
+int main(int argc, char **argv)
+{
+    int val;
+    socklen_t len;
+    char buf[16];
+    struct sockaddr_in addr = {
+        .sin_family = AF_INET,
+        .sin_port = htons(12345),
+        .sin_addr = {htonl(INADDR_LOOPBACK)},
+    };
+
+    // test socket options
+    int server = socket(AF_INET, SOCK_STREAM, 0);
+    assert(server >= 0);
+    val = 1;
+    assert(setsockopt(server, SOL_SOCKET, SO_REUSEADDR, &val, sizeof(val)) == 0);
+    len = sizeof(val);
+    assert(getsockopt(server, SOL_SOCKET, SO_REUSEADDR, &val, &len) == 0);
+    assert(val == 1 && len == sizeof(val));
+    val = 4096;
+    assert(setsockopt(server, SOL_SOCKET, SO_RCVBUF, &val, sizeof(val)) == 0);
+    assert(getsockopt(server, SOL_SOCKET, SO_RCVBUF, &val, &len) == 0);
+    assert(val == 8192);
+    assert(getsockopt(server, SOL_SOCKET, SO_TYPE, &val, &len) == 0);
+    assert(val == SOCK_STREAM);
+    assert(bind(server, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+    assert(listen(server, 4) == 0);
+
+    // test non-blocking connect
+    int client = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0);
+    assert(client >= 0);
+    assert(connect(client, (struct sockaddr *)&addr, sizeof(addr)) == -1);
+    assert(errno == EINPROGRESS);
+    struct pollfd fds[1];
+    fds[0].fd = client;
+    fds[0].events = POLLOUT;
+    assert(poll(fds, 1, 1000) == 1);
+    assert(fds[0].revents & POLLOUT);
+    assert(getsockopt(client, SOL_SOCKET, SO_ERROR, &val, &len) == 0);
+    assert(val == 0);
+
+    // test accept
+    int conn = accept4(server, NULL, NULL, SOCK_CLOEXEC);
+    assert(conn >= 0);
+
+    // test MSG_PEEK
+    assert(recv(client, buf, sizeof(buf), 0) == -1);
+    assert(errno == EAGAIN);
+    assert(send(client, "hello", 5, 0) == 5);
+    memset(buf, 0, sizeof(buf));
+    assert(recv(conn, buf, sizeof(buf), MSG_PEEK) == 5);
+    assert(strcmp(buf, "hello") == 0);
+    memset(buf, 0, sizeof(buf));
+    assert(recv(conn, buf, sizeof(buf), 0) == 5);
+    assert(strcmp(buf, "hello") == 0);
+
+    // test shutdown
+    assert(shutdown(client, SHUT_WR) == 0);
+    assert(recv(conn, buf, sizeof(buf), 0) == 0);
+    assert(send(client, "hello", 5, MSG_NOSIGNAL) == -1);
+    assert(errno == EPIPE);
+
+    close(conn);
+    close(client);
+    close(server);
+
+    printf("socket test passed\n");
+    return 0;
+}
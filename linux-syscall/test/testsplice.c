@@ -0,0 +1,125 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/sendfile.h>
+
+#define SRC_PATH "/tmp/testsplice.src"
+#define DST_PATH "/tmp/testsplice.dst"
+
+static const char data[] = "0123456789abcdef";
+
+static void check_file(int fd, off_t offset, const char *expected)
+{
+    char buf[64] = {0};
+    size_t len = strlen(expected);
+    assert(pread(fd, buf, sizeof(buf), offset) == len);
+    assert(memcmp(buf, expected, len) == 0);
+}
+
+static void test_sendfile(int src, int dst)
+{
+    int fds[2];
+    char buf[32] = {0};
+
+    // from the file position, which is moved
+    assert(lseek(src, 4, SEEK_SET) == 4);
+    assert(sendfile(dst, src, NULL, 4) == 4);
+    assert(lseek(src, 0, SEEK_CUR) == 8);
+    check_file(dst, 0, "4567");
+
+    // from the offset given, which is updated instead
+    off_t offset = 12;
+    assert(sendfile(dst, src, &offset, 100) == 4);
+    assert(offset == 16);
+    assert(lseek(src, 0, SEEK_CUR) == 8);
+    check_file(dst, 0, "4567cdef");
+
+    // a pipe has no offset
+    assert(pipe(fds) == 0);
+    offset = 0;
+    assert(sendfile(fds[1], src, &offset, 3) == 3);
+    assert(read(fds[0], buf, sizeof(buf)) == 3 && memcmp(buf, "012", 3) == 0);
+    assert(write(fds[1], "x", 1) == 1);
+    offset = 0;
+    assert(sendfile(dst, fds[0], &offset, 1) == -1 && errno == ESPIPE);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+static void test_splice(int src, int dst)
+{
+    int fds[2];
+    char buf[32] = {0};
+    assert(pipe(fds) == 0);
+
+    // one end must be a pipe, whose offset can not be given
+    loff_t off_in = 0, off_out = 0;
+    assert(splice(src, &off_in, dst, &off_out, 4, 0) == -1 && errno == EINVAL);
+    assert(splice(src, &off_in, fds[1], &off_out, 4, 0) == -1 && errno == ESPIPE);
+
+    off_in = 10;
+    assert(splice(src, &off_in, fds[1], NULL, 4, 0) == 4);
+    assert(off_in == 14);
+    off_out = 20;
+    assert(splice(fds[0], NULL, dst, &off_out, 16, 0) == 4);
+    assert(off_out == 24);
+    check_file(dst, 20, "abcd");
+
+    // an empty pipe fails without blocking
+    assert(splice(fds[0], NULL, dst, NULL, 4, SPLICE_F_NONBLOCK) == -1 && errno == EAGAIN);
+    assert(splice(src, NULL, fds[1], NULL, 4, 0x100) == -1 && errno == EINVAL);
+
+    // nothing is left in the empty pipe once the writers are closed
+    close(fds[1]);
+    assert(splice(fds[0], NULL, dst, NULL, 4, 0) == 0);
+    assert(read(fds[0], buf, sizeof(buf)) == 0);
+    close(fds[0]);
+}
+
+static void test_copy_file_range(int src, int dst)
+{
+    assert(ftruncate(dst, 0) == 0);
+    assert(lseek(src, 2, SEEK_SET) == 2);
+    assert(lseek(dst, 0, SEEK_SET) == 0);
+
+    // the file positions are moved
+    assert(copy_file_range(src, NULL, dst, NULL, 3, 0) == 3);
+    assert(lseek(src, 0, SEEK_CUR) == 5 && lseek(dst, 0, SEEK_CUR) == 3);
+    check_file(dst, 0, "234");
+
+    // the offsets are updated instead
+    loff_t off_in = 10, off_out = 3;
+    assert(copy_file_range(src, &off_in, dst, &off_out, 100, 0) == 6);
+    assert(off_in == 16 && off_out == 9);
+    assert(lseek(src, 0, SEEK_CUR) == 5);
+    check_file(dst, 0, "234abcdef");
+
+    // the ranges in the same file may not overlap
+    off_in = 0, off_out = 2;
+    assert(copy_file_range(dst, &off_in, dst, &off_out, 4, 0) == -1 && errno == EINVAL);
+    assert(copy_file_range(src, NULL, dst, NULL, 1, 1) == -1 && errno == EINVAL);
+}
+
+int main(int argc, char **argv)
+{
+    int src = open(SRC_PATH, O_CREAT | O_TRUNC | O_RDWR, 0644);
+    int dst = open(DST_PATH, O_CREAT | O_TRUNC | O_RDWR, 0644);
+    assert(src >= 0 && dst >= 0);
+    assert(write(src, data, 16) == 16);
+
+    test_sendfile(src, dst);
+    test_splice(src, dst);
+    test_copy_file_range(src, dst);
+
+    close(src);
+    close(dst);
+    assert(unlink(SRC_PATH) == 0);
+    assert(unlink(DST_PATH) == 0);
+    printf("splice test passed\n");
+    return 0;
+}
@@ -0,0 +1,54 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <assert.h>
+#include <dirent.h>
+#include <sys/stat.h>
+
+static void read_file(const char *path, char *buf, int size)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    int n = read(fd, buf, size - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+}
+
+int main(int argc, char **argv)
+{
+    char buf[256];
+    struct stat st;
+
+    // device attributes
+    read_file("/sys/class/mem/null/dev", buf, sizeof(buf));
+    assert(strcmp(buf, "1:3\n") == 0);
+    read_file("/sys/class/mem/urandom/uevent", buf, sizeof(buf));
+    assert(strstr(buf, "DEVNAME=urandom\n") != NULL);
+    read_file("/sys/dev/char/5:0/uevent", buf, sizeof(buf));
+    assert(strstr(buf, "DEVNAME=tty\n") != NULL);
+
+    // every device in /sys/class/mem exists in /dev
+    DIR *dir = opendir("/sys/class/mem");
+    assert(dir != NULL);
+    struct dirent *ent;
+    int count = 0;
+    while ((ent = readdir(dir)) != NULL)
+    {
+        if (ent->d_name[0] == '.')
+            continue;
+        sprintf(buf, "/dev/%s", ent->d_name);
+        assert(stat(buf, &st) == 0);
+        count++;
+    }
+    closedir(dir);
+    assert(count == 4);
+
+    // the console terminal
+    int fd = open("/dev/tty", O_WRONLY);
+    assert(fd >= 0);
+    assert(write(fd, "tty\n", 4) == 4);
+    close(fd);
+    return 0;
+}
@@ -0,0 +1,69 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <assert.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
+
+int main(int argc, char **argv)
+{
+    char buf[64];
+    struct stat st;
+
+    // write and read back
+    int fd = open("/tmp/testtmpfs", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(write(fd, "hello tmpfs", 11) == 11);
+    assert(fstat(fd, &st) == 0 && st.st_size == 11);
+    assert(pread(fd, buf, sizeof(buf), 6) == 5);
+    assert(memcmp(buf, "tmpfs", 5) == 0);
+
+    // truncate
+    assert(ftruncate(fd, 5) == 0);
+    assert(fstat(fd, &st) == 0 && st.st_size == 5);
+    assert(ftruncate(fd, 4096) == 0);
+    assert(pread(fd, buf, 8, 0) == 8);
+    assert(memcmp(buf, "hello\0\0\0", 8) == 0);
+
+    // shared mapping writes through to the file
+    char *p = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(memcmp(p, "hello", 5) == 0);
+    memcpy(p, "HELLO", 5);
+    assert(pread(fd, buf, 5, 0) == 5);
+    assert(memcmp(buf, "HELLO", 5) == 0);
+    munmap(p, 4096);
+
+    // private mapping does not
+    p = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(p != MAP_FAILED);
+    memcpy(p, "world", 5);
+    assert(pread(fd, buf, 5, 0) == 5);
+    assert(memcmp(buf, "HELLO", 5) == 0);
+    munmap(p, 4096);
+    close(fd);
+
+    // O_TRUNC on open
+    fd = open("/tmp/testtmpfs", O_WRONLY | O_TRUNC);
+    assert(fd >= 0);
+    assert(fstat(fd, &st) == 0 && st.st_size == 0);
+    close(fd);
+    assert(unlink("/tmp/testtmpfs") == 0);
+
+    // size accounting
+    struct statfs sfs;
+    assert(statfs("/tmp", &sfs) == 0);
+    long free = sfs.f_bfree;
+    fd = open("/tmp", O_TMPFILE | O_RDWR, 0600);
+    assert(fd >= 0);
+    assert(ftruncate(fd, 4 * 4096) == 0);
+    assert(statfs("/tmp", &sfs) == 0 && sfs.f_bfree == free - 4);
+    assert(write(fd, "tmp", 3) == 3);
+    assert(pread(fd, buf, 3, 0) == 3 && memcmp(buf, "tmp", 3) == 0);
+    close(fd);
+    assert(statfs("/tmp", &sfs) == 0 && sfs.f_bfree == free);
+    return 0;
+}
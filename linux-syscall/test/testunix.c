@@ -0,0 +1,122 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <errno.h>
+#include <stddef.h>
+#include <sys/socket.h>
+#include <sys/un.h>
+
+// send `fd` along one byte on `sock`
+static void send_fd(int sock, int fd)
+{
+    char byte = 'F';
+    struct iovec iov = {.iov_base = &byte, .iov_len = 1};
+    char control[CMSG_SPACE(sizeof(int))];
+    struct msghdr msg = {
+        .msg_iov = &iov,
+        .msg_iovlen = 1,
+        .msg_control = control,
+        .msg_controllen = sizeof(control),
+    };
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    cmsg->cmsg_level = SOL_SOCKET;
+    cmsg->cmsg_type = SCM_RIGHTS;
+    cmsg->cmsg_len = CMSG_LEN(sizeof(int));
+    memcpy(CMSG_DATA(cmsg), &fd, sizeof(int));
+    assert(sendmsg(sock, &msg, 0) == 1);
+}
+
+// receive a file descriptor sent by `send_fd`
+static int recv_fd(int sock)
+{
+    char byte;
+    struct iovec iov = {.iov_base = &byte, .iov_len = 1};
+    char control[CMSG_SPACE(sizeof(int))];
+    struct msghdr msg = {
+        .msg_iov = &iov,
+        .msg_iovlen = 1,
+        .msg_control = control,
+        .msg_controllen = sizeof(control),
+    };
+    assert(recvmsg(sock, &msg, 0) == 1 && byte == 'F');
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    assert(cmsg != NULL);
+    assert(cmsg->cmsg_level == SOL_SOCKET && cmsg->cmsg_type == SCM_RIGHTS);
+    int fd;
+    memcpy(&fd, CMSG_DATA(cmsg), sizeof(int));
+    return fd;
+}
+
+int main(int argc, char **argv)
+{
+    char buf[16];
+
+    // test socketpair and fd passing
+    int sv[2];
+    assert(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+    assert(write(sv[0], "hello", 5) == 5);
+    assert(read(sv[1], buf, sizeof(buf)) == 5 && memcmp(buf, "hello", 5) == 0);
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    send_fd(sv[0], pipefd[1]);
+    int fd = recv_fd(sv[1]);
+    assert(fd >= 0 && fd != pipefd[1]);
+    assert(write(fd, "pipe", 4) == 4);
+    assert(read(pipefd[0], buf, sizeof(buf)) == 4 && memcmp(buf, "pipe", 4) == 0);
+    close(fd);
+
+    // test peer credentials
+    struct ucred cred;
+    socklen_t len = sizeof(cred);
+    assert(getsockopt(sv[0], SOL_SOCKET, SO_PEERCRED, &cred, &len) == 0);
+    assert(cred.pid == getpid() && len == sizeof(cred));
+
+    // the end of the stream after the peer is closed
+    close(sv[0]);
+    assert(read(sv[1], buf, sizeof(buf)) == 0);
+    assert(write(sv[1], "x", 1) == -1 && errno == EPIPE);
+    close(sv[1]);
+
+    // test a listening socket in the abstract namespace
+    struct sockaddr_un addr = {.sun_family = AF_UNIX};
+    memcpy(addr.sun_path, "\0testunix", 9);
+    socklen_t addrlen = offsetof(struct sockaddr_un, sun_path) + 9;
+    int server = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert(server >= 0);
+    assert(bind(server, (struct sockaddr *)&addr, addrlen) == 0);
+    assert(listen(server, 4) == 0);
+    int other = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert(bind(other, (struct sockaddr *)&addr, addrlen) == -1 && errno == EADDRINUSE);
+    close(other);
+    int client = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert(connect(client, (struct sockaddr *)&addr, addrlen) == 0);
+    int conn = accept(server, NULL, NULL);
+    assert(conn >= 0);
+    assert(send(client, "ping", 4, 0) == 4);
+    assert(recv(conn, buf, sizeof(buf), MSG_PEEK) == 4);
+    assert(recv(conn, buf, sizeof(buf), 0) == 4 && memcmp(buf, "ping", 4) == 0);
+    assert(recv(conn, buf, sizeof(buf), MSG_DONTWAIT) == -1 && errno == EAGAIN);
+    struct sockaddr_un peer;
+    len = sizeof(peer);
+    assert(getpeername(client, (struct sockaddr *)&peer, &len) == 0);
+    assert(len == addrlen && memcmp(peer.sun_path, addr.sun_path, 9) == 0);
+    close(client);
+    close(conn);
+    close(server);
+
+    // test datagrams on a path
+    struct sockaddr_un path = {.sun_family = AF_UNIX, .sun_path = "/tmp/testunix.sock"};
+    int dgram = socket(AF_UNIX, SOCK_DGRAM, 0);
+    assert(bind(dgram, (struct sockaddr *)&path, sizeof(path)) == 0);
+    int sender = socket(AF_UNIX, SOCK_DGRAM, 0);
+    assert(sendto(sender, "one", 3, 0, (struct sockaddr *)&path, sizeof(path)) == 3);
+    assert(sendto(sender, "two", 3, 0, (struct sockaddr *)&path, sizeof(path)) == 3);
+    assert(recv(dgram, buf, 2, 0) == 2 && memcmp(buf, "on", 2) == 0);
+    assert(recv(dgram, buf, sizeof(buf), 0) == 3 && memcmp(buf, "two", 3) == 0);
+    close(sender);
+    close(dgram);
+
+    printf("unix socket test passed\n");
+    return 0;
+}
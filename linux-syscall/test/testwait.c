@@ -0,0 +1,69 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <signal.h>
+#include <assert.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+
+int main(int argc, char **argv)
+{
+    int status;
+    pid_t child;
+
+    // a normal exit keeps the low 8 bits of the code
+    child = fork();
+    if (child == 0)
+        exit(42);
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFEXITED(status) && !WIFSIGNALED(status) && !WIFSTOPPED(status));
+    assert(WEXITSTATUS(status) == 42);
+
+    child = fork();
+    if (child == 0)
+        _exit(0x103);
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 3);
+
+    // a signal without a handler terminates the child
+    child = fork();
+    if (child == 0)
+    {
+        while (1)
+            pause();
+    }
+    assert(kill(child, SIGTERM) == 0);
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && !WIFEXITED(status));
+    assert(WTERMSIG(status) == SIGTERM);
+    assert(!WCOREDUMP(status));
+
+    // no core is dumped without the limit
+    child = fork();
+    if (child == 0)
+    {
+        struct rlimit limit = {0, 0};
+        setrlimit(RLIMIT_CORE, &limit);
+        abort();
+    }
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGABRT);
+    assert(!WCOREDUMP(status));
+
+    // a stopped child is reported with WUNTRACED, then killed
+    child = fork();
+    if (child == 0)
+    {
+        raise(SIGSTOP);
+        exit(1);
+    }
+    assert(waitpid(child, &status, WUNTRACED) == child);
+    assert(WIFSTOPPED(status) && !WIFEXITED(status) && !WIFSIGNALED(status));
+    assert(WSTOPSIG(status) == SIGSTOP);
+    assert(kill(child, SIGKILL) == 0);
+    assert(waitpid(child, &status, 0) == child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+
+    printf("wait status test passed\n");
+    return 0;
+}
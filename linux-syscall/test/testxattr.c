@@ -0,0 +1,69 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/wait.h>
+#include <sys/xattr.h>
+
+#define FILE_PATH "/tmp/testxattr"
+
+int main(int argc, char **argv)
+{
+    int status;
+    char buf[64];
+    unlink(FILE_PATH);
+    int fd = open(FILE_PATH, O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+
+    assert(setxattr(FILE_PATH, "user.b", "22", 2, 0) == 0);
+    assert(fsetxattr(fd, "user.a", "1", 1, XATTR_CREATE) == 0);
+    assert(fsetxattr(fd, "user.a", "11", 2, XATTR_CREATE) == -1 && errno == EEXIST);
+    assert(setxattr(FILE_PATH, "user.c", "3", 1, XATTR_REPLACE) == -1 && errno == ENODATA);
+    assert(setxattr(FILE_PATH, "user.a", "111", 3, XATTR_REPLACE) == 0);
+
+    // the size is returned for a zero size, and a smaller buffer fails
+    assert(getxattr(FILE_PATH, "user.a", NULL, 0) == 3);
+    assert(getxattr(FILE_PATH, "user.a", buf, 2) == -1 && errno == ERANGE);
+    assert(fgetxattr(fd, "user.a", buf, sizeof(buf)) == 3);
+    assert(memcmp(buf, "111", 3) == 0);
+    assert(getxattr(FILE_PATH, "user.c", buf, sizeof(buf)) == -1 && errno == ENODATA);
+
+    // the names are listed one after another, each ends with a null byte
+    const char names[] = "user.a\0user.b";
+    assert(listxattr(FILE_PATH, NULL, 0) == sizeof(names));
+    assert(listxattr(FILE_PATH, buf, 4) == -1 && errno == ERANGE);
+    assert(flistxattr(fd, buf, sizeof(buf)) == sizeof(names));
+    assert(memcmp(buf, names, sizeof(names)) == 0);
+
+    // the namespace must be known
+    assert(setxattr(FILE_PATH, "other.a", "1", 1, 0) == -1 && errno == EOPNOTSUPP);
+    assert(setxattr(FILE_PATH, "noprefix", "1", 1, 0) == -1 && errno == EOPNOTSUPP);
+    assert(setxattr(FILE_PATH, "trusted.a", "1", 1, 0) == 0);
+
+    // an unprivileged user needs the permission of the file
+    pid_t child = fork();
+    if (child == 0)
+    {
+        assert(setuid(1000) == 0);
+        char value[8];
+        assert(getxattr(FILE_PATH, "user.b", value, sizeof(value)) == 2);
+        assert(setxattr(FILE_PATH, "user.b", "2", 1, 0) == -1 && errno == EACCES);
+        assert(removexattr(FILE_PATH, "user.b") == -1 && errno == EACCES);
+        assert(setxattr(FILE_PATH, "trusted.b", "2", 1, 0) == -1 && errno == EPERM);
+        exit(0);
+    }
+    assert(waitpid(child, &status, 0) == child && status == 0);
+
+    assert(removexattr(FILE_PATH, "user.a") == 0);
+    assert(fremovexattr(fd, "user.a") == -1 && errno == ENODATA);
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == -1 && errno == ENODATA);
+    assert(getxattr(FILE_PATH, "user.b", buf, sizeof(buf)) == 2);
+
+    close(fd);
+    assert(unlink(FILE_PATH) == 0);
+    printf("xattr test passed\n");
+    return 0;
+}
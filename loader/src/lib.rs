@@ -10,6 +10,8 @@ extern crate log;
 #[macro_use]
 extern crate cfg_if;
 
+use zircon_object::kcounter;
+
 cfg_if! {
     if #[cfg(any(feature = "linux", doc))] {
         #[doc(cfg(feature = "linux"))]
@@ -23,3 +25,9 @@ cfg_if! {
         pub mod zircon;
     }
 }
+
+// kernel counters shared by Linux and Zircon user programs
+kcounter!(EXCEPTIONS_USER, "exceptions.user");
+kcounter!(EXCEPTIONS_IRQ, "exceptions.irq");
+kcounter!(EXCEPTIONS_PGFAULT, "exceptions.pgfault");
+kcounter!(SYSCALLS, "syscalls.total");
@@ -1,19 +1,25 @@
 //! Run Linux process and manage trap/interrupt/syscall.
 
-use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
 use core::{future::Future, pin::Pin};
+use linux_object::coredump::dump_core;
 use linux_object::signal::{
-    MachineContext, SigInfo, Signal, SignalActionFlags, SignalUserContext, Sigset,
+    MachineContext, SigInfo, Signal, SignalActionFlags, SignalDefaultAction, SignalUserContext,
+    Sigset, SIG_DFL, SIG_IGN,
 };
+use linux_object::sync::{wait_for_event, Event};
 
 use kernel_hal::context::{TrapReason, UserContext, UserContextField};
 use kernel_hal::interrupt::{intr_off, intr_on};
 use linux_object::fs::{vfs::FileSystem, INodeExt};
 use linux_object::thread::{CurrentThreadExt, ThreadExt};
 use linux_object::{loader::LinuxElfLoader, process::ProcessExt};
-use zircon_object::task::{CurrentThread, Job, Process, Thread, ThreadState};
+use zircon_object::task::{crash_report, CurrentThread, Job, Process, Task, Thread, ThreadState};
+use zircon_object::util::ktrace;
 use zircon_object::{object::KernelObject, vm::USER_STACK_PAGES, ZxError, ZxResult};
 
+use crate::{EXCEPTIONS_IRQ, EXCEPTIONS_PGFAULT, EXCEPTIONS_USER, SYSCALLS};
+
 /// Create and run main Linux process
 pub fn run(args: Vec<String>, envs: Vec<String>, rootfs: Arc<dyn FileSystem>) -> Arc<Process> {
     info!("Run Linux process: args={:?}, envs={:?}", args, envs);
@@ -30,6 +36,8 @@ pub fn run(args: Vec<String>, envs: Vec<String>, rootfs: Arc<dyn FileSystem>) ->
     let inode = rootfs.root_inode().lookup(&args[0]).unwrap();
     let data = inode.read_as_vec().unwrap();
     let path = args[0].clone();
+    proc.linux().set_execute_path(&path);
+    proc.linux().set_args(&args);
 
     let pg_token = kernel_hal::vm::current_vmtoken();
     debug!("current pgt = {:#x}", pg_token);
@@ -64,8 +72,36 @@ async fn run_user(thread: CurrentThread) {
         }
 
         // check the signal and handle
-        if let Some((signal, sigmask)) = thread.inner().lock_linux().handle_signal() {
-            ctx = handle_signal(&thread, ctx, signal, sigmask);
+        let mut signal = thread.inner().lock_linux().handle_signal();
+        // a traced process stops on every signal but `SIGKILL`, then the
+        // tracer chooses the signal to deliver
+        if let Some((sig, sigmask)) = signal.filter(|(sig, _)| *sig != Signal::SIGKILL) {
+            let linux = thread.proc().linux();
+            if linux.is_traced() {
+                thread.put_context(ctx);
+                thread.suspend();
+                linux.ptrace_stop(&thread.inner(), sig);
+                ctx = thread.wait_for_run().await;
+                if thread.state() == ThreadState::Dying {
+                    break;
+                }
+                signal = linux.take_ptrace_signal().map(|sig| (sig, sigmask));
+                if signal.is_none() {
+                    thread.inner().lock_linux().end_handle_signal(sigmask);
+                }
+            }
+        }
+        if let Some((signal, sigmask)) = signal {
+            match thread.proc().linux().signal_action(signal).handler {
+                SIG_IGN => thread.inner().lock_linux().end_handle_signal(sigmask),
+                SIG_DFL => {
+                    thread.inner().lock_linux().end_handle_signal(sigmask);
+                    if !handle_default_signal(&thread, signal, &ctx).await {
+                        break;
+                    }
+                }
+                _ => ctx = handle_signal(&thread, ctx, signal, sigmask),
+            }
         }
 
         // run
@@ -75,7 +111,11 @@ async fn run_user(thread: CurrentThread) {
             ctx.get_field(UserContextField::InstrPointer)
         );
         trace!("ctx = {:#x?}", ctx);
+        let tmp_time = kernel_hal::timer::timer_now().as_nanos();
         ctx.enter_uspace();
+        let time = kernel_hal::timer::timer_now().as_nanos() - tmp_time;
+        thread.time_add(time);
+        EXCEPTIONS_USER.add(1);
         debug!(
             "back from user: tid = {} pc = {:x} trap reason = {:?}",
             thread.id(),
@@ -83,14 +123,106 @@ async fn run_user(thread: CurrentThread) {
             ctx.trap_reason(),
         );
         trace!("ctx = {:#x?}", ctx);
+        let reason = ctx.trap_reason();
         // handle trap/interrupt/syscall
         if let Err(err) = handle_user_trap(&thread, ctx).await {
-            thread.exit_linux(err as i32);
+            match fault_signal(&reason) {
+                Some(signal) => force_signal(&thread, signal),
+                None => thread.exit_linux(err as i32),
+            }
         }
     }
     kernel_hal::thread::set_current_thread(None);
 }
 
+/// The signal sent to a thread for the fault it failed to handle.
+fn fault_signal(reason: &TrapReason) -> Option<Signal> {
+    match reason {
+        TrapReason::PageFault(..) | TrapReason::GernelFault(_) => Some(Signal::SIGSEGV),
+        TrapReason::UndefinedInstruction => Some(Signal::SIGILL),
+        TrapReason::SoftwareBreakpoint | TrapReason::HardwareBreakpoint => Some(Signal::SIGTRAP),
+        TrapReason::UnalignedAccess => Some(Signal::SIGBUS),
+        TrapReason::Syscall | TrapReason::Interrupt(_) => None,
+    }
+}
+
+/// Send a fault signal to the current thread, which can not be blocked.
+///
+/// As in Linux, a blocked fault signal, or one raised in a signal handler,
+/// takes the default action, otherwise the thread would fault forever.
+fn force_signal(thread: &CurrentThread, signal: Signal) {
+    let mut linux = thread.lock_linux();
+    if linux.signal_mask.contains(signal) || linux.handling_signal.is_some() {
+        linux.signal_mask.remove(signal);
+        linux.handling_signal = None;
+        let proc = thread.proc().linux();
+        let mut action = proc.signal_action(signal);
+        action.handler = SIG_DFL;
+        proc.set_signal_action(signal, action);
+    }
+    drop(linux);
+    thread.send_signal(signal);
+}
+
+/// Take the default action of a signal without a handler.
+///
+/// Returns `false` if the process is terminated.
+async fn handle_default_signal(thread: &CurrentThread, signal: Signal, cx: &UserContext) -> bool {
+    let proc = thread.proc();
+    match signal.default_action() {
+        SignalDefaultAction::Terminate => {
+            info!("process {} terminated by {:?}", proc.id(), signal);
+            proc.exit_by_signal(signal, false);
+            false
+        }
+        SignalDefaultAction::CoreDump => {
+            let reason = format!("killed by {:?}", signal);
+            error!("{}", crash_report(&thread.inner(), &reason, cx));
+            let core_dumped = match dump_core(proc, &thread.inner(), signal, cx) {
+                Ok(0) => {
+                    info!("process {} terminated by {:?}", proc.id(), signal);
+                    false
+                }
+                Ok(len) => {
+                    info!(
+                        "process {} terminated by {:?}, core dumped ({} bytes)",
+                        proc.id(),
+                        signal,
+                        len
+                    );
+                    true
+                }
+                Err(err) => {
+                    warn!(
+                        "failed to dump the core of process {}: {:?}",
+                        proc.id(),
+                        err
+                    );
+                    false
+                }
+            };
+            proc.exit_by_signal(signal, core_dumped);
+            false
+        }
+        SignalDefaultAction::Stop => {
+            info!("process {} stopped by {:?}", proc.id(), signal);
+            let linux = proc.linux();
+            linux.stop(signal);
+            // wait for SIGCONT or SIGKILL
+            let bus = linux.event_bus();
+            loop {
+                bus.lock().clear(Event::RECEIVE_SIGNAL);
+                if !linux.is_stopped() || thread.state() == ThreadState::Dying {
+                    break;
+                }
+                wait_for_event(bus.clone(), Event::RECEIVE_SIGNAL).await;
+            }
+            thread.state() != ThreadState::Dying
+        }
+        SignalDefaultAction::Ignore | SignalDefaultAction::Continue => true,
+    }
+}
+
 fn handle_signal(
     thread: &CurrentThread,
     mut ctx: Box<UserContext>,
@@ -160,6 +292,7 @@ macro_rules! run_with_irq_enable {
 async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) -> ZxResult {
     let reason = ctx.trap_reason();
     if let TrapReason::Syscall = reason {
+        SYSCALLS.add(1);
         let num = syscall_num(&ctx);
         let args = syscall_args(&ctx);
         ctx.advance_pc(reason);
@@ -170,9 +303,11 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
             syscall_entry: kernel_hal::context::syscall_entry as usize,
         };
         trace!("Syscall : {} {:x?}", num as u32, args);
+        ktrace::syscall_enter(num as u32);
         run_with_irq_enable! {
             let ret = syscall.syscall(num as u32, args).await as usize
         }
+        ktrace::syscall_exit(num as u32);
         thread.with_context(|ctx| ctx.set_field(UserContextField::ReturnValue, ret))?;
         return Ok(());
     }
@@ -182,14 +317,18 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
     let pid = thread.proc().id();
     match reason {
         TrapReason::Interrupt(vector) => {
+            EXCEPTIONS_IRQ.add(1);
+            ktrace::irq_enter(vector as u32);
             kernel_hal::interrupt::handle_irq(vector);
+            ktrace::irq_exit(vector as u32);
             #[cfg(not(feature = "libos"))]
             if vector == kernel_hal::context::TIMER_INTERRUPT_VEC {
-                kernel_hal::thread::yield_now().await;
+                thread.preempt().await;
             }
             Ok(())
         }
         TrapReason::PageFault(vaddr, flags) => {
+            EXCEPTIONS_PGFAULT.add(1);
             warn!(
                 "page fault from user mode @ {:#x}({:?}), pid={}",
                 vaddr, flags, pid
@@ -206,6 +345,11 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
                 err
             })
         }
+        TrapReason::SoftwareBreakpoint | TrapReason::HardwareBreakpoint => {
+            // a breakpoint or a single step, the thread gets `SIGTRAP`
+            debug!("breakpoint from user mode: {:x?}, pid={}", reason, pid);
+            Err(ZxError::STOP)
+        }
         _ => {
             error!(
                 "unsupported trap from user mode: {:x?}, pid={}, {:#x?}",
@@ -225,7 +369,7 @@ fn syscall_num(ctx: &UserContext) -> usize {
             regs.rax
         } else if #[cfg(target_arch = "aarch64")] {
             regs.x8
-        } else if #[cfg(target_arch = "riscv64")] {
+        } else if #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))] {
             regs.a7
         } else {
             unimplemented!()
@@ -240,7 +384,7 @@ fn syscall_args(ctx: &UserContext) -> [usize; 6] {
             [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9]
         } else if #[cfg(target_arch = "aarch64")] {
             [regs.x0, regs.x1, regs.x2, regs.x3, regs.x4, regs.x5]
-        } else if #[cfg(target_arch = "riscv64")] {
+        } else if #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))] {
             [regs.a0, regs.a1, regs.a2, regs.a3, regs.a4, regs.a5]
         } else {
             unimplemented!()
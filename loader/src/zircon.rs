@@ -11,11 +11,18 @@ use kernel_hal::context::{TrapReason, UserContext, UserContextField};
 use kernel_hal::{MMUFlags, PAGE_SIZE};
 use zircon_object::dev::{Resource, ResourceFlags, ResourceKind};
 use zircon_object::ipc::{Channel, MessagePacket};
-use zircon_object::kcounter;
 use zircon_object::object::{Handle, KernelObject, Rights};
 use zircon_object::task::{CurrentThread, ExceptionType, Job, Process, Thread, ThreadState};
 use zircon_object::util::elf_loader::{ElfExt, VmarExt};
-use zircon_object::vm::{VmObject, VmarFlags};
+use zircon_object::util::ktrace;
+use zircon_object::util::zbi::{Zbi, ZbiType};
+use zircon_object::vm::{
+    pages, start_memory_watchdog, start_zero_page_scanner, wait_for_pager, PressureThresholds,
+    VmObject, VmarFlags,
+};
+use zircon_object::ZxError;
+
+use crate::{EXCEPTIONS_IRQ, EXCEPTIONS_PGFAULT, EXCEPTIONS_USER, SYSCALLS};
 
 // These describe userboot itself
 const K_PROC_SELF: usize = 0;
@@ -70,7 +77,7 @@ fn kcounter_vmos() -> (Arc<VmObject>, Arc<VmObject>) {
         (desc_vmo, arena_vmo)
     } else {
         use kernel_hal::vm::{GenericPageTable, PageTable};
-        use zircon_object::{util::kcounter::AllCounters, vm::pages};
+        use zircon_object::util::kcounter::AllCounters;
         let pgtable = PageTable::from_current();
 
         // kcounters names table.
@@ -146,10 +153,22 @@ pub fn run_userboot(zbi: impl AsRef<[u8]>, cmdline: &str) -> Arc<Process> {
         vdso_vmo
     };
 
-    // zbi
+    // zbi, without the kernel items if it is a bootable one
+    let zbi = zbi.as_ref();
+    let parsed = match Zbi::parse(zbi) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            warn!("invalid ZBI: {:?}", e);
+            None
+        }
+    };
+    let data_zbi = parsed.map(|parsed| parsed.data_zbi());
     let zbi_vmo = {
-        let vmo = VmObject::new_paged(zbi.as_ref().len() / PAGE_SIZE + 1);
-        vmo.write(0, zbi.as_ref()).unwrap();
+        let zbi = data_zbi.as_deref().unwrap_or(zbi);
+        let vmo = VmObject::new_paged(zbi.len() / PAGE_SIZE + 1);
+        // committed at once, so that it is mapped by huge pages
+        vmo.commit(0, vmo.len()).unwrap();
+        vmo.write(0, zbi).unwrap();
         vmo.set_name("zbi");
         vmo
     };
@@ -175,7 +194,7 @@ pub fn run_userboot(zbi: impl AsRef<[u8]>, cmdline: &str) -> Arc<Process> {
     let mut handles = alloc::vec![Handle::new(proc.clone(), Rights::empty()); K_HANDLECOUNT];
     handles[K_PROC_SELF] = Handle::new(proc.clone(), Rights::DEFAULT_PROCESS);
     handles[K_VMARROOT_SELF] = Handle::new(proc.vmar(), Rights::DEFAULT_VMAR | Rights::IO);
-    handles[K_ROOTJOB] = Handle::new(job, Rights::DEFAULT_JOB);
+    handles[K_ROOTJOB] = Handle::new(job.clone(), Rights::DEFAULT_JOB);
     handles[K_ROOTRESOURCE] = Handle::new(resource, Rights::DEFAULT_RESOURCE);
     handles[K_ZBI] = Handle::new(zbi_vmo, Rights::DEFAULT_VMO);
 
@@ -194,8 +213,13 @@ pub fn run_userboot(zbi: impl AsRef<[u8]>, cmdline: &str) -> Arc<Process> {
     handles[K_FIRSTVDSO + 1] = Handle::new(vdso_test1, Rights::DEFAULT_VMO | Rights::EXECUTE);
     handles[K_FIRSTVDSO + 2] = Handle::new(vdso_test2, Rights::DEFAULT_VMO | Rights::EXECUTE);
 
-    // TODO: use correct CrashLogVmo handle
-    let crash_log_vmo = VmObject::new_paged(1);
+    // the crash log of the previous boot, saved by the bootloader
+    let crashlog = parsed
+        .and_then(|parsed| parsed.find(ZbiType::Crashlog))
+        .map_or(&[][..], |item| item.payload);
+    let crash_log_vmo = VmObject::new_paged(pages(crashlog.len()).max(1));
+    crash_log_vmo.write(0, crashlog).unwrap();
+    crash_log_vmo.set_content_size(crashlog.len()).unwrap();
     crash_log_vmo.set_name("crashlog");
     handles[K_CRASHLOG] = Handle::new(crash_log_vmo, Rights::DEFAULT_VMO);
 
@@ -218,6 +242,36 @@ pub fn run_userboot(zbi: impl AsRef<[u8]>, cmdline: &str) -> Arc<Process> {
 
     // check: handle to root proc should be only
 
+    // the zero page scanner runs only if asked for, by the option of Zircon
+    let zero_page_scans = cmdline
+        .split(':')
+        .find_map(|opt| opt.strip_prefix("kernel.page-scanner.zero-page-scans-per-second="))
+        .and_then(|rate| rate.parse().ok());
+    if let Some(rate) = zero_page_scans.filter(|&rate| rate != 0) {
+        start_zero_page_scanner(rate);
+    }
+
+    // the memory watchdog runs unless disabled, signaling the events of
+    // memory pressure and killing jobs when free memory drops below the
+    // thresholds, 300, 150 and 50 MB by default
+    let oom_option = |name: &str, default: usize| {
+        cmdline
+            .split(':')
+            .find_map(|opt| opt.strip_prefix(name)?.strip_prefix('='))
+            .map_or(default, |mb| mb.parse().unwrap_or(default))
+    };
+    if !cmdline
+        .split(':')
+        .any(|opt| opt == "kernel.oom.enable=false")
+    {
+        let thresholds = PressureThresholds {
+            out_of_memory: oom_option("kernel.oom.outofmemory-mb", 50) * 0x10_0000,
+            critical: oom_option("kernel.oom.critical-mb", 150) * 0x10_0000,
+            warning: oom_option("kernel.oom.warning-mb", 300) * 0x10_0000,
+        };
+        start_memory_watchdog(&job, thresholds);
+    }
+
     let data = Vec::from(cmdline.replace(':', "\0") + "\0");
     let msg = MessagePacket { data, handles };
     kernel_channel.write(msg).unwrap();
@@ -227,10 +281,6 @@ pub fn run_userboot(zbi: impl AsRef<[u8]>, cmdline: &str) -> Arc<Process> {
     proc
 }
 
-kcounter!(EXCEPTIONS_USER, "exceptions.user");
-kcounter!(EXCEPTIONS_IRQ, "exceptions.irq");
-kcounter!(EXCEPTIONS_PGFAULT, "exceptions.pgfault");
-
 fn thread_fn(thread: CurrentThread) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
     Box::pin(run_user(thread))
 }
@@ -286,15 +336,22 @@ async fn handler_user_trap(
     let reason = ctx.trap_reason();
 
     if let TrapReason::Syscall = reason {
+        SYSCALLS.add(1);
         let num = syscall_num(&ctx);
         let args = syscall_args(&ctx);
         ctx.advance_pc(reason);
         thread.put_context(ctx);
         let mut syscall = zircon_syscall::Syscall { thread, thread_fn };
+        ktrace::syscall_enter(num as u32);
         let ret = syscall.syscall(num as u32, args).await as usize;
+        ktrace::syscall_exit(num as u32);
         thread
             .with_context(|ctx| ctx.set_field(UserContextField::ReturnValue, ret))
             .map_err(|_| ExceptionType::ThreadExiting)?;
+        // a job policy may raise an exception on the syscall
+        if thread.take_policy_exception() {
+            return Err(ExceptionType::PolicyError);
+        }
         return Ok(());
     }
 
@@ -302,15 +359,22 @@ async fn handler_user_trap(
     match reason {
         TrapReason::Interrupt(vector) => {
             EXCEPTIONS_IRQ.add(1); // FIXME
+            ktrace::irq_enter(vector as u32);
             kernel_hal::interrupt::handle_irq(vector);
-            kernel_hal::thread::yield_now().await;
+            ktrace::irq_exit(vector as u32);
+            thread.preempt().await;
             Ok(())
         }
         TrapReason::PageFault(vaddr, flags) => {
             EXCEPTIONS_PGFAULT.add(1);
             info!("page fault from user mode @ {:#x}({:?})", vaddr, flags);
             let vmar = thread.proc().vmar();
-            vmar.handle_page_fault(vaddr, flags).map_err(|err| {
+            // the page may be supplied later by a user pager
+            let ret = wait_for_pager(thread, || vmar.handle_page_fault(vaddr, flags)).await;
+            ret.map_err(|err| {
+                if err == ZxError::STOP {
+                    return ExceptionType::ThreadExiting;
+                }
                 error!(
                     "failed to handle page fault from user mode @ {:#x}({:?}): {:?}\n{:#x?}",
                     vaddr,
@@ -145,6 +145,11 @@ async fn test_sem() {
     assert_eq!(test("/bin/testsem1").await, 0);
 }
 
+#[async_std::test]
+async fn test_semop_msg() {
+    assert_eq!(test("/bin/testipc").await, 0);
+}
+
 #[async_std::test]
 async fn test_shm() {
     assert_eq!(test("/bin/testshm1").await, 0);
@@ -159,3 +164,163 @@ async fn test_select() {
 async fn test_poll() {
     assert_eq!(test("/bin/testpoll").await, 0);
 }
+
+#[async_std::test]
+async fn test_eventfd() {
+    assert_eq!(test("/bin/testeventfd").await, 0);
+}
+
+#[async_std::test]
+async fn test_epoll() {
+    assert_eq!(test("/bin/testepoll").await, 0);
+}
+
+#[async_std::test]
+async fn test_pty() {
+    assert_eq!(test("/bin/testpty").await, 0);
+}
+
+#[async_std::test]
+async fn test_jobctl() {
+    assert_eq!(test("/bin/testjobctl").await, 0);
+}
+
+#[async_std::test]
+async fn test_wait_status() {
+    assert_eq!(test("/bin/testwait").await, 0);
+}
+
+#[async_std::test]
+async fn test_fifo() {
+    assert_eq!(test("/bin/testfifo").await, 0);
+}
+
+#[async_std::test]
+async fn test_io_uring() {
+    assert_eq!(test("/bin/testiouring").await, 0);
+}
+
+#[async_std::test]
+async fn test_file_lock() {
+    assert_eq!(test("/bin/testlock").await, 0);
+}
+
+#[async_std::test]
+async fn test_seccomp() {
+    assert_eq!(test("/bin/testseccomp").await, 0);
+}
+
+#[async_std::test]
+async fn test_credentials() {
+    assert_eq!(test("/bin/testcred").await, 0);
+}
+
+#[async_std::test]
+async fn test_coredump() {
+    assert_eq!(test("/bin/testcoredump").await, 0);
+}
+
+#[async_std::test]
+async fn test_ptrace() {
+    assert_eq!(test("/bin/testptrace").await, 0);
+}
+
+#[async_std::test]
+async fn test_inotify() {
+    assert_eq!(test("/bin/testinotify").await, 0);
+}
+
+#[async_std::test]
+async fn test_xattr() {
+    assert_eq!(test("/bin/testxattr").await, 0);
+}
+
+#[async_std::test]
+async fn test_namespace() {
+    assert_eq!(test("/bin/testns").await, 0);
+}
+
+#[async_std::test]
+async fn test_overlayfs() {
+    assert_eq!(test("/bin/testoverlay").await, 0);
+}
+
+#[async_std::test]
+async fn test_splice() {
+    assert_eq!(test("/bin/testsplice").await, 0);
+}
+
+#[async_std::test]
+async fn test_readv() {
+    assert_eq!(test("/bin/testreadv").await, 0);
+}
+
+#[async_std::test]
+async fn test_pipe_capacity() {
+    assert_eq!(test("/bin/testpipe3").await, 0);
+}
+
+#[async_std::test]
+async fn test_shm_remove() {
+    assert_eq!(test("/bin/testshm3").await, 0);
+}
+
+#[async_std::test]
+async fn test_procfs() {
+    assert_eq!(test("/bin/testprocfs").await, 0);
+}
+
+#[async_std::test]
+async fn test_sysfs() {
+    assert_eq!(test("/bin/testsysfs").await, 0);
+}
+
+#[async_std::test]
+async fn test_tmpfs() {
+    assert_eq!(test("/bin/testtmpfs").await, 0);
+}
+
+#[async_std::test]
+async fn test_mremap() {
+    assert_eq!(test("/bin/testmremap").await, 0);
+}
+
+#[async_std::test]
+async fn test_madvise() {
+    assert_eq!(test("/bin/testmadvise").await, 0);
+}
+
+#[async_std::test]
+async fn test_affinity() {
+    assert_eq!(test("/bin/testaffinity").await, 0);
+}
+
+#[async_std::test]
+async fn test_clock() {
+    assert_eq!(test("/bin/testclock").await, 0);
+}
+
+#[async_std::test]
+async fn test_itimer() {
+    assert_eq!(test("/bin/testitimer").await, 0);
+}
+
+#[async_std::test]
+async fn test_socket() {
+    assert_eq!(test("/bin/testsocket").await, 0);
+}
+
+#[async_std::test]
+async fn test_unix_socket() {
+    assert_eq!(test("/bin/testunix").await, 0);
+}
+
+#[async_std::test]
+async fn test_netlink() {
+    assert_eq!(test("/bin/testnetlink").await, 0);
+}
+
+#[async_std::test]
+async fn test_ping() {
+    assert_eq!(test("/bin/testping").await, 0);
+}
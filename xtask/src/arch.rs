@@ -10,6 +10,8 @@ pub(crate) enum Arch {
     Riscv64,
     X86_64,
     Aarch64,
+    /// 需要带有 LLVM LoongArch 后端（LLVM 16 以上）的工具链。
+    Loongarch64,
 }
 
 impl Arch {
@@ -20,6 +22,7 @@ impl Arch {
             Self::Riscv64 => "riscv64",
             Self::X86_64 => "x86_64",
             Self::Aarch64 => "aarch64",
+            Self::Loongarch64 => "loongarch64",
         }
     }
 
@@ -35,9 +38,43 @@ impl Arch {
         TARGET.join(self.name())
     }
 
+    /// musl 交叉工具链的目标名，也是工具链中各工具的前缀。
+    #[inline]
+    pub fn musl_target(&self) -> String {
+        format!("{}-linux-musl", self.name())
+    }
+
+    /// musl 动态链接器的文件名，应用程序通过它加载动态库。
+    #[inline]
+    pub fn musl_ld_name(&self) -> String {
+        format!("ld-musl-{}.so.1", self.name())
+    }
+
+    /// musl 交叉工具链的下载地址。
+    ///
+    /// 可以通过环境变量 `ZCORE_MUSL_CROSS_<ARCH>` 指定，
+    /// 用于尚无预编译缓存的架构。
+    fn musl_cross_url(&self) -> String {
+        let name = format!("{}-cross", self.musl_target());
+        let var = format!("ZCORE_MUSL_CROSS_{}", self.name().to_uppercase());
+        std::env::var(var).unwrap_or_else(|_| {
+            format!("https://github.com/YdrMaster/zCore/releases/download/musl-cache/{name}.tgz")
+        })
+    }
+
+    /// 预编译测例的下载地址，包括无法交叉编译的 libc-test 测例和 oscomp 测例。
+    fn prebuilt_tests_url(&self) -> Option<&'static str> {
+        match self {
+            Self::Riscv64 => Some(
+                "https://github.com/rcore-os/libc-test-prebuilt/releases/download/0.1/prebuild.tar.xz",
+            ),
+            Self::X86_64 | Self::Aarch64 | Self::Loongarch64 => None,
+        }
+    }
+
     /// Downloads linux musl toolchain, and returns its path.
     pub fn linux_musl_cross(&self) -> PathBuf {
-        let name = format!("{}-linux-musl-cross", self.name().to_lowercase());
+        let name = format!("{}-cross", self.musl_target());
 
         let origin = self.origin();
         let target = self.target();
@@ -48,14 +85,24 @@ impl Arch {
         dir::create_parent(&dir).unwrap();
         dir::rm(&dir).unwrap();
 
-        wget(
-            format!("https://github.com/YdrMaster/zCore/releases/download/musl-cache/{name}.tgz"),
-            &tgz,
-        );
+        wget(self.musl_cross_url(), &tgz);
         Tar::xf(&tgz, Some(target)).invoke();
 
         dir
     }
+
+    /// Downloads the prebuilt tests if there are for this architecture, and
+    /// returns their path.
+    pub fn prebuilt_tests(&self) -> Option<PathBuf> {
+        let url = self.prebuilt_tests_url()?;
+        let tar = self.origin().join("prebuild.tar.xz");
+        wget(url, &tar);
+        // 解压到目标路径
+        let dir = self.target().join("prebuilt-tests");
+        dir::clear(&dir).unwrap();
+        Tar::xf(&tar, Some(&dir)).invoke();
+        Some(dir.join("prebuild"))
+    }
 }
 
 impl FromStr for Arch {
@@ -66,6 +113,7 @@ impl FromStr for Arch {
             "riscv64" => Ok(Self::Riscv64),
             "x86_64" => Ok(Self::X86_64),
             "aarch64" => Ok(Self::Aarch64),
+            "loongarch64" => Ok(Self::Loongarch64),
             _ => Err(XError::EnumParse {
                 type_name: "Arch",
                 value: s.into(),
@@ -76,7 +124,7 @@ impl FromStr for Arch {
 
 #[derive(Clone, Copy, Args)]
 pub(crate) struct ArchArg {
-    /// Build architecture, `riscv64` or `x86_64`.
+    /// Build architecture, `riscv64`, `x86_64`, `aarch64` or `loongarch64`.
     #[clap(short, long)]
     pub arch: Arch,
 }
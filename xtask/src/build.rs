@@ -1,6 +1,9 @@
 ﻿use crate::{Arch, ArchArg, PROJECT_DIR};
 use command_ext::{dir, BinUtil, Cargo, CommandExt, Ext, Qemu};
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 #[derive(Clone, Args)]
 pub(crate) struct BuildArgs {
@@ -29,11 +32,23 @@ pub(crate) struct QemuArgs {
     /// Number of hart (SMP for Symmetrical Multiple Processor).
     #[clap(long)]
     smp: Option<u8>,
+    /// Version of the ARM GIC emulated, 2 or 3 (aarch64 only).
+    #[clap(long)]
+    gic: Option<u8>,
     /// Port for gdb to connect. If set, qemu will block and wait gdb to connect.
     #[clap(long)]
     gdb: Option<u16>,
 }
 
+#[derive(Args)]
+pub(crate) struct DebugArgs {
+    #[clap(flatten)]
+    qemu: QemuArgs,
+    /// Launches gdb-multiarch and attaches it to the halted qemu.
+    #[clap(long)]
+    attach: bool,
+}
+
 #[derive(Args)]
 pub(crate) struct GdbArgs {
     #[clap(flatten)]
@@ -117,35 +132,84 @@ impl OutArgs {
 }
 
 impl QemuArgs {
+    /// 使用默认的 qemu 参数。
+    pub fn new(build: BuildArgs) -> Self {
+        Self {
+            build,
+            smp: None,
+            gic: None,
+            gdb: None,
+        }
+    }
+
     /// 在 qemu 中启动。
     pub fn qemu(&self) {
+        self.prepare();
+        self.command("\"LOG=warn\"").invoke();
+    }
+
+    /// 生成启动需要的镜像和内核。
+    pub fn prepare(&self) {
         // 递归 image
         self.build.arch.linux_rootfs().image();
-        // 递归 build
-        self.build.invoke(Cargo::build);
+        match self.build.arch() {
+            // x86_64 由 zboot 从 ESP 中加载 ELF 内核
+            Arch::X86_64 => {
+                self.build.invoke(Cargo::build);
+                let esp = self.esp();
+                let boot = esp.join("EFI").join("Boot");
+                let zcore = esp.join("EFI").join("zCore");
+                dir::clear(&boot).unwrap();
+                dir::clear(&zcore).unwrap();
+                fs::copy(zboot(), boot.join("BootX64.efi")).unwrap();
+                fs::copy(self.build.target_file_path(), zcore.join("zcore.elf")).unwrap();
+                fs::copy(INNER.join("x86_64.img"), zcore.join("x86_64.img")).unwrap();
+            }
+            // 递归 bin
+            _ => {
+                OutArgs {
+                    build: self.build.clone(),
+                    output: None,
+                }
+                .bin();
+            }
+        }
+    }
+
+    /// x86_64 的 EFI 系统分区目录，qemu 将其作为 FAT 磁盘。
+    fn esp(&self) -> PathBuf {
+        self.build.target_file_path().with_file_name("esp")
+    }
+
+    /// 构造启动 qemu 的命令，`cmdline` 为传给内核的命令行。
+    ///
+    /// 需要先调用 [`QemuArgs::prepare`]。
+    pub fn command(&self, cmdline: &str) -> Qemu {
         // 构造各种字符串
         let arch = self.build.arch();
         let arch_str = arch.name();
         let obj = self.build.target_file_path();
-        let bin = OutArgs {
-            build: self.build.clone(),
-            output: None,
-        }
-        .bin();
+        // QEMU only boots an ELF kernel on loongarch virt
+        let kernel = match arch {
+            Arch::Loongarch64 => obj.clone(),
+            _ => obj.with_extension("bin"),
+        };
         // 设置 Qemu 参数
         let mut qemu = Qemu::system(arch_str);
         qemu.args(&["-m", "1G"])
-            .arg("-kernel")
-            .arg(&bin)
-            .arg("-initrd")
-            .arg(INNER.join(format!("{arch_str}.img")))
-            .args(&["-append", "\"LOG=warn\""])
             .args(&["-display", "none"])
             .arg("-no-reboot")
             .arg("-nographic")
             .optional(&self.smp, |qemu, smp| {
                 qemu.args(&["-smp", &smp.to_string()]);
             });
+        if !matches!(arch, Arch::X86_64) {
+            qemu.arg("-kernel")
+                .arg(&kernel)
+                .arg("-initrd")
+                .arg(INNER.join(format!("{arch_str}.img")))
+                .args(&["-append", cmdline]);
+        }
         match arch {
             Arch::Riscv64 => {
                 qemu.args(&["-machine", "virt"])
@@ -153,10 +217,30 @@ impl QemuArgs {
                     .arg(rustsbi_qemu())
                     .args(&["-serial", "mon:stdio"]);
             }
-            Arch::X86_64 => todo!(),
+            Arch::X86_64 => {
+                let esp = self.esp();
+                write_zboot_conf(&esp, cmdline.trim_matches('"'));
+                let ovmf = ovmf();
+                qemu.args(&["-machine", "q35"])
+                    .args(&["-cpu", "Haswell,+smap,-check,-fsgsbase"])
+                    .args(&[
+                        "-drive",
+                        &format!("format=raw,if=pflash,readonly=on,file={}", ovmf.display()),
+                    ])
+                    .args(&[
+                        "-drive",
+                        &format!("format=raw,file=fat:rw:{}", esp.display()),
+                    ])
+                    .args(&["-serial", "mon:stdio"])
+                    .args(&["-nic", "none"]);
+            }
             Arch::Aarch64 => {
                 fs::copy(obj, INNER.join("disk").join("os")).unwrap();
-                qemu.args(&["-machine", "virt"])
+                let machine = match self.gic {
+                    Some(version) => format!("virt,gic-version={version}"),
+                    None => "virt".into(),
+                };
+                qemu.args(&["-machine", &machine])
                     .args(&["-cpu", "cortex-a72"])
                     .arg("-bios")
                     .arg(arch.target().join("firmware").join("QEMU_EFI.fd"))
@@ -173,11 +257,51 @@ impl QemuArgs {
                         "virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0",
                     ]);
             }
+            Arch::Loongarch64 => {
+                qemu.args(&["-machine", "virt"])
+                    .args(&["-serial", "mon:stdio"]);
+            }
         }
         qemu.optional(&self.gdb, |qemu, port| {
             qemu.args(&["-S", "-gdb", &format!("tcp::{port}")]);
-        })
-        .invoke();
+        });
+        qemu
+    }
+}
+
+impl DebugArgs {
+    /// 启动 qemu 并等待 gdb 连接。
+    pub fn debug(mut self) {
+        const DEFAULT_PORT: u16 = 1234;
+        let port = *self.qemu.gdb.get_or_insert(DEFAULT_PORT);
+        let arch = self.qemu.build.arch();
+        let obj = self.qemu.build.target_file_path();
+        self.qemu.prepare();
+        let mut qemu = self.qemu.command("\"LOG=warn\"");
+        if !self.attach {
+            println!("qemu is waiting for gdb on port {port}, symbol file: {obj:?}");
+            qemu.invoke();
+            return;
+        }
+        let mut child = qemu.as_mut().spawn().unwrap();
+        Ext::new("gdb-multiarch")
+            .args(&["-ex", &format!("set architecture {}", gdb_arch(arch))])
+            .args(&["-ex", &format!("file {}", obj.display())])
+            .args(&["-ex", &format!("target remote localhost:{port}")])
+            .status();
+        // gdb 退出后不再需要 qemu
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
+
+/// gdb 中的架构名。
+fn gdb_arch(arch: Arch) -> &'static str {
+    match arch {
+        Arch::Riscv64 => "riscv:rv64",
+        Arch::X86_64 => "i386:x86-64",
+        Arch::Aarch64 => "aarch64",
+        Arch::Loongarch64 => "Loongarch64",
     }
 }
 
@@ -194,11 +318,55 @@ impl GdbArgs {
                     .args(&["-ex", &format!("target remote localhost:{}", self.port)])
                     .invoke();
             }
+            Arch::Loongarch64 => {
+                Ext::new("loongarch64-unknown-linux-gnu-gdb")
+                    .args(&["-ex", &format!("target remote localhost:{}", self.port)])
+                    .invoke();
+            }
             Arch::X86_64 => todo!(),
         }
     }
 }
 
+/// 编译 zboot，返回 efi 文件路径。
+fn zboot() -> PathBuf {
+    let zboot = PROJECT_DIR.join("zboot");
+    // 目标和 build-std 由 `zboot/.cargo/config.toml` 指定
+    Cargo::build().current_dir(&zboot).release().invoke();
+    zboot
+        .join("target")
+        .join("x86_64-unknown-uefi")
+        .join("release")
+        .join("zboot.efi")
+}
+
+/// x86_64 的 UEFI 固件，可以用环境变量 `OVMF` 指定，默认使用系统安装的 OVMF。
+fn ovmf() -> PathBuf {
+    match std::env::var_os("OVMF") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from("/usr/share/ovmf/OVMF.fd"),
+    }
+}
+
+/// 根据 `zCore/zboot.conf` 生成 zboot 的配置，指定 initramfs 和内核命令行。
+fn write_zboot_conf(esp: &Path, cmdline: &str) {
+    let conf = fs::read_to_string(INNER.join("zboot.conf"))
+        .unwrap()
+        .lines()
+        .map(|line| {
+            if line.starts_with("initramfs=") {
+                r"initramfs=\EFI\zCore\x86_64.img".into()
+            } else if line.starts_with("cmdline=") {
+                format!("cmdline={cmdline}")
+            } else {
+                line.into()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    fs::write(esp.join("EFI").join("Boot").join("zboot.conf"), conf).unwrap();
+}
+
 /// 下载 rustsbi。
 fn rustsbi_qemu() -> PathBuf {
     // https://github.com/opencv/opencv/archive/refs/heads/4.x.zip
@@ -1,10 +1,61 @@
-﻿use crate::{commands::wget, Arch, PROJECT_DIR};
-use command_ext::{dir, CommandExt, Qemu, Tar};
-use std::{fs, path::Path};
+﻿use crate::{commands::wget, Arch, ArchArg, XError, PROJECT_DIR};
+use command_ext::{dir, CommandExt, Ext, Tar};
+use std::{fs, path::Path, str::FromStr};
+
+/// 镜像的文件系统格式。
+#[derive(Clone, Copy)]
+pub(crate) enum FsType {
+    /// rcore-fs 的 SimpleFileSystem，需要安装 `rcore-fs-fuse`。
+    Sfs,
+    Ext4,
+    /// 需要安装 `mkfs.vfat` 和 mtools，符号链接会被替换为文件。
+    Fat32,
+    /// newc 格式的 cpio 归档，内核还不能直接挂载。
+    Cpio,
+}
+
+impl FromStr for FsType {
+    type Err = XError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sfs" => Ok(Self::Sfs),
+            "ext4" => Ok(Self::Ext4),
+            "fat32" => Ok(Self::Fat32),
+            "cpio" => Ok(Self::Cpio),
+            _ => Err(XError::EnumParse {
+                type_name: "FsType",
+                value: s.into(),
+            }),
+        }
+    }
+}
+
+#[derive(Args)]
+pub(crate) struct ImageArgs {
+    #[clap(flatten)]
+    arch: ArchArg,
+    /// File system of the image, `sfs`, `ext4`, `fat32` or `cpio`.
+    #[clap(long, default_value = "ext4")]
+    fs: FsType,
+}
+
+impl ImageArgs {
+    /// 生成指定格式的镜像。
+    pub fn image(&self) {
+        self.arch.linux_rootfs().image_as(self.fs);
+    }
+}
 
 impl super::LinuxRootfs {
-    /// 生成镜像。
+    /// 生成 ext4 镜像。
+    #[inline]
     pub fn image(&self) {
+        self.image_as(FsType::Ext4);
+    }
+
+    /// 生成指定文件系统格式的镜像。
+    pub fn image_as(&self, fs: FsType) {
         // 递归 rootfs
         self.make(false);
         // 镜像路径
@@ -30,33 +81,92 @@ impl super::LinuxRootfs {
             fs::copy(fw_dir.join("Boot.json"), boot_dir.join("Boot.json")).unwrap();
         }
         // 生成镜像
-        fuse(self.path(), &image);
-        // 扩充一些额外空间，供某些测试使用
-        Qemu::img()
-            .arg("resize")
-            .args(&["-f", "raw"])
+        let dir = self.path();
+        if image.exists() {
+            fs::remove_file(&image).unwrap();
+        }
+        match fs {
+            FsType::Sfs => sfs(dir, &image),
+            FsType::Ext4 => mkfs(dir, &image),
+            FsType::Fat32 => fat32(dir, &image),
+            FsType::Cpio => cpio(dir, &image),
+        }
+    }
+}
+
+/// 镜像大小，留出元数据的空间，并扩充一些额外空间，供某些测试使用。
+fn image_size(dir: &Path) -> u64 {
+    const EXTRA_SPACE: u64 = 32 * 1024 * 1024; // 32MiB
+    dir_size(dir) * 5 / 4 + EXTRA_SPACE
+}
+
+/// 制作 SimpleFileSystem 镜像，兼容旧版本的内核。
+fn sfs(dir: impl AsRef<Path>, image: impl AsRef<Path>) {
+    Ext::new("rcore-fs-fuse")
+        .arg(image.as_ref())
+        .arg(dir.as_ref())
+        .arg("zip")
+        .invoke();
+}
+
+/// 制作 ext4 镜像，可以用标准的 Linux 工具检查和修改。
+fn mkfs(dir: impl AsRef<Path>, image: impl AsRef<Path>) {
+    let size = image_size(dir.as_ref());
+    Ext::new("mkfs.ext4")
+        .args(&["-F", "-q", "-d"])
+        .arg(dir.as_ref())
+        .arg(image.as_ref())
+        .arg(format!("{}K", size / 1024))
+        .invoke();
+}
+
+/// 制作 FAT32 镜像，再用 mtools 拷入文件。
+fn fat32(dir: impl AsRef<Path>, image: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    let image = image.as_ref();
+    // FAT32 至少需要 65525 个簇
+    const MIN_SIZE: u64 = 64 * 1024 * 1024; // 64MiB
+    let size = image_size(dir).max(MIN_SIZE);
+    Ext::new("mkfs.vfat")
+        .args(&["-F", "32", "-C"])
+        .arg(image)
+        .arg(format!("{}", size / 1024))
+        .invoke();
+    for entry in fs::read_dir(dir).unwrap() {
+        Ext::new("mcopy")
+            .args(&["-s", "-o", "-i"])
             .arg(image)
-            .arg("+5M")
+            .arg(entry.unwrap().path())
+            .arg("::")
             .invoke();
     }
 }
 
-/// 制作镜像。
-fn fuse(dir: impl AsRef<Path>, image: impl AsRef<Path>) {
-    use rcore_fs::vfs::FileSystem;
-    use rcore_fs_fuse::zip::zip_dir;
-    use rcore_fs_sfs::SimpleFileSystem;
-    use std::sync::{Arc, Mutex};
-
-    let file = fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(image)
-        .expect("failed to open image");
-    const MAX_SPACE: usize = 1024 * 1024 * 1024; // 1GiB
-    let fs = SimpleFileSystem::create(Arc::new(Mutex::new(file)), MAX_SPACE)
-        .expect("failed to create sfs");
-    zip_dir(dir.as_ref(), fs.root_inode()).expect("failed to zip fs");
+/// 制作 newc 格式的 cpio 归档，`image` 需要是绝对路径。
+fn cpio(dir: impl AsRef<Path>, image: impl AsRef<Path>) {
+    let image = image.as_ref();
+    Ext::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "find . | cpio -o -H newc --quiet > {}",
+            image.display()
+        ))
+        .current_dir(dir.as_ref())
+        .invoke();
+}
+
+/// 递归统计目录中文件的大小。
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let meta = entry.metadata().unwrap();
+            if meta.is_dir() {
+                dir_size(&entry.path())
+            } else {
+                meta.len()
+            }
+        })
+        .sum()
 }
@@ -10,8 +10,12 @@ use std::{
 
 mod image;
 mod opencv;
+mod report;
 mod test;
 
+pub(crate) use image::ImageArgs;
+pub(crate) use report::LibcTestArgs;
+
 lazy_static::lazy_static! {
     static ref LIBOS_MUSL_LIBC_PATH: PathBuf = Arch::X86_64.origin().join("libc-libos.so");
 }
@@ -46,11 +50,8 @@ impl LinuxRootfs {
         // 拷贝 busybox
         fs::copy(busybox, bin.join("busybox")).unwrap();
         // 拷贝 libc.so
-        let from = musl
-            .join(format!("{}-linux-musl", self.0.name()))
-            .join("lib")
-            .join("libc.so");
-        let to = lib.join(format!("ld-musl-{arch}.so.1", arch = self.0.name()));
+        let from = musl.join(self.0.musl_target()).join("lib").join("libc.so");
+        let to = lib.join(self.0.musl_ld_name());
         fs::copy(from, &to).unwrap();
         Ext::new(self.strip(musl)).arg("-s").arg(to).invoke();
         // 为常用功能建立符号链接
@@ -70,7 +71,7 @@ impl LinuxRootfs {
         // 递归 rootfs
         self.make(false);
         let dir = self.0.linux_musl_cross();
-        self.put_libs(&dir, dir.join(format!("{}-linux-musl", self.0.name())));
+        self.put_libs(&dir, dir.join(self.0.musl_target()));
         dir
     }
 
@@ -110,9 +111,9 @@ impl LinuxRootfs {
         Make::new()
             .current_dir(&target)
             .arg(format!(
-                "CROSS_COMPILE={musl}/{arch}-linux-musl-",
+                "CROSS_COMPILE={musl}/{target}-",
                 musl = musl.canonicalize().unwrap().join("bin").display(),
-                arch = self.0.name(),
+                target = self.0.musl_target(),
             ))
             .invoke();
         // 裁剪
@@ -126,13 +127,13 @@ impl LinuxRootfs {
     fn strip(&self, musl: impl AsRef<Path>) -> PathBuf {
         musl.as_ref()
             .join("bin")
-            .join(format!("{}-linux-musl-strip", self.0.name()))
+            .join(format!("{}-strip", self.0.musl_target()))
     }
 
     /// 从安装目录拷贝所有 so 和 so 链接到 rootfs
     fn put_libs(&self, musl: impl AsRef<Path>, dir: impl AsRef<Path>) {
         let lib = self.path().join("lib");
-        let musl_libc_protected = format!("ld-musl-{}.so.1", self.0.name());
+        let musl_libc_protected = self.0.musl_ld_name();
         let musl_libc_ignored = "libc.so";
         let strip = self.strip(musl);
         dir.as_ref()
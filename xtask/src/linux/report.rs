@@ -0,0 +1,315 @@
+use crate::{
+    build::{BuildArgs, QemuArgs},
+    PROJECT_DIR,
+};
+use command_ext::dir;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// 在 rootfs 中依次执行测例的脚本。
+const RUN_SCRIPT: &str = "\
+#!/bin/sh
+skip=${1:-0}
+i=0
+while read case; do
+  if [ $i -ge $skip ]; then
+    echo \"=== START $case\"
+    /libc-test/$case
+    echo \"=== RESULT $case $?\"
+  fi
+  i=$((i+1))
+done < /libc-test/cases.txt
+echo \"=== DONE\"
+";
+
+#[derive(Args)]
+pub(crate) struct LibcTestArgs {
+    #[clap(flatten)]
+    build: BuildArgs,
+    /// The report of a previous run to compare with.
+    #[clap(long)]
+    baseline: Option<PathBuf>,
+    /// Overwrites the baseline with the report of this run.
+    #[clap(long)]
+    bless: bool,
+    /// Seconds to wait for output before a case is considered hung.
+    #[clap(long, default_value = "30")]
+    timeout: u64,
+}
+
+/// 单个测例的结果。
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    /// 测例没有在限定时间内结束。
+    Timeout,
+    /// 测例执行时内核退出。
+    Crash,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::Timeout => "timeout",
+            Self::Crash => "crash",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pass" => Some(Self::Pass),
+            "fail" => Some(Self::Fail),
+            "timeout" => Some(Self::Timeout),
+            "crash" => Some(Self::Crash),
+            _ => None,
+        }
+    }
+}
+
+/// 一次 qemu 运行读到的输出。
+enum Line {
+    Start(String),
+    Result(String, Outcome),
+    Done,
+}
+
+impl LibcTestArgs {
+    /// 在 qemu 中运行 libc-test 并生成报告。
+    pub fn run(mut self) {
+        // 测试结束后内核需要关机退出 qemu
+        if self.build.features.is_none() {
+            self.build.features = Some("linux baremetal-test".into());
+        }
+        let arch = self.build.arch.arch;
+        let rootfs = self.build.arch.linux_rootfs();
+        if !rootfs.path().join("libc-test").is_dir() {
+            rootfs.put_libc_test();
+        }
+        let cases = put_run_script(&rootfs.path().join("libc-test"));
+        let qemu = QemuArgs::new(self.build.clone());
+        qemu.prepare();
+        // 逐个执行测例，内核卡死或退出时从下一个测例重新启动
+        let mut results = BTreeMap::new();
+        while let Some(skip) = cases.iter().position(|case| !results.contains_key(case)) {
+            let cmdline = format!("LOG=warn:ROOTPROC=/bin/busybox?sh?/libc-test/run-all.sh?{skip}");
+            let mut current = None;
+            let mut child = qemu
+                .command(&cmdline)
+                .as_mut()
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let lines = read_lines(child.stdout.take().unwrap());
+            loop {
+                let outcome = match lines.recv_timeout(Duration::from_secs(self.timeout)) {
+                    Ok(Line::Start(case)) => {
+                        current = Some(case);
+                        continue;
+                    }
+                    Ok(Line::Result(case, outcome)) => {
+                        current = None;
+                        results.insert(case, outcome);
+                        continue;
+                    }
+                    Ok(Line::Done) => Outcome::Crash,
+                    Err(mpsc::RecvTimeoutError::Timeout) => Outcome::Timeout,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Outcome::Crash,
+                };
+                // 当前测例没有结果，或者输出不完整，不再重试
+                let case = current.unwrap_or_else(|| cases[skip].clone());
+                results.entry(case).or_insert(outcome);
+                break;
+            }
+            child.kill().ok();
+            child.wait().unwrap();
+            // 测例列表之外的输出不计入结果
+            results.retain(|case, _| cases.contains(case));
+        }
+        // 生成报告
+        let out = PROJECT_DIR.join("target").join("libc-test");
+        fs::create_dir_all(&out).unwrap();
+        let json = to_json(arch.name(), &results);
+        let baseline = self.baseline.as_ref().map(|path| {
+            let text = fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read baseline {path:?}: {e}"));
+            from_json(&text)
+        });
+        let markdown = to_markdown(arch.name(), &results, baseline.as_ref());
+        let json_path = out.join(format!("{}.json", arch.name()));
+        let md_path = out.join(format!("{}.md", arch.name()));
+        fs::write(&json_path, &json).unwrap();
+        fs::write(&md_path, &markdown).unwrap();
+        println!("{markdown}");
+        println!("Report saved to {json_path:?} and {md_path:?}.");
+        if self.bless {
+            if let Some(path) = &self.baseline {
+                dir::create_parent(path).unwrap();
+                fs::write(path, &json).unwrap();
+                println!("Baseline {path:?} updated.");
+            }
+        } else if let Some(baseline) = baseline {
+            let regressions = regressions(&results, &baseline);
+            if !regressions.is_empty() {
+                eprintln!("{} regression(s) against the baseline.", regressions.len());
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// 列出所有测例并写入执行脚本，返回测例列表。
+fn put_run_script(libc_test: &Path) -> Vec<String> {
+    let mut cases = Vec::new();
+    for kind in ["functional", "regression", "math"] {
+        let dir = libc_test.join("src").join(kind);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|res| res.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".exe") {
+                cases.push(format!("src/{kind}/{name}"));
+            }
+        }
+    }
+    cases.sort();
+    let mut list = cases.join("\n");
+    list.push('\n');
+    fs::write(libc_test.join("cases.txt"), list).unwrap();
+    fs::write(libc_test.join("run-all.sh"), RUN_SCRIPT).unwrap();
+    cases
+}
+
+/// 在后台线程中读取并转发 qemu 的输出，解析出测例的开始和结束。
+fn read_lines(stdout: impl std::io::Read + Send + 'static) -> mpsc::Receiver<Line> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            println!("{line}");
+            let pos = match line.find("=== ") {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let mut words = line[pos + 4..].split_whitespace();
+            let parsed = match (words.next(), words.next(), words.next()) {
+                (Some("START"), Some(case), _) => Line::Start(case.into()),
+                (Some("RESULT"), Some(case), Some(code)) => {
+                    let outcome = if code == "0" {
+                        Outcome::Pass
+                    } else {
+                        Outcome::Fail
+                    };
+                    Line::Result(case.into(), outcome)
+                }
+                (Some("DONE"), _, _) => Line::Done,
+                _ => continue,
+            };
+            if tx.send(parsed).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn to_json(arch: &str, results: &BTreeMap<String, Outcome>) -> String {
+    let mut json = String::new();
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"arch\": \"{arch}\",").unwrap();
+    writeln!(json, "  \"cases\": {{").unwrap();
+    let mut iter = results.iter().peekable();
+    while let Some((case, outcome)) = iter.next() {
+        let comma = if iter.peek().is_some() { "," } else { "" };
+        writeln!(json, "    \"{case}\": \"{}\"{comma}", outcome.as_str()).unwrap();
+    }
+    writeln!(json, "  }}").unwrap();
+    writeln!(json, "}}").unwrap();
+    json
+}
+
+/// 读取 [`to_json`] 生成的报告，每行一个测例。
+fn from_json(json: &str) -> BTreeMap<String, Outcome> {
+    json.lines()
+        .filter_map(|line| {
+            let (case, outcome) = line.trim().trim_end_matches(',').split_once(':')?;
+            let case = case.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let outcome = outcome.trim().strip_prefix('"')?.strip_suffix('"')?;
+            Some((case.into(), Outcome::parse(outcome)?))
+        })
+        .collect()
+}
+
+/// 在基线中通过，这次没有通过的测例。
+fn regressions<'a>(
+    results: &'a BTreeMap<String, Outcome>,
+    baseline: &BTreeMap<String, Outcome>,
+) -> Vec<(&'a str, Outcome)> {
+    results
+        .iter()
+        .filter(|(case, outcome)| {
+            **outcome != Outcome::Pass && baseline.get(*case) == Some(&Outcome::Pass)
+        })
+        .map(|(case, outcome)| (case.as_str(), *outcome))
+        .collect()
+}
+
+fn to_markdown(
+    arch: &str,
+    results: &BTreeMap<String, Outcome>,
+    baseline: Option<&BTreeMap<String, Outcome>>,
+) -> String {
+    let count = |o: Outcome| results.values().filter(|x| **x == o).count();
+    let mut md = String::new();
+    writeln!(md, "# libc-test on {arch}\n").unwrap();
+    writeln!(md, "| total | pass | fail | timeout | crash |").unwrap();
+    writeln!(md, "|:-----:|:----:|:----:|:-------:|:-----:|").unwrap();
+    writeln!(
+        md,
+        "| {} | {} | {} | {} | {} |",
+        results.len(),
+        count(Outcome::Pass),
+        count(Outcome::Fail),
+        count(Outcome::Timeout),
+        count(Outcome::Crash),
+    )
+    .unwrap();
+    if let Some(baseline) = baseline {
+        let fixed = results
+            .iter()
+            .filter(|(case, outcome)| {
+                **outcome == Outcome::Pass && baseline.get(*case) != Some(&Outcome::Pass)
+            })
+            .collect::<Vec<_>>();
+        writeln!(md, "\n## Regressions\n").unwrap();
+        for (case, outcome) in regressions(results, baseline) {
+            writeln!(md, "- `{case}`: {}", outcome.as_str()).unwrap();
+        }
+        writeln!(md, "\n## Fixed\n").unwrap();
+        for (case, _) in fixed {
+            writeln!(md, "- `{case}`").unwrap();
+        }
+    }
+    writeln!(md, "\n## Not passed\n").unwrap();
+    for (case, outcome) in results.iter().filter(|(_, o)| **o != Outcome::Pass) {
+        writeln!(md, "- `{case}`: {}", outcome.as_str()).unwrap();
+    }
+    md
+}
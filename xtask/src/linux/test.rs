@@ -1,7 +1,6 @@
 ﻿use super::join_path_env;
-use crate::{commands::wget, Arch};
-use command_ext::{dir, CommandExt, Ext, Make, Tar};
-use std::{ffi::OsStr, fs, path::PathBuf};
+use command_ext::{dir, CommandExt, Ext, Make};
+use std::{ffi::OsStr, fs};
 
 impl super::LinuxRootfs {
     /// 将 libc-test 放入 rootfs。
@@ -17,7 +16,7 @@ impl super::LinuxRootfs {
         Make::new()
             .j(usize::MAX)
             .env("ARCH", self.0.name())
-            .env("CROSS_COMPILE", &format!("{}-linux-musl-", self.0.name()))
+            .env("CROSS_COMPILE", &format!("{}-", self.0.musl_target()))
             .env(
                 "PATH",
                 join_path_env(&[self.0.linux_musl_cross().join("bin")]),
@@ -25,9 +24,9 @@ impl super::LinuxRootfs {
             .current_dir(&dir)
             .invoke();
         // FIXME 为什么要替换？
-        if let Arch::Riscv64 = self.0 {
+        if let Some(prebuilt) = self.0.prebuilt_tests() {
             fs::copy(
-                riscv64_special().join("libc-test/functional/tls_align-static.exe"),
+                prebuilt.join("libc-test/functional/tls_align-static.exe"),
                 dir.join("src/functional/tls_align-static.exe"),
             )
             .unwrap();
@@ -44,7 +43,7 @@ impl super::LinuxRootfs {
             .0
             .linux_musl_cross()
             .join("bin")
-            .join(format!("{}-linux-musl-gcc", self.0.name()));
+            .join(format!("{}-gcc", self.0.musl_target()));
         fs::read_dir("linux-syscall/test")
             .unwrap()
             .filter_map(|res| res.ok())
@@ -57,21 +56,9 @@ impl super::LinuxRootfs {
                     .arg(bin.join(c.file_stem().unwrap()))
                     .invoke()
             });
-        // 再为 riscv64 添加 oscomp
-        if let Arch::Riscv64 = self.0 {
-            dircpy::copy_dir(riscv64_special().join("oscomp"), self.path().join("oscomp")).unwrap();
+        // 再添加预编译的 oscomp 测例
+        if let Some(prebuilt) = self.0.prebuilt_tests() {
+            dircpy::copy_dir(prebuilt.join("oscomp"), self.path().join("oscomp")).unwrap();
         }
     }
 }
-
-fn riscv64_special() -> PathBuf {
-    const URL: &str =
-        "https://github.com/rcore-os/libc-test-prebuilt/releases/download/0.1/prebuild.tar.xz";
-    let tar = Arch::Riscv64.origin().join("prebuild.tar.xz");
-    wget(URL, &tar);
-    // 解压到目标路径
-    let dir = Arch::Riscv64.target();
-    dir::clear(&dir).unwrap();
-    Tar::xf(&tar, Some(&dir)).invoke();
-    dir.join("prebuild")
-}
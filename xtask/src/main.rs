@@ -18,9 +18,9 @@ mod errors;
 mod linux;
 
 use arch::{Arch, ArchArg};
-use build::{BuildArgs, GdbArgs, OutArgs, QemuArgs};
+use build::{BuildArgs, DebugArgs, GdbArgs, OutArgs, QemuArgs};
 use errors::XError;
-use linux::LinuxRootfs;
+use linux::{ImageArgs, LibcTestArgs, LinuxRootfs};
 
 lazy_static::lazy_static! {
     /// The path of zCore project.
@@ -164,6 +164,20 @@ enum Commands {
     /// ```
     Gdb(GdbArgs),
 
+    /// 在 qemu 中启动 zCore 并等待 gdb 连接。Runs zCore in qemu halted for gdb.
+    ///
+    /// 默认端口为 1234。指定 `--attach` 时同时启动 `gdb-multiarch` 并加载内核符号。
+    ///
+    /// The default port is 1234. With `--attach`, `gdb-multiarch` is launched
+    /// with the kernel symbols loaded.
+    ///
+    /// # Example
+    ///
+    /// ```bash
+    /// cargo debug --arch riscv64 --attach
+    /// ```
+    Debug(DebugArgs),
+
     // ========================================================
     // 管理 linux rootfs
     // --------------------------------------------------------
@@ -221,6 +235,22 @@ enum Commands {
     /// ```
     LibcTest(ArchArg),
 
+    /// 在 qemu 中运行 libc-test 并生成报告。Runs libc-test in qemu and reports the results.
+    ///
+    /// 报告保存到 `target/libc-test/{arch}.json` 和 `target/libc-test/{arch}.md`。
+    /// 指定 `--baseline` 时与之前的报告比较，有测例退化则返回失败；同时指定 `--bless` 则用这次的报告更新它。
+    ///
+    /// The report is saved to `target/libc-test/{arch}.json` and `target/libc-test/{arch}.md`.
+    /// With `--baseline`, it is compared with a previous report and fails on regressions;
+    /// add `--bless` to update the baseline instead.
+    ///
+    /// # Example
+    ///
+    /// ```bash
+    /// cargo libc-test-run --arch riscv64 --baseline libc-test-riscv64.json
+    /// ```
+    LibcTestRun(LibcTestArgs),
+
     /// 将其他测试集拷贝到 rootfs 目录对应位置。Copies other test files to rootfs directory.
     ///
     /// # Example
@@ -232,12 +262,16 @@ enum Commands {
 
     /// 构造 Linux rootfs 镜像文件。Builds the linux rootfs image file.
     ///
+    /// 通过 `--fs` 选择文件系统格式，默认为 ext4。
+    ///
+    /// Select the file system by `--fs`, ext4 by default.
+    ///
     /// # Example
     ///
     /// ```bash
-    /// cargo image --arch riscv64
+    /// cargo image --arch riscv64 --fs fat32
     /// ```
-    Image(ArchArg),
+    Image(ImageArgs),
 
     // ========================================================
     // Libos 模式
@@ -313,8 +347,9 @@ fn main() {
         Opencv(arg) => arg.linux_rootfs().put_opencv(),
         Ffmpeg(arg) => arg.linux_rootfs().put_ffmpeg(),
         LibcTest(arg) => arg.linux_rootfs().put_libc_test(),
+        LibcTestRun(args) => args.run(),
         OtherTest(arg) => arg.linux_rootfs().put_other_test(),
-        Image(arg) => arg.linux_rootfs().image(),
+        Image(args) => args.image(),
 
         Asm(args) => args.asm(),
         Bin(args) => {
@@ -323,6 +358,7 @@ fn main() {
         }
         Qemu(args) => args.qemu(),
         Gdb(args) => args.gdb(),
+        Debug(args) => args.debug(),
 
         LibosLibcTest => {
             libos::rootfs(true);
@@ -15,17 +15,48 @@ cfg_if! {
 
         #[cfg(not(feature = "libos"))]
         pub fn rootfs() -> Arc<dyn FileSystem> {
+            use kernel_hal::drivers::{self, block::scan_partitions, scheme::BlockScheme};
             use linux_object::fs::rcore_fs_wrapper::{Block, BlockCache, MemBuf};
-            use rcore_fs::dev::Device;
+            use linux_object::fs::{Ext4FileSystem, FatFileSystem};
+            use rcore_fs::{dev::Device, vfs::FsError};
 
             let device: Arc<dyn Device> = if let Some(initrd) = init_ram_disk() {
                 Arc::new(MemBuf::new(initrd))
             } else {
-                let block = kernel_hal::drivers::all_block().first_unwrap();
+                let disk = drivers::all_block().first_unwrap();
+                // the first Linux partition if the disk is partitioned
+                let partitions = scan_partitions(&disk).unwrap_or_default();
+                let block: Arc<dyn BlockScheme> =
+                    match partitions.into_iter().find(|p| p.part_type().is_linux()) {
+                        Some(part) => {
+                            info!("Rootfs on partition {}", part.number());
+                            part
+                        }
+                        None => disk,
+                    };
                 Arc::new(BlockCache::new(Block::new(block), 0x100))
             };
             info!("Opening the rootfs...");
-            rcore_fs_sfs::SimpleFileSystem::open(device).expect("failed to open device SimpleFS")
+            match Ext4FileSystem::open(device.clone()) {
+                Ok(fs) => return fs,
+                Err(FsError::WrongFs) => {}
+                Err(e) => panic!("failed to open device ext4: {:?}", e),
+            }
+            // images made by `cargo image --fs sfs` or older versions of xtask
+            match rcore_fs_sfs::SimpleFileSystem::open(device.clone()) {
+                Ok(fs) => return fs,
+                Err(FsError::WrongFs) => {}
+                Err(e) => panic!("failed to open device SimpleFS: {:?}", e),
+            }
+            // read-only, made by `cargo image --fs fat32`
+            FatFileSystem::open(device).expect("failed to open device FAT")
+        }
+
+        /// Overlay `rootfs` with a tmpfs, so that the changes are kept in
+        /// memory and the image stays unchanged.
+        pub fn overlay(rootfs: Arc<dyn FileSystem>) -> Arc<dyn FileSystem> {
+            use linux_object::fs::{OverlayFS, TmpFS};
+            OverlayFS::new(rootfs.root_inode(), TmpFS::new().root_inode())
         }
     } else if #[cfg(feature = "zircon")] {
 
@@ -43,7 +74,7 @@ cfg_if! {
 }
 
 #[cfg(not(feature = "libos"))]
-fn init_ram_disk() -> Option<&'static mut [u8]> {
+pub fn init_ram_disk() -> Option<&'static mut [u8]> {
     if cfg!(feature = "link-user-img") {
         extern "C" {
             fn _user_img_start();
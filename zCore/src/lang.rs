@@ -1,6 +1,7 @@
 // Rust language features implementations
 
 use core::panic::PanicInfo;
+use kernel_hal::debug::{backtrace, parse_build_id, Markup};
 use log::*;
 
 #[panic_handler]
@@ -8,6 +9,7 @@ fn panic(info: &PanicInfo) -> ! {
     println!("\n\npanic cpu={}", kernel_hal::cpu::cpu_id());
     println!("\n\n{info}");
     error!("\n\n{info}");
+    print_backtrace();
 
     if cfg!(feature = "baremetal-test") {
         kernel_hal::cpu::reset();
@@ -17,3 +19,48 @@ fn panic(info: &PanicInfo) -> ! {
         }
     }
 }
+
+/// Print the backtrace in the symbolizer markup, which the host tools
+/// symbolize with the kernel ELF file of the same build ID.
+fn print_backtrace() {
+    extern "C" {
+        fn stext();
+        fn etext();
+        fn sbuild_id();
+        fn ebuild_id();
+    }
+    let build_id = unsafe {
+        let len = ebuild_id as usize - sbuild_id as usize;
+        core::slice::from_raw_parts(sbuild_id as *const u8, len)
+    };
+    println!("{}", Markup::Reset);
+    println!(
+        "{}",
+        Markup::Module {
+            id: 0,
+            name: "zcore",
+            build_id: parse_build_id(build_id).unwrap_or_default(),
+        }
+    );
+    // the kernel is linked at where it runs
+    println!(
+        "{}",
+        Markup::Mmap {
+            start: stext as usize,
+            size: etext as usize - stext as usize,
+            module: 0,
+            flags: "rx",
+            module_addr: stext as usize,
+        }
+    );
+    for (frame, pc) in backtrace().into_iter().enumerate() {
+        println!(
+            "{}",
+            Markup::Backtrace {
+                frame,
+                pc,
+                is_return_address: true,
+            }
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use core::fmt;
+use core::fmt::{self, Write};
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
 /// Initialize logging with the default max log level (WARN).
@@ -114,7 +114,43 @@ impl Log for SimpleLogger {
             info = with_color!(ColorCode::White, "{cpu_id} {pid}:{tid} {target}]"),
             data = with_color!(args_color, "{args}", args = record.args()),
         ));
+        // also keep it in the kernel log for debuglog readers and /dev/kmsg
+        let mut buf = RecordBuf::new();
+        let _ = write!(buf, "{}: {}", target, record.args());
+        zircon_object::debuglog::write_kernel(level.into(), buf.as_str());
     }
 
     fn flush(&self) {}
 }
+
+/// A buffer on the stack to format a kernel log record, which does not allocate.
+struct RecordBuf {
+    buf: [u8; zircon_object::debuglog::DLOG_MAX_DATA],
+    len: usize,
+}
+
+impl RecordBuf {
+    fn new() -> Self {
+        Self {
+            buf: [0; zircon_object::debuglog::DLOG_MAX_DATA],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // always valid since it is truncated at char boundaries
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl Write for RecordBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = s.len().min(self.buf.len() - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
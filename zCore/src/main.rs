@@ -3,6 +3,7 @@
 #![no_main]
 #![feature(naked_functions, asm_sym, asm_const)]
 #![feature(default_alloc_error_handler)]
+#![cfg_attr(target_arch = "loongarch64", feature(asm_experimental_arch))]
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
@@ -23,6 +24,8 @@ mod handler;
 mod memory;
 mod platform;
 mod utils;
+#[cfg(all(feature = "zircon", not(feature = "libos")))]
+mod zbi;
 
 static STARTED: AtomicBool = AtomicBool::new(false);
 
@@ -33,8 +36,19 @@ fn primary_main(config: kernel_hal::KernelConfig) {
     let options = utils::boot_options();
     logging::set_max_level(&options.log_level);
     info!("Boot options: {:#?}", options);
-    memory::init_frame_allocator(&kernel_hal::mem::free_pmem_regions());
+    let regions = kernel_hal::mem::free_pmem_regions();
+    #[cfg(all(feature = "zircon", not(feature = "libos")))]
+    let regions = {
+        zbi::dump();
+        zbi::filter_pmem_regions(regions)
+    };
+    memory::init_frame_allocator(&regions);
     kernel_hal::primary_init();
+    #[cfg(feature = "gdbstub")]
+    if kernel_hal::gdbstub::init() {
+        // wait for GDB to attach before running anything
+        kernel_hal::gdbstub::breakpoint();
+    }
     STARTED.store(true, Ordering::SeqCst);
     cfg_if! {
         if #[cfg(all(feature = "linux", feature = "zircon"))] {
@@ -43,6 +57,11 @@ fn primary_main(config: kernel_hal::KernelConfig) {
             let args = options.root_proc.split('?').map(Into::into).collect(); // parse "arg0?arg1?arg2"
             let envs = alloc::vec!["PATH=/usr/sbin:/usr/bin:/sbin:/bin".into()];
             let rootfs = fs::rootfs();
+            let rootfs = if options.root_overlay {
+                fs::overlay(rootfs)
+            } else {
+                rootfs
+            };
             let proc = zcore_loader::linux::run(args, envs, rootfs);
             utils::wait_for_exit(Some(proc))
         } else if #[cfg(feature = "zircon")] {
@@ -55,7 +74,7 @@ fn primary_main(config: kernel_hal::KernelConfig) {
     }
 }
 
-#[cfg(not(any(feature = "libos", target_arch = "aarch64")))]
+#[cfg(not(any(feature = "libos", target_arch = "loongarch64")))]
 fn secondary_main() -> ! {
     while !STARTED.load(Ordering::SeqCst) {
         core::hint::spin_loop();
@@ -15,6 +15,9 @@ type FrameAlloc = bitmap_allocator::BitAlloc1M; // max 4G
 #[cfg(target_arch = "aarch64")]
 type FrameAlloc = bitmap_allocator::BitAlloc1M; // max 4G
 
+#[cfg(target_arch = "loongarch64")]
+type FrameAlloc = bitmap_allocator::BitAlloc1M; // max 4G
+
 const PAGE_SIZE: usize = 4096;
 
 /// Global physical frame allocator
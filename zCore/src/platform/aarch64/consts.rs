@@ -2,6 +2,12 @@
 
 pub const KERNEL_HEAP_SIZE: usize = 16 * 1024 * 1024; // 32 MB
 
+/// The maximum number of CPUs booted, as `cpu_id()` is `Aff0` in 2 bits.
+pub const MAX_CPU_NUM: usize = 4;
+
+/// The pages of the boot stack of each secondary CPU.
+pub const STACK_PAGES_PER_CPU: usize = 8;
+
 #[inline]
 pub fn phys_memory_base() -> usize {
     kernel_hal::arch::config::PHYS_MEMORY_BASE
@@ -1,3 +1,5 @@
+use super::consts::{MAX_CPU_NUM, STACK_PAGES_PER_CPU};
+use core::sync::atomic::{AtomicBool, Ordering};
 use kernel_hal::KernelConfig;
 use rayboot::Aarch64BootInfo;
 core::arch::global_asm!(include_str!("space.s"));
@@ -24,6 +26,132 @@ extern "C" fn rust_main(boot_info: &'static Aarch64BootInfo) -> ! {
         gic_base: boot_info.gic_base,
         phys_to_virt_offset: boot_info.offset,
     };
+    boot_secondary_cpus(boot_info.offset);
     crate::primary_main(config);
     unreachable!()
 }
+
+/// The system registers of the primary CPU, and the stack, passed to the
+/// secondary CPU being booted, whose MMU is off.
+#[repr(C)]
+struct SecondaryBootArgs {
+    mair: u64,
+    tcr: u64,
+    ttbr0: u64,
+    ttbr1: u64,
+    sctlr: u64,
+    stack_top: u64,
+    entry: u64,
+}
+
+static mut SECONDARY_BOOT_ARGS: SecondaryBootArgs = SecondaryBootArgs {
+    mair: 0,
+    tcr: 0,
+    ttbr0: 0,
+    ttbr1: 0,
+    sctlr: 0,
+    stack_top: 0,
+    entry: 0,
+};
+
+/// Set by the secondary CPU being booted once it leaves its boot arguments.
+static SECONDARY_BOOTED: AtomicBool = AtomicBool::new(false);
+
+const STACK_LEN_PER_CPU: usize = 4096 * STACK_PAGES_PER_CPU;
+
+static mut SECONDARY_BOOT_STACK: [[u8; STACK_LEN_PER_CPU]; MAX_CPU_NUM] =
+    [[0; STACK_LEN_PER_CPU]; MAX_CPU_NUM];
+
+const PSCI_CPU_ON: usize = 0xc400_0003;
+const PSCI_SUCCESS: isize = 0;
+const PSCI_ALREADY_ON: isize = -4;
+
+/// Boot the secondary CPUs one by one with PSCI `CPU_ON`, which all share the
+/// page tables of the primary CPU, where the kernel is identity mapped by
+/// `TTBR0` until the kernel page table is activated.
+fn boot_secondary_cpus(phys_to_virt_offset: usize) {
+    let args = unsafe { &mut SECONDARY_BOOT_ARGS };
+    let mpidr: u64;
+    unsafe {
+        core::arch::asm!(
+            "mrs {mpidr}, mpidr_el1
+             mrs {mair}, mair_el1
+             mrs {tcr}, tcr_el1
+             mrs {ttbr0}, ttbr0_el1
+             mrs {ttbr1}, ttbr1_el1
+             mrs {sctlr}, sctlr_el1",
+            mpidr = out(reg) mpidr,
+            mair = out(reg) args.mair,
+            tcr = out(reg) args.tcr,
+            ttbr0 = out(reg) args.ttbr0,
+            ttbr1 = out(reg) args.ttbr1,
+            sctlr = out(reg) args.sctlr,
+        );
+    }
+    let primary_id = (mpidr & 0xff) as usize;
+    args.entry = secondary_rust_main as usize as u64;
+    let args_paddr = args as *const _ as usize - phys_to_virt_offset;
+    let entry_paddr = secondary_start as usize - phys_to_virt_offset;
+    for cpu_id in (0..MAX_CPU_NUM).filter(|&id| id != primary_id) {
+        let stack = unsafe { &SECONDARY_BOOT_STACK[cpu_id] };
+        args.stack_top = (stack.as_ptr() as usize + STACK_LEN_PER_CPU) as u64;
+        SECONDARY_BOOTED.store(false, Ordering::SeqCst);
+        match psci_cpu_on(cpu_id, entry_paddr, args_paddr) {
+            PSCI_SUCCESS => {
+                while !SECONDARY_BOOTED.load(Ordering::Acquire) {
+                    core::hint::spin_loop();
+                }
+            }
+            PSCI_ALREADY_ON => continue,
+            // no more CPUs
+            _ => break,
+        }
+    }
+}
+
+fn psci_cpu_on(mpidr: usize, entry: usize, context_id: usize) -> isize {
+    let ret: isize;
+    unsafe {
+        core::arch::asm!(
+            "hvc #0",
+            inlateout("x0") PSCI_CPU_ON => ret,
+            in("x1") mpidr,
+            in("x2") entry,
+            in("x3") context_id,
+        );
+    }
+    ret
+}
+
+/// The entry of secondary CPUs, at the physical address with the MMU off, and
+/// `x0` the physical address of [`SecondaryBootArgs`].
+#[naked]
+unsafe extern "C" fn secondary_start() -> ! {
+    core::arch::asm!(
+        "
+        mov     x1, #(3 << 20)
+        msr     cpacr_el1, x1
+        ldp     x1, x2, [x0]
+        msr     mair_el1, x1
+        msr     tcr_el1, x2
+        ldp     x1, x2, [x0, #16]
+        msr     ttbr0_el1, x1
+        msr     ttbr1_el1, x2
+        dsb     nsh
+        tlbi    vmalle1
+        dsb     nsh
+        isb
+        ldr     x1, [x0, #32]
+        msr     sctlr_el1, x1
+        isb
+        ldp     x1, x2, [x0, #40]
+        mov     sp, x1
+        br      x2",
+        options(noreturn),
+    )
+}
+
+extern "C" fn secondary_rust_main() -> ! {
+    SECONDARY_BOOTED.store(true, Ordering::Release);
+    crate::secondary_main()
+}
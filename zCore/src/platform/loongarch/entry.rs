@@ -0,0 +1,65 @@
+use kernel_hal::KernelConfig;
+
+/// The cached direct mapped window, where the kernel is linked.
+const CACHED_WINDOW_BASE: usize = 0x9000_0000_0000_0000;
+const BOOT_STACK_SIZE: usize = 0x8000; // 32K
+
+#[link_section = ".bss.stack"]
+static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
+
+/// The kernel entry, in the direct address mode, with `a1` the physical
+/// address of the command line if `a0` is not 0.
+///
+/// # Safety
+///
+/// Naked function.
+#[naked]
+#[no_mangle]
+#[link_section = ".text.entry"]
+unsafe extern "C" fn _start() -> ! {
+    core::arch::asm!(
+        // DMW0: uncached at 0x8000..., DMW1: cached at 0x9000..., for PLV0
+        "li.d    $t0, 0x8000000000000001",
+        "csrwr   $t0, 0x180",
+        "li.d    $t0, 0x9000000000000011",
+        "csrwr   $t0, 0x181",
+        // jump to the address in DMW1
+        "li.d    $t0, {window}",
+        "pcaddi  $t1, 0",
+        "or      $t0, $t0, $t1",
+        "jirl    $zero, $t0, 0xc",
+        // PLV = 0, IE = 0, PG = 1, and coherent cached before paged
+        "li.w    $t0, 0xb0",
+        "csrwr   $t0, 0x0",
+        "la.abs  $sp, {stack}",
+        "li.d    $t0, {stack_size}",
+        "add.d   $sp, $sp, $t0",
+        "b       {main}",
+        window = const CACHED_WINDOW_BASE,
+        stack = sym BOOT_STACK,
+        stack_size = const BOOT_STACK_SIZE,
+        main = sym rust_main,
+        options(noreturn),
+    )
+}
+
+extern "C" fn rust_main(argc: usize, cmdline_paddr: usize) -> ! {
+    let cmdline = if argc != 0 && cmdline_paddr != 0 {
+        unsafe { c_str((CACHED_WINDOW_BASE + cmdline_paddr) as *const u8) }
+    } else {
+        ""
+    };
+    crate::primary_main(KernelConfig {
+        cmdline,
+        phys_to_virt_offset: CACHED_WINDOW_BASE,
+    });
+    unreachable!()
+}
+
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
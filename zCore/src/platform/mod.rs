@@ -11,6 +11,9 @@ cfg_if! {
     } else if #[cfg(target_arch = "aarch64")] {
         #[path = "aarch64/mod.rs"]
         mod arch;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        #[path = "loongarch/mod.rs"]
+        mod arch;
     }
 }
 
@@ -7,6 +7,9 @@ pub struct BootOptions {
     pub log_level: String,
     #[cfg(feature = "linux")]
     pub root_proc: String,
+    /// Overlay the rootfs with a tmpfs, keeping the image unchanged
+    #[cfg(feature = "linux")]
+    pub root_overlay: bool,
 }
 
 fn parse_cmdline(cmdline: &str) -> BTreeMap<&str, &str> {
@@ -48,16 +51,22 @@ pub fn boot_options() -> BootOptions {
                 log_level,
                 #[cfg(feature = "linux")]
                 root_proc: args[1..].join("?"),
+                #[cfg(feature = "linux")]
+                root_overlay: std::env::var("OVERLAY").map_or(false, |v| v == "on"),
             }
         } else {
             use alloc::string::ToString;
             let cmdline = kernel_hal::boot::cmdline();
+            #[cfg(feature = "zircon")]
+            let cmdline = crate::zbi::cmdline(cmdline);
             let options = parse_cmdline(&cmdline);
             BootOptions {
                 cmdline: cmdline.clone(),
                 log_level: options.get("LOG").unwrap_or(&"").to_string(),
                 #[cfg(feature = "linux")]
                 root_proc: options.get("ROOTPROC").unwrap_or(&"/bin/busybox?sh").to_string(),
+                #[cfg(feature = "linux")]
+                root_overlay: options.get("OVERLAY") == Some(&"on"),
             }
         }
     }
@@ -118,7 +127,7 @@ pub fn wait_for_exit(proc: Option<Arc<Process>>) -> ! {
     kernel_hal::timer::timer_enable();
     info!("executor run!");
     loop {
-        let has_task = executor::run_until_idle();
+        let has_task = kernel_hal::thread::run_until_idle();
         if !has_task && cfg!(feature = "baremetal-test") {
             proc.map(check_exit_code);
             kernel_hal::cpu::reset();
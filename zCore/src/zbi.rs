@@ -0,0 +1,91 @@
+//! Items of the boot ZBI consumed by the kernel, when zCore boots a
+//! `fuchsia.zbi` in place of Zircon.
+//!
+//! The ZBI is the init RAM disk. `CMDLINE` items are merged into the boot
+//! options and `MEM_CONFIG` limits the memory given to the frame allocator.
+//! `CRASHLOG` and `STORAGE_RAMDISK` are left to the userspace, which receives
+//! the ZBI without the kernel item from [`zcore_loader::zircon::run_userboot`].
+
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+use kernel_hal::PhysAddr;
+use zircon_object::util::zbi::{Zbi, ZbiType, ZBI_MEM_RANGE_RAM};
+
+/// The boot ZBI, or `None` if the init RAM disk is not a ZBI.
+fn boot_zbi() -> Option<Zbi<'static>> {
+    let data = crate::fs::init_ram_disk()?;
+    match Zbi::parse(data) {
+        Ok(zbi) => Some(zbi),
+        Err(e) => {
+            warn!("the init RAM disk is not a valid ZBI: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Prints the items of the boot ZBI.
+pub fn dump() {
+    let zbi = match boot_zbi() {
+        Some(zbi) => zbi,
+        None => return,
+    };
+    for item in zbi.items() {
+        match item {
+            Ok(item) => match item.type_() {
+                Some(ZbiType::StorageRamdisk) => {
+                    info!("ZBI ramdisk: {:#x} bytes", item.header.extra)
+                }
+                Some(ZbiType::Crashlog) => {
+                    info!("ZBI crashlog: {:#x} bytes", item.payload.len())
+                }
+                _ => debug!(
+                    "ZBI item {:#x}: {:#x} bytes",
+                    item.header.type_,
+                    item.payload.len()
+                ),
+            },
+            Err(e) => warn!("malformed ZBI item: {:?}", e),
+        }
+    }
+}
+
+/// Prepends the `CMDLINE` items to the command line from the bootloader.
+///
+/// Zircon separates the options by spaces, and zCore by colons.
+pub fn cmdline(bootloader: String) -> String {
+    let zbi = match boot_zbi() {
+        Some(zbi) => zbi.cmdline(),
+        None => return bootloader,
+    };
+    let mut cmdline = zbi.split_whitespace().collect::<Vec<_>>().join(":");
+    // options from the bootloader override the ones in the image
+    if !bootloader.is_empty() {
+        if !cmdline.is_empty() {
+            cmdline.push(':');
+        }
+        cmdline.push_str(&bootloader);
+    }
+    cmdline
+}
+
+/// Keeps only the parts of `regions` which are RAM in the `MEM_CONFIG` item.
+pub fn filter_pmem_regions(regions: Vec<Range<PhysAddr>>) -> Vec<Range<PhysAddr>> {
+    let ram = match boot_zbi().and_then(|zbi| zbi.find(ZbiType::MemConfig)) {
+        Some(item) => item
+            .mem_ranges()
+            .filter(|range| range.type_ == ZBI_MEM_RANGE_RAM)
+            .map(|range| range.paddr as usize..(range.paddr + range.length) as usize)
+            .collect::<Vec<_>>(),
+        None => return regions,
+    };
+    regions
+        .iter()
+        .flat_map(|region| {
+            ram.iter().filter_map(move |ram| {
+                let start = region.start.max(ram.start);
+                let end = region.end.min(ram.end);
+                (start < end).then_some(start..end)
+            })
+        })
+        .collect()
+}
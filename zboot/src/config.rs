@@ -0,0 +1,79 @@
+//! The config file of zboot, one `key=value` per line, `#` starts a comment.
+
+/// Where the config file is placed in the EFI system partition.
+pub const CONFIG_PATH: &str = r"\EFI\Boot\zboot.conf";
+
+/// Options read from the config file.
+#[derive(Debug)]
+pub struct Config<'a> {
+    /// The virtual address of the bottom of the kernel stack.
+    pub kernel_stack_address: u64,
+    /// The size of the kernel stack in 4KiB pages.
+    pub kernel_stack_size: u64,
+    /// The virtual address where the physical memory is mapped.
+    pub physical_memory_offset: u64,
+    /// The path of the kernel ELF.
+    pub kernel_path: &'a str,
+    /// The path of the initramfs.
+    pub initramfs: Option<&'a str>,
+    /// The resolution of the graphic output, keeps the current mode if not set.
+    pub resolution: Option<(usize, usize)>,
+    /// The kernel command line.
+    pub cmdline: &'a str,
+}
+
+impl Default for Config<'_> {
+    fn default() -> Self {
+        Self {
+            kernel_stack_address: 0xFFFF_FF80_0000_0000,
+            kernel_stack_size: 512,
+            physical_memory_offset: 0xFFFF_8000_0000_0000,
+            kernel_path: r"\EFI\zCore\zcore.elf",
+            initramfs: None,
+            resolution: None,
+            cmdline: "",
+        }
+    }
+}
+
+impl<'a> Config<'a> {
+    /// Parses the content of the config file, unknown keys are ignored.
+    pub fn parse(content: &'a str) -> Self {
+        let mut config = Self::default();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => panic!("invalid line in config: {line}"),
+            };
+            match key.trim() {
+                "kernel_stack_address" => config.kernel_stack_address = parse_int(value),
+                "kernel_stack_size" => config.kernel_stack_size = parse_int(value),
+                "physical_memory_offset" => config.physical_memory_offset = parse_int(value),
+                "kernel_path" => config.kernel_path = value,
+                "initramfs" => config.initramfs = Some(value),
+                "resolution" => {
+                    let (width, height) = value.split_once('x').expect("invalid resolution");
+                    config.resolution =
+                        Some((parse_int(width) as usize, parse_int(height) as usize));
+                }
+                "cmdline" => config.cmdline = value,
+                _ => warn!("unknown config key: {key}"),
+            }
+        }
+        config
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal integer.
+fn parse_int(s: &str) -> u64 {
+    let s = s.trim();
+    let res = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.unwrap_or_else(|_| panic!("invalid integer in config: {s}"))
+}
@@ -0,0 +1,70 @@
+//! Just enough of ELF64 to load the kernel.
+
+/// `PT_LOAD`
+const PT_LOAD: u32 = 1;
+/// The segment is executable.
+pub const PF_X: u32 = 1;
+/// The segment is writable.
+pub const PF_W: u32 = 2;
+
+/// A little-endian ELF64 file.
+pub struct Elf<'a> {
+    data: &'a [u8],
+}
+
+/// A loadable segment.
+pub struct Segment<'a> {
+    pub vaddr: u64,
+    pub mem_size: u64,
+    pub flags: u32,
+    /// The initialized part, the rest up to `mem_size` is zero.
+    pub data: &'a [u8],
+}
+
+impl<'a> Elf<'a> {
+    /// Checks the header, returns `None` if it is not a little-endian ELF64 file.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 64 || data[..4] != *b"\x7fELF" || data[4] != 2 || data[5] != 1 {
+            return None;
+        }
+        Some(Self { data })
+    }
+
+    /// The entry point.
+    pub fn entry(&self) -> u64 {
+        self.u64_at(24)
+    }
+
+    /// Iterates over the `PT_LOAD` segments.
+    pub fn segments(&self) -> impl Iterator<Item = Segment<'a>> + '_ {
+        let phoff = self.u64_at(32) as usize;
+        let phentsize = self.u16_at(54) as usize;
+        let phnum = self.u16_at(56) as usize;
+        let data = self.data;
+        (0..phnum)
+            .map(move |i| phoff + i * phentsize)
+            .filter(move |&ph| self.u32_at(ph) == PT_LOAD)
+            .map(move |ph| {
+                let offset = self.u64_at(ph + 8) as usize;
+                let file_size = self.u64_at(ph + 32) as usize;
+                Segment {
+                    vaddr: self.u64_at(ph + 16),
+                    mem_size: self.u64_at(ph + 40),
+                    flags: self.u32_at(ph + 4),
+                    data: &data[offset..offset + file_size],
+                }
+            })
+    }
+
+    fn u16_at(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap())
+    }
+}
@@ -0,0 +1,48 @@
+//! The boot information passed from zboot to the kernel.
+//!
+//! zboot loads the kernel ELF, maps the physical memory at a fixed offset and
+//! calls the kernel entry `extern "C" fn _start(&'static BootInfo) -> !` on a
+//! new stack, with the page table of the firmware extended by these mappings.
+
+#![no_std]
+#![deny(missing_docs)]
+
+pub use uefi::proto::console::gop::ModeInfo;
+pub use uefi::table::boot::MemoryDescriptor;
+
+/// Information collected from the boot services before exiting them.
+///
+/// Addresses are physical, and the memory they point to is `LOADER_DATA`, which
+/// is not reported as free in `memory_map`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct BootInfo {
+    /// The memory map after exiting the boot services.
+    pub memory_map: &'static [&'static MemoryDescriptor],
+    /// The virtual address where the physical memory is mapped.
+    pub physical_memory_offset: u64,
+    /// The graphic mode and the framebuffer.
+    pub graphic_info: GraphicInfo,
+    /// The address of the ACPI 2.0 RSDP, or 0 if not found.
+    pub acpi2_rsdp_addr: u64,
+    /// The address of the SMBIOS entry point, or 0 if not found.
+    pub smbios_addr: u64,
+    /// The address of the initramfs, or 0 if not specified.
+    pub initramfs_addr: u64,
+    /// The size of the initramfs in bytes.
+    pub initramfs_size: u64,
+    /// The kernel command line.
+    pub cmdline: &'static str,
+}
+
+/// The graphic output set up by the bootloader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GraphicInfo {
+    /// The current graphic mode.
+    pub mode: ModeInfo,
+    /// The physical address of the framebuffer.
+    pub fb_addr: u64,
+    /// The size of the framebuffer in bytes.
+    pub fb_size: u64,
+}
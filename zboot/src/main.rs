@@ -0,0 +1,206 @@
+//! zboot, the UEFI bootloader of zCore on x86_64.
+//!
+//! Reads `\EFI\Boot\zboot.conf`, loads the kernel and the initramfs from the
+//! boot partition, sets the graphic mode, and jumps to the kernel with a
+//! [`BootInfo`] after exiting the boot services.
+
+#![no_std]
+#![no_main]
+#![feature(abi_efiapi)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod config;
+mod elf;
+mod page_table;
+
+use alloc::{boxed::Box, vec::Vec};
+use core::mem::{size_of, MaybeUninit};
+use uefi::{
+    prelude::*,
+    proto::{
+        console::gop::GraphicsOutput,
+        loaded_image::LoadedImage,
+        media::{
+            file::{Directory, File, FileAttribute, FileInfo, FileMode, FileType},
+            fs::SimpleFileSystem,
+        },
+    },
+    table::{
+        boot::MemoryDescriptor,
+        cfg::{ACPI2_GUID, SMBIOS_GUID},
+    },
+    CStr16, Guid,
+};
+use zboot::{BootInfo, GraphicInfo};
+
+use config::{Config, CONFIG_PATH};
+use elf::Elf;
+use page_table::{alloc_pages, UefiFrameAllocator};
+
+/// The physical memory below 4GiB is always mapped, for the local APIC and other devices.
+const MIN_PHYSICAL_MEMORY_END: u64 = 0x1_0000_0000;
+
+#[entry]
+fn efi_main(image: Handle, mut st: SystemTable<Boot>) -> Status {
+    uefi_services::init(&mut st).expect("failed to initialize utilities");
+    info!("zboot is running");
+    let bs = st.boot_services();
+    let mut root = open_root(bs, image);
+
+    let config =
+        core::str::from_utf8(load_file(bs, &mut root, CONFIG_PATH)).expect("config is not UTF-8");
+    let config = Config::parse(config);
+    info!("config: {config:#x?}");
+
+    let graphic_info = init_graphic(bs, config.resolution);
+    let acpi2_rsdp_addr = find_config_table(&st, ACPI2_GUID);
+    let smbios_addr = find_config_table(&st, SMBIOS_GUID);
+
+    let elf = load_file(bs, &mut root, config.kernel_path);
+    let elf = Elf::parse(elf).expect("the kernel is not an ELF64 file");
+    let (initramfs_addr, initramfs_size) = match config.initramfs {
+        Some(path) => {
+            let initramfs = load_file(bs, &mut root, path);
+            (initramfs.as_ptr() as u64, initramfs.len() as u64)
+        }
+        None => (0, 0),
+    };
+
+    let mut frames = UefiFrameAllocator(bs);
+    let mut pt = unsafe { page_table::current() };
+    let entry = page_table::map_elf(&elf, &mut pt, &mut frames);
+    let stack_top = page_table::map_stack(
+        config.kernel_stack_address,
+        config.kernel_stack_size,
+        &mut pt,
+        &mut frames,
+    );
+    let end = physical_memory_end(bs).max(MIN_PHYSICAL_MEMORY_END);
+    page_table::map_physical_memory(config.physical_memory_offset, end, &mut pt, &mut frames);
+    page_table::finish();
+    info!("kernel entry: {entry:#x}, stack top: {stack_top:#x}");
+
+    // nothing can be allocated after exiting the boot services
+    let mmap_size = bs.memory_map_size().map_size + 8 * size_of::<MemoryDescriptor>();
+    let mmap_buf = new_buffer(bs, mmap_size);
+    let mut memory_map = Vec::with_capacity(mmap_size / size_of::<MemoryDescriptor>());
+    let boot_info = Box::leak(Box::new(MaybeUninit::<BootInfo>::uninit()));
+
+    drop(root);
+    info!("exit boot services");
+    let (_rt, descs) = st
+        .exit_boot_services(image, mmap_buf)
+        .expect("failed to exit boot services");
+    memory_map.extend(descs);
+    let boot_info = boot_info.write(BootInfo {
+        memory_map: memory_map.leak(),
+        physical_memory_offset: config.physical_memory_offset,
+        graphic_info,
+        acpi2_rsdp_addr,
+        smbios_addr,
+        initramfs_addr,
+        initramfs_size,
+        cmdline: config.cmdline,
+    });
+    unsafe { jump_to_entry(boot_info, entry, stack_top) }
+}
+
+/// Opens the root directory of the partition zboot is loaded from.
+fn open_root(bs: &BootServices, image: Handle) -> Directory {
+    let loaded_image = bs
+        .handle_protocol::<LoadedImage>(image)
+        .expect("failed to get LoadedImage");
+    let device = unsafe { &*loaded_image.get() }.device();
+    let sfs = bs
+        .handle_protocol::<SimpleFileSystem>(device)
+        .expect("failed to get SimpleFileSystem");
+    unsafe { &mut *sfs.get() }
+        .open_volume()
+        .expect("failed to open the volume")
+}
+
+/// Allocates a zeroed buffer which is valid after exiting the boot services.
+fn new_buffer(bs: &BootServices, size: usize) -> &'static mut [u8] {
+    let pages = (size + 0xfff) / 0x1000;
+    let addr = alloc_pages(bs, pages.max(1));
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, size) }
+}
+
+/// Reads the whole file at `path` into a new buffer.
+fn load_file(bs: &BootServices, root: &mut Directory, path: &str) -> &'static mut [u8] {
+    info!("loading {path}");
+    let mut name = [0u16; 256];
+    let name = CStr16::from_str_with_buf(path, &mut name).expect("invalid file path");
+    let file = root
+        .open(name, FileMode::Read, FileAttribute::empty())
+        .unwrap_or_else(|_| panic!("failed to open {path}"));
+    let mut file = match file.into_type().expect("failed to get the file type") {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => panic!("{path} is a directory"),
+    };
+    // `FileInfo` needs an aligned buffer
+    let info = new_buffer(bs, 0x1000);
+    let size = file
+        .get_info::<FileInfo>(info)
+        .expect("failed to get the file info")
+        .file_size() as usize;
+    let buf = new_buffer(bs, size);
+    let len = file.read(buf).expect("failed to read the file");
+    &mut buf[..len]
+}
+
+/// Sets the resolution if specified, returns the current graphic mode.
+fn init_graphic(bs: &BootServices, resolution: Option<(usize, usize)>) -> GraphicInfo {
+    let gop = bs
+        .locate_protocol::<GraphicsOutput>()
+        .expect("failed to get GraphicsOutput");
+    let gop = unsafe { &mut *gop.get() };
+    if let Some(resolution) = resolution {
+        let mode = gop
+            .modes()
+            .find(|mode| mode.info().resolution() == resolution)
+            .expect("graphic mode not found");
+        gop.set_mode(&mode).expect("failed to set the graphic mode");
+    }
+    let mut fb = gop.frame_buffer();
+    GraphicInfo {
+        mode: gop.current_mode_info(),
+        fb_addr: fb.as_mut_ptr() as u64,
+        fb_size: fb.size() as u64,
+    }
+}
+
+/// Returns the address of the configuration table, or 0 if not found.
+fn find_config_table(st: &SystemTable<Boot>, guid: Guid) -> u64 {
+    st.config_table()
+        .iter()
+        .find(|entry| entry.guid == guid)
+        .map_or(0, |entry| entry.address as u64)
+}
+
+/// Returns the end of the highest region in the memory map.
+fn physical_memory_end(bs: &BootServices) -> u64 {
+    let size = bs.memory_map_size().map_size + 8 * size_of::<MemoryDescriptor>();
+    let (_key, descs) = bs
+        .memory_map(new_buffer(bs, size))
+        .expect("failed to get the memory map");
+    descs
+        .map(|desc| desc.phys_start + desc.page_count * 0x1000)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Switches to the kernel stack and calls `_start(boot_info)`.
+unsafe fn jump_to_entry(boot_info: *const BootInfo, entry: u64, stack_top: u64) -> ! {
+    core::arch::asm!(
+        "mov rsp, {stack_top}",
+        "call {entry}",
+        stack_top = in(reg) stack_top,
+        entry = in(reg) entry,
+        in("rdi") boot_info,
+        options(noreturn),
+    )
+}
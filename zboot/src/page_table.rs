@@ -0,0 +1,127 @@
+//! Extends the page table of the firmware with the mappings the kernel needs.
+//!
+//! The firmware identity maps the physical memory, so the page table is
+//! modified in place and stays in use when jumping to the kernel.
+
+use uefi::table::boot::{AllocateType, BootServices, MemoryType};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3, Efer, EferFlags};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size2MiB, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::elf::{Elf, PF_W, PF_X};
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+/// Allocates zeroed `LOADER_DATA` pages, which the kernel does not reuse.
+pub fn alloc_pages(bs: &BootServices, count: usize) -> u64 {
+    let addr = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, count)
+        .expect("failed to allocate pages");
+    unsafe { core::ptr::write_bytes(addr as *mut u8, 0, count * PAGE_SIZE as usize) };
+    addr
+}
+
+/// Allocates page table frames from the boot services.
+pub struct UefiFrameAllocator<'a>(pub &'a BootServices);
+
+unsafe impl FrameAllocator<Size4KiB> for UefiFrameAllocator<'_> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let addr = alloc_pages(self.0, 1);
+        Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+/// Returns the page table in use and makes it writable.
+///
+/// # Safety
+///
+/// The page table must not be referenced anywhere else.
+pub unsafe fn current() -> OffsetPageTable<'static> {
+    // the firmware may map its page tables read-only
+    Cr0::update(|f| f.remove(Cr0Flags::WRITE_PROTECT));
+    Efer::update(|f| f.insert(EferFlags::NO_EXECUTE_ENABLE));
+    let p4 = Cr3::read().0.start_address().as_u64() as *mut PageTable;
+    OffsetPageTable::new(&mut *p4, VirtAddr::new(0))
+}
+
+/// Restores the write protection after the page table is modified.
+pub fn finish() {
+    unsafe { Cr0::update(|f| f.insert(Cr0Flags::WRITE_PROTECT)) };
+}
+
+/// Loads and maps the segments of the kernel, returns the entry point.
+pub fn map_elf(elf: &Elf, pt: &mut OffsetPageTable, frames: &mut UefiFrameAllocator) -> u64 {
+    for seg in elf.segments() {
+        let mut flags = PageTableFlags::PRESENT;
+        if seg.flags & PF_W != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if seg.flags & PF_X == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        let start = seg.vaddr & !(PAGE_SIZE - 1);
+        let end = seg.vaddr + seg.mem_size;
+        for vaddr in (start..end).step_by(PAGE_SIZE as usize) {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(vaddr));
+            // segments may share a page at their boundary
+            let paddr = match pt.translate_addr(page.start_address()) {
+                Some(paddr) => paddr.as_u64(),
+                None => {
+                    let frame = frames.allocate_frame().unwrap();
+                    unsafe { pt.map_to(page, frame, flags, frames) }
+                        .expect("failed to map the kernel")
+                        .flush();
+                    frame.start_address().as_u64()
+                }
+            };
+            // copy the part of the segment data in this page
+            let data_start = vaddr.max(seg.vaddr);
+            let data_end = (vaddr + PAGE_SIZE).min(seg.vaddr + seg.data.len() as u64);
+            if data_start < data_end {
+                let src =
+                    &seg.data[(data_start - seg.vaddr) as usize..(data_end - seg.vaddr) as usize];
+                let dst = (paddr + data_start - vaddr) as *mut u8;
+                unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) };
+            }
+        }
+    }
+    elf.entry()
+}
+
+/// Maps the kernel stack, returns the top of it.
+pub fn map_stack(
+    address: u64,
+    pages: u64,
+    pt: &mut OffsetPageTable,
+    frames: &mut UefiFrameAllocator,
+) -> u64 {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for i in 0..pages {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(address + i * PAGE_SIZE));
+        let frame = frames.allocate_frame().unwrap();
+        unsafe { pt.map_to(page, frame, flags, frames) }
+            .expect("failed to map the kernel stack")
+            .flush();
+    }
+    address + pages * PAGE_SIZE
+}
+
+/// Maps the physical memory `[0, end)` at `offset` with 2MiB pages.
+pub fn map_physical_memory(
+    offset: u64,
+    end: u64,
+    pt: &mut OffsetPageTable,
+    frames: &mut UefiFrameAllocator,
+) {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for paddr in (0..end).step_by(Size2MiB::SIZE as usize) {
+        let frame = PhysFrame::<Size2MiB>::containing_address(PhysAddr::new(paddr));
+        let page = Page::<Size2MiB>::containing_address(VirtAddr::new(offset + paddr));
+        unsafe { pt.map_to(page, frame, flags, frames) }
+            .expect("failed to map the physical memory")
+            .flush();
+    }
+}
@@ -1,17 +1,34 @@
 //! Objects for Kernel Debuglog.
+//!
+//! Records from the kernel `log` macros and from the userspace are kept in a
+//! ring buffer of [`DLOG_SIZE`] bytes. When it is full, the oldest records are
+//! overwritten, and the readers which have not read them count them as dropped.
 use {
     super::*,
     crate::object::*,
-    alloc::{sync::Arc, vec::Vec},
-    lazy_static::lazy_static,
+    alloc::{
+        sync::{Arc, Weak},
+        vec::Vec,
+    },
+    core::sync::atomic::{AtomicBool, Ordering},
     lock::Mutex,
 };
 
-lazy_static! {
-    static ref DLOG: Mutex<DlogBuffer> = Mutex::new(DlogBuffer {
-        buf: Vec::with_capacity(0x1000),
-    });
-}
+/// Size of the ring buffer of the kernel log.
+pub const DLOG_SIZE: usize = 128 * 1024;
+
+/// `ZX_LOG_FLAG_READABLE`, the created `DebugLog` is a reader.
+pub const FLAG_READABLE: u32 = 0x4000_0000;
+
+/// It does not allocate, so that the kernel can log before the heap is ready.
+static DLOG: Mutex<DlogBuffer> = Mutex::new(DlogBuffer::new());
+
+/// Readers to be signaled when a record is written.
+static READERS: Mutex<Vec<Weak<DebugLog>>> = Mutex::new(Vec::new());
+
+/// Set while changing the signals of the readers, so that the logs printed by
+/// the signal callbacks do not change them again.
+static NOTIFYING: AtomicBool = AtomicBool::new(false);
 
 /// Debuglog - Kernel debuglog
 ///
@@ -21,39 +38,158 @@ lazy_static! {
 pub struct DebugLog {
     base: KObjectBase,
     flags: u32,
-    read_offset: Mutex<usize>,
+    reader: Mutex<Reader>,
+}
+
+/// The position of a reader in the ring buffer.
+#[derive(Debug, Default)]
+struct Reader {
+    /// Sequence number of the next record to read.
+    seq: u64,
+    /// Offset of the next record, valid if it is not overwritten.
+    offset: usize,
+    /// Number of records overwritten before being read.
+    dropped: u64,
 }
 
 struct DlogBuffer {
-    /// Append only buffer
-    buf: Vec<u8>,
+    buf: [u8; DLOG_SIZE],
+    /// Offset of the oldest record, which increases without wrapping around.
+    head: usize,
+    /// Offset after the newest record.
+    tail: usize,
+    /// Sequence number of the oldest record.
+    head_seq: u64,
+    /// Sequence number of the next record to write.
+    next_seq: u64,
 }
 
 impl_kobject!(DebugLog);
 
+/// A record read from the kernel log.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Sequence number, starting from 0 at boot.
+    pub seq: u64,
+    /// Severity of the record.
+    pub severity: Severity,
+    /// Flags passed by the writer.
+    pub flags: u8,
+    /// Time since boot in nanoseconds.
+    pub timestamp: u64,
+    /// Koid of the writer process, 0 for the kernel.
+    pub pid: u64,
+    /// Koid of the writer thread, 0 for the kernel.
+    pub tid: u64,
+    /// The message.
+    pub data: Vec<u8>,
+}
+
 impl DebugLog {
-    /// Create a new `DebugLog`.
+    /// Create a new `DebugLog`, a reader starts from the oldest record.
     pub fn create(flags: u32) -> Arc<Self> {
-        Arc::new(DebugLog {
+        let reader = DLOG.lock().oldest();
+        let dlog = Arc::new(DebugLog {
             base: KObjectBase::new(),
             flags,
-            read_offset: Default::default(),
-        })
+            reader: Mutex::new(reader),
+        });
+        if flags & FLAG_READABLE != 0 {
+            let mut readers = READERS.lock();
+            readers.retain(|r| r.strong_count() > 0);
+            readers.push(Arc::downgrade(&dlog));
+            drop(readers);
+            dlog.update_signal();
+        }
+        dlog
     }
 
-    /// Read a log, return the actual read size.
+    /// Read a record in the format of `zx_debuglog_read`, return the actual read size.
     pub fn read(&self, buf: &mut [u8]) -> usize {
-        let mut offset = self.read_offset.lock();
-        let len = DLOG.lock().read_at(*offset, buf);
-        *offset += len;
-        len
+        self.read_raw(buf).map_or(0, |(len, _)| len)
+    }
+
+    /// Read a record, return `None` if all records are read.
+    #[allow(unsafe_code)]
+    pub fn read_record(&self) -> Option<Record> {
+        let mut buf = [0; DLOG_MAX_LEN];
+        let (_, seq) = self.read_raw(&mut buf)?;
+        let header = unsafe { (buf.as_ptr() as *const DlogHeader).read_unaligned() };
+        let data = &buf[HEADER_SIZE..HEADER_SIZE + header.datalen as usize];
+        Some(Record {
+            seq,
+            severity: header.severity,
+            flags: header.flags,
+            timestamp: header.timestamp,
+            pid: header.pid,
+            tid: header.tid,
+            data: data.to_vec(),
+        })
     }
 
     /// Write a log.
     pub fn write(&self, severity: Severity, flags: u32, tid: u64, pid: u64, data: &str) {
-        DLOG.lock()
-            .write(severity, flags | self.flags, tid, pid, data.as_bytes());
+        write_record(severity, flags | self.flags, tid, pid, data.as_bytes());
+    }
+
+    /// Number of records overwritten before this reader read them.
+    pub fn dropped(&self) -> u64 {
+        self.reader.lock().dropped
     }
+
+    fn read_raw(&self, buf: &mut [u8]) -> Option<(usize, u64)> {
+        let res = DLOG.lock().read(&mut self.reader.lock(), buf);
+        self.update_signal();
+        res
+    }
+
+    /// Asserts `READABLE` if there are records not read yet.
+    fn update_signal(&self) {
+        let guard = !NOTIFYING.swap(true, Ordering::Acquire);
+        let unread = || {
+            let seq = self.reader.lock().seq;
+            seq != next_seq()
+        };
+        if unread() {
+            self.base.signal_set(Signal::READABLE);
+        } else {
+            self.base.signal_clear(Signal::READABLE);
+            // a record may be written before the signal is cleared
+            if unread() {
+                self.base.signal_set(Signal::READABLE);
+            }
+        }
+        if guard {
+            NOTIFYING.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// Write a log of the kernel, such as the output of the `log` macros.
+pub fn write_kernel(severity: Severity, data: &str) {
+    write_record(severity, 0, 0, 0, data.as_bytes());
+}
+
+/// Sequence number of the next record to write.
+pub fn next_seq() -> u64 {
+    DLOG.lock().next_seq
+}
+
+fn write_record(severity: Severity, flags: u32, tid: u64, pid: u64, data: &[u8]) {
+    let data = &data[..data.len().min(DLOG_MAX_DATA)];
+    DLOG.lock().write(severity, flags, tid, pid, data);
+    if NOTIFYING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let readers = READERS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>();
+    for reader in readers {
+        reader.base.signal_set(Signal::READABLE);
+    }
+    NOTIFYING.store(false, Ordering::Release);
 }
 
 #[repr(C)]
@@ -71,7 +207,7 @@ struct DlogHeader {
 /// Log entry severity. Used for coarse filtering of log messages.
 #[allow(missing_docs)]
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Severity {
     Trace = 0x10,
     Debug = 0x20,
@@ -81,30 +217,91 @@ pub enum Severity {
     Fatal = 0x60,
 }
 
+impl From<log::Level> for Severity {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Severity::Error,
+            log::Level::Warn => Severity::Warning,
+            log::Level::Info => Severity::Info,
+            log::Level::Debug => Severity::Debug,
+            log::Level::Trace => Severity::Trace,
+        }
+    }
+}
+
 const HEADER_SIZE: usize = core::mem::size_of::<DlogHeader>();
 /// Max length of Dlog read buffer.
 pub const DLOG_MAX_LEN: usize = 256;
+/// Max length of the data in a record, the longer data is truncated.
+pub const DLOG_MAX_DATA: usize = DLOG_MAX_LEN - HEADER_SIZE;
 
 #[allow(unsafe_code)]
 impl DlogBuffer {
-    /// Read one record at offset.
-    #[allow(clippy::cast_ptr_alignment)]
-    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> usize {
+    const fn new() -> Self {
+        DlogBuffer {
+            buf: [0; DLOG_SIZE],
+            head: 0,
+            tail: 0,
+            head_seq: 0,
+            next_seq: 0,
+        }
+    }
+
+    /// A reader at the oldest record.
+    fn oldest(&self) -> Reader {
+        Reader {
+            seq: self.head_seq,
+            offset: self.head,
+            dropped: 0,
+        }
+    }
+
+    fn copy_out(&self, offset: usize, buf: &mut [u8]) {
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = self.buf[(offset + i) % DLOG_SIZE];
+        }
+    }
+
+    fn copy_in(&mut self, offset: usize, data: &[u8]) {
+        for (i, &b) in data.iter().enumerate() {
+            self.buf[(offset + i) % DLOG_SIZE] = b;
+        }
+    }
+
+    /// The size of the record at `offset`, including the padding.
+    fn wire_size_at(&self, offset: usize) -> usize {
+        let mut rollout = [0; 4];
+        self.copy_out(offset, &mut rollout);
+        (u32::from_ne_bytes(rollout) & 0xFFF) as usize
+    }
+
+    /// Read the next record of `reader`, return its size and sequence number.
+    fn read(&self, reader: &mut Reader, buf: &mut [u8]) -> Option<(usize, u64)> {
         assert!(buf.len() >= DLOG_MAX_LEN);
-        if offset == self.buf.len() {
-            return 0;
+        if reader.seq < self.head_seq {
+            reader.dropped += self.head_seq - reader.seq;
+            reader.seq = self.head_seq;
+            reader.offset = self.head;
         }
-        let header_buf = &self.buf[offset..offset + HEADER_SIZE];
-        buf[..HEADER_SIZE].copy_from_slice(header_buf);
-        let header = unsafe { &*(header_buf.as_ptr() as *const DlogHeader) };
-        let len = (header.rollout & 0xFFF) as usize;
-        buf[HEADER_SIZE..len].copy_from_slice(&self.buf[offset + HEADER_SIZE..offset + len]);
-        len
+        if reader.seq == self.next_seq {
+            return None;
+        }
+        let len = self.wire_size_at(reader.offset);
+        self.copy_out(reader.offset, &mut buf[..len]);
+        let seq = reader.seq;
+        reader.seq += 1;
+        reader.offset += len;
+        Some((len, seq))
     }
 
     fn write(&mut self, severity: Severity, flags: u32, tid: u64, pid: u64, data: &[u8]) {
         let wire_size = HEADER_SIZE + align_up_4(data.len());
         let size = HEADER_SIZE + data.len();
+        // overwrite the oldest records
+        while self.tail + wire_size - self.head > DLOG_SIZE {
+            self.head += self.wire_size_at(self.head);
+            self.head_seq += 1;
+        }
         let header = DlogHeader {
             rollout: ((size as u32) << 12) | (wire_size as u32),
             datalen: data.len() as u16,
@@ -115,12 +312,73 @@ impl DlogBuffer {
             tid,
         };
         let header_buf: [u8; HEADER_SIZE] = unsafe { core::mem::transmute(header) };
-        self.buf.extend(header_buf.iter());
-        self.buf.extend(data);
-        self.buf.extend(&[0u8; 4][..wire_size - size]);
+        let offset = self.tail;
+        self.copy_in(offset, &header_buf);
+        self.copy_in(offset + HEADER_SIZE, data);
+        self.copy_in(offset + size, &[0u8; 4][..wire_size - size]);
+        self.tail += wire_size;
+        self.next_seq += 1;
     }
 }
 
 fn align_up_4(x: usize) -> usize {
     (x + 3) & !3
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn ring_buffer() {
+        let mut dlog = Box::new(DlogBuffer::new());
+        let mut reader = dlog.oldest();
+        let mut buf = [0; DLOG_MAX_LEN];
+        assert_eq!(dlog.read(&mut reader, &mut buf), None);
+
+        let data = [b'x'; 100];
+        dlog.write(Severity::Info, 0, 1, 2, &data);
+        assert_eq!(
+            dlog.read(&mut reader, &mut buf),
+            Some((HEADER_SIZE + 100, 0))
+        );
+        assert_eq!(&buf[HEADER_SIZE..HEADER_SIZE + 100], &data[..]);
+        assert_eq!(dlog.read(&mut reader, &mut buf), None);
+
+        // fill the buffer several times, wrapping around
+        let count = DLOG_SIZE / (HEADER_SIZE + 100) * 3;
+        for i in 0..count {
+            let data = [i as u8; 100];
+            dlog.write(Severity::Warning, 0, 1, 2, &data);
+        }
+        let (len, seq) = dlog.read(&mut reader, &mut buf).unwrap();
+        assert_eq!(len, HEADER_SIZE + 100);
+        assert_eq!(seq, dlog.head_seq);
+        assert_eq!(reader.dropped, dlog.head_seq - 1);
+        assert_eq!(buf[HEADER_SIZE], (seq - 1) as u8);
+        let mut read = 1;
+        while dlog.read(&mut reader, &mut buf).is_some() {
+            read += 1;
+        }
+        assert_eq!(reader.dropped + read, count as u64);
+    }
+
+    #[test]
+    fn reader() {
+        let dlog = DebugLog::create(FLAG_READABLE);
+        // skip the existing records
+        while dlog.read_record().is_some() {}
+        assert!(!dlog.signal().contains(Signal::READABLE));
+        let seq = next_seq();
+        dlog.write(Severity::Info, 0, 3, 4, "hello");
+        assert!(dlog.signal().contains(Signal::READABLE));
+        let record = dlog.read_record().unwrap();
+        assert!(record.seq >= seq);
+        if record.seq == seq {
+            assert_eq!(record.data, b"hello");
+            assert_eq!((record.tid, record.pid), (3, 4));
+            assert_eq!(record.severity, Severity::Info);
+        }
+    }
+}
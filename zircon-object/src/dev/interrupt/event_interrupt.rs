@@ -12,12 +12,14 @@ struct EventInterruptInner {
 }
 
 impl EventInterrupt {
-    pub fn new(vector: usize) -> Box<Self> {
-        // TODO check vector is a vaild IRQ number
-        Box::new(EventInterrupt {
+    pub fn new(vector: usize) -> ZxResult<Box<Self>> {
+        if !interrupt::is_valid_irq(vector) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        Ok(Box::new(EventInterrupt {
             vector,
             inner: Default::default(),
-        })
+        }))
     }
 }
 
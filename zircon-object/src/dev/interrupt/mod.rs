@@ -1,16 +1,21 @@
 use {
     self::event_interrupt::*,
+    self::msi_interrupt::*,
     self::pci_interrupt::*,
     self::virtual_interrupt::*,
-    crate::dev::pci::IPciNode,
+    crate::dev::{pci::IPciNode, MsiAllocation},
     crate::object::*,
     crate::signal::*,
+    crate::vm::VmObject,
     alloc::{boxed::Box, sync::Arc},
     bitflags::bitflags,
+    kernel_hal::drivers::prelude::{IrqPolarity, IrqTriggerMode},
+    kernel_hal::interrupt,
     lock::Mutex,
 };
 
 mod event_interrupt;
+mod msi_interrupt;
 mod pci_interrupt;
 mod virtual_interrupt;
 
@@ -69,20 +74,31 @@ impl Interrupt {
     }
 
     /// Create a new physical interrupt.
+    ///
+    /// A level-triggered interrupt is masked once it fires, and unmasked when
+    /// it is waited for or acknowledged again.
     pub fn new_physical(vector: usize, options: InterruptOptions) -> ZxResult<Arc<Self>> {
         let mode = options.to_mode();
-        if mode != InterruptOptions::MODE_DEFAULT && mode != InterruptOptions::MODE_EDGE_HIGH {
-            unimplemented!();
-        }
+        let flags = if mode == InterruptOptions::MODE_LEVEL_LOW
+            || mode == InterruptOptions::MODE_LEVEL_HIGH
+        {
+            InterruptFlags::UNMASK_PREWAIT | InterruptFlags::MASK_POSTWAIT
+        } else {
+            InterruptFlags::empty()
+        };
         if options.contains(InterruptOptions::REMAP_IRQ) {
             warn!("Skip Interrupt.Remap");
         }
+        let trait_ = EventInterrupt::new(vector)?;
+        if let Some((tm, pol)) = mode.to_trigger()? {
+            interrupt::configure_irq(vector, tm, pol).map_err(|_| ZxError::NOT_SUPPORTED)?;
+        }
         let interrupt = Arc::new(Interrupt {
             base: KObjectBase::new(),
             has_vcpu: false,
-            flags: InterruptFlags::empty(),
+            flags,
             inner: Default::default(),
-            trait_: EventInterrupt::new(vector),
+            trait_,
         });
         let interrupt_clone = interrupt.clone();
         interrupt
@@ -109,6 +125,39 @@ impl Interrupt {
         Ok(interrupt)
     }
 
+    /// Create a new interrupt on the vector `msi_id` of an MSI allocation.
+    ///
+    /// `vmo` maps the MSI capability of the device at `offset`, or the MSI-X
+    /// table if `msix`. The interrupt is masked once it fires, and unmasked
+    /// when it is waited for or acknowledged again, if the device supports it.
+    pub fn new_msi(
+        allocation: Arc<MsiAllocation>,
+        msi_id: usize,
+        vmo: Arc<VmObject>,
+        offset: usize,
+        msix: bool,
+    ) -> ZxResult<Arc<Self>> {
+        let trait_ = MsiInterrupt::new(allocation, msi_id, vmo, offset, msix)?;
+        let flags = if trait_.maskable() {
+            InterruptFlags::UNMASK_PREWAIT | InterruptFlags::MASK_POSTWAIT
+        } else {
+            InterruptFlags::empty()
+        };
+        let interrupt = Arc::new(Interrupt {
+            base: KObjectBase::new(),
+            has_vcpu: false,
+            flags,
+            inner: Default::default(),
+            trait_,
+        });
+        let interrupt_clone = interrupt.clone();
+        interrupt
+            .trait_
+            .register_handler(Box::new(move || interrupt_clone.handle_interrupt()))?;
+        interrupt.trait_.unmask();
+        Ok(interrupt)
+    }
+
     /// Bind the interrupt object to a port.
     pub fn bind(&self, port: &Arc<Port>, key: u64) -> ZxResult {
         let mut inner = self.inner.lock();
@@ -292,7 +341,7 @@ impl Interrupt {
             self.trait_.mask();
         }
         if inner.timestamp == 0 {
-            // Not sure ZX_CLOCK_MONOTONIC or ZX_CLOCK_UTC
+            // the timestamp of interrupt packets is on the monotonic clock
             inner.timestamp = kernel_hal::timer::timer_now().as_nanos() as i64;
         }
         match &inner.port {
@@ -375,6 +424,21 @@ impl InterruptOptions {
     pub fn to_mode(self) -> Self {
         InterruptOptions::from_bits_truncate(0xe) & self
     }
+
+    /// Get the trigger mode and polarity to configure the IRQ with, or
+    /// `None` to keep the default one.
+    fn to_trigger(self) -> ZxResult<Option<(IrqTriggerMode, IrqPolarity)>> {
+        use {IrqPolarity::*, IrqTriggerMode::*};
+        Ok(Some(match self.to_mode() {
+            Self::MODE_DEFAULT => return Ok(None),
+            Self::MODE_EDGE_LOW => (Edge, ActiveLow),
+            Self::MODE_EDGE_HIGH => (Edge, ActiveHigh),
+            Self::MODE_LEVEL_LOW => (Level, ActiveLow),
+            Self::MODE_LEVEL_HIGH => (Level, ActiveHigh),
+            Self::MODE_EDGE_BOTH => return Err(ZxError::NOT_SUPPORTED),
+            _ => return Err(ZxError::INVALID_ARGS),
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -412,4 +476,40 @@ mod tests {
         );
         assert!(interrupt.unbind(&port).is_ok());
     }
+
+    #[async_std::test]
+    async fn physical() {
+        assert_eq!(
+            Interrupt::new_physical(usize::MAX, InterruptOptions::empty()).err(),
+            Some(ZxError::INVALID_ARGS)
+        );
+        assert_eq!(
+            Interrupt::new_physical(10, InterruptOptions::MODE_EDGE_BOTH).err(),
+            Some(ZxError::NOT_SUPPORTED)
+        );
+
+        let interrupt = Interrupt::new_physical(10, InterruptOptions::MODE_LEVEL_HIGH).unwrap();
+        assert_eq!(
+            Interrupt::new_physical(10, InterruptOptions::empty()).err(),
+            Some(ZxError::ALREADY_BOUND)
+        );
+        assert_eq!(interrupt.trigger(0), Err(ZxError::BAD_STATE));
+
+        interrupt::handle_irq(10);
+        assert_eq!(interrupt.signal(), Signal::INTERRUPT_SIGNAL);
+        assert!(interrupt.wait().await.unwrap() > 0);
+        assert_eq!(interrupt.signal(), Signal::empty());
+
+        // masked until it is waited for again
+        interrupt::handle_irq(10);
+        assert_eq!(interrupt.signal(), Signal::empty());
+
+        interrupt.destroy().unwrap();
+        assert_eq!(interrupt.wait().await, Err(ZxError::CANCELED));
+        // the IRQ is free again
+        Interrupt::new_physical(10, InterruptOptions::empty())
+            .unwrap()
+            .destroy()
+            .unwrap();
+    }
 }
@@ -0,0 +1,153 @@
+use alloc::{boxed::Box, sync::Arc};
+use lock::Mutex;
+
+use super::InterruptTrait;
+use crate::dev::MsiAllocation;
+use crate::vm::VmObject;
+use crate::{ZxError, ZxResult};
+
+/// The capability id of MSI.
+const PCI_CAP_ID_MSI: u8 = 0x5;
+/// The size of an entry of an MSI-X table.
+const MSIX_ENTRY_SIZE: usize = 16;
+
+/// An interrupt on a vector of an MSI allocation.
+///
+/// The VMO maps the MSI capability of the device, or the MSI-X table, to mask
+/// the interrupt, and to program the MSI-X entry.
+pub struct MsiInterrupt {
+    allocation: Arc<MsiAllocation>,
+    msi_id: usize,
+    vmo: Arc<VmObject>,
+    mode: MsiMode,
+    inner: Mutex<MsiInterruptInner>,
+}
+
+enum MsiMode {
+    /// The offset of the mask bits in the MSI capability, if the device
+    /// supports per-vector masking.
+    Msi { mask_bits: Option<usize> },
+    /// The offset of the entry in the MSI-X table.
+    MsiX { entry: usize },
+}
+
+#[derive(Default)]
+struct MsiInterruptInner {
+    register: bool,
+}
+
+impl MsiInterrupt {
+    pub fn new(
+        allocation: Arc<MsiAllocation>,
+        msi_id: usize,
+        vmo: Arc<VmObject>,
+        offset: usize,
+        msix: bool,
+    ) -> ZxResult<Box<Self>> {
+        if !vmo.is_contiguous() {
+            return Err(ZxError::WRONG_TYPE);
+        }
+        let mode = if msix {
+            let entry = offset + msi_id * MSIX_ENTRY_SIZE;
+            if entry + MSIX_ENTRY_SIZE > vmo.len() {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            MsiMode::MsiX { entry }
+        } else {
+            let mut cap = [0u8; 4];
+            vmo.read(offset, &mut cap)?;
+            if cap[0] != PCI_CAP_ID_MSI {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            let ctrl = u16::from_le_bytes([cap[2], cap[3]]);
+            let has_pvm = ctrl & 0x100 != 0;
+            let is_64bit = ctrl & 0x80 != 0;
+            MsiMode::Msi {
+                mask_bits: has_pvm.then(|| offset + if is_64bit { 0x10 } else { 0xc }),
+            }
+        };
+        allocation.reserve_id(msi_id)?;
+        Ok(Box::new(MsiInterrupt {
+            allocation,
+            msi_id,
+            vmo,
+            mode,
+            inner: Default::default(),
+        }))
+    }
+
+    /// Whether the interrupt may be masked on the device.
+    pub fn maskable(&self) -> bool {
+        !matches!(self.mode, MsiMode::Msi { mask_bits: None })
+    }
+
+    fn read32(&self, offset: usize) -> u32 {
+        let mut buf = [0u8; 4];
+        if self.vmo.read(offset, &mut buf).is_err() {
+            warn!("failed to read MSI registers at {:#x}", offset);
+        }
+        u32::from_le_bytes(buf)
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        if self.vmo.write(offset, &value.to_le_bytes()).is_err() {
+            warn!("failed to write MSI registers at {:#x}", offset);
+        }
+    }
+
+    fn set_masked(&self, masked: bool) {
+        let (offset, bit) = match self.mode {
+            MsiMode::Msi {
+                mask_bits: Some(mask_bits),
+            } => (mask_bits, 1 << self.msi_id),
+            // the vector control word
+            MsiMode::MsiX { entry } => (entry + 12, 1),
+            MsiMode::Msi { mask_bits: None } => return,
+        };
+        let value = self.read32(offset);
+        self.write32(offset, if masked { value | bit } else { value & !bit });
+    }
+}
+
+impl InterruptTrait for MsiInterrupt {
+    fn mask(&self) {
+        self.set_masked(true);
+    }
+
+    fn unmask(&self) {
+        self.set_masked(false);
+    }
+
+    fn register_handler(&self, handle: Box<dyn Fn() + Send + Sync>) -> ZxResult {
+        let mut inner = self.inner.lock();
+        if inner.register {
+            return Err(ZxError::ALREADY_BOUND);
+        }
+        self.allocation.register_handler(self.msi_id, handle);
+        if let MsiMode::MsiX { entry } = self.mode {
+            let (addr, data) = self.allocation.target(self.msi_id);
+            self.write32(entry, addr as u32);
+            self.write32(entry + 4, (addr >> 32) as u32);
+            self.write32(entry + 8, data);
+        }
+        inner.register = true;
+        Ok(())
+    }
+
+    fn unregister_handler(&self) -> ZxResult {
+        let mut inner = self.inner.lock();
+        if !inner.register {
+            return Ok(());
+        }
+        self.allocation
+            .register_handler(self.msi_id, Box::new(|| {}));
+        inner.register = false;
+        Ok(())
+    }
+}
+
+impl Drop for MsiInterrupt {
+    fn drop(&mut self) {
+        self.allocation.release_id(self.msi_id);
+    }
+}
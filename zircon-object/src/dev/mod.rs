@@ -3,8 +3,9 @@
 mod bti;
 mod interrupt;
 mod iommu;
+mod msi;
 pub mod pci;
 mod pmt;
 mod resource;
 
-pub use self::{bti::*, interrupt::*, iommu::*, pmt::*, resource::*};
+pub use self::{bti::*, interrupt::*, iommu::*, msi::*, pmt::*, resource::*};
@@ -0,0 +1,117 @@
+use {
+    crate::dev::pci::PciMsiBlock, crate::object::*, alloc::boxed::Box, alloc::sync::Arc,
+    lock::Mutex,
+};
+
+/// The most interrupts an MSI allocation may have.
+pub const MSI_MAX_IRQS: u32 = 32;
+
+/// Message signaled interrupts allocation
+///
+/// ## SYNOPSIS
+///
+/// An MSI allocation holds a block of contiguous interrupt vectors, raised by
+/// a device writing the target data, plus the interrupt id, to the target
+/// address. Interrupt objects are created on its vectors with
+/// `zx_msi_create()`, and the vectors are freed once it is destroyed.
+pub struct MsiAllocation {
+    base: KObjectBase,
+    block: PciMsiBlock,
+    /// The ids with interrupt objects, one bit for each.
+    ids_in_use: Mutex<u32>,
+}
+
+impl_kobject!(MsiAllocation);
+
+impl MsiAllocation {
+    /// Allocate a block of `count` vectors, which must be a power of 2.
+    pub fn create(count: u32) -> ZxResult<Arc<Self>> {
+        if count == 0 || count > MSI_MAX_IRQS || !count.is_power_of_two() {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        Ok(Arc::new(MsiAllocation {
+            base: KObjectBase::new(),
+            block: PciMsiBlock::allocate(count as usize)?,
+            ids_in_use: Mutex::new(0),
+        }))
+    }
+
+    /// Number of vectors of the allocation.
+    pub fn count(&self) -> usize {
+        self.block.num_irq
+    }
+
+    /// The address and data a device writes to raise the interrupt `msi_id`.
+    pub fn target(&self, msi_id: usize) -> (u64, u32) {
+        (
+            self.block.target_addr,
+            self.block.target_data + msi_id as u32,
+        )
+    }
+
+    /// Reserve `msi_id` for an interrupt object.
+    pub(crate) fn reserve_id(&self, msi_id: usize) -> ZxResult {
+        if msi_id >= self.count() {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut ids_in_use = self.ids_in_use.lock();
+        if *ids_in_use & (1 << msi_id) != 0 {
+            return Err(ZxError::ALREADY_BOUND);
+        }
+        *ids_in_use |= 1 << msi_id;
+        Ok(())
+    }
+
+    /// Release `msi_id` once its interrupt object is destroyed.
+    pub(crate) fn release_id(&self, msi_id: usize) {
+        *self.ids_in_use.lock() &= !(1 << msi_id);
+    }
+
+    /// Set the handler of the vector of `msi_id`.
+    pub(crate) fn register_handler(&self, msi_id: usize, handler: Box<dyn Fn() + Send + Sync>) {
+        self.block.register_handler(msi_id, handler);
+    }
+
+    /// Get information of the allocation.
+    pub fn get_info(&self) -> MsiInfo {
+        MsiInfo {
+            target_addr: self.block.target_addr,
+            target_data: self.block.target_data,
+            base_irq_id: self.block.base_irq as u32,
+            num_irq: self.block.num_irq as u32,
+            interrupt_count: self.ids_in_use.lock().count_ones(),
+        }
+    }
+}
+
+impl Drop for MsiAllocation {
+    fn drop(&mut self) {
+        self.block.free();
+    }
+}
+
+/// Information of an MSI allocation.
+#[repr(C)]
+#[derive(Default)]
+pub struct MsiInfo {
+    target_addr: u64,
+    target_data: u32,
+    base_irq_id: u32,
+    num_irq: u32,
+    interrupt_count: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create() {
+        for count in [0, 3, MSI_MAX_IRQS * 2] {
+            assert_eq!(
+                MsiAllocation::create(count).err(),
+                Some(ZxError::INVALID_ARGS)
+            );
+        }
+    }
+}
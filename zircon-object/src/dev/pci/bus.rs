@@ -488,8 +488,14 @@ pub struct PcieDeviceInfo {
 pub struct PcieDeviceKObject {
     base: KObjectBase,
     device: Arc<dyn IPciNode>,
-    irqs_avail_cnt: u32, // WARNING
-    irqs_maskable: bool, // WARNING
+    irqs: Mutex<PcieDeviceIrqs>,
+}
+
+/// The IRQs of the IRQ mode set.
+#[derive(Default)]
+struct PcieDeviceIrqs {
+    avail_cnt: u32,
+    maskable: bool,
 }
 
 impl_kobject!(PcieDeviceKObject);
@@ -500,8 +506,7 @@ impl PcieDeviceKObject {
         Arc::new(PcieDeviceKObject {
             base: KObjectBase::new(),
             device,
-            irqs_avail_cnt: 10,  // WARNING
-            irqs_maskable: true, // WARNING
+            irqs: Mutex::new(PcieDeviceIrqs::default()),
         })
     }
 
@@ -512,11 +517,17 @@ impl PcieDeviceKObject {
     }
 
     /// Map the interrupt to the IRQ.
+    ///
+    /// `irq` must be one of the IRQs of the IRQ mode set.
     pub fn map_interrupt(&self, irq: i32) -> ZxResult<Arc<Interrupt>> {
-        if irq < 0 || irq as u32 >= self.irqs_avail_cnt {
+        let (avail_cnt, maskable) = {
+            let irqs = self.irqs.lock();
+            (irqs.avail_cnt, irqs.maskable)
+        };
+        if irq < 0 || irq as u32 >= avail_cnt {
             return Err(ZxError::INVALID_ARGS);
         }
-        Interrupt::new_pci(self.device.clone(), irq as u32, self.irqs_maskable)
+        Interrupt::new_pci(self.device.clone(), irq as u32, maskable)
     }
 
     /// Enable MMIO.
@@ -541,7 +552,33 @@ impl PcieDeviceKObject {
 
     /// Set IRQ mode.
     pub fn set_irq_mode(&self, mode: PcieIrqMode, requested_irqs: usize) -> ZxResult {
-        self.device.device().set_irq_mode(mode, requested_irqs)
+        let device = self.device.device();
+        let maskable = match mode {
+            PcieIrqMode::Disabled => false,
+            _ => {
+                device
+                    .get_irq_mode_capabilities(mode)?
+                    .per_vector_masking_supported
+            }
+        };
+        device.set_irq_mode(mode, requested_irqs)?;
+        *self.irqs.lock() = PcieDeviceIrqs {
+            avail_cnt: match mode {
+                PcieIrqMode::Disabled => 0,
+                _ => requested_irqs as u32,
+            },
+            maskable,
+        };
+        Ok(())
+    }
+
+    /// Reset the device by a function level reset.
+    ///
+    /// The IRQ mode is disabled by the reset.
+    pub async fn reset(&self) -> ZxResult {
+        self.device.device().reset().await?;
+        *self.irqs.lock() = PcieDeviceIrqs::default();
+        Ok(())
     }
 
     /// Read the device's config.
@@ -9,6 +9,7 @@ pub use bus::{
     MmioPcieAddressProvider, PCIeBusDriver, PcieDeviceInfo, PcieDeviceKObject,
     PmioPcieAddressProvider,
 };
+pub(crate) use caps::PciMsiBlock;
 pub use nodes::{IPciNode, PcieIrqMode};
 pub use pmio::{pio_config_read, pio_config_write};
 
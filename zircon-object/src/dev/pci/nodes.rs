@@ -16,6 +16,7 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::time::Duration;
 use kernel_hal::interrupt;
 use lock::{Mutex, MutexGuard};
 use numeric_enum_macro::numeric_enum;
@@ -364,6 +365,12 @@ impl PcieIrqHandlerState {
     }
 }
 
+/// Size of the standard header of the config space.
+const PCI_STANDARD_HEADER_SIZE: usize = 0x40;
+
+/// How long a device takes to be ready after a function level reset.
+const FLR_DELAY: Duration = Duration::from_millis(100);
+
 pub struct PcieDevice {
     pub bus_id: usize,
     pub dev_id: usize,
@@ -816,6 +823,60 @@ impl PcieDevice {
         }
     }
 
+    /// Reset the device by a function level reset.
+    ///
+    /// The BARs, the interrupt line and the command register are restored
+    /// once it is done, but the IRQ mode is disabled.
+    pub async fn reset(&self) -> ZxResult {
+        let flr = {
+            let inner = self.inner.lock();
+            if !inner.plugged_in {
+                return Err(ZxError::UNAVAILABLE);
+            }
+            inner
+                .caps
+                .iter()
+                .find_map(|cap| match cap {
+                    PciCapability::Pcie(std, pcie) if std.is_valid() && pcie.has_flr => {
+                        Some(Flr::Pcie(std.base as usize))
+                    }
+                    PciCapability::AdvFeatures(std, af) if std.is_valid() && af.has_flr => {
+                        Some(Flr::AdvFeatures(std.base as usize))
+                    }
+                    _ => None,
+                })
+                .ok_or(ZxError::NOT_SUPPORTED)?
+        };
+        self.set_irq_mode(PcieIrqMode::Disabled, 0)?;
+        let cfg = self.cfg.as_ref().unwrap();
+        let header: Vec<u32> = (0..PCI_STANDARD_HEADER_SIZE)
+            .step_by(4)
+            .map(|offset| cfg.read32_(offset))
+            .collect();
+        // stop the device before resetting it
+        self.modify_cmd(
+            PCI_COMMAND_IO_EN | PCI_COMMAND_MEM_EN | PCI_COMMAND_BUS_MASTER_EN,
+            0,
+        );
+        match flr {
+            // Initiate Function Level Reset in the Device Control register
+            Flr::Pcie(base) => cfg.write16_(base + 0x8, cfg.read16_(base + 0x8) | 1 << 15),
+            // Initiate FLR in the AF Control register
+            Flr::AdvFeatures(base) => cfg.write8_offset(cfg.base + base + 0x4, 1),
+        }
+        // the device must be ready in 100 ms after a function level reset
+        kernel_hal::thread::sleep_until(kernel_hal::timer::timer_now() + FLR_DELAY).await;
+        for bar in 0..self.bar_count {
+            cfg.write_bar(bar, header[(PciReg32::BARBase as usize >> 2) + bar]);
+        }
+        cfg.write8(
+            PciReg8::InterruptLine,
+            header[PciReg8::InterruptLine as usize >> 2] as u8,
+        );
+        self.assign_cmd(header[PciReg16::Command as usize >> 2] as u16);
+        Ok(())
+    }
+
     /// Enable an IRQ.
     pub fn enable_irq(&self, irq_id: usize, enable: bool) {
         let _dev_lcok = self.dev_lock.lock();
@@ -1205,6 +1266,12 @@ impl PcieDevice {
     }
 }
 
+/// The capability to initiate a function level reset, with its offset.
+enum Flr {
+    Pcie(usize),
+    AdvFeatures(usize),
+}
+
 #[derive(PartialEq, Eq)]
 pub enum PciNodeType {
     Root,
@@ -334,42 +334,47 @@ impl dyn KernelObject {
     /// Once one of the `signal` asserted, push a packet with `key` into the `port`,
     ///
     /// It's used to implement `sys_object_wait_async`.
-    #[allow(unsafe_code)]
     pub fn send_signal_to_port_async(self: &Arc<Self>, signal: Signal, port: &Arc<Port>, key: u64) {
-        let current_signal = self.signal();
-        if !(current_signal & signal).is_empty() {
-            port.push(PortPacketRepr {
-                key,
-                status: ZxError::OK,
-                data: PayloadRepr::Signal(PacketSignal {
-                    trigger: signal,
-                    observed: current_signal,
-                    count: 1,
-                    timestamp: 0,
-                    _reserved1: 0,
-                }),
-            });
-            return;
-        }
-        self.add_signal_callback(Box::new({
-            let port = port.clone();
-            move |s| {
-                if (s & signal).is_empty() {
-                    return false;
+        self.wait_async(signal, port, key, WaitAsyncOptions::empty());
+    }
+
+    /// Once one of the `signal` asserted, push a packet with `key` into the
+    /// `port`, unless the wait is canceled by `Port::cancel` before.
+    ///
+    /// With `WaitAsyncOptions::EDGE`, only the signals asserted after the call
+    /// are observed. With `WaitAsyncOptions::TIMESTAMP`, the packet carries the
+    /// monotonic time when the signal is asserted.
+    pub fn wait_async(
+        self: &Arc<Self>,
+        signal: Signal,
+        port: &Arc<Port>,
+        key: u64,
+        options: WaitAsyncOptions,
+    ) {
+        let observer = port.add_observer(self.id(), key);
+        let port = port.clone();
+        // the signals asserted at the last call, for edge-triggered waits
+        let asserted: Mutex<Option<Signal>> = Mutex::new(None);
+        self.add_signal_callback(Box::new(move |s| {
+            if observer.is_canceled() {
+                return true;
+            }
+            let triggered = s & signal;
+            if options.contains(WaitAsyncOptions::EDGE) {
+                match asserted.lock().replace(triggered) {
+                    Some(before) if !(triggered & !before).is_empty() => {}
+                    _ => return false,
                 }
-                port.push(PortPacketRepr {
-                    key,
-                    status: ZxError::OK,
-                    data: PayloadRepr::Signal(PacketSignal {
-                        trigger: signal,
-                        observed: s,
-                        count: 1,
-                        timestamp: 0,
-                        _reserved1: 0,
-                    }),
-                });
-                true
+            } else if triggered.is_empty() {
+                return false;
             }
+            let timestamp = if options.contains(WaitAsyncOptions::TIMESTAMP) {
+                kernel_hal::timer::timer_now().as_nanos() as u64
+            } else {
+                0
+            };
+            port.push_signal(&observer, signal, s, timestamp);
+            true
         }));
     }
 }
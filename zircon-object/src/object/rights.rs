@@ -123,6 +123,9 @@ bitflags! {
         /// BASIC | WRITE | SIGNAL
         const DEFAULT_TIMER = Self::BASIC.bits | Self::WRITE.bits | Self::SIGNAL.bits;
 
+        /// BASIC | IO
+        const DEFAULT_CLOCK = Self::BASIC.bits | Self::IO.bits;
+
         /// BASIC | SIGNAL
         const DEFAULT_EVENT = Self::BASIC.bits | Self::SIGNAL.bits;
 
@@ -147,6 +150,9 @@ bitflags! {
         /// BASIC | IO
         const DEFAULT_DEVICE = Self::BASIC.bits | Self::IO.bits;
 
+        /// BASIC
+        const DEFAULT_MSI = Self::BASIC.bits;
+
         /// BASIC | IO | SIGNAL
         const DEFAULT_PCI_INTERRUPT = Self::BASIC.bits | Self::IO.bits | Self::SIGNAL.bits;
 
@@ -158,6 +164,12 @@ bitflags! {
 
         /// BASIC | IO | EXECUTE | SIGNAL
         const DEFAULT_VCPU = Self::BASIC.bits | Self::IO.bits | Self::EXECUTE.bits | Self::SIGNAL.bits;
+
+        /// TRANSFER | INSPECT | PROPERTY
+        const DEFAULT_PAGER = Self::TRANSFER.bits | Self::INSPECT.bits | Self::PROPERTY.bits;
+
+        /// BASIC | APPLY_PROFILE
+        const DEFAULT_PROFILE = Self::BASIC.bits | Self::APPLY_PROFILE.bits;
     }
 }
 
@@ -0,0 +1,427 @@
+use super::*;
+use crate::object::*;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use kernel_hal::timer::timer_now;
+use lock::Mutex;
+
+/// The error bound of a clock which is unknown.
+pub const CLOCK_UNKNOWN_ERROR: u64 = u64::MAX;
+/// The lowest rate adjustment of a clock, in parts per million.
+pub const CLOCK_MIN_RATE_ADJUST: i32 = -1000;
+/// The highest rate adjustment of a clock, in parts per million.
+pub const CLOCK_MAX_RATE_ADJUST: i32 = 1000;
+
+/// The reference ticks of the rate of a clock, so that the rate adjustment
+/// is in parts per million.
+const RATE_REFERENCE_TICKS: u32 = 1_000_000;
+
+/// A kernel clock, maintaining a synthetic timeline
+///
+/// ## SYNOPSIS
+///
+/// A clock maps the monotonic time to its synthetic time by a linear
+/// transformation, which is replaced each time the clock is updated by
+/// `zx_clock_update()`. It is used to maintain the UTC time in the userspace.
+///
+/// A clock is not started until its value is set for the first time, and
+/// reads its backstop time until then. Signal `CLOCK_STARTED` is asserted
+/// once it is started.
+pub struct Clock {
+    base: KObjectBase,
+    _counter: CountHelper,
+    options: ClockOptions,
+    backstop: i64,
+    inner: Mutex<ClockInner>,
+}
+
+impl_kobject!(Clock);
+define_count_helper!(Clock);
+
+struct ClockInner {
+    /// The transformation from the monotonic time.
+    transform: ClockTransformation,
+    started: bool,
+    error_bound: u64,
+    last_value_update: i64,
+    last_rate_adjust_update: i64,
+    last_error_bounds_update: i64,
+    /// Number of updates.
+    generation: u32,
+}
+
+bitflags! {
+    /// Options to create a clock.
+    pub struct ClockOptions: u64 {
+        #[allow(clippy::identity_op)]
+        /// The clock never goes backwards.
+        const MONOTONIC = 1 << 0;
+        /// The clock never jumps once started. It must be monotonic.
+        const CONTINUOUS = 1 << 1;
+        /// The clock is started as a clone of the monotonic clock.
+        const AUTO_START = 1 << 2;
+    }
+}
+
+bitflags! {
+    /// The fields of [`ClockUpdateArgs`] to update.
+    pub struct ClockUpdateOptions: u64 {
+        #[allow(clippy::identity_op)]
+        /// Set the value.
+        const VALUE_VALID = 1 << 0;
+        /// Set the rate adjustment.
+        const RATE_ADJUST_VALID = 1 << 1;
+        /// Set the error bound.
+        const ERROR_BOUND_VALID = 1 << 2;
+    }
+}
+
+/// The rate of a clock transformation.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClockRate {
+    /// Synthetic ticks per `reference_ticks`.
+    pub synthetic_ticks: u32,
+    /// Reference ticks per `synthetic_ticks`.
+    pub reference_ticks: u32,
+}
+
+/// A linear transformation from a reference timeline to a synthetic one.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTransformation {
+    /// The reference time where the transformation starts.
+    pub reference_offset: i64,
+    /// The synthetic time at `reference_offset`.
+    pub synthetic_offset: i64,
+    /// The rate of the synthetic time to the reference one.
+    pub rate: ClockRate,
+}
+
+impl ClockTransformation {
+    /// Transform the reference time `reference` to the synthetic time.
+    pub fn apply(&self, reference: i64) -> i64 {
+        let delta = (reference as i128 - self.reference_offset as i128)
+            * self.rate.synthetic_ticks as i128
+            / self.rate.reference_ticks as i128;
+        (self.synthetic_offset as i128 + delta).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+}
+
+/// Arguments to create a clock, version 1.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClockCreateArgs {
+    /// The earliest time the clock may read.
+    pub backstop_time: i64,
+}
+
+/// Arguments to update a clock, version 1.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClockUpdateArgs {
+    /// The rate adjustment in parts per million.
+    pub rate_adjust: i32,
+    padding1: [u8; 4],
+    /// The synthetic value at the time of the update.
+    pub value: i64,
+    /// The error bound of the clock in nanoseconds.
+    pub error_bound: u64,
+}
+
+/// Details of a clock, version 1.
+///
+/// The ticks are counted in nanoseconds of the monotonic clock.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClockDetails {
+    /// The options the clock was created with.
+    pub options: u64,
+    /// The backstop time of the clock.
+    pub backstop_time: i64,
+    /// The transformation from the ticks.
+    pub ticks_to_synthetic: ClockTransformation,
+    /// The transformation from the monotonic time.
+    pub mono_to_synthetic: ClockTransformation,
+    /// The error bound of the clock in nanoseconds.
+    pub error_bound: u64,
+    /// The ticks when the details are queried.
+    pub query_ticks: i64,
+    /// The ticks when the value was last set.
+    pub last_value_update_ticks: i64,
+    /// The ticks when the rate adjustment was last set.
+    pub last_rate_adjust_update_ticks: i64,
+    /// The ticks when the error bound was last set.
+    pub last_error_bounds_update_ticks: i64,
+    /// Number of updates of the clock.
+    pub generation_counter: u32,
+    padding1: [u8; 4],
+}
+
+fn mono_now() -> i64 {
+    timer_now().as_nanos() as i64
+}
+
+impl Clock {
+    /// Create a new clock which reads no earlier than `backstop`.
+    pub fn create(options: u64, backstop: i64) -> ZxResult<Arc<Self>> {
+        let options = ClockOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
+        if options.contains(ClockOptions::CONTINUOUS) && !options.contains(ClockOptions::MONOTONIC)
+        {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        if backstop < 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut inner = ClockInner {
+            // stay at the backstop time until started
+            transform: ClockTransformation {
+                reference_offset: 0,
+                synthetic_offset: backstop,
+                rate: ClockRate {
+                    synthetic_ticks: 0,
+                    reference_ticks: 1,
+                },
+            },
+            started: false,
+            error_bound: CLOCK_UNKNOWN_ERROR,
+            last_value_update: 0,
+            last_rate_adjust_update: 0,
+            last_error_bounds_update: 0,
+            generation: 0,
+        };
+        if options.contains(ClockOptions::AUTO_START) {
+            let now = mono_now();
+            if backstop > now {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            inner.transform = ClockTransformation {
+                reference_offset: now,
+                synthetic_offset: now,
+                rate: ClockRate {
+                    synthetic_ticks: 1,
+                    reference_ticks: 1,
+                },
+            };
+            inner.started = true;
+            inner.last_value_update = now;
+            inner.last_rate_adjust_update = now;
+        }
+        let clock = Arc::new(Clock {
+            base: KObjectBase::new(),
+            _counter: CountHelper::new(),
+            options,
+            backstop,
+            inner: Mutex::new(inner),
+        });
+        if options.contains(ClockOptions::AUTO_START) {
+            clock.base.signal_set(Signal::CLOCK_STARTED);
+        }
+        Ok(clock)
+    }
+
+    /// Read the synthetic time of the clock.
+    pub fn read(&self) -> i64 {
+        self.inner.lock().transform.apply(mono_now())
+    }
+
+    /// Update the clock with the fields of `args` in `options`.
+    ///
+    /// The value of a monotonic clock may not go backwards, and that of a
+    /// continuous clock may only be set to start it. The first update must
+    /// set the value.
+    pub fn update(&self, options: u64, args: &ClockUpdateArgs) -> ZxResult {
+        let options = ClockUpdateOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
+        if options.is_empty() {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let set_value = options.contains(ClockUpdateOptions::VALUE_VALID);
+        let set_rate = options.contains(ClockUpdateOptions::RATE_ADJUST_VALID);
+        if set_rate && !(CLOCK_MIN_RATE_ADJUST..=CLOCK_MAX_RATE_ADJUST).contains(&args.rate_adjust)
+        {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut inner = self.inner.lock();
+        let now = mono_now();
+        let current = inner.transform.apply(now);
+        if set_value {
+            if args.value < self.backstop {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            if inner.started
+                && (self.options.contains(ClockOptions::CONTINUOUS)
+                    || (self.options.contains(ClockOptions::MONOTONIC) && args.value < current))
+            {
+                return Err(ZxError::INVALID_ARGS);
+            }
+        } else if !inner.started {
+            return Err(ZxError::BAD_STATE);
+        }
+
+        let rate = if set_rate {
+            ClockRate {
+                synthetic_ticks: (RATE_REFERENCE_TICKS as i32 + args.rate_adjust) as u32,
+                reference_ticks: RATE_REFERENCE_TICKS,
+            }
+        } else if inner.started {
+            inner.transform.rate
+        } else {
+            ClockRate {
+                synthetic_ticks: 1,
+                reference_ticks: 1,
+            }
+        };
+        inner.transform = ClockTransformation {
+            reference_offset: now,
+            synthetic_offset: if set_value { args.value } else { current },
+            rate,
+        };
+        if set_value {
+            inner.last_value_update = now;
+        }
+        if set_rate {
+            inner.last_rate_adjust_update = now;
+        }
+        if options.contains(ClockUpdateOptions::ERROR_BOUND_VALID) {
+            inner.error_bound = args.error_bound;
+            inner.last_error_bounds_update = now;
+        }
+        inner.generation = inner.generation.wrapping_add(1);
+        if !inner.started {
+            inner.started = true;
+            self.base.signal_set(Signal::CLOCK_STARTED);
+        }
+        Ok(())
+    }
+
+    /// Get the details of the clock.
+    pub fn get_details(&self) -> ClockDetails {
+        let inner = self.inner.lock();
+        ClockDetails {
+            options: self.options.bits(),
+            backstop_time: self.backstop,
+            ticks_to_synthetic: inner.transform,
+            mono_to_synthetic: inner.transform,
+            error_bound: inner.error_bound,
+            query_ticks: mono_now(),
+            last_value_update_ticks: inner.last_value_update,
+            last_rate_adjust_update_ticks: inner.last_rate_adjust_update,
+            last_error_bounds_update_ticks: inner.last_error_bounds_update,
+            generation_counter: inner.generation,
+            padding1: [0; 4],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update_value(value: i64) -> ClockUpdateArgs {
+        ClockUpdateArgs {
+            value,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn create() {
+        assert_eq!(Clock::create(1 << 3, 0).err(), Some(ZxError::INVALID_ARGS));
+        assert_eq!(
+            Clock::create(ClockOptions::CONTINUOUS.bits(), 0).err(),
+            Some(ZxError::INVALID_ARGS)
+        );
+        assert_eq!(
+            Clock::create(ClockOptions::AUTO_START.bits(), i64::MAX).err(),
+            Some(ZxError::INVALID_ARGS)
+        );
+
+        // reads the backstop time until started
+        let clock = Clock::create(0, 1000).unwrap();
+        assert_eq!(clock.read(), 1000);
+        assert_eq!(clock.signal(), Signal::empty());
+
+        let clock = Clock::create(ClockOptions::AUTO_START.bits(), 0).unwrap();
+        assert_eq!(clock.signal(), Signal::CLOCK_STARTED);
+        let mono = mono_now();
+        let time = clock.read();
+        assert!(time >= mono && time - mono < 1_000_000_000);
+    }
+
+    #[test]
+    fn update() {
+        let clock = Clock::create(ClockOptions::MONOTONIC.bits(), 1000).unwrap();
+        let rate = ClockUpdateOptions::RATE_ADJUST_VALID.bits();
+        let value = ClockUpdateOptions::VALUE_VALID.bits();
+        // the first update must set the value
+        assert_eq!(
+            clock.update(rate, &ClockUpdateArgs::default()),
+            Err(ZxError::BAD_STATE)
+        );
+        assert_eq!(
+            clock.update(value, &update_value(999)),
+            Err(ZxError::INVALID_ARGS)
+        );
+        let start = 1_000_000_000_000;
+        clock.update(value, &update_value(start)).unwrap();
+        assert_eq!(clock.signal(), Signal::CLOCK_STARTED);
+        assert!(clock.read() >= start);
+
+        // monotonic clocks never go backwards
+        assert_eq!(
+            clock.update(value, &update_value(start - 1)),
+            Err(ZxError::INVALID_ARGS)
+        );
+        clock.update(value, &update_value(start * 2)).unwrap();
+
+        // the rate adjustment is limited
+        let mut args = ClockUpdateArgs {
+            rate_adjust: CLOCK_MAX_RATE_ADJUST + 1,
+            ..Default::default()
+        };
+        assert_eq!(clock.update(rate, &args), Err(ZxError::INVALID_ARGS));
+        args.rate_adjust = CLOCK_MAX_RATE_ADJUST;
+        clock.update(rate, &args).unwrap();
+        let details = clock.get_details();
+        assert_eq!(details.mono_to_synthetic.rate.synthetic_ticks, 1_001_000);
+        assert_eq!(details.generation_counter, 3);
+        assert_eq!(details.error_bound, CLOCK_UNKNOWN_ERROR);
+        assert!(details.mono_to_synthetic.synthetic_offset >= start * 2);
+    }
+
+    #[test]
+    fn continuous() {
+        let options = ClockOptions::MONOTONIC | ClockOptions::CONTINUOUS;
+        let clock = Clock::create(options.bits(), 0).unwrap();
+        let value = ClockUpdateOptions::VALUE_VALID.bits();
+        clock.update(value, &update_value(1000)).unwrap();
+        // may not jump once started
+        assert_eq!(
+            clock.update(value, &update_value(i64::MAX / 2)),
+            Err(ZxError::INVALID_ARGS)
+        );
+        let args = ClockUpdateArgs {
+            error_bound: 10,
+            ..Default::default()
+        };
+        clock
+            .update(ClockUpdateOptions::ERROR_BOUND_VALID.bits(), &args)
+            .unwrap();
+        assert_eq!(clock.get_details().error_bound, 10);
+    }
+
+    #[test]
+    fn transformation() {
+        let transform = ClockTransformation {
+            reference_offset: 100,
+            synthetic_offset: 1000,
+            rate: ClockRate {
+                synthetic_ticks: 2,
+                reference_ticks: 1,
+            },
+        };
+        assert_eq!(transform.apply(100), 1000);
+        assert_eq!(transform.apply(150), 1100);
+        assert_eq!(transform.apply(i64::MAX), i64::MAX);
+    }
+}
@@ -71,7 +71,7 @@ impl Futex {
     /// The owner of the futex is set to nothing, regardless of the wake count.
     pub fn wake(&self, wake_count: usize) -> usize {
         let mut inner = self.inner.lock();
-        inner.set_owner(None);
+        inner.set_owner(self.id(), None);
         for i in 0..wake_count {
             if let Some(waiter) = inner.waiter_queue.pop_front() {
                 waiter.wake();
@@ -132,7 +132,12 @@ impl Futex {
                 // check wakeup
                 if inner.woken {
                     // set new owner on success
-                    inner.futex.inner.lock().set_owner(self.new_owner.clone());
+                    let id = inner.futex.id();
+                    inner
+                        .futex
+                        .inner
+                        .lock()
+                        .set_owner(id, self.new_owner.clone());
                     return Poll::Ready(Ok(()));
                 }
                 // first time?
@@ -148,6 +153,7 @@ impl Futex {
                         return Poll::Ready(Err(ZxError::INVALID_ARGS));
                     }
                     futex.waiter_queue.push_back(self.waiter.clone());
+                    futex.donate_priority(inner.futex.id());
                     drop(futex);
                     inner.waker.replace(cx.waker().clone());
                 }
@@ -161,11 +167,13 @@ impl Futex {
                 let inner = self.waiter.inner.lock();
                 if !inner.woken {
                     let futex = inner.futex.clone();
-                    let queue = &mut futex.inner.lock().waiter_queue;
+                    let mut futex_inner = futex.inner.lock();
+                    let queue = &mut futex_inner.waiter_queue;
                     if let Some(pos) = queue.iter().position(|x| Arc::ptr_eq(x, &self.waiter)) {
                         // Nobody cares about the order of queue, so just remove faster
                         queue.swap_remove_back(pos);
                     }
+                    futex_inner.donate_priority(futex.id());
                 }
             }
         }
@@ -199,7 +207,7 @@ impl Futex {
             waiter.wake();
             waiter.thread.clone()
         });
-        inner.set_owner(new_owner);
+        inner.set_owner(self.id(), new_owner);
     }
 
     /// Requeuing is a generalization of waking.
@@ -247,8 +255,8 @@ impl Futex {
             new_inner.waiter_queue.push_back(waiter);
         }
         // set owner
-        inner.set_owner(None);
-        new_inner.set_owner(new_requeue_owner);
+        inner.set_owner(self.id(), None);
+        new_inner.set_owner(requeue_futex.id(), new_requeue_owner);
         Ok(())
     }
 }
@@ -269,9 +277,28 @@ impl FutexInner {
         true
     }
 
-    fn set_owner(&mut self, owner: Option<Arc<Thread>>) {
-        // TODO: change the priority of owner thread
+    /// Set the owner of the futex `id`, which inherits the priority of the
+    /// waiters instead of the old owner.
+    fn set_owner(&mut self, id: KoID, owner: Option<Arc<Thread>>) {
+        if let Some(old_owner) = self.owner.take() {
+            old_owner.donate_priority(id, None);
+        }
         self.owner = owner;
+        self.donate_priority(id);
+    }
+
+    /// Donate the highest priority of the waiters of the futex `id` to the
+    /// owner.
+    fn donate_priority(&self, id: KoID) {
+        if let Some(owner) = &self.owner {
+            let priority = self
+                .waiter_queue
+                .iter()
+                .filter_map(|waiter| waiter.thread.as_ref())
+                .map(|thread| thread.effective_priority())
+                .max();
+            owner.donate_priority(id, priority);
+        }
     }
 }
 
@@ -305,7 +332,7 @@ impl Waiter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::task::{Job, Process};
+    use crate::task::*;
     use core::time::Duration;
 
     #[async_std::test]
@@ -396,7 +423,10 @@ mod tests {
         static VALUE: AtomicI32 = AtomicI32::new(1);
         let futex = proc.get_futex(&VALUE);
         assert!(futex.owner().is_none());
-        futex.inner.lock().set_owner(Some(thread.clone()));
+        futex
+            .inner
+            .lock()
+            .set_owner(futex.id(), Some(thread.clone()));
 
         {
             let futex = futex.clone();
@@ -417,9 +447,45 @@ mod tests {
             ZxError::INVALID_ARGS
         );
 
-        futex.inner.lock().set_owner(None);
+        futex.inner.lock().set_owner(futex.id(), None);
         futex.wake_single_owner();
         assert!(Arc::ptr_eq(&futex.owner().unwrap(), &thread));
         assert_eq!(futex.wake(1), 0);
     }
+
+    #[async_std::test]
+    async fn donate_priority() {
+        let root_job = Job::root();
+        let proc = Process::create(&root_job, "proc").expect("failed to create process");
+        let owner = Thread::create(&proc, "owner").expect("failed to create thread");
+        let waiter = Thread::create(&proc, "waiter").expect("failed to create thread");
+        let info = ProfileInfo {
+            flags: ProfileInfoFlags::PRIORITY.bits(),
+            priority: PRIORITY_HIGHEST,
+            ..Default::default()
+        };
+        waiter.set_profile(&Profile::create(&info).unwrap());
+
+        static VALUE: AtomicI32 = AtomicI32::new(1);
+        let futex = proc.get_futex(&VALUE);
+        futex
+            .inner
+            .lock()
+            .set_owner(futex.id(), Some(owner.clone()));
+        {
+            let futex = futex.clone();
+            let waiter = waiter.clone();
+            async_std::task::spawn(async move {
+                futex.wait_with_owner(1, Some(waiter), None).await.unwrap();
+            });
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+        // the owner inherits the priority of the waiter
+        assert_eq!(owner.priority(), PRIORITY_DEFAULT);
+        assert_eq!(owner.effective_priority(), PRIORITY_HIGHEST);
+
+        // and gives it back when the waiter becomes the owner
+        futex.wake_single_owner();
+        assert_eq!(owner.effective_priority(), PRIORITY_DEFAULT);
+    }
 }
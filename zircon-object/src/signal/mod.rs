@@ -2,10 +2,11 @@
 
 use super::*;
 
+mod clock;
 mod event;
 mod eventpair;
 mod futex;
 mod port;
 mod timer;
 
-pub use self::{event::*, eventpair::*, futex::*, port::*, timer::*};
+pub use self::{clock::*, event::*, eventpair::*, futex::*, port::*, timer::*};
@@ -3,7 +3,9 @@ use super::*;
 use crate::object::*;
 use alloc::collections::{BTreeSet, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use bitflags::bitflags;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lock::Mutex;
 
 #[path = "port_packet.rs"]
@@ -30,10 +32,34 @@ impl_kobject!(Port);
 
 #[derive(Default, Debug)]
 struct PortInner {
-    queue: VecDeque<PortPacket>,
+    queue: VecDeque<QueuedPacket>,
     interrupt_queue: VecDeque<PortInterruptPacket>,
     interrupt_grave: BTreeSet<u64>,
     interrupt_pid: u64,
+    /// The async waits neither fired nor canceled.
+    observers: Vec<Arc<PortObserver>>,
+}
+
+#[derive(Debug)]
+struct QueuedPacket {
+    packet: PortPacket,
+    /// The object whose async wait pushed the packet.
+    source: Option<KoID>,
+}
+
+/// An async wait on an object, which pushes a signal packet into a port.
+#[derive(Debug)]
+pub struct PortObserver {
+    source: KoID,
+    key: u64,
+    canceled: AtomicBool,
+}
+
+impl PortObserver {
+    /// Whether the wait is canceled by `Port::cancel`.
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::Acquire)
+    }
 }
 
 #[derive(Debug)]
@@ -67,11 +93,108 @@ impl Port {
     /// Push a `packet` into the port.
     pub fn push(&self, packet: impl Into<PortPacket>) {
         let mut inner = self.inner.lock();
-        inner.queue.push_back(packet.into());
+        inner.queue.push_back(QueuedPacket {
+            packet: packet.into(),
+            source: None,
+        });
+        drop(inner);
+        self.base.signal_set(Signal::READABLE);
+    }
+
+    /// Register an async wait on the object `source` with `key`.
+    pub(crate) fn add_observer(&self, source: KoID, key: u64) -> Arc<PortObserver> {
+        let observer = Arc::new(PortObserver {
+            source,
+            key,
+            canceled: AtomicBool::new(false),
+        });
+        self.inner.lock().observers.push(observer.clone());
+        observer
+    }
+
+    /// Push a signal packet for the async wait `observer` which has fired.
+    ///
+    /// If a signal packet with the same source and key is still in the queue,
+    /// it is updated with the signals observed instead, and its count is
+    /// increased, so that no duplicated packets pile up.
+    pub(crate) fn push_signal(
+        &self,
+        observer: &PortObserver,
+        trigger: Signal,
+        observed: Signal,
+        timestamp: u64,
+    ) {
+        let mut inner = self.inner.lock();
+        inner
+            .observers
+            .retain(|o| !core::ptr::eq(o.as_ref(), observer));
+        if observer.is_canceled() {
+            return;
+        }
+        let queued = inner.queue.iter_mut().find(|queued| {
+            queued.source == Some(observer.source)
+                && queued.packet.key == observer.key
+                && queued.packet.type_ == PacketType::SignalOne
+        });
+        if let Some(queued) = queued {
+            let mut repr = PortPacketRepr::from(&queued.packet);
+            if let PayloadRepr::Signal(signal) = &mut repr.data {
+                signal.observed |= observed;
+                signal.count += 1;
+            }
+            queued.packet = repr.into();
+            return;
+        }
+        inner.queue.push_back(QueuedPacket {
+            packet: PortPacketRepr {
+                key: observer.key,
+                status: ZxError::OK,
+                data: PayloadRepr::Signal(PacketSignal {
+                    trigger,
+                    observed,
+                    count: 1,
+                    timestamp,
+                    _reserved1: 0,
+                }),
+            }
+            .into(),
+            source: Some(observer.source),
+        });
         drop(inner);
         self.base.signal_set(Signal::READABLE);
     }
 
+    /// Cancel the async waits on the object `source` with `key`, and remove
+    /// the packets they have pushed from the queue.
+    pub fn cancel(&self, source: KoID, key: u64) -> ZxResult {
+        let mut inner = self.inner.lock();
+        let mut found = false;
+        inner.observers.retain(|o| {
+            if o.source == source && o.key == key {
+                o.canceled.store(true, Ordering::Release);
+                found = true;
+                false
+            } else {
+                true
+            }
+        });
+        let len = inner.queue.len();
+        inner
+            .queue
+            .retain(|queued| queued.source != Some(source) || queued.packet.key != key);
+        found |= inner.queue.len() != len;
+        if inner.queue.is_empty()
+            && (inner.interrupt_queue.is_empty() || !self.can_bind_to_interrupt())
+        {
+            self.base.signal_clear(Signal::READABLE);
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(ZxError::NOT_FOUND)
+        }
+    }
+
     /// Push a `User` type `packet` into the port.
     pub fn push_user(&self, packet: impl Into<PortPacket>) -> ZxResult<()> {
         let mut packet = packet.into();
@@ -130,13 +253,13 @@ impl Port {
                     .into();
                 }
             }
-            if let Some(packet) = inner.queue.pop_front() {
+            if let Some(queued) = inner.queue.pop_front() {
                 if inner.queue.is_empty()
                     && (inner.interrupt_queue.is_empty() || !self.can_bind_to_interrupt())
                 {
                     self.base.signal_clear(Signal::READABLE);
                 }
-                return packet;
+                return queued.packet;
             }
         }
     }
@@ -153,6 +276,17 @@ impl Port {
     }
 }
 
+bitflags! {
+    /// Options of `zx_object_wait_async`.
+    pub struct WaitAsyncOptions: u32 {
+        #[allow(clippy::identity_op)]
+        /// Record the time when the signal is asserted in the packet.
+        const TIMESTAMP                = 1 << 0;
+        /// Only observe the signals asserted after the wait begins.
+        const EDGE                     = 1 << 1;
+    }
+}
+
 bitflags! {
     /// If you need this port to be bound to an interrupt, pass **BIND_TO_INTERRUPT** to *options*,
     /// otherwise it should be **0**.
@@ -231,4 +365,59 @@ mod tests {
         let packet = port.wait().await;
         assert_eq!(PortPacketRepr::from(&packet), packet_repr);
     }
+
+    #[test]
+    fn cancel() {
+        let port = Port::new(0).unwrap();
+        let object = DummyObject::new() as Arc<dyn KernelObject>;
+        object.send_signal_to_port_async(Signal::READABLE, &port, 1);
+        object.send_signal_to_port_async(Signal::WRITABLE, &port, 2);
+        assert_eq!(port.cancel(object.id(), 1), Ok(()));
+        assert_eq!(port.cancel(object.id(), 1), Err(ZxError::NOT_FOUND));
+
+        // the canceled wait never fires
+        object.signal_set(Signal::READABLE);
+        assert_eq!(port.len(), 0);
+        assert_eq!(port.signal(), Signal::empty());
+
+        // packets queued are removed
+        object.signal_set(Signal::WRITABLE);
+        assert_eq!(port.len(), 1);
+        assert_eq!(port.cancel(object.id(), 2), Ok(()));
+        assert_eq!(port.len(), 0);
+        assert_eq!(port.signal(), Signal::empty());
+    }
+
+    #[test]
+    fn dedup() {
+        let port = Port::new(0).unwrap();
+        let object = DummyObject::new() as Arc<dyn KernelObject>;
+        object.send_signal_to_port_async(Signal::READABLE, &port, 1);
+        object.send_signal_to_port_async(Signal::READABLE, &port, 1);
+        object.send_signal_to_port_async(Signal::READABLE, &port, 2);
+        object.signal_set(Signal::READABLE);
+        assert_eq!(port.len(), 2);
+    }
+
+    #[async_std::test]
+    async fn options() {
+        let port = Port::new(0).unwrap();
+        let object = DummyObject::new() as Arc<dyn KernelObject>;
+        object.signal_set(Signal::READABLE);
+        object.wait_async(Signal::READABLE, &port, 1, WaitAsyncOptions::EDGE);
+        // not fired by the signal asserted before
+        object.signal_set(Signal::WRITABLE);
+        assert_eq!(port.len(), 0);
+        object.signal_clear(Signal::READABLE);
+        object.signal_set(Signal::READABLE);
+        assert_eq!(port.len(), 1);
+        port.wait().await;
+
+        let now = kernel_hal::timer::timer_now().as_nanos() as u64;
+        object.wait_async(Signal::READABLE, &port, 1, WaitAsyncOptions::TIMESTAMP);
+        match PortPacketRepr::from(&port.wait().await).data {
+            PayloadRepr::Signal(signal) => assert!(signal.timestamp >= now),
+            _ => panic!("not a signal packet"),
+        }
+    }
 }
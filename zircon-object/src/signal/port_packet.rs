@@ -40,7 +40,7 @@ pub union Payload {
     guest_io: PacketGuestIo,
     guest_vcpu: PacketGuestVcpu,
     interrupt: PacketInterrupt,
-    // TODO: PacketPageRequest
+    page_request: PacketPageRequest,
 }
 
 pub type PacketUser = [u8; 32];
@@ -158,6 +158,34 @@ pub struct PacketInterrupt {
     pub _reserved2: u64,
 }
 
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PacketPageRequest {
+    pub command: PageRequestCommand,
+    pub flags: u16,
+    pub _reserved0: u32,
+    pub offset: u64,
+    pub length: u64,
+    pub _reserved1: u64,
+}
+
+// reference: zircon/system/public/zircon/syscalls/port.h ZX_PAGER_VMO_*
+/// The command of a page request.
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PageRequestCommand {
+    /// Supply the pages in the range.
+    Read = 0,
+    /// The VMO is detached from the pager, and no more requests follow.
+    Complete = 1,
+}
+
+impl Default for PageRequestCommand {
+    fn default() -> Self {
+        PageRequestCommand::Read
+    }
+}
+
 // Rust struct: for internal constructing and debugging
 
 /// A high-level representation of a packet sent through a port.
@@ -178,6 +206,7 @@ pub enum PayloadRepr {
     GuestIo(PacketGuestIo),
     GuestVcpu(PacketGuestVcpu),
     Interrupt(PacketInterrupt),
+    PageRequest(PacketPageRequest),
 }
 
 impl PayloadRepr {
@@ -190,6 +219,7 @@ impl PayloadRepr {
             PayloadRepr::GuestIo(_) => PacketType::GuestIo,
             PayloadRepr::GuestVcpu(_) => PacketType::GuestVcpu,
             PayloadRepr::Interrupt(_) => PacketType::Interrupt,
+            PayloadRepr::PageRequest(_) => PacketType::PageRequest,
         }
     }
     fn encode(&self) -> Payload {
@@ -201,6 +231,7 @@ impl PayloadRepr {
             PayloadRepr::GuestIo(guest_io) => Payload { guest_io },
             PayloadRepr::GuestVcpu(guest_vcpu) => Payload { guest_vcpu },
             PayloadRepr::Interrupt(interrupt) => Payload { interrupt },
+            PayloadRepr::PageRequest(page_request) => Payload { page_request },
         }
     }
     #[allow(unsafe_code)]
@@ -215,7 +246,7 @@ impl PayloadRepr {
                 PacketType::GuestIo => PayloadRepr::GuestIo(data.guest_io),
                 PacketType::GuestVcpu => PayloadRepr::GuestVcpu(data.guest_vcpu),
                 PacketType::Interrupt => PayloadRepr::Interrupt(data.interrupt),
-                _ => unimplemented!(),
+                PacketType::PageRequest => PayloadRepr::PageRequest(data.page_request),
             }
         }
     }
@@ -299,6 +330,7 @@ mod tests {
         assert_eq!(size_of::<PacketGuestIo>(), 32);
         assert_eq!(size_of::<PacketGuestVcpu>(), 32);
         assert_eq!(size_of::<PacketInterrupt>(), 32);
+        assert_eq!(size_of::<PacketPageRequest>(), 32);
     }
 
     fn test_encdec(data: PayloadRepr) {
@@ -429,9 +461,13 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "not implemented")]
     fn page_request() {
-        let data: PacketUser = [0u8; 32];
-        PayloadRepr::decode(PacketType::PageRequest, &Payload { user: data });
+        let page_request = PacketPageRequest {
+            command: PageRequestCommand::Read,
+            offset: 0x1000,
+            length: 0x2000,
+            ..Default::default()
+        };
+        test_encdec(PayloadRepr::PageRequest(page_request));
     }
 }
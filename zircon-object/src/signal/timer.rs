@@ -14,7 +14,6 @@ use lock::Mutex;
 pub struct Timer {
     base: KObjectBase,
     _counter: CountHelper,
-    #[allow(dead_code)]
     slack: Slack,
     inner: Mutex<TimerInner>,
 }
@@ -24,12 +23,14 @@ define_count_helper!(Timer);
 
 #[derive(Default)]
 struct TimerInner {
+    /// The earliest time the timer may fire.
     deadline: Option<Duration>,
+    /// Bumped on every `set` and `cancel`, so that the callbacks of earlier
+    /// settings racing with them are ignored.
+    generation: usize,
 }
 
 /// Slack specifies how much a timer or event is allowed to deviate from its deadline.
-///
-/// **Not supported: Now slack has no effect on the timer.**
 #[repr(u32)]
 #[derive(Debug, Copy, Clone)]
 pub enum Slack {
@@ -66,28 +67,56 @@ impl Timer {
 
     /// Starts a one-shot timer that will fire when `deadline` passes.
     ///
+    /// It may fire early or late by `slack`, as the slack mode of the timer
+    /// allows, to be coalesced with other timers.
+    ///
     /// If a previous call to `set` was pending, the previous timer is canceled
     /// and `Signal::SIGNALED` is de-asserted as needed.
-    pub fn set(self: &Arc<Self>, deadline: Duration, _slack: Duration) {
+    pub fn set(self: &Arc<Self>, deadline: Duration, slack: Duration) {
+        let (earliest, window) = match self.slack {
+            Slack::Center => (deadline.saturating_sub(slack), slack.saturating_mul(2)),
+            Slack::Early => (deadline.saturating_sub(slack), slack),
+            Slack::Late => (deadline, slack),
+        };
         let mut inner = self.inner.lock();
-        inner.deadline = Some(deadline);
+        inner.generation += 1;
         self.base.signal_clear(Signal::SIGNALED);
+        if earliest <= kernel_hal::timer::timer_now() {
+            // fire at once
+            inner.deadline = None;
+            self.base.signal_set(Signal::SIGNALED);
+            return;
+        }
+        inner.deadline = Some(earliest);
+        let generation = inner.generation;
         let me = Arc::downgrade(self);
-        kernel_hal::timer::timer_set(
-            deadline,
-            Box::new(move |now| me.upgrade().map(|timer| timer.touch(now)).unwrap_or(())),
+        kernel_hal::timer::timer_set_with_slack(
+            earliest,
+            window,
+            Box::new(move |now| {
+                me.upgrade()
+                    .map(|timer| timer.touch(now, generation))
+                    .unwrap_or(())
+            }),
         );
     }
 
     /// Cancel the pending timer started by `set`.
+    ///
+    /// `Signal::SIGNALED` is de-asserted, even if the timer has fired.
     pub fn cancel(&self) {
         let mut inner = self.inner.lock();
+        inner.generation += 1;
         inner.deadline = None;
+        self.base.signal_clear(Signal::SIGNALED);
     }
 
     /// Called by HAL timer.
-    fn touch(&self, now: Duration) {
+    fn touch(&self, now: Duration, generation: usize) {
         let mut inner = self.inner.lock();
+        if inner.generation != generation {
+            return;
+        }
         if let Some(deadline) = inner.deadline {
             if now >= deadline {
                 self.base.signal_set(Signal::SIGNALED);
@@ -128,6 +157,27 @@ mod tests {
         assert_eq!(timer.signal(), Signal::empty());
     }
 
+    #[test]
+    fn slack() {
+        // fires early by the slack at most
+        let timer = Timer::with_slack(Slack::Early);
+        timer.set(
+            timer_now() + Duration::from_millis(40),
+            Duration::from_millis(30),
+        );
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(timer.signal(), Signal::SIGNALED);
+
+        // fires late only
+        let timer = Timer::with_slack(Slack::Late);
+        timer.set(
+            timer_now() + Duration::from_millis(20),
+            Duration::from_millis(30),
+        );
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(timer.signal(), Signal::empty());
+    }
+
     #[test]
     fn cancel() {
         let timer = Timer::new();
@@ -138,5 +188,32 @@ mod tests {
 
         std::thread::sleep(Duration::from_millis(50));
         assert_eq!(timer.signal(), Signal::empty());
+
+        // de-asserts the signal after fired
+        let timer = Timer::one_shot(Duration::default());
+        assert_eq!(timer.signal(), Signal::SIGNALED);
+        timer.cancel();
+        assert_eq!(timer.signal(), Signal::empty());
+    }
+
+    #[test]
+    fn past_deadline() {
+        let timer = Timer::new();
+        timer.set(timer_now(), Duration::default());
+        assert_eq!(timer.signal(), Signal::SIGNALED);
+    }
+
+    #[async_std::test]
+    async fn wait_on_port() {
+        let port = Port::new(0).unwrap();
+        let timer = Timer::new();
+        (timer.clone() as Arc<dyn KernelObject>).send_signal_to_port_async(
+            Signal::SIGNALED,
+            &port,
+            1,
+        );
+        timer.set(timer_now() + Duration::from_millis(10), Duration::default());
+        let packet = PortPacketRepr::from(&port.wait().await);
+        assert_eq!(packet.key, 1);
     }
 }
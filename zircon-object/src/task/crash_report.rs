@@ -0,0 +1,193 @@
+//! Crash reports of user threads.
+
+use super::Thread;
+use crate::object::KernelObject;
+use crate::vm::{pages, VmAddressRegion, PAGE_SIZE};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::fmt::Write;
+use kernel_hal::context::{UserContext, UserContextField};
+use kernel_hal::debug::{parse_build_id, walk_frames, Markup};
+
+/// An ELF module loaded in the address space of a process.
+struct Module {
+    name: String,
+    build_id: Vec<u8>,
+    /// The start address, the size, the permissions and the address in the
+    /// module of the loaded segments.
+    segments: Vec<(usize, usize, String, usize)>,
+}
+
+/// Generate the crash report of `thread`, which failed for `reason` in the context `cx`.
+///
+/// It consists of the registers, the modules and the backtrace. The last two
+/// are in the symbolizer markup, so that the host tools can symbolize the
+/// addresses with the ELF files of the same build IDs.
+pub fn crash_report(thread: &Arc<Thread>, reason: &str, cx: &UserContext) -> String {
+    let proc = thread.proc();
+    let vmar = proc.vmar();
+    let mut cx = *cx;
+    let pc = cx.get_field(UserContextField::InstrPointer);
+    let sp = cx.get_field(UserContextField::StackPointer);
+    let fp = cx.get_field(UserContextField::FramePointer);
+    let mut s = String::new();
+    writeln!(
+        s,
+        "<== fatal exception: process {}[{}] thread {}[{}]",
+        proc.name(),
+        proc.id(),
+        thread.name(),
+        thread.id()
+    )
+    .unwrap();
+    writeln!(s, "<== {}, PC at {:#x}", reason, pc).unwrap();
+    writeln!(s, "{:#x?}", cx.general()).unwrap();
+    writeln!(s, "pc {:#x} sp {:#x} fp {:#x}", pc, sp, fp).unwrap();
+
+    writeln!(s, "{}", Markup::Reset).unwrap();
+    for (id, module) in modules(&vmar).iter().enumerate() {
+        let markup = Markup::Module {
+            id,
+            name: &module.name,
+            build_id: &module.build_id,
+        };
+        writeln!(s, "{}", markup).unwrap();
+        for (start, size, flags, module_addr) in module.segments.iter() {
+            let markup = Markup::Mmap {
+                start: *start,
+                size: *size,
+                module: id,
+                flags,
+                module_addr: *module_addr,
+            };
+            writeln!(s, "{}", markup).unwrap();
+        }
+    }
+    writeln!(
+        s,
+        "{}",
+        Markup::Backtrace {
+            frame: 0,
+            pc,
+            is_return_address: false,
+        }
+    )
+    .unwrap();
+    let frames = walk_frames(fp, |addr| read_word(&vmar, addr));
+    for (i, ra) in frames.into_iter().enumerate() {
+        let markup = Markup::Backtrace {
+            frame: i + 1,
+            pc: ra,
+            is_return_address: true,
+        };
+        writeln!(s, "{}", markup).unwrap();
+    }
+    s
+}
+
+fn read_word(vmar: &VmAddressRegion, addr: usize) -> Option<usize> {
+    let mut buf = [0u8; core::mem::size_of::<usize>()];
+    match vmar.read_memory(addr, &mut buf) {
+        Ok(len) if len == buf.len() => Some(usize::from_ne_bytes(buf)),
+        _ => None,
+    }
+}
+
+fn read_memory(vmar: &VmAddressRegion, addr: usize, len: usize) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    match vmar.read_memory(addr, &mut buf) {
+        Ok(actual) if actual == len => Some(buf),
+        _ => None,
+    }
+}
+
+/// Find the ELF modules, whose headers are at the start of their first segments.
+fn modules(vmar: &VmAddressRegion) -> Vec<Module> {
+    vmar.get_mappings_info()
+        .into_iter()
+        .filter_map(|info| {
+            let name = if info.vmo_name.is_empty() {
+                String::from("<unknown>")
+            } else {
+                info.vmo_name
+            };
+            read_module(vmar, info.addr, name)
+        })
+        .collect()
+}
+
+/// Read the ELF module loaded at `base` from the memory.
+fn read_module(vmar: &VmAddressRegion, base: usize, name: String) -> Option<Module> {
+    const PT_LOAD: u32 = 1;
+    const PT_NOTE: u32 = 4;
+    const MAX_PHNUM: usize = 64;
+    const MAX_NOTE_SIZE: usize = PAGE_SIZE;
+
+    let ehdr = read_memory(vmar, base, 64)?;
+    // only 64-bit little-endian ELF
+    if ehdr[..6] != [0x7f, b'E', b'L', b'F', 2, 1] {
+        return None;
+    }
+    let u16_at = |buf: &[u8], i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]) as usize;
+    let u32_at = |buf: &[u8], i: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[i..i + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    let u64_at = |buf: &[u8], i: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[i..i + 8]);
+        u64::from_le_bytes(bytes) as usize
+    };
+    let (phoff, phentsize, phnum) = (
+        u64_at(&ehdr, 0x20),
+        u16_at(&ehdr, 0x36),
+        u16_at(&ehdr, 0x38),
+    );
+    if phentsize < 56 || phnum > MAX_PHNUM {
+        return None;
+    }
+    let phdrs = read_memory(vmar, base.checked_add(phoff)?, phentsize * phnum)?;
+    let phdrs: Vec<&[u8]> = phdrs.chunks(phentsize).collect();
+
+    // the load bias, by which the first segment is loaded at `base`
+    let first_vaddr = phdrs
+        .iter()
+        .filter(|ph| u32_at(ph, 0) == PT_LOAD)
+        .map(|ph| u64_at(ph, 0x10))
+        .min()?;
+    let bias = base.wrapping_sub(first_vaddr / PAGE_SIZE * PAGE_SIZE);
+
+    let mut module = Module {
+        name,
+        build_id: Vec::new(),
+        segments: Vec::new(),
+    };
+    for ph in phdrs {
+        let (flags, vaddr, memsz) = (u32_at(ph, 4), u64_at(ph, 0x10), u64_at(ph, 0x28));
+        match u32_at(ph, 0) {
+            PT_LOAD => {
+                let start = vaddr / PAGE_SIZE * PAGE_SIZE;
+                let mut perms = String::new();
+                for (bit, c) in [(4, 'r'), (2, 'w'), (1, 'x')] {
+                    if flags & bit != 0 {
+                        perms.push(c);
+                    }
+                }
+                let size = pages(vaddr.checked_add(memsz)?) * PAGE_SIZE - start;
+                module
+                    .segments
+                    .push((bias.wrapping_add(start), size, perms, start));
+            }
+            PT_NOTE if module.build_id.is_empty() => {
+                let filesz = u64_at(ph, 0x20).min(MAX_NOTE_SIZE);
+                if let Some(notes) = read_memory(vmar, bias.wrapping_add(vaddr), filesz) {
+                    if let Some(build_id) = parse_build_id(&notes) {
+                        module.build_id = build_id.into();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(module)
+}
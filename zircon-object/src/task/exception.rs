@@ -1,11 +1,11 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{format, sync::Arc, vec::Vec};
 use core::mem::size_of;
 
 use futures::channel::oneshot;
 use kernel_hal::context::{TrapReason, UserContext};
 use lock::Mutex;
 
-use super::{Job, Task, Thread};
+use super::{crash_report, Job, Task, Thread};
 use crate::ipc::{Channel, MessagePacket};
 use crate::object::{Handle, KObjectBase, KernelObject, KoID, Rights, Signal};
 use crate::{impl_kobject, ZxError, ZxResult};
@@ -396,8 +396,14 @@ impl Exception {
                     .await
             }
         };
-        if result == Err(ZxError::NEXT) && !self.type_.is_synth() {
+        // a policy exception acts as a breakpoint, unlike the other synthetic ones
+        let fatal = !self.type_.is_synth() || self.type_ == ExceptionType::PolicyError;
+        if result == Err(ZxError::NEXT) && fatal {
             // Nobody handled the exception, kill myself
+            if let Ok(cx) = self.thread.context_cloned() {
+                let reason = format!("{:?}", self.type_);
+                error!("{}", crash_report(&self.thread, &reason, &cx));
+            }
             self.thread.proc().exit(super::TASK_RETCODE_SYSCALL_KILL);
         }
     }
@@ -65,6 +65,8 @@ struct JobInner {
     // if the job is killed, no more child creation should works
     killed: bool,
     timer_policy: TimerSlack,
+    /// Whether the job is killed when the system runs out of memory.
+    kill_on_oom: bool,
     self_ref: Weak<Job>,
 }
 
@@ -140,16 +142,12 @@ impl Job {
         if !inner.is_empty() {
             return Err(ZxError::BAD_STATE);
         }
+        // nothing is applied if any of the policies fails
+        let mut new_policy = inner.policy;
         for policy in policies {
-            if self.parent_policy.get_action(policy.condition).is_some() {
-                match options {
-                    SetPolicyOptions::Absolute => return Err(ZxError::ALREADY_EXISTS),
-                    SetPolicyOptions::Relative => {}
-                }
-            } else {
-                inner.policy.apply(*policy);
-            }
+            new_policy.apply(*policy, &self.parent_policy, options)?;
         }
+        inner.policy = new_policy;
         Ok(())
     }
 
@@ -186,7 +184,74 @@ impl Job {
 
     /// Get information of this job.
     pub fn get_info(&self) -> JobInfo {
-        JobInfo::default()
+        JobInfo {
+            kill_on_oom: self.kill_on_oom(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the job is killed when the system runs out of memory.
+    pub fn kill_on_oom(&self) -> bool {
+        self.inner.lock().kill_on_oom
+    }
+
+    /// Set whether the job is killed when the system runs out of memory.
+    pub fn set_kill_on_oom(&self, kill: bool) {
+        self.inner.lock().kill_on_oom = kill;
+    }
+
+    /// Get the number of bytes committed by the processes of this job and
+    /// its child jobs.
+    pub fn committed_bytes(&self) -> usize {
+        let (children, processes) = {
+            let inner = self.inner.lock();
+            (inner.children.clone(), inner.processes.clone())
+        };
+        let children_bytes: usize = children
+            .iter()
+            .filter_map(|j| j.upgrade())
+            .map(|j| j.committed_bytes())
+            .sum();
+        let processes_bytes: usize = processes
+            .iter()
+            .map(|p| p.vmar().get_task_stats().committed_bytes())
+            .sum();
+        children_bytes + processes_bytes
+    }
+
+    /// Find the job to kill when the system runs out of memory.
+    ///
+    /// Among this job and its descendants which are to be killed on OOM and
+    /// not killed yet, the deepest one is the least important. Of those as
+    /// deep, the one committing the most memory is chosen.
+    pub fn find_oom_victim(self: &Arc<Self>) -> Option<Arc<Job>> {
+        let mut victim = None;
+        self.find_oom_victim_in(0, &mut victim);
+        victim.map(|(_, _, job)| job)
+    }
+
+    fn find_oom_victim_in(
+        self: &Arc<Self>,
+        depth: usize,
+        victim: &mut Option<(usize, usize, Arc<Job>)>,
+    ) {
+        let (children, candidate) = {
+            let inner = self.inner.lock();
+            (inner.children.clone(), inner.kill_on_oom && !inner.killed)
+        };
+        if candidate {
+            let bytes = self.committed_bytes();
+            let better = match victim {
+                Some((d, b, _)) => (depth, bytes) > (*d, *b),
+                None => true,
+            };
+            if better {
+                *victim = Some((depth, bytes, self.clone()));
+            }
+        }
+        for child in children.iter().filter_map(|j| j.upgrade()) {
+            child.find_oom_victim_in(depth + 1, victim);
+        }
     }
 
     /// Check whether this job is root job.
@@ -334,6 +399,7 @@ mod tests {
         let policy = &[BasicPolicy {
             condition: PolicyCondition::BadHandle,
             action: PolicyAction::Deny,
+            overridable: false,
         }];
         root_job
             .set_policy_basic(SetPolicyOptions::Relative, policy)
@@ -347,6 +413,7 @@ mod tests {
         let policy = &[BasicPolicy {
             condition: PolicyCondition::BadHandle,
             action: PolicyAction::Allow,
+            overridable: false,
         }];
         root_job
             .set_policy_basic(SetPolicyOptions::Relative, policy)
@@ -375,6 +442,7 @@ mod tests {
         let policy = &[BasicPolicy {
             condition: PolicyCondition::WrongObject,
             action: PolicyAction::Allow,
+            overridable: false,
         }];
         job.set_policy_basic(SetPolicyOptions::Relative, policy)
             .expect("failed to set policy");
@@ -387,6 +455,7 @@ mod tests {
         let policy = &[BasicPolicy {
             condition: PolicyCondition::BadHandle,
             action: PolicyAction::Deny,
+            overridable: false,
         }];
         job.set_policy_basic(SetPolicyOptions::Relative, policy)
             .expect("failed to set policy");
@@ -402,6 +471,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn override_policy() {
+        let root_job = Job::root();
+        let parent = root_job.create_child().unwrap();
+        let policies = &[
+            BasicPolicy {
+                condition: PolicyCondition::NewChannel,
+                action: PolicyAction::Deny,
+                overridable: true,
+            },
+            BasicPolicy {
+                condition: PolicyCondition::NewEvent,
+                action: PolicyAction::Deny,
+                overridable: false,
+            },
+        ];
+        parent
+            .set_policy_basic(SetPolicyOptions::Absolute, policies)
+            .expect("failed to set policy");
+        let job = parent.create_child().unwrap();
+
+        // the same action as the parent's is not an override.
+        let same = BasicPolicy {
+            condition: PolicyCondition::NewEvent,
+            action: PolicyAction::Deny,
+            overridable: false,
+        };
+        assert!(job
+            .set_policy_basic(SetPolicyOptions::Absolute, &[same])
+            .is_ok());
+
+        // nothing is applied if one of the policies fails.
+        let allow = |condition| BasicPolicy {
+            condition,
+            action: PolicyAction::Allow,
+            overridable: false,
+        };
+        let policies = &[
+            allow(PolicyCondition::NewChannel),
+            allow(PolicyCondition::NewEvent),
+        ];
+        assert_eq!(
+            job.set_policy_basic(SetPolicyOptions::Absolute, policies),
+            Err(ZxError::ALREADY_EXISTS)
+        );
+        assert_eq!(
+            job.policy().get_action(PolicyCondition::NewChannel),
+            Some(PolicyAction::Deny)
+        );
+
+        // only the overridable policy is changed.
+        job.set_policy_basic(SetPolicyOptions::Relative, policies)
+            .expect("failed to set policy");
+        assert_eq!(
+            job.policy().get_action(PolicyCondition::NewChannel),
+            Some(PolicyAction::Allow)
+        );
+        assert_eq!(
+            job.policy().get_action(PolicyCondition::NewEvent),
+            Some(PolicyAction::Deny)
+        );
+
+        // `NewAny` is checked for each of the conditions.
+        let any = BasicPolicy {
+            condition: PolicyCondition::NewAny,
+            action: PolicyAction::Kill,
+            overridable: false,
+        };
+        let child = job.create_child().unwrap();
+        assert_eq!(
+            child.set_policy_basic(SetPolicyOptions::Absolute, &[any]),
+            Err(ZxError::ALREADY_EXISTS)
+        );
+        child
+            .set_policy_basic(SetPolicyOptions::Relative, &[any])
+            .expect("failed to set policy");
+        let policy = child.policy();
+        assert_eq!(
+            policy.get_action(PolicyCondition::NewEvent),
+            Some(PolicyAction::Deny)
+        );
+        assert_eq!(
+            policy.get_action(PolicyCondition::NewPort),
+            Some(PolicyAction::Kill)
+        );
+    }
+
+    #[test]
+    fn kill_on_oom() {
+        let job = Job::create_child(&Job::root()).unwrap();
+        assert!(!job.kill_on_oom());
+        job.set_kill_on_oom(true);
+        assert!(job.kill_on_oom());
+        assert!(job.get_info().kill_on_oom);
+    }
+
     #[test]
     fn parent_child() {
         let root_job = Job::root();
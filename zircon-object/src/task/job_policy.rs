@@ -1,11 +1,14 @@
 use crate::error::*;
 use crate::signal::Slack;
+use numeric_enum_macro::numeric_enum;
 
 /// Security and resource policies of a job.
 #[derive(Default, Copy, Clone)]
 pub struct JobPolicy {
     // TODO: use bitset
     action: [Option<PolicyAction>; 15],
+    /// Whether the action can be overridden by the child jobs.
+    overridable: [bool; 15],
 }
 
 impl JobPolicy {
@@ -14,17 +17,45 @@ impl JobPolicy {
         self.action[condition as usize]
     }
 
-    /// Apply a basic policy.
-    pub fn apply(&mut self, policy: BasicPolicy) {
-        self.action[policy.condition as usize] = Some(policy.action);
+    /// Apply a basic policy on a job whose parent has the policy `parent`.
+    ///
+    /// `PolicyCondition::NewAny` applies to all the `NEW_*` conditions.
+    /// A condition set by the parent can not be changed unless the parent
+    /// allows to override it: it is skipped with `SetPolicyOptions::Relative`,
+    /// and fails with `SetPolicyOptions::Absolute`.
+    pub fn apply(
+        &mut self,
+        policy: BasicPolicy,
+        parent: &Self,
+        options: SetPolicyOptions,
+    ) -> ZxResult {
+        let conditions = if let PolicyCondition::NewAny = policy.condition {
+            PolicyCondition::NewAny as usize..=PolicyCondition::NewProfile as usize
+        } else {
+            policy.condition as usize..=policy.condition as usize
+        };
+        for i in conditions {
+            let fixed = parent.action[i].is_some() && !parent.overridable[i];
+            if fixed && parent.action[i] != Some(policy.action) {
+                match options {
+                    SetPolicyOptions::Absolute => return Err(ZxError::ALREADY_EXISTS),
+                    SetPolicyOptions::Relative => continue,
+                }
+            }
+            self.action[i] = Some(policy.action);
+            self.overridable[i] = policy.overridable;
+        }
+        Ok(())
     }
 
     /// Merge the policy with `parent`'s.
     pub fn merge(&self, parent: &Self) -> Self {
         let mut new = *self;
         for i in 0..15 {
-            if parent.action[i].is_some() {
+            let overridden = parent.overridable[i] && self.action[i].is_some();
+            if parent.action[i].is_some() && !overridden {
                 new.action[i] = parent.action[i];
+                new.overridable[i] = parent.overridable[i];
             }
         }
         new
@@ -49,8 +80,11 @@ pub struct BasicPolicy {
     pub condition: PolicyCondition,
     ///
     pub action: PolicyAction,
+    /// Whether the child jobs can override the policy.
+    pub overridable: bool,
 }
 
+numeric_enum! {
 /// The condition when a policy is applied.
 #[repr(u32)]
 #[derive(Debug, Copy, Clone)]
@@ -92,7 +126,9 @@ pub enum PolicyCondition {
     /// with a ZX_HANDLE_INVALID as the second argument rather than a valid ZX_RSRC_KIND_VMEX.
     AmbientMarkVMOExec = 14,
 }
+}
 
+numeric_enum! {
 /// The action taken when the condition happens specified by a policy.
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -109,6 +145,7 @@ pub enum PolicyAction {
     /// Terminate the process.
     Kill = 4,
 }
+}
 
 /// Timer slack policy.
 ///
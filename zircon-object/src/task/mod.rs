@@ -3,16 +3,18 @@
 use super::*;
 use alloc::sync::Arc;
 
+mod crash_report;
 mod exception;
 mod job;
 mod job_policy;
 mod process;
+mod profile;
 mod suspend_token;
 mod thread;
 
 pub use {
-    self::exception::*, self::job::*, self::job_policy::*, self::process::*,
-    self::suspend_token::*, self::thread::*,
+    self::crash_report::*, self::exception::*, self::job::*, self::job_policy::*, self::process::*,
+    self::profile::*, self::suspend_token::*, self::thread::*,
 };
 
 /// Task (Thread, Process, or Job)
@@ -36,3 +38,6 @@ pub trait Task: Sync + Send {
 
 /// The return code set when a task is killed via zx_task_kill().
 pub const TASK_RETCODE_SYSCALL_KILL: i64 = -1028;
+
+/// The return code set when a process is killed by a job policy.
+pub const TASK_RETCODE_POLICY_KILL: i64 = -1026;
@@ -10,8 +10,9 @@ use super::job_policy::{JobPolicy, PolicyAction, PolicyCondition};
 use super::{Job, Task, Thread, ThreadFn};
 use crate::object::{Handle, HandleBasicInfo, HandleValue, INVALID_HANDLE};
 use crate::object::{KObjectBase, KernelObject, KoID, Rights, Signal};
+use crate::vm::{VmAddressRegion, VmObject, VmoInfo, VmoInfoFlags};
 use crate::{define_count_helper, impl_kobject};
-use crate::{signal::Futex, vm::VmAddressRegion, ZxError, ZxResult};
+use crate::{signal::Futex, ZxError, ZxResult};
 
 /// Process abstraction
 ///
@@ -84,6 +85,9 @@ struct ProcessInner {
     handles: HashMap<HandleValue, (Handle, Vec<Sender<()>>)>,
     futexes: HashMap<usize, Arc<Futex>>,
     threads: Vec<Arc<Thread>>,
+    /// The number of existing `SuspendToken`s of the process, which also
+    /// suspend the threads created later.
+    suspend_count: usize,
 
     // special info
     debug_addr: usize,
@@ -243,6 +247,10 @@ impl Process {
     }
 
     /// Check whether `condition` is allowed in the parent job's policy.
+    ///
+    /// The process is killed if the action is `PolicyAction::Kill`. With the
+    /// `*Exception` actions, a policy exception is raised on the current
+    /// thread if it belongs to the process.
     pub fn check_policy(&self, condition: PolicyCondition) -> ZxResult {
         match self
             .policy
@@ -251,10 +259,38 @@ impl Process {
         {
             PolicyAction::Allow => Ok(()),
             PolicyAction::Deny => Err(ZxError::ACCESS_DENIED),
-            _ => unimplemented!(),
+            PolicyAction::AllowException => {
+                self.signal_policy_exception();
+                Ok(())
+            }
+            PolicyAction::DenyException => {
+                self.signal_policy_exception();
+                Err(ZxError::ACCESS_DENIED)
+            }
+            PolicyAction::Kill => {
+                self.exit(super::TASK_RETCODE_POLICY_KILL);
+                Err(ZxError::ACCESS_DENIED)
+            }
         }
     }
 
+    /// Raise a policy exception on the current thread, if it is in the process.
+    fn signal_policy_exception(&self) {
+        let current = kernel_hal::thread::get_current_thread()
+            .and_then(|thread| thread.downcast::<Thread>().ok());
+        match current {
+            Some(thread) if thread.proc().id() == self.id() => thread.signal_policy_exception(),
+            _ => warn!("policy exception out of process {}", self.id()),
+        }
+    }
+
+    /// Apply the policy of `condition` on a failed handle operation, and
+    /// return `err` whether allowed or not.
+    fn policy_error(&self, condition: PolicyCondition, err: ZxError) -> ZxError {
+        self.check_policy(condition).ok();
+        err
+    }
+
     /// Set a process as critical to the job.
     ///
     /// When process terminates, job will be terminated as if `task_kill()` was
@@ -358,7 +394,19 @@ impl Process {
 
     /// Get a handle from the process
     fn get_handle(&self, handle_value: HandleValue) -> ZxResult<Handle> {
-        self.inner.lock().get_handle(handle_value)
+        let ret = self.inner.lock().get_handle(handle_value);
+        match ret {
+            Err(ZxError::BAD_HANDLE) if handle_value != INVALID_HANDLE => {
+                Err(self.policy_error(PolicyCondition::BadHandle, ZxError::BAD_HANDLE))
+            }
+            ret => ret,
+        }
+    }
+
+    fn downcast_object<T: KernelObject>(&self, object: Arc<dyn KernelObject>) -> ZxResult<Arc<T>> {
+        object
+            .downcast_arc::<T>()
+            .map_err(|_| self.policy_error(PolicyCondition::WrongObject, ZxError::WRONG_TYPE))
     }
 
     /// Get a futex from the process
@@ -400,7 +448,7 @@ impl Process {
         desired_rights: Rights,
     ) -> ZxResult<Arc<T>> {
         self.get_dyn_object_with_rights(handle_value, desired_rights)
-            .and_then(|obj| obj.downcast_arc::<T>().map_err(|_| ZxError::WRONG_TYPE))
+            .and_then(|obj| self.downcast_object(obj))
     }
 
     /// Get the kernel object corresponding to this `handle_value` and this handle's rights.
@@ -409,10 +457,7 @@ impl Process {
         handle_value: HandleValue,
     ) -> ZxResult<(Arc<T>, Rights)> {
         let (object, rights) = self.get_dyn_object_and_rights(handle_value)?;
-        let object = object
-            .downcast_arc::<T>()
-            .map_err(|_| ZxError::WRONG_TYPE)?;
-        Ok((object, rights))
+        Ok((self.downcast_object(object)?, rights))
     }
 
     /// Get the kernel object corresponding to this `handle_value`,
@@ -442,11 +487,7 @@ impl Process {
     /// Get the kernel object corresponding to this `handle_value`
     pub fn get_object<T: KernelObject>(&self, handle_value: HandleValue) -> ZxResult<Arc<T>> {
         let handle = self.get_handle(handle_value)?;
-        let object = handle
-            .object
-            .downcast_arc::<T>()
-            .map_err(|_| ZxError::WRONG_TYPE)?;
-        Ok(object)
+        self.downcast_object(handle.object)
     }
 
     /// Get the handle's information corresponding to `handle_value`.
@@ -461,6 +502,9 @@ impl Process {
         if let Status::Exited(_) = inner.status {
             return Err(ZxError::BAD_STATE);
         }
+        for _ in 0..inner.suspend_count {
+            thread.suspend();
+        }
         inner.threads.push(thread);
         Ok(())
     }
@@ -501,6 +545,30 @@ impl Process {
         info
     }
 
+    /// Get information of the VMOs the process has handles to or maps, for
+    /// `ZX_INFO_PROCESS_VMOS`. A VMO may appear more than once.
+    pub fn get_vmos_info(&self) -> Vec<VmoInfo> {
+        let mut infos: Vec<VmoInfo> = self
+            .inner
+            .lock()
+            .handles
+            .values()
+            .filter_map(|(handle, _)| {
+                let vmo = handle.object.clone().downcast_arc::<VmObject>().ok()?;
+                let mut info = vmo.get_info();
+                info.flags |= VmoInfoFlags::VIA_HANDLE;
+                info.rights |= handle.rights;
+                Some(info)
+            })
+            .collect();
+        infos.extend(self.vmar().get_mapped_vmos().iter().map(|vmo| {
+            let mut info = vmo.get_info();
+            info.flags |= VmoInfoFlags::VIA_MAPPING;
+            info
+        }));
+        infos
+    }
+
     /// Set the debug address.
     pub fn set_debug_addr(&self, addr: usize) {
         self.inner.lock().debug_addr = addr;
@@ -555,14 +623,17 @@ impl Task for Process {
     }
 
     fn suspend(&self) {
-        let inner = self.inner.lock();
+        let mut inner = self.inner.lock();
+        inner.suspend_count += 1;
         for thread in inner.threads.iter() {
             thread.suspend();
         }
     }
 
     fn resume(&self) {
-        let inner = self.inner.lock();
+        let mut inner = self.inner.lock();
+        assert_ne!(inner.suspend_count, 0);
+        inner.suspend_count -= 1;
         for thread in inner.threads.iter() {
             thread.resume();
         }
@@ -795,6 +866,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn suspend() {
+        let root_job = Job::root();
+        let proc = Process::create(&root_job, "proc").expect("failed to create process");
+        let thread = Thread::create(&proc, "thread").expect("failed to create thread");
+
+        let task: Arc<dyn Task> = proc.clone();
+        let token = SuspendToken::create(&task);
+        assert_eq!(thread.state(), ThreadState::Suspended);
+        // the threads created later are suspended too
+        let thread1 = Thread::create(&proc, "thread1").expect("failed to create thread");
+        assert_eq!(thread1.state(), ThreadState::Suspended);
+
+        drop(token);
+        assert_eq!(thread.state(), ThreadState::New);
+        assert_eq!(thread1.state(), ThreadState::New);
+    }
+
     #[test]
     fn contains_thread() {
         let root_job = Job::root();
@@ -814,10 +903,12 @@ mod tests {
         let policy1 = BasicPolicy {
             condition: PolicyCondition::BadHandle,
             action: PolicyAction::Allow,
+            overridable: false,
         };
         let policy2 = BasicPolicy {
             condition: PolicyCondition::NewChannel,
             action: PolicyAction::Deny,
+            overridable: false,
         };
 
         assert!(root_job
@@ -840,4 +931,62 @@ mod tests {
             Some(ZxError::BAD_STATE)
         );
     }
+
+    #[test]
+    fn policy_kill() {
+        let root_job = Job::root();
+        let job = root_job.create_child().unwrap();
+        let policy = BasicPolicy {
+            condition: PolicyCondition::NewAny,
+            action: PolicyAction::Kill,
+            overridable: false,
+        };
+        job.set_policy_basic(SetPolicyOptions::Absolute, &[policy])
+            .unwrap();
+        let proc = Process::create(&job, "proc").expect("failed to create process");
+
+        assert!(proc.check_policy(PolicyCondition::BadHandle).is_ok());
+        assert_eq!(
+            proc.check_policy(PolicyCondition::NewPort).err(),
+            Some(ZxError::ACCESS_DENIED)
+        );
+        assert_eq!(proc.exit_code(), Some(TASK_RETCODE_POLICY_KILL));
+    }
+
+    #[test]
+    fn policy_exception() {
+        let root_job = Job::root();
+        let job = root_job.create_child().unwrap();
+        let policy = |condition, action| BasicPolicy {
+            condition,
+            action,
+            overridable: false,
+        };
+        let policies = &[
+            policy(PolicyCondition::NewPort, PolicyAction::AllowException),
+            policy(PolicyCondition::NewEvent, PolicyAction::DenyException),
+        ];
+        job.set_policy_basic(SetPolicyOptions::Absolute, policies)
+            .unwrap();
+        let proc = Process::create(&job, "proc").expect("failed to create process");
+        let thread = Thread::create(&proc, "thread").expect("failed to create thread");
+        kernel_hal::thread::set_current_thread(Some(thread.clone()));
+
+        assert!(proc.check_policy(PolicyCondition::NewPort).is_ok());
+        assert!(thread.take_policy_exception());
+        assert!(!thread.take_policy_exception());
+        assert_eq!(
+            proc.check_policy(PolicyCondition::NewEvent).err(),
+            Some(ZxError::ACCESS_DENIED)
+        );
+        assert!(thread.take_policy_exception());
+        assert!(proc.check_policy(PolicyCondition::NewChannel).is_ok());
+        assert!(!thread.take_policy_exception());
+
+        // not raised on the threads of other processes
+        let other = Process::create(&job, "other").expect("failed to create process");
+        assert!(other.check_policy(PolicyCondition::NewPort).is_ok());
+        assert!(!thread.take_policy_exception());
+        kernel_hal::thread::set_current_thread(None);
+    }
 }
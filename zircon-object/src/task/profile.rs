@@ -0,0 +1,111 @@
+use {super::*, crate::object::*, alloc::sync::Arc, bitflags::bitflags};
+
+/// The lowest scheduling priority of threads.
+pub const PRIORITY_LOWEST: i32 = 0;
+/// The default scheduling priority of threads.
+pub const PRIORITY_DEFAULT: i32 = 16;
+/// The highest scheduling priority of threads.
+pub const PRIORITY_HIGHEST: i32 = 31;
+
+/// Scheduling profile
+///
+/// ## SYNOPSIS
+///
+/// A profile holds the scheduling parameters, the priority and the CPU
+/// affinity, to be applied to threads with `zx_object_set_profile()`.
+pub struct Profile {
+    base: KObjectBase,
+    info: ProfileInfo,
+}
+
+impl_kobject!(Profile);
+
+impl Profile {
+    /// Create a new profile with the scheduling parameters in `info`.
+    pub fn create(info: &ProfileInfo) -> ZxResult<Arc<Self>> {
+        let flags = ProfileInfoFlags::from_bits(info.flags).ok_or(ZxError::INVALID_ARGS)?;
+        if flags.is_empty() {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        if flags.contains(ProfileInfoFlags::DEADLINE) {
+            return Err(ZxError::NOT_SUPPORTED);
+        }
+        if flags.contains(ProfileInfoFlags::PRIORITY)
+            && !(PRIORITY_LOWEST..=PRIORITY_HIGHEST).contains(&info.priority)
+        {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        Ok(Arc::new(Profile {
+            base: KObjectBase::new(),
+            info: *info,
+        }))
+    }
+
+    /// Get the priority set by the profile.
+    pub fn priority(&self) -> Option<i32> {
+        self.flags()
+            .contains(ProfileInfoFlags::PRIORITY)
+            .then(|| self.info.priority)
+    }
+
+    /// Get the CPU affinity mask set by the profile.
+    pub fn cpu_affinity_mask(&self) -> Option<[u64; 8]> {
+        self.flags()
+            .contains(ProfileInfoFlags::CPU_MASK)
+            .then(|| self.info.cpu_affinity_mask)
+    }
+
+    fn flags(&self) -> ProfileInfoFlags {
+        ProfileInfoFlags::from_bits_truncate(self.info.flags)
+    }
+}
+
+bitflags! {
+    /// Scheduling parameters set by a profile.
+    pub struct ProfileInfoFlags: u32 {
+        #[allow(clippy::identity_op)]
+        /// The priority is set.
+        const PRIORITY = 1 << 0;
+        /// The CPU affinity mask is set.
+        const CPU_MASK = 1 << 1;
+        /// The deadline parameters are set.
+        const DEADLINE = 1 << 2;
+    }
+}
+
+/// Scheduling parameters of a profile.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProfileInfo {
+    /// Which parameters are set, of [`ProfileInfoFlags`].
+    pub flags: u32,
+    padding1: u32,
+    /// The priority, from [`PRIORITY_LOWEST`] to [`PRIORITY_HIGHEST`].
+    pub priority: i32,
+    padding2: [u8; 20],
+    /// The CPUs the threads may run on, one bit for each CPU.
+    pub cpu_affinity_mask: [u64; 8],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create() {
+        let mut info = ProfileInfo::default();
+        assert_eq!(Profile::create(&info).err(), Some(ZxError::INVALID_ARGS));
+
+        info.flags = ProfileInfoFlags::PRIORITY.bits();
+        info.priority = PRIORITY_HIGHEST + 1;
+        assert_eq!(Profile::create(&info).err(), Some(ZxError::INVALID_ARGS));
+
+        info.priority = PRIORITY_HIGHEST;
+        let profile = Profile::create(&info).unwrap();
+        assert_eq!(profile.priority(), Some(PRIORITY_HIGHEST));
+        assert_eq!(profile.cpu_affinity_mask(), None);
+
+        info.flags = ProfileInfoFlags::DEADLINE.bits();
+        assert_eq!(Profile::create(&info).err(), Some(ZxError::NOT_SUPPORTED));
+    }
+}
@@ -2,7 +2,7 @@ mod thread_state;
 
 pub use self::thread_state::ThreadStateKind;
 
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::task::{Context, Poll, Waker};
 use core::time::Duration;
 use core::{any::Any, future::Future, pin::Pin};
@@ -14,9 +14,10 @@ use kernel_hal::context::UserContext;
 use lock::Mutex;
 
 use self::thread_state::ContextAccessState;
-use super::{exception::*, Process, Task};
+use super::{exception::*, Process, Profile, Task, PRIORITY_DEFAULT};
 use crate::object::{KObjectBase, KoID, Signal};
-use crate::{define_count_helper, impl_kobject, ZxError, ZxResult};
+use crate::util::ktrace;
+use crate::{define_count_helper, impl_kobject, kcounter, ZxError, ZxResult};
 
 /// Runnable / computation entity
 ///
@@ -128,9 +129,20 @@ struct ThreadInner {
     first_thread: bool,
     /// Should The ThreadExiting exception do not block this thread
     killed: bool,
+    /// A policy exception is raised on the return from the current syscall
+    policy_exception: bool,
     /// The time this thread has run on cpu
     time: u128,
     flags: ThreadFlag,
+    /// The scheduling priority, set by a profile
+    priority: i32,
+    /// The priorities donated by threads waiting for this one, by the
+    /// objects they wait on
+    donated_priorities: Vec<(KoID, i32)>,
+    /// The CPUs the thread may run on, one bit for each, or none for any
+    cpu_affinity_mask: [u64; 8],
+    /// The CPU the thread was scheduled on the last time
+    last_scheduled_cpu: u32,
 }
 
 impl ThreadInner {
@@ -225,6 +237,7 @@ impl Thread {
             exceptionate: Exceptionate::new(ExceptionChannelType::Thread),
             inner: Mutex::new(ThreadInner {
                 context: Some(Box::new(UserContext::new())),
+                priority: PRIORITY_DEFAULT,
                 ..Default::default()
             }),
         });
@@ -367,7 +380,64 @@ impl Thread {
                 .exception
                 .as_ref()
                 .map_or(0, |exception| exception.current_channel_type() as u32),
-            cpu_affinity_mask: [0u64; 8],
+            cpu_affinity_mask: inner.cpu_affinity_mask,
+        }
+    }
+
+    /// Apply the scheduling parameters set by `profile` to the thread.
+    pub fn set_profile(&self, profile: &Profile) {
+        let mut inner = self.inner.lock();
+        if let Some(priority) = profile.priority() {
+            inner.priority = priority;
+        }
+        if let Some(mask) = profile.cpu_affinity_mask() {
+            inner.cpu_affinity_mask = mask;
+        }
+    }
+
+    /// Get the CPUs the thread may run on, one bit for each, or none for any.
+    pub fn cpu_affinity_mask(&self) -> [u64; 8] {
+        self.inner.lock().cpu_affinity_mask
+    }
+
+    /// Set the CPUs the thread may run on, one bit for each, or none for any.
+    pub fn set_cpu_affinity_mask(&self, mask: [u64; 8]) {
+        self.inner.lock().cpu_affinity_mask = mask;
+    }
+
+    /// Get the thread's scheduling statistics.
+    pub fn get_thread_stats(&self) -> ThreadStats {
+        let inner = self.inner.lock();
+        ThreadStats {
+            total_runtime: inner.time as i64,
+            last_scheduled_cpu: inner.last_scheduled_cpu,
+        }
+    }
+
+    /// Get the scheduling priority of the thread.
+    pub fn priority(&self) -> i32 {
+        self.inner.lock().priority
+    }
+
+    /// Get the priority the thread is scheduled with, the highest of its
+    /// own and the donated ones.
+    pub fn effective_priority(&self) -> i32 {
+        let inner = self.inner.lock();
+        inner
+            .donated_priorities
+            .iter()
+            .map(|&(_, priority)| priority)
+            .fold(inner.priority, i32::max)
+    }
+
+    /// Donate `priority` to the thread on behalf of the object `source`,
+    /// which threads of the priority wait on and this thread owns. It
+    /// replaces the donation of `source` before, and `None` withdraws it.
+    pub fn donate_priority(&self, source: KoID, priority: Option<i32>) {
+        let mut inner = self.inner.lock();
+        inner.donated_priorities.retain(|&(id, _)| id != source);
+        if let Some(priority) = priority {
+            inner.donated_priorities.push((source, priority));
         }
     }
 
@@ -406,6 +476,17 @@ impl Thread {
         self.inner.lock().first_thread
     }
 
+    /// Raise a policy exception, which is handled on the return from the
+    /// current syscall, when the syscall has completed.
+    pub fn signal_policy_exception(&self) {
+        self.inner.lock().policy_exception = true;
+    }
+
+    /// Take the policy exception raised by the current syscall.
+    pub fn take_policy_exception(&self) -> bool {
+        core::mem::take(&mut self.inner.lock().policy_exception)
+    }
+
     /// Get the thread's flags.
     pub fn flags(&self) -> ThreadFlag {
         self.inner.lock().flags
@@ -513,6 +594,13 @@ impl CurrentThread {
         }
     }
 
+    /// Give up the CPU on a timer tick, if the time slice is used up.
+    pub async fn preempt(&self) {
+        if kernel_hal::thread::need_resched() {
+            kernel_hal::thread::yield_now().await;
+        }
+    }
+
     /// The thread ends running and takes back the context.
     pub fn put_context(&self, context: Box<UserContext>) {
         let mut inner = self.inner.lock();
@@ -720,6 +808,17 @@ pub struct ThreadInfo {
     cpu_affinity_mask: [u64; 8],
 }
 
+/// The scheduling statistics of a thread.
+#[repr(C)]
+pub struct ThreadStats {
+    /// The time the thread has run on CPUs, in nanoseconds.
+    total_runtime: i64,
+    /// The CPU the thread was scheduled on the last time.
+    last_scheduled_cpu: u32,
+}
+
+kcounter!(CONTEXT_SWITCHES, "thread.context_switch");
+
 struct ThreadSwitchFuture {
     thread: Arc<Thread>,
     future: Mutex<ThreadFuturePinned>,
@@ -745,9 +844,18 @@ impl Future for ThreadSwitchFuture {
                 kernel_hal::vm::activate_paging(self.thread.proc().vmar().table_phys());
             }
         }
+        CONTEXT_SWITCHES.add(1);
+        ktrace::context_switch(None, Some(&self.thread));
         kernel_hal::thread::set_current_thread(Some(self.thread.clone()));
+        kernel_hal::thread::set_priority(self.thread.effective_priority() as usize);
+        {
+            let mut inner = self.thread.inner.lock();
+            kernel_hal::thread::set_cpu_affinity(inner.cpu_affinity_mask[0] as usize);
+            inner.last_scheduled_cpu = kernel_hal::cpu::cpu_id() as u32;
+        }
         let ret = self.future.lock().as_mut().poll(cx);
         kernel_hal::thread::set_current_thread(None);
+        ktrace::context_switch(Some(&self.thread), None);
         ret
     }
 }
@@ -888,6 +996,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn profile() {
+        let root_job = Job::root();
+        let proc = Process::create(&root_job, "proc").expect("failed to create process");
+        let thread = Thread::create(&proc, "thread").expect("failed to create thread");
+        assert_eq!(thread.priority(), PRIORITY_DEFAULT);
+
+        let mut info = ProfileInfo {
+            flags: (ProfileInfoFlags::PRIORITY | ProfileInfoFlags::CPU_MASK).bits(),
+            priority: PRIORITY_HIGHEST,
+            ..Default::default()
+        };
+        info.cpu_affinity_mask[0] = 0b11;
+        thread.set_profile(&Profile::create(&info).unwrap());
+        assert_eq!(thread.priority(), PRIORITY_HIGHEST);
+        assert_eq!(thread.get_thread_info().cpu_affinity_mask[0], 0b11);
+
+        // only the parameters set by the profile are changed
+        info.flags = ProfileInfoFlags::PRIORITY.bits();
+        info.priority = PRIORITY_LOWEST;
+        info.cpu_affinity_mask[0] = 0;
+        thread.set_profile(&Profile::create(&info).unwrap());
+        assert_eq!(thread.priority(), PRIORITY_LOWEST);
+        assert_eq!(thread.get_thread_info().cpu_affinity_mask[0], 0b11);
+    }
+
     #[test]
     fn read_write_state() {
         let root_job = Job::root();
@@ -921,7 +1055,59 @@ mod tests {
             .read_state(ThreadStateKind::General, &mut buf)
             .is_ok());
         assert!(thread.write_state(ThreadStateKind::General, &buf).is_ok());
-        // TODO
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut step = [0u8; 4];
+            thread
+                .write_state(ThreadStateKind::SingleStep, &1u32.to_ne_bytes())
+                .unwrap();
+            thread
+                .read_state(ThreadStateKind::SingleStep, &mut step)
+                .unwrap();
+            assert_eq!(u32::from_ne_bytes(step), 1);
+            assert_eq!(
+                thread.write_state(ThreadStateKind::SingleStep, &2u32.to_ne_bytes()),
+                Err(ZxError::INVALID_ARGS)
+            );
+
+            // the x87 and SSE state, of 160 and 2120 bytes
+            let mut fp = [0u8; 160];
+            assert_eq!(
+                thread.read_state(ThreadStateKind::FloatPoint, &mut fp),
+                Ok(160)
+            );
+            assert_eq!(u16::from_ne_bytes([fp[0], fp[1]]), 0x37f);
+            let mut vector = [0u8; 2120];
+            vector[16] = 0xab;
+            thread
+                .write_state(ThreadStateKind::Vector, &vector)
+                .unwrap();
+            let mut read = [0u8; 2120];
+            thread
+                .read_state(ThreadStateKind::Vector, &mut read)
+                .unwrap();
+            assert_eq!(read[16], 0);
+            assert_eq!(read[64], 0);
+            vector[64] = 0xab;
+            thread
+                .write_state(ThreadStateKind::Vector, &vector)
+                .unwrap();
+            thread
+                .read_state(ThreadStateKind::Vector, &mut read)
+                .unwrap();
+            assert_eq!(read[64], 0xab);
+
+            // only local breakpoints in user space
+            let mut debug = [0u8; 48];
+            debug[40] = 0x2; // G0 in DR7
+            assert_eq!(
+                thread.write_state(ThreadStateKind::Debug, &debug),
+                Err(ZxError::INVALID_ARGS)
+            );
+            debug[40] = 0x1; // L0 in DR7
+            thread.write_state(ThreadStateKind::Debug, &debug).unwrap();
+        }
     }
 
     #[test]
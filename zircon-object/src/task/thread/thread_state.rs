@@ -25,6 +25,15 @@ impl ContextAccessState for UserContext {
     fn read_state(&self, kind: ThreadStateKind, buf: &mut [u8]) -> ZxResult<usize> {
         match kind {
             ThreadStateKind::General => buf.write_struct(self.general()),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::FloatPoint => buf.write_struct(&FpRegs::from(self.vector())),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::Vector => buf.write_struct(&VectorRegs::from(self.vector())),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::Debug => buf.write_struct(self.debug()),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::SingleStep => buf.write_struct(&(self.single_step() as u32)),
+            #[allow(unreachable_patterns)]
             _ => Err(ZxError::NOT_SUPPORTED),
         }
     }
@@ -32,12 +41,142 @@ impl ContextAccessState for UserContext {
     fn write_state(&mut self, kind: ThreadStateKind, buf: &[u8]) -> ZxResult {
         match kind {
             ThreadStateKind::General => *self.general_mut() = buf.read_struct()?,
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::FloatPoint => buf.read_struct::<FpRegs>()?.apply(self.vector_mut()),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::Vector => buf.read_struct::<VectorRegs>()?.apply(self.vector_mut()),
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::Debug => {
+                let regs: DebugRegs = buf.read_struct()?;
+                check_debug_regs(&regs)?;
+                *self.debug_mut() = regs;
+            }
+            #[cfg(target_arch = "x86_64")]
+            ThreadStateKind::SingleStep => match buf.read_struct::<u32>()? {
+                0 => self.set_single_step(false),
+                1 => self.set_single_step(true),
+                _ => return Err(ZxError::INVALID_ARGS),
+            },
+            #[allow(unreachable_patterns)]
             _ => return Err(ZxError::NOT_SUPPORTED),
         }
         Ok(())
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        use crate::vm::{USER_ASPACE_BASE, USER_ASPACE_SIZE};
+        use kernel_hal::context::{DebugRegs, VectorRegs as FxsaveArea, U128};
+
+        /// The x87 FPU state, `zx_thread_state_fp_regs_t`.
+        #[repr(C)]
+        #[derive(Debug, Clone, Copy)]
+        struct FpRegs {
+            fcw: u16,
+            fsw: u16,
+            ftw: u8,
+            reserved: u8,
+            fop: u16,
+            fip: u64,
+            fdp: u64,
+            padding1: [u8; 8],
+            st: [U128; 8],
+        }
+
+        impl From<&FxsaveArea> for FpRegs {
+            fn from(area: &FxsaveArea) -> Self {
+                FpRegs {
+                    fcw: area.fcw,
+                    fsw: area.fsw,
+                    ftw: area.ftw,
+                    reserved: 0,
+                    fop: area.fop,
+                    // the 64-bit layout of `fxsave64`
+                    fip: area.fip as u64 | (area.fcs as u64) << 32 | (area._pad1 as u64) << 48,
+                    fdp: area.fdp as u64 | (area.fds as u64) << 32 | (area._pad2 as u64) << 48,
+                    padding1: [0; 8],
+                    st: area.mm,
+                }
+            }
+        }
+
+        impl FpRegs {
+            fn apply(&self, area: &mut FxsaveArea) {
+                area.fcw = self.fcw;
+                area.fsw = self.fsw;
+                area.ftw = self.ftw;
+                area.fop = self.fop;
+                area.fip = self.fip as u32;
+                area.fcs = (self.fip >> 32) as u16;
+                area._pad1 = (self.fip >> 48) as u16;
+                area.fdp = self.fdp as u32;
+                area.fds = (self.fdp >> 32) as u16;
+                area._pad2 = (self.fdp >> 48) as u16;
+                area.mm = self.st;
+            }
+        }
+
+        /// The SSE state, `zx_thread_state_vector_regs_t`.
+        ///
+        /// Only the lower 128 bits of the first 16 registers are supported, as
+        /// AVX is not enabled.
+        #[repr(C)]
+        #[derive(Debug, Clone, Copy)]
+        struct VectorRegs {
+            zmm: [[u64; 8]; 32],
+            opmask: [u64; 8],
+            mxcsr: u32,
+            padding1: [u8; 4],
+        }
+
+        impl From<&FxsaveArea> for VectorRegs {
+            fn from(area: &FxsaveArea) -> Self {
+                let mut regs = VectorRegs {
+                    zmm: [[0; 8]; 32],
+                    opmask: [0; 8],
+                    mxcsr: area.mxcsr,
+                    padding1: [0; 4],
+                };
+                for (zmm, xmm) in regs.zmm.iter_mut().zip(area.xmm.iter()) {
+                    zmm[..2].copy_from_slice(&xmm.0);
+                }
+                regs
+            }
+        }
+
+        impl VectorRegs {
+            fn apply(&self, area: &mut FxsaveArea) {
+                // the default mask if not reported by the processor
+                let mask = match area.mxcsr_mask {
+                    0 => 0xffbf,
+                    mask => mask,
+                };
+                area.mxcsr = self.mxcsr & mask;
+                for (xmm, zmm) in area.xmm.iter_mut().zip(self.zmm.iter()) {
+                    xmm.0.copy_from_slice(&zmm[..2]);
+                }
+            }
+        }
+
+        /// Check the breakpoints are local ones in user space.
+        fn check_debug_regs(regs: &DebugRegs) -> ZxResult {
+            // the global enable bits, general detect, and the reserved bits
+            const DR7_INVALID: u64 = 0xffff_ffff_0000_f8aa;
+            if regs.dr7 & DR7_INVALID != 0 {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            for (i, &addr) in regs.dr.iter().enumerate() {
+                let enabled = regs.dr7 & (1 << (i * 2)) != 0;
+                if enabled && !(USER_ASPACE_BASE..USER_ASPACE_BASE + USER_ASPACE_SIZE).contains(&addr) {
+                    return Err(ZxError::INVALID_ARGS);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
 trait BufExt {
     fn read_struct<T>(&self) -> ZxResult<T>;
     fn write_struct<T: Copy>(&mut self, value: &T) -> ZxResult<usize>;
@@ -141,8 +141,8 @@ impl AllCounters {
         unsafe { from_raw_parts(desc_vmo_start as *const _, desc_vmo_end - desc_vmo_start) }
     }
 
-    /// Data of the kcounter arena VMO, consists of the [`DescriptorVmoHeader`]
-    /// and an table of [`Descriptor`].
+    /// Data of the kcounter arena VMO, consists of the [`Counter`]s in the
+    /// same order as the descriptor table.
     pub fn raw_arena_vmo_data() -> &'static [u8] {
         let arena_vmo_start = kcounters_arena_start as usize;
         let arena_vmo_end = kcounters_arena_end as usize;
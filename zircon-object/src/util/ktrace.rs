@@ -0,0 +1,470 @@
+//! Kernel tracing.
+//!
+//! Events are recorded into per-CPU buffers in a layout based on the ktrace
+//! records of Zircon, which userspace reads with `zx_ktrace_read`. The buffers
+//! can also be converted to the Fuchsia trace format by [`export_fxt`], which
+//! is understood by the Perfetto UI and the Fuchsia trace viewer.
+//!
+//! All records except the metadata are 32 bytes: a 16-byte header of the tag,
+//! the thread koid and the timestamp in nanoseconds, followed by 4 arguments.
+
+use crate::task::Thread;
+use crate::{ZxError, ZxResult};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::convert::TryInto;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lock::Mutex;
+
+/// Metadata, always recorded.
+pub const KTRACE_GRP_META: u32 = 0x001;
+/// Creation and destruction of objects.
+pub const KTRACE_GRP_LIFECYCLE: u32 = 0x002;
+/// Context switches.
+pub const KTRACE_GRP_SCHEDULER: u32 = 0x004;
+/// Tasks.
+pub const KTRACE_GRP_TASKS: u32 = 0x008;
+/// IPC.
+pub const KTRACE_GRP_IPC: u32 = 0x010;
+/// Interrupts.
+pub const KTRACE_GRP_IRQ: u32 = 0x020;
+/// Probes written by the kernel or `zx_ktrace_write`.
+pub const KTRACE_GRP_PROBE: u32 = 0x040;
+/// Architecture specific events.
+pub const KTRACE_GRP_ARCH: u32 = 0x080;
+/// Syscalls.
+pub const KTRACE_GRP_SYSCALL: u32 = 0x100;
+/// Virtual memory.
+pub const KTRACE_GRP_VM: u32 = 0x200;
+/// All groups.
+pub const KTRACE_GRP_ALL: u32 = 0xFFF;
+
+/// Start tracing, `options` is the mask of groups.
+pub const KTRACE_ACTION_START: u32 = 1;
+/// Stop tracing.
+pub const KTRACE_ACTION_STOP: u32 = 2;
+/// Discard the recorded events, only when stopped.
+pub const KTRACE_ACTION_REWIND: u32 = 3;
+/// Register a probe by name, returns its id.
+pub const KTRACE_ACTION_NEW_PROBE: u32 = 4;
+
+/// Size of the trace buffer of each CPU.
+pub const KTRACE_BUFSIZE_PER_CPU: usize = 256 * 1024;
+
+/// Max length of a probe name.
+pub const KTRACE_MAX_NAME_LEN: usize = 35;
+
+const MAX_CPUS: usize = 8;
+const RECORD_SIZE: usize = 32;
+const NAME_RECORD_SIZE: usize = 48;
+const FIRST_PROBE_ID: u32 = 0x800;
+const MAX_PROBE_ID: u32 = 0xFFF;
+const KTRACE_VERSION: u32 = 0x0002_0000;
+
+const fn ktrace_tag(event: u32, group: u32, size: usize) -> u32 {
+    ((group & 0xFFF) << 20) | ((event & 0xFFF) << 8) | ((size as u32 >> 3) & 0xF)
+}
+
+fn tag_len(tag: u32) -> usize {
+    ((tag & 0xF) << 3) as usize
+}
+
+fn tag_group(tag: u32) -> u32 {
+    (tag >> 20) & 0xFFF
+}
+
+fn tag_event(tag: u32) -> u32 {
+    (tag >> 8) & 0xFFF
+}
+
+const TAG_VERSION: u32 = ktrace_tag(0x000, KTRACE_GRP_META, RECORD_SIZE);
+const TAG_TICKS_PER_MS: u32 = ktrace_tag(0x001, KTRACE_GRP_META, RECORD_SIZE);
+const TAG_PROBE_NAME: u32 = ktrace_tag(0x025, KTRACE_GRP_META, NAME_RECORD_SIZE);
+const TAG_IRQ_ENTER: u32 = ktrace_tag(0x030, KTRACE_GRP_IRQ, RECORD_SIZE);
+const TAG_IRQ_EXIT: u32 = ktrace_tag(0x031, KTRACE_GRP_IRQ, RECORD_SIZE);
+const TAG_SYSCALL_ENTER: u32 = ktrace_tag(0x032, KTRACE_GRP_SYSCALL, RECORD_SIZE);
+const TAG_SYSCALL_EXIT: u32 = ktrace_tag(0x033, KTRACE_GRP_SYSCALL, RECORD_SIZE);
+const TAG_CONTEXT_SWITCH: u32 = ktrace_tag(0x040, KTRACE_GRP_SCHEDULER, RECORD_SIZE);
+
+const fn tag_probe(id: u32) -> u32 {
+    ktrace_tag(id, KTRACE_GRP_PROBE, RECORD_SIZE)
+}
+
+/// The enabled groups, 0 if stopped.
+static GROUPS: AtomicU32 = AtomicU32::new(0);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Trace buffers of CPUs, allocated when tracing starts for the first time.
+static BUFFERS: [Mutex<Vec<u8>>; MAX_CPUS] = [EMPTY_BUFFER; MAX_CPUS];
+
+/// Names of probes, the id of the first one is `FIRST_PROBE_ID`.
+static PROBES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn enabled(group: u32) -> bool {
+    GROUPS.load(Ordering::Relaxed) & group != 0
+}
+
+fn current_buffer() -> &'static Mutex<Vec<u8>> {
+    &BUFFERS[kernel_hal::cpu::cpu_id() as usize % MAX_CPUS]
+}
+
+/// Append a record if the buffer is not full.
+fn append(buf: &mut Vec<u8>, tag: u32, tid: u32, ts: u64, args: [u32; 4]) {
+    if buf.len() + RECORD_SIZE > buf.capacity() {
+        return;
+    }
+    buf.extend_from_slice(&tag.to_le_bytes());
+    buf.extend_from_slice(&tid.to_le_bytes());
+    buf.extend_from_slice(&ts.to_le_bytes());
+    for arg in args {
+        buf.extend_from_slice(&arg.to_le_bytes());
+    }
+}
+
+fn append_name(buf: &mut Vec<u8>, id: u32, name: &str) {
+    if buf.len() + NAME_RECORD_SIZE > buf.capacity() {
+        return;
+    }
+    let mut name_buf = [0u8; NAME_RECORD_SIZE - 12];
+    name_buf[..name.len()].copy_from_slice(name.as_bytes());
+    buf.extend_from_slice(&TAG_PROBE_NAME.to_le_bytes());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&name_buf);
+}
+
+fn record(tag: u32, tid: u32, args: [u32; 4]) {
+    if !enabled(tag_group(tag)) {
+        return;
+    }
+    let ts = kernel_hal::timer::timer_now().as_nanos() as u64;
+    append(&mut current_buffer().lock(), tag, tid, ts, args);
+}
+
+/// Koids of the current process and thread, 0 if not in a thread.
+fn current_ids() -> (u32, u32) {
+    kernel_hal::thread::get_current_thread()
+        .and_then(|thread| thread.downcast::<Thread>().ok())
+        .map_or((0, 0), |thread| ids(&thread))
+}
+
+fn ids(thread: &Arc<Thread>) -> (u32, u32) {
+    use crate::object::KernelObject;
+    (thread.proc().id() as u32, thread.id() as u32)
+}
+
+/// Start tracing the `groups`.
+pub fn start(groups: u32) {
+    let groups = groups & KTRACE_GRP_ALL;
+    if groups == 0 {
+        stop();
+        return;
+    }
+    if GROUPS.load(Ordering::Relaxed) == 0 {
+        for buf in BUFFERS.iter() {
+            let mut buf = buf.lock();
+            let len = buf.len();
+            buf.reserve_exact(KTRACE_BUFSIZE_PER_CPU - len);
+        }
+        // metadata is at the beginning of the buffer of the first CPU
+        let mut buf = BUFFERS[0].lock();
+        if buf.is_empty() {
+            append(&mut buf, TAG_VERSION, KTRACE_VERSION, 0, [0; 4]);
+            // the timestamps are in nanoseconds
+            append(&mut buf, TAG_TICKS_PER_MS, 1_000_000, 0, [0; 4]);
+            for (i, name) in PROBES.lock().iter().enumerate() {
+                append_name(&mut buf, FIRST_PROBE_ID + i as u32, name);
+            }
+        }
+    }
+    GROUPS.store(groups, Ordering::Relaxed);
+}
+
+/// Stop tracing.
+pub fn stop() {
+    GROUPS.store(0, Ordering::Relaxed);
+}
+
+/// Discard the recorded events, fails if tracing is not stopped.
+pub fn rewind() -> ZxResult {
+    if GROUPS.load(Ordering::Relaxed) != 0 {
+        return Err(ZxError::BAD_STATE);
+    }
+    for buf in BUFFERS.iter() {
+        buf.lock().clear();
+    }
+    Ok(())
+}
+
+/// Register a probe by `name`, return its id.
+///
+/// The id of an existing probe is returned if the name is registered.
+pub fn new_probe(name: &str) -> ZxResult<u32> {
+    if name.is_empty() || name.len() > KTRACE_MAX_NAME_LEN {
+        return Err(ZxError::INVALID_ARGS);
+    }
+    let mut probes = PROBES.lock();
+    if let Some(i) = probes.iter().position(|n| n == name) {
+        return Ok(FIRST_PROBE_ID + i as u32);
+    }
+    let id = FIRST_PROBE_ID + probes.len() as u32;
+    if id > MAX_PROBE_ID {
+        return Err(ZxError::NO_RESOURCES);
+    }
+    probes.push(String::from(name));
+    drop(probes);
+    if GROUPS.load(Ordering::Relaxed) != 0 {
+        append_name(&mut BUFFERS[0].lock(), id, name);
+    }
+    Ok(id)
+}
+
+/// Record a probe with 2 arguments on the current thread.
+pub fn probe(id: u32, arg0: u32, arg1: u32) -> ZxResult {
+    if id < FIRST_PROBE_ID || id >= FIRST_PROBE_ID + PROBES.lock().len() as u32 {
+        return Err(ZxError::INVALID_ARGS);
+    }
+    let (pid, tid) = current_ids();
+    record(tag_probe(id), tid, [arg0, arg1, pid, 0]);
+    Ok(())
+}
+
+/// Record entering the syscall `num` on the current thread.
+pub fn syscall_enter(num: u32) {
+    if enabled(KTRACE_GRP_SYSCALL) {
+        let (pid, tid) = current_ids();
+        record(TAG_SYSCALL_ENTER, tid, [num, pid, 0, 0]);
+    }
+}
+
+/// Record leaving the syscall `num` on the current thread.
+pub fn syscall_exit(num: u32) {
+    if enabled(KTRACE_GRP_SYSCALL) {
+        let (pid, tid) = current_ids();
+        record(TAG_SYSCALL_EXIT, tid, [num, pid, 0, 0]);
+    }
+}
+
+/// Record entering the handler of the interrupt `vector`.
+pub fn irq_enter(vector: u32) {
+    if enabled(KTRACE_GRP_IRQ) {
+        let (pid, tid) = current_ids();
+        record(TAG_IRQ_ENTER, tid, [vector, pid, 0, 0]);
+    }
+}
+
+/// Record leaving the handler of the interrupt `vector`.
+pub fn irq_exit(vector: u32) {
+    if enabled(KTRACE_GRP_IRQ) {
+        let (pid, tid) = current_ids();
+        record(TAG_IRQ_EXIT, tid, [vector, pid, 0, 0]);
+    }
+}
+
+/// Record a context switch on the current CPU, `None` is the idle thread.
+pub fn context_switch(from: Option<&Arc<Thread>>, to: Option<&Arc<Thread>>) {
+    if enabled(KTRACE_GRP_SCHEDULER) {
+        let (from_pid, from_tid) = from.map_or((0, 0), ids);
+        let (to_pid, to_tid) = to.map_or((0, 0), ids);
+        let cpu = kernel_hal::cpu::cpu_id() as u32;
+        record(
+            TAG_CONTEXT_SWITCH,
+            from_tid,
+            [to_tid, cpu, from_pid, to_pid],
+        );
+    }
+}
+
+/// Total size of the recorded data.
+pub fn size() -> usize {
+    BUFFERS.iter().map(|buf| buf.lock().len()).sum()
+}
+
+/// Read the recorded data of all CPUs at `offset`, return the actual read size.
+pub fn read(offset: usize, buf: &mut [u8]) -> usize {
+    let mut start = 0;
+    let mut len = 0;
+    for cpu_buf in BUFFERS.iter() {
+        let cpu_buf = cpu_buf.lock();
+        let end = start + cpu_buf.len();
+        let pos = offset + len;
+        if pos < end && len < buf.len() {
+            let n = (end - pos).min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&cpu_buf[pos - start..pos - start + n]);
+            len += n;
+        }
+        start = end;
+    }
+    len
+}
+
+/// Convert the recorded data to the Fuchsia trace format.
+///
+/// See <https://fuchsia.dev/fuchsia-src/reference/tracing/trace-format>.
+pub fn export_fxt() -> Vec<u8> {
+    let mut fxt = FxtWriter::default();
+    fxt.word(0x0016_5478_4604_0010);
+    // initialization record, the timestamps are in nanoseconds
+    fxt.word(1 | 2 << 4);
+    fxt.word(1_000_000_000);
+    for (i, &s) in FXT_STRINGS.iter().enumerate() {
+        fxt.string(i as u16 + 1, s);
+    }
+    for (i, name) in PROBES.lock().iter().enumerate() {
+        fxt.string(FXT_STRINGS.len() as u16 + 1 + i as u16, name);
+    }
+    for buf in BUFFERS.iter() {
+        let buf = buf.lock();
+        let mut records = &buf[..];
+        while records.len() >= 4 {
+            let word = |i: usize| u32::from_le_bytes(records[i * 4..i * 4 + 4].try_into().unwrap());
+            let tag = word(0);
+            let len = tag_len(tag);
+            if len == 0 || len > records.len() {
+                break;
+            }
+            if len == RECORD_SIZE && tag_group(tag) != KTRACE_GRP_META {
+                let ts = u64::from_le_bytes(records[8..16].try_into().unwrap());
+                let tid = word(1) as u64;
+                let args = [word(4), word(5), word(6), word(7)];
+                fxt.event(tag, tid, ts, args);
+            }
+            records = &records[len..];
+        }
+    }
+    fxt.0
+}
+
+/// Strings referred by index in the Fuchsia trace format, followed by the probe names.
+const FXT_STRINGS: [&str; 8] = [
+    "kernel:syscall",
+    "kernel:irq",
+    "kernel:probe",
+    "syscall",
+    "irq",
+    "num",
+    "arg0",
+    "arg1",
+];
+
+fn fxt_string_ref(s: &str) -> u64 {
+    FXT_STRINGS.iter().position(|&t| t == s).unwrap() as u64 + 1
+}
+
+#[derive(Default)]
+struct FxtWriter(Vec<u8>);
+
+impl FxtWriter {
+    fn word(&mut self, word: u64) {
+        self.0.extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn string(&mut self, index: u16, s: &str) {
+        let words = (s.len() + 7) / 8;
+        self.word(2 | (1 + words as u64) << 4 | (index as u64) << 16 | (s.len() as u64) << 32);
+        self.0.extend_from_slice(s.as_bytes());
+        self.0.resize(self.0.len() + words * 8 - s.len(), 0);
+    }
+
+    /// Event record with an inline thread and uint32 arguments.
+    fn event_record(
+        &mut self,
+        ty: u64,
+        cat: u64,
+        name: u64,
+        ts: u64,
+        thread: (u64, u64),
+        args: &[(u64, u32)],
+    ) {
+        let size = 4 + args.len() as u64;
+        self.word(4 | size << 4 | ty << 16 | (args.len() as u64) << 20 | cat << 32 | name << 48);
+        self.word(ts);
+        self.word(thread.0);
+        self.word(thread.1);
+        for &(name, value) in args {
+            self.word(2 | 1 << 4 | name << 16 | (value as u64) << 32);
+        }
+    }
+
+    fn event(&mut self, tag: u32, tid: u64, ts: u64, args: [u32; 4]) {
+        const INSTANT: u64 = 0;
+        const BEGIN: u64 = 2;
+        const END: u64 = 3;
+        let (syscall, irq, num) = (
+            fxt_string_ref("syscall"),
+            fxt_string_ref("irq"),
+            fxt_string_ref("num"),
+        );
+        let pid = args[1] as u64;
+        match tag {
+            TAG_SYSCALL_ENTER | TAG_SYSCALL_EXIT | TAG_IRQ_ENTER | TAG_IRQ_EXIT => {
+                let ty = if tag == TAG_SYSCALL_ENTER || tag == TAG_IRQ_ENTER {
+                    BEGIN
+                } else {
+                    END
+                };
+                let (cat, name) = if tag_group(tag) == KTRACE_GRP_SYSCALL {
+                    (fxt_string_ref("kernel:syscall"), syscall)
+                } else {
+                    (fxt_string_ref("kernel:irq"), irq)
+                };
+                self.event_record(ty, cat, name, ts, (pid, tid), &[(num, args[0])]);
+            }
+            TAG_CONTEXT_SWITCH => {
+                // legacy context switch record, the outgoing thread is blocked
+                let cpu = args[1] as u64;
+                self.word(8 | 6 << 4 | cpu << 16 | 3 << 24);
+                self.word(ts);
+                self.word(args[2] as u64);
+                self.word(tid);
+                self.word(args[3] as u64);
+                self.word(args[0] as u64);
+            }
+            _ if tag_group(tag) == KTRACE_GRP_PROBE => {
+                let name = FXT_STRINGS.len() as u64 + 1 + (tag_event(tag) - FIRST_PROBE_ID) as u64;
+                let pid = args[2] as u64;
+                let arg_names = (fxt_string_ref("arg0"), fxt_string_ref("arg1"));
+                self.event_record(
+                    INSTANT,
+                    fxt_string_ref("kernel:probe"),
+                    name,
+                    ts,
+                    (pid, tid),
+                    &[(arg_names.0, args[0]), (arg_names.1, args[1])],
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag() {
+        assert_eq!(tag_len(TAG_CONTEXT_SWITCH), RECORD_SIZE);
+        assert_eq!(tag_len(TAG_PROBE_NAME), NAME_RECORD_SIZE);
+        assert_eq!(tag_group(TAG_SYSCALL_ENTER), KTRACE_GRP_SYSCALL);
+        assert_eq!(tag_event(tag_probe(0x801)), 0x801);
+        assert_eq!(tag_group(tag_probe(0x801)), KTRACE_GRP_PROBE);
+    }
+
+    #[test]
+    fn fxt_records() {
+        let mut fxt = FxtWriter::default();
+        fxt.string(1, "syscall");
+        // header and one word of data
+        assert_eq!(fxt.0.len(), 16);
+        assert_eq!(fxt.0[0] & 0xF, 2);
+        fxt.event(TAG_SYSCALL_ENTER, 2, 100, [7, 1, 0, 0]);
+        // header, timestamp, thread and an argument
+        assert_eq!(fxt.0.len(), 16 + 5 * 8);
+        let header = u64::from_le_bytes(fxt.0[16..24].try_into().unwrap());
+        assert_eq!(header & 0xF, 4);
+        assert_eq!((header >> 4) & 0xFFF, 5);
+        assert_eq!((header >> 16) & 0xF, 2);
+    }
+}
@@ -3,3 +3,6 @@ pub(crate) mod block_range;
 #[cfg(feature = "elf")]
 pub mod elf_loader;
 pub mod kcounter;
+pub mod ktrace;
+pub mod syscall_stats;
+pub mod zbi;
@@ -0,0 +1,166 @@
+//! Per-syscall invocation counts and latency histograms.
+//!
+//! Recording is compiled in only with the feature `syscall-stats`, otherwise
+//! [`SyscallTimer`] does nothing and all statistics read as zero.
+
+use alloc::vec::Vec;
+#[cfg(feature = "syscall-stats")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Syscall numbers at or above this are not recorded.
+pub const MAX_SYSCALL_NUM: usize = 512;
+
+/// Number of buckets of the latency histogram.
+///
+/// The bucket `0` counts latencies less than 1us, the bucket `i` counts
+/// latencies in `[2^(i-1), 2^i)` us, and the last one counts all the longer.
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// The syscall interface a syscall number belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SyscallTable {
+    /// Zircon syscalls.
+    Zircon = 0,
+    /// Linux syscalls.
+    Linux = 1,
+}
+
+/// Statistics of a syscall.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SyscallStatsInfo {
+    /// The [`SyscallTable`] of the syscall.
+    pub table: u32,
+    /// The syscall number.
+    pub num: u32,
+    /// Number of invocations.
+    pub count: u64,
+    /// Total time spent in the syscall, in nanoseconds.
+    pub total_ns: u64,
+    /// The longest invocation, in nanoseconds.
+    pub max_ns: u64,
+    /// Histogram of latencies, see [`LATENCY_BUCKETS`].
+    pub histogram: [u64; LATENCY_BUCKETS],
+}
+
+#[cfg(feature = "syscall-stats")]
+struct SyscallStat {
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    max_ns: AtomicU64,
+    histogram: [AtomicU64; LATENCY_BUCKETS],
+}
+
+#[cfg(feature = "syscall-stats")]
+impl SyscallStat {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: Self = SyscallStat {
+        count: AtomicU64::new(0),
+        total_ns: AtomicU64::new(0),
+        max_ns: AtomicU64::new(0),
+        histogram: [Self::ZERO; LATENCY_BUCKETS],
+    };
+
+    fn record(&self, ns: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.max_ns.fetch_max(ns, Ordering::Relaxed);
+        self.histogram[bucket(ns)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn info(&self, table: SyscallTable, num: usize) -> SyscallStatsInfo {
+        let mut info = SyscallStatsInfo {
+            table: table as u32,
+            num: num as u32,
+            count: self.count.load(Ordering::Relaxed),
+            total_ns: self.total_ns.load(Ordering::Relaxed),
+            max_ns: self.max_ns.load(Ordering::Relaxed),
+            histogram: [0; LATENCY_BUCKETS],
+        };
+        for (h, stat) in info.histogram.iter_mut().zip(self.histogram.iter()) {
+            *h = stat.load(Ordering::Relaxed);
+        }
+        info
+    }
+}
+
+#[cfg(feature = "syscall-stats")]
+static STATS: [[SyscallStat; MAX_SYSCALL_NUM]; 2] = [
+    [SyscallStat::NEW; MAX_SYSCALL_NUM],
+    [SyscallStat::NEW; MAX_SYSCALL_NUM],
+];
+
+/// The histogram bucket of a latency of `ns` nanoseconds.
+#[cfg_attr(not(feature = "syscall-stats"), allow(dead_code))]
+fn bucket(ns: u64) -> usize {
+    let us = ns / 1000;
+    ((64 - us.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1)
+}
+
+/// Measures a syscall from its creation to its drop.
+pub struct SyscallTimer {
+    #[cfg(feature = "syscall-stats")]
+    table: SyscallTable,
+    #[cfg(feature = "syscall-stats")]
+    num: u32,
+    #[cfg(feature = "syscall-stats")]
+    begin: core::time::Duration,
+}
+
+impl SyscallTimer {
+    /// Start measuring the syscall `num` of `table`.
+    #[allow(unused_variables)]
+    pub fn start(table: SyscallTable, num: u32) -> Self {
+        SyscallTimer {
+            #[cfg(feature = "syscall-stats")]
+            table,
+            #[cfg(feature = "syscall-stats")]
+            num,
+            #[cfg(feature = "syscall-stats")]
+            begin: kernel_hal::timer::timer_now(),
+        }
+    }
+}
+
+#[cfg(feature = "syscall-stats")]
+impl Drop for SyscallTimer {
+    fn drop(&mut self) {
+        if let Some(stat) = STATS[self.table as usize].get(self.num as usize) {
+            let elapsed = kernel_hal::timer::timer_now().saturating_sub(self.begin);
+            stat.record(elapsed.as_nanos() as u64);
+        }
+    }
+}
+
+/// Statistics of the syscalls of `table` invoked at least once.
+#[allow(unused_variables)]
+pub fn syscall_stats(table: SyscallTable) -> Vec<SyscallStatsInfo> {
+    #[cfg(feature = "syscall-stats")]
+    {
+        STATS[table as usize]
+            .iter()
+            .enumerate()
+            .map(|(num, stat)| stat.info(table, num))
+            .filter(|info| info.count != 0)
+            .collect()
+    }
+    #[cfg(not(feature = "syscall-stats"))]
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_bucket() {
+        assert_eq!(bucket(999), 0);
+        assert_eq!(bucket(1_000), 1);
+        assert_eq!(bucket(3_999), 2);
+        assert_eq!(bucket(4_000), 3);
+        assert_eq!(bucket(u64::MAX), LATENCY_BUCKETS - 1);
+    }
+}
@@ -0,0 +1,323 @@
+//! Zircon Boot Image (ZBI) parsing.
+//!
+//! A ZBI is a container of items, each starting with a [`ZbiHeader`] and
+//! padded to [`ZBI_ALIGNMENT`]. A bootable ZBI has the kernel as its first
+//! item, followed by the data items for the kernel and the userspace.
+//!
+//! Reference: <https://fuchsia.googlesource.com/fuchsia/+/3c234f79f71/zircon/system/public/zircon/boot/image.h>
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::mem::size_of;
+use numeric_enum_macro::numeric_enum;
+
+/// Items and the container are aligned to 8 bytes.
+pub const ZBI_ALIGNMENT: usize = 8;
+
+/// `ZBI_CONTAINER_MAGIC`, in the `extra` of the container header.
+const ZBI_CONTAINER_MAGIC: u32 = 0x868c_f7e6;
+/// `ZBI_ITEM_MAGIC`, in the `magic` of every header.
+const ZBI_ITEM_MAGIC: u32 = 0xb578_1729;
+/// `ZBI_FLAGS_VERSION`, must be set in every header.
+const ZBI_FLAGS_VERSION: u32 = 0x0001_0000;
+
+/// The header of the container and of each item, `zbi_header_t`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZbiHeader {
+    /// The item type, one of [`ZbiType`].
+    pub type_: u32,
+    /// The size of the payload, not including the header and the padding.
+    pub length: u32,
+    /// Type-specific data.
+    pub extra: u32,
+    /// Flags, `ZBI_FLAGS_*`.
+    pub flags: u32,
+    /// Reserved, must be zero.
+    pub reserved0: u32,
+    /// Reserved, must be zero.
+    pub reserved1: u32,
+    /// Must be `ZBI_ITEM_MAGIC`.
+    pub magic: u32,
+    /// CRC32 of the payload, if `ZBI_FLAGS_CRC32` is set.
+    pub crc32: u32,
+}
+
+/// Size of [`ZbiHeader`].
+pub const ZBI_HEADER_SIZE: usize = size_of::<ZbiHeader>();
+
+numeric_enum! {
+    /// Types of the items used by zCore.
+    #[repr(u32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ZbiType {
+        /// `ZBI_TYPE_CONTAINER`, 'BOOT'.
+        Container = 0x544f_4f42,
+        /// `ZBI_TYPE_KERNEL_X64`, 'KRNL'.
+        KernelX64 = 0x4c4e_524b,
+        /// `ZBI_TYPE_KERNEL_ARM64`, 'KRN8'.
+        KernelArm64 = 0x384e_524b,
+        /// `ZBI_TYPE_CMDLINE`, 'CMDL', the kernel command line.
+        Cmdline = 0x4c44_4d43,
+        /// `ZBI_TYPE_MEM_CONFIG`, 'MEMC', an array of [`ZbiMemRange`].
+        MemConfig = 0x434d_454d,
+        /// `ZBI_TYPE_CRASHLOG`, 'BOOM', the crash log of the previous boot.
+        Crashlog = 0x4d4f_4f42,
+        /// `ZBI_TYPE_STORAGE_RAMDISK`, 'RDSK', a disk image for the userspace.
+        StorageRamdisk = 0x4b53_4452,
+        /// `ZBI_TYPE_STORAGE_BOOTFS`, 'BFSB', the bootfs for the userspace.
+        StorageBootfs = 0x4253_4642,
+    }
+}
+
+/// Whether `type_` is a kernel item, `ZBI_IS_KERNEL_BOOTITEM`.
+pub fn is_kernel_item(type_: u32) -> bool {
+    type_ & 0x00ff_ffff == 0x004e_524b
+}
+
+/// A physical memory range in [`ZbiType::MemConfig`], `zbi_mem_range_t`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ZbiMemRange {
+    /// The start physical address.
+    pub paddr: u64,
+    /// The size in bytes.
+    pub length: u64,
+    /// One of `ZBI_MEM_RANGE_*`.
+    pub type_: u32,
+    /// Reserved.
+    pub reserved: u32,
+}
+
+/// `ZBI_MEM_RANGE_RAM`, usable memory.
+pub const ZBI_MEM_RANGE_RAM: u32 = 1;
+/// `ZBI_MEM_RANGE_PERIPHERAL`, device memory.
+pub const ZBI_MEM_RANGE_PERIPHERAL: u32 = 2;
+/// `ZBI_MEM_RANGE_RESERVED`, memory which must not be touched.
+pub const ZBI_MEM_RANGE_RESERVED: u32 = 3;
+
+/// Errors of a malformed ZBI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZbiError {
+    /// The container header is missing or invalid.
+    BadContainer,
+    /// An item header is invalid, at the given offset.
+    BadItem(usize),
+    /// An item goes beyond the container, at the given offset.
+    Truncated(usize),
+}
+
+/// An item in a ZBI.
+#[derive(Debug, Clone, Copy)]
+pub struct ZbiItem<'a> {
+    /// The header of the item.
+    pub header: ZbiHeader,
+    /// The offset of the header in the ZBI.
+    pub offset: usize,
+    /// The payload.
+    pub payload: &'a [u8],
+}
+
+impl<'a> ZbiItem<'a> {
+    /// The item type, or `None` if zCore does not know it.
+    pub fn type_(&self) -> Option<ZbiType> {
+        ZbiType::try_from(self.header.type_).ok()
+    }
+
+    /// The header, the payload and the padding of the item in the ZBI.
+    pub fn raw_len(&self) -> usize {
+        ZBI_HEADER_SIZE + align_up(self.payload.len())
+    }
+
+    /// The command line in a [`ZbiType::Cmdline`] item, without the trailing NULs.
+    pub fn cmdline(&self) -> &'a str {
+        let s = core::str::from_utf8(self.payload).unwrap_or_default();
+        s.trim_end_matches('\0')
+    }
+
+    /// The ranges in a [`ZbiType::MemConfig`] item.
+    pub fn mem_ranges(&self) -> impl Iterator<Item = ZbiMemRange> + 'a {
+        self.payload
+            .chunks_exact(size_of::<ZbiMemRange>())
+            .map(|chunk| unsafe { (chunk.as_ptr() as *const ZbiMemRange).read_unaligned() })
+    }
+}
+
+/// A parsed ZBI container.
+#[derive(Debug, Clone, Copy)]
+pub struct Zbi<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Zbi<'a> {
+    /// Checks the container header, the items are checked by [`Zbi::items`].
+    pub fn parse(data: &'a [u8]) -> Result<Self, ZbiError> {
+        let header = read_header(data, 0).ok_or(ZbiError::BadContainer)?;
+        if header.type_ != ZbiType::Container as u32
+            || header.extra != ZBI_CONTAINER_MAGIC
+            || header.magic != ZBI_ITEM_MAGIC
+        {
+            return Err(ZbiError::BadContainer);
+        }
+        let end = ZBI_HEADER_SIZE + header.length as usize;
+        if end > data.len() {
+            return Err(ZbiError::Truncated(0));
+        }
+        Ok(Self { data: &data[..end] })
+    }
+
+    /// Iterates over the items, stops at the first malformed one.
+    pub fn items(&self) -> impl Iterator<Item = Result<ZbiItem<'a>, ZbiError>> {
+        let data = self.data;
+        let mut offset = ZBI_HEADER_SIZE;
+        core::iter::from_fn(move || {
+            if offset >= data.len() {
+                return None;
+            }
+            let item = read_item(data, offset);
+            offset = match &item {
+                Ok(item) => offset + item.raw_len(),
+                Err(_) => data.len(),
+            };
+            Some(item)
+        })
+    }
+
+    /// Finds the first item of the given type.
+    pub fn find(&self, type_: ZbiType) -> Option<ZbiItem<'a>> {
+        self.items()
+            .map_while(Result::ok)
+            .find(|item| item.type_() == Some(type_))
+    }
+
+    /// The command line joined from all [`ZbiType::Cmdline`] items, separated by spaces.
+    pub fn cmdline(&self) -> alloc::string::String {
+        let items = self.items().map_while(Result::ok);
+        let cmdlines = items
+            .filter(|item| item.type_() == Some(ZbiType::Cmdline))
+            .map(|item| item.cmdline())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        cmdlines.join(" ")
+    }
+
+    /// Builds a new container without the kernel items, as the userspace
+    /// expects when the ZBI is handed over by the kernel.
+    pub fn data_zbi(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.data.len());
+        out.extend_from_slice(&self.data[..ZBI_HEADER_SIZE]);
+        for item in self.items().map_while(Result::ok) {
+            if !is_kernel_item(item.header.type_) {
+                let end = (item.offset + item.raw_len()).min(self.data.len());
+                out.extend_from_slice(&self.data[item.offset..end]);
+            }
+        }
+        let length = (out.len() - ZBI_HEADER_SIZE) as u32;
+        out[4..8].copy_from_slice(&length.to_le_bytes());
+        out
+    }
+}
+
+fn align_up(len: usize) -> usize {
+    (len + ZBI_ALIGNMENT - 1) & !(ZBI_ALIGNMENT - 1)
+}
+
+fn read_header(data: &[u8], offset: usize) -> Option<ZbiHeader> {
+    let bytes = data.get(offset..offset + ZBI_HEADER_SIZE)?;
+    Some(unsafe { (bytes.as_ptr() as *const ZbiHeader).read_unaligned() })
+}
+
+fn read_item(data: &[u8], offset: usize) -> Result<ZbiItem<'_>, ZbiError> {
+    let header = read_header(data, offset).ok_or(ZbiError::Truncated(offset))?;
+    if header.magic != ZBI_ITEM_MAGIC || header.flags & ZBI_FLAGS_VERSION == 0 {
+        return Err(ZbiError::BadItem(offset));
+    }
+    let start = offset + ZBI_HEADER_SIZE;
+    let payload = data
+        .get(start..start + header.length as usize)
+        .ok_or(ZbiError::Truncated(offset))?;
+    Ok(ZbiItem {
+        header,
+        offset,
+        payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_item(zbi: &mut Vec<u8>, type_: u32, payload: &[u8]) {
+        let header = ZbiHeader {
+            type_,
+            length: payload.len() as u32,
+            flags: ZBI_FLAGS_VERSION,
+            magic: ZBI_ITEM_MAGIC,
+            ..Default::default()
+        };
+        let bytes: [u8; ZBI_HEADER_SIZE] = unsafe { core::mem::transmute(header) };
+        zbi.extend_from_slice(&bytes);
+        zbi.extend_from_slice(payload);
+        zbi.resize(align_up(zbi.len()), 0);
+    }
+
+    fn build(items: &[(ZbiType, &[u8])]) -> Vec<u8> {
+        let mut zbi = Vec::new();
+        push_item(&mut zbi, ZbiType::Container as u32, &[]);
+        zbi[8..12].copy_from_slice(&ZBI_CONTAINER_MAGIC.to_le_bytes());
+        for (type_, payload) in items {
+            push_item(&mut zbi, *type_ as u32, payload);
+        }
+        let length = (zbi.len() - ZBI_HEADER_SIZE) as u32;
+        zbi[4..8].copy_from_slice(&length.to_le_bytes());
+        zbi
+    }
+
+    #[test]
+    fn parse_items() {
+        let range = ZbiMemRange {
+            paddr: 0x10_0000,
+            length: 0x100_0000,
+            type_: ZBI_MEM_RANGE_RAM,
+            reserved: 0,
+        };
+        let range_bytes: [u8; 24] = unsafe { core::mem::transmute(range) };
+        let items: [(ZbiType, &[u8]); 4] = [
+            (ZbiType::KernelX64, &[0; 13]),
+            (ZbiType::Cmdline, b"a=1\0"),
+            (ZbiType::MemConfig, &range_bytes),
+            (ZbiType::Cmdline, b"b=2"),
+        ];
+        let data = build(&items);
+        let zbi = Zbi::parse(&data).unwrap();
+        assert_eq!(zbi.items().count(), 4);
+        assert!(zbi.items().all(|item| item.is_ok()));
+        assert_eq!(zbi.cmdline(), "a=1 b=2");
+        let mem = zbi.find(ZbiType::MemConfig).unwrap();
+        assert_eq!(mem.mem_ranges().collect::<Vec<_>>(), [range]);
+        assert!(zbi.find(ZbiType::Crashlog).is_none());
+
+        // the kernel is removed from the data ZBI
+        let data_zbi = zbi.data_zbi();
+        let data_zbi = Zbi::parse(&data_zbi).unwrap();
+        assert_eq!(data_zbi.items().count(), 3);
+        assert!(data_zbi.find(ZbiType::KernelX64).is_none());
+        assert_eq!(data_zbi.cmdline(), "a=1 b=2");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(Zbi::parse(&[0; 16]).unwrap_err(), ZbiError::BadContainer);
+        let mut data = build(&[(ZbiType::Crashlog, &b"boom"[..])]);
+        assert_eq!(
+            Zbi::parse(&data[..data.len() - 8]).unwrap_err(),
+            ZbiError::Truncated(0)
+        );
+        // a bad magic in the item
+        data[ZBI_HEADER_SIZE + 24] = 0;
+        let zbi = Zbi::parse(&data).unwrap();
+        let items = zbi.items().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].unwrap_err(), ZbiError::BadItem(ZBI_HEADER_SIZE));
+    }
+}
@@ -1,10 +1,12 @@
 //! Objects for Virtual Memory Management.
 
+mod pager;
 mod stream;
 mod vmar;
 mod vmo;
+mod watchdog;
 
-pub use self::{stream::*, vmar::*, vmo::*};
+pub use self::{pager::*, stream::*, vmar::*, vmo::*, watchdog::*};
 use super::{ZxError, ZxResult};
 use alloc::sync::Arc;
 pub use kernel_hal::{CachePolicy, MMUFlags};
@@ -25,6 +27,12 @@ pub const PAGE_SIZE: usize = 0x1000;
 /// log2(PAGE_SIZE)
 pub const PAGE_SIZE_LOG2: usize = 12;
 
+/// Size of a huge page, which is mapped by one entry of the page table
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
+
+/// Number of pages in a huge page
+const HUGE_PAGE_PAGES: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
+
 /// Check whether `x` is a multiple of `PAGE_SIZE`.
 pub fn page_aligned(x: usize) -> bool {
     check_aligned(x, PAGE_SIZE)
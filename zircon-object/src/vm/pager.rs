@@ -0,0 +1,295 @@
+use {
+    super::*,
+    crate::object::*,
+    crate::signal::*,
+    crate::task::{CurrentThread, ThreadState},
+    alloc::{boxed::Box, collections::BTreeSet, vec, vec::Vec},
+    core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+        time::Duration,
+    },
+    lazy_static::lazy_static,
+    lock::Mutex,
+};
+
+/// Create VMOs whose pages are supplied by user space
+///
+/// ## SYNOPSIS
+///
+/// A pager creates VMOs whose content is provided by a user space program,
+/// e.g. a file system. When a page which is not supplied yet is accessed, a
+/// page request is sent to the port of its VMO, and the access blocks until
+/// the page is supplied with `zx_pager_supply_pages`.
+pub struct Pager {
+    base: KObjectBase,
+    vmos: Mutex<Vec<(Weak<VmObject>, Arc<UserPager>)>>,
+}
+
+impl_kobject!(Pager);
+
+/// The source of the pages of a VMO created by a [`Pager`].
+struct UserPager {
+    port: Arc<Port>,
+    key: u64,
+    inner: Mutex<UserPagerInner>,
+}
+
+#[derive(Default)]
+struct UserPagerInner {
+    /// Pages requested but not supplied yet.
+    requested: BTreeSet<usize>,
+    detached: bool,
+}
+
+/// Incremented whenever pages are supplied or VMOs are detached, so that
+/// the accesses waiting for them are retried.
+static SUPPLY_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref SUPPLY_WAITERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+}
+
+impl Pager {
+    /// Create a new `Pager`.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Pager {
+            base: KObjectBase::new(),
+            vmos: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Create a VMO of `pages`, whose pages are requested through `port`
+    /// in packets with `key`.
+    pub fn create_vmo(&self, port: Arc<Port>, key: u64, pages: usize) -> Arc<VmObject> {
+        let source = Arc::new(UserPager {
+            port,
+            key,
+            inner: Mutex::default(),
+        });
+        let vmo = VmObject::new_paged_with_pager(pages, source.clone());
+        let mut vmos = self.vmos.lock();
+        vmos.retain(|(vmo, _)| vmo.strong_count() != 0);
+        vmos.push((Arc::downgrade(&vmo), source));
+        vmo
+    }
+
+    /// Get the source of `vmo`, which must be created by this pager.
+    fn source(&self, vmo: &Arc<VmObject>) -> ZxResult<Arc<UserPager>> {
+        let mut vmos = self.vmos.lock();
+        vmos.retain(|(vmo, _)| vmo.strong_count() != 0);
+        vmos.iter()
+            .find(|(v, _)| v.as_ptr() == Arc::as_ptr(vmo))
+            .map(|(_, source)| source.clone())
+            .ok_or(ZxError::INVALID_ARGS)
+    }
+
+    /// Supply the pages in `[offset, offset + len)` of `vmo` with those of
+    /// `aux_vmo` from `aux_offset`, which are taken away from it.
+    ///
+    /// The pages of `vmo` already supplied are left unchanged.
+    pub fn supply_pages(
+        &self,
+        vmo: &Arc<VmObject>,
+        offset: usize,
+        len: usize,
+        aux_vmo: &Arc<VmObject>,
+        aux_offset: usize,
+    ) -> ZxResult {
+        if !page_aligned(offset) || !page_aligned(len) || !page_aligned(aux_offset) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let source = self.source(vmo)?;
+        let end = offset.checked_add(len).ok_or(ZxError::OUT_OF_RANGE)?;
+        let aux_end = aux_offset.checked_add(len).ok_or(ZxError::OUT_OF_RANGE)?;
+        if end > vmo.len() || aux_end > aux_vmo.len() {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        let mut buf = vec![0u8; len];
+        aux_vmo.read(aux_offset, &mut buf)?;
+        vmo.supply_pages(offset, &buf)?;
+        aux_vmo.decommit(aux_offset, len)?;
+        let mut inner = source.inner.lock();
+        let mut supplied = inner.requested.split_off(&(offset / PAGE_SIZE));
+        inner
+            .requested
+            .append(&mut supplied.split_off(&(end / PAGE_SIZE)));
+        drop(inner);
+        notify_supplied();
+        Ok(())
+    }
+
+    /// Detach `vmo` from the pager. The accesses to its pages not supplied
+    /// fail then.
+    pub fn detach_vmo(&self, vmo: &Arc<VmObject>) -> ZxResult {
+        let source = self.source(vmo)?;
+        self.vmos
+            .lock()
+            .retain(|(_, other)| !Arc::ptr_eq(other, &source));
+        source.detach();
+        Ok(())
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        for (_, source) in self.vmos.lock().drain(..) {
+            source.detach();
+        }
+    }
+}
+
+impl UserPager {
+    fn detach(&self) {
+        let mut inner = self.inner.lock();
+        if inner.detached {
+            return;
+        }
+        inner.detached = true;
+        inner.requested.clear();
+        drop(inner);
+        self.port.push(PortPacketRepr {
+            key: self.key,
+            status: ZxError::OK,
+            data: PayloadRepr::PageRequest(PacketPageRequest {
+                command: PageRequestCommand::Complete,
+                ..Default::default()
+            }),
+        });
+        notify_supplied();
+    }
+}
+
+impl VmoPager for UserPager {
+    fn supply_page(&self, page_idx: usize, _buf: &mut [u8]) -> ZxResult {
+        let mut inner = self.inner.lock();
+        if inner.detached {
+            return Err(ZxError::BAD_STATE);
+        }
+        // the page is requested once, until it is supplied
+        if inner.requested.insert(page_idx) {
+            self.port.push(PortPacketRepr {
+                key: self.key,
+                status: ZxError::OK,
+                data: PayloadRepr::PageRequest(PacketPageRequest {
+                    command: PageRequestCommand::Read,
+                    offset: (page_idx * PAGE_SIZE) as u64,
+                    length: PAGE_SIZE as u64,
+                    ..Default::default()
+                }),
+            });
+        }
+        Err(ZxError::SHOULD_WAIT)
+    }
+}
+
+fn notify_supplied() {
+    let mut waiters = SUPPLY_WAITERS.lock();
+    SUPPLY_GENERATION.fetch_add(1, Ordering::SeqCst);
+    for waker in waiters.drain(..) {
+        waker.wake();
+    }
+}
+
+/// A future which is ready once pages are supplied after `generation`.
+struct SupplyFuture {
+    generation: usize,
+}
+
+impl Future for SupplyFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut waiters = SUPPLY_WAITERS.lock();
+        if SUPPLY_GENERATION.load(Ordering::SeqCst) != self.generation {
+            return Poll::Ready(());
+        }
+        waiters.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Call `f` until it doesn't wait for pages to be supplied by a [`Pager`],
+/// blocking `thread` in the meantime.
+pub async fn wait_for_pager<T: Send>(
+    thread: &CurrentThread,
+    mut f: impl FnMut() -> ZxResult<T> + Send,
+) -> ZxResult<T> {
+    match f() {
+        Err(ZxError::SHOULD_WAIT) => {}
+        ret => return ret,
+    }
+    let future = async {
+        loop {
+            let generation = SUPPLY_GENERATION.load(Ordering::SeqCst);
+            match f() {
+                Err(ZxError::SHOULD_WAIT) => SupplyFuture { generation }.await,
+                ret => return ret,
+            }
+        }
+    };
+    thread
+        .blocking_run(
+            Box::pin(future),
+            ThreadState::BlockedPager,
+            Duration::from_nanos(u64::max_value()),
+            None,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn supply_pages() {
+        let pager = Pager::new();
+        let port = Port::new(0).unwrap();
+        let vmo = pager.create_vmo(port.clone(), 7, 2);
+
+        // the access waits, and the page is requested once
+        let mut buf = [0u8; 4];
+        assert_eq!(vmo.read(PAGE_SIZE, &mut buf), Err(ZxError::SHOULD_WAIT));
+        assert_eq!(vmo.read(PAGE_SIZE, &mut buf), Err(ZxError::SHOULD_WAIT));
+        let packet = PortPacketRepr::from(&port.wait().await);
+        assert_eq!(packet.key, 7);
+        assert_eq!(
+            packet.data,
+            PayloadRepr::PageRequest(PacketPageRequest {
+                command: PageRequestCommand::Read,
+                offset: PAGE_SIZE as u64,
+                length: PAGE_SIZE as u64,
+                ..Default::default()
+            })
+        );
+        assert!(!port.signal().contains(Signal::READABLE));
+
+        let aux_vmo = VmObject::new_paged(1);
+        aux_vmo.write(0, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            pager.supply_pages(&aux_vmo, 0, PAGE_SIZE, &aux_vmo, 0),
+            Err(ZxError::INVALID_ARGS)
+        );
+        pager
+            .supply_pages(&vmo, PAGE_SIZE, PAGE_SIZE, &aux_vmo, 0)
+            .unwrap();
+        vmo.read(PAGE_SIZE, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(aux_vmo.committed_pages_in_range(0, 1), 0);
+
+        // the accesses to the pages not supplied fail after detached
+        pager.detach_vmo(&vmo).unwrap();
+        assert_eq!(vmo.read(0, &mut buf), Err(ZxError::BAD_STATE));
+        let packet = PortPacketRepr::from(&port.wait().await);
+        assert!(matches!(
+            packet.data,
+            PayloadRepr::PageRequest(PacketPageRequest {
+                command: PageRequestCommand::Complete,
+                ..
+            })
+        ));
+    }
+}
@@ -15,6 +15,9 @@ pub struct Stream {
 
 impl_kobject!(Stream);
 
+/// The option of [`Stream::create`] to write at the end of the content always.
+pub const STREAM_MODE_APPEND: u32 = 1 << 2;
+
 numeric_enum! {
     #[repr(usize)]
     #[derive(Debug)]
@@ -61,9 +64,11 @@ impl Stream {
     }
 
     /// write data to the stream at the current seek offset or append data at the end of content
+    ///
+    /// The data is always appended if the stream is created in append mode.
     pub fn write(&self, data: &[u8], append: bool) -> ZxResult<usize> {
         let mut seek = self.seek.lock();
-        if append {
+        if append || self.options & STREAM_MODE_APPEND != 0 {
             *seek = self.vmo.content_size();
         }
         let length = self.write_at(data, *seek)?;
@@ -147,3 +152,42 @@ pub struct StreamInfo {
     /// get/set through 'object_[get/set]_property(vmo_handle, ...)'
     content_size: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write() {
+        let vmo = VmObject::new_paged_with_resizable(true, 1);
+        vmo.set_content_size(4).unwrap();
+        let stream = Stream::create(vmo.clone(), 0, 0);
+
+        // reads stop at the content size
+        let mut buf = [0u8; 8];
+        assert_eq!(stream.read(&mut buf), Ok(4));
+        assert_eq!(stream.read(&mut buf), Ok(0));
+
+        // writes beyond the content size extend it and resize the VMO
+        assert_eq!(
+            stream.seek(SeekOrigin::End, PAGE_SIZE as isize),
+            Ok(PAGE_SIZE + 4)
+        );
+        assert_eq!(stream.write(&[1, 2], false), Ok(2));
+        assert_eq!(vmo.content_size(), PAGE_SIZE + 6);
+        assert_eq!(vmo.len(), 2 * PAGE_SIZE);
+        assert_eq!(stream.read_at(&mut buf, PAGE_SIZE + 2), Ok(4));
+        assert_eq!(buf[..4], [0, 0, 1, 2]);
+        assert_eq!(stream.seek(SeekOrigin::Current, -1), Ok(PAGE_SIZE + 5));
+        assert_eq!(
+            stream.seek(SeekOrigin::Start, -1),
+            Err(ZxError::INVALID_ARGS)
+        );
+
+        // a stream in append mode writes at the end of the content
+        let stream = Stream::create(vmo.clone(), 0, STREAM_MODE_APPEND);
+        assert_eq!(stream.write(&[3], false), Ok(1));
+        assert_eq!(vmo.content_size(), PAGE_SIZE + 7);
+        assert_eq!(stream.get_info().seek, PAGE_SIZE as u64 + 7);
+    }
+}
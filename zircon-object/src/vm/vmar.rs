@@ -1,10 +1,11 @@
 use {
     super::*,
     crate::object::*,
-    alloc::{sync::Arc, vec, vec::Vec},
+    alloc::{string::String, sync::Arc, vec, vec::Vec},
     bitflags::bitflags,
     kernel_hal::vm::{
         GenericPageTable, IgnoreNotMappedErr, Page, PageSize, PageTable, PagingError, PagingResult,
+        TlbBatch,
     },
     lock::Mutex,
 };
@@ -32,8 +33,10 @@ bitflags! {
         const CAN_MAP_EXECUTE       = 1 << 6;
         /// Require that VMO backing the mapping is non-resizable.
         const REQUIRE_NON_RESIZABLE = 1 << 7;
-        /// Treat the offset as an upper limit when allocating a VMO or child VMAR.
+        /// Allow VMOs whose pages may fail to be supplied to be mapped.
         const ALLOW_FAULTS          = 1 << 8;
+        /// Treat the offset as an upper limit when allocating a VMO or child VMAR.
+        const OFFSET_IS_UPPER_LIMIT = 1 << 9;
 
         /// Allow VmMappings to be created inside the region with read, write and execute permissions.
         const CAN_MAP_RXW           = Self::CAN_MAP_READ.bits | Self::CAN_MAP_EXECUTE.bits | Self::CAN_MAP_WRITE.bits;
@@ -134,6 +137,9 @@ impl VmAddressRegion {
     }
 
     /// Create a child VMAR with optional `offset`.
+    ///
+    /// The `offset` is the upper limit of the end of the child if `flags`
+    /// contains `OFFSET_IS_UPPER_LIMIT`.
     pub fn allocate(
         self: &Arc<Self>,
         offset: Option<usize>,
@@ -143,7 +149,10 @@ impl VmAddressRegion {
     ) -> ZxResult<Arc<Self>> {
         let mut guard = self.inner.lock();
         let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
-        let offset = self.determine_offset(inner, offset, len, align)?;
+        if flags.contains(VmarFlags::SPECIFIC_OVERWRITE) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let offset = self.determine_offset(inner, offset, len, align, flags)?;
         let child = Arc::new(VmAddressRegion {
             flags,
             base: KObjectBase::new(),
@@ -184,14 +193,20 @@ impl VmAddressRegion {
             vmo,
             vmo_offset,
             len,
+            PAGE_SIZE,
             MMUFlags::RXW,
             flags,
-            false,
+            VmarFlags::empty(),
             true,
         )
     }
 
     /// Map the `vmo` into this VMAR.
+    ///
+    /// The placement of the mapping is specified by `options`: what is
+    /// mapped at `vmar_offset` is replaced if it contains `SPECIFIC_OVERWRITE`,
+    /// and `vmar_offset` is the upper limit of the end of the mapping if it
+    /// contains `OFFSET_IS_UPPER_LIMIT`.
     #[allow(clippy::too_many_arguments)]
     pub fn map_ext(
         &self,
@@ -199,9 +214,10 @@ impl VmAddressRegion {
         vmo: Arc<VmObject>,
         vmo_offset: usize,
         len: usize,
+        align: usize,
         permissions: MMUFlags,
         flags: MMUFlags,
-        overwrite: bool,
+        options: VmarFlags,
         map_range: bool,
     ) -> ZxResult<VirtAddr> {
         if !page_aligned(vmo_offset) || !page_aligned(len) || vmo_offset.overflowing_add(len).1 {
@@ -216,20 +232,24 @@ impl VmAddressRegion {
         }
         let mut guard = self.inner.lock();
         let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
-        let offset = self.determine_offset(inner, vmar_offset, len, PAGE_SIZE)?;
+        let offset = self.determine_offset(inner, vmar_offset, len, align, options)?;
         let addr = self.addr + offset;
         let mut flags = flags;
         // if vmo != 0
         {
             flags |= MMUFlags::from_bits_truncate(vmo.cache_policy() as usize);
         }
-        // align = 1K? 2K? 4K? 8K? ...
-        if !self.test_map(inner, offset, len, PAGE_SIZE) {
-            if overwrite {
-                self.unmap_inner(addr, len, inner)?;
-            } else {
-                return Err(ZxError::NO_MEMORY);
+        if !self.test_map(inner, offset, len, align) {
+            // the mappings are replaced, but not the sub-regions
+            if !options.contains(VmarFlags::SPECIFIC_OVERWRITE)
+                || inner
+                    .children
+                    .iter()
+                    .any(|vmar| vmar.overlap(addr, addr + len))
+            {
+                return Err(ZxError::INVALID_ARGS);
             }
+            self.unmap_inner(addr, len, inner)?;
         }
         // TODO: Fix map_range bugs and remove this line
         let map_range = map_range || vmo.name() != "";
@@ -249,6 +269,56 @@ impl VmAddressRegion {
         Ok(addr)
     }
 
+    /// Map the whole `vmo` with its first `guard_size` bytes as guard pages,
+    /// whose accesses fault, so that nothing else can be mapped just below the
+    /// rest of it.
+    ///
+    /// If `grows_down`, the mapping is extended down into the guard pages when
+    /// they are accessed, except the lowest one, like the stacks of Linux.
+    ///
+    /// Returns the address of the mapping above the guard pages.
+    pub fn map_with_guard(
+        &self,
+        vmar_offset: Option<usize>,
+        vmo: Arc<VmObject>,
+        guard_size: usize,
+        flags: MMUFlags,
+        grows_down: bool,
+    ) -> ZxResult<VirtAddr> {
+        let len = vmo.len();
+        if !page_aligned(guard_size) || guard_size == 0 || guard_size >= len {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut guard = self.inner.lock();
+        let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
+        let offset =
+            self.determine_offset(inner, vmar_offset, len, PAGE_SIZE, VmarFlags::empty())?;
+        let addr = self.addr + offset;
+        let guard_mapping = VmMapping::new(
+            addr,
+            guard_size,
+            vmo.clone(),
+            0,
+            MMUFlags::RXW,
+            flags - MMUFlags::RXW,
+            self.page_table.clone(),
+        );
+        let mapping = VmMapping::new(
+            addr + guard_size,
+            len - guard_size,
+            vmo,
+            guard_size,
+            MMUFlags::RXW,
+            flags,
+            self.page_table.clone(),
+        );
+        mapping.inner.lock().grows_down = grows_down;
+        mapping.map()?;
+        inner.mappings.push(guard_mapping);
+        inner.mappings.push(mapping);
+        Ok(addr + guard_size)
+    }
+
     /// Unmaps all VMO mappings and destroys all sub-regions within the absolute range
     /// including `addr` and ending before exclusively at `addr + len`.
     /// Any sub-region that is in the range must be fully in the range
@@ -281,6 +351,7 @@ impl VmAddressRegion {
         let mut new_maps = Vec::new();
         for map in core::mem::take(&mut inner.mappings) {
             if let Some(new) = map.cut(begin, end) {
+                new.vmo.append_mapping(Arc::downgrade(&new));
                 new_maps.push(new);
             }
             if map.size() > 0 {
@@ -299,58 +370,196 @@ impl VmAddressRegion {
     }
 
     /// Change protections on a subset of the region of memory in the containing
-    /// address space.  If the requested range overlaps with a subregion,
-    /// protect() will fail.
+    /// address space, including the mappings in its sub-regions.
+    ///
+    /// All pages in the range must be mapped, and the mappings must allow the
+    /// new protections.
     pub fn protect(&self, addr: usize, len: usize, flags: MMUFlags) -> ZxResult {
         if !page_aligned(addr) || !page_aligned(len) {
             return Err(ZxError::INVALID_ARGS);
         }
+        let end_addr = addr.checked_add(len).ok_or(ZxError::INVALID_ARGS)?;
         let mut guard = self.inner.lock();
         let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
-        let end_addr = addr + len;
-        // check if there are overlapping subregion
-        if inner
-            .children
+        let mut ranges = Vec::new();
+        inner.mappings_in_range(addr, end_addr, &mut ranges)?;
+        let mapped: usize = ranges.iter().map(|(_, start, end)| end - start).sum();
+        if mapped * PAGE_SIZE != len {
+            return Err(ZxError::NOT_FOUND);
+        }
+        // check if protect flags is valid
+        if ranges
             .iter()
-            .any(|child| child.end_addr() >= addr && child.addr() <= end_addr)
+            .any(|(map, _, _)| !map.is_valid_mapping_flags(flags))
         {
+            return Err(ZxError::ACCESS_DENIED);
+        }
+        inner.protect(addr, end_addr, flags)
+    }
+
+    /// Change the size of the mapped range `[addr, addr + old_len)`, which must be in one
+    /// mapping, to `new_len`.
+    ///
+    /// If the range can't grow in place, it is moved to a free area if `may_move`. If
+    /// `new_addr` is given, the range is always moved there, replacing what was mapped.
+    /// The VMO is resized if it is too small for the new range, which must be allowed.
+    ///
+    /// Returns the new address of the range.
+    pub fn remap(
+        &self,
+        addr: VirtAddr,
+        old_len: usize,
+        new_len: usize,
+        may_move: bool,
+        new_addr: Option<VirtAddr>,
+    ) -> ZxResult<VirtAddr> {
+        if !page_aligned(addr) || !page_aligned(old_len) || !page_aligned(new_len) {
             return Err(ZxError::INVALID_ARGS);
         }
-        let length: usize = inner
+        if old_len == 0 || new_len == 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut guard = self.inner.lock();
+        let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
+        let old_end = addr.checked_add(old_len).ok_or(ZxError::INVALID_ARGS)?;
+        let map = match inner.mappings.iter().find(|map| map.contains(addr)) {
+            Some(map) if old_end <= map.end_addr() => map.clone(),
+            _ => return Err(ZxError::NOT_FOUND),
+        };
+        if let Some(new_addr) = new_addr {
+            let new_end = new_addr.checked_add(new_len).ok_or(ZxError::INVALID_ARGS)?;
+            if !page_aligned(new_addr)
+                || new_addr < self.addr
+                || new_end > self.end_addr()
+                || (new_addr < old_end && addr < new_end)
+            {
+                return Err(ZxError::INVALID_ARGS);
+            }
+        } else if new_len <= old_len {
+            if new_len < old_len {
+                self.unmap_inner(addr + new_len, old_len - new_len, inner)?;
+            }
+            return Ok(addr);
+        }
+        let in_place = new_addr.is_none()
+            && self.test_map(inner, old_end - self.addr, new_len - old_len, PAGE_SIZE);
+        if new_addr.is_none() && !in_place && !may_move {
+            return Err(ZxError::NO_MEMORY);
+        }
+        let vmo = map.vmo.clone();
+        let old_vmo_len = vmo.len();
+        let vmo_end = map.vmo_offset() + (addr - map.addr()) + new_len;
+        let grow_vmo = vmo_end > old_vmo_len;
+        if grow_vmo && !vmo.is_resizable() {
+            return Err(ZxError::NO_MEMORY);
+        }
+        // the VMO is enlarged only once the destination is known, and shrunk
+        // back if mapping it fails
+        let rollback = |err: ZxError| {
+            if grow_vmo {
+                let _ = vmo.set_len(old_vmo_len);
+            }
+            err
+        };
+        // split the mappings at the boundaries, so that the range is a mapping
+        inner.split_mappings_at(addr);
+        inner.split_mappings_at(old_end);
+        let map = inner
             .mappings
             .iter()
-            .filter_map(|map| {
-                if map.end_addr() >= addr && map.addr() <= end_addr {
-                    Some(end_addr.min(map.end_addr()) - addr.max(map.addr()))
-                } else {
-                    None
-                }
-            })
-            .sum();
-        if length != len {
-            return Err(ZxError::NOT_FOUND);
+            .find(|map| map.addr() == addr)
+            .unwrap()
+            .clone();
+        if in_place {
+            if grow_vmo {
+                vmo.set_len(vmo_end)?;
+            }
+            map.grow(new_len).map_err(rollback)?;
+            return Ok(addr);
         }
-        // check if protect flags is valid
-        if inner
-            .mappings
+        if let Some(new_addr) = new_addr {
+            if !self.test_map(inner, new_addr - self.addr, new_len, PAGE_SIZE) {
+                self.unmap_inner(new_addr, new_len, inner)?;
+            }
+        }
+        let offset = self.determine_offset(
+            inner,
+            new_addr.map(|a| a - self.addr),
+            new_len,
+            PAGE_SIZE,
+            VmarFlags::empty(),
+        )?;
+        if grow_vmo {
+            vmo.set_len(vmo_end)?;
+        }
+        let new_map = map.moved(self.addr + offset, new_len);
+        new_map.map().map_err(rollback)?;
+        // the old mapping is unmapped when dropped
+        inner.mappings.retain(|m| !Arc::ptr_eq(m, &map));
+        inner.mappings.push(new_map);
+        Ok(self.addr + offset)
+    }
+
+    /// Commit the pages mapped in `[addr, addr + len)`, which must be all mapped
+    /// and writable, as if they are written.
+    pub fn commit(&self, addr: VirtAddr, len: usize) -> ZxResult {
+        let ranges = self.mappings_in_range(addr, len)?;
+        if ranges
             .iter()
-            .filter(|map| map.end_addr() >= addr && map.addr() <= end_addr) // get mappings in range: [addr, end_addr]
-            .any(|map| !map.is_valid_mapping_flags(flags))
+            .any(|(map, _, _)| !map.is_valid_mapping_flags(MMUFlags::WRITE))
         {
             return Err(ZxError::ACCESS_DENIED);
         }
-        inner
-            .mappings
-            .iter()
-            .filter(|map| map.end_addr() >= addr && map.addr() <= end_addr)
-            .for_each(|map| {
-                let start_index = pages(addr.max(map.addr()) - map.addr());
-                let end_index = pages(end_addr.min(map.end_addr()) - map.addr());
-                map.protect(flags, start_index, end_index);
-            });
+        for (map, start, end) in ranges {
+            map.commit(start, end)?;
+        }
+        Ok(())
+    }
+
+    /// Discard the pages mapped in `[addr, addr + len)`, which must be all mapped.
+    ///
+    /// The next access to them sees zero pages, except for shared VMOs, whose
+    /// pages are only unmapped.
+    pub fn decommit(&self, addr: VirtAddr, len: usize) -> ZxResult {
+        for (map, start, end) in self.mappings_in_range(addr, len)? {
+            map.decommit(start, end)?;
+        }
+        Ok(())
+    }
+
+    /// Mark the pages mapped in `[addr, addr + len)`, which must be all mapped,
+    /// as freeable. They may be discarded until written again.
+    pub fn mark_freeable(&self, addr: VirtAddr, len: usize) -> ZxResult {
+        for (map, start, end) in self.mappings_in_range(addr, len)? {
+            let vmo_offset = map.vmo_offset() / PAGE_SIZE;
+            map.vmo.mark_freeable(vmo_offset + start, vmo_offset + end);
+        }
         Ok(())
     }
 
+    /// Returns the mappings in `[addr, addr + len)`, including those in the
+    /// sub-regions, with the range of pages in each one. All pages in the range
+    /// must be mapped.
+    pub fn mappings_in_range(
+        &self,
+        addr: VirtAddr,
+        len: usize,
+    ) -> ZxResult<Vec<(Arc<VmMapping>, usize, usize)>> {
+        if !page_aligned(addr) || !page_aligned(len) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let guard = self.inner.lock();
+        let inner = guard.as_ref().ok_or(ZxError::BAD_STATE)?;
+        let end_addr = addr.checked_add(len).ok_or(ZxError::INVALID_ARGS)?;
+        let mut ranges = Vec::new();
+        inner.mappings_in_range(addr, end_addr, &mut ranges)?;
+        let mapped: usize = ranges.iter().map(|(_, start, end)| end - start).sum();
+        if mapped * PAGE_SIZE != len {
+            return Err(ZxError::NOT_FOUND);
+        }
+        Ok(ranges)
+    }
+
     /// Unmap all mappings within the VMAR, and destroy all sub-regions of the region.
     pub fn destroy(self: &Arc<Self>) -> ZxResult {
         self.destroy_internal()?;
@@ -425,27 +634,43 @@ impl VmAddressRegion {
     }
 
     /// Determine final address with given input `offset` and `len`.
+    ///
+    /// The range at a specific `offset` must be free, unless `options`
+    /// contains `SPECIFIC_OVERWRITE`. If `options` contains
+    /// `OFFSET_IS_UPPER_LIMIT`, `offset` is the upper limit of the end.
     fn determine_offset(
         &self,
         inner: &VmarInner,
         offset: Option<usize>,
         len: usize,
         align: usize,
+        options: VmarFlags,
     ) -> ZxResult<VirtAddr> {
-        if !check_aligned(len, align) {
-            Err(ZxError::INVALID_ARGS)
-        } else if let Some(offset) = offset {
-            if check_aligned(offset, align) && self.test_map(inner, offset, len, align) {
-                Ok(offset)
-            } else {
-                Err(ZxError::INVALID_ARGS)
+        if !page_aligned(len) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        match offset {
+            Some(offset) if !options.contains(VmarFlags::OFFSET_IS_UPPER_LIMIT) => {
+                let valid = check_aligned(offset, align)
+                    && offset
+                        .checked_add(len)
+                        .map_or(false, |end| end <= self.size);
+                if valid
+                    && (options.contains(VmarFlags::SPECIFIC_OVERWRITE)
+                        || self.test_map(inner, offset, len, align))
+                {
+                    Ok(offset)
+                } else {
+                    Err(ZxError::INVALID_ARGS)
+                }
             }
-        } else if len > self.size {
-            Err(ZxError::INVALID_ARGS)
-        } else {
-            match self.find_free_area(inner, 0, len, align) {
-                Some(offset) => Ok(offset),
-                None => Err(ZxError::NO_MEMORY),
+            _ => {
+                let upper_limit = offset.map_or(self.size, |limit| limit.min(self.size));
+                if len > self.size {
+                    return Err(ZxError::INVALID_ARGS);
+                }
+                self.find_free_area(inner, len, align, upper_limit)
+                    .ok_or(ZxError::NO_MEMORY)
             }
         }
     }
@@ -453,7 +678,7 @@ impl VmAddressRegion {
     /// Test if can create a new mapping at `offset` with `len`.
     fn test_map(&self, inner: &VmarInner, offset: usize, len: usize, align: usize) -> bool {
         debug_assert!(check_aligned(offset, align));
-        debug_assert!(check_aligned(len, align));
+        debug_assert!(page_aligned(len));
         let begin = self.addr + offset;
         let end = begin + len;
         if end > self.addr + self.size {
@@ -469,23 +694,66 @@ impl VmAddressRegion {
         true
     }
 
-    /// Find a free area with `len`.
+    /// Find a free area with `len` which ends before the offset `upper_limit`.
+    ///
+    /// The lowest one is taken if the VMAR is compact, otherwise one of all
+    /// the possible places is picked randomly for ASLR. In libos the address
+    /// space is shared with the host process, so the allocations are always
+    /// compact there.
     fn find_free_area(
         &self,
         inner: &VmarInner,
-        offset_hint: usize,
         len: usize,
         align: usize,
+        upper_limit: usize,
     ) -> Option<usize> {
-        // TODO: randomize
-        debug_assert!(check_aligned(offset_hint, align));
-        debug_assert!(check_aligned(len, align));
-        // brute force:
-        // try each area's end address as the start
-        core::iter::once(offset_hint)
-            .chain(inner.children.iter().map(|map| map.end_addr() - self.addr))
-            .chain(inner.mappings.iter().map(|map| map.end_addr() - self.addr))
-            .find(|&offset| self.test_map(inner, offset, len, align))
+        let mut used: Vec<(usize, usize)> = inner
+            .children
+            .iter()
+            .map(|vmar| (vmar.addr, vmar.end_addr()))
+            .chain(
+                inner
+                    .mappings
+                    .iter()
+                    .map(|map| (map.addr(), map.end_addr())),
+            )
+            .map(|(begin, end)| (begin - self.addr, end - self.addr))
+            .collect();
+        used.sort_unstable();
+        // the first offset and the number of the aligned offsets in each gap
+        let mut gaps = Vec::new();
+        let mut free_begin = 0;
+        for (begin, end) in used
+            .into_iter()
+            .chain(core::iter::once((upper_limit, upper_limit)))
+        {
+            let begin = begin.min(upper_limit);
+            let first = (free_begin + align - 1) / align * align;
+            if first <= begin && begin - first >= len {
+                gaps.push((first, (begin - first - len) / align + 1));
+            }
+            free_begin = free_begin.max(end);
+            if free_begin >= upper_limit {
+                break;
+            }
+        }
+        if cfg!(feature = "libos") || self.flags.contains(VmarFlags::COMPACT) {
+            return gaps.first().map(|&(first, _)| first);
+        }
+        let total: usize = gaps.iter().map(|&(_, count)| count).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut random = [0u8; 8];
+        kernel_hal::rand::fill_random(&mut random);
+        let mut index = (u64::from_ne_bytes(random) % total as u64) as usize;
+        for (first, count) in gaps {
+            if index < count {
+                return Some(first + index * align);
+            }
+            index -= count;
+        }
+        unreachable!()
     }
 
     fn end_addr(&self) -> VirtAddr {
@@ -567,7 +835,14 @@ impl VmAddressRegion {
             return child.handle_page_fault(vaddr, flags);
         }
         if let Some(mapping) = inner.mappings.iter().find(|map| map.contains(vaddr)) {
-            return mapping.handle_page_fault(vaddr, flags);
+            return match mapping.handle_page_fault(vaddr, flags) {
+                // the guard pages below a stack growing down
+                Err(ZxError::ACCESS_DENIED) => match inner.grow_stack(mapping, vaddr) {
+                    Some(stack) => stack.handle_page_fault(vaddr, flags),
+                    None => Err(ZxError::ACCESS_DENIED),
+                },
+                res => res,
+            };
         }
         Err(ZxError::NOT_FOUND)
     }
@@ -597,6 +872,70 @@ impl VmAddressRegion {
         task_stats
     }
 
+    /// Returns the information about the address space of the VMAR, whose name
+    /// is `aspace_name`, for `ZX_INFO_PROCESS_MAPS`.
+    ///
+    /// The address space is followed by the VMAR, and then everything in it in
+    /// depth-first order, sorted by address in each VMAR.
+    pub fn get_maps_info(&self, aspace_name: &str) -> Vec<MapsInfo> {
+        let mut infos = vec![MapsInfo::new(
+            aspace_name,
+            self.addr,
+            self.size,
+            0,
+            MapsInfoType::Aspace,
+        )];
+        self.fill_maps_info(1, &mut infos);
+        infos
+    }
+
+    fn fill_maps_info(&self, depth: usize, infos: &mut Vec<MapsInfo>) {
+        let name = if self.parent.is_none() {
+            "root"
+        } else {
+            "useralloc"
+        };
+        infos.push(MapsInfo::new(
+            name,
+            self.addr,
+            self.size,
+            depth,
+            MapsInfoType::Vmar,
+        ));
+        let guard = self.inner.lock();
+        let inner = match guard.as_ref() {
+            Some(inner) => inner,
+            None => return,
+        };
+        let mut groups: Vec<Vec<MapsInfo>> = inner
+            .mappings
+            .iter()
+            .map(|map| vec![map.maps_info(depth + 1)])
+            .chain(inner.children.iter().map(|child| {
+                let mut infos = Vec::new();
+                child.fill_maps_info(depth + 1, &mut infos);
+                infos
+            }))
+            .collect();
+        groups.sort_by_key(|infos| infos[0].base);
+        infos.extend(groups.into_iter().flatten());
+    }
+
+    /// Returns the VMOs mapped in the address space, once for each mapping.
+    pub fn get_mapped_vmos(&self) -> Vec<Arc<VmObject>> {
+        let mut vmos = Vec::new();
+        self.for_each_mapping(&mut |map| vmos.push(map.vmo.clone()));
+        vmos
+    }
+
+    /// Returns information about all mappings in the address space, sorted by address.
+    pub fn get_mappings_info(&self) -> Vec<VmMappingInfo> {
+        let mut infos = Vec::new();
+        self.for_each_mapping(&mut |map| infos.push(map.info()));
+        infos.sort_by_key(|info| info.addr);
+        infos
+    }
+
     /// Read from address space.
     ///
     /// Return the actual number of bytes read.
@@ -656,6 +995,73 @@ impl VmAddressRegion {
 }
 
 impl VmarInner {
+    /// Split the mapping containing `addr` into two at `addr`, if any.
+    fn split_mappings_at(&mut self, addr: VirtAddr) {
+        if let Some(new) = self.mappings.iter().find_map(|map| map.split(addr)) {
+            new.vmo.append_mapping(Arc::downgrade(&new));
+            self.mappings.push(new);
+        }
+    }
+
+    /// Extend the stack growing down just above the `guard` mapping to the page
+    /// of `vaddr`, which is one of the guard pages, except the lowest one.
+    ///
+    /// Returns the stack if it is extended.
+    fn grow_stack(&self, guard: &Arc<VmMapping>, vaddr: VirtAddr) -> Option<Arc<VmMapping>> {
+        let stack = self
+            .mappings
+            .iter()
+            .find(|map| map.addr() == guard.end_addr() && map.inner.lock().grows_down)?;
+        let addr = round_down_pages(vaddr);
+        let size = stack.addr() - addr;
+        if addr <= guard.addr() || stack.vmo_offset() < size || !guard.is_guard(addr, stack.addr())
+        {
+            return None;
+        }
+        guard.shrink(addr - guard.addr());
+        stack.grow_down(size);
+        Some(stack.clone())
+    }
+
+    /// Collect the mappings in `[begin, end)` into `ranges`, recursing into
+    /// the sub-regions, with the range of pages in each one.
+    fn mappings_in_range(
+        &self,
+        begin: VirtAddr,
+        end: VirtAddr,
+        ranges: &mut Vec<(Arc<VmMapping>, usize, usize)>,
+    ) -> ZxResult {
+        for map in self.mappings.iter().filter(|map| map.overlap(begin, end)) {
+            let start_index = (begin.max(map.addr()) - map.addr()) / PAGE_SIZE;
+            let end_index = (end.min(map.end_addr()) - map.addr()) / PAGE_SIZE;
+            ranges.push((map.clone(), start_index, end_index));
+        }
+        for child in self.children.iter().filter(|vmar| vmar.overlap(begin, end)) {
+            let guard = child.inner.lock();
+            let inner = guard.as_ref().ok_or(ZxError::BAD_STATE)?;
+            inner.mappings_in_range(begin.max(child.addr), end.min(child.end_addr()), ranges)?;
+        }
+        Ok(())
+    }
+
+    /// Change the protections of the mappings in `[begin, end)`, recursing
+    /// into the sub-regions.
+    fn protect(&mut self, begin: VirtAddr, end: VirtAddr, flags: MMUFlags) -> ZxResult {
+        // split the mappings at the boundaries, so that the flags of each one
+        // are the same for all its pages
+        self.split_mappings_at(begin);
+        self.split_mappings_at(end);
+        for map in self.mappings.iter().filter(|map| map.overlap(begin, end)) {
+            map.protect(flags, 0, pages(map.size()))?;
+        }
+        for child in self.children.iter().filter(|vmar| vmar.overlap(begin, end)) {
+            let mut guard = child.inner.lock();
+            let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
+            inner.protect(begin.max(child.addr), end.min(child.end_addr()), flags)?;
+        }
+        Ok(())
+    }
+
     /// Clone the entire address space and VMOs from source VMAR. (For Linux fork)
     fn fork_from(
         &mut self,
@@ -701,6 +1107,8 @@ struct VmMappingInner {
     addr: VirtAddr,
     size: usize,
     vmo_offset: usize,
+    /// Whether the mapping grows down into the guard pages below it.
+    grows_down: bool,
 }
 
 /// Statistics about resources (e.g., memory) used by a task.
@@ -713,6 +1121,90 @@ pub struct TaskStatsInfo {
     scaled_shared_bytes: u64,
 }
 
+impl TaskStatsInfo {
+    /// Number of bytes committed by the task, with the shared ones divided
+    /// among the tasks sharing them.
+    pub fn committed_bytes(&self) -> usize {
+        (self.private_bytes + self.scaled_shared_bytes) as usize
+    }
+}
+
+/// Information about a single mapping in an address space.
+#[derive(Debug, Clone)]
+pub struct VmMappingInfo {
+    /// The base address of the mapping
+    pub addr: VirtAddr,
+    /// The size of the mapping in bytes
+    pub size: usize,
+    /// The actual flags of the first page in the mapping
+    pub flags: MMUFlags,
+    /// The offset of the mapping into the VMO
+    pub vmo_offset: usize,
+    /// The koid of the mapped VMO
+    pub vmo_koid: KoID,
+    /// The name of the mapped VMO
+    pub vmo_name: String,
+    /// The number of bytes committed in the mapped range of the VMO
+    pub committed_bytes: usize,
+}
+
+/// Information about the address space, a VMAR or a mapping, for
+/// `ZX_INFO_PROCESS_MAPS`.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct MapsInfo {
+    /// The name of the address space, the VMAR or the mapped VMO.
+    name: [u8; 32],
+    base: usize,
+    size: usize,
+    /// The depth in the tree, where the address space is at 0.
+    depth: usize,
+    type_: MapsInfoType,
+    padding1: u32,
+    /// The flags of the mapping in `ZX_VM_PERM_*`. Zero for the others, as the
+    /// following fields.
+    mmu_flags: u32,
+    padding2: u32,
+    vmo_koid: KoID,
+    vmo_offset: u64,
+    committed_pages: u64,
+}
+
+/// The type of a [`MapsInfo`].
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapsInfoType {
+    /// Not used.
+    None = 0,
+    /// The address space.
+    Aspace = 1,
+    /// A VMAR.
+    Vmar = 2,
+    /// A mapping.
+    Mapping = 3,
+}
+
+impl Default for MapsInfoType {
+    fn default() -> Self {
+        MapsInfoType::None
+    }
+}
+
+impl MapsInfo {
+    fn new(name: &str, base: usize, size: usize, depth: usize, type_: MapsInfoType) -> Self {
+        let mut info = MapsInfo {
+            base,
+            size,
+            depth,
+            type_,
+            ..Default::default()
+        };
+        let length = name.len().min(info.name.len());
+        info.name[..length].copy_from_slice(&name.as_bytes()[..length]);
+        info
+    }
+}
+
 impl core::fmt::Debug for VmMapping {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let inner = self.inner.lock();
@@ -743,6 +1235,7 @@ impl VmMapping {
                 addr,
                 size,
                 vmo_offset,
+                grows_down: false,
             }),
             permissions,
             page_table,
@@ -757,12 +1250,44 @@ impl VmMapping {
     /// Temporarily used for development. A standard procedure for
     /// vmo is: create_vmo, op_range(commit), map
     fn map(self: &Arc<Self>) -> ZxResult {
+        let page_num = self.size() / PAGE_SIZE;
+        self.map_pages(0, page_num)
+    }
+
+    /// Commit and map the pages in `[start_index, end_index)` of the mapping.
+    ///
+    /// The pages of a huge page are mapped by one entry if their frames are
+    /// contiguous and aligned, which are committed at once if written.
+    fn map_pages(self: &Arc<Self>, start_index: usize, end_index: usize) -> ZxResult {
+        let (huge_pages, written) = {
+            let inner = self.inner.lock();
+            let huge_pages = inner.huge_pages(start_index, end_index);
+            let written: Vec<usize> = huge_pages
+                .iter()
+                .filter(|&&i| inner.flags[i].contains(MMUFlags::WRITE))
+                .map(|&i| inner.vmo_offset + i * PAGE_SIZE)
+                .collect();
+            (huge_pages, written)
+        };
+        for offset in written {
+            self.vmo.commit(offset, HUGE_PAGE_SIZE)?;
+        }
         self.vmo.commit_pages_with(&mut |commit| {
             let inner = self.inner.lock();
             let mut page_table = self.page_table.lock();
-            let page_num = inner.size / PAGE_SIZE;
             let vmo_offset = inner.vmo_offset / PAGE_SIZE;
-            for i in 0..page_num {
+            let mut i = start_index;
+            while i < end_index {
+                if huge_pages.binary_search(&i).is_ok() {
+                    if let Some(paddr) = commit_huge_page(commit, vmo_offset + i, inner.flags[i])? {
+                        let page = Page::new_aligned(inner.addr + i * PAGE_SIZE, PageSize::Size2M);
+                        // a table of 4K pages may be left there
+                        if page_table.map(page, paddr, inner.flags[i]).is_ok() {
+                            i += HUGE_PAGE_PAGES;
+                            continue;
+                        }
+                    }
+                }
                 let paddr = commit(vmo_offset + i, inner.flags[i])?;
                 //通过GenericPageTable的hal_pt_map进行页表映射
                 page_table
@@ -772,18 +1297,64 @@ impl VmMapping {
                         inner.flags[i],
                     )
                     .expect("failed to map");
+                i += 1;
             }
             Ok(())
         })
     }
 
     fn unmap(&self) {
+        let batch = {
+            let inner = self.inner.lock();
+            // TODO inner.vmo_offset unused?
+            let mut page_table = self.page_table.lock();
+            page_table
+                .unmap_cont(inner.addr, inner.size)
+                .expect("failed to unmap");
+            let mut batch = TlbBatch::new(page_table.table_phys());
+            batch.add_range(inner.addr, inner.size);
+            batch
+        };
+        batch.flush();
+    }
+
+    fn info(&self) -> VmMappingInfo {
         let inner = self.inner.lock();
-        // TODO inner.vmo_offset unused?
-        self.page_table
-            .lock()
-            .unmap_cont(inner.addr, inner.size)
-            .expect("failed to unmap")
+        let start_idx = inner.vmo_offset / PAGE_SIZE;
+        let end_idx = start_idx + inner.size / PAGE_SIZE;
+        VmMappingInfo {
+            addr: inner.addr,
+            size: inner.size,
+            flags: inner.flags.first().copied().unwrap_or(self.permissions),
+            vmo_offset: inner.vmo_offset,
+            vmo_koid: self.vmo.id(),
+            vmo_name: self.vmo.name(),
+            committed_bytes: self.vmo.committed_pages_in_range(start_idx, end_idx) * PAGE_SIZE,
+        }
+    }
+
+    fn maps_info(&self, depth: usize) -> MapsInfo {
+        let info = self.info();
+        let mut maps_info = MapsInfo::new(
+            &info.vmo_name,
+            info.addr,
+            info.size,
+            depth,
+            MapsInfoType::Mapping,
+        );
+        // ZX_VM_PERM_READ, ZX_VM_PERM_WRITE and ZX_VM_PERM_EXECUTE
+        for (i, flag) in [MMUFlags::READ, MMUFlags::WRITE, MMUFlags::EXECUTE]
+            .iter()
+            .enumerate()
+        {
+            if info.flags.contains(*flag) {
+                maps_info.mmu_flags |= 1 << i;
+            }
+        }
+        maps_info.vmo_koid = info.vmo_koid;
+        maps_info.vmo_offset = info.vmo_offset as u64;
+        maps_info.committed_pages = (info.committed_bytes / PAGE_SIZE) as u64;
+        maps_info
     }
 
     fn fill_in_task_status(&self, task_stats: &mut TaskStatsInfo) {
@@ -792,7 +1363,7 @@ impl VmMapping {
             let start_idx = inner.vmo_offset / PAGE_SIZE;
             (start_idx, start_idx + inner.size / PAGE_SIZE)
         };
-        task_stats.mapped_bytes += self.vmo.len() as u64;
+        task_stats.mapped_bytes += ((end_idx - start_idx) * PAGE_SIZE) as u64;
         let committed_pages = self.vmo.committed_pages_in_range(start_idx, end_idx);
         let share_count = self.vmo.share_count();
         if share_count == 1 {
@@ -810,61 +1381,70 @@ impl VmMapping {
         if !self.overlap(begin, end) {
             return None;
         }
-        let mut inner = self.inner.lock();
-        let mut page_table = self.page_table.lock();
-        if inner.addr >= begin && inner.end_addr() <= end {
-            // subset: [xxxxxxxxxx]
-            page_table
-                .unmap_cont(inner.addr, inner.size)
-                .expect("failed to unmap");
-            inner.size = 0;
-            inner.flags.clear();
-            None
-        } else if inner.addr >= begin && inner.addr < end {
-            // prefix: [xxxx------]
-            let cut_len = end - inner.addr;
-            page_table
-                .unmap_cont(inner.addr, cut_len)
-                .expect("failed to unmap");
-            inner.addr = end;
-            inner.size -= cut_len;
-            inner.vmo_offset += cut_len;
-            inner.flags.drain(0..pages(cut_len));
-            None
-        } else if inner.end_addr() <= end && inner.end_addr() > begin {
-            // postfix: [------xxxx]
-            let cut_len = inner.end_addr() - begin;
-            let new_len = begin - inner.addr;
-            page_table
-                .unmap_cont(begin, cut_len)
-                .expect("failed to unmap");
-            inner.size = new_len;
-            inner.flags.truncate(new_len);
-            None
-        } else {
-            // superset: [---xxxx---]
-            let cut_len = end - begin;
-            let new_len1 = begin - inner.addr;
-            let new_len2 = inner.end_addr() - end;
-            page_table
-                .unmap_cont(begin, cut_len)
-                .expect("failed to unmap");
-            let new_flags_range = (pages(inner.size) - pages(new_len2))..pages(inner.size);
-            let new_mapping = Arc::new(VmMapping {
-                permissions: self.permissions,
-                vmo: self.vmo.clone(),
-                page_table: self.page_table.clone(),
-                inner: Mutex::new(VmMappingInner {
-                    flags: inner.flags.drain(new_flags_range).collect(),
-                    addr: end,
-                    size: new_len2,
-                    vmo_offset: inner.vmo_offset + (end - inner.addr),
-                }),
-            });
-            inner.size = new_len1;
-            inner.flags.truncate(new_len1);
-            Some(new_mapping)
-        }
+        let (mapping, batch) = {
+            let mut inner = self.inner.lock();
+            let mut page_table = self.page_table.lock();
+            let mut batch = TlbBatch::new(page_table.table_phys());
+            let cut_begin = begin.max(inner.addr);
+            batch.add_range(cut_begin, end.min(inner.end_addr()) - cut_begin);
+            let mapping = if inner.addr >= begin && inner.end_addr() <= end {
+                // subset: [xxxxxxxxxx]
+                page_table
+                    .unmap_cont(inner.addr, inner.size)
+                    .expect("failed to unmap");
+                inner.size = 0;
+                inner.flags.clear();
+                None
+            } else if inner.addr >= begin && inner.addr < end {
+                // prefix: [xxxx------]
+                let cut_len = end - inner.addr;
+                page_table
+                    .unmap_cont(inner.addr, cut_len)
+                    .expect("failed to unmap");
+                inner.addr = end;
+                inner.size -= cut_len;
+                inner.vmo_offset += cut_len;
+                inner.flags.drain(0..pages(cut_len));
+                None
+            } else if inner.end_addr() <= end && inner.end_addr() > begin {
+                // postfix: [------xxxx]
+                let cut_len = inner.end_addr() - begin;
+                let new_len = begin - inner.addr;
+                page_table
+                    .unmap_cont(begin, cut_len)
+                    .expect("failed to unmap");
+                inner.size = new_len;
+                inner.flags.truncate(new_len);
+                None
+            } else {
+                // superset: [---xxxx---]
+                let cut_len = end - begin;
+                let new_len1 = begin - inner.addr;
+                let new_len2 = inner.end_addr() - end;
+                page_table
+                    .unmap_cont(begin, cut_len)
+                    .expect("failed to unmap");
+                let new_flags_range = (pages(inner.size) - pages(new_len2))..pages(inner.size);
+                let new_mapping = Arc::new(VmMapping {
+                    permissions: self.permissions,
+                    vmo: self.vmo.clone(),
+                    page_table: self.page_table.clone(),
+                    inner: Mutex::new(VmMappingInner {
+                        flags: inner.flags.drain(new_flags_range).collect(),
+                        addr: end,
+                        size: new_len2,
+                        vmo_offset: inner.vmo_offset + (end - inner.addr),
+                        grows_down: false,
+                    }),
+                });
+                inner.size = new_len1;
+                inner.flags.truncate(new_len1);
+                Some(new_mapping)
+            };
+            (mapping, batch)
+        };
+        batch.flush();
+        mapping
     }
 
     fn overlap(&self, begin: VirtAddr, end: VirtAddr) -> bool {
@@ -881,19 +1461,193 @@ impl VmMapping {
         self.permissions.contains(flags & MMUFlags::RXW)
     }
 
-    fn protect(&self, flags: MMUFlags, start_index: usize, end_index: usize) {
-        let mut inner = self.inner.lock();
-        let mut pg_table = self.page_table.lock();
+    fn protect(&self, flags: MMUFlags, start_index: usize, end_index: usize) -> ZxResult {
+        let mut batch = TlbBatch::new(self.page_table.lock().table_phys());
+        let res = self.vmo.commit_pages_with(&mut |commit| {
+            let mut inner = self.inner.lock();
+            let mut pg_table = self.page_table.lock();
+            let vmo_offset = inner.vmo_offset / PAGE_SIZE;
+            for i in start_index..end_index {
+                let old_flags = inner.flags[i];
+                let mut new_flags = old_flags;
+                new_flags.remove(MMUFlags::RXW);
+                new_flags.insert(flags & MMUFlags::RXW);
+                inner.flags[i] = new_flags;
+                let vaddr = inner.addr + i * PAGE_SIZE;
+                batch.add(vaddr);
+                pg_table
+                    .demote(vaddr)
+                    .ignore()
+                    .map_err(|_| ZxError::NO_MEMORY)?;
+                if !new_flags.contains(MMUFlags::WRITE) || old_flags.contains(MMUFlags::WRITE) {
+                    pg_table
+                        .update(vaddr, None, Some(new_flags))
+                        .ignore()
+                        .unwrap();
+                    continue;
+                }
+                // a page which was not writable may be the zero frame, or shared
+                // for copy-on-write, so it is committed for writing again
+                pg_table
+                    .unmap(vaddr)
+                    .ignore()
+                    .map_err(|_| ZxError::NO_MEMORY)?;
+                if cfg!(feature = "libos") {
+                    // without page faults, the page is committed now
+                    let paddr = commit(vmo_offset + i, new_flags)?;
+                    pg_table
+                        .map(Page::new_aligned(vaddr, PageSize::Size4K), paddr, new_flags)
+                        .map_err(|_| ZxError::NO_MEMORY)?;
+                }
+            }
+            Ok(())
+        });
+        batch.flush();
+        res
+    }
+
+    /// Commit the pages in `[start_index, end_index)` of the mapping for
+    /// writing, and map them.
+    fn commit(&self, start_index: usize, end_index: usize) -> ZxResult {
+        let addr = self.addr();
         for i in start_index..end_index {
-            let mut new_flags = inner.flags[i];
-            new_flags.remove(MMUFlags::RXW);
-            new_flags.insert(flags & MMUFlags::RXW);
-            inner.flags[i] = new_flags;
-            pg_table
-                .update(inner.addr + i * PAGE_SIZE, None, Some(new_flags))
-                .ignore()
-                .unwrap();
+            self.handle_page_fault(addr + i * PAGE_SIZE, MMUFlags::WRITE)?;
         }
+        Ok(())
+    }
+
+    /// Discard the pages in `[start_index, end_index)` of the mapping.
+    fn decommit(self: &Arc<Self>, start_index: usize, end_index: usize) -> ZxResult {
+        let (addr, vmo_offset) = {
+            let inner = self.inner.lock();
+            (inner.addr, inner.vmo_offset)
+        };
+        let start_vaddr = addr + start_index * PAGE_SIZE;
+        let size = (end_index - start_index) * PAGE_SIZE;
+        let mut batch = {
+            let mut page_table = self.page_table.lock();
+            page_table
+                .unmap_cont(start_vaddr, size)
+                .map_err(|_| ZxError::NO_MEMORY)?;
+            TlbBatch::new(page_table.table_phys())
+        };
+        // the pages are invalidated on other CPUs before they are freed
+        batch.add_range(start_vaddr, size);
+        batch.flush();
+        // the pages of a slice are shared, so they are kept
+        if !self.vmo.is_slice() {
+            let offset = vmo_offset + start_index * PAGE_SIZE;
+            let len = (end_index - start_index) * PAGE_SIZE;
+            match self.vmo.decommit(offset, len) {
+                // the pages of a snapshot may be in its parent
+                Err(ZxError::NOT_SUPPORTED) => self.vmo.zero(offset, len)?,
+                res => res?,
+            }
+        }
+        if cfg!(feature = "libos") {
+            // without page faults, the pages are mapped again now
+            self.map_pages(start_index, end_index)?;
+        }
+        Ok(())
+    }
+
+    /// Split the mapping into two at `addr`, returning the part after `addr`
+    /// if it is within the mapping.
+    fn split(&self, addr: VirtAddr) -> Option<Arc<Self>> {
+        let mut inner = self.inner.lock();
+        if addr <= inner.addr || addr >= inner.end_addr() {
+            return None;
+        }
+        let offset = addr - inner.addr;
+        // no huge page is in both parts
+        self.page_table.lock().demote(addr).ignore().unwrap();
+        let new_mapping = Arc::new(VmMapping {
+            permissions: self.permissions,
+            vmo: self.vmo.clone(),
+            page_table: self.page_table.clone(),
+            inner: Mutex::new(VmMappingInner {
+                flags: inner.flags.split_off(pages(offset)),
+                addr,
+                size: inner.size - offset,
+                vmo_offset: inner.vmo_offset + offset,
+                grows_down: false,
+            }),
+        });
+        inner.size = offset;
+        Some(new_mapping)
+    }
+
+    /// Grow the mapping to `size` in place, mapping the new pages with the
+    /// flags of the last page.
+    fn grow(self: &Arc<Self>, size: usize) -> ZxResult {
+        let old_pages = {
+            let mut inner = self.inner.lock();
+            let last = *inner.flags.last().unwrap();
+            let old_pages = pages(inner.size);
+            inner.flags.resize(pages(size), last);
+            inner.size = size;
+            old_pages
+        };
+        self.map_pages(old_pages, pages(size))
+    }
+
+    /// Whether the pages in `[begin, end)` of the mapping are guard pages,
+    /// which can't be accessed.
+    fn is_guard(&self, begin: VirtAddr, end: VirtAddr) -> bool {
+        let inner = self.inner.lock();
+        let range = (begin - inner.addr) / PAGE_SIZE..(end - inner.addr) / PAGE_SIZE;
+        inner.flags[range]
+            .iter()
+            .all(|flags| !flags.intersects(MMUFlags::RXW))
+    }
+
+    /// Shrink the mapping to `size`, which is not mapped in the page table
+    /// beyond that.
+    fn shrink(&self, size: usize) {
+        let mut inner = self.inner.lock();
+        inner.size = size;
+        inner.flags.truncate(pages(size));
+    }
+
+    /// Extend the mapping down by `size`, with the flags of its first page.
+    fn grow_down(&self, size: usize) {
+        let mut inner = self.inner.lock();
+        let first = inner.flags[0];
+        inner.addr -= size;
+        inner.size += size;
+        inner.vmo_offset -= size;
+        inner
+            .flags
+            .splice(0..0, core::iter::repeat(first).take(pages(size)));
+    }
+
+    /// Create a mapping of the same range of the VMO at `addr`, resized to
+    /// `size`. It is not mapped yet.
+    fn moved(&self, addr: VirtAddr, size: usize) -> Arc<Self> {
+        let (mut flags, vmo_offset, grows_down) = {
+            let inner = self.inner.lock();
+            (inner.flags.clone(), inner.vmo_offset, inner.grows_down)
+        };
+        let last = *flags.last().unwrap();
+        flags.resize(pages(size), last);
+        let mapping = Arc::new(VmMapping {
+            permissions: self.permissions,
+            vmo: self.vmo.clone(),
+            page_table: self.page_table.clone(),
+            inner: Mutex::new(VmMappingInner {
+                flags,
+                addr,
+                size,
+                vmo_offset,
+                grows_down,
+            }),
+        });
+        self.vmo.append_mapping(Arc::downgrade(&mapping));
+        mapping
+    }
+
+    fn vmo_offset(&self) -> usize {
+        self.inner.lock().vmo_offset
     }
 
     fn size(&self) -> usize {
@@ -929,11 +1683,18 @@ impl VmMapping {
         // If we are already locked, we are handling page fault/map range
         // In this case we can just ignore the operation since we will update the mapping later
         if let Some(inner) = inner {
-            let start = offset.max(inner.vmo_offset);
-            let end = (inner.vmo_offset + inner.size / PAGE_SIZE).min(offset + len);
+            let start_idx = inner.vmo_offset / PAGE_SIZE;
+            let start = offset.max(start_idx);
+            let end = (start_idx + inner.size / PAGE_SIZE).min(offset + len);
             if !(start..end).is_empty() {
                 let mut pg_table = self.page_table.lock();
-                for i in (start - inner.vmo_offset)..(end - inner.vmo_offset) {
+                let mut batch = TlbBatch::new(pg_table.table_phys());
+                for i in (start - start_idx)..(end - start_idx) {
+                    batch.add(inner.addr + i * PAGE_SIZE);
+                    pg_table
+                        .demote(inner.addr + i * PAGE_SIZE)
+                        .ignore()
+                        .unwrap();
                     match op {
                         RangeChangeOp::RemoveWrite => {
                             let mut new_flag = inner.flags[i];
@@ -948,6 +1709,8 @@ impl VmMapping {
                         }
                     };
                 }
+                drop(pg_table);
+                batch.flush();
             }
         }
     }
@@ -972,6 +1735,7 @@ impl VmMapping {
         let paddr = self.vmo.commit_page(vmo_offset / PAGE_SIZE, access_flags)?;
         // error!("paddr = {:x}", paddr);
         let mut pg_table = self.page_table.lock();
+        pg_table.demote(vaddr).ignore().unwrap();
         let mut res = pg_table.map(Page::new_aligned(vaddr, PageSize::Size4K), paddr, flags);
         if let Err(PagingError::AlreadyMapped) = res {
             res = pg_table.update(vaddr, Some(paddr), Some(flags)).map(|_| ());
@@ -983,7 +1747,12 @@ impl VmMapping {
     /// Clone VMO and map it to a new page table. (For Linux)
     fn clone_map(&self, page_table: Arc<Mutex<dyn GenericPageTable>>) -> ZxResult<Arc<Self>> {
         //这里调用 hal protect 后, protect() 好像会破坏页表
-        let new_vmo = self.vmo.create_child(false, 0, self.vmo.len())?;
+        // a slice is shared memory, which is mapped by both processes
+        let new_vmo = if self.vmo.is_slice() {
+            self.vmo.clone()
+        } else {
+            self.vmo.create_child(false, 0, self.vmo.len())?
+        };
         let mapping = Arc::new(VmMapping {
             inner: Mutex::new(self.inner.lock().clone()),
             permissions: self.permissions,
@@ -995,10 +1764,45 @@ impl VmMapping {
     }
 }
 
+/// Commit the pages of the huge page starting from `page_idx` of the VMO,
+/// returning the address of their frames if they are contiguous and aligned.
+fn commit_huge_page(
+    commit: &mut dyn FnMut(usize, MMUFlags) -> ZxResult<PhysAddr>,
+    page_idx: usize,
+    flags: MMUFlags,
+) -> ZxResult<Option<PhysAddr>> {
+    let paddr = commit(page_idx, flags)?;
+    let mut contiguous = PageSize::Size2M.is_aligned(paddr);
+    for i in 1..HUGE_PAGE_PAGES {
+        contiguous &= commit(page_idx + i, flags)? == paddr + i * PAGE_SIZE;
+    }
+    Ok(contiguous.then(|| paddr))
+}
+
 impl VmMappingInner {
     fn end_addr(&self) -> VirtAddr {
         self.addr + self.size
     }
+
+    /// Returns the first pages of the huge pages in `[start_index, end_index)`,
+    /// which are aligned in both the address space and the VMO, and whose
+    /// pages have the same flags.
+    fn huge_pages(&self, start_index: usize, end_index: usize) -> Vec<usize> {
+        if cfg!(feature = "libos") {
+            // the page table of libos maps 4K pages only
+            return Vec::new();
+        }
+        (start_index..end_index)
+            .filter(|&i| {
+                i + HUGE_PAGE_PAGES <= end_index
+                    && PageSize::Size2M.is_aligned(self.addr + i * PAGE_SIZE)
+                    && PageSize::Size2M.is_aligned(self.vmo_offset + i * PAGE_SIZE)
+                    && self.flags[i..i + HUGE_PAGE_PAGES]
+                        .iter()
+                        .all(|&flags| flags == self.flags[i])
+            })
+            .collect()
+    }
 }
 
 impl Drop for VmMapping {
@@ -1216,6 +2020,276 @@ mod tests {
         assert_eq!(vmar.used_size(), 0x1000);
     }
 
+    #[test]
+    fn protect_and_remap() {
+        let vmar = VmAddressRegion::new_root();
+        let base = vmar.addr();
+        let vmo = VmObject::new_paged_with_resizable(true, 2);
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        vmar.map_at(0, vmo.clone(), 0, 0x2000, flags).unwrap();
+        vmo.write(0, &[1]).unwrap();
+
+        // protecting a part splits the mapping
+        vmar.protect(base + 0x1000, 0x1000, MMUFlags::READ).unwrap();
+        assert_eq!(vmar.count(), 2);
+        let map = vmar.find_mapping(base + 0x1000).unwrap();
+        assert_eq!(
+            map.get_flags(base + 0x1000).unwrap() & MMUFlags::RXW,
+            MMUFlags::READ
+        );
+        assert_eq!(
+            vmar.protect(base, 0x3000, MMUFlags::READ).err(),
+            Some(ZxError::NOT_FOUND)
+        );
+
+        // grow in place, resizing the VMO
+        assert_eq!(
+            vmar.remap(base + 0x1000, 0x1000, 0x2000, false, None),
+            Ok(base + 0x1000)
+        );
+        assert_eq!(vmo.len(), 0x3000);
+        assert_eq!(vmar.used_size(), 0x3000);
+
+        // move if it can't grow in place
+        vmar.map_at(0x3000, VmObject::new_paged(1), 0, 0x1000, flags)
+            .unwrap();
+        assert_eq!(
+            vmar.remap(base, 0x1000, 0x2000, false, None).err(),
+            Some(ZxError::NO_MEMORY)
+        );
+        let addr = vmar.remap(base, 0x1000, 0x2000, true, None).unwrap();
+        assert_ne!(addr, base);
+        assert_eq!(vmar.used_size(), 0x5000);
+        let mut buf = [0];
+        vmar.read_memory(addr, &mut buf).unwrap();
+        assert_eq!(buf[0], 1);
+
+        // shrink
+        assert_eq!(vmar.remap(addr, 0x2000, 0x1000, false, None), Ok(addr));
+        assert_eq!(vmar.used_size(), 0x4000);
+    }
+
+    #[test]
+    fn placement() {
+        let root = VmAddressRegion::new_root();
+        let base = root.addr();
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        let map = |offset, options| {
+            root.map_ext(
+                Some(offset),
+                VmObject::new_paged(1),
+                0,
+                0x1000,
+                PAGE_SIZE,
+                flags,
+                flags,
+                options,
+                false,
+            )
+        };
+
+        // the mapping ends below the upper limit
+        let upper_limit = VmarFlags::OFFSET_IS_UPPER_LIMIT;
+        assert_eq!(map(0x1000, upper_limit), Ok(base));
+        assert_eq!(map(0x1000, upper_limit), Err(ZxError::NO_MEMORY));
+        let addr = map(0x3000, upper_limit).unwrap();
+        assert!(addr == base + 0x1000 || addr == base + 0x2000);
+
+        // overwrite the mappings, but not the sub-regions
+        assert_eq!(map(0, VmarFlags::empty()), Err(ZxError::INVALID_ARGS));
+        assert_eq!(map(0, VmarFlags::SPECIFIC_OVERWRITE), Ok(base));
+        assert_eq!(root.count(), 2);
+        root.allocate_at(0x4000, 0x1000, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+            .unwrap();
+        assert_eq!(
+            map(0x4000, VmarFlags::SPECIFIC_OVERWRITE),
+            Err(ZxError::INVALID_ARGS)
+        );
+    }
+
+    #[test]
+    fn grows_down() {
+        let vmar = VmAddressRegion::new_root();
+        let vmo = VmObject::new_paged(4);
+        let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
+        let addr = vmar
+            .map_with_guard(None, vmo.clone(), 0x3000, flags, true)
+            .unwrap();
+        let base = addr - 0x3000;
+        assert_eq!(vmar.used_size(), 0x4000);
+
+        // the stack grows down to the guard page accessed
+        vmar.handle_page_fault(base + 0x1000, MMUFlags::WRITE)
+            .unwrap();
+        let stack = vmar.find_mapping(base + 0x1000).unwrap();
+        assert_eq!(stack.addr(), base + 0x1000);
+        assert_eq!(stack.size(), 0x3000);
+        assert_eq!(vmo.committed_pages_in_range(1, 2), 1);
+
+        // but the lowest guard page is always left
+        assert_eq!(vmar.find_mapping(base).unwrap().size(), 0x1000);
+        assert_eq!(
+            vmar.handle_page_fault(base, MMUFlags::READ),
+            Err(ZxError::ACCESS_DENIED)
+        );
+
+        // the guard pages of a fixed mapping are never accessible
+        let addr = vmar
+            .map_with_guard(None, VmObject::new_paged(3), 0x2000, flags, false)
+            .unwrap();
+        assert_eq!(
+            vmar.handle_page_fault(addr - 0x1000, MMUFlags::READ),
+            Err(ZxError::ACCESS_DENIED)
+        );
+    }
+
+    #[test]
+    fn maps_info() {
+        let root = VmAddressRegion::new_root();
+        let base = root.addr();
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        let child = root
+            .allocate_at(0x2000, 0x2000, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+            .unwrap();
+        let vmo = VmObject::new_paged(2);
+        vmo.write(0, &[1]).unwrap();
+        child.map_at(0x1000, vmo.clone(), 0, 0x1000, flags).unwrap();
+        root.map_at(0, VmObject::new_paged(1), 0, 0x1000, MMUFlags::READ)
+            .unwrap();
+
+        let infos = root.get_maps_info("proc");
+        let types: Vec<_> = infos.iter().map(|i| (i.type_, i.base, i.depth)).collect();
+        assert_eq!(
+            types,
+            [
+                (MapsInfoType::Aspace, base, 0),
+                (MapsInfoType::Vmar, base, 1),
+                (MapsInfoType::Mapping, base, 2),
+                (MapsInfoType::Vmar, base + 0x2000, 2),
+                (MapsInfoType::Mapping, base + 0x3000, 3),
+            ]
+        );
+        assert_eq!(&infos[0].name[..5], b"proc\0");
+        assert_eq!(&infos[1].name[..5], b"root\0");
+        assert_eq!(infos[2].mmu_flags, 1);
+        assert_eq!(infos[4].mmu_flags, 3);
+        assert_eq!(infos[4].vmo_koid, vmo.id());
+        assert_eq!(infos[4].committed_pages, 1);
+        assert_eq!(root.get_mapped_vmos().len(), 2);
+    }
+
+    #[test]
+    fn subregion_range_ops() {
+        let root = VmAddressRegion::new_root();
+        let base = root.addr();
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        root.map_at(0, VmObject::new_paged(1), 0, 0x1000, flags)
+            .unwrap();
+        let child = root
+            .allocate_at(0x1000, 0x2000, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+            .unwrap();
+        let vmo = VmObject::new_paged(2);
+        child
+            .map_ext(
+                Some(0),
+                vmo.clone(),
+                0,
+                0x1000,
+                PAGE_SIZE,
+                flags,
+                flags,
+                VmarFlags::empty(),
+                false,
+            )
+            .unwrap();
+
+        // the ranges can't cross unmapped gaps
+        assert_eq!(
+            root.protect(base, 0x3000, MMUFlags::READ).err(),
+            Some(ZxError::NOT_FOUND)
+        );
+        assert_eq!(root.commit(base, 0x3000).err(), Some(ZxError::NOT_FOUND));
+
+        // but can cross into the mappings of sub-regions
+        assert_eq!(vmo.committed_pages_in_range(0, 2), 0);
+        root.commit(base, 0x2000).unwrap();
+        assert_eq!(vmo.committed_pages_in_range(0, 2), 1);
+        root.protect(base, 0x2000, MMUFlags::READ).unwrap();
+        let map = child.find_mapping(base + 0x1000).unwrap();
+        assert_eq!(
+            map.get_flags(base + 0x1000).unwrap() & MMUFlags::RXW,
+            MMUFlags::READ
+        );
+        assert_eq!(
+            root.commit(base, 0x2000).err(),
+            Some(ZxError::ACCESS_DENIED)
+        );
+        assert_eq!(
+            root.protect(base, 0x2000, MMUFlags::EXECUTE).err(),
+            Some(ZxError::ACCESS_DENIED)
+        );
+    }
+
+    #[test]
+    fn huge_page() {
+        let vmar = VmAddressRegion::new_root();
+        let offset = ceil(vmar.addr(), HUGE_PAGE_SIZE) * HUGE_PAGE_SIZE - vmar.addr();
+        let vmo = VmObject::new_paged(2 * HUGE_PAGE_PAGES);
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        let addr = vmar
+            .map_at(offset, vmo.clone(), 0, vmo.len(), flags)
+            .unwrap();
+        // the page table of libos maps 4K pages only
+        let page_size = |vaddr| {
+            let map = vmar.find_mapping(vaddr).unwrap();
+            map.query_vaddr(vaddr).unwrap().2
+        };
+        if !cfg!(feature = "libos") {
+            assert_eq!(page_size(addr), PageSize::Size2M);
+        }
+        vmo.write(0x1000, &[1]).unwrap();
+
+        // protecting or unmapping a part splits the huge page
+        vmar.protect(addr, 0x1000, MMUFlags::READ).unwrap();
+        vmar.unmap(addr + HUGE_PAGE_SIZE, 0x1000).unwrap();
+        if !cfg!(feature = "libos") {
+            assert_eq!(page_size(addr + 0x1000), PageSize::Size4K);
+            assert_eq!(page_size(addr + HUGE_PAGE_SIZE + 0x1000), PageSize::Size4K);
+        }
+        let mut buf = [0];
+        vmar.read_memory(addr + 0x1000, &mut buf).unwrap();
+        assert_eq!(buf[0], 1);
+        assert_eq!(vmar.count(), 3);
+    }
+
+    #[test]
+    fn decommit() {
+        let vmar = VmAddressRegion::new_root();
+        let base = vmar.addr();
+        let vmo = VmObject::new_paged(2);
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        vmar.map_at(0, vmo.clone(), 0, 0x2000, flags).unwrap();
+        vmo.write(0, &[1]).unwrap();
+        vmo.write(0x1000, &[2]).unwrap();
+
+        assert_eq!(vmar.decommit(base, 0x3000).err(), Some(ZxError::NOT_FOUND));
+        vmar.decommit(base, 0x1000).unwrap();
+        let mut buf = [0];
+        vmar.read_memory(base, &mut buf).unwrap();
+        assert_eq!(buf[0], 0);
+        vmar.read_memory(base + 0x1000, &mut buf).unwrap();
+        assert_eq!(buf[0], 2);
+
+        // a snapshot is zeroed instead
+        let child = vmo.create_child(false, 0, 0x2000).unwrap();
+        vmar.map_at(0x2000, child, 0, 0x2000, flags).unwrap();
+        vmar.decommit(base + 0x3000, 0x1000).unwrap();
+        vmar.read_memory(base + 0x3000, &mut buf).unwrap();
+        assert_eq!(buf[0], 0);
+        vmo.read(0x1000, &mut buf).unwrap();
+        assert_eq!(buf[0], 2);
+    }
+
     #[test]
     #[allow(unsafe_code)]
     fn copy_on_write_update_mapping() {
@@ -14,8 +14,11 @@ use {
 
 mod paged;
 mod physical;
+mod scanner;
 mod slice;
 
+pub use self::scanner::{scan_zero_pages, start_zero_page_scanner, zero_page_stats, ZeroPageStats};
+
 kcounter!(VMO_PAGE_ALLOC, "vmo.page_alloc");
 kcounter!(VMO_PAGE_DEALLOC, "vmo.page_dealloc");
 
@@ -36,6 +39,10 @@ pub trait VMObjectTrait: Sync + Send {
     /// Resets the range of bytes in the VMO from `offset` to `offset+len` to 0.
     fn zero(&self, offset: usize, len: usize) -> ZxResult;
 
+    /// Write back and invalidate the data cache of the committed pages in
+    /// `[offset, offset + len)`.
+    fn cache_flush(&self, offset: usize, len: usize) -> ZxResult;
+
     /// Get the length of VMO.
     fn len(&self) -> usize;
 
@@ -108,6 +115,41 @@ pub trait VMObjectTrait: Sync + Send {
 
     /// Mark as not contiguous
     fn unset_contiguous(&self) {}
+
+    /// Returns true if the object is a slice of another VMO, sharing its pages.
+    fn is_slice(&self) -> bool {
+        false
+    }
+
+    /// Take the indexes of the pages in `[start_idx, end_idx)` written through
+    /// mappings since they were last taken. Only VMOs with a pager track them.
+    fn take_dirty_pages(&self, _start_idx: usize, _end_idx: usize) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Remove the write permission of the mappings of the pages in
+    /// `[start_idx, end_idx)`, so that the next write to them is seen again.
+    fn write_protect(&self, _start_idx: usize, _end_idx: usize) {}
+
+    /// Mark the committed pages in `[start_idx, end_idx)` as freeable. They are
+    /// reclaimed when a page can't be allocated, unless they are written again.
+    fn mark_freeable(&self, _start_idx: usize, _end_idx: usize) {}
+
+    /// Commit the pages from `offset` with the content in `buf`, supplied by
+    /// a user pager. The pages already committed are left unchanged.
+    fn supply_pages(&self, _offset: usize, _buf: &[u8]) -> ZxResult {
+        Err(ZxError::NOT_SUPPORTED)
+    }
+}
+
+/// The source of the content of a VMO, e.g. a file which it caches.
+pub trait VmoPager: Sync + Send {
+    /// Fill `buf` with the content of the page at `page_idx`, which is about
+    /// to be committed.
+    ///
+    /// Returns `ZxError::SHOULD_WAIT` if the page is supplied later, by
+    /// [`VMObjectTrait::supply_pages`].
+    fn supply_page(&self, page_idx: usize, buf: &mut [u8]) -> ZxResult;
 }
 
 /// Virtual memory containers
@@ -153,6 +195,17 @@ impl VmObject {
         })
     }
 
+    /// Create a new VMO whose pages are supplied by `pager` when committed.
+    pub fn new_paged_with_pager(pages: usize, pager: Arc<dyn VmoPager>) -> Arc<Self> {
+        Arc::new(VmObject {
+            base: KObjectBase::with_signal(Signal::VMO_ZERO_CHILDREN),
+            resizable: false,
+            _counter: CountHelper::new(),
+            trait_: VMObjectPaged::new_with_pager(pages, pager),
+            inner: Mutex::new(VmObjectInner::default()),
+        })
+    }
+
     /// Create a new VMO representing a piece of contiguous physical memory.
     pub fn new_physical(paddr: PhysAddr, pages: usize) -> Arc<Self> {
         Arc::new(VmObject {
@@ -312,7 +365,11 @@ impl VmObject {
             },
             size: self.trait_.len() as u64,
             parent_koid: inner.parent.upgrade().map(|p| p.id()).unwrap_or(0),
-            num_children: inner.children.len() as u64,
+            num_children: inner
+                .children
+                .iter()
+                .filter(|child| child.strong_count() != 0)
+                .count() as u64,
             flags: if self.resizable {
                 VmoInfoFlags::RESIZABLE
             } else {
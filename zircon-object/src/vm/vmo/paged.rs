@@ -1,8 +1,8 @@
 use {
     super::*,
     crate::util::block_range::BlockIter,
-    alloc::collections::BTreeMap,
     alloc::collections::VecDeque,
+    alloc::collections::{BTreeMap, BTreeSet},
     alloc::sync::{Arc, Weak},
     alloc::vec::Vec,
     core::cell::{Ref, RefCell, RefMut},
@@ -96,6 +96,12 @@ struct VMObjectPagedInner {
     self_ref: WeakRef,
     /// Sum of pin_count
     pin_count: usize,
+    /// The source of the pages committed for the first time.
+    pager: Option<Arc<dyn VmoPager>>,
+    /// Pages written through mappings, which are tracked if there is a pager.
+    dirty: BTreeSet<usize>,
+    /// Pages which may be reclaimed, until they are written again.
+    freeable: BTreeSet<usize>,
 }
 
 /// Page state in VMO.
@@ -175,11 +181,21 @@ impl VMObjectPaged {
                 contiguous: false,
                 self_ref: Default::default(),
                 pin_count: 0,
+                pager: None,
+                dirty: BTreeSet::new(),
+                freeable: BTreeSet::new(),
             },
             None,
         )
     }
 
+    /// Create a new VMO whose pages are supplied by `pager` when committed.
+    pub fn new_with_pager(pages: usize, pager: Arc<dyn VmoPager>) -> Arc<Self> {
+        let vmo = Self::new(pages);
+        vmo.get_inner_mut().1.pager = Some(pager);
+        vmo
+    }
+
     /// Create a new VMO backing on contiguous pages.
     pub fn new_contiguous(pages: usize, align_log2: usize) -> ZxResult<Arc<Self>> {
         let vmo = Self::new(pages);
@@ -207,6 +223,7 @@ impl VMObjectPaged {
             inner: RefCell::new(inner),
         });
         obj.inner.borrow_mut().self_ref = Arc::downgrade(&obj);
+        scanner::register_vmo(&obj);
         obj
     }
 
@@ -219,6 +236,56 @@ impl VMObjectPaged {
     fn get_inner_mut(&self) -> (MutexGuard<()>, RefMut<VMObjectPagedInner>) {
         (self.lock.lock(), self.inner.borrow_mut())
     }
+
+    /// Free the committed pages containing only zeros, scanning at most
+    /// `max_pages` of them from `start_idx`.
+    ///
+    /// Returns the number of pages scanned and freed, and the page to continue
+    /// from if the scan stops before the end of the VMO.
+    pub(super) fn dedup_zero_pages(
+        &self,
+        start_idx: usize,
+        max_pages: usize,
+    ) -> (usize, usize, Option<usize>) {
+        let (_guard, mut inner) = self.get_inner_mut();
+        inner.clear_invalild_mappings();
+        // the pages of a snapshot may be shared, those of a pager are its
+        // cache, and without page faults, a mapped page can't be got back
+        if inner.parent.is_some()
+            || inner.type_.is_hidden()
+            || inner.pager.is_some()
+            || inner.contiguous
+            || inner.cache_policy != CachePolicy::Cached
+            || (cfg!(feature = "libos") && !inner.mappings.is_empty())
+        {
+            return (0, 0, None);
+        }
+        let mut scanned: Vec<usize> = inner
+            .frames
+            .range(start_idx..)
+            .map(|(&idx, _)| idx)
+            .take(max_pages + 1)
+            .collect();
+        let next = if scanned.len() > max_pages {
+            scanned.pop()
+        } else {
+            None
+        };
+        let mut freed = 0;
+        for &idx in scanned.iter() {
+            let page = &inner.frames[&idx];
+            if page.pin_count != 0 || !is_zero_page(page.frame.paddr()) {
+                continue;
+            }
+            inner.unmap_page(idx);
+            // the page may be written until it is unmapped
+            if is_zero_page(inner.frames[&idx].frame.paddr()) {
+                inner.frames.remove(&idx);
+                freed += 1;
+            }
+        }
+        (scanned.len(), freed, next)
+    }
 }
 
 impl VMObjectTrait for VMObjectPaged {
@@ -254,14 +321,33 @@ impl VMObjectTrait for VMObjectPaged {
         };
         let mut unwanted = VecDeque::new();
         for block in iter {
-            //let paddr = self.commit_page(block.block, MMUFlags::READ)?;
+            let page_idx = block.block;
             if block.len() == PAGE_SIZE && !inner.is_contiguous() {
-                let _ = inner.commit_page(block.block, MMUFlags::WRITE)?;
-                unwanted.push_back(block.block + inner.parent_offset / PAGE_SIZE);
-                inner.frames.remove(&block.block);
-            } else if inner.committed_pages_in_range(block.block, block.block + 1) != 0 {
+                match inner.frames.get(&page_idx) {
+                    // a pinned page stays where it is
+                    Some(page) if page.pin_count != 0 => {
+                        kernel_hal::mem::pmem_zero(page.frame.paddr(), PAGE_SIZE);
+                        continue;
+                    }
+                    // an uncommitted page reads as zeros, so is left as it is
+                    None if inner.parent.is_none() && inner.pager.is_none() => continue,
+                    _ => {}
+                }
+                inner.unmap_page(page_idx);
+                if inner.pager.is_some() {
+                    // otherwise the page would be supplied by the pager again
+                    let frame = PhysFrame::new_zero().ok_or(ZxError::NO_MEMORY)?;
+                    inner.frames.insert(page_idx, PageState::new(frame));
+                    continue;
+                }
+                if inner.parent.is_some() {
+                    let _ = inner.commit_page(page_idx, MMUFlags::WRITE)?;
+                    unwanted.push_back(page_idx + inner.parent_offset / PAGE_SIZE);
+                }
+                inner.frames.remove(&page_idx);
+            } else if inner.committed_pages_in_range(page_idx, page_idx + 1) != 0 {
                 // check whether this page is initialized, otherwise nothing should be done
-                let paddr = inner.commit_page(block.block, MMUFlags::WRITE)?;
+                let paddr = inner.commit_page(page_idx, MMUFlags::WRITE)?;
                 kernel_hal::mem::pmem_zero(paddr + block.begin, block.len());
             }
         }
@@ -269,6 +355,18 @@ impl VMObjectTrait for VMObjectPaged {
         Ok(())
     }
 
+    fn cache_flush(&self, offset: usize, len: usize) -> ZxResult {
+        let (_guard, inner) = self.get_inner();
+        if offset + len > inner.size {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        let range = offset / PAGE_SIZE..pages(offset + len);
+        for page in inner.frames.range(range).map(|(_, page)| page) {
+            kernel_hal::mem::frame_flush(page.frame.paddr());
+        }
+        Ok(())
+    }
+
     fn len(&self) -> usize {
         self.get_inner().1.size
     }
@@ -287,7 +385,17 @@ impl VMObjectTrait for VMObjectPaged {
     }
 
     fn commit_page(&self, page_idx: usize, flags: MMUFlags) -> ZxResult<PhysAddr> {
-        self.get_inner_mut().1.commit_page(page_idx, flags)
+        let (_guard, mut inner) = self.get_inner_mut();
+        inner.mark_dirty(page_idx, flags);
+        match inner.commit_page(page_idx, flags) {
+            // no mapping is locked when a page fault is handled, so the
+            // freeable pages can be unmapped and reclaimed
+            Err(ZxError::NO_MEMORY) if !inner.freeable.is_empty() => {
+                inner.reclaim_freeable();
+                inner.commit_page(page_idx, flags)
+            }
+            res => res,
+        }
     }
 
     fn commit_pages_with(
@@ -295,13 +403,17 @@ impl VMObjectTrait for VMObjectPaged {
         f: &mut dyn FnMut(&mut dyn FnMut(usize, MMUFlags) -> ZxResult<PhysAddr>) -> ZxResult,
     ) -> ZxResult {
         let (_guard, mut inner) = self.get_inner_mut();
-        f(&mut |page_idx, flags| inner.commit_page(page_idx, flags))
+        f(&mut |page_idx, flags| {
+            inner.mark_dirty(page_idx, flags);
+            inner.commit_page(page_idx, flags)
+        })
     }
 
     fn commit(&self, offset: usize, len: usize) -> ZxResult {
         let (_guard, mut inner) = self.get_inner_mut();
         let start_page = offset / PAGE_SIZE;
         let pages = len / PAGE_SIZE;
+        inner.commit_huge_pages(start_page, start_page + pages);
         for i in 0..pages {
             inner.commit_page(start_page + i, MMUFlags::WRITE)?;
         }
@@ -346,6 +458,9 @@ impl VMObjectTrait for VMObjectPaged {
     fn complete_info(&self, info: &mut VmoInfo) {
         let (_guard, inner) = self.get_inner();
         info.flags |= VmoInfoFlags::TYPE_PAGED;
+        if inner.pager.is_some() {
+            info.flags |= VmoInfoFlags::PAGER_BACKED;
+        }
         inner.complete_info(info);
     }
 
@@ -462,6 +577,76 @@ impl VMObjectTrait for VMObjectPaged {
             }
         }
     }
+
+    fn take_dirty_pages(&self, start_idx: usize, end_idx: usize) -> Vec<usize> {
+        let (_guard, mut inner) = self.get_inner_mut();
+        if cfg!(feature = "libos") {
+            // without page faults, the next write to a page can't be seen,
+            // so a page stays dirty once written
+            return inner.dirty.range(start_idx..end_idx).copied().collect();
+        }
+        let mut taken = inner.dirty.split_off(&start_idx);
+        let mut rest = taken.split_off(&end_idx);
+        inner.dirty.append(&mut rest);
+        taken.into_iter().collect()
+    }
+
+    fn write_protect(&self, start_idx: usize, end_idx: usize) {
+        if cfg!(feature = "libos") || start_idx >= end_idx {
+            return;
+        }
+        let (_guard, inner) = self.get_inner();
+        for map in inner.mappings.iter() {
+            if let Some(map) = map.upgrade() {
+                map.range_change(start_idx, end_idx - start_idx, RangeChangeOp::RemoveWrite);
+            }
+        }
+    }
+
+    fn mark_freeable(&self, start_idx: usize, end_idx: usize) {
+        if cfg!(feature = "libos") || start_idx >= end_idx {
+            // without page faults, the next write to a page can't be seen
+            return;
+        }
+        let (_guard, mut inner) = self.get_inner_mut();
+        // the pages of a snapshot may be shared, and those of a pager are its cache
+        if inner.parent.is_some() || inner.pager.is_some() {
+            return;
+        }
+        let freeable: Vec<usize> = inner
+            .frames
+            .range(start_idx..end_idx)
+            .filter(|(_, page)| page.pin_count == 0)
+            .map(|(&idx, _)| idx)
+            .collect();
+        inner.freeable.extend(freeable);
+        // the write to a page faults, and then it is not freeable
+        for map in inner.mappings.iter() {
+            if let Some(map) = map.upgrade() {
+                map.range_change(start_idx, end_idx - start_idx, RangeChangeOp::RemoveWrite);
+            }
+        }
+    }
+
+    fn supply_pages(&self, offset: usize, buf: &[u8]) -> ZxResult {
+        let (_guard, mut inner) = self.get_inner_mut();
+        if inner.pager.is_none() {
+            return Err(ZxError::NOT_SUPPORTED);
+        }
+        if offset + buf.len() > inner.size {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        for (i, data) in buf.chunks(PAGE_SIZE).enumerate() {
+            let page_idx = offset / PAGE_SIZE + i;
+            if inner.frames.contains_key(&page_idx) {
+                continue;
+            }
+            let frame = PhysFrame::new().ok_or(ZxError::NO_MEMORY)?;
+            kernel_hal::mem::pmem_write(frame.paddr(), data);
+            inner.frames.insert(page_idx, PageState::new(frame));
+        }
+        Ok(())
+    }
 }
 
 enum CommitResult {
@@ -518,6 +703,9 @@ impl VMObjectPagedInner {
     }
 
     fn commit_page(&mut self, page_idx: usize, flags: MMUFlags) -> ZxResult<PhysAddr> {
+        if flags.contains(MMUFlags::WRITE) {
+            self.freeable.remove(&page_idx);
+        }
         let ret = match self.commit_page_internal(page_idx, flags, &Weak::new())? {
             CommitResult::Ref(paddr) => Ok(paddr),
             _ => unreachable!(),
@@ -546,13 +734,20 @@ impl VMObjectPagedInner {
         if no_frame {
             // if out_of_range
             if out_of_range || no_parent {
-                if !flags.contains(MMUFlags::WRITE) {
-                    // read-only, just return zero frame
-                    return Ok(CommitResult::Ref(kernel_hal::mem::ZERO_FRAME.paddr()));
-                }
-                // lazy allocate zero frame
-                // 这里会调用HAL层的hal_frame_alloc, 请注意实现该函数时参数要一样
-                let target_frame = PhysFrame::new_zero().ok_or(ZxError::NO_MEMORY)?;
+                let target_frame = match &self.pager {
+                    // the page is read from the pager even if only for reading,
+                    // it has content unlike the zero frame
+                    Some(pager) if !out_of_range => supply_page(&**pager, page_idx)?,
+                    _ => {
+                        if !flags.contains(MMUFlags::WRITE) {
+                            // read-only, just return zero frame
+                            return Ok(CommitResult::Ref(kernel_hal::mem::ZERO_FRAME.paddr()));
+                        }
+                        // lazy allocate zero frame
+                        // 这里会调用HAL层的hal_frame_alloc, 请注意实现该函数时参数要一样
+                        PhysFrame::new_zero().ok_or(ZxError::NO_MEMORY)?
+                    }
+                };
                 if out_of_range {
                     // can never be a hidden vmo
                     assert!(!self.type_.is_hidden());
@@ -643,6 +838,75 @@ impl VMObjectPagedInner {
         self.frames.remove(&page_idx);
     }
 
+    /// Record a page written through a mapping, to be written back to the
+    /// source of the pager.
+    fn mark_dirty(&mut self, page_idx: usize, flags: MMUFlags) {
+        if self.pager.is_some() && flags.contains(MMUFlags::WRITE) {
+            self.dirty.insert(page_idx);
+        }
+    }
+
+    /// Commit each huge page in `[start_idx, end_idx)` with nothing committed
+    /// by contiguous and aligned frames, so that it can be mapped by one entry
+    /// of the page table. Otherwise, the pages are left to be committed alone.
+    fn commit_huge_pages(&mut self, start_idx: usize, end_idx: usize) {
+        // the page table of libos maps 4K pages only, and the pages of a
+        // snapshot may be in its parent
+        if cfg!(feature = "libos")
+            || self.parent.is_some()
+            || self.type_.is_hidden()
+            || self.pager.is_some()
+            || self.contiguous
+            || self.cache_policy != CachePolicy::Cached
+        {
+            return;
+        }
+        let end_idx = end_idx.min(self.size / PAGE_SIZE);
+        let mut idx = ceil(start_idx, HUGE_PAGE_PAGES) * HUGE_PAGE_PAGES;
+        while idx + HUGE_PAGE_PAGES <= end_idx {
+            if self
+                .frames
+                .range(idx..idx + HUGE_PAGE_PAGES)
+                .next()
+                .is_none()
+            {
+                let frames = PhysFrame::new_contiguous(
+                    HUGE_PAGE_PAGES,
+                    HUGE_PAGE_SIZE.trailing_zeros() as usize - PAGE_SIZE_LOG2,
+                );
+                if frames.is_empty() {
+                    return;
+                }
+                kernel_hal::mem::pmem_zero(frames[0].paddr(), HUGE_PAGE_SIZE);
+                for (i, frame) in frames.into_iter().enumerate() {
+                    self.frames.insert(idx + i, PageState::new(frame));
+                }
+            }
+            idx += HUGE_PAGE_PAGES;
+        }
+    }
+
+    /// Unmap the page at `page_idx` from the mappings.
+    fn unmap_page(&self, page_idx: usize) {
+        for map in self.mappings.iter() {
+            if let Some(map) = map.upgrade() {
+                map.range_change(page_idx, 1, RangeChangeOp::Unmap);
+            }
+        }
+    }
+
+    /// Reclaim the freeable pages, unmapping them from the mappings.
+    fn reclaim_freeable(&mut self) {
+        for idx in core::mem::take(&mut self.freeable) {
+            match self.frames.get(&idx) {
+                Some(page) if page.pin_count == 0 && !page.tag.is_split() => {}
+                _ => continue,
+            }
+            self.unmap_page(idx);
+            self.frames.remove(&idx);
+        }
+    }
+
     fn range_change(&self, parent_offset: usize, parent_limit: usize, op: RangeChangeOp) {
         let mut start = self.parent_offset.max(parent_offset);
         let mut end = self.parent_limit.min(parent_limit);
@@ -772,6 +1036,10 @@ impl VMObjectPagedInner {
         if self.cache_policy != CachePolicy::Cached || self.pin_count != 0 {
             return Err(ZxError::BAD_STATE);
         }
+        // the pages of a snapshot would be supplied by nobody
+        if self.pager.is_some() {
+            return Err(ZxError::NOT_SUPPORTED);
+        }
         // create child VMO
         let child = VMObjectPaged::wrap(
             VMObjectPagedInner {
@@ -787,6 +1055,9 @@ impl VMObjectPagedInner {
                 contiguous: false,
                 self_ref: Default::default(),
                 pin_count: 0,
+                pager: None,
+                dirty: BTreeSet::new(),
+                freeable: BTreeSet::new(),
             },
             Some(lock_ref.clone()),
         );
@@ -808,6 +1079,9 @@ impl VMObjectPagedInner {
                 contiguous: self.contiguous,
                 self_ref: Default::default(),
                 pin_count: self.pin_count,
+                pager: None,
+                dirty: BTreeSet::new(),
+                freeable: BTreeSet::new(),
             },
             Some(lock_ref.clone()),
         );
@@ -827,6 +1101,8 @@ impl VMObjectPagedInner {
         self.parent = Some(hidden.clone());
         self.parent_offset = 0;
         self.parent_limit = self.size;
+        // the pages are shared with the child now
+        self.freeable.clear();
         child.inner.borrow_mut().parent = Some(hidden);
         // update mappings, for COW, remove write flags in PageTable
         for map in self.mappings.iter() {
@@ -1080,6 +1356,29 @@ fn new_owner_id() -> u64 {
     OWNER_ID.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Allocate a frame for the page at `page_idx`, filled by `pager`.
+#[allow(unsafe_code)]
+fn supply_page(pager: &dyn VmoPager, page_idx: usize) -> ZxResult<PhysFrame> {
+    let frame = PhysFrame::new().ok_or(ZxError::NO_MEMORY)?;
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(phys_to_virt(frame.paddr()) as *mut u8, PAGE_SIZE)
+    };
+    pager.supply_page(page_idx, buf)?;
+    Ok(frame)
+}
+
+/// Whether the page at `paddr` contains only zeros.
+#[allow(unsafe_code)]
+fn is_zero_page(paddr: PhysAddr) -> bool {
+    let words = unsafe {
+        core::slice::from_raw_parts(
+            phys_to_virt(paddr) as *const u64,
+            PAGE_SIZE / core::mem::size_of::<u64>(),
+        )
+    };
+    words.iter().all(|&w| w == 0)
+}
+
 const VM_PAGE_OBJECT_MAX_PIN_COUNT: u8 = 31;
 
 #[cfg(test)]
@@ -1092,6 +1391,41 @@ mod tests {
         super::super::tests::read_write(&*vmo);
     }
 
+    #[test]
+    fn dedup_zero_pages() {
+        let vmo = VMObjectPaged::new(4);
+        vmo.write(0, &[1; 8]).unwrap();
+        vmo.commit(PAGE_SIZE, 2 * PAGE_SIZE).unwrap();
+        vmo.write(3 * PAGE_SIZE + 8, &[2]).unwrap();
+        assert_eq!(vmo.committed_pages_in_range(0, 4), 4);
+
+        assert_eq!(vmo.dedup_zero_pages(0, 2), (2, 1, Some(2)));
+        assert_eq!(vmo.dedup_zero_pages(2, 2), (2, 1, None));
+        assert_eq!(vmo.committed_pages_in_range(0, 4), 2);
+        let mut buf = [0xff; 8];
+        vmo.read(PAGE_SIZE, &mut buf).unwrap();
+        assert_eq!(buf, [0; 8]);
+        vmo.read(3 * PAGE_SIZE + 8, &mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 2);
+    }
+
+    #[test]
+    fn zero() {
+        let vmo = VmObject::new_paged(3);
+        vmo.test_write(1, 1);
+        vmo.zero(0, 2 * PAGE_SIZE).unwrap();
+        assert_eq!(vmo.committed_pages_in_range(0, 3), 0);
+        assert_eq!(vmo.test_read(1), 0);
+
+        // a partial page is zeroed in place, if committed
+        vmo.write(2 * PAGE_SIZE, &[1; 8]).unwrap();
+        vmo.zero(2 * PAGE_SIZE + 4, 4).unwrap();
+        let mut buf = [0xff; 8];
+        vmo.read(2 * PAGE_SIZE, &mut buf).unwrap();
+        assert_eq!(buf, [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(vmo.committed_pages_in_range(0, 3), 1);
+    }
+
     #[test]
     fn create_child() {
         let vmo = VmObject::new_paged(1);
@@ -1108,6 +1442,30 @@ mod tests {
         assert_eq!(child_vmo.test_read(0), 2);
     }
 
+    #[test]
+    fn children_info() {
+        let vmo = VmObject::new_paged(2);
+        vmo.test_write(0, 1);
+        let snapshot = vmo.create_child(false, 0, PAGE_SIZE).unwrap();
+        let slice = vmo.create_slice(PAGE_SIZE, PAGE_SIZE).unwrap();
+        assert_eq!(vmo.get_info().num_children, 2);
+        assert_eq!(snapshot.get_info().parent_koid, vmo.id());
+        assert_eq!(slice.get_info().parent_koid, vmo.id());
+        assert!(snapshot
+            .get_info()
+            .flags
+            .contains(VmoInfoFlags::IS_COW_CLONE));
+
+        // a snapshot doesn't see the changes of its parent, and a slice does
+        vmo.test_write(0, 2);
+        vmo.test_write(1, 3);
+        assert_eq!(snapshot.test_read(0), 1);
+        assert_eq!(slice.test_read(0), 3);
+
+        drop(snapshot);
+        assert_eq!(vmo.get_info().num_children, 1);
+    }
+
     #[test]
     #[ignore] // FIXME
     fn zero_page_write() {
@@ -1209,6 +1567,57 @@ mod tests {
         }
     }
 
+    struct TestPager;
+
+    impl VmoPager for TestPager {
+        fn supply_page(&self, page_idx: usize, buf: &mut [u8]) -> ZxResult {
+            buf.fill(page_idx as u8 + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pager() {
+        let vmo = VmObject::new_paged_with_pager(3, Arc::new(TestPager));
+        assert_eq!(vmo.test_read(0), 1);
+        assert_eq!(vmo.test_read(2), 3);
+        assert_eq!(vmo.committed_pages_in_range(0, 3), 2);
+
+        // only writes through mappings make pages dirty
+        vmo.test_write(0, 4);
+        vmo.commit_page(1, MMUFlags::WRITE).unwrap();
+        assert_eq!(vmo.test_read(1), 2);
+        assert_eq!(vmo.take_dirty_pages(0, 3), [1]);
+        if !cfg!(feature = "libos") {
+            assert!(vmo.take_dirty_pages(0, 3).is_empty());
+        }
+
+        // the pages of a slice are supplied by its parent
+        let slice = vmo.create_slice(PAGE_SIZE, 2 * PAGE_SIZE).unwrap();
+        slice.commit_page(1, MMUFlags::WRITE).unwrap();
+        assert!(slice.take_dirty_pages(0, 2).contains(&1));
+        assert!(vmo.create_child(false, 0, PAGE_SIZE).is_err());
+    }
+
+    #[test]
+    fn freeable() {
+        let vmo = VMObjectPaged::new(2);
+        vmo.write(0, &[1]).unwrap();
+        vmo.write(PAGE_SIZE, &[2]).unwrap();
+        vmo.mark_freeable(0, 2);
+        // written again, so it is kept
+        vmo.commit_page(1, MMUFlags::WRITE).unwrap();
+        vmo.get_inner_mut().1.reclaim_freeable();
+        let mut buf = [0; 1];
+        vmo.read(PAGE_SIZE, &mut buf).unwrap();
+        assert_eq!(buf[0], 2);
+        if !cfg!(feature = "libos") {
+            assert_eq!(vmo.committed_pages_in_range(0, 2), 1);
+            vmo.read(0, &mut buf).unwrap();
+            assert_eq!(buf[0], 0);
+        }
+    }
+
     impl VmObject {
         pub fn test_write(&self, page: usize, value: u8) {
             self.write(page * PAGE_SIZE, &[value]).unwrap();
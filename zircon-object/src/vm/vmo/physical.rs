@@ -57,6 +57,16 @@ impl VMObjectTrait for VMObjectPhysical {
         Ok(())
     }
 
+    fn cache_flush(&self, offset: usize, len: usize) -> ZxResult {
+        if offset + len > self.len() {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        for page_offset in (round_down_pages(offset)..offset + len).step_by(PAGE_SIZE) {
+            kernel_hal::mem::frame_flush(self.paddr + page_offset);
+        }
+        Ok(())
+    }
+
     fn len(&self) -> usize {
         self.pages * PAGE_SIZE
     }
@@ -0,0 +1,114 @@
+//! The zero page scanner, which frees the committed pages of VMOs containing
+//! only zeros, like the one of Zircon.
+
+use {super::*, alloc::collections::BTreeMap, core::time::Duration, lazy_static::lazy_static};
+
+kcounter!(ZERO_SCAN_SCANNED, "vm.scanner.zero_scan.scanned");
+kcounter!(ZERO_SCAN_DEDUPED, "vm.scanner.zero_scan.deduped");
+
+/// Number of pages scanned with the lock of a VMO held.
+const SCAN_BATCH_PAGES: usize = 64;
+
+lazy_static! {
+    /// All paged VMOs, by their addresses.
+    static ref PAGED_VMOS: Mutex<BTreeMap<usize, Weak<VMObjectPaged>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Where the next scan continues: the address of a VMO, and a page of it.
+static SCAN_CURSOR: Mutex<(usize, usize)> = Mutex::new((0, 0));
+
+/// Statistics of the zero page scanner.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZeroPageStats {
+    /// Number of committed pages scanned.
+    pub scanned_pages: usize,
+    /// Number of pages freed for containing only zeros.
+    pub deduped_pages: usize,
+}
+
+/// Get the statistics of the zero page scanner.
+pub fn zero_page_stats() -> ZeroPageStats {
+    ZeroPageStats {
+        scanned_pages: ZERO_SCAN_SCANNED.get(),
+        deduped_pages: ZERO_SCAN_DEDUPED.get(),
+    }
+}
+
+/// Add a new paged VMO to be scanned.
+pub(super) fn register_vmo(vmo: &Arc<VMObjectPaged>) {
+    PAGED_VMOS
+        .lock()
+        .insert(Arc::as_ptr(vmo) as usize, Arc::downgrade(vmo));
+}
+
+/// Scan at most `max_pages` committed pages of VMOs, continuing from where
+/// the last scan stopped, and free those containing only zeros. The shared
+/// zero page is read from them then.
+///
+/// Returns the number of pages freed.
+pub fn scan_zero_pages(max_pages: usize) -> usize {
+    let mut cursor = SCAN_CURSOR.lock();
+    let (mut scanned, mut freed) = (0, 0);
+    let mut wrapped = false;
+    while scanned < max_pages {
+        let next = PAGED_VMOS
+            .lock()
+            .range(cursor.0..)
+            .next()
+            .map(|(&key, vmo)| (key, vmo.clone()));
+        let (key, vmo) = match next {
+            Some(next) => next,
+            // start over from the first VMO, once in a scan
+            None if !wrapped => {
+                *cursor = (0, 0);
+                wrapped = true;
+                continue;
+            }
+            None => break,
+        };
+        match vmo.upgrade() {
+            Some(vmo) => {
+                let batch = (max_pages - scanned).min(SCAN_BATCH_PAGES);
+                let (n, f, next_idx) = vmo.dedup_zero_pages(cursor.1, batch);
+                scanned += n;
+                freed += f;
+                *cursor = match next_idx {
+                    Some(idx) => (key, idx),
+                    None => (key + 1, 0),
+                };
+            }
+            None => {
+                let mut vmos = PAGED_VMOS.lock();
+                // the address may be of a new VMO now
+                if vmos.get(&key).map_or(false, |vmo| vmo.strong_count() == 0) {
+                    vmos.remove(&key);
+                }
+                *cursor = (key + 1, 0);
+            }
+        }
+    }
+    ZERO_SCAN_SCANNED.add(scanned);
+    ZERO_SCAN_DEDUPED.add(freed);
+    freed
+}
+
+/// Start a task scanning `pages_per_second` committed pages of VMOs every
+/// second, to free those containing only zeros.
+pub fn start_zero_page_scanner(pages_per_second: usize) {
+    info!("zero page scanner: {} pages per second", pages_per_second);
+    kernel_hal::thread::spawn(async move {
+        loop {
+            let deadline = kernel_hal::timer::timer_now() + Duration::from_secs(1);
+            kernel_hal::thread::sleep_until(deadline).await;
+            let mut freed = 0;
+            for pos in (0..pages_per_second).step_by(SCAN_BATCH_PAGES) {
+                freed += scan_zero_pages((pages_per_second - pos).min(SCAN_BATCH_PAGES));
+                kernel_hal::thread::yield_now().await;
+            }
+            if freed != 0 {
+                debug!("zero page scanner: {} pages freed", freed);
+            }
+        }
+    });
+}
@@ -7,6 +7,8 @@ pub struct VMObjectSlice {
     offset: usize,
     /// The size in bytes.
     size: usize,
+    /// All mappings to this slice.
+    mappings: Mutex<Vec<Weak<VmMapping>>>,
 }
 
 impl VMObjectSlice {
@@ -15,6 +17,7 @@ impl VMObjectSlice {
             parent,
             offset,
             size,
+            mappings: Mutex::new(Vec::new()),
         })
     }
 
@@ -42,6 +45,11 @@ impl VMObjectTrait for VMObjectSlice {
         self.parent.zero(offset + self.offset, len)
     }
 
+    fn cache_flush(&self, offset: usize, len: usize) -> ZxResult {
+        self.check_range(offset, len)?;
+        self.parent.cache_flush(offset + self.offset, len)
+    }
+
     fn len(&self) -> usize {
         self.size
     }
@@ -59,7 +67,9 @@ impl VMObjectTrait for VMObjectSlice {
         &self,
         f: &mut dyn FnMut(&mut dyn FnMut(usize, MMUFlags) -> ZxResult<PhysAddr>) -> ZxResult,
     ) -> ZxResult {
-        self.parent.commit_pages_with(f)
+        let po = self.offset / PAGE_SIZE;
+        self.parent
+            .commit_pages_with(&mut |commit| f(&mut |page_idx, flags| commit(page_idx + po, flags)))
     }
 
     fn commit(&self, offset: usize, len: usize) -> ZxResult {
@@ -74,6 +84,18 @@ impl VMObjectTrait for VMObjectSlice {
         Err(ZxError::NOT_SUPPORTED)
     }
 
+    fn append_mapping(&self, mapping: Weak<VmMapping>) {
+        let mut mappings = self.mappings.lock();
+        mappings.retain(|map| map.strong_count() != 0);
+        mappings.push(mapping);
+    }
+
+    fn remove_mapping(&self, mapping: Weak<VmMapping>) {
+        self.mappings
+            .lock()
+            .retain(|map| map.strong_count() != 0 && !map.ptr_eq(&mapping));
+    }
+
     fn complete_info(&self, info: &mut VmoInfo) {
         self.parent.complete_info(info);
     }
@@ -109,4 +131,31 @@ impl VMObjectTrait for VMObjectSlice {
     fn is_paged(&self) -> bool {
         self.parent.is_paged()
     }
+
+    fn is_slice(&self) -> bool {
+        true
+    }
+
+    fn take_dirty_pages(&self, start_idx: usize, end_idx: usize) -> Vec<usize> {
+        let po = pages(self.offset);
+        let end_idx = end_idx.min(pages(self.size));
+        if start_idx >= end_idx {
+            return Vec::new();
+        }
+        let mut dirty = self.parent.take_dirty_pages(start_idx + po, end_idx + po);
+        dirty.iter_mut().for_each(|idx| *idx -= po);
+        dirty
+    }
+
+    fn write_protect(&self, start_idx: usize, end_idx: usize) {
+        let end_idx = end_idx.min(pages(self.size));
+        if cfg!(feature = "libos") || start_idx >= end_idx {
+            return;
+        }
+        for map in self.mappings.lock().iter() {
+            if let Some(map) = map.upgrade() {
+                map.range_change(start_idx, end_idx - start_idx, RangeChangeOp::RemoveWrite);
+            }
+        }
+    }
 }
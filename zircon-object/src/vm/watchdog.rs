@@ -0,0 +1,199 @@
+//! The memory watchdog, which signals the events of memory pressure, and
+//! kills jobs when the system runs out of memory, like the one of Zircon.
+
+use {
+    super::*,
+    crate::{
+        object::{KernelObject, Signal},
+        signal::Event,
+        task::{Job, Task},
+    },
+    alloc::sync::Weak,
+    core::time::Duration,
+    lazy_static::lazy_static,
+    lock::Mutex,
+};
+
+/// The level of memory pressure of the system, from the most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PressureLevel {
+    /// The system is out of memory, and jobs are killed.
+    OutOfMemory = 0,
+    /// Free memory is critically low.
+    Critical = 1,
+    /// Free memory is low.
+    Warning = 2,
+    /// Enough memory is free.
+    Normal = 3,
+}
+
+/// Thresholds of free memory in bytes, below which the system is under
+/// each level of memory pressure.
+#[derive(Debug, Clone, Copy)]
+pub struct PressureThresholds {
+    /// Below this the system is out of memory.
+    pub out_of_memory: usize,
+    /// Below this memory pressure is critical.
+    pub critical: usize,
+    /// Below this memory pressure is at warning level.
+    pub warning: usize,
+}
+
+impl PressureThresholds {
+    /// Get the level of memory pressure when `free_bytes` are free.
+    pub fn level(&self, free_bytes: usize) -> PressureLevel {
+        if free_bytes < self.out_of_memory {
+            PressureLevel::OutOfMemory
+        } else if free_bytes < self.critical {
+            PressureLevel::Critical
+        } else if free_bytes < self.warning {
+            PressureLevel::Warning
+        } else {
+            PressureLevel::Normal
+        }
+    }
+}
+
+lazy_static! {
+    /// The events of each level of memory pressure, the one of the current
+    /// level signaled.
+    static ref PRESSURE_EVENTS: [Arc<Event>; 4] = {
+        let events = [Event::new(), Event::new(), Event::new(), Event::new()];
+        events[PressureLevel::Normal as usize].signal_set(Signal::SIGNALED);
+        events
+    };
+}
+
+/// The current level of memory pressure.
+static PRESSURE_LEVEL: Mutex<PressureLevel> = Mutex::new(PressureLevel::Normal);
+
+/// Get the event signaled while the system is out of memory.
+pub fn oom_event() -> Arc<Event> {
+    pressure_event(PressureLevel::OutOfMemory)
+}
+
+/// Get the event signaled while the system is under the `level` of memory
+/// pressure.
+pub fn pressure_event(level: PressureLevel) -> Arc<Event> {
+    PRESSURE_EVENTS[level as usize].clone()
+}
+
+/// Get the current level of memory pressure.
+pub fn pressure_level() -> PressureLevel {
+    *PRESSURE_LEVEL.lock()
+}
+
+/// Check the free memory of the system against `thresholds`, and signal the
+/// event of the level of memory pressure, clearing the others.
+///
+/// If the system is out of memory, the least important job under `root_job`
+/// to be killed on OOM is killed, which is returned.
+pub fn check_memory(root_job: &Arc<Job>, thresholds: &PressureThresholds) -> Option<Arc<Job>> {
+    let free_bytes = kernel_hal::mem::frame_stats().free() * PAGE_SIZE;
+    let level = thresholds.level(free_bytes);
+    {
+        let mut current = PRESSURE_LEVEL.lock();
+        if *current != level {
+            debug!(
+                "memory watchdog: {:?} => {:?}, {} bytes free",
+                *current, level, free_bytes
+            );
+            PRESSURE_EVENTS[*current as usize].signal_clear(Signal::SIGNALED);
+            PRESSURE_EVENTS[level as usize].signal_set(Signal::SIGNALED);
+            *current = level;
+        }
+    }
+    if level != PressureLevel::OutOfMemory {
+        return None;
+    }
+    let victim = root_job.find_oom_victim();
+    match &victim {
+        Some(job) => {
+            warn!(
+                "memory watchdog: {} bytes free, killing job {} committing {} bytes",
+                free_bytes,
+                job.id(),
+                job.committed_bytes()
+            );
+            job.kill();
+        }
+        None => warn!("memory watchdog: {} bytes free, no job to kill", free_bytes),
+    }
+    victim
+}
+
+/// Start a task checking the free memory of the system every second against
+/// `thresholds`, and killing jobs under `root_job` if it is out of memory.
+///
+/// The task stops after `root_job` is dropped.
+pub fn start_memory_watchdog(root_job: &Arc<Job>, thresholds: PressureThresholds) {
+    info!("memory watchdog: {:#x?}", thresholds);
+    let root_job = Arc::downgrade(root_job);
+    kernel_hal::thread::spawn(async move {
+        loop {
+            let deadline = kernel_hal::timer::timer_now() + Duration::from_secs(1);
+            kernel_hal::thread::sleep_until(deadline).await;
+            match Weak::upgrade(&root_job) {
+                Some(root_job) => {
+                    check_memory(&root_job, &thresholds);
+                }
+                None => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Process;
+
+    #[test]
+    fn pressure() {
+        let root_job = Job::root();
+        let job1 = root_job.create_child().unwrap();
+        let job2 = job1.create_child().unwrap();
+        let job3 = root_job.create_child().unwrap();
+        let _proc = Process::create(&job2, "proc").unwrap();
+        job1.set_kill_on_oom(true);
+        job2.set_kill_on_oom(true);
+        job3.set_kill_on_oom(true);
+
+        let signaled = |level| pressure_event(level).signal().contains(Signal::SIGNALED);
+        let mut thresholds = PressureThresholds {
+            out_of_memory: 0,
+            critical: 0,
+            warning: 0,
+        };
+
+        // plenty of memory
+        assert!(check_memory(&root_job, &thresholds).is_none());
+        assert_eq!(pressure_level(), PressureLevel::Normal);
+        assert!(signaled(PressureLevel::Normal));
+        assert!(!signaled(PressureLevel::OutOfMemory));
+
+        thresholds.warning = usize::MAX;
+        assert!(check_memory(&root_job, &thresholds).is_none());
+        assert_eq!(pressure_level(), PressureLevel::Warning);
+        assert!(signaled(PressureLevel::Warning));
+        assert!(!signaled(PressureLevel::Normal));
+
+        thresholds.critical = usize::MAX;
+        assert!(check_memory(&root_job, &thresholds).is_none());
+        assert_eq!(pressure_level(), PressureLevel::Critical);
+        assert!(signaled(PressureLevel::Critical));
+        assert!(!signaled(PressureLevel::Warning));
+
+        // the deepest job is killed first
+        thresholds.out_of_memory = usize::MAX;
+        let victim = check_memory(&root_job, &thresholds).unwrap();
+        assert_eq!(victim.id(), job2.id());
+        assert!(signaled(PressureLevel::OutOfMemory));
+        assert!(!signaled(PressureLevel::Critical));
+        let victim = check_memory(&root_job, &thresholds).unwrap();
+        assert_eq!(victim.id(), job1.id());
+        let victim = check_memory(&root_job, &thresholds).unwrap();
+        assert_eq!(victim.id(), job3.id());
+        assert!(check_memory(&root_job, &thresholds).is_none());
+    }
+}
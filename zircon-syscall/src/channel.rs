@@ -5,7 +5,7 @@ use {
     zircon_object::{
         ipc::{Channel, MessagePacket},
         object::{obj_type, HandleInfo},
-        task::ThreadState,
+        task::{PolicyCondition, ThreadState},
     },
 };
 
@@ -127,6 +127,7 @@ impl Syscall<'_> {
             return Err(ZxError::INVALID_ARGS);
         }
         let proc = self.thread.proc();
+        proc.check_policy(PolicyCondition::NewChannel)?;
         let (end0, end1) = Channel::create();
         let handle0 = proc.add_handle(Handle::new(end0, Rights::DEFAULT_CHANNEL));
         let handle1 = proc.add_handle(Handle::new(end1, Rights::DEFAULT_CHANNEL));
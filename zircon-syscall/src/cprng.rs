@@ -1,5 +1,8 @@
 use super::*;
 
+/// The most bytes drawn from or added to the kernel CPRNG at once.
+const CPRNG_MAX_LEN: usize = 256;
+
 impl Syscall<'_> {
     /// Draw random bytes from the kernel CPRNG.
     ///
@@ -8,10 +11,27 @@ impl Syscall<'_> {
     /// Clients that require a large volume of randomness should consider using these bytes to seed a user-space random number generator for better performance.
     pub fn sys_cprng_draw_once(&self, mut buf: UserOutPtr<u8>, len: usize) -> ZxResult {
         info!("cprng_draw_once: buf=({:?}; {:?})", buf, len);
+        if len > CPRNG_MAX_LEN {
+            return Err(ZxError::INVALID_ARGS);
+        }
         let mut res = vec![0u8; len];
         // Fill random bytes to the buffer
         kernel_hal::rand::fill_random(&mut res);
         buf.write_array(&res)?;
         Ok(())
     }
+
+    /// Add entropy to the kernel CPRNG.
+    ///
+    /// The bytes are mixed into the pool, which never weakens the output,
+    /// whatever they are.
+    pub fn sys_cprng_add_entropy(&self, buf: UserInPtr<u8>, len: usize) -> ZxResult {
+        info!("cprng_add_entropy: buf=({:?}; {:?})", buf, len);
+        if len > CPRNG_MAX_LEN {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let data = buf.read_array(len)?;
+        kernel_hal::rand::add_entropy(&data);
+        Ok(())
+    }
 }
@@ -168,7 +168,7 @@ impl Syscall<'_> {
             resource, src_num, options
         );
         let proc = self.thread.proc();
-        let options = InterruptOptions::from_bits_truncate(options);
+        let options = InterruptOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
         let interrupt = if options.contains(InterruptOptions::VIRTUAL) {
             if options != InterruptOptions::VIRTUAL {
                 return Err(ZxError::INVALID_ARGS);
@@ -184,6 +184,61 @@ impl Syscall<'_> {
         Ok(())
     }
 
+    /// Allocates a block of `count` message signaled interrupts.
+    pub fn sys_msi_allocate(
+        &self,
+        resource: HandleValue,
+        count: u32,
+        mut out: UserOutPtr<HandleValue>,
+    ) -> ZxResult {
+        info!(
+            "msi.allocate: resource={:#x?}, count={:#x}",
+            resource, count
+        );
+        let proc = self.thread.proc();
+        proc.get_object::<Resource>(resource)?
+            .validate(ResourceKind::ROOT)?;
+        let allocation = MsiAllocation::create(count)?;
+        let handle = proc.add_handle(Handle::new(allocation, Rights::DEFAULT_MSI));
+        out.write(handle)?;
+        Ok(())
+    }
+
+    /// Creates an interrupt object on the interrupt `msi_id` of an MSI allocation.
+    ///
+    /// The VMO maps the MSI capability of the device at `vmo_offset`, or the MSI-X table
+    /// if `ZX_MSI_MODE_MSI_X` is set in `options`.
+    pub fn sys_msi_create(
+        &self,
+        allocation: HandleValue,
+        options: u32,
+        msi_id: u32,
+        vmo: HandleValue,
+        vmo_offset: usize,
+        mut out: UserOutPtr<HandleValue>,
+    ) -> ZxResult {
+        info!(
+            "msi.create: allocation={:#x?}, options={:#x}, msi_id={:#x}, vmo={:#x?}, offset={:#x}",
+            allocation, options, msi_id, vmo, vmo_offset
+        );
+        if options & !MSI_MODE_MSI_X != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let proc = self.thread.proc();
+        let allocation = proc.get_object::<MsiAllocation>(allocation)?;
+        let vmo = proc.get_object_with_rights::<VmObject>(vmo, Rights::MAP)?;
+        let interrupt = Interrupt::new_msi(
+            allocation,
+            msi_id as usize,
+            vmo,
+            vmo_offset,
+            options & MSI_MODE_MSI_X != 0,
+        )?;
+        let handle = proc.add_handle(Handle::new(interrupt, Rights::DEFAULT_INTERRUPT));
+        out.write(handle)?;
+        Ok(())
+    }
+
     /// Binds or unbinds an interrupt object to a port.
     ///
     /// The key used when binding the interrupt will be present in the key field of the `zx_port_packet_t`.
@@ -291,6 +346,9 @@ bitflags! {
     }
 }
 
+/// The MSI-X table is mapped instead of the MSI capability.
+const MSI_MODE_MSI_X: u32 = 1;
+
 enum InterruptOp {
     Bind = 0,
     Unbind = 1,
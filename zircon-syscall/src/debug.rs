@@ -1,5 +1,6 @@
 use super::*;
 use zircon_object::dev::*;
+use zircon_object::util::ktrace;
 
 impl Syscall<'_> {
     /// Write debug info to the serial port.
@@ -30,4 +31,74 @@ impl Syscall<'_> {
         actual.write(len as u32)?;
         Ok(())
     }
+
+    /// Read the kernel trace records at `offset`.
+    ///
+    /// If `buf` is null, the total size of the records is written to `actual`.
+    pub fn sys_ktrace_read(
+        &self,
+        handle: HandleValue,
+        mut buf: UserOutPtr<u8>,
+        offset: u32,
+        len: usize,
+        mut actual: UserOutPtr<usize>,
+    ) -> ZxResult {
+        info!(
+            "ktrace.read: handle={:#x}, buf=({:?}; {:#x}), offset={:#x}",
+            handle, buf, len, offset
+        );
+        let proc = self.thread.proc();
+        proc.get_object::<Resource>(handle)?
+            .validate(ResourceKind::ROOT)?;
+        if buf.is_null() {
+            actual.write(ktrace::size())?;
+            return Ok(());
+        }
+        let mut vec = vec![0u8; len];
+        let len = ktrace::read(offset as usize, &mut vec);
+        buf.write_array(&vec[..len])?;
+        actual.write(len)?;
+        Ok(())
+    }
+
+    /// Start, stop or rewind the kernel trace, or register a probe.
+    pub fn sys_ktrace_control(
+        &self,
+        handle: HandleValue,
+        action: u32,
+        options: u32,
+        ptr: UserInPtr<u8>,
+    ) -> ZxResult {
+        info!(
+            "ktrace.control: handle={:#x}, action={}, options={:#x}",
+            handle, action, options
+        );
+        let proc = self.thread.proc();
+        proc.get_object::<Resource>(handle)?
+            .validate(ResourceKind::ROOT)?;
+        match action {
+            ktrace::KTRACE_ACTION_START => ktrace::start(options),
+            ktrace::KTRACE_ACTION_STOP => ktrace::stop(),
+            ktrace::KTRACE_ACTION_REWIND => ktrace::rewind()?,
+            ktrace::KTRACE_ACTION_NEW_PROBE => {
+                let id = ktrace::new_probe(ptr.as_c_str()?)?;
+                // special case: return the probe id as status
+                return Err(unsafe { core::mem::transmute(id) });
+            }
+            _ => return Err(ZxError::INVALID_ARGS),
+        }
+        Ok(())
+    }
+
+    /// Record a probe registered by `zx_ktrace_control`.
+    pub fn sys_ktrace_write(&self, handle: HandleValue, id: u32, arg0: u32, arg1: u32) -> ZxResult {
+        info!(
+            "ktrace.write: handle={:#x}, id={:#x}, args=({:#x}, {:#x})",
+            handle, id, arg0, arg1
+        );
+        let proc = self.thread.proc();
+        proc.get_object::<Resource>(handle)?
+            .validate(ResourceKind::ROOT)?;
+        ktrace::probe(id, arg0, arg1)
+    }
 }
@@ -21,7 +21,6 @@ impl Syscall<'_> {
                 .validate(ResourceKind::ROOT)?;
         }
         let dlog = DebugLog::create(options);
-        const FLAG_READABLE: u32 = 0x4000_0000u32;
         let dlog_right = if options & FLAG_READABLE == 0 {
             Rights::DEFAULT_DEBUGLOG
         } else {
@@ -48,7 +47,7 @@ impl Syscall<'_> {
         if options & !LOG_FLAGS_MASK != 0 {
             return Err(ZxError::INVALID_ARGS);
         }
-        let datalen = len.min(224);
+        let datalen = len.min(DLOG_MAX_DATA);
         let data = buf.as_str(datalen as usize)?;
         let proc = self.thread.proc();
         let dlog = proc.get_object_with_rights::<DebugLog>(handle_value, Rights::WRITE)?;
@@ -1,4 +1,4 @@
-use {super::*, zircon_object::ipc::Fifo};
+use {super::*, zircon_object::ipc::Fifo, zircon_object::task::PolicyCondition};
 
 impl Syscall<'_> {
     /// Creates a fifo, which is actually a pair of fifos of `elem_count` entries of `elem_size` bytes.
@@ -20,8 +20,9 @@ impl Syscall<'_> {
         if !elem_count.is_power_of_two() || elem_size == 0 || elem_count * elem_size > 4096 {
             return Err(ZxError::OUT_OF_RANGE);
         }
-        let (end0, end1) = Fifo::create(elem_count, elem_size);
         let proc = self.thread.proc();
+        proc.check_policy(PolicyCondition::NewFIFO)?;
+        let (end0, end1) = Fifo::create(elem_count, elem_size);
         let handle0 = proc.add_handle(Handle::new(end0, Rights::DEFAULT_FIFO));
         let handle1 = proc.add_handle(Handle::new(end1, Rights::DEFAULT_FIFO));
         out0.write(handle0)?;
@@ -18,6 +18,7 @@ use kernel_hal::user::{IoVecIn, IoVecOut, UserInOutPtr, UserInPtr, UserOutPtr};
 use zircon_object::object::{wait_signal_many, KernelObject, KoID, Rights, Signal};
 use zircon_object::object::{Handle, HandleBasicInfo, HandleValue, INVALID_HANDLE};
 use zircon_object::task::{CurrentThread, ThreadFn};
+use zircon_object::util::syscall_stats::{SyscallTable, SyscallTimer};
 use zircon_object::{ZxError, ZxResult};
 
 use self::consts::SyscallType as Sys;
@@ -36,8 +37,10 @@ mod handle;
 #[cfg(feature = "hypervisor")]
 mod hypervisor;
 mod object;
+mod pager;
 mod pci;
 mod port;
+mod profile;
 mod resource;
 mod signal;
 mod socket;
@@ -64,6 +67,7 @@ impl Syscall<'_> {
                 return ZxError::INVALID_ARGS as _;
             }
         };
+        let _timer = SyscallTimer::start(SyscallTable::Zircon, num);
         debug!(
             "{}|{} {:?} => args={:x?}",
             proc_name, thread_name, sys_type, args
@@ -83,6 +87,7 @@ impl Syscall<'_> {
             Sys::OBJECT_SET_PROPERTY => {
                 self.sys_object_set_property(a0 as _, a1 as _, a2 as _, a3 as _)
             }
+            Sys::OBJECT_SET_PROFILE => self.sys_object_set_profile(a0 as _, a1 as _, a2 as _),
             Sys::OBJECT_SIGNAL => self.sys_object_signal(a0 as _, a1 as _, a2 as _),
             Sys::OBJECT_SIGNAL_PEER => self.sys_object_signal_peer(a0 as _, a1 as _, a2 as _),
             Sys::OBJECT_WAIT_ONE => {
@@ -178,18 +183,30 @@ impl Syscall<'_> {
                 self.sys_socket_read(a0 as _, a1 as _, a2.into(), a3 as _, a4.into())
             }
             Sys::SOCKET_SHUTDOWN => self.sys_socket_shutdown(a0 as _, a1 as _),
+            Sys::PAGER_CREATE => self.sys_pager_create(a0 as _, a1.into()),
+            Sys::PAGER_CREATE_VMO => {
+                self.sys_pager_create_vmo(a0 as _, a1 as _, a2 as _, a3 as _, a4 as _, a5.into())
+            }
+            Sys::PAGER_DETACH_VMO => self.sys_pager_detach_vmo(a0 as _, a1 as _),
+            Sys::PAGER_SUPPLY_PAGES => {
+                self.sys_pager_supply_pages(a0 as _, a1 as _, a2 as _, a3 as _, a4 as _, a5 as _)
+            }
             Sys::STREAM_CREATE => self.sys_stream_create(a0 as _, a1 as _, a2 as _, a3.into()),
             Sys::STREAM_WRITEV => {
                 self.sys_stream_writev(a0 as _, a1 as _, a2.into(), a3 as _, a4.into())
+                    .await
             }
             Sys::STREAM_WRITEV_AT => {
                 self.sys_stream_writev_at(a0 as _, a1 as _, a2 as _, a3.into(), a4 as _, a5.into())
+                    .await
             }
             Sys::STREAM_READV => {
                 self.sys_stream_readv(a0 as _, a1 as _, a2.into(), a3 as _, a4.into())
+                    .await
             }
             Sys::STREAM_READV_AT => {
                 self.sys_stream_readv_at(a0 as _, a1 as _, a2 as _, a3.into(), a4 as _, a5.into())
+                    .await
             }
             Sys::STREAM_SEEK => self.sys_stream_seek(a0 as _, a1 as _, a2 as _, a3.into()),
             Sys::FIFO_CREATE => {
@@ -202,10 +219,7 @@ impl Syscall<'_> {
             Sys::PORT_CREATE => self.sys_port_create(a0 as _, a1.into()),
             Sys::PORT_WAIT => self.sys_port_wait(a0 as _, a1.into(), a2.into()).await,
             Sys::PORT_QUEUE => self.sys_port_queue(a0 as _, a1.into()),
-            Sys::PORT_CANCEL => {
-                error!("Skip PORT_CANCEL");
-                Ok(())
-            }
+            Sys::PORT_CANCEL => self.sys_port_cancel(a0 as _, a1 as _, a2 as _),
             Sys::FUTEX_WAIT => {
                 self.sys_futex_wait(a0.into(), a1 as _, a2 as _, a3.into())
                     .await
@@ -216,8 +230,14 @@ impl Syscall<'_> {
             }
             Sys::FUTEX_WAKE_SINGLE_OWNER => self.sys_futex_wake_single_owner(a0.into()),
             Sys::VMO_CREATE => self.sys_vmo_create(a0 as _, a1 as _, a2.into()),
-            Sys::VMO_READ => self.sys_vmo_read(a0 as _, a1.into(), a2 as _, a3 as _),
-            Sys::VMO_WRITE => self.sys_vmo_write(a0 as _, a1.into(), a2 as _, a3 as _),
+            Sys::VMO_READ => {
+                self.sys_vmo_read(a0 as _, a1.into(), a2 as _, a3 as _)
+                    .await
+            }
+            Sys::VMO_WRITE => {
+                self.sys_vmo_write(a0 as _, a1.into(), a2 as _, a3 as _)
+                    .await
+            }
             Sys::VMO_GET_SIZE => self.sys_vmo_get_size(a0 as _, a1.into()),
             Sys::VMO_SET_SIZE => self.sys_vmo_set_size(a0 as _, a1 as _),
             Sys::VMO_OP_RANGE => {
@@ -251,11 +271,16 @@ impl Syscall<'_> {
             }
             Sys::VMAR_PROTECT => self.sys_vmar_protect(a0 as _, a1 as _, a2 as _, a3 as _),
             Sys::VMAR_DESTROY => self.sys_vmar_destroy(a0 as _),
+            Sys::VMAR_OP_RANGE => {
+                self.sys_vmar_op_range(a0 as _, a1 as _, a2 as _, a3 as _, a4.into(), a5 as _)
+            }
             Sys::CPRNG_DRAW_ONCE => self.sys_cprng_draw_once(a0.into(), a1 as _),
+            Sys::CPRNG_ADD_ENTROPY => self.sys_cprng_add_entropy(a0.into(), a1 as _),
             Sys::NANOSLEEP => self.sys_nanosleep(a0.into()).await,
             Sys::CLOCK_CREATE => self.sys_clock_create(a0 as _, a1.into(), a2.into()),
             Sys::CLOCK_GET => self.sys_clock_get(a0 as _, a1.into()),
             Sys::CLOCK_READ => self.sys_clock_read(a0 as _, a1.into()),
+            Sys::CLOCK_GET_DETAILS => self.sys_clock_get_details(a0 as _, a1 as _, a2.into()),
             Sys::CLOCK_ADJUST => self.sys_clock_adjust(a0 as _, a1 as _, a2 as _),
             Sys::CLOCK_UPDATE => self.sys_clock_update(a0 as _, a1 as _, a2.into()),
             Sys::TIMER_CREATE => self.sys_timer_create(a0 as _, a1 as _, a2.into()),
@@ -273,12 +298,18 @@ impl Syscall<'_> {
                 a6.into(),
             ),
             Sys::SYSTEM_GET_EVENT => self.sys_system_get_event(a0 as _, a1 as _, a2.into()),
+            Sys::PROFILE_CREATE => self.sys_profile_create(a0 as _, a1 as _, a2.into(), a3.into()),
             Sys::TIMER_SET => self.sys_timer_set(a0 as _, a1.into(), a2 as _),
             Sys::TIMER_CANCEL => self.sys_timer_cancel(a0 as _),
             Sys::DEBUG_READ => {
                 self.sys_debug_read(a0 as _, a1.into(), a2 as _, a3.into())
                     .await
             }
+            Sys::KTRACE_READ => {
+                self.sys_ktrace_read(a0 as _, a1.into(), a2 as _, a3 as _, a4.into())
+            }
+            Sys::KTRACE_CONTROL => self.sys_ktrace_control(a0 as _, a1 as _, a2 as _, a3.into()),
+            Sys::KTRACE_WRITE => self.sys_ktrace_write(a0 as _, a1 as _, a2 as _, a3 as _),
             Sys::TASK_CREATE_EXCEPTION_CHANNEL => {
                 self.sys_create_exception_channel(a0 as _, a1 as _, a2.into())
             }
@@ -337,6 +368,7 @@ impl Syscall<'_> {
             Sys::PCI_MAP_INTERRUPT => self.sys_pci_map_interrupt(a0 as _, a1 as _, a2.into()),
             Sys::PCI_GET_BAR => self.sys_pci_get_bar(a0 as _, a1 as _, a2.into(), a3.into()),
             Sys::PCI_ENABLE_BUS_MASTER => self.sys_pci_enable_bus_master(a0 as _, a1 != 0),
+            Sys::PCI_RESET_DEVICE => self.sys_pci_reset_device(a0 as _).await,
             Sys::PCI_QUERY_IRQ_MODE => self.sys_pci_query_irq_mode(a0 as _, a1 as _, a2.into()),
             Sys::PCI_SET_IRQ_MODE => self.sys_pci_set_irq_mode(a0 as _, a1 as _, a2 as _),
             Sys::PCI_CONFIG_READ => self.sys_pci_config_read(a0 as _, a1 as _, a2 as _, a3.into()),
@@ -349,6 +381,10 @@ impl Syscall<'_> {
             Sys::INTERRUPT_ACK => self.sys_interrupt_ack(a0 as _),
             Sys::INTERRUPT_DESTROY => self.sys_interrupt_destroy(a0 as _),
             Sys::INTERRUPT_WAIT => self.sys_interrupt_wait(a0 as _, a1.into()).await,
+            Sys::MSI_ALLOCATE => self.sys_msi_allocate(a0 as _, a1 as _, a2.into()),
+            Sys::MSI_CREATE => {
+                self.sys_msi_create(a0 as _, a1 as _, a2 as _, a3 as _, a4 as _, a5.into())
+            }
             Sys::EXCEPTION_GET_THREAD => self.sys_exception_get_thread(a0 as _, a1.into()),
             Sys::EXCEPTION_GET_PROCESS => self.sys_exception_get_process(a0 as _, a1.into()),
             Sys::IOPORTS_REQUEST => {
@@ -3,7 +3,14 @@ use {
     alloc::vec::Vec,
     core::convert::TryFrom,
     numeric_enum_macro::numeric_enum,
-    zircon_object::{dev::*, ipc::*, signal::Port, task::*, vm::*},
+    zircon_object::{
+        dev::*,
+        ipc::*,
+        signal::{Port, WaitAsyncOptions},
+        task::*,
+        util::syscall_stats::{syscall_stats, SyscallStatsInfo, SyscallTable},
+        vm::*,
+    },
 };
 
 impl Syscall<'_> {
@@ -76,6 +83,14 @@ impl Syscall<'_> {
                 info_ptr.write(tx)?;
                 Ok(())
             }
+            Property::JobKillOnOom => {
+                let mut info_ptr = UserOutPtr::<usize>::from_addr_size(buffer, buffer_size)?;
+                let kill = proc
+                    .get_object_with_rights::<Job>(handle_value, Rights::GET_PROPERTY)?
+                    .kill_on_oom();
+                info_ptr.write(kill as usize)?;
+                Ok(())
+            }
             Property::VmoContentSize => {
                 let mut info_ptr = UserOutPtr::<usize>::from_addr_size(buffer, buffer_size)?;
                 let content_size = proc
@@ -164,10 +179,19 @@ impl Syscall<'_> {
                 proc.get_object::<Socket>(handle_value)?
                     .set_write_threshold(threshold)
             }
+            Property::JobKillOnOom => {
+                let kill = UserInPtr::<usize>::from_addr_size(buffer, buffer_size)?.read()?;
+                if kill > 1 {
+                    return Err(ZxError::INVALID_ARGS);
+                }
+                proc.get_object_with_rights::<Job>(handle_value, Rights::SET_PROPERTY)?
+                    .set_kill_on_oom(kill == 1);
+                Ok(())
+            }
             Property::VmoContentSize => {
                 let content_size =
                     UserInPtr::<usize>::from_addr_size(buffer, buffer_size)?.read()?;
-                proc.get_object::<VmObject>(handle_value)?
+                proc.get_object_with_rights::<VmObject>(handle_value, Rights::WRITE)?
                     .set_content_size(content_size)
             }
             Property::ExceptionState => {
@@ -272,6 +296,11 @@ impl Syscall<'_> {
                 let thread = proc.get_object_with_rights::<Thread>(handle, Rights::INSPECT)?;
                 info_ptr.write(thread.get_thread_info())?;
             }
+            Topic::ThreadStats => {
+                let mut info_ptr = UserOutPtr::<ThreadStats>::from_addr_size(buffer, buffer_size)?;
+                let thread = proc.get_object_with_rights::<Thread>(handle, Rights::INSPECT)?;
+                info_ptr.write(thread.get_thread_stats())?;
+            }
             Topic::ThreadExceptionReport => {
                 let mut info_ptr =
                     UserOutPtr::<ExceptionReport>::from_addr_size(buffer, buffer_size)?;
@@ -289,10 +318,22 @@ impl Syscall<'_> {
                 let job = proc.get_object_with_rights::<Job>(handle, Rights::INSPECT)?;
                 info_ptr.write(job.get_info())?;
             }
+            Topic::ProcessMaps => {
+                let process = proc.get_object_with_rights::<Process>(handle, Rights::INSPECT)?;
+                let infos = process.vmar().get_maps_info(&process.name());
+                let count = (buffer_size / core::mem::size_of::<MapsInfo>()).min(infos.len());
+                UserOutPtr::<MapsInfo>::from(buffer).write_array(&infos[..count])?;
+                actual.write(count)?;
+                avail.write(infos.len())?;
+            }
             Topic::ProcessVmos => {
-                warn!("A dummy implementation for utest Bti.NoDelayedUnpin, it does not check the reture value");
-                actual.write(0)?;
-                avail.write(0)?;
+                let infos = proc
+                    .get_object_with_rights::<Process>(handle, Rights::INSPECT)?
+                    .get_vmos_info();
+                let count = (buffer_size / core::mem::size_of::<VmoInfo>()).min(infos.len());
+                UserOutPtr::<VmoInfo>::from(buffer).write_array(&infos[..count])?;
+                actual.write(count)?;
+                avail.write(infos.len())?;
             }
             Topic::Vmo => {
                 let mut info_ptr = UserOutPtr::<VmoInfo>::from_addr_size(buffer, buffer_size)?;
@@ -303,12 +344,29 @@ impl Syscall<'_> {
                 info_ptr.write(info)?;
             }
             Topic::KmemStats => {
-                let mut info_ptr = UserOutPtr::<KmemInfo>::from_addr_size(buffer, buffer_size)?;
+                proc.get_object::<Resource>(handle)?
+                    .validate(ResourceKind::ROOT)?;
+                let frames = kernel_hal::mem::frame_stats();
+                let vmo_bytes = vmo_page_bytes();
                 let kmem = KmemInfo {
-                    vmo_bytes: vmo_page_bytes() as u64,
+                    total_bytes: (frames.total * PAGE_SIZE) as u64,
+                    free_bytes: (frames.free() * PAGE_SIZE) as u64,
+                    // the frames allocated but not committed to VMOs, e.g. page tables
+                    wired_bytes: (frames.allocated * PAGE_SIZE).saturating_sub(vmo_bytes) as u64,
+                    vmo_bytes: vmo_bytes as u64,
                     ..Default::default()
                 };
-                info_ptr.write(kmem)?;
+                // the statistics of the zero page scanner follow, if there is room
+                if buffer_size >= core::mem::size_of::<KmemScannerInfo>() {
+                    let zero_pages = zero_page_stats();
+                    UserOutPtr::<KmemScannerInfo>::from(buffer).write(KmemScannerInfo {
+                        kmem,
+                        zero_scanned_bytes: (zero_pages.scanned_pages * PAGE_SIZE) as u64,
+                        zero_deduped_bytes: (zero_pages.deduped_pages * PAGE_SIZE) as u64,
+                    })?;
+                } else {
+                    UserOutPtr::<KmemInfo>::from_addr_size(buffer, buffer_size)?.write(kmem)?;
+                }
             }
             Topic::TaskStats => {
                 let mut info_ptr =
@@ -359,6 +417,23 @@ impl Syscall<'_> {
                 let stream = proc.get_object_with_rights::<Stream>(handle, Rights::INSPECT)?;
                 info_ptr.write(stream.get_info())?;
             }
+            Topic::Msi => {
+                let mut info_ptr = UserOutPtr::<MsiInfo>::from_addr_size(buffer, buffer_size)?;
+                let allocation =
+                    proc.get_object_with_rights::<MsiAllocation>(handle, Rights::INSPECT)?;
+                info_ptr.write(allocation.get_info())?;
+            }
+            Topic::SyscallStats => {
+                proc.get_object::<Resource>(handle)?
+                    .validate(ResourceKind::ROOT)?;
+                let mut infos = syscall_stats(SyscallTable::Zircon);
+                infos.extend(syscall_stats(SyscallTable::Linux));
+                let count =
+                    (buffer_size / core::mem::size_of::<SyscallStatsInfo>()).min(infos.len());
+                UserOutPtr::<SyscallStatsInfo>::from(buffer).write_array(&infos[..count])?;
+                actual.write(count)?;
+                avail.write(infos.len())?;
+            }
             _ => {
                 error!("not supported info topic: {:?}", topic);
                 return Err(ZxError::NOT_SUPPORTED);
@@ -401,14 +476,11 @@ impl Syscall<'_> {
             "object.wait_async: handle={:#x}, port={:#x}, key={:#x}, signal={:?}, options={:#X}",
             handle_value, port_handle_value, key, signals, options
         );
-        if options != 0 {
-            unimplemented!()
-        }
-        // TODO filter `options`
+        let options = WaitAsyncOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
         let proc = self.thread.proc();
         let object = proc.get_dyn_object_with_rights(handle_value, Rights::WAIT)?;
         let port = proc.get_object_with_rights::<Port>(port_handle_value, Rights::WRITE)?;
-        object.send_signal_to_port_async(signals, &port, key);
+        object.wait_async(signals, &port, key, options);
         Ok(())
     }
 
@@ -526,6 +598,9 @@ numeric_enum! {
         Job = 24,
         Timer = 25,
         Stream = 26,
+        Msi = 28,
+        // zCore extension: statistics of syscalls, for the root resource
+        SyscallStats = 0x5A43_0001,
     }
 }
 
@@ -541,6 +616,7 @@ numeric_enum! {
         ProcessBreakOnLoad = 7,
         SocketRxThreshold = 12,
         SocketTxThreshold = 13,
+        JobKillOnOom = 15,
         ExceptionState = 16,
         VmoContentSize = 17,
         ExceptionStrategy = 18,
@@ -571,3 +647,10 @@ struct KmemInfo {
     ipc_bytes: u64,
     other_bytes: u64,
 }
+
+#[repr(C)]
+struct KmemScannerInfo {
+    kmem: KmemInfo,
+    zero_scanned_bytes: u64,
+    zero_deduped_bytes: u64,
+}
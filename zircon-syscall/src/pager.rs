@@ -0,0 +1,84 @@
+use {super::*, zircon_object::signal::Port, zircon_object::vm::*};
+
+impl Syscall<'_> {
+    /// Create a pager object.
+    ///
+    /// The VMOs created by a pager have their pages supplied by user space.
+    pub fn sys_pager_create(&self, options: u32, mut out: UserOutPtr<HandleValue>) -> ZxResult {
+        info!("pager.create: options={:#x?}", options);
+        if options != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let pager = Pager::new();
+        let handle = self
+            .thread
+            .proc()
+            .add_handle(Handle::new(pager, Rights::DEFAULT_PAGER));
+        out.write(handle)?;
+        Ok(())
+    }
+
+    /// Create a pager owned VMO.
+    ///
+    /// The page requests of the VMO are sent to `port` in packets with `key`.
+    pub fn sys_pager_create_vmo(
+        &self,
+        pager: HandleValue,
+        options: u32,
+        port: HandleValue,
+        key: u64,
+        size: usize,
+        mut out: UserOutPtr<HandleValue>,
+    ) -> ZxResult {
+        info!(
+            "pager.create_vmo: pager={:#x?}, options={:#x?}, port={:#x?}, key={:#x?}, size={:#x?}",
+            pager, options, port, key, size
+        );
+        if options != 0 {
+            return Err(ZxError::NOT_SUPPORTED);
+        }
+        let proc = self.thread.proc();
+        let pager = proc.get_object::<Pager>(pager)?;
+        let port = proc.get_object_with_rights::<Port>(port, Rights::WRITE)?;
+        let vmo = pager.create_vmo(port, key, pages(size));
+        let handle = proc.add_handle(Handle::new(vmo, Rights::DEFAULT_VMO));
+        out.write(handle)?;
+        Ok(())
+    }
+
+    /// Detach a VMO from its pager.
+    ///
+    /// The accesses to its pages not supplied fail afterwards.
+    pub fn sys_pager_detach_vmo(&self, pager: HandleValue, vmo: HandleValue) -> ZxResult {
+        info!("pager.detach_vmo: pager={:#x?}, vmo={:#x?}", pager, vmo);
+        let proc = self.thread.proc();
+        let pager = proc.get_object::<Pager>(pager)?;
+        let vmo = proc.get_object::<VmObject>(vmo)?;
+        pager.detach_vmo(&vmo)
+    }
+
+    /// Supply pages into a pager owned VMO.
+    ///
+    /// The pages are moved from `aux_vmo`, and the accesses waiting for them
+    /// continue.
+    pub fn sys_pager_supply_pages(
+        &self,
+        pager: HandleValue,
+        pager_vmo: HandleValue,
+        offset: usize,
+        length: usize,
+        aux_vmo: HandleValue,
+        aux_offset: usize,
+    ) -> ZxResult {
+        info!(
+            "pager.supply_pages: pager={:#x?}, pager_vmo={:#x?}, offset={:#x?}, length={:#x?}, aux_vmo={:#x?}, aux_offset={:#x?}",
+            pager, pager_vmo, offset, length, aux_vmo, aux_offset
+        );
+        let proc = self.thread.proc();
+        let pager = proc.get_object::<Pager>(pager)?;
+        let pager_vmo = proc.get_object::<VmObject>(pager_vmo)?;
+        let aux_vmo =
+            proc.get_object_with_rights::<VmObject>(aux_vmo, Rights::READ | Rights::WRITE)?;
+        pager.supply_pages(&pager_vmo, offset, length, &aux_vmo, aux_offset)
+    }
+}
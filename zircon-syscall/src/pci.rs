@@ -9,7 +9,7 @@ use zircon_object::{
         PcieDeviceKObject, PcieIrqMode, PmioPcieAddressProvider,
     },
     dev::{Resource, ResourceKind},
-    vm::{pages, VmObject},
+    vm::{pages, CachePolicy, VmObject},
 };
 
 impl Syscall<'_> {
@@ -202,13 +202,14 @@ impl Syscall<'_> {
             proc.get_object_with_rights::<PcieDeviceKObject>(handle, Rights::READ | Rights::WRITE)?;
         let info = device.get_bar(bar_num)?;
         let mut bar_ = PciBar {
-            id: 0,
+            id: bar_num,
             size: info.size as usize,
             bar_type: if info.is_mmio { 1 } else { 2 },
             addr: 0,
         };
         if info.is_mmio {
             let vmo = VmObject::new_physical(info.bus_addr as usize, pages(info.size as usize));
+            vmo.set_cache_policy(CachePolicy::UncachedDevice)?;
             let handle = proc.add_handle(Handle::new(vmo, Rights::DEFAULT_VMO));
             out_handle.write(handle)?;
             device.enable_mmio()?;
@@ -227,6 +228,13 @@ impl Syscall<'_> {
         device.enable_master(enable)
     }
 
+    pub async fn sys_pci_reset_device(&self, handle: HandleValue) -> ZxResult {
+        info!("pci.reset_device: handle={:#x}", handle);
+        let proc = self.thread.proc();
+        let device = proc.get_object_with_rights::<PcieDeviceKObject>(handle, Rights::WRITE)?;
+        device.reset().await
+    }
+
     pub fn sys_pci_query_irq_mode(
         &self,
         handle: HandleValue,
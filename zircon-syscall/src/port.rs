@@ -7,8 +7,10 @@ impl Syscall<'_> {
     /// Create an IO port.  
     pub fn sys_port_create(&self, options: u32, mut out: UserOutPtr<HandleValue>) -> ZxResult {
         info!("port.create: options={:#x}", options);
+        let proc = self.thread.proc();
+        proc.check_policy(PolicyCondition::NewPort)?;
         let port_handle = Handle::new(Port::new(options)?, Rights::DEFAULT_PORT);
-        let handle_value = self.thread.proc().add_handle(port_handle);
+        let handle_value = proc.add_handle(port_handle);
         out.write(handle_value)?;
         Ok(())
     }
@@ -53,4 +55,22 @@ impl Syscall<'_> {
         port.push_user(packet)?;
         Ok(())
     }
+
+    /// Cancel async waits on the object `source` with `key` posted to a port,
+    /// and remove the packets they have queued.
+    pub fn sys_port_cancel(
+        &self,
+        handle_value: HandleValue,
+        source: HandleValue,
+        key: u64,
+    ) -> ZxResult {
+        info!(
+            "port.cancel: handle={:#x}, source={:#x}, key={:#x}",
+            handle_value, source, key
+        );
+        let proc = self.thread.proc();
+        let port = proc.get_object_with_rights::<Port>(handle_value, Rights::WRITE)?;
+        let object = proc.get_dyn_object_with_rights(source, Rights::WAIT)?;
+        port.cancel(object.id(), key)
+    }
 }
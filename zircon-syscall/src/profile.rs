@@ -0,0 +1,51 @@
+use {super::*, zircon_object::task::*};
+
+impl Syscall<'_> {
+    /// Create a scheduling profile with the parameters in `profile`.
+    ///
+    /// `root_job` must be a handle to the root job of the system.
+    pub fn sys_profile_create(
+        &self,
+        root_job: HandleValue,
+        options: u32,
+        profile: UserInPtr<ProfileInfo>,
+        mut out: UserOutPtr<HandleValue>,
+    ) -> ZxResult {
+        info!(
+            "profile.create: root_job={:#x}, options={:#x}, profile={:#x?}",
+            root_job, options, profile
+        );
+        if options != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let proc = self.thread.proc();
+        proc.check_policy(PolicyCondition::NewProfile)?;
+        proc.get_object_with_rights::<Job>(root_job, Rights::MANAGE_PROCESS)?
+            .check_root_job()?;
+        let profile = Profile::create(&profile.read()?)?;
+        let handle = proc.add_handle(Handle::new(profile, Rights::DEFAULT_PROFILE));
+        out.write(handle)?;
+        Ok(())
+    }
+
+    /// Apply a scheduling profile to a thread.
+    pub fn sys_object_set_profile(
+        &self,
+        handle: HandleValue,
+        profile: HandleValue,
+        options: u32,
+    ) -> ZxResult {
+        info!(
+            "object.set_profile: handle={:#x}, profile={:#x}, options={:#x}",
+            handle, profile, options
+        );
+        if options != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let proc = self.thread.proc();
+        let thread = proc.get_object_with_rights::<Thread>(handle, Rights::MANAGE_THREAD)?;
+        let profile = proc.get_object_with_rights::<Profile>(profile, Rights::APPLY_PROFILE)?;
+        thread.set_profile(&profile);
+        Ok(())
+    }
+}
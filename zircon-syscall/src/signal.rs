@@ -59,7 +59,7 @@ impl Syscall<'_> {
             return Err(ZxError::NOT_SUPPORTED);
         }
         let proc = self.thread.proc();
-        proc.check_policy(PolicyCondition::NewEvent)?;
+        proc.check_policy(PolicyCondition::NewEventPair)?;
         let (event0, event1) = EventPair::create();
         let handle0 = Handle::new(event0, Rights::DEFAULT_EVENTPAIR);
         let handle1 = Handle::new(event1, Rights::DEFAULT_EVENTPAIR);
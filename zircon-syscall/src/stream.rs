@@ -20,6 +20,7 @@ impl Syscall<'_> {
                 #[allow(clippy::identity_op)]
                 const MODE_READ     = 1 << 0;
                 const MODE_WRITE    = 1 << 1;
+                const MODE_APPEND   = 1 << 2;
             }
         }
         let options = CreateOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
@@ -42,7 +43,7 @@ impl Syscall<'_> {
     }
 
     /// Write data to a stream at the current seek offset.   
-    pub fn sys_stream_writev(
+    pub async fn sys_stream_writev(
         &self,
         handle_value: HandleValue,
         options: u32,
@@ -63,17 +64,22 @@ impl Syscall<'_> {
         let options = WriteOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
         let proc = self.thread.proc();
         let stream = proc.get_object_with_rights::<Stream>(handle_value, Rights::WRITE)?;
+        let append = options.contains(WriteOptions::APPEND);
         let mut actual_count = 0;
         for io_vec in data.iter() {
-            actual_count +=
-                stream.write(io_vec.as_slice()?, options.contains(WriteOptions::APPEND))?;
+            let buf = io_vec.as_slice()?;
+            let count = wait_for_pager(self.thread, || stream.write(buf, append)).await?;
+            actual_count += count;
+            if count < buf.len() {
+                break;
+            }
         }
         actual_count_ptr.write_if_not_null(actual_count)?;
         Ok(())
     }
 
     /// Write data to a stream at the given offset.   
-    pub fn sys_stream_writev_at(
+    pub async fn sys_stream_writev_at(
         &self,
         handle_value: HandleValue,
         options: u32,
@@ -94,15 +100,20 @@ impl Syscall<'_> {
         let stream = proc.get_object_with_rights::<Stream>(handle_value, Rights::WRITE)?;
         let mut actual_count = 0;
         for io_vec in data.iter() {
-            actual_count += stream.write_at(io_vec.as_slice()?, offset)?;
-            offset += actual_count;
+            let buf = io_vec.as_slice()?;
+            let count = wait_for_pager(self.thread, || stream.write_at(buf, offset)).await?;
+            actual_count += count;
+            offset += count;
+            if count < buf.len() {
+                break;
+            }
         }
         actual_count_ptr.write_if_not_null(actual_count)?;
         Ok(())
     }
 
     /// Read data from a stream at the current seek offset.   
-    pub fn sys_stream_readv(
+    pub async fn sys_stream_readv(
         &self,
         handle_value: HandleValue,
         options: u32,
@@ -122,14 +133,20 @@ impl Syscall<'_> {
         let stream = proc.get_object_with_rights::<Stream>(handle_value, Rights::READ)?;
         let mut actual_count = 0usize;
         for io_vec in data.iter_mut() {
-            actual_count += stream.read(io_vec.as_mut_slice()?)?;
+            let buf = io_vec.as_mut_slice()?;
+            let len = buf.len();
+            let count = wait_for_pager(self.thread, || stream.read(buf)).await?;
+            actual_count += count;
+            if count < len {
+                break;
+            }
         }
         actual_count_ptr.write_if_not_null(actual_count)?;
         Ok(())
     }
 
     /// Read data from a stream at the given offset.   
-    pub fn sys_stream_readv_at(
+    pub async fn sys_stream_readv_at(
         &self,
         handle_value: HandleValue,
         options: u32,
@@ -150,8 +167,14 @@ impl Syscall<'_> {
         let stream = proc.get_object_with_rights::<Stream>(handle_value, Rights::READ)?;
         let mut actual_count = 0usize;
         for io_vec in data.iter_mut() {
-            actual_count += stream.read_at(io_vec.as_mut_slice()?, offset)?;
-            offset += actual_count;
+            let buf = io_vec.as_mut_slice()?;
+            let len = buf.len();
+            let count = wait_for_pager(self.thread, || stream.read_at(buf, offset)).await?;
+            actual_count += count;
+            offset += count;
+            if count < len {
+                break;
+            }
         }
         actual_count_ptr.write_if_not_null(actual_count)?;
         Ok(())
@@ -1,5 +1,8 @@
 use super::*;
-use zircon_object::{signal::Event, task::Job};
+use zircon_object::{
+    task::Job,
+    vm::{pressure_event, PressureLevel},
+};
 
 impl Syscall<'_> {
     /// Retrieve a handle to a system event.
@@ -22,23 +25,25 @@ impl Syscall<'_> {
             "system.get_event: root_job={:#x}, kind={:#x}, out_ptr={:#x?}",
             root_job, kind, out
         );
-        match kind {
-            EVENT_OUT_OF_MEMORY => {
-                let proc = self.thread.proc();
-                proc.get_object_with_rights::<Job>(root_job, Rights::MANAGE_PROCESS)?
-                    .check_root_job()?;
-                // TODO: out-of-memory event
-                let event = Event::new();
-                let event_handle = proc.add_handle(Handle::new(event, Rights::DEFAULT_EVENT));
-                out.write(event_handle)?;
-                Ok(())
-            }
-            _ => unimplemented!(),
-        }
+        let level = match kind {
+            EVENT_OUT_OF_MEMORY => PressureLevel::OutOfMemory,
+            EVENT_MEMORY_PRESSURE_CRITICAL => PressureLevel::Critical,
+            EVENT_MEMORY_PRESSURE_WARNING => PressureLevel::Warning,
+            EVENT_MEMORY_PRESSURE_NORMAL => PressureLevel::Normal,
+            _ => return Err(ZxError::INVALID_ARGS),
+        };
+        let proc = self.thread.proc();
+        proc.get_object_with_rights::<Job>(root_job, Rights::MANAGE_PROCESS)?
+            .check_root_job()?;
+        // the system events can only be waited on, not signaled by users
+        let rights = Rights::WAIT | Rights::DUPLICATE | Rights::TRANSFER;
+        let event_handle = proc.add_handle(Handle::new(pressure_event(level), rights));
+        out.write(event_handle)?;
+        Ok(())
     }
 }
 
 const EVENT_OUT_OF_MEMORY: u32 = 1;
-const _EVENT_MEMORY_PRESSURE_CRITICAL: u32 = 2;
-const _EVENT_MEMORY_PRESSURE_WARNING: u32 = 3;
-const _EVENT_MEMORY_PRESSURE_NORMAL: u32 = 4;
+const EVENT_MEMORY_PRESSURE_CRITICAL: u32 = 2;
+const EVENT_MEMORY_PRESSURE_WARNING: u32 = 3;
+const EVENT_MEMORY_PRESSURE_NORMAL: u32 = 4;
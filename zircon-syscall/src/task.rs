@@ -23,6 +23,7 @@ impl Syscall<'_> {
             return Err(ZxError::INVALID_ARGS);
         }
         let proc = self.thread.proc();
+        proc.check_policy(PolicyCondition::NewProcess)?;
         let job = proc
             .get_object_with_rights::<Job>(job, Rights::MANAGE_PROCESS)
             .or_else(|_| proc.get_object_with_rights::<Job>(job, Rights::WRITE))?;
@@ -220,22 +221,31 @@ impl Syscall<'_> {
     ) -> ZxResult {
         info!("task.suspend_token: handle={:?}, token={:?}", handle, token);
         let proc = self.thread.proc();
-        if let Ok(thread) = proc.get_object_with_rights::<Thread>(handle, Rights::WRITE) {
+        let (object, rights) = proc.get_dyn_object_and_rights(handle)?;
+        if !rights.contains(Rights::WRITE) {
+            return Err(ZxError::ACCESS_DENIED);
+        }
+        let task: Arc<dyn Task> = if let Ok(thread) = object.clone().downcast_arc::<Thread>() {
             if Arc::ptr_eq(&thread, self.thread) {
                 return Err(ZxError::NOT_SUPPORTED);
             }
             if thread.state() == ThreadState::Dying || thread.state() == ThreadState::Dead {
                 return Err(ZxError::BAD_STATE);
             }
-            let thread: Arc<dyn Task> = thread;
-            let token_handle =
-                Handle::new(SuspendToken::create(&thread), Rights::DEFAULT_SUSPEND_TOKEN);
-            token.write(proc.add_handle(token_handle))?;
-            return Ok(());
-        }
-        if let Ok(_process) = proc.get_object_with_rights::<Process>(handle, Rights::WRITE) {
-            return Err(ZxError::NOT_SUPPORTED);
-        }
+            thread
+        } else if let Ok(process) = object.downcast_arc::<Process>() {
+            if Arc::ptr_eq(&process, proc) {
+                return Err(ZxError::NOT_SUPPORTED);
+            }
+            if let Status::Exited(_) = process.status() {
+                return Err(ZxError::BAD_STATE);
+            }
+            process
+        } else {
+            return Err(ZxError::WRONG_TYPE);
+        };
+        let token_handle = Handle::new(SuspendToken::create(&task), Rights::DEFAULT_SUSPEND_TOKEN);
+        token.write(proc.add_handle(token_handle))?;
         Ok(())
     }
 
@@ -243,13 +253,12 @@ impl Syscall<'_> {
     pub fn sys_task_kill(&mut self, handle: HandleValue) -> ZxResult {
         info!("task.kill: handle={:?}", handle);
         let proc = self.thread.proc();
-
-        if let Ok(job) = proc.get_object_with_rights::<Job>(handle, Rights::DESTROY) {
+        let object = proc.get_dyn_object_with_rights(handle, Rights::DESTROY)?;
+        if let Ok(job) = object.clone().downcast_arc::<Job>() {
             job.kill();
-        } else if let Ok(process) = proc.get_object_with_rights::<Process>(handle, Rights::DESTROY)
-        {
+        } else if let Ok(process) = object.clone().downcast_arc::<Process>() {
             process.kill();
-        } else if let Ok(thread) = proc.get_object_with_rights::<Thread>(handle, Rights::DESTROY) {
+        } else if let Ok(thread) = object.downcast_arc::<Thread>() {
             thread.kill();
         } else {
             return Err(ZxError::WRONG_TYPE);
@@ -303,12 +312,31 @@ impl Syscall<'_> {
                     JOB_POL_ABSOLUTE => SetPolicyOptions::Absolute,
                     _ => return Err(ZxError::INVALID_ARGS),
                 };
-                job.set_policy_basic(
-                    policy_option,
-                    UserInPtr::from(policy).as_slice(count as usize)?,
-                )
+                if count as usize > JOB_POL_MAX_COUNT {
+                    return Err(ZxError::OUT_OF_RANGE);
+                }
+                let policies = if topic == JOB_POL_BASE_V1 {
+                    UserInPtr::<BasicPolicyV1>::from(policy)
+                        .read_array(count as usize)?
+                        .iter()
+                        .map(|p| basic_policy(p.condition, p.policy, false))
+                        .collect::<ZxResult<Vec<_>>>()?
+                } else {
+                    UserInPtr::<BasicPolicyV2>::from(policy)
+                        .read_array(count as usize)?
+                        .iter()
+                        .map(|p| {
+                            let overridable = match p.flags {
+                                JOB_POL_OVERRIDE_ALLOW => true,
+                                JOB_POL_OVERRIDE_DENY => false,
+                                _ => return Err(ZxError::INVALID_ARGS),
+                            };
+                            basic_policy(p.condition, p.action, overridable)
+                        })
+                        .collect::<ZxResult<Vec<_>>>()?
+                };
+                job.set_policy_basic(policy_option, &policies)
             }
-            //JOB_POL_BASE_V2 => unimplemented!(),
             JOB_POL_TIMER_SLACK => {
                 if options != JOB_POL_RELATIVE {
                     return Err(ZxError::INVALID_ARGS);
@@ -371,6 +399,31 @@ impl Syscall<'_> {
     }
 }
 
+/// `zx_policy_basic_v1_t`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BasicPolicyV1 {
+    condition: u32,
+    policy: u32,
+}
+
+/// `zx_policy_basic_v2_t`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BasicPolicyV2 {
+    condition: u32,
+    action: u32,
+    flags: u32,
+}
+
+fn basic_policy(condition: u32, action: u32, overridable: bool) -> ZxResult<BasicPolicy> {
+    Ok(BasicPolicy {
+        condition: PolicyCondition::try_from(condition).map_err(|_| ZxError::INVALID_ARGS)?,
+        action: PolicyAction::try_from(action).map_err(|_| ZxError::INVALID_ARGS)?,
+        overridable,
+    })
+}
+
 const JOB_POL_BASE_V1: u32 = 0;
 const JOB_POL_BASE_V2: u32 = 0x0100_0000;
 const JOB_POL_TIMER_SLACK: u32 = 1;
@@ -378,4 +431,9 @@ const JOB_POL_TIMER_SLACK: u32 = 1;
 const JOB_POL_RELATIVE: u32 = 0;
 const JOB_POL_ABSOLUTE: u32 = 1;
 
+const JOB_POL_OVERRIDE_ALLOW: u32 = 0;
+const JOB_POL_OVERRIDE_DENY: u32 = 1;
+
+const JOB_POL_MAX_COUNT: usize = 32;
+
 const MAX_BLOCK: usize = 64 * 1024 * 1024; //64M
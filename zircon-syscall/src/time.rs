@@ -6,7 +6,7 @@ use {
         time::Duration,
     },
     kernel_hal::timer::timer_now,
-    zircon_object::{dev::*, task::*},
+    zircon_object::{dev::*, signal::*, task::*},
 };
 
 static UTC_OFFSET: AtomicU64 = AtomicU64::new(0);
@@ -15,15 +15,41 @@ const ZX_CLOCK_MONOTONIC: u32 = 0;
 const ZX_CLOCK_UTC: u32 = 1;
 const ZX_CLOCK_THREAD: u32 = 2;
 
+/// The version of arguments, in the highest bits of options.
+const ZX_CLOCK_ARGS_VERSION_SHIFT: u64 = 58;
+const ZX_CLOCK_ARGS_VERSION_MASK: u64 = 0xf << ZX_CLOCK_ARGS_VERSION_SHIFT;
+
+/// Split `options` into the version of arguments and the other options.
+fn split_args_version(options: u64) -> (u64, u64) {
+    (
+        (options & ZX_CLOCK_ARGS_VERSION_MASK) >> ZX_CLOCK_ARGS_VERSION_SHIFT,
+        options & !ZX_CLOCK_ARGS_VERSION_MASK,
+    )
+}
+
 impl Syscall<'_> {
     /// Create a new clock object.
+    ///
+    /// The arguments of version 1 are read from `user_args` for the backstop
+    /// time, which is 0 without arguments.
     pub fn sys_clock_create(
         &self,
-        _options: u64,
-        _user_args: UserInPtr<u8>,
-        mut _out: UserOutPtr<HandleValue>,
+        options: u64,
+        user_args: UserInPtr<ClockCreateArgs>,
+        mut out: UserOutPtr<HandleValue>,
     ) -> ZxResult {
-        warn!("clock.create: skip");
+        info!(
+            "clock.create: options={:#x}, user_args={:?}",
+            options, user_args
+        );
+        let backstop = match split_args_version(options) {
+            (0, _) => 0,
+            (1, _) => user_args.read()?.backstop_time,
+            _ => return Err(ZxError::INVALID_ARGS),
+        };
+        let clock = Clock::create(split_args_version(options).1, backstop)?;
+        let proc = self.thread.proc();
+        out.write(proc.add_handle(Handle::new(clock, Rights::DEFAULT_CLOCK)))?;
         Ok(())
     }
 
@@ -51,10 +77,31 @@ impl Syscall<'_> {
     }
 
     /// Perform a basic read of the clock.
-    pub fn sys_clock_read(&self, handle: HandleValue, mut now: UserOutPtr<u64>) -> ZxResult {
+    pub fn sys_clock_read(&self, handle: HandleValue, mut now: UserOutPtr<i64>) -> ZxResult {
         info!("clock.read: handle={:#x?}", handle);
-        warn!("ignore clock handle");
-        now.write(timer_now().as_nanos() as u64)?;
+        let proc = self.thread.proc();
+        let clock = proc.get_object_with_rights::<Clock>(handle, Rights::READ)?;
+        now.write(clock.read())?;
+        Ok(())
+    }
+
+    /// Fetch all of the low level details of the clock's current status.
+    pub fn sys_clock_get_details(
+        &self,
+        handle: HandleValue,
+        options: u64,
+        mut details: UserOutPtr<ClockDetails>,
+    ) -> ZxResult {
+        info!(
+            "clock.get_details: handle={:#x?}, options={:#x}",
+            handle, options
+        );
+        if split_args_version(options) != (1, 0) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let proc = self.thread.proc();
+        let clock = proc.get_object_with_rights::<Clock>(handle, Rights::READ)?;
+        details.write(clock.get_details())?;
         Ok(())
     }
 
@@ -78,14 +125,26 @@ impl Syscall<'_> {
     }
 
     /// Make adjustments to a clock object.
+    ///
+    /// Only the arguments of version 1 are supported.
     pub fn sys_clock_update(
         &self,
-        _handle: HandleValue,
-        _options: u64,
-        _user_args: UserInPtr<u8>,
+        handle: HandleValue,
+        options: u64,
+        user_args: UserInPtr<ClockUpdateArgs>,
     ) -> ZxResult {
-        warn!("clock.update: skip");
-        Ok(())
+        info!(
+            "clock.update: handle={:#x?}, options={:#x}, user_args={:?}",
+            handle, options, user_args
+        );
+        let (version, options) = split_args_version(options);
+        if version != 1 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let args = user_args.read()?;
+        let proc = self.thread.proc();
+        let clock = proc.get_object_with_rights::<Clock>(handle, Rights::WRITE)?;
+        clock.update(options, &args)
     }
 
     /// Sleep for some number of nanoseconds.
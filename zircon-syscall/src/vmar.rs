@@ -1,4 +1,7 @@
-use {super::*, bitflags::bitflags, zircon_object::vm::*};
+use {
+    super::*, bitflags::bitflags, numeric_enum_macro::numeric_enum,
+    zircon_object::task::PolicyCondition, zircon_object::vm::*,
+};
 
 fn amount_of_alignments(options: u32) -> ZxResult<usize> {
     let mut align_pow2 = (options >> 24) as usize;
@@ -44,7 +47,8 @@ impl Syscall<'_> {
             !(VmarFlags::SPECIFIC
                 | VmarFlags::CAN_MAP_SPECIFIC
                 | VmarFlags::COMPACT
-                | VmarFlags::CAN_MAP_RXW),
+                | VmarFlags::CAN_MAP_RXW
+                | VmarFlags::OFFSET_IS_UPPER_LIMIT),
         ) {
             return Err(ZxError::INVALID_ARGS);
         }
@@ -53,16 +57,7 @@ impl Syscall<'_> {
         let align = amount_of_alignments(options)?;
 
         // get offest with options
-        let offset = if vm_options.contains(VmOptions::SPECIFIC) {
-            Some(offset as usize)
-        } else if vm_options.contains(VmOptions::SPECIFIC_OVERWRITE) {
-            unimplemented!()
-        } else {
-            if offset != 0 {
-                return Err(ZxError::INVALID_ARGS);
-            }
-            None
-        };
+        let offset = vm_options.placement_offset(offset as usize)?;
 
         let size = roundup_pages(size as usize);
         // check `size`
@@ -96,6 +91,7 @@ impl Syscall<'_> {
             "vmar.map: vmar_handle={:#x?}, options={:#x?}, vmar_offset={:#x?}, vmo_handle={:#x?}, vmo_offset={:#x?}, len={:#x?}",
             vmar_handle, options, vmar_offset, vmo_handle, vmo_offset, len
         );
+        let align = amount_of_alignments(options)?;
         let options = VmOptions::from_bits(options).ok_or(ZxError::INVALID_ARGS)?;
         let proc = self.thread.proc();
         let (vmar, vmar_rights) = proc.get_object_and_rights::<VmAddressRegion>(vmar_handle)?;
@@ -112,11 +108,7 @@ impl Syscall<'_> {
             return Err(ZxError::NOT_SUPPORTED);
         }
         // check SPECIFIC options with offset
-        let is_specific = options.contains(VmOptions::SPECIFIC)
-            || options.contains(VmOptions::SPECIFIC_OVERWRITE);
-        if !is_specific && vmar_offset != 0 {
-            return Err(ZxError::INVALID_ARGS);
-        }
+        let vmar_offset = options.placement_offset(vmar_offset)?;
         if !vmar_rights.contains(options.to_required_rights()) {
             return Err(ZxError::ACCESS_DENIED);
         }
@@ -128,7 +120,16 @@ impl Syscall<'_> {
         mapping_flags.set(MMUFlags::READ, options.contains(VmOptions::PERM_READ));
         mapping_flags.set(MMUFlags::WRITE, options.contains(VmOptions::PERM_WRITE));
         mapping_flags.set(MMUFlags::EXECUTE, options.contains(VmOptions::PERM_EXECUTE));
+        if mapping_flags.contains(MMUFlags::WRITE | MMUFlags::EXECUTE) {
+            proc.check_policy(PolicyCondition::VmarWx)?;
+        }
         let overwrite = options.contains(VmOptions::SPECIFIC_OVERWRITE);
+        let mut placement = VmarFlags::empty();
+        placement.set(VmarFlags::SPECIFIC_OVERWRITE, overwrite);
+        placement.set(
+            VmarFlags::OFFSET_IS_UPPER_LIMIT,
+            options.contains(VmOptions::OFFSET_IS_UPPER_LIMIT),
+        );
         let map_range = if cfg!(any(feature = "deny-page-fault", not(target_os = "none"))) {
             true
         } else {
@@ -136,8 +137,8 @@ impl Syscall<'_> {
         };
 
         info!(
-            "mmuflags: {:?}, is_specific {:?}, overwrite {:?}, map_range {:?}",
-            mapping_flags, is_specific, overwrite, map_range
+            "mmuflags: {:?}, offset {:?}, placement {:?}, map_range {:?}",
+            mapping_flags, vmar_offset, placement, map_range
         );
         if map_range && overwrite {
             return Err(ZxError::INVALID_ARGS);
@@ -150,15 +151,15 @@ impl Syscall<'_> {
         if len == 0 {
             return Err(ZxError::INVALID_ARGS);
         }
-        let vmar_offset = if is_specific { Some(vmar_offset) } else { None };
         let vaddr = vmar.map_ext(
             vmar_offset,
             vmo,
             vmo_offset,
             len,
+            align,
             permissions,
             mapping_flags,
-            overwrite,
+            placement,
             map_range,
         )?;
         info!("vmar.map: at {:#x?}", vaddr);
@@ -202,6 +203,9 @@ impl Syscall<'_> {
         mapping_flags.set(MMUFlags::WRITE, options.contains(VmOptions::PERM_WRITE));
         mapping_flags.set(MMUFlags::EXECUTE, options.contains(VmOptions::PERM_EXECUTE));
         info!("mmuflags: {:?}", mapping_flags);
+        if mapping_flags.contains(MMUFlags::WRITE | MMUFlags::EXECUTE) {
+            proc.check_policy(PolicyCondition::VmarWx)?;
+        }
         let len = roundup_pages(len as usize);
         if len == 0 {
             return Err(ZxError::INVALID_ARGS);
@@ -221,6 +225,63 @@ impl Syscall<'_> {
         vmar.unmap(addr, pages(len) * PAGE_SIZE)?;
         Ok(())
     }
+
+    /// Perform an operation on VMOs mapped into this VMAR.
+    ///
+    /// The range may cross the sub-regions, but must not contain unmapped gaps.
+    pub fn sys_vmar_op_range(
+        &self,
+        handle_value: HandleValue,
+        op: u32,
+        addr: usize,
+        len: usize,
+        buffer: UserInOutPtr<u8>,
+        buffer_size: usize,
+    ) -> ZxResult {
+        info!(
+            "vmar.op_range: handle={:#x}, op={:#X}, addr={:#x}, len={:#x}, buffer_size={:#x}",
+            handle_value, op, addr, len, buffer_size,
+        );
+        if !buffer.is_null() || buffer_size != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let op = VmarOpType::try_from(op).or(Err(ZxError::NOT_SUPPORTED))?;
+        let len = roundup_pages(len);
+        if !page_aligned(addr) || len == 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let proc = self.thread.proc();
+        let (vmar, rights) = proc.get_object_and_rights::<VmAddressRegion>(handle_value)?;
+        let info = vmar.get_info();
+        let end = addr.checked_add(len).ok_or(ZxError::OUT_OF_RANGE)?;
+        if addr < info.base || end > info.base + info.len {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        let res = match op {
+            VmarOpType::Commit => {
+                if !rights.contains(Rights::WRITE) {
+                    return Err(ZxError::ACCESS_DENIED);
+                }
+                vmar.commit(addr, len)
+            }
+            VmarOpType::Decommit => {
+                if !rights.contains(Rights::WRITE) {
+                    return Err(ZxError::ACCESS_DENIED);
+                }
+                vmar.decommit(addr, len)
+            }
+            // the pages are mapped on the first access anyway, and the hints
+            // are not used, so only the range is checked
+            VmarOpType::MapRange | VmarOpType::DontNeed | VmarOpType::AlwaysNeed => {
+                vmar.mappings_in_range(addr, len).map(|_| ())
+            }
+        };
+        // a range with unmapped gaps is in a bad state for the operations
+        res.map_err(|err| match err {
+            ZxError::NOT_FOUND => ZxError::BAD_STATE,
+            err => err,
+        })
+    }
 }
 
 bitflags! {
@@ -239,12 +300,31 @@ bitflags! {
         const MAP_RANGE             = 1 << 10;
         const REQUIRE_NON_RESIZABLE = 1 << 11;
         const ALLOW_FAULTS          = 1 << 12;
+        const OFFSET_IS_UPPER_LIMIT = 1 << 13;
+        const ALIGN_MASK            = 0x1f << 24;
         const CAN_MAP_RXW           = Self::CAN_MAP_READ.bits | Self::CAN_MAP_EXECUTE.bits | Self::CAN_MAP_WRITE.bits;
         const PERM_RXW           = Self::PERM_READ.bits | Self::PERM_WRITE.bits | Self::PERM_EXECUTE.bits;
     }
 }
 
 impl VmOptions {
+    /// The offset to place a mapping or sub-region at, or the upper limit of
+    /// its end, which is only given with the options specifying its placement.
+    fn placement_offset(self, offset: usize) -> ZxResult<Option<usize>> {
+        let is_specific = self.intersects(VmOptions::SPECIFIC | VmOptions::SPECIFIC_OVERWRITE);
+        let is_upper_limit = self.contains(VmOptions::OFFSET_IS_UPPER_LIMIT);
+        if is_specific && is_upper_limit {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        if !is_specific && !is_upper_limit {
+            if offset != 0 {
+                return Err(ZxError::INVALID_ARGS);
+            }
+            return Ok(None);
+        }
+        Ok(Some(offset))
+    }
+
     fn to_rights(self) -> Rights {
         let mut rights = Rights::empty();
         if self.contains(VmOptions::CAN_MAP_READ) {
@@ -302,6 +382,21 @@ impl VmOptions {
         if self.contains(VmOptions::ALLOW_FAULTS) {
             flags.insert(VmarFlags::ALLOW_FAULTS);
         }
+        if self.contains(VmOptions::OFFSET_IS_UPPER_LIMIT) {
+            flags.insert(VmarFlags::OFFSET_IS_UPPER_LIMIT);
+        }
         flags
     }
 }
+
+numeric_enum! {
+    #[repr(u32)]
+    /// VMAR Opcodes (for vmar_op_range)
+    pub enum VmarOpType {
+        Commit = 1,
+        Decommit = 2,
+        MapRange = 3,
+        DontNeed = 12,
+        AlwaysNeed = 13,
+    }
+}
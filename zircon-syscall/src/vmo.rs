@@ -24,13 +24,14 @@ impl Syscall<'_> {
         let resizable = options != 0;
         let proc = self.thread.proc();
         let vmo = VmObject::new_paged_with_resizable(resizable, pages(size as usize));
+        vmo.set_content_size(size as usize)?;
         let handle_value = proc.add_handle(Handle::new(vmo, Rights::DEFAULT_VMO));
         out.write(handle_value)?;
         Ok(())
     }
 
     /// Read bytes from a VMO.
-    pub fn sys_vmo_read(
+    pub async fn sys_vmo_read(
         &self,
         handle_value: HandleValue,
         mut buf: UserOutPtr<u8>,
@@ -49,13 +50,13 @@ impl Syscall<'_> {
         }
         // TODO: optimize
         let mut buffer = vec![0u8; buf_size];
-        vmo.read(offset as usize, &mut buffer)?;
+        wait_for_pager(self.thread, || vmo.read(offset as usize, &mut buffer)).await?;
         buf.write_array(&buffer)?;
         Ok(())
     }
 
     /// Write bytes to a VMO.
-    pub fn sys_vmo_write(
+    pub async fn sys_vmo_write(
         &self,
         handle_value: HandleValue,
         buf: UserInPtr<u8>,
@@ -71,7 +72,8 @@ impl Syscall<'_> {
         if offset as usize > vmo.len() || buf_size > vmo.len() - (offset as usize) {
             return Err(ZxError::OUT_OF_RANGE);
         }
-        vmo.write(offset as usize, buf.as_slice(buf_size)?)
+        let buf = buf.as_slice(buf_size)?;
+        wait_for_pager(self.thread, || vmo.write(offset as usize, buf)).await
     }
 
     /// Add execute rights to a VMO.
@@ -149,11 +151,18 @@ impl Syscall<'_> {
                 vmo.create_slice(offset, child_size)
             }
         } else {
-            // TODO: ZX_VMO_CHILD_SNAPSHOT
-            if !options.contains(VmoCloneFlags::SNAPSHOT_AT_LEAST_ON_WRITE) {
-                return Err(ZxError::NOT_SUPPORTED);
+            // exactly one type of child is given
+            let snapshot =
+                options & (VmoCloneFlags::SNAPSHOT | VmoCloneFlags::SNAPSHOT_AT_LEAST_ON_WRITE);
+            if snapshot.bits().count_ones() != 1 || !page_aligned(offset) {
+                return Err(ZxError::INVALID_ARGS);
             }
-            vmo.create_child(resizable, offset as usize, child_size)
+            if offset.checked_add(child_size).is_none() {
+                return Err(ZxError::OUT_OF_RANGE);
+            }
+            // a snapshot never sees the later changes of its parent, which is
+            // also allowed for an at-least-on-write one
+            vmo.create_child(resizable, offset, child_size)
         }?;
         // generate rights
         let mut child_rights = parent_rights;
@@ -245,7 +254,8 @@ impl Syscall<'_> {
             size,
             vmo.len(),
         );
-        vmo.set_len(size)
+        vmo.set_len(size)?;
+        vmo.set_content_size(size)
     }
 
     /// Perform an operation on a range of a VMO.
@@ -265,6 +275,9 @@ impl Syscall<'_> {
             handle_value, op, offset, len, _buffer_size,
         );
         let op = VmoOpType::try_from(op).or(Err(ZxError::INVALID_ARGS))?;
+        if offset.checked_add(len).is_none() {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
         let proc = self.thread.proc();
         let (vmo, rights) = proc.get_object_and_rights::<VmObject>(handle_value)?;
         match op {
@@ -293,7 +306,19 @@ impl Syscall<'_> {
                 }
                 vmo.zero(offset, len)
             }
-            _ => unimplemented!(),
+            VmoOpType::CacheSync | VmoOpType::CacheClean | VmoOpType::CacheCleanInvalidate => {
+                if !rights.contains(Rights::READ) {
+                    return Err(ZxError::ACCESS_DENIED);
+                }
+                vmo.cache_flush(offset, len)
+            }
+            VmoOpType::CacheInvalidate => {
+                if !rights.contains(Rights::WRITE) {
+                    return Err(ZxError::ACCESS_DENIED);
+                }
+                vmo.cache_flush(offset, len)
+            }
+            VmoOpType::Lock | VmoOpType::Unlock => Err(ZxError::NOT_SUPPORTED),
         }
     }
 